@@ -3,4 +3,35 @@
 
 fn main() {
     built::write_built_file().expect("Failed to acquire build-time information");
+
+    // `built` only lists dependency versions with its `cargo-lock` feature; reading the one
+    // version `get-server-info` needs from the lockfile is enough.
+    let wasmtime_version = std::fs::read_to_string("Cargo.lock")
+        .ok()
+        .and_then(|lock| locked_version(&lock, "wasmtime"))
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=WASMTIME_VERSION={wasmtime_version}");
+
+    // `wassette security selftest` bundles its adversarial component if it was built first,
+    // with `just build-selftest`.
+    println!("cargo:rustc-check-cfg=cfg(bundled_selftest)");
+    let selftest =
+        std::path::absolute("examples/selftest-rs/target/wasm32-wasip2/release/selftest.wasm")
+            .expect("The working directory of build scripts exists");
+    if selftest.is_file() {
+        println!("cargo:rustc-cfg=bundled_selftest");
+        println!(
+            "cargo:rustc-env=WASSETTE_SELFTEST_COMPONENT={}",
+            selftest.display()
+        );
+    }
+}
+
+/// Version of `package` in the contents of a `Cargo.lock`.
+fn locked_version(lock: &str, package: &str) -> Option<String> {
+    let name = format!("name = \"{package}\"");
+    let mut lines = lock.lines();
+    lines.find(|line| *line == name)?;
+    let version = lines.next()?.strip_prefix("version = \"")?;
+    Some(version.trim_end_matches('"').to_string())
 }
@@ -0,0 +1,372 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Differential fuzz target for the WIT <-> JSON conversion layer.
+//!
+//! Each input describes an arbitrary WIT type and a value of that type. The type is compiled
+//! into a type-only component so we get a real [`wasmtime::component::Type`], the value is
+//! converted to JSON with `vals_to_json` and back with `json_to_vals`, and the result must be
+//! identical to the original value.
+
+#![no_main]
+
+use arbitrary::{Result, Unstructured};
+use component2json::{json_to_vals, vals_to_json};
+use libfuzzer_sys::fuzz_target;
+use serde_json::json;
+use wasmtime::component::types::{ComponentFunc, ComponentItem};
+use wasmtime::component::{Component, Type, Val};
+use wasmtime::Engine;
+
+const MAX_DEPTH: u32 = 4;
+const MAX_ITEMS: usize = 4;
+
+/// Shape of a WIT value type, rendered into WAT to obtain a wasmtime [`Type`].
+#[derive(Debug)]
+enum Shape {
+    Bool,
+    S8,
+    S16,
+    S32,
+    S64,
+    U8,
+    U16,
+    U32,
+    U64,
+    Float32,
+    Float64,
+    Char,
+    String,
+    List(Box<Shape>),
+    Record(Vec<Shape>),
+    Tuple(Vec<Shape>),
+    Variant(Vec<Option<Shape>>),
+    Enum(usize),
+    Option(Box<Shape>),
+    Result(Option<Box<Shape>>, Option<Box<Shape>>),
+    Flags(usize),
+}
+
+fn arbitrary_shape(u: &mut Unstructured<'_>, depth: u32) -> Result<Shape> {
+    let max_kind = if depth >= MAX_DEPTH { 12 } else { 20 };
+    Ok(match u.int_in_range(0..=max_kind)? {
+        0 => Shape::Bool,
+        1 => Shape::S8,
+        2 => Shape::S16,
+        3 => Shape::S32,
+        4 => Shape::S64,
+        5 => Shape::U8,
+        6 => Shape::U16,
+        7 => Shape::U32,
+        8 => Shape::U64,
+        9 => Shape::Float32,
+        10 => Shape::Float64,
+        11 => Shape::Char,
+        12 => Shape::String,
+        13 => Shape::List(Box::new(arbitrary_shape(u, depth + 1)?)),
+        14 => Shape::Record(arbitrary_shapes(u, depth)?),
+        15 => Shape::Tuple(arbitrary_shapes(u, depth)?),
+        16 => {
+            let count = u.int_in_range(1..=MAX_ITEMS)?;
+            let mut cases = Vec::with_capacity(count);
+            for _ in 0..count {
+                cases.push(if u.arbitrary()? {
+                    Some(arbitrary_shape(u, depth + 1)?)
+                } else {
+                    None
+                });
+            }
+            Shape::Variant(cases)
+        }
+        17 => Shape::Enum(u.int_in_range(1..=MAX_ITEMS)?),
+        18 => {
+            // `option<option<T>>` is deliberately excluded: both `none` and `some(none)` map
+            // to JSON `null`, so the nested form cannot round-trip by design.
+            let mut inner = arbitrary_shape(u, depth + 1)?;
+            while matches!(inner, Shape::Option(_)) {
+                inner = arbitrary_shape(u, MAX_DEPTH)?;
+            }
+            Shape::Option(Box::new(inner))
+        }
+        19 => {
+            let ok = if u.arbitrary()? {
+                Some(Box::new(arbitrary_shape(u, depth + 1)?))
+            } else {
+                None
+            };
+            let err = if u.arbitrary()? {
+                Some(Box::new(arbitrary_shape(u, depth + 1)?))
+            } else {
+                None
+            };
+            Shape::Result(ok, err)
+        }
+        _ => Shape::Flags(u.int_in_range(1..=32)?),
+    })
+}
+
+fn arbitrary_shapes(u: &mut Unstructured<'_>, depth: u32) -> Result<Vec<Shape>> {
+    let count = u.int_in_range(1..=MAX_ITEMS)?;
+    (0..count).map(|_| arbitrary_shape(u, depth + 1)).collect()
+}
+
+/// Accumulates type definitions for the inner component type of the generated WAT.
+#[derive(Default)]
+struct TypeDefs {
+    defs: Vec<String>,
+    next: u32,
+}
+
+impl TypeDefs {
+    fn define(&mut self, def: String) -> u32 {
+        let idx = self.next;
+        self.defs.push(format!("(type (;{idx};) {def})"));
+        self.next += 1;
+        idx
+    }
+
+    /// Records, variants, enums and flags must be exported under a name before a function
+    /// signature may refer to them.
+    fn define_named(&mut self, def: String) -> u32 {
+        let idx = self.define(def);
+        let exported = self.next;
+        self.defs.push(format!(
+            "(export (;{exported};) \"t{idx}\" (type (eq {idx})))"
+        ));
+        self.next += 1;
+        exported
+    }
+
+    fn render(&mut self, shape: &Shape) -> String {
+        match shape {
+            Shape::Bool => "bool".to_string(),
+            Shape::S8 => "s8".to_string(),
+            Shape::S16 => "s16".to_string(),
+            Shape::S32 => "s32".to_string(),
+            Shape::S64 => "s64".to_string(),
+            Shape::U8 => "u8".to_string(),
+            Shape::U16 => "u16".to_string(),
+            Shape::U32 => "u32".to_string(),
+            Shape::U64 => "u64".to_string(),
+            Shape::Float32 => "f32".to_string(),
+            Shape::Float64 => "f64".to_string(),
+            Shape::Char => "char".to_string(),
+            Shape::String => "string".to_string(),
+            Shape::List(inner) => {
+                let inner = self.render(inner);
+                self.define(format!("(list {inner})")).to_string()
+            }
+            Shape::Record(fields) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, f)| format!("(field \"f{i}\" {})", self.render(f)))
+                    .collect();
+                self.define_named(format!("(record {})", fields.join(" ")))
+                    .to_string()
+            }
+            Shape::Tuple(items) => {
+                let items: Vec<_> = items.iter().map(|i| self.render(i)).collect();
+                self.define(format!("(tuple {})", items.join(" ")))
+                    .to_string()
+            }
+            Shape::Variant(cases) => {
+                let cases: Vec<_> = cases
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| match c {
+                        Some(payload) => format!("(case \"c{i}\" {})", self.render(payload)),
+                        None => format!("(case \"c{i}\")"),
+                    })
+                    .collect();
+                self.define_named(format!("(variant {})", cases.join(" ")))
+                    .to_string()
+            }
+            Shape::Enum(count) => {
+                let names: Vec<_> = (0..*count).map(|i| format!("\"e{i}\"")).collect();
+                self.define_named(format!("(enum {})", names.join(" ")))
+                    .to_string()
+            }
+            Shape::Option(inner) => {
+                let inner = self.render(inner);
+                self.define(format!("(option {inner})")).to_string()
+            }
+            Shape::Result(ok, err) => {
+                let ok = ok.as_ref().map(|ok| self.render(ok));
+                let err = err
+                    .as_ref()
+                    .map(|err| format!("(error {})", self.render(err)));
+                let parts: Vec<_> = ok.into_iter().chain(err).collect();
+                self.define(format!("(result {})", parts.join(" ")))
+                    .to_string()
+            }
+            Shape::Flags(count) => {
+                let names: Vec<_> = (0..*count).map(|i| format!("\"g{i}\"")).collect();
+                self.define_named(format!("(flags {})", names.join(" ")))
+                    .to_string()
+            }
+        }
+    }
+}
+
+/// Compile a type-only component exporting `run(v: T)` and return `T`.
+fn wit_type(engine: &Engine, shape: &Shape) -> Type {
+    let mut defs = TypeDefs::default();
+    let param = defs.render(shape);
+    let func = defs.define(format!("(func (param \"v\" {param}))"));
+    let wat = format!(
+        r#"(component
+            (type (component
+                (type (component
+                    {}
+                    (export "run" (func (type {func})))
+                ))
+                (export "fuzz:fuzz/fuzz" (component (type 0)))
+            ))
+            (export "fuzz" (type 0))
+        )"#,
+        defs.defs.join("\n")
+    );
+
+    let component = Component::new(engine, &wat)
+        .unwrap_or_else(|e| panic!("generated WAT failed to compile: {e:?}\n{wat}"));
+    let func = component
+        .component_type()
+        .exports(engine)
+        .find_map(|(_, item)| find_func(&item, engine))
+        .expect("generated component exports a function");
+    let (_, ty) = func.params().next().expect("function has one parameter");
+    ty
+}
+
+fn find_func(item: &ComponentItem, engine: &Engine) -> Option<ComponentFunc> {
+    match item {
+        ComponentItem::ComponentFunc(func) => Some(func.clone()),
+        ComponentItem::Component(c) => c
+            .exports(engine)
+            .find_map(|(_, item)| find_func(&item, engine)),
+        _ => None,
+    }
+}
+
+fn arbitrary_finite_f64(u: &mut Unstructured<'_>) -> Result<f64> {
+    let f: f64 = u.arbitrary()?;
+    Ok(if f.is_finite() { f } else { 0.0 })
+}
+
+/// Generate an arbitrary value inhabiting `ty`.
+fn arbitrary_val(u: &mut Unstructured<'_>, ty: &Type) -> Result<Val> {
+    Ok(match ty {
+        Type::Bool => Val::Bool(u.arbitrary()?),
+        Type::S8 => Val::S8(u.arbitrary()?),
+        Type::S16 => Val::S16(u.arbitrary()?),
+        Type::S32 => Val::S32(u.arbitrary()?),
+        Type::S64 => Val::S64(u.arbitrary()?),
+        Type::U8 => Val::U8(u.arbitrary()?),
+        Type::U16 => Val::U16(u.arbitrary()?),
+        Type::U32 => Val::U32(u.arbitrary()?),
+        Type::U64 => Val::U64(u.arbitrary()?),
+        // JSON has no representation for NaN or infinities.
+        Type::Float32 => {
+            let f: f32 = u.arbitrary()?;
+            Val::Float32(if f.is_finite() { f } else { 0.0 })
+        }
+        Type::Float64 => Val::Float64(arbitrary_finite_f64(u)?),
+        Type::Char => Val::Char(u.arbitrary()?),
+        Type::String => Val::String(u.arbitrary()?),
+        Type::List(list) => {
+            let len = u.int_in_range(0..=MAX_ITEMS)?;
+            let elem = list.ty();
+            Val::List(
+                (0..len)
+                    .map(|_| arbitrary_val(u, &elem))
+                    .collect::<Result<_>>()?,
+            )
+        }
+        Type::Record(record) => Val::Record(
+            record
+                .fields()
+                .map(|field| Ok((field.name.to_string(), arbitrary_val(u, &field.ty)?)))
+                .collect::<Result<_>>()?,
+        ),
+        Type::Tuple(tuple) => Val::Tuple(
+            tuple
+                .types()
+                .map(|ty| arbitrary_val(u, &ty))
+                .collect::<Result<_>>()?,
+        ),
+        Type::Variant(variant) => {
+            let cases: Vec<_> = variant.cases().collect();
+            let case = u.choose(&cases)?;
+            let payload = match &case.ty {
+                Some(ty) => Some(Box::new(arbitrary_val(u, ty)?)),
+                None => None,
+            };
+            Val::Variant(case.name.to_string(), payload)
+        }
+        Type::Enum(enum_ty) => {
+            let names: Vec<_> = enum_ty.names().collect();
+            Val::Enum(u.choose(&names)?.to_string())
+        }
+        Type::Option(option) => {
+            if u.arbitrary()? {
+                Val::Option(Some(Box::new(arbitrary_val(u, &option.ty())?)))
+            } else {
+                Val::Option(None)
+            }
+        }
+        Type::Result(result) => {
+            let (is_ok, payload_ty) = if u.arbitrary()? {
+                (true, result.ok())
+            } else {
+                (false, result.err())
+            };
+            let payload = match payload_ty {
+                Some(ty) => Some(Box::new(arbitrary_val(u, &ty)?)),
+                None => None,
+            };
+            Val::Result(if is_ok { Ok(payload) } else { Err(payload) })
+        }
+        Type::Flags(flags) => {
+            let mut set = Vec::new();
+            for name in flags.names() {
+                if u.arbitrary()? {
+                    set.push(name.to_string());
+                }
+            }
+            Val::Flags(set)
+        }
+        Type::Own(_) | Type::Borrow(_) | Type::Future(_) | Type::Stream(_) | Type::ErrorContext => {
+            unreachable!("generator never produces {ty:?}")
+        }
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    thread_local! {
+        static ENGINE: Engine = Engine::default();
+    }
+
+    let mut u = Unstructured::new(data);
+    let Ok(shape) = arbitrary_shape(&mut u, 0) else {
+        return;
+    };
+
+    ENGINE.with(|engine| {
+        let ty = wit_type(engine, &shape);
+        let Ok(val) = arbitrary_val(&mut u, &ty) else {
+            return;
+        };
+
+        let encoded = vals_to_json(std::slice::from_ref(&val));
+        let args = json!({ "v": encoded["result"] });
+        let decoded = json_to_vals(&args, &[("v".to_string(), ty.clone())])
+            .unwrap_or_else(|e| panic!("failed to decode {args} as {ty:?}: {e}"));
+
+        assert_eq!(
+            decoded,
+            vec![val],
+            "round trip through {args} changed the value"
+        );
+    });
+});
@@ -181,15 +181,23 @@ pub fn vals_to_json(vals: &[Val]) -> Value {
 
 /// Converts a JSON object to a vector of `Val` objects based on the provided type mappings for each
 /// field.
+///
+/// Fields typed as `option<T>` may be omitted entirely, in which case they are passed as `none`.
 pub fn json_to_vals(value: &Value, types: &[(String, Type)]) -> Result<Vec<Val>, ValError> {
     match value {
         Value::Object(obj) => {
             let mut results = Vec::new();
             for (name, ty) in types {
-                let value = obj.get(name).ok_or_else(|| {
-                    ValError::ShapeError("object", format!("missing field {name}"))
-                })?;
-                results.push(json_to_val(value, ty)?);
+                match obj.get(name) {
+                    Some(value) => results.push(json_to_val(value, ty)?),
+                    None if matches!(ty, Type::Option(_)) => results.push(Val::Option(None)),
+                    None => {
+                        return Err(ValError::ShapeError(
+                            "object",
+                            format!("missing field {name}"),
+                        ))
+                    }
+                }
             }
             Ok(results)
         }
@@ -200,6 +208,139 @@ pub fn json_to_vals(value: &Value, types: &[(String, Type)]) -> Result<Vec<Val>,
     }
 }
 
+/// Name of the custom section a component can embed to declare default argument values.
+///
+/// The section payload is a JSON object keyed by function name (or `interface#function` for
+/// interface exports), mapping each parameter name to its default value:
+///
+/// ```json
+/// { "fetch": { "user-agent": "wassette" } }
+/// ```
+pub const ARGUMENT_DEFAULTS_SECTION: &str = "wassette:defaults";
+
+/// Extracts the argument defaults declared in the [`ARGUMENT_DEFAULTS_SECTION`] custom section of
+/// a component binary.
+///
+/// Returns an empty map when the section is absent or malformed, since defaults are advisory.
+pub fn argument_defaults_from_bytes(bytes: &[u8]) -> Map<String, Value> {
+    find_custom_section(bytes, ARGUMENT_DEFAULTS_SECTION)
+        .and_then(|payload| serde_json::from_slice::<Map<String, Value>>(payload).ok())
+        .unwrap_or_default()
+}
+
+/// Name of the custom section a component can embed its usage guide in, as UTF-8 Markdown.
+pub const README_SECTION: &str = "wassette:readme";
+
+/// Extracts the usage guide embedded in the [`README_SECTION`] custom section of a component
+/// binary.
+///
+/// Returns `None` when the section is absent, empty or not UTF-8.
+pub fn readme_from_bytes(bytes: &[u8]) -> Option<String> {
+    find_custom_section(bytes, README_SECTION)
+        .and_then(|payload| std::str::from_utf8(payload).ok())
+        .filter(|readme| !readme.trim().is_empty())
+        .map(str::to_string)
+}
+
+/// Annotates each tool's input schema with the defaults declared for its function.
+///
+/// Parameters with a default gain a JSON Schema `default` keyword and are removed from the
+/// `required` list. Defaults for parameters the function doesn't have are ignored.
+pub fn apply_argument_defaults(tools: &mut [ToolMetadata], defaults: &Map<String, Value>) {
+    if defaults.is_empty() {
+        return;
+    }
+
+    for tool in tools.iter_mut() {
+        let qualified = tool
+            .identifier
+            .interface_name
+            .as_ref()
+            .map(|iface| format!("{iface}#{}", tool.identifier.function_name));
+        let Some(Value::Object(func_defaults)) = qualified
+            .and_then(|name| defaults.get(&name))
+            .or_else(|| defaults.get(&tool.identifier.function_name))
+        else {
+            continue;
+        };
+
+        let Some(input_schema) = tool
+            .schema
+            .get_mut("inputSchema")
+            .and_then(Value::as_object_mut)
+        else {
+            continue;
+        };
+
+        let mut defaulted = Vec::new();
+        if let Some(Value::Object(properties)) = input_schema.get_mut("properties") {
+            for (param, default) in func_defaults {
+                if let Some(Value::Object(property)) = properties.get_mut(param) {
+                    property.insert("default".to_string(), default.clone());
+                    defaulted.push(param.as_str());
+                }
+            }
+        }
+
+        if let Some(Value::Array(required)) = input_schema.get_mut("required") {
+            required.retain(|name| !defaulted.iter().any(|param| name == *param));
+        }
+    }
+}
+
+/// Fills in arguments missing from `arguments` using the `default` values declared in the tool's
+/// input schema. Arguments that are present, including explicit `null`s, are left untouched.
+pub fn fill_argument_defaults(arguments: &mut Value, input_schema: &Value) {
+    let (Value::Object(args), Some(properties)) = (
+        arguments,
+        input_schema.get("properties").and_then(Value::as_object),
+    ) else {
+        return;
+    };
+
+    for (name, property) in properties {
+        if let Some(default) = property.get("default") {
+            if !args.contains_key(name) {
+                args.insert(name.clone(), default.clone());
+            }
+        }
+    }
+}
+
+/// Walks the top-level sections of a wasm binary looking for a custom section with `name`.
+fn find_custom_section<'a>(bytes: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    // Skip the magic number and version/layer header.
+    let mut rest = bytes.strip_prefix(b"\0asm")?.get(4..)?;
+
+    while let Some((&id, tail)) = rest.split_first() {
+        let (size, tail) = read_leb128_u32(tail)?;
+        let section = tail.get(..size as usize)?;
+        rest = &tail[size as usize..];
+
+        if id != 0 {
+            continue;
+        }
+        let (name_len, section) = read_leb128_u32(section)?;
+        let section_name = section.get(..name_len as usize)?;
+        if section_name == name.as_bytes() {
+            return Some(&section[name_len as usize..]);
+        }
+    }
+
+    None
+}
+
+fn read_leb128_u32(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    let mut result: u32 = 0;
+    for (i, byte) in bytes.iter().enumerate().take(5) {
+        result |= u32::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((result, &bytes[i + 1..]));
+        }
+    }
+    None
+}
+
 /// Prepares a placeholder `Vec<Val>` to receive the results of a component function call.
 /// The vector will have the correct length and correctly-typed (but empty/zeroed) values.
 pub fn create_placeholder_results(results: &[Type]) -> Vec<Val> {
@@ -238,7 +379,9 @@ fn type_to_json_schema(t: &Type) -> Value {
             let mut props = serde_json::Map::new();
             let mut required_fields = Vec::new();
             for field in r.fields() {
-                required_fields.push(field.name.to_string());
+                if !matches!(field.ty, Type::Option(_)) {
+                    required_fields.push(field.name.to_string());
+                }
                 props.insert(field.name.to_string(), type_to_json_schema(&field.ty));
             }
             json!({
@@ -382,7 +525,10 @@ fn component_func_to_schema(name: &str, func: &ComponentFunc, output: bool) -> s
     let mut required = Vec::new();
 
     for (param_name, param_type) in func.params() {
-        required.push(param_name.to_string());
+        // `option<T>` parameters may be omitted by callers and are passed as `none`.
+        if !matches!(param_type, Type::Option(_)) {
+            required.push(param_name.to_string());
+        }
         properties.insert(param_name.to_string(), type_to_json_schema(&param_type));
     }
 
@@ -695,10 +841,17 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
             Value::Object(obj) => {
                 let mut fields = Vec::<(String, Val)>::new();
                 for field in r.fields() {
-                    let value = obj.get(field.name).ok_or_else(|| {
-                        ValError::ShapeError("record", format!("missing field {}", field.name))
-                    })?;
-                    fields.push((field.name.to_string(), json_to_val(value, &field.ty)?));
+                    let val = match obj.get(field.name) {
+                        Some(value) => json_to_val(value, &field.ty)?,
+                        None if matches!(field.ty, Type::Option(_)) => Val::Option(None),
+                        None => {
+                            return Err(ValError::ShapeError(
+                                "record",
+                                format!("missing field {}", field.name),
+                            ))
+                        }
+                    };
+                    fields.push((field.name.to_string(), val));
                 }
                 Ok(Val::Record(fields))
             }
@@ -769,15 +922,15 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
         Type::Result(res_handle) => match value {
             Value::Object(obj) => {
                 if let Some(ok_val) = obj.get("ok") {
-                    let ok_ty = res_handle.ok().unwrap_or(Type::Bool);
-                    Ok(Val::Result(Ok(Some(Box::new(json_to_val(
-                        ok_val, &ok_ty,
-                    )?)))))
+                    Ok(Val::Result(Ok(result_payload_from_json(
+                        ok_val,
+                        res_handle.ok(),
+                    )?)))
                 } else if let Some(err_val) = obj.get("err") {
-                    let err_ty = res_handle.err().unwrap_or(Type::Bool);
-                    Ok(Val::Result(Err(Some(Box::new(json_to_val(
-                        err_val, &err_ty,
-                    )?)))))
+                    Ok(Val::Result(Err(result_payload_from_json(
+                        err_val,
+                        res_handle.err(),
+                    )?)))
                 } else {
                     Err(ValError::ShapeError("result", format!("{value:?}")))
                 }
@@ -812,6 +965,15 @@ fn json_to_val(value: &Value, ty: &Type) -> Result<Val, ValError> {
     }
 }
 
+/// Converts the payload of a `result` case. Cases without a payload type are encoded as `null`.
+fn result_payload_from_json(value: &Value, ty: Option<Type>) -> Result<Option<Box<Val>>, ValError> {
+    match ty {
+        Some(ty) => Ok(Some(Box::new(json_to_val(value, &ty)?))),
+        None if value.is_null() => Ok(None),
+        None => Err(ValError::ShapeError("result", format!("{value:?}"))),
+    }
+}
+
 fn default_val_for_type(ty: &Type) -> Val {
     match ty {
         Type::Bool => Val::Bool(false),
@@ -879,7 +1041,7 @@ mod tests {
 
     use super::*;
 
-    fn result_schema<'a>(schema: &'a Value) -> &'a Value {
+    fn result_schema(schema: &Value) -> &Value {
         schema
             .get("properties")
             .and_then(|props| props.get("result"))
@@ -1605,6 +1767,189 @@ mod tests {
         assert!(json_to_vals(&invalid_type, &types).is_err());
     }
 
+    fn optional_param_component(engine: &Engine) -> Component {
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (option string))
+                    (type (func (param "url" string) (param "user-agent" 0) (result string)))
+                    (export "fetch" (func (type 1)))
+                ))
+                (export "foo:foo/http" (component (type 0)))
+            ))
+            (export "foo" (type 0))
+        )"#;
+        Component::new(engine, wat).unwrap()
+    }
+
+    #[test]
+    fn test_option_params_are_not_required() {
+        let engine = Engine::default();
+        let component = optional_param_component(&engine);
+        let tools = component_exports_to_tools(&component, &engine, false);
+        assert_eq!(tools.len(), 1);
+
+        let input_schema = &tools[0].schema["inputSchema"];
+        assert_eq!(input_schema["required"], json!(["url"]));
+        assert!(input_schema["properties"]
+            .as_object()
+            .unwrap()
+            .contains_key("user-agent"));
+    }
+
+    #[test]
+    fn test_json_to_vals_missing_option_is_none() {
+        let engine = Engine::default();
+        let component = optional_param_component(&engine);
+        fn find_func(item: &ComponentItem, engine: &Engine) -> Option<ComponentFunc> {
+            match item {
+                ComponentItem::ComponentFunc(func) => Some(func.clone()),
+                ComponentItem::Component(c) => c
+                    .exports(engine)
+                    .find_map(|(_, item)| find_func(&item, engine)),
+                _ => None,
+            }
+        }
+
+        let func = component
+            .component_type()
+            .exports(&engine)
+            .find_map(|(_, item)| find_func(&item, &engine))
+            .expect("expected exported function");
+        let types: Vec<_> = func
+            .params()
+            .map(|(name, ty)| (name.to_string(), ty))
+            .collect();
+
+        let vals = json_to_vals(&json!({"url": "https://example.com"}), &types).unwrap();
+        assert_eq!(vals.len(), 2);
+        assert!(matches!(&vals[1], Val::Option(None)));
+
+        // Non-optional parameters are still mandatory.
+        assert!(json_to_vals(&json!({"user-agent": "x"}), &types).is_err());
+    }
+
+    #[test]
+    fn test_json_to_vals_result_without_payload() {
+        let engine = Engine::default();
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (result (error string)))
+                    (type (func (param "status" 0)))
+                    (export "report" (func (type 1)))
+                ))
+                (export "foo:foo/status" (component (type 0)))
+            ))
+            (export "foo" (type 0))
+        )"#;
+        let component = Component::new(&engine, wat).unwrap();
+        let ComponentItem::Component(outer) = component
+            .component_type()
+            .exports(&engine)
+            .next()
+            .unwrap()
+            .1
+        else {
+            panic!("expected exported component");
+        };
+        let ComponentItem::Component(interface) = outer.exports(&engine).next().unwrap().1 else {
+            panic!("expected exported interface");
+        };
+        let ComponentItem::ComponentFunc(func) = interface.exports(&engine).next().unwrap().1
+        else {
+            panic!("expected exported function");
+        };
+        let types: Vec<_> = func
+            .params()
+            .map(|(name, ty)| (name.to_string(), ty))
+            .collect();
+
+        let ok = Val::Result(Ok(None));
+        let json = vals_to_json(std::slice::from_ref(&ok));
+        let vals = json_to_vals(&json!({"status": json["result"]}), &types).unwrap();
+        assert_eq!(vals, vec![ok]);
+
+        // A payload-less case must not silently accept a value.
+        assert!(json_to_vals(&json!({"status": {"ok": true}}), &types).is_err());
+    }
+
+    #[test]
+    fn test_argument_defaults_from_custom_section() {
+        let name = ARGUMENT_DEFAULTS_SECTION.as_bytes();
+        let payload = br#"{"foo:foo/http#fetch": {"user-agent": "wassette"}}"#;
+
+        // Component header followed by a single custom section.
+        let mut bytes = b"\0asm\x0d\x00\x01\x00".to_vec();
+        bytes.push(0);
+        bytes.push((1 + name.len() + payload.len()) as u8);
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name);
+        bytes.extend_from_slice(payload);
+
+        let defaults = argument_defaults_from_bytes(&bytes);
+        assert_eq!(
+            defaults.get("foo:foo/http#fetch"),
+            Some(&json!({"user-agent": "wassette"}))
+        );
+
+        assert!(argument_defaults_from_bytes(b"not wasm").is_empty());
+        assert!(argument_defaults_from_bytes(&bytes[..bytes.len() - 1]).is_empty());
+    }
+
+    #[test]
+    fn test_readme_from_custom_section() {
+        let section = |payload: &[u8]| {
+            let name = README_SECTION.as_bytes();
+            let mut bytes = b"\0asm\x0d\x00\x01\x00".to_vec();
+            bytes.push(0);
+            bytes.push((1 + name.len() + payload.len()) as u8);
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name);
+            bytes.extend_from_slice(payload);
+            bytes
+        };
+
+        assert_eq!(
+            readme_from_bytes(&section(b"# Weather\n\nCall `forecast` first.")).as_deref(),
+            Some("# Weather\n\nCall `forecast` first.")
+        );
+        assert_eq!(readme_from_bytes(&section(b" \n")), None);
+        assert_eq!(readme_from_bytes(&section(b"\xff\xfe")), None);
+        assert_eq!(readme_from_bytes(b"\0asm\x0d\x00\x01\x00"), None);
+    }
+
+    #[test]
+    fn test_apply_and_fill_argument_defaults() {
+        let engine = Engine::default();
+        let component = optional_param_component(&engine);
+        let mut tools = component_exports_to_tools(&component, &engine, false);
+
+        let defaults = json!({
+            "fetch": { "user-agent": "wassette", "unknown": 1 },
+        });
+        apply_argument_defaults(&mut tools, defaults.as_object().unwrap());
+
+        let input_schema = &tools[0].schema["inputSchema"];
+        assert_eq!(
+            input_schema["properties"]["user-agent"]["default"],
+            json!("wassette")
+        );
+        assert!(input_schema["properties"].get("unknown").is_none());
+
+        let mut args = json!({"url": "https://example.com"});
+        fill_argument_defaults(&mut args, input_schema);
+        assert_eq!(
+            args,
+            json!({"url": "https://example.com", "user-agent": "wassette"})
+        );
+
+        // Explicit values win over defaults.
+        let mut args = json!({"url": "https://example.com", "user-agent": null});
+        fill_argument_defaults(&mut args, input_schema);
+        assert_eq!(args["user-agent"], Value::Null);
+    }
+
     #[test]
     fn test_roundtrip() {
         let mut config = wasmtime::Config::new();
@@ -0,0 +1,122 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Canonical JSON output, for deployments that record or diff what the server returns.
+//!
+//! With `canonical_json` set, the JSON in tool results, tool schemas and resources is written
+//! with the keys of every object sorted and without insignificant whitespace, and the tool
+//! list is sorted by name, so the same state yields byte-identical output across runs and
+//! releases. Text that isn't JSON is left as it is, and so is the order of the elements of
+//! arrays, which tools may rely on.
+
+use std::sync::Arc;
+
+use rmcp::model::{
+    CallToolResult, JsonObject, RawContent, ReadResourceResult, ResourceContents, Tool,
+};
+use serde_json::Value;
+
+/// `text` rewritten canonically if it is JSON.
+fn canonical_text(text: &str) -> Option<String> {
+    let mut value: Value = serde_json::from_str(text).ok()?;
+    value.sort_all_objects();
+    serde_json::to_string(&value).ok()
+}
+
+fn canonical_schema(schema: &mut Arc<JsonObject>) {
+    let schema = Arc::make_mut(schema);
+    schema.sort_keys();
+    schema.values_mut().for_each(Value::sort_all_objects);
+}
+
+/// Rewrite the JSON text and structured content of `result` canonically.
+pub fn canonicalize_tool_result(result: &mut CallToolResult) {
+    for content in result.content.iter_mut().flatten() {
+        if let RawContent::Text(text) = &mut content.raw {
+            if let Some(canonical) = canonical_text(&text.text) {
+                text.text = canonical;
+            }
+        }
+    }
+    if let Some(structured) = &mut result.structured_content {
+        structured.sort_all_objects();
+    }
+}
+
+/// Sort `tools` by name and the keys of their schemas.
+pub fn canonicalize_tools(tools: &mut [Tool]) {
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+    for tool in tools {
+        canonical_schema(&mut tool.input_schema);
+        if let Some(output_schema) = &mut tool.output_schema {
+            canonical_schema(output_schema);
+        }
+    }
+}
+
+/// Rewrite the resource contents in `result` that are JSON text canonically.
+pub fn canonicalize_resource(result: &mut ReadResourceResult) {
+    for contents in &mut result.contents {
+        if let ResourceContents::TextResourceContents { text, .. } = contents {
+            if let Some(canonical) = canonical_text(text) {
+                *text = canonical;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::model::Content;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_canonical_tool_result() {
+        let mut result = CallToolResult {
+            content: Some(vec![
+                Content::text("{\n  \"b\": [3, 1, {\"z\": 1, \"a\": 2}],\n  \"a\": 1.5\n}"),
+                Content::text("not json {"),
+            ]),
+            structured_content: Some(json!({"b": 1, "a": {"d": 1, "c": 2}})),
+            is_error: None,
+        };
+        canonicalize_tool_result(&mut result);
+        let texts: Vec<&str> = result
+            .content
+            .iter()
+            .flatten()
+            .filter_map(|content| content.as_text())
+            .map(|text| text.text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            [r#"{"a":1.5,"b":[3,1,{"a":2,"z":1}]}"#, "not json {"]
+        );
+        assert_eq!(
+            serde_json::to_string(&result.structured_content).unwrap(),
+            r#"{"a":{"c":2,"d":1},"b":1}"#
+        );
+    }
+
+    #[test]
+    fn test_canonical_tools() {
+        let schema = |value: Value| Arc::new(value.as_object().unwrap().clone());
+        let mut tools = vec![
+            Tool::new("time", "Current time", schema(json!({"type": "object"}))),
+            Tool::new(
+                "fetch",
+                "Fetch a URL",
+                schema(json!({"type": "object", "properties": {"url": {"type": "string"}}})),
+            ),
+        ];
+        canonicalize_tools(&mut tools);
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_ref()).collect();
+        assert_eq!(names, ["fetch", "time"]);
+        assert_eq!(
+            serde_json::to_string(&tools[0].input_schema).unwrap(),
+            r#"{"properties":{"url":{"type":"string"}},"type":"object"}"#
+        );
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! MCP `completion/complete` support for prompt and tool arguments.
+//!
+//! A `ref/prompt` naming one of the prompts components export completes that prompt's
+//! argument. MCP only defines completion references for prompts and resources, so a
+//! `ref/prompt` whose name is a component tool instead is treated as a request to complete that
+//! tool's argument.
+
+use anyhow::Result;
+use rmcp::model::{CompleteRequestParam, CompleteResult, CompletionInfo, Reference};
+use tracing::{debug, instrument};
+use wassette::LifecycleManager;
+
+/// Handles a completion request by forwarding it to the component of the prompt or tool.
+#[instrument(skip_all, fields(argument = %req.argument.name))]
+pub async fn handle_completion(
+    req: CompleteRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CompleteResult> {
+    let values = match &req.r#ref {
+        Reference::Prompt(prompt)
+            if lifecycle_manager
+                .list_prompts()
+                .iter()
+                .any(|listed| listed.name == prompt.name) =>
+        {
+            debug!(prompt = %prompt.name, "Completing prompt argument");
+            lifecycle_manager
+                .complete_prompt_argument(&prompt.name, &req.argument.name, &req.argument.value)
+                .await?
+        }
+        Reference::Prompt(prompt) => {
+            debug!(tool = %prompt.name, "Completing tool argument");
+            lifecycle_manager
+                .complete_tool_argument(&prompt.name, &req.argument.name, &req.argument.value)
+                .await?
+        }
+        Reference::Resource(_) => Vec::new(),
+    };
+
+    Ok(CompleteResult {
+        completion: CompletionInfo {
+            values,
+            total: None,
+            has_more: None,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::model::{ArgumentInfo, PromptReference, ResourceReference};
+
+    use super::*;
+
+    /// A component with the tool `ping`, completed with `pong`, and the prompt `greet`, whose
+    /// `name` argument is completed with `Ada` by a hook in the prompts interface.
+    const GREETER: &str = r#"(component
+        (core module $m
+            (memory (export "memory") 1)
+            (data (i32.const 64) "\80\00\00\00\01\00\00\00")
+            (data (i32.const 128)
+                "\00\01\00\00\05\00\00\00"
+                "\00\00\00\00\00\00\00\00\00\00\00\00"
+                "\c0\00\00\00\01\00\00\00"
+                "\10\01\00\00\0e\00\00\00")
+            (data (i32.const 192)
+                "\20\01\00\00\04\00\00\00"
+                "\00\00\00\00\00\00\00\00\00\00\00\00"
+                "\00\00\00\00")
+            (data (i32.const 256) "greet")
+            (data (i32.const 272) "Hello {{name}}")
+            (data (i32.const 288) "name")
+            (data (i32.const 320) "\50\01\00\00\01\00\00\00")
+            (data (i32.const 336) "\60\01\00\00\03\00\00\00")
+            (data (i32.const 352) "Ada")
+            (data (i32.const 400) "\a0\01\00\00\01\00\00\00")
+            (data (i32.const 416) "\b0\01\00\00\04\00\00\00")
+            (data (i32.const 432) "pong")
+            (func (export "realloc") (param i32 i32 i32 i32) (result i32) (i32.const 1024))
+            (func (export "ping") (result i32) (i32.const 1))
+            (func (export "list-prompts") (result i32) (i32.const 64))
+            (func (export "complete-prompt") (param i32 i32 i32 i32) (result i32)
+                (i32.const 320))
+            (func (export "complete") (param i32 i32 i32 i32) (result i32) (i32.const 400)))
+        (core instance $i (instantiate $m))
+        (func (export "ping") (result u32) (canon lift (core func $i "ping")))
+        (func (export "complete") (param "argument" string) (param "prefix" string)
+            (result (list string))
+            (canon lift (core func $i "complete") (memory $i "memory")
+                (realloc (func $i "realloc"))))
+        (type $argument (record
+            (field "name" string)
+            (field "description" (option string))
+            (field "required" bool)))
+        (type $prompt (record
+            (field "name" string)
+            (field "description" (option string))
+            (field "arguments" (list $argument))
+            (field "template" string)))
+        (func $list-prompts (result (list $prompt))
+            (canon lift (core func $i "list-prompts") (memory $i "memory")))
+        (func $complete-prompt (param "argument" string) (param "prefix" string)
+            (result (list string))
+            (canon lift (core func $i "complete-prompt") (memory $i "memory")
+                (realloc (func $i "realloc"))))
+        (instance $prompts
+            (export "prompt-argument" (type $argument))
+            (export "prompt" (type $prompt))
+            (export "list-prompts" (func $list-prompts))
+            (export "complete" (func $complete-prompt)))
+        (export "wassette:prompts/prompts" (instance $prompts))
+    )"#;
+
+    fn prompt_request(name: &str, argument: &str) -> CompleteRequestParam {
+        CompleteRequestParam {
+            r#ref: Reference::Prompt(PromptReference {
+                name: name.to_string(),
+            }),
+            argument: ArgumentInfo {
+                name: argument.to_string(),
+                value: String::new(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt_references_complete_prompt_arguments() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new_unloaded(tempdir.path().join("components")).await?;
+        let path = tempdir.path().join("greeter.wasm");
+        std::fs::write(&path, wat::parse_str(GREETER)?)?;
+        manager
+            .load_component(&format!("file://{}", path.display()))
+            .await?;
+
+        let result = handle_completion(prompt_request("greet", "name"), &manager).await?;
+        assert_eq!(result.completion.values, ["Ada"]);
+        // The prompt doesn't declare the argument.
+        let result = handle_completion(prompt_request("greet", "host"), &manager).await?;
+        assert!(result.completion.values.is_empty());
+        // Names that aren't prompts are tools.
+        let result = handle_completion(prompt_request("ping", "host"), &manager).await?;
+        assert_eq!(result.completion.values, ["pong"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resource_references_have_no_completions() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new_unloaded(tempdir.path()).await?;
+        let req = CompleteRequestParam {
+            r#ref: Reference::Resource(ResourceReference {
+                uri: "file:///tmp".to_string(),
+            }),
+            argument: ArgumentInfo {
+                name: "path".to_string(),
+                value: "/t".to_string(),
+            },
+        };
+
+        let result = handle_completion(req, &manager).await?;
+        assert!(result.completion.values.is_empty());
+        Ok(())
+    }
+}
@@ -7,44 +7,38 @@ use std::sync::Arc;
 use anyhow::Result;
 use futures::stream::{self, StreamExt};
 use rmcp::model::{CallToolRequestParam, CallToolResult, Content, Tool};
+use rmcp::service::RequestContext;
 use rmcp::{Peer, RoleServer};
 use serde_json::{json, Value};
 use tracing::{debug, error, info, instrument};
 use wassette::schema::{canonicalize_output_schema, ensure_structured_result};
-use wassette::{ComponentLoadOutcome, LifecycleManager, LoadResult};
+use wassette::{
+    CallContext, CallTimedOut, ComponentLoadOutcome, LifecycleManager, LoadResult,
+    OutsideAllowedHours, ResourceLimitExceeded, ToolOutput, UpdateStatus,
+};
+
+use crate::progress::load_context;
+use crate::sampling::{PeerPrompter, PeerSampler};
 
 #[instrument(skip(lifecycle_manager))]
 pub(crate) async fn get_component_tools(lifecycle_manager: &LifecycleManager) -> Result<Vec<Tool>> {
-    debug!("Listing components");
-    // Use known components (loaded or present on disk) for fast listing
-    let component_ids = lifecycle_manager.list_components_known().await;
-
-    info!(count = component_ids.len(), "Found components");
-    let mut tools = Vec::new();
-
-    for id in component_ids {
-        debug!(component_id = %id, "Getting component details");
-        if let Some(schema) = lifecycle_manager.get_component_schema(&id).await {
-            if let Some(arr) = schema.get("tools").and_then(|v| v.as_array()) {
-                let tool_count = arr.len();
-                debug!(component_id = %id, tool_count, "Found tools in component");
-                for tool_json in arr {
-                    if let Some(tool) = parse_tool_schema(tool_json) {
-                        tools.push(tool);
-                    }
-                }
-            }
-        }
-    }
+    debug!("Listing component tools");
+    // Served from the persisted tool index; this never waits on component compilation.
+    let tools: Vec<Tool> = lifecycle_manager
+        .list_tools()
+        .await
+        .iter()
+        .filter_map(parse_tool_schema)
+        .collect();
     info!(total_tools = tools.len(), "Total tools collected");
     Ok(tools)
 }
 
-#[instrument(skip(lifecycle_manager))]
+#[instrument(skip(lifecycle_manager, ctx))]
 pub(crate) async fn handle_load_component(
     req: &CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
-    server_peer: Peer<RoleServer>,
+    ctx: &RequestContext<RoleServer>,
 ) -> Result<CallToolResult> {
     let args = extract_args_from_request(req)?;
     let path = args
@@ -54,9 +48,14 @@ pub(crate) async fn handle_load_component(
 
     info!(path, "Loading component");
 
-    match lifecycle_manager.load_component(path).await {
+    let context = load_context(ctx);
+    match lifecycle_manager
+        .load_component_with_context(path, &context)
+        .await
+    {
         Ok(outcome) => {
-            handle_tool_list_notification(Some(server_peer), &outcome.component_id, "load").await;
+            handle_tool_list_notification(Some(ctx.peer.clone()), &outcome.component_id, "load")
+                .await;
             create_load_component_success_result(&outcome)
         }
         Err(e) => {
@@ -96,10 +95,61 @@ pub(crate) async fn handle_unload_component(
     }
 }
 
-#[instrument(skip(lifecycle_manager))]
+#[instrument(skip(lifecycle_manager, server_peer))]
+pub(crate) async fn handle_update_component(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    server_peer: Peer<RoleServer>,
+) -> Result<CallToolResult> {
+    update_component(req, lifecycle_manager, Some(server_peer)).await
+}
+
+/// Update a component from where it was loaded, telling `server_peer` if its tools changed.
+async fn update_component(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    server_peer: Option<Peer<RoleServer>>,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+    let id = args
+        .get("component_id")
+        .or_else(|| args.get("id"))
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'component_id'"))?;
+
+    info!(component_id = %id, "Updating component");
+
+    match lifecycle_manager.update_component(id).await {
+        Ok(report) => {
+            if matches!(
+                report.status,
+                UpdateStatus::Updated | UpdateStatus::RolledBack { .. }
+            ) {
+                if let Some(peer) = server_peer {
+                    if let Err(e) = peer.notify_tool_list_changed().await {
+                        error!(error = %e, "Failed to send tool list change notification");
+                    }
+                }
+            }
+            Ok(CallToolResult {
+                content: Some(vec![Content::text(serde_json::to_string(&report)?)]),
+                structured_content: None,
+                is_error: Some(matches!(report.status, UpdateStatus::RolledBack { .. })),
+            })
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to update component");
+            Ok(create_component_error_result("update", id, &e))
+        }
+    }
+}
+
+#[instrument(skip(lifecycle_manager, context))]
 pub(crate) async fn handle_component_call(
     req: &CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
+    server_peer: Peer<RoleServer>,
+    mut context: CallContext,
 ) -> Result<CallToolResult> {
     let args = extract_args_from_request(req)?;
 
@@ -117,13 +167,28 @@ pub(crate) async fn handle_component_call(
         .get_tool_schema_for_component(&component_id, &method_name)
         .await;
 
+    if let Some(prompter) = PeerPrompter::for_peer(server_peer.clone()) {
+        context = context.with_prompter(Arc::new(prompter));
+    }
+    if let Some(sampler) = PeerSampler::for_peer(server_peer) {
+        context = context.with_sampler(Arc::new(sampler));
+    }
+
     let result = lifecycle_manager
-        .execute_component_call(&component_id, &method_name, &serde_json::to_string(&args)?)
+        .call_tool(
+            &component_id,
+            &method_name,
+            &serde_json::to_string(&args)?,
+            &context,
+        )
         .await;
 
     match result {
-        Ok(result_str) => {
-            debug!("Component call successful");
+        Ok(ToolOutput {
+            output: result_str,
+            cached,
+        }) => {
+            debug!(cached, "Component call successful");
 
             let parsed_value = parse_structured_result(&result_str);
             let display_value = unwrap_result_wrapper(&parsed_value);
@@ -138,7 +203,11 @@ pub(crate) async fn handle_component_call(
                 align_structured_result_with_schema(Some(schema), parsed_value.clone())
             });
 
-            let contents = vec![Content::text(response_text)];
+            let mut contents = vec![Content::text(response_text)];
+            if cached {
+                // The result has no `_meta` to carry the flag, so it gets its own item.
+                contents.push(Content::text(json!({ "cached": true }).to_string()));
+            }
 
             Ok(CallToolResult {
                 content: Some(contents),
@@ -148,11 +217,73 @@ pub(crate) async fn handle_component_call(
         }
         Err(e) => {
             error!(error = %e, "Component call failed");
+            if let Some(exceeded) = e.downcast_ref::<ResourceLimitExceeded>() {
+                return Ok(resource_limit_result(exceeded));
+            }
+            if let Some(timed_out) = e.downcast_ref::<CallTimedOut>() {
+                return Ok(timeout_result(timed_out));
+            }
+            if let Some(outside) = e.downcast_ref::<OutsideAllowedHours>() {
+                return Ok(outside_hours_result(outside));
+            }
             Err(anyhow::anyhow!(e.to_string()))
         }
     }
 }
 
+/// The result of a call stopped by a limit of the component's policy. Its structured content
+/// names the limit, so clients can tell it from a failure of the tool itself.
+fn resource_limit_result(exceeded: &ResourceLimitExceeded) -> CallToolResult {
+    CallToolResult {
+        content: Some(vec![Content::text(format!("Error: {exceeded}"))]),
+        structured_content: Some(json!({
+            "error": "resource_limit_exceeded",
+            "component_id": exceeded.component_id,
+            "resource": exceeded.resource,
+            "limit": exceeded.limit,
+        })),
+        is_error: Some(true),
+    }
+}
+
+/// The result of a call stopped at its timeout, which structured content reports like a
+/// resource limit.
+fn timeout_result(timed_out: &CallTimedOut) -> CallToolResult {
+    CallToolResult {
+        content: Some(vec![Content::text(format!("Error: {timed_out}"))]),
+        structured_content: Some(json!({
+            "error": "timeout",
+            "component_id": timed_out.component_id,
+            "timeout_ms": timed_out.timeout.as_millis() as u64,
+        })),
+        is_error: Some(true),
+    }
+}
+
+/// The result of a call refused outside the allowed hours of its tool. Confirmable calls were
+/// put to the user through a sampling request when the client supports sampling, so the
+/// refusal means the user declined or couldn't be asked.
+fn outside_hours_result(outside: &OutsideAllowedHours) -> CallToolResult {
+    let mut text = format!("Error: {outside}.");
+    if outside.confirmable {
+        text.push_str(
+            " Running it now needs the user's confirmation, which they didn't give or the \
+             client couldn't ask for; call the tool again within the allowed hours.",
+        );
+    }
+    CallToolResult {
+        content: Some(vec![Content::text(text)]),
+        structured_content: Some(json!({
+            "error": "outside_allowed_hours",
+            "component_id": outside.component_id,
+            "tool": outside.tool,
+            "allowed_hours": outside.allowed_hours,
+            "confirmable": outside.confirmable,
+        })),
+        is_error: Some(true),
+    }
+}
+
 fn parse_structured_result(result: &str) -> Value {
     serde_json::from_str(result).unwrap_or_else(|_| Value::String(result.to_string()))
 }
@@ -199,12 +330,16 @@ pub async fn handle_list_components(
 ) -> Result<CallToolResult> {
     info!("Listing loaded components");
 
-    // Use known components (loaded or present on disk) for fast listing
-    let component_ids = lifecycle_manager.list_components_known().await;
+    // Use known components (loaded or present on disk) for fast listing, in a stable order
+    let mut component_ids = lifecycle_manager.list_components_known().await;
+    component_ids.sort();
 
     let components_info = stream::iter(component_ids)
         .map(|id| async move {
             debug!(component_id = %id, "Getting component details");
+            let wasm_proposals = lifecycle_manager.get_component_wasm_proposals(&id).await;
+            let usage = lifecycle_manager.usage_uri(&id);
+            let warnings = lifecycle_manager.get_component_warnings(&id).await;
             if let Some(schema) = lifecycle_manager.get_component_schema(&id).await {
                 let tools_count = schema
                     .get("tools")
@@ -215,17 +350,23 @@ pub async fn handle_list_components(
                 json!({
                     "id": id,
                     "tools_count": tools_count,
+                    "wasm_proposals": wasm_proposals,
+                    "usage": usage,
+                    "warnings": warnings,
                     "schema": schema
                 })
             } else {
                 json!({
                     "id": id,
                     "tools_count": 0,
+                    "wasm_proposals": wasm_proposals,
+                    "usage": usage,
+                    "warnings": warnings,
                     "schema": null
                 })
             }
         })
-        .buffer_unordered(50)
+        .buffered(50)
         .collect::<Vec<_>>()
         .await;
 
@@ -289,6 +430,7 @@ fn create_load_component_success_result(outcome: &ComponentLoadOutcome) -> Resul
         "status": status,
         "id": &outcome.component_id,
         "tools": &outcome.tool_names,
+        "warnings": &outcome.warnings,
     }))?;
 
     let contents = vec![Content::text(status_text)];
@@ -400,6 +542,15 @@ pub async fn handle_unload_component_cli(
     }
 }
 
+/// CLI-specific version of handle_update_component that doesn't require server peer notifications
+#[instrument(skip(lifecycle_manager))]
+pub async fn handle_update_component_cli(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    update_component(req, lifecycle_manager, None).await
+}
+
 #[instrument]
 pub(crate) fn parse_tool_schema(tool_json: &Value) -> Option<Tool> {
     let name = tool_json
@@ -437,7 +588,9 @@ pub(crate) fn parse_tool_schema(tool_json: &Value) -> Option<Tool> {
         description: Some(Cow::Owned(description.to_string())),
         input_schema: Arc::new(serde_json::from_value(input_schema).unwrap_or_default()),
         output_schema: output_schema_arc,
-        annotations: None,
+        annotations: tool_json
+            .get("annotations")
+            .and_then(|annotations| serde_json::from_value(annotations.clone()).ok()),
     })
 }
 
@@ -477,6 +630,71 @@ mod tests {
         assert_eq!(schema_json, expected);
     }
 
+    #[test]
+    fn test_resource_limit_result() {
+        let error = anyhow::anyhow!("wasm trap: all fuel consumed by WebAssembly").context(
+            ResourceLimitExceeded {
+                component_id: "fib".to_string(),
+                resource: wassette::LimitedResource::Fuel,
+                limit: 1000,
+            },
+        );
+        let exceeded = error.downcast_ref::<ResourceLimitExceeded>().unwrap();
+        let result = resource_limit_result(exceeded);
+
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(
+            result.structured_content,
+            Some(json!({
+                "error": "resource_limit_exceeded",
+                "component_id": "fib",
+                "resource": "fuel",
+                "limit": 1000,
+            }))
+        );
+        let text = serde_json::to_value(&result.content.unwrap()[0]).unwrap();
+        assert_eq!(
+            text["text"],
+            "Error: resource limit exceeded: component fib ran into its `fuel` limit of 1000"
+        );
+    }
+
+    #[test]
+    fn test_timeout_result() {
+        let result = timeout_result(&CallTimedOut {
+            component_id: "crawl".to_string(),
+            timeout: std::time::Duration::from_secs(30),
+        });
+
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(
+            result.structured_content,
+            Some(json!({
+                "error": "timeout",
+                "component_id": "crawl",
+                "timeout_ms": 30000,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_outside_hours_result() {
+        let result = outside_hours_result(&OutsideAllowedHours {
+            component_id: "db".to_string(),
+            tool: "drop-table".to_string(),
+            allowed_hours: "09:00-18:00 Mon-Fri".to_string(),
+            confirmable: true,
+        });
+
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(result.structured_content.unwrap()["confirmable"], true);
+        let text = serde_json::to_value(&result.content.unwrap()[0]).unwrap();
+        assert!(text["text"]
+            .as_str()
+            .unwrap()
+            .ends_with("call the tool again within the allowed hours."));
+    }
+
     #[test]
     fn test_extract_args_from_request() {
         let req = CallToolRequestParam {
@@ -3,11 +3,35 @@
 
 pub use wassette::LifecycleManager;
 
+pub mod canonical;
+pub mod completions;
 pub mod components;
+pub mod logging;
+mod progress;
 pub mod prompts;
 pub mod resources;
+pub mod roots;
+pub mod sampling;
+pub mod server_info;
+pub mod throttle;
 pub mod tools;
+pub mod trace_context;
 
-pub use prompts::handle_prompts_list;
-pub use resources::handle_resources_list;
-pub use tools::{handle_tools_call, handle_tools_list};
+pub use canonical::{canonicalize_resource, canonicalize_tool_result, canonicalize_tools};
+pub use completions::handle_completion;
+pub use logging::{
+    forward_anomalies, forward_component_output, forward_rollout_events, ComponentFilter,
+};
+pub use prompts::{forward_prompt_changes, handle_prompts_get, handle_prompts_list};
+pub use resources::{
+    forward_resource_changes, handle_resources_list, handle_resources_read,
+    handle_resources_subscribe, handle_resources_unsubscribe, ResourceSubscriptions,
+};
+pub use roots::sync_workspace_roots;
+pub use server_info::ServerDetails;
+pub use throttle::{CallThrottle, CallThrottled};
+pub use tools::{
+    caller_context, handle_tools_call, handle_tools_list, is_builtin_tool, requested_overrides,
+    CallerSubject,
+};
+pub use trace_context::adopt_trace_context;
@@ -0,0 +1,144 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Forwarding of component output, rollout stages and anomalies to the MCP client's log
+//! channel.
+
+use std::sync::Arc;
+
+use rmcp::model::{LoggingLevel, LoggingMessageNotificationParam};
+use rmcp::{Peer, RoleServer};
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, instrument, warn};
+use wassette::{Anomaly, LifecycleManager, OutputLine, RolloutEvent, RolloutStage, StdioStream};
+
+/// Logger of the notifications reporting rollout stages
+pub const ROLLOUT_LOGGER: &str = "wassette.rollout";
+
+/// Logger of the notifications reporting anomalous tool calls
+pub const ANOMALY_LOGGER: &str = "wassette.anomaly";
+
+/// Whether a session may see the events of the component with the given id, e.g. as the
+/// scope of its access token allows.
+pub type ComponentFilter = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Send the lines the components `may_see` allows write to streams with the `log` sink to the
+/// client as `notifications/message`, until the client goes away.
+#[instrument(skip_all)]
+pub async fn forward_component_output(
+    peer: Peer<RoleServer>,
+    lifecycle_manager: LifecycleManager,
+    may_see: ComponentFilter,
+) {
+    let mut lines = lifecycle_manager.subscribe_output();
+    loop {
+        let line = match lines.recv().await {
+            Ok(line) => line,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "Dropped component output lines for a slow client");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if !may_see(&line.component_id) {
+            continue;
+        }
+        if let Err(error) = peer.notify_logging_message(log_message(line)).await {
+            debug!(%error, "Stopped forwarding component output");
+            return;
+        }
+    }
+}
+
+fn log_message(line: OutputLine) -> LoggingMessageNotificationParam {
+    let level = match line.stream {
+        StdioStream::Stdout => LoggingLevel::Info,
+        StdioStream::Stderr => LoggingLevel::Warning,
+    };
+    LoggingMessageNotificationParam {
+        level,
+        logger: Some(line.component_id),
+        data: json!({ "stream": line.stream, "line": line.line }),
+    }
+}
+
+/// Send the stages of the rollouts of the components `may_see` allows to the client as
+/// `notifications/message`, until the client goes away.
+#[instrument(skip_all)]
+pub async fn forward_rollout_events(
+    peer: Peer<RoleServer>,
+    lifecycle_manager: LifecycleManager,
+    may_see: ComponentFilter,
+) {
+    let mut events = lifecycle_manager.subscribe_rollouts();
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "Dropped rollout events for a slow client");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if !may_see(&event.component_id) {
+            continue;
+        }
+        if let Err(error) = peer.notify_logging_message(rollout_message(event)).await {
+            debug!(%error, "Stopped forwarding rollout events");
+            return;
+        }
+    }
+}
+
+fn rollout_message(event: RolloutEvent) -> LoggingMessageNotificationParam {
+    let level = match event.stage {
+        RolloutStage::RolledBack => LoggingLevel::Warning,
+        _ => LoggingLevel::Info,
+    };
+    LoggingMessageNotificationParam {
+        level,
+        logger: Some(ROLLOUT_LOGGER.to_string()),
+        data: json!(event),
+    }
+}
+
+/// Send the tool calls that deviated from the baseline of their component to the client as
+/// warnings in `notifications/message`, for the components `may_see` allows, until the client
+/// goes away.
+#[instrument(skip_all)]
+pub async fn forward_anomalies(
+    peer: Peer<RoleServer>,
+    lifecycle_manager: LifecycleManager,
+    may_see: ComponentFilter,
+) {
+    let mut anomalies = lifecycle_manager.subscribe_anomalies();
+    loop {
+        let anomaly = match anomalies.recv().await {
+            Ok(anomaly) => anomaly,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "Dropped anomalies for a slow client");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if !may_see(&anomaly.component_id) {
+            continue;
+        }
+        if let Err(error) = peer.notify_logging_message(anomaly_message(anomaly)).await {
+            debug!(%error, "Stopped forwarding anomalies");
+            return;
+        }
+    }
+}
+
+fn anomaly_message(anomaly: Anomaly) -> LoggingMessageNotificationParam {
+    let message = anomaly.to_string();
+    let mut data = json!(anomaly);
+    data["message"] = json!(message);
+    LoggingMessageNotificationParam {
+        level: LoggingLevel::Warning,
+        logger: Some(ANOMALY_LOGGER.to_string()),
+        data,
+    }
+}
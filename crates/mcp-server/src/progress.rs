@@ -0,0 +1,205 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! MCP progress notifications and cancellation for long-running component loads and tool
+//! calls.
+
+use rmcp::model::{ProgressNotificationParam, ProgressToken};
+use rmcp::service::RequestContext;
+use rmcp::{Peer, RoleServer};
+use tokio::sync::mpsc;
+use tracing::debug;
+use wassette::{CallContext, CallProgress, LoadContext, LoadProgress, LoadStage};
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// Build the [`LoadContext`] for a `load-component` request: the load is abandoned when the
+/// client cancels the request, and progress is sent as `notifications/progress` if the client
+/// asked for it with a progress token.
+pub(crate) fn load_context(ctx: &RequestContext<RoleServer>) -> LoadContext {
+    let context = LoadContext::default().with_cancellation(ctx.ct.clone());
+    let Some(token) = ctx.meta.get_progress_token() else {
+        return context;
+    };
+
+    // Notifications are sent from a task of their own so a slow client never stalls the
+    // download; the task ends once the load drops its context.
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(forward_progress(ctx.peer.clone(), token, receiver));
+    context.with_progress(move |progress| {
+        let _ = sender.send(progress);
+    })
+}
+
+async fn forward_progress(
+    peer: Peer<RoleServer>,
+    token: ProgressToken,
+    mut updates: mpsc::UnboundedReceiver<LoadProgress>,
+) {
+    let mut last = 0.0;
+    while let Some(update) = updates.recv().await {
+        let param = notification(&token, update, &mut last);
+        if let Err(error) = peer.notify_progress(param).await {
+            debug!(%error, "Failed to send load progress");
+            return;
+        }
+    }
+}
+
+/// Add to `context` what a component tool call needs from its request: the call is stopped
+/// when the client cancels the request, and the progress the component reports is sent as
+/// `notifications/progress` if the client asked for it with a progress token.
+pub(crate) fn call_context(ctx: &RequestContext<RoleServer>, context: CallContext) -> CallContext {
+    let context = context.with_cancellation(ctx.ct.clone());
+    let Some(token) = ctx.meta.get_progress_token() else {
+        return context;
+    };
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(forward_call_progress(ctx.peer.clone(), token, receiver));
+    context.with_progress(move |progress| {
+        let _ = sender.send(progress);
+    })
+}
+
+async fn forward_call_progress(
+    peer: Peer<RoleServer>,
+    token: ProgressToken,
+    mut updates: mpsc::UnboundedReceiver<CallProgress>,
+) {
+    let mut last = None;
+    while let Some(update) = updates.recv().await {
+        let Some(param) = call_notification(&token, update, &mut last) else {
+            continue;
+        };
+        if let Err(error) = peer.notify_progress(param).await {
+            debug!(%error, "Failed to send call progress");
+            return;
+        }
+    }
+}
+
+/// Turn a component's progress report into a progress notification, out of 100. MCP requires
+/// progress to increase, so a report that only changes the message advances it halfway to the
+/// next percent; `None` once that no longer increases it.
+fn call_notification(
+    token: &ProgressToken,
+    update: CallProgress,
+    last: &mut Option<f64>,
+) -> Option<ProgressNotificationParam> {
+    let percent = f64::from(update.percent);
+    let progress = match *last {
+        Some(last) if percent <= last => last + (last.floor() + 1.0 - last) / 2.0,
+        _ => percent,
+    };
+    if last.is_some_and(|last| progress <= last) {
+        return None;
+    }
+    *last = Some(progress);
+    Some(ProgressNotificationParam {
+        progress_token: token.clone(),
+        progress,
+        total: Some(100.0),
+        message: update.message,
+    })
+}
+
+/// Turn a load update into a progress notification. MCP requires progress to increase, so
+/// stages without a byte count advance it by one.
+fn notification(
+    token: &ProgressToken,
+    update: LoadProgress,
+    last: &mut f64,
+) -> ProgressNotificationParam {
+    let (progress, total, message) = match update.stage {
+        LoadStage::Downloading => {
+            let bytes = update.bytes as f64;
+            let message = match update.total_bytes {
+                Some(total) => format!(
+                    "Downloading component: {:.1} of {:.1} MiB",
+                    bytes / MIB,
+                    total as f64 / MIB
+                ),
+                None => format!("Downloading component: {:.1} MiB", bytes / MIB),
+            };
+            (
+                bytes.max(*last),
+                update.total_bytes.map(|total| total as f64),
+                message,
+            )
+        }
+        LoadStage::Compiling => (*last + 1.0, None, "Compiling component".to_string()),
+    };
+    *last = progress;
+    ProgressNotificationParam {
+        progress_token: token.clone(),
+        progress,
+        total,
+        message: Some(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::model::NumberOrString;
+
+    use super::*;
+
+    #[test]
+    fn test_progress_only_increases() {
+        let token = ProgressToken(NumberOrString::Number(1));
+        let mut last = 0.0;
+
+        let download = notification(
+            &token,
+            LoadProgress {
+                stage: LoadStage::Downloading,
+                bytes: 2 * 1024 * 1024,
+                total_bytes: Some(4 * 1024 * 1024),
+            },
+            &mut last,
+        );
+        assert_eq!(download.progress, 2.0 * MIB);
+        assert_eq!(download.total, Some(4.0 * MIB));
+        assert_eq!(
+            download.message.as_deref(),
+            Some("Downloading component: 2.0 of 4.0 MiB")
+        );
+
+        let compile = notification(
+            &token,
+            LoadProgress {
+                stage: LoadStage::Compiling,
+                bytes: 0,
+                total_bytes: None,
+            },
+            &mut last,
+        );
+        assert!(compile.progress > download.progress);
+        assert_eq!(compile.message.as_deref(), Some("Compiling component"));
+    }
+
+    #[test]
+    fn test_call_progress_only_increases() {
+        let token = ProgressToken(NumberOrString::Number(1));
+        let mut last = None;
+        let report = |percent, message: &str| CallProgress {
+            percent,
+            message: Some(message.to_string()),
+        };
+
+        let first = call_notification(&token, report(0, "Starting"), &mut last).unwrap();
+        assert_eq!(first.progress, 0.0);
+        assert_eq!(first.total, Some(100.0));
+        assert_eq!(first.message.as_deref(), Some("Starting"));
+
+        let fetching = call_notification(&token, report(40, "Fetching"), &mut last).unwrap();
+        assert_eq!(fetching.progress, 40.0);
+        let parsing = call_notification(&token, report(40, "Parsing"), &mut last).unwrap();
+        assert_eq!(parsing.progress, 40.5);
+        let writing = call_notification(&token, report(40, "Writing"), &mut last).unwrap();
+        assert_eq!(writing.progress, 40.75);
+        let done = call_notification(&token, report(41, "Done"), &mut last).unwrap();
+        assert_eq!(done.progress, 41.0);
+    }
+}
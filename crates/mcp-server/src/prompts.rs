@@ -1,14 +1,115 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT license.
 
+//! MCP prompts from the prompt templates components export.
+
+use std::collections::HashMap;
+
 use anyhow::Result;
-use rmcp::model::{ListPromptsRequest, ListPromptsResult};
+use rmcp::model::{
+    GetPromptRequestParam, GetPromptResult, ListPromptsResult, Prompt, PromptArgument,
+    PromptMessage, PromptMessageRole,
+};
+use rmcp::{Peer, RoleServer};
+use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, instrument};
+use wassette::LifecycleManager;
+
+use crate::logging::ComponentFilter;
 
-pub async fn handle_prompts_list(req: serde_json::Value) -> Result<serde_json::Value> {
-    let _parsed_req: ListPromptsRequest = serde_json::from_value(req)?;
-    let response = ListPromptsResult {
-        prompts: vec![],
+pub fn handle_prompts_list(lifecycle_manager: &LifecycleManager) -> ListPromptsResult {
+    let prompts = lifecycle_manager
+        .list_prompts()
+        .into_iter()
+        .map(|prompt| {
+            let arguments = prompt
+                .prompt
+                .arguments
+                .into_iter()
+                .map(|argument| PromptArgument {
+                    name: argument.name,
+                    description: argument.description,
+                    required: Some(argument.required),
+                })
+                .collect();
+            Prompt {
+                name: prompt.name,
+                description: prompt.prompt.description,
+                arguments: Some(arguments),
+            }
+        })
+        .collect();
+    ListPromptsResult {
+        prompts,
         next_cursor: None,
-    };
-    Ok(serde_json::to_value(response)?)
+    }
+}
+
+/// Render the prompt in `req` as a single user message.
+pub fn handle_prompts_get(
+    req: &GetPromptRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<GetPromptResult> {
+    let arguments: HashMap<String, String> = req
+        .arguments
+        .iter()
+        .flatten()
+        .map(|(name, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            (name.clone(), value)
+        })
+        .collect();
+    let (prompt, text) = lifecycle_manager.get_prompt(&req.name, &arguments)?;
+    Ok(GetPromptResult {
+        description: prompt.prompt.description,
+        messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+    })
+}
+
+/// Send `notifications/prompts/list_changed` to the client when components `may_see` allows
+/// add or drop prompts, until the client goes away.
+#[instrument(skip_all)]
+pub async fn forward_prompt_changes(
+    peer: Peer<RoleServer>,
+    lifecycle_manager: LifecycleManager,
+    may_see: ComponentFilter,
+) {
+    let mut changes = lifecycle_manager.subscribe_prompts();
+    loop {
+        match changes.recv().await {
+            Ok(component_id) if !may_see(&component_id) => continue,
+            // Missed changes may have been of any component; listing again tells nothing
+            // beyond what the client may see.
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return,
+        }
+        if let Err(error) = peer.notify_prompt_list_changed().await {
+            debug!(%error, "Stopped forwarding prompt changes");
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_no_prompts() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new_unloaded(tempdir.path()).await?;
+        assert!(handle_prompts_list(&manager).prompts.is_empty());
+
+        let req = GetPromptRequestParam {
+            name: "forecast".to_string(),
+            arguments: None,
+        };
+        let error = handle_prompts_get(&req, &manager).unwrap_err();
+        assert_eq!(error.to_string(), "Prompt not found: forecast");
+        Ok(())
+    }
 }
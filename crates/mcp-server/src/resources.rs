@@ -1,14 +1,264 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT license.
 
-use anyhow::Result;
-use rmcp::model::{ListResourcesRequest, ListResourcesResult};
+//! The `wassette://server` and `wassette://updates` resources and the resources components
+//! publish, with subscriptions to their updates.
 
-pub async fn handle_resources_list(req: serde_json::Value) -> Result<serde_json::Value> {
-    let _parsed_req: ListResourcesRequest = serde_json::from_value(req)?;
-    let response = ListResourcesResult {
-        resources: vec![],
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+use base64::Engine;
+use rmcp::model::{
+    AnnotateAble, ListResourcesResult, RawResource, ReadResourceRequestParam, ReadResourceResult,
+    ResourceContents, ResourceUpdatedNotificationParam, SubscribeRequestParam,
+    UnsubscribeRequestParam,
+};
+use rmcp::{Peer, RoleServer};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, instrument, warn};
+use wassette::{LifecycleManager, ResourceChange};
+
+use crate::logging::ComponentFilter;
+use crate::server_info::{server_info, ServerDetails, SERVER_RESOURCE_URI};
+
+/// URI of the resource listing the recent version changes of components.
+pub const UPDATES_RESOURCE_URI: &str = "wassette://updates";
+
+/// Version changes the updates resource lists.
+const RECENT_UPDATES: usize = 50;
+
+/// URIs of the resources a client subscribed to.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceSubscriptions(Arc<Mutex<HashSet<String>>>);
+
+impl ResourceSubscriptions {
+    fn uris(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether the client subscribed to `uri`.
+    pub fn contains(&self, uri: &str) -> bool {
+        self.uris().contains(uri)
+    }
+}
+
+pub fn handle_resources_list(lifecycle_manager: &LifecycleManager) -> ListResourcesResult {
+    let server = RawResource {
+        description: Some(
+            "Version, transport, host interfaces and enforced limits of this server".to_string(),
+        ),
+        mime_type: Some("application/json".to_string()),
+        ..RawResource::new(SERVER_RESOURCE_URI, "server")
+    };
+    let updates = RawResource {
+        description: Some(
+            "Recent installs, updates and rollbacks of components, newest first".to_string(),
+        ),
+        mime_type: Some("application/json".to_string()),
+        ..RawResource::new(UPDATES_RESOURCE_URI, "updates")
+    };
+    let published = lifecycle_manager
+        .published_resources()
+        .into_iter()
+        .map(|resource| {
+            RawResource {
+                description: resource.info.description,
+                mime_type: resource.info.mime_type,
+                ..RawResource::new(resource.uri, resource.info.name)
+            }
+            .no_annotation()
+        });
+    ListResourcesResult {
+        resources: [server.no_annotation(), updates.no_annotation()]
+            .into_iter()
+            .chain(published)
+            .collect(),
         next_cursor: None,
+    }
+}
+
+pub async fn handle_resources_read(
+    req: &ReadResourceRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    server_details: &ServerDetails,
+) -> Result<ReadResourceResult> {
+    if req.uri == SERVER_RESOURCE_URI {
+        let info = server_info(server_details, lifecycle_manager);
+        return Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: SERVER_RESOURCE_URI.to_string(),
+                mime_type: Some("application/json".to_string()),
+                text: serde_json::to_string(&info)?,
+            }],
+        });
+    }
+    if req.uri == UPDATES_RESOURCE_URI {
+        let changes = lifecycle_manager
+            .component_history(None, Some(RECENT_UPDATES))
+            .await?;
+        return Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: UPDATES_RESOURCE_URI.to_string(),
+                mime_type: Some("application/json".to_string()),
+                text: serde_json::to_string(&serde_json::json!({ "changes": changes }))?,
+            }],
+        });
+    }
+
+    let (resource, data) = lifecycle_manager.read_published_resource(&req.uri).await?;
+    let mime_type = resource.info.mime_type;
+    let contents = match String::from_utf8(data) {
+        Ok(text) => ResourceContents::TextResourceContents {
+            uri: resource.uri,
+            mime_type,
+            text,
+        },
+        Err(error) => ResourceContents::BlobResourceContents {
+            uri: resource.uri,
+            mime_type,
+            blob: base64::engine::general_purpose::STANDARD.encode(error.into_bytes()),
+        },
     };
-    Ok(serde_json::to_value(response)?)
+    Ok(ReadResourceResult {
+        contents: vec![contents],
+    })
+}
+
+/// Subscribe the client to updates of the resource in `req`, which must exist.
+pub fn handle_resources_subscribe(
+    req: &SubscribeRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    subscriptions: &ResourceSubscriptions,
+) -> Result<()> {
+    let known = req.uri == SERVER_RESOURCE_URI
+        || lifecycle_manager
+            .published_resources()
+            .iter()
+            .any(|resource| resource.uri == req.uri);
+    if !known {
+        bail!("Unknown resource: {}", req.uri);
+    }
+    subscriptions.uris().insert(req.uri.clone());
+    Ok(())
+}
+
+pub fn handle_resources_unsubscribe(
+    req: &UnsubscribeRequestParam,
+    subscriptions: &ResourceSubscriptions,
+) {
+    subscriptions.uris().remove(&req.uri);
+}
+
+/// Send `notifications/resources/list_changed` to the client when components `may_see` allows
+/// publish or unpublish resources, and `notifications/resources/updated` when they publish a
+/// resource the client subscribed to again, until the client goes away.
+#[instrument(skip_all)]
+pub async fn forward_resource_changes(
+    peer: Peer<RoleServer>,
+    lifecycle_manager: LifecycleManager,
+    subscriptions: ResourceSubscriptions,
+    may_see: ComponentFilter,
+) {
+    let mut changes = lifecycle_manager.subscribe_resources();
+    loop {
+        let change = match changes.recv().await {
+            Ok(change) => Some(change),
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "Dropped resource changes for a slow client");
+                // Have the client list the resources again rather than miss one; listing
+                // tells it nothing beyond what it may see.
+                None
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let result = match change {
+            Some(ResourceChange::ListChanged(component_id)) if !may_see(&component_id) => continue,
+            Some(ResourceChange::ListChanged(_)) | None => {
+                peer.notify_resource_list_changed().await
+            }
+            // Subscribing took the right to read the resource.
+            Some(ResourceChange::Updated(uri)) if subscriptions.contains(&uri) => {
+                peer.notify_resource_updated(ResourceUpdatedNotificationParam { uri })
+                    .await
+            }
+            Some(ResourceChange::Updated(_)) => continue,
+        };
+        if let Err(error) = result {
+            debug!(%error, "Stopped forwarding resource changes");
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_server_resource() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new_unloaded(tempdir.path()).await?;
+        let resources = handle_resources_list(&manager).resources;
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources[0].raw.uri, SERVER_RESOURCE_URI);
+        assert_eq!(resources[1].raw.uri, UPDATES_RESOURCE_URI);
+
+        let details = ServerDetails {
+            version: "0.3.0".to_string(),
+            ..Default::default()
+        };
+        let req = ReadResourceRequestParam {
+            uri: SERVER_RESOURCE_URI.to_string(),
+        };
+        let result = handle_resources_read(&req, &manager, &details).await?;
+        let ResourceContents::TextResourceContents { text, .. } = &result.contents[0] else {
+            panic!("expected text contents");
+        };
+        let info: serde_json::Value = serde_json::from_str(text)?;
+        assert_eq!(info["version"], "0.3.0");
+
+        let req = ReadResourceRequestParam {
+            uri: UPDATES_RESOURCE_URI.to_string(),
+        };
+        let result = handle_resources_read(&req, &manager, &details).await?;
+        let ResourceContents::TextResourceContents { text, .. } = &result.contents[0] else {
+            panic!("expected text contents");
+        };
+        let updates: serde_json::Value = serde_json::from_str(text)?;
+        assert_eq!(updates["changes"], serde_json::json!([]));
+
+        let req = ReadResourceRequestParam {
+            uri: "wassette://elsewhere".to_string(),
+        };
+        assert!(handle_resources_read(&req, &manager, &details)
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new_unloaded(tempdir.path()).await?;
+        let subscriptions = ResourceSubscriptions::default();
+
+        let req = SubscribeRequestParam {
+            uri: SERVER_RESOURCE_URI.to_string(),
+        };
+        handle_resources_subscribe(&req, &manager, &subscriptions)?;
+        assert!(subscriptions.contains(SERVER_RESOURCE_URI));
+
+        let req = SubscribeRequestParam {
+            uri: wassette::resource_uri("weather", "today.txt"),
+        };
+        assert!(handle_resources_subscribe(&req, &manager, &subscriptions).is_err());
+
+        let req = UnsubscribeRequestParam {
+            uri: SERVER_RESOURCE_URI.to_string(),
+        };
+        handle_resources_unsubscribe(&req, &subscriptions);
+        assert!(!subscriptions.contains(SERVER_RESOURCE_URI));
+        Ok(())
+    }
 }
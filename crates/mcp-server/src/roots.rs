@@ -0,0 +1,35 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Tracking of the MCP client's workspace roots for workspace components.
+
+use anyhow::Result;
+use rmcp::{Peer, RoleServer};
+use tracing::{debug, info, instrument};
+use wassette::LifecycleManager;
+
+/// Fetch the client's roots and hand them to the lifecycle manager, so workspace components
+/// can read the open project. Does nothing if no component is opted in or the client doesn't
+/// support roots.
+#[instrument(skip_all)]
+pub async fn sync_workspace_roots(
+    peer: &Peer<RoleServer>,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<()> {
+    if !lifecycle_manager.has_workspace_components() {
+        return Ok(());
+    }
+    let supported = peer
+        .peer_info()
+        .is_some_and(|info| info.capabilities.roots.is_some());
+    if !supported {
+        debug!("Client does not support roots");
+        return Ok(());
+    }
+
+    let result = peer.list_roots().await?;
+    let uris: Vec<String> = result.roots.into_iter().map(|root| root.uri).collect();
+    let roots = lifecycle_manager.set_workspace_roots(&uris);
+    info!(?roots, "Updated workspace roots");
+    Ok(())
+}
@@ -0,0 +1,157 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Forwarding of component sampling requests to the connected MCP client, and of permission
+//! prompts, which go to the client as sampling requests too.
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rmcp::model::{Content, CreateMessageRequestParam, Role, SamplingMessage};
+use rmcp::{Peer, RoleServer};
+use serde_json::{json, Value};
+use tracing::debug;
+use wassette::{
+    OutsideAllowedHours, PermissionPrompter, PermissionRequest, Sampler, SamplingRequest,
+};
+
+/// Instructions sent with permission prompts
+const PERMISSION_PROMPT: &str = "A tool is asking for a permission its policy doesn't grant. \
+Show the request to the user and ask whether to grant it. Reply with the single word `allow` \
+if the user approves, and `deny` otherwise. Never approve on the user's behalf.";
+
+/// Instructions sent with confirmations of calls outside a tool's allowed hours
+const CONFIRM_PROMPT: &str = "A tool is being called outside the hours its policy allows, \
+which it only may with the user's confirmation. Show the call to the user and ask whether to \
+run it. Reply with the single word `allow` if the user confirms, and `deny` otherwise. Never \
+confirm on the user's behalf.";
+
+/// Answers sampling requests with `sampling/createMessage` calls to the client.
+pub struct PeerSampler {
+    peer: Peer<RoleServer>,
+}
+
+impl PeerSampler {
+    /// Create a sampler for `peer`, or `None` if the client doesn't support sampling.
+    pub fn for_peer(peer: Peer<RoleServer>) -> Option<Self> {
+        let supported = peer
+            .peer_info()
+            .is_some_and(|info| info.capabilities.sampling.is_some());
+        supported.then_some(Self { peer })
+    }
+}
+
+impl Sampler for PeerSampler {
+    fn sample(&self, request: SamplingRequest) -> BoxFuture<'_, Result<String>> {
+        async move {
+            debug!(
+                max_tokens = request.max_tokens,
+                "Forwarding sampling request"
+            );
+            let result = self
+                .peer
+                .create_message(CreateMessageRequestParam {
+                    messages: vec![SamplingMessage {
+                        role: Role::User,
+                        content: Content::text(request.prompt),
+                    }],
+                    model_preferences: None,
+                    system_prompt: request.system_prompt,
+                    include_context: None,
+                    temperature: None,
+                    max_tokens: request.max_tokens,
+                    stop_sequences: None,
+                    metadata: None,
+                })
+                .await?;
+            result
+                .message
+                .content
+                .as_text()
+                .map(|text| text.text.clone())
+                .ok_or_else(|| anyhow!("Client returned a non-text sampling result"))
+        }
+        .boxed()
+    }
+}
+
+/// Asks the user whether to grant permissions, and to run calls outside the allowed hours of
+/// their tool, with `sampling/createMessage` calls to the client, which shows them to the user for review. The answer is only as trustworthy as that
+/// review: the client's model may write it instead of the user, so approvals last for the call
+/// unless `persist` is configured. Prompts move to elicitation, which asks the user directly,
+/// once the MCP SDK supports it.
+pub struct PeerPrompter {
+    peer: Peer<RoleServer>,
+}
+
+impl PeerPrompter {
+    /// Create a prompter for `peer`, or `None` if the client doesn't support sampling.
+    pub fn for_peer(peer: Peer<RoleServer>) -> Option<Self> {
+        let supported = peer
+            .peer_info()
+            .is_some_and(|info| info.capabilities.sampling.is_some());
+        supported.then_some(Self { peer })
+    }
+}
+
+impl PeerPrompter {
+    /// Put `text` to the user with the instructions `system_prompt`, returning whether they
+    /// answered `allow`.
+    async fn ask(&self, system_prompt: &str, text: String, metadata: Value) -> Result<bool> {
+        let result = self
+            .peer
+            .create_message(CreateMessageRequestParam {
+                messages: vec![SamplingMessage {
+                    role: Role::User,
+                    content: Content::text(text),
+                }],
+                model_preferences: None,
+                system_prompt: Some(system_prompt.to_string()),
+                include_context: None,
+                temperature: Some(0.0),
+                max_tokens: 8,
+                stop_sequences: None,
+                metadata: Some(json!({ "wassette": metadata })),
+            })
+            .await?;
+        let answer = result
+            .message
+            .content
+            .as_text()
+            .map(|text| text.text.trim().trim_matches('`').to_string())
+            .ok_or_else(|| anyhow!("Client returned a non-text answer to a permission prompt"))?;
+        Ok(answer.eq_ignore_ascii_case("allow"))
+    }
+}
+
+impl PermissionPrompter for PeerPrompter {
+    fn approve(&self, request: &PermissionRequest) -> BoxFuture<'_, Result<bool>> {
+        let request = request.clone();
+        async move {
+            debug!(%request, "Asking the client for a permission");
+            let metadata = json!({ "permission_request": request });
+            self.ask(PERMISSION_PROMPT, request.to_string(), metadata)
+                .await
+        }
+        .boxed()
+    }
+
+    fn confirm(&self, outside: &OutsideAllowedHours) -> BoxFuture<'_, Result<bool>> {
+        let text = format!(
+            "Tool {} of component {} may only be called {}, and is being called now",
+            outside.tool, outside.component_id, outside.allowed_hours
+        );
+        let metadata = json!({
+            "outside_allowed_hours": {
+                "component_id": outside.component_id,
+                "tool": outside.tool,
+                "allowed_hours": outside.allowed_hours,
+            }
+        });
+        async move {
+            debug!(%text, "Asking the client to confirm a call");
+            self.ask(CONFIRM_PROMPT, text, metadata).await
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Self-description of a running server, returned by the `get-server-info` tool and the
+//! `wassette://server` resource so clients and support scripts can adapt to the deployment.
+
+use anyhow::Result;
+use rmcp::model::{CallToolResult, Content};
+use serde_json::{json, Value};
+use tracing::instrument;
+use wassette::LifecycleManager;
+
+/// URI of the resource describing the server.
+pub const SERVER_RESOURCE_URI: &str = "wassette://server";
+
+/// MCP transports the server can be started with.
+pub const TRANSPORTS: &[&str] = &["stdio", "sse", "streamable-http"];
+
+/// Facts about the server process that the lifecycle manager doesn't know.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerDetails {
+    /// Version of wassette
+    pub version: String,
+    /// Version of the wasmtime runtime wassette was built with
+    pub wasmtime_version: String,
+    /// MCP transport the server is running on, one of [`TRANSPORTS`]
+    pub transport: String,
+}
+
+/// Describe the server: its versions, transports, the host interfaces offered to components
+/// and the resource limits it enforces.
+pub fn server_info(details: &ServerDetails, lifecycle_manager: &LifecycleManager) -> Value {
+    let runtime = lifecycle_manager.runtime_info();
+    json!({
+        "version": details.version,
+        "wasmtime_version": details.wasmtime_version,
+        "transport": details.transport,
+        "transports": TRANSPORTS,
+        "hosts": runtime.hosts,
+        "resource_limits": runtime.resource_limits,
+        "fs_hardening": runtime.fs_hardening,
+        "coredump_on_trap": runtime.coredump_on_trap,
+        "wasm_proposals": runtime.wasm_proposals,
+    })
+}
+
+#[instrument(skip_all)]
+pub(crate) async fn handle_get_server_info(
+    details: &ServerDetails,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    let info = server_info(details, lifecycle_manager);
+    Ok(CallToolResult {
+        content: Some(vec![Content::text(serde_json::to_string(&info)?)]),
+        structured_content: Some(info),
+        is_error: Some(false),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_server_info() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::new_unloaded(tempdir.path()).await?;
+        let details = ServerDetails {
+            version: "0.3.0".to_string(),
+            wasmtime_version: "36.0.2".to_string(),
+            transport: "stdio".to_string(),
+        };
+
+        let info = server_info(&details, &manager);
+        assert_eq!(info["version"], "0.3.0");
+        assert_eq!(info["wasmtime_version"], "36.0.2");
+        assert_eq!(info["transport"], "stdio");
+        assert_eq!(info["transports"], json!(TRANSPORTS));
+        assert!(info["hosts"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("wasi:http")));
+        assert!(info["resource_limits"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("memory")));
+        assert_eq!(info["fs_hardening"], "enforce");
+        assert_eq!(info["wasm_proposals"], json!(["threads", "memory64", "relaxed-simd"]));
+
+        let result = handle_get_server_info(&details, &manager).await?;
+        assert_eq!(result.structured_content, Some(info));
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Per-component caps on tool calls, so one runaway agent can't monopolize the host.
+//!
+//! A component's policy can cap, under `resources.limits`, how many calls of its tools are in
+//! progress at once (`max-concurrent-invocations`) and how many start per minute
+//! (`rate-limit`). The rate is a token bucket per component holding a minute's worth of calls
+//! and refilled continuously, so a burst up to the limit goes through at once. Calls past
+//! either cap are refused right away with a [`CallThrottled`] error telling the client when to
+//! retry, rather than queued. The caps count the calls of every client of the server.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rmcp::model::{ErrorCode, ErrorData};
+use serde_json::json;
+use wassette::InvocationLimits;
+
+/// JSON-RPC error code of calls refused by a [`CallThrottle`], from the range reserved for
+/// implementation-defined server errors.
+pub const THROTTLED: ErrorCode = ErrorCode(-32000);
+
+/// When to retry a call refused for too many calls in progress, since when one of them
+/// finishes can't be known.
+const CONCURRENCY_RETRY: Duration = Duration::from_secs(1);
+
+/// Calls in progress and the token bucket of one component.
+#[derive(Debug)]
+struct ComponentCalls {
+    limits: InvocationLimits,
+    in_progress: u32,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl ComponentCalls {
+    fn new(limits: InvocationLimits, now: Instant) -> Self {
+        Self {
+            limits,
+            in_progress: 0,
+            tokens: limits.per_minute.map_or(0.0, f64::from),
+            refilled: now,
+        }
+    }
+
+    /// Follow a change of the component's policy, keeping the calls in progress.
+    fn update(&mut self, limits: InvocationLimits, now: Instant) {
+        if self.limits.per_minute != limits.per_minute {
+            let capacity = limits.per_minute.map_or(0.0, f64::from);
+            self.tokens = match self.limits.per_minute {
+                Some(_) => self.tokens.min(capacity),
+                None => capacity,
+            };
+            self.refilled = now;
+        }
+        self.limits = limits;
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(per_minute) = self.limits.per_minute {
+            let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+            self.tokens =
+                (self.tokens + elapsed * f64::from(per_minute) / 60.0).min(f64::from(per_minute));
+        }
+        self.refilled = now;
+    }
+}
+
+/// The caps on the tool calls of every component, shared by all sessions of a server.
+#[derive(Debug, Clone, Default)]
+pub struct CallThrottle {
+    components: Arc<Mutex<HashMap<String, ComponentCalls>>>,
+}
+
+impl CallThrottle {
+    /// Admit a call of a tool of `component_id` within `limits`, counting it as in progress
+    /// until the returned permit is dropped, or refuse it. Without limits no permit is needed.
+    pub fn admit(
+        &self,
+        component_id: &str,
+        limits: InvocationLimits,
+    ) -> Result<Option<CallPermit>, CallThrottled> {
+        self.admit_at(component_id, limits, Instant::now())
+    }
+
+    fn admit_at(
+        &self,
+        component_id: &str,
+        limits: InvocationLimits,
+        now: Instant,
+    ) -> Result<Option<CallPermit>, CallThrottled> {
+        let mut components = self.components.lock().unwrap();
+        if limits == InvocationLimits::default() {
+            // Calls admitted under earlier limits stay counted until their permits are dropped,
+            // in case the limits come back before then.
+            match components.get_mut(component_id) {
+                Some(calls) if calls.in_progress > 0 => calls.update(limits, now),
+                Some(_) => {
+                    components.remove(component_id);
+                }
+                None => {}
+            }
+            return Ok(None);
+        }
+        let calls = components
+            .entry(component_id.to_string())
+            .or_insert_with(|| ComponentCalls::new(limits, now));
+        calls.update(limits, now);
+        calls.refill(now);
+
+        let throttled = |limit, retry_after| CallThrottled {
+            component_id: component_id.to_string(),
+            limit,
+            retry_after,
+        };
+        if let Some(max) = limits.max_concurrent {
+            if calls.in_progress >= max {
+                return Err(throttled("max-concurrent-invocations", CONCURRENCY_RETRY));
+            }
+        }
+        if let Some(per_minute) = limits.per_minute {
+            if calls.tokens < 1.0 {
+                let wait = (1.0 - calls.tokens) * 60.0 / f64::from(per_minute);
+                return Err(throttled("rate-limit", Duration::from_secs_f64(wait)));
+            }
+            calls.tokens -= 1.0;
+        }
+        calls.in_progress += 1;
+        Ok(Some(CallPermit {
+            components: Arc::clone(&self.components),
+            component_id: component_id.to_string(),
+        }))
+    }
+}
+
+/// A call admitted by a [`CallThrottle`], in progress until dropped.
+#[derive(Debug)]
+pub struct CallPermit {
+    components: Arc<Mutex<HashMap<String, ComponentCalls>>>,
+    component_id: String,
+}
+
+impl Drop for CallPermit {
+    fn drop(&mut self) {
+        let mut components = self.components.lock().unwrap();
+        if let Some(calls) = components.get_mut(&self.component_id) {
+            calls.in_progress = calls.in_progress.saturating_sub(1);
+            if calls.in_progress == 0 && calls.limits == InvocationLimits::default() {
+                components.remove(&self.component_id);
+            }
+        }
+    }
+}
+
+/// A tool call refused because its component is past one of its caps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallThrottled {
+    /// The component whose tool was called
+    pub component_id: String,
+    /// The `resources.limits` key of the cap
+    pub limit: &'static str,
+    /// How long until a call may be admitted again
+    pub retry_after: Duration,
+}
+
+impl CallThrottled {
+    /// Whole seconds to wait before retrying, at least one.
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+}
+
+impl fmt::Display for CallThrottled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Component '{}' is past its `{}` limit; retry in {}s",
+            self.component_id,
+            self.limit,
+            self.retry_after_secs()
+        )
+    }
+}
+
+impl std::error::Error for CallThrottled {}
+
+impl From<CallThrottled> for ErrorData {
+    fn from(throttled: CallThrottled) -> Self {
+        let data = json!({
+            "error": "throttled",
+            "component_id": throttled.component_id,
+            "limit": throttled.limit,
+            "retry_after_secs": throttled.retry_after_secs(),
+        });
+        ErrorData::new(THROTTLED, throttled.to_string(), Some(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calls_past_the_caps_are_refused() {
+        let throttle = CallThrottle::default();
+        let start = Instant::now();
+        let limits = InvocationLimits {
+            max_concurrent: Some(2),
+            per_minute: Some(2),
+        };
+
+        let first = throttle.admit_at("fetch", limits, start).unwrap();
+        let second = throttle.admit_at("fetch", limits, start).unwrap();
+        let err = throttle.admit_at("fetch", limits, start).unwrap_err();
+        assert_eq!(err.limit, "max-concurrent-invocations");
+        // Other components have caps of their own.
+        assert!(throttle.admit_at("time", limits, start).is_ok());
+
+        drop((first, second));
+        let err = throttle.admit_at("fetch", limits, start).unwrap_err();
+        assert_eq!(err.limit, "rate-limit");
+        assert_eq!(err.retry_after_secs(), 30);
+        let later = start + Duration::from_secs(30);
+        assert!(throttle.admit_at("fetch", limits, later).is_ok());
+
+        // Without caps nothing is counted.
+        assert!(throttle
+            .admit_at("fetch", InvocationLimits::default(), later)
+            .unwrap()
+            .is_none());
+
+        let error = ErrorData::from(err);
+        assert_eq!(error.code, THROTTLED);
+        assert_eq!(error.data.unwrap()["retry_after_secs"], 30);
+    }
+
+    #[test]
+    fn test_calls_in_progress_outlive_lifted_limits() {
+        let throttle = CallThrottle::default();
+        let now = Instant::now();
+        let limits = InvocationLimits {
+            max_concurrent: Some(1),
+            ..Default::default()
+        };
+        let unlimited = InvocationLimits::default();
+
+        let permit = throttle.admit_at("fetch", limits, now).unwrap();
+        assert!(throttle
+            .admit_at("fetch", unlimited, now)
+            .unwrap()
+            .is_none());
+        // The call admitted before the limit was lifted still counts when it comes back.
+        let err = throttle.admit_at("fetch", limits, now).unwrap_err();
+        assert_eq!(err.limit, "max-concurrent-invocations");
+
+        assert!(throttle
+            .admit_at("fetch", unlimited, now)
+            .unwrap()
+            .is_none());
+        drop(permit);
+        assert!(throttle.components.lock().unwrap().is_empty());
+    }
+}
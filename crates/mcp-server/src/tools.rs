@@ -3,21 +3,24 @@
 
 use std::borrow::Cow;
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::Result;
-use rmcp::model::{CallToolRequestParam, CallToolResult, Content, Tool};
-use rmcp::{Peer, RoleServer};
+use rmcp::model::{CallToolRequestParam, CallToolResult, Content, Meta, Tool};
+use rmcp::service::RequestContext;
+use rmcp::RoleServer;
 use serde_json::{json, Value};
-use tracing::{debug, error, info, instrument};
-use wassette::LifecycleManager;
+use tracing::{debug, error, info, instrument, warn};
+use wassette::{AuditOutcome, AuditRecord, CallContext, CallOverrides, LifecycleManager};
 
 use crate::components::{
     extract_args_from_request, get_component_tools, handle_component_call, handle_list_components,
-    handle_load_component, handle_unload_component,
+    handle_load_component, handle_unload_component, handle_update_component,
 };
-
-/// The list of components that Wassette knows about
-const COMPONENT_LIST: &str = include_str!("../../../component-registry.json");
+use crate::progress::call_context;
+use crate::server_info::{handle_get_server_info, ServerDetails};
+use crate::throttle::{CallPermit, CallThrottle};
+use crate::trace_context::adopt_trace_context;
 
 /// Handles a request to list available tools.
 #[instrument(skip(lifecycle_manager))]
@@ -37,19 +40,35 @@ pub async fn handle_tools_list(lifecycle_manager: &LifecycleManager) -> Result<V
 }
 
 /// Handles a tool call request.
+///
+/// Calls of component tools past the caps of their component in `throttle` fail with a
+/// [`CallThrottled`](crate::CallThrottled) error rather than a tool error.
 #[instrument(skip_all, fields(method_name = %req.name))]
 pub async fn handle_tools_call(
     req: CallToolRequestParam,
     lifecycle_manager: &LifecycleManager,
-    server_peer: Peer<RoleServer>,
+    server_details: &ServerDetails,
+    throttle: &CallThrottle,
+    ctx: RequestContext<RoleServer>,
 ) -> Result<Value> {
+    adopt_trace_context(&ctx.meta);
     info!("Handling tool call");
+    let server_peer = ctx.peer.clone();
+    let context = caller_context(&ctx);
+    let started = Instant::now();
+    let _permit = if is_builtin_tool(&req.name) {
+        None
+    } else {
+        admit_call(&req, lifecycle_manager, throttle, &context).await?
+    };
 
     let result = match req.name.as_ref() {
-        "load-component" => handle_load_component(&req, lifecycle_manager, server_peer).await,
+        "load-component" => handle_load_component(&req, lifecycle_manager, &ctx).await,
         "unload-component" => handle_unload_component(&req, lifecycle_manager, server_peer).await,
+        "update-component" => handle_update_component(&req, lifecycle_manager, server_peer).await,
         "list-components" => handle_list_components(lifecycle_manager).await,
         "get-policy" => handle_get_policy(&req, lifecycle_manager).await,
+        "get-usage" => handle_get_usage(&req, lifecycle_manager).await,
         "grant-storage-permission" => {
             handle_grant_storage_permission(&req, lifecycle_manager).await
         }
@@ -69,13 +88,43 @@ pub async fn handle_tools_call(
             handle_revoke_environment_variable_permission(&req, lifecycle_manager).await
         }
         "search-components" => handle_search_component(&req, lifecycle_manager).await,
+        "inspect-component" => handle_inspect_component(&req, lifecycle_manager).await,
         "reset-permission" => handle_reset_permission(&req, lifecycle_manager).await,
-        _ => handle_component_call(&req, lifecycle_manager).await,
+        "get-server-info" => handle_get_server_info(server_details, lifecycle_manager).await,
+        _ => match requested_overrides(&ctx.meta)
+            .map(CallOverrides::from_json)
+            .transpose()
+        {
+            Ok(overrides) => {
+                let context = call_context(&ctx, context.clone())
+                    .with_overrides(overrides.unwrap_or_default());
+                handle_component_call(&req, lifecycle_manager, server_peer, context).await
+            }
+            Err(e) => Err(e),
+        },
     };
 
     if let Err(ref e) = result {
         error!(error = ?e, "Tool call failed");
     }
+    // Calls of component tools are audited by the lifecycle manager.
+    if is_builtin_tool(&req.name) {
+        let arguments = serde_json::to_string(&req.arguments.clone().unwrap_or_default())?;
+        let outcome = match &result {
+            Ok(CallToolResult {
+                is_error: Some(true),
+                ..
+            }) => AuditOutcome::ToolError,
+            Ok(_) => AuditOutcome::Success,
+            Err(_) => AuditOutcome::Failed,
+        };
+        let record = AuditRecord::tool_call(None, &req.name, &arguments, &context).finished(
+            started.elapsed(),
+            outcome,
+            result.as_ref().err().map(|e| e.to_string()),
+        );
+        lifecycle_manager.audit(&record);
+    }
 
     match result {
         Ok(result) => Ok(serde_json::to_value(result)?),
@@ -93,6 +142,60 @@ pub async fn handle_tools_call(
     }
 }
 
+/// Count a call of a component tool against the caps of its component, auditing it as
+/// refused when it's past them.
+async fn admit_call(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+    throttle: &CallThrottle,
+    context: &CallContext,
+) -> Result<Option<CallPermit>> {
+    // Calls of unknown tools fail on their own.
+    let Ok(component_id) = lifecycle_manager.get_component_id_for_tool(&req.name).await else {
+        return Ok(None);
+    };
+    let limits = lifecycle_manager.invocation_limits(&component_id).await;
+    throttle.admit(&component_id, limits).map_err(|throttled| {
+        warn!(%component_id, limit = throttled.limit, "Refused tool call past its component's cap");
+        let arguments =
+            serde_json::to_string(&req.arguments.clone().unwrap_or_default()).unwrap_or_default();
+        let record = AuditRecord::tool_call(Some(&component_id), &req.name, &arguments, context)
+            .refused(throttled.to_string());
+        lifecycle_manager.audit(&record);
+        throttled.into()
+    })
+}
+
+/// Subject of the access token a request was made with, which the transport that checked
+/// the token puts in the request's extensions.
+#[derive(Debug, Clone)]
+pub struct CallerSubject(pub String);
+
+/// Who makes the request in `ctx`, as far as the audit log and components granted the session
+/// context are concerned.
+pub fn caller_context(ctx: &RequestContext<RoleServer>) -> CallContext {
+    let mut context = CallContext::default();
+    if let Some(info) = ctx.peer.peer_info() {
+        context = context.with_client(&info.client_info.name, &info.client_info.version);
+    }
+    if let Some(CallerSubject(subject)) = ctx.extensions.get::<CallerSubject>() {
+        context = context.with_subject(subject);
+    }
+    context
+}
+
+/// Limit overrides a request asks for in its `_meta`, as `wassette.overrides`.
+///
+/// Embedders that check who may call what should only let trusted callers pass them.
+pub fn requested_overrides(meta: &Meta) -> Option<&Value> {
+    meta.get("wassette")?.get("overrides")
+}
+
+/// Whether `name` is one of the built-in tools rather than a component's.
+pub fn is_builtin_tool(name: &str) -> bool {
+    get_builtin_tools().iter().any(|tool| tool.name == name)
+}
+
 fn get_builtin_tools() -> Vec<Tool> {
     debug!("Getting builtin tools");
     vec![
@@ -132,6 +235,27 @@ fn get_builtin_tools() -> Vec<Tool> {
             output_schema: None,
             annotations: None,
         },
+        Tool {
+            name: Cow::Borrowed("update-component"),
+            description: Some(Cow::Borrowed(
+                "Updates a loaded component to the latest release published where it was loaded from (an OCI tag, an https URL or a signed manifest). The component keeps the permissions granted to it, and the previous release is restored if the new one fails to load. Reports the previous and new digests and versions",
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "component_id": {
+                            "type": "string",
+                            "description": "ID of the component to update"
+                        }
+                    },
+                    "required": ["component_id"]
+                }))
+                .unwrap_or_default(),
+            ),
+            output_schema: None,
+            annotations: None,
+        },
         Tool {
             name: Cow::Borrowed("list-components"),
             description: Some(Cow::Borrowed(
@@ -169,6 +293,27 @@ fn get_builtin_tools() -> Vec<Tool> {
             output_schema: None,
             annotations: None,
         },
+        Tool {
+            name: Cow::Borrowed("get-usage"),
+            description: Some(Cow::Borrowed(
+                "Gets the usage guide a component ships: detailed instructions for its tools, such as workflows, argument formats and examples",
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "component_id": {
+                            "type": "string",
+                            "description": "ID of the component to get the usage guide of"
+                        }
+                    },
+                    "required": ["component_id"]
+                }))
+                .unwrap_or_default(),
+            ),
+            output_schema: None,
+            annotations: None,
+        },
         Tool {
             name: Cow::Borrowed("grant-storage-permission"),
             description: Some(Cow::Borrowed(
@@ -227,7 +372,22 @@ fn get_builtin_tools() -> Vec<Tool> {
                         "properties": {
                           "host": { 
                             "type": "string",
-                            "description": "Host to grant network access to"
+                            "description": "Host to grant network access to, or *.domain for the hosts below a domain"
+                          },
+                          "ports": {
+                            "type": "array",
+                            "items": { "type": "integer", "minimum": 1, "maximum": 65535 },
+                            "description": "Ports requests to the host may use; any port if omitted"
+                          },
+                          "schemes": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "URL schemes requests to the host may use, e.g. https; any scheme if omitted"
+                          },
+                          "address-family": {
+                            "type": "string",
+                            "enum": ["v4-only", "v6-only", "dual"],
+                            "description": "IP versions requests to the host may connect over; dual if omitted"
                           }
                         },
                         "required": ["host"],
@@ -260,6 +420,10 @@ fn get_builtin_tools() -> Vec<Tool> {
                           "key": { 
                             "type": "string",
                             "description": "Environment variable key to grant access to"
+                          },
+                          "value": {
+                            "type": "string",
+                            "description": "Value to give the variable instead of the server's; may refer to the component's secrets as ${secret:name} and to server environment variables as ${env:NAME}"
                           }
                         },
                         "required": ["key"],
@@ -393,12 +557,17 @@ fn get_builtin_tools() -> Vec<Tool> {
         Tool {
             name: Cow::Borrowed("search-components"),
             description: Some(Cow::Borrowed(
-                "Lists all known components that can be fetched and loaded",
+                "Searches the configured component catalogs for components that can be fetched and loaded, with their version and the permissions their policy asks for. Lists every known component without a query",
             )),
             input_schema: Arc::new(
                 serde_json::from_value(json!({
                     "type": "object",
-                    "properties": {},
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "Words the name, description or URI of a component must all contain"
+                        }
+                    },
                     "required": []
                 }))
                 .unwrap_or_default(),
@@ -419,6 +588,19 @@ fn get_builtin_tools() -> Vec<Tool> {
                             "type": "string",
                             "description": "The canonical OCI URI, including the leading `oci://` and `:version` suffix. This string can be directly passed to Wassette's `load component` tool call."
                         },
+                        "version": {
+                            "type": "string",
+                            "description": "Version of the release the catalog describes, if it names one"
+                        },
+                        "permissions": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "What the component's policy grants, e.g. `network: api.github.com`"
+                        },
+                        "index": {
+                            "type": "string",
+                            "description": "The catalog index listing the component"
+                        },
                     },
                     "required": ["name", "description", "uri"]
                 }))
@@ -426,18 +608,58 @@ fn get_builtin_tools() -> Vec<Tool> {
             )),
             annotations: None,
         },
+        Tool {
+            name: Cow::Borrowed("inspect-component"),
+            description: Some(Cow::Borrowed(
+                "Describes a component from the filesystem or an OCI registry without loading it: the interfaces it imports and exports, its tools with their JSON schemas, the permissions it asks for and its size and compile time. Use it to review a component before loading it",
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Location of the component, as for load-component"
+                        }
+                    },
+                    "required": ["path"]
+                }))
+                .unwrap_or_default(),
+            ),
+            output_schema: None,
+            annotations: None,
+        },
+        Tool {
+            name: Cow::Borrowed("get-server-info"),
+            description: Some(Cow::Borrowed(
+                "Describes this Wassette server: its version, the wasmtime version, the transport in use, the host interfaces offered to components and the resource limits it enforces",
+            )),
+            input_schema: Arc::new(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": {},
+                    "required": []
+                }))
+                .unwrap_or_default(),
+            ),
+            output_schema: None,
+            annotations: None,
+        },
     ]
 }
 
-#[instrument(skip(_lifecycle_manager))]
+#[instrument(skip(lifecycle_manager))]
 async fn handle_search_component(
-    _req: &CallToolRequestParam,
-    _lifecycle_manager: &LifecycleManager,
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
 ) -> Result<CallToolResult> {
-    let components_value: Value = serde_json::from_str(COMPONENT_LIST)?;
+    let args = extract_args_from_request(req)?;
+    let query = args.get("query").and_then(|v| v.as_str()).unwrap_or("");
+    let search = lifecycle_manager.search_catalog(query).await;
     let status_text = serde_json::to_string(&json!({
         "status": "Component list found",
-        "components": components_value,
+        "components": search.components,
+        "errors": search.errors,
     }))?;
 
     let contents = vec![Content::text(status_text)];
@@ -449,6 +671,29 @@ async fn handle_search_component(
     })
 }
 
+#[instrument(skip(lifecycle_manager))]
+pub async fn handle_inspect_component(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let path = args
+        .get("path")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'path'"))?;
+
+    info!("Inspecting component {}", path);
+
+    let inspection = lifecycle_manager.inspect_component(path).await?;
+
+    Ok(CallToolResult {
+        content: Some(vec![Content::text(serde_json::to_string(&inspection)?)]),
+        structured_content: None,
+        is_error: None,
+    })
+}
+
 #[instrument(skip(lifecycle_manager))]
 pub async fn handle_get_policy(
     req: &CallToolRequestParam,
@@ -499,6 +744,37 @@ pub async fn handle_get_policy(
     })
 }
 
+#[instrument(skip(lifecycle_manager))]
+pub async fn handle_get_usage(
+    req: &CallToolRequestParam,
+    lifecycle_manager: &LifecycleManager,
+) -> Result<CallToolResult> {
+    let args = extract_args_from_request(req)?;
+
+    let component_id = args
+        .get("component_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Missing required argument: 'component_id'"))?;
+
+    info!("Getting usage guide for component {}", component_id);
+
+    lifecycle_manager
+        .ensure_component_loaded(component_id)
+        .await
+        .map_err(|e| anyhow::anyhow!("Component not found: {} ({})", component_id, e))?;
+
+    let usage = lifecycle_manager
+        .component_usage(component_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Component {component_id} doesn't ship a usage guide"))?;
+
+    Ok(CallToolResult {
+        content: Some(vec![Content::text(usage)]),
+        structured_content: None,
+        is_error: None,
+    })
+}
+
 #[instrument(skip(lifecycle_manager))]
 pub async fn handle_grant_storage_permission(
     req: &CallToolRequestParam,
@@ -956,11 +1232,13 @@ mod tests {
     #[test]
     fn test_get_builtin_tools() {
         let tools = get_builtin_tools();
-        assert_eq!(tools.len(), 12);
+        assert_eq!(tools.len(), 16);
         assert!(tools.iter().any(|t| t.name == "load-component"));
         assert!(tools.iter().any(|t| t.name == "unload-component"));
+        assert!(tools.iter().any(|t| t.name == "update-component"));
         assert!(tools.iter().any(|t| t.name == "list-components"));
         assert!(tools.iter().any(|t| t.name == "get-policy"));
+        assert!(tools.iter().any(|t| t.name == "get-usage"));
         assert!(tools.iter().any(|t| t.name == "grant-storage-permission"));
         assert!(tools.iter().any(|t| t.name == "grant-network-permission"));
         assert!(tools
@@ -973,6 +1251,10 @@ mod tests {
             .any(|t| t.name == "revoke-environment-variable-permission"));
         assert!(tools.iter().any(|t| t.name == "reset-permission"));
         assert!(tools.iter().any(|t| t.name == "search-components"));
+        assert!(tools.iter().any(|t| t.name == "inspect-component"));
+        assert!(tools.iter().any(|t| t.name == "get-server-info"));
+        assert!(is_builtin_tool("load-component"));
+        assert!(!is_builtin_tool("fetch"));
     }
 
     #[tokio::test]
@@ -0,0 +1,73 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Joining the trace of the MCP client that sent a request.
+//!
+//! Clients that trace their requests pass the W3C trace context in the request's `_meta`,
+//! as `traceparent` and `tracestate`. The span handling the request is parented to it, so
+//! the time spent in Wassette shows up in the client's trace.
+
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TraceContextExt;
+use rmcp::model::{JsonObject, Meta};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Reads the trace context from a request's `_meta`.
+struct MetaExtractor<'a>(&'a JsonObject);
+
+impl Extractor for MetaExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key)?.as_str()
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(String::as_str).collect()
+    }
+}
+
+/// The trace context passed in `meta`, if any.
+fn extract(meta: &Meta) -> Option<opentelemetry::Context> {
+    let context = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&MetaExtractor(meta))
+    });
+    context.span().span_context().is_valid().then_some(context)
+}
+
+/// Parent the current span to the trace context passed in `meta`, if any.
+///
+/// Call it before entering any child span: spans already created keep their parent.
+pub fn adopt_trace_context(meta: &Meta) {
+    if let Some(context) = extract(meta) {
+        tracing::Span::current().set_parent(context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::TraceId;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_trace_context_is_read_from_meta() {
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let mut meta = Meta::new();
+        meta.insert(
+            "traceparent".to_string(),
+            json!("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+        );
+        let context = extract(&meta).expect("trace context");
+        assert_eq!(
+            context.span().span_context().trace_id(),
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+
+        assert!(extract(&Meta::new()).is_none());
+        let mut meta = Meta::new();
+        meta.insert("traceparent".to_string(), json!("not-a-trace"));
+        assert!(extract(&meta).is_none());
+    }
+}
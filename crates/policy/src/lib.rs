@@ -9,10 +9,17 @@
 use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
 
+pub mod network;
 pub mod parser;
+pub mod schedule;
+pub mod schema;
+mod strict;
 pub mod types;
 
+pub use network::{NetworkDenial, NetworkRequest};
 pub use parser::PolicyParser;
+pub use schedule::{AllowedHours, ScheduleZone};
+pub use schema::{policy_schema, validate_against_schema, SchemaViolation, POLICY_SCHEMA};
 pub use types::*;
 
 /// Policy document structure
@@ -0,0 +1,776 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Matching of outgoing requests against the `network` permission
+//!
+//! A rule names where requests may go, by `host` or by `cidr`, and can narrow that down to
+//! some `ports` and URL `schemes`, and a `host` to an `address-family`:
+//!
+//! ```yaml
+//! network:
+//!   allow:
+//!     - host: "*.github.com"     # every name below github.com, not github.com itself
+//!       schemes: [https]
+//!     - host: api.example.com
+//!       ports: [443, 8443]
+//!       address-family: v4-only
+//!     - cidr: 10.20.0.0/16       # requests addressed to an IP address in the range
+//!   deny:
+//!     - host: gist.github.com
+//! ```
+//!
+//! `host` is a host name, `*.` followed by a domain for the names below it, or `*` for any
+//! host. It may also be written as an origin such as `https://api.example.com:8443`, which
+//! restricts the scheme and port as `schemes` and `ports` do. A `cidr` rule matches requests
+//! whose host is an IP address in the range, and the addresses host names resolve to when
+//! connecting: [`PermissionList::check_address`] refuses those a deny `cidr` rule covers, and
+//! those of a host name only allow `cidr` rules let through that none of them covers. A request
+//! is allowed when an allow rule matches it and no deny rule does.
+//!
+//! `address-family` is `v4-only`, `v6-only` or `dual`, the default. A request to an IP address
+//! only matches rules of its family, IPv4-mapped IPv6 addresses counting as IPv4. A request to
+//! a host name is connected over the families of the allow rules matching it, less those of
+//! the deny rules matching it that name one: `deny: [{host: "*", address-family: v6-only}]`
+//! keeps every request off IPv6. [`PermissionList::check_families`] tells which remain.
+
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::{
+    AddressFamily, NetworkPermission, PermissionList, Permissions, PolicyResult, RequestHeader,
+};
+
+/// An outgoing request, as far as network rules are concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkRequest<'a> {
+    /// URL scheme, e.g. `https`
+    pub scheme: &'a str,
+    /// Host name or IP address; IPv6 addresses may be in brackets
+    pub host: &'a str,
+    /// Destination port
+    pub port: u16,
+}
+
+impl NetworkRequest<'_> {
+    fn host(&self) -> &str {
+        let host = self.host.trim_end_matches('.');
+        host.strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host)
+    }
+
+    /// The IP address the request is addressed to, if its host is one
+    fn address(&self) -> Option<IpAddr> {
+        self.host().parse().ok()
+    }
+}
+
+impl AddressFamily {
+    /// Whether `address` is of the family. IPv4-mapped IPv6 addresses count as IPv4.
+    pub fn allows(self, address: IpAddr) -> bool {
+        let v4 = match address {
+            IpAddr::V4(_) => true,
+            IpAddr::V6(address) => address.to_ipv4_mapped().is_some(),
+        };
+        if v4 {
+            self.v4()
+        } else {
+            self.v6()
+        }
+    }
+
+    fn v4(self) -> bool {
+        self != AddressFamily::V6Only
+    }
+
+    fn v6(self) -> bool {
+        self != AddressFamily::V4Only
+    }
+
+    /// The family of IPv4 if `v4` and of IPv6 if `v6`, or `None` for neither
+    fn from_flags(v4: bool, v6: bool) -> Option<Self> {
+        match (v4, v6) {
+            (true, true) => Some(AddressFamily::Dual),
+            (true, false) => Some(AddressFamily::V4Only),
+            (false, true) => Some(AddressFamily::V6Only),
+            (false, false) => None,
+        }
+    }
+}
+
+impl fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AddressFamily::V4Only => "v4-only",
+            AddressFamily::V6Only => "v6-only",
+            AddressFamily::Dual => "dual",
+        })
+    }
+}
+
+impl fmt::Display for NetworkRequest<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}:{}", self.scheme, self.host, self.port)
+    }
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkDenial {
+    /// The deny rule matching the request, or the allow rule naming its host but not its port
+    /// or scheme; for an address its host name resolved to, the deny `cidr` rule covering it
+    /// or the allow `cidr` rule it is outside of. `None` when no rule names the host
+    pub rule: Option<NetworkPermission>,
+    /// Explanation naming the request and the rule
+    pub reason: String,
+}
+
+impl fmt::Display for NetworkDenial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
+impl std::error::Error for NetworkDenial {}
+
+/// A `host` rule taken apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct HostPattern<'a> {
+    /// Scheme of an origin such as `https://api.example.com`
+    pub(crate) scheme: Option<&'a str>,
+    /// Host name, `*.domain` or `*`
+    pub(crate) name: &'a str,
+    /// Port of an origin such as `api.example.com:8443`
+    pub(crate) port: Option<u16>,
+}
+
+impl<'a> HostPattern<'a> {
+    pub(crate) fn parse(host: &'a str) -> Self {
+        let (scheme, rest) = match host.split_once("://") {
+            Some((scheme, rest)) => (Some(scheme), rest),
+            None => (None, host),
+        };
+        let authority = rest.split('/').next().unwrap_or(rest);
+        let (name, port) = match authority.strip_prefix('[') {
+            Some(bracketed) => match bracketed.split_once(']') {
+                Some((address, tail)) => (
+                    address,
+                    tail.strip_prefix(':').and_then(|port| port.parse().ok()),
+                ),
+                None => (authority, None),
+            },
+            None => match authority.rsplit_once(':') {
+                Some((name, port)) if !name.contains(':') => match port.parse() {
+                    Ok(port) => (name, Some(port)),
+                    Err(_) => (authority, None),
+                },
+                _ => (authority, None),
+            },
+        };
+        Self { scheme, name, port }
+    }
+
+    fn matches_host(&self, host: &str) -> bool {
+        if self.name == "*" {
+            return true;
+        }
+        match self.name.strip_prefix("*.") {
+            Some(domain) => host
+                .len()
+                .checked_sub(domain.len() + 1)
+                .filter(|&dot| host.as_bytes()[dot] == b'.' && dot > 0)
+                .and_then(|dot| host.get(dot + 1..))
+                .is_some_and(|suffix| suffix.eq_ignore_ascii_case(domain)),
+            None => host.eq_ignore_ascii_case(self.name.trim_end_matches('.')),
+        }
+    }
+}
+
+/// A `cidr` range parsed, with its prefix length
+pub(crate) fn parse_cidr(cidr: &str) -> Option<(IpAddr, u32)> {
+    let (address, prefix) = cidr.split_once('/')?;
+    let address: IpAddr = address.parse().ok()?;
+    let prefix: u32 = prefix.parse().ok()?;
+    let bits = if address.is_ipv4() { 32 } else { 128 };
+    (prefix <= bits).then_some((address, prefix))
+}
+
+fn cidr_contains((network, prefix): (IpAddr, u32), address: IpAddr) -> bool {
+    match (network, address) {
+        (IpAddr::V4(network), IpAddr::V4(address)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(network) & mask == u32::from(address) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(address)) => match address.to_ipv4_mapped() {
+            Some(address) => cidr_contains((IpAddr::V6(network), prefix), address.into()),
+            None => {
+                let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+        },
+        (IpAddr::V4(_), IpAddr::V6(address)) => address
+            .to_ipv4_mapped()
+            .is_some_and(|address| cidr_contains((network, prefix), address.into())),
+        (IpAddr::V6(_), IpAddr::V4(_)) => false,
+    }
+}
+
+/// The `cidr` rules among `rules`
+fn cidr_rules(rules: &Option<Vec<NetworkPermission>>) -> impl Iterator<Item = &NetworkPermission> {
+    rules
+        .iter()
+        .flatten()
+        .filter(|rule| matches!(rule, NetworkPermission::Cidr(_)))
+}
+
+/// The part of a request a rule doesn't cover
+enum Mismatch {
+    Host,
+    Port,
+    Scheme,
+    AddressFamily,
+}
+
+impl NetworkPermission {
+    /// The host pattern or CIDR range the rule names
+    pub fn destination(&self) -> &str {
+        match self {
+            NetworkPermission::Host(rule) => &rule.host,
+            NetworkPermission::Cidr(rule) => &rule.cidr,
+        }
+    }
+
+    /// The IP versions the rule covers; a `cidr` rule covers those of its range
+    pub fn address_family(&self) -> AddressFamily {
+        match self {
+            NetworkPermission::Host(rule) => rule.address_family.unwrap_or_default(),
+            NetworkPermission::Cidr(_) => AddressFamily::Dual,
+        }
+    }
+
+    /// Check that the rule is well formed
+    pub fn validate(&self) -> PolicyResult<()> {
+        Permissions::validate_network_rule(self)
+    }
+
+    /// Whether the rule covers `request`
+    pub fn matches(&self, request: &NetworkRequest<'_>) -> bool {
+        self.mismatch(request).is_none()
+    }
+
+    fn mismatch(&self, request: &NetworkRequest<'_>) -> Option<Mismatch> {
+        let host = request.host();
+        let inside = match self {
+            NetworkPermission::Host(rule) => HostPattern::parse(&rule.host).matches_host(host),
+            NetworkPermission::Cidr(rule) => parse_cidr(&rule.cidr)
+                .zip(host.parse().ok())
+                .is_some_and(|(range, address)| cidr_contains(range, address)),
+        };
+        if !inside {
+            return Some(Mismatch::Host);
+        }
+        self.mismatch_beyond_host(request)
+    }
+
+    /// The part of `request` other than its host the rule doesn't cover
+    fn mismatch_beyond_host(&self, request: &NetworkRequest<'_>) -> Option<Mismatch> {
+        let (scheme, port, ports, schemes) = match self {
+            NetworkPermission::Host(rule) => {
+                let pattern = HostPattern::parse(&rule.host);
+                (pattern.scheme, pattern.port, &rule.ports, &rule.schemes)
+            }
+            NetworkPermission::Cidr(rule) => (None, None, &rule.ports, &rule.schemes),
+        };
+        let port_allowed = port.is_none_or(|port| port == request.port)
+            && ports
+                .as_ref()
+                .is_none_or(|ports| ports.contains(&request.port));
+        if !port_allowed {
+            return Some(Mismatch::Port);
+        }
+        let scheme_allowed = scheme
+            .is_none_or(|scheme| scheme.eq_ignore_ascii_case(request.scheme))
+            && schemes.as_ref().is_none_or(|schemes| {
+                schemes
+                    .iter()
+                    .any(|scheme| scheme.eq_ignore_ascii_case(request.scheme))
+            });
+        if !scheme_allowed {
+            return Some(Mismatch::Scheme);
+        }
+        if request
+            .address()
+            .is_some_and(|address| !self.address_family().allows(address))
+        {
+            return Some(Mismatch::AddressFamily);
+        }
+        None
+    }
+}
+
+impl RequestHeader {
+    /// Whether the header goes on `request`: on every request unless it names `hosts`, and
+    /// otherwise on those whose host matches one of them as in a `host` rule
+    pub fn applies_to(&self, request: &NetworkRequest<'_>) -> bool {
+        let Some(hosts) = &self.hosts else {
+            return true;
+        };
+        hosts.iter().any(|host| {
+            let pattern = HostPattern::parse(host);
+            pattern.matches_host(request.host())
+                && pattern.port.is_none_or(|port| port == request.port)
+                && pattern
+                    .scheme
+                    .is_none_or(|scheme| scheme.eq_ignore_ascii_case(request.scheme))
+        })
+    }
+}
+
+impl fmt::Display for NetworkPermission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (ports, schemes) = match self {
+            NetworkPermission::Host(rule) => {
+                write!(f, "host: {}", rule.host)?;
+                if let Some(family) = rule.address_family {
+                    write!(f, ", address-family: {family}")?;
+                }
+                (&rule.ports, &rule.schemes)
+            }
+            NetworkPermission::Cidr(rule) => {
+                write!(f, "cidr: {}", rule.cidr)?;
+                (&rule.ports, &rule.schemes)
+            }
+        };
+        if let Some(ports) = ports {
+            write!(f, ", ports: {ports:?}")?;
+        }
+        if let Some(schemes) = schemes {
+            write!(f, ", schemes: [{}]", schemes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl PermissionList<NetworkPermission> {
+    /// Check `request` against the rules: it is refused if a deny rule matches it or no allow
+    /// rule does.
+    pub fn check(&self, request: &NetworkRequest<'_>) -> Result<(), NetworkDenial> {
+        self.check_families(request).map(drop)
+    }
+
+    /// Check `request` as [`check`](Self::check) does, returning the IP versions its host
+    /// name may be connected over. Refuses it if the deny rules leave none.
+    ///
+    /// An allow `cidr` rule covering the port and scheme of a request to a host name lets it
+    /// through here, as the name may resolve into the range: the addresses it resolves to are
+    /// then held to [`check_address`](Self::check_address).
+    pub fn check_families(
+        &self,
+        request: &NetworkRequest<'_>,
+    ) -> Result<AddressFamily, NetworkDenial> {
+        let denied = |rule: &NetworkPermission| NetworkDenial {
+            rule: Some(rule.clone()),
+            reason: format!("{request} is denied by the network rule `{rule}`"),
+        };
+        // Deny rules naming a family take it away from a host name, and deny an address of it.
+        let mut narrowing = Vec::new();
+        for rule in self.deny.iter().flatten() {
+            if !rule.matches(request) {
+                continue;
+            }
+            match rule.address_family() {
+                AddressFamily::Dual => return Err(denied(rule)),
+                _ if request.address().is_some() => return Err(denied(rule)),
+                _ => narrowing.push(rule),
+            }
+        }
+        let (mut v4, mut v6) = (false, false);
+        let mut restricted = None;
+        for rule in self.allow.iter().flatten() {
+            let mismatch = match rule {
+                NetworkPermission::Cidr(_) if request.address().is_none() => {
+                    rule.mismatch_beyond_host(request).map(|_| Mismatch::Host)
+                }
+                _ => rule.mismatch(request),
+            };
+            match mismatch {
+                None => {
+                    let family = rule.address_family();
+                    v4 |= family.v4();
+                    v6 |= family.v6();
+                }
+                Some(Mismatch::Host) => {}
+                Some(mismatch) => {
+                    restricted.get_or_insert((rule, mismatch));
+                }
+            }
+        }
+        if v4 || v6 {
+            for rule in &narrowing {
+                let family = rule.address_family();
+                v4 &= !family.v4();
+                v6 &= !family.v6();
+                if !(v4 || v6) {
+                    return Err(denied(rule));
+                }
+            }
+            return Ok(AddressFamily::from_flags(v4, v6).expect("a family is left"));
+        }
+        Err(match restricted {
+            Some((rule, mismatch)) => {
+                let what = match mismatch {
+                    Mismatch::Port => format!("port {}", request.port),
+                    Mismatch::AddressFamily => match request.address() {
+                        Some(address) if AddressFamily::V4Only.allows(address) => "IPv4",
+                        _ => "IPv6",
+                    }
+                    .to_string(),
+                    _ => format!("scheme {}", request.scheme),
+                };
+                NetworkDenial {
+                    rule: Some(rule.clone()),
+                    reason: format!(
+                        "{request} is not allowed: {what} is outside the network rule `{rule}`"
+                    ),
+                }
+            }
+            None => NetworkDenial {
+                rule: None,
+                reason: format!(
+                    "{request} is not allowed: no network rule allows {}",
+                    request.host
+                ),
+            },
+        })
+    }
+
+    /// Check `address`, which the host name of `request` resolved to, against the `cidr`
+    /// rules: it is refused if a deny rule covers it, or if no `host` rule allows the request
+    /// and no allow rule covers it. `request` is taken to have passed
+    /// [`check_families`](Self::check_families) already.
+    pub fn check_address(
+        &self,
+        request: &NetworkRequest<'_>,
+        address: IpAddr,
+    ) -> Result<(), NetworkDenial> {
+        if request.address().is_some() {
+            return Ok(());
+        }
+        let host = address.to_string();
+        let resolved = NetworkRequest {
+            host: &host,
+            ..*request
+        };
+        if let Some(rule) = cidr_rules(&self.deny).find(|rule| rule.matches(&resolved)) {
+            return Err(NetworkDenial {
+                rule: Some(rule.clone()),
+                reason: format!(
+                    "{request} resolves to {address}, which the network rule `{rule}` denies"
+                ),
+            });
+        }
+        if self.allows_by_name(request)
+            || cidr_rules(&self.allow).any(|rule| rule.matches(&resolved))
+        {
+            return Ok(());
+        }
+        let rule =
+            cidr_rules(&self.allow).find(|rule| rule.mismatch_beyond_host(request).is_none());
+        Err(NetworkDenial {
+            rule: rule.cloned(),
+            reason: match rule {
+                Some(rule) => format!(
+                    "{request} is not allowed: it resolves to {address}, outside the network \
+                     rule `{rule}`"
+                ),
+                None => format!(
+                    "{request} is not allowed: no network rule allows {}",
+                    request.host
+                ),
+            },
+        })
+    }
+
+    /// Whether [`check_address`](Self::check_address) may refuse an address the host name of
+    /// `request` resolves to, so that it has to be resolved to be checked
+    pub fn checks_addresses(&self, request: &NetworkRequest<'_>) -> bool {
+        if request.address().is_some() {
+            return false;
+        }
+        cidr_rules(&self.deny).any(|rule| rule.mismatch_beyond_host(request).is_none())
+            || !self.allows_by_name(request)
+    }
+
+    /// Whether an allow `host` rule matches `request`
+    fn allows_by_name(&self, request: &NetworkRequest<'_>) -> bool {
+        self.allow
+            .iter()
+            .flatten()
+            .any(|rule| matches!(rule, NetworkPermission::Host(_)) && rule.matches(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NetworkCidrPermission, NetworkHostPermission};
+
+    fn host(host: &str) -> NetworkPermission {
+        NetworkPermission::Host(NetworkHostPermission {
+            host: host.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn cidr(cidr: &str) -> NetworkPermission {
+        NetworkPermission::Cidr(NetworkCidrPermission {
+            cidr: cidr.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn request(url: &str) -> NetworkRequest<'_> {
+        let (scheme, rest) = url.split_once("://").unwrap();
+        let (host, port) = rest.rsplit_once(':').unwrap();
+        NetworkRequest {
+            scheme,
+            host,
+            port: port.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_host_patterns() {
+        let exact = host("api.example.com");
+        assert!(exact.matches(&request("https://API.example.com:443")));
+        assert!(exact.matches(&request("http://api.example.com.:8080")));
+        assert!(!exact.matches(&request("https://other.example.com:443")));
+
+        let wildcard = host("*.github.com");
+        assert!(wildcard.matches(&request("https://api.github.com:443")));
+        assert!(wildcard.matches(&request("https://a.b.GitHub.com:443")));
+        assert!(!wildcard.matches(&request("https://github.com:443")));
+        assert!(!wildcard.matches(&request("https://evilgithub.com:443")));
+        assert!(!wildcard.matches(&request("https://.github.com:443")));
+
+        assert!(host("*").matches(&request("http://10.0.0.1:80")));
+
+        let origin = host("https://api.example.com:8443");
+        assert!(origin.matches(&request("https://api.example.com:8443")));
+        assert!(!origin.matches(&request("https://api.example.com:443")));
+        assert!(!origin.matches(&request("http://api.example.com:8443")));
+    }
+
+    #[test]
+    fn test_cidr_ranges() {
+        let private = cidr("10.20.0.0/16");
+        assert!(private.matches(&request("http://10.20.3.4:80")));
+        assert!(!private.matches(&request("http://10.21.0.1:80")));
+        assert!(private.matches(&request("http://[::ffff:10.20.0.9]:80")));
+        // Host names aren't resolved.
+        assert!(!private.matches(&request("http://intranet.local:80")));
+
+        let v6 = cidr("fd00::/8");
+        assert!(v6.matches(&request("http://[fd12::1]:80")));
+        assert!(!v6.matches(&request("http://[fe80::1]:80")));
+        assert!(cidr("0.0.0.0/0").matches(&request("http://192.0.2.1:80")));
+        assert_eq!(parse_cidr("10.0.0.0/33"), None);
+    }
+
+    fn family(host: &str, family: AddressFamily) -> NetworkPermission {
+        NetworkPermission::Host(NetworkHostPermission {
+            host: host.to_string(),
+            address_family: Some(family),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_address_families() {
+        let rules = PermissionList {
+            allow: Some(vec![
+                family("api.example.com", AddressFamily::V4Only),
+                host("dual.example.com"),
+                family("*", AddressFamily::V6Only),
+            ]),
+            deny: None,
+        };
+        assert_eq!(
+            rules.check_families(&request("https://api.example.com:443")),
+            Ok(AddressFamily::Dual)
+        );
+        assert_eq!(
+            rules
+                .check_families(&request("http://192.0.2.1:80"))
+                .unwrap_err()
+                .reason,
+            "http://192.0.2.1:80 is not allowed: IPv4 is outside the network rule \
+             `host: *, address-family: v6-only`"
+        );
+        assert_eq!(
+            rules.check_families(&request("http://[2001:db8::1]:80")),
+            Ok(AddressFamily::V6Only)
+        );
+
+        let rules = PermissionList {
+            allow: Some(vec![family("api.example.com", AddressFamily::V4Only)]),
+            deny: None,
+        };
+        assert_eq!(
+            rules.check_families(&request("https://api.example.com:443")),
+            Ok(AddressFamily::V4Only)
+        );
+        assert!(rules
+            .check(&request("http://[::ffff:192.0.2.1]:80"))
+            .is_err());
+
+        // A deny rule naming a family keeps host names off it.
+        let rules = PermissionList {
+            allow: Some(vec![host("*")]),
+            deny: Some(vec![family("*", AddressFamily::V6Only)]),
+        };
+        assert_eq!(
+            rules.check_families(&request("https://api.example.com:443")),
+            Ok(AddressFamily::V4Only)
+        );
+        assert!(rules.check(&request("http://192.0.2.1:80")).is_ok());
+        assert!(rules.check(&request("http://[2001:db8::1]:80")).is_err());
+        let rules = PermissionList {
+            allow: Some(vec![family("*", AddressFamily::V6Only)]),
+            deny: Some(vec![family("*", AddressFamily::V6Only)]),
+        };
+        assert!(rules
+            .check(&request("https://api.example.com:443"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_cidr_rules_hold_resolved_addresses() {
+        let address = |address: &str| address.parse::<IpAddr>().unwrap();
+        let rules = PermissionList {
+            allow: Some(vec![
+                host("api.example.com"),
+                NetworkPermission::Cidr(NetworkCidrPermission {
+                    cidr: "10.20.0.0/16".to_string(),
+                    ports: Some(vec![443]),
+                    ..Default::default()
+                }),
+            ]),
+            deny: Some(vec![cidr("169.254.0.0/16")]),
+        };
+
+        // A name a host rule allows may resolve anywhere but into a denied range.
+        let named = request("https://api.example.com:443");
+        assert!(rules.checks_addresses(&named));
+        assert!(rules.check_address(&named, address("192.0.2.1")).is_ok());
+        let denial = rules
+            .check_address(&named, address("169.254.169.254"))
+            .unwrap_err();
+        assert_eq!(denial.rule, Some(cidr("169.254.0.0/16")));
+        assert_eq!(
+            denial.reason,
+            "https://api.example.com:443 resolves to 169.254.169.254, which the network rule \
+             `cidr: 169.254.0.0/16` denies"
+        );
+        assert!(rules
+            .check_address(&named, address("::ffff:169.254.1.1"))
+            .is_err());
+
+        // One only a cidr rule lets through has to resolve into its range.
+        let internal = request("https://wiki.corp.example:443");
+        assert_eq!(rules.check_families(&internal), Ok(AddressFamily::Dual));
+        assert!(rules.checks_addresses(&internal));
+        assert!(rules.check_address(&internal, address("10.20.3.4")).is_ok());
+        let denial = rules
+            .check_address(&internal, address("192.0.2.1"))
+            .unwrap_err();
+        assert_eq!(denial.rule, rules.allow.as_ref().unwrap().get(1).cloned());
+        assert!(denial.reason.contains("resolves to 192.0.2.1, outside"));
+        // The cidr rule's port still applies to the name.
+        assert!(rules
+            .check(&request("http://wiki.corp.example:80"))
+            .is_err());
+
+        // Requests to addresses were checked against the rules already.
+        assert!(!rules.checks_addresses(&request("https://10.20.3.4:443")));
+        assert!(rules
+            .check_address(&request("https://10.20.3.4:443"), address("10.20.3.4"))
+            .is_ok());
+        let rules = PermissionList {
+            allow: Some(vec![host("*")]),
+            deny: None,
+        };
+        assert!(!rules.checks_addresses(&named));
+    }
+
+    #[test]
+    fn test_request_header_hosts() {
+        let mut header = RequestHeader {
+            name: "User-Agent".to_string(),
+            value: "acme-agent/1.0".to_string(),
+            hosts: None,
+            replace: false,
+        };
+        assert!(header.applies_to(&request("http://localhost:8080")));
+
+        header.hosts = Some(vec![
+            "*.example.com".to_string(),
+            "https://api.example.org".to_string(),
+        ]);
+        assert!(header.applies_to(&request("https://www.example.com:443")));
+        assert!(!header.applies_to(&request("https://example.com:443")));
+        assert!(header.applies_to(&request("https://api.example.org:443")));
+        assert!(!header.applies_to(&request("http://api.example.org:80")));
+    }
+
+    #[test]
+    fn test_check_names_the_violated_rule() {
+        let rules = PermissionList {
+            allow: Some(vec![
+                NetworkPermission::Host(NetworkHostPermission {
+                    host: "*.github.com".to_string(),
+                    ports: Some(vec![443]),
+                    schemes: Some(vec!["https".to_string()]),
+                    ..Default::default()
+                }),
+                NetworkPermission::Cidr(NetworkCidrPermission {
+                    cidr: "10.0.0.0/8".to_string(),
+                    ports: Some(vec![8080]),
+                    ..Default::default()
+                }),
+            ]),
+            deny: Some(vec![host("gist.github.com")]),
+        };
+        assert!(rules.check(&request("https://api.github.com:443")).is_ok());
+        assert!(rules.check(&request("http://10.1.2.3:8080")).is_ok());
+
+        let denial = rules
+            .check(&request("https://gist.github.com:443"))
+            .unwrap_err();
+        assert_eq!(
+            denial.reason,
+            "https://gist.github.com:443 is denied by the network rule `host: gist.github.com`"
+        );
+
+        let denial = rules
+            .check(&request("https://api.github.com:8443"))
+            .unwrap_err();
+        assert_eq!(
+            denial.reason,
+            "https://api.github.com:8443 is not allowed: port 8443 is outside the network rule \
+             `host: *.github.com, ports: [443], schemes: [https]`"
+        );
+        let denial = rules
+            .check(&request("http://api.github.com:443"))
+            .unwrap_err();
+        assert!(denial.reason.contains("scheme http is outside"));
+
+        let denial = rules
+            .check(&request("https://example.com:443"))
+            .unwrap_err();
+        assert_eq!(denial.rule, None);
+        assert!(denial
+            .reason
+            .ends_with("no network rule allows example.com"));
+    }
+}
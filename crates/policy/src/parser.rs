@@ -37,6 +37,34 @@ impl PolicyParser {
         Ok(document)
     }
 
+    /// Parse a policy document from a YAML string, rejecting unknown and deprecated keys
+    ///
+    /// [`parse_str`](Self::parse_str) ignores keys it doesn't know, so a misspelt key silently
+    /// drops the permission it was meant to express. Errors here give the key's path, line and
+    /// column.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use policy::PolicyParser;
+    ///
+    /// let yaml_content = r#"
+    /// version: "1.0"
+    /// permissions:
+    ///   network:
+    ///     alow:
+    ///     - host: "api.example.com"
+    /// "#;
+    ///
+    /// assert!(PolicyParser::parse_str(yaml_content).is_ok());
+    /// let error = PolicyParser::parse_str_strict(yaml_content).unwrap_err();
+    /// assert!(error.to_string().contains("unknown field `alow`"));
+    /// ```
+    pub fn parse_str_strict(content: impl AsRef<str>) -> PolicyResult<PolicyDocument> {
+        crate::strict::check(content.as_ref())?;
+        Self::parse_str(content)
+    }
+
     /// Parse a policy document from a file path
     ///
     /// # Example
@@ -52,6 +80,16 @@ impl PolicyParser {
         Self::parse_str(&content)
     }
 
+    /// Parse a policy document from a file path, rejecting unknown and deprecated keys
+    ///
+    /// See [`parse_str_strict`](Self::parse_str_strict).
+    pub fn parse_file_strict<P: AsRef<Path>>(path: P) -> PolicyResult<PolicyDocument> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        Self::parse_str_strict(&content)
+            .with_context(|| format!("Invalid policy {}", path.display()))
+    }
+
     /// Parse a policy document from bytes
     ///
     /// # Example
@@ -114,7 +152,8 @@ mod tests {
 
     use super::*;
     use crate::{
-        AccessType, CapabilityAction, CpuLimit, MemoryLimit, PermissionList, Permissions,
+        AccessType, AddressFamily, CacheTtl, CapabilityAction, ContextAccess, CpuLimit,
+        MemoryLimit, NetworkPermission, PermissionList, Permissions, ScheduleZone,
         StoragePermission,
     };
 
@@ -144,6 +183,308 @@ permissions:
         );
     }
 
+    #[test]
+    fn test_parse_str_sampling() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  sampling:
+    max-tokens: 256
+    max-calls: 3
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let sampling = policy.permissions.sampling.unwrap();
+        assert_eq!(sampling.max_tokens, Some(256));
+        assert_eq!(sampling.max_calls, Some(3));
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  sampling:
+    max-tokens: 0
+"#;
+        assert!(PolicyParser::parse_str(yaml_content).is_err());
+    }
+
+    #[test]
+    fn test_parse_str_keyvalue() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  keyvalue:
+    max-bytes: "1Mi"
+    max-keys: 500
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let keyvalue = policy.permissions.keyvalue.unwrap();
+        assert_eq!(keyvalue.max_bytes.unwrap().to_bytes().unwrap(), 1024 * 1024);
+        assert_eq!(keyvalue.max_keys, Some(500));
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  keyvalue:
+    max-keys: 0
+"#;
+        assert!(PolicyParser::parse_str(yaml_content).is_err());
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  keyvalue:
+    max-bytes: "lots"
+"#;
+        assert!(PolicyParser::parse_str(yaml_content).is_err());
+    }
+
+    #[test]
+    fn test_parse_str_vector() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  vector:
+    max-bytes: "16Mi"
+    max-entries: 10000
+    max-dimensions: 1536
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let vector = policy.permissions.vector.unwrap();
+        assert_eq!(
+            vector.max_bytes.unwrap().to_bytes().unwrap(),
+            16 * 1024 * 1024
+        );
+        assert_eq!(vector.max_entries, Some(10000));
+        assert_eq!(vector.max_dimensions, Some(1536));
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  vector:
+    max-dimensions: 0
+"#;
+        assert!(PolicyParser::parse_str(yaml_content).is_err());
+    }
+
+    #[test]
+    fn test_parse_str_browser() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  browser:
+    allow:
+    - host: "docs.rs"
+    - host: "*.wikipedia.org"
+    max-pages: 4
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let browser = policy.permissions.browser.unwrap();
+        let hosts: Vec<_> = browser
+            .allow
+            .unwrap()
+            .into_iter()
+            .map(|perm| perm.host)
+            .collect();
+        assert_eq!(hosts, ["docs.rs", "*.wikipedia.org"]);
+        assert_eq!(browser.max_pages, Some(4));
+
+        for invalid in [
+            "  browser: {}\n",
+            "  browser:\n    allow:\n    - host: \"*\"\n",
+            "  browser:\n    allow:\n    - host: \"a.com, EXCLUDE b.com\"\n",
+            "  browser:\n    allow:\n    - host: a.com\n    max-pages: 0\n",
+        ] {
+            let yaml_content = format!("version: \"1.0\"\npermissions:\n{invalid}");
+            assert!(PolicyParser::parse_str(&yaml_content).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_parse_str_email() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  email:
+    allow:
+    - address: "oncall@example.com"
+    - domain: "alerts.example.org"
+    max-per-hour: 20
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let email = policy.permissions.email.unwrap();
+        let recipients = email.allow.unwrap();
+        assert_eq!(recipients[0].address.as_deref(), Some("oncall@example.com"));
+        assert_eq!(recipients[1].domain.as_deref(), Some("alerts.example.org"));
+        assert_eq!(email.max_per_hour, Some(20));
+
+        for invalid in [
+            "  email: {}\n",
+            "  email:\n    allow:\n    - {}\n",
+            "  email:\n    allow:\n    - address: a@b.com\n      domain: b.com\n",
+            "  email:\n    allow:\n    - address: \"Ops <ops@b.com>\"\n",
+            "  email:\n    allow:\n    - address: ops@b.com, evil@c.com\n",
+            "  email:\n    allow:\n    - domain: \"*.b.com\"\n",
+            "  email:\n    allow:\n    - domain: b.com\n    max-per-hour: 0\n",
+        ] {
+            let yaml_content = format!("version: \"1.0\"\npermissions:\n{invalid}");
+            assert!(PolicyParser::parse_str(&yaml_content).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_parse_str_context() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  context: read
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        assert_eq!(policy.permissions.context, Some(ContextAccess::Read));
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  context: write
+"#;
+        assert!(PolicyParser::parse_str(yaml_content).is_err());
+    }
+
+    #[test]
+    fn test_parse_str_mounts() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  mounts:
+    - archive: datasets/census.zip
+      at: /data
+    - file: model.bin
+      at: /models/model.bin
+    - tmpfs: 64Mi
+      at: /scratch
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let mounts = policy.permissions.mounts.unwrap();
+        assert_eq!(mounts.len(), 3);
+        assert_eq!(
+            mounts[2].tmpfs.as_ref().unwrap().to_bytes().unwrap(),
+            64 << 20
+        );
+        assert_eq!(mounts[0].archive.as_deref(), Some("datasets/census.zip"));
+        assert_eq!(mounts[0].at, "/data");
+        assert_eq!(mounts[1].file.as_deref(), Some("model.bin"));
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  mounts:
+    - archive: data.zip
+      at: data
+"#;
+        assert!(PolicyParser::parse_str(yaml_content).is_err());
+    }
+
+    #[test]
+    fn test_parse_str_schedule() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  schedule:
+    - tool: drop-table
+      allowed-hours: "09:00-18:00 Mon-Fri"
+      timezone: "+01:00"
+      require-confirm-outside-hours: true
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let schedule = policy.permissions.schedule.unwrap();
+        assert_eq!(schedule[0].tool, "drop-table");
+        assert!(schedule[0].require_confirm_outside_hours);
+        let (hours, zone) = schedule[0].window().unwrap();
+        assert!(hours.contains(0, 10 * 60));
+        assert_eq!(zone, ScheduleZone::Offset(60));
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  schedule:
+    - tool: drop-table
+      allowed-hours: "9am-6pm"
+"#;
+        assert!(PolicyParser::parse_str(yaml_content).is_err());
+    }
+
+    #[test]
+    fn test_parse_str_headers() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  headers:
+    - name: User-Agent
+      value: "acme-agent/1.0 (ops@acme.example)"
+      replace: true
+    - name: Accept-Language
+      value: en-US
+      hosts: ["*.example.com", "https://api.example.org"]
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let headers = policy.permissions.headers.unwrap();
+        assert_eq!(headers[0].name, "User-Agent");
+        assert!(headers[0].replace);
+        assert!(!headers[1].replace);
+        assert_eq!(headers[1].hosts.as_ref().unwrap().len(), 2);
+
+        for header in [
+            "{name: Host, value: example.com}",
+            "{name: \"X Agent\", value: a}",
+            "{name: X-Agent, value: \"a\\r\\nX-Injected: b\"}",
+            "{name: X-Agent, value: a, hosts: []}",
+            "{name: X-Agent, value: a, hosts: [\"api.*.com\"]}",
+            "{name: X-Agent, value: a, append: true}",
+        ] {
+            let yaml_content =
+                format!("version: \"1.0\"\npermissions:\n  headers:\n    - {header}\n");
+            assert!(
+                PolicyParser::parse_str(&yaml_content).is_err(),
+                "accepted {header}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_str_cache() {
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  cache:
+    - tool: get-weather
+      ttl: 5m
+    - tool: lookup
+      ttl: 30
+"#;
+
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let cache = policy.permissions.cache.unwrap();
+        assert_eq!(cache[0].tool, "get-weather");
+        assert_eq!(cache[0].ttl.to_duration().unwrap().as_secs(), 300);
+        assert_eq!(cache[1].ttl, CacheTtl::Number(30));
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  cache:
+    - tool: get-weather
+      ttl: 0s
+"#;
+        assert!(PolicyParser::parse_str(yaml_content).is_err());
+    }
+
     #[test]
     fn test_parse_str_invalid_version() {
         let yaml_content = r#"
@@ -169,6 +510,40 @@ invalid: yaml: content
         assert!(!result.unwrap_err().to_string().is_empty());
     }
 
+    #[test]
+    fn test_parse_network_address_family() {
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+permissions:
+  network:
+    allow:
+      - host: api.example.com
+        address-family: v4-only
+    deny:
+      - host: "*"
+        address-family: v6-only
+"#,
+        )
+        .unwrap();
+        let network = policy.permissions.network.unwrap();
+        let allow = network.allow.unwrap();
+        assert_eq!(allow[0].address_family(), AddressFamily::V4Only);
+        assert_eq!(
+            network.deny.unwrap()[0].address_family(),
+            AddressFamily::V6Only
+        );
+        assert!(serde_yaml::to_string(&allow[0])
+            .unwrap()
+            .contains("address-family: v4-only"));
+
+        let unknown = "version: \"1.0\"\npermissions:\n  network:\n    allow:\n      - host: a.com\n        address-family: v5\n";
+        assert!(PolicyParser::parse_str(unknown).is_err());
+        let excluded = "version: \"1.0\"\npermissions:\n  network:\n    allow:\n      - host: \"[::1]\"\n        address-family: v4-only\n";
+        let error = PolicyParser::parse_str(excluded).unwrap_err();
+        assert!(format!("{error:#}").contains("address family excludes the host"));
+    }
+
     #[test]
     fn test_round_trip_serialization() {
         let permissions = Permissions {
@@ -301,6 +676,14 @@ permissions: {}
         let network = policy.permissions.network.unwrap();
         let allow_list = network.allow.unwrap();
         assert_eq!(allow_list.len(), 5);
+        match &allow_list[1] {
+            NetworkPermission::Host(rule) => {
+                assert_eq!(rule.host, "*.googleapis.com");
+                assert_eq!(rule.ports, Some(vec![443]));
+                assert_eq!(rule.schemes, None);
+            }
+            other => panic!("Expected a host rule, got {other:?}"),
+        }
 
         let deny_list = network.deny.unwrap();
         assert_eq!(deny_list.len(), 3);
@@ -507,6 +890,19 @@ permissions: {}
             assert_eq!(memory_limit.to_bytes().unwrap(), 512 * 1024 * 1024);
         }
 
+        // Test count limits
+        assert_eq!(limits.open_files, Some(64));
+        assert_eq!(limits.instances, Some(4));
+        assert_eq!(limits.max_concurrent_invocations, Some(8));
+        assert_eq!(limits.rate_limit, Some(120));
+        assert_eq!(limits.tables, Some(8));
+        assert_eq!(limits.table_elements, Some(10000));
+        assert_eq!(limits.fuel, Some(1_000_000_000));
+        assert_eq!(
+            limits.timeout.as_ref().unwrap().to_duration().unwrap(),
+            std::time::Duration::from_secs(30)
+        );
+
         // Ensure legacy fields are not used
         assert!(resources.cpu.is_none());
         assert!(resources.memory.is_none());
@@ -563,4 +959,19 @@ permissions: {}
             });
         }
     }
+
+    #[test]
+    fn test_testdata_policies_parse_strictly() {
+        let testdata = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        for entry in fs::read_dir(testdata).unwrap() {
+            let path = entry.unwrap().path();
+            // Legacy resource fields are deprecated, but no key may be unknown.
+            if let Err(error) = PolicyParser::parse_file_strict(&path) {
+                assert!(
+                    format!("{error:#}").contains("deprecated field"),
+                    "{error:#}"
+                );
+            }
+        }
+    }
 }
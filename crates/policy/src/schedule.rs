@@ -0,0 +1,191 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Hours at which the tools of a component may be called.
+//!
+//! A `schedule` entry of a policy restricts one tool to a weekly window such as
+//! `09:00-18:00 Mon-Fri`, in the server's local time or a fixed offset from UTC. This module
+//! parses the window and tells whether a local weekday and time of day fall in it; reading
+//! the clock is left to the runtime.
+
+use anyhow::{bail, Context};
+
+use crate::PolicyResult;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// A weekly window written as `HH:MM-HH:MM`, optionally followed by days such as `Mon-Fri`
+/// or `Sat,Sun`.
+///
+/// A window ending before it starts runs past midnight, and belongs to the day it opens on:
+/// `22:00-06:00 Fri` is open from Friday 22:00 to Saturday 06:00. `24:00` ends a window at
+/// midnight. Without days the window is open every day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllowedHours {
+    /// Minute of the day the window opens
+    start: u16,
+    /// Minute of the day the window closes, 1440 for midnight
+    end: u16,
+    /// Days the window opens on, Monday first
+    days: [bool; 7],
+}
+
+impl AllowedHours {
+    /// Parse a window such as `09:00-18:00 Mon-Fri`.
+    pub fn parse(value: &str) -> PolicyResult<Self> {
+        let (times, days) = match value.trim().split_once(char::is_whitespace) {
+            Some((times, days)) => (times, Some(days.trim())),
+            None => (value.trim(), None),
+        };
+        let (start, end) = times
+            .split_once('-')
+            .with_context(|| format!("Allowed hours need a range such as 09:00-18:00: {value}"))?;
+        let start = parse_time(start).with_context(|| format!("Invalid allowed hours: {value}"))?;
+        let end = parse_time(end).with_context(|| format!("Invalid allowed hours: {value}"))?;
+        if start == MINUTES_PER_DAY {
+            bail!("Allowed hours can't start at 24:00: {value}");
+        }
+        if start == end {
+            bail!("Allowed hours start and end at the same time: {value}");
+        }
+        let days = match days {
+            Some(days) => {
+                parse_days(days).with_context(|| format!("Invalid allowed hours: {value}"))?
+            }
+            None => [true; 7],
+        };
+        Ok(Self { start, end, days })
+    }
+
+    /// Whether the window is open at `minute` of the day on `weekday`, counted from 0 for
+    /// Monday.
+    pub fn contains(&self, weekday: usize, minute: u16) -> bool {
+        let opened_on = |weekday: usize| self.days[weekday % 7];
+        if self.start < self.end {
+            opened_on(weekday) && (self.start..self.end).contains(&minute)
+        } else {
+            // Open past midnight: from the start to midnight, then on the next day until the end.
+            (minute >= self.start && opened_on(weekday))
+                || (minute < self.end && opened_on(weekday + 6))
+        }
+    }
+}
+
+/// The time zone allowed hours are read in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScheduleZone {
+    /// The server's own time zone
+    #[default]
+    Local,
+    /// A fixed offset east of UTC, in minutes
+    Offset(i32),
+}
+
+impl ScheduleZone {
+    /// Parse `local`, `utc` or an offset such as `+02:00` or `-05:30`.
+    pub fn parse(value: &str) -> PolicyResult<Self> {
+        let value = value.trim();
+        match value.to_ascii_lowercase().as_str() {
+            "local" => return Ok(Self::Local),
+            "utc" => return Ok(Self::Offset(0)),
+            _ => {}
+        }
+        let invalid = || {
+            format!("Invalid time zone `{value}`; expected local, utc or an offset such as +02:00")
+        };
+        let (sign, offset) = match value.split_at_checked(1) {
+            Some(("+", offset)) => (1, offset),
+            Some(("-", offset)) => (-1, offset),
+            _ => bail!(invalid()),
+        };
+        let minutes = parse_time(offset).with_context(invalid)?;
+        if minutes >= MINUTES_PER_DAY {
+            bail!(invalid());
+        }
+        Ok(Self::Offset(sign * i32::from(minutes)))
+    }
+}
+
+/// Minutes since midnight of `HH:MM`, up to `24:00`.
+fn parse_time(value: &str) -> PolicyResult<u16> {
+    let value = value.trim();
+    let parsed = value.split_once(':').and_then(|(hours, minutes)| {
+        let two_digits = |part: &str| part.len() == 2 && part.bytes().all(|b| b.is_ascii_digit());
+        if !two_digits(hours) || !two_digits(minutes) {
+            return None;
+        }
+        let (hours, minutes) = (hours.parse::<u16>().ok()?, minutes.parse::<u16>().ok()?);
+        (minutes < 60 && hours * 60 + minutes <= MINUTES_PER_DAY).then_some(hours * 60 + minutes)
+    });
+    parsed.with_context(|| format!("`{value}` is not a time such as 09:00"))
+}
+
+/// Days such as `Mon-Fri`, `Sat,Sun` or `Fri-Mon`.
+fn parse_days(value: &str) -> PolicyResult<[bool; 7]> {
+    let weekday = |name: &str| {
+        WEEKDAYS
+            .iter()
+            .position(|day| day.eq_ignore_ascii_case(name.trim()))
+            .with_context(|| format!("`{}` is not a day such as Mon", name.trim()))
+    };
+    let mut days = [false; 7];
+    for part in value.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (weekday(first)?, weekday(last)?);
+                let span = (last + 7 - first) % 7;
+                for offset in 0..=span {
+                    days[(first + offset) % 7] = true;
+                }
+            }
+            None => days[weekday(part)?] = true,
+        }
+    }
+    Ok(days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_hours() {
+        let office = AllowedHours::parse("09:00-18:00 Mon-Fri").unwrap();
+        assert!(office.contains(0, 9 * 60));
+        assert!(!office.contains(0, 18 * 60));
+        assert!(!office.contains(5, 12 * 60));
+
+        let night = AllowedHours::parse("22:00-06:00 Fri").unwrap();
+        assert!(night.contains(4, 23 * 60));
+        assert!(night.contains(5, 5 * 60));
+        assert!(!night.contains(4, 5 * 60));
+        assert!(!night.contains(6, 23 * 60));
+
+        let weekend = AllowedHours::parse("00:00-24:00 sat,Sun").unwrap();
+        assert!(weekend.contains(6, 23 * 60 + 59));
+        assert!(!weekend.contains(0, 0));
+        assert!(AllowedHours::parse("08:00-12:00 Fri-Mon")
+            .unwrap()
+            .contains(0, 10 * 60));
+
+        for invalid in [
+            "9-18",
+            "09:00",
+            "09:00-09:00",
+            "09:00-18:60",
+            "09:00-18:00 Monday",
+        ] {
+            assert!(AllowedHours::parse(invalid).is_err(), "{invalid}");
+        }
+
+        assert_eq!(ScheduleZone::parse("local").unwrap(), ScheduleZone::Local);
+        assert_eq!(ScheduleZone::parse("UTC").unwrap(), ScheduleZone::Offset(0));
+        assert_eq!(
+            ScheduleZone::parse("-05:30").unwrap(),
+            ScheduleZone::Offset(-330)
+        );
+        assert!(ScheduleZone::parse("Europe/Paris").is_err());
+    }
+}
@@ -0,0 +1,560 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! JSON Schema of the policy format
+//!
+//! The schema is embedded so editors can be pointed at it for completion, and so policy files
+//! can be checked in CI. [`validate_against_schema`] checks a YAML document against it and
+//! reports every violation with its JSON pointer and, where it can be found, its line and
+//! column in the source.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+
+use crate::PolicyResult;
+
+/// The JSON Schema (draft 2020-12) of policy documents
+pub const POLICY_SCHEMA: &str = include_str!("../schema/policy.schema.json");
+
+/// The policy schema as a JSON value
+pub fn policy_schema() -> &'static Value {
+    static SCHEMA: OnceLock<Value> = OnceLock::new();
+    SCHEMA.get_or_init(|| serde_json::from_str(POLICY_SCHEMA).expect("embedded schema is JSON"))
+}
+
+/// A place where a policy document doesn't match the schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// JSON pointer to the offending value, e.g. `/permissions/network/allow/0`
+    pub pointer: String,
+    /// What is wrong with it
+    pub message: String,
+    /// Line and column of the value in the YAML source, when it can be found
+    pub location: Option<(usize, usize)>,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pointer = if self.pointer.is_empty() {
+            "/"
+        } else {
+            &self.pointer
+        };
+        write!(f, "{pointer}: {}", self.message)?;
+        if let Some((line, column)) = self.location {
+            write!(f, " at line {line} column {column}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the policy YAML `content` against [`POLICY_SCHEMA`].
+///
+/// Returns the violations found, none if the document matches. Fails only if `content` isn't
+/// YAML at all. Matching the schema doesn't make a policy valid: values such as storage URIs
+/// and memory sizes are still checked when the policy is parsed.
+///
+/// # Example
+///
+/// ```rust
+/// let violations = policy::validate_against_schema(
+///     "version: \"1.0\"\npermissions:\n  network:\n    allow:\n    - hots: example.com\n",
+/// )
+/// .unwrap();
+/// assert_eq!(violations[0].pointer, "/permissions/network/allow/0");
+/// assert_eq!(violations[0].location, Some((5, 7)));
+/// ```
+pub fn validate_against_schema(content: &str) -> PolicyResult<Vec<SchemaViolation>> {
+    let document: Value = serde_yaml::from_str(content)?;
+    let root = policy_schema();
+    let mut errors = Vec::new();
+    check(root, root, &document, "", &mut errors);
+    let mut violations: Vec<_> = errors
+        .into_iter()
+        .map(|(pointer, message)| SchemaViolation {
+            location: locate(content, &pointer),
+            pointer,
+            message,
+        })
+        .collect();
+    // The schema's mappings are unordered; report in document order.
+    violations.sort_by_key(|violation| violation.location.unwrap_or((usize::MAX, 0)));
+    Ok(violations)
+}
+
+type Errors = Vec<(String, String)>;
+
+/// Check `value` at `pointer` against `schema`, a subschema of `root`.
+fn check(root: &Value, schema: &Value, value: &Value, pointer: &str, errors: &mut Errors) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if let Some(target) = schema.get("$ref").and_then(Value::as_str) {
+        if let Some(resolved) = resolve(root, target) {
+            check(root, resolved, value, pointer, errors);
+        }
+    }
+
+    if let Some(branches) = schema.get("anyOf").and_then(Value::as_array) {
+        check_any_of(root, branches, value, pointer, errors);
+    }
+
+    if let Some(types) = schema.get("type") {
+        if !types_of(types).any(|ty| has_type(value, ty)) {
+            errors.push((
+                pointer.to_string(),
+                format!(
+                    "expected {}, got {}",
+                    join_or(types_of(types).map(type_name)),
+                    kind(value)
+                ),
+            ));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            errors.push((
+                pointer.to_string(),
+                format!(
+                    "expected {}, got {}",
+                    join_or(allowed.iter().map(|value| format!("`{}`", display(value)))),
+                    display(value)
+                ),
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(map) => check_object(root, schema, map, pointer, errors),
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    errors.push((
+                        pointer.to_string(),
+                        format!(
+                            "needs at least {min} item{}",
+                            if min == 1 { "" } else { "s" }
+                        ),
+                    ));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(root, item_schema, item, &format!("{pointer}/{i}"), errors);
+                }
+            }
+        }
+        Value::String(s) => {
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if (s.chars().count() as u64) < min {
+                    errors.push((pointer.to_string(), "can't be empty".to_string()));
+                }
+            }
+        }
+        Value::Number(n) => {
+            if let (Some(min), Some(n)) =
+                (schema.get("minimum").and_then(Value::as_f64), n.as_f64())
+            {
+                if n < min {
+                    errors.push((pointer.to_string(), format!("must be at least {min}")));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_object(
+    root: &Value,
+    schema: &Map<String, Value>,
+    map: &Map<String, Value>,
+    pointer: &str,
+    errors: &mut Errors,
+) {
+    let properties = schema.get("properties").and_then(Value::as_object);
+
+    for name in schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        if !map.contains_key(name) {
+            errors.push((
+                pointer.to_string(),
+                format!("missing required field `{name}`"),
+            ));
+        }
+    }
+
+    for (name, value) in map {
+        let child = format!("{pointer}/{}", escape(name));
+        match properties.and_then(|properties| properties.get(name)) {
+            Some(property) => check(root, property, value, &child, errors),
+            None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                let expected = properties
+                    .map(|properties| join_or(properties.keys().map(|key| format!("`{key}`"))))
+                    .unwrap_or_else(|| "no fields".to_string());
+                errors.push((
+                    child,
+                    format!("unknown field `{name}`, expected {expected}"),
+                ));
+            }
+            None => {}
+        }
+    }
+}
+
+/// Check `value` against alternatives, reporting the closest one when none matches.
+fn check_any_of(
+    root: &Value,
+    branches: &[Value],
+    value: &Value,
+    pointer: &str,
+    errors: &mut Errors,
+) {
+    let mut attempts = Vec::new();
+    for branch in branches {
+        let mut branch_errors = Vec::new();
+        check(root, branch, value, pointer, &mut branch_errors);
+        if branch_errors.is_empty() {
+            return;
+        }
+        attempts.push((branch, branch_errors));
+    }
+
+    // When no alternative even has the right type, say which types would do.
+    let typed: Vec<_> = attempts
+        .iter()
+        .filter(|(branch, _)| {
+            branch_types(root, branch)
+                .is_none_or(|types| types.iter().any(|ty| has_type(value, ty)))
+        })
+        .collect();
+    if typed.is_empty() {
+        let mut names: Vec<&str> = Vec::new();
+        for (branch, _) in &attempts {
+            for ty in branch_types(root, branch).unwrap_or_default() {
+                let name = type_name(ty);
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        errors.push((
+            pointer.to_string(),
+            format!(
+                "expected {}, got {}",
+                join_or(names.into_iter().map(str::to_string)),
+                kind(value)
+            ),
+        ));
+        return;
+    }
+
+    if let Some((_, closest)) = typed.into_iter().min_by_key(|(_, errors)| errors.len()) {
+        errors.extend(closest.iter().cloned());
+    }
+}
+
+/// The types a branch of `anyOf` accepts, if it restricts them.
+fn branch_types<'a>(root: &'a Value, branch: &'a Value) -> Option<Vec<&'a str>> {
+    let branch = match branch.get("$ref").and_then(Value::as_str) {
+        Some(target) => resolve(root, target)?,
+        None => branch,
+    };
+    branch.get("type").map(|types| types_of(types).collect())
+}
+
+fn resolve<'a>(root: &'a Value, target: &str) -> Option<&'a Value> {
+    root.pointer(target.strip_prefix('#')?)
+}
+
+fn types_of(types: &Value) -> impl Iterator<Item = &str> {
+    let list: Vec<&str> = match types {
+        Value::String(ty) => vec![ty.as_str()],
+        Value::Array(tys) => tys.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    };
+    list.into_iter()
+}
+
+fn has_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => true,
+    }
+}
+
+/// A YAML reader's name for a JSON Schema type.
+fn type_name(ty: &str) -> &'static str {
+    match ty {
+        "object" => "a mapping",
+        "array" => "a list",
+        "string" => "a string",
+        "boolean" => "a boolean",
+        "null" => "nothing",
+        "number" => "a number",
+        "integer" => "an integer",
+        _ => "a value",
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Object(_) => "a mapping",
+        Value::Array(_) => "a list",
+        Value::String(_) => "a string",
+        Value::Bool(_) => "a boolean",
+        Value::Null => "nothing",
+        Value::Number(n) if n.is_f64() => "a number",
+        Value::Number(_) => "an integer",
+    }
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn join_or(items: impl Iterator<Item = impl AsRef<str>>) -> String {
+    let items: Vec<String> = items.map(|item| item.as_ref().to_string()).collect();
+    match items.as_slice() {
+        [] => "nothing".to_string(),
+        [only] => only.clone(),
+        [first, second] => format!("{first} or {second}"),
+        [rest @ .., last] => format!("one of {}, or {last}", rest.join(", ")),
+    }
+}
+
+fn escape(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+fn unescape(segment: &str) -> String {
+    segment.replace("~1", "/").replace("~0", "~")
+}
+
+/// Line and column of the value at `pointer` in the YAML `content`, or of its key if it is
+/// in a mapping.
+///
+/// The YAML is walked down to the value, where the walk stops with an error; the YAML
+/// deserializer marks the error with the position it was raised at.
+fn locate(content: &str, pointer: &str) -> Option<(usize, usize)> {
+    let path: Vec<String> = pointer.split('/').skip(1).map(unescape).collect();
+    let error = Locate(&path)
+        .deserialize(serde_yaml::Deserializer::from_str(content))
+        .err()?;
+    let location = error.location()?;
+    Some((location.line(), location.column()))
+}
+
+/// Walks to the value at a path, failing at its key or, for list items, at the value.
+struct Locate<'a>(&'a [String]);
+
+impl<'de> DeserializeSeed<'de> for Locate<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        if self.0.is_empty() {
+            deserializer.deserialize_any(Found)
+        } else {
+            deserializer.deserialize_any(self)
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for Locate<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a policy value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let (segment, rest) = self.0.split_first().expect("path isn't empty");
+        if rest.is_empty() {
+            while map.next_key_seed(Key(segment))?.is_some() {
+                map.next_value::<IgnoredAny>()?;
+            }
+            return Ok(());
+        }
+        while let Some(key) = map.next_key::<String>()? {
+            if &key == segment {
+                map.next_value_seed(Locate(rest))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let (segment, rest) = self.0.split_first().expect("path isn't empty");
+        let index: Option<usize> = segment.parse().ok();
+        let mut i = 0;
+        loop {
+            let found = if Some(i) == index {
+                seq.next_element_seed(Locate(rest))?
+            } else {
+                seq.next_element::<IgnoredAny>()?.map(drop)
+            };
+            if found.is_none() {
+                return Ok(());
+            }
+            i += 1;
+        }
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+/// Rejects the mapping key it is looking for, so the error is marked with the key's position.
+struct Key<'a>(&'a str);
+
+impl<'de> DeserializeSeed<'de> for Key<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Key<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a mapping key")
+    }
+
+    fn visit_str<E: de::Error>(self, key: &str) -> Result<(), E> {
+        if key == self.0 {
+            Err(E::custom("found"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Rejects whatever value it is given, so the error is marked with the value's position.
+struct Found;
+
+impl<'de> Visitor<'de> for Found {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("nothing")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_is_embedded() {
+        let schema = policy_schema();
+        assert_eq!(schema["title"], "Wassette component policy");
+        assert!(schema["$defs"]["permissions"]["properties"]["keyvalue"].is_object());
+    }
+
+    #[test]
+    fn test_testdata_and_examples_match_schema() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut paths: Vec<_> = std::fs::read_dir(root.join("testdata"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.push(root.join("../../policy.yaml"));
+        for path in paths {
+            let content = std::fs::read_to_string(&path).unwrap();
+            let violations = validate_against_schema(&content).unwrap();
+            assert!(violations.is_empty(), "{}: {violations:?}", path.display());
+        }
+    }
+
+    #[test]
+    fn test_reports_violations_with_location() {
+        let yaml = r#"version: "1.0"
+permissions:
+  storage:
+    allow:
+    - uri: "fs://work/**"
+      access: [read, exec]
+  network:
+    alow:
+    - host: example.com
+  resources:
+    limits:
+      instances: 0
+      memory: true
+"#;
+        let violations = validate_against_schema(yaml).unwrap();
+        let messages: Vec<String> = violations.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "/permissions/storage/allow/0/access/1: expected `read` or `write`, got exec at line 6 column 22",
+                "/permissions/network/alow: unknown field `alow`, expected `allow` or `deny` at line 8 column 5",
+                "/permissions/resources/limits/instances: must be at least 1 at line 12 column 7",
+                "/permissions/resources/limits/memory: expected one of a string, an integer, or nothing, got a boolean at line 13 column 7",
+            ],
+            "{messages:#?}"
+        );
+    }
+
+    #[test]
+    fn test_reports_missing_fields_and_types() {
+        let violations = validate_against_schema("description: 3\n").unwrap();
+        let messages: Vec<String> = violations.iter().map(|v| v.message.clone()).collect();
+        assert!(messages.contains(&"missing required field `version`".to_string()));
+        assert!(messages.contains(&"missing required field `permissions`".to_string()));
+        assert!(messages.contains(&"expected a string or nothing, got an integer".to_string()));
+
+        let violations = validate_against_schema(
+            "version: \"1.0\"\npermissions:\n  network:\n    allow:\n    - {}\n",
+        )
+        .unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].message, "missing required field `host`");
+
+        assert!(validate_against_schema("version: [unclosed").is_err());
+    }
+}
@@ -0,0 +1,428 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Strict checking of policy documents
+//!
+//! The policy types ignore keys they don't know, so a typo like `alow` silently drops a rule.
+//! Strict mode walks the YAML against the keys the policy format defines and rejects unknown
+//! and deprecated keys, reporting where they are in the document.
+
+use std::fmt;
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+use crate::PolicyResult;
+
+/// The keys allowed at a position in the document.
+#[derive(Clone, Copy)]
+enum Shape {
+    /// A mapping with only these keys
+    Fields(&'static [Field]),
+    /// A sequence whose items have this shape
+    List(&'static Shape),
+    /// Anything; the policy types check the value itself
+    Any,
+}
+
+#[derive(Clone, Copy)]
+struct Field {
+    name: &'static str,
+    shape: Shape,
+    /// What to use instead, if the key is deprecated
+    replacement: Option<&'static str>,
+}
+
+const fn field(name: &'static str, shape: Shape) -> Field {
+    Field {
+        name,
+        shape,
+        replacement: None,
+    }
+}
+
+const fn deprecated(name: &'static str, replacement: &'static str) -> Field {
+    Field {
+        name,
+        shape: Shape::Any,
+        replacement: Some(replacement),
+    }
+}
+
+const STORAGE_RULE: Shape = Shape::Fields(&[field("uri", Shape::Any), field("access", Shape::Any)]);
+const NETWORK_RULE: Shape = Shape::Fields(&[
+    field("host", Shape::Any),
+    field("cidr", Shape::Any),
+    field("ports", Shape::Any),
+    field("schemes", Shape::Any),
+    field("address-family", Shape::Any),
+]);
+const ENVIRONMENT_RULE: Shape = Shape::Fields(&[field("key", Shape::Any)]);
+const BROWSER_RULE: Shape = Shape::Fields(&[field("host", Shape::Any)]);
+
+const EMAIL_RULE: Shape =
+    Shape::Fields(&[field("address", Shape::Any), field("domain", Shape::Any)]);
+
+const IPC_RULE: Shape = Shape::Fields(&[field("uri", Shape::Any)]);
+
+const DOCKER: Shape = Shape::Fields(&[field(
+    "security",
+    Shape::Fields(&[
+        field("privileged", Shape::Any),
+        field("no_new_privileges", Shape::Any),
+        field(
+            "capabilities",
+            Shape::Fields(&[field("drop", Shape::Any), field("add", Shape::Any)]),
+        ),
+    ]),
+)]);
+
+const LIMITS: Shape = Shape::Fields(&[
+    field("cpu", Shape::Any),
+    field("memory", Shape::Any),
+    field("open-files", Shape::Any),
+    field("instances", Shape::Any),
+    field("max-concurrent-invocations", Shape::Any),
+    field("rate-limit", Shape::Any),
+    field("tables", Shape::Any),
+    field("table-elements", Shape::Any),
+    field("fuel", Shape::Any),
+    field("timeout", Shape::Any),
+]);
+
+const MOUNT: Shape = Shape::Fields(&[
+    field("archive", Shape::Any),
+    field("file", Shape::Any),
+    field("tmpfs", Shape::Any),
+    field("at", Shape::Any),
+]);
+
+const TOOL_CACHE: Shape = Shape::Fields(&[field("tool", Shape::Any), field("ttl", Shape::Any)]);
+
+const TOOL_SCHEDULE: Shape = Shape::Fields(&[
+    field("tool", Shape::Any),
+    field("allowed-hours", Shape::Any),
+    field("timezone", Shape::Any),
+    field("require-confirm-outside-hours", Shape::Any),
+]);
+
+const REQUEST_HEADER: Shape = Shape::Fields(&[
+    field("name", Shape::Any),
+    field("value", Shape::Any),
+    field("hosts", Shape::Any),
+    field("replace", Shape::Any),
+]);
+
+const PERMISSIONS: Shape = Shape::Fields(&[
+    field(
+        "storage",
+        Shape::Fields(&[
+            field("allow", Shape::List(&STORAGE_RULE)),
+            field("deny", Shape::List(&STORAGE_RULE)),
+        ]),
+    ),
+    field(
+        "network",
+        Shape::Fields(&[
+            field("allow", Shape::List(&NETWORK_RULE)),
+            field("deny", Shape::List(&NETWORK_RULE)),
+        ]),
+    ),
+    field(
+        "environment",
+        Shape::Fields(&[field("allow", Shape::List(&ENVIRONMENT_RULE))]),
+    ),
+    field(
+        "runtime",
+        Shape::Fields(&[field("docker", DOCKER), field("hyperlight", Shape::Any)]),
+    ),
+    field(
+        "resources",
+        Shape::Fields(&[
+            field("limits", LIMITS),
+            deprecated("cpu", "use `limits.cpu`"),
+            deprecated("memory", "use `limits.memory`"),
+            deprecated("io", "it is not enforced"),
+            field("threads", Shape::Any),
+            field("memory64", Shape::Any),
+        ]),
+    ),
+    field(
+        "ipc",
+        Shape::Fields(&[
+            field("allow", Shape::List(&IPC_RULE)),
+            field("deny", Shape::List(&IPC_RULE)),
+        ]),
+    ),
+    field(
+        "sampling",
+        Shape::Fields(&[
+            field("max-tokens", Shape::Any),
+            field("max-calls", Shape::Any),
+        ]),
+    ),
+    field("mounts", Shape::List(&MOUNT)),
+    field("cache", Shape::List(&TOOL_CACHE)),
+    field(
+        "keyvalue",
+        Shape::Fields(&[
+            field("max-bytes", Shape::Any),
+            field("max-keys", Shape::Any),
+        ]),
+    ),
+    field(
+        "vector",
+        Shape::Fields(&[
+            field("max-bytes", Shape::Any),
+            field("max-entries", Shape::Any),
+            field("max-dimensions", Shape::Any),
+        ]),
+    ),
+    field("context", Shape::Any),
+    field(
+        "browser",
+        Shape::Fields(&[
+            field("allow", Shape::List(&BROWSER_RULE)),
+            field("max-pages", Shape::Any),
+        ]),
+    ),
+    field(
+        "email",
+        Shape::Fields(&[
+            field("allow", Shape::List(&EMAIL_RULE)),
+            field("max-per-hour", Shape::Any),
+        ]),
+    ),
+    field("schedule", Shape::List(&TOOL_SCHEDULE)),
+    field("headers", Shape::List(&REQUEST_HEADER)),
+]);
+
+const DOCUMENT: Shape = Shape::Fields(&[
+    field("version", Shape::Any),
+    field("description", Shape::Any),
+    field("permissions", PERMISSIONS),
+]);
+
+/// Reject unknown and deprecated keys in the policy YAML `content`.
+///
+/// Errors name the key's path and its line and column, e.g. ``permissions.network: unknown
+/// field `alow`, expected `allow` or `deny` at line 4 column 5``. Values are left to the
+/// policy types to check.
+pub(crate) fn check(content: &str) -> PolicyResult<()> {
+    DOCUMENT.deserialize(serde_yaml::Deserializer::from_str(content))?;
+    Ok(())
+}
+
+impl<'de> DeserializeSeed<'de> for Shape {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        match self {
+            Shape::Any => deserializer.deserialize_ignored_any(IgnoredAny).map(drop),
+            shape => deserializer.deserialize_any(ShapeVisitor(shape)),
+        }
+    }
+}
+
+/// Checks mappings and sequences against a shape, accepting any other value as is.
+struct ShapeVisitor(Shape);
+
+impl<'de> Visitor<'de> for ShapeVisitor {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a policy value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let Shape::Fields(fields) = self.0 else {
+            while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+            return Ok(());
+        };
+        while let Some(field) = map.next_key_seed(FieldName(fields))? {
+            map.next_value_seed(field.shape)?;
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let item = match self.0 {
+            Shape::List(item) => *item,
+            _ => Shape::Any,
+        };
+        while seq.next_element_seed(item)?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        self.0.deserialize(deserializer)
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+/// Looks a mapping key up among the allowed fields. Checking the key while it is read makes
+/// the error point at the key rather than at the start of its mapping.
+struct FieldName(&'static [Field]);
+
+impl<'de> DeserializeSeed<'de> for FieldName {
+    type Value = Field;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Field, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FieldName {
+    type Value = Field;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", OneOf(self.0))
+    }
+
+    fn visit_str<E: de::Error>(self, key: &str) -> Result<Field, E> {
+        let Some(field) = self.0.iter().find(|field| field.name == key) else {
+            return Err(E::custom(format_args!(
+                "unknown field `{key}`, expected {}",
+                OneOf(self.0)
+            )));
+        };
+        if let Some(replacement) = field.replacement {
+            return Err(E::custom(format_args!(
+                "deprecated field `{key}`: {replacement}"
+            )));
+        }
+        Ok(*field)
+    }
+}
+
+/// The keys allowed in a mapping, as a list for an error message.
+struct OneOf(&'static [Field]);
+
+impl fmt::Display for OneOf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self
+            .0
+            .iter()
+            .filter(|field| field.replacement.is_none())
+            .map(|field| format!("`{}`", field.name))
+            .collect();
+        match names.as_slice() {
+            [] => f.write_str("no fields"),
+            [only] => f.write_str(only),
+            [first, second] => write!(f, "{first} or {second}"),
+            [rest @ .., last] => write!(f, "one of {}, or {last}", rest.join(", ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepts_known_keys() {
+        let yaml = r#"
+version: "1.0"
+description: "All sections"
+permissions:
+  storage:
+    allow:
+    - uri: "fs://work/**"
+      access: ["read"]
+  network:
+    allow:
+    - host: "api.example.com"
+    deny:
+    - cidr: "10.0.0.0/8"
+  environment:
+    allow:
+    - key: "HOME"
+  runtime:
+    docker:
+      security:
+        privileged: false
+        capabilities:
+          drop: ["ALL"]
+    hyperlight:
+      anything: goes
+  resources:
+    limits:
+      cpu: "500m"
+      memory: "64Mi"
+  sampling:
+    max-tokens: 100
+  mounts:
+  - tmpfs: "1Mi"
+    at: /tmp
+  cache:
+  - tool: fetch
+    ttl: 5m
+  keyvalue: {}
+  vector:
+    max-entries: 1000
+  browser:
+    allow:
+    - host: "*.example.com"
+    max-pages: 2
+  email:
+    allow:
+    - address: oncall@example.com
+    - domain: example.org
+    max-per-hour: 5
+"#;
+        check(yaml).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_unknown_key_with_location() {
+        let yaml =
+            "version: \"1.0\"\npermissions:\n  network:\n    alow:\n    - host: example.com\n";
+        let error = check(yaml).unwrap_err().to_string();
+        assert!(error.contains("permissions.network"), "{error}");
+        assert!(error.contains("unknown field `alow`"), "{error}");
+        assert!(error.contains("expected `allow` or `deny`"), "{error}");
+        assert!(error.contains("line 4 column 5"), "{error}");
+
+        let yaml = "version: \"1.0\"\npermissions:\n  storage:\n    allow:\n    - uri: fs://a\n      acess: [read]\n";
+        let error = check(yaml).unwrap_err().to_string();
+        assert!(error.contains("unknown field `acess`"), "{error}");
+        assert!(error.contains("line 6 column 7"), "{error}");
+    }
+
+    #[test]
+    fn test_rejects_deprecated_key() {
+        let yaml = "version: \"1.0\"\npermissions:\n  resources:\n    memory: 512\n";
+        let error = check(yaml).unwrap_err().to_string();
+        assert!(
+            error.contains("deprecated field `memory`: use `limits.memory`"),
+            "{error}"
+        );
+        assert!(error.contains("line 4"), "{error}");
+    }
+}
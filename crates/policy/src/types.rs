@@ -6,10 +6,13 @@
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::sync::OnceLock;
+use std::time::Duration;
 
-use anyhow::bail;
+use anyhow::{bail, Context};
 use serde::{Deserialize, Serialize};
 
+use crate::network::{parse_cidr, HostPattern};
+use crate::schedule::{AllowedHours, ScheduleZone};
 use crate::PolicyResult;
 
 /// read: read access
@@ -21,7 +24,22 @@ pub enum AccessType {
     Write,
 }
 
-/// uri: URI pattern for the resource (e.g. fs://work/agent/**)
+/// Scheme of storage URIs naming a `wasi:keyvalue` bucket, `kv://*` standing for every bucket
+pub const KEYVALUE_SCHEME: &str = "kv://";
+
+/// Scheme of storage URIs naming host paths
+pub const FILESYSTEM_SCHEME: &str = "fs://";
+
+/// What a storage permission grants access to, told by the scheme of its URI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind<'a> {
+    /// Host paths matching the pattern (`fs://`)
+    Filesystem(&'a str),
+    /// A key-value bucket, or every bucket with `*` (`kv://`)
+    KeyValue(&'a str),
+}
+
+/// uri: URI pattern for the resource (e.g. fs://work/agent/** or kv://cache)
 /// access: Access types allowed (read, write)
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StoragePermission {
@@ -31,22 +49,71 @@ pub struct StoragePermission {
     pub access: Vec<AccessType>,
 }
 
+impl StoragePermission {
+    /// The kind of resource the URI names, or `None` for a scheme storage doesn't know.
+    pub fn kind(&self) -> Option<StorageKind<'_>> {
+        if let Some(path) = self.uri.strip_prefix(FILESYSTEM_SCHEME) {
+            Some(StorageKind::Filesystem(path))
+        } else {
+            self.uri
+                .strip_prefix(KEYVALUE_SCHEME)
+                .map(StorageKind::KeyValue)
+        }
+    }
+}
+
 /// Network host permission
 ///
 /// host: Hostname or pattern (supports wildcards like *.domain.com)
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// ports: Ports the host may be reached on (any if unset)
+/// schemes: URL schemes the host may be reached with (any if unset)
+/// address-family: IP versions the host may be reached over (both if unset)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
 pub struct NetworkHostPermission {
     /// Hostname or pattern (supports wildcards like *.domain.com)
     pub host: String,
+    /// Ports the host may be reached on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ports: Option<Vec<u16>>,
+    /// URL schemes the host may be reached with, e.g. `https`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schemes: Option<Vec<String>>,
+    /// IP versions the host may be reached over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address_family: Option<AddressFamily>,
+}
+
+/// IP versions a network rule covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+pub enum AddressFamily {
+    /// IPv4 only
+    #[serde(rename = "v4-only")]
+    V4Only,
+    /// IPv6 only
+    #[serde(rename = "v6-only")]
+    V6Only,
+    /// Both IPv4 and IPv6
+    #[default]
+    #[serde(rename = "dual")]
+    Dual,
 }
 
 /// Network CIDR permission
 ///
 /// cidr: CIDR notation for network range (e.g. 10.0.0.0/8)
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// ports: Ports the addresses may be reached on (any if unset)
+/// schemes: URL schemes the addresses may be reached with (any if unset)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct NetworkCidrPermission {
     /// CIDR notation for network range
     pub cidr: String,
+    /// Ports the addresses may be reached on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ports: Option<Vec<u16>>,
+    /// URL schemes the addresses may be reached with, e.g. `https`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schemes: Option<Vec<String>>,
 }
 
 /// Network permission entry - can be either host or CIDR
@@ -61,6 +128,10 @@ pub enum NetworkPermission {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EnvironmentPermission {
     pub key: String,
+    /// Value to give the variable instead of the server's, which may refer to secrets as
+    /// `${secret:name}` and to server environment variables as `${env:NAME}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
 }
 
 /// Docker capability action
@@ -142,11 +213,36 @@ pub enum MemoryLimit {
 
 /// Resource limit values under the limits section
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
 pub struct ResourceLimitValues {
     /// CPU limit in k8s format (millicores "500m" or cores "1")
     pub cpu: Option<CpuLimit>,
     /// Memory limit in k8s format ("512Mi", "1Gi", "256Ki")
     pub memory: Option<MemoryLimit>,
+    /// Maximum number of files and directories a single instance may hold open
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_files: Option<u32>,
+    /// Maximum number of instances of the component running at the same time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instances: Option<u32>,
+    /// Maximum number of tool calls of the component in progress at the same time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_invocations: Option<u32>,
+    /// Tool calls of the component allowed per minute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<u32>,
+    /// Maximum number of tables a single instance may create
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tables: Option<u32>,
+    /// Maximum number of elements in any one table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table_elements: Option<u32>,
+    /// Fuel a single tool invocation may consume, about one unit per WebAssembly instruction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuel: Option<u64>,
+    /// Wall-clock time a single tool invocation may run ("30s", "5m")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<CallTimeout>,
     /// Cached parsed CPU value in cores (not serialized)
     #[serde(skip)]
     cpu_cores_cache: OnceLock<f64>,
@@ -167,6 +263,13 @@ pub struct ResourceLimits {
     pub memory: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub io: Option<u64>,
+    /// Threads the component may run; grants shared memory and atomics, bounded by
+    /// `limits.memory`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+    /// Whether the component may use 64-bit memories, bounded by `limits.memory`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub memory64: bool,
 }
 
 /// IPC permission configuration (future/TODO)
@@ -175,6 +278,189 @@ pub struct IpcPermission {
     pub uri: String,
 }
 
+/// Sampling permission: lets the component request LLM completions from the connected client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct SamplingPermission {
+    /// Upper bound on `max-tokens` for a single sampling request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Maximum number of sampling requests during a single tool invocation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_calls: Option<u32>,
+}
+
+/// Key-value permission: caps the persistent state the component keeps through `wasi:keyvalue`
+/// in the buckets its `kv://` storage permissions grant
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct KeyValuePermission {
+    /// Upper bound on the total size of keys and values ("1Mi")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<MemoryLimit>,
+    /// Maximum number of keys across all buckets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_keys: Option<u32>,
+}
+
+/// Vector permission: gives the component persistent embedding collections through
+/// `wassette:vector/store`, capped in size
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct VectorPermission {
+    /// Upper bound on the total size of ids, vectors and metadata ("16Mi")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<MemoryLimit>,
+    /// Maximum number of entries across all namespaces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_entries: Option<u32>,
+    /// Maximum number of dimensions of a vector
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_dimensions: Option<u32>,
+}
+
+/// Browser permission: lets the component load pages of the allowed sites in a host-managed
+/// headless browser through `wassette:browser/page`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct BrowserPermission {
+    /// Sites the browser may load pages and their subresources from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow: Option<Vec<NetworkHostPermission>>,
+    /// Maximum number of pages loaded during a single tool invocation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_pages: Option<u32>,
+}
+
+/// Email permission: lets the component send plain-text email to the allowed recipients through
+/// `wassette:email/send`, over the SMTP server named in its secrets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct EmailPermission {
+    /// Recipients the component may send to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow: Option<Vec<EmailRecipientPermission>>,
+    /// Maximum number of messages sent in any hour
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_per_hour: Option<u32>,
+}
+
+/// An allowed email recipient: a single address, or every address at a domain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct EmailRecipientPermission {
+    /// Address such as `oncall@example.com`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Domain such as `example.com`, allowing every address at it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+/// Session context permission: lets the component read the client's name and version, the
+/// locale and the user's profile values through `wassette:context/session`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextAccess {
+    Read,
+}
+
+/// Result cache of an idempotent tool: identical calls within `ttl` are answered with the
+/// first call's result instead of running the component again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolCache {
+    /// Name of the tool
+    pub tool: String,
+    /// How long a result stays valid
+    pub ttl: CacheTtl,
+}
+
+/// Hours at which a tool may be called, checked at every call. Outside them, calls are
+/// refused, or need the caller's confirmation with `require-confirm-outside-hours`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ToolSchedule {
+    /// Name of the tool
+    pub tool: String,
+    /// Weekly window such as "09:00-18:00 Mon-Fri"
+    pub allowed_hours: String,
+    /// Time zone of `allowed-hours`: "local" (the default), "utc" or an offset such as "+02:00"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// Let calls outside the hours through once the caller confirms them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_confirm_outside_hours: bool,
+}
+
+impl ToolSchedule {
+    /// The parsed `allowed-hours` and `timezone`.
+    pub fn window(&self) -> PolicyResult<(AllowedHours, ScheduleZone)> {
+        let hours = AllowedHours::parse(&self.allowed_hours)?;
+        let zone = match &self.timezone {
+            Some(zone) => ScheduleZone::parse(zone)?,
+            None => ScheduleZone::Local,
+        };
+        Ok((hours, zone))
+    }
+}
+
+/// A header added to the component's outgoing HTTP requests, such as a `User-Agent` naming the
+/// deployment. It is added when the component didn't set the header itself, or in place of the
+/// component's with `replace`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RequestHeader {
+    /// Header name, e.g. `User-Agent`
+    pub name: String,
+    /// Header value
+    pub value: String,
+    /// Host patterns of the requests the header goes on, as in network rules; every request
+    /// when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hosts: Option<Vec<String>>,
+    /// Replace the header when the component sets it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replace: bool,
+}
+
+/// Lifetime of a cached tool result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CacheTtl {
+    /// String format with a unit: "30s", "5m", "1h" or "1d"
+    String(String),
+    /// Number of seconds
+    Number(u64),
+}
+
+/// Wall-clock time limit of a tool invocation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CallTimeout {
+    /// String format with a unit: "30s", "5m", "1h" or "1d"
+    String(String),
+    /// Number of seconds
+    Number(u64),
+}
+
+/// Mount in the component's filesystem: a host archive or file, read-only, or an in-memory
+/// scratch directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Mount {
+    /// Zip or tar archive whose contents appear below `at`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive: Option<String>,
+    /// Host file that appears at `at`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Size cap of an empty in-memory filesystem mounted at `at` ("64Mi")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tmpfs: Option<MemoryLimit>,
+    /// Absolute path of the mount inside the component
+    pub at: String,
+}
+
 /// Runtime configuration
 ///
 /// TODO: add more sandboxing runtimes
@@ -215,6 +501,26 @@ pub struct Permissions {
     pub runtime: Option<Runtime>,
     pub resources: Option<ResourceLimits>,
     pub ipc: Option<PermissionList<IpcPermission>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SamplingPermission>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mounts: Option<Vec<Mount>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<Vec<ToolCache>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyvalue: Option<KeyValuePermission>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector: Option<VectorPermission>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<ContextAccess>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub browser: Option<BrowserPermission>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<EmailPermission>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Vec<ToolSchedule>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<Vec<RequestHeader>>,
 }
 
 impl CpuLimit {
@@ -309,12 +615,71 @@ impl MemoryLimit {
     }
 }
 
+/// Parse a number of seconds with an optional unit: "30s", "5m", "1h" or "1d". `what` names
+/// the value in errors.
+fn parse_seconds(s: &str, what: &str) -> PolicyResult<u64> {
+    let (value_str, multiplier) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1u64),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 60 * 60),
+        Some((i, 'd')) => (&s[..i], 24 * 60 * 60),
+        // No suffix, assume seconds
+        _ => (s, 1),
+    };
+    let value: u64 = value_str
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid {}: {}", what, s))?;
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow::anyhow!("Invalid {}: {} is too large", what, s))
+}
+
+impl CacheTtl {
+    /// Validate and convert the TTL to a duration
+    pub fn to_duration(&self) -> PolicyResult<Duration> {
+        let seconds = match self {
+            CacheTtl::String(s) => parse_seconds(s, "cache ttl")?,
+            CacheTtl::Number(n) => *n,
+        };
+
+        if seconds == 0 {
+            bail!("Cache ttl cannot be zero");
+        }
+
+        Ok(Duration::from_secs(seconds))
+    }
+}
+
+impl CallTimeout {
+    /// Validate and convert the timeout to a duration
+    pub fn to_duration(&self) -> PolicyResult<Duration> {
+        let seconds = match self {
+            CallTimeout::String(s) => parse_seconds(s, "timeout")?,
+            CallTimeout::Number(n) => *n,
+        };
+
+        if seconds == 0 {
+            bail!("Resource limit 'timeout' must be at least 1 second");
+        }
+
+        Ok(Duration::from_secs(seconds))
+    }
+}
+
 impl ResourceLimitValues {
     /// Create a new ResourceLimitValues instance
     pub fn new(cpu: Option<CpuLimit>, memory: Option<MemoryLimit>) -> Self {
         Self {
             cpu,
             memory,
+            open_files: None,
+            instances: None,
+            max_concurrent_invocations: None,
+            rate_limit: None,
+            tables: None,
+            table_elements: None,
+            fuel: None,
+            timeout: None,
             cpu_cores_cache: OnceLock::new(),
             memory_bytes_cache: OnceLock::new(),
         }
@@ -359,6 +724,27 @@ impl ResourceLimitValues {
         // Validation now uses the cached getters, which will parse and cache the values
         self.cpu_cores()?;
         self.memory_bytes()?;
+        for (name, value) in [
+            ("open-files", self.open_files),
+            ("instances", self.instances),
+            (
+                "max-concurrent-invocations",
+                self.max_concurrent_invocations,
+            ),
+            ("rate-limit", self.rate_limit),
+            ("tables", self.tables),
+            ("table-elements", self.table_elements),
+        ] {
+            if value == Some(0) {
+                bail!("Resource limit '{}' must be at least 1", name);
+            }
+        }
+        if self.fuel == Some(0) {
+            bail!("Resource limit 'fuel' must be at least 1");
+        }
+        if let Some(timeout) = &self.timeout {
+            timeout.to_duration()?;
+        }
         Ok(())
     }
 }
@@ -385,16 +771,44 @@ impl ResourceLimits {
             // IO values are fine as u64 is naturally non-negative
         }
 
+        let memory_limited = self.memory.is_some()
+            || self
+                .limits
+                .as_ref()
+                .is_some_and(|limits| limits.memory.is_some());
+        if let Some(threads) = self.threads {
+            if threads == 0 {
+                bail!("Resource 'threads' must be at least 1");
+            }
+            if !memory_limited {
+                bail!("Resource 'threads' needs 'limits.memory' to bound the shared memory");
+            }
+        }
+        if self.memory64 && !memory_limited {
+            bail!("Resource 'memory64' needs 'limits.memory' to bound the 64-bit memories");
+        }
+
         Ok(())
     }
 }
 
 impl Permissions {
-    fn validate_storage_uri(uri: &str) -> PolicyResult<()> {
+    /// Check that `uri` is a storage URI a policy may hold, such as a single `kv://` bucket or
+    /// an `fs://` glob with well-formed wildcards.
+    pub fn validate_storage_uri(uri: &str) -> PolicyResult<()> {
         if uri.is_empty() {
             bail!("Storage URI can't be empty");
         }
 
+        if let Some(bucket) = uri.strip_prefix(KEYVALUE_SCHEME) {
+            if bucket.contains('*') && bucket != "*" {
+                bail!(
+                    "Key-value storage URI {uri} must name one bucket, or every bucket with kv://*"
+                );
+            }
+            return Ok(());
+        }
+
         if uri.contains("***") {
             bail!("Too many wildcards in: {}", uri);
         }
@@ -440,6 +854,150 @@ impl Permissions {
         Ok(())
     }
 
+    pub(crate) fn validate_network_rule(rule: &NetworkPermission) -> PolicyResult<()> {
+        let (ports, schemes) = match rule {
+            NetworkPermission::Host(host_perm) => {
+                let pattern = HostPattern::parse(&host_perm.host);
+                if pattern.scheme == Some("") {
+                    bail!("Scheme is empty in host: {}", host_perm.host);
+                }
+                Self::validate_network_host(pattern.name)?;
+                let excluded = pattern
+                    .name
+                    .parse()
+                    .is_ok_and(|address| !rule.address_family().allows(address));
+                if excluded {
+                    bail!("The address family excludes the host of network rule: {rule}");
+                }
+                (&host_perm.ports, &host_perm.schemes)
+            }
+            NetworkPermission::Cidr(cidr_perm) => {
+                if cidr_perm.cidr.is_empty() {
+                    bail!("CIDR can't be empty");
+                }
+                if !cidr_perm.cidr.contains('/') {
+                    bail!("CIDR needs a slash: {}", cidr_perm.cidr);
+                }
+                if parse_cidr(&cidr_perm.cidr).is_none() {
+                    bail!("Invalid CIDR range: {}", cidr_perm.cidr);
+                }
+                (&cidr_perm.ports, &cidr_perm.schemes)
+            }
+        };
+        if let Some(ports) = ports {
+            if ports.is_empty() {
+                bail!("Ports can't be empty in network rule: {rule}");
+            }
+            if ports.contains(&0) {
+                bail!("Port 0 is not valid in network rule: {rule}");
+            }
+        }
+        if let Some(schemes) = schemes {
+            if schemes.is_empty() {
+                bail!("Schemes can't be empty in network rule: {rule}");
+            }
+            let valid = |scheme: &String| {
+                scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && scheme
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+            };
+            if let Some(scheme) = schemes.iter().find(|scheme| !valid(scheme)) {
+                bail!("Invalid scheme {scheme:?} in network rule: {rule}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Headers that frame the request or belong to the connection are the HTTP client's to set.
+    fn validate_request_header(header: &RequestHeader) -> PolicyResult<()> {
+        const RESERVED: &[&str] = &[
+            "host",
+            "content-length",
+            "transfer-encoding",
+            "connection",
+            "keep-alive",
+            "upgrade",
+            "te",
+            "trailer",
+            "proxy-authorization",
+            "proxy-connection",
+        ];
+        let name = &header.name;
+        let token = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+        if name.is_empty() || !name.chars().all(token) {
+            bail!("Invalid request header name: {name:?}");
+        }
+        if RESERVED
+            .iter()
+            .any(|reserved| name.eq_ignore_ascii_case(reserved))
+        {
+            bail!("Request header {name} can't be set by policy");
+        }
+        if header
+            .value
+            .chars()
+            .any(|c| c.is_ascii_control() && c != '\t')
+        {
+            bail!("Value of request header {name} can't contain control characters");
+        }
+        if let Some(hosts) = &header.hosts {
+            if hosts.is_empty() {
+                bail!("Hosts can't be empty for request header {name}");
+            }
+            for host in hosts {
+                Self::validate_network_host(HostPattern::parse(host).name)
+                    .with_context(|| format!("Invalid host for request header {name}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Browser hosts name sites: a bare `*` would open the whole web, including the local
+    /// network, to the page and everything it loads.
+    fn validate_browser_host(host: &str) -> PolicyResult<()> {
+        Self::validate_network_host(host)?;
+        if host == "*" {
+            bail!("Browser hosts must name sites, `*` is not allowed");
+        }
+        let name = host.strip_prefix("*.").unwrap_or(host);
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        {
+            bail!("Browser host must be a hostname: {}", host);
+        }
+        Ok(())
+    }
+
+    /// Email domains are plain hostnames; there are no wildcards.
+    fn validate_email_domain(domain: &str) -> PolicyResult<()> {
+        if domain.is_empty()
+            || domain.starts_with('.')
+            || domain.ends_with('.')
+            || !domain
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        {
+            bail!("Email domain must be a hostname: {}", domain);
+        }
+        Ok(())
+    }
+
+    fn validate_email_address(address: &str) -> PolicyResult<()> {
+        let Some((local, domain)) = address.rsplit_once('@') else {
+            bail!("Email address needs an @: {}", address);
+        };
+        if local.is_empty()
+            || local
+                .chars()
+                .any(|c| c.is_whitespace() || c.is_control() || "<>,;:\"@".contains(c))
+        {
+            bail!("Email address is not a plain address: {}", address);
+        }
+        Self::validate_email_domain(domain)
+    }
+
     fn validate_environment_key(key: &str) -> PolicyResult<()> {
         if key.is_empty() {
             bail!("Environment key can't be empty");
@@ -453,6 +1011,41 @@ impl Permissions {
         Ok(())
     }
 
+    fn validate_mount(mount: &Mount) -> PolicyResult<()> {
+        let sources = [
+            mount.archive.is_some(),
+            mount.file.is_some(),
+            mount.tmpfs.is_some(),
+        ];
+        match sources.iter().filter(|&&set| set).count() {
+            0 => bail!("Mount at {} needs an archive, a file or a tmpfs", mount.at),
+            1 => {}
+            _ => bail!(
+                "Mount at {} can only have one of archive, file or tmpfs",
+                mount.at
+            ),
+        }
+        if mount.archive.as_deref() == Some("") || mount.file.as_deref() == Some("") {
+            bail!("Mount source can't be empty");
+        }
+        if let Some(size) = &mount.tmpfs {
+            size.to_bytes()
+                .with_context(|| format!("Invalid tmpfs size for mount at {}", mount.at))?;
+        }
+
+        if !mount.at.starts_with('/') {
+            bail!("Mount path must be absolute: {}", mount.at);
+        }
+        if mount.at.split('/').any(|part| part == "..") {
+            bail!("Mount path can't contain '..': {}", mount.at);
+        }
+        if mount.file.is_some() && mount.at.trim_end_matches('/').is_empty() {
+            bail!("A file can't be mounted at /");
+        }
+
+        Ok(())
+    }
+
     /// Validate the permissions structure
     pub fn validate(&self) -> PolicyResult<()> {
         if let Some(storage) = &self.storage {
@@ -475,39 +1068,8 @@ impl Permissions {
         }
 
         if let Some(network) = &self.network {
-            if let Some(allow_list) = &network.allow {
-                for perm in allow_list {
-                    match perm {
-                        NetworkPermission::Host(host_perm) => {
-                            Self::validate_network_host(&host_perm.host)?;
-                        }
-                        NetworkPermission::Cidr(cidr_perm) => {
-                            if cidr_perm.cidr.is_empty() {
-                                bail!("CIDR can't be empty");
-                            }
-                            if !cidr_perm.cidr.contains('/') {
-                                bail!("CIDR needs a slash: {}", cidr_perm.cidr);
-                            }
-                        }
-                    }
-                }
-            }
-            if let Some(deny_list) = &network.deny {
-                for perm in deny_list {
-                    match perm {
-                        NetworkPermission::Host(host_perm) => {
-                            Self::validate_network_host(&host_perm.host)?;
-                        }
-                        NetworkPermission::Cidr(cidr_perm) => {
-                            if cidr_perm.cidr.is_empty() {
-                                bail!("CIDR can't be empty");
-                            }
-                            if !cidr_perm.cidr.contains('/') {
-                                bail!("CIDR needs a slash: {}", cidr_perm.cidr);
-                            }
-                        }
-                    }
-                }
+            for perm in network.allow.iter().chain(&network.deny).flatten() {
+                Self::validate_network_rule(perm)?;
             }
         }
 
@@ -519,10 +1081,104 @@ impl Permissions {
             }
         }
 
+        if let Some(mounts) = &self.mounts {
+            for mount in mounts {
+                Self::validate_mount(mount)?;
+            }
+        }
+
         if let Some(resources) = &self.resources {
             resources.validate()?;
         }
 
+        if let Some(sampling) = &self.sampling {
+            if sampling.max_tokens == Some(0) {
+                bail!("Sampling max-tokens must be greater than zero");
+            }
+        }
+
+        if let Some(keyvalue) = &self.keyvalue {
+            if let Some(max_bytes) = &keyvalue.max_bytes {
+                max_bytes
+                    .to_bytes()
+                    .context("Invalid key-value max-bytes")?;
+            }
+            if keyvalue.max_keys == Some(0) {
+                bail!("Key-value max-keys must be greater than zero");
+            }
+        }
+
+        if let Some(vector) = &self.vector {
+            if let Some(max_bytes) = &vector.max_bytes {
+                max_bytes.to_bytes().context("Invalid vector max-bytes")?;
+            }
+            if vector.max_entries == Some(0) {
+                bail!("Vector max-entries must be greater than zero");
+            }
+            if vector.max_dimensions == Some(0) {
+                bail!("Vector max-dimensions must be greater than zero");
+            }
+        }
+
+        if let Some(browser) = &self.browser {
+            let hosts = browser.allow.as_deref().unwrap_or_default();
+            if hosts.is_empty() {
+                bail!("Browser needs at least one allowed host");
+            }
+            for perm in hosts {
+                Self::validate_browser_host(&perm.host)?;
+            }
+            if browser.max_pages == Some(0) {
+                bail!("Browser max-pages must be greater than zero");
+            }
+        }
+
+        if let Some(email) = &self.email {
+            let recipients = email.allow.as_deref().unwrap_or_default();
+            if recipients.is_empty() {
+                bail!("Email needs at least one allowed recipient");
+            }
+            for perm in recipients {
+                match (&perm.address, &perm.domain) {
+                    (Some(address), None) => Self::validate_email_address(address)?,
+                    (None, Some(domain)) => Self::validate_email_domain(domain)?,
+                    _ => bail!("Email recipient needs either an address or a domain"),
+                }
+            }
+            if email.max_per_hour == Some(0) {
+                bail!("Email max-per-hour must be greater than zero");
+            }
+        }
+
+        if let Some(cache) = &self.cache {
+            for entry in cache {
+                if entry.tool.is_empty() {
+                    bail!("Cache tool name can't be empty");
+                }
+                entry
+                    .ttl
+                    .to_duration()
+                    .with_context(|| format!("Invalid cache ttl for tool {}", entry.tool))?;
+            }
+        }
+
+        if let Some(schedule) = &self.schedule {
+            for entry in schedule {
+                if entry.tool.is_empty() {
+                    bail!("Schedule tool name can't be empty");
+                }
+                entry
+                    .window()
+                    .with_context(|| format!("Invalid schedule for tool {}", entry.tool))?;
+            }
+        }
+
+        if let Some(headers) = &self.headers {
+            for header in headers {
+                Self::validate_request_header(header)?;
+            }
+        }
+
         Ok(())
     }
 }
@@ -553,6 +1209,7 @@ mod tests {
             network: Some(PermissionList {
                 allow: Some(vec![NetworkPermission::Cidr(NetworkCidrPermission {
                     cidr: "invalid-cidr".to_string(), // Invalid CIDR format
+                    ..Default::default()
                 })]),
                 deny: None,
             }),
@@ -560,6 +1217,27 @@ mod tests {
         };
 
         assert!(permissions.validate().is_err());
+
+        let rule = |cidr: &str, ports: Option<Vec<u16>>, schemes: Option<&[&str]>| Permissions {
+            network: Some(PermissionList {
+                allow: Some(vec![NetworkPermission::Cidr(NetworkCidrPermission {
+                    cidr: cidr.to_string(),
+                    ports,
+                    schemes: schemes.map(|schemes| schemes.iter().map(|s| s.to_string()).collect()),
+                })]),
+                deny: None,
+            }),
+            ..Default::default()
+        };
+        assert!(rule("10.0.0.0/8", Some(vec![443]), Some(&["https"]))
+            .validate()
+            .is_ok());
+        assert!(rule("10.0.0.0/33", None, None).validate().is_err());
+        assert!(rule("10.0.0.0/8", Some(vec![]), None).validate().is_err());
+        assert!(rule("10.0.0.0/8", Some(vec![0]), None).validate().is_err());
+        assert!(rule("10.0.0.0/8", None, Some(&["https://"]))
+            .validate()
+            .is_err());
     }
 
     #[test]
@@ -578,6 +1256,28 @@ mod tests {
         assert!(permissions.validate().is_ok());
     }
 
+    #[test]
+    fn test_storage_kind() {
+        let storage = |uri: &str| StoragePermission {
+            uri: uri.to_string(),
+            access: vec![AccessType::Read],
+        };
+        assert_eq!(
+            storage("fs://work/**").kind(),
+            Some(StorageKind::Filesystem("work/**"))
+        );
+        assert_eq!(
+            storage("kv://cache").kind(),
+            Some(StorageKind::KeyValue("cache"))
+        );
+        assert_eq!(storage("s3://bucket").kind(), None);
+
+        assert!(Permissions::validate_storage_uri("kv://cache").is_ok());
+        assert!(Permissions::validate_storage_uri("kv://").is_ok());
+        assert!(Permissions::validate_storage_uri("kv://*").is_ok());
+        assert!(Permissions::validate_storage_uri("kv://cache-*").is_err());
+    }
+
     #[test]
     fn test_storage_uri_wildcard_validation() {
         assert!(Permissions::validate_storage_uri("fs://work/agent/**").is_ok());
@@ -647,13 +1347,16 @@ mod tests {
                 allow: Some(vec![
                     NetworkPermission::Host(NetworkHostPermission {
                         host: "*.example.com".to_string(),
+                        ..Default::default()
                     }),
                     NetworkPermission::Host(NetworkHostPermission {
                         host: "api.service.com".to_string(),
+                        ..Default::default()
                     }),
                 ]),
                 deny: Some(vec![NetworkPermission::Host(NetworkHostPermission {
                     host: "*.malicious.com".to_string(),
+                    ..Default::default()
                 })]),
             }),
             // Test environment with valid keys (no wildcards allowed)
@@ -661,12 +1364,15 @@ mod tests {
                 allow: Some(vec![
                     EnvironmentPermission {
                         key: "PATH".to_string(),
+                        value: None,
                     },
                     EnvironmentPermission {
                         key: "HOME".to_string(),
+                        value: None,
                     },
                     EnvironmentPermission {
                         key: "MY_DEBUG_VAR".to_string(),
+                        value: None,
                     },
                 ]),
             }),
@@ -776,6 +1482,44 @@ mod tests {
         let invalid_memory =
             ResourceLimitValues::new(None, Some(MemoryLimit::String("invalidMi".to_string())));
         assert!(invalid_memory.validate().is_err());
+
+        // Count limits must allow at least one
+        let mut counts = ResourceLimitValues::new(None, None);
+        counts.open_files = Some(64);
+        counts.instances = Some(2);
+        counts.max_concurrent_invocations = Some(2);
+        counts.rate_limit = Some(60);
+        assert!(counts.validate().is_ok());
+        counts.rate_limit = Some(0);
+        assert!(counts.validate().is_err());
+        counts.rate_limit = Some(60);
+        counts.tables = Some(0);
+        assert!(counts.validate().is_err());
+
+        let mut fuel = ResourceLimitValues::new(None, None);
+        fuel.fuel = Some(1_000_000);
+        assert!(fuel.validate().is_ok());
+        fuel.fuel = Some(0);
+        assert!(fuel.validate().is_err());
+
+        let mut timeout = ResourceLimitValues::new(None, None);
+        for (value, seconds) in [
+            (CallTimeout::String("30s".to_string()), 30),
+            (CallTimeout::String("2m".to_string()), 120),
+            (CallTimeout::Number(5), 5),
+        ] {
+            assert_eq!(value.to_duration().unwrap().as_secs(), seconds);
+            timeout.timeout = Some(value);
+            assert!(timeout.validate().is_ok());
+        }
+        for value in [
+            CallTimeout::String("0s".to_string()),
+            CallTimeout::String("soon".to_string()),
+            CallTimeout::Number(0),
+        ] {
+            timeout.timeout = Some(value);
+            assert!(timeout.validate().is_err());
+        }
     }
 
     #[test]
@@ -819,6 +1563,8 @@ mod tests {
             cpu: None,
             memory: None,
             io: None,
+            threads: None,
+            memory64: false,
         };
         assert!(valid_new.validate().is_ok());
 
@@ -828,6 +1574,8 @@ mod tests {
             cpu: Some(1.5),
             memory: Some(512),
             io: Some(1000),
+            threads: None,
+            memory64: false,
         };
         assert!(valid_legacy.validate().is_ok());
 
@@ -840,6 +1588,8 @@ mod tests {
             cpu: None,
             memory: None,
             io: None,
+            threads: None,
+            memory64: false,
         };
         assert!(invalid_new.validate().is_err());
 
@@ -849,8 +1599,34 @@ mod tests {
             cpu: Some(-1.0),
             memory: None,
             io: None,
+            threads: None,
+            memory64: false,
         };
         assert!(invalid_legacy.validate().is_err());
+
+        // Threads need a memory limit to bound the shared memory
+        let threads = |memory: Option<&str>, threads: u32| ResourceLimits {
+            limits: Some(ResourceLimitValues::new(
+                None,
+                memory.map(|memory| MemoryLimit::String(memory.to_string())),
+            )),
+            threads: Some(threads),
+            ..Default::default()
+        };
+        assert!(threads(Some("1Gi"), 4).validate().is_ok());
+        assert!(threads(None, 4).validate().is_err());
+        assert!(threads(Some("1Gi"), 0).validate().is_err());
+
+        let memory64 = ResourceLimits {
+            memory64: true,
+            ..Default::default()
+        };
+        assert!(memory64.validate().is_err());
+        let memory64 = ResourceLimits {
+            memory: Some(8192),
+            ..memory64
+        };
+        assert!(memory64.validate().is_ok());
     }
 
     #[test]
@@ -874,13 +1650,101 @@ mod tests {
                 cpu: None,
                 memory: None,
                 io: None,
+                threads: None,
+                memory64: false,
             }),
             ipc: None,
+            sampling: None,
+            mounts: None,
+            cache: None,
+            keyvalue: None,
+            vector: None,
+            context: None,
+            browser: None,
+            email: None,
+            schedule: None,
+            headers: None,
         };
 
         assert!(permissions.validate().is_ok());
     }
 
+    #[test]
+    fn test_mount_validation() {
+        let mount = |archive: Option<&str>, file: Option<&str>, at: &str| Permissions {
+            mounts: Some(vec![Mount {
+                archive: archive.map(str::to_string),
+                file: file.map(str::to_string),
+                at: at.to_string(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+
+        assert!(mount(Some("data.zip"), None, "/data").validate().is_ok());
+        assert!(mount(None, Some("model.bin"), "/models/model.bin")
+            .validate()
+            .is_ok());
+        assert!(mount(Some("data.zip"), Some("model.bin"), "/data")
+            .validate()
+            .is_err());
+        assert!(mount(None, None, "/data").validate().is_err());
+        assert!(mount(Some(""), None, "/data").validate().is_err());
+        assert!(mount(Some("data.zip"), None, "data").validate().is_err());
+        assert!(mount(Some("data.zip"), None, "/data/../etc")
+            .validate()
+            .is_err());
+        assert!(mount(None, Some("model.bin"), "/").validate().is_err());
+
+        let tmpfs = |size: MemoryLimit| Permissions {
+            mounts: Some(vec![Mount {
+                tmpfs: Some(size),
+                at: "/scratch".to_string(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        assert!(tmpfs(MemoryLimit::String("64Mi".to_string()))
+            .validate()
+            .is_ok());
+        assert!(tmpfs(MemoryLimit::String("0".to_string()))
+            .validate()
+            .is_err());
+        assert!(tmpfs(MemoryLimit::String("lots".to_string()))
+            .validate()
+            .is_err());
+        let mut both = tmpfs(MemoryLimit::Number(64));
+        both.mounts.as_mut().unwrap()[0].archive = Some("data.zip".to_string());
+        assert!(both.validate().is_err());
+    }
+
+    #[test]
+    fn test_cache_ttl_parsing() {
+        let secs = |ttl: CacheTtl| ttl.to_duration().map(|d| d.as_secs());
+        assert_eq!(secs(CacheTtl::String("30s".to_string())).unwrap(), 30);
+        assert_eq!(secs(CacheTtl::String("5m".to_string())).unwrap(), 300);
+        assert_eq!(secs(CacheTtl::String("1h".to_string())).unwrap(), 3600);
+        assert_eq!(secs(CacheTtl::String("1d".to_string())).unwrap(), 86400);
+        assert_eq!(secs(CacheTtl::String("45".to_string())).unwrap(), 45);
+        assert_eq!(secs(CacheTtl::Number(10)).unwrap(), 10);
+
+        assert!(secs(CacheTtl::String("0s".to_string())).is_err());
+        assert!(secs(CacheTtl::String("".to_string())).is_err());
+        assert!(secs(CacheTtl::String("soon".to_string())).is_err());
+        assert!(secs(CacheTtl::String("-5m".to_string())).is_err());
+        assert!(secs(CacheTtl::Number(0)).is_err());
+
+        let cache = |tool: &str| Permissions {
+            cache: Some(vec![ToolCache {
+                tool: tool.to_string(),
+                ttl: CacheTtl::String("5m".to_string()),
+            }]),
+            ..Default::default()
+        };
+        assert!(cache("get-weather").validate().is_ok());
+        assert!(cache("").validate().is_err());
+    }
+
     #[test]
     fn test_invalid_wildcard_combinations() {
         let mut permissions = Permissions {
@@ -900,6 +1764,7 @@ mod tests {
         permissions.network = Some(PermissionList {
             allow: Some(vec![NetworkPermission::Host(NetworkHostPermission {
                 host: "example*.com".to_string(), // Invalid: * in middle
+                ..Default::default()
             })]),
             deny: None,
         });
@@ -909,6 +1774,7 @@ mod tests {
         permissions.environment = Some(EnvironmentPermissions {
             allow: Some(vec![EnvironmentPermission {
                 key: "PATH_WITH_WILDCARD_*".to_string(),
+                value: None,
             }]),
         });
         assert!(permissions.validate().is_err());
@@ -0,0 +1,121 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Throughput of argument validation and conversion inline on the async runtime versus on a
+//! dedicated validation pool, for batches of concurrent calls.
+//!
+//! The runtime has two worker threads, standing in for runtime threads that also drive the
+//! I/O of every other call; the pool has one thread per CPU. Run with
+//! `cargo bench -p wassette --bench validation`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use component2json::{json_to_vals, vals_to_json};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::{json, Value};
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{Component, Type};
+use wasmtime::Engine;
+use wassette::ValidationPool;
+
+const RUNTIME_THREADS: usize = 2;
+const CONCURRENCY: &[usize] = &[1, 16, 128];
+
+/// A component importing a function whose parameters are validated against.
+const COMPONENT: &str = r#"
+    (component
+      (type $item' (record (field "name" string) (field "tags" (list string)) (field "score" float64)))
+      (import "item" (type $item (eq $item')))
+      (import "index" (func (param "items" (list $item)) (param "limit" (option u32))))
+    )
+"#;
+
+fn parameter_types() -> Arc<Vec<(String, Type)>> {
+    let engine = Engine::default();
+    let component = Component::new(&engine, COMPONENT).expect("valid component");
+    let func = component
+        .component_type()
+        .imports(&engine)
+        .find_map(|(name, item)| match item {
+            ComponentItem::ComponentFunc(func) if name == "index" => Some(func),
+            _ => None,
+        })
+        .expect("index import");
+    Arc::new(
+        func.params()
+            .map(|(name, ty)| (name.to_string(), ty))
+            .collect(),
+    )
+}
+
+/// Arguments of about 40 KiB, the size of a page of search results.
+fn arguments() -> Arc<str> {
+    let items: Vec<Value> = (0..200)
+        .map(|i| {
+            json!({
+                "name": format!("document-{i}"),
+                "tags": ["alpha", "beta", "gamma", format!("tag-{i}")],
+                "score": i as f64 / 7.0,
+            })
+        })
+        .collect();
+    json!({"items": items, "limit": 50}).to_string().into()
+}
+
+/// What a call does with its arguments and results outside the component.
+fn convert(arguments: &str, types: &[(String, Type)]) -> Result<String> {
+    let arguments: Value = serde_json::from_str(arguments)?;
+    let vals = json_to_vals(&arguments, types)?;
+    Ok(serde_json::to_string(&vals_to_json(&vals))?)
+}
+
+fn bench_validation(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(RUNTIME_THREADS)
+        .enable_all()
+        .build()
+        .unwrap();
+    let types = parameter_types();
+    let arguments = arguments();
+    let pools = [
+        ("inline", Arc::new(ValidationPool::inline())),
+        (
+            "pool",
+            Arc::new(ValidationPool::new(num_cpus::get(), Duration::from_secs(60)).unwrap()),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("validation");
+    for &concurrency in CONCURRENCY {
+        group.throughput(Throughput::Elements(concurrency as u64));
+        for (name, pool) in &pools {
+            group.bench_with_input(
+                BenchmarkId::new(*name, concurrency),
+                &concurrency,
+                |b, &n| {
+                    b.to_async(&runtime).iter(|| async {
+                        let calls: Vec<_> = (0..n)
+                            .map(|_| {
+                                let pool = Arc::clone(pool);
+                                let types = Arc::clone(&types);
+                                let arguments = Arc::clone(&arguments);
+                                tokio::spawn(async move {
+                                    pool.run(move || convert(&arguments, &types)).await
+                                })
+                            })
+                            .collect();
+                        for call in calls {
+                            call.await.unwrap().unwrap();
+                        }
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_validation);
+criterion_main!(benches);
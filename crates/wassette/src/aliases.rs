@@ -0,0 +1,153 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Alternative ids of components.
+//!
+//! A component's id comes from the file name of its source, so moving it to another OCI
+//! repository would give it a new id, and with it no policy, secrets, presets or state. An
+//! alias maps such an id to the id of an existing component: loading a source whose id is an
+//! alias replaces that component, and every lookup by component id treats the alias as the id
+//! it points to. Aliases are kept in `aliases.json` in the plugin directory.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use tokio::sync::RwLock;
+
+/// Name of the alias file in the plugin directory.
+pub const ALIASES_FILE: &str = "aliases.json";
+
+/// Check that `id` can name a component: ASCII letters, digits, `-`, `_` and `.`, not starting
+/// with `.`, since it names the component's files.
+pub(crate) fn check_component_id(id: &str) -> Result<()> {
+    let valid = !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        bail!("Component ids must be ASCII letters, digits, '-', '_' or '.', got: {id:?}");
+    }
+    Ok(())
+}
+
+/// The aliases of a lifecycle manager, by alias.
+pub(crate) struct ComponentAliases {
+    path: PathBuf,
+    aliases: RwLock<BTreeMap<String, String>>,
+}
+
+impl ComponentAliases {
+    /// Read the aliases of the components in `plugin_dir`.
+    pub(crate) async fn load(plugin_dir: &Path) -> Result<Self> {
+        let path = plugin_dir.join(ALIASES_FILE);
+        let aliases = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Malformed alias file {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Self {
+            path,
+            aliases: RwLock::new(aliases),
+        })
+    }
+
+    /// The component id `id` stands for.
+    pub(crate) async fn resolve(&self, id: &str) -> String {
+        self.aliases
+            .read()
+            .await
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| id.to_string())
+    }
+
+    pub(crate) async fn list(&self) -> BTreeMap<String, String> {
+        self.aliases.read().await.clone()
+    }
+
+    /// Make `alias` stand for `component_id`, which must not be an alias itself.
+    pub(crate) async fn add(&self, alias: &str, component_id: &str) -> Result<()> {
+        let mut aliases = self.aliases.write().await;
+        if aliases.contains_key(component_id) {
+            bail!("'{component_id}' is an alias, not a component id");
+        }
+        if aliases.values().any(|id| id == alias) {
+            bail!("'{alias}' is the id of a component that has aliases");
+        }
+        let mut updated = aliases.clone();
+        updated.insert(alias.to_string(), component_id.to_string());
+        self.write(&updated).await?;
+        *aliases = updated;
+        Ok(())
+    }
+
+    /// Remove `alias`, returning the component id it stood for.
+    pub(crate) async fn remove(&self, alias: &str) -> Result<Option<String>> {
+        let mut aliases = self.aliases.write().await;
+        let mut updated = aliases.clone();
+        let Some(component_id) = updated.remove(alias) else {
+            return Ok(None);
+        };
+        self.write(&updated).await?;
+        *aliases = updated;
+        Ok(Some(component_id))
+    }
+
+    async fn write(&self, aliases: &BTreeMap<String, String>) -> Result<()> {
+        let mut contents = serde_json::to_vec_pretty(aliases)?;
+        contents.push(b'\n');
+        let staged = self.path.with_extension("json.tmp");
+        tokio::fs::write(&staged, contents)
+            .await
+            .with_context(|| format!("Failed to write {}", staged.display()))?;
+        tokio::fs::rename(&staged, &self.path)
+            .await
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_component_ids() {
+        for id in ["fetch-rs", "fetch_rs", "time.server", "v2"] {
+            check_component_id(id).unwrap();
+        }
+        for id in ["", ".hidden", "../fetch", "a/b", "a b", "fétch"] {
+            assert!(check_component_id(id).is_err(), "{id}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_aliases_resolve_and_persist() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let aliases = ComponentAliases::load(dir.path()).await?;
+        assert_eq!(aliases.resolve("web-fetch").await, "web-fetch");
+
+        aliases.add("web-fetch", "fetch-rs").await?;
+        assert_eq!(aliases.resolve("web-fetch").await, "fetch-rs");
+        assert_eq!(aliases.resolve("fetch-rs").await, "fetch-rs");
+        // No chains
+        assert!(aliases.add("fetch", "web-fetch").await.is_err());
+        assert!(aliases.add("fetch-rs", "other").await.is_err());
+
+        let reloaded = ComponentAliases::load(dir.path()).await?;
+        assert_eq!(reloaded.resolve("web-fetch").await, "fetch-rs");
+        assert_eq!(
+            reloaded.remove("web-fetch").await?,
+            Some("fetch-rs".to_string())
+        );
+        assert_eq!(reloaded.remove("web-fetch").await?, None);
+        assert!(ComponentAliases::load(dir.path())
+            .await?
+            .list()
+            .await
+            .is_empty());
+        Ok(())
+    }
+}
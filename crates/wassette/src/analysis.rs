@@ -0,0 +1,237 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Heuristic warnings about components that look risky.
+//!
+//! When a component is loaded its bytes are scanned for traits that are rare in ordinary tools
+//! and common in careless or malicious ones:
+//!
+//! - importing both outgoing network interfaces (`wasi:http/outgoing-handler`, `wasi:sockets`)
+//!   and filesystem directories (`wasi:filesystem/preopens`), which together could send local
+//!   files elsewhere;
+//! - IPv4 address literals in data segments, which often point at hard-coded servers;
+//! - data segments larger than [`LARGE_DATA_BYTES`], which can hide an embedded payload.
+//!
+//! The warnings are advisory. They neither stop the load nor change what the component may
+//! do, which is still up to its policy; they are reported with the load result and by
+//! `list-components` so users take a closer look before granting permissions.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use wasmparser::{Parser, Payload};
+
+/// Total size of data segments from which a component is flagged.
+///
+/// Interpreters such as those embedded by `componentize-py` carry tens of megabytes of data,
+/// so the bar is set well above them.
+const LARGE_DATA_BYTES: usize = 64 * 1024 * 1024;
+
+/// Most IP addresses named in a warning.
+const MAX_LISTED_ADDRESSES: usize = 5;
+
+const NETWORK_IMPORTS: &[&str] = &["wasi:http/outgoing-handler", "wasi:sockets/"];
+const FILESYSTEM_IMPORT: &str = "wasi:filesystem/preopens";
+
+/// A suspicious trait of a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    /// The component imports both outgoing network and filesystem interfaces
+    NetworkAndFilesystem,
+    /// The component's data contains IP address literals
+    IpLiteral,
+    /// The component's data segments are unusually large
+    LargeData,
+}
+
+/// A warning about a suspicious trait found in a component.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentWarning {
+    /// What was found
+    pub kind: WarningKind,
+    /// Details for the user
+    pub message: String,
+}
+
+impl fmt::Display for ComponentWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// The warnings about the component in `bytes`. Bytes that don't parse yield the warnings
+/// found up to the error; the compiler reports the error itself.
+pub(crate) fn analyze(bytes: &[u8]) -> Vec<ComponentWarning> {
+    let mut imports = Vec::new();
+    let mut addresses = BTreeSet::new();
+    let mut data_bytes = 0usize;
+    // Imports of nested components are satisfied inside the component, so only those of the
+    // outermost one count.
+    let mut depth = 0usize;
+    for payload in Parser::new(0).parse_all(bytes) {
+        let Ok(payload) = payload else {
+            break;
+        };
+        match payload {
+            Payload::Version { .. } => depth += 1,
+            Payload::End(_) => depth = depth.saturating_sub(1),
+            Payload::ComponentImportSection(reader) if depth == 1 => {
+                imports.extend(reader.into_iter().flatten().map(|import| import.name.0));
+            }
+            Payload::DataSection(reader) => {
+                for data in reader.into_iter().flatten() {
+                    data_bytes += data.data.len();
+                    ip_literals(data.data, &mut addresses);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut warnings = Vec::new();
+    let network = imports.iter().find(|name| {
+        NETWORK_IMPORTS
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+    });
+    let filesystem = imports
+        .iter()
+        .any(|name| name.starts_with(FILESYSTEM_IMPORT));
+    if let (Some(network), true) = (network, filesystem) {
+        let network = network.split('@').next().unwrap_or(network);
+        warnings.push(ComponentWarning {
+            kind: WarningKind::NetworkAndFilesystem,
+            message: format!(
+                "imports outgoing network access ({network}) and filesystem directories \
+                 ({FILESYSTEM_IMPORT}); granting both would let it send local files elsewhere"
+            ),
+        });
+    }
+    if !addresses.is_empty() {
+        let count = addresses.len();
+        let mut listed: Vec<String> = addresses.into_iter().take(MAX_LISTED_ADDRESSES).collect();
+        if count > MAX_LISTED_ADDRESSES {
+            listed.push(format!("and {} more", count - MAX_LISTED_ADDRESSES));
+        }
+        warnings.push(ComponentWarning {
+            kind: WarningKind::IpLiteral,
+            message: format!(
+                "embeds IP addresses ({}); check what it connects to before granting network \
+                 access",
+                listed.join(", ")
+            ),
+        });
+    }
+    if data_bytes > LARGE_DATA_BYTES {
+        warnings.push(ComponentWarning {
+            kind: WarningKind::LargeData,
+            message: format!(
+                "has {} MiB of data segments, unusually large for a tool",
+                data_bytes / (1024 * 1024)
+            ),
+        });
+    }
+    warnings
+}
+
+/// Add the IPv4 address literals in `data` to `addresses`, except unspecified, loopback and
+/// broadcast addresses and dotted numbers following a letter, like versions (`v1.2.3.4`).
+fn ip_literals(data: &[u8], addresses: &mut BTreeSet<String>) {
+    let mut start = 0;
+    while start < data.len() {
+        if !data[start].is_ascii_digit() {
+            start += 1;
+            continue;
+        }
+        let end = data[start..]
+            .iter()
+            .position(|b| !(b.is_ascii_digit() || *b == b'.'))
+            .map_or(data.len(), |len| start + len);
+        let after_letter = start > 0 && data[start - 1].is_ascii_alphabetic();
+        if !after_letter {
+            let run = std::str::from_utf8(&data[start..end]).unwrap_or_default();
+            if let Some(address) = ipv4_literal(run.trim_end_matches('.')) {
+                addresses.insert(address);
+            }
+        }
+        start = end;
+    }
+}
+
+fn ipv4_literal(text: &str) -> Option<String> {
+    let octets = text
+        .split('.')
+        .map(|octet| {
+            let valid =
+                (1..=3).contains(&octet.len()) && !(octet.len() > 1 && octet.starts_with('0'));
+            valid.then(|| octet.parse::<u8>().ok()).flatten()
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    let [a, b, c, d] = octets[..] else {
+        return None;
+    };
+    let address = std::net::Ipv4Addr::new(a, b, c, d);
+    (!address.is_unspecified() && !address.is_loopback() && !address.is_broadcast())
+        .then(|| address.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(imports: &[&str], data: &str) -> Vec<u8> {
+        let imports: String = imports
+            .iter()
+            .map(|name| format!("(import \"{name}\" (instance))"))
+            .collect();
+        wat::parse_str(format!(
+            "(component {imports} (core module (memory 1) (data (i32.const 0) \"{data}\")))"
+        ))
+        .unwrap()
+    }
+
+    fn kinds(warnings: &[ComponentWarning]) -> Vec<WarningKind> {
+        warnings.iter().map(|warning| warning.kind).collect()
+    }
+
+    #[test]
+    fn test_plain_component_has_no_warnings() {
+        let bytes = component(
+            &[
+                "wasi:http/outgoing-handler@0.2.0",
+                "wasi:clocks/wall-clock@0.2.0",
+            ],
+            "fetched from https://example.com with v1.2.3.4 on 127.0.0.1 and 0.0.0.0",
+        );
+        assert_eq!(analyze(&bytes), Vec::new());
+    }
+
+    #[test]
+    fn test_network_and_filesystem() {
+        let bytes = component(
+            &["wasi:sockets/tcp@0.2.0", "wasi:filesystem/preopens@0.2.0"],
+            "",
+        );
+        let warnings = analyze(&bytes);
+        assert_eq!(kinds(&warnings), [WarningKind::NetworkAndFilesystem]);
+        assert!(warnings[0].message.contains("(wasi:sockets/tcp)"));
+    }
+
+    #[test]
+    fn test_ip_literals() {
+        let bytes = component(
+            &[],
+            "connect 203.0.113.7:443, 10.0.0.1. or 256.1.1.1 and 01.2.3.4",
+        );
+        let warnings = analyze(&bytes);
+        assert_eq!(kinds(&warnings), [WarningKind::IpLiteral]);
+        assert!(warnings[0].message.contains("(10.0.0.1, 203.0.113.7)"));
+
+        let mut addresses = BTreeSet::new();
+        let data: String = (1..=7).map(|i| format!("192.0.2.{i} ")).collect();
+        ip_literals(data.as_bytes(), &mut addresses);
+        assert_eq!(addresses.len(), 7);
+    }
+}
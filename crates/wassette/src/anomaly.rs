@@ -0,0 +1,460 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Early warning of components that behave unlike they usually do.
+//!
+//! With anomaly detection on, the manager learns a baseline of the tool calls of every
+//! component: how many calls start within a minute, how large their arguments are, and how
+//! many outgoing HTTP requests each call makes and to which hosts. Once a component has made
+//! `warmup-calls` calls, a call that takes one of these measures past `threshold` times its
+//! baseline, or that reaches a host the component never reached before, is reported as an
+//! [`Anomaly`]: it is recorded in the audit log and sent to
+//! [subscribers](crate::LifecycleManager::subscribe_anomalies), which the MCP server forwards
+//! to its clients as warnings. An agent misled by a prompt injection often shows this way,
+//! e.g. as a sudden burst of requests from a component that usually makes one per call.
+//!
+//! ```toml
+//! [anomaly_detection]
+//! warmup-calls = 20
+//! threshold = 5.0
+//! ```
+//!
+//! Anomalies only warn: calls are never refused. Baselines are kept in memory, and keep
+//! learning from anomalous calls too, so that a lasting change of behavior becomes the new
+//! baseline after a while.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// Anomalies buffered for subscribers that fall behind.
+const EVENT_BUFFER: usize = 64;
+
+/// Window over which calls are counted for the call rate.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Weight of a new measure in a baseline once the warmup is over.
+const SMOOTHING: f64 = 0.05;
+
+/// Fewest calls within a minute reported as a burst, however low the baseline.
+const MIN_BURST_CALLS: u32 = 10;
+
+/// Smallest arguments reported as unusually large, in bytes.
+const MIN_ARGUMENT_BYTES: usize = 4096;
+
+/// Fewest HTTP requests of one call reported as unusually many.
+const MIN_REQUESTS: usize = 5;
+
+/// Shortest time between two reports of the same signal of a component.
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Settings of anomaly detection, under `[anomaly_detection]` in the configuration file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AnomalyConfig {
+    /// Calls a component makes before its baseline is trusted
+    pub warmup_calls: u32,
+    /// How many times its baseline a measure must reach to be anomalous
+    pub threshold: f64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            warmup_calls: 20,
+            threshold: 5.0,
+        }
+    }
+}
+
+impl AnomalyConfig {
+    /// Check that the warmup and the threshold make sense.
+    pub fn validate(&self) -> Result<()> {
+        if self.warmup_calls == 0 {
+            bail!("Anomaly detection needs at least one warmup call");
+        }
+        if self.threshold.is_nan() || self.threshold <= 1.0 {
+            bail!("The anomaly threshold must be above 1");
+        }
+        Ok(())
+    }
+}
+
+/// A tool call that deviated sharply from the baseline of its component.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Anomaly {
+    /// The component that was called
+    pub component_id: String,
+    /// The tool that was called
+    pub tool: String,
+    /// What deviated
+    #[serde(flatten)]
+    pub signal: AnomalySignal,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unusual call of tool {} of component {}: {}",
+            self.tool, self.component_id, self.signal
+        )
+    }
+}
+
+/// What deviated in an [`Anomaly`], and by how much.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "signal", rename_all = "kebab-case")]
+pub enum AnomalySignal {
+    /// Far more calls of the component started within a minute than usual
+    CallRate {
+        /// Calls within the last minute
+        calls: u32,
+        /// Calls within a minute usually
+        baseline: f64,
+    },
+    /// The arguments of the call were far larger than usual
+    ArgumentSize {
+        /// Size of the arguments, in bytes
+        bytes: usize,
+        /// Size of the arguments usually
+        baseline: f64,
+    },
+    /// The call sent far more HTTP requests than usual
+    OutboundRequests {
+        /// Requests the call sent
+        requests: usize,
+        /// Requests of a call usually
+        baseline: f64,
+    },
+    /// The call sent a request to a host the component never reached before
+    NewHost {
+        /// The host
+        host: String,
+    },
+}
+
+impl AnomalySignal {
+    /// Name of the signal, as in the `signal` field of its JSON.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CallRate { .. } => "call-rate",
+            Self::ArgumentSize { .. } => "argument-size",
+            Self::OutboundRequests { .. } => "outbound-requests",
+            Self::NewHost { .. } => "new-host",
+        }
+    }
+}
+
+impl fmt::Display for AnomalySignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CallRate { calls, baseline } => {
+                write!(f, "{calls} calls within a minute, {baseline:.1} usually")
+            }
+            Self::ArgumentSize { bytes, baseline } => {
+                write!(f, "{bytes} bytes of arguments, {baseline:.0} usually")
+            }
+            Self::OutboundRequests { requests, baseline } => {
+                write!(f, "{requests} HTTP requests, {baseline:.1} usually")
+            }
+            Self::NewHost { host } => write!(f, "HTTP request to new host {host}"),
+        }
+    }
+}
+
+/// Hosts a call sent HTTP requests to, recorded as the component sends them.
+#[derive(Debug, Default)]
+pub(crate) struct OutboundRequests {
+    hosts: Mutex<Vec<String>>,
+}
+
+impl OutboundRequests {
+    pub(crate) fn record(&self, host: &str) {
+        self.hosts.lock().unwrap().push(host.to_ascii_lowercase());
+    }
+
+    pub(crate) fn hosts(&self) -> Vec<String> {
+        self.hosts.lock().unwrap().clone()
+    }
+}
+
+/// Running mean of a measure: the plain mean during the warmup, then exponentially weighted.
+#[derive(Debug, Default, Clone, Copy)]
+struct Mean {
+    value: f64,
+    samples: u64,
+}
+
+impl Mean {
+    fn add(&mut self, measure: f64) {
+        self.samples += 1;
+        let weight = (1.0 / self.samples as f64).max(SMOOTHING);
+        self.value += weight * (measure - self.value);
+    }
+
+    /// Whether `measure` is past `threshold` times the mean and at least `floor`.
+    fn exceeded_by(&self, measure: f64, threshold: f64, floor: f64) -> bool {
+        self.samples > 0 && measure >= floor && measure > threshold * self.value
+    }
+}
+
+/// What is known of the calls of one component.
+#[derive(Debug, Default)]
+struct Baseline {
+    calls: u64,
+    /// Start of the current rate window and the calls within it
+    window: Option<(Instant, u32)>,
+    calls_per_minute: Mean,
+    argument_bytes: Mean,
+    requests: Mean,
+    hosts: HashSet<String>,
+    /// When each signal was last reported
+    reported: HashMap<&'static str, Instant>,
+}
+
+impl Baseline {
+    /// Count a call starting at `now`, returning the calls within the current window.
+    fn count_call(&mut self, now: Instant) -> u32 {
+        self.calls += 1;
+        match &mut self.window {
+            Some((start, calls)) if now.duration_since(*start) < RATE_WINDOW => {
+                *calls += 1;
+                *calls
+            }
+            window => {
+                // Only windows with calls are learned, so idle hours don't make any use
+                // look like a burst.
+                if let Some((_, calls)) = window {
+                    self.calls_per_minute.add(f64::from(*calls));
+                }
+                *window = Some((now, 1));
+                1
+            }
+        }
+    }
+
+    /// Whether `signal` may be reported at `now`, and if so remember that it was.
+    fn may_report(&mut self, signal: &AnomalySignal, now: Instant) -> bool {
+        match self.reported.get(signal.name()) {
+            Some(last) if now.duration_since(*last) < REPORT_INTERVAL => false,
+            _ => {
+                self.reported.insert(signal.name(), now);
+                true
+            }
+        }
+    }
+}
+
+/// The baselines of every component, and the subscribers to their anomalies.
+#[derive(Debug)]
+pub(crate) struct AnomalyDetector {
+    config: Option<AnomalyConfig>,
+    components: Mutex<HashMap<String, Baseline>>,
+    events: broadcast::Sender<Anomaly>,
+}
+
+impl AnomalyDetector {
+    /// A detector with `config`, or one that never reports anything without.
+    pub(crate) fn new(config: Option<AnomalyConfig>) -> Self {
+        Self {
+            config,
+            components: Mutex::default(),
+            events: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<Anomaly> {
+        self.events.subscribe()
+    }
+
+    /// Learn from a call of `tool` of `component_id` with `argument_bytes` of arguments that
+    /// sent requests to `hosts`, if it ran the component, and return how it deviated.
+    pub(crate) fn observe(
+        &self,
+        component_id: &str,
+        tool: &str,
+        argument_bytes: usize,
+        hosts: Option<&[String]>,
+    ) -> Vec<Anomaly> {
+        self.observe_at(component_id, tool, argument_bytes, hosts, Instant::now())
+    }
+
+    /// Learn from a finished call whose outbound requests `outbound` recorded, as
+    /// [`Self::observe`]. Cached results didn't run the component, so they say nothing about
+    /// its requests.
+    pub(crate) fn observe_call(
+        &self,
+        component_id: &str,
+        tool: &str,
+        argument_bytes: usize,
+        outbound: &OutboundRequests,
+        cached: bool,
+    ) -> Vec<Anomaly> {
+        let hosts = (!cached).then(|| outbound.hosts());
+        self.observe(component_id, tool, argument_bytes, hosts.as_deref())
+    }
+
+    fn observe_at(
+        &self,
+        component_id: &str,
+        tool: &str,
+        argument_bytes: usize,
+        hosts: Option<&[String]>,
+        now: Instant,
+    ) -> Vec<Anomaly> {
+        let Some(config) = &self.config else {
+            return Vec::new();
+        };
+        let mut components = self.components.lock().unwrap();
+        let baseline = components.entry(component_id.to_string()).or_default();
+        let trusted = baseline.calls >= u64::from(config.warmup_calls);
+        let mut signals = Vec::new();
+
+        let calls = baseline.count_call(now);
+        if baseline.calls_per_minute.exceeded_by(
+            f64::from(calls),
+            config.threshold,
+            f64::from(MIN_BURST_CALLS),
+        ) {
+            signals.push(AnomalySignal::CallRate {
+                calls,
+                baseline: baseline.calls_per_minute.value,
+            });
+        }
+
+        if baseline.argument_bytes.exceeded_by(
+            argument_bytes as f64,
+            config.threshold,
+            MIN_ARGUMENT_BYTES as f64,
+        ) {
+            signals.push(AnomalySignal::ArgumentSize {
+                bytes: argument_bytes,
+                baseline: baseline.argument_bytes.value,
+            });
+        }
+        baseline.argument_bytes.add(argument_bytes as f64);
+
+        if let Some(hosts) = hosts {
+            if baseline.requests.exceeded_by(
+                hosts.len() as f64,
+                config.threshold,
+                MIN_REQUESTS as f64,
+            ) {
+                signals.push(AnomalySignal::OutboundRequests {
+                    requests: hosts.len(),
+                    baseline: baseline.requests.value,
+                });
+            }
+            baseline.requests.add(hosts.len() as f64);
+            for host in hosts {
+                if baseline.hosts.insert(host.clone()) && trusted {
+                    signals.push(AnomalySignal::NewHost { host: host.clone() });
+                }
+            }
+        }
+
+        if !trusted {
+            return Vec::new();
+        }
+        signals
+            .into_iter()
+            .filter(|signal| baseline.may_report(signal, now))
+            .map(|signal| Anomaly {
+                component_id: component_id.to_string(),
+                tool: tool.to_string(),
+                signal,
+            })
+            .collect()
+    }
+
+    /// Send `anomaly` to the subscribers.
+    pub(crate) fn report(&self, anomaly: Anomaly) {
+        warn!(
+            component_id = %anomaly.component_id,
+            tool = %anomaly.tool,
+            signal = anomaly.signal.name(),
+            "{}",
+            anomaly.signal
+        );
+        // Nobody may be subscribed, which is fine.
+        let _ = self.events.send(anomaly);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(hosts: &[&str]) -> Vec<String> {
+        hosts.iter().map(|host| host.to_string()).collect()
+    }
+
+    #[test]
+    fn test_deviations_from_the_baseline_are_reported() {
+        let detector = AnomalyDetector::new(Some(AnomalyConfig {
+            warmup_calls: 4,
+            threshold: 5.0,
+        }));
+        let start = Instant::now();
+        let usual = hosts(&["api.example.com"]);
+        // One call a minute, with small arguments and one request each.
+        for minute in 0..4 {
+            let now = start + Duration::from_secs(60 * minute);
+            let anomalies = detector.observe_at("fetch", "get", 100, Some(&usual), now);
+            assert!(anomalies.is_empty());
+        }
+
+        let now = start + Duration::from_secs(240);
+        let burst = vec!["api.example.com".to_string(); 12];
+        let anomalies = detector.observe_at("fetch", "get", 100, Some(&burst), now);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].signal.name(), "outbound-requests");
+
+        let later = now + Duration::from_secs(1);
+        let anomalies = detector.observe_at(
+            "fetch",
+            "get",
+            50_000,
+            Some(&hosts(&["exfil.example.net"])),
+            later,
+        );
+        let signals: Vec<_> = anomalies.iter().map(|a| a.signal.name()).collect();
+        assert_eq!(signals, ["argument-size", "new-host"]);
+        let json = serde_json::to_value(&anomalies[1]).unwrap();
+        assert_eq!(json["signal"], "new-host");
+        assert_eq!(json["host"], "exfil.example.net");
+
+        // A burst of calls within a minute, reported once.
+        let reported: usize = (0..12)
+            .map(|i| {
+                let now = later + Duration::from_millis(100 * i);
+                detector
+                    .observe_at("fetch", "get", 100, Some(&usual), now)
+                    .iter()
+                    .filter(|a| a.signal.name() == "call-rate")
+                    .count()
+            })
+            .sum();
+        assert_eq!(reported, 1);
+
+        // Other components have baselines of their own, and nothing is reported without
+        // a configuration.
+        assert!(detector
+            .observe_at("time", "now", 50_000, None, later)
+            .is_empty());
+        assert!(AnomalyDetector::new(None)
+            .observe_at("fetch", "get", 50_000, Some(&burst), later)
+            .is_empty());
+    }
+}
@@ -0,0 +1,512 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! The audit log of tool calls and permission decisions.
+//!
+//! Every tool call, whether it ran, was served from the result cache or was refused, every
+//! request a policy refused while a component ran, and every change to a component's
+//! permissions is appended as a JSON line to `audit/calls.jsonl` under the plugin directory.
+//! Records carry who made the call (the MCP client and, on the HTTP transports, the subject of
+//! its access token), a SHA-256 hash of the arguments rather than the arguments themselves,
+//! the decision, and for calls how long they took and how they ended. The log is append-only:
+//! Wassette never edits a record, it only rotates the file to `calls.jsonl.1` (shifting older
+//! files up to `calls.jsonl.5`) once it reaches 10 MiB.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::anomaly::Anomaly;
+use crate::coredump::CoreDumpSaved;
+use crate::fs_audit::{self, AUDIT_DIR};
+use crate::journal::JournalEntry;
+use crate::limits::{CallTimedOut, ResourceLimitExceeded};
+use crate::output::RotatingFile;
+use crate::stats::ComponentMetrics;
+use crate::{result_cache, CallContext, CallOverrides, OutsideAllowedHours, ToolOutput};
+
+/// Name of the audit log in the audit directory.
+pub const AUDIT_LOG_FILE: &str = "calls.jsonl";
+
+/// Size at which the audit log is rotated.
+const MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Number of rotated audit logs kept.
+const MAX_LOG_FILES: u32 = 5;
+
+/// What an audit record is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditEvent {
+    /// A call of a component's tool or of a built-in tool
+    ToolCall,
+    /// A request a component made that its policy doesn't allow
+    PermissionDenied,
+    /// A permission was granted to a component
+    PermissionGrant,
+    /// A permission was revoked from a component
+    PermissionRevoke,
+    /// A component's permissions were reset
+    PermissionReset,
+    /// A tool call deviated sharply from the baseline of its component
+    Anomaly,
+}
+
+/// Whether what was asked for was allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditDecision {
+    /// It was allowed
+    Allowed,
+    /// It was refused
+    Denied,
+}
+
+/// How a tool call that was allowed ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditOutcome {
+    /// The tool returned a result
+    Success,
+    /// The tool returned an error result
+    ToolError,
+    /// The call failed, e.g. the component trapped
+    Failed,
+    /// The call ran past its timeout
+    TimedOut,
+    /// The call exceeded a resource limit of the component's policy
+    LimitExceeded,
+    /// The server stopped before the call ended, e.g. because it crashed
+    Interrupted,
+}
+
+/// A record of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Time of the event (seconds since epoch)
+    pub created_at: u64,
+    /// What the record is about
+    pub event: AuditEvent,
+    /// Component concerned, unless a built-in tool was called
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component_id: Option<String>,
+    /// Tool called
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    /// SHA-256 of the arguments of the call, as canonical JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments_sha256: Option<String>,
+    /// MCP client that made the call, as `<name>/<version>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    /// Subject of the access token the call was made with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// Whether it was allowed
+    pub decision: AuditDecision,
+    /// Kind of permission, e.g. `network`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission: Option<String>,
+    /// What the permission is about, e.g. the host of a refused request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<String>,
+    /// How long the call took
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// How the call ended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<AuditOutcome>,
+    /// Whether the result came from the tool's result cache
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// Limits the caller overrode for the call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<CallOverrides>,
+    /// Whether the caller said the user confirmed the call
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub confirmed: bool,
+    /// Call id of the core dump captured when the call trapped, for `wassette debug coredump`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coredump: Option<String>,
+    /// Why the call failed or was refused
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    fn new(event: AuditEvent, component_id: Option<&str>, decision: AuditDecision) -> Self {
+        Self {
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            event,
+            component_id: component_id.map(str::to_string),
+            tool: None,
+            arguments_sha256: None,
+            client: None,
+            subject: None,
+            decision,
+            permission: None,
+            resource: None,
+            duration_ms: None,
+            outcome: None,
+            cached: false,
+            overrides: None,
+            confirmed: false,
+            coredump: None,
+            error: None,
+        }
+    }
+
+    /// A call of `tool` of `component_id` (`None` for a built-in tool) with the JSON
+    /// `arguments`, made by the caller in `context`. The call is allowed until it is
+    /// [finished](Self::finished) or [refused](Self::refused).
+    pub fn tool_call(
+        component_id: Option<&str>,
+        tool: &str,
+        arguments: &str,
+        context: &CallContext,
+    ) -> Self {
+        let mut record = Self::new(AuditEvent::ToolCall, component_id, AuditDecision::Allowed);
+        record.tool = Some(tool.to_string());
+        record.arguments_sha256 = Some(arguments_sha256(arguments));
+        record.client = context
+            .client
+            .as_ref()
+            .map(|(name, version)| format!("{name}/{version}"));
+        record.subject = context.subject.clone();
+        record.overrides =
+            Some(context.overrides.clone()).filter(|overrides| !overrides.is_empty());
+        record.confirmed = context.confirmed;
+        record
+    }
+
+    /// The call took `elapsed` and ended as `outcome`, failing with `error` if any.
+    pub fn finished(
+        mut self,
+        elapsed: Duration,
+        outcome: AuditOutcome,
+        error: Option<String>,
+    ) -> Self {
+        self.duration_ms = Some(elapsed.as_millis() as u64);
+        self.outcome = Some(outcome);
+        self.error = error;
+        self
+    }
+
+    /// The call was refused because of `reason`.
+    pub fn refused(mut self, reason: impl Into<String>) -> Self {
+        self.decision = AuditDecision::Denied;
+        self.error = Some(reason.into());
+        self
+    }
+
+    /// The call of a component's tool took `elapsed` and returned `result`.
+    pub(crate) fn component_call_finished(
+        self,
+        elapsed: Duration,
+        result: &anyhow::Result<ToolOutput>,
+    ) -> Self {
+        match result {
+            Ok(output) => {
+                let reported_error = serde_json::from_str::<Value>(&output.output)
+                    .is_ok_and(|value| result_cache::reports_error(&value));
+                let outcome = if reported_error {
+                    AuditOutcome::ToolError
+                } else {
+                    AuditOutcome::Success
+                };
+                let mut record = self.finished(elapsed, outcome, None);
+                record.cached = output.cached;
+                record
+            }
+            Err(error) if error.is::<OutsideAllowedHours>() => self.refused(error.to_string()),
+            Err(error) => {
+                let outcome = if error.is::<CallTimedOut>() {
+                    AuditOutcome::TimedOut
+                } else if error.is::<ResourceLimitExceeded>() {
+                    AuditOutcome::LimitExceeded
+                } else {
+                    AuditOutcome::Failed
+                };
+                let mut record = self.finished(elapsed, outcome, Some(error.to_string()));
+                record.coredump = error
+                    .downcast_ref::<CoreDumpSaved>()
+                    .map(|saved| saved.call_id.clone());
+                record
+            }
+        }
+    }
+
+    /// A call journaled as `entry` that the server stopped before it ended, found when the
+    /// server started again.
+    pub(crate) fn interrupted(entry: &JournalEntry) -> Self {
+        let mut record = Self::new(
+            AuditEvent::ToolCall,
+            Some(&entry.component_id),
+            AuditDecision::Allowed,
+        );
+        record.tool = Some(entry.tool.clone());
+        record.arguments_sha256 = entry.arguments_sha256.clone();
+        record.client = entry.client.clone();
+        record.subject = entry.subject.clone();
+        record.outcome = Some(AuditOutcome::Interrupted);
+        record.error = Some(format!(
+            "The server stopped before the call accepted at {} ended",
+            entry.accepted_at
+        ));
+        record
+    }
+
+    /// A call that deviated from its component's baseline as `anomaly` tells, recorded with
+    /// the signal as the permission and what was observed as the resource.
+    pub(crate) fn anomaly(anomaly: &Anomaly) -> Self {
+        let mut record = Self::new(
+            AuditEvent::Anomaly,
+            Some(&anomaly.component_id),
+            AuditDecision::Allowed,
+        );
+        record.tool = Some(anomaly.tool.clone());
+        record.permission = Some(anomaly.signal.name().to_string());
+        record.resource = Some(anomaly.signal.to_string());
+        record
+    }
+
+    /// A change of the permissions of `component_id`.
+    pub(crate) fn permission_change(
+        event: AuditEvent,
+        component_id: &str,
+        permission: Option<&str>,
+        details: Option<&Value>,
+    ) -> Self {
+        let mut record = Self::new(event, Some(component_id), AuditDecision::Allowed);
+        record.permission = permission.map(str::to_string);
+        record.resource = details.map(Value::to_string);
+        record
+    }
+}
+
+/// SHA-256 of `arguments` as canonical JSON, or of the raw text if it isn't JSON.
+fn arguments_sha256(arguments: &str) -> String {
+    let canonical = serde_json::from_str::<Value>(arguments)
+        .map(|value| result_cache::canonical_json(&value))
+        .unwrap_or_else(|_| arguments.to_string());
+    hex::encode(Sha256::digest(canonical.as_bytes()))
+}
+
+/// The audit log of a plugin directory.
+pub(crate) struct AuditLog {
+    file: Mutex<RotatingFile>,
+}
+
+impl std::fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditLog").finish_non_exhaustive()
+    }
+}
+
+impl AuditLog {
+    pub(crate) fn new(plugin_dir: &Path) -> Self {
+        Self {
+            file: Mutex::new(RotatingFile::new(
+                log_path(plugin_dir),
+                MAX_LOG_BYTES,
+                MAX_LOG_FILES,
+            )),
+        }
+    }
+
+    /// Append `record`. Failures are logged: they never fail what is being recorded.
+    pub(crate) fn record(&self, record: &AuditRecord) {
+        let result = serde_json::to_vec(record)
+            .map_err(std::io::Error::from)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+                file.write_all(&line)?;
+                file.flush()
+            });
+        if let Err(error) = result {
+            warn!(%error, "Failed to write audit record");
+        }
+    }
+}
+
+/// Where the denials of the policy of one component are reported.
+#[derive(Debug, Clone)]
+pub(crate) struct Denials {
+    component_id: String,
+    metrics: Arc<ComponentMetrics>,
+    audit: Arc<AuditLog>,
+}
+
+impl Denials {
+    pub(crate) fn new(
+        component_id: &str,
+        metrics: Arc<ComponentMetrics>,
+        audit: Arc<AuditLog>,
+    ) -> Self {
+        Self {
+            component_id: component_id.to_string(),
+            metrics,
+            audit,
+        }
+    }
+
+    /// Count and record that the policy refused the component `permission` to `resource`.
+    pub(crate) fn record(&self, permission: &str, resource: &str) {
+        self.metrics.record_denial(&self.component_id);
+        let mut record = AuditRecord::new(
+            AuditEvent::PermissionDenied,
+            Some(&self.component_id),
+            AuditDecision::Denied,
+        );
+        record.permission = Some(permission.to_string());
+        record.resource = Some(resource.to_string());
+        self.audit.record(&record);
+    }
+}
+
+/// Path of the audit log of `plugin_dir`.
+pub(crate) fn log_path(plugin_dir: &Path) -> PathBuf {
+    plugin_dir.join(AUDIT_DIR).join(AUDIT_LOG_FILE)
+}
+
+/// Which audit records to list.
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    /// Only records about this component
+    pub component_id: Option<String>,
+    /// Only records of calls of this tool
+    pub tool: Option<String>,
+    /// Only records about this
+    pub event: Option<AuditEvent>,
+    /// Only records with this decision
+    pub decision: Option<AuditDecision>,
+    /// Only calls made with an access token issued to this subject
+    pub subject: Option<String>,
+    /// Only records of this time or later (seconds since epoch)
+    pub since: Option<u64>,
+    /// Only the last records, at most this many
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        fn matches<T: PartialEq>(wanted: &Option<T>, actual: Option<&T>) -> bool {
+            wanted.as_ref().is_none_or(|wanted| Some(wanted) == actual)
+        }
+        matches(&self.component_id, record.component_id.as_ref())
+            && matches(&self.tool, record.tool.as_ref())
+            && matches(&self.event, Some(&record.event))
+            && matches(&self.decision, Some(&record.decision))
+            && matches(&self.subject, record.subject.as_ref())
+            && self.since.is_none_or(|since| record.created_at >= since)
+    }
+}
+
+/// The records of the audit log of `plugin_dir` matching `query`, oldest first, including
+/// those of rotated files. Unreadable lines are skipped.
+pub(crate) async fn query(
+    plugin_dir: &Path,
+    query: &AuditQuery,
+) -> anyhow::Result<Vec<AuditRecord>> {
+    let path = log_path(plugin_dir);
+    let mut files: Vec<PathBuf> = (1..=MAX_LOG_FILES)
+        .rev()
+        .map(|n| PathBuf::from(format!("{}.{n}", path.display())))
+        .collect();
+    files.push(path);
+
+    let mut records = Vec::new();
+    for file in files {
+        let file_records: Vec<AuditRecord> = fs_audit::read_records(&file).await?;
+        records.extend(
+            file_records
+                .into_iter()
+                .filter(|record| query.matches(record)),
+        );
+    }
+    if let Some(limit) = query.limit {
+        records.drain(..records.len().saturating_sub(limit));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_records_are_appended_and_queried() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let log = Arc::new(AuditLog::new(dir.path()));
+        let context = CallContext::default()
+            .with_client("inspector", "0.9")
+            .with_subject("agent-7");
+
+        let call = AuditRecord::tool_call(Some("fetch"), "fetch", r#"{"b":1,"a":2}"#, &context)
+            .finished(Duration::from_millis(12), AuditOutcome::Success, None);
+        log.record(&call);
+        Denials::new("fetch", Default::default(), Arc::clone(&log))
+            .record("network", "evil.example.com");
+        log.record(
+            &AuditRecord::tool_call(None, "load-component", "{}", &CallContext::default())
+                .refused("outside the token's scope"),
+        );
+
+        let all = query(dir.path(), &AuditQuery::default()).await?;
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0], call);
+        assert_eq!(all[0].client.as_deref(), Some("inspector/0.9"));
+        assert_eq!(all[0].duration_ms, Some(12));
+        // The hash doesn't depend on the order of the arguments.
+        assert_eq!(
+            all[0].arguments_sha256,
+            Some(arguments_sha256(r#"{"a":2,"b":1}"#))
+        );
+        assert_eq!(all[1].event, AuditEvent::PermissionDenied);
+        assert_eq!(all[1].resource.as_deref(), Some("evil.example.com"));
+
+        let denied = AuditQuery {
+            decision: Some(AuditDecision::Denied),
+            ..Default::default()
+        };
+        assert_eq!(query(dir.path(), &denied).await?.len(), 2);
+        let fetch = AuditQuery {
+            component_id: Some("fetch".to_string()),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let last = query(dir.path(), &fetch).await?;
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].event, AuditEvent::PermissionDenied);
+        Ok(())
+    }
+
+    #[test]
+    fn test_trapped_calls_name_their_core_dump() {
+        let call = || AuditRecord::tool_call(Some("fetch"), "fetch", "{}", &CallContext::default());
+        let trapped: anyhow::Result<ToolOutput> =
+            Err(anyhow::anyhow!("unreachable").context(CoreDumpSaved {
+                call_id: "18f2a-0001".to_string(),
+            }));
+        let record = call().component_call_finished(Duration::from_millis(3), &trapped);
+        assert_eq!(record.outcome, Some(AuditOutcome::Failed));
+        assert_eq!(record.coredump.as_deref(), Some("18f2a-0001"));
+        assert!(record
+            .error
+            .unwrap()
+            .contains("core dump saved for call 18f2a-0001"));
+
+        let failed: anyhow::Result<ToolOutput> = Err(anyhow::anyhow!("unreachable"));
+        let record = call().component_call_finished(Duration::from_millis(3), &failed);
+        assert_eq!(record.coredump, None);
+    }
+}
@@ -0,0 +1,692 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Headless browser exposed to components through the `wassette:browser/page` host interface.
+//!
+//! Components import
+//!
+//! ```wit
+//! package wassette:browser;
+//!
+//! interface page {
+//!     /// The DOM of the page at `url` after its scripts ran, serialized as HTML
+//!     navigate: func(url: string) -> result<string, string>;
+//!     /// The readable text of the page at `url` after its scripts ran
+//!     text: func(url: string) -> result<string, string>;
+//!     /// A PNG screenshot of the page at `url` in a `width` x `height` viewport
+//!     screenshot: func(url: string, width: u32, height: u32) -> result<list<u8>, string>;
+//! }
+//! ```
+//!
+//! to read pages rendered by JavaScript, which a plain `wasi:http` fetch only sees as an empty
+//! shell. Each page is loaded by a fresh headless Chromium process with a throwaway profile,
+//! configured under `[browser]`. The browser is denied unless the component's policy has a
+//! `browser` section, which names the sites it may load and caps the pages per invocation.
+//!
+//! The allowed sites bind the whole page, not only the URL asked for: host names outside
+//! them don't resolve, and every connection to another host, including to IP addresses, is
+//! sent to a proxy that doesn't exist. Redirects, scripts, images and frames can therefore
+//! only reach the sites the policy names.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use policy::PolicyDocument;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use url::Url;
+use wasmtime::component::Linker;
+
+use crate::{WasiState, WassetteWasiState};
+
+/// Name of the host interface that provides the browser.
+pub const BROWSER_INTERFACE: &str = "wassette:browser/page";
+
+/// Page cap applied when the policy allows the browser without setting `max-pages`.
+pub const DEFAULT_MAX_PAGES: u32 = 4;
+
+/// Seconds a page may take to load and render unless configured otherwise.
+pub const DEFAULT_BROWSER_TIMEOUT_SECS: u64 = 30;
+
+/// Largest DOM or screenshot, in bytes, handed to a component.
+pub const MAX_PAGE_BYTES: usize = 8 * 1024 * 1024;
+
+/// Largest screenshot width and height, in pixels.
+pub const MAX_VIEWPORT: u32 = 4096;
+
+/// Executables looked up on `PATH` when none is configured.
+const EXECUTABLES: &[&str] = &[
+    "chromium",
+    "chromium-browser",
+    "google-chrome",
+    "google-chrome-stable",
+];
+
+/// Proxy every connection outside the allowed sites is sent to. The `.invalid` name never
+/// resolves, so those connections fail.
+const BLOCKING_PROXY: &str = "http://blocked.invalid:9";
+
+/// Arguments the browser always runs with.
+const HEADLESS_ARGS: &[&str] = &[
+    "--headless",
+    "--disable-gpu",
+    "--no-first-run",
+    "--no-default-browser-check",
+    "--disable-extensions",
+    "--disable-sync",
+    "--disable-background-networking",
+    "--disable-component-update",
+    "--hide-scrollbars",
+    "--mute-audio",
+    "--webrtc-ip-handling-policy=disable_non_proxied_udp",
+    "--virtual-time-budget=5000",
+];
+
+/// Arguments that would undo the confinement to the allowed sites, or take the browser out of
+/// this module's control.
+const RESERVED_ARGS: &[&str] = &[
+    "--host-resolver-rules",
+    "--proxy-server",
+    "--proxy-bypass-list",
+    "--proxy-pac-url",
+    "--no-proxy-server",
+    "--user-data-dir",
+    "--remote-debugging-port",
+    "--remote-debugging-pipe",
+];
+
+/// Settings of the headless browser, under `[browser]` in the configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BrowserConfig {
+    /// Chromium or Chrome executable; looked up on `PATH` when unset
+    pub executable: Option<PathBuf>,
+    /// Extra arguments, e.g. `--no-sandbox` when the server runs as root in a container
+    pub args: Vec<String>,
+    /// Seconds a page may take to load and render; defaults to
+    /// [`DEFAULT_BROWSER_TIMEOUT_SECS`]
+    pub timeout_secs: Option<u64>,
+}
+
+impl BrowserConfig {
+    /// Check the timeout and that no argument overrides the confinement to allowed sites.
+    pub fn validate(&self) -> Result<()> {
+        if self.timeout_secs == Some(0) {
+            bail!("Browser timeout must be at least one second");
+        }
+        if let Some(arg) = self.args.iter().find(|arg| {
+            RESERVED_ARGS
+                .iter()
+                .any(|reserved| arg.split('=').next() == Some(reserved))
+        }) {
+            bail!("Browser argument `{arg}` is set by Wassette and can't be overridden");
+        }
+        Ok(())
+    }
+
+    /// The executables the browser may be run from: the configured one, or those of the
+    /// usual names found on `PATH`.
+    pub fn executables(&self) -> Vec<PathBuf> {
+        match &self.executable {
+            Some(executable) => vec![executable.clone()],
+            None => executables_on_path(),
+        }
+    }
+}
+
+/// What to take from a loaded page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Capture {
+    Dom,
+    Screenshot { width: u32, height: u32 },
+}
+
+/// Loads pages in headless Chromium, one process per page.
+#[derive(Debug)]
+pub struct HeadlessBrowser {
+    executable: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl HeadlessBrowser {
+    /// Browser run as configured by `config`, failing if no executable can be found.
+    pub(crate) fn new(config: BrowserConfig) -> Result<Self> {
+        let executable = match config.executable {
+            Some(executable) => executable,
+            None => find_executable().context(
+                "No Chromium or Chrome executable found on PATH; set `executable` under [browser]",
+            )?,
+        };
+        Ok(Self {
+            executable,
+            args: config.args,
+            timeout: Duration::from_secs(
+                config.timeout_secs.unwrap_or(DEFAULT_BROWSER_TIMEOUT_SECS),
+            ),
+        })
+    }
+
+    /// Load `url`, letting the page reach only `hosts`, and return what `capture` asks for.
+    async fn load(&self, url: &Url, hosts: &[String], capture: Capture) -> Result<Vec<u8>> {
+        let profile = tempfile::tempdir().context("Failed to create a browser profile")?;
+        let screenshot = profile.path().join("screenshot.png");
+
+        let mut command = tokio::process::Command::new(&self.executable);
+        command
+            .args(HEADLESS_ARGS)
+            .arg(format!("--user-data-dir={}", profile.path().display()))
+            .args(confinement_args(hosts))
+            .args(&self.args);
+        match capture {
+            Capture::Dom => command.arg("--dump-dom"),
+            Capture::Screenshot { width, height } => command
+                .arg(format!("--screenshot={}", screenshot.display()))
+                .arg(format!("--window-size={width},{height}")),
+        };
+        command
+            .arg(url.as_str())
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true);
+
+        let run = async {
+            let mut child = command
+                .spawn()
+                .with_context(|| format!("Failed to start {}", self.executable.display()))?;
+            let mut output = Vec::new();
+            if let Some(stdout) = child.stdout.take() {
+                stdout
+                    .take(MAX_PAGE_BYTES as u64 + 1)
+                    .read_to_end(&mut output)
+                    .await?;
+            }
+            if output.len() > MAX_PAGE_BYTES {
+                bail!("The page is larger than {MAX_PAGE_BYTES} bytes");
+            }
+            let status = child.wait().await?;
+            if !status.success() {
+                bail!("The browser exited with {status}");
+            }
+            Ok(output)
+        };
+        let output = tokio::time::timeout(self.timeout, run)
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "The page didn't load within {} seconds",
+                    self.timeout.as_secs()
+                )
+            })??;
+
+        match capture {
+            Capture::Dom if output.is_empty() => bail!("The browser returned no page"),
+            Capture::Dom => Ok(output),
+            Capture::Screenshot { .. } => read_screenshot(&screenshot).await,
+        }
+    }
+}
+
+async fn read_screenshot(path: &Path) -> Result<Vec<u8>> {
+    let size = tokio::fs::metadata(path)
+        .await
+        .context("The browser didn't take a screenshot")?
+        .len();
+    if size > MAX_PAGE_BYTES as u64 {
+        bail!("The screenshot is larger than {MAX_PAGE_BYTES} bytes");
+    }
+    Ok(tokio::fs::read(path).await?)
+}
+
+/// The first of [`EXECUTABLES`] found on `PATH`.
+fn find_executable() -> Option<PathBuf> {
+    executables_on_path().into_iter().next()
+}
+
+/// Every one of [`EXECUTABLES`] found on `PATH`, in the order they are tried.
+fn executables_on_path() -> Vec<PathBuf> {
+    let Some(path) = std::env::var_os("PATH") else {
+        return Vec::new();
+    };
+    EXECUTABLES
+        .iter()
+        .filter_map(|name| {
+            std::env::split_paths(&path)
+                .map(|dir| dir.join(name))
+                .find(|candidate| candidate.is_file())
+        })
+        .collect()
+}
+
+/// Browser arguments that keep a page and everything it loads on `hosts`: other names don't
+/// resolve, and connections to anything but `hosts`, loopback included, go to a proxy that
+/// can't be reached.
+fn confinement_args(hosts: &[String]) -> Vec<String> {
+    let mut resolver_rules = vec!["MAP * ~NOTFOUND".to_string()];
+    resolver_rules.extend(hosts.iter().map(|host| format!("EXCLUDE {host}")));
+    let mut bypass = hosts.to_vec();
+    bypass.push("<-loopback>".to_string());
+    vec![
+        format!("--host-resolver-rules={}", resolver_rules.join(", ")),
+        format!("--proxy-server={BLOCKING_PROXY}"),
+        format!("--proxy-bypass-list={}", bypass.join(";")),
+    ]
+}
+
+/// Whether `host` is one of `hosts`, where `*.example.com` stands for the subdomains of
+/// `example.com`.
+fn host_allowed(hosts: &[String], host: &str) -> bool {
+    hosts
+        .iter()
+        .any(|allowed| match allowed.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+            None => allowed == host,
+        })
+}
+
+/// Per-component browser caps derived from the policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowserLimits {
+    /// Sites pages may be loaded from, lowercase
+    pub hosts: Vec<String>,
+    /// Maximum number of pages loaded during a single tool invocation
+    pub max_pages: u32,
+}
+
+impl BrowserLimits {
+    /// Limits granted by `policy`, or `None` if the policy doesn't allow the browser.
+    pub fn from_policy(policy: &PolicyDocument) -> Option<Self> {
+        policy.permissions.browser.as_ref().map(|browser| Self {
+            hosts: browser
+                .allow
+                .iter()
+                .flatten()
+                .map(|perm| perm.host.to_ascii_lowercase())
+                .collect(),
+            max_pages: browser.max_pages.unwrap_or(DEFAULT_MAX_PAGES),
+        })
+    }
+}
+
+/// Browser state of a single store.
+#[derive(Debug, Default, Clone)]
+pub struct BrowserState {
+    /// Caps from the policy; `None` denies the browser
+    pub limits: Option<BrowserLimits>,
+    /// The host's browser; `None` when none is configured
+    pub browser: Option<Arc<HeadlessBrowser>>,
+    /// Pages loaded so far in this store
+    pub pages: u32,
+}
+
+impl BrowserState {
+    /// Check `url` against the policy and the page cap, and record the page.
+    fn admit(
+        &mut self,
+        url: &str,
+    ) -> std::result::Result<(Arc<HeadlessBrowser>, Url, Vec<String>), String> {
+        let limits = self
+            .limits
+            .as_ref()
+            .ok_or_else(|| "the browser is not permitted by the component policy".to_string())?;
+        let browser = self
+            .browser
+            .clone()
+            .ok_or_else(|| "no browser is configured on the host".to_string())?;
+        let url = Url::parse(url).map_err(|e| format!("invalid URL `{url}`: {e}"))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("URL `{url}` must use http or https"));
+        }
+        let host = url.host_str().unwrap_or_default();
+        if !host_allowed(&limits.hosts, host) {
+            return Err(format!(
+                "host `{host}` is not allowed by the component policy"
+            ));
+        }
+        if self.pages >= limits.max_pages {
+            return Err(format!(
+                "browser limit of {} pages per invocation reached",
+                limits.max_pages
+            ));
+        }
+        self.pages += 1;
+        Ok((browser, url, limits.hosts.clone()))
+    }
+}
+
+/// Add the `wassette:browser/page` interface to `linker`.
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    let mut instance = linker.instance(BROWSER_INTERFACE)?;
+    instance.func_wrap_async("navigate", |mut store, (url,): (String,)| {
+        let admitted = store.data_mut().inner.browser.admit(&url);
+        Box::new(async move {
+            let result = match admitted {
+                Ok((browser, url, hosts)) => browser
+                    .load(&url, &hosts, Capture::Dom)
+                    .await
+                    .map(|dom| String::from_utf8_lossy(&dom).into_owned())
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            };
+            Ok((result,))
+        })
+    })?;
+    instance.func_wrap_async("text", |mut store, (url,): (String,)| {
+        let admitted = store.data_mut().inner.browser.admit(&url);
+        Box::new(async move {
+            let result = match admitted {
+                Ok((browser, url, hosts)) => browser
+                    .load(&url, &hosts, Capture::Dom)
+                    .await
+                    .map(|dom| html_to_text(&String::from_utf8_lossy(&dom)))
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            };
+            Ok((result,))
+        })
+    })?;
+    instance.func_wrap_async(
+        "screenshot",
+        |mut store, (url, width, height): (String, u32, u32)| {
+            let admitted = store.data_mut().inner.browser.admit(&url);
+            let capture = Capture::Screenshot {
+                width: width.clamp(1, MAX_VIEWPORT),
+                height: height.clamp(1, MAX_VIEWPORT),
+            };
+            Box::new(async move {
+                let result = match admitted {
+                    Ok((browser, url, hosts)) => browser
+                        .load(&url, &hosts, capture)
+                        .await
+                        .map_err(|e| e.to_string()),
+                    Err(e) => Err(e),
+                };
+                Ok((result,))
+            })
+        },
+    )
+}
+
+/// Elements whose content isn't text a reader sees.
+const HIDDEN_ELEMENTS: &[&str] = &["script", "style", "noscript", "template", "svg"];
+
+/// Elements that start a new line of text.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "title",
+    "tr",
+    "ul",
+];
+
+/// The readable text of the serialized DOM `html`: one line per block, whitespace collapsed,
+/// entities decoded and scripts, styles and comments dropped.
+///
+/// Chromium serializes the DOM with lowercase element names and escapes `<` in text, which is
+/// all this relies on.
+fn html_to_text(html: &str) -> String {
+    let mut raw = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        push_text(&rest[..start], &mut raw);
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = tag_end(rest) else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(char::is_ascii_alphanumeric)
+            .collect();
+        if !closing && HIDDEN_ELEMENTS.contains(&name.as_str()) {
+            rest = match rest.find(&format!("</{name}")) {
+                Some(close) => tag_end(&rest[close..]).map_or("", |end| &rest[close + end + 1..]),
+                None => "",
+            };
+        } else if BLOCK_ELEMENTS.contains(&name.as_str()) {
+            raw.push('\n');
+        } else if matches!(name.as_str(), "td" | "th") {
+            raw.push(' ');
+        }
+    }
+    push_text(rest, &mut raw);
+
+    raw.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Append the text node `text` to `out`; only elements break lines.
+fn push_text(text: &str, out: &mut String) {
+    let start = out.len();
+    decode_entities(text, out);
+    let flowed = out[start..].replace(['\n', '\r'], " ");
+    out.replace_range(start.., &flowed);
+}
+
+/// Index of the `>` closing the tag at the start of `tag`, skipping quoted attribute values.
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Append `text` to `out` with character references decoded.
+fn decode_entities(text: &str, out: &mut String) {
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let decoded = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((entity(&rest[..end])?, end + 1)));
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => out.push('&'),
+        }
+    }
+    out.push_str(rest);
+}
+
+/// The character the reference `&name;` stands for.
+fn entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = name.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use policy::PolicyParser;
+
+    use super::*;
+
+    fn state(hosts: &[&str], max_pages: u32) -> BrowserState {
+        BrowserState {
+            limits: Some(BrowserLimits {
+                hosts: hosts.iter().map(|host| host.to_string()).collect(),
+                max_pages,
+            }),
+            browser: Some(Arc::new(HeadlessBrowser {
+                executable: PathBuf::from("chromium"),
+                args: Vec::new(),
+                timeout: Duration::from_secs(1),
+            })),
+            pages: 0,
+        }
+    }
+
+    #[test]
+    fn test_limits_from_policy() {
+        let policy = PolicyParser::parse_str("version: \"1.0\"\npermissions: {}\n").unwrap();
+        assert_eq!(BrowserLimits::from_policy(&policy), None);
+
+        let policy = PolicyParser::parse_str(
+            "version: \"1.0\"\npermissions:\n  browser:\n    allow:\n    - host: Docs.rs\n",
+        )
+        .unwrap();
+        assert_eq!(
+            BrowserLimits::from_policy(&policy),
+            Some(BrowserLimits {
+                hosts: vec!["docs.rs".to_string()],
+                max_pages: DEFAULT_MAX_PAGES,
+            })
+        );
+    }
+
+    #[test]
+    fn test_admit_enforces_url_policy() {
+        let mut state = state(&["docs.rs", "*.wikipedia.org"], 10);
+        assert!(state.admit("https://docs.rs/tokio").is_ok());
+        assert!(state.admit("https://en.wikipedia.org/wiki/Rust").is_ok());
+        for denied in [
+            "https://wikipedia.org/",
+            "https://evilwikipedia.org/",
+            "https://docs.rs.evil.example/",
+            "http://127.0.0.1:8080/",
+            "file:///etc/passwd",
+            "javascript:alert(1)",
+            "not a url",
+        ] {
+            assert!(state.admit(denied).is_err(), "{denied}");
+        }
+        assert_eq!(state.pages, 2);
+
+        let mut state = BrowserState {
+            limits: None,
+            ..state
+        };
+        assert!(state.admit("https://docs.rs/").is_err());
+    }
+
+    #[test]
+    fn test_admit_enforces_page_cap() {
+        let mut state = state(&["docs.rs"], 2);
+        assert!(state.admit("https://docs.rs/a").is_ok());
+        assert!(state.admit("https://docs.rs/b").is_ok());
+        let error = state.admit("https://docs.rs/c").unwrap_err();
+        assert_eq!(error, "browser limit of 2 pages per invocation reached");
+
+        state.pages = 0;
+        state.browser = None;
+        assert_eq!(
+            state.admit("https://docs.rs/a").unwrap_err(),
+            "no browser is configured on the host"
+        );
+    }
+
+    #[test]
+    fn test_confinement_args() {
+        let args = confinement_args(&["docs.rs".to_string(), "*.example.com".to_string()]);
+        assert_eq!(
+            args,
+            [
+                "--host-resolver-rules=MAP * ~NOTFOUND, EXCLUDE docs.rs, EXCLUDE *.example.com",
+                "--proxy-server=http://blocked.invalid:9",
+                "--proxy-bypass-list=docs.rs;*.example.com;<-loopback>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_validation() {
+        assert!(BrowserConfig::default().validate().is_ok());
+        let config = BrowserConfig {
+            args: vec!["--no-sandbox".to_string()],
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let config = BrowserConfig {
+            args: vec!["--proxy-server=direct://".to_string()],
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        let config = BrowserConfig {
+            timeout_secs: Some(0),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = r#"<html><head><title>Crates &amp; docs</title>
+<style>body { color: red }</style><script>document.write("<p>hidden</p>")</script></head>
+<body><!-- nav --><div class="a>b">Hello,
+   <b>world</b>&nbsp;&#x21;</div><ul><li>one</li><li>two &lt;3</li></ul>
+<table><tr><td>a</td><td>b</td></tr></table><noscript>enable JS</noscript>x &unknown; y</body></html>"#;
+        assert_eq!(
+            html_to_text(html),
+            "Crates & docs\nHello, world !\none\ntwo <3\na b\nx &unknown; y"
+        );
+        assert_eq!(html_to_text("plain & simple"), "plain & simple");
+        assert_eq!(html_to_text("<p>cut <b"), "cut");
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Progress reports and cancellation of tool calls.
+//!
+//! Components import
+//!
+//! ```wit
+//! package wassette:progress;
+//!
+//! interface report {
+//!     /// Report that the call is `percent` done, with an optional status message.
+//!     report: func(percent: u8, message: option<string>);
+//! }
+//! ```
+//!
+//! to tell the caller how far a long-running tool call has got, e.g. as MCP
+//! `notifications/progress`. The percentage never goes back: a lower one is raised to the last
+//! reported, and percentages above 100 are capped. Reports that change nothing are dropped, as
+//! are reports beyond [`MAX_REPORTS`] per call, so a component reporting from a tight loop
+//! can't flood the client. No policy section is needed.
+//!
+//! A call whose [`CallContext`](crate::CallContext) carries a cancellation token stops when the
+//! token is cancelled: the epoch callback traps at the next epoch tick, or a call waiting on the
+//! host is dropped, and the call fails with a [`CallCancelled`].
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Result;
+use wasmtime::component::Linker;
+
+use crate::{WasiState, WassetteWasiState};
+
+/// Name of the host interface through which components report progress.
+pub const PROGRESS_INTERFACE: &str = "wassette:progress/report";
+
+/// Most reports forwarded per call.
+pub const MAX_REPORTS: u32 = 1000;
+
+/// Longest message, in characters, forwarded; longer messages are cut.
+pub const MAX_MESSAGE_CHARS: usize = 256;
+
+/// How far a tool call has got, as its component reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallProgress {
+    /// Percentage done, from 0 to 100; never lower than in an earlier report
+    pub percent: u8,
+    /// What the component is doing
+    pub message: Option<String>,
+}
+
+/// Callback receiving the [`CallProgress`] reports of a call.
+pub type CallProgressFn = Arc<dyn Fn(CallProgress) + Send + Sync>;
+
+/// A tool call was stopped because its caller cancelled it.
+///
+/// Like [`CallTimedOut`](crate::CallTimedOut), it is attached to the error of the call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallCancelled {
+    /// The component that was called
+    pub component_id: String,
+}
+
+impl fmt::Display for CallCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tool call cancelled: the call of component {} was cancelled by its caller",
+            self.component_id
+        )
+    }
+}
+
+impl std::error::Error for CallCancelled {}
+
+/// Progress reporting of a single store.
+#[derive(Clone, Default)]
+pub struct ProgressState {
+    /// Where the reports of the current invocation go; `None` drops them
+    pub reporter: Option<CallProgressFn>,
+    /// The last report forwarded
+    last: Option<CallProgress>,
+    /// Reports forwarded in the current invocation
+    reports: u32,
+}
+
+impl ProgressState {
+    /// Forward the reports of the next invocation to `reporter`.
+    pub(crate) fn start(&mut self, reporter: Option<CallProgressFn>) {
+        *self = Self {
+            reporter,
+            ..Self::default()
+        };
+    }
+
+    /// The report to forward for `percent` and `message`, if any.
+    fn admit(&mut self, percent: u8, message: Option<String>) -> Option<CallProgress> {
+        if self.reporter.is_none() || self.reports >= MAX_REPORTS {
+            return None;
+        }
+        let last = self.last.as_ref().map_or(0, |last| last.percent);
+        let message = message
+            .map(|message| {
+                message
+                    .chars()
+                    .filter(|c| !c.is_control())
+                    .take(MAX_MESSAGE_CHARS)
+                    .collect::<String>()
+            })
+            .filter(|message| !message.trim().is_empty());
+        let progress = CallProgress {
+            percent: percent.clamp(last, 100),
+            message,
+        };
+        if self.last.as_ref() == Some(&progress) {
+            return None;
+        }
+        self.reports += 1;
+        self.last = Some(progress.clone());
+        Some(progress)
+    }
+
+    fn report(&mut self, percent: u8, message: Option<String>) {
+        if let Some(progress) = self.admit(percent, message) {
+            if let Some(reporter) = &self.reporter {
+                reporter(progress);
+            }
+        }
+    }
+}
+
+/// Add the `wassette:progress/report` interface to `linker`.
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    linker.instance(PROGRESS_INTERFACE)?.func_wrap(
+        "report",
+        |mut store, (percent, message): (u8, Option<String>)| {
+            store.data_mut().inner.progress.report(percent, message);
+            Ok(())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn recording() -> (ProgressState, Arc<Mutex<Vec<CallProgress>>>) {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reports);
+        let mut state = ProgressState::default();
+        state.start(Some(Arc::new(move |progress| {
+            sink.lock().unwrap().push(progress)
+        })));
+        (state, reports)
+    }
+
+    #[test]
+    fn test_reports_never_go_back() {
+        let (mut state, reports) = recording();
+        state.report(40, Some("Fetching\u{1b}[0m pages".to_string()));
+        state.report(40, Some("Fetching\u{1b}[0m pages".to_string()));
+        state.report(20, None);
+        state.report(250, Some(" ".to_string()));
+
+        assert_eq!(
+            *reports.lock().unwrap(),
+            vec![
+                CallProgress {
+                    percent: 40,
+                    message: Some("Fetching[0m pages".to_string()),
+                },
+                CallProgress {
+                    percent: 40,
+                    message: None,
+                },
+                CallProgress {
+                    percent: 100,
+                    message: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_are_capped_per_call() {
+        let (mut state, reports) = recording();
+        for i in 0..MAX_REPORTS + 10 {
+            state.report(0, Some(i.to_string()));
+        }
+        assert_eq!(reports.lock().unwrap().len(), MAX_REPORTS as usize);
+
+        // The next invocation starts over.
+        let reporter = state.reporter.clone();
+        state.start(reporter);
+        state.report(10, None);
+        assert_eq!(reports.lock().unwrap().len(), MAX_REPORTS as usize + 1);
+    }
+
+    #[test]
+    fn test_reports_without_reporter_are_dropped() {
+        let mut state = ProgressState::default();
+        assert_eq!(state.admit(50, None), None);
+    }
+}
@@ -0,0 +1,447 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Search of the catalog indexes listing components that can be loaded.
+//!
+//! The `[catalog]` section of the configuration file lists the indexes to search, besides the
+//! catalog bundled with wassette:
+//!
+//! ```toml
+//! [catalog]
+//! indexes = [
+//!     "https://tools.example.com/catalog.json",
+//!     "file:///etc/wassette/catalog.json",
+//!     "oci://ghcr.io/example/catalog:latest",
+//! ]
+//! builtin = true
+//! ```
+//!
+//! `file://` and `https://` indexes are static JSON: a list of entries, or an object with a
+//! `components` list, each with a `name`, `description`, `uri`, and optionally a `version` and
+//! the `permissions` section of the component's policy. An `oci://` index is an artifact whose
+//! [referrers](https://github.com/opencontainers/distribution-spec/blob/main/spec.md#listing-referrers)
+//! of type [`CATALOG_ENTRY_ARTIFACT_TYPE`] are the entries, described by the annotations of
+//! their manifests: [`COMPONENT_ANNOTATION`] for the URI to load the component from, the
+//! standard title, description and version annotations, and [`PERMISSIONS_ANNOTATION`] for
+//! the permissions as JSON. Publishing an entry is then a matter of attaching an artifact to
+//! the index, without rewriting a shared file.
+//!
+//! An index that can't be read is reported with the results of the others.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use oci_client::Reference;
+use policy::{AccessType, Permissions};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::oci_pull::OciPull;
+
+/// Artifact type of the referrers of an OCI index that are catalog entries.
+pub const CATALOG_ENTRY_ARTIFACT_TYPE: &str = "application/vnd.wassette.catalog-entry";
+
+/// Annotation of a catalog entry holding the URI to load the component from.
+pub const COMPONENT_ANNOTATION: &str = "dev.wassette.component";
+
+/// Annotation of a catalog entry holding the permissions of the component's policy, as JSON.
+pub const PERMISSIONS_ANNOTATION: &str = "dev.wassette.permissions";
+
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+const DESCRIPTION_ANNOTATION: &str = "org.opencontainers.image.description";
+const VERSION_ANNOTATION: &str = "org.opencontainers.image.version";
+
+/// Index name of the catalog bundled with wassette.
+const BUILTIN_INDEX: &str = "builtin";
+
+const BUILTIN_CATALOG: &str = include_str!("../../../component-registry.json");
+
+/// Catalog indexes to search, under `[catalog]` in the configuration file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CatalogConfig {
+    /// URIs of the indexes, searched in order: `file://`, `https://` or `oci://`
+    pub indexes: Vec<String>,
+    /// Whether to search the catalog bundled with wassette after the indexes
+    pub builtin: bool,
+}
+
+impl Default for CatalogConfig {
+    fn default() -> Self {
+        Self {
+            indexes: Vec::new(),
+            builtin: true,
+        }
+    }
+}
+
+impl CatalogConfig {
+    /// Check that every index has a supported scheme.
+    pub fn validate(&self) -> Result<()> {
+        for index in &self.indexes {
+            match index.split_once("://") {
+                Some(("file", path)) if Path::new(path).is_absolute() => {}
+                Some(("file", _)) => bail!("Catalog index path must be absolute: {index}"),
+                Some(("https", _)) => {}
+                Some(("oci", reference)) => {
+                    reference
+                        .parse::<Reference>()
+                        .with_context(|| format!("Invalid catalog index {index}"))?;
+                }
+                _ => bail!("Unsupported catalog index {index}; use file://, https:// or oci://"),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A component listed in a catalog index
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CatalogEntry {
+    /// Human-readable name
+    pub name: String,
+    /// What the component does
+    pub description: String,
+    /// URI to load the component from
+    pub uri: String,
+    /// Version of the release the entry describes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// What the component's policy grants, one line per grant, e.g. `network: api.github.com`
+    pub permissions: Vec<String>,
+    /// The index listing the component
+    pub index: String,
+}
+
+/// An index that couldn't be searched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CatalogError {
+    /// URI of the index
+    pub index: String,
+    /// Why it couldn't be read
+    pub error: String,
+}
+
+/// Result of a catalog search
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CatalogSearch {
+    /// Matching components, in index order; a URI listed by several indexes appears once
+    pub components: Vec<CatalogEntry>,
+    /// Indexes that couldn't be searched
+    pub errors: Vec<CatalogError>,
+}
+
+/// An entry of a static JSON index
+#[derive(Debug, Deserialize)]
+struct IndexEntry {
+    name: String,
+    #[serde(default)]
+    description: String,
+    uri: String,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    permissions: Option<Permissions>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum IndexDocument {
+    List(Vec<IndexEntry>),
+    Object { components: Vec<IndexEntry> },
+}
+
+/// Search the indexes of `config` for components matching every word of `query`, in their
+/// name, description or URI, ignoring case. An empty query lists every component.
+pub(crate) async fn search(
+    config: &CatalogConfig,
+    query: &str,
+    oci_client: &oci_client::Client,
+    http_client: &reqwest::Client,
+    pull: &OciPull,
+) -> CatalogSearch {
+    let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    let mut indexes: Vec<&str> = config.indexes.iter().map(String::as_str).collect();
+    if config.builtin {
+        indexes.push(BUILTIN_INDEX);
+    }
+
+    let mut search = CatalogSearch::default();
+    let mut seen = HashSet::new();
+    for index in indexes {
+        let entries = match read_index(index, oci_client, http_client, pull).await {
+            Ok(entries) => entries,
+            Err(error) => {
+                warn!(
+                    index,
+                    error = format!("{error:#}"),
+                    "Failed to read catalog index"
+                );
+                search.errors.push(CatalogError {
+                    index: index.to_string(),
+                    error: format!("{error:#}"),
+                });
+                continue;
+            }
+        };
+        for entry in entries {
+            if matches(&entry, &terms) && seen.insert(entry.uri.clone()) {
+                search.components.push(entry);
+            }
+        }
+    }
+    search
+}
+
+fn matches(entry: &CatalogEntry, terms: &[String]) -> bool {
+    let text = format!("{} {} {}", entry.name, entry.description, entry.uri).to_lowercase();
+    terms.iter().all(|term| text.contains(term.as_str()))
+}
+
+async fn read_index(
+    index: &str,
+    oci_client: &oci_client::Client,
+    http_client: &reqwest::Client,
+    pull: &OciPull,
+) -> Result<Vec<CatalogEntry>> {
+    if index == BUILTIN_INDEX {
+        return parse_static(index, BUILTIN_CATALOG.as_bytes());
+    }
+    match index.split_once("://") {
+        Some(("file", path)) => {
+            let content = tokio::fs::read(path)
+                .await
+                .with_context(|| format!("Failed to read {path}"))?;
+            parse_static(index, &content)
+        }
+        Some(("https", _)) => {
+            let response = http_client.get(index).send().await?.error_for_status()?;
+            parse_static(index, &response.bytes().await?)
+        }
+        Some(("oci", reference)) => read_referrers(index, reference, oci_client, pull).await,
+        _ => bail!("Unsupported catalog index {index}"),
+    }
+}
+
+fn parse_static(index: &str, content: &[u8]) -> Result<Vec<CatalogEntry>> {
+    let entries = match serde_json::from_slice(content).context("Invalid catalog index")? {
+        IndexDocument::List(entries) => entries,
+        IndexDocument::Object { components } => components,
+    };
+    Ok(entries
+        .into_iter()
+        .map(|entry| CatalogEntry {
+            name: entry.name,
+            description: entry.description,
+            uri: entry.uri,
+            version: entry.version,
+            permissions: entry
+                .permissions
+                .as_ref()
+                .map(summarize)
+                .unwrap_or_default(),
+            index: index.to_string(),
+        })
+        .collect())
+}
+
+/// The entries attached to the OCI artifact `reference`, asking its mirrors first.
+async fn read_referrers(
+    index: &str,
+    reference: &str,
+    oci_client: &oci_client::Client,
+    pull: &OciPull,
+) -> Result<Vec<CatalogEntry>> {
+    let reference: Reference = reference.parse().context("Invalid OCI reference")?;
+    let mut last_error = anyhow!("No registry to search for {reference}");
+    for candidate in pull.candidates(&reference) {
+        // Authenticating here also authorizes the referrers request.
+        let referrers = match oci_client
+            .pull_manifest(&candidate.reference, &candidate.auth)
+            .await
+        {
+            Ok((_, digest)) => {
+                let subject = candidate.reference.clone_with_digest(digest);
+                oci_client
+                    .pull_referrers(&subject, Some(CATALOG_ENTRY_ARTIFACT_TYPE))
+                    .await
+            }
+            Err(error) => Err(error),
+        };
+        match referrers {
+            Ok(referrers) => {
+                return Ok(referrers
+                    .manifests
+                    .iter()
+                    .filter_map(|referrer| from_annotations(index, referrer.annotations.as_ref()))
+                    .collect())
+            }
+            Err(error) => last_error = error.into(),
+        }
+    }
+    Err(last_error.context("Failed to list the catalog entries"))
+}
+
+/// The entry described by the annotations of a referrer, if they name a component.
+fn from_annotations(
+    index: &str,
+    annotations: Option<&BTreeMap<String, String>>,
+) -> Option<CatalogEntry> {
+    let annotation = |key| {
+        annotations
+            .and_then(|annotations| annotations.get(key))
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+    let uri = annotation(COMPONENT_ANNOTATION)?.to_string();
+    let permissions = annotation(PERMISSIONS_ANNOTATION).and_then(|permissions| {
+        serde_json::from_str::<Permissions>(permissions)
+            .inspect_err(|error| warn!(%uri, %error, "Ignoring invalid catalog permissions"))
+            .ok()
+    });
+    Some(CatalogEntry {
+        name: annotation(TITLE_ANNOTATION).unwrap_or(&uri).to_string(),
+        description: annotation(DESCRIPTION_ANNOTATION)
+            .unwrap_or_default()
+            .to_string(),
+        version: annotation(VERSION_ANNOTATION).map(str::to_string),
+        permissions: permissions.as_ref().map(summarize).unwrap_or_default(),
+        uri,
+        index: index.to_string(),
+    })
+}
+
+/// One line per grant of `permissions`, naming the other sections it sets.
+pub(crate) fn summarize(permissions: &Permissions) -> Vec<String> {
+    let mut summary = Vec::new();
+    for rule in permissions
+        .network
+        .iter()
+        .flat_map(|network| network.allow.iter().flatten())
+    {
+        summary.push(format!("network: {}", rule.destination()));
+    }
+    for rule in permissions
+        .storage
+        .iter()
+        .flat_map(|storage| storage.allow.iter().flatten())
+    {
+        let access: Vec<&str> = rule
+            .access
+            .iter()
+            .map(|access| match access {
+                AccessType::Read => "read",
+                AccessType::Write => "write",
+            })
+            .collect();
+        summary.push(format!("storage: {} ({})", rule.uri, access.join(", ")));
+    }
+    for rule in permissions
+        .environment
+        .iter()
+        .flat_map(|environment| environment.allow.iter().flatten())
+    {
+        summary.push(format!("environment: {}", rule.key));
+    }
+    if let Ok(Value::Object(sections)) = serde_json::to_value(permissions) {
+        for (section, value) in sections {
+            if !value.is_null()
+                && !["network", "storage", "environment"].contains(&section.as_str())
+            {
+                summary.push(section);
+            }
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_indexes_are_searched_with_permission_summaries() {
+        let index = r#"{"components": [
+            {
+                "name": "GitHub",
+                "description": "Issues and pull requests",
+                "uri": "oci://ghcr.io/example/github:1.2.0",
+                "version": "1.2.0",
+                "permissions": {
+                    "network": {"allow": [{"host": "api.github.com"}]},
+                    "storage": {"allow": [{"uri": "fs:///tmp/cache", "access": ["read", "write"]}]},
+                    "environment": {"allow": [{"key": "GITHUB_TOKEN"}]},
+                    "keyvalue": {}
+                }
+            },
+            {"name": "Fetch", "uri": "oci://ghcr.io/example/fetch:latest"}
+        ]}"#;
+        let entries = parse_static("file:///catalog.json", index.as_bytes()).unwrap();
+        assert_eq!(
+            entries[0].permissions,
+            vec![
+                "network: api.github.com",
+                "storage: fs:///tmp/cache (read, write)",
+                "environment: GITHUB_TOKEN",
+                "keyvalue",
+            ]
+        );
+        assert_eq!(entries[1].description, "");
+        assert!(entries[1].permissions.is_empty());
+
+        let terms = |query: &str| -> Vec<String> {
+            query.split_whitespace().map(str::to_lowercase).collect()
+        };
+        assert!(matches(&entries[0], &terms("pull GITHUB")));
+        assert!(!matches(&entries[0], &terms("github weather")));
+        assert!(matches(&entries[1], &terms("")));
+
+        let builtin = parse_static(BUILTIN_INDEX, BUILTIN_CATALOG.as_bytes()).unwrap();
+        assert!(!builtin.is_empty());
+    }
+
+    #[test]
+    fn test_referrer_annotations() {
+        let annotations = BTreeMap::from([
+            (
+                COMPONENT_ANNOTATION.to_string(),
+                "oci://ghcr.io/example/fetch:2".to_string(),
+            ),
+            (VERSION_ANNOTATION.to_string(), "2.0.0".to_string()),
+            (
+                PERMISSIONS_ANNOTATION.to_string(),
+                r#"{"network": {"allow": [{"host": "*.example.com"}]}}"#.to_string(),
+            ),
+        ]);
+        let entry =
+            from_annotations("oci://ghcr.io/example/catalog:latest", Some(&annotations)).unwrap();
+        assert_eq!(entry.name, "oci://ghcr.io/example/fetch:2");
+        assert_eq!(entry.version.as_deref(), Some("2.0.0"));
+        assert_eq!(entry.permissions, vec!["network: *.example.com"]);
+
+        assert!(from_annotations("oci://ghcr.io/example/catalog:latest", None).is_none());
+    }
+
+    #[test]
+    fn test_catalog_config_validation() {
+        let config = |index: &str| CatalogConfig {
+            indexes: vec![index.to_string()],
+            builtin: true,
+        };
+        assert!(config("https://example.com/catalog.json")
+            .validate()
+            .is_ok());
+        assert!(config("oci://ghcr.io/example/catalog:latest")
+            .validate()
+            .is_ok());
+        assert!(config("file:///etc/wassette/catalog.json")
+            .validate()
+            .is_ok());
+        assert!(config("file://catalog.json").validate().is_err());
+        assert!(config("http://example.com/catalog.json")
+            .validate()
+            .is_err());
+    }
+}
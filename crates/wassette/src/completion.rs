@@ -0,0 +1,170 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Argument completion hooks exported by components.
+//!
+//! A component can offer autocompletion for its tools' arguments by exporting
+//!
+//! ```wit
+//! complete: func(argument: string, prefix: string) -> list<string>;
+//! ```
+//!
+//! either at the top level or inside an exported interface. A hook inside an interface
+//! completes arguments of that interface's tools, and one inside the prompts interface those of
+//! the component's prompts; a top-level hook covers every tool and prompt of the component that
+//! has no hook of its own. Hooks are not exposed as tools.
+//!
+//! Any client can ask for completions, so hooks run like tool calls: in an instance slot, on
+//! the component's fuel allowance and under a deadline, [`COMPLETION_TIMEOUT`] or the call
+//! timeout of the tool if shorter.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use component2json::ToolMetadata;
+use wasmtime::component::types::{ComponentFunc, ComponentItem};
+use wasmtime::component::{Component, Instance, Type};
+use wasmtime::{Engine, Store};
+
+use crate::{WasiState, WassetteWasiState};
+
+/// Name of the export that provides argument completions.
+pub(crate) const COMPLETE_EXPORT: &str = "complete";
+
+/// Maximum number of completion values returned to the client, as mandated by MCP.
+pub(crate) const MAX_COMPLETIONS: usize = 100;
+
+/// Longest a completion hook may run, including instantiating its component.
+pub(crate) const COMPLETION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interfaces (`None` for the top level) that export a completion hook.
+pub(crate) fn completion_hooks(component: &Component, engine: &Engine) -> Vec<Option<String>> {
+    let mut hooks = Vec::new();
+    for (name, item) in component.component_type().exports(engine) {
+        match item {
+            ComponentItem::ComponentFunc(func)
+                if name == COMPLETE_EXPORT && is_completion_hook(&func) =>
+            {
+                hooks.push(None);
+            }
+            ComponentItem::ComponentInstance(instance) => {
+                let exports_hook = instance.exports(engine).any(|(export, item)| {
+                    export == COMPLETE_EXPORT
+                        && matches!(&item, ComponentItem::ComponentFunc(func) if is_completion_hook(func))
+                });
+                if exports_hook {
+                    hooks.push(Some(name.to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+    hooks
+}
+
+/// `func(string, string) -> list<string>`
+fn is_completion_hook(func: &ComponentFunc) -> bool {
+    let params: Vec<Type> = func.params().map(|(_, ty)| ty).collect();
+    let results: Vec<Type> = func.results().collect();
+    matches!(params.as_slice(), [Type::String, Type::String])
+        && matches!(results.as_slice(), [Type::List(list)] if matches!(list.ty(), Type::String))
+}
+
+/// Remove completion hooks from the tools generated for a component.
+pub(crate) fn strip_completion_hooks(tools: &mut Vec<ToolMetadata>, hooks: &[Option<String>]) {
+    tools.retain(|tool| {
+        tool.identifier.function_name != COMPLETE_EXPORT
+            || !hooks.contains(&tool.identifier.interface_name)
+    });
+}
+
+/// Pick the hook that completes arguments of a tool exported from `interface`.
+pub(crate) fn hook_for<'a>(
+    hooks: &'a [Option<String>],
+    interface: Option<&str>,
+) -> Option<&'a Option<String>> {
+    hooks
+        .iter()
+        .find(|hook| hook.as_deref() == interface && interface.is_some())
+        .or_else(|| hooks.iter().find(|hook| hook.is_none()))
+}
+
+/// Invoke the completion hook exported from `interface` on an instantiated component.
+pub(crate) async fn complete(
+    store: &mut Store<WassetteWasiState<WasiState>>,
+    instance: &Instance,
+    interface: Option<&str>,
+    argument: &str,
+    prefix: &str,
+) -> Result<Vec<String>> {
+    let parent = match interface {
+        Some(interface) => Some(
+            instance
+                .get_export_index(&mut *store, None, interface)
+                .with_context(|| format!("Interface not found: {interface}"))?,
+        ),
+        None => None,
+    };
+    let index = instance
+        .get_export_index(&mut *store, parent.as_ref(), COMPLETE_EXPORT)
+        .context("Completion hook not found")?;
+    let func = instance
+        .get_typed_func::<(String, String), (Vec<String>,)>(&mut *store, index)
+        .context("Completion hook has an unexpected signature")?;
+
+    let (mut values,) = func
+        .call_async(&mut *store, (argument.to_string(), prefix.to_string()))
+        .await?;
+    values.truncate(MAX_COMPLETIONS);
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use component2json::component_exports_to_tools;
+
+    use super::*;
+
+    fn component(engine: &Engine) -> Component {
+        let wat = r#"(component
+            (type (component
+                (type (component
+                    (type (list string))
+                    (type (func (param "argument" string) (param "prefix" string) (result 0)))
+                    (export "complete" (func (type 1)))
+                    (type (func (param "path" string) (result string)))
+                    (export "read" (func (type 2)))
+                ))
+                (export "foo:foo/files" (component (type 0)))
+            ))
+            (export "foo" (type 0))
+        )"#;
+        Component::new(engine, wat).unwrap()
+    }
+
+    #[test]
+    fn test_hook_selection() {
+        let hooks = vec![Some("foo:foo/files".to_string()), None];
+        assert_eq!(
+            hook_for(&hooks, Some("foo:foo/files")),
+            Some(&Some("foo:foo/files".to_string()))
+        );
+        assert_eq!(hook_for(&hooks, Some("foo:foo/other")), Some(&None));
+        assert_eq!(hook_for(&hooks, None), Some(&None));
+        assert_eq!(hook_for(&[], None), None);
+    }
+
+    #[test]
+    fn test_hooks_are_not_tools() {
+        let engine = Engine::default();
+        let component = component(&engine);
+        let mut tools = component_exports_to_tools(&component, &engine, true);
+        assert_eq!(tools.len(), 2);
+
+        // Type-only components export no instances, so pretend the interface has a hook.
+        let hooks = vec![tools[0].identifier.interface_name.clone()];
+        strip_completion_hooks(&mut tools, &hooks);
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].identifier.function_name, "read");
+    }
+}
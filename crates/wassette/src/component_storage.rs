@@ -14,7 +14,12 @@ use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio::task::spawn_blocking;
 
 use crate::loader::DownloadedResource;
-use crate::{ComponentMetadata, ValidationStamp};
+use crate::manifest::Manifest;
+use crate::updates::UpdateSource;
+use crate::{ComponentMetadata, ToolPresets, ValidationStamp};
+
+/// Name of the key-value state database in a component's data directory.
+const KEYVALUE_FILE: &str = "keyvalue.redb";
 
 /// Handles filesystem layout and metadata persistence for components.
 #[derive(Clone)]
@@ -56,7 +61,6 @@ impl ComponentStorage {
     }
 
     /// Directory used for staging downloaded artifacts.
-    #[allow(dead_code)]
     pub fn downloads_dir(&self) -> &Path {
         &self.downloads_dir
     }
@@ -96,6 +100,36 @@ impl ComponentStorage {
         self.root.join(format!("{component_id}.policy.meta.json"))
     }
 
+    /// Directory of the data a component keeps across calls.
+    pub fn data_dir(&self, component_id: &str) -> PathBuf {
+        self.root.join(crate::DATA_DIR).join(component_id)
+    }
+
+    /// Absolute path to the key-value state database of a component.
+    pub fn state_path(&self, component_id: &str) -> PathBuf {
+        self.data_dir(component_id).join(KEYVALUE_FILE)
+    }
+
+    /// Absolute path to the vector collections of a component.
+    pub fn vectors_path(&self, component_id: &str) -> PathBuf {
+        self.root.join(format!("{component_id}.vectors.json"))
+    }
+
+    /// Absolute path to the per-tool argument presets for a component.
+    pub fn presets_path(&self, component_id: &str) -> PathBuf {
+        self.root.join(format!("{component_id}.presets.json"))
+    }
+
+    /// Absolute path to the manifest a component was installed from.
+    pub fn manifest_path(&self, component_id: &str) -> PathBuf {
+        self.root.join(format!("{component_id}.manifest.json"))
+    }
+
+    /// Absolute path to the record of where a component can be updated from.
+    pub fn source_path(&self, component_id: &str) -> PathBuf {
+        self.root.join(format!("{component_id}.source.json"))
+    }
+
     /// Stage a downloaded component artifact into storage, replacing any existing files.
     pub async fn install_component_artifact(
         &self,
@@ -106,12 +140,15 @@ impl ComponentStorage {
 
         self.remove_component_artifacts(component_id).await?;
 
-        resource.copy_to(self.root()).await.with_context(|| {
-            format!(
-                "Failed to copy component to destination: {}",
-                self.root.display()
-            )
-        })?;
+        resource
+            .copy_to(self.root(), component_id)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to copy component to destination: {}",
+                    self.root.display()
+                )
+            })?;
 
         Ok(self.component_path(component_id))
     }
@@ -170,15 +207,160 @@ impl ComponentStorage {
         Ok(Some(metadata))
     }
 
-    /// Write precompiled component bytes to disk.
-    pub async fn write_precompiled(&self, component_id: &str, bytes: &[u8]) -> Result<()> {
-        let path = self.precompiled_path(component_id);
-        tokio::fs::write(&path, bytes).await.with_context(|| {
-            format!(
-                "Failed to write precompiled component to {}",
-                path.display()
-            )
-        })
+    /// Load the argument presets for a component, returning an empty set if none are stored.
+    pub async fn read_presets(&self, component_id: &str) -> Result<ToolPresets> {
+        let path = self.presets_path(component_id);
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Failed to parse presets at {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ToolPresets::new()),
+            Err(e) => {
+                Err(e).with_context(|| format!("Failed to read presets at {}", path.display()))
+            }
+        }
+    }
+
+    /// Persist the argument presets for a component, removing the file once it is empty.
+    pub async fn write_presets(&self, component_id: &str, presets: &ToolPresets) -> Result<()> {
+        let path = self.presets_path(component_id);
+        if presets.is_empty() {
+            return self
+                .remove_if_exists(&path, "presets file", component_id)
+                .await;
+        }
+
+        let json = serde_json::to_string_pretty(presets).context("Failed to serialize presets")?;
+        tokio::fs::write(&path, json)
+            .await
+            .with_context(|| format!("Failed to write presets to {}", path.display()))
+    }
+
+    /// Create the data directory of a component, readable by this user only.
+    pub async fn create_data_dir(&self, component_id: &str) -> Result<()> {
+        let dir = self.data_dir(component_id);
+        let mut builder = tokio::fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        builder.mode(0o700);
+        builder
+            .create(&dir)
+            .await
+            .with_context(|| format!("Failed to create {}", dir.display()))
+    }
+
+    /// Remove the key-value state of a component, and its data directory once nothing else
+    /// is kept in it.
+    pub async fn remove_state(&self, component_id: &str) -> Result<()> {
+        self.remove_if_exists(
+            &self.state_path(component_id),
+            "state database",
+            component_id,
+        )
+        .await?;
+        let _ = tokio::fs::remove_dir(self.data_dir(component_id)).await;
+        Ok(())
+    }
+
+    /// Load the stored vector collections of a component, if any.
+    pub async fn read_vectors(&self, component_id: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.vectors_path(component_id);
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => {
+                Err(e).with_context(|| format!("Failed to read vectors at {}", path.display()))
+            }
+        }
+    }
+
+    /// Persist the vector collections of a component, or remove them with `None`. The file is
+    /// replaced atomically.
+    pub async fn write_vectors(&self, component_id: &str, contents: Option<&[u8]>) -> Result<()> {
+        let path = self.vectors_path(component_id);
+        let Some(contents) = contents else {
+            return self
+                .remove_if_exists(&path, "vectors file", component_id)
+                .await;
+        };
+
+        let staged = path.with_extension("json.tmp");
+        tokio::fs::write(&staged, contents)
+            .await
+            .with_context(|| format!("Failed to write vectors to {}", staged.display()))?;
+        tokio::fs::rename(&staged, &path)
+            .await
+            .with_context(|| format!("Failed to write vectors to {}", path.display()))
+    }
+
+    /// Load the manifest a component was installed from, if any. The manifest was verified at
+    /// install time and is not verified again.
+    pub async fn read_manifest(&self, component_id: &str) -> Result<Option<Manifest>> {
+        let path = self.manifest_path(component_id);
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .with_context(|| format!("Failed to parse manifest at {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => {
+                Err(e).with_context(|| format!("Failed to read manifest at {}", path.display()))
+            }
+        }
+    }
+
+    /// Record the manifest a component was installed from, or forget it with `None`.
+    pub async fn write_manifest(
+        &self,
+        component_id: &str,
+        manifest: Option<&Manifest>,
+    ) -> Result<()> {
+        let path = self.manifest_path(component_id);
+        let Some(manifest) = manifest else {
+            return self
+                .remove_if_exists(&path, "manifest file", component_id)
+                .await;
+        };
+
+        let json =
+            serde_json::to_string_pretty(manifest).context("Failed to serialize manifest")?;
+        tokio::fs::write(&path, json)
+            .await
+            .with_context(|| format!("Failed to write manifest to {}", path.display()))
+    }
+
+    /// Load where a component can be updated from, if it can be.
+    pub(crate) async fn read_update_source(
+        &self,
+        component_id: &str,
+    ) -> Result<Option<UpdateSource>> {
+        let path = self.source_path(component_id);
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .with_context(|| format!("Failed to parse update source at {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to read update source at {}", path.display())),
+        }
+    }
+
+    /// Record where a component can be updated from, or forget it with `None`.
+    pub(crate) async fn write_update_source(
+        &self,
+        component_id: &str,
+        source: Option<&UpdateSource>,
+    ) -> Result<()> {
+        let path = self.source_path(component_id);
+        let Some(source) = source else {
+            return self
+                .remove_if_exists(&path, "update source file", component_id)
+                .await;
+        };
+
+        let json =
+            serde_json::to_string_pretty(source).context("Failed to serialize update source")?;
+        tokio::fs::write(&path, json)
+            .await
+            .with_context(|| format!("Failed to write update source to {}", path.display()))
     }
 
     /// Remove a file if it exists, translating IO errors into `anyhow`.
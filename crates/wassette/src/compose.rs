@@ -0,0 +1,143 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Composition of components with the library components they depend on.
+//!
+//! A manifest can list libraries, such as a shared auth helper, whose interfaces its component
+//! imports instead of bundling them. On install each library is fetched, from the location
+//! configured for its name under `[libraries]` if any and otherwise from the manifest, and
+//! plugged into the component as `wac plug` does: the component's imports named like exports
+//! of a library are satisfied by an instance of that library, and whatever the libraries import
+//! themselves is left to the host. The composed component is what gets stored, locked and
+//! loaded, so restarts don't compose again; a new release of a library is picked up the next
+//! time the component is installed or updated.
+
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Context, Result};
+use wac_graph::types::Package;
+use wac_graph::{plug, CompositionGraph, EncodeOptions};
+use wasmparser::{Encoding, Parser, Payload};
+
+/// Names of the imports and exports of the outermost component in `bytes`.
+fn interface_names(bytes: &[u8]) -> Result<(BTreeSet<String>, BTreeSet<String>)> {
+    let mut imports = BTreeSet::new();
+    let mut exports = BTreeSet::new();
+    // Imports and exports of nested components are internal to the component.
+    let mut depth = 0usize;
+    for payload in Parser::new(0).parse_all(bytes) {
+        match payload? {
+            Payload::Version { encoding, .. } => {
+                if depth == 0 && encoding != Encoding::Component {
+                    bail!("Not a WebAssembly component");
+                }
+                depth += 1;
+            }
+            Payload::End(_) => depth = depth.saturating_sub(1),
+            Payload::ComponentImportSection(reader) if depth == 1 => {
+                for import in reader {
+                    imports.insert(import?.name.0.to_string());
+                }
+            }
+            Payload::ComponentExportSection(reader) if depth == 1 => {
+                for export in reader {
+                    exports.insert(export?.name.0.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok((imports, exports))
+}
+
+/// Plug `libraries`, given by name and bytes, into the imports of `component`, returning the
+/// composed component. Every library must satisfy at least one import.
+pub(crate) fn compose(component: &[u8], libraries: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    let (imports, _) = interface_names(component).context("Failed to read the component")?;
+    let mut graph = CompositionGraph::new();
+    let package = Package::from_bytes(
+        "wassette:component",
+        None,
+        component.to_vec(),
+        graph.types_mut(),
+    )
+    .context("Failed to read the component")?;
+    let socket = graph.register_package(package)?;
+
+    let mut plugs = Vec::with_capacity(libraries.len());
+    for (index, (name, bytes)) in libraries.iter().enumerate() {
+        let (_, exports) =
+            interface_names(bytes).with_context(|| format!("Failed to read library {name}"))?;
+        if exports.is_disjoint(&imports) {
+            bail!("Library {name} exports none of the interfaces the component imports");
+        }
+        // Package names only need to be unique within the graph.
+        let package = Package::from_bytes(
+            &format!("wassette:library{index}"),
+            None,
+            bytes.clone(),
+            graph.types_mut(),
+        )
+        .with_context(|| format!("Failed to read library {name}"))?;
+        plugs.push(graph.register_package(package)?);
+    }
+
+    plug(&mut graph, plugs, socket).context("Failed to plug the libraries into the component")?;
+    graph
+        .encode(EncodeOptions::default())
+        .context("Failed to encode the composed component")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY: &str = r#"
+        (component
+          (core module $m (func (export "get") (result i32) i32.const 42))
+          (core instance $i (instantiate $m))
+          (func $get (result u32) (canon lift (core func $i "get")))
+          (instance $token (export "get" (func $get)))
+          (export "acme:auth/token@1.0.0" (instance $token)))
+    "#;
+
+    const COMPONENT: &str = r#"
+        (component
+          (import "acme:auth/token@1.0.0" (instance $token (export "get" (func (result u32)))))
+          (import "wasi:clocks/wall-clock@0.2.0" (instance))
+          (alias export $token "get" (func $get))
+          (export "get-token" (func $get)))
+    "#;
+
+    #[test]
+    fn test_interface_names() {
+        let (imports, exports) = interface_names(&wat::parse_str(COMPONENT).unwrap()).unwrap();
+        assert_eq!(
+            Vec::from_iter(imports),
+            ["acme:auth/token@1.0.0", "wasi:clocks/wall-clock@0.2.0"]
+        );
+        assert_eq!(Vec::from_iter(exports), ["get-token"]);
+
+        let module = wat::parse_str("(module)").unwrap();
+        assert!(interface_names(&module).is_err());
+    }
+
+    #[test]
+    fn test_compose_plugs_library_imports() {
+        let component = wat::parse_str(COMPONENT).unwrap();
+        let library = wat::parse_str(LIBRARY).unwrap();
+        let composed = compose(&component, &[("acme:auth".to_string(), library)]).unwrap();
+
+        let (imports, exports) = interface_names(&composed).unwrap();
+        assert_eq!(Vec::from_iter(imports), ["wasi:clocks/wall-clock@0.2.0"]);
+        assert_eq!(Vec::from_iter(exports), ["get-token"]);
+    }
+
+    #[test]
+    fn test_unused_library_is_refused() {
+        let component = wat::parse_str(COMPONENT).unwrap();
+        let unrelated = wat::parse_str("(component)").unwrap();
+        let err = compose(&component, &[("acme:other".to_string(), unrelated)]).unwrap_err();
+        assert!(err.to_string().contains("acme:other"));
+    }
+}
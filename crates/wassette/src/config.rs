@@ -4,25 +4,65 @@
 //! Builder and configuration helpers for constructing
 //! [`LifecycleManager`](crate::LifecycleManager).
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
 use crate::{
-    get_default_secrets_dir, LifecycleManager, DEFAULT_HTTP_TIMEOUT_SECS, DEFAULT_OCI_TIMEOUT_SECS,
+    get_default_secrets_dir, AnomalyConfig, BrowserConfig, CatalogConfig, FsHardening, HookConfig,
+    LifecycleManager, NetworkConfig, OutputConfig, OverrideCeiling, PermissionPrompts,
+    ProvenanceConfig, PullOptions, RegistryConfig, RuntimeSettings, SecretsBackendConfig,
+    SessionProfile, SignatureConfig, ToolNaming, ToolSettings, UpdateConfig, WasiCtxHook,
+    WasmProposal, DEFAULT_HTTP_TIMEOUT_SECS, DEFAULT_OCI_TIMEOUT_SECS, DEFAULT_WASM_PROPOSALS,
 };
 
+/// Most standby instances kept per latency-critical tool.
+const MAX_POOL_SIZE: usize = 64;
+
 /// Fully-specified configuration for constructing a [`LifecycleManager`].
 #[derive(Clone)]
 pub struct LifecycleConfig {
-    plugin_dir: PathBuf,
-    secrets_dir: PathBuf,
-    environment_vars: HashMap<String, String>,
-    http_client: reqwest::Client,
-    oci_client: oci_client::Client,
-    eager_load: bool,
+    pub(crate) plugin_dir: PathBuf,
+    pub(crate) secrets_dir: PathBuf,
+    pub(crate) secrets_backend: SecretsBackendConfig,
+    pub(crate) environment_vars: HashMap<String, String>,
+    pub(crate) http_client: reqwest::Client,
+    pub(crate) oci_client: oci_client::Client,
+    pub(crate) eager_load: bool,
+    pub(crate) coredump_on_trap: bool,
+    pub(crate) strict_policy: bool,
+    pub(crate) fs_hardening: FsHardening,
+    pub(crate) profile_guest: HashSet<String>,
+    pub(crate) workspace_components: HashSet<String>,
+    pub(crate) tool_naming: ToolNaming,
+    pub(crate) wasi_ctx_hooks: Vec<WasiCtxHook>,
+    pub(crate) output: OutputConfig,
+    pub(crate) pull_options: PullOptions,
+    pub(crate) registries: RegistryConfig,
+    pub(crate) signatures: SignatureConfig,
+    pub(crate) provenance: ProvenanceConfig,
+    pub(crate) libraries: HashMap<String, String>,
+    pub(crate) tools: HashMap<String, ToolSettings>,
+    pub(crate) max_concurrent_calls: usize,
+    pub(crate) call_timeout: Option<Duration>,
+    pub(crate) override_ceiling: OverrideCeiling,
+    pub(crate) hooks: Vec<HookConfig>,
+    pub(crate) session_profile: SessionProfile,
+    pub(crate) wasm_proposals: BTreeSet<WasmProposal>,
+    pub(crate) browser: Option<BrowserConfig>,
+    pub(crate) network: NetworkConfig,
+    pub(crate) watch: bool,
+    pub(crate) updates: Option<UpdateConfig>,
+    pub(crate) kept_versions: usize,
+    pub(crate) permission_prompts: PermissionPrompts,
+    pub(crate) anomaly_detection: Option<AnomalyConfig>,
+    pub(crate) catalog: CatalogConfig,
+    pub(crate) lockfile: PathBuf,
+    pub(crate) locked: bool,
+    pub(crate) runtime_settings: RuntimeSettings,
 }
 
 impl LifecycleConfig {
@@ -36,6 +76,11 @@ impl LifecycleConfig {
         &self.secrets_dir
     }
 
+    /// Backend component secrets are kept in.
+    pub fn secrets_backend(&self) -> &SecretsBackendConfig {
+        &self.secrets_backend
+    }
+
     /// Environment variables exposed to components.
     pub fn environment_vars(&self) -> &HashMap<String, String> {
         &self.environment_vars
@@ -56,24 +101,139 @@ impl LifecycleConfig {
         self.eager_load
     }
 
-    pub(crate) fn into_parts(
-        self,
-    ) -> (
-        PathBuf,
-        PathBuf,
-        HashMap<String, String>,
-        reqwest::Client,
-        oci_client::Client,
-        bool,
-    ) {
-        (
-            self.plugin_dir,
-            self.secrets_dir,
-            self.environment_vars,
-            self.http_client,
-            self.oci_client,
-            self.eager_load,
-        )
+    /// Whether a Wasm core dump is captured when a component traps.
+    pub fn coredump_on_trap(&self) -> bool {
+        self.coredump_on_trap
+    }
+
+    /// Whether policies with unknown or deprecated keys are rejected.
+    pub fn strict_policy(&self) -> bool {
+        self.strict_policy
+    }
+
+    /// How attempts to escape granted directories are handled.
+    pub fn fs_hardening(&self) -> FsHardening {
+        self.fs_hardening
+    }
+
+    /// Components whose invocations are sampled by the guest profiler.
+    pub fn profile_guest(&self) -> &HashSet<String> {
+        &self.profile_guest
+    }
+
+    /// Components granted read access to the client's workspace roots.
+    pub fn workspace_components(&self) -> &HashSet<String> {
+        &self.workspace_components
+    }
+
+    /// Tool namespacing and collision handling.
+    pub fn tool_naming(&self) -> &ToolNaming {
+        &self.tool_naming
+    }
+
+    /// Where component stdout and stderr go.
+    pub fn output(&self) -> &OutputConfig {
+        &self.output
+    }
+
+    /// Retry, timeout and mirror settings for OCI pulls.
+    pub fn pull_options(&self) -> &PullOptions {
+        &self.pull_options
+    }
+
+    /// Mirrors used in place of upstream registries.
+    pub fn registries(&self) -> &RegistryConfig {
+        &self.registries
+    }
+
+    /// Signers trusted for the components of each registry.
+    pub fn signatures(&self) -> &SignatureConfig {
+        &self.signatures
+    }
+
+    /// Publisher keys components are pinned to.
+    pub fn provenance(&self) -> &ProvenanceConfig {
+        &self.provenance
+    }
+
+    /// Locations of library components, keyed by the name manifests declare them by.
+    pub fn libraries(&self) -> &HashMap<String, String> {
+        &self.libraries
+    }
+
+    /// Runtime settings of individual tools, keyed by tool name.
+    pub fn tools(&self) -> &HashMap<String, ToolSettings> {
+        &self.tools
+    }
+
+    /// Tool calls that run at once when some tool has batch priority.
+    pub fn max_concurrent_calls(&self) -> usize {
+        self.max_concurrent_calls
+    }
+
+    /// How long a tool call may run, unless its tool's settings say otherwise.
+    pub fn call_timeout(&self) -> Option<Duration> {
+        self.call_timeout
+    }
+
+    /// Commands and webhooks run on component lifecycle events.
+    pub fn hooks(&self) -> &[HookConfig] {
+        &self.hooks
+    }
+
+    /// Locale and profile values shared with components granted the session context.
+    pub fn session_profile(&self) -> &SessionProfile {
+        &self.session_profile
+    }
+
+    /// Optional WebAssembly proposals the engine enables.
+    pub fn wasm_proposals(&self) -> &BTreeSet<WasmProposal> {
+        &self.wasm_proposals
+    }
+
+    /// Headless browser offered to components granted `browser`, if any.
+    pub fn browser(&self) -> Option<&BrowserConfig> {
+        self.browser.as_ref()
+    }
+
+    /// Proxy and trusted CAs of the outgoing HTTP requests of components.
+    pub fn network(&self) -> &NetworkConfig {
+        &self.network
+    }
+
+    /// Whether the files components are loaded from are watched for changes.
+    pub fn watch(&self) -> bool {
+        self.watch
+    }
+
+    /// Scheduled updates of components pinned by tag, if enabled.
+    pub fn updates(&self) -> Option<&UpdateConfig> {
+        self.updates.as_ref()
+    }
+
+    /// Earlier versions of each component kept to roll back to.
+    pub fn kept_versions(&self) -> usize {
+        self.kept_versions
+    }
+
+    /// Kinds of refusals the user is asked about during tool calls.
+    pub fn permission_prompts(&self) -> PermissionPrompts {
+        self.permission_prompts
+    }
+
+    /// Lockfile recording the resolved digests of loaded components.
+    pub fn lockfile(&self) -> &Path {
+        &self.lockfile
+    }
+
+    /// Whether components are refused unless they match the lockfile.
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Settings of the engine shared by every component.
+    pub fn runtime_settings(&self) -> &RuntimeSettings {
+        &self.runtime_settings
     }
 }
 
@@ -82,10 +242,42 @@ impl LifecycleConfig {
 pub struct LifecycleBuilder {
     plugin_dir: PathBuf,
     secrets_dir: Option<PathBuf>,
+    secrets_backend: SecretsBackendConfig,
     environment_vars: HashMap<String, String>,
     http_client: Option<reqwest::Client>,
     oci_client: Option<oci_client::Client>,
     eager_load: bool,
+    coredump_on_trap: bool,
+    strict_policy: bool,
+    fs_hardening: FsHardening,
+    profile_guest: HashSet<String>,
+    workspace_components: HashSet<String>,
+    tool_naming: ToolNaming,
+    wasi_ctx_hooks: Vec<WasiCtxHook>,
+    output: OutputConfig,
+    pull_options: PullOptions,
+    registries: RegistryConfig,
+    signatures: SignatureConfig,
+    provenance: ProvenanceConfig,
+    libraries: HashMap<String, String>,
+    tools: HashMap<String, ToolSettings>,
+    max_concurrent_calls: Option<usize>,
+    call_timeout: Option<Duration>,
+    override_ceiling: OverrideCeiling,
+    hooks: Vec<HookConfig>,
+    session_profile: SessionProfile,
+    wasm_proposals: BTreeSet<WasmProposal>,
+    browser: Option<BrowserConfig>,
+    network: NetworkConfig,
+    watch: bool,
+    updates: Option<UpdateConfig>,
+    kept_versions: usize,
+    permission_prompts: PermissionPrompts,
+    anomaly_detection: Option<AnomalyConfig>,
+    catalog: CatalogConfig,
+    lockfile: Option<PathBuf>,
+    locked: bool,
+    runtime_settings: RuntimeSettings,
 }
 
 impl LifecycleBuilder {
@@ -95,10 +287,42 @@ impl LifecycleBuilder {
         Self {
             plugin_dir,
             secrets_dir: None,
+            secrets_backend: SecretsBackendConfig::default(),
             environment_vars: HashMap::new(),
             http_client: None,
             oci_client: None,
             eager_load: true,
+            coredump_on_trap: false,
+            strict_policy: false,
+            fs_hardening: FsHardening::default(),
+            profile_guest: HashSet::new(),
+            workspace_components: HashSet::new(),
+            tool_naming: ToolNaming::default(),
+            wasi_ctx_hooks: Vec::new(),
+            output: OutputConfig::default(),
+            pull_options: PullOptions::default(),
+            registries: RegistryConfig::default(),
+            signatures: SignatureConfig::default(),
+            provenance: ProvenanceConfig::default(),
+            libraries: HashMap::new(),
+            tools: HashMap::new(),
+            max_concurrent_calls: None,
+            call_timeout: None,
+            override_ceiling: OverrideCeiling::default(),
+            hooks: Vec::new(),
+            session_profile: SessionProfile::default(),
+            wasm_proposals: DEFAULT_WASM_PROPOSALS.iter().copied().collect(),
+            browser: None,
+            network: NetworkConfig::default(),
+            watch: false,
+            updates: None,
+            kept_versions: crate::DEFAULT_KEPT_VERSIONS,
+            permission_prompts: PermissionPrompts::default(),
+            anomaly_detection: None,
+            catalog: CatalogConfig::default(),
+            lockfile: None,
+            locked: false,
+            runtime_settings: RuntimeSettings::default(),
         }
     }
 
@@ -124,6 +348,13 @@ impl LifecycleBuilder {
         self
     }
 
+    /// Keep component secrets in `backend` instead of files in the secrets directory. The
+    /// directory still holds runtime configuration.
+    pub fn with_secrets_backend(mut self, backend: SecretsBackendConfig) -> Self {
+        self.secrets_backend = backend;
+        self
+    }
+
     /// Override the HTTP client.
     pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
         self.http_client = Some(client);
@@ -142,6 +373,268 @@ impl LifecycleBuilder {
         self
     }
 
+    /// Capture a Wasm core dump whenever a component traps so it can be exported later with
+    /// [`LifecycleManager::get_coredump`](crate::LifecycleManager::get_coredump).
+    pub fn with_coredump_on_trap(mut self, enabled: bool) -> Self {
+        self.coredump_on_trap = enabled;
+        self
+    }
+
+    /// Reject component policies with unknown or deprecated keys instead of ignoring them, so
+    /// a misspelt key can't silently change a component's permissions.
+    pub fn with_strict_policy(mut self, enabled: bool) -> Self {
+        self.strict_policy = enabled;
+        self
+    }
+
+    /// Choose how paths escaping a component's granted directories through `..` or symlinks
+    /// are handled. Attempts are refused by default; either way they are recorded and can be
+    /// listed with
+    /// [`LifecycleManager::list_escape_attempts`](crate::LifecycleManager::list_escape_attempts).
+    pub fn with_fs_hardening(mut self, hardening: FsHardening) -> Self {
+        self.fs_hardening = hardening;
+        self
+    }
+
+    /// Enable Wasmtime's guest profiler for the given components. Every invocation writes a
+    /// profile to the `profiles` directory under the plugin directory.
+    pub fn with_guest_profiling<I, S>(mut self, component_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.profile_guest
+            .extend(component_ids.into_iter().map(Into::into));
+        self
+    }
+
+    /// Grant the given components read access to the client's workspace roots, as reported
+    /// to [`LifecycleManager::set_workspace_roots`](crate::LifecycleManager::set_workspace_roots).
+    pub fn with_workspace_components<I, S>(mut self, component_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.workspace_components
+            .extend(component_ids.into_iter().map(Into::into));
+        self
+    }
+
+    /// Configure the separator used for publisher namespaces and how tool name collisions
+    /// between components are resolved.
+    pub fn with_tool_naming(mut self, naming: ToolNaming) -> Self {
+        self.tool_naming = naming;
+        self
+    }
+
+    /// Configure where component stdout and stderr go. Streams are inherited from the server
+    /// process unless configured otherwise.
+    pub fn with_output(mut self, output: OutputConfig) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Configure how OCI pulls retry, how long a stalled download is waited for and which
+    /// mirrors are tried when the component's registry keeps failing.
+    pub fn with_pull_options(mut self, options: PullOptions) -> Self {
+        self.pull_options = options;
+        self
+    }
+
+    /// Configure internal mirrors or pull-through caches for upstream registries. Components
+    /// referencing e.g. `oci://ghcr.io/...` are pulled from the registry's mirrors first.
+    pub fn with_registries(mut self, registries: RegistryConfig) -> Self {
+        self.registries = registries;
+        self
+    }
+
+    /// Check the cosign signatures of OCI components against the keys and keyless identities
+    /// trusted for their registry. Components with a signature that fails to verify are
+    /// refused, and so are unsigned ones when `require_signed` is set.
+    pub fn with_signatures(mut self, signatures: SignatureConfig) -> Self {
+        self.signatures = signatures;
+        self
+    }
+
+    /// Pin components to the publisher keys that must sign their manifests. Pinned components
+    /// are refused unless installed from a digest-pinned manifest signed by one of their keys.
+    pub fn with_provenance(mut self, provenance: ProvenanceConfig) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Set where library components are fetched from, keyed by the name manifests declare them
+    /// by, instead of the locations in manifests, so shared libraries can be mirrored or
+    /// pinned centrally. Libraries missing here are fetched from their manifest's location.
+    pub fn with_libraries(mut self, libraries: HashMap<String, String>) -> Self {
+        self.libraries = libraries;
+        self
+    }
+
+    /// Set runtime settings for individual tools, keyed by tool name. Tools marked
+    /// `latency_critical` always have an instantiated instance of their component ready, so
+    /// calls skip instantiation at the cost of the memory of one idle instance each, or of
+    /// `pool_size` (see [`with_runtime_settings`](Self::with_runtime_settings)). Tools
+    /// with `batch` priority wait behind interactive calls and give up their execution slot
+    /// to them at epoch boundaries.
+    pub fn with_tool_settings(mut self, tools: HashMap<String, ToolSettings>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// Limit how many tool calls run at once when some tool has batch priority. Defaults to
+    /// the number of CPUs.
+    pub fn with_max_concurrent_calls(mut self, max_concurrent_calls: usize) -> Self {
+        self.max_concurrent_calls = Some(max_concurrent_calls);
+        self
+    }
+
+    /// Stop tool calls that run longer than `timeout` and fail them with a
+    /// [`CallTimedOut`](crate::CallTimedOut) error. A tool's `timeout_secs` setting replaces
+    /// it, and a component's policy can set a shorter `resources.limits.timeout`. Calls are
+    /// unlimited by default.
+    pub fn with_call_timeout(mut self, timeout: Duration) -> Self {
+        self.call_timeout = Some(timeout);
+        self
+    }
+
+    /// Let callers raise the timeout and memory limit of single calls up to `ceiling`,
+    /// through [`CallContext::with_overrides`](crate::CallContext::with_overrides). Overrides
+    /// are refused by default.
+    pub fn with_override_ceiling(mut self, ceiling: OverrideCeiling) -> Self {
+        self.override_ceiling = ceiling;
+        self
+    }
+
+    /// Run commands or webhooks on component load, unload, load errors and runtime permission
+    /// grants. Hooks receive the event as JSON and run in the background.
+    pub fn with_hooks(mut self, hooks: Vec<HookConfig>) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Share the user's locale and profile values with components whose policy grants
+    /// `context: read`, alongside the name and version of the calling client.
+    pub fn with_session_profile(mut self, profile: SessionProfile) -> Self {
+        self.session_profile = profile;
+        self
+    }
+
+    /// Enable exactly these optional WebAssembly proposals, replacing
+    /// [`DEFAULT_WASM_PROPOSALS`]. Only proposals that are
+    /// [configurable](WasmProposal::is_configurable) are accepted.
+    pub fn with_wasm_proposals(
+        mut self,
+        proposals: impl IntoIterator<Item = WasmProposal>,
+    ) -> Self {
+        self.wasm_proposals = proposals.into_iter().collect();
+        self
+    }
+
+    /// Offer components whose policy has a `browser` section a headless Chromium, which loads
+    /// pages of the sites the policy allows through the `wassette:browser/page` interface.
+    /// Without a browser those components get an error from every call.
+    pub fn with_browser(mut self, browser: BrowserConfig) -> Self {
+        self.browser = Some(browser);
+        self
+    }
+
+    /// Route the outgoing HTTP requests of components through an egress proxy, globally or
+    /// per component, and trust the CA certificates of a proxy that intercepts TLS. Network
+    /// policy still applies to each request's destination.
+    pub fn with_network(mut self, network: NetworkConfig) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Record the resolved digests of loaded components in `path` instead of
+    /// [`LOCKFILE_NAME`](crate::LOCKFILE_NAME) in the plugin directory.
+    pub fn with_lockfile(mut self, path: impl Into<PathBuf>) -> Self {
+        self.lockfile = Some(path.into());
+        self
+    }
+
+    /// Refuse `oci://` and `https://` components whose id, URI or digest don't match an entry
+    /// of the lockfile, which is then left unchanged. The lockfile must exist.
+    pub fn with_locked(mut self, locked: bool) -> Self {
+        self.locked = locked;
+        self
+    }
+
+    /// Watch the files components are loaded from with `file://` URIs, so
+    /// [`LifecycleManager::watch_components`](crate::LifecycleManager::watch_components) can
+    /// reload them when they change.
+    pub fn with_watch(mut self, enabled: bool) -> Self {
+        self.watch = enabled;
+        self
+    }
+
+    /// Update components loaded from OCI tags or installed from manifest URLs in a daily
+    /// maintenance window, run by
+    /// [`LifecycleManager::run_scheduled_updates`](crate::LifecycleManager::run_scheduled_updates).
+    pub fn with_updates(mut self, updates: UpdateConfig) -> Self {
+        self.updates = Some(updates);
+        self
+    }
+
+    /// Keep the last `kept_versions` versions of each component that loads replaced, so
+    /// [`LifecycleManager::rollback_component`](crate::LifecycleManager::rollback_component) can
+    /// put them back; none are kept with zero. Five by default.
+    pub fn with_kept_versions(mut self, kept_versions: usize) -> Self {
+        self.kept_versions = kept_versions;
+        self
+    }
+
+    /// Ask the user, through the [`PermissionPrompter`](crate::PermissionPrompter) of each
+    /// call, before refusing the kinds of permissions in `prompts`, and grant those they
+    /// approve. No one is asked by default.
+    pub fn with_permission_prompts(mut self, prompts: PermissionPrompts) -> Self {
+        self.permission_prompts = prompts;
+        self
+    }
+
+    /// Learn the usual calls of every component and report calls that deviate sharply from
+    /// them, in the audit log and to
+    /// [`LifecycleManager::subscribe_anomalies`](crate::LifecycleManager::subscribe_anomalies).
+    /// Off by default.
+    pub fn with_anomaly_detection(mut self, config: AnomalyConfig) -> Self {
+        self.anomaly_detection = Some(config);
+        self
+    }
+
+    /// Search these catalog indexes in
+    /// [`LifecycleManager::search_catalog`](crate::LifecycleManager::search_catalog). By default
+    /// only the catalog bundled with wassette is searched.
+    pub fn with_catalog(mut self, catalog: CatalogConfig) -> Self {
+        self.catalog = catalog;
+        self
+    }
+
+    /// Configure the engine shared by every component. A `pool_size` allocates instances from
+    /// Wasmtime's pooling allocator and keeps that many standby instances ready for each
+    /// latency-critical tool (see [`with_tool_settings`](Self::with_tool_settings)), for tools
+    /// called faster than a single standby can be replaced. `validation_threads` moves the
+    /// validation and JSON conversion of tool call arguments and results from the async
+    /// runtime to a dedicated pool, each conversion bounded by `validation_budget_ms`.
+    pub fn with_runtime_settings(mut self, settings: RuntimeSettings) -> Self {
+        self.runtime_settings = settings;
+        self
+    }
+
+    /// Register a hook that customizes each component's [`WasiCtxBuilder`] before
+    /// instantiation, e.g. to add preopens, redirect stdout or set extra environment variables.
+    /// Hooks run in registration order and receive the component id; the component's policy
+    /// is still applied on top of their changes (see [`WasiCtxHook`]).
+    ///
+    /// [`WasiCtxBuilder`]: wasmtime_wasi::WasiCtxBuilder
+    pub fn with_wasi_ctx_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, &mut wasmtime_wasi::WasiCtxBuilder) -> Result<()> + Send + Sync + 'static,
+    {
+        self.wasi_ctx_hooks.push(Arc::new(hook));
+        self
+    }
+
     /// Produce a validated [`LifecycleConfig`] without constructing a manager.
     pub fn build_config(self) -> Result<LifecycleConfig> {
         let plugin_dir = match self.plugin_dir.canonicalize() {
@@ -151,6 +644,79 @@ impl LifecycleBuilder {
 
         let secrets_dir = self.secrets_dir.unwrap_or_else(get_default_secrets_dir);
 
+        self.output
+            .validate()
+            .context("Invalid component output configuration")?;
+        self.registries
+            .validate()
+            .context("Invalid registry configuration")?;
+        self.signatures
+            .validate()
+            .context("Invalid signature configuration")?;
+        self.provenance
+            .validate()
+            .context("Invalid provenance configuration")?;
+        for hook in &self.hooks {
+            hook.validate().context("Invalid hook configuration")?;
+        }
+        self.session_profile
+            .validate()
+            .context("Invalid session profile")?;
+        crate::proposals::validate(&self.wasm_proposals).context("Invalid wasm_proposals")?;
+        if let Some(browser) = &self.browser {
+            browser
+                .validate()
+                .context("Invalid browser configuration")?;
+        }
+        if let Some(updates) = &self.updates {
+            updates.validate().context("Invalid update configuration")?;
+        }
+        if let Some(anomaly_detection) = &self.anomaly_detection {
+            anomaly_detection
+                .validate()
+                .context("Invalid anomaly detection configuration")?;
+        }
+        self.catalog
+            .validate()
+            .context("Invalid catalog configuration")?;
+        self.secrets_backend
+            .validate()
+            .context("Invalid secrets backend configuration")?;
+        if let Some((name, location)) = self
+            .libraries
+            .iter()
+            .find(|(_, location)| !location.starts_with("oci://"))
+        {
+            bail!("Library {name} must be an oci:// reference, got: {location}");
+        }
+        if self.max_concurrent_calls == Some(0) {
+            bail!("At least one tool call must be allowed to run at once");
+        }
+        if let Some(pool_size) = self.runtime_settings.pool_size {
+            if !(1..=MAX_POOL_SIZE).contains(&pool_size) {
+                bail!("The pool size must be between 1 and {MAX_POOL_SIZE}");
+            }
+        }
+        if self.runtime_settings.validation_threads == Some(0) {
+            bail!("At least one validation thread is needed");
+        }
+        if self.runtime_settings.validation_budget_ms == Some(0) {
+            bail!("The validation budget must be longer than zero");
+        }
+        self.override_ceiling
+            .validate()
+            .context("Invalid call override ceiling")?;
+        if self.call_timeout == Some(Duration::ZERO) {
+            bail!("The call timeout must be longer than zero");
+        }
+        if let Some((tool, _)) = self
+            .tools
+            .iter()
+            .find(|(_, settings)| settings.timeout_secs == Some(0))
+        {
+            bail!("The call timeout of tool {tool} must be at least one second");
+        }
+
         let http_client = match self.http_client {
             Some(client) => client,
             None => default_http_client()?,
@@ -161,13 +727,49 @@ impl LifecycleBuilder {
             None => default_oci_client()?,
         };
 
+        let lockfile = self
+            .lockfile
+            .unwrap_or_else(|| plugin_dir.join(crate::LOCKFILE_NAME));
+
         Ok(LifecycleConfig {
             plugin_dir,
             secrets_dir,
+            secrets_backend: self.secrets_backend,
             environment_vars: self.environment_vars,
             http_client,
             oci_client,
             eager_load: self.eager_load,
+            coredump_on_trap: self.coredump_on_trap,
+            strict_policy: self.strict_policy,
+            fs_hardening: self.fs_hardening,
+            profile_guest: self.profile_guest,
+            workspace_components: self.workspace_components,
+            tool_naming: self.tool_naming,
+            wasi_ctx_hooks: self.wasi_ctx_hooks,
+            output: self.output,
+            pull_options: self.pull_options,
+            registries: self.registries,
+            signatures: self.signatures,
+            provenance: self.provenance,
+            libraries: self.libraries,
+            tools: self.tools,
+            max_concurrent_calls: self.max_concurrent_calls.unwrap_or_else(num_cpus::get),
+            call_timeout: self.call_timeout,
+            override_ceiling: self.override_ceiling,
+            hooks: self.hooks,
+            session_profile: self.session_profile,
+            wasm_proposals: self.wasm_proposals,
+            browser: self.browser,
+            network: self.network,
+            watch: self.watch,
+            updates: self.updates,
+            kept_versions: self.kept_versions,
+            permission_prompts: self.permission_prompts,
+            anomaly_detection: self.anomaly_detection,
+            catalog: self.catalog,
+            lockfile,
+            locked: self.locked,
+            runtime_settings: self.runtime_settings,
         })
     }
 
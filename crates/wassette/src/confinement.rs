@@ -0,0 +1,148 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Host resources granted by the stored component policies.
+//!
+//! Operating system confinement (AppArmor, SELinux) applies to the whole wassette process, so
+//! a profile has to allow the union of what every component may reach. This collects that
+//! union from the policy files in the plugin directory, resolving paths the same way
+//! [`create_wasi_state_template_from_policy`](crate::create_wasi_state_template_from_policy)
+//! does.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use policy::PolicyParser;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::wasistate::{expand_home, extract_allowed_hosts, extract_storage_permissions};
+
+/// Suffix of the policy files stored next to components.
+const POLICY_SUFFIX: &str = ".policy.yaml";
+
+/// The union of the host resources all stored policies grant.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GrantedAccess {
+    /// Host directories granted to components, and whether any component may write to them
+    pub directories: BTreeMap<PathBuf, bool>,
+    /// Host archives and files mounted read-only into components
+    pub mounted_files: BTreeSet<PathBuf>,
+    /// Network hosts components may connect to
+    pub hosts: BTreeSet<String>,
+    /// Whether any component may send email, over the SMTP server named in its secrets
+    pub email: bool,
+}
+
+/// Collect the grants of every policy file in `plugin_dir`. Policies that don't parse are
+/// skipped, as they are when components load.
+pub(crate) async fn granted_access(plugin_dir: &Path) -> Result<GrantedAccess> {
+    let mut access = GrantedAccess::default();
+    let mut entries = tokio::fs::read_dir(plugin_dir)
+        .await
+        .with_context(|| format!("Failed to read {}", plugin_dir.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_policy = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(POLICY_SUFFIX));
+        if !is_policy {
+            continue;
+        }
+
+        let content = tokio::fs::read_to_string(&path).await?;
+        let policy = match PolicyParser::parse_str(&content) {
+            Ok(policy) => policy,
+            Err(error) => {
+                warn!(path = %path.display(), %error, "Skipping unparsable policy");
+                continue;
+            }
+        };
+
+        for dir in extract_storage_permissions(&policy, plugin_dir)? {
+            let writable = dir.dir_perms.contains(wasmtime_wasi::DirPerms::MUTATE);
+            *access.directories.entry(dir.host_path).or_default() |= writable;
+        }
+        for mount in policy.permissions.mounts.iter().flatten() {
+            for source in [&mount.archive, &mount.file].into_iter().flatten() {
+                access
+                    .mounted_files
+                    .insert(plugin_dir.join(expand_home(source)));
+            }
+        }
+        access.hosts.extend(extract_allowed_hosts(&policy));
+        access.email |= policy.permissions.email.is_some();
+    }
+    Ok(access)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_granted_access_merges_policies() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let dir = tempdir.path();
+        tokio::fs::write(
+            dir.join("fetch.policy.yaml"),
+            r#"
+version: "1.0"
+permissions:
+  network:
+    allow:
+      - host: "api.example.com"
+  storage:
+    allow:
+      - uri: "fs:///srv/data"
+        access: ["read"]
+  mounts:
+    - archive: "/srv/models.tar"
+      at: /models
+"#,
+        )
+        .await?;
+        tokio::fs::write(
+            dir.join("notes.policy.yaml"),
+            r#"
+version: "1.0"
+permissions:
+  network:
+    allow:
+      - host: "github.com"
+  email:
+    allow:
+      - domain: "example.com"
+  storage:
+    allow:
+      - uri: "fs:///srv/data"
+        access: ["read", "write"]
+      - uri: "fs:///srv/notes/**/*.md"
+        access: ["read"]
+"#,
+        )
+        .await?;
+        tokio::fs::write(dir.join("broken.policy.yaml"), "permissions: [").await?;
+
+        let access = granted_access(dir).await?;
+        assert_eq!(
+            access.directories,
+            BTreeMap::from([
+                (PathBuf::from("/srv/data"), true),
+                (PathBuf::from("/srv/notes"), false),
+            ])
+        );
+        assert_eq!(
+            access.mounted_files,
+            BTreeSet::from([PathBuf::from("/srv/models.tar")])
+        );
+        assert_eq!(
+            access.hosts,
+            BTreeSet::from(["api.example.com".to_string(), "github.com".to_string()])
+        );
+        assert!(access.email);
+        Ok(())
+    }
+}
@@ -0,0 +1,749 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Checking the tools of a component against an API contract.
+//!
+//! Teams generating components from an API definition need to know when a component drifts
+//! from it. A contract is either an OpenAPI 3 document, whose operations are matched to tools
+//! by `operationId`, or a list of tool schemas shaped like the result of MCP's `tools/list`
+//! (`tools: [{name, inputSchema, outputSchema}]`). Names of tools and properties are compared
+//! ignoring case and punctuation, so `getWeather`, `get_weather` and `get-weather` match.
+//!
+//! The input of an operation is its path and query parameters plus the properties of its JSON
+//! request body, or a `body` property when the body isn't an object. Its output is the JSON
+//! body of its first success response, which is compared with what a tool returns on success:
+//! the `ok` case of a tool returning a `result`. Local `$ref`s are inlined. Schemas are compared
+//! by type (`integer` and `number` are the same, since components describe both as `number`),
+//! properties, which properties are required, array items and enum values. Other keywords,
+//! like formats and patterns, aren't compared.
+
+use std::collections::HashSet;
+
+use anyhow::{bail, Context, Result};
+use component2json::ToolMetadata;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// Nested `$ref`s followed before giving up on a (likely cyclic) reference.
+const MAX_REF_DEPTH: usize = 32;
+
+/// HTTP methods of OpenAPI operations.
+const METHODS: &[&str] = &[
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// Responses of an OpenAPI operation holding its result, in order of preference.
+const SUCCESS_RESPONSES: &[&str] = &["200", "201", "202", "2XX", "default"];
+
+/// A tool described by a contract.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractTool {
+    /// Name of the tool, or `operationId` of the operation
+    pub name: String,
+    /// Schema of the tool's arguments
+    pub input: Value,
+    /// Schema of what the tool returns on success, if the contract describes it
+    pub output: Option<Value>,
+}
+
+/// An API contract components are checked against.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contract {
+    /// The tools the contract describes
+    pub tools: Vec<ContractTool>,
+}
+
+impl Contract {
+    /// Parse an OpenAPI 3 document or a list of tool schemas, in YAML or JSON.
+    pub fn parse(document: &str) -> Result<Self> {
+        let document: Value =
+            serde_yaml::from_str(document).context("The contract is neither YAML nor JSON")?;
+        if document.get("openapi").is_some() {
+            from_openapi(&document)
+        } else if let Some(tools) = document.get("tools").and_then(Value::as_array) {
+            from_tool_list(&document, tools)
+        } else if document.get("swagger").is_some() {
+            bail!("Swagger 2.0 documents aren't supported, convert the contract to OpenAPI 3")
+        } else {
+            bail!("Expected an OpenAPI 3 document or a list of tool schemas under `tools`")
+        }
+    }
+}
+
+/// How a component differs from its contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MismatchKind {
+    /// The contract describes a tool the component doesn't export
+    MissingTool,
+    /// The component exports a tool the contract doesn't describe
+    ExtraTool,
+    /// The contract describes a property the component's schema lacks
+    MissingProperty,
+    /// The component's schema has a property the contract doesn't describe
+    ExtraProperty,
+    /// A property is required on one side and optional on the other
+    Required,
+    /// The types differ
+    Type,
+    /// The contract allows enum values the component doesn't
+    Enum,
+    /// The contract describes a result the component doesn't return
+    MissingOutput,
+}
+
+/// A difference between a component and its contract.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Mismatch {
+    /// Tool the difference is in, named as in the contract unless it is only in the component
+    pub tool: String,
+    /// What differs
+    pub kind: MismatchKind,
+    /// Where in the tool, e.g. `input.city` or `output.days[]`; empty for whole tools
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub path: String,
+    /// Description of the difference
+    pub message: String,
+}
+
+/// Outcome of checking a component against a contract.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ContractReport {
+    /// Tools both the contract and the component have, named as in the contract
+    pub matched: Vec<String>,
+    /// Differences between them
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl ContractReport {
+    /// Whether the component matches the contract.
+    pub fn is_compatible(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Check the tools of a component against `contract`.
+pub(crate) fn check(contract: &Contract, tools: &[ToolMetadata]) -> ContractReport {
+    let mut report = ContractReport::default();
+    let mut matched_tools = HashSet::new();
+    for expected in &contract.tools {
+        let name = key(&expected.name);
+        let found = tools.iter().position(|tool| {
+            key(&tool.identifier.function_name) == name || key(&tool.normalized_name) == name
+        });
+        let mut comparison = Comparison {
+            tool: &expected.name,
+            mismatches: &mut report.mismatches,
+        };
+        let Some(index) = found else {
+            comparison.report(
+                MismatchKind::MissingTool,
+                "",
+                "the component exports no matching function".to_string(),
+            );
+            continue;
+        };
+        matched_tools.insert(index);
+        report.matched.push(expected.name.clone());
+
+        let schema = &tools[index].schema;
+        comparison.compare("input", &expected.input, &schema["inputSchema"]);
+        match (&expected.output, schema.get("outputSchema")) {
+            (Some(expected), Some(actual)) => {
+                comparison.compare("output", expected, success_schema(result_schema(actual)))
+            }
+            (Some(_), None) => comparison.report(
+                MismatchKind::MissingOutput,
+                "output",
+                "the contract describes a result, the function returns nothing".to_string(),
+            ),
+            (None, _) => {}
+        }
+    }
+    for (index, tool) in tools.iter().enumerate() {
+        if !matched_tools.contains(&index) {
+            report.mismatches.push(Mismatch {
+                tool: tool.normalized_name.clone(),
+                kind: MismatchKind::ExtraTool,
+                path: String::new(),
+                message: "the contract doesn't describe this tool".to_string(),
+            });
+        }
+    }
+    report
+}
+
+/// The differences found between the schemas of one tool.
+struct Comparison<'a> {
+    tool: &'a str,
+    mismatches: &'a mut Vec<Mismatch>,
+}
+
+impl Comparison<'_> {
+    fn report(&mut self, kind: MismatchKind, path: &str, message: String) {
+        self.mismatches.push(Mismatch {
+            tool: self.tool.to_string(),
+            kind,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn compare(&mut self, path: &str, expected: &Value, actual: &Value) {
+        let (expected, actual) = (non_null(expected), non_null(actual));
+        if let (Some(expected_type), Some(actual_type)) =
+            (schema_type(expected), schema_type(actual))
+        {
+            if expected_type != actual_type {
+                self.report(
+                    MismatchKind::Type,
+                    path,
+                    format!(
+                        "the contract expects {expected_type}, the component has {actual_type}"
+                    ),
+                );
+                return;
+            }
+        }
+
+        if let Some(expected_properties) = expected.get("properties").and_then(Value::as_object) {
+            let empty = Map::new();
+            let actual_properties = actual
+                .get("properties")
+                .and_then(Value::as_object)
+                .unwrap_or(&empty);
+            let (expected_required, actual_required) = (required(expected), required(actual));
+            for (name, expected_property) in expected_properties {
+                let property = format!("{path}.{name}");
+                let Some((actual_name, actual_property)) = actual_properties
+                    .iter()
+                    .find(|(actual_name, _)| key(actual_name) == key(name))
+                else {
+                    self.report(
+                        MismatchKind::MissingProperty,
+                        &property,
+                        "missing from the component".to_string(),
+                    );
+                    continue;
+                };
+                match (
+                    expected_required.contains(&key(name)),
+                    actual_required.contains(&key(actual_name)),
+                ) {
+                    (true, false) => self.report(
+                        MismatchKind::Required,
+                        &property,
+                        "required by the contract, optional in the component".to_string(),
+                    ),
+                    (false, true) => self.report(
+                        MismatchKind::Required,
+                        &property,
+                        "optional in the contract, required by the component".to_string(),
+                    ),
+                    _ => {}
+                }
+                self.compare(&property, expected_property, actual_property);
+            }
+            for actual_name in actual_properties.keys() {
+                if !expected_properties
+                    .keys()
+                    .any(|name| key(name) == key(actual_name))
+                {
+                    self.report(
+                        MismatchKind::ExtraProperty,
+                        &format!("{path}.{actual_name}"),
+                        "not in the contract".to_string(),
+                    );
+                }
+            }
+        }
+
+        if let (Some(expected_items), Some(actual_items)) =
+            (expected.get("items"), actual.get("items"))
+        {
+            self.compare(&format!("{path}[]"), expected_items, actual_items);
+        }
+
+        if let (Some(expected_values), Some(actual_values)) = (
+            expected.get("enum").and_then(Value::as_array),
+            actual.get("enum").and_then(Value::as_array),
+        ) {
+            let missing: Vec<String> = expected_values
+                .iter()
+                .filter(|value| !actual_values.contains(value))
+                .map(Value::to_string)
+                .collect();
+            if !missing.is_empty() {
+                self.report(
+                    MismatchKind::Enum,
+                    path,
+                    format!("the component doesn't accept {}", missing.join(", ")),
+                );
+            }
+        }
+    }
+}
+
+/// `name` without case and punctuation.
+fn key(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// The type `schema` declares, with `integer` as `number` and `null` left out.
+fn schema_type(schema: &Value) -> Option<&str> {
+    let declared = match schema.get("type")? {
+        Value::String(declared) => declared.as_str(),
+        Value::Array(types) => {
+            let mut types = types
+                .iter()
+                .filter_map(Value::as_str)
+                .filter(|declared| *declared != "null");
+            let declared = types.next()?;
+            if types.next().is_some() {
+                return None;
+            }
+            declared
+        }
+        _ => return None,
+    };
+    Some(if declared == "integer" {
+        "number"
+    } else {
+        declared
+    })
+}
+
+/// `schema` without the alternative of being null: the value of an `option`, or of a nullable
+/// OpenAPI schema.
+fn non_null(schema: &Value) -> &Value {
+    for combinator in ["anyOf", "oneOf"] {
+        if let Some(alternatives) = schema.get(combinator).and_then(Value::as_array) {
+            let values: Vec<&Value> = alternatives
+                .iter()
+                .filter(|alternative| alternative.get("type") != Some(&json!("null")))
+                .collect();
+            if let [value] = values.as_slice() {
+                if alternatives.len() > 1 {
+                    return value;
+                }
+            }
+        }
+    }
+    schema
+}
+
+/// Keys of the properties `schema` requires.
+fn required(schema: &Value) -> HashSet<String> {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(key)
+        .collect()
+}
+
+/// The schema of the `result` an output schema wraps the returned value in.
+fn result_schema(output: &Value) -> &Value {
+    match output.get("properties").and_then(Value::as_object) {
+        Some(properties) if properties.len() == 1 => properties.get("result").unwrap_or(output),
+        _ => output,
+    }
+}
+
+/// The schema of the `ok` case of a `result`, or `schema` when it isn't one.
+fn success_schema(schema: &Value) -> &Value {
+    let cases = schema.get("oneOf").and_then(Value::as_array);
+    let ok = cases.and_then(|cases| match cases.as_slice() {
+        [ok, err] if err["properties"].get("err").is_some() => ok["properties"].get("ok"),
+        _ => None,
+    });
+    ok.unwrap_or(schema)
+}
+
+/// Follow `value` to what it refers to, if it is a local `$ref`.
+fn resolve<'a>(document: &'a Value, mut value: &'a Value) -> &'a Value {
+    for _ in 0..MAX_REF_DEPTH {
+        let Some(target) = value
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|reference| reference.strip_prefix('#'))
+            .and_then(|pointer| document.pointer(pointer))
+        else {
+            break;
+        };
+        value = target;
+    }
+    value
+}
+
+/// `schema` with its local `$ref`s replaced by what they refer to.
+fn inline(document: &Value, schema: &Value) -> Value {
+    inline_at(document, schema, 0)
+}
+
+fn inline_at(document: &Value, schema: &Value, depth: usize) -> Value {
+    if depth == MAX_REF_DEPTH {
+        return schema.clone();
+    }
+    match resolve(document, schema) {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(name, value)| (name.clone(), inline_at(document, value, depth + 1)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(
+            values
+                .iter()
+                .map(|value| inline_at(document, value, depth + 1))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// The schema of the JSON in `content`, the content map of an OpenAPI body.
+fn json_content(content: Option<&Value>) -> Option<&Value> {
+    let content = content?.as_object()?;
+    let media = content.get("application/json").or_else(|| {
+        content
+            .iter()
+            .find(|(media_type, _)| media_type.contains("json"))
+            .map(|(_, media)| media)
+    })?;
+    media.get("schema")
+}
+
+fn from_openapi(document: &Value) -> Result<Contract> {
+    let version = document["openapi"].as_str().unwrap_or_default();
+    if !version.starts_with("3.") {
+        bail!("Unsupported OpenAPI version {version:?}, expected 3.x");
+    }
+    let paths = document
+        .get("paths")
+        .and_then(Value::as_object)
+        .context("The OpenAPI document has no paths")?;
+    let mut tools = Vec::new();
+    for item in paths.values() {
+        let item = resolve(document, item);
+        let shared_parameters = item.get("parameters").and_then(Value::as_array);
+        for method in METHODS {
+            let Some(operation) = item.get(method) else {
+                continue;
+            };
+            // Operations are matched to tools by id.
+            let Some(name) = operation.get("operationId").and_then(Value::as_str) else {
+                continue;
+            };
+            let mut properties = Map::new();
+            let mut required = Vec::new();
+            let parameters = shared_parameters
+                .into_iter()
+                .chain(operation.get("parameters").and_then(Value::as_array))
+                .flatten();
+            for parameter in parameters {
+                let parameter = resolve(document, parameter);
+                if !matches!(parameter["in"].as_str(), Some("path" | "query")) {
+                    continue;
+                }
+                let Some(parameter_name) = parameter["name"].as_str() else {
+                    continue;
+                };
+                let schema = parameter.get("schema").unwrap_or(&Value::Null);
+                properties.insert(parameter_name.to_string(), inline(document, schema));
+                if parameter["required"].as_bool() == Some(true) {
+                    required.push(json!(parameter_name));
+                }
+            }
+            if let Some(body) = operation.get("requestBody") {
+                let body = resolve(document, body);
+                if let Some(schema) = json_content(body.get("content")) {
+                    let schema = inline(document, schema);
+                    let body_required = body["required"].as_bool() == Some(true);
+                    match schema.get("properties").and_then(Value::as_object) {
+                        Some(body_properties) => {
+                            properties.extend(body_properties.clone());
+                            if body_required {
+                                required.extend(
+                                    schema["required"].as_array().cloned().unwrap_or_default(),
+                                );
+                            }
+                        }
+                        None => {
+                            properties.insert("body".to_string(), schema);
+                            if body_required {
+                                required.push(json!("body"));
+                            }
+                        }
+                    }
+                }
+            }
+            let output = SUCCESS_RESPONSES
+                .iter()
+                .find_map(|status| operation.get("responses")?.get(status))
+                .and_then(|response| json_content(resolve(document, response).get("content")))
+                .map(|schema| inline(document, schema));
+            tools.push(ContractTool {
+                name: name.to_string(),
+                input: json!({
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                }),
+                output,
+            });
+        }
+    }
+    Ok(Contract { tools })
+}
+
+fn from_tool_list(document: &Value, tools: &[Value]) -> Result<Contract> {
+    let tools = tools
+        .iter()
+        .map(|tool| {
+            let name = tool
+                .get("name")
+                .and_then(Value::as_str)
+                .context("Every tool of the contract needs a name")?;
+            Ok(ContractTool {
+                name: name.to_string(),
+                input: tool
+                    .get("inputSchema")
+                    .map(|schema| inline(document, schema))
+                    .unwrap_or_else(|| json!({ "type": "object" })),
+                output: tool
+                    .get("outputSchema")
+                    .map(|schema| inline(document, result_schema(schema))),
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(Contract { tools })
+}
+
+#[cfg(test)]
+mod tests {
+    use component2json::FunctionIdentifier;
+
+    use super::*;
+
+    const OPENAPI: &str = r##"
+openapi: 3.0.3
+info: { title: Weather, version: "1" }
+paths:
+  /forecast/{city}:
+    parameters:
+      - { name: city, in: path, required: true, schema: { type: string } }
+    get:
+      operationId: getForecast
+      parameters:
+        - { name: days, in: query, schema: { type: integer } }
+        - { name: units, in: query, schema: { $ref: "#/components/schemas/Units" } }
+        - { name: X-Trace, in: header, schema: { type: string } }
+      responses:
+        200:
+          description: The forecast
+          content:
+            application/json:
+              schema: { $ref: "#/components/schemas/Forecast" }
+  /alerts:
+    post:
+      operationId: subscribe_alerts
+      requestBody:
+        required: true
+        content:
+          application/json:
+            schema:
+              type: object
+              required: [email]
+              properties:
+                email: { type: string }
+      responses:
+        "201": { description: Subscribed }
+  /health:
+    get:
+      responses:
+        "200": { description: No id, so not a tool }
+components:
+  schemas:
+    Units: { type: string, enum: [metric, imperial, kelvin] }
+    Forecast:
+      type: object
+      properties:
+        days:
+          type: array
+          items:
+            type: object
+            properties:
+              high: { type: number }
+              summary: { type: string }
+"##;
+
+    fn tool(function_name: &str, schema: Value) -> ToolMetadata {
+        ToolMetadata {
+            identifier: FunctionIdentifier {
+                package_name: Some("example:weather".to_string()),
+                interface_name: None,
+                function_name: function_name.to_string(),
+            },
+            normalized_name: format!("example_weather_{}", function_name.replace('-', "_")),
+            schema,
+        }
+    }
+
+    #[test]
+    fn test_openapi_operations_become_tools() -> Result<()> {
+        let contract = Contract::parse(OPENAPI)?;
+        // In the order of their paths
+        let names: Vec<_> = contract
+            .tools
+            .iter()
+            .map(|tool| tool.name.as_str())
+            .collect();
+        assert_eq!(names, ["subscribe_alerts", "getForecast"]);
+
+        let forecast = &contract.tools[1];
+        assert_eq!(forecast.input["required"], json!(["city"]));
+        let properties = forecast.input["properties"].as_object().unwrap();
+        assert_eq!(
+            properties.keys().collect::<Vec<_>>(),
+            ["city", "days", "units"]
+        );
+        assert_eq!(properties["units"]["enum"][2], "kelvin");
+        let output = forecast.output.as_ref().unwrap();
+        assert_eq!(output["properties"]["days"]["items"]["type"], "object");
+
+        let alerts = &contract.tools[0];
+        assert_eq!(alerts.input["required"], json!(["email"]));
+        assert_eq!(alerts.output, None);
+
+        assert!(Contract::parse("swagger: '2.0'").is_err());
+        assert!(Contract::parse("title: nothing").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_is_checked_against_the_contract() -> Result<()> {
+        let contract = Contract::parse(OPENAPI)?;
+        let tools = [
+            tool(
+                "get-forecast",
+                json!({
+                    "name": "example_weather_get_forecast",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {
+                            "city": { "type": "string" },
+                            "days": { "type": "number" },
+                            "units": { "type": "string", "enum": ["metric", "imperial"] },
+                            "lang": { "type": "string" }
+                        },
+                        "required": ["city", "days", "units", "lang"]
+                    },
+                    "outputSchema": {
+                        "type": "object",
+                        "properties": {
+                            "result": {
+                                "oneOf": [
+                                    {
+                                        "type": "object",
+                                        "properties": {
+                                            "ok": {
+                                                "type": "object",
+                                                "properties": {
+                                                    "days": {
+                                                        "type": "array",
+                                                        "items": {
+                                                            "type": "object",
+                                                            "properties": {
+                                                                "high": { "type": "string" },
+                                                                "summary": {
+                                                                    "anyOf": [
+                                                                        { "type": "null" },
+                                                                        { "type": "string" }
+                                                                    ]
+                                                                }
+                                                            },
+                                                            "required": ["high"]
+                                                        }
+                                                    }
+                                                },
+                                                "required": ["days"]
+                                            }
+                                        },
+                                        "required": ["ok"]
+                                    },
+                                    {
+                                        "type": "object",
+                                        "properties": { "err": { "type": "string" } },
+                                        "required": ["err"]
+                                    }
+                                ]
+                            }
+                        },
+                        "required": ["result"]
+                    }
+                }),
+            ),
+            tool(
+                "reset",
+                json!({ "name": "example_weather_reset", "inputSchema": { "type": "object" } }),
+            ),
+        ];
+
+        let report = check(&contract, &tools);
+        assert_eq!(report.matched, ["getForecast"]);
+        let found: Vec<_> = report
+            .mismatches
+            .iter()
+            .map(|mismatch| {
+                (
+                    mismatch.tool.as_str(),
+                    mismatch.kind,
+                    mismatch.path.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("subscribe_alerts", MismatchKind::MissingTool, ""),
+                ("getForecast", MismatchKind::Required, "input.days"),
+                ("getForecast", MismatchKind::Required, "input.units"),
+                ("getForecast", MismatchKind::Enum, "input.units"),
+                ("getForecast", MismatchKind::ExtraProperty, "input.lang"),
+                ("getForecast", MismatchKind::Required, "output.days"),
+                ("getForecast", MismatchKind::Required, "output.days[].high"),
+                ("getForecast", MismatchKind::Type, "output.days[].high"),
+                ("example_weather_reset", MismatchKind::ExtraTool, ""),
+            ]
+        );
+        assert!(!report.is_compatible());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tool_list_contracts() -> Result<()> {
+        let schema = json!({
+            "name": "example_weather_reset",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "hard": { "type": "boolean" } },
+                "required": ["hard"]
+            },
+            "outputSchema": {
+                "type": "object",
+                "properties": { "result": { "type": "string" } },
+                "required": ["result"]
+            }
+        });
+        let contract = Contract::parse(&json!({ "tools": [schema] }).to_string())?;
+        let report = check(&contract, &[tool("reset", schema)]);
+        assert_eq!(report.matched, ["example_weather_reset"]);
+        assert!(report.is_compatible(), "{:?}", report.mismatches);
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Capture and retrieval of Wasm core dumps produced when a component traps.
+//!
+//! Each dump is stored under the plugin directory as `<call-id>.coredump` together with a
+//! `<call-id>.json` record describing the failed invocation, so component authors can later
+//! export the dump and inspect it with tools such as `wasmgdb`. The audit entry of the call
+//! names the dump by its call id in `coredump`.
+//!
+//! A dump is an image of the guest's memory, which may hold secrets the component was given,
+//! so both files are readable by the server's user only. The newest [`MAX_COREDUMPS`] dumps
+//! are kept, up to [`MAX_COREDUMP_BYTES`] in total, and older ones are removed as new ones
+//! come in.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+/// Directory (relative to the plugin directory) where core dumps are written.
+pub(crate) const COREDUMPS_DIR: &str = "coredumps";
+
+/// Most core dumps kept.
+pub(crate) const MAX_COREDUMPS: usize = 32;
+
+/// Most bytes of core dumps kept; the newest dump is kept whatever its size.
+pub(crate) const MAX_COREDUMP_BYTES: u64 = 512 * 1024 * 1024;
+
+static CALL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Describes the invocation that produced a core dump.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoreDumpRecord {
+    /// Identifier of the failed call
+    pub call_id: String,
+    /// Component that trapped
+    pub component_id: String,
+    /// Tool (function) that was being invoked
+    pub function_name: String,
+    /// Rendered trap error
+    pub error: String,
+    /// Capture time (seconds since epoch)
+    pub created_at: u64,
+    /// Size of the core dump in bytes
+    pub size: u64,
+}
+
+/// Attached to the error of a call whose trap left a core dump, naming the dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CoreDumpSaved {
+    pub(crate) call_id: String,
+}
+
+impl std::fmt::Display for CoreDumpSaved {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "component trapped; core dump saved for call {}",
+            self.call_id
+        )
+    }
+}
+
+/// Generate a process-unique identifier for a component call.
+pub(crate) fn new_call_id() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let seq = CALL_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{millis:x}-{seq:04x}")
+}
+
+/// Reject call ids that could escape the core dump directory.
+fn validate_call_id(call_id: &str) -> Result<()> {
+    if call_id.is_empty()
+        || !call_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("Invalid call id: {call_id}");
+    }
+    Ok(())
+}
+
+pub(crate) fn dump_path(dir: &Path, call_id: &str) -> PathBuf {
+    dir.join(format!("{call_id}.coredump"))
+}
+
+fn record_path(dir: &Path, call_id: &str) -> PathBuf {
+    dir.join(format!("{call_id}.json"))
+}
+
+/// Write `contents` to a new file at `path` readable by the server's user only.
+async fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path).await?;
+    file.write_all(contents).await?;
+    file.flush().await
+}
+
+/// Persist a core dump and its record in `dir`, removing the oldest dumps past the limits.
+pub(crate) async fn save(dir: &Path, record: &CoreDumpRecord, dump: &[u8]) -> Result<()> {
+    validate_call_id(&record.call_id)?;
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("Failed to create core dump directory {}", dir.display()))?;
+    #[cfg(unix)]
+    tokio::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+        .await
+        .with_context(|| format!("Failed to restrict core dump directory {}", dir.display()))?;
+
+    let path = dump_path(dir, &record.call_id);
+    write_private(&path, dump)
+        .await
+        .with_context(|| format!("Failed to write core dump to {}", path.display()))?;
+
+    let json =
+        serde_json::to_string_pretty(record).context("Failed to serialize core dump record")?;
+    let path = record_path(dir, &record.call_id);
+    write_private(&path, json.as_bytes())
+        .await
+        .with_context(|| format!("Failed to write core dump record to {}", path.display()))?;
+
+    prune(dir, MAX_COREDUMPS, MAX_COREDUMP_BYTES).await
+}
+
+/// Remove the oldest core dumps in `dir` beyond the newest `max_count`, or once the dumps kept
+/// add up to more than `max_bytes`.
+async fn prune(dir: &Path, max_count: usize, max_bytes: u64) -> Result<()> {
+    let mut kept_bytes = 0u64;
+    for (i, record) in list(dir).await?.into_iter().enumerate() {
+        kept_bytes = kept_bytes.saturating_add(record.size);
+        if i == 0 || (i < max_count && kept_bytes <= max_bytes) {
+            continue;
+        }
+        for path in [
+            dump_path(dir, &record.call_id),
+            record_path(dir, &record.call_id),
+        ] {
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to remove old core dump {}", path.display())
+                    })
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Load the record and raw bytes of the core dump captured for `call_id`.
+pub(crate) async fn load(dir: &Path, call_id: &str) -> Result<(CoreDumpRecord, Vec<u8>)> {
+    validate_call_id(call_id)?;
+
+    let path = record_path(dir, call_id);
+    let record = tokio::fs::read(&path)
+        .await
+        .with_context(|| format!("No core dump recorded for call {call_id}"))?;
+    let record: CoreDumpRecord = serde_json::from_slice(&record)
+        .with_context(|| format!("Failed to parse core dump record {}", path.display()))?;
+
+    let path = dump_path(dir, call_id);
+    let dump = tokio::fs::read(&path)
+        .await
+        .with_context(|| format!("Failed to read core dump {}", path.display()))?;
+
+    Ok((record, dump))
+}
+
+/// List the records of all captured core dumps, newest first.
+pub(crate) async fn list(dir: &Path) -> Result<Vec<CoreDumpRecord>> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to read core dump directory {}", dir.display()))
+        }
+    };
+
+    let mut records = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            match tokio::fs::read(&path)
+                .await
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<CoreDumpRecord>(&bytes).ok())
+            {
+                Some(record) => records.push(record),
+                None => {
+                    tracing::warn!(path = %path.display(), "Skipping unreadable core dump record")
+                }
+            }
+        }
+    }
+
+    records.sort_by_key(|record| std::cmp::Reverse(record.created_at));
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_load_and_list() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let record = CoreDumpRecord {
+            call_id: new_call_id(),
+            component_id: "fetch".to_string(),
+            function_name: "fetch".to_string(),
+            error: "wasm trap: unreachable".to_string(),
+            created_at: 42,
+            size: 4,
+        };
+
+        save(dir.path(), &record, b"\0asm").await?;
+
+        let (loaded, dump) = load(dir.path(), &record.call_id).await?;
+        assert_eq!(loaded.component_id, "fetch");
+        assert_eq!(dump, b"\0asm");
+
+        let records = list(dir.path()).await?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].call_id, record.call_id);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dumps_are_private_and_oldest_pruned() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for created_at in 1..=4 {
+            let record = CoreDumpRecord {
+                call_id: format!("call-{created_at}"),
+                component_id: "fetch".to_string(),
+                function_name: "fetch".to_string(),
+                error: "wasm trap: unreachable".to_string(),
+                created_at,
+                size: 4,
+            };
+            save(dir.path(), &record, b"\0asm").await?;
+        }
+
+        #[cfg(unix)]
+        assert_eq!(
+            tokio::fs::metadata(dump_path(dir.path(), "call-4"))
+                .await?
+                .permissions()
+                .mode()
+                & 0o777,
+            0o600
+        );
+
+        prune(dir.path(), 3, u64::MAX).await?;
+        let kept: Vec<String> = list(dir.path())
+            .await?
+            .into_iter()
+            .map(|r| r.call_id)
+            .collect();
+        assert_eq!(kept, ["call-4", "call-3", "call-2"]);
+        assert!(!dump_path(dir.path(), "call-1").exists());
+
+        // The byte limit drops the oldest first but always keeps the newest dump.
+        prune(dir.path(), 3, 6).await?;
+        let kept: Vec<String> = list(dir.path())
+            .await?
+            .into_iter()
+            .map(|r| r.call_id)
+            .collect();
+        assert_eq!(kept, ["call-4"]);
+        prune(dir.path(), 3, 0).await?;
+        assert_eq!(list(dir.path()).await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rejects_path_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load(dir.path(), "../secrets").await.is_err());
+        assert!(load(dir.path(), "").await.is_err());
+    }
+
+    #[test]
+    fn test_call_ids_are_unique() {
+        assert_ne!(new_call_id(), new_call_id());
+    }
+}
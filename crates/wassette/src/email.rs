@@ -0,0 +1,524 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Email sent by components through the `wassette:email/send` host interface.
+//!
+//! Components import
+//!
+//! ```wit
+//! package wassette:email;
+//!
+//! interface send {
+//!     record message {
+//!         /// Recipient addresses, each allowed by the component's policy
+//!         to: list<string>,
+//!         subject: string,
+//!         /// Plain-text body
+//!         body: string,
+//!     }
+//!
+//!     send: func(message: message) -> result<_, string>;
+//! }
+//! ```
+//!
+//! so a notification tool can mail a report or an alert without being granted network access
+//! to a mail server. Sending is denied unless the component's policy has an `email` section,
+//! which lists the addresses and domains the component may send to and caps the messages per
+//! hour.
+//!
+//! The SMTP server is configured in the component's secrets, `SMTP_HOST` and `SMTP_FROM`, with
+//! `SMTP_PORT`, `SMTP_USERNAME` and `SMTP_PASSWORD` optional. These secrets are kept from a
+//! component whose policy grants email, so it can't use the credentials itself. Port 465 uses
+//! TLS from the start and any other port, 587 by default, must offer STARTTLS; mail is never
+//! sent in plain text.
+//!
+//! The hourly quota is counted per component since the server started, across invocations and
+//! reloads. Every message the policy lets through counts, including those the SMTP server then
+//! rejects, so a component can't retry without bound.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use policy::PolicyDocument;
+use wasmtime::component::{ComponentType, Lift, Linker};
+
+use crate::secrets::SecretsManager;
+use crate::{WasiState, WassetteWasiState};
+
+/// Name of the host interface that sends email.
+pub const EMAIL_INTERFACE: &str = "wassette:email/send";
+
+/// Hourly cap applied when the policy allows email without setting `max-per-hour`.
+pub const DEFAULT_MAX_PER_HOUR: u32 = 10;
+
+/// Most recipients a single message may have.
+pub const MAX_RECIPIENTS: usize = 20;
+
+/// Largest message body, in bytes.
+pub const MAX_BODY_BYTES: usize = 256 * 1024;
+
+/// Secrets holding the SMTP settings, which a component allowed to send email doesn't see.
+pub(crate) const SMTP_SECRETS: &[&str] = &[
+    "SMTP_HOST",
+    "SMTP_PORT",
+    "SMTP_USERNAME",
+    "SMTP_PASSWORD",
+    "SMTP_FROM",
+];
+
+/// The `secrets` a component sees, leaving out the SMTP settings if it `sends_email`.
+pub(crate) fn visible_secrets(
+    secrets: &HashMap<String, String>,
+    sends_email: bool,
+) -> HashMap<String, String> {
+    secrets
+        .iter()
+        .filter(|(key, _)| !(sends_email && SMTP_SECRETS.contains(&key.as_str())))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Submission port used when `SMTP_PORT` isn't set.
+const DEFAULT_SMTP_PORT: u16 = 587;
+
+/// Port on which the server expects TLS from the start rather than STARTTLS.
+const SMTPS_PORT: u16 = 465;
+
+/// How long the SMTP server may take to answer.
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Period the quota counts messages over.
+const QUOTA_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// An email message sent by a component.
+#[derive(ComponentType, Lift, Debug, Clone, PartialEq, Eq)]
+#[component(record)]
+pub struct EmailMessage {
+    /// Recipient addresses
+    pub to: Vec<String>,
+    /// Subject line
+    pub subject: String,
+    /// Plain-text body
+    pub body: String,
+}
+
+/// Per-component email permissions derived from the policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailLimits {
+    /// Addresses the component may send to, with lowercase domains
+    pub addresses: Vec<String>,
+    /// Domains at which the component may send to every address, lowercase
+    pub domains: Vec<String>,
+    /// Maximum number of messages sent in any hour
+    pub max_per_hour: u32,
+}
+
+impl EmailLimits {
+    /// Limits granted by `policy`, or `None` if the policy doesn't allow email.
+    pub fn from_policy(policy: &PolicyDocument) -> Option<Self> {
+        policy.permissions.email.as_ref().map(|email| {
+            let recipients = email.allow.iter().flatten();
+            Self {
+                addresses: recipients
+                    .clone()
+                    .filter_map(|perm| perm.address.as_deref())
+                    .map(normalize_address)
+                    .collect(),
+                domains: recipients
+                    .filter_map(|perm| perm.domain.as_deref())
+                    .map(str::to_ascii_lowercase)
+                    .collect(),
+                max_per_hour: email.max_per_hour.unwrap_or(DEFAULT_MAX_PER_HOUR),
+            }
+        })
+    }
+
+    fn allows(&self, address: &Address) -> bool {
+        let domain = address.domain().to_ascii_lowercase();
+        self.domains.contains(&domain)
+            || self
+                .addresses
+                .contains(&format!("{}@{domain}", address.user()))
+    }
+}
+
+/// `address` with its domain lowercased, since domains are case-insensitive and local parts
+/// may not be.
+fn normalize_address(address: &str) -> String {
+    match address.rsplit_once('@') {
+        Some((local, domain)) => format!("{local}@{}", domain.to_ascii_lowercase()),
+        None => address.to_string(),
+    }
+}
+
+/// SMTP settings read from a component's secrets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SmtpSettings {
+    host: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+    from: Mailbox,
+}
+
+impl SmtpSettings {
+    fn from_secrets(secrets: &HashMap<String, String>) -> Result<Self> {
+        let get = |key: &str| {
+            secrets
+                .get(key)
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+        let host = get("SMTP_HOST")
+            .context("email is not configured: the component has no SMTP_HOST secret")?;
+        let from = get("SMTP_FROM")
+            .context("email is not configured: the component has no SMTP_FROM secret")?;
+        let port = match get("SMTP_PORT") {
+            Some(port) => port
+                .parse()
+                .map_err(|_| anyhow!("invalid SMTP_PORT secret `{port}`"))?,
+            None => DEFAULT_SMTP_PORT,
+        };
+        let credentials = match (get("SMTP_USERNAME"), secrets.get("SMTP_PASSWORD")) {
+            (Some(username), Some(password)) => Some((username.to_string(), password.clone())),
+            (None, None) => None,
+            _ => {
+                return Err(anyhow!(
+                    "SMTP_USERNAME and SMTP_PASSWORD secrets must be set together"
+                ))
+            }
+        };
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            credentials,
+            from: from
+                .parse()
+                .map_err(|e| anyhow!("invalid SMTP_FROM secret `{from}`: {e}"))?,
+        })
+    }
+}
+
+/// Sends the email of all components and keeps their hourly quotas.
+pub(crate) struct Outbox {
+    secrets: Arc<SecretsManager>,
+    /// Times of the messages sent within the last hour, by component
+    sent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl Outbox {
+    pub(crate) fn new(secrets: Arc<SecretsManager>) -> Self {
+        Self {
+            secrets,
+            sent: Mutex::default(),
+        }
+    }
+
+    /// Count a message of `component_id` against its quota of `max_per_hour` messages.
+    fn reserve(
+        &self,
+        component_id: &str,
+        max_per_hour: u32,
+        now: Instant,
+    ) -> std::result::Result<(), String> {
+        let mut sent = self.sent.lock().unwrap();
+        let times = sent.entry(component_id.to_string()).or_default();
+        while times
+            .front()
+            .is_some_and(|time| now.duration_since(*time) >= QUOTA_WINDOW)
+        {
+            times.pop_front();
+        }
+        if times.len() >= max_per_hour as usize {
+            return Err(format!(
+                "email limit of {max_per_hour} messages per hour reached"
+            ));
+        }
+        times.push_back(now);
+        Ok(())
+    }
+
+    /// Send `message` to `to` through the SMTP server in the secrets of `component_id`.
+    async fn send(
+        &self,
+        component_id: &str,
+        to: Vec<Address>,
+        message: EmailMessage,
+    ) -> Result<()> {
+        let secrets = self.secrets.load_component_secrets(component_id).await?;
+        let settings = SmtpSettings::from_secrets(&secrets)?;
+        let email = build_message(settings.from.clone(), to, message)?;
+
+        let relay = if settings.port == SMTPS_PORT {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+        };
+        let mut transport = relay
+            .with_context(|| format!("invalid SMTP_HOST secret `{}`", settings.host))?
+            .port(settings.port)
+            .timeout(Some(SMTP_TIMEOUT));
+        if let Some((username, password)) = settings.credentials {
+            transport = transport.credentials(Credentials::new(username, password));
+        }
+        transport
+            .build()
+            .send(email)
+            .await
+            .with_context(|| format!("failed to send email through {}", settings.host))?;
+        Ok(())
+    }
+}
+
+fn build_message(from: Mailbox, to: Vec<Address>, message: EmailMessage) -> Result<Message> {
+    let mut builder = Message::builder()
+        .from(from)
+        .subject(message.subject)
+        .header(ContentType::TEXT_PLAIN);
+    for address in to {
+        builder = builder.to(Mailbox::new(None, address));
+    }
+    builder
+        .body(message.body)
+        .context("failed to build the email")
+}
+
+/// Email state of a single store.
+#[derive(Default, Clone)]
+pub struct EmailState {
+    /// Recipients and quota from the policy; `None` denies email
+    pub limits: Option<EmailLimits>,
+    /// The manager's outbox and the component sending through it
+    pub(crate) outbox: Option<(Arc<Outbox>, String)>,
+}
+
+impl EmailState {
+    /// Check `message` against the policy and the hourly quota, and count it.
+    fn admit(
+        &self,
+        message: &EmailMessage,
+    ) -> std::result::Result<(Arc<Outbox>, String, Vec<Address>), String> {
+        let limits = self
+            .limits
+            .as_ref()
+            .ok_or_else(|| "email is not permitted by the component policy".to_string())?;
+        let (outbox, component_id) = self
+            .outbox
+            .clone()
+            .ok_or_else(|| "email is not available".to_string())?;
+        if message.to.is_empty() {
+            return Err("the message has no recipients".to_string());
+        }
+        if message.to.len() > MAX_RECIPIENTS {
+            return Err(format!(
+                "the message has more than {MAX_RECIPIENTS} recipients"
+            ));
+        }
+        if message.subject.chars().any(char::is_control) {
+            return Err("the subject must be a single line".to_string());
+        }
+        if message.body.len() > MAX_BODY_BYTES {
+            return Err(format!(
+                "the message body is larger than {MAX_BODY_BYTES} bytes"
+            ));
+        }
+        let mut to = Vec::with_capacity(message.to.len());
+        for recipient in &message.to {
+            let address: Address = recipient
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid recipient `{recipient}`: {e}"))?;
+            if !limits.allows(&address) {
+                return Err(format!(
+                    "recipient `{recipient}` is not allowed by the component policy"
+                ));
+            }
+            to.push(address);
+        }
+        outbox.reserve(&component_id, limits.max_per_hour, Instant::now())?;
+        Ok((outbox, component_id, to))
+    }
+}
+
+/// Add the `wassette:email/send` interface to `linker`.
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    linker.instance(EMAIL_INTERFACE)?.func_wrap_async(
+        "send",
+        |store, (message,): (EmailMessage,)| {
+            let admitted = store.data().inner.email.admit(&message);
+            Box::new(async move {
+                let result = match admitted {
+                    Ok((outbox, component_id, to)) => outbox
+                        .send(&component_id, to, message)
+                        .await
+                        .map_err(|e| format!("{e:#}")),
+                    Err(e) => Err(e),
+                };
+                Ok((result,))
+            })
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use policy::PolicyParser;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn limits() -> EmailLimits {
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+permissions:
+  email:
+    allow:
+    - address: OnCall@Example.com
+    - domain: Alerts.Example.org
+    max-per-hour: 2
+"#,
+        )
+        .unwrap();
+        EmailLimits::from_policy(&policy).unwrap()
+    }
+
+    fn message(to: &[&str]) -> EmailMessage {
+        EmailMessage {
+            to: to.iter().map(|to| to.to_string()).collect(),
+            subject: "Build failed".to_string(),
+            body: "main is red".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_limits_from_policy() {
+        let policy = PolicyParser::parse_str("version: \"1.0\"\npermissions: {}\n").unwrap();
+        assert_eq!(EmailLimits::from_policy(&policy), None);
+
+        let limits = limits();
+        assert_eq!(limits.addresses, ["OnCall@example.com"]);
+        assert_eq!(limits.domains, ["alerts.example.org"]);
+        assert_eq!(limits.max_per_hour, 2);
+    }
+
+    #[test]
+    fn test_admit_checks_recipients_and_quota() {
+        let dir = TempDir::new().unwrap();
+        let outbox = Arc::new(Outbox::new(Arc::new(SecretsManager::new(
+            dir.path().to_path_buf(),
+        ))));
+        let mut state = EmailState {
+            limits: None,
+            outbox: Some((Arc::clone(&outbox), "notify".to_string())),
+        };
+        assert!(state.admit(&message(&["OnCall@example.com"])).is_err());
+
+        state.limits = Some(limits());
+        for denied in [
+            &[][..],
+            &["oncall@example.com"],
+            &["OnCall@example.com.evil.net"],
+            &["ops@example.org"],
+            &["ops@sub.alerts.example.org"],
+            &["Ops <ops@alerts.example.org>"],
+            &["OnCall@example.com", "someone@elsewhere.net"],
+        ] {
+            assert!(state.admit(&message(denied)).is_err(), "{denied:?}");
+        }
+        let mut injected = message(&["OnCall@example.com"]);
+        injected.subject = "hi\r\nBcc: someone@elsewhere.net".to_string();
+        assert!(state.admit(&injected).is_err());
+
+        let (_, component_id, to) = state
+            .admit(&message(&["OnCall@EXAMPLE.com", "ops@ALERTS.example.org"]))
+            .unwrap();
+        assert_eq!(component_id, "notify");
+        assert_eq!(to.len(), 2);
+        assert!(state.admit(&message(&["ops@alerts.example.org"])).is_ok());
+        assert_eq!(
+            state
+                .admit(&message(&["ops@alerts.example.org"]))
+                .map(|_| ()),
+            Err("email limit of 2 messages per hour reached".to_string())
+        );
+
+        // The quota is per component and frees up after an hour.
+        assert!(outbox.reserve("other", 2, Instant::now()).is_ok());
+        let later = Instant::now() + QUOTA_WINDOW;
+        assert!(outbox.reserve("notify", 2, later).is_ok());
+    }
+
+    #[test]
+    fn test_smtp_settings_from_secrets() {
+        let secrets = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+
+        let settings = SmtpSettings::from_secrets(&secrets(&[
+            ("SMTP_HOST", "smtp.example.com"),
+            ("SMTP_FROM", "Alerts <alerts@example.com>"),
+        ]))
+        .unwrap();
+        assert_eq!(settings.port, DEFAULT_SMTP_PORT);
+        assert_eq!(settings.credentials, None);
+        assert_eq!(settings.from.email.to_string(), "alerts@example.com");
+
+        let settings = SmtpSettings::from_secrets(&secrets(&[
+            ("SMTP_HOST", "smtp.example.com"),
+            ("SMTP_FROM", "alerts@example.com"),
+            ("SMTP_PORT", "465"),
+            ("SMTP_USERNAME", "alerts"),
+            ("SMTP_PASSWORD", "hunter2"),
+        ]))
+        .unwrap();
+        assert_eq!(settings.port, 465);
+        assert_eq!(
+            settings.credentials,
+            Some(("alerts".to_string(), "hunter2".to_string()))
+        );
+
+        for invalid in [
+            &[("SMTP_FROM", "alerts@example.com")][..],
+            &[("SMTP_HOST", "smtp.example.com")],
+            &[
+                ("SMTP_HOST", "smtp.example.com"),
+                ("SMTP_FROM", "alerts@example.com"),
+                ("SMTP_PORT", "smtp"),
+            ],
+            &[
+                ("SMTP_HOST", "smtp.example.com"),
+                ("SMTP_FROM", "alerts@example.com"),
+                ("SMTP_USERNAME", "alerts"),
+            ],
+        ] {
+            assert!(
+                SmtpSettings::from_secrets(&secrets(invalid)).is_err(),
+                "{invalid:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_message() {
+        let message = build_message(
+            "Alerts <alerts@example.com>".parse().unwrap(),
+            vec!["oncall@example.com".parse().unwrap()],
+            message(&[]),
+        )
+        .unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.contains("From: Alerts <alerts@example.com>\r\n"));
+        assert!(formatted.contains("To: oncall@example.com\r\n"));
+        assert!(formatted.contains("Subject: Build failed\r\n"));
+        assert!(formatted.ends_with("\r\n\r\nmain is red"));
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! `${secret:name}` and `${env:NAME}` references in the values of granted environment variables.
+//!
+//! An environment grant of a policy can give its variable a value, and a grant without one
+//! passes the server's variable of the same name. Either value may refer to a secret of the
+//! component or to another variable of the server environment:
+//!
+//! ```yaml
+//! permissions:
+//!   environment:
+//!     allow:
+//!       - key: API_TOKEN
+//!         value: "Bearer ${secret:api-token}"
+//!       - key: DATABASE_URL
+//!         value: "postgres://app@${env:DB_HOST}/app"
+//! ```
+//!
+//! References are resolved every time the component is instantiated, so a rotated secret
+//! reaches the next instance without attaching the policy again. A reference that can't be
+//! resolved fails the instantiation instead of handing the component a partial value. Values
+//! of secrets are never searched for references, and neither is the value an `${env:NAME}`
+//! reference stands for.
+//!
+//! A component sees each variable with the value of the first of these sources that has one:
+//!
+//! 1. the `value` of its grant in the policy;
+//! 2. the server environment variable of the same name, for a grant without a value;
+//! 3. the component's secret of the same name.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+/// Where the value of an environment variable of a component comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VariableSource {
+    /// The `value` of the variable's grant in the policy
+    Policy,
+    /// The server environment variable the policy grants
+    Environment,
+    /// A secret of the component
+    Secret,
+}
+
+/// An environment variable a component is given when it is instantiated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InjectedVariable {
+    /// Name of the variable
+    pub key: String,
+    /// Value with its references resolved
+    pub value: String,
+    /// Where the value comes from
+    pub source: VariableSource,
+    /// References in the value as written, e.g. `secret:api-token`
+    pub references: Vec<String>,
+}
+
+/// A part of a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part<'a> {
+    Text(&'a str),
+    Secret(&'a str),
+    Env(&'a str),
+}
+
+/// Split `value` into text and references. Only `${secret:` and `${env:` start a reference,
+/// so other `${...}` are kept as they are.
+fn parse(value: &str) -> Result<Vec<Part<'_>>> {
+    let mut parts = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let after = &rest[start + 2..];
+        let (kind, name_start) = if let Some(name) = after.strip_prefix("secret:") {
+            ("secret", name)
+        } else if let Some(name) = after.strip_prefix("env:") {
+            ("env", name)
+        } else {
+            parts.push(Part::Text(&rest[..start + 2]));
+            rest = after;
+            continue;
+        };
+        let Some(end) = name_start.find('}') else {
+            bail!("Unterminated reference `${{{kind}:` in {value:?}");
+        };
+        let name = &name_start[..end];
+        if name.is_empty() {
+            bail!("Reference `${{{kind}:}}` without a name in {value:?}");
+        }
+        if !rest[..start].is_empty() {
+            parts.push(Part::Text(&rest[..start]));
+        }
+        parts.push(match kind {
+            "secret" => Part::Secret(name),
+            _ => Part::Env(name),
+        });
+        rest = &name_start[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    Ok(parts)
+}
+
+/// The references in `value`, as `secret:<name>` and `env:<NAME>`
+pub(crate) fn references(value: &str) -> Result<Vec<String>> {
+    Ok(parse(value)?
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::Text(_) => None,
+            Part::Secret(name) => Some(format!("secret:{name}")),
+            Part::Env(name) => Some(format!("env:{name}")),
+        })
+        .collect())
+}
+
+/// Replace the references in `value` with the `secrets` of the component and the variables
+/// of the server `environment`.
+pub(crate) fn resolve(
+    value: &str,
+    secrets: &HashMap<String, String>,
+    environment: &HashMap<String, String>,
+) -> Result<String> {
+    let mut resolved = String::with_capacity(value.len());
+    for part in parse(value)? {
+        match part {
+            Part::Text(text) => resolved.push_str(text),
+            Part::Secret(name) => resolved.push_str(
+                secrets
+                    .get(name)
+                    .with_context(|| format!("Secret {name} is not set"))?,
+            ),
+            Part::Env(name) => resolved.push_str(
+                environment
+                    .get(name)
+                    .with_context(|| format!("Environment variable {name} is not set"))?,
+            ),
+        }
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_references_are_resolved_from_secrets_and_environment() {
+        let secrets = HashMap::from([("api-token".to_string(), "s3cr3t".to_string())]);
+        let environment = HashMap::from([
+            ("DB_HOST".to_string(), "db.internal".to_string()),
+            ("NESTED".to_string(), "${secret:api-token}".to_string()),
+        ]);
+
+        let value = "Bearer ${secret:api-token} for ${env:DB_HOST}, ${HOME} as is";
+        assert_eq!(
+            references(value).unwrap(),
+            vec!["secret:api-token", "env:DB_HOST"]
+        );
+        assert_eq!(
+            resolve(value, &secrets, &environment).unwrap(),
+            "Bearer s3cr3t for db.internal, ${HOME} as is"
+        );
+        // The value a reference stands for is used as it is.
+        assert_eq!(
+            resolve("${env:NESTED}", &secrets, &environment).unwrap(),
+            "${secret:api-token}"
+        );
+        assert!(references("plain").unwrap().is_empty());
+
+        let missing = resolve("${secret:other}", &secrets, &environment).unwrap_err();
+        assert!(missing.to_string().contains("Secret other is not set"));
+        assert!(references("${secret:api-token").is_err());
+        assert!(references("${env:}").is_err());
+    }
+}
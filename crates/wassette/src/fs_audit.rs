@@ -0,0 +1,369 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Host-side protection against filesystem escapes, and the audit trail of attempts.
+//!
+//! Preopens already confine a component to the directories it was granted, but that
+//! confinement lives inside the WASI implementation. As a second line of defense, the
+//! `wasi:filesystem` shadows in [`crate::fs_grants`] resolve every path a component passes
+//! against the host directory of its preopen: a `..` climbing above the preopen, an absolute
+//! path, or a symlink whose target lies outside the preopen is an escape. Escapes are refused
+//! with `not-permitted`, or only reported in [`FsHardening::Audit`] mode. Attempts are logged
+//! as `wassette::audit` events and appended to `audit/fs-escapes.jsonl` under the plugin
+//! directory.
+//!
+//! A component can make attempts in a loop, so each instance reports an attempt once, and at
+//! most [`MAX_REPORTS_PER_STORE`] different ones; later attempts are still refused. The file
+//! is moved to `fs-escapes.jsonl.1` once it reaches [`MAX_ESCAPES_BYTES`], replacing the
+//! previous one.
+
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+use crate::audit::Denials;
+
+/// Directory (relative to the plugin directory) holding audit records.
+pub(crate) const AUDIT_DIR: &str = "audit";
+const ESCAPES_FILE: &str = "fs-escapes.jsonl";
+
+/// Different escape attempts reported per instance.
+pub(crate) const MAX_REPORTS_PER_STORE: usize = 32;
+
+/// Size at which the file of escape attempts is rotated.
+pub(crate) const MAX_ESCAPES_BYTES: u64 = 10 * 1024 * 1024;
+
+/// How escapes from granted directories are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FsHardening {
+    /// Refuse the operation and report the attempt
+    #[default]
+    Enforce,
+    /// Report symlink escapes but leave the decision to the WASI preopen
+    Audit,
+}
+
+/// How a path tried to leave its preopen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EscapeKind {
+    /// `..` above the preopen, or an absolute path
+    Traversal,
+    /// A symlink resolving outside the preopen
+    Symlink,
+}
+
+/// A recorded attempt to reach a host path outside a granted directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EscapeAttempt {
+    /// Component that made the attempt
+    pub component_id: String,
+    /// `wasi:filesystem` function called, e.g. `open-at`
+    pub operation: String,
+    /// Guest path of the preopen the path was relative to
+    pub preopen: String,
+    /// Path passed by the component
+    pub path: String,
+    /// How the path escaped
+    pub kind: EscapeKind,
+    /// Host path a symlink resolved to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+    /// Whether the operation was refused
+    pub blocked: bool,
+    /// Time of the attempt (seconds since epoch)
+    pub created_at: u64,
+}
+
+/// Where the escape attempts of one store are reported.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EscapeAudit {
+    component_id: String,
+    mode: FsHardening,
+    log: Option<PathBuf>,
+    /// Where blocked attempts are counted and audited as permission denials
+    denials: Option<Denials>,
+    /// Attempts of the store reported so far
+    reported: Arc<Mutex<Reported>>,
+}
+
+#[derive(Debug, Default)]
+struct Reported {
+    /// Operation, preopen, path and kind of each attempt
+    attempts: HashSet<(String, String, String, EscapeKind)>,
+    /// Whether attempts past the limit were found
+    overflowed: bool,
+}
+
+impl EscapeAudit {
+    pub(crate) fn new(component_id: &str, mode: FsHardening, plugin_dir: &Path) -> Self {
+        Self {
+            component_id: component_id.to_string(),
+            mode,
+            log: Some(log_path(plugin_dir)),
+            denials: None,
+            reported: Default::default(),
+        }
+    }
+
+    /// Report blocked attempts as permission denials to `denials`.
+    pub(crate) fn with_denials(mut self, denials: Denials) -> Self {
+        self.denials = Some(denials);
+        self
+    }
+
+    /// Whether the attempt is the first of its kind in the store, within the limit.
+    fn first_report(&self, operation: &str, preopen: &str, path: &str, kind: EscapeKind) -> bool {
+        let mut reported = self.reported.lock().unwrap_or_else(|e| e.into_inner());
+        let attempt = (
+            operation.to_string(),
+            preopen.to_string(),
+            path.to_string(),
+            kind,
+        );
+        if reported.attempts.contains(&attempt) {
+            return false;
+        }
+        if reported.attempts.len() >= MAX_REPORTS_PER_STORE {
+            if !reported.overflowed {
+                reported.overflowed = true;
+                warn!(
+                    target: "wassette::audit",
+                    component_id = %self.component_id,
+                    "Too many filesystem escape attempts; further ones of this instance are refused without a record"
+                );
+            }
+            return false;
+        }
+        reported.attempts.insert(attempt)
+    }
+
+    /// Report an escape attempt. Returns whether the operation must be refused.
+    pub(crate) async fn report(
+        &self,
+        operation: &str,
+        preopen: &str,
+        path: &str,
+        kind: EscapeKind,
+        target: Option<PathBuf>,
+    ) -> bool {
+        // `..` and absolute paths are refused by the preopen as well.
+        let blocked = kind == EscapeKind::Traversal || self.mode == FsHardening::Enforce;
+        if !self.first_report(operation, preopen, path, kind) {
+            return blocked;
+        }
+        warn!(
+            target: "wassette::audit",
+            component_id = %self.component_id,
+            operation,
+            preopen,
+            path,
+            kind = ?kind,
+            target = ?target,
+            blocked,
+            "Filesystem escape attempt"
+        );
+        if let Some(denials) = self.denials.as_ref().filter(|_| blocked) {
+            denials.record(
+                "storage",
+                &format!("{}/{path}", preopen.trim_end_matches('/')),
+            );
+        }
+        let attempt = EscapeAttempt {
+            component_id: self.component_id.clone(),
+            operation: operation.to_string(),
+            preopen: preopen.to_string(),
+            path: path.to_string(),
+            kind,
+            target,
+            blocked,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+        if let Some(log) = &self.log {
+            if let Err(error) = append_rotated(log, &attempt, MAX_ESCAPES_BYTES).await {
+                warn!(%error, "Failed to record filesystem escape attempt");
+            }
+        }
+        blocked
+    }
+}
+
+/// Append `record` as a line of the JSONL file `log`, first moving `log` to `<log>.1` if it
+/// reached `max_bytes`.
+async fn append_rotated<T: Serialize>(log: &Path, record: &T, max_bytes: u64) -> Result<()> {
+    if let Some(dir) = log.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("Failed to create audit directory {}", dir.display()))?;
+    }
+    let full = tokio::fs::metadata(log)
+        .await
+        .is_ok_and(|metadata| metadata.len() >= max_bytes);
+    if full {
+        let mut rotated = log.as_os_str().to_owned();
+        rotated.push(".1");
+        // Another store may have rotated it first.
+        let _ = tokio::fs::rename(log, PathBuf::from(rotated)).await;
+    }
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    // A single append keeps lines from concurrent stores intact.
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)
+        .await
+        .with_context(|| format!("Failed to open {}", log.display()))?;
+    file.write_all(&line)
+        .await
+        .with_context(|| format!("Failed to append to {}", log.display()))?;
+    file.flush().await?;
+    Ok(())
+}
+
+fn log_path(plugin_dir: &Path) -> PathBuf {
+    plugin_dir.join(AUDIT_DIR).join(ESCAPES_FILE)
+}
+
+/// Append `record` as a line of the JSONL file `log`.
+pub(crate) fn append<T: Serialize>(log: &Path, record: &T) -> Result<()> {
+    if let Some(dir) = log.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create audit directory {}", dir.display()))?;
+    }
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    // A single append keeps lines from concurrent stores intact.
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)
+        .and_then(|mut file| file.write_all(&line))
+        .with_context(|| format!("Failed to append to {}", log.display()))
+}
+
+/// Escape attempts recorded under `plugin_dir`, oldest first. Unreadable lines are skipped.
+pub(crate) async fn list(plugin_dir: &Path) -> Result<Vec<EscapeAttempt>> {
+    read_records(&log_path(plugin_dir)).await
+}
+
+/// Records of the JSONL file `path`, skipping unreadable lines. A missing file has none.
+pub(crate) async fn read_records<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    let contents = match tokio::fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(error).with_context(|| format!("Failed to read {}", path.display()))
+        }
+    };
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_escape_attempts_are_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(list(dir.path()).await.unwrap().is_empty());
+
+        let audit = EscapeAudit::new("fetch", FsHardening::Audit, dir.path());
+        assert!(
+            audit
+                .report("open-at", "/data", "../etc", EscapeKind::Traversal, None)
+                .await
+        );
+        let target = PathBuf::from("/etc/passwd");
+        assert!(
+            !audit
+                .report(
+                    "open-at",
+                    "/data",
+                    "passwd",
+                    EscapeKind::Symlink,
+                    Some(target.clone())
+                )
+                .await
+        );
+        let enforcing = EscapeAudit::new("fetch", FsHardening::Enforce, dir.path());
+        assert!(
+            enforcing
+                .report("stat-at", "/data", "passwd", EscapeKind::Symlink, None)
+                .await
+        );
+
+        let attempts = list(dir.path()).await.unwrap();
+        assert_eq!(attempts.len(), 3);
+        assert_eq!(attempts[0].kind, EscapeKind::Traversal);
+        assert_eq!(attempts[1].target, Some(target));
+        assert!(!attempts[1].blocked);
+        assert_eq!(attempts[2].operation, "stat-at");
+    }
+
+    #[tokio::test]
+    async fn test_repeated_attempts_are_reported_once_and_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit = EscapeAudit::new("fetch", FsHardening::Enforce, dir.path());
+        for _ in 0..3 {
+            assert!(
+                audit
+                    .report("open-at", "/data", "../x", EscapeKind::Traversal, None)
+                    .await
+            );
+        }
+        assert_eq!(list(dir.path()).await.unwrap().len(), 1);
+
+        for i in 0..2 * MAX_REPORTS_PER_STORE {
+            let path = format!("../x{i}");
+            // Attempts past the limit are still refused.
+            assert!(
+                audit
+                    .report("open-at", "/data", &path, EscapeKind::Traversal, None)
+                    .await
+            );
+        }
+        assert_eq!(list(dir.path()).await.unwrap().len(), MAX_REPORTS_PER_STORE);
+
+        // Another instance reports its own attempts.
+        let other = EscapeAudit::new("fetch", FsHardening::Enforce, dir.path());
+        other
+            .report("open-at", "/data", "../x", EscapeKind::Traversal, None)
+            .await;
+        assert_eq!(
+            list(dir.path()).await.unwrap().len(),
+            MAX_REPORTS_PER_STORE + 1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_escapes_file_is_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = log_path(dir.path());
+        append_rotated(&log, &"first", 1024).await.unwrap();
+        append_rotated(&log, &"second", 1).await.unwrap();
+        let rotated: Vec<String> =
+            read_records(&dir.path().join(AUDIT_DIR).join("fs-escapes.jsonl.1"))
+                .await
+                .unwrap();
+        assert_eq!(rotated, ["first"]);
+        let current: Vec<String> = read_records(&log).await.unwrap();
+        assert_eq!(current, ["second"]);
+    }
+}
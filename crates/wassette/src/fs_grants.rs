@@ -0,0 +1,984 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Glob-restricted filesystem grants.
+//!
+//! A storage permission like `fs://~/projects/**/*.md` preopens its longest literal prefix
+//! (`~/projects`) and restricts what the component can reach below it to paths matching the
+//! rest of the pattern (`**/*.md`). The restriction is enforced by shadowing the path-taking
+//! `wasi:filesystem` functions in the linker: each opened descriptor is tracked back to its
+//! location below the preopen, and operations on paths that don't match are refused with
+//! `access`. Directories stay reachable so matching files can be found, but directory listings
+//! hide non-matching files. With `storage` permission prompts on, a refused file is asked
+//! about first (see [`crate::elevation`]), and reachable for the rest of the call if the user
+//! approves.
+//!
+//! The same shadows resolve every path below a preopen, filtered or not, against its host
+//! directory to catch escapes (see [`crate::fs_audit`]), route descriptors inside
+//! [`crate::mounts`] to their mounted tree, and count open descriptors against the
+//! `open-files` limit (see [`crate::limits`]).
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use wasmtime::component::{Linker, Resource};
+use wasmtime_wasi::p2::bindings::filesystem::preopens::Host as _;
+use wasmtime_wasi::p2::bindings::filesystem::types::{
+    Descriptor, DescriptorFlags, DescriptorStat, DescriptorType, DirectoryEntry,
+    DirectoryEntryStream, ErrorCode, Host as _, HostDescriptor, HostDirectoryEntryStream,
+    MetadataHashValue, NewTimestamp, OpenFlags, PathFlags,
+};
+use wasmtime_wasi::p2::FsResult;
+use wasmtime_wasi::WasiCtxView;
+
+use crate::elevation::{Elevation, PermissionKind};
+use crate::fs_audit::{EscapeAudit, EscapeKind};
+use crate::limits::OpenFiles;
+use crate::mounts::{self, VirtualFs, VirtualMount};
+use crate::wasistate::{PreopenedDir, WasiState};
+use crate::WassetteWasiState;
+
+const PREOPENS_INTERFACE: &str = "wasi:filesystem/preopens@0.2.6";
+const TYPES_INTERFACE: &str = "wasi:filesystem/types@0.2.6";
+/// Symlinks followed while resolving a dangling link, as in `SYMLOOP_MAX`.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Glob patterns selecting the paths reachable below a preopened directory.
+///
+/// Patterns are matched against `/`-separated paths relative to the preopen. `**` as a whole
+/// segment matches any number of directories, `*` matches within a segment and `?` matches a
+/// single character.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathFilter {
+    patterns: Vec<String>,
+}
+
+impl PathFilter {
+    /// A filter admitting paths that match any of `patterns`.
+    pub fn new(patterns: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            patterns: patterns.into_iter().collect(),
+        }
+    }
+
+    /// Admit the paths `other` admits as well.
+    pub(crate) fn extend(&mut self, other: PathFilter) {
+        for pattern in other.patterns {
+            if !self.patterns.contains(&pattern) {
+                self.patterns.push(pattern);
+            }
+        }
+    }
+
+    /// Whether the relative path `path` matches one of the patterns.
+    pub fn matches(&self, path: &Path) -> bool {
+        let segments: Vec<String> = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        self.patterns.iter().any(|pattern| {
+            let pattern: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+            match_segments(&pattern, &segments)
+        })
+    }
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| match_segments(rest, &path[skip..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path_rest)) => {
+                match_segment(segment.as_bytes(), name.as_bytes())
+                    && match_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_segment(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| match_segment(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && match_segment(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && match_segment(rest, &name[1..]),
+    }
+}
+
+/// Split a storage path into the directory to preopen and the pattern below it. A pattern of
+/// `**` grants the whole directory and yields no filter.
+pub(crate) fn split_glob(path: &str) -> (String, Option<PathFilter>) {
+    let segments: Vec<&str> = path.split('/').collect();
+    let Some(first_glob) = segments
+        .iter()
+        .position(|segment| segment.contains(['*', '?']))
+    else {
+        return (path.to_string(), None);
+    };
+    let base = segments[..first_glob].join("/");
+    let base = if base.is_empty() && path.starts_with('/') {
+        "/".to_string()
+    } else {
+        base
+    };
+    let pattern = segments[first_glob..].join("/");
+    if pattern == "**" {
+        (base, None)
+    } else {
+        (base, Some(PathFilter::new([pattern])))
+    }
+}
+
+/// A preopen, with the filter restricting it if any.
+#[derive(Debug)]
+struct GrantedRoot {
+    guest_path: String,
+    host_path: PathBuf,
+    filter: Option<PathFilter>,
+    readable: bool,
+    writable: bool,
+}
+
+/// Where a descriptor or path lives below a preopen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Location {
+    root: usize,
+    rel: PathBuf,
+}
+
+/// Per-store tracking of descriptors opened below preopens or inside mounts.
+#[derive(Debug, Default)]
+pub struct FsGrants {
+    roots: Vec<GrantedRoot>,
+    descriptors: HashMap<u32, Location>,
+    streams: HashMap<u32, Location>,
+    pub(crate) mounts: VirtualFs,
+    /// Where escape attempts are reported
+    pub(crate) audit: EscapeAudit,
+    /// Descriptors opened by the component, bounded by the policy
+    pub(crate) open_files: OpenFiles,
+    /// Where the user is asked for files the filters refuse during a call
+    pub(crate) elevation: Option<Arc<Elevation>>,
+}
+
+impl FsGrants {
+    /// Track the descriptors below `preopened_dirs` and inside `mounts`.
+    pub(crate) fn new(preopened_dirs: &[PreopenedDir], mounts: &[VirtualMount]) -> Self {
+        let roots = preopened_dirs
+            .iter()
+            .map(|dir| GrantedRoot {
+                guest_path: dir.guest_path.clone(),
+                host_path: dir.host_path.clone(),
+                filter: dir.path_filter.clone(),
+                readable: dir.file_perms.contains(wasmtime_wasi::FilePerms::READ),
+                writable: dir.file_perms.contains(wasmtime_wasi::FilePerms::WRITE),
+            })
+            .collect();
+        Self {
+            roots,
+            mounts: VirtualFs::new(mounts),
+            ..Default::default()
+        }
+    }
+
+    fn track_preopen(&mut self, rep: u32, guest_path: &str) {
+        match self
+            .roots
+            .iter()
+            .position(|root| root.guest_path == guest_path)
+        {
+            Some(root) => {
+                self.descriptors.insert(
+                    rep,
+                    Location {
+                        root,
+                        rel: PathBuf::new(),
+                    },
+                );
+            }
+            None => {
+                self.descriptors.remove(&rep);
+            }
+        }
+    }
+
+    /// Where `path` relative to descriptor `fd` lives, or `None` when `fd` isn't below a
+    /// preopen. Paths leaving the preopen are reported as escapes by `operation`.
+    async fn resolve(
+        &self,
+        operation: &str,
+        fd: u32,
+        path: &str,
+    ) -> Result<Option<Location>, ErrorCode> {
+        let Some(base) = self.descriptors.get(&fd) else {
+            return Ok(None);
+        };
+        let mut rel = base.rel.clone();
+        for component in Path::new(path).components() {
+            let inside = match component {
+                Component::Normal(name) => {
+                    rel.push(name);
+                    true
+                }
+                Component::CurDir => true,
+                Component::ParentDir => rel.pop(),
+                Component::RootDir | Component::Prefix(_) => false,
+            };
+            if !inside {
+                let preopen = &self.roots[base.root].guest_path;
+                self.audit
+                    .report(operation, preopen, path, EscapeKind::Traversal, None)
+                    .await;
+                return Err(ErrorCode::NotPermitted);
+            }
+        }
+        Ok(Some(Location {
+            root: base.root,
+            rel,
+        }))
+    }
+
+    /// `location` with symlinks resolved, so a matching link can't expose a non-matching file.
+    /// Falls back to `location` when it can't be resolved, and fails with the resolved host
+    /// path when that lies outside the root.
+    async fn real(&self, location: Location, follow: bool) -> Result<Location, PathBuf> {
+        let root = &self.roots[location.root];
+        let host = root.host_path.join(&location.rel);
+        let resolved = resolve_host(host, follow).await;
+        let real_root = tokio::fs::canonicalize(&root.host_path).await.ok();
+        let (Some(resolved), Some(real_root)) = (resolved, real_root) else {
+            return Ok(location);
+        };
+        match resolved.strip_prefix(&real_root) {
+            Ok(rel) => Ok(Location {
+                root: location.root,
+                rel: rel.to_path_buf(),
+            }),
+            Err(_) => Err(resolved),
+        }
+    }
+
+    /// `location` with symlinks resolved, reporting symlinks that escape the root.
+    async fn harden(
+        &self,
+        operation: &str,
+        location: &Location,
+        path: &str,
+        follow: bool,
+    ) -> Result<Location, ErrorCode> {
+        match self.real(location.clone(), follow).await {
+            Ok(real) => Ok(real),
+            Err(target) => {
+                let preopen = &self.roots[location.root].guest_path;
+                let blocked = self
+                    .audit
+                    .report(operation, preopen, path, EscapeKind::Symlink, Some(target))
+                    .await;
+                if blocked {
+                    return Err(ErrorCode::NotPermitted);
+                }
+                Ok(location.clone())
+            }
+        }
+    }
+
+    fn allows(&self, location: &Location) -> bool {
+        match &self.roots[location.root].filter {
+            Some(filter) => filter.matches(&location.rel),
+            None => true,
+        }
+    }
+
+    /// Ask the user to grant `location`, which the filter of its root refuses. Directories
+    /// aren't asked about, since they stay reachable anyway.
+    async fn ask(&self, location: &Location) -> bool {
+        let Some(elevation) = self
+            .elevation
+            .as_ref()
+            .filter(|elevation| elevation.covers(PermissionKind::Storage))
+        else {
+            return false;
+        };
+        let root = &self.roots[location.root];
+        let path = root.host_path.join(&location.rel);
+        if tokio::fs::metadata(&path)
+            .await
+            .is_ok_and(|metadata| metadata.is_dir())
+        {
+            return false;
+        }
+        elevation
+            .ask_storage(&path.to_string_lossy(), root.writable)
+            .await
+    }
+
+    /// Refuse `path` relative to `fd` if it escapes its preopen (`not-permitted`) or, symlinks
+    /// resolved, doesn't match (`access`) and the user doesn't grant it.
+    async fn check(
+        &self,
+        operation: &str,
+        fd: u32,
+        path: &str,
+        follow: bool,
+    ) -> Result<(), ErrorCode> {
+        let Some(location) = self.resolve(operation, fd, path).await? else {
+            return Ok(());
+        };
+        let real = self.harden(operation, &location, path, follow).await?;
+        if (!self.allows(&location) || !self.allows(&real)) && !self.ask(&real).await {
+            return Err(ErrorCode::Access);
+        }
+        Ok(())
+    }
+
+    /// The file at the absolute guest path `path`, for the host to read on the component's
+    /// behalf. The file must lie below a readable preopen and match its filter.
+    pub(crate) fn granted_file(&self, path: &str) -> Result<GrantedFile, String> {
+        let not_granted = || format!("`{path}` is not in a directory the policy grants");
+        let (root, below) = self
+            .roots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, root)| {
+                let below = Path::new(path).strip_prefix(&root.guest_path).ok()?;
+                Some((i, below))
+            })
+            .max_by_key(|(i, _)| self.roots[*i].guest_path.len())
+            .ok_or_else(not_granted)?;
+        let mut rel = PathBuf::new();
+        for component in below.components() {
+            let inside = match component {
+                Component::Normal(name) => {
+                    rel.push(name);
+                    true
+                }
+                Component::CurDir => true,
+                Component::ParentDir => rel.pop(),
+                Component::RootDir | Component::Prefix(_) => false,
+            };
+            if !inside {
+                return Err(not_granted());
+            }
+        }
+        let location = Location { root, rel };
+        if !self.roots[root].readable || !self.allows(&location) {
+            return Err(not_granted());
+        }
+        Ok(GrantedFile {
+            host_root: self.roots[root].host_path.clone(),
+            rel: location.rel,
+            filter: self.roots[root].filter.clone(),
+        })
+    }
+}
+
+/// A file below a preopen, read by the host rather than the component.
+#[derive(Debug, Clone)]
+pub(crate) struct GrantedFile {
+    host_root: PathBuf,
+    rel: PathBuf,
+    filter: Option<PathFilter>,
+}
+
+impl GrantedFile {
+    /// Read the file if it is at most `max_bytes` long. Symlinks are resolved first, so a
+    /// link replacing the file can't lead out of the preopen or to a path its filter excludes.
+    pub(crate) async fn read(&self, max_bytes: u64) -> Result<Vec<u8>> {
+        let host_path = self.host_root.join(&self.rel);
+        let root = tokio::fs::canonicalize(&self.host_root).await?;
+        let real = tokio::fs::canonicalize(&host_path)
+            .await
+            .with_context(|| format!("Failed to resolve {}", host_path.display()))?;
+        let allowed = real
+            .strip_prefix(&root)
+            .is_ok_and(|rel| self.filter.as_ref().is_none_or(|f| f.matches(rel)));
+        if !allowed {
+            bail!(
+                "{} leads outside the directory the component was granted",
+                host_path.display()
+            );
+        }
+        let len = tokio::fs::metadata(&real).await?.len();
+        if len > max_bytes {
+            bail!("{} is larger than {max_bytes} bytes", host_path.display());
+        }
+        Ok(tokio::fs::read(&real).await?)
+    }
+}
+
+/// The real host path of `host`. Unless `follow` is set, or when the path doesn't exist,
+/// only its parent is resolved. Dangling symlinks are followed to where a file would be
+/// created.
+async fn resolve_host(mut host: PathBuf, follow: bool) -> Option<PathBuf> {
+    if follow {
+        for _ in 0..MAX_SYMLINK_HOPS {
+            if let Ok(resolved) = tokio::fs::canonicalize(&host).await {
+                return Some(resolved);
+            }
+            match tokio::fs::read_link(&host).await {
+                Ok(target) => host = host.parent()?.join(target),
+                Err(_) => break,
+            }
+        }
+    }
+    let (parent, name) = (host.parent()?, host.file_name()?);
+    let parent = tokio::fs::canonicalize(parent).await.ok()?;
+    Some(parent.join(name))
+}
+
+/// Shadow the path-taking `wasi:filesystem` functions with ones enforcing [`PathFilter`]s.
+/// Must be called after the WASI interfaces were added to `linker`.
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    linker.allow_shadowing(true);
+    let result = add_shadows(linker).and_then(|()| mounts::add_shadows(linker));
+    linker.allow_shadowing(false);
+    result
+}
+
+pub(crate) fn split(state: &mut WasiState) -> (&mut FsGrants, WasiCtxView<'_>) {
+    (
+        &mut state.fs_grants,
+        WasiCtxView {
+            ctx: &mut state.ctx,
+            table: &mut state.table,
+        },
+    )
+}
+
+pub(crate) fn lower<T>(
+    view: &mut WasiCtxView<'_>,
+    result: FsResult<T>,
+) -> Result<(Result<T, ErrorCode>,)> {
+    match result {
+        Ok(value) => Ok((Ok(value),)),
+        Err(error) => Ok((Err(view.convert_error_code(error)?),)),
+    }
+}
+
+fn borrow(fd: &Resource<Descriptor>) -> Resource<Descriptor> {
+    Resource::new_borrow(fd.rep())
+}
+
+async fn is_directory(view: &mut WasiCtxView<'_>, fd: &Resource<Descriptor>) -> bool {
+    matches!(
+        HostDescriptor::get_type(view, borrow(fd)).await,
+        Ok(DescriptorType::Directory)
+    )
+}
+
+fn add_shadows(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    linker
+        .instance(PREOPENS_INTERFACE)?
+        .func_wrap("get-directories", |mut store, (): ()| {
+            let (grants, mut view) = split(&mut store.data_mut().inner);
+            let directories = view.get_directories()?;
+            for (fd, guest_path) in &directories {
+                grants.track_preopen(fd.rep(), guest_path);
+                grants.mounts.track_preopen(fd.rep(), guest_path);
+            }
+            Ok((directories,))
+        })?;
+
+    let mut types = linker.instance(TYPES_INTERFACE)?;
+
+    types.func_wrap_async(
+        "[method]descriptor.open-at",
+        |mut store,
+         (fd, path_flags, path, oflags, flags): (
+            Resource<Descriptor>,
+            PathFlags,
+            String,
+            OpenFlags,
+            DescriptorFlags,
+        )| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                if let Err(code) = grants.open_files.check(view.table) {
+                    return Ok((Err(code),));
+                }
+                if let Some(handle) = grants.mounts.handle(fd.rep()) {
+                    let result = grants
+                        .mounts
+                        .open_at(&mut view, handle, fd, &path, oflags, flags)
+                        .await;
+                    if let Ok(opened) = &result {
+                        grants.descriptors.remove(&opened.rep());
+                        grants.open_files.opened(opened.rep());
+                    }
+                    return lower(&mut view, result);
+                }
+                let location = match grants.resolve("open-at", fd.rep(), &path).await {
+                    Ok(location) => location,
+                    Err(code) => return Ok((Err(code),)),
+                };
+                let follow = path_flags.contains(PathFlags::SYMLINK_FOLLOW);
+                let Some(location) = location else {
+                    let result =
+                        HostDescriptor::open_at(&mut view, fd, path_flags, path, oflags, flags)
+                            .await;
+                    if let Ok(opened) = &result {
+                        // Forget a tracked or mounted descriptor that previously had this handle.
+                        grants.descriptors.remove(&opened.rep());
+                        grants.mounts.forget(opened.rep());
+                        grants.open_files.opened(opened.rep());
+                    }
+                    return lower(&mut view, result);
+                };
+
+                let real = match grants.harden("open-at", &location, &path, follow).await {
+                    Ok(real) => real,
+                    Err(code) => return Ok((Err(code),)),
+                };
+                let matches = grants.allows(&location) && grants.allows(&real);
+                let modifies = oflags.intersects(OpenFlags::CREATE | OpenFlags::TRUNCATE)
+                    || flags.intersects(DescriptorFlags::WRITE | DescriptorFlags::MUTATE_DIRECTORY);
+                if !matches && modifies && !grants.ask(&real).await {
+                    return Ok((Err(ErrorCode::Access),));
+                }
+                let result =
+                    HostDescriptor::open_at(&mut view, fd, path_flags, path, oflags, flags).await;
+                let opened = match result {
+                    Ok(opened) => opened,
+                    Err(error) => return lower(&mut view, Err(error)),
+                };
+                // Non-matching paths may only be opened to traverse directories, unless the
+                // user grants them.
+                if !matches && !is_directory(&mut view, &opened).await && !grants.ask(&real).await {
+                    HostDescriptor::drop(&mut view, opened)?;
+                    return Ok((Err(ErrorCode::Access),));
+                }
+                grants.descriptors.insert(opened.rep(), real);
+                grants.mounts.forget(opened.rep());
+                grants.open_files.opened(opened.rep());
+                Ok((Ok(opened),))
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.stat-at",
+        |mut store, (fd, path_flags, path): (Resource<Descriptor>, PathFlags, String)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                if let Some(handle) = grants.mounts.handle(fd.rep()) {
+                    let result = grants.mounts.stat_at(handle, &path);
+                    return lower(&mut view, result);
+                }
+                let follow = path_flags.contains(PathFlags::SYMLINK_FOLLOW);
+                let denied = match grants.check("stat-at", fd.rep(), &path, follow).await {
+                    Err(ErrorCode::Access) => Some(ErrorCode::Access),
+                    Err(code) => return Ok((Err(code),)),
+                    Ok(()) => None,
+                };
+                let result = HostDescriptor::stat_at(&mut view, fd, path_flags, path).await;
+                match (result, denied) {
+                    (Ok(stat), Some(code)) if stat.type_ != DescriptorType::Directory => {
+                        Ok((Err(code),))
+                    }
+                    (result, _) => lower::<DescriptorStat>(&mut view, result),
+                }
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.metadata-hash-at",
+        |mut store, (fd, path_flags, path): (Resource<Descriptor>, PathFlags, String)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                if let Some(handle) = grants.mounts.handle(fd.rep()) {
+                    let result = grants.mounts.metadata_hash_at(handle, &path);
+                    return lower(&mut view, result);
+                }
+                let follow = path_flags.contains(PathFlags::SYMLINK_FOLLOW);
+                if let Err(code) = grants
+                    .check("metadata-hash-at", fd.rep(), &path, follow)
+                    .await
+                {
+                    if code != ErrorCode::Access {
+                        return Ok((Err(code),));
+                    }
+                    let stat =
+                        HostDescriptor::stat_at(&mut view, borrow(&fd), path_flags, path.clone())
+                            .await;
+                    if !matches!(stat, Ok(stat) if stat.type_ == DescriptorType::Directory) {
+                        return Ok((Err(code),));
+                    }
+                }
+                let result =
+                    HostDescriptor::metadata_hash_at(&mut view, fd, path_flags, path).await;
+                lower::<MetadataHashValue>(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.read-directory",
+        |mut store, (fd,): (Resource<Descriptor>,)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                if let Some(handle) = grants.mounts.handle(fd.rep()) {
+                    let result = grants.mounts.read_directory(&mut view, handle, fd).await;
+                    if let Ok(stream) = &result {
+                        grants.streams.remove(&stream.rep());
+                    }
+                    return lower(&mut view, result);
+                }
+                let location = grants.descriptors.get(&fd.rep()).cloned();
+                let result = HostDescriptor::read_directory(&mut view, fd).await;
+                if let Ok(stream) = &result {
+                    grants.mounts.forget_stream(stream.rep());
+                }
+                match (&result, location) {
+                    (Ok(stream), Some(location)) => {
+                        grants.streams.insert(stream.rep(), location);
+                    }
+                    (Ok(stream), None) => {
+                        grants.streams.remove(&stream.rep());
+                    }
+                    _ => {}
+                }
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]directory-entry-stream.read-directory-entry",
+        |mut store, (stream,): (Resource<DirectoryEntryStream>,)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                if let Some(entry) = grants.mounts.read_directory_entry(stream.rep()) {
+                    return Ok((Ok(entry),));
+                }
+                let location = grants.streams.get(&stream.rep()).cloned();
+                loop {
+                    let result = HostDirectoryEntryStream::read_directory_entry(
+                        &mut view,
+                        Resource::new_borrow(stream.rep()),
+                    )
+                    .await;
+                    // Hide files that don't match; directories may contain matches.
+                    if let (Ok(Some(entry)), Some(location)) = (&result, &location) {
+                        let entry_location = Location {
+                            root: location.root,
+                            rel: location.rel.join(&entry.name),
+                        };
+                        if entry.type_ != DescriptorType::Directory
+                            && !grants.allows(&entry_location)
+                        {
+                            continue;
+                        }
+                    }
+                    return lower::<Option<DirectoryEntry>>(&mut view, result);
+                }
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.readlink-at",
+        |mut store, (fd, path): (Resource<Descriptor>, String)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                if let Some(handle) = grants.mounts.handle(fd.rep()) {
+                    let result = grants.mounts.readlink_at(handle, &path);
+                    return lower(&mut view, result);
+                }
+                if let Err(code) = grants.check("readlink-at", fd.rep(), &path, false).await {
+                    return Ok((Err(code),));
+                }
+                let result = HostDescriptor::readlink_at(&mut view, fd, path).await;
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.unlink-file-at",
+        |mut store, (fd, path): (Resource<Descriptor>, String)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                if let Some(handle) = grants.mounts.handle(fd.rep()) {
+                    let result = grants.mounts.unlink_file_at(handle, &path);
+                    return lower(&mut view, result);
+                }
+                if let Err(code) = grants.check("unlink-file-at", fd.rep(), &path, false).await {
+                    return Ok((Err(code),));
+                }
+                let result = HostDescriptor::unlink_file_at(&mut view, fd, path).await;
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.set-times-at",
+        |mut store,
+         (fd, path_flags, path, atim, mtim): (
+            Resource<Descriptor>,
+            PathFlags,
+            String,
+            NewTimestamp,
+            NewTimestamp,
+        )| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                if let Some(handle) = grants.mounts.handle(fd.rep()) {
+                    let result = grants.mounts.set_times_at(handle, &path, mtim);
+                    return lower(&mut view, result);
+                }
+                let follow = path_flags.contains(PathFlags::SYMLINK_FOLLOW);
+                if let Err(code) = grants.check("set-times-at", fd.rep(), &path, follow).await {
+                    return Ok((Err(code),));
+                }
+                let result =
+                    HostDescriptor::set_times_at(&mut view, fd, path_flags, path, atim, mtim).await;
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.rename-at",
+        |mut store,
+         (fd, old_path, new_fd, new_path): (
+            Resource<Descriptor>,
+            String,
+            Resource<Descriptor>,
+            String,
+        )| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                if let Some(result) =
+                    grants
+                        .mounts
+                        .rename_at(fd.rep(), &old_path, new_fd.rep(), &new_path)
+                {
+                    return lower(&mut view, result);
+                }
+                for (rep, path) in [(fd.rep(), &old_path), (new_fd.rep(), &new_path)] {
+                    if let Err(code) = grants.check("rename-at", rep, path, false).await {
+                        return Ok((Err(code),));
+                    }
+                }
+                let result =
+                    HostDescriptor::rename_at(&mut view, fd, old_path, new_fd, new_path).await;
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.link-at",
+        |mut store,
+         (fd, old_path_flags, old_path, new_fd, new_path): (
+            Resource<Descriptor>,
+            PathFlags,
+            String,
+            Resource<Descriptor>,
+            String,
+        )| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                let follow = old_path_flags.contains(PathFlags::SYMLINK_FOLLOW);
+                for (rep, path, follow) in [
+                    (fd.rep(), &old_path, follow),
+                    (new_fd.rep(), &new_path, false),
+                ] {
+                    if let Err(code) = grants.check("link-at", rep, path, follow).await {
+                        return Ok((Err(code),));
+                    }
+                }
+                let result = HostDescriptor::link_at(
+                    &mut view,
+                    fd,
+                    old_path_flags,
+                    old_path,
+                    new_fd,
+                    new_path,
+                )
+                .await;
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.symlink-at",
+        |mut store, (fd, src_path, dest_path): (Resource<Descriptor>, String, String)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                // The link target is resolved relative to the directory holding the link.
+                let target = Path::new(&dest_path)
+                    .parent()
+                    .unwrap_or(Path::new(""))
+                    .join(&src_path)
+                    .to_string_lossy()
+                    .into_owned();
+                for path in [dest_path.as_str(), target.as_str()] {
+                    if let Err(code) = grants.check("symlink-at", fd.rep(), path, false).await {
+                        return Ok((Err(code),));
+                    }
+                }
+                let result = HostDescriptor::symlink_at(&mut view, fd, src_path, dest_path).await;
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_glob() {
+        assert_eq!(split_glob("work/agent"), ("work/agent".to_string(), None));
+        assert_eq!(
+            split_glob("work/agent/**"),
+            ("work/agent".to_string(), None)
+        );
+        let (base, filter) = split_glob("/home/me/projects/**/*.md");
+        assert_eq!(base, "/home/me/projects");
+        assert_eq!(filter, Some(PathFilter::new(["**/*.md".to_string()])));
+        let (base, _) = split_glob("/*.log");
+        assert_eq!(base, "/");
+    }
+
+    #[test]
+    fn test_path_filter_matches() {
+        let filter = PathFilter::new(["**/*.md".to_string(), "config/?.yaml".to_string()]);
+        assert!(filter.matches(Path::new("README.md")));
+        assert!(filter.matches(Path::new("a/b/c/notes.md")));
+        assert!(!filter.matches(Path::new("a/b/secret.txt")));
+        assert!(!filter.matches(Path::new("notes.md/key.pem")));
+        assert!(filter.matches(Path::new("config/a.yaml")));
+        assert!(!filter.matches(Path::new("config/ab.yaml")));
+        assert!(!filter.matches(Path::new("config/nested/a.yaml")));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_stays_below_root() {
+        let mut grants = FsGrants::new(
+            &[PreopenedDir {
+                host_path: PathBuf::from("/srv/docs"),
+                guest_path: "/srv/docs".to_string(),
+                dir_perms: wasmtime_wasi::DirPerms::READ,
+                file_perms: wasmtime_wasi::FilePerms::READ,
+                path_filter: Some(PathFilter::new(["*.md".to_string()])),
+            }],
+            &[],
+        );
+        grants.track_preopen(7, "/srv/docs");
+
+        let location = grants
+            .resolve("open-at", 7, "guide/../intro.md")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(location.rel, PathBuf::from("intro.md"));
+        assert!(grants.allows(&location));
+        assert_eq!(
+            grants.resolve("open-at", 7, "../etc/passwd").await,
+            Err(ErrorCode::NotPermitted)
+        );
+        assert_eq!(
+            grants.resolve("open-at", 7, "/etc/passwd").await,
+            Err(ErrorCode::NotPermitted)
+        );
+        assert_eq!(grants.resolve("open-at", 8, "anything").await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_granted_file() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("notes.md"), "# Notes").unwrap();
+        std::fs::write(root.path().join("secret.txt"), "x").unwrap();
+        let guest = root.path().to_string_lossy().to_string();
+        let grants = FsGrants::new(
+            &[PreopenedDir {
+                host_path: root.path().to_path_buf(),
+                guest_path: guest.clone(),
+                dir_perms: wasmtime_wasi::DirPerms::READ,
+                file_perms: wasmtime_wasi::FilePerms::READ,
+                path_filter: Some(PathFilter::new(["*.md".to_string()])),
+            }],
+            &[],
+        );
+
+        let file = grants.granted_file(&format!("{guest}/notes.md")).unwrap();
+        assert_eq!(file.read(1024).await.unwrap(), b"# Notes");
+        assert!(file.read(3).await.is_err());
+        assert!(grants.granted_file(&format!("{guest}/secret.txt")).is_err());
+        assert!(grants
+            .granted_file(&format!("{guest}/../notes.md"))
+            .is_err());
+        assert!(grants.granted_file("/etc/passwd").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlink_escapes_are_refused_and_recorded() {
+        use std::os::unix::fs::symlink;
+
+        use crate::fs_audit::{self, FsHardening};
+
+        let plugin_dir = tempfile::tempdir().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret"), "x").unwrap();
+        std::fs::write(root.path().join("inside.txt"), "x").unwrap();
+        symlink(outside.path().join("secret"), root.path().join("link")).unwrap();
+        symlink(outside.path().join("new"), root.path().join("dangling")).unwrap();
+        symlink("inside.txt", root.path().join("ok-link")).unwrap();
+
+        let mut grants = FsGrants::new(
+            &[PreopenedDir {
+                host_path: root.path().to_path_buf(),
+                guest_path: "/data".to_string(),
+                dir_perms: wasmtime_wasi::DirPerms::all(),
+                file_perms: wasmtime_wasi::FilePerms::all(),
+                path_filter: None,
+            }],
+            &[],
+        );
+        grants.audit = EscapeAudit::new("fetch", FsHardening::Enforce, plugin_dir.path());
+        grants.track_preopen(3, "/data");
+
+        let refused = Err(ErrorCode::NotPermitted);
+        assert_eq!(grants.check("open-at", 3, "link", true).await, refused);
+        assert_eq!(grants.check("open-at", 3, "dangling", true).await, refused);
+        assert_eq!(grants.check("open-at", 3, "../x", true).await, refused);
+        // The link itself is inside the preopen.
+        assert_eq!(
+            grants.check("unlink-file-at", 3, "link", false).await,
+            Ok(())
+        );
+        assert_eq!(grants.check("open-at", 3, "ok-link", true).await, Ok(()));
+
+        let attempts = fs_audit::list(plugin_dir.path()).await.unwrap();
+        let kinds: Vec<EscapeKind> = attempts.iter().map(|attempt| attempt.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                EscapeKind::Symlink,
+                EscapeKind::Symlink,
+                EscapeKind::Traversal
+            ]
+        );
+        assert_eq!(attempts[0].preopen, "/data");
+        assert!(attempts.iter().all(|attempt| attempt.blocked));
+
+        grants.audit = EscapeAudit::new("fetch", FsHardening::Audit, plugin_dir.path());
+        assert_eq!(grants.check("open-at", 3, "link", true).await, Ok(()));
+        let attempts = fs_audit::list(plugin_dir.path()).await.unwrap();
+        assert!(!attempts[3].blocked);
+    }
+}
@@ -0,0 +1,364 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Finding the files left behind by components that are no longer installed.
+//!
+//! Unloading a component removes the component and its policy, but its state, vectors,
+//! presets, logs, core dumps, profiles, kept versions, secrets and configuration stay, as do
+//! the files of components whose `.wasm` was deleted by hand. On a long-lived install they pile
+//! up, and a component installed later under the same id silently inherits them. A component
+//! is installed while its `.wasm` is in the plugin directory; every other file named after, or
+//! recording, a component id that isn't is orphaned.
+//!
+//! Secrets kept in the OS keyring or in Vault can't be listed, so they aren't scanned and the
+//! result says so. Scratch mounts live in memory with their instance and leave nothing behind.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::coredump::{self, CoreDumpRecord, COREDUMPS_DIR};
+use crate::output::LOGS_DIR;
+use crate::profiling::PROFILES_DIR;
+use crate::runtime_config::CONFIG_DIR;
+use crate::secrets::sanitize_component_id;
+use crate::versions::VERSIONS_DIR;
+use crate::{DATA_DIR, METADATA_EXT, PRECOMPILED_EXT};
+
+/// What an orphaned file held for its component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OrphanKind {
+    /// Policy, including the permissions granted at runtime
+    Policy,
+    /// Where the policy was attached from
+    PolicyMetadata,
+    /// Tool index entry
+    Metadata,
+    /// Compiled component cache
+    Precompiled,
+    /// Data directory, holding the `wasi:keyvalue` state
+    State,
+    /// Vector collections
+    Vectors,
+    /// Per-tool argument presets
+    Presets,
+    /// Manifest the component was installed from
+    Manifest,
+    /// Where the component is updated from
+    Source,
+    /// Output captured by the `file` sink
+    Log,
+    /// Core dump of a trapped call, or its record
+    CoreDump,
+    /// Guest profile of a call
+    Profile,
+    /// Directory of the versions kept to roll back to
+    Versions,
+    /// Secrets
+    Secrets,
+    /// Configuration set with `wassette config set`
+    Config,
+}
+
+/// Files in the plugin directory named `<component id>.<extension>`, by extension.
+const PLUGIN_DIR_FILES: &[(&str, OrphanKind)] = &[
+    ("policy.yaml", OrphanKind::Policy),
+    ("policy.meta.json", OrphanKind::PolicyMetadata),
+    (METADATA_EXT, OrphanKind::Metadata),
+    (PRECOMPILED_EXT, OrphanKind::Precompiled),
+    ("vectors.json", OrphanKind::Vectors),
+    ("presets.json", OrphanKind::Presets),
+    ("manifest.json", OrphanKind::Manifest),
+    ("source.json", OrphanKind::Source),
+];
+
+/// A file of a component that is no longer installed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OrphanedFile {
+    /// Id of the component the file belonged to
+    pub component_id: String,
+    /// What the file held
+    pub kind: OrphanKind,
+    /// Where the file is, or the directory for [`OrphanKind::Versions`] and
+    /// [`OrphanKind::State`]
+    pub path: PathBuf,
+}
+
+/// What the components that are no longer installed left behind.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Orphans {
+    /// Their files
+    pub files: Vec<OrphanedFile>,
+    /// Aliases standing for them, mapped to the component id
+    pub aliases: BTreeMap<String, String>,
+    /// Stores that may hold more of what they left behind but weren't scanned
+    pub not_scanned: Vec<String>,
+}
+
+impl Orphans {
+    /// Whether nothing was left behind.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.aliases.is_empty()
+    }
+}
+
+/// The component id and kind of a file in the plugin directory, if it belongs to a component.
+fn plugin_dir_file(name: &str) -> Option<(&str, OrphanKind)> {
+    PLUGIN_DIR_FILES.iter().find_map(|(extension, kind)| {
+        let id = name.strip_suffix(extension)?.strip_suffix('.')?;
+        (!id.is_empty()).then_some((id, *kind))
+    })
+}
+
+/// The component id of a log written by the `file` sink without a path of its own:
+/// `<id>.<stream>.log`, or `<id>.<stream>.log.<n>` once rotated.
+fn log_file(name: &str) -> Option<&str> {
+    let name = match name.rsplit_once('.') {
+        Some((rest, n)) if n.chars().all(|c| c.is_ascii_digit()) => rest,
+        _ => name,
+    };
+    let stem = name.strip_suffix(".log")?;
+    let id = stem
+        .strip_suffix(".stdout")
+        .or_else(|| stem.strip_suffix(".stderr"))?;
+    (!id.is_empty()).then_some(id)
+}
+
+/// The component id of a profile, named `<id>-<call id>.json` with a call id made of two
+/// hexadecimal parts.
+fn profile_file(name: &str) -> Option<&str> {
+    let mut parts = name.strip_suffix(".json")?.rsplitn(3, '-');
+    let seq = parts.next()?;
+    let millis = parts.next()?;
+    let id = parts.next()?;
+    let hex = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_hexdigit());
+    (hex(seq) && hex(millis) && !id.is_empty()).then_some(id)
+}
+
+/// Names of the files in `dir`, empty if it doesn't exist.
+fn file_names(dir: &Path) -> Result<Vec<String>> {
+    entry_names(dir, false)
+}
+
+/// Names of the directories in `dir`, empty if it doesn't exist.
+fn dir_names(dir: &Path) -> Result<Vec<String>> {
+    entry_names(dir, true)
+}
+
+fn entry_names(dir: &Path, dirs: bool) -> Result<Vec<String>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if (dirs && file_type.is_dir()) || (!dirs && file_type.is_file()) {
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// The files in `plugin_dir`, and with `secrets_dir` the secrets and configuration, of
+/// components that aren't in `installed`.
+pub(crate) fn find_orphaned_files(
+    plugin_dir: &Path,
+    secrets_dir: Option<&Path>,
+    installed: &HashSet<String>,
+) -> Result<Vec<OrphanedFile>> {
+    let mut orphans = Vec::new();
+    let mut orphan = |component_id: &str, kind, path: PathBuf| {
+        orphans.push(OrphanedFile {
+            component_id: component_id.to_string(),
+            kind,
+            path,
+        })
+    };
+
+    for name in file_names(plugin_dir)? {
+        if let Some((id, kind)) = plugin_dir_file(&name) {
+            if !installed.contains(id) {
+                orphan(id, kind, plugin_dir.join(&name));
+            }
+        }
+    }
+    let logs_dir = plugin_dir.join(LOGS_DIR);
+    for name in file_names(&logs_dir)? {
+        if let Some(id) = log_file(&name) {
+            if !installed.contains(id) {
+                orphan(id, OrphanKind::Log, logs_dir.join(&name));
+            }
+        }
+    }
+    // Core dumps are named after the call; their record names the component.
+    let coredumps_dir = plugin_dir.join(COREDUMPS_DIR);
+    for name in file_names(&coredumps_dir)? {
+        let Some(call_id) = name.strip_suffix(".json") else {
+            continue;
+        };
+        let record = std::fs::read(coredumps_dir.join(&name))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<CoreDumpRecord>(&bytes).ok());
+        if let Some(record) = record {
+            if !installed.contains(&record.component_id) {
+                let dump = coredump::dump_path(&coredumps_dir, call_id);
+                if dump.exists() {
+                    orphan(record.component_id.as_str(), OrphanKind::CoreDump, dump);
+                }
+                orphan(
+                    record.component_id.as_str(),
+                    OrphanKind::CoreDump,
+                    coredumps_dir.join(&name),
+                );
+            }
+        }
+    }
+    let profiles_dir = plugin_dir.join(PROFILES_DIR);
+    for name in file_names(&profiles_dir)? {
+        if let Some(id) = profile_file(&name) {
+            if !installed.contains(id) {
+                orphan(id, OrphanKind::Profile, profiles_dir.join(&name));
+            }
+        }
+    }
+    let data_dir = plugin_dir.join(DATA_DIR);
+    for id in dir_names(&data_dir)? {
+        if !installed.contains(&id) {
+            orphan(id.as_str(), OrphanKind::State, data_dir.join(&id));
+        }
+    }
+    let versions_dir = plugin_dir.join(VERSIONS_DIR);
+    for id in dir_names(&versions_dir)? {
+        if !installed.contains(&id) {
+            orphan(id.as_str(), OrphanKind::Versions, versions_dir.join(&id));
+        }
+    }
+
+    if let Some(secrets_dir) = secrets_dir {
+        // Secrets files are named after a sanitized form of the id.
+        let sanitized: HashSet<String> = installed
+            .iter()
+            .map(|id| sanitize_component_id(id))
+            .collect();
+        for name in file_names(secrets_dir)? {
+            if let Some(id) = name.strip_suffix(".yaml") {
+                if !sanitized.contains(id) {
+                    orphan(id, OrphanKind::Secrets, secrets_dir.join(&name));
+                }
+            }
+        }
+        let config_dir = secrets_dir.join(CONFIG_DIR);
+        for name in file_names(&config_dir)? {
+            if let Some(id) = name.strip_suffix(".yaml") {
+                if !installed.contains(id) {
+                    orphan(id, OrphanKind::Config, config_dir.join(&name));
+                }
+            }
+        }
+    }
+    Ok(orphans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files_of_uninstalled_components_are_orphaned() -> Result<()> {
+        let plugin_dir = tempfile::tempdir()?;
+        let secrets_dir = tempfile::tempdir()?;
+        let plugin = plugin_dir.path();
+        std::fs::create_dir_all(plugin.join(LOGS_DIR))?;
+        std::fs::create_dir_all(plugin.join(COREDUMPS_DIR))?;
+        std::fs::create_dir_all(plugin.join(PROFILES_DIR))?;
+        std::fs::create_dir_all(plugin.join(VERSIONS_DIR).join("fetch"))?;
+        std::fs::create_dir_all(plugin.join(DATA_DIR).join("fetch"))?;
+        std::fs::create_dir_all(plugin.join(DATA_DIR).join("time.server"))?;
+        std::fs::create_dir_all(plugin.join(VERSIONS_DIR).join("time.server").join("ab12"))?;
+        std::fs::create_dir_all(secrets_dir.path().join(CONFIG_DIR))?;
+        for file in [
+            "fetch.wasm",
+            "fetch.policy.yaml",
+            "data/fetch/keyvalue.redb",
+            "data/time.server/keyvalue.redb",
+            "logs/fetch.stdout.log",
+            "time.server.policy.yaml",
+            "time.server.policy.meta.json",
+            "time.server.metadata.json",
+            "time.server.cwasm",
+            "time.server.presets.json",
+            "logs/time.server.stderr.log.2",
+            "logs/shared.log",
+            "coredumps/18f2a-0001.coredump",
+            "coredumps/18f2a-0002.coredump",
+            "profiles/fetch-18f2a-0003.json",
+            "profiles/time.server-18f2a-0004.json",
+            "profiles/notes.json",
+            "state.json",
+            "wassette.lock",
+        ] {
+            std::fs::write(plugin.join(file), "")?;
+        }
+        for (call_id, component_id) in [("18f2a-0001", "fetch"), ("18f2a-0002", "time.server")] {
+            let record = CoreDumpRecord {
+                call_id: call_id.to_string(),
+                component_id: component_id.to_string(),
+                function_name: "run".to_string(),
+                error: "unreachable".to_string(),
+                created_at: 0,
+                size: 0,
+            };
+            std::fs::write(
+                plugin.join(COREDUMPS_DIR).join(format!("{call_id}.json")),
+                serde_json::to_vec(&record)?,
+            )?;
+        }
+        for file in ["fetch.yaml", "gone.yaml", "state.key", "config/gone.yaml"] {
+            std::fs::write(secrets_dir.path().join(file), "")?;
+        }
+        let installed = HashSet::from(["fetch".to_string()]);
+
+        let orphans = find_orphaned_files(plugin, None, &installed)?;
+        let found: Vec<_> = orphans
+            .iter()
+            .map(|orphan| (orphan.component_id.as_str(), orphan.kind))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("time.server", OrphanKind::Precompiled),
+                ("time.server", OrphanKind::Metadata),
+                ("time.server", OrphanKind::PolicyMetadata),
+                ("time.server", OrphanKind::Policy),
+                ("time.server", OrphanKind::Presets),
+                ("time.server", OrphanKind::Log),
+                ("time.server", OrphanKind::CoreDump),
+                ("time.server", OrphanKind::CoreDump),
+                ("time.server", OrphanKind::Profile),
+                ("time.server", OrphanKind::State),
+                ("time.server", OrphanKind::Versions),
+            ]
+        );
+
+        let orphans = find_orphaned_files(plugin, Some(secrets_dir.path()), &installed)?;
+        let in_secrets_dir: Vec<_> = orphans
+            .iter()
+            .filter(|orphan| orphan.path.starts_with(secrets_dir.path()))
+            .map(|orphan| (orphan.component_id.as_str(), orphan.kind))
+            .collect();
+        assert_eq!(
+            in_secrets_dir,
+            vec![("gone", OrphanKind::Secrets), ("gone", OrphanKind::Config)]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! History of the versions of installed components.
+//!
+//! Whenever a load swaps in an artifact other than the one installed, the change is appended
+//! to `audit/versions.jsonl` under the plugin directory: the old and new artifact digests,
+//! where the new one came from and, when known, its release version and changelog. Images
+//! pulled from OCI registries carry these in the `org.opencontainers.image.version` and
+//! [`CHANGELOG_ANNOTATION`] annotations of their manifest; components installed from a signed
+//! manifest take the manifest's version. A version restored after its update failed a health
+//! check, or put back on request, is recorded as a rollback. The history answers questions such as whether a tool
+//! changed yesterday when investigating an incident.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::fs_audit::{self, AUDIT_DIR};
+
+/// Annotation of an OCI image manifest holding the changelog of the release.
+pub const CHANGELOG_ANNOTATION: &str = "dev.wassette.changelog";
+
+/// Standard annotation of an OCI image manifest holding the release version.
+const VERSION_ANNOTATION: &str = "org.opencontainers.image.version";
+
+const VERSIONS_FILE: &str = "versions.jsonl";
+
+/// Longest changelog kept, in bytes; longer ones are cut.
+const MAX_CHANGELOG_BYTES: usize = 16 * 1024;
+
+/// Extension of the file a download keeps its [`Release`] in, next to the component.
+pub(crate) const RELEASE_EXTENSION: &str = "release.json";
+
+/// How a component's version changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VersionChangeKind {
+    /// The component was installed where none was
+    Install,
+    /// A new version replaced the installed one
+    Update,
+    /// An earlier version was put back, on request or after an update failed its health check
+    Rollback,
+}
+
+/// What the publisher says about a release.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Release {
+    /// Release version, e.g. `1.4.0`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// What changed in the release
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changelog: Option<String>,
+}
+
+impl Release {
+    /// The release described by the annotations of an image manifest, if they describe one.
+    pub(crate) fn from_annotations(annotations: Option<&BTreeMap<String, String>>) -> Option<Self> {
+        let annotation = |key| {
+            annotations
+                .and_then(|annotations| annotations.get(key))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+        let release = Self {
+            version: annotation(VERSION_ANNOTATION).map(str::to_string),
+            changelog: annotation(CHANGELOG_ANNOTATION).map(|changelog| {
+                let mut end = changelog.len().min(MAX_CHANGELOG_BYTES);
+                while !changelog.is_char_boundary(end) {
+                    end -= 1;
+                }
+                changelog[..end].to_string()
+            }),
+        };
+        (release != Self::default()).then_some(release)
+    }
+}
+
+/// A change of the installed version of a component.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionChange {
+    /// The component whose version changed
+    pub component_id: String,
+    /// How it changed
+    pub kind: VersionChangeKind,
+    /// URI the new version was loaded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Digest of the artifact replaced, as `sha256:<hex>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_digest: Option<String>,
+    /// Digest of the artifact installed, as `sha256:<hex>`
+    pub digest: String,
+    /// Release version of the new version, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Changelog of the new version, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changelog: Option<String>,
+    /// Time of the change (seconds since epoch)
+    pub created_at: u64,
+}
+
+impl VersionChange {
+    /// The change from the artifact with `previous_digest`, if any, to the one with `digest`.
+    pub(crate) fn new(
+        component_id: &str,
+        kind: VersionChangeKind,
+        previous_digest: Option<String>,
+        digest: String,
+    ) -> Self {
+        Self {
+            component_id: component_id.to_string(),
+            kind,
+            source: None,
+            previous_digest,
+            digest,
+            version: None,
+            changelog: None,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Note that the new version came from `source` as `release`.
+    pub(crate) fn with_release(mut self, source: &str, release: Option<Release>) -> Self {
+        let release = release.unwrap_or_default();
+        self.source = Some(source.to_string());
+        self.version = release.version;
+        self.changelog = release.changelog;
+        self
+    }
+}
+
+fn log_path(plugin_dir: &Path) -> PathBuf {
+    plugin_dir.join(AUDIT_DIR).join(VERSIONS_FILE)
+}
+
+/// Append `change` to the history under `plugin_dir`.
+pub(crate) fn record(plugin_dir: &Path, change: &VersionChange) -> Result<()> {
+    fs_audit::append(&log_path(plugin_dir), change)
+}
+
+/// The version changes recorded under `plugin_dir`, newest first: those of `component_id`
+/// only if given, and at most `limit` of them. Unreadable lines are skipped.
+pub(crate) async fn list(
+    plugin_dir: &Path,
+    component_id: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Vec<VersionChange>> {
+    let changes: Vec<VersionChange> = fs_audit::read_records(&log_path(plugin_dir)).await?;
+    Ok(changes
+        .into_iter()
+        .rev()
+        .filter(|change| component_id.is_none_or(|id| change.component_id == id))
+        .take(limit.unwrap_or(usize::MAX))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_from_annotations() {
+        assert_eq!(Release::from_annotations(None), None);
+
+        let mut annotations = BTreeMap::from([
+            (VERSION_ANNOTATION.to_string(), " 1.4.0 ".to_string()),
+            (
+                "org.opencontainers.image.source".to_string(),
+                "https://github.com/acme/fetch".to_string(),
+            ),
+        ]);
+        assert_eq!(
+            Release::from_annotations(Some(&annotations)),
+            Some(Release {
+                version: Some("1.4.0".to_string()),
+                changelog: None,
+            })
+        );
+
+        annotations.insert(
+            CHANGELOG_ANNOTATION.to_string(),
+            "é".repeat(MAX_CHANGELOG_BYTES),
+        );
+        let release = Release::from_annotations(Some(&annotations)).unwrap();
+        assert_eq!(release.changelog.unwrap().len(), MAX_CHANGELOG_BYTES);
+
+        annotations.clear();
+        annotations.insert(VERSION_ANNOTATION.to_string(), " ".to_string());
+        assert_eq!(Release::from_annotations(Some(&annotations)), None);
+    }
+
+    #[tokio::test]
+    async fn test_history_is_listed_newest_first() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let release = Release {
+            version: Some("2.0.0".to_string()),
+            changelog: Some("Follow redirects".to_string()),
+        };
+        for change in [
+            VersionChange::new("fetch", VersionChangeKind::Install, None, "sha256:a".into())
+                .with_release("oci://ghcr.io/acme/fetch:1", None),
+            VersionChange::new("time", VersionChangeKind::Install, None, "sha256:t".into()),
+            VersionChange::new(
+                "fetch",
+                VersionChangeKind::Update,
+                Some("sha256:a".into()),
+                "sha256:b".into(),
+            )
+            .with_release("oci://ghcr.io/acme/fetch:2", Some(release)),
+        ] {
+            record(dir.path(), &change)?;
+        }
+
+        let all = list(dir.path(), None, None).await?;
+        let digests: Vec<&str> = all.iter().map(|change| change.digest.as_str()).collect();
+        assert_eq!(digests, ["sha256:b", "sha256:t", "sha256:a"]);
+        assert_eq!(all[0].changelog.as_deref(), Some("Follow redirects"));
+        assert_eq!(all[0].previous_digest.as_deref(), Some("sha256:a"));
+
+        let fetch = list(dir.path(), Some("fetch"), Some(1)).await?;
+        assert_eq!(fetch.len(), 1);
+        assert_eq!(fetch[0].version.as_deref(), Some("2.0.0"));
+        assert!(list(dir.path(), Some("missing"), None).await?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Operator hooks fired on component lifecycle events.
+//!
+//! A hook either runs a local command with the event as JSON on stdin or POSTs the event JSON
+//! to a URL, which lets operators feed wassette events into chat alerts or inventory systems.
+//! Hooks run in the background: they never hold up or fail the operation that fired them, and
+//! a hook that fails or outlives its timeout is logged and abandoned.
+
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, warn};
+
+/// Seconds a hook may run before it is abandoned, unless it sets `timeout-secs`.
+pub const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 10;
+
+/// Component lifecycle events hooks can fire on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    /// A component was loaded or replaced
+    Load,
+    /// A component was unloaded
+    Unload,
+    /// A component failed to load or was refused, e.g. by its provenance pin
+    Error,
+    /// A permission was granted to a component at runtime
+    PermissionGrant,
+}
+
+/// A command or webhook run on lifecycle events.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct HookConfig {
+    /// Events the hook fires on; every event when empty
+    pub events: Vec<HookEvent>,
+    /// Program and arguments run with the event JSON on stdin
+    pub command: Vec<String>,
+    /// URL the event JSON is POSTed to
+    pub url: Option<String>,
+    /// Seconds the hook may run; defaults to [`DEFAULT_HOOK_TIMEOUT_SECS`]
+    pub timeout_secs: Option<u64>,
+}
+
+impl HookConfig {
+    /// Check that the hook has exactly one of a command or an http(s) URL.
+    pub fn validate(&self) -> Result<()> {
+        match (self.command.is_empty(), &self.url) {
+            (true, None) => bail!("Hook needs a `command` or a `url`"),
+            (false, Some(_)) => bail!("Hook can't have both a `command` and a `url`"),
+            (true, Some(url)) => {
+                let parsed =
+                    url::Url::parse(url).with_context(|| format!("Invalid hook URL '{url}'"))?;
+                if !matches!(parsed.scheme(), "http" | "https") {
+                    bail!("Hook URL '{url}' must use http or https");
+                }
+            }
+            (false, None) => {
+                if self.command[0].is_empty() {
+                    bail!("Hook command names no program");
+                }
+            }
+        }
+        if self.timeout_secs == Some(0) {
+            bail!("Hook timeout must be at least one second");
+        }
+        Ok(())
+    }
+
+    fn fires_on(&self, event: HookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs.unwrap_or(DEFAULT_HOOK_TIMEOUT_SECS))
+    }
+
+    fn describe(&self) -> String {
+        match &self.url {
+            Some(url) => url.clone(),
+            None => self.command.join(" "),
+        }
+    }
+}
+
+/// The configured hooks of a lifecycle manager.
+pub(crate) struct Hooks {
+    hooks: Arc<[HookConfig]>,
+    http_client: reqwest::Client,
+}
+
+impl Hooks {
+    pub(crate) fn new(hooks: Vec<HookConfig>, http_client: reqwest::Client) -> Self {
+        Self {
+            hooks: hooks.into(),
+            http_client,
+        }
+    }
+
+    /// Run every hook registered for `event` in the background.
+    pub(crate) fn fire(&self, event: HookEvent, component_id: Option<&str>, details: Value) {
+        if !self.hooks.iter().any(|hook| hook.fires_on(event)) {
+            return;
+        }
+        let payload = Arc::new(event_payload(event, component_id, details));
+        for (index, hook) in self.hooks.iter().enumerate() {
+            if !hook.fires_on(event) {
+                continue;
+            }
+            let hooks = Arc::clone(&self.hooks);
+            let http_client = self.http_client.clone();
+            let payload = Arc::clone(&payload);
+            tokio::spawn(async move {
+                let hook = &hooks[index];
+                let outcome =
+                    tokio::time::timeout(hook.timeout(), run_hook(hook, &http_client, &payload))
+                        .await;
+                match outcome {
+                    Ok(Ok(())) => debug!(hook = %hook.describe(), ?event, "Hook ran"),
+                    Ok(Err(error)) => {
+                        let error = format!("{error:#}");
+                        warn!(hook = %hook.describe(), ?event, %error, "Hook failed")
+                    }
+                    Err(_) => warn!(hook = %hook.describe(), ?event, "Hook timed out"),
+                }
+            });
+        }
+    }
+}
+
+/// The JSON document describing an event, as hooks receive it.
+fn event_payload(event: HookEvent, component_id: Option<&str>, details: Value) -> Vec<u8> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let payload = json!({
+        "event": event,
+        "component_id": component_id,
+        "timestamp": timestamp,
+        "details": details,
+    });
+    serde_json::to_vec(&payload).unwrap_or_default()
+}
+
+async fn run_hook(hook: &HookConfig, http_client: &reqwest::Client, payload: &[u8]) -> Result<()> {
+    if let Some(url) = &hook.url {
+        http_client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(payload.to_vec())
+            .send()
+            .await?
+            .error_for_status()?;
+        return Ok(());
+    }
+
+    let mut child = tokio::process::Command::new(&hook.command[0])
+        .args(&hook.command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start '{}'", hook.command[0]))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that doesn't read the event is fine.
+        let _ = stdin.write_all(payload).await;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        bail!(
+            "exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command_hook(command: &[&str]) -> HookConfig {
+        HookConfig {
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hook_validation() {
+        assert!(command_hook(&["notify"]).validate().is_ok());
+        assert!(HookConfig {
+            url: Some("https://hooks.example.com/wassette".to_string()),
+            ..Default::default()
+        }
+        .validate()
+        .is_ok());
+
+        assert!(HookConfig::default().validate().is_err());
+        assert!(command_hook(&[""]).validate().is_err());
+        assert!(HookConfig {
+            url: Some("file:///tmp/events".to_string()),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(HookConfig {
+            url: Some("https://hooks.example.com".to_string()),
+            ..command_hook(&["notify"])
+        }
+        .validate()
+        .is_err());
+        assert!(HookConfig {
+            timeout_secs: Some(0),
+            ..command_hook(&["notify"])
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_hook_events() {
+        let all = command_hook(&["notify"]);
+        assert!(all.fires_on(HookEvent::Load));
+        assert!(all.fires_on(HookEvent::PermissionGrant));
+
+        let hook: HookConfig = serde_json::from_value(
+            json!({"events": ["unload", "permission-grant"], "command": ["notify"]}),
+        )
+        .unwrap();
+        assert!(hook.fires_on(HookEvent::PermissionGrant));
+        assert!(hook.fires_on(HookEvent::Unload));
+        assert!(!hook.fires_on(HookEvent::Load));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_hook_receives_event_on_stdin() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let events = tempdir.path().join("events.json");
+        let hook = command_hook(&["sh", "-c", &format!("cat > {}", events.display())]);
+        let payload = event_payload(
+            HookEvent::PermissionGrant,
+            Some("fetch"),
+            json!({"permission_type": "network"}),
+        );
+
+        run_hook(&hook, &reqwest::Client::new(), &payload).await?;
+        let received: Value = serde_json::from_slice(&tokio::fs::read(&events).await?)?;
+        assert_eq!(received["event"], "permission-grant");
+        assert_eq!(received["component_id"], "fetch");
+        assert_eq!(received["details"]["permission_type"], "network");
+
+        let failing = command_hook(&["sh", "-c", "echo nope >&2; exit 3"]);
+        let error = run_hook(&failing, &reqwest::Client::new(), &payload)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("nope"));
+        Ok(())
+    }
+}
@@ -2,102 +2,146 @@
 // Licensed under the MIT license.
 
 use std::collections::HashSet;
-
-use anyhow::Result;
-use tracing::{debug, warn};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use http_body_util::BodyExt;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper_util::rt::TokioIo;
+use policy::{
+    AddressFamily, NetworkDenial, NetworkHostPermission, NetworkPermission, NetworkRequest,
+    PermissionList, RequestHeader,
+};
+use tracing::{debug, info_span, warn, Instrument};
 use url::Url;
 use wasmtime::component::{Resource, ResourceTable};
 use wasmtime_wasi::{WasiCtxView, WasiView};
 use wasmtime_wasi_http::bindings::http::types;
-use wasmtime_wasi_http::types::{HostFutureIncomingResponse, OutgoingRequestConfig};
-use wasmtime_wasi_http::{HttpResult, WasiHttpView};
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct AllowedHost {
-    scheme: Option<String>,
-    host: String,
-}
-
-impl AllowedHost {
-    fn from_str(host_str: &str) -> Result<Self> {
-        if let Ok(url) = Url::parse(host_str) {
-            Ok(AllowedHost {
-                scheme: Some(url.scheme().to_string()),
-                host: url.host_str().unwrap_or("").to_string(),
-            })
-        } else if let Ok(url) = Url::parse(&format!("http://{host_str}")) {
-            Ok(AllowedHost {
-                scheme: None,
-                host: url.host_str().unwrap_or("").to_string(),
-            })
-        } else {
-            Err(anyhow::anyhow!("Invalid host format: {}", host_str))
-        }
-    }
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::types::{
+    HostFutureIncomingResponse, IncomingResponse, OutgoingRequestConfig,
+};
+use wasmtime_wasi_http::{hyper_request_error, HttpResult, WasiHttpView};
 
-    fn matches(&self, request_host: &str, request_scheme: Option<&str>) -> bool {
-        if self.host != request_host {
-            return false;
-        }
-
-        match (&self.scheme, request_scheme) {
-            (Some(allowed_scheme), Some(req_scheme)) => allowed_scheme == req_scheme,
-            _ => true,
-        }
-    }
-}
+use crate::anomaly::OutboundRequests;
+use crate::audit::Denials;
+use crate::egress::{EgressError, EgressRoute};
+use crate::elevation::{Elevation, PermissionKind};
 
 /// WassetteWasiState is a wrapper around a WASI state that enforces network policies by filtering
-/// outgoing HTTP requests based on a list of allowed hosts from the component's policy document.
+/// outgoing HTTP requests against the network rules of the component's policy document.
 pub struct WassetteWasiState<T> {
     /// The underlying WASI state
     pub inner: T,
 
-    /// Set of allowed hosts for network requests (extracted from policy document)
-    allowed_hosts: HashSet<AllowedHost>,
+    /// Network rules for outgoing requests (from the policy document)
+    network_rules: Arc<PermissionList<NetworkPermission>>,
+
+    /// Where blocked requests are counted and audited
+    pub(crate) denials: Option<Denials>,
+
+    /// How allowed requests connect, or `None` to connect directly with the default timeouts
+    pub(crate) egress: Option<Arc<EgressRoute>>,
+
+    /// Where the hosts of allowed requests are recorded during a call, for anomaly detection
+    pub(crate) outbound: Option<Arc<OutboundRequests>>,
+
+    /// Headers the policy adds to allowed requests
+    pub(crate) request_headers: Vec<RequestHeader>,
+
+    /// Where the user is asked to grant what the policy refuses during a call
+    pub(crate) elevation: Option<Arc<Elevation>>,
 }
 
 impl<T> WassetteWasiState<T> {
-    /// Create a new WassetteWasiState with the given allowed hosts
+    /// Create a new WassetteWasiState allowing requests to the given hosts
     pub fn new(inner: T, allowed_hosts: HashSet<String>) -> Result<Self> {
-        let mut parsed_hosts = HashSet::new();
-
-        for host_str in allowed_hosts {
-            match AllowedHost::from_str(&host_str) {
-                Ok(parsed_host) => {
-                    parsed_hosts.insert(parsed_host);
-                }
-                Err(e) => {
-                    warn!("Failed to parse allowed host '{}': {}", host_str, e);
-                    return Err(e);
-                }
+        let mut allow = Vec::with_capacity(allowed_hosts.len());
+        for host in allowed_hosts {
+            let valid = match Url::parse(&host) {
+                Ok(url) => url.host_str().is_some_and(|host| !host.is_empty()),
+                Err(_) => Url::parse(&format!("http://{host}")).is_ok(),
+            };
+            if !valid {
+                warn!("Failed to parse allowed host '{}'", host);
+                bail!("Invalid host format: {}", host);
             }
+            allow.push(NetworkPermission::Host(NetworkHostPermission {
+                host,
+                ..Default::default()
+            }));
         }
-
-        Ok(Self {
+        Ok(Self::with_rules(
             inner,
-            allowed_hosts: parsed_hosts,
-        })
+            PermissionList {
+                allow: Some(allow),
+                deny: None,
+            },
+        ))
     }
 
-    /// Check if a host is allowed by the policy
-    fn is_host_allowed(&self, uri: &hyper::Uri) -> bool {
-        let request_host = if let Some(host) = uri.host() {
-            host.to_string()
-        } else {
-            return false;
-        };
+    /// Create a new WassetteWasiState enforcing the given network rules
+    pub fn with_rules(inner: T, network_rules: PermissionList<NetworkPermission>) -> Self {
+        Self {
+            inner,
+            network_rules: Arc::new(network_rules),
+            denials: None,
+            egress: None,
+            outbound: None,
+            request_headers: Vec::new(),
+            elevation: None,
+        }
+    }
 
-        let request_scheme = uri.scheme().map(|s| s.as_str());
+    /// Check a request URI against the network rules, returning the IP versions it may be
+    /// sent over
+    fn check_request(&self, uri: &hyper::Uri) -> Result<AddressFamily, NetworkDenial> {
+        self.network_rules.check_families(&network_request(uri))
+    }
 
-        let req = request_host.to_ascii_lowercase();
-        for allowed_host in &self.allowed_hosts {
-            if allowed_host.matches(&req, request_scheme) {
-                return true;
+    /// Add the policy's headers that apply to `request`, leaving those the component set
+    /// unless the policy replaces them
+    fn add_request_headers<B>(&self, request: &mut hyper::Request<B>) {
+        if self.request_headers.is_empty() {
+            return;
+        }
+        let uri = request.uri().clone();
+        let target = network_request(&uri);
+        let headers = request.headers_mut();
+        for header in &self.request_headers {
+            if !header.applies_to(&target) {
+                continue;
+            }
+            let (name, value) = match (
+                HeaderName::from_bytes(header.name.as_bytes()),
+                HeaderValue::from_bytes(header.value.as_bytes()),
+            ) {
+                (Ok(name), Ok(value)) => (name, value),
+                _ => {
+                    warn!(header = %header.name, "Skipping invalid request header of policy");
+                    continue;
+                }
+            };
+            if header.replace || !headers.contains_key(&name) {
+                headers.insert(name, value);
             }
         }
+    }
+}
 
-        false
+/// The request to `uri`, as far as network rules are concerned
+fn network_request(uri: &hyper::Uri) -> NetworkRequest<'_> {
+    let scheme = uri.scheme_str().unwrap_or("http");
+    NetworkRequest {
+        scheme,
+        host: uri.host().unwrap_or_default(),
+        port: uri
+            .port_u16()
+            .unwrap_or(if scheme.eq_ignore_ascii_case("https") {
+                443
+            } else {
+                80
+            }),
     }
 }
 
@@ -127,7 +171,7 @@ impl<T: WasiHttpView> WasiHttpView for WassetteWasiState<T> {
 
     fn send_request(
         &mut self,
-        request: hyper::Request<wasmtime_wasi_http::body::HyperOutgoingBody>,
+        mut request: hyper::Request<wasmtime_wasi_http::body::HyperOutgoingBody>,
         config: OutgoingRequestConfig,
     ) -> HttpResult<HostFutureIncomingResponse> {
         let uri = request.uri();
@@ -137,18 +181,77 @@ impl<T: WasiHttpView> WasiHttpView for WassetteWasiState<T> {
             return Err(types::ErrorCode::HttpRequestUriInvalid.into());
         }
 
-        if !self.is_host_allowed(uri) {
-            warn!(
-                uri = %uri,
-                allowed_hosts = ?self.allowed_hosts,
-                "HTTP request blocked by network policy"
-            );
-            return Err(types::ErrorCode::HttpRequestDenied.into());
-        }
-
-        debug!(uri = %uri, "HTTP request allowed by network policy");
+        let host = uri.host().unwrap_or_default().to_string();
+        let (family, elevation) = match self.check_request(uri) {
+            Ok(family) => {
+                debug!(uri = %uri, "HTTP request allowed by network policy");
+                (family, None)
+            }
+            // Hosts no rule names may be granted by the user; the request waits for them.
+            Err(denial)
+                if denial.rule.is_none()
+                    && self
+                        .elevation
+                        .as_ref()
+                        .is_some_and(|elevation| elevation.covers(PermissionKind::Network)) =>
+            {
+                (AddressFamily::Dual, self.elevation.clone())
+            }
+            Err(denial) => {
+                warn!(reason = %denial, "HTTP request blocked by network policy");
+                if let Some(denials) = &self.denials {
+                    denials.record("network", &host);
+                }
+                return Err(types::ErrorCode::HttpRequestDenied.into());
+            }
+        };
 
-        self.inner.send_request(request, config)
+        // Only the host is recorded: paths and queries may carry credentials.
+        let span = info_span!(
+            "http_request",
+            method = %request.method(),
+            host = %host,
+        );
+        self.add_request_headers(&mut request);
+        // The default handler tries a destination's addresses one at a time, so requests are
+        // sent through a route, which races them.
+        let route = self.egress.clone().unwrap_or_else(EgressRoute::direct);
+        let mut rules = self.network_rules.clone();
+        let denials = self.denials.clone();
+        let outbound = self.outbound.clone();
+        // The response is awaited in a task of its own, which doesn't inherit the guest
+        // call's span; wrap it so the span lasts until the response headers arrive.
+        let response = async move {
+            if let Some(elevation) = elevation {
+                if !elevation.ask(PermissionKind::Network, &host).await {
+                    warn!("HTTP request to a host no network rule names refused by the user");
+                    if let Some(denials) = &denials {
+                        denials.record("network", &host);
+                    }
+                    return anyhow::Ok(Err(types::ErrorCode::HttpRequestDenied));
+                }
+                // The addresses of a granted host are still held to the deny rules.
+                Arc::make_mut(&mut rules)
+                    .allow
+                    .get_or_insert_with(Vec::new)
+                    .push(NetworkPermission::Host(NetworkHostPermission {
+                        host: host.clone(),
+                        ..Default::default()
+                    }));
+            }
+            if let Some(outbound) = &outbound {
+                outbound.record(&host);
+            }
+            let response = send_through(&route, request, config, family, &rules).await;
+            if let (Err(types::ErrorCode::HttpRequestDenied), Some(denials)) = (&response, &denials)
+            {
+                denials.record("network", &host);
+            }
+            anyhow::Ok(response)
+        };
+        Ok(HostFutureIncomingResponse::pending(
+            wasmtime_wasi::runtime::spawn(response.instrument(span)),
+        ))
     }
 
     fn is_forbidden_header(&mut self, name: &hyper::header::HeaderName) -> bool {
@@ -164,6 +267,85 @@ impl<T: WasiHttpView> WasiHttpView for WassetteWasiState<T> {
     }
 }
 
+/// Send `request` over a connection opened by `route` over the IP versions of `family`, to
+/// the addresses `rules` allow, as wasi-http's default handler does over a direct one, within
+/// the timeouts of the route.
+async fn send_through(
+    route: &EgressRoute,
+    mut request: hyper::Request<HyperOutgoingBody>,
+    OutgoingRequestConfig {
+        use_tls,
+        connect_timeout,
+        first_byte_timeout,
+        between_bytes_timeout,
+    }: OutgoingRequestConfig,
+    family: AddressFamily,
+    rules: &PermissionList<NetworkPermission>,
+) -> Result<IncomingResponse, types::ErrorCode> {
+    let uri = request.uri();
+    let host = uri
+        .host()
+        .ok_or(types::ErrorCode::HttpRequestUriInvalid)?
+        .to_string();
+    let port = uri.port_u16().unwrap_or(if use_tls { 443 } else { 80 });
+    let connect_timeout = route.connect_timeout(connect_timeout);
+    let first_byte_timeout = route.read_timeout(first_byte_timeout);
+    let between_bytes_timeout = route.read_timeout(between_bytes_timeout);
+    let connection = route
+        .connect(&host, port, use_tls, family, rules, connect_timeout)
+        .await
+        .map_err(|e| {
+            match &e {
+                EgressError::Denied(denial) => {
+                    warn!(reason = %denial, "HTTP request blocked by network policy")
+                }
+                _ => warn!(%host, error = %e, "HTTP request failed to connect"),
+            }
+            egress_error_code(e)
+        })?;
+    let stream = connection.prepare(&mut request);
+
+    let (mut sender, conn) = tokio::time::timeout(
+        connect_timeout,
+        hyper::client::conn::http1::handshake(TokioIo::new(stream)),
+    )
+    .await
+    .map_err(|_| types::ErrorCode::ConnectionTimeout)?
+    .map_err(hyper_request_error)?;
+    let worker = wasmtime_wasi::runtime::spawn(async move {
+        if let Err(e) = conn.await {
+            warn!("HTTP connection failed: {e}");
+        }
+    });
+
+    let resp = tokio::time::timeout(first_byte_timeout, sender.send_request(request))
+        .await
+        .map_err(|_| types::ErrorCode::ConnectionReadTimeout)?
+        .map_err(hyper_request_error)?
+        .map(|body| body.map_err(hyper_request_error).boxed());
+    Ok(IncomingResponse {
+        resp,
+        worker: Some(worker),
+        between_bytes_timeout,
+    })
+}
+
+fn egress_error_code(error: EgressError) -> types::ErrorCode {
+    match error {
+        EgressError::Timeout => types::ErrorCode::ConnectionTimeout,
+        EgressError::Dns | EgressError::InvalidName => {
+            types::ErrorCode::DnsError(types::DnsErrorPayload {
+                rcode: Some(error.to_string()),
+                info_code: Some(0),
+            })
+        }
+        EgressError::Refused | EgressError::Tunnel(_) => types::ErrorCode::ConnectionRefused,
+        EgressError::Certificate(_) => types::ErrorCode::TlsCertificateError,
+        EgressError::Tls(_) => types::ErrorCode::TlsProtocolError,
+        EgressError::Denied(_) => types::ErrorCode::HttpRequestDenied,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
@@ -217,9 +399,9 @@ mod tests {
         let uri2: hyper::Uri = "http://other.example.com".parse().unwrap();
         let uri3: hyper::Uri = "http://malicious.com".parse().unwrap();
 
-        assert!(state.is_host_allowed(&uri1));
-        assert!(!state.is_host_allowed(&uri2));
-        assert!(!state.is_host_allowed(&uri3));
+        assert!(state.check_request(&uri1).is_ok());
+        assert!(state.check_request(&uri2).is_err());
+        assert!(state.check_request(&uri3).is_err());
     }
 
     #[test]
@@ -233,9 +415,9 @@ mod tests {
         let uri2: hyper::Uri = "https://api.example.com".parse().unwrap();
         let uri3: hyper::Uri = "http://api.example.com".parse().unwrap();
 
-        assert!(!state.is_host_allowed(&uri1));
-        assert!(state.is_host_allowed(&uri2));
-        assert!(!state.is_host_allowed(&uri3));
+        assert!(state.check_request(&uri1).is_err());
+        assert!(state.check_request(&uri2).is_ok());
+        assert!(state.check_request(&uri3).is_err());
     }
 
     #[test]
@@ -248,8 +430,8 @@ mod tests {
         let uri1: hyper::Uri = "http://api.example.com:8080".parse().unwrap();
         let uri2: hyper::Uri = "http://api.example.com:443".parse().unwrap();
 
-        assert!(state.is_host_allowed(&uri1));
-        assert!(state.is_host_allowed(&uri2));
+        assert!(state.check_request(&uri1).is_ok());
+        assert!(state.check_request(&uri2).is_ok());
     }
 
     #[test]
@@ -264,15 +446,15 @@ mod tests {
         let https_secure: hyper::Uri = "https://secure.api.com".parse().unwrap();
         let http_secure: hyper::Uri = "http://secure.api.com".parse().unwrap();
 
-        assert!(state.is_host_allowed(&https_secure));
-        assert!(!state.is_host_allowed(&http_secure));
+        assert!(state.check_request(&https_secure).is_ok());
+        assert!(state.check_request(&http_secure).is_err());
 
         // Scheme-agnostic host should match both
         let https_example: hyper::Uri = "https://api.example.com".parse().unwrap();
         let http_example: hyper::Uri = "http://api.example.com".parse().unwrap();
 
-        assert!(state.is_host_allowed(&https_example));
-        assert!(state.is_host_allowed(&http_example));
+        assert!(state.check_request(&https_example).is_ok());
+        assert!(state.check_request(&http_example).is_ok());
     }
 
     #[test]
@@ -297,7 +479,88 @@ mod tests {
         let uri1: hyper::Uri = "http://api.example.com".parse().unwrap();
         let uri2: hyper::Uri = "http://API.EXAMPLE.COM".parse().unwrap();
 
-        assert!(state.is_host_allowed(&uri1));
-        assert!(state.is_host_allowed(&uri2));
+        assert!(state.check_request(&uri1).is_ok());
+        assert!(state.check_request(&uri2).is_ok());
+    }
+
+    #[test]
+    fn test_network_rules() {
+        let rules: PermissionList<NetworkPermission> = serde_yaml::from_str(
+            r#"
+allow:
+  - host: "*.github.com"
+    schemes: [https]
+  - host: api.example.com
+    ports: [8443]
+  - cidr: 10.0.0.0/8
+    ports: [9000]
+  - host: v4.example.com
+    address-family: v4-only
+deny:
+  - host: gist.github.com
+"#,
+        )
+        .unwrap();
+        let state = WassetteWasiState::with_rules(create_mock_wasi_state(), rules);
+        let check = |uri: &str| state.check_request(&uri.parse().unwrap());
+
+        assert!(check("https://api.github.com/repos").is_ok());
+        assert!(check("https://api.example.com:8443").is_ok());
+        assert!(check("http://10.1.2.3:9000").is_ok());
+        assert_eq!(check("https://v4.example.com"), Ok(AddressFamily::V4Only));
+        assert_eq!(check("https://api.github.com"), Ok(AddressFamily::Dual));
+
+        let denial = check("http://api.github.com").unwrap_err();
+        assert!(denial.reason.contains("scheme http is outside"));
+        let denial = check("https://api.example.com").unwrap_err();
+        assert!(denial.reason.contains("port 443 is outside"));
+        let denial = check("https://gist.github.com").unwrap_err();
+        assert!(denial
+            .reason
+            .contains("denied by the network rule `host: gist.github.com`"));
+        assert_eq!(check("http://192.168.0.1").unwrap_err().rule, None);
+        assert!(check("https://github.com").is_err());
+    }
+
+    #[test]
+    fn test_policy_request_headers() {
+        let mut state = WassetteWasiState::new(create_mock_wasi_state(), HashSet::new()).unwrap();
+        state.request_headers = serde_yaml::from_str(
+            r#"
+- name: User-Agent
+  value: acme-agent/1.0
+- name: Accept-Language
+  value: en-US
+  hosts: ["*.example.com"]
+- name: X-Api-Client
+  value: acme
+  replace: true
+"#,
+        )
+        .unwrap();
+        let send = |uri: &str| {
+            let mut request = hyper::Request::builder()
+                .uri(uri)
+                .header("x-api-client", "component")
+                .body(())
+                .unwrap();
+            state.add_request_headers(&mut request);
+            request.into_parts().0.headers
+        };
+
+        let headers = send("https://api.example.com/v1");
+        assert_eq!(headers["user-agent"], "acme-agent/1.0");
+        assert_eq!(headers["accept-language"], "en-US");
+        assert_eq!(headers["x-api-client"], "acme");
+        assert!(!send("https://api.other.com").contains_key("accept-language"));
+
+        // A header the component set is left alone unless the policy replaces it.
+        let mut request = hyper::Request::builder()
+            .uri("https://api.example.com")
+            .header("user-agent", "component/2.0")
+            .body(())
+            .unwrap();
+        state.add_request_headers(&mut request);
+        assert_eq!(request.headers()["user-agent"], "component/2.0");
     }
 }
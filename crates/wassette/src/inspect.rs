@@ -0,0 +1,207 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Inspection of a component before it is loaded.
+//!
+//! `wassette component inspect` and the `inspect-component` tool fetch a component the way a
+//! load would, compile it and describe it: the interfaces of its world, the tools its exports
+//! become with their JSON schemas, the permissions it asks for and what compiling it costs.
+//! Nothing is written to the plugin directory and no policy is attached, so a component can be
+//! reviewed before it is trusted with anything.
+//!
+//! The permissions come from two places. A component published with a policy layer in its OCI
+//! artifact ships the policy it expects, which is summarized grant by grant. Whatever the
+//! policy, the WASI interfaces a component imports tell which sections of a policy can give it
+//! anything at all: a component that doesn't import `wasi:filesystem` has no use for a storage
+//! grant.
+
+use std::collections::BTreeSet;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use component2json::{
+    apply_argument_defaults, argument_defaults_from_bytes, component_exports_to_tools,
+};
+use policy::PolicyParser;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+use crate::analysis::{self, ComponentWarning};
+use crate::{catalog, completion, prompts, used_proposals};
+
+/// Prefixes of the imports through which a component uses each section of a policy
+const POLICY_SECTION_IMPORTS: &[(&str, &str)] = &[
+    ("wasi:http/outgoing-handler", "network"),
+    ("wasi:sockets/", "network"),
+    ("wasi:filesystem/", "storage"),
+    ("wasi:cli/environment", "environment"),
+];
+
+/// What a component is, as found without loading it
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentInspection {
+    /// Where the component was fetched from
+    pub uri: String,
+    /// Digest of the component, as `sha256:<hex>`
+    pub digest: String,
+    /// Size of the component in bytes
+    pub size_bytes: u64,
+    /// Interfaces and functions the component imports
+    pub imports: Vec<String>,
+    /// Interfaces and functions the component exports
+    pub exports: Vec<String>,
+    /// Tools its exports become, with their input and output schemas
+    pub tools: Vec<Value>,
+    /// Grants of the policy published with the component, e.g. `network: api.github.com`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy: Option<Vec<String>>,
+    /// Sections of a policy the component's imports can make use of
+    pub policy_sections: Vec<String>,
+    /// Optional WebAssembly proposals the component uses
+    pub wasm_proposals: Vec<String>,
+    /// Suspicious traits found in the component
+    pub warnings: Vec<ComponentWarning>,
+    /// Milliseconds compiling the component took
+    pub compile_ms: u64,
+    /// Size of the compiled component in bytes
+    pub compiled_bytes: u64,
+}
+
+/// Describe the component in `wasm_bytes`, fetched from `uri` along with `policy` if it was
+/// published with one, compiling it with `engine`.
+pub(crate) fn inspect(
+    engine: &Engine,
+    uri: &str,
+    wasm_bytes: &[u8],
+    policy: Option<&str>,
+) -> Result<ComponentInspection> {
+    let started = Instant::now();
+    let component = Component::new(engine, wasm_bytes)?;
+    let compile_ms = started.elapsed().as_millis() as u64;
+    let compiled_bytes = component.serialize()?.len() as u64;
+
+    let policy = policy
+        .map(|policy| {
+            PolicyParser::parse_str(policy)
+                .map(|document| catalog::summarize(&document.permissions))
+                .context("Invalid policy published with the component")
+        })
+        .transpose()?;
+
+    let component_type = component.component_type();
+    let imports: Vec<String> = component_type
+        .imports(engine)
+        .map(|(name, _)| name.to_string())
+        .collect();
+    let exports = component_type
+        .exports(engine)
+        .map(|(name, _)| name.to_string())
+        .collect();
+
+    let mut tools = component_exports_to_tools(&component, engine, true);
+    completion::strip_completion_hooks(
+        &mut tools,
+        &completion::completion_hooks(&component, engine),
+    );
+    prompts::strip_prompts_export(
+        &mut tools,
+        prompts::prompts_export(&component, engine).as_deref(),
+    );
+    apply_argument_defaults(&mut tools, &argument_defaults_from_bytes(wasm_bytes));
+
+    Ok(ComponentInspection {
+        uri: uri.to_string(),
+        digest: format!("sha256:{}", hex::encode(Sha256::digest(wasm_bytes))),
+        size_bytes: wasm_bytes.len() as u64,
+        policy_sections: policy_sections(&imports),
+        imports,
+        exports,
+        tools: tools.into_iter().map(|tool| tool.schema).collect(),
+        policy,
+        wasm_proposals: used_proposals(wasm_bytes)
+            .unwrap_or_default()
+            .iter()
+            .map(|proposal| proposal.to_string())
+            .collect(),
+        warnings: analysis::analyze(wasm_bytes),
+        compile_ms,
+        compiled_bytes,
+    })
+}
+
+/// The sections of a policy that components importing `imports` can make use of.
+fn policy_sections(imports: &[String]) -> Vec<String> {
+    let sections: BTreeSet<&str> = imports
+        .iter()
+        .flat_map(|import| {
+            POLICY_SECTION_IMPORTS
+                .iter()
+                .filter(|(prefix, _)| import.starts_with(prefix))
+                .map(|(_, section)| *section)
+        })
+        .collect();
+    sections.into_iter().map(str::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPONENT: &str = r#"(component
+        (import "wasi:http/outgoing-handler@0.2.0" (instance))
+        (import "wasi:cli/environment@0.2.0" (instance))
+        (type $world (component
+            (type (component
+                (type (func (param "url" string) (result string)))
+                (export "fetch" (func (type 0)))
+            ))
+            (export "foo:foo/http" (component (type 0)))
+        ))
+        (export "foo" (type $world))
+    )"#;
+
+    #[test]
+    fn test_inspect_describes_world_tools_and_permissions() {
+        let engine = Engine::default();
+        let wasm_bytes = wat::parse_str(COMPONENT).unwrap();
+        let policy = r#"
+version: "1.0"
+permissions:
+  network:
+    allow:
+      - host: "example.com"
+"#;
+
+        let inspection = inspect(&engine, "file:///foo.wasm", &wasm_bytes, Some(policy)).unwrap();
+        assert_eq!(
+            inspection.imports,
+            [
+                "wasi:http/outgoing-handler@0.2.0",
+                "wasi:cli/environment@0.2.0"
+            ]
+        );
+        assert_eq!(inspection.exports, ["foo"]);
+        assert_eq!(inspection.tools.len(), 1);
+        assert!(inspection.tools[0]["inputSchema"]["properties"]
+            .get("url")
+            .is_some());
+        assert_eq!(inspection.policy_sections, ["environment", "network"]);
+        assert_eq!(
+            inspection.policy,
+            Some(vec!["network: example.com".to_string()])
+        );
+        assert_eq!(inspection.size_bytes, wasm_bytes.len() as u64);
+        assert!(inspection.compiled_bytes > 0);
+
+        assert!(inspect(
+            &engine,
+            "file:///foo.wasm",
+            &wasm_bytes,
+            Some("permissions: [")
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,483 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Journal of the tool calls in progress, so calls a crash cut short aren't lost silently.
+//!
+//! Every call of a component's tool is written to `journal/<session>/<n>.json` under the
+//! plugin directory when it is accepted, and the file is removed once the call ends, however
+//! it ends. Each server holds a lock on its session directory while it runs. A server starting
+//! up later takes over the sessions nobody holds anymore: their calls are the ones interrupted
+//! by a crash, and are recorded in the audit log as `interrupted`. It locks such a session and
+//! moves it aside before reading it, so of several servers starting at once only one recovers
+//! it.
+//!
+//! Calls of tools marked `idempotent` in the server's tool settings are journaled with their
+//! arguments and run again on recovery, once: a call interrupted while it was replayed is only
+//! reported, so a call that brings the server down can't do so on every restart. Arguments of
+//! other tools are never written to disk, only their hash.
+//!
+//! Only calls made without an access token are replayed. The token of an authenticated call
+//! may have expired or lost its scope by the time the server restarts, and the library can't
+//! check it again, so such calls are only reported and their arguments aren't kept. Neither
+//! are the arguments of tools whose input schema marks a property as secret, with
+//! `"writeOnly": true` or `"format": "password"`. The journal is readable by the server's
+//! user only.
+
+use std::collections::HashSet;
+use std::fs::{self, File, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{AuditOutcome, AuditRecord};
+
+/// Directory of the journal under the plugin directory.
+const JOURNAL_DIR: &str = "journal";
+
+/// Lock file a server holds in its session directory while it runs.
+const LOCK_FILE: &str = "lock";
+
+/// Extension a session directory is renamed with when a server takes it over.
+const CLAIMED_EXTENSION: &str = "recovering";
+
+/// Times an interrupted call is run again.
+const MAX_REPLAYS: u32 = 1;
+
+/// A call as written to the journal when it is accepted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct JournalEntry {
+    pub(crate) component_id: String,
+    pub(crate) tool: String,
+    pub(crate) arguments_sha256: Option<String>,
+    /// Arguments of the call, kept only for idempotent tools
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) arguments: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) client: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) subject: Option<String>,
+    /// Time the call was accepted (seconds since epoch)
+    pub(crate) accepted_at: u64,
+    /// How many times the call already ran again after an interruption
+    #[serde(default)]
+    pub(crate) replays: u32,
+}
+
+impl JournalEntry {
+    /// Whether the call should run again now that it was found interrupted.
+    pub(crate) fn replayable(&self) -> bool {
+        self.arguments.is_some() && self.subject.is_none() && self.replays < MAX_REPLAYS
+    }
+}
+
+/// A tool call a crash of the server interrupted, found when the server started again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InterruptedCall {
+    /// Component whose tool was called
+    pub component_id: String,
+    /// Tool called
+    pub tool: String,
+    /// Time the call was accepted (seconds since epoch)
+    pub accepted_at: u64,
+    /// How the call ended when it was run again, if its tool is idempotent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replayed: Option<AuditOutcome>,
+}
+
+/// The journal directory of the running server
+#[derive(Debug)]
+struct Session {
+    dir: PathBuf,
+    /// Held for the life of the server, telling other servers the session is alive
+    _lock: File,
+}
+
+/// The journal of a plugin directory.
+#[derive(Debug)]
+pub(crate) struct CallJournal {
+    root: PathBuf,
+    idempotent: HashSet<String>,
+    /// Created on the first call; `None` if it couldn't be, which turns journaling off
+    session: OnceLock<Option<Session>>,
+    next: AtomicU64,
+}
+
+impl CallJournal {
+    /// The journal under `plugin_dir`, keeping the arguments of the `idempotent` tools.
+    pub(crate) fn new(plugin_dir: &Path, idempotent: HashSet<String>) -> Self {
+        Self {
+            root: plugin_dir.join(JOURNAL_DIR),
+            idempotent,
+            session: OnceLock::new(),
+            next: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether calls of `tool` may run again after an interruption.
+    pub(crate) fn is_idempotent(&self, tool: &str) -> bool {
+        self.idempotent.contains(tool)
+    }
+
+    fn session(&self) -> Option<&Session> {
+        self.session
+            .get_or_init(|| match self.open_session() {
+                Ok(session) => Some(session),
+                Err(error) => {
+                    warn!(%error, "Failed to open the call journal; calls won't be journaled");
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    fn open_session(&self) -> std::io::Result<Session> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let dir = self.root.join(format!("{}-{started}", std::process::id()));
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&self.root)?;
+        builder.create(&dir)?;
+        // Locked before it appears, so no other server takes the session for abandoned.
+        let staged = dir.join(format!("{LOCK_FILE}.tmp"));
+        let lock = create_private(&staged)?;
+        lock.lock()?;
+        fs::rename(&staged, dir.join(LOCK_FILE))?;
+        Ok(Session { dir, _lock: lock })
+    }
+
+    /// Journal the call `record` describes, made with `arguments` after `replays` earlier
+    /// interruptions, of a tool taking `input_schema`. The call is journaled until the returned
+    /// guard is dropped.
+    pub(crate) fn begin(
+        &self,
+        record: &AuditRecord,
+        arguments: &str,
+        input_schema: Option<&Value>,
+        replays: u32,
+    ) -> Option<JournalGuard> {
+        let session = self.session()?;
+        let tool = record.tool.clone().unwrap_or_default();
+        let keep_arguments = self.is_idempotent(&tool)
+            && record.subject.is_none()
+            && !input_schema.is_some_and(marks_secrets);
+        let entry = JournalEntry {
+            component_id: record.component_id.clone().unwrap_or_default(),
+            arguments: keep_arguments.then(|| arguments.to_string()),
+            tool,
+            arguments_sha256: record.arguments_sha256.clone(),
+            client: record.client.clone(),
+            subject: record.subject.clone(),
+            accepted_at: record.created_at,
+            replays,
+        };
+        let n = self.next.fetch_add(1, Ordering::Relaxed);
+        let path = session.dir.join(format!("{n}.json"));
+        let staged = session.dir.join(format!("{n}.json.tmp"));
+        let written = serde_json::to_vec(&entry)
+            .map_err(std::io::Error::from)
+            .and_then(|content| create_private(&staged)?.write_all(&content))
+            .and_then(|()| fs::rename(&staged, &path));
+        match written {
+            Ok(()) => Some(JournalGuard { path }),
+            Err(error) => {
+                warn!(%error, tool = %entry.tool, "Failed to journal tool call");
+                None
+            }
+        }
+    }
+
+    /// Take over the sessions of servers that stopped without ending their calls, returning
+    /// those calls, oldest first.
+    pub(crate) fn recover(&self) -> Vec<JournalEntry> {
+        let own = self
+            .session
+            .get()
+            .and_then(Option::as_ref)
+            .map(|session| session.dir.clone());
+        let Ok(sessions) = fs::read_dir(&self.root) else {
+            return Vec::new();
+        };
+        let mut entries = Vec::new();
+        for session in sessions.flatten() {
+            let dir = session.path();
+            if !dir.is_dir() || Some(&dir) == own.as_ref() {
+                continue;
+            }
+            if dir.extension().is_some_and(|ext| ext == CLAIMED_EXTENSION) {
+                remove_stale_claim(&dir);
+                continue;
+            }
+            match claim(&dir) {
+                Ok(Some((claimed, _lock))) => {
+                    entries.extend(read_entries(&claimed));
+                    // Removed before the lock is released, so no other server reads it.
+                    if let Err(error) = fs::remove_dir_all(&claimed) {
+                        warn!(%error, dir = %claimed.display(), "Failed to remove journal session");
+                    }
+                }
+                // Another server is still running it, or took it over first.
+                Ok(None) => {}
+                Err(error) => {
+                    warn!(%error, dir = %dir.display(), "Failed to take over journal session");
+                }
+            }
+        }
+        entries.sort_by_key(|entry| entry.accepted_at);
+        entries
+    }
+}
+
+impl Drop for CallJournal {
+    fn drop(&mut self) {
+        if let Some(Some(session)) = self.session.get() {
+            // A session with calls still journaled is left for the next server to recover.
+            let lock = session.dir.join(LOCK_FILE);
+            let ended = fs::read_dir(&session.dir)
+                .is_ok_and(|files| files.flatten().all(|file| file.path() == lock));
+            if ended {
+                let _ = fs::remove_dir_all(&session.dir);
+            }
+        }
+    }
+}
+
+/// Create the file at `path`, readable and writable by the server's user only.
+fn create_private(path: &Path) -> std::io::Result<File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+/// Whether a JSON schema marks any value it describes as secret.
+fn marks_secrets(schema: &Value) -> bool {
+    match schema {
+        Value::Object(schema) => {
+            schema.get("writeOnly") == Some(&Value::Bool(true))
+                || schema.get("format").and_then(Value::as_str) == Some("password")
+                || schema.values().any(marks_secrets)
+        }
+        Value::Array(items) => items.iter().any(marks_secrets),
+        _ => false,
+    }
+}
+
+/// Lock the session `dir`, or `None` if its server is still running or it is gone.
+fn lock_session(dir: &Path) -> std::io::Result<Option<File>> {
+    let lock = match File::open(dir.join(LOCK_FILE)) {
+        Ok(lock) => lock,
+        // A server is still setting the session up, or another one removed it.
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    match lock.try_lock() {
+        Ok(()) => Ok(Some(lock)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(error)) => Err(error),
+    }
+}
+
+/// Take over the session `dir` of a server that stopped by moving it aside, returning where it
+/// went and its lock, held until it is removed. `None` if its server is still running or
+/// another server took it over first.
+fn claim(dir: &Path) -> std::io::Result<Option<(PathBuf, File)>> {
+    let Some(lock) = lock_session(dir)? else {
+        return Ok(None);
+    };
+    let claimed = dir.with_extension(CLAIMED_EXTENSION);
+    match fs::rename(dir, &claimed) {
+        Ok(()) => Ok(Some((claimed, lock))),
+        // Taken over, and removed, by a server that locked it first.
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Remove the session `dir` a server took over but didn't finish recovering, having stopped
+/// itself. Its calls may have run again already, so they are dropped.
+fn remove_stale_claim(dir: &Path) {
+    match lock_session(dir) {
+        Ok(Some(_lock)) => {
+            warn!(dir = %dir.display(), "Dropping journal session a stopped server was recovering");
+            if let Err(error) = fs::remove_dir_all(dir) {
+                warn!(%error, dir = %dir.display(), "Failed to remove journal session");
+            }
+        }
+        Ok(None) => {}
+        Err(error) => warn!(%error, dir = %dir.display(), "Failed to read journal session"),
+    }
+}
+
+/// The calls journaled in the session `dir`.
+fn read_entries(dir: &Path) -> Vec<JournalEntry> {
+    let files = match fs::read_dir(dir) {
+        Ok(files) => files,
+        Err(error) => {
+            warn!(%error, dir = %dir.display(), "Failed to read journal session");
+            return Vec::new();
+        }
+    };
+    let mut entries = Vec::new();
+    for file in files.flatten() {
+        let path = file.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        match fs::read(&path).map(|content| serde_json::from_slice::<JournalEntry>(&content)) {
+            Ok(Ok(entry)) => entries.push(entry),
+            Ok(Err(error)) => {
+                warn!(%error, path = %path.display(), "Ignoring invalid journal entry")
+            }
+            Err(error) => warn!(%error, path = %path.display(), "Failed to read journal entry"),
+        }
+    }
+    entries
+}
+
+/// Keeps a call in the journal until it ends.
+#[derive(Debug)]
+pub(crate) struct JournalGuard {
+    path: PathBuf,
+}
+
+impl Drop for JournalGuard {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            warn!(%error, path = %self.path.display(), "Failed to remove journaled call");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CallContext;
+
+    fn record(tool: &str) -> AuditRecord {
+        AuditRecord::tool_call(
+            Some("fetch"),
+            tool,
+            r#"{"url":"a"}"#,
+            &CallContext::default(),
+        )
+    }
+
+    #[test]
+    fn test_interrupted_calls_are_recovered_once_their_server_stopped() {
+        let plugin_dir = tempfile::tempdir().unwrap();
+        let idempotent = HashSet::from(["get".to_string()]);
+
+        let crashed = CallJournal::new(plugin_dir.path(), idempotent.clone());
+        let finished = crashed.begin(&record("get"), r#"{"url":"a"}"#, None, 0);
+        drop(finished);
+        let get = crashed
+            .begin(&record("get"), r#"{"url":"a"}"#, None, 0)
+            .unwrap();
+        let post = crashed
+            .begin(&record("post"), r#"{"url":"a"}"#, None, 0)
+            .unwrap();
+
+        // The session of a running server is left alone.
+        let restarted = CallJournal::new(plugin_dir.path(), idempotent.clone());
+        assert!(restarted.recover().is_empty());
+
+        // A crash leaves the journal as it was.
+        std::mem::forget((get, post));
+        drop(crashed);
+        let mut entries = restarted.recover();
+        entries.sort_by(|a, b| a.tool.cmp(&b.tool));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].tool, "get");
+        assert_eq!(entries[0].arguments.as_deref(), Some(r#"{"url":"a"}"#));
+        assert!(entries[0].replayable());
+        assert_eq!(entries[1].tool, "post");
+        assert_eq!(entries[1].arguments, None);
+        assert!(!entries[1].replayable());
+        assert!(restarted.recover().is_empty());
+
+        let replayed = restarted
+            .begin(&record("get"), r#"{"url":"a"}"#, None, 1)
+            .unwrap();
+        std::mem::forget(replayed);
+        drop(restarted);
+        let entries = CallJournal::new(plugin_dir.path(), idempotent).recover();
+        assert_eq!(entries.len(), 1);
+        assert!(!entries[0].replayable());
+    }
+
+    #[test]
+    fn test_a_session_is_recovered_by_one_server_only() {
+        let plugin_dir = tempfile::tempdir().unwrap();
+        let crashed = CallJournal::new(plugin_dir.path(), HashSet::new());
+        std::mem::forget(crashed.begin(&record("get"), "{}", None, 0));
+        let dir = crashed.session().unwrap().dir.clone();
+        drop(crashed);
+
+        // A server that locked the session first holds it until it is gone.
+        let (claimed, lock) = claim(&dir).unwrap().unwrap();
+        assert!(claim(&dir).unwrap().is_none());
+        let other = CallJournal::new(plugin_dir.path(), HashSet::new());
+        assert!(other.recover().is_empty());
+        assert!(claimed.exists());
+
+        // One that stopped while recovering leaves the session to be dropped.
+        drop(lock);
+        assert!(other.recover().is_empty());
+        assert!(!claimed.exists());
+    }
+
+    #[test]
+    fn test_authenticated_and_secret_arguments_are_not_kept() {
+        let plugin_dir = tempfile::tempdir().unwrap();
+        let idempotent = HashSet::from(["get".to_string()]);
+        let crashed = CallJournal::new(plugin_dir.path(), idempotent.clone());
+
+        let with_token = AuditRecord::tool_call(
+            Some("fetch"),
+            "get",
+            r#"{"url":"a"}"#,
+            &CallContext::default().with_subject("alice"),
+        );
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"token": {"type": "string", "format": "password"}}
+        });
+        let guards = [
+            crashed.begin(&with_token, r#"{"url":"a"}"#, None, 0),
+            crashed.begin(&record("get"), r#"{"token":"t"}"#, Some(&schema), 0),
+        ];
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let path = guards[0].as_ref().unwrap().path.clone();
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            let mode = fs::metadata(path.parent().unwrap())
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        std::mem::forget(guards);
+        drop(crashed);
+        let entries = CallJournal::new(plugin_dir.path(), idempotent).recover();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.arguments.is_none()));
+        assert!(entries.iter().all(|entry| !entry.replayable()));
+    }
+}
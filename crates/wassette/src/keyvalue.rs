@@ -0,0 +1,1235 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Persistent per-component state exposed through the `wasi:keyvalue/store` host interface.
+//!
+//! Components import
+//!
+//! ```wit
+//! package wasi:keyvalue@0.2.0-draft;
+//!
+//! interface store {
+//!     variant error { no-such-store, access-denied, other(string) }
+//!
+//!     record key-response { keys: list<string>, cursor: option<u64> }
+//!
+//!     open: func(identifier: string) -> result<bucket, error>;
+//!
+//!     resource bucket {
+//!         get: func(key: string) -> result<option<list<u8>>, error>;
+//!         set: func(key: string, value: list<u8>) -> result<_, error>;
+//!         delete: func(key: string) -> result<_, error>;
+//!         exists: func(key: string) -> result<bool, error>;
+//!         list-keys: func(cursor: option<u64>) -> result<key-response, error>;
+//!     }
+//! }
+//! ```
+//!
+//! Every bucket a component opens lives in one embedded redb database,
+//! `data/<component>/keyvalue.redb` in the plugin directory, so the state survives restarts
+//! and can be exported, imported or cleared with `wassette state`.
+//! Buckets are granted by `storage` permissions with `kv://` URIs: `kv://<bucket>` names one
+//! bucket and `kv://*` every bucket, `read` allowing `get`, `exists` and `list-keys` and
+//! `write` allowing `set` and `delete`. A bucket no permission grants doesn't exist for the
+//! component. The `keyvalue` section of the policy caps the number of keys and the total size
+//! of keys and values, checked in the transaction that writes them.
+//!
+//! Entries are sealed with AES-256-GCM under a key derived for the component from the master
+//! state key in the secrets directory, so tokens or user data a tool keeps don't sit in the
+//! plugin directory in plain text. They are indexed by a keyed hash of their bucket and key,
+//! which keeps key names as private as values, and the component id and index are bound to
+//! the ciphertext, so a database copied over another component's fails to open.
+//!
+//! A component's database is opened on first use and kept open for the life of the process,
+//! which holds redb's file lock on it: `wassette state` can't reach the state of a component
+//! while a server that used it runs. Reads run concurrently; redb serializes writes.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use policy::{AccessType, PolicyDocument, StorageKind, StoragePermission};
+use redb::{
+    Database, DatabaseError, ReadTransaction, ReadableTable, Table, TableDefinition, TableError,
+    WriteTransaction,
+};
+use ring::aead::{Aad, LessSafeKey, Nonce, NONCE_LEN};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::task::spawn_blocking;
+use wasmtime::component::{ComponentType, Linker, Lower, Resource, ResourceType};
+
+use crate::component_storage::ComponentStorage;
+use crate::secrets::SecretsManager;
+use crate::{WasiState, WassetteWasiState};
+
+/// Name of the host interface that provides key-value state.
+pub const KEYVALUE_INTERFACE: &str = "wasi:keyvalue/store@0.2.0-draft";
+
+/// Size cap applied when the policy grants buckets without setting `max-bytes`.
+pub const DEFAULT_MAX_BYTES: u64 = 1024 * 1024;
+
+/// Key cap applied when the policy grants buckets without setting `max-keys`.
+pub const DEFAULT_MAX_KEYS: u32 = 1024;
+
+/// Keys returned by a single `list-keys` call.
+const LIST_KEYS_PAGE: usize = 1000;
+
+/// Sealed entries by id, the index of their bucket followed by the index of their key.
+const ENTRIES: TableDefinition<&[u8], &[u8]> = TableDefinition::new("entries");
+
+/// Number of keys and bytes stored, kept in step with `ENTRIES` for quota checks.
+const USAGE: TableDefinition<&str, u64> = TableDefinition::new("usage");
+
+/// Length of the bucket and key indexes making up an entry id.
+const INDEX_LEN: usize = 16;
+
+/// Bucket identifier of `kv://*`, granting every bucket.
+const ANY_BUCKET: &str = "*";
+
+/// Access a component has to a bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BucketGrant {
+    /// `get`, `exists` and `list-keys`
+    pub read: bool,
+    /// `set` and `delete`
+    pub write: bool,
+}
+
+impl BucketGrant {
+    fn union(self, other: Self) -> Self {
+        Self {
+            read: self.read || other.read,
+            write: self.write || other.write,
+        }
+    }
+}
+
+/// Per-component size quotas and bucket grants derived from the policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyValueQuota {
+    /// Upper bound on the total size of keys and values across all buckets
+    pub max_bytes: u64,
+    /// Maximum number of keys across all buckets
+    pub max_keys: u32,
+    /// Access allowed by `kv://` storage permissions, by bucket identifier or `*`
+    pub buckets: Arc<BTreeMap<String, BucketGrant>>,
+    /// Access denied by `kv://` storage permissions, by bucket identifier or `*`
+    pub denied: Arc<BTreeMap<String, BucketGrant>>,
+}
+
+impl KeyValueQuota {
+    /// Quota granted by `policy`, or `None` if the policy grants no bucket.
+    pub fn from_policy(policy: &PolicyDocument) -> Result<Option<Self>> {
+        let storage = policy.permissions.storage.as_ref();
+        let buckets = bucket_grants(storage.and_then(|storage| storage.allow.as_deref()));
+        if buckets.is_empty() {
+            return Ok(None);
+        }
+        let denied = bucket_grants(storage.and_then(|storage| storage.deny.as_deref()));
+        let keyvalue = policy.permissions.keyvalue.clone().unwrap_or_default();
+        let max_bytes = match &keyvalue.max_bytes {
+            Some(limit) => limit.to_bytes()?,
+            None => DEFAULT_MAX_BYTES,
+        };
+        Ok(Some(Self {
+            max_bytes,
+            max_keys: keyvalue.max_keys.unwrap_or(DEFAULT_MAX_KEYS),
+            buckets: Arc::new(buckets),
+            denied: Arc::new(denied),
+        }))
+    }
+
+    /// Access the component has to the bucket named `identifier`.
+    pub fn grant(&self, identifier: &str) -> BucketGrant {
+        let lookup = |grants: &BTreeMap<String, BucketGrant>| {
+            [identifier, ANY_BUCKET]
+                .iter()
+                .filter_map(|bucket| grants.get(*bucket).copied())
+                .fold(BucketGrant::default(), BucketGrant::union)
+        };
+        let allowed = lookup(&self.buckets);
+        let denied = lookup(&self.denied);
+        BucketGrant {
+            read: allowed.read && !denied.read,
+            write: allowed.write && !denied.write,
+        }
+    }
+
+    /// Check `keys` keys holding `bytes` bytes against the quota.
+    fn check(&self, keys: u64, bytes: u64) -> Result<()> {
+        if keys > u64::from(self.max_keys) {
+            bail!(
+                "key-value quota of {} keys exceeded ({keys} keys)",
+                self.max_keys
+            );
+        }
+        if bytes > self.max_bytes {
+            bail!(
+                "key-value quota of {} bytes exceeded ({bytes} bytes)",
+                self.max_bytes
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Access granted to each bucket by the `kv://` URIs among `permissions`.
+fn bucket_grants(permissions: Option<&[StoragePermission]>) -> BTreeMap<String, BucketGrant> {
+    let mut grants = BTreeMap::<String, BucketGrant>::new();
+    for permission in permissions.into_iter().flatten() {
+        if let Some(StorageKind::KeyValue(bucket)) = permission.kind() {
+            let grant = grants.entry(bucket.to_string()).or_default();
+            grant.read |= permission.access.contains(&AccessType::Read);
+            grant.write |= permission.access.contains(&AccessType::Write);
+        }
+    }
+    grants
+}
+
+/// A stored value, written as base64 in exports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateValue(pub Vec<u8>);
+
+impl Serialize for StateValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for StateValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map(StateValue)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The key-value state of a component, as exported.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ComponentState {
+    /// Values by key, by bucket identifier
+    pub buckets: BTreeMap<String, BTreeMap<String, StateValue>>,
+}
+
+impl ComponentState {
+    /// Number of keys across all buckets.
+    pub fn keys(&self) -> usize {
+        self.buckets.values().map(BTreeMap::len).sum()
+    }
+
+    /// Total size of keys and values across all buckets.
+    pub fn bytes(&self) -> u64 {
+        self.buckets
+            .values()
+            .flat_map(BTreeMap::iter)
+            .map(|(key, value)| (key.len() + value.0.len()) as u64)
+            .sum()
+    }
+
+    /// Check the state against `quota`.
+    pub fn check(&self, quota: &KeyValueQuota) -> Result<()> {
+        quota.check(self.keys() as u64, self.bytes())
+    }
+}
+
+/// A stored entry, sealed in the database.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    bucket: String,
+    key: String,
+    value: StateValue,
+}
+
+impl Entry {
+    /// Size counted against the `max-bytes` quota.
+    fn size(&self) -> u64 {
+        (self.key.len() + self.value.0.len()) as u64
+    }
+}
+
+/// Keys sealing and indexing the entries of a component.
+struct EntryKeys {
+    component_id: String,
+    seal: LessSafeKey,
+    index: hmac::Key,
+}
+
+impl EntryKeys {
+    /// Index of `bucket`, the prefix of the ids of its entries.
+    fn bucket_index(&self, bucket: &str) -> [u8; INDEX_LEN] {
+        self.tag(&[b"bucket\0", bucket.as_bytes()])
+    }
+
+    /// Id of the entry holding `key` in `bucket`.
+    fn entry_id(&self, bucket: &str, key: &str) -> [u8; 2 * INDEX_LEN] {
+        let mut id = [0u8; 2 * INDEX_LEN];
+        id[..INDEX_LEN].copy_from_slice(&self.bucket_index(bucket));
+        id[INDEX_LEN..].copy_from_slice(&self.tag(&[
+            b"key\0",
+            &(bucket.len() as u64).to_be_bytes(),
+            bucket.as_bytes(),
+            key.as_bytes(),
+        ]));
+        id
+    }
+
+    fn tag(&self, parts: &[&[u8]]) -> [u8; INDEX_LEN] {
+        let mut context = hmac::Context::with_key(&self.index);
+        for part in parts {
+            context.update(part);
+        }
+        let mut tag = [0u8; INDEX_LEN];
+        tag.copy_from_slice(&context.sign().as_ref()[..INDEX_LEN]);
+        tag
+    }
+
+    /// Data bound to the ciphertext of the entry `id`.
+    fn aad(&self, id: &[u8]) -> Vec<u8> {
+        [self.component_id.as_bytes(), id].concat()
+    }
+
+    /// `entry` sealed as the entry `id`, its nonce followed by the ciphertext and tag.
+    fn seal(&self, id: &[u8], entry: &Entry) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("Failed to generate a nonce"))?;
+        let mut sealed = serde_json::to_vec(entry).context("Failed to serialize state")?;
+        self.seal
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(self.aad(id)),
+                &mut sealed,
+            )
+            .map_err(|_| anyhow!("Failed to seal the state of {}", self.component_id))?;
+        Ok([nonce.as_slice(), &sealed].concat())
+    }
+
+    /// Open the sealed entry `id`.
+    fn open(&self, id: &[u8], record: &[u8]) -> Result<Entry> {
+        let (nonce, ciphertext) = record
+            .split_at_checked(NONCE_LEN)
+            .ok_or_else(|| anyhow!("Truncated entry in the state of {}", self.component_id))?;
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| anyhow!("Invalid nonce in the state of {}", self.component_id))?;
+        let mut ciphertext = ciphertext.to_vec();
+        let plaintext = self
+            .seal
+            .open_in_place(nonce, Aad::from(self.aad(id)), &mut ciphertext)
+            .map_err(|_| {
+                anyhow!(
+                    "Failed to decrypt the state of {}: it was sealed with a different state key or for another component",
+                    self.component_id
+                )
+            })?;
+        serde_json::from_slice(plaintext)
+            .with_context(|| format!("Failed to parse the state of {}", self.component_id))
+    }
+}
+
+/// Number of keys and bytes a component stores.
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    keys: u64,
+    bytes: u64,
+}
+
+impl Usage {
+    fn read(table: &impl ReadableTable<&'static str, u64>) -> Result<Self> {
+        let get = |name: &str| -> Result<u64> {
+            Ok(table.get(name)?.map(|value| value.value()).unwrap_or(0))
+        };
+        Ok(Self {
+            keys: get("keys")?,
+            bytes: get("bytes")?,
+        })
+    }
+
+    /// Count an entry of `size` bytes in.
+    fn add(&mut self, size: u64) {
+        self.keys = self.keys.saturating_add(1);
+        self.bytes = self.bytes.saturating_add(size);
+    }
+
+    /// Count an entry of `size` bytes out. A stale record bottoms out at zero rather than
+    /// wrapping around, which would lift the quota.
+    fn remove(&mut self, size: u64) {
+        self.keys = self.keys.saturating_sub(1);
+        self.bytes = self.bytes.saturating_sub(size);
+    }
+
+    fn write(&self, table: &mut Table<&'static str, u64>) -> Result<()> {
+        table.insert("keys", self.keys)?;
+        table.insert("bytes", self.bytes)?;
+        Ok(())
+    }
+}
+
+/// Open the database at `path`, creating it if needed.
+fn open_database(path: &Path) -> Result<Database> {
+    Database::create(path).map_err(|e| match e {
+        DatabaseError::DatabaseAlreadyOpen => anyhow!(
+            "State at {} is in use by another wassette process",
+            path.display()
+        ),
+        e => anyhow::Error::new(e).context(format!("Failed to open state at {}", path.display())),
+    })
+}
+
+/// The entries table of `txn`, `None` before anything was stored.
+fn read_entries(
+    txn: &ReadTransaction,
+) -> Result<Option<impl ReadableTable<&'static [u8], &'static [u8]>>> {
+    match txn.open_table(ENTRIES) {
+        Ok(table) => Ok(Some(table)),
+        Err(TableError::TableDoesNotExist(_)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Store `entry` in `txn`, refusing it if the state would exceed `quota`.
+fn put_entry(
+    txn: &WriteTransaction,
+    keys: &EntryKeys,
+    entry: &Entry,
+    quota: Option<&KeyValueQuota>,
+) -> Result<()> {
+    let id = keys.entry_id(&entry.bucket, &entry.key);
+    let mut entries = txn.open_table(ENTRIES)?;
+    let mut usage_table = txn.open_table(USAGE)?;
+    let mut usage = Usage::read(&usage_table)?;
+    if let Some(previous) = entries.get(id.as_slice())? {
+        usage.remove(keys.open(&id, previous.value())?.size());
+    }
+    usage.add(entry.size());
+    if let Some(quota) = quota {
+        quota.check(usage.keys, usage.bytes)?;
+    }
+    entries.insert(id.as_slice(), keys.seal(&id, entry)?.as_slice())?;
+    usage.write(&mut usage_table)
+}
+
+/// The value of `key` in `bucket`.
+fn get_entry(
+    database: &Database,
+    keys: &EntryKeys,
+    bucket: &str,
+    key: &str,
+) -> Result<Option<Vec<u8>>> {
+    let txn = database.begin_read()?;
+    let Some(entries) = read_entries(&txn)? else {
+        return Ok(None);
+    };
+    let id = keys.entry_id(bucket, key);
+    let Some(record) = entries.get(id.as_slice())? else {
+        return Ok(None);
+    };
+    Ok(Some(keys.open(&id, record.value())?.value.0))
+}
+
+/// Whether `bucket` holds `key`.
+fn contains_entry(database: &Database, keys: &EntryKeys, bucket: &str, key: &str) -> Result<bool> {
+    let txn = database.begin_read()?;
+    let Some(entries) = read_entries(&txn)? else {
+        return Ok(false);
+    };
+    let exists = entries
+        .get(keys.entry_id(bucket, key).as_slice())?
+        .is_some();
+    Ok(exists)
+}
+
+/// Remove `key` from `bucket`.
+fn remove_entry(database: &Database, keys: &EntryKeys, bucket: &str, key: &str) -> Result<()> {
+    let id = keys.entry_id(bucket, key);
+    let txn = database.begin_write()?;
+    {
+        let mut entries = txn.open_table(ENTRIES)?;
+        let Some(removed) = entries.remove(id.as_slice())? else {
+            return Ok(());
+        };
+        let size = keys.open(&id, removed.value())?.size();
+        drop(removed);
+        let mut usage_table = txn.open_table(USAGE)?;
+        let mut usage = Usage::read(&usage_table)?;
+        usage.remove(size);
+        usage.write(&mut usage_table)?;
+    }
+    txn.commit()?;
+    Ok(())
+}
+
+/// The keys of `bucket`, sorted.
+fn bucket_keys(database: &Database, keys: &EntryKeys, bucket: &str) -> Result<Vec<String>> {
+    let txn = database.begin_read()?;
+    let Some(entries) = read_entries(&txn)? else {
+        return Ok(Vec::new());
+    };
+    let index = keys.bucket_index(bucket);
+    let start = [index, [0x00; INDEX_LEN]].concat();
+    let end = [index, [0xff; INDEX_LEN]].concat();
+    let mut names = Vec::new();
+    for item in entries.range(start.as_slice()..=end.as_slice())? {
+        let (id, record) = item?;
+        names.push(keys.open(id.value(), record.value())?.key);
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Every entry of the database.
+fn read_state(database: &Database, keys: &EntryKeys) -> Result<ComponentState> {
+    let txn = database.begin_read()?;
+    let mut state = ComponentState::default();
+    let Some(entries) = read_entries(&txn)? else {
+        return Ok(state);
+    };
+    for item in entries.iter()? {
+        let (id, record) = item?;
+        let entry = keys.open(id.value(), record.value())?;
+        state
+            .buckets
+            .entry(entry.bucket)
+            .or_default()
+            .insert(entry.key, entry.value);
+    }
+    Ok(state)
+}
+
+/// Replace every entry of the database with `state`, returning the number of keys replaced.
+fn replace_state(database: &Database, keys: &EntryKeys, state: ComponentState) -> Result<u64> {
+    let txn = database.begin_write()?;
+    let replaced = Usage::read(&txn.open_table(USAGE)?)?.keys;
+    txn.delete_table(ENTRIES)?;
+    txn.delete_table(USAGE)?;
+    insert_state(&txn, keys, state)?;
+    txn.commit()?;
+    Ok(replaced)
+}
+
+/// Store every entry of `state` in `txn`, regardless of quotas.
+fn insert_state(txn: &WriteTransaction, keys: &EntryKeys, state: ComponentState) -> Result<()> {
+    for (bucket, values) in state.buckets {
+        for (key, value) in values {
+            let entry = Entry {
+                bucket: bucket.clone(),
+                key,
+                value,
+            };
+            put_entry(txn, keys, &entry, None)?;
+        }
+    }
+    Ok(())
+}
+
+/// The open database of a component.
+struct StateDatabase {
+    database: Database,
+    keys: EntryKeys,
+}
+
+impl StateDatabase {
+    /// Run `operation` on the database on a blocking thread.
+    async fn run<R: Send + 'static>(
+        self: Arc<Self>,
+        operation: impl FnOnce(&Database, &EntryKeys) -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        spawn_blocking(move || operation(&self.database, &self.keys))
+            .await
+            .context("State operation panicked")?
+    }
+}
+
+/// The open database of a component, if any. Operations hold the read side; opening and
+/// removing the database take the write side.
+type DatabaseSlot = tokio::sync::RwLock<Option<Arc<StateDatabase>>>;
+
+/// The state databases of all components in the plugin directory.
+pub(crate) struct StateStore {
+    storage: ComponentStorage,
+    secrets: Arc<SecretsManager>,
+    /// Databases by component, opened on first use
+    databases: Mutex<HashMap<String, Arc<DatabaseSlot>>>,
+}
+
+impl StateStore {
+    pub(crate) fn new(storage: ComponentStorage, secrets: Arc<SecretsManager>) -> Self {
+        Self {
+            storage,
+            secrets,
+            databases: Mutex::default(),
+        }
+    }
+
+    fn slot(&self, component_id: &str) -> Arc<DatabaseSlot> {
+        let mut databases = self.databases.lock().unwrap();
+        Arc::clone(databases.entry(component_id.to_string()).or_default())
+    }
+
+    /// Open the database of `component_id`, or `None` if the component has no state and
+    /// `create` isn't set.
+    async fn open(&self, component_id: &str, create: bool) -> Result<Option<StateDatabase>> {
+        let path = self.storage.state_path(component_id);
+        if !create && !tokio::fs::try_exists(&path).await? {
+            return Ok(None);
+        }
+        self.storage.create_data_dir(component_id).await?;
+        let keys = EntryKeys {
+            component_id: component_id.to_string(),
+            seal: self.secrets.state_key(component_id).await?,
+            index: self.secrets.state_index_key(component_id).await?,
+        };
+        let database = spawn_blocking(move || open_database(&path))
+            .await
+            .context("Opening state panicked")??;
+        Ok(Some(StateDatabase { database, keys }))
+    }
+
+    /// Run `operation` on the database of `component_id`, `None` if the component has no
+    /// state and `create` isn't set.
+    async fn with_database<R: Send + 'static>(
+        &self,
+        component_id: &str,
+        create: bool,
+        operation: impl FnOnce(&Database, &EntryKeys) -> Result<R> + Send + 'static,
+    ) -> Result<Option<R>> {
+        let slot = self.slot(component_id);
+        let mut open = slot.read().await;
+        if open.is_none() {
+            drop(open);
+            let mut opening = slot.write().await;
+            if opening.is_none() {
+                *opening = self.open(component_id, create).await?.map(Arc::new);
+            }
+            open = opening.downgrade();
+        }
+        let Some(database) = open.as_ref().map(Arc::clone) else {
+            return Ok(None);
+        };
+        database.run(operation).await.map(Some)
+    }
+
+    /// The stored state of `component_id`, empty if it has none.
+    pub(crate) async fn read(&self, component_id: &str) -> Result<ComponentState> {
+        Ok(self
+            .with_database(component_id, false, read_state)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Replace the state of `component_id` with `state`, removing its database once empty.
+    /// Returns the number of keys replaced.
+    pub(crate) async fn replace(&self, component_id: &str, state: ComponentState) -> Result<usize> {
+        let slot = self.slot(component_id);
+        let mut open = slot.write().await;
+        let empty = state.keys() == 0;
+        if open.is_none() {
+            *open = self.open(component_id, !empty).await?.map(Arc::new);
+        }
+        let Some(database) = open.as_ref().map(Arc::clone) else {
+            return Ok(0);
+        };
+        let replaced = database
+            .run(move |database, keys| replace_state(database, keys, state))
+            .await?;
+        if empty {
+            // Close the database before removing it; no operation holds it meanwhile.
+            *open = None;
+            self.storage.remove_state(component_id).await?;
+        }
+        Ok(replaced as usize)
+    }
+
+    async fn get(
+        &self,
+        component_id: &str,
+        bucket: String,
+        key: String,
+    ) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .with_database(component_id, false, move |database, keys| {
+                get_entry(database, keys, &bucket, &key)
+            })
+            .await?
+            .flatten())
+    }
+
+    async fn set(&self, component_id: &str, entry: Entry, quota: KeyValueQuota) -> Result<()> {
+        self.with_database(component_id, true, move |database, keys| {
+            let txn = database.begin_write()?;
+            put_entry(&txn, keys, &entry, Some(&quota))?;
+            txn.commit()?;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, component_id: &str, bucket: String, key: String) -> Result<()> {
+        self.with_database(component_id, false, move |database, keys| {
+            remove_entry(database, keys, &bucket, &key)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn exists(&self, component_id: &str, bucket: String, key: String) -> Result<bool> {
+        Ok(self
+            .with_database(component_id, false, move |database, keys| {
+                contains_entry(database, keys, &bucket, &key)
+            })
+            .await?
+            .unwrap_or(false))
+    }
+
+    async fn bucket_keys(&self, component_id: &str, bucket: String) -> Result<Vec<String>> {
+        Ok(self
+            .with_database(component_id, false, move |database, keys| {
+                bucket_keys(database, keys, &bucket)
+            })
+            .await?
+            .unwrap_or_default())
+    }
+}
+
+/// Key-value state of a single store.
+#[derive(Default, Clone)]
+pub struct KeyValueState {
+    /// Quota and bucket grants from the policy; `None` denies access
+    pub quota: Option<KeyValueQuota>,
+    /// Where the component's state is kept
+    pub(crate) store: Option<(Arc<StateStore>, String)>,
+}
+
+impl KeyValueState {
+    fn access(&self) -> std::result::Result<(Arc<StateStore>, String, KeyValueQuota), Error> {
+        match (&self.store, &self.quota) {
+            (Some((store, component_id)), Some(quota)) => {
+                Ok((Arc::clone(store), component_id.clone(), quota.clone()))
+            }
+            _ => Err(Error::AccessDenied),
+        }
+    }
+}
+
+/// An open bucket, identified by name.
+pub struct Bucket {
+    name: String,
+    grant: BucketGrant,
+}
+
+/// `wasi:keyvalue/store.error`
+#[derive(ComponentType, Lower, Debug, Clone, PartialEq, Eq)]
+#[component(variant)]
+enum Error {
+    #[component(name = "no-such-store")]
+    NoSuchStore,
+    #[component(name = "access-denied")]
+    AccessDenied,
+    #[component(name = "other")]
+    Other(String),
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        Error::Other(format!("{error:#}"))
+    }
+}
+
+/// `wasi:keyvalue/store.key-response`
+#[derive(ComponentType, Lower, Debug, Clone, PartialEq, Eq)]
+#[component(record)]
+struct KeyResponse {
+    keys: Vec<String>,
+    cursor: Option<u64>,
+}
+
+type StoreResult<T> = std::result::Result<T, Error>;
+
+/// An open bucket of a component, resolved from its resource.
+struct BucketAccess {
+    states: Arc<StateStore>,
+    component_id: String,
+    quota: KeyValueQuota,
+    name: String,
+    grant: BucketGrant,
+}
+
+impl BucketAccess {
+    fn resolve(state: &mut WasiState, bucket: &Resource<Bucket>) -> StoreResult<Self> {
+        let (states, component_id, quota) = state.keyvalue.access()?;
+        let bucket = state
+            .table
+            .get(bucket)
+            .map_err(|e| Error::Other(e.to_string()))?;
+        Ok(Self {
+            states,
+            component_id,
+            quota,
+            name: bucket.name.clone(),
+            grant: bucket.grant,
+        })
+    }
+
+    fn readable(&self) -> StoreResult<()> {
+        if self.grant.read {
+            Ok(())
+        } else {
+            Err(Error::AccessDenied)
+        }
+    }
+
+    fn writable(&self) -> StoreResult<()> {
+        if self.grant.write {
+            Ok(())
+        } else {
+            Err(Error::AccessDenied)
+        }
+    }
+
+    async fn get(&self, key: String) -> StoreResult<Option<Vec<u8>>> {
+        self.readable()?;
+        Ok(self
+            .states
+            .get(&self.component_id, self.name.clone(), key)
+            .await?)
+    }
+
+    async fn set(&self, key: String, value: Vec<u8>) -> StoreResult<()> {
+        self.writable()?;
+        let entry = Entry {
+            bucket: self.name.clone(),
+            key,
+            value: StateValue(value),
+        };
+        self.states
+            .set(&self.component_id, entry, self.quota.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: String) -> StoreResult<()> {
+        self.writable()?;
+        self.states
+            .delete(&self.component_id, self.name.clone(), key)
+            .await?;
+        Ok(())
+    }
+
+    async fn exists(&self, key: String) -> StoreResult<bool> {
+        self.readable()?;
+        Ok(self
+            .states
+            .exists(&self.component_id, self.name.clone(), key)
+            .await?)
+    }
+
+    async fn list_keys(&self, cursor: Option<u64>) -> StoreResult<KeyResponse> {
+        self.readable()?;
+        let keys = self
+            .states
+            .bucket_keys(&self.component_id, self.name.clone())
+            .await?;
+        Ok(list_keys(keys, cursor))
+    }
+}
+
+/// Add the `wasi:keyvalue/store` interface to `linker`.
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    let mut keyvalue = linker.instance(KEYVALUE_INTERFACE)?;
+
+    keyvalue.resource(
+        "bucket",
+        ResourceType::host::<Bucket>(),
+        |mut store, rep| {
+            store
+                .data_mut()
+                .inner
+                .table
+                .delete(Resource::<Bucket>::new_own(rep))?;
+            Ok(())
+        },
+    )?;
+
+    keyvalue.func_wrap("open", |mut store, (identifier,): (String,)| {
+        let state = &mut store.data_mut().inner;
+        let result = state.keyvalue.access().and_then(|(_, _, quota)| {
+            // Buckets no storage permission grants don't exist for the component.
+            let grant = quota.grant(&identifier);
+            if !grant.read && !grant.write {
+                return Err(Error::NoSuchStore);
+            }
+            state
+                .table
+                .push(Bucket {
+                    name: identifier,
+                    grant,
+                })
+                .map_err(|e| Error::Other(e.to_string()))
+        });
+        Ok((result,))
+    })?;
+
+    keyvalue.func_wrap_async(
+        "[method]bucket.get",
+        |mut store, (bucket, key): (Resource<Bucket>, String)| {
+            let access = BucketAccess::resolve(&mut store.data_mut().inner, &bucket);
+            Box::new(async move {
+                let result = match access {
+                    Ok(access) => access.get(key).await,
+                    Err(e) => Err(e),
+                };
+                Ok((result,))
+            })
+        },
+    )?;
+
+    keyvalue.func_wrap_async(
+        "[method]bucket.set",
+        |mut store, (bucket, key, value): (Resource<Bucket>, String, Vec<u8>)| {
+            let access = BucketAccess::resolve(&mut store.data_mut().inner, &bucket);
+            Box::new(async move {
+                let result = match access {
+                    Ok(access) => access.set(key, value).await,
+                    Err(e) => Err(e),
+                };
+                Ok((result,))
+            })
+        },
+    )?;
+
+    keyvalue.func_wrap_async(
+        "[method]bucket.delete",
+        |mut store, (bucket, key): (Resource<Bucket>, String)| {
+            let access = BucketAccess::resolve(&mut store.data_mut().inner, &bucket);
+            Box::new(async move {
+                let result = match access {
+                    Ok(access) => access.delete(key).await,
+                    Err(e) => Err(e),
+                };
+                Ok((result,))
+            })
+        },
+    )?;
+
+    keyvalue.func_wrap_async(
+        "[method]bucket.exists",
+        |mut store, (bucket, key): (Resource<Bucket>, String)| {
+            let access = BucketAccess::resolve(&mut store.data_mut().inner, &bucket);
+            Box::new(async move {
+                let result = match access {
+                    Ok(access) => access.exists(key).await,
+                    Err(e) => Err(e),
+                };
+                Ok((result,))
+            })
+        },
+    )?;
+
+    keyvalue.func_wrap_async(
+        "[method]bucket.list-keys",
+        |mut store, (bucket, cursor): (Resource<Bucket>, Option<u64>)| {
+            let access = BucketAccess::resolve(&mut store.data_mut().inner, &bucket);
+            Box::new(async move {
+                let result = match access {
+                    Ok(access) => access.list_keys(cursor).await,
+                    Err(e) => Err(e),
+                };
+                Ok((result,))
+            })
+        },
+    )?;
+
+    Ok(())
+}
+
+/// One page of the sorted `keys` of a bucket, starting at the offset `cursor`.
+fn list_keys(keys: Vec<String>, cursor: Option<u64>) -> KeyResponse {
+    let start = cursor.unwrap_or(0) as usize;
+    let mut keys: Vec<String> = keys.into_iter().skip(start).collect();
+    if keys.len() > LIST_KEYS_PAGE {
+        keys.truncate(LIST_KEYS_PAGE);
+        KeyResponse {
+            keys,
+            cursor: Some((start + LIST_KEYS_PAGE) as u64),
+        }
+    } else {
+        KeyResponse { keys, cursor: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use policy::PolicyParser;
+
+    use super::*;
+
+    fn state(entries: &[(&str, &str, &[u8])]) -> ComponentState {
+        let mut state = ComponentState::default();
+        for (bucket, key, value) in entries {
+            state
+                .buckets
+                .entry(bucket.to_string())
+                .or_default()
+                .insert(key.to_string(), StateValue(value.to_vec()));
+        }
+        state
+    }
+
+    fn quota(max_bytes: u64, max_keys: u32) -> KeyValueQuota {
+        KeyValueQuota {
+            max_bytes,
+            max_keys,
+            buckets: Arc::default(),
+            denied: Arc::default(),
+        }
+    }
+
+    fn entry(bucket: &str, key: &str, value: &[u8]) -> Entry {
+        Entry {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            value: StateValue(value.to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_quota_from_policy() {
+        let policy = PolicyParser::parse_str(
+            "version: \"1.0\"\npermissions:\n  keyvalue:\n    max-bytes: \"2Ki\"\n",
+        )
+        .unwrap();
+        assert_eq!(KeyValueQuota::from_policy(&policy).unwrap(), None);
+
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+permissions:
+  storage:
+    allow:
+      - uri: "kv://*"
+        access: ["read"]
+      - uri: "kv://cache"
+        access: ["write"]
+      - uri: "kv://"
+        access: ["read", "write"]
+      - uri: "fs://work"
+        access: ["read", "write"]
+    deny:
+      - uri: "kv://"
+        access: ["write"]
+  keyvalue:
+    max-bytes: "2Ki"
+"#,
+        )
+        .unwrap();
+        let quota = KeyValueQuota::from_policy(&policy).unwrap().unwrap();
+        assert_eq!(quota.max_bytes, 2048);
+        assert_eq!(quota.max_keys, DEFAULT_MAX_KEYS);
+        assert_eq!(quota.buckets.len(), 3);
+        let grant = |read, write| BucketGrant { read, write };
+        assert_eq!(quota.grant("cache"), grant(true, true));
+        assert_eq!(quota.grant("tokens"), grant(true, false));
+        assert_eq!(quota.grant(""), grant(true, false));
+
+        let policy = PolicyParser::parse_str(
+            "version: \"1.0\"\npermissions:\n  storage:\n    allow:\n      - uri: \"kv://cache\"\n        access: [\"read\"]\n",
+        )
+        .unwrap();
+        let quota = KeyValueQuota::from_policy(&policy).unwrap().unwrap();
+        assert_eq!(quota.max_bytes, DEFAULT_MAX_BYTES);
+        assert_eq!(quota.grant("cache"), grant(true, false));
+        assert_eq!(quota.grant("tokens"), grant(false, false));
+    }
+
+    #[test]
+    fn test_quota_check() {
+        let state = state(&[("", "token", b"abcd"), ("cache", "a", b"")]);
+        assert_eq!(state.keys(), 2);
+        assert_eq!(state.bytes(), 10);
+
+        assert!(state.check(&quota(10, 2)).is_ok());
+        assert!(state.check(&quota(9, 2)).is_err());
+        assert!(state.check(&quota(10, 1)).is_err());
+    }
+
+    #[test]
+    fn test_usage_doesnt_wrap() {
+        let mut usage = Usage { keys: 1, bytes: 4 };
+        usage.remove(10);
+        usage.remove(1);
+        assert_eq!((usage.keys, usage.bytes), (0, 0));
+        usage.add(3);
+        assert_eq!((usage.keys, usage.bytes), (1, 3));
+    }
+
+    #[test]
+    fn test_state_serializes_values_as_base64() {
+        let state = state(&[("", "token", b"secret")]);
+        let json = serde_json::to_value(&state).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"buckets": {"": {"token": "c2VjcmV0"}}})
+        );
+        assert_eq!(
+            serde_json::from_value::<ComponentState>(json).unwrap(),
+            state
+        );
+        assert!(serde_json::from_str::<ComponentState>(
+            r#"{"buckets": {"": {"token": "not base64!"}}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_list_keys_pages() {
+        let keys: Vec<String> = (0..LIST_KEYS_PAGE + 5).map(|i| format!("{i:05}")).collect();
+
+        let first = list_keys(keys.clone(), None);
+        assert_eq!(first.keys.len(), LIST_KEYS_PAGE);
+        let second = list_keys(keys, first.cursor);
+        assert_eq!(second.keys.len(), 5);
+        assert_eq!(second.cursor, None);
+        assert!(list_keys(Vec::new(), None).keys.is_empty());
+    }
+
+    async fn state_store(dir: &std::path::Path) -> Result<StateStore> {
+        let storage = ComponentStorage::new(dir.join("plugins"), 1).await?;
+        let secrets = Arc::new(SecretsManager::new(dir.join("secrets")));
+        Ok(StateStore::new(storage, secrets))
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_kept_per_bucket() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let states = state_store(tempdir.path()).await?;
+        let quota = quota(1024, 16);
+        assert_eq!(states.get("notes", "".into(), "a".into()).await?, None);
+        assert!(!states.storage.state_path("notes").exists());
+
+        states
+            .set("notes", entry("", "a", b"1"), quota.clone())
+            .await?;
+        states
+            .set("notes", entry("", "b", b"2"), quota.clone())
+            .await?;
+        states
+            .set("notes", entry("cache", "a", b"3"), quota.clone())
+            .await?;
+        states
+            .set("notes", entry("", "a", b"4"), quota.clone())
+            .await?;
+        assert_eq!(
+            states.get("notes", "".into(), "a".into()).await?,
+            Some(b"4".to_vec())
+        );
+        assert!(states.exists("notes", "cache".into(), "a".into()).await?);
+        assert!(!states.exists("notes", "cache".into(), "b".into()).await?);
+        assert_eq!(states.bucket_keys("notes", "".into()).await?, ["a", "b"]);
+        assert_eq!(states.bucket_keys("notes", "cache".into()).await?, ["a"]);
+
+        states.delete("notes", "".into(), "a".into()).await?;
+        states.delete("notes", "".into(), "missing".into()).await?;
+        assert_eq!(
+            states.read("notes").await?,
+            state(&[("", "b", b"2"), ("cache", "a", b"3")])
+        );
+
+        assert_eq!(states.replace("notes", ComponentState::default()).await?, 2);
+        assert!(!states.storage.data_dir("notes").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_operations_share_the_database() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let states = Arc::new(state_store(tempdir.path()).await?);
+        let quota = quota(1024, 64);
+
+        let writes = (0..32).map(|i| {
+            let (states, quota) = (Arc::clone(&states), quota.clone());
+            tokio::spawn(async move {
+                let key = format!("{i:02}");
+                states.set("notes", entry("", &key, b"1"), quota).await?;
+                states.get("notes", "".into(), key).await
+            })
+        });
+        for write in futures::future::join_all(writes).await {
+            assert_eq!(write??, Some(b"1".to_vec()));
+        }
+        assert_eq!(states.bucket_keys("notes", "".into()).await?.len(), 32);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_keeps_state_when_quota_is_exceeded() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let states = state_store(tempdir.path()).await?;
+        let quota = quota(8, 2);
+
+        states
+            .set("notes", entry("", "a", b"1234"), quota.clone())
+            .await?;
+        let error = states
+            .set("notes", entry("", "a", b"123456789"), quota.clone())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("quota"));
+        states
+            .set("notes", entry("", "b", b""), quota.clone())
+            .await?;
+        let error = states
+            .set("notes", entry("", "c", b""), quota.clone())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("quota"));
+        assert_eq!(
+            states.read("notes").await?,
+            state(&[("", "a", b"1234"), ("", "b", b"")])
+        );
+
+        // Overwriting and deleting give their room back.
+        states
+            .set("notes", entry("", "a", b"12345"), quota.clone())
+            .await?;
+        states.delete("notes", "".into(), "b".into()).await?;
+        states.set("notes", entry("", "c", b""), quota).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_state_is_sealed_at_rest() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let states = state_store(tempdir.path()).await?;
+        let secret = state(&[("vault", "token", b"hunter2")]);
+        states.replace("notes", secret.clone()).await?;
+
+        let path = states.storage.state_path("notes");
+        let contents = String::from_utf8_lossy(&tokio::fs::read(&path).await?).into_owned();
+        assert!(!contents.contains("vault"));
+        assert!(!contents.contains("token"));
+        assert!(!contents.contains("hunter2"));
+        assert!(!contents.contains("aHVudGVyMg"));
+        assert_eq!(states.read("notes").await?, secret);
+
+        // A database moved to another component doesn't open.
+        tokio::fs::create_dir_all(states.storage.data_dir("other")).await?;
+        tokio::fs::copy(&path, states.storage.state_path("other")).await?;
+        assert!(states.read("other").await.is_err());
+
+        // Nor does one read with a different master key.
+        let elsewhere = tempfile::tempdir()?;
+        let other_key = state_store(elsewhere.path()).await?;
+        tokio::fs::create_dir_all(other_key.storage.data_dir("notes")).await?;
+        tokio::fs::copy(&path, other_key.storage.state_path("notes")).await?;
+        assert!(other_key.read("notes").await.is_err());
+        Ok(())
+    }
+}
@@ -5,50 +5,207 @@
 
 #![warn(missing_docs)]
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, bail, Context, Result};
 use component2json::{
-    component_exports_to_json_schema, component_exports_to_tools, create_placeholder_results,
-    json_to_vals, vals_to_json, FunctionIdentifier, ToolMetadata,
+    apply_argument_defaults, argument_defaults_from_bytes, component_exports_to_tools,
+    create_placeholder_results, fill_argument_defaults, json_to_vals, readme_from_bytes,
+    vals_to_json, FunctionIdentifier, ToolMetadata,
 };
 use etcetera::BaseStrategy;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Map, Value};
 use tokio::fs::DirEntry;
 use tokio::sync::{RwLock, Semaphore};
-use tracing::{debug, info, instrument, warn};
-use wasmtime::component::{Component, InstancePre};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, info_span, instrument, warn, Instrument};
+use wasmtime::component::{Component, Func, Instance, InstancePre, Val};
 use wasmtime::Store;
 
+mod aliases;
+mod analysis;
+mod anomaly;
+mod audit;
+mod browser;
+mod call_progress;
+mod catalog;
+mod completion;
 mod component_storage;
+mod compose;
 mod config;
+mod confinement;
+mod contract;
+mod coredump;
+mod egress;
+mod elevation;
+mod email;
+mod env_references;
+mod fs_audit;
+mod fs_grants;
+mod gc;
+mod history;
+mod hooks;
 mod http;
+mod inspect;
+mod journal;
+mod keyvalue;
+mod limits;
 mod loader;
+mod lockfile;
+pub mod manifest;
+mod migration;
+mod mounts;
+mod naming;
 pub mod oci_multi_layer;
+mod oci_pull;
+mod output;
+mod overrides;
 mod policy_internal;
+mod precompiled;
+mod profiling;
+mod progress;
+mod prompts;
+mod proposals;
+mod provenance;
+mod registry;
+mod resources;
+mod result_cache;
+mod rollout;
+mod runtime_config;
 mod runtime_context;
+mod sampling;
+mod schedule;
+mod scheduler;
 pub mod schema;
 mod secrets;
+mod session_context;
+mod signatures;
+mod stats;
+mod toolchain;
+mod updates;
+mod validation;
+mod vault;
+mod vector;
+pub mod vendor;
+mod versions;
+mod warm;
 mod wasistate;
-
+mod watch;
+mod workspace;
+
+use aliases::ComponentAliases;
+pub use aliases::ALIASES_FILE;
+pub use analysis::{ComponentWarning, WarningKind};
+pub use anomaly::{Anomaly, AnomalyConfig, AnomalySignal};
+use anomaly::{AnomalyDetector, OutboundRequests};
+pub use audit::{AuditDecision, AuditEvent, AuditOutcome, AuditQuery, AuditRecord, AUDIT_LOG_FILE};
+use audit::{AuditLog, Denials};
+use browser::HeadlessBrowser;
+pub use browser::{BrowserConfig, BrowserLimits, BROWSER_INTERFACE, DEFAULT_BROWSER_TIMEOUT_SECS};
+pub use call_progress::{CallCancelled, CallProgress, CallProgressFn, PROGRESS_INTERFACE};
+pub use catalog::{
+    CatalogConfig, CatalogEntry, CatalogError, CatalogSearch, CATALOG_ENTRY_ARTIFACT_TYPE,
+    COMPONENT_ANNOTATION, PERMISSIONS_ANNOTATION,
+};
 use component_storage::ComponentStorage;
 pub use config::{LifecycleBuilder, LifecycleConfig};
+pub use confinement::GrantedAccess;
+pub use contract::{Contract, ContractReport, ContractTool, Mismatch, MismatchKind};
+pub use coredump::CoreDumpRecord;
+use egress::Egress;
+pub use egress::{EgressSettings, NetworkConfig};
+use elevation::Elevation;
+pub use elevation::{PermissionKind, PermissionPrompter, PermissionPrompts, PermissionRequest};
+use email::Outbox;
+pub use email::{EmailLimits, EmailMessage, DEFAULT_MAX_PER_HOUR, EMAIL_INTERFACE};
+pub use env_references::{InjectedVariable, VariableSource};
+use fs_audit::EscapeAudit;
+pub use fs_audit::{EscapeAttempt, EscapeKind, FsHardening};
+pub use gc::{OrphanKind, OrphanedFile, Orphans};
+use history::Release;
+pub use history::{VersionChange, VersionChangeKind, CHANGELOG_ANNOTATION};
+use hooks::Hooks;
+pub use hooks::{HookConfig, HookEvent, DEFAULT_HOOK_TIMEOUT_SECS};
 pub use http::WassetteWasiState;
+pub use inspect::ComponentInspection;
+use journal::CallJournal;
+pub use journal::InterruptedCall;
+use keyvalue::StateStore;
+pub use keyvalue::{
+    BucketGrant, ComponentState, KeyValueQuota, StateValue, DEFAULT_MAX_BYTES, DEFAULT_MAX_KEYS,
+    KEYVALUE_INTERFACE,
+};
+pub use limits::{CallTimedOut, InvocationLimits, LimitedResource, ResourceLimitExceeded};
+use limits::{CallTimeouts, InstanceSlots};
 use loader::{ComponentResource, DownloadedResource};
+use lockfile::ComponentLock;
+pub use lockfile::LOCKFILE_NAME;
+use manifest::{Dependency, Manifest};
+pub use migration::{migrate_state, MigrationReport, STATE_VERSION};
+pub use naming::{ToolCollisionPolicy, ToolNaming, DEFAULT_TOOL_SEPARATOR};
+use oci_pull::OciPull;
+pub use oci_pull::{PullOptions, RegistryConfig, RegistryMirror};
+use output::ComponentOutput;
+pub use output::{FileSink, OutputConfig, OutputLine, OutputSink, StdioSinks, StdioStream};
+use overrides::OverrideLimits;
+pub use overrides::{CallOverrides, OverrideCeiling};
 use policy_internal::PolicyManager;
 pub use policy_internal::{PermissionGrantRequest, PermissionRule, PolicyInfo};
-use runtime_context::RuntimeContext;
-pub use secrets::SecretsManager;
+pub use progress::{LoadContext, LoadProgress, LoadProgressFn, LoadStage};
+use prompts::ComponentPrompts;
+pub use prompts::{ComponentPrompt, Prompt, PromptArgument, PROMPTS_INTERFACE};
+pub use proposals::{used_proposals, WasmProposal, DEFAULT_WASM_PROPOSALS};
+pub use provenance::{ProvenanceConfig, ProvenancePin};
+use registry::{ComponentRegistry, ToolInfo};
+use resources::PublishedResources;
+pub use resources::{
+    resource_uri, PublishedResource, ResourceChange, ResourceInfo, README_NAME,
+    RESOURCES_INTERFACE, RESOURCE_URI_PREFIX,
+};
+use result_cache::{CachedCall, ResultCache};
+use rollout::{Rollout, Rollouts};
+pub use rollout::{RolloutEvent, RolloutStage};
+use runtime_config::RuntimeConfig;
+pub use runtime_config::{ComponentConfig, CONFIG_DIR};
+use runtime_context::{RuntimeContext, RuntimeOptions};
+pub use sampling::{Sampler, SamplingLimits, SamplingRequest, SAMPLING_INTERFACE};
+pub use schedule::OutsideAllowedHours;
+pub use scheduler::Priority;
+use scheduler::Scheduler;
+pub use secrets::{
+    FileSecretsBackend, KeyringSecretsBackend, SecretsBackend, SecretsBackendConfig, SecretsManager,
+};
+pub use session_context::{SessionContext, SessionProfile, CONTEXT_INTERFACE};
+use signatures::SignatureVerifier;
+pub use signatures::{SignatureConfig, SignerIdentity, TrustedSigners};
+use stats::{CallStats, ComponentMetrics};
+pub use stats::{ComponentStats, RuntimeStats, LATENCY_BUCKETS_SECS};
+pub use toolchain::Toolchain;
+pub use updates::{ComponentUpdate, HealthCheck, UpdateConfig, UpdateReport, UpdateStatus};
+use updates::{Snapshot, UpdateSource};
+pub use validation::{ValidationPool, DEFAULT_VALIDATION_BUDGET};
+pub use vault::{VaultAuth, VaultConfig, VaultSecretsBackend};
+use vector::VectorStore;
+pub use vector::{VectorEntry, VectorMatch, VectorQuota, VECTOR_INTERFACE};
+pub use versions::{KeptVersion, RollbackReport, DEFAULT_KEPT_VERSIONS};
+pub use warm::{RuntimeSettings, ToolSettings};
+use warm::{Standby, WarmPool};
 use wasistate::WasiState;
 pub use wasistate::{
-    create_wasi_state_template_from_policy, CustomResourceLimiter, WasiStateTemplate,
+    create_wasi_state_template_from_policy, CustomResourceLimiter, WasiCtxHook, WasiStateTemplate,
 };
+use watch::SourceWatch;
+pub use watch::WATCH_DEBOUNCE;
+use workspace::WorkspaceAccess;
 
 const DOWNLOADS_DIR: &str = "downloads";
+/// Directory in the plugin directory holding a directory of data for each component
+const DATA_DIR: &str = "data";
 const PRECOMPILED_EXT: &str = "cwasm";
 const METADATA_EXT: &str = "metadata.json";
 
@@ -69,13 +226,6 @@ pub(crate) fn get_default_secrets_dir() -> PathBuf {
     }
 }
 
-#[derive(Debug, Clone)]
-struct ToolInfo {
-    component_id: String,
-    identifier: FunctionIdentifier,
-    schema: Value,
-}
-
 /// Component metadata for fast startup without compilation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComponentMetadata {
@@ -91,8 +241,29 @@ pub struct ComponentMetadata {
     pub validation_stamp: ValidationStamp,
     /// Metadata creation timestamp
     pub created_at: u64,
+    /// Optional WebAssembly proposals the component uses; absent in metadata written by
+    /// older releases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wasm_proposals: Option<BTreeSet<WasmProposal>>,
+    /// Prompts the component exports through `wassette:prompts/prompts`; absent in metadata
+    /// written by older releases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts: Option<Vec<Prompt>>,
+    /// Usage guide embedded in the component's `wassette:readme` section
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readme: Option<String>,
+    /// Suspicious traits found in the component when it was loaded; absent in metadata
+    /// written by older releases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warnings: Option<Vec<ComponentWarning>>,
 }
 
+/// Argument presets for a component's tools, keyed by normalized tool name.
+///
+/// Presets are merged into the arguments of every call to the tool; arguments supplied by the
+/// caller always take precedence.
+pub type ToolPresets = BTreeMap<String, serde_json::Map<String, Value>>;
+
 /// Validation stamp to check if component has changed
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidationStamp {
@@ -104,28 +275,6 @@ pub struct ValidationStamp {
     pub content_hash: Option<String>,
 }
 
-#[derive(Clone, Default)]
-struct ComponentRegistry {
-    state: Arc<RwLock<ComponentRegistryState>>,
-}
-
-#[derive(Default)]
-struct ComponentRegistryState {
-    components: HashMap<String, ComponentInstance>,
-    tool_map: HashMap<String, Vec<ToolInfo>>,
-    component_map: HashMap<String, Vec<String>>,
-}
-
-impl std::fmt::Debug for ComponentRegistryState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("ComponentRegistryState")
-            .field("components_len", &self.components.len())
-            .field("tool_map", &self.tool_map)
-            .field("component_map", &self.component_map)
-            .finish()
-    }
-}
-
 /// The returned status when loading a component
 #[derive(Debug, PartialEq, Clone)]
 pub enum LoadResult {
@@ -144,151 +293,114 @@ pub struct ComponentLoadOutcome {
     pub status: LoadResult,
     /// Normalized tool names exposed by the component after registration.
     pub tool_names: Vec<String>,
+    /// Suspicious traits found in the component, to review before granting permissions.
+    pub warnings: Vec<ComponentWarning>,
 }
 
-impl ComponentRegistry {
-    fn new() -> Self {
-        Self::default()
-    }
-
-    async fn upsert_component(
-        &self,
-        component_id: String,
-        instance: ComponentInstance,
-        tools: Vec<ToolMetadata>,
-    ) -> Result<LoadResult> {
-        let mut state = self.state.write().await;
-        state.upsert_component(component_id, instance, tools)
-    }
+/// Per-invocation facilities a tool call can use to talk back to the client.
+#[derive(Clone, Default)]
+pub struct CallContext {
+    sampler: Option<Arc<dyn Sampler>>,
+    client: Option<(String, String)>,
+    subject: Option<String>,
+    progress: Option<CallProgressFn>,
+    cancellation: Option<CancellationToken>,
+    overrides: CallOverrides,
+    confirmed: bool,
+    /// Where the hosts the call sends HTTP requests to are recorded, for anomaly detection
+    outbound: Option<Arc<OutboundRequests>>,
+    /// How many times the call already ran again after a crash interrupted it
+    replays: u32,
+    prompter: Option<Arc<dyn PermissionPrompter>>,
+    /// Permission prompts of the call, when the manager asks for refused permissions
+    elevation: Option<Arc<Elevation>>,
+}
 
-    async fn remove_component(&self, component_id: &str) -> Option<ComponentInstance> {
-        let mut state = self.state.write().await;
-        state.unregister_component(component_id)
+impl CallContext {
+    /// Answer the component's `wassette:ai/sample` requests with `sampler`.
+    pub fn with_sampler(mut self, sampler: Arc<dyn Sampler>) -> Self {
+        self.sampler = Some(sampler);
+        self
     }
 
-    async fn get_component(&self, component_id: &str) -> Option<ComponentInstance> {
-        let state = self.state.read().await;
-        state.components.get(component_id).cloned()
+    /// Tell components granted the session context that the call comes from the MCP client
+    /// `name` at `version`.
+    pub fn with_client(mut self, name: impl Into<String>, version: impl Into<String>) -> Self {
+        self.client = Some((name.into(), version.into()));
+        self
     }
 
-    async fn contains_component(&self, component_id: &str) -> bool {
-        self.state
-            .read()
-            .await
-            .components
-            .contains_key(component_id)
+    /// Record in the audit log that the call was made with an access token issued to
+    /// `subject`.
+    pub fn with_subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = Some(subject.into());
+        self
     }
 
-    async fn list_components(&self) -> Vec<String> {
-        let state = self.state.read().await;
-        let mut ids: Vec<String> = state.components.keys().cloned().collect();
-        ids.sort();
-        ids
+    /// Pass the progress the component reports through `wassette:progress/report` to
+    /// `progress`.
+    pub fn with_progress(
+        mut self,
+        progress: impl Fn(CallProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
     }
 
-    async fn tool_identifier(&self, tool_name: &str) -> Option<FunctionIdentifier> {
-        let state = self.state.read().await;
-        state
-            .tool_map
-            .get(tool_name)
-            .and_then(|infos| infos.first().map(|info| info.identifier.clone()))
+    /// Stop the call when `token` is cancelled; it then fails with a [`CallCancelled`].
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
     }
 
-    async fn tool_infos(&self, tool_name: &str) -> Option<Vec<ToolInfo>> {
-        let state = self.state.read().await;
-        state.tool_map.get(tool_name).cloned()
+    /// Run the call with the timeout and memory limit of `overrides` instead of the
+    /// component's. The call fails if they go past the manager's
+    /// [`OverrideCeiling`](crate::OverrideCeiling); only pass overrides from trusted callers.
+    pub fn with_overrides(mut self, overrides: CallOverrides) -> Self {
+        self.overrides = overrides;
+        self
     }
 
-    async fn list_tools(&self) -> Vec<Value> {
-        let state = self.state.read().await;
-        state
-            .tool_map
-            .values()
-            .flat_map(|tools| tools.iter().map(|t| t.schema.clone()))
-            .collect()
+    /// Record that the user confirmed the call, which lets it run outside the hours of a
+    /// tool with `require-confirm-outside-hours`. Only pass what the user answered when asked,
+    /// never a flag the agent making the call could set. Calls with a prompter ask it instead.
+    pub fn with_confirmation(mut self, confirmed: bool) -> Self {
+        self.confirmed = confirmed;
+        self
     }
 
-    async fn register_metadata_if_absent(
-        &self,
-        component_id: &str,
-        tools: Vec<ToolMetadata>,
-    ) -> Result<bool> {
-        let mut state = self.state.write().await;
-
-        if state.components.contains_key(component_id)
-            || state.component_map.contains_key(component_id)
-        {
-            return Ok(false);
-        }
-
-        state.register_tools_only(component_id, tools);
-        Ok(true)
+    /// Ask the user through `prompter` whether to grant what the component's policy refuses,
+    /// for the kinds of permissions the manager's
+    /// [`PermissionPrompts`](crate::PermissionPrompts) cover, and whether to run a call made
+    /// outside the allowed hours of a tool with `require-confirm-outside-hours`.
+    pub fn with_prompter(mut self, prompter: Arc<dyn PermissionPrompter>) -> Self {
+        self.prompter = Some(prompter);
+        self
     }
 }
 
-impl ComponentRegistryState {
-    fn upsert_component(
-        &mut self,
-        component_id: String,
-        instance: ComponentInstance,
-        tools: Vec<ToolMetadata>,
-    ) -> Result<LoadResult> {
-        let replaced = self.components.contains_key(&component_id);
-        self.unregister_tools(&component_id);
-        self.register_tools_only(&component_id, tools);
-        self.components.insert(component_id, instance);
-
-        Ok(if replaced {
-            LoadResult::Replaced
-        } else {
-            LoadResult::New
-        })
-    }
-
-    fn unregister_component(&mut self, component_id: &str) -> Option<ComponentInstance> {
-        self.unregister_tools(component_id);
-        self.components.remove(component_id)
-    }
-
-    fn unregister_tools(&mut self, component_id: &str) {
-        if let Some(tools) = self.component_map.remove(component_id) {
-            for tool_name in tools {
-                if let Some(tool_infos) = self.tool_map.get_mut(&tool_name) {
-                    tool_infos.retain(|info| info.component_id != component_id);
-                    if tool_infos.is_empty() {
-                        self.tool_map.remove(&tool_name);
-                    }
-                }
-            }
-        }
-    }
-
-    fn register_tools_only(&mut self, component_id: &str, tools: Vec<ToolMetadata>) {
-        let mut tool_names = Vec::new();
-
-        for tool_metadata in tools {
-            let ToolMetadata {
-                identifier,
-                schema,
-                normalized_name,
-            } = tool_metadata;
-
-            let tool_info = ToolInfo {
-                component_id: component_id.to_string(),
-                identifier,
-                schema,
-            };
-
-            self.tool_map
-                .entry(normalized_name.clone())
-                .or_default()
-                .push(tool_info);
-            tool_names.push(normalized_name);
-        }
+/// Output of a tool call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolOutput {
+    /// The tool's result, serialized as JSON unless it is a plain string
+    pub output: String,
+    /// Whether the output came from the tool's result cache
+    pub cached: bool,
+}
 
-        self.component_map
-            .insert(component_id.to_string(), tool_names);
-    }
+/// What the runtime of a [`LifecycleManager`] offers components.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuntimeInfo {
+    /// WIT packages implemented by the host
+    pub hosts: Vec<String>,
+    /// Keys of a policy's `resources.limits` that are enforced
+    pub resource_limits: Vec<String>,
+    /// How escapes from granted directories are handled
+    pub fs_hardening: FsHardening,
+    /// Whether traps leave a core dump
+    pub coredump_on_trap: bool,
+    /// Optional WebAssembly proposals the engine enables
+    pub wasm_proposals: Vec<String>,
 }
 
 /// A manager that handles the dynamic lifecycle of WebAssembly components.
@@ -301,6 +413,48 @@ pub struct LifecycleManager {
     oci_client: Arc<oci_wasm::WasmClient>,
     http_client: reqwest::Client,
     secrets_manager: Arc<SecretsManager>,
+    runtime_config: Arc<RuntimeConfig>,
+    coredump_on_trap: bool,
+    fs_hardening: FsHardening,
+    profile_guest: Arc<HashSet<String>>,
+    workspace: Arc<WorkspaceAccess>,
+    tool_naming: Arc<ToolNaming>,
+    wasi_ctx_hooks: Arc<Vec<WasiCtxHook>>,
+    output: Arc<ComponentOutput>,
+    pull: Arc<OciPull>,
+    provenance: Arc<ProvenanceConfig>,
+    validation: Arc<ValidationPool>,
+    libraries: Arc<HashMap<String, String>>,
+    instance_slots: Arc<InstanceSlots>,
+    warm: Arc<WarmPool>,
+    result_cache: Arc<ResultCache>,
+    state: Arc<StateStore>,
+    vectors: Arc<VectorStore>,
+    scheduler: Arc<Scheduler>,
+    call_timeouts: Arc<CallTimeouts>,
+    override_limits: OverrideLimits,
+    hooks: Arc<Hooks>,
+    session_profile: Arc<SessionProfile>,
+    browser: Option<Arc<HeadlessBrowser>>,
+    egress: Arc<Egress>,
+    outbox: Arc<Outbox>,
+    published: Arc<PublishedResources>,
+    prompts: Arc<ComponentPrompts>,
+    source_watch: Option<Arc<SourceWatch>>,
+    updates: Option<Arc<UpdateConfig>>,
+    kept_versions: usize,
+    permission_prompts: PermissionPrompts,
+    rollouts: Arc<Rollouts>,
+    call_stats: Arc<CallStats>,
+    component_metrics: Arc<ComponentMetrics>,
+    audit: Arc<AuditLog>,
+    anomalies: Arc<AnomalyDetector>,
+    journal: Arc<CallJournal>,
+    catalog: Arc<CatalogConfig>,
+    lock: Arc<ComponentLock>,
+    aliases: Arc<ComponentAliases>,
+    /// Held for reading by every component operation; set once the manager shuts down
+    shut_down: Arc<RwLock<bool>>,
 }
 
 /// A representation of a loaded component instance. It contains both the base component info and a
@@ -309,6 +463,39 @@ pub struct LifecycleManager {
 pub struct ComponentInstance {
     component: Arc<Component>,
     instance_pre: Arc<InstancePre<WassetteWasiState<WasiState>>>,
+    /// Optional WebAssembly proposals the component uses
+    wasm_proposals: BTreeSet<WasmProposal>,
+}
+
+/// What a component's bytes say about it besides its exports, read once from the bytes the
+/// component is compiled from. The argument defaults end up in the tool schemas saved with
+/// the component's metadata, so later loads from that metadata don't read the bytes at all.
+#[derive(Debug, Clone, Default)]
+struct ArtifactDetails {
+    /// Argument defaults declared in the `wassette:defaults` section
+    argument_defaults: Map<String, Value>,
+    /// Usage guide embedded in the `wassette:readme` section
+    readme: Option<String>,
+    /// Suspicious traits found by the load-time analysis
+    warnings: Vec<ComponentWarning>,
+}
+
+impl ArtifactDetails {
+    fn from_bytes(wasm_bytes: &[u8]) -> Self {
+        Self {
+            argument_defaults: argument_defaults_from_bytes(wasm_bytes),
+            readme: readme_from_bytes(wasm_bytes),
+            warnings: analysis::analyze(wasm_bytes),
+        }
+    }
+}
+
+/// A compiled component version waiting to be swapped in.
+struct PreparedComponent {
+    instance: ComponentInstance,
+    tools: Vec<ToolMetadata>,
+    prompts: Vec<Prompt>,
+    details: ArtifactDetails,
 }
 
 impl LifecycleManager {
@@ -336,19 +523,96 @@ impl LifecycleManager {
     /// Construct a lifecycle manager from an explicit configuration without loading components.
     #[instrument(skip_all, fields(plugin_dir = %config.plugin_dir().display()))]
     pub async fn from_config(config: LifecycleConfig) -> Result<Self> {
-        let (plugin_dir, secrets_dir, environment_vars, http_client, oci_client, _) =
-            config.into_parts();
+        let LifecycleConfig {
+            plugin_dir,
+            secrets_dir,
+            secrets_backend,
+            environment_vars,
+            http_client,
+            oci_client,
+            eager_load: _,
+            coredump_on_trap,
+            strict_policy,
+            fs_hardening,
+            profile_guest,
+            workspace_components,
+            tool_naming,
+            wasi_ctx_hooks,
+            output,
+            pull_options,
+            registries,
+            signatures,
+            provenance,
+            libraries,
+            tools,
+            max_concurrent_calls,
+            call_timeout,
+            override_ceiling,
+            hooks,
+            session_profile,
+            wasm_proposals,
+            browser,
+            network,
+            watch,
+            updates,
+            kept_versions,
+            permission_prompts,
+            anomaly_detection,
+            catalog,
+            lockfile,
+            locked,
+            runtime_settings,
+        } = config;
 
         let storage =
             ComponentStorage::new(plugin_dir.clone(), DEFAULT_DOWNLOAD_CONCURRENCY).await?;
+        let migration = migrate_state(&plugin_dir, false).await?;
+        for change in &migration.changes {
+            info!(plugin_dir = %plugin_dir.display(), "Migration: {change}");
+        }
 
-        let runtime = Arc::new(RuntimeContext::initialize()?);
+        let scheduler = Arc::new(Scheduler::new(&tools, max_concurrent_calls));
+        let journal = Arc::new(CallJournal::new(
+            &plugin_dir,
+            tools
+                .iter()
+                .filter(|(_, settings)| settings.idempotent)
+                .map(|(tool, _)| tool.clone())
+                .collect(),
+        ));
+        let call_timeouts = Arc::new(CallTimeouts::new(call_timeout, &tools));
+        let override_limits = override_ceiling.limits()?;
+        let runtime = Arc::new(RuntimeContext::initialize(&RuntimeOptions {
+            coredump_on_trap,
+            wasm_proposals,
+            pooling: runtime_settings.pool_size.is_some(),
+        })?);
+        let validation = match runtime_settings.validation_threads {
+            Some(threads) => ValidationPool::new(
+                threads,
+                runtime_settings
+                    .validation_budget_ms
+                    .map_or(DEFAULT_VALIDATION_BUDGET, Duration::from_millis),
+            )?,
+            None => ValidationPool::inline(),
+        };
 
-        let secrets_manager = Arc::new(SecretsManager::new(secrets_dir.clone()));
+        let secrets_manager = Arc::new(SecretsManager::with_backend(
+            secrets_dir.clone(),
+            secrets_backend.open(&secrets_dir, &http_client)?,
+        ));
         secrets_manager.ensure_secrets_dir().await?;
 
         let environment_vars = Arc::new(environment_vars);
         let oci_client = Arc::new(oci_wasm::WasmClient::new(oci_client));
+        let pull = Arc::new(
+            OciPull::new(
+                pull_options,
+                registries,
+                storage.downloads_dir().join(oci_pull::PARTIAL_DIR),
+            )
+            .with_signatures(SignatureVerifier::new(&signatures)?),
+        );
 
         let policy_manager = PolicyManager::new(
             storage.clone(),
@@ -356,9 +620,25 @@ impl LifecycleManager {
             Arc::clone(&environment_vars),
             Arc::clone(&oci_client),
             http_client.clone(),
+            strict_policy,
         );
 
-        Ok(Self {
+        let hooks = Arc::new(Hooks::new(hooks, http_client.clone()));
+        let browser = browser
+            .map(HeadlessBrowser::new)
+            .transpose()
+            .context("Failed to set up the headless browser")?
+            .map(Arc::new);
+        let egress = Arc::new(Egress::new(&network)?);
+        let outbox = Arc::new(Outbox::new(Arc::clone(&secrets_manager)));
+        let source_watch = watch.then(SourceWatch::new).transpose()?.map(Arc::new);
+        let state = Arc::new(StateStore::new(
+            storage.clone(),
+            Arc::clone(&secrets_manager),
+        ));
+        let vectors = Arc::new(VectorStore::new(storage.clone()));
+
+        let manager = Self {
             runtime,
             registry: ComponentRegistry::new(),
             storage,
@@ -366,58 +646,241 @@ impl LifecycleManager {
             oci_client,
             http_client,
             secrets_manager,
-        })
+            runtime_config: Arc::new(RuntimeConfig::new(&secrets_dir)),
+            coredump_on_trap,
+            fs_hardening,
+            profile_guest: Arc::new(profile_guest),
+            workspace: Arc::new(WorkspaceAccess::new(workspace_components)),
+            tool_naming: Arc::new(tool_naming),
+            wasi_ctx_hooks: Arc::new(wasi_ctx_hooks),
+            output: Arc::new(ComponentOutput::new(
+                output,
+                plugin_dir.join(output::LOGS_DIR),
+            )),
+            pull,
+            provenance: Arc::new(provenance),
+            validation: Arc::new(validation),
+            libraries: Arc::new(libraries),
+            instance_slots: Arc::new(InstanceSlots::default()),
+            warm: Arc::new(WarmPool::new(
+                &tools,
+                runtime_settings.pool_size.unwrap_or(1),
+            )),
+            result_cache: Arc::new(ResultCache::default()),
+            state,
+            vectors,
+            scheduler,
+            call_timeouts,
+            override_limits,
+            hooks,
+            session_profile: Arc::new(session_profile),
+            browser,
+            egress,
+            outbox,
+            published: Arc::new(PublishedResources::default()),
+            prompts: Arc::new(ComponentPrompts::default()),
+            source_watch,
+            updates: updates.map(Arc::new),
+            kept_versions,
+            permission_prompts,
+            rollouts: Arc::new(Rollouts::new(&plugin_dir)),
+            call_stats: Arc::new(CallStats::default()),
+            component_metrics: Arc::new(ComponentMetrics::default()),
+            audit: Arc::new(AuditLog::new(&plugin_dir)),
+            anomalies: Arc::new(AnomalyDetector::new(anomaly_detection)),
+            journal,
+            catalog: Arc::new(catalog),
+            lock: Arc::new(ComponentLock::open(lockfile, locked).await?),
+            aliases: Arc::new(ComponentAliases::load(&plugin_dir).await?),
+            shut_down: Arc::new(RwLock::new(false)),
+        };
+
+        // Restore the tool index from persisted metadata so tools can be listed before (and
+        // without) compiling anything.
+        if let Err(error) = manager.populate_registry_from_metadata().await {
+            warn!(%error, "Failed to restore tool index from component metadata");
+        }
+
+        Ok(manager)
     }
 
     /// Load every component present in the plugin directory, updating the registry and cache.
+    /// Components are compiled and registered concurrently, a few at a time, and the time each
+    /// took is logged in a summary once all are done.
     #[instrument(skip(self))]
     pub async fn load_all_components(&self) -> Result<()> {
-        let loaded_components =
-            load_components_parallel(self.storage.root(), Arc::clone(&self.runtime)).await?;
+        let started = Instant::now();
+        let semaphore = Arc::new(Semaphore::new(default_load_concurrency()));
+        let mut tasks = JoinSet::new();
+        let mut entries = tokio::fs::read_dir(self.storage.root()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let manager = self.clone();
+            let semaphore = Arc::clone(&semaphore);
+            tasks.spawn(
+                async move {
+                    let path = entry.path();
+                    let _permit = semaphore.acquire().await;
+                    let load_started = Instant::now();
+                    let result = manager.load_stored_component(entry).await;
+                    (path, result, load_started.elapsed())
+                }
+                .in_current_span(),
+            );
+        }
 
-        let mut registered_ids = Vec::new();
+        LoadSummary::collect(tasks).await.log(started.elapsed());
+        Ok(())
+    }
 
-        for (component_instance, name) in loaded_components {
-            let tool_metadata = component_exports_to_tools(
-                &component_instance.component,
-                self.runtime.as_ref(),
-                true,
+    /// Compile and register the component stored at `entry`, returning its id, or `None` if
+    /// `entry` isn't a component.
+    async fn load_stored_component(&self, entry: DirEntry) -> Result<Option<String>> {
+        let Some((component_instance, details, name)) =
+            load_component_from_entry(Arc::clone(&self.runtime), entry).await?
+        else {
+            return Ok(None);
+        };
+        if let Err(error) = self.verify_stored_component(&name).await {
+            self.hooks.fire(
+                HookEvent::Error,
+                Some(&name),
+                json!({"error": format!("{error:#}")}),
             );
-
-            if let Err(error) = self
-                .registry
-                .upsert_component(name.clone(), component_instance, tool_metadata)
+            return Err(error);
+        }
+        let mut tool_metadata =
+            component_exports_to_tools(&component_instance.component, self.runtime.as_ref(), true);
+        completion::strip_completion_hooks(
+            &mut tool_metadata,
+            &completion::completion_hooks(&component_instance.component, self.runtime.as_ref()),
+        );
+        let prompts_export =
+            prompts::prompts_export(&component_instance.component, self.runtime.as_ref());
+        prompts::strip_prompts_export(&mut tool_metadata, prompts_export.as_deref());
+        let component_prompts = self
+            .read_prompts(&name, &component_instance, prompts_export.as_deref())
+            .await;
+        apply_argument_defaults(&mut tool_metadata, &details.argument_defaults);
+        let wasm_path = self.component_path(&name);
+        if let Ok(validation_stamp) = self
+            .storage
+            .create_validation_stamp(&wasm_path, false)
+            .await
+        {
+            if let Err(e) = self
+                .save_component_metadata(
+                    &name,
+                    &tool_metadata,
+                    &component_instance.wasm_proposals,
+                    &component_prompts,
+                    &details,
+                    validation_stamp,
+                )
                 .await
             {
-                warn!(%name, %error, "Failed to register component in registry");
-                continue;
+                warn!(component_id = %name, error = %e, "Failed to save component metadata");
             }
-
-            registered_ids.push(name);
         }
 
-        for component_id in registered_ids {
-            if let Err(error) = self.restore_policy_attachment(&component_id).await {
-                warn!(%component_id, %error, "Failed to restore policy attachment");
-            }
+        let _lease = self.registry.lock(&name).await?;
+        self.registry
+            .upsert_component(name.clone(), component_instance, tool_metadata)
+            .await
+            .context("Failed to register component in registry")?;
+        self.prompts.set(&name, component_prompts);
+        self.published.set_readme(&name, details.readme.as_deref());
+
+        if let Err(error) = self.restore_policy_attachment(&name).await {
+            warn!(component_id = %name, %error, "Failed to restore policy attachment");
         }
+        self.component_changed(&name).await;
+        Ok(Some(name))
+    }
 
-        info!("LifecycleManager finished loading components");
-        Ok(())
+    /// Check a stored component against its provenance pin and, in locked mode, its locked
+    /// digest before compiling it.
+    async fn verify_stored_component(&self, component_id: &str) -> Result<()> {
+        self.lock
+            .check_stored(component_id, &self.component_path(component_id))
+            .await
+            .context("Refusing to load stored component")?;
+        if !self.provenance.is_pinned(component_id) {
+            return Ok(());
+        }
+        let manifest = self.storage.read_manifest(component_id).await?;
+        self.provenance
+            .verify(component_id, manifest.as_ref())
+            .context("Refusing to load stored component")
     }
 
     async fn restore_policy_attachment(&self, component_id: &str) -> Result<()> {
         self.policy_manager.restore_from_disk(component_id).await
     }
 
-    async fn resolve_component_resource(&self, uri: &str) -> Result<(String, DownloadedResource)> {
-        let resource =
-            loader::load_resource::<ComponentResource>(uri, &self.oci_client, &self.http_client)
-                .await?;
+    async fn resolve_component_resource(
+        &self,
+        uri: &str,
+        context: &LoadContext,
+    ) -> Result<(String, DownloadedResource)> {
+        let resource = loader::load_resource::<ComponentResource>(
+            uri,
+            &self.oci_client,
+            &self.http_client,
+            context,
+        )
+        .await?;
         let id = resource.id()?;
         Ok((id, resource))
     }
 
+    /// Where the library `dependency` is fetched from: its `[libraries]` location if
+    /// configured, otherwise the one in the manifest.
+    pub fn library_source<'a>(&'a self, dependency: &'a Dependency) -> &'a str {
+        self.libraries
+            .get(&dependency.name)
+            .unwrap_or(&dependency.component)
+    }
+
+    /// Compose the downloaded component in `resource` with the libraries it depends on, in
+    /// place, fetching each from its `[libraries]` location or else the manifest's.
+    async fn link_libraries(
+        &self,
+        component_id: &str,
+        dependencies: &[Dependency],
+        resource: &DownloadedResource,
+        context: &LoadContext,
+    ) -> Result<()> {
+        let DownloadedResource::Temp((_, path)) = resource else {
+            bail!("Only downloaded components can be composed with libraries");
+        };
+        let mut libraries = Vec::with_capacity(dependencies.len());
+        for dependency in dependencies {
+            let uri = self.library_source(dependency);
+            let library = loader::load_resource::<ComponentResource>(
+                uri,
+                &self.oci_client,
+                &self.http_client,
+                context,
+            )
+            .await
+            .with_context(|| format!("Failed to fetch library {}", dependency.name))?;
+            let bytes = tokio::fs::read(library.as_ref()).await?;
+            libraries.push((dependency.name.clone(), bytes));
+        }
+
+        let component = tokio::fs::read(path).await?;
+        let composed =
+            tokio::task::spawn_blocking(move || compose::compose(&component, &libraries)).await??;
+        // Written over the download so the policy and release stored next to it stay put.
+        tokio::fs::write(path, composed).await?;
+        info!(
+            %component_id,
+            libraries = ?dependencies.iter().map(|d| d.name.as_str()).collect::<Vec<_>>(),
+            "Composed component with its libraries"
+        );
+        Ok(())
+    }
+
     async fn stage_component_artifact(
         &self,
         component_id: &str,
@@ -439,22 +902,76 @@ impl LifecycleManager {
         component_id: &str,
         wasm_path: &Path,
     ) -> Result<ComponentLoadOutcome> {
-        let (component, _wasm_bytes) = self
-            .load_component_optimized(wasm_path, component_id)
-            .await?;
+        let prepared = self.prepare_component(component_id, wasm_path).await?;
+        self.register_prepared(component_id, wasm_path, prepared, Vec::new())
+            .await
+    }
 
-        let instance_pre = self
-            .runtime
-            .instantiate_pre(&component)
-            .context("failed to instantiate component")?;
+    /// Compile the component at `wasm_path` and describe its tools, without registering it.
+    async fn prepare_component(
+        &self,
+        component_id: &str,
+        wasm_path: &Path,
+    ) -> Result<PreparedComponent> {
+        let compiled = async {
+            let (component, wasm_bytes) = self
+                .load_component_optimized(wasm_path, component_id)
+                .await?;
+            let instance_pre = self
+                .runtime
+                .instantiate_pre(&component)
+                .context("failed to instantiate component")?;
+            anyhow::Ok((component, wasm_bytes, instance_pre))
+        };
+        let (component, wasm_bytes, instance_pre) = match compiled.await {
+            Ok(compiled) => compiled,
+            Err(error) => return Err(diagnose_load_error(&self.runtime, wasm_path, error).await),
+        };
 
         let component_instance = ComponentInstance {
             component: Arc::new(component),
             instance_pre: Arc::new(instance_pre),
+            wasm_proposals: used_proposals(&wasm_bytes).unwrap_or_default(),
         };
 
-        let tool_metadata =
+        let mut tool_metadata =
             component_exports_to_tools(&component_instance.component, self.runtime.as_ref(), true);
+        completion::strip_completion_hooks(
+            &mut tool_metadata,
+            &completion::completion_hooks(&component_instance.component, self.runtime.as_ref()),
+        );
+        let prompts_export =
+            prompts::prompts_export(&component_instance.component, self.runtime.as_ref());
+        prompts::strip_prompts_export(&mut tool_metadata, prompts_export.as_deref());
+        let details = ArtifactDetails::from_bytes(&wasm_bytes);
+        apply_argument_defaults(&mut tool_metadata, &details.argument_defaults);
+        self.name_tools(component_id, &mut tool_metadata).await?;
+        let prompts = self
+            .read_prompts(component_id, &component_instance, prompts_export.as_deref())
+            .await;
+        Ok(PreparedComponent {
+            instance: component_instance,
+            tools: tool_metadata,
+            prompts,
+            details,
+        })
+    }
+
+    /// Swap `prepared` in for the running version of `component_id`, if any, keeping
+    /// `standbys` for the next calls of their tools.
+    async fn register_prepared(
+        &self,
+        component_id: &str,
+        wasm_path: &Path,
+        prepared: PreparedComponent,
+        standbys: Vec<(String, Standby)>,
+    ) -> Result<ComponentLoadOutcome> {
+        let PreparedComponent {
+            instance: component_instance,
+            tools: tool_metadata,
+            prompts: component_prompts,
+            details,
+        } = prepared;
         let tool_names: Vec<String> = tool_metadata
             .iter()
             .map(|tool| tool.normalized_name.clone())
@@ -462,7 +979,14 @@ impl LifecycleManager {
 
         if let Ok(validation_stamp) = self.storage.create_validation_stamp(wasm_path, false).await {
             if let Err(e) = self
-                .save_component_metadata(component_id, &tool_metadata, validation_stamp)
+                .save_component_metadata(
+                    component_id,
+                    &tool_metadata,
+                    &component_instance.wasm_proposals,
+                    &component_prompts,
+                    &details,
+                    validation_stamp,
+                )
                 .await
             {
                 warn!(%component_id, error = %e, "Failed to save component metadata");
@@ -473,18 +997,82 @@ impl LifecycleManager {
             .registry
             .upsert_component(component_id.to_string(), component_instance, tool_metadata)
             .await?;
+        self.prompts.set(component_id, component_prompts);
+        self.published
+            .set_readme(component_id, details.readme.as_deref());
+        self.component_metrics.record_load(component_id);
+        for warning in &details.warnings {
+            warn!(%component_id, kind = ?warning.kind, "Suspicious component: {warning}");
+        }
 
         if let Err(error) = self.policy_manager.restore_from_disk(component_id).await {
             warn!(%component_id, %error, "Failed to restore policy attachment");
         }
+        self.component_swapped(component_id, standbys).await;
 
         Ok(ComponentLoadOutcome {
             component_id: component_id.to_string(),
             status: load_result,
             tool_names,
+            warnings: details.warnings,
         })
     }
 
+    /// Read the prompts a component exports from `interface`, running it under its policy.
+    /// Components whose prompts can't be read are loaded without them.
+    async fn read_prompts(
+        &self,
+        component_id: &str,
+        component: &ComponentInstance,
+        interface: Option<&str>,
+    ) -> Vec<Prompt> {
+        let Some(interface) = interface else {
+            return Vec::new();
+        };
+        let read = async {
+            let mut standby = self.instantiate_for(component_id, component, false).await?;
+            prompts::list(&mut standby.store, &standby.instance, interface).await
+        };
+        match read.await {
+            Ok(prompts) => prompts,
+            Err(error) => {
+                warn!(%component_id, %error, "Failed to read the component's prompts");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Apply the publisher namespace and the collision policy to a component's tool names.
+    async fn name_tools(&self, component_id: &str, tools: &mut [ToolMetadata]) -> Result<()> {
+        let namespace = match self.storage.read_manifest(component_id).await {
+            Ok(manifest) => manifest.and_then(|manifest| manifest.publisher.namespace),
+            Err(error) => {
+                warn!(%component_id, %error, "Ignoring unreadable component manifest");
+                None
+            }
+        };
+        if let Some(namespace) = namespace {
+            self.tool_naming.apply_namespace(tools, &namespace);
+        }
+
+        for tool in tools.iter_mut() {
+            let other = self
+                .registry
+                .tool_infos(&tool.normalized_name)
+                .await
+                .and_then(|infos| {
+                    infos
+                        .into_iter()
+                        .find(|info| info.component_id != component_id)
+                });
+            if let Some(other) = other {
+                self.tool_naming
+                    .resolve_collision(tool, component_id, &other.component_id)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Loads a new component from the given URI. This URI can be a file path, an OCI reference, or a URL.
     ///
     /// If a component with the given id already exists, it will be updated with the new component.
@@ -492,20 +1080,152 @@ impl LifecycleManager {
     /// component and whether it replaced an existing instance.
     #[instrument(skip(self))]
     pub async fn load_component(&self, uri: &str) -> Result<ComponentLoadOutcome> {
+        self.load_component_from(uri, None, &LoadContext::default())
+            .await
+    }
+
+    /// Same as [`load_component`](Self::load_component), reporting download and compilation
+    /// progress through `context` and giving up if it is cancelled before the download
+    /// completes.
+    #[instrument(skip(self, context))]
+    pub async fn load_component_with_context(
+        &self,
+        uri: &str,
+        context: &LoadContext,
+    ) -> Result<ComponentLoadOutcome> {
+        self.load_component_from(uri, None, context).await
+    }
+
+    /// Load a component, recording the manifest it was installed from (if any) so its tools
+    /// are named after the publisher's namespace.
+    async fn load_component_from(
+        &self,
+        uri: &str,
+        manifest: Option<Manifest>,
+        context: &LoadContext,
+    ) -> Result<ComponentLoadOutcome> {
+        match self.load_component_inner(uri, manifest, context).await {
+            Ok(outcome) => {
+                self.hooks.fire(
+                    HookEvent::Load,
+                    Some(&outcome.component_id),
+                    json!({
+                        "uri": uri,
+                        "replaced": outcome.status == LoadResult::Replaced,
+                        "tools": outcome.tool_names,
+                    }),
+                );
+                Ok(outcome)
+            }
+            Err(error) => {
+                self.hooks.fire(
+                    HookEvent::Error,
+                    None,
+                    json!({"uri": uri, "error": format!("{error:#}")}),
+                );
+                Err(error)
+            }
+        }
+    }
+
+    async fn load_component_inner(
+        &self,
+        uri: &str,
+        manifest: Option<Manifest>,
+        context: &LoadContext,
+    ) -> Result<ComponentLoadOutcome> {
         debug!(uri, "Loading component");
-        let (component_id, resource) = self.resolve_component_resource(uri).await?;
-        let staged_path = self
-            .stage_component_artifact(&component_id, resource)
+        let context = context.clone().with_pull(Arc::clone(&self.pull));
+        let (derived_id, resource) = context
+            .cancellable(self.resolve_component_resource(uri, &context))
             .await?;
+        let component_id = self
+            .resolve_component_id(
+                manifest
+                    .as_ref()
+                    .and_then(|manifest| manifest.id.as_deref())
+                    .unwrap_or(&derived_id),
+            )
+            .await;
+        self.provenance
+            .verify(&component_id, manifest.as_ref())
+            .context("Refusing to load component")?;
+        if let Some(manifest) = manifest
+            .as_ref()
+            .filter(|manifest| !manifest.dependencies.is_empty())
+        {
+            context
+                .cancellable(self.link_libraries(
+                    &component_id,
+                    &manifest.dependencies,
+                    &resource,
+                    &context,
+                ))
+                .await
+                .context("Failed to compose component with its libraries")?;
+        }
+        let digest = if lockfile::is_locked_source(uri) {
+            Some(updates::file_digest(resource.as_ref()).await?)
+        } else {
+            None
+        };
+        if let Some(digest) = &digest {
+            self.lock
+                .check(&component_id, uri, digest)
+                .await
+                .context("Refusing to load component")?;
+        }
+        let release = match resource.release().await {
+            Some(release) => Some(release),
+            None => manifest.as_ref().map(|manifest| Release {
+                version: Some(manifest.version.clone()),
+                changelog: None,
+            }),
+        };
+        let id = component_id.clone();
+        let source = uri.to_string();
+        let update_source = UpdateSource::for_uri(uri);
         let outcome = self
-            .compile_and_register_component(&component_id, &staged_path)
-            .await
-            .with_context(|| {
-                format!(
-                    "Failed to compile component from path: {}. Please ensure the file is a valid WebAssembly component.",
-                    staged_path.display()
-                )
-            })?;
+            .run_exclusive(&component_id, move |manager| async move {
+                // Last chance to back out: from here on the stored artifact is replaced.
+                context.check_cancelled()?;
+                context.report(LoadStage::Compiling, 0, None);
+                let previous_digest = manager.installed_digest(&id).await;
+                let outcome = manager
+                    .roll_out_component(
+                        &id,
+                        resource,
+                        manifest,
+                        update_source,
+                        context.keeps_policy(),
+                    )
+                    .await?;
+                if let Err(error) = manager.lock.record(&id, &source, digest.as_deref()).await {
+                    warn!(component_id = %id, %error, "Failed to update the lockfile");
+                }
+                let kind = if previous_digest.is_some() {
+                    VersionChangeKind::Update
+                } else {
+                    VersionChangeKind::Install
+                };
+                manager
+                    .record_version(&id, kind, previous_digest, |change| {
+                        change.with_release(&source, release)
+                    })
+                    .await;
+                Ok(outcome)
+            })
+            .await?;
+
+        if let (Some(watch), Some(path)) = (&self.source_watch, uri.strip_prefix("file://")) {
+            if let Err(error) = watch.track(&outcome.component_id, Path::new(path)).await {
+                warn!(
+                    component_id = %outcome.component_id,
+                    %error,
+                    "Failed to watch component source"
+                );
+            }
+        }
 
         info!(
             component_id = %outcome.component_id,
@@ -516,37 +1236,307 @@ impl LifecycleManager {
         Ok(outcome)
     }
 
-    /// Unloads the component with the specified id. This removes the component from the runtime
-    /// and removes all associated files from disk, making it the reverse operation of load_component.
-    /// This function fails if any files cannot be removed (except when they don't exist).
-    #[instrument(skip(self))]
-    pub async fn unload_component(&self, id: &str) -> Result<()> {
-        debug!("Unloading component and removing files from disk");
+    /// Swap the version of `component_id` in `resource` in for the running version, if any,
+    /// in the stages laid out in [`rollout`], keeping the running version's policy if
+    /// `keep_policy`. Must run under the component's registry lease.
+    async fn roll_out_component(
+        &self,
+        component_id: &str,
+        resource: DownloadedResource,
+        manifest: Option<Manifest>,
+        update_source: Option<UpdateSource>,
+        keep_policy: bool,
+    ) -> Result<ComponentLoadOutcome> {
+        let previous = self.registry.get_component(component_id).await;
+        let snapshot = if self.component_path(component_id).exists() {
+            Some(Snapshot::capture(&self.storage, component_id).await?)
+        } else {
+            None
+        };
+        let replaced_version = match &snapshot {
+            Some(_) => self.installed_version(component_id).await,
+            None => None,
+        };
+        let mut rollout = self.rollouts.start(component_id, snapshot.is_some());
+        let staged = self
+            .stage_rollout(
+                &mut rollout,
+                component_id,
+                resource,
+                manifest,
+                update_source,
+                snapshot.as_ref().filter(|_| keep_policy),
+            )
+            .await;
+        let outcome = match staged {
+            Ok(outcome) => outcome,
+            Err(error) => {
+                // The registry still holds the running version; only its files need restoring.
+                let restored = match snapshot {
+                    Some(snapshot) => snapshot.restore(&self.storage, component_id).await,
+                    None => self.remove_staged_files(component_id).await,
+                };
+                if let Err(restore_error) = restored {
+                    warn!(%component_id, error = %restore_error, "Failed to restore component files");
+                }
+                rollout.roll_back(&error);
+                return Err(error);
+            }
+        };
+        if let Some(snapshot) = &snapshot {
+            self.keep_version(component_id, snapshot, replaced_version)
+                .await;
+        }
 
-        // Remove files first, then clean up memory on success
-        self.storage.remove_component_artifacts(id).await?;
+        match previous {
+            Some(previous) => {
+                rollout.enter(RolloutStage::Drain);
+                // Calls in flight hold the old version; the standbys built from it were dropped
+                // by the swap.
+                let old = Arc::downgrade(&previous.instance_pre);
+                drop(previous);
+                let component_id = component_id.to_string();
+                tokio::spawn(async move {
+                    let started = Instant::now();
+                    while old.strong_count() > 0 {
+                        if started.elapsed() >= rollout::DRAIN_TIMEOUT {
+                            warn!(%component_id, "Calls on the replaced version are still running");
+                            break;
+                        }
+                        tokio::time::sleep(rollout::DRAIN_POLL).await;
+                    }
+                    rollout.complete();
+                });
+            }
+            None => rollout.complete(),
+        }
+        Ok(outcome)
+    }
 
-        let policy_path = self.get_component_policy_path(id);
-        self.storage
-            .remove_if_exists(&policy_path, "policy file", id)
+    /// The load, verify, warm and swap stages of a rollout. The policy of `kept_policy` replaces
+    /// any published with the new version before it is verified.
+    async fn stage_rollout(
+        &self,
+        rollout: &mut Rollout,
+        component_id: &str,
+        resource: DownloadedResource,
+        manifest: Option<Manifest>,
+        update_source: Option<UpdateSource>,
+        kept_policy: Option<&Snapshot>,
+    ) -> Result<ComponentLoadOutcome> {
+        let staged_path = self
+            .stage_component_artifact(component_id, resource)
             .await?;
-
-        let metadata_path = self.get_component_metadata_path(id);
+        if let Some(snapshot) = kept_policy {
+            snapshot.restore_policy(&self.storage, component_id).await?;
+        }
         self.storage
-            .remove_if_exists(&metadata_path, "policy metadata file", id)
+            .write_manifest(component_id, manifest.as_ref())
             .await?;
+        // A manifest install records the manifest's location instead.
+        if manifest.is_none() {
+            self.storage
+                .write_update_source(component_id, update_source.as_ref())
+                .await?;
+        }
+        let prepared = self
+            .prepare_component(component_id, &staged_path)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to compile component from path: {}. Please ensure the file is a valid WebAssembly component.",
+                    staged_path.display()
+                )
+            })?;
 
-        // Only cleanup memory after all files are successfully removed
-        self.registry.remove_component(id).await;
-        self.policy_manager.cleanup(id).await;
+        rollout.enter(RolloutStage::Verify);
+        // Proposals are checked per call: policies often grant them after the load.
+        let verified = self
+            .instantiate_for(component_id, &prepared.instance, false)
+            .await
+            .context("Failed to instantiate the new version of the component")?;
 
-        info!(component_id = %id, "Component unloaded successfully");
-        Ok(())
+        rollout.enter(RolloutStage::Warm);
+        let standbys = self.warm_prepared(component_id, &prepared, verified).await;
+
+        rollout.enter(RolloutStage::Swap);
+        self.register_prepared(component_id, &staged_path, prepared, standbys)
+            .await
     }
 
-    /// Returns the component ID for a given tool name.
-    /// If there are multiple components with the same tool name, returns an error.
-    #[instrument(skip(self))]
+    /// Build the standbys of `prepared` for its latency-critical tools, starting with `verified`.
+    /// Tools whose standby can't be built are warmed again after the swap, and none are built
+    /// while the policy doesn't grant the proposals the component uses.
+    async fn warm_prepared(
+        &self,
+        component_id: &str,
+        prepared: &PreparedComponent,
+        verified: Standby,
+    ) -> Vec<(String, Standby)> {
+        // Profiled calls need a store that was sampled from the start.
+        if self.profile_guest.contains(component_id) {
+            return Vec::new();
+        }
+        let policy_template = self
+            .policy_manager
+            .template_for_component(component_id)
+            .await;
+        if let Err(error) = proposals::check_granted(
+            component_id,
+            &prepared.instance.wasm_proposals,
+            &policy_template.granted_proposals(),
+        ) {
+            debug!(%component_id, %error, "Not warming standby instances");
+            return Vec::new();
+        }
+        let mut verified = Some(verified);
+        let mut standbys = Vec::new();
+        for tool in &prepared.tools {
+            let tool = &tool.normalized_name;
+            if !self.warm.is_latency_critical(tool) {
+                continue;
+            }
+            for _ in 0..self.warm.size() {
+                let standby = match verified.take() {
+                    Some(standby) => Ok(standby),
+                    None => {
+                        self.instantiate_for(component_id, &prepared.instance, false)
+                            .await
+                    }
+                };
+                match standby {
+                    Ok(standby) => standbys.push((tool.clone(), standby)),
+                    Err(error) => {
+                        warn!(%component_id, %tool, %error, "Failed to warm standby instance");
+                        break;
+                    }
+                }
+            }
+        }
+        standbys
+    }
+
+    /// Keep the version of `component_id` in `snapshot`, released as `version`, to roll back
+    /// to, unless it is the version installed now.
+    async fn keep_version(&self, component_id: &str, snapshot: &Snapshot, version: Option<String>) {
+        if self.installed_digest(component_id).await == Some(snapshot.digest()) {
+            return;
+        }
+        if let Err(error) = versions::keep(
+            self.plugin_root(),
+            component_id,
+            snapshot,
+            version,
+            self.kept_versions,
+        )
+        .await
+        {
+            warn!(%component_id, %error, "Failed to keep the replaced version");
+        }
+    }
+
+    /// Remove the files staged for a component that wasn't stored before.
+    async fn remove_staged_files(&self, component_id: &str) -> Result<()> {
+        self.storage
+            .remove_component_artifacts(component_id)
+            .await?;
+        self.storage.write_manifest(component_id, None).await?;
+        self.storage.write_update_source(component_id, None).await
+    }
+
+    /// Unloads the component with the specified id. This removes the component from the runtime
+    /// and removes all associated files from disk, making it the reverse operation of load_component.
+    /// This function fails if any files cannot be removed (except when they don't exist).
+    #[instrument(skip(self))]
+    pub async fn unload_component(&self, id: &str) -> Result<()> {
+        let id = &*self.resolve_component_id(id).await;
+        debug!("Unloading component and removing files from disk");
+
+        let component_id = id.to_string();
+        self.run_exclusive(id, move |manager| async move {
+            let id = component_id.as_str();
+
+            // Remove files first, then clean up memory on success
+            manager.storage.remove_component_artifacts(id).await?;
+
+            let policy_path = manager.get_component_policy_path(id);
+            manager
+                .storage
+                .remove_if_exists(&policy_path, "policy file", id)
+                .await?;
+
+            let metadata_path = manager.get_component_metadata_path(id);
+            manager
+                .storage
+                .remove_if_exists(&metadata_path, "policy metadata file", id)
+                .await?;
+            manager.storage.write_manifest(id, None).await?;
+            manager.storage.write_update_source(id, None).await?;
+            versions::forget(manager.plugin_root(), id).await?;
+            if let Err(error) = manager.lock.remove(id).await {
+                warn!(component_id = %id, %error, "Failed to update the lockfile");
+            }
+
+            // Only cleanup memory after all files are successfully removed
+            manager.registry.remove_component(id).await?;
+            manager.component_metrics.record_unload(id);
+            manager.policy_manager.cleanup(id).await;
+            manager.warm.forget(id);
+            manager.result_cache.forget(id);
+            manager.published.forget(id);
+            manager.prompts.forget(id);
+            Ok(())
+        })
+        .await?;
+
+        if let Some(watch) = &self.source_watch {
+            watch.untrack(id);
+        }
+        info!(component_id = %id, "Component unloaded successfully");
+        self.hooks.fire(HookEvent::Unload, Some(id), json!({}));
+        Ok(())
+    }
+
+    /// Run a multi-step operation on `component_id` while holding its registry lease.
+    ///
+    /// Operations on the same component run one at a time, in arrival order. The operation
+    /// runs on its own task, so cancelling the caller (e.g. an aborted MCP request) can't
+    /// interrupt it between updating the files on disk and the in-memory registry.
+    async fn run_exclusive<T, Fut>(
+        &self,
+        component_id: &str,
+        op: impl FnOnce(Self) -> Fut,
+    ) -> Result<T>
+    where
+        Fut: std::future::Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let running = Arc::clone(&self.shut_down).read_owned().await;
+        if *running {
+            bail!("The server is shutting down");
+        }
+        let lease = self.registry.lock(component_id).await?;
+        let op = op(self.clone());
+        tokio::spawn(async move {
+            let result = op.await;
+            drop(lease);
+            drop(running);
+            result
+        })
+        .await
+        .context("Component operation panicked")?
+    }
+
+    /// Wait for the component loads, updates and unloads in progress to finish and refuse new
+    /// ones, so the process can exit without leaving a component half replaced. Component
+    /// state and the lockfile are written as they change, so nothing else needs saving.
+    pub async fn shutdown(&self) {
+        *self.shut_down.write().await = true;
+    }
+
+    /// Returns the component ID for a given tool name.
+    /// If there are multiple components with the same tool name, returns an error.
+    #[instrument(skip(self))]
     pub async fn get_component_id_for_tool(&self, tool_name: &str) -> Result<String> {
         let tool_infos = self
             .registry
@@ -569,7 +1559,11 @@ impl LifecycleManager {
         Ok(tool_infos[0].component_id.clone())
     }
 
-    /// Lists all available tools across all components
+    /// Lists all available tools across all components.
+    ///
+    /// Tools are served from the in-memory tool index, which is restored from persisted
+    /// component metadata at startup and updated whenever a component is (re)loaded, so listing
+    /// never compiles or instantiates anything.
     #[instrument(skip(self))]
     pub async fn list_tools(&self) -> Vec<Value> {
         self.registry.list_tools().await
@@ -582,6 +1576,7 @@ impl LifecycleManager {
         component_id: &str,
         tool_name: &str,
     ) -> Option<Value> {
+        let component_id = &*self.resolve_component_id(component_id).await;
         let tool_infos = self.registry.tool_infos(tool_name).await?;
         tool_infos
             .iter()
@@ -592,6 +1587,7 @@ impl LifecycleManager {
     /// Returns the requested component. Returns `None` if the component is not found.
     #[instrument(skip(self))]
     pub async fn get_component(&self, component_id: &str) -> Option<ComponentInstance> {
+        let component_id = &*self.resolve_component_id(component_id).await;
         self.registry.get_component(component_id).await
     }
 
@@ -601,11 +1597,157 @@ impl LifecycleManager {
         self.registry.list_components().await
     }
 
+    /// Search the configured catalog indexes for components matching every word of `query`.
+    /// Indexes that can't be read are reported in the result instead of failing the search.
+    pub async fn search_catalog(&self, query: &str) -> CatalogSearch {
+        catalog::search(
+            &self.catalog,
+            query,
+            &self.oci_client,
+            &self.http_client,
+            &self.pull,
+        )
+        .await
+    }
+
+    /// The component id that `id` stands for: the id an alias points to, or `id` itself.
+    pub async fn resolve_component_id(&self, id: &str) -> String {
+        self.aliases.resolve(id).await
+    }
+
+    /// Lists the component aliases, mapping each alias to the component id it stands for.
+    pub async fn list_component_aliases(&self) -> BTreeMap<String, String> {
+        self.aliases.list().await
+    }
+
+    /// Make `alias` stand for the known component `component_id`. Loading a source whose id is
+    /// `alias`, e.g. the same component from a new OCI repository, then replaces that component
+    /// and keeps its tools, policy, secrets and state.
+    #[instrument(skip(self))]
+    pub async fn alias_component(&self, component_id: &str, alias: &str) -> Result<()> {
+        aliases::check_component_id(alias)?;
+        let known = self.list_components_known().await;
+        if !known.iter().any(|id| id == component_id) {
+            bail!("Component not found: {component_id}");
+        }
+        if known.iter().any(|id| id == alias) {
+            bail!("'{alias}' is already the id of a component");
+        }
+        self.aliases.add(alias, component_id).await?;
+        info!(%component_id, %alias, "Added component alias");
+        Ok(())
+    }
+
+    /// Remove `alias`, returning the component id it stood for, or `None` if it wasn't an
+    /// alias.
+    #[instrument(skip(self))]
+    pub async fn remove_component_alias(&self, alias: &str) -> Result<Option<String>> {
+        let removed = self.aliases.remove(alias).await?;
+        if let Some(component_id) = &removed {
+            info!(%component_id, %alias, "Removed component alias");
+        }
+        Ok(removed)
+    }
+
+    /// Ids of the components whose `.wasm` is in the plugin directory, and of any others that
+    /// are loaded.
+    async fn installed_components(&self) -> Result<HashSet<String>> {
+        let mut installed: HashSet<String> =
+            self.registry.list_components().await.into_iter().collect();
+        let mut entries = tokio::fs::read_dir(self.storage.root()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "wasm") {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    installed.insert(stem.to_string());
+                }
+            }
+        }
+        Ok(installed)
+    }
+
+    /// Find the files and aliases left behind by components that are no longer installed.
+    /// With `include_secrets`, their secrets and configuration count too; leave those out when
+    /// other plugin directories share the secrets directory. Stores that can't be scanned are
+    /// named in the result.
+    #[instrument(skip(self))]
+    pub async fn find_orphans(&self, include_secrets: bool) -> Result<Orphans> {
+        let installed = self.installed_components().await?;
+        let secrets_dir = include_secrets.then(|| self.secrets_manager.secrets_dir());
+        let files = gc::find_orphaned_files(self.storage.root(), secrets_dir, &installed)?;
+        let mut not_scanned = Vec::new();
+        if !include_secrets {
+            not_scanned.push(format!(
+                "secrets and configuration in {}",
+                self.secrets_manager.secrets_dir().display()
+            ));
+        } else if let Some(store) = self.secrets_manager.backend().external_store() {
+            not_scanned.push(format!("secrets in {store}"));
+        }
+        let aliases = self
+            .aliases
+            .list()
+            .await
+            .into_iter()
+            .filter(|(_, component_id)| !installed.contains(component_id))
+            .collect();
+        Ok(Orphans {
+            files,
+            aliases,
+            not_scanned,
+        })
+    }
+
+    /// Remove what [`Self::find_orphans`] found, except what belongs to a component that has
+    /// been installed since. Returns how many files and aliases were removed.
+    #[instrument(skip(self, orphans))]
+    pub async fn remove_orphans(&self, orphans: &Orphans) -> Result<usize> {
+        let mut by_component: BTreeMap<String, Vec<OrphanedFile>> = BTreeMap::new();
+        for file in &orphans.files {
+            by_component
+                .entry(file.component_id.clone())
+                .or_default()
+                .push(file.clone());
+        }
+        let mut removed = 0;
+        for (component_id, files) in by_component {
+            let id = component_id.clone();
+            removed += self
+                .run_exclusive(&component_id, move |manager| async move {
+                    let component_id = id;
+                    if manager.component_path(&component_id).exists() {
+                        return Ok(0);
+                    }
+                    for file in &files {
+                        if matches!(file.kind, OrphanKind::Versions | OrphanKind::State) {
+                            versions::remove_dir_if_exists(&file.path).await?;
+                        } else {
+                            manager
+                                .storage
+                                .remove_if_exists(&file.path, "orphaned file", &component_id)
+                                .await?;
+                        }
+                    }
+                    manager.policy_manager.cleanup(&component_id).await;
+                    info!(%component_id, files = files.len(), "Removed orphaned files");
+                    Ok(files.len())
+                })
+                .await?;
+        }
+        let installed = self.installed_components().await?;
+        for (alias, component_id) in &orphans.aliases {
+            if !installed.contains(component_id) && self.aliases.remove(alias).await?.is_some() {
+                info!(%component_id, %alias, "Removed orphaned alias");
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
     /// Lists all known components by ID (union of loaded components and any
     /// `*.wasm` files present in the plugin directory). Does not compile components.
     #[instrument(skip(self))]
     pub async fn list_components_known(&self) -> Vec<String> {
-        use std::collections::HashSet;
         let loaded = self.registry.list_components().await;
         let mut set: HashSet<String> = loaded.into_iter().collect();
 
@@ -647,13 +1789,11 @@ impl LifecycleManager {
     /// Gets the schema for a specific component
     #[instrument(skip(self))]
     pub async fn get_component_schema(&self, component_id: &str) -> Option<Value> {
-        // Prefer live component schema if loaded
-        if let Some(component_instance) = self.get_component(component_id).await {
-            return Some(component_exports_to_json_schema(
-                &component_instance.component,
-                self.runtime.as_ref(),
-                true,
-            ));
+        let component_id = &*self.resolve_component_id(component_id).await;
+        // Prefer the tool index, which covers loaded components and those restored from
+        // metadata, so this never needs the runtime.
+        if let Some(tools) = self.registry.component_tools(component_id).await {
+            return Some(serde_json::json!({ "tools": tools }));
         }
 
         // Fallback to metadata-based schema without compiling the component
@@ -672,6 +1812,43 @@ impl LifecycleManager {
         }
     }
 
+    /// The optional WebAssembly proposals `component_id` uses, or `None` if it isn't known
+    /// or its metadata predates this information.
+    pub async fn get_component_wasm_proposals(
+        &self,
+        component_id: &str,
+    ) -> Option<BTreeSet<WasmProposal>> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        if let Some(component) = self.registry.get_component(component_id).await {
+            return Some(component.wasm_proposals);
+        }
+        self.load_component_metadata(component_id)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|metadata| metadata.wasm_proposals)
+    }
+
+    /// Suspicious traits found in the component when it was loaded, to review before granting
+    /// it permissions.
+    pub async fn get_component_warnings(&self, component_id: &str) -> Vec<ComponentWarning> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        let stored = self
+            .load_component_metadata(component_id)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|metadata| metadata.warnings);
+        if let Some(warnings) = stored {
+            return warnings;
+        }
+        // Metadata written by older releases has no analysis.
+        match tokio::fs::read(self.component_path(component_id)).await {
+            Ok(wasm_bytes) => analysis::analyze(&wasm_bytes),
+            Err(_) => Vec::new(),
+        }
+    }
+
     fn component_path(&self, component_id: &str) -> PathBuf {
         self.storage.component_path(component_id)
     }
@@ -691,21 +1868,110 @@ impl LifecycleManager {
 
     /// Attach a policy to a component by URI.
     pub async fn attach_policy(&self, component_id: &str, policy_uri: &str) -> Result<()> {
-        if !self.registry.contains_component(component_id).await {
-            return Err(anyhow!("Component not found: {}", component_id));
-        }
-        self.policy_manager
-            .attach_policy(component_id, policy_uri)
+        let component_id = &*self.resolve_component_id(component_id).await;
+        let policy_uri = policy_uri.to_string();
+        self.run_on_loaded(component_id, move |manager, id| async move {
+            manager.policy_manager.attach_policy(&id, &policy_uri).await
+        })
+        .await
+    }
+
+    /// Fetch a component manifest from an `https://` URL, a `file://` URI or a local path, and
+    /// verify its signature.
+    #[instrument(skip(self))]
+    pub async fn fetch_manifest(&self, location: &str) -> Result<Manifest> {
+        let bytes = if location.starts_with("https://") || location.starts_with("http://") {
+            let response = self
+                .http_client
+                .get(location)
+                .send()
+                .await
+                .with_context(|| format!("Failed to fetch manifest from {location}"))?;
+            if !response.status().is_success() {
+                bail!(
+                    "Failed to fetch manifest from {}: {}",
+                    location,
+                    response.status()
+                );
+            }
+            response.bytes().await?.to_vec()
+        } else {
+            let path = location.strip_prefix("file://").unwrap_or(location);
+            tokio::fs::read(path)
+                .await
+                .with_context(|| format!("Failed to read manifest: {path}"))?
+        };
+        Manifest::parse_and_verify(&bytes)
+    }
+
+    /// Install the component described by a verified manifest and attach its embedded policy.
+    #[instrument(skip(self, manifest), fields(name = %manifest.name, version = %manifest.version))]
+    pub async fn install_manifest(
+        &self,
+        manifest: &Manifest,
+        source: &str,
+    ) -> Result<ComponentLoadOutcome> {
+        self.install_manifest_with(manifest, source, &LoadContext::default())
             .await
     }
 
+    /// Install the component of `manifest` as [`install_manifest`](Self::install_manifest)
+    /// does, leaving a policy the component already has alone if `context` keeps it.
+    async fn install_manifest_with(
+        &self,
+        manifest: &Manifest,
+        source: &str,
+        context: &LoadContext,
+    ) -> Result<ComponentLoadOutcome> {
+        let outcome = self
+            .load_component_from(&manifest.component, Some(manifest.clone()), context)
+            .await?;
+        let kept =
+            context.keeps_policy() && self.storage.policy_path(&outcome.component_id).exists();
+        if let Some(policy) = manifest.policy.clone().filter(|_| !kept) {
+            let source = source.to_string();
+            self.run_on_loaded(&outcome.component_id, move |manager, id| async move {
+                manager
+                    .policy_manager
+                    .attach_policy_document(&id, &policy, &source)
+                    .await
+            })
+            .await?;
+        }
+        self.storage
+            .write_update_source(
+                &outcome.component_id,
+                UpdateSource::for_manifest(source).as_ref(),
+            )
+            .await?;
+        info!(component_id = %outcome.component_id, "Installed component from manifest");
+        Ok(outcome)
+    }
+
     /// Detach any policy associated with the given component.
     pub async fn detach_policy(&self, component_id: &str) -> Result<()> {
-        self.policy_manager.detach_policy(component_id).await
+        let component_id = &*self.resolve_component_id(component_id).await;
+        let id = component_id.to_string();
+        self.run_exclusive(component_id, move |manager| async move {
+            manager.policy_manager.detach_policy(&id).await?;
+            manager.component_changed(&id).await;
+            Ok(())
+        })
+        .await
+    }
+
+    /// How often and how many at once the tools of `component_id` may be called, per its
+    /// policy. Calls made through the manager aren't held to them.
+    pub async fn invocation_limits(&self, component_id: &str) -> InvocationLimits {
+        self.policy_manager
+            .template_for_component(component_id)
+            .await
+            .invocation_limits
     }
 
     /// Retrieve policy metadata for a component if one is attached.
     pub async fn get_policy_info(&self, component_id: &str) -> Option<PolicyInfo> {
+        let component_id = &*self.resolve_component_id(component_id).await;
         self.policy_manager.get_policy_info(component_id).await
     }
 
@@ -717,12 +1983,26 @@ impl LifecycleManager {
         permission_type: &str,
         details: &serde_json::Value,
     ) -> Result<()> {
-        if !self.registry.contains_component(component_id).await {
-            return Err(anyhow!("Component not found: {}", component_id));
-        }
-        self.policy_manager
-            .grant_permission(component_id, permission_type, details)
-            .await
+        let component_id = &*self.resolve_component_id(component_id).await;
+        let permission = json!({"permission_type": permission_type, "details": details});
+        let permission_type = permission_type.to_string();
+        let details = details.clone();
+        self.run_on_loaded(component_id, move |manager, id| async move {
+            manager
+                .policy_manager
+                .grant_permission(&id, &permission_type, &details)
+                .await
+        })
+        .await?;
+        self.audit.record(&AuditRecord::permission_change(
+            AuditEvent::PermissionGrant,
+            component_id,
+            permission["permission_type"].as_str(),
+            Some(&permission["details"]),
+        ));
+        self.hooks
+            .fire(HookEvent::PermissionGrant, Some(component_id), permission);
+        Ok(())
     }
 
     /// Revoke a specific permission rule from a component.
@@ -733,21 +2013,41 @@ impl LifecycleManager {
         permission_type: &str,
         details: &serde_json::Value,
     ) -> Result<()> {
-        if !self.registry.contains_component(component_id).await {
-            return Err(anyhow!("Component not found: {}", component_id));
-        }
-        self.policy_manager
-            .revoke_permission(component_id, permission_type, details)
-            .await
+        let component_id = &*self.resolve_component_id(component_id).await;
+        let record = AuditRecord::permission_change(
+            AuditEvent::PermissionRevoke,
+            component_id,
+            Some(permission_type),
+            Some(details),
+        );
+        let permission_type = permission_type.to_string();
+        let details = details.clone();
+        self.run_on_loaded(component_id, move |manager, id| async move {
+            manager
+                .policy_manager
+                .revoke_permission(&id, &permission_type, &details)
+                .await
+        })
+        .await?;
+        self.audit.record(&record);
+        Ok(())
     }
 
     /// Reset all permissions for a component to defaults.
     #[instrument(skip(self))]
     pub async fn reset_permission(&self, component_id: &str) -> Result<()> {
-        if !self.registry.contains_component(component_id).await {
-            return Err(anyhow!("Component not found: {}", component_id));
-        }
-        self.policy_manager.reset_permission(component_id).await
+        let component_id = &*self.resolve_component_id(component_id).await;
+        self.run_on_loaded(component_id, move |manager, id| async move {
+            manager.policy_manager.reset_permission(&id).await
+        })
+        .await?;
+        self.audit.record(&AuditRecord::permission_change(
+            AuditEvent::PermissionReset,
+            component_id,
+            None,
+            None,
+        ));
+        Ok(())
     }
 
     /// Revoke storage permission for a specific URI.
@@ -757,12 +2057,39 @@ impl LifecycleManager {
         component_id: &str,
         uri: &str,
     ) -> Result<()> {
-        if !self.registry.contains_component(component_id).await {
-            return Err(anyhow!("Component not found: {}", component_id));
-        }
-        self.policy_manager
-            .revoke_storage_permission_by_uri(component_id, uri)
-            .await
+        let component_id = &*self.resolve_component_id(component_id).await;
+        let uri = uri.to_string();
+        self.run_on_loaded(component_id, move |manager, id| async move {
+            manager
+                .policy_manager
+                .revoke_storage_permission_by_uri(&id, &uri)
+                .await
+        })
+        .await
+    }
+
+    /// Like [`Self::run_exclusive`], but fails unless the component is loaded once the lease
+    /// has been acquired.
+    async fn run_on_loaded<T, Fut>(
+        &self,
+        component_id: &str,
+        op: impl FnOnce(Self, String) -> Fut + Send + 'static,
+    ) -> Result<T>
+    where
+        Fut: std::future::Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let id = component_id.to_string();
+        self.run_exclusive(component_id, move |manager| async move {
+            if !manager.registry.contains_component(&id).await {
+                bail!("Component not found: {}", id);
+            }
+            let result = op(manager.clone(), id.clone()).await;
+            // Standbys and cached results came from permissions the operation may have changed.
+            manager.component_changed(&id).await;
+            result
+        })
+        .await
     }
 
     /// Returns the plugin directory root on disk.
@@ -775,25 +2102,37 @@ impl LifecycleManager {
     /// the plugin directory, an error is returned.
     #[instrument(skip(self))]
     pub async fn ensure_component_loaded(&self, component_id: &str) -> Result<()> {
+        let component_id = &*self.resolve_component_id(component_id).await;
         if self.registry.contains_component(component_id).await {
             return Ok(());
         }
 
-        let entry_path = self.component_path(component_id);
-        if !entry_path.exists() {
-            bail!("Component not found: {}", component_id);
-        }
+        let id = component_id.to_string();
+        self.run_exclusive(component_id, move |manager| async move {
+            // Another caller may have loaded the component while we waited for the lease.
+            if manager.registry.contains_component(&id).await {
+                return Ok(());
+            }
 
-        self.compile_and_register_component(component_id, &entry_path)
-            .await
-            .with_context(|| {
-                format!(
-                    "Failed to compile component from path: {}",
-                    entry_path.display()
-                )
-            })?;
+            let entry_path = manager.component_path(&id);
+            if !entry_path.exists() {
+                bail!("Component not found: {}", id);
+            }
+            manager.verify_stored_component(&id).await?;
 
-        Ok(())
+            manager
+                .compile_and_register_component(&id, &entry_path)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to compile component from path: {}",
+                        entry_path.display()
+                    )
+                })?;
+
+            Ok(())
+        })
+        .await
     }
 
     /// Save component metadata to disk
@@ -801,6 +2140,9 @@ impl LifecycleManager {
         &self,
         component_id: &str,
         tool_metadata: &[ToolMetadata],
+        wasm_proposals: &BTreeSet<WasmProposal>,
+        prompts: &[Prompt],
+        details: &ArtifactDetails,
         validation_stamp: ValidationStamp,
     ) -> Result<()> {
         let metadata = ComponentMetadata {
@@ -816,6 +2158,10 @@ impl LifecycleManager {
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs(),
+            wasm_proposals: Some(wasm_proposals.clone()),
+            prompts: Some(prompts.to_vec()),
+            readme: details.readme.clone(),
+            warnings: Some(details.warnings.clone()),
         };
 
         self.storage.write_metadata(&metadata).await?;
@@ -832,67 +2178,27 @@ impl LifecycleManager {
         self.storage.read_metadata(component_id).await
     }
 
-    /// Save precompiled component to disk
-    async fn save_precompiled_component(
-        &self,
-        component_id: &str,
-        wasm_bytes: &[u8],
-    ) -> Result<()> {
-        let precompiled_data = self
-            .runtime
-            .precompile_component(wasm_bytes)
-            .context("Failed to precompile component")?;
-
-        self.storage
-            .write_precompiled(component_id, &precompiled_data)
-            .await?;
-
-        info!(component_id = %component_id, "Saved precompiled component");
-        Ok(())
-    }
-
-    /// Load component from precompiled cache or compile fresh
+    /// Load a component, from the precompiled cache when it holds the component compiled by
+    /// this engine, compiling and caching it otherwise.
     async fn load_component_optimized(
         &self,
         wasm_path: &Path,
         component_id: &str,
     ) -> Result<(Component, Vec<u8>)> {
-        let precompiled_path = self.component_precompiled_path(component_id);
-
-        // Try to load from precompiled cache first
-        if precompiled_path.exists() {
-            match unsafe { Component::deserialize_file(self.runtime.as_ref(), &precompiled_path) } {
-                Ok(component) => {
-                    debug!(component_id = %component_id, "Loaded component from precompiled cache");
-                    // Still need the wasm bytes for metadata/validation
-                    let wasm_bytes = tokio::fs::read(wasm_path)
-                        .await
-                        .context("Failed to read wasm file")?;
-                    return Ok((component, wasm_bytes));
-                }
-                Err(e) => {
-                    warn!(%component_id, error = %e, "Failed to load precompiled component, falling back to compilation");
-                }
-            }
-        }
-
-        // Fall back to compilation
         let wasm_bytes = tokio::fs::read(wasm_path)
             .await
             .context("Failed to read wasm file")?;
+        let runtime = Arc::clone(&self.runtime);
+        let precompiled_path = self.component_precompiled_path(component_id);
+        let (component, wasm_bytes, cache) = tokio::task::spawn_blocking(move || {
+            let (component, cache) =
+                precompiled::load_or_compile(&runtime, &precompiled_path, &wasm_bytes)
+                    .context("Failed to compile component")?;
+            anyhow::Ok((component, wasm_bytes, cache))
+        })
+        .await??;
 
-        let component = Component::new(self.runtime.as_ref(), &wasm_bytes)
-            .context("Failed to compile component")?;
-
-        // Save precompiled version for next time (async, don't block on this)
-        if let Err(e) = self
-            .save_precompiled_component(component_id, &wasm_bytes)
-            .await
-        {
-            warn!(%component_id, error = %e, "Failed to save precompiled component");
-        }
-
-        debug!(component_id = %component_id, "Compiled component and saved to cache");
+        debug!(%component_id, ?cache, "Loaded component");
         Ok((component, wasm_bytes))
     }
 
@@ -904,53 +2210,973 @@ impl LifecycleManager {
             .policy_manager
             .template_for_component(component_id)
             .await;
+        let config = self.runtime_config.load(component_id).await?;
+        let variables = self
+            .policy_manager
+            .instantiation_variables(component_id, &policy_template)
+            .await?;
+        let instance_slot = self
+            .instance_slots
+            .try_acquire(component_id, policy_template.instance_limit)?;
+        let (mut state, resource_limiter) = self.wasi_state_from_template(
+            component_id,
+            &policy_template,
+            &config,
+            variables.as_ref(),
+        )?;
+        state.inner.instance_slot = instance_slot;
+        Ok((state, resource_limiter))
+    }
 
-        let wasi_state = policy_template.build()?;
-        let allowed_hosts = policy_template.allowed_hosts.clone();
+    /// Build the WASI state of `component_id` from its policy template and configuration,
+    /// without taking an instance slot, adding the environment `variables` resolved at
+    /// instantiation.
+    fn wasi_state_from_template(
+        &self,
+        component_id: &str,
+        policy_template: &Arc<WasiStateTemplate>,
+        config: &ComponentConfig,
+        variables: Option<&HashMap<String, String>>,
+    ) -> Result<(WassetteWasiState<WasiState>, Option<CustomResourceLimiter>)> {
+        let workspace_dirs = self
+            .workspace
+            .preopens_for(component_id, &policy_template.preopened_dirs);
+        let template = if workspace_dirs.is_empty() && variables.is_none() {
+            policy_template.clone()
+        } else {
+            let mut template = (**policy_template).clone();
+            template.preopened_dirs.extend(workspace_dirs);
+            if let Some(variables) = variables {
+                template.config_vars.extend(variables.clone());
+            }
+            Arc::new(template)
+        };
+        let mut wasi_state = template.build_with(|builder| {
+            self.output.apply(component_id, builder);
+            for hook in self.wasi_ctx_hooks.iter() {
+                hook(component_id, builder).with_context(|| {
+                    format!("WASI context hook failed for component {component_id}")
+                })?;
+            }
+            Ok(())
+        })?;
+        let denials = Denials::new(
+            component_id,
+            Arc::clone(&self.component_metrics),
+            Arc::clone(&self.audit),
+        );
+        wasi_state.fs_grants.audit =
+            EscapeAudit::new(component_id, self.fs_hardening, self.plugin_root())
+                .with_denials(denials.clone());
+        for (key, value) in config {
+            wasi_state
+                .config_values
+                .insert(key.to_string(), value.to_string());
+            wasi_state.wasi_config_vars.insert(key, value);
+        }
+        wasi_state.keyvalue.store = Some((Arc::clone(&self.state), component_id.to_string()));
+        wasi_state.vector.store = Some((Arc::clone(&self.vectors), component_id.to_string()));
+        wasi_state.browser.browser = self.browser.clone();
+        wasi_state.email.outbox = Some((Arc::clone(&self.outbox), component_id.to_string()));
+        wasi_state.resources.published =
+            Some((Arc::clone(&self.published), component_id.to_string()));
+        let network_rules = policy_template.network_rules.clone();
         let resource_limiter = wasi_state.resource_limiter.clone();
 
-        let wassette_wasi_state = WassetteWasiState::new(wasi_state, allowed_hosts)?;
+        let mut wassette_wasi_state = WassetteWasiState::with_rules(wasi_state, network_rules);
+        wassette_wasi_state.denials = Some(denials);
+        wassette_wasi_state.egress = Some(self.egress.route_for(component_id));
+        wassette_wasi_state.request_headers = policy_template.request_headers.clone();
         Ok((wassette_wasi_state, resource_limiter))
     }
 
-    /// Executes a function call on a WebAssembly component
-    #[instrument(skip(self))]
-    pub async fn execute_component_call(
+    /// Create a store for running `component`, loaded as `component_id`, under its policy.
+    async fn new_store(
         &self,
         component_id: &str,
-        function_name: &str,
-        parameters: &str,
-    ) -> Result<String> {
-        let component = self
-            .get_component(component_id)
-            .await
-            .ok_or_else(|| anyhow!("Component not found: {}", component_id))?;
-
-        let (state, resource_limiter) = self.get_wasi_state_for_component(component_id).await?;
+        component: &ComponentInstance,
+    ) -> Result<Store<WassetteWasiState<WasiState>>> {
+        if !component.wasm_proposals.is_empty() {
+            let policy_template = self
+                .policy_manager
+                .template_for_component(component_id)
+                .await;
+            proposals::check_granted(
+                component_id,
+                &component.wasm_proposals,
+                &policy_template.granted_proposals(),
+            )?;
+        }
+        let (state, _) = self.get_wasi_state_for_component(component_id).await?;
+        self.store_for(state)
+    }
 
-        let mut store = Store::new(self.runtime.as_ref(), state);
+    /// Wrap `state` in a store under its resource limiter, or under one that only tracks
+    /// memory usage when the policy sets no limits. The store gets unlimited fuel until a call
+    /// sets the component's allowance.
+    fn store_for(
+        &self,
+        mut state: WassetteWasiState<WasiState>,
+    ) -> Result<Store<WassetteWasiState<WasiState>>> {
+        state
+            .inner
+            .resource_limiter
+            .get_or_insert_with(|| CustomResourceLimiter::new(wasmtime::StoreLimits::default()));
+        let mut store = Store::new(self.runtime.as_ref(), state);
+        store.set_fuel(u64::MAX)?;
+
+        store.limiter(|state: &mut WassetteWasiState<WasiState>| {
+            state
+                .inner
+                .resource_limiter
+                .as_mut()
+                .expect("Resource limiter should be present - installed above")
+        });
+
+        profiling::disable_sampling(&mut store);
+
+        Ok(store)
+    }
+
+    /// Drop the cached results of `component_id` and replace the standby instances of its
+    /// latency-critical tools, both of which came from its previous state.
+    async fn component_changed(&self, component_id: &str) {
+        self.component_swapped(component_id, Vec::new()).await;
+    }
+
+    /// Same as [`Self::component_changed`], keeping the standbys already built from the new
+    /// state of `component_id` instead of warming replacements.
+    async fn component_swapped(&self, component_id: &str, standbys: Vec<(String, Standby)>) {
+        self.result_cache.forget(component_id);
+        self.warm.forget(component_id);
+        let generation = self.warm.generation(component_id);
+        let mut warmed: HashMap<String, usize> = HashMap::new();
+        for (tool, standby) in standbys {
+            self.warm.put(&tool, component_id, generation, standby);
+            *warmed.entry(tool).or_default() += 1;
+        }
+        let Some(tools) = self.registry.component_tools(component_id).await else {
+            return;
+        };
+        for tool in tools.iter().filter_map(|schema| schema["name"].as_str()) {
+            if self.warm.is_latency_critical(tool) {
+                let missing = self
+                    .warm
+                    .size()
+                    .saturating_sub(warmed.get(tool).copied().unwrap_or_default());
+                for _ in 0..missing {
+                    self.rewarm(component_id, tool);
+                }
+            }
+        }
+    }
+
+    /// Instantiate a standby for the next call of `tool` in the background.
+    fn rewarm(&self, component_id: &str, tool: &str) {
+        // Profiled calls need a store that was sampled from the start.
+        if self.profile_guest.contains(component_id) {
+            return;
+        }
+        let generation = self.warm.generation(component_id);
+        let manager = self.clone();
+        let component_id = component_id.to_string();
+        let tool = tool.to_string();
+        tokio::spawn(async move {
+            match manager.standby(&component_id).await {
+                Ok(Some(standby)) => manager.warm.put(&tool, &component_id, generation, standby),
+                Ok(None) => {}
+                Err(error) => {
+                    warn!(%component_id, %tool, %error, "Failed to warm standby instance")
+                }
+            }
+        });
+    }
+
+    /// Instantiate `component_id` under its policy, without taking an instance slot. Returns
+    /// `None` if the component isn't loaded.
+    async fn standby(&self, component_id: &str) -> Result<Option<Standby>> {
+        let Some(component) = self.registry.get_component(component_id).await else {
+            return Ok(None);
+        };
+        let standby = self.instantiate_for(component_id, &component, true).await?;
+        debug!(%component_id, "Warmed standby instance");
+        Ok(Some(standby))
+    }
+
+    /// Instantiate `component` as `component_id` under its policy, without taking an instance
+    /// slot. The proposals it uses must be granted if `check_proposals`.
+    async fn instantiate_for(
+        &self,
+        component_id: &str,
+        component: &ComponentInstance,
+        check_proposals: bool,
+    ) -> Result<Standby> {
+        let policy_template = self
+            .policy_manager
+            .template_for_component(component_id)
+            .await;
+        if check_proposals {
+            proposals::check_granted(
+                component_id,
+                &component.wasm_proposals,
+                &policy_template.granted_proposals(),
+            )?;
+        }
+        let config = self.runtime_config.load(component_id).await?;
+        let variables = self
+            .policy_manager
+            .instantiation_variables(component_id, &policy_template)
+            .await?;
+        let (state, _) = self.wasi_state_from_template(
+            component_id,
+            &policy_template,
+            &config,
+            variables.as_ref(),
+        )?;
+        let mut store = self.store_for(state)?;
+        // Instantiating runs the component's start functions, which get the fuel allowance
+        // and the deadline of a call.
+        let fuel = store.data().inner.fuel_limit;
+        store.set_fuel(fuel.unwrap_or(u64::MAX))?;
+        let timeout = self
+            .call_timeouts
+            .for_instantiation(store.data().inner.timeout_limit);
+        limits::start_deadline(&mut store, Some(timeout));
+        let deadline = store.data().inner.deadline;
+        let instantiated = limits::until_deadline(
+            deadline,
+            None,
+            component.instance_pre.instantiate_async(&mut store),
+        )
+        .await;
+        let instance = instantiated.map_err(|error| {
+            limits::classify_failure(
+                error,
+                component_id,
+                fuel,
+                store.data().inner.limit_exceeded(),
+                Some(timeout),
+                None,
+            )
+        })?;
+        Ok(Standby {
+            instance_pre: Arc::clone(&component.instance_pre),
+            store,
+            instance,
+            instance_limit: policy_template.instance_limit,
+        })
+    }
+
+    /// Receive the lines components write to streams with the [`OutputSink::Log`] sink from
+    /// now on. Lines are dropped for receivers that fall too far behind.
+    pub fn subscribe_output(&self) -> tokio::sync::broadcast::Receiver<OutputLine> {
+        self.output.subscribe()
+    }
+
+    /// The resources components published through `wassette:resources/publish`, sorted by
+    /// URI.
+    pub fn published_resources(&self) -> Vec<PublishedResource> {
+        self.published.list()
+    }
+
+    /// The resource a component published at `uri`, with its contents.
+    pub async fn read_published_resource(&self, uri: &str) -> Result<(PublishedResource, Vec<u8>)> {
+        resources::read(&self.published, uri).await
+    }
+
+    /// Receive the changes of the published resources from now on. Changes are dropped for
+    /// receivers that fall too far behind.
+    pub fn subscribe_resources(&self) -> tokio::sync::broadcast::Receiver<ResourceChange> {
+        self.published.subscribe()
+    }
+
+    /// The URI of the usage guide `component_id` embeds, if it embeds one.
+    pub fn usage_uri(&self, component_id: &str) -> Option<String> {
+        let uri = resource_uri(component_id, README_NAME);
+        self.published.get(&uri).map(|_| uri)
+    }
+
+    /// The usage guide the component `id`, or the component it is an alias of, embeds.
+    pub async fn component_usage(&self, id: &str) -> Result<Option<String>> {
+        let component_id = self.resolve_component_id(id).await;
+        let Some(uri) = self.usage_uri(&component_id) else {
+            return Ok(None);
+        };
+        let (_, contents) = resources::read(&self.published, &uri).await?;
+        Ok(Some(String::from_utf8_lossy(&contents).into_owned()))
+    }
+
+    /// The prompts of the loaded components, by component id and name.
+    pub fn list_prompts(&self) -> Vec<ComponentPrompt> {
+        self.prompts.list(&self.tool_naming.separator)
+    }
+
+    /// The prompt clients know as `name`, rendered with `arguments`.
+    pub fn get_prompt(
+        &self,
+        name: &str,
+        arguments: &HashMap<String, String>,
+    ) -> Result<(ComponentPrompt, String)> {
+        let prompt = self
+            .list_prompts()
+            .into_iter()
+            .find(|prompt| prompt.name == name)
+            .ok_or_else(|| anyhow!("Prompt not found: {name}"))?;
+        let text = prompt.prompt.render(arguments)?;
+        Ok((prompt, text))
+    }
+
+    /// Receive the id of a component whenever it adds or drops prompts. Notifications are
+    /// dropped for receivers that fall too far behind.
+    pub fn subscribe_prompts(&self) -> tokio::sync::broadcast::Receiver<String> {
+        self.prompts.subscribe()
+    }
+
+    /// The union of the host directories, files and network hosts the stored policies grant,
+    /// e.g. to derive an operating system confinement profile.
+    pub async fn granted_access(&self) -> Result<GrantedAccess> {
+        confinement::granted_access(self.storage.root()).await
+    }
+
+    /// What the runtime offers components.
+    pub fn runtime_info(&self) -> RuntimeInfo {
+        RuntimeInfo {
+            hosts: runtime_context::HOST_PACKAGES
+                .iter()
+                .map(|host| host.to_string())
+                .collect(),
+            resource_limits: limits::ENFORCED_LIMITS
+                .iter()
+                .map(|limit| limit.to_string())
+                .collect(),
+            fs_hardening: self.fs_hardening,
+            coredump_on_trap: self.coredump_on_trap,
+            wasm_proposals: self
+                .runtime
+                .wasm_proposals()
+                .iter()
+                .map(|proposal| proposal.to_string())
+                .collect(),
+        }
+    }
+
+    /// Whether a caller may override a call's limits, `manages` telling whether its access
+    /// token has the manage scope if it has one. Callers without a token may only if
+    /// `allow_unauthenticated` is set under `[call_overrides]`.
+    pub fn accepts_overrides_from(&self, manages: Option<bool>) -> bool {
+        self.override_limits.accept_from(manages)
+    }
+
+    /// Whether any component is configured to read the client's workspace roots.
+    pub fn has_workspace_components(&self) -> bool {
+        self.workspace.is_enabled()
+    }
+
+    /// Replace the client's workspace roots with the directories named by the `file://`
+    /// `uris`. Workspace components get read access to them from their next invocation on.
+    /// Returns the roots that were accepted.
+    #[instrument(skip(self))]
+    pub fn set_workspace_roots(&self, uris: &[String]) -> Vec<PathBuf> {
+        let roots = self.workspace.set_roots(uris);
+        self.result_cache.clear();
+        for (component_id, tool) in self.warm.forget_all() {
+            for _ in 0..self.warm.size() {
+                self.rewarm(&component_id, &tool);
+            }
+        }
+        roots
+    }
+
+    /// The client's workspace roots currently readable by workspace components.
+    pub fn workspace_roots(&self) -> Vec<PathBuf> {
+        self.workspace.roots()
+    }
+
+    /// Offer completions for an argument of a tool, using the completion hook exported by
+    /// the tool's component. Returns no values if the component doesn't export a hook.
+    #[instrument(skip(self))]
+    pub async fn complete_tool_argument(
+        &self,
+        tool_name: &str,
+        argument: &str,
+        prefix: &str,
+    ) -> Result<Vec<String>> {
+        let component_id = self.get_component_id_for_tool(tool_name).await?;
+        self.ensure_component_loaded(&component_id).await?;
+
+        let (component, tool_info) = self
+            .registry
+            .call_target(&component_id, tool_name)
+            .await
+            .ok_or_else(|| anyhow!("Component not found: {}", component_id))?;
+        let interface = tool_info.and_then(|info| info.identifier.interface_name);
+        self.run_completion_hook(
+            &component_id,
+            &component,
+            tool_name,
+            interface.as_deref(),
+            argument,
+            prefix,
+        )
+        .await
+    }
+
+    /// Offer completions for an argument of the prompt clients know as `prompt_name`, using
+    /// the completion hook its component exports from the prompts interface or else at the
+    /// top level. Arguments the prompt doesn't declare have no completions.
+    #[instrument(skip(self))]
+    pub async fn complete_prompt_argument(
+        &self,
+        prompt_name: &str,
+        argument: &str,
+        prefix: &str,
+    ) -> Result<Vec<String>> {
+        let prompt = self
+            .list_prompts()
+            .into_iter()
+            .find(|prompt| prompt.name == prompt_name)
+            .ok_or_else(|| anyhow!("Prompt not found: {prompt_name}"))?;
+        if !prompt
+            .prompt
+            .arguments
+            .iter()
+            .any(|declared| declared.name == argument)
+        {
+            return Ok(Vec::new());
+        }
+        let component_id = prompt.component_id;
+        self.ensure_component_loaded(&component_id).await?;
+        let component = self
+            .registry
+            .get_component(&component_id)
+            .await
+            .ok_or_else(|| anyhow!("Component not found: {}", component_id))?;
+        let interface = prompts::prompts_export(&component.component, self.runtime.as_ref());
+        self.run_completion_hook(
+            &component_id,
+            &component,
+            prompt_name,
+            interface.as_deref(),
+            argument,
+            prefix,
+        )
+        .await
+    }
+
+    /// Run the completion hook of `component` for `interface` on `argument`, scheduled and
+    /// limited like a call of `tool_name`.
+    async fn run_completion_hook(
+        &self,
+        component_id: &str,
+        component: &ComponentInstance,
+        tool_name: &str,
+        interface: Option<&str>,
+        argument: &str,
+        prefix: &str,
+    ) -> Result<Vec<String>> {
+        let hooks = completion::completion_hooks(&component.component, self.runtime.as_ref());
+        let Some(hook) = completion::hook_for(&hooks, interface) else {
+            return Ok(Vec::new());
+        };
+
+        // Hooks wait their turn and take an instance slot like the tool's calls.
+        let _slot = self.scheduler.acquire(tool_name).await;
+        let mut store = self.new_store(component_id, component).await?;
+        let fuel = store.data().inner.fuel_limit;
+        store.set_fuel(fuel.unwrap_or(u64::MAX))?;
+        let timeout = self
+            .call_timeouts
+            .for_call(tool_name, store.data().inner.timeout_limit)
+            .map_or(completion::COMPLETION_TIMEOUT, |timeout| {
+                timeout.min(completion::COMPLETION_TIMEOUT)
+            });
+        limits::start_deadline(&mut store, Some(timeout));
+        let deadline = store.data().inner.deadline;
+        let result = limits::until_deadline(deadline, None, async {
+            let instance = component.instance_pre.instantiate_async(&mut store).await?;
+            completion::complete(&mut store, &instance, hook.as_deref(), argument, prefix).await
+        })
+        .await;
+        result.map_err(|error| {
+            limits::classify_failure(
+                error,
+                component_id,
+                fuel,
+                store.data().inner.limit_exceeded(),
+                Some(timeout),
+                None,
+            )
+        })
+    }
+
+    /// Executes a function call on a WebAssembly component
+    #[instrument(skip(self))]
+    pub async fn execute_component_call(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+    ) -> Result<String> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        self.execute_component_call_with_context(
+            component_id,
+            function_name,
+            parameters,
+            &CallContext::default(),
+        )
+        .await
+    }
+
+    /// Executes a function call on a WebAssembly component, giving it access to the
+    /// facilities in `context`.
+    #[instrument(skip(self, context))]
+    pub async fn execute_component_call_with_context(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+        context: &CallContext,
+    ) -> Result<String> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        Ok(self
+            .call_tool(component_id, function_name, parameters, context)
+            .await?
+            .output)
+    }
+
+    /// Like [`Self::execute_component_call_with_context`], but also tells whether the output
+    /// was served from the tool's result cache instead of running the component.
+    #[instrument(skip(self, context))]
+    pub async fn call_tool(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+        context: &CallContext,
+    ) -> Result<ToolOutput> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        let context = &CallContext {
+            confirmed: context.confirmed
+                || self
+                    .confirm_outside_hours(component_id, function_name, context)
+                    .await,
+            ..context.clone()
+        };
+        let record = AuditRecord::tool_call(Some(component_id), function_name, parameters, context);
+        let input_schema = self.input_schema(component_id, function_name).await;
+        let _journaled =
+            self.journal
+                .begin(&record, parameters, input_schema.as_ref(), context.replays);
+        let outbound = self
+            .anomalies
+            .is_enabled()
+            .then(|| Arc::new(OutboundRequests::default()));
+        let elevation = context
+            .prompter
+            .clone()
+            .filter(|_| self.permission_prompts.any())
+            .map(|prompter| {
+                Arc::new(Elevation::new(
+                    component_id,
+                    function_name,
+                    self.permission_prompts,
+                    prompter,
+                    self.policy_manager.environment_vars(),
+                ))
+            });
+        let context = &CallContext {
+            outbound: outbound.clone(),
+            elevation: elevation.clone(),
+            ..context.clone()
+        };
+        let started = Instant::now();
+        let result = self
+            .run_tool(component_id, function_name, parameters, context)
+            .await;
+        self.audit
+            .record(&record.component_call_finished(started.elapsed(), &result));
+        if let Some(outbound) = outbound {
+            let cached = matches!(&result, Ok(output) if output.cached);
+            let anomalies = self.anomalies.observe_call(
+                component_id,
+                function_name,
+                parameters.len(),
+                &outbound,
+                cached,
+            );
+            for anomaly in anomalies {
+                self.audit.record(&AuditRecord::anomaly(&anomaly));
+                self.anomalies.report(anomaly);
+            }
+        }
+        if let Some(elevation) = elevation {
+            self.persist_approvals(component_id, &elevation).await;
+        }
+        result
+    }
 
-        // Apply memory limits if configured in the policy by setting up a limiter closure
-        // that extracts the resource limiter from the WasiState
-        if resource_limiter.is_some() {
-            store.limiter(|state: &mut WassetteWasiState<WasiState>| {
-                // Extract the resource limiter from the inner state
-                state
-                    .inner
-                    .resource_limiter
-                    .as_mut()
-                    .expect("Resource limiter should be present - checked above")
+    /// Grant `component_id` the permissions approved during a call for good. Approvals are
+    /// sampling answers, which may not be the user's; they only outlive the call when the
+    /// configuration asks for it.
+    async fn persist_approvals(&self, component_id: &str, elevation: &Elevation) {
+        if !elevation.persists() {
+            return;
+        }
+        for request in elevation.approved().await {
+            let (permission_type, details) = request.grant();
+            if let Err(error) = self
+                .grant_permission(component_id, permission_type, &details)
+                .await
+            {
+                warn!(%error, %request, "Failed to grant permission approved by the user");
+            }
+        }
+    }
+
+    /// JSON schema of the arguments of `function_name` of `component_id`, if it has the tool.
+    async fn input_schema(&self, component_id: &str, function_name: &str) -> Option<Value> {
+        let (_, tool_info) = self
+            .registry
+            .call_target(component_id, function_name)
+            .await?;
+        Some(tool_info?.schema["inputSchema"].clone())
+    }
+
+    /// Report the tool calls that servers using this plugin directory accepted but never
+    /// finished because they crashed, recording them in the audit log as interrupted, and run
+    /// those of idempotent tools again. Call once the components are loaded; calls of servers
+    /// still running are left alone.
+    pub async fn recover_interrupted_calls(&self) -> Vec<InterruptedCall> {
+        let mut interrupted = Vec::new();
+        for entry in self.journal.recover() {
+            self.audit.record(&AuditRecord::interrupted(&entry));
+            warn!(
+                component_id = %entry.component_id,
+                tool = %entry.tool,
+                accepted_at = entry.accepted_at,
+                "Tool call interrupted by a server crash"
+            );
+            let replayed = match &entry.arguments {
+                Some(arguments)
+                    if entry.replayable() && self.journal.is_idempotent(&entry.tool) =>
+                {
+                    Some(self.replay_call(&entry, arguments).await)
+                }
+                _ => None,
+            };
+            interrupted.push(InterruptedCall {
+                component_id: entry.component_id,
+                tool: entry.tool,
+                accepted_at: entry.accepted_at,
+                replayed,
             });
         }
+        interrupted
+    }
 
-        let instance = component.instance_pre.instantiate_async(&mut store).await?;
+    /// Run an interrupted call of an idempotent tool again, returning how it ended. Only calls
+    /// made without an access token are replayed, so the replay has no subject either.
+    async fn replay_call(&self, entry: &journal::JournalEntry, arguments: &str) -> AuditOutcome {
+        info!(
+            component_id = %entry.component_id,
+            tool = %entry.tool,
+            "Replaying interrupted tool call"
+        );
+        let context = CallContext {
+            replays: entry.replays + 1,
+            ..CallContext::default()
+        };
+        let started = Instant::now();
+        let result = match self.ensure_component_loaded(&entry.component_id).await {
+            Ok(()) => {
+                self.call_tool(&entry.component_id, &entry.tool, arguments, &context)
+                    .await
+            }
+            Err(error) => Err(error),
+        };
+        AuditRecord::tool_call(Some(&entry.component_id), &entry.tool, arguments, &context)
+            .component_call_finished(started.elapsed(), &result)
+            .outcome
+            .unwrap_or(AuditOutcome::Failed)
+    }
 
-        // Use the new function identifier lookup instead of dot-splitting
-        let function_id = self
+    /// Append `record` to the audit log, e.g. for a call of a built-in tool or one refused
+    /// before reaching the component.
+    pub fn audit(&self, record: &AuditRecord) {
+        self.audit.record(record);
+    }
+
+    /// Records of the audit log matching `query`, oldest first.
+    pub async fn audit_log(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+        let mut query = query.clone();
+        if let Some(component_id) = query.component_id.take() {
+            query.component_id = Some(self.resolve_component_id(&component_id).await);
+        }
+        audit::query(self.plugin_root(), &query).await
+    }
+
+    /// Path of the audit log, for following it as it grows.
+    pub fn audit_log_path(&self) -> PathBuf {
+        audit::log_path(self.plugin_root())
+    }
+
+    /// Whether the user, asked through the prompter of `context`, confirmed a call of `tool`
+    /// made outside its allowed hours. Only calls the hours would refuse and a confirmation
+    /// would let through are asked about.
+    async fn confirm_outside_hours(
+        &self,
+        component_id: &str,
+        tool: &str,
+        context: &CallContext,
+    ) -> bool {
+        let Some(prompter) = &context.prompter else {
+            return false;
+        };
+        let Some(hours) = self.policy_manager.tool_hours(component_id, tool).await else {
+            return false;
+        };
+        let outside = match hours.check(component_id, tool, false, chrono::Utc::now()) {
+            Err(outside) if outside.confirmable => outside,
+            _ => return false,
+        };
+        match prompter.confirm(&outside).await {
+            Ok(confirmed) => {
+                info!(%outside, confirmed, "Call outside allowed hours put to the user");
+                confirmed
+            }
+            Err(error) => {
+                warn!(%error, %outside, "Failed to ask for confirmation; refusing the call");
+                false
+            }
+        }
+    }
+
+    /// The body of [`Self::call_tool`], whose result is audited.
+    async fn run_tool(
+        &self,
+        component_id: &str,
+        function_name: &str,
+        parameters: &str,
+        context: &CallContext,
+    ) -> Result<ToolOutput> {
+        self.override_limits.check(&context.overrides)?;
+        if let Some(hours) = self
+            .policy_manager
+            .tool_hours(component_id, function_name)
+            .await
+        {
+            hours.check(
+                component_id,
+                function_name,
+                context.confirmed,
+                chrono::Utc::now(),
+            )?;
+        }
+        let (component, tool_info) = self
             .registry
-            .tool_identifier(function_name)
+            .call_target(component_id, function_name)
             .await
-            .ok_or_else(|| anyhow!("Unknown tool name: {}", function_name))?;
+            .ok_or_else(|| anyhow!("Component not found: {}", component_id))?;
+        let tool_info = tool_info.ok_or_else(|| anyhow!("Unknown tool name: {}", function_name))?;
+
+        let preset = self
+            .storage
+            .read_presets(component_id)
+            .await?
+            .remove(function_name);
+        let parameters = parameters.to_string();
+        let input_schema = tool_info.schema["inputSchema"].clone();
+        let params = self
+            .validation
+            .run(move || {
+                let mut params: serde_json::Value = serde_json::from_str(&parameters)?;
+                if let (Some(preset), Some(args)) = (preset, params.as_object_mut()) {
+                    for (key, value) in preset {
+                        args.entry(key).or_insert(value);
+                    }
+                }
+                fill_argument_defaults(&mut params, &input_schema);
+                Ok(Arc::new(params))
+            })
+            .await?;
+
+        // Results read from the client's workspace depend on the session of the caller.
+        let cached_call = self
+            .policy_manager
+            .cache_ttl(component_id, function_name)
+            .await
+            .filter(|_| !self.workspace.includes(component_id))
+            .map(|ttl| CachedCall {
+                component_id,
+                tool: function_name,
+                subject: context.subject.as_deref(),
+                arguments: &params,
+                ttl,
+            });
+        if let Some(output) = cached_call
+            .as_ref()
+            .and_then(|call| self.result_cache.lookup(call))
+        {
+            debug!(%component_id, tool = %function_name, "Serving cached tool result");
+            return Ok(ToolOutput {
+                output,
+                cached: true,
+            });
+        }
+
+        let call = self.call_stats.start();
+        let result = self
+            .invoke(
+                component,
+                &tool_info,
+                component_id,
+                function_name,
+                Arc::clone(&params),
+                context,
+            )
+            .await;
+        let succeeded = matches!(&result, Ok((value, _)) if !result_cache::reports_error(value));
+        let elapsed = call.finish(succeeded);
+        self.component_metrics
+            .record_call(component_id, elapsed, succeeded);
+        let (result_json, output) = result?;
+        if let Some(call) = &cached_call {
+            self.result_cache.keep(call, &result_json, &output);
+        }
+        Ok(ToolOutput {
+            output,
+            cached: false,
+        })
+    }
+
+    /// Start the clock on a call of `tool` about to run in `store`, if the call has a timeout
+    /// or can be cancelled, returning the timeout. A timeout override replaces the configured
+    /// and policy timeouts.
+    fn start_clock(
+        &self,
+        store: &mut Store<WassetteWasiState<WasiState>>,
+        tool: &str,
+        overrides: &CallOverrides,
+    ) -> Option<Duration> {
+        let timeout = overrides.timeout_secs.map(Duration::from_secs).or_else(|| {
+            self.call_timeouts
+                .for_call(tool, store.data().inner.timeout_limit)
+        });
+        if timeout.is_some() || store.data().inner.cancellation.is_some() {
+            limits::start_deadline(store, timeout);
+        }
+        timeout
+    }
+
+    /// Give a store what it needs from the caller for one invocation.
+    fn attach_call_context(&self, state: &mut WassetteWasiState<WasiState>, context: &CallContext) {
+        state.outbound = context.outbound.clone();
+        state.elevation = context.elevation.clone();
+        state.inner.fs_grants.elevation = context.elevation.clone();
+        let state = &mut state.inner;
+        if let Some(memory) = context.overrides.memory_bytes {
+            state
+                .resource_limiter
+                .get_or_insert_with(|| CustomResourceLimiter::new(wasmtime::StoreLimits::default()))
+                .override_memory_limit(memory);
+        }
+        state.sampling.sampler = context.sampler.clone();
+        state.progress.start(context.progress.clone());
+        state.cancellation = context.cancellation.clone();
+        state.context.session = SessionContext::new(
+            context
+                .client
+                .as_ref()
+                .map(|(name, version)| (name.as_str(), version.as_str())),
+            &self.session_profile,
+        );
+    }
+
+    /// Run `tool_info`'s function of `component` in a fresh instance, returning its results
+    /// as JSON and the output they make.
+    async fn invoke(
+        &self,
+        component: ComponentInstance,
+        tool_info: &ToolInfo,
+        component_id: &str,
+        function_name: &str,
+        params: Arc<Value>,
+        context: &CallContext,
+    ) -> Result<(Value, String)> {
+        let call_id = coredump::new_call_id();
+        let profile = self.profile_guest.contains(component_id);
+        let slot = self
+            .scheduler
+            .acquire(function_name)
+            .instrument(info_span!("queue", %component_id, %function_name))
+            .await;
+        let standby = if !profile && self.warm.is_latency_critical(function_name) {
+            let standby = self.warm.take(function_name, &component.instance_pre);
+            // Start on the replacement right away so back-to-back calls find one ready.
+            self.rewarm(component_id, function_name);
+            standby
+        } else {
+            None
+        };
+
+        let (mut store, instance, timeout) = match standby {
+            Some(Standby {
+                mut store,
+                instance,
+                instance_limit,
+                ..
+            }) => {
+                store.data_mut().inner.instance_slot = self
+                    .instance_slots
+                    .try_acquire(component_id, instance_limit)?;
+                // The deadline of the standby's instantiation doesn't carry over to the call.
+                store.data_mut().inner.deadline = None;
+                profiling::disable_sampling(&mut store);
+                self.attach_call_context(store.data_mut(), context);
+                let timeout = self.start_clock(&mut store, function_name, &context.overrides);
+                (store, instance, timeout)
+            }
+            None => {
+                let mut store = self.new_store(component_id, &component).await?;
+                self.attach_call_context(store.data_mut(), context);
+                // A cold call's time includes instantiating the component.
+                let timeout = self.start_clock(&mut store, function_name, &context.overrides);
+                if profile {
+                    profiling::start(&mut store, component_id, &component.component);
+                }
+                let deadline = store.data().inner.deadline;
+                let instantiation = component.instance_pre.instantiate_async(&mut store);
+                let instance = match limits::until_deadline(
+                    deadline,
+                    context.cancellation.as_ref(),
+                    instantiation,
+                )
+                .instrument(info_span!("instantiate", %component_id))
+                .await
+                {
+                    Ok(instance) => instance,
+                    Err(error) => {
+                        self.count_trap(component_id, &error);
+                        return Err(limits::classify_failure(
+                            error,
+                            component_id,
+                            None,
+                            store.data().inner.limit_exceeded(),
+                            timeout,
+                            context.cancellation.as_ref(),
+                        ));
+                    }
+                };
+                (store, instance, timeout)
+            }
+        };
+        // Profiled calls keep the profiler's epoch callback and aren't pre-empted.
+        if let Some(slot) = slot.as_ref().filter(|_| !profile) {
+            slot.preempt_at_epochs(&mut store);
+        }
+
+        // Use the new function identifier lookup instead of dot-splitting
+        let function_id = &tool_info.identifier;
 
         let (interface_name, func_name) = (
             function_id.interface_name.as_deref().unwrap_or(""),
@@ -990,76 +3216,751 @@ impl LifecycleManager {
                 .ok_or_else(|| anyhow!("Function not found: {}", func_name))?
         };
 
-        let params: serde_json::Value = serde_json::from_str(parameters)?;
-        let argument_vals = json_to_vals(&params, &func.params(&store))?;
+        let param_types = func.params(&store);
+        let argument_vals = self
+            .validation
+            .run(move || Ok(json_to_vals(&params, &param_types)?))
+            .await?;
+
+        let mut results = create_placeholder_results(&func.results(&store));
+
+        // Each invocation gets the component's fuel allowance, whatever instantiation used.
+        let fuel_limit = store.data().inner.fuel_limit;
+        store.set_fuel(fuel_limit.unwrap_or(u64::MAX))?;
+        let deadline = store.data().inner.deadline;
+        let concurrent = component
+            .wasm_proposals
+            .contains(&WasmProposal::ComponentModelAsync);
+        let call_result = limits::until_deadline(
+            deadline,
+            context.cancellation.as_ref(),
+            call_export(
+                &mut store,
+                instance,
+                func,
+                &argument_vals,
+                &mut results,
+                concurrent,
+            ),
+        )
+        .instrument(info_span!("wasm_call", %component_id, %function_name, %call_id))
+        .await;
+
+        if profile {
+            let dir = self.plugin_root().join(profiling::PROFILES_DIR);
+            match profiling::finish(&mut store, &dir, component_id, &call_id).await {
+                Ok(Some(path)) => {
+                    info!(%component_id, %call_id, path = %path.display(), "Wrote guest profile")
+                }
+                Ok(None) => {}
+                Err(e) => warn!(%component_id, error = %e, "Failed to write guest profile"),
+            }
+        }
+
+        if let Some(limiter) = &store.data().inner.resource_limiter {
+            self.component_metrics
+                .record_memory(component_id, limiter.memory_usage() as u64);
+        }
+        if let Err(error) = call_result {
+            self.count_trap(component_id, &error);
+            let exceeded = store.data().inner.limit_exceeded();
+            let error = self
+                .capture_coredump(&mut store, &call_id, component_id, function_name, error)
+                .await;
+            return Err(limits::classify_failure(
+                error,
+                component_id,
+                fuel_limit,
+                exceeded,
+                timeout,
+                context.cancellation.as_ref(),
+            ));
+        }
+
+        self.validation
+            .run(move || {
+                let results = vals_to_json(&results);
+                let output = match results.as_str() {
+                    Some(text) => text.to_string(),
+                    None => serde_json::to_string(&results)?,
+                };
+                Ok((results, output))
+            })
+            .await
+    }
+
+    /// Count `error`, the failure of a call of `component_id`, if the component trapped.
+    fn count_trap(&self, component_id: &str, error: &anyhow::Error) {
+        if error.downcast_ref::<wasmtime::Trap>().is_some() {
+            self.component_metrics.record_trap(component_id);
+        }
+    }
+
+    /// Persist the core dump attached to a trap, if any, and tag the error with the call id
+    /// needed to retrieve it.
+    async fn capture_coredump(
+        &self,
+        store: &mut Store<WassetteWasiState<WasiState>>,
+        call_id: &str,
+        component_id: &str,
+        function_name: &str,
+        error: anyhow::Error,
+    ) -> anyhow::Error {
+        if !self.coredump_on_trap {
+            return error;
+        }
+        let Some(dump) = error.downcast_ref::<wasmtime::WasmCoreDump>() else {
+            return error;
+        };
+
+        let bytes = dump.serialize(&mut *store, component_id);
+        let record = CoreDumpRecord {
+            call_id: call_id.to_string(),
+            component_id: component_id.to_string(),
+            function_name: function_name.to_string(),
+            error: format!("{error:#}"),
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            size: bytes.len() as u64,
+        };
+
+        match coredump::save(&self.coredump_dir(), &record, &bytes).await {
+            Ok(()) => {
+                warn!(%component_id, call_id = %record.call_id, "Component trapped, core dump captured");
+                error.context(coredump::CoreDumpSaved {
+                    call_id: record.call_id,
+                })
+            }
+            Err(save_error) => {
+                warn!(%component_id, error = %save_error, "Failed to persist core dump");
+                error
+            }
+        }
+    }
+
+    fn coredump_dir(&self) -> PathBuf {
+        self.plugin_root().join(coredump::COREDUMPS_DIR)
+    }
+
+    /// List the core dumps captured from trapped component calls, newest first
+    pub async fn list_coredumps(&self) -> Result<Vec<CoreDumpRecord>> {
+        coredump::list(&self.coredump_dir()).await
+    }
+
+    /// Retrieve the record and raw bytes of the core dump captured for a call
+    pub async fn get_coredump(&self, call_id: &str) -> Result<(CoreDumpRecord, Vec<u8>)> {
+        coredump::load(&self.coredump_dir(), call_id).await
+    }
+
+    /// Check the tools of the component at `wasm_path` against `contract`, without loading it.
+    pub async fn check_component_contract(
+        &self,
+        wasm_path: &Path,
+        contract: &Contract,
+    ) -> Result<ContractReport> {
+        let wasm_bytes = tokio::fs::read(wasm_path)
+            .await
+            .with_context(|| format!("Failed to read {}", wasm_path.display()))?;
+        let runtime = Arc::clone(&self.runtime);
+        let compiled = tokio::task::spawn_blocking(move || {
+            let component = Component::new(runtime.as_ref(), &wasm_bytes)?;
+            let mut tools = component_exports_to_tools(&component, runtime.as_ref(), true);
+            completion::strip_completion_hooks(
+                &mut tools,
+                &completion::completion_hooks(&component, runtime.as_ref()),
+            );
+            prompts::strip_prompts_export(
+                &mut tools,
+                prompts::prompts_export(&component, runtime.as_ref()).as_deref(),
+            );
+            anyhow::Ok(tools)
+        })
+        .await?;
+        let tools = match compiled {
+            Ok(tools) => tools,
+            Err(error) => return Err(diagnose_load_error(&self.runtime, wasm_path, error).await),
+        };
+        Ok(contract::check(contract, &tools))
+    }
+
+    /// Fetch the component at `uri` and describe it: its world, its tools with their schemas,
+    /// the permissions it asks for and what compiling it costs. The component isn't stored,
+    /// registered or given a policy.
+    #[instrument(skip(self))]
+    pub async fn inspect_component(&self, uri: &str) -> Result<ComponentInspection> {
+        let context = LoadContext::default().with_pull(Arc::clone(&self.pull));
+        let resource = loader::load_resource::<ComponentResource>(
+            uri,
+            &self.oci_client,
+            &self.http_client,
+            &context,
+        )
+        .await?;
+        let wasm_bytes = tokio::fs::read(resource.as_ref())
+            .await
+            .with_context(|| format!("Failed to read {}", resource.as_ref().display()))?;
+        let policy = resource.policy().await;
+        let runtime = Arc::clone(&self.runtime);
+        let uri = uri.trim().to_string();
+        let inspected = tokio::task::spawn_blocking(move || {
+            inspect::inspect(runtime.as_ref(), &uri, &wasm_bytes, policy.as_deref())
+        })
+        .await?;
+        match inspected {
+            Ok(inspection) => Ok(inspection),
+            Err(error) => Err(diagnose_load_error(&self.runtime, resource.as_ref(), error).await),
+        }
+    }
+
+    /// Current load of the runtime: queued and running calls, instances and call latencies.
+    pub async fn stats(&self) -> RuntimeStats {
+        let (queued_interactive_calls, queued_batch_calls) = self.scheduler.queued();
+        let active_calls = self
+            .call_stats
+            .in_progress()
+            .saturating_sub(queued_interactive_calls + queued_batch_calls);
+        let execution_slots = self.scheduler.slots();
+        let (limited_instances, instance_capacity) = self.instance_slots.usage();
+        RuntimeStats {
+            queued_interactive_calls,
+            queued_batch_calls,
+            active_calls,
+            execution_slots,
+            pool_utilization: active_calls as f64 / execution_slots as f64,
+            limited_instances,
+            instance_capacity,
+            standby_instances: self.warm.ready(),
+            latency_critical_tools: self.warm.latency_critical_tools(),
+            loaded_components: self.registry.list_components().await.len(),
+            calls_total: self.call_stats.completed(),
+            call_errors_total: self.call_stats.failed(),
+            latency_p50_secs: self.call_stats.latency(0.5).map(|d| d.as_secs_f64()),
+            latency_p95_secs: self.call_stats.latency(0.95).map(|d| d.as_secs_f64()),
+            components: self.component_metrics.snapshot(),
+        }
+    }
+
+    /// Receive the tool calls that deviated from the baseline of their component from now on,
+    /// if anomaly detection is configured
+    /// ([`LifecycleBuilder::with_anomaly_detection`](crate::LifecycleBuilder::with_anomaly_detection)).
+    /// Anomalies are dropped for receivers that fall too far behind.
+    pub fn subscribe_anomalies(&self) -> tokio::sync::broadcast::Receiver<Anomaly> {
+        self.anomalies.subscribe()
+    }
+
+    /// Receive the stages of component rollouts from now on. Events are dropped for receivers
+    /// that fall too far behind.
+    pub fn subscribe_rollouts(&self) -> tokio::sync::broadcast::Receiver<RolloutEvent> {
+        self.rollouts.subscribe()
+    }
+
+    /// The stages of every component rollout recorded in the plugin directory, oldest first.
+    pub async fn list_rollouts(&self) -> Result<Vec<RolloutEvent>> {
+        rollout::list(self.plugin_root()).await
+    }
+
+    /// The recorded changes of the installed versions of components, newest first: those of
+    /// `component_id` only if given, and at most `limit` of them.
+    pub async fn component_history(
+        &self,
+        component_id: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Vec<VersionChange>> {
+        let component_id = match component_id {
+            Some(id) => Some(self.resolve_component_id(id).await),
+            None => None,
+        };
+        history::list(self.plugin_root(), component_id.as_deref(), limit).await
+    }
+
+    /// Digest of the artifact installed for `component_id`, if there is one.
+    async fn installed_digest(&self, component_id: &str) -> Option<String> {
+        let path = self.component_path(component_id);
+        if !path.exists() {
+            return None;
+        }
+        updates::file_digest(&path).await.ok()
+    }
+
+    /// Add the change of `component_id` from the artifact with `previous_digest` to the one
+    /// installed now to the version history, described by `describe`, unless the artifact
+    /// is the same.
+    async fn record_version(
+        &self,
+        component_id: &str,
+        kind: VersionChangeKind,
+        previous_digest: Option<String>,
+        describe: impl FnOnce(VersionChange) -> VersionChange,
+    ) {
+        let Some(digest) = self.installed_digest(component_id).await else {
+            return;
+        };
+        if previous_digest.as_ref() == Some(&digest) {
+            return;
+        }
+        let change = describe(VersionChange::new(
+            component_id,
+            kind,
+            previous_digest,
+            digest,
+        ));
+        if let Err(error) = history::record(self.plugin_root(), &change) {
+            warn!(%component_id, %error, "Failed to record the version change");
+        }
+    }
+
+    /// Attempts by components to reach host paths outside their granted directories, oldest
+    /// first.
+    pub async fn list_escape_attempts(&self) -> Result<Vec<EscapeAttempt>> {
+        fs_audit::list(self.plugin_root()).await
+    }
+
+    /// Load existing components from plugin directory in the background with bounded parallelism
+    /// Default concurrency is min(num_cpus, 4) if not specified. The time each component took is
+    /// logged in a summary once all are done.
+    #[instrument(skip(self, notify_fn))]
+    pub async fn load_existing_components_async<F>(
+        &self,
+        concurrency: Option<usize>,
+        notify_fn: Option<F>,
+    ) -> Result<()>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        // Tools of components with valid cached metadata were already registered when the
+        // manager was constructed; this only compiles components.
+        let concurrency = concurrency.unwrap_or_else(default_load_concurrency);
+
+        info!(
+            "Starting background component loading with concurrency: {}",
+            concurrency
+        );
+
+        let started = Instant::now();
+        let semaphore = Arc::new(Semaphore::new(concurrency));
+        let notify_fn = notify_fn.map(Arc::new);
+        let mut tasks = JoinSet::new();
+        let mut entries = tokio::fs::read_dir(self.storage.root()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let manager = self.clone();
+            let semaphore = Arc::clone(&semaphore);
+            let notify_fn = notify_fn.clone();
+            tasks.spawn(
+                async move {
+                    let path = entry.path();
+                    let _permit = semaphore.acquire().await;
+                    let load_started = Instant::now();
+                    let result = manager.load_component_from_entry_optimized(entry).await;
+                    // Let the caller announce each component's tools as soon as they're ready.
+                    if let (Ok(Some(_)), Some(notify)) = (&result, &notify_fn) {
+                        notify();
+                    }
+                    (path, result, load_started.elapsed())
+                }
+                .in_current_span(),
+            );
+        }
+
+        LoadSummary::collect(tasks).await.log(started.elapsed());
+        Ok(())
+    }
+
+    /// Reload components loaded from `file://` URIs whenever their files change, calling
+    /// `on_reload` after each reload so the caller can announce the new tool list. Runs for as
+    /// long as the manager lives, so spawn it on its own task; requires watch mode
+    /// ([`LifecycleBuilder::with_watch`](crate::LifecycleBuilder::with_watch)).
+    ///
+    /// A reload compiles the new file and then swaps the component's tools in one step, so
+    /// calls see either the old tools or the new ones. If the new file fails to load, the
+    /// loaded version keeps serving until the file changes again.
+    pub async fn watch_components<F>(&self, on_reload: F) -> Result<()>
+    where
+        F: Fn(&ComponentLoadOutcome) + Send + Sync + 'static,
+    {
+        let watch = self
+            .source_watch
+            .clone()
+            .context("Watch mode is not enabled")?;
+        info!("Watching component sources for changes");
+        while let Some(changes) = watch.next_changes().await {
+            for (component_id, path) in changes {
+                let uri = format!("file://{}", path.display());
+                match self.load_component(&uri).await {
+                    Ok(outcome) => {
+                        info!(
+                            %component_id,
+                            tools = ?outcome.tool_names,
+                            "Reloaded changed component"
+                        );
+                        on_reload(&outcome);
+                    }
+                    Err(error) => warn!(
+                        %component_id,
+                        error = format!("{error:#}"),
+                        "Failed to reload changed component; keeping the loaded version"
+                    ),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check every component loaded from an OCI tag or an `https://` URL, or installed from a
+    /// fetched manifest, for a new release and load it, rolling back to the loaded release if
+    /// the new one fails to load or fails its health check. Components still waiting at
+    /// `deadline` are left for the next pass. Returns what happened to each component that had a new release or couldn't be
+    /// checked.
+    #[instrument(skip(self))]
+    pub async fn update_components(&self, deadline: Option<Instant>) -> Vec<ComponentUpdate> {
+        let mut updates = Vec::new();
+        for component_id in self.list_components_known().await {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                info!("Maintenance window closed; remaining components wait for the next one");
+                break;
+            }
+            let status = match self.storage.read_update_source(&component_id).await {
+                Ok(None) => continue,
+                Ok(Some(source)) => self.update_from_source(&component_id, &source).await,
+                Err(error) => Err(error),
+            };
+            let status = match status {
+                Ok(UpdateStatus::UpToDate) => continue,
+                Ok(status) => status,
+                Err(error) => UpdateStatus::Failed {
+                    error: format!("{error:#}"),
+                },
+            };
+            match &status {
+                UpdateStatus::UpToDate => {}
+                UpdateStatus::Updated => info!(%component_id, "Updated component"),
+                UpdateStatus::RolledBack { error } => {
+                    warn!(%component_id, %error, "Rolled back failed update")
+                }
+                UpdateStatus::Failed { error } => {
+                    warn!(%component_id, %error, "Failed to update component")
+                }
+            }
+            updates.push(ComponentUpdate {
+                component_id,
+                status,
+            });
+        }
+        updates
+    }
 
-        let mut results = create_placeholder_results(&func.results(&store));
+    /// Check `component_id` for a new release where it came from and load it, keeping the
+    /// component's policy. Fails if the component wasn't loaded from an OCI tag, an `https://`
+    /// URL or a fetched manifest.
+    #[instrument(skip(self))]
+    pub async fn update_component(&self, component_id: &str) -> Result<UpdateReport> {
+        let component_id = self.resolve_component_id(component_id).await;
+        let Some(previous_digest) = self.installed_digest(&component_id).await else {
+            bail!("Component not found: {component_id}");
+        };
+        let source = self
+            .storage
+            .read_update_source(&component_id)
+            .await?
+            .with_context(|| {
+                format!(
+                    "Component {component_id} wasn't loaded from an OCI tag, an https:// URL or \
+                     a fetched manifest, so it has nowhere to be updated from"
+                )
+            })?;
+        let previous_version = self.installed_version(&component_id).await;
+        let status = self.update_from_source(&component_id, &source).await?;
+        match &status {
+            UpdateStatus::UpToDate => info!(%component_id, "Component is up to date"),
+            UpdateStatus::Updated => info!(%component_id, "Updated component"),
+            UpdateStatus::RolledBack { error } => {
+                warn!(%component_id, %error, "Rolled back failed update")
+            }
+            UpdateStatus::Failed { error } => {
+                warn!(%component_id, %error, "Failed to update component")
+            }
+        }
+        Ok(UpdateReport {
+            source: source.location().to_string(),
+            status,
+            previous_digest: Some(previous_digest),
+            digest: self.installed_digest(&component_id).await,
+            previous_version,
+            version: self.installed_version(&component_id).await,
+            component_id,
+        })
+    }
 
-        func.call_async(&mut store, &argument_vals, &mut results)
-            .await?;
+    /// Release version of the installed `component_id`: that of its manifest, or else the
+    /// one recorded when it was last loaded.
+    async fn installed_version(&self, component_id: &str) -> Option<String> {
+        if let Ok(Some(manifest)) = self.storage.read_manifest(component_id).await {
+            return Some(manifest.version);
+        }
+        history::list(self.plugin_root(), Some(component_id), Some(1))
+            .await
+            .ok()?
+            .into_iter()
+            .next()?
+            .version
+    }
 
-        let result_json = vals_to_json(&results);
+    /// Update `component_id` from `source` if it has a new release, keeping its policy.
+    async fn update_from_source(
+        &self,
+        component_id: &str,
+        source: &UpdateSource,
+    ) -> Result<UpdateStatus> {
+        let context = LoadContext::default().keeping_policy();
+        match source {
+            UpdateSource::Oci { reference } => {
+                let remote = self.remote_component_digest(reference).await?;
+                let local = updates::file_digest(&self.component_path(component_id)).await?;
+                if remote == local {
+                    return Ok(UpdateStatus::UpToDate);
+                }
+                self.apply_update(
+                    component_id,
+                    self.load_component_from(reference, None, &context),
+                )
+                .await
+            }
+            UpdateSource::Url { url } => {
+                let resource = loader::load_resource::<ComponentResource>(
+                    url,
+                    &self.oci_client,
+                    &self.http_client,
+                    &context,
+                )
+                .await?;
+                let remote = updates::file_digest(resource.as_ref()).await?;
+                let local = updates::file_digest(&self.component_path(component_id)).await?;
+                if remote == local {
+                    return Ok(UpdateStatus::UpToDate);
+                }
+                self.apply_update(component_id, self.load_component_from(url, None, &context))
+                    .await
+            }
+            UpdateSource::Manifest { location } => {
+                let manifest = self.fetch_manifest(location).await?;
+                let current = self.storage.read_manifest(component_id).await?;
+                if current.is_some_and(|current| {
+                    current.component == manifest.component && current.version == manifest.version
+                }) {
+                    return Ok(UpdateStatus::UpToDate);
+                }
+                self.apply_update(
+                    component_id,
+                    self.install_manifest_with(&manifest, location, &context),
+                )
+                .await
+            }
+        }
+    }
 
-        if let Some(result_str) = result_json.as_str() {
-            Ok(result_str.to_string())
-        } else {
-            Ok(serde_json::to_string(&result_json)?)
+    /// Digest of the component layer `reference` names now, asking its mirrors first.
+    async fn remote_component_digest(&self, reference: &str) -> Result<String> {
+        let reference: oci_client::Reference = reference
+            .strip_prefix("oci://")
+            .unwrap_or(reference)
+            .parse()
+            .context("Failed to parse OCI reference")?;
+        let mut last_error = anyhow!("No registry to check {reference}");
+        for candidate in self.pull.candidates(&reference) {
+            match self
+                .oci_client
+                .pull_image_manifest(&candidate.reference, &candidate.auth)
+                .await
+            {
+                Ok((manifest, _)) => return updates::component_layer_digest(&manifest),
+                Err(error) => last_error = error.into(),
+            }
         }
+        Err(last_error.context("Failed to check for a new release"))
     }
 
-    /// Load existing components from plugin directory in the background with bounded parallelism
-    /// Default concurrency is min(num_cpus, 4) if not specified
-    #[instrument(skip(self, notify_fn))]
-    pub async fn load_existing_components_async<F>(
+    /// Run `update` after copying the component aside, and restore the copy if the update or
+    /// the health check after it fails.
+    async fn apply_update(
         &self,
-        concurrency: Option<usize>,
-        notify_fn: Option<F>,
-    ) -> Result<()>
-    where
-        F: Fn() + Send + Sync + 'static,
-    {
-        // First phase: Quick metadata-based registry population
-        self.populate_registry_from_metadata().await?;
+        component_id: &str,
+        update: impl std::future::Future<Output = Result<ComponentLoadOutcome>>,
+    ) -> Result<UpdateStatus> {
+        let snapshot = Snapshot::capture(&self.storage, component_id).await?;
+        info!(%component_id, "Updating component");
+        let error = match update.await {
+            Ok(_) => match self.health_check(component_id).await {
+                Ok(()) => return Ok(UpdateStatus::Updated),
+                Err(error) => error.context("Health check failed"),
+            },
+            Err(error) => error,
+        };
 
-        let concurrency = concurrency.unwrap_or_else(|| std::cmp::min(num_cpus::get(), 4));
+        let id = component_id.to_string();
+        self.run_exclusive(component_id, move |manager| async move {
+            let failed_digest = manager.installed_digest(&id).await;
+            snapshot.restore(&manager.storage, &id).await?;
+            manager.policy_manager.cleanup(&id).await;
+            let wasm_path = manager.component_path(&id);
+            manager
+                .compile_and_register_component(&id, &wasm_path)
+                .await?;
+            manager
+                .record_version(
+                    &id,
+                    VersionChangeKind::Rollback,
+                    failed_digest,
+                    std::convert::identity,
+                )
+                .await;
+            Ok(())
+        })
+        .await
+        .with_context(|| format!("Failed to roll back after: {error:#}"))?;
+        Ok(UpdateStatus::RolledBack {
+            error: format!("{error:#}"),
+        })
+    }
 
+    /// Put back an earlier version of `component_id` kept when it was replaced: the one whose
+    /// digest is or starts with `digest` if given, or else the latest. The version comes back
+    /// with the policy it had, and the version it replaces is kept in turn.
+    #[instrument(skip(self))]
+    pub async fn rollback_component(
+        &self,
+        component_id: &str,
+        digest: Option<&str>,
+    ) -> Result<RollbackReport> {
+        let component_id = self.resolve_component_id(component_id).await;
+        let digest = digest.map(str::to_string);
+        let id = component_id.clone();
+        let report = self
+            .run_exclusive(&component_id, move |manager| async move {
+                let Some(previous_digest) = manager.installed_digest(&id).await else {
+                    bail!("Component not found: {id}");
+                };
+                let target = versions::find(
+                    manager.plugin_root(),
+                    &id,
+                    digest.as_deref(),
+                    &previous_digest,
+                )
+                .await?;
+                let restored = versions::load(manager.plugin_root(), &id, &target).await?;
+                let replaced = Snapshot::capture(&manager.storage, &id).await?;
+                let previous_version = manager.installed_version(&id).await;
+
+                restored.restore(&manager.storage, &id).await?;
+                manager.policy_manager.cleanup(&id).await;
+                let wasm_path = manager.component_path(&id);
+                if let Err(error) = manager
+                    .compile_and_register_component(&id, &wasm_path)
+                    .await
+                {
+                    // Its policy was dropped with the cleanup, so the running version is
+                    // registered again as well.
+                    replaced.restore(&manager.storage, &id).await?;
+                    manager
+                        .compile_and_register_component(&id, &wasm_path)
+                        .await?;
+                    return Err(error.context(format!(
+                        "Failed to load version {} of the component",
+                        target.digest
+                    )));
+                }
+                manager
+                    .keep_version(&id, &replaced, previous_version.clone())
+                    .await;
+                let version = target.version.clone();
+                manager
+                    .record_version(
+                        &id,
+                        VersionChangeKind::Rollback,
+                        Some(previous_digest.clone()),
+                        |mut change| {
+                            change.version = version;
+                            change
+                        },
+                    )
+                    .await;
+                Ok(RollbackReport {
+                    component_id: id,
+                    previous_digest,
+                    digest: target.digest,
+                    previous_version,
+                    version: target.version,
+                })
+            })
+            .await?;
         info!(
-            "Starting background component loading with concurrency: {}",
-            concurrency
+            component_id = %report.component_id,
+            digest = %report.digest,
+            "Rolled back component"
         );
+        Ok(report)
+    }
 
-        let semaphore = Arc::new(Semaphore::new(concurrency));
-        let mut entries = tokio::fs::read_dir(self.storage.root()).await?;
-        let mut load_futures = Vec::new();
-
-        while let Some(entry) = entries.next_entry().await? {
-            let self_clone = self.clone();
-            let semaphore = semaphore.clone();
-            let notify_fn = notify_fn.as_ref().map(std::sync::Arc::new);
-
-            let future = async move {
-                let _permit = semaphore.acquire().await.unwrap();
-
-                match self_clone.load_component_from_entry_optimized(entry).await {
-                    Ok(true) => {
-                        // Component was loaded, notify if callback provided
-                        if let Some(notify) = notify_fn {
-                            notify();
-                        }
-                    }
-                    Ok(false) => {} // No component to load (not a .wasm file)
-                    Err(e) => warn!("Failed to load component: {}", e),
-                }
-            };
-            load_futures.push(future);
+    /// Call the health check configured for `component_id`, if any.
+    async fn health_check(&self, component_id: &str) -> Result<()> {
+        let Some(check) = self
+            .updates
+            .as_ref()
+            .and_then(|updates| updates.health_checks.get(component_id))
+        else {
+            return Ok(());
+        };
+        let arguments = serde_json::to_string(&check.arguments)?;
+        let output = self
+            .call_tool(
+                component_id,
+                &check.tool,
+                &arguments,
+                &CallContext::default(),
+            )
+            .await?;
+        if serde_json::from_str(&output.output)
+            .is_ok_and(|result: Value| result_cache::reports_error(&result))
+        {
+            bail!("{} returned an error: {}", check.tool, output.output);
         }
-
-        // Wait for all components to load
-        futures::future::join_all(load_futures).await;
-        info!("Background component loading completed");
         Ok(())
     }
 
+    /// Apply updates in the configured maintenance window every day, calling `on_update` for
+    /// each component updated, rolled back or failed, so the caller can announce the new tool
+    /// list. Runs for as long as the manager lives, so spawn it on its own task; requires
+    /// updates to be configured
+    /// ([`LifecycleBuilder::with_updates`](crate::LifecycleBuilder::with_updates)).
+    pub async fn run_scheduled_updates<F>(&self, on_update: F) -> Result<()>
+    where
+        F: Fn(&ComponentUpdate) + Send + Sync + 'static,
+    {
+        let window = self
+            .updates
+            .as_ref()
+            .context("Scheduled updates are not configured")?
+            .maintenance_window()?;
+        loop {
+            let now = chrono::Utc::now();
+            let (opens, closes) = window.next(now);
+            if opens > now {
+                // Wake up at least hourly, so a clock that jumps (a suspended host, a change
+                // of time zone) doesn't make the window go by unnoticed.
+                let wait = (opens - now).to_std().unwrap_or_default();
+                tokio::time::sleep(wait.min(Duration::from_secs(3600))).await;
+                continue;
+            }
+
+            let deadline = Instant::now() + (closes - now).to_std().unwrap_or_default();
+            info!(%closes, "Maintenance window open; checking components for updates");
+            for update in self.update_components(Some(deadline)).await {
+                on_update(&update);
+            }
+            tokio::time::sleep_until(deadline.into()).await;
+        }
+    }
+
     /// Populate tool registry from cached metadata without compiling components
     async fn populate_registry_from_metadata(&self) -> Result<()> {
         let mut entries = tokio::fs::read_dir(self.storage.root()).await?;
@@ -1084,6 +3985,8 @@ impl LifecycleManager {
             if let Ok(Some(metadata)) = self.load_component_metadata(component_id).await {
                 // Validate that the component file hasn't changed
                 if ComponentStorage::validate_stamp(&entry_path, &metadata.validation_stamp).await {
+                    let prompts = metadata.prompts.unwrap_or_default();
+                    let readme = metadata.readme;
                     let tool_metadata: Vec<ToolMetadata> = metadata
                         .function_identifiers
                         .into_iter()
@@ -1106,6 +4009,8 @@ impl LifecycleManager {
                     {
                         Ok(true) => {
                             loaded_count += 1;
+                            self.prompts.set(component_id, prompts);
+                            self.published.set_readme(component_id, readme.as_deref());
                             debug!(component_id = %component_id, "Registered tools from cached metadata");
                             continue;
                         }
@@ -1135,7 +4040,7 @@ impl LifecycleManager {
     }
 
     /// Load a component from directory entry with optimization
-    async fn load_component_from_entry_optimized(&self, entry: DirEntry) -> Result<bool> {
+    async fn load_component_from_entry_optimized(&self, entry: DirEntry) -> Result<Option<String>> {
         let entry_path = entry.path();
         let is_file = entry
             .metadata()
@@ -1147,7 +4052,7 @@ impl LifecycleManager {
             .map(|ext| ext == "wasm")
             .unwrap_or(false);
         if !(is_file && is_wasm) {
-            return Ok(false);
+            return Ok(None);
         }
 
         let component_id = entry_path
@@ -1156,61 +4061,161 @@ impl LifecycleManager {
             .map(String::from)
             .context("wasm file didn't have a valid file name")?;
 
-        if self.registry.contains_component(&component_id).await {
-            debug!(component_id = %component_id, "Component already loaded in memory");
-            return Ok(false);
-        }
-
-        let start_time = Instant::now();
-        self.compile_and_register_component(&component_id, &entry_path)
-            .await
-            .with_context(|| {
-                format!(
-                    "Failed to compile component from path: {}",
-                    entry_path.display()
-                )
-            })?;
+        let id = component_id.clone();
+        self.run_exclusive(&component_id, move |manager| async move {
+            if manager.registry.contains_component(&id).await {
+                debug!(component_id = %id, "Component already loaded in memory");
+                return Ok(None);
+            }
 
-        info!(component_id = %component_id, elapsed = ?start_time.elapsed(), "component loaded");
-        Ok(true)
+            manager
+                .compile_and_register_component(&id, &entry_path)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to compile component from path: {}",
+                        entry_path.display()
+                    )
+                })?;
+            Ok(Some(id))
+        })
+        .await
     }
 
     // Granular permission system methods
 }
-// Load components in parallel for improved startup performance
-async fn load_components_parallel(
-    plugin_dir: &Path,
-    runtime: Arc<RuntimeContext>,
-) -> Result<Vec<(ComponentInstance, String)>> {
-    let mut entries = tokio::fs::read_dir(plugin_dir).await?;
-    let mut load_futures = Vec::new();
-
-    while let Some(entry) = entries.next_entry().await? {
-        let runtime_clone = Arc::clone(&runtime);
-        let future = async move {
-            match load_component_from_entry(runtime_clone, entry).await {
-                Ok(Some(result)) => Some(Ok(result)),
-                Ok(None) => None,
-                Err(e) => Some(Err(e)),
+impl LifecycleManager {
+    /// List the argument presets stored for a component's tools
+    pub async fn list_tool_presets(&self, component_id: &str) -> Result<ToolPresets> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        self.storage.read_presets(component_id).await
+    }
+
+    /// Set argument presets for a tool, merging them into any existing presets
+    pub async fn set_tool_preset(
+        &self,
+        component_id: &str,
+        tool_name: &str,
+        values: serde_json::Map<String, Value>,
+    ) -> Result<()> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        let (id, tool) = (component_id.to_string(), tool_name.to_string());
+        self.run_exclusive(component_id, move |manager| async move {
+            let mut presets = manager.storage.read_presets(&id).await?;
+            presets.entry(tool).or_default().extend(values);
+            manager.storage.write_presets(&id, &presets).await
+        })
+        .await?;
+        info!(%component_id, %tool_name, "Updated tool argument presets");
+        Ok(())
+    }
+
+    /// Remove argument presets for a tool. When `keys` is empty all presets for the tool are removed
+    pub async fn remove_tool_preset(
+        &self,
+        component_id: &str,
+        tool_name: &str,
+        keys: &[String],
+    ) -> Result<()> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        let (id, tool, keys) = (
+            component_id.to_string(),
+            tool_name.to_string(),
+            keys.to_vec(),
+        );
+        self.run_exclusive(component_id, move |manager| async move {
+            let mut presets = manager.storage.read_presets(&id).await?;
+            if keys.is_empty() {
+                presets.remove(&tool);
+            } else if let Some(preset) = presets.get_mut(&tool) {
+                for key in &keys {
+                    preset.remove(key);
+                }
+                if preset.is_empty() {
+                    presets.remove(&tool);
+                }
             }
-        };
-        load_futures.push(future);
+            manager.storage.write_presets(&id, &presets).await
+        })
+        .await?;
+        info!(%component_id, %tool_name, "Removed tool argument presets");
+        Ok(())
     }
 
-    let results = futures::future::join_all(load_futures).await;
-    let mut components = Vec::new();
+    /// The key-value state a component has stored through `wasi:keyvalue`
+    pub async fn export_state(&self, component_id: &str) -> Result<ComponentState> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        self.state.read(component_id).await
+    }
 
-    for result in results.into_iter().flatten() {
-        match result {
-            Ok(component) => components.push(component),
-            Err(e) => warn!("Failed to load component: {}", e),
+    /// Replace the key-value state of a component, e.g. with an earlier export. The state
+    /// must fit the quota of the component's policy when the policy grants buckets
+    pub async fn import_state(&self, component_id: &str, state: ComponentState) -> Result<()> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        if !self.storage.component_path(component_id).exists() {
+            bail!("Component not found: {component_id}");
         }
+        let policy = self
+            .policy_manager
+            .load_or_create_component_policy(component_id)
+            .await?;
+        if let Some(quota) = KeyValueQuota::from_policy(&policy)? {
+            state.check(&quota)?;
+        }
+        let keys = state.keys();
+        self.state.replace(component_id, state).await?;
+        self.result_cache.forget(component_id);
+        info!(%component_id, keys, "Imported component state");
+        Ok(())
     }
 
-    Ok(components)
-}
+    /// Remove all key-value state of a component, returning the number of keys removed
+    pub async fn clear_state(&self, component_id: &str) -> Result<usize> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        let keys = self
+            .state
+            .replace(component_id, ComponentState::default())
+            .await?;
+        self.result_cache.forget(component_id);
+        info!(%component_id, keys, "Cleared component state");
+        Ok(keys)
+    }
+
+    /// List the runtime configuration of a component, served through `wasi:config/store`
+    pub async fn list_component_config(&self, component_id: &str) -> Result<ComponentConfig> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        Ok(self
+            .runtime_config
+            .load(component_id)
+            .await?
+            .as_ref()
+            .clone())
+    }
+
+    /// Set runtime configuration values for a component
+    pub async fn set_component_config(
+        &self,
+        component_id: &str,
+        values: &[(String, String)],
+    ) -> Result<()> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        self.runtime_config.set(component_id, values).await?;
+        self.component_changed(component_id).await;
+        Ok(())
+    }
+
+    /// Remove runtime configuration keys from a component, returning how many were set
+    pub async fn unset_component_config(
+        &self,
+        component_id: &str,
+        keys: &[String],
+    ) -> Result<usize> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        let removed = self.runtime_config.unset(component_id, keys).await?;
+        self.component_changed(component_id).await;
+        Ok(removed)
+    }
 
-impl LifecycleManager {
     /// Get the secrets manager
     pub fn secrets_manager(&self) -> &SecretsManager {
         &self.secrets_manager
@@ -1222,20 +4227,34 @@ impl LifecycleManager {
         component_id: &str,
         show_values: bool,
     ) -> Result<std::collections::HashMap<String, Option<String>>> {
+        let component_id = &*self.resolve_component_id(component_id).await;
         self.secrets_manager
             .list_component_secrets(component_id, show_values)
             .await
     }
 
+    /// The environment variables a component would be given if it were instantiated now, with
+    /// the references in their values resolved
+    pub async fn resolve_component_environment(
+        &self,
+        component_id: &str,
+    ) -> Result<Vec<InjectedVariable>> {
+        let component_id = &*self.resolve_component_id(component_id).await;
+        self.policy_manager.injected_variables(component_id).await
+    }
+
     /// Set secrets for a component
     pub async fn set_component_secrets(
         &self,
         component_id: &str,
         secrets: &[(String, String)],
     ) -> Result<()> {
+        let component_id = &*self.resolve_component_id(component_id).await;
         self.secrets_manager
             .set_component_secrets(component_id, secrets)
-            .await
+            .await?;
+        self.component_changed(component_id).await;
+        Ok(())
     }
 
     /// Delete secrets for a component
@@ -1244,9 +4263,12 @@ impl LifecycleManager {
         component_id: &str,
         keys: &[String],
     ) -> Result<()> {
+        let component_id = &*self.resolve_component_id(component_id).await;
         self.secrets_manager
             .delete_component_secrets(component_id, keys)
-            .await
+            .await?;
+        self.component_changed(component_id).await;
+        Ok(())
     }
 
     /// Load secrets for a component as environment variables
@@ -1254,16 +4276,121 @@ impl LifecycleManager {
         &self,
         component_id: &str,
     ) -> Result<std::collections::HashMap<String, String>> {
+        let component_id = &*self.resolve_component_id(component_id).await;
         self.secrets_manager
             .load_component_secrets(component_id)
             .await
     }
 }
 
+/// Annotate a compile or link error with the disabled proposals the component uses, or with
+/// advice for the toolchain that built it.
+async fn diagnose_load_error(
+    runtime: &RuntimeContext,
+    wasm_path: &Path,
+    error: anyhow::Error,
+) -> anyhow::Error {
+    let Ok(wasm_bytes) = tokio::fs::read(wasm_path).await else {
+        return error;
+    };
+    let hint = proposals::diagnose(&wasm_bytes, runtime.wasm_proposals())
+        .or_else(|| toolchain::diagnose(&wasm_bytes, &error));
+    match hint {
+        Some(hint) => error.context(hint),
+        None => error,
+    }
+}
+
+/// Call `func` of `instance`. Components using the async ABI are called through Wasmtime's
+/// concurrent call API, which drives the guest task, and the host futures it waits on, to
+/// completion and runs its post-return; other components are called as they always were.
+async fn call_export(
+    store: &mut Store<WassetteWasiState<WasiState>>,
+    instance: Instance,
+    func: Func,
+    params: &[Val],
+    results: &mut [Val],
+    concurrent: bool,
+) -> Result<()> {
+    if concurrent {
+        return instance
+            .run_concurrent(&mut *store, async |accessor| {
+                func.call_concurrent(accessor, params, results).await
+            })
+            .await?;
+    }
+    func.call_async(&mut *store, params, results).await
+}
+
+/// Components compiled at once when loading the plugin directory.
+fn default_load_concurrency() -> usize {
+    std::cmp::min(num_cpus::get(), 4)
+}
+
+/// A load of a stored component: its path, the id it was loaded as, if it was a component,
+/// and how long the load took.
+type StoredLoad = (PathBuf, Result<Option<String>>, Duration);
+
+/// Components loaded from the plugin directory and how long each took.
+#[derive(Debug, Default)]
+struct LoadSummary {
+    timings: Vec<(String, Duration)>,
+    failed: usize,
+}
+
+impl LoadSummary {
+    /// Wait for the loads in `tasks`, warning about those that fail.
+    async fn collect(mut tasks: JoinSet<StoredLoad>) -> Self {
+        let mut summary = Self::default();
+        while let Some(joined) = tasks.join_next().await {
+            let (path, result, elapsed) = match joined {
+                Ok(load) => load,
+                Err(error) => {
+                    warn!(%error, "Component load task failed");
+                    summary.failed += 1;
+                    continue;
+                }
+            };
+            match result {
+                Ok(Some(component_id)) => summary.timings.push((component_id, elapsed)),
+                Ok(None) => {}
+                Err(error) => {
+                    warn!(
+                        path = %path.display(),
+                        error = format!("{error:#}"),
+                        "Failed to load component"
+                    );
+                    summary.failed += 1;
+                }
+            }
+        }
+        summary
+    }
+
+    /// Log how many components loaded and failed, and the load time of each, slowest first.
+    fn log(mut self, elapsed: Duration) {
+        self.timings
+            .sort_by_key(|timing| std::cmp::Reverse(timing.1));
+        let timings = self
+            .timings
+            .iter()
+            .map(|(component_id, took)| format!("{component_id}={took:.1?}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        info!(
+            loaded = self.timings.len(),
+            failed = self.failed,
+            ?elapsed,
+            %timings,
+            "Finished loading components"
+        );
+    }
+}
+
 async fn load_component_from_entry(
     runtime: Arc<RuntimeContext>,
     entry: DirEntry,
-) -> Result<Option<(ComponentInstance, String)>> {
+) -> Result<Option<(ComponentInstance, ArtifactDetails, String)>> {
     let start_time = Instant::now();
     let is_file = entry
         .metadata()
@@ -1280,29 +4407,48 @@ async fn load_component_from_entry(
     }
     let entry_path = entry.path();
     let runtime_for_component = Arc::clone(&runtime);
-    let component = tokio::task::spawn_blocking(move || {
-        Component::from_file(runtime_for_component.as_ref(), entry_path)
+    let compiled = tokio::task::spawn_blocking(move || {
+        let wasm_bytes = std::fs::read(&entry_path)
+            .with_context(|| format!("failed to read {}", entry_path.display()))?;
+        let precompiled_path = entry_path.with_extension(PRECOMPILED_EXT);
+        let (component, _) =
+            precompiled::load_or_compile(&runtime_for_component, &precompiled_path, &wasm_bytes)?;
+        anyhow::Ok((
+            component,
+            used_proposals(&wasm_bytes).unwrap_or_default(),
+            ArtifactDetails::from_bytes(&wasm_bytes),
+        ))
     })
-    .await??;
+    .await?
+    .and_then(|(component, wasm_proposals, details)| {
+        let instance_pre = runtime.instantiate_pre(&component)?;
+        Ok((component, instance_pre, wasm_proposals, details))
+    });
+    let (component, instance_pre, wasm_proposals, details) = match compiled {
+        Ok(compiled) => compiled,
+        Err(error) => return Err(diagnose_load_error(&runtime, &entry.path(), error).await),
+    };
     let name = entry
         .path()
         .file_stem()
         .and_then(|s| s.to_str())
         .map(String::from)
         .context("wasm file didn't have a valid file name")?;
-    info!(component_id = %name, elapsed = ?start_time.elapsed(), "component loaded");
-    let instance_pre = runtime.instantiate_pre(&component)?;
+    debug!(component_id = %name, elapsed = ?start_time.elapsed(), "Compiled component");
     Ok(Some((
         ComponentInstance {
             component: Arc::new(component),
             instance_pre: Arc::new(instance_pre),
+            wasm_proposals,
         },
+        details,
         name,
     )))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
     use std::ops::Deref;
     use std::path::PathBuf;
     use std::process::Command;
@@ -1378,6 +4524,23 @@ mod tests {
         Ok(component_path)
     }
 
+    #[test(tokio::test)]
+    async fn test_runtime_info() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let info = manager.runtime_info();
+        assert!(info.hosts.contains(&"wasi:http".to_string()));
+        // The sampling interface belongs to one of the listed packages.
+        assert!(info
+            .hosts
+            .iter()
+            .any(|host| SAMPLING_INTERFACE.starts_with(&format!("{host}/"))));
+        assert!(info.resource_limits.contains(&"memory".to_string()));
+        assert_eq!(info.fs_hardening, FsHardening::Enforce);
+        assert!(!info.coredump_on_trap);
+        assert_eq!(info.wasm_proposals, ["threads", "memory64", "relaxed-simd"]);
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_lifecycle_manager_tool_registry() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -1403,6 +4566,27 @@ mod tests {
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_load_all_components_concurrently() -> Result<()> {
+        let component_path = build_example_component().await?;
+        let tempdir = tempfile::tempdir()?;
+        for id in ["fetch-a", "fetch-b", "fetch-c", "fetch-d", "fetch-e"] {
+            std::fs::copy(&component_path, tempdir.path().join(format!("{id}.wasm")))?;
+        }
+        std::fs::write(tempdir.path().join("broken.wasm"), b"not wasm")?;
+        std::fs::write(tempdir.path().join("notes.txt"), b"not a component")?;
+
+        // Loading at build time skips the broken component and keeps the others.
+        let manager = LifecycleManager::new(&tempdir).await?;
+        let mut loaded = manager.list_components().await;
+        loaded.sort();
+        assert_eq!(
+            loaded,
+            ["fetch-a", "fetch-b", "fetch-c", "fetch-d", "fetch-e"]
+        );
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_load_and_unload_component() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -1410,15 +4594,182 @@ mod tests {
         let load_result = manager.load_component("/path/to/nonexistent").await;
         assert!(load_result.is_err());
 
-        manager.load_test_component().await?;
+        manager.load_test_component().await?;
+
+        let loaded_components = manager.list_components().await;
+        assert_eq!(loaded_components.len(), 1);
+
+        manager.unload_component(TEST_COMPONENT_ID).await?;
+
+        let loaded_components = manager.list_components().await;
+        assert!(loaded_components.is_empty());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_component_operations_are_refused_after_shutdown() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+
+        manager.shutdown().await;
+        let error = manager
+            .unload_component(TEST_COMPONENT_ID)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("shutting down"));
+        assert_eq!(manager.list_components().await.len(), 1);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_aliased_source_replaces_the_component() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        manager
+            .grant_permission(
+                TEST_COMPONENT_ID,
+                "network",
+                &serde_json::json!({"host": "example.com"}),
+            )
+            .await?;
+
+        assert!(manager.alias_component("missing", "renamed").await.is_err());
+        manager
+            .alias_component(TEST_COMPONENT_ID, "fetch-renamed")
+            .await?;
+        // Lookups through the alias reach the component.
+        assert!(manager.get_component("fetch-renamed").await.is_some());
+        assert!(manager.get_policy_info("fetch-renamed").await.is_some());
+
+        // The same component published under another name keeps the id, policy and tools.
+        let moved = tempfile::tempdir()?;
+        let moved_path = moved.path().join("fetch-renamed.wasm");
+        tokio::fs::copy(build_example_component().await?, &moved_path).await?;
+        let outcome = manager
+            .load_component(&format!("file://{}", moved_path.display()))
+            .await?;
+        assert_eq!(outcome.component_id, TEST_COMPONENT_ID);
+        assert_eq!(manager.list_components().await, vec![TEST_COMPONENT_ID]);
+        assert!(manager.get_policy_info(TEST_COMPONENT_ID).await.is_some());
+        assert!(!manager.plugin_root().join("fetch-renamed.wasm").exists());
+
+        assert_eq!(
+            manager.remove_component_alias("fetch-renamed").await?,
+            Some(TEST_COMPONENT_ID.to_string())
+        );
+        assert!(manager.get_component("fetch-renamed").await.is_none());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_orphans_of_uninstalled_components_are_removed() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        manager.alias_component(TEST_COMPONENT_ID, "fetch").await?;
+        let root = manager.plugin_root().to_path_buf();
+        tokio::fs::create_dir_all(root.join(DATA_DIR).join("gone")).await?;
+        tokio::fs::write(root.join(DATA_DIR).join("gone").join("keyvalue.redb"), "{}").await?;
+        tokio::fs::write(root.join("gone.presets.json"), "{}").await?;
+        tokio::fs::write(root.join(format!("{TEST_COMPONENT_ID}.presets.json")), "{}").await?;
+
+        let orphans = manager.find_orphans(false).await?;
+        assert!(orphans.aliases.is_empty());
+        assert_eq!(orphans.files.len(), 2);
+        assert!(orphans.files.iter().all(|file| file.component_id == "gone"));
+        assert_eq!(manager.remove_orphans(&orphans).await?, 2);
+        assert!(!root.join(DATA_DIR).join("gone").exists());
+        assert!(root
+            .join(format!("{TEST_COMPONENT_ID}.presets.json"))
+            .exists());
+
+        // Unloading leaves the presets and the alias behind.
+        manager.unload_component(TEST_COMPONENT_ID).await?;
+        let orphans = manager.find_orphans(false).await?;
+        assert_eq!(orphans.aliases.get("fetch").unwrap(), TEST_COMPONENT_ID);
+        assert_eq!(orphans.files.len(), 1);
+        assert_eq!(orphans.files[0].kind, OrphanKind::Presets);
+        assert_eq!(manager.remove_orphans(&orphans).await?, 2);
+        assert!(manager.find_orphans(false).await?.is_empty());
+
+        // Kept versions are a directory per component.
+        let versions = root.join("versions").join("gone").join("ab12");
+        tokio::fs::create_dir_all(&versions).await?;
+        tokio::fs::write(versions.join("version.json"), "{}").await?;
+        let orphans = manager.find_orphans(false).await?;
+        assert_eq!(orphans.files.len(), 1);
+        assert_eq!(orphans.files[0].kind, OrphanKind::Versions);
+        // The secrets directory was left out, and the result says so.
+        assert_eq!(orphans.not_scanned.len(), 1);
+        assert!(orphans.not_scanned[0].starts_with("secrets and configuration"));
+        assert_eq!(manager.remove_orphans(&orphans).await?, 1);
+        assert!(!root.join("versions").join("gone").exists());
+        Ok(())
+    }
+
+    #[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+    async fn test_concurrent_load_unload_keeps_disk_and_registry_in_sync() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let uri = format!("file://{}", build_example_component().await?.display());
+
+        let tasks: Vec<_> = (0..12)
+            .map(|i| {
+                let manager = manager.manager.clone();
+                let uri = uri.clone();
+                tokio::spawn(async move {
+                    match i % 3 {
+                        0 => manager.load_component(&uri).await.map(|_| ()),
+                        1 => manager.unload_component(TEST_COMPONENT_ID).await,
+                        _ => manager
+                            .grant_permission(
+                                TEST_COMPONENT_ID,
+                                "network",
+                                &serde_json::json!({"host": "example.com"}),
+                            )
+                            .await
+                            .or_else(|e| {
+                                // Granting races with unloading; "not found" is a valid outcome.
+                                if e.to_string().contains("Component not found") {
+                                    Ok(())
+                                } else {
+                                    Err(e)
+                                }
+                            }),
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await??;
+        }
+
+        let loaded = manager.get_component(TEST_COMPONENT_ID).await.is_some();
+        let on_disk = manager.component_path(TEST_COMPONENT_ID).exists();
+        assert_eq!(loaded, on_disk);
+
+        Ok(())
+    }
+
+    #[test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+    async fn test_cancelled_load_completes_atomically() -> Result<()> {
+        let manager = create_test_manager().await?;
+        let uri = format!("file://{}", build_example_component().await?.display());
 
-        let loaded_components = manager.list_components().await;
-        assert_eq!(loaded_components.len(), 1);
+        // Abandon the load as soon as it is underway; it must still finish (or not start) as
+        // a whole rather than leaving files without a registry entry.
+        let _ = tokio::time::timeout(
+            std::time::Duration::from_millis(1),
+            manager.load_component(&uri),
+        )
+        .await;
 
-        manager.unload_component(TEST_COMPONENT_ID).await?;
+        // Any in-flight operation holds the lease, so taking it waits for completion.
+        drop(manager.registry.lock(TEST_COMPONENT_ID).await?);
 
-        let loaded_components = manager.list_components().await;
-        assert!(loaded_components.is_empty());
+        let loaded = manager.get_component(TEST_COMPONENT_ID).await.is_some();
+        let on_disk = manager.component_path(TEST_COMPONENT_ID).exists();
+        assert_eq!(loaded, on_disk);
 
         Ok(())
     }
@@ -1479,6 +4830,68 @@ mod tests {
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_failed_replacement_rolls_back() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        let stored = tokio::fs::read(manager.component_path(TEST_COMPONENT_ID)).await?;
+
+        let broken_dir = tempfile::tempdir()?;
+        let broken_path = broken_dir.path().join(format!("{TEST_COMPONENT_ID}.wasm"));
+        tokio::fs::write(&broken_path, b"not a component").await?;
+        assert!(manager
+            .load_component(&format!("file://{}", broken_path.display()))
+            .await
+            .is_err());
+
+        // The running version keeps serving, from its own files.
+        assert_eq!(
+            manager.get_component_id_for_tool("fetch").await?,
+            TEST_COMPONENT_ID
+        );
+        assert!(manager.get_component(TEST_COMPONENT_ID).await.is_some());
+        assert_eq!(
+            tokio::fs::read(manager.component_path(TEST_COMPONENT_ID)).await?,
+            stored
+        );
+
+        let rollouts = manager.list_rollouts().await?;
+        let last = rollouts.last().unwrap();
+        assert_eq!(last.stage, RolloutStage::RolledBack);
+        assert_eq!(last.failed_stage, Some(RolloutStage::Load));
+        assert!(last.replacing);
+        assert_eq!(
+            rollouts.first().map(|event| event.stage),
+            Some(RolloutStage::Load)
+        );
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_tools_listed_from_persisted_metadata() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        let tools = manager.list_tools().await;
+        assert!(!tools.is_empty());
+
+        // A fresh manager over the same plugin directory lists the same tools without
+        // compiling the component.
+        let restored = LifecycleManager::new_unloaded(manager.plugin_root()).await?;
+        assert!(restored.list_components().await.is_empty());
+        assert_eq!(restored.list_tools().await.len(), tools.len());
+        assert_eq!(
+            restored.get_component_id_for_tool("fetch").await?,
+            TEST_COMPONENT_ID
+        );
+        assert!(restored
+            .get_component_schema(TEST_COMPONENT_ID)
+            .await
+            .is_some());
+
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_component_path_update() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -1521,6 +4934,47 @@ permissions:
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_concurrent_instances_are_capped_by_policy() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+
+        let policy_content = r#"
+version: "1.0"
+description: "Test policy"
+permissions:
+  resources:
+    limits:
+      instances: 1
+      open-files: 8
+"#;
+        let policy_path = manager.plugin_root().join("test-policy.yaml");
+        tokio::fs::write(&policy_path, policy_content).await?;
+        let policy_uri = format!("file://{}", policy_path.display());
+        manager
+            .attach_policy(TEST_COMPONENT_ID, &policy_uri)
+            .await?;
+
+        let (running, _) = manager
+            .get_wasi_state_for_component(TEST_COMPONENT_ID)
+            .await?;
+        let err = manager
+            .get_wasi_state_for_component(TEST_COMPONENT_ID)
+            .await
+            .err()
+            .expect("a second instance should exceed the limit");
+        assert!(err
+            .to_string()
+            .contains("maximum of 1 concurrent instances"));
+
+        drop(running);
+        manager
+            .get_wasi_state_for_component(TEST_COMPONENT_ID)
+            .await?;
+
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_policy_restoration_on_startup() -> Result<()> {
         let tempdir = tempfile::tempdir()?;
@@ -1559,6 +5013,40 @@ permissions:
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_strict_policy_rejects_unknown_keys() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::builder(tempdir.path())
+            .with_eager_loading(false)
+            .with_strict_policy(true)
+            .build()
+            .await?;
+        let component_path = build_example_component().await?;
+        manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+
+        let policy_path = tempdir.path().join("typo-policy.yaml");
+        tokio::fs::write(
+            &policy_path,
+            "version: \"1.0\"\npermissions:\n  network:\n    alow:\n    - host: example.com\n",
+        )
+        .await?;
+        let policy_uri = format!("file://{}", policy_path.display());
+        let err = manager
+            .attach_policy(TEST_COMPONENT_ID, &policy_uri)
+            .await
+            .expect_err("strict mode should reject the misspelt key");
+        let message = format!("{err:#}");
+        assert!(message.contains("unknown field `alow`"), "{message}");
+        assert!(message.contains("line 4 column 5"), "{message}");
+        assert!(!manager
+            .get_component_policy_path(TEST_COMPONENT_ID)
+            .exists());
+
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_policy_file_not_found_error() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -1622,6 +5110,287 @@ permissions:
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_latency_critical_tool_keeps_a_standby() -> Result<()> {
+        async fn wait_until_ready(manager: &LifecycleManager, tool: &str) {
+            for _ in 0..300 {
+                if manager.warm.is_ready(tool) {
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+            panic!("no standby instance for {tool}");
+        }
+
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::builder(tempdir.path())
+            .with_eager_loading(false)
+            .with_tool_settings(HashMap::from([(
+                "fetch".to_string(),
+                ToolSettings {
+                    latency_critical: true,
+                    ..Default::default()
+                },
+            )]))
+            .build()
+            .await?;
+        let component_path = build_example_component().await?;
+        manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+        wait_until_ready(&manager, "fetch").await;
+
+        // The call consumes the standby, and a fresh one replaces it.
+        let _ = manager
+            .execute_component_call(
+                TEST_COMPONENT_ID,
+                "fetch",
+                r#"{"url": "https://example.com"}"#,
+            )
+            .await;
+        wait_until_ready(&manager, "fetch").await;
+
+        // Changing the policy replaces the standby built under the old one.
+        let generation = manager.warm.generation(TEST_COMPONENT_ID);
+        manager
+            .grant_permission(
+                TEST_COMPONENT_ID,
+                "network",
+                &serde_json::json!({"host": "example.com"}),
+            )
+            .await?;
+        assert!(manager.warm.generation(TEST_COMPONENT_ID) > generation);
+        wait_until_ready(&manager, "fetch").await;
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_instance_pool_keeps_several_standbys() -> Result<()> {
+        async fn wait_until_full(manager: &LifecycleManager) {
+            for _ in 0..300 {
+                if manager.warm.ready() == 3 {
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+            panic!("the pool holds {} standbys", manager.warm.ready());
+        }
+
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::builder(tempdir.path())
+            .with_eager_loading(false)
+            .with_tool_settings(HashMap::from([(
+                "fetch".to_string(),
+                ToolSettings {
+                    latency_critical: true,
+                    ..Default::default()
+                },
+            )]))
+            .with_runtime_settings(RuntimeSettings {
+                pool_size: Some(3),
+                ..Default::default()
+            })
+            .build()
+            .await?;
+        let component_path = build_example_component().await?;
+        manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+        wait_until_full(&manager).await;
+
+        // Calls take standbys from the pool, which is topped up again.
+        for _ in 0..2 {
+            let _ = manager
+                .execute_component_call(
+                    TEST_COMPONENT_ID,
+                    "fetch",
+                    r#"{"url": "https://example.com"}"#,
+                )
+                .await;
+        }
+        wait_until_full(&manager).await;
+
+        assert!(LifecycleManager::builder(tempdir.path())
+            .with_runtime_settings(RuntimeSettings {
+                pool_size: Some(0),
+                ..Default::default()
+            })
+            .build_config()
+            .is_err());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_calls_with_validation_threads() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let settings = RuntimeSettings {
+            validation_threads: Some(2),
+            ..Default::default()
+        };
+        let manager = LifecycleManager::builder(tempdir.path())
+            .with_eager_loading(false)
+            .with_runtime_settings(settings.clone())
+            .build()
+            .await?;
+        let component_path = build_example_component().await?;
+        manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+
+        // Without network access the fetch reports an error, converted like any result.
+        let output = manager
+            .execute_component_call(
+                TEST_COMPONENT_ID,
+                "fetch",
+                r#"{"url": "https://example.com"}"#,
+            )
+            .await?;
+        assert!(output.contains("err"));
+        assert!(manager
+            .execute_component_call(TEST_COMPONENT_ID, "fetch", r#"{"url": 5}"#)
+            .await
+            .is_err());
+
+        assert!(LifecycleManager::builder(tempdir.path())
+            .with_runtime_settings(RuntimeSettings {
+                validation_threads: Some(0),
+                ..settings
+            })
+            .build_config()
+            .is_err());
+        Ok(())
+    }
+
+    /// A component with a `ping` tool whose completion hook never returns
+    const SPINNING_COMPLETION: &str = r#"(component
+        (core module $m
+            (memory (export "memory") 1)
+            (func (export "realloc") (param i32 i32 i32 i32) (result i32) (i32.const 1024))
+            (func (export "ping") (result i32) (i32.const 1))
+            (func (export "complete") (param i32 i32 i32 i32) (result i32)
+                (loop $spin (br $spin))
+                (unreachable)))
+        (core instance $i (instantiate $m))
+        (func (export "ping") (result u32) (canon lift (core func $i "ping")))
+        (func (export "complete") (param "argument" string) (param "prefix" string)
+            (result (list string))
+            (canon lift (core func $i "complete") (memory $i "memory")
+                (realloc (func $i "realloc"))))
+    )"#;
+
+    #[test(tokio::test)]
+    async fn test_completion_hooks_run_under_a_deadline() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::builder(tempdir.path().join("components"))
+            .with_eager_loading(false)
+            .with_call_timeout(Duration::from_secs(1))
+            .build()
+            .await?;
+        let component_path = tempdir.path().join("spin.wasm");
+        std::fs::write(&component_path, wat::parse_str(SPINNING_COMPLETION)?)?;
+        manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+
+        let started = Instant::now();
+        let error = manager
+            .complete_tool_argument("ping", "host", "")
+            .await
+            .expect_err("the hook never returns");
+        assert!(error.is::<CallTimedOut>(), "{error:#}");
+        assert!(started.elapsed() < completion::COMPLETION_TIMEOUT);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_instantiation_outside_calls_runs_under_a_deadline() -> Result<()> {
+        const SPINNING_START: &str = r#"(component
+            (core module $m
+                (func $spin (loop $spin (br $spin)))
+                (start $spin)
+                (func (export "ping") (result i32) (i32.const 1)))
+            (core instance $i (instantiate $m))
+            (func (export "ping") (result u32) (canon lift (core func $i "ping")))
+        )"#;
+
+        let tempdir = tempfile::tempdir()?;
+        let manager = LifecycleManager::builder(tempdir.path().join("components"))
+            .with_eager_loading(false)
+            .with_call_timeout(Duration::from_secs(1))
+            .build()
+            .await?;
+        let component_path = tempdir.path().join("spin.wasm");
+        std::fs::write(&component_path, wat::parse_str(SPINNING_START)?)?;
+
+        // Verifying a new component instantiates it like a standby would be.
+        let error = manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await
+            .expect_err("the start function never returns");
+        assert!(format!("{error:#}").contains("timed out"), "{error:#}");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_cached_tool_results_skip_errors() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+
+        // Without network access every fetch reports an error, which must not be cached.
+        let policy_content = r#"
+version: "1.0"
+description: "Cache fetch results"
+permissions:
+  cache:
+    - tool: fetch
+      ttl: 5m
+"#;
+        let policy_path = manager.plugin_root().join("cache-policy.yaml");
+        tokio::fs::write(&policy_path, policy_content).await?;
+        let policy_uri = format!("file://{}", policy_path.display());
+        manager
+            .attach_policy(TEST_COMPONENT_ID, &policy_uri)
+            .await?;
+        assert_eq!(
+            manager
+                .policy_manager
+                .cache_ttl(TEST_COMPONENT_ID, "fetch")
+                .await,
+            Some(std::time::Duration::from_secs(300))
+        );
+
+        for _ in 0..2 {
+            let output = manager
+                .call_tool(
+                    TEST_COMPONENT_ID,
+                    "fetch",
+                    r#"{"url": "https://example.com"}"#,
+                    &CallContext::default(),
+                )
+                .await?;
+            assert!(output.output.contains("err"));
+            assert!(!output.cached);
+        }
+        // Tool errors count as failed calls.
+        let stats = manager.stats().await;
+        assert_eq!((stats.calls_total, stats.call_errors_total), (2, 2));
+        assert_eq!(stats.active_calls, 0);
+        assert!(stats.latency_p95_secs.is_some());
+
+        // A detached policy no longer caches anything.
+        manager.detach_policy(TEST_COMPONENT_ID).await?;
+        assert_eq!(
+            manager
+                .policy_manager
+                .cache_ttl(TEST_COMPONENT_ID, "fetch")
+                .await,
+            None
+        );
+
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn test_wasi_state_template_allowed_hosts() -> Result<()> {
         // Test that WasiStateTemplate correctly stores allowed hosts from policy
@@ -1644,6 +5413,44 @@ permissions:
         assert_eq!(template.allowed_hosts.len(), 2);
         assert!(template.allowed_hosts.contains("api.example.com"));
         assert!(template.allowed_hosts.contains("cdn.example.com"));
+        assert_eq!(
+            template.network_rules.allow.map(|allow| allow.len()),
+            Some(2)
+        );
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_wasi_ctx_hooks_run_per_component() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&seen);
+        let manager = LifecycleManager::builder(tempdir.path())
+            .with_eager_loading(false)
+            .with_wasi_ctx_hook(move |component_id, builder| {
+                recorder.lock().unwrap().push(component_id.to_string());
+                builder.env("EMBEDDER", "1");
+                Ok(())
+            })
+            .with_wasi_ctx_hook(|component_id, _| {
+                if component_id == "broken" {
+                    bail!("no sink for {component_id}");
+                }
+                Ok(())
+            })
+            .build()
+            .await?;
+
+        manager.get_wasi_state_for_component("fetch").await?;
+        assert_eq!(*seen.lock().unwrap(), vec!["fetch".to_string()]);
+
+        let err = manager
+            .get_wasi_state_for_component("broken")
+            .await
+            .err()
+            .expect("hook error should fail the store");
+        assert!(format!("{err:#}").contains("no sink for broken"));
 
         Ok(())
     }
@@ -1841,4 +5648,137 @@ permissions:
 
         Ok(())
     }
+
+    #[test(tokio::test)]
+    async fn test_tool_presets_set_merge_and_remove() -> Result<()> {
+        let manager = create_test_manager().await?;
+
+        let mut values = serde_json::Map::new();
+        values.insert("user_agent".to_string(), Value::from("wassette-test"));
+        manager
+            .set_tool_preset(TEST_COMPONENT_ID, "fetch", values)
+            .await?;
+
+        let mut values = serde_json::Map::new();
+        values.insert("timeout".to_string(), Value::from(30));
+        manager
+            .set_tool_preset(TEST_COMPONENT_ID, "fetch", values)
+            .await?;
+
+        let presets = manager.list_tool_presets(TEST_COMPONENT_ID).await?;
+        let fetch = presets.get("fetch").expect("fetch presets should exist");
+        assert_eq!(fetch.get("user_agent"), Some(&Value::from("wassette-test")));
+        assert_eq!(fetch.get("timeout"), Some(&Value::from(30)));
+
+        manager
+            .remove_tool_preset(TEST_COMPONENT_ID, "fetch", &["timeout".to_string()])
+            .await?;
+        let presets = manager.list_tool_presets(TEST_COMPONENT_ID).await?;
+        assert!(!presets["fetch"].contains_key("timeout"));
+
+        manager
+            .remove_tool_preset(TEST_COMPONENT_ID, "fetch", &[])
+            .await?;
+        assert!(manager
+            .list_tool_presets(TEST_COMPONENT_ID)
+            .await?
+            .is_empty());
+        assert!(!manager.storage.presets_path(TEST_COMPONENT_ID).exists());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_component_state_import_export_clear() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+        tokio::fs::write(
+            manager.get_component_policy_path(TEST_COMPONENT_ID),
+            "version: \"1.0\"\npermissions:\n  storage:\n    allow:\n      - uri: \"kv://*\"\n        access: [\"read\", \"write\"]\n  keyvalue:\n    max-keys: 1\n",
+        )
+        .await?;
+
+        let state: ComponentState =
+            serde_json::from_value(json!({"buckets": {"": {"token": "c2VjcmV0"}}}))?;
+        manager
+            .import_state(TEST_COMPONENT_ID, state.clone())
+            .await?;
+        assert_eq!(manager.export_state(TEST_COMPONENT_ID).await?, state);
+
+        let too_large: ComponentState =
+            serde_json::from_value(json!({"buckets": {"": {"a": "", "b": ""}}}))?;
+        assert!(manager
+            .import_state(TEST_COMPONENT_ID, too_large)
+            .await
+            .is_err());
+        assert!(manager
+            .import_state("missing", state.clone())
+            .await
+            .is_err());
+        assert_eq!(manager.export_state(TEST_COMPONENT_ID).await?, state);
+
+        assert_eq!(manager.clear_state(TEST_COMPONENT_ID).await?, 1);
+        assert_eq!(
+            manager.export_state(TEST_COMPONENT_ID).await?,
+            ComponentState::default()
+        );
+        assert!(!manager.storage.state_path(TEST_COMPONENT_ID).exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test(tokio::test)]
+    async fn test_hooks_fire_on_lifecycle_events() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        // One file per event, since hooks run concurrently.
+        let events = tempdir.path().join("events");
+        tokio::fs::create_dir(&events).await?;
+        let hook = HookConfig {
+            events: vec![HookEvent::Load, HookEvent::Unload],
+            command: vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                format!("cat > $(mktemp -p {})", events.display()),
+            ],
+            ..Default::default()
+        };
+        let manager = LifecycleManager::builder(tempdir.path().join("components"))
+            .with_hooks(vec![hook])
+            .with_eager_loading(false)
+            .build()
+            .await?;
+
+        let component_path = build_example_component().await?;
+        manager
+            .load_component(&format!("file://{}", component_path.display()))
+            .await?;
+        manager.unload_component(TEST_COMPONENT_ID).await?;
+
+        let mut received = Vec::new();
+        for _ in 0..50 {
+            received.clear();
+            let mut entries = tokio::fs::read_dir(&events).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if let Ok(event) =
+                    serde_json::from_slice::<Value>(&tokio::fs::read(entry.path()).await?)
+                {
+                    received.push(event);
+                }
+            }
+            if received.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let mut kinds: Vec<&str> = received
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .collect();
+        kinds.sort();
+        assert_eq!(kinds, ["load", "unload"]);
+        assert!(received
+            .iter()
+            .all(|event| event["component_id"] == TEST_COMPONENT_ID));
+        Ok(())
+    }
 }
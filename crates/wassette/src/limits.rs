@@ -0,0 +1,582 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Per-component caps on host resources that aren't covered by memory and CPU limits.
+//!
+//! A policy can bound, under `resources.limits`, how many descriptors one instance holds open
+//! (`open-files`), how many instances of a component run at once (`instances`), and how many
+//! tables and table elements an instance creates (`tables`, `table-elements`). Table caps are
+//! enforced by the store's [`wasmtime::StoreLimits`]; this module holds the other two: open
+//! files are counted in the `wasi:filesystem` shadows of [`crate::fs_grants`], and instances
+//! take a slot from a per-component semaphore for as long as their store lives.
+//!
+//! `fuel` bounds the work of a single tool invocation: the engine meters fuel for every
+//! store, and each call starts with the component's allowance. A call that runs out of fuel,
+//! or traps after its memory or a table was refused growth past the policy's limit, fails
+//! with a [`ResourceLimitExceeded`] instead of the bare trap.
+//!
+//! `timeout` bounds the wall-clock time of a tool invocation, along with the server's call
+//! timeout and the `timeout_secs` of the tool's settings. The engine's epoch advances every
+//! [`EPOCH_TICK`](crate::runtime_context::EPOCH_TICK), and the epoch callback of a store with a
+//! deadline traps once it has passed; a call waiting on the host past its deadline is dropped
+//! instead. Either way the call fails with a [`CallTimedOut`] and its store is discarded. A
+//! call cancelled by its caller is stopped the same way and fails with a [`CallCancelled`].
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+use wasmtime::component::{Resource, ResourceTable};
+use wasmtime::{Store, Trap, UpdateDeadline};
+use wasmtime_wasi::p2::bindings::filesystem::types::{Descriptor, ErrorCode};
+
+use crate::{CallCancelled, ToolSettings, WasiState, WassetteWasiState};
+
+/// Keys of a policy's `resources.limits` that are enforced.
+pub(crate) const ENFORCED_LIMITS: &[&str] = &[
+    "memory",
+    "open-files",
+    "instances",
+    "tables",
+    "table-elements",
+    "fuel",
+    "timeout",
+];
+
+/// How many calls of a component's tools may be in progress at once and start per minute,
+/// from the `max-concurrent-invocations` and `rate-limit` of its policy.
+///
+/// The manager doesn't enforce them: they are meant for the server dispatching calls, which
+/// can refuse a call before it queues for an instance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InvocationLimits {
+    /// Tool calls in progress at the same time
+    pub max_concurrent: Option<u32>,
+    /// Tool calls per minute
+    pub per_minute: Option<u32>,
+}
+
+/// A resource whose policy limit a call ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LimitedResource {
+    /// Linear memory, in bytes
+    Memory,
+    /// Elements of one table
+    TableElements,
+    /// Fuel, consumed about once per WebAssembly instruction
+    Fuel,
+}
+
+impl fmt::Display for LimitedResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Memory => "memory",
+            Self::TableElements => "table-elements",
+            Self::Fuel => "fuel",
+        })
+    }
+}
+
+/// A tool call failed because its component ran into a limit of its policy.
+///
+/// It is attached to the error of the call, where callers find it with
+/// [`anyhow::Error::downcast_ref`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResourceLimitExceeded {
+    /// The component that was called
+    pub component_id: String,
+    /// The `resources.limits` key of the limit
+    pub resource: LimitedResource,
+    /// The limit: bytes of memory, table elements or units of fuel
+    pub limit: u64,
+}
+
+impl fmt::Display for ResourceLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "resource limit exceeded: component {} ran into its `{}` limit of {}",
+            self.component_id, self.resource, self.limit
+        )
+    }
+}
+
+impl std::error::Error for ResourceLimitExceeded {}
+
+/// A tool call was stopped because it ran longer than its timeout.
+///
+/// Like [`ResourceLimitExceeded`], it is attached to the error of the call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallTimedOut {
+    /// The component that was called
+    pub component_id: String,
+    /// How long the call was allowed to run
+    pub timeout: Duration,
+}
+
+impl fmt::Display for CallTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tool call timed out: component {} ran longer than its timeout of {:?}",
+            self.component_id, self.timeout
+        )
+    }
+}
+
+impl std::error::Error for CallTimedOut {}
+
+/// Longest instantiating a component outside a call may take, e.g. for a standby, when no
+/// call timeout applies.
+pub(crate) const INSTANTIATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeouts of tool calls from the server's configuration.
+#[derive(Debug, Default)]
+pub(crate) struct CallTimeouts {
+    default: Option<Duration>,
+    tools: HashMap<String, Duration>,
+}
+
+impl CallTimeouts {
+    pub(crate) fn new(default: Option<Duration>, tools: &HashMap<String, ToolSettings>) -> Self {
+        Self {
+            default,
+            tools: tools
+                .iter()
+                .filter_map(|(name, settings)| {
+                    Some((name.clone(), Duration::from_secs(settings.timeout_secs?)))
+                })
+                .collect(),
+        }
+    }
+
+    /// Timeout of a call of `tool` whose component's policy sets `policy`: the tool's own
+    /// timeout or else the default, cut down to the policy's.
+    pub(crate) fn for_call(&self, tool: &str, policy: Option<Duration>) -> Option<Duration> {
+        let configured = self.tools.get(tool).copied().or(self.default);
+        configured.into_iter().chain(policy).min()
+    }
+
+    /// Timeout of instantiating a component whose policy sets `policy` outside a call: the
+    /// default call timeout cut down to the policy's, or [`INSTANTIATION_TIMEOUT`].
+    pub(crate) fn for_instantiation(&self, policy: Option<Duration>) -> Duration {
+        self.default
+            .into_iter()
+            .chain(policy)
+            .min()
+            .unwrap_or(INSTANTIATION_TIMEOUT)
+    }
+}
+
+/// Start the clock on a call with `timeout` about to run in `store`. Its epoch callback traps
+/// once the deadline has passed or the call was cancelled; callbacks installed later must call
+/// [`check_deadline`].
+pub(crate) fn start_deadline(
+    store: &mut Store<WassetteWasiState<WasiState>>,
+    timeout: Option<Duration>,
+) {
+    store.data_mut().inner.deadline = timeout.map(|timeout| Instant::now() + timeout);
+    store.set_epoch_deadline(1);
+    store.epoch_deadline_callback(|ctx| {
+        check_deadline(&ctx.data().inner)?;
+        Ok(UpdateDeadline::Continue(1))
+    });
+}
+
+/// Fail with [`Trap::Interrupt`] if the call running in `state`'s store is past its deadline
+/// or was cancelled.
+pub(crate) fn check_deadline(state: &WasiState) -> Result<()> {
+    if is_cancelled(state.cancellation.as_ref()) {
+        return Err(Trap::Interrupt.into());
+    }
+    match state.deadline {
+        Some(deadline) if Instant::now() >= deadline => Err(Trap::Interrupt.into()),
+        _ => Ok(()),
+    }
+}
+
+fn is_cancelled(cancellation: Option<&CancellationToken>) -> bool {
+    cancellation.is_some_and(CancellationToken::is_cancelled)
+}
+
+/// Await `call`, or fail with [`Trap::Interrupt`] and drop it once `deadline` has passed or
+/// `cancellation` is cancelled.
+pub(crate) async fn until_deadline<T>(
+    deadline: Option<Instant>,
+    cancellation: Option<&CancellationToken>,
+    call: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let timed = async {
+        let Some(deadline) = deadline else {
+            return call.await;
+        };
+        tokio::time::timeout_at(deadline.into(), call)
+            .await
+            .unwrap_or_else(|_| Err(Trap::Interrupt.into()))
+    };
+    let Some(cancellation) = cancellation else {
+        return timed.await;
+    };
+    tokio::select! {
+        result = timed => result,
+        () = cancellation.cancelled() => Err(Trap::Interrupt.into()),
+    }
+}
+
+/// Attach a [`ResourceLimitExceeded`] to `error`, the failure of a call of `component_id`,
+/// when the call ran out of its `fuel` or `exceeded`, the limit its store's limiter refused,
+/// a [`CallCancelled`] when it was interrupted after `cancellation` was cancelled, or a
+/// [`CallTimedOut`] when it was interrupted after running for its `timeout`.
+pub(crate) fn classify_failure(
+    error: anyhow::Error,
+    component_id: &str,
+    fuel: Option<u64>,
+    exceeded: Option<(LimitedResource, u64)>,
+    timeout: Option<Duration>,
+    cancellation: Option<&CancellationToken>,
+) -> anyhow::Error {
+    let trap = error.downcast_ref::<Trap>().copied();
+    if trap == Some(Trap::Interrupt) && is_cancelled(cancellation) {
+        return error.context(CallCancelled {
+            component_id: component_id.to_string(),
+        });
+    }
+    if let Some(timeout) = timeout.filter(|_| trap == Some(Trap::Interrupt)) {
+        return error.context(CallTimedOut {
+            component_id: component_id.to_string(),
+            timeout,
+        });
+    }
+    let out_of_fuel = trap == Some(Trap::OutOfFuel);
+    let exceeded = match fuel {
+        Some(limit) if out_of_fuel => Some((LimitedResource::Fuel, limit)),
+        _ => exceeded,
+    };
+    match exceeded {
+        Some((resource, limit)) => error.context(ResourceLimitExceeded {
+            component_id: component_id.to_string(),
+            resource,
+            limit,
+        }),
+        None => error,
+    }
+}
+
+/// Concurrent instance slots of every component with an `instances` limit.
+#[derive(Debug, Default)]
+pub(crate) struct InstanceSlots {
+    slots: Mutex<HashMap<String, (usize, Arc<Semaphore>)>>,
+}
+
+impl InstanceSlots {
+    /// Take one of the `limit` slots of `component_id`, failing when all of them are in use.
+    /// Without a limit no slot is needed.
+    pub(crate) fn try_acquire(
+        &self,
+        component_id: &str,
+        limit: Option<usize>,
+    ) -> Result<Option<OwnedSemaphorePermit>> {
+        let Some(limit) = limit else {
+            return Ok(None);
+        };
+        let semaphore = {
+            let mut slots = self.slots.lock().unwrap();
+            let entry = slots
+                .entry(component_id.to_string())
+                .or_insert_with(|| (limit, Arc::new(Semaphore::new(limit))));
+            // A changed policy starts over; instances holding old slots finish undisturbed.
+            if entry.0 != limit {
+                *entry = (limit, Arc::new(Semaphore::new(limit)));
+            }
+            Arc::clone(&entry.1)
+        };
+        match semaphore.try_acquire_owned() {
+            Ok(permit) => Ok(Some(permit)),
+            Err(_) => bail!(
+                "Component '{component_id}' already runs its maximum of {limit} concurrent instances"
+            ),
+        }
+    }
+
+    /// Instances holding a slot, and the sum of the limits, across components.
+    pub(crate) fn usage(&self) -> (usize, usize) {
+        self.slots.lock().unwrap().values().fold(
+            (0, 0),
+            |(in_use, capacity), (limit, semaphore)| {
+                (
+                    in_use + limit.saturating_sub(semaphore.available_permits()),
+                    capacity + limit,
+                )
+            },
+        )
+    }
+}
+
+/// The descriptors one store opened, bounded by the `open-files` limit.
+#[derive(Debug, Default)]
+pub(crate) struct OpenFiles {
+    limit: Option<usize>,
+    reps: HashSet<u32>,
+}
+
+impl OpenFiles {
+    pub(crate) fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            reps: HashSet::new(),
+        }
+    }
+
+    /// Fail with `quota` when opening another descriptor would exceed the limit.
+    pub(crate) fn check(&mut self, table: &ResourceTable) -> Result<(), ErrorCode> {
+        let Some(limit) = self.limit else {
+            return Ok(());
+        };
+        // Descriptors the component dropped since are gone from the table.
+        self.reps
+            .retain(|rep| table.get(&Resource::<Descriptor>::new_borrow(*rep)).is_ok());
+        if self.reps.len() >= limit {
+            return Err(ErrorCode::Quota);
+        }
+        Ok(())
+    }
+
+    /// Count a descriptor returned by `open-at`.
+    pub(crate) fn opened(&mut self, rep: u32) {
+        if self.limit.is_some() {
+            self.reps.insert(rep);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime_wasi::p2::bindings::filesystem::preopens::Host as _;
+    use wasmtime_wasi::p2::bindings::filesystem::types::{
+        DescriptorFlags, HostDescriptor, OpenFlags, PathFlags,
+    };
+    use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxBuilder, WasiCtxView};
+
+    use super::*;
+
+    #[test]
+    fn test_enforced_limits_are_policy_keys() {
+        let mut limits = policy::ResourceLimitValues::new(None, None);
+        limits.open_files = Some(1);
+        limits.instances = Some(1);
+        limits.tables = Some(1);
+        limits.table_elements = Some(1);
+        limits.fuel = Some(1);
+        limits.timeout = Some(policy::CallTimeout::Number(1));
+        limits.memory = Some(policy::MemoryLimit::Number(1));
+        let keys = serde_json::to_value(&limits).unwrap();
+        for limit in ENFORCED_LIMITS {
+            assert!(!keys[limit].is_null(), "{limit} is not a resource limit");
+        }
+    }
+
+    #[test]
+    fn test_failures_past_limits_are_classified() {
+        let out_of_fuel = || anyhow::Error::from(wasmtime::Trap::OutOfFuel).context("call failed");
+        let error = classify_failure(out_of_fuel(), "fib", Some(1000), None, None, None);
+        assert_eq!(
+            error.downcast_ref::<ResourceLimitExceeded>(),
+            Some(&ResourceLimitExceeded {
+                component_id: "fib".to_string(),
+                resource: LimitedResource::Fuel,
+                limit: 1000,
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            "resource limit exceeded: component fib ran into its `fuel` limit of 1000"
+        );
+
+        // A trap after memory growth was refused
+        let trap = anyhow::Error::from(wasmtime::Trap::UnreachableCodeReached);
+        let error = classify_failure(
+            trap,
+            "fib",
+            None,
+            Some((LimitedResource::Memory, 1 << 20)),
+            None,
+            None,
+        );
+        let exceeded = error.downcast_ref::<ResourceLimitExceeded>().unwrap();
+        assert_eq!(exceeded.resource, LimitedResource::Memory);
+        assert_eq!(exceeded.limit, 1 << 20);
+
+        // Other failures are left alone.
+        let trap = anyhow::Error::from(wasmtime::Trap::UnreachableCodeReached);
+        let error = classify_failure(
+            trap,
+            "fib",
+            Some(1000),
+            None,
+            Some(Duration::from_secs(1)),
+            None,
+        );
+        assert!(error.downcast_ref::<ResourceLimitExceeded>().is_none());
+        assert!(error.downcast_ref::<CallTimedOut>().is_none());
+        let error = classify_failure(out_of_fuel(), "fib", None, None, None, None);
+        assert!(error.downcast_ref::<ResourceLimitExceeded>().is_none());
+
+        // An interrupted call with a timeout
+        let interrupt = anyhow::Error::from(Trap::Interrupt);
+        let error = classify_failure(
+            interrupt,
+            "fib",
+            None,
+            None,
+            Some(Duration::from_secs(30)),
+            None,
+        );
+        assert_eq!(
+            error.downcast_ref::<CallTimedOut>(),
+            Some(&CallTimedOut {
+                component_id: "fib".to_string(),
+                timeout: Duration::from_secs(30),
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            "tool call timed out: component fib ran longer than its timeout of 30s"
+        );
+
+        // An interrupted call its caller cancelled
+        let token = CancellationToken::new();
+        token.cancel();
+        let interrupt = anyhow::Error::from(Trap::Interrupt);
+        let error = classify_failure(
+            interrupt,
+            "fib",
+            None,
+            None,
+            Some(Duration::from_secs(30)),
+            Some(&token),
+        );
+        assert!(error.downcast_ref::<CallTimedOut>().is_none());
+        assert_eq!(
+            error.to_string(),
+            "tool call cancelled: the call of component fib was cancelled by its caller"
+        );
+    }
+
+    #[test]
+    fn test_call_timeouts() {
+        let tools = HashMap::from([(
+            "reindex".to_string(),
+            ToolSettings {
+                timeout_secs: Some(600),
+                ..Default::default()
+            },
+        )]);
+        let secs = Duration::from_secs;
+
+        let timeouts = CallTimeouts::new(Some(secs(60)), &tools);
+        assert_eq!(timeouts.for_call("fetch", None), Some(secs(60)));
+        assert_eq!(timeouts.for_call("reindex", None), Some(secs(600)));
+        assert_eq!(timeouts.for_call("reindex", Some(secs(5))), Some(secs(5)));
+        assert_eq!(timeouts.for_call("fetch", Some(secs(90))), Some(secs(60)));
+
+        let timeouts = CallTimeouts::new(None, &HashMap::new());
+        assert_eq!(timeouts.for_call("fetch", None), None);
+        assert_eq!(timeouts.for_call("fetch", Some(secs(5))), Some(secs(5)));
+    }
+
+    #[tokio::test]
+    async fn test_calls_are_dropped_past_their_deadline() {
+        let pending = std::future::pending::<Result<()>>();
+        let deadline = Instant::now() + Duration::from_millis(20);
+        let error = until_deadline(Some(deadline), None, pending)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref::<Trap>(), Some(&Trap::Interrupt));
+        assert_eq!(
+            until_deadline(None, None, async { Ok(1) }).await.unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_calls_are_dropped_when_cancelled() {
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            cancel.cancel();
+        });
+        let pending = std::future::pending::<Result<()>>();
+        let error = until_deadline(None, Some(&token), pending)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref::<Trap>(), Some(&Trap::Interrupt));
+    }
+
+    #[test]
+    fn test_instance_slots_are_capped_per_component() {
+        let slots = InstanceSlots::default();
+        assert!(slots.try_acquire("fetch", None).unwrap().is_none());
+
+        let first = slots.try_acquire("fetch", Some(2)).unwrap();
+        let _second = slots.try_acquire("fetch", Some(2)).unwrap();
+        let err = slots.try_acquire("fetch", Some(2)).unwrap_err();
+        assert!(err.to_string().contains("maximum of 2"));
+        assert!(slots.try_acquire("other", Some(1)).unwrap().is_some());
+
+        assert_eq!(slots.usage(), (2, 3));
+        drop(first);
+        assert_eq!(slots.usage(), (1, 3));
+        assert!(slots.try_acquire("fetch", Some(2)).is_ok());
+        // A new limit takes effect right away.
+        assert!(slots.try_acquire("fetch", Some(3)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_open_files_count_live_descriptors() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut builder = WasiCtxBuilder::new();
+        builder
+            .preopened_dir(dir.path(), "/data", DirPerms::all(), FilePerms::all())
+            .unwrap();
+        let mut ctx = builder.build();
+        let mut table = ResourceTable::new();
+        let mut view = WasiCtxView {
+            ctx: &mut ctx,
+            table: &mut table,
+        };
+        let (preopen, _) = view.get_directories().unwrap().remove(0);
+        let mut files = OpenFiles::new(Some(2));
+        for name in ["a", "b"] {
+            files.check(view.table).unwrap();
+            let opened = HostDescriptor::open_at(
+                &mut view,
+                Resource::new_borrow(preopen.rep()),
+                PathFlags::empty(),
+                name.to_string(),
+                OpenFlags::CREATE,
+                DescriptorFlags::READ,
+            )
+            .await
+            .unwrap();
+            files.opened(opened.rep());
+        }
+        assert_eq!(files.check(view.table), Err(ErrorCode::Quota));
+
+        // Dropping a descriptor frees its place.
+        let first = files.reps.iter().copied().min().unwrap();
+        HostDescriptor::drop(&mut view, Resource::new_own(first)).unwrap();
+        assert_eq!(files.check(view.table), Ok(()));
+
+        let mut unlimited = OpenFiles::new(None);
+        unlimited.opened(preopen.rep());
+        assert_eq!(unlimited.check(view.table), Ok(()));
+    }
+}
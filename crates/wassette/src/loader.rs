@@ -10,6 +10,10 @@ use tokio::fs::metadata;
 use tokio::io::AsyncWriteExt;
 use tracing::{debug, info, warn};
 
+use crate::history::{Release, RELEASE_EXTENSION};
+use crate::oci_pull::{OciPull, PullSource};
+use crate::progress::{LoadContext, LoadStage};
+
 /// Represents a downloaded resource, either from a local file or a temporary one.
 pub enum DownloadedResource {
     Local(PathBuf),
@@ -69,7 +73,45 @@ impl DownloadedResource {
             .ok_or_else(|| anyhow::anyhow!("Failed to extract resource ID from path"))
     }
 
-    pub async fn copy_to(self, dest: impl AsRef<Path>) -> Result<()> {
+    /// Keep `release` next to a downloaded resource, to be read back by [`Self::release`].
+    async fn save_release(&self, release: Option<Release>) -> Result<()> {
+        let (DownloadedResource::Temp((tempdir, _)), Some(release)) = (self, release) else {
+            return Ok(());
+        };
+        let path = tempdir
+            .path()
+            .join(format!("{}.{RELEASE_EXTENSION}", self.id()?));
+        tokio::fs::write(&path, serde_json::to_vec(&release)?)
+            .await
+            .context("Failed to save release information")
+    }
+
+    /// The release the resource was published as, if the registry said.
+    pub async fn release(&self) -> Option<Release> {
+        let DownloadedResource::Temp((tempdir, _)) = self else {
+            return None;
+        };
+        let path = tempdir
+            .path()
+            .join(format!("{}.{RELEASE_EXTENSION}", self.id().ok()?));
+        let data = tokio::fs::read(&path).await.ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// The policy published with the resource, e.g. as a layer of its OCI artifact.
+    pub async fn policy(&self) -> Option<String> {
+        let DownloadedResource::Temp((tempdir, file)) = self else {
+            return None;
+        };
+        let stem = file.file_stem()?.to_str()?;
+        tokio::fs::read_to_string(tempdir.path().join(format!("{stem}.policy.yaml")))
+            .await
+            .ok()
+    }
+
+    /// Move the resource into the `dest` directory as `<name>.wasm`, along with a co-located
+    /// policy file as `<name>.policy.yaml`.
+    pub async fn copy_to(self, dest: impl AsRef<Path>, name: &str) -> Result<()> {
         let meta = tokio::fs::metadata(&dest).await?;
         if !meta.is_dir() {
             bail!(
@@ -79,10 +121,7 @@ impl DownloadedResource {
         }
         match self {
             DownloadedResource::Local(path) => {
-                let dest = dest.as_ref().join(
-                    path.file_name()
-                        .context("Path to copy is missing filename")?,
-                );
+                let dest = dest.as_ref().join(format!("{name}.wasm"));
                 tokio::fs::copy(path, dest).await?;
             }
             DownloadedResource::Temp((tempdir, file)) => {
@@ -93,7 +132,7 @@ impl DownloadedResource {
                 let policy_path = tempdir.path().join(format!("{wasm_stem}.policy.yaml"));
 
                 if policy_path.exists() {
-                    let policy_dest = dest_dir.join(format!("{wasm_stem}.policy.yaml"));
+                    let policy_dest = dest_dir.join(format!("{name}.policy.yaml"));
                     debug!(
                         "Copying co-located policy file from {:?} to {:?}",
                         policy_path, policy_dest
@@ -104,10 +143,7 @@ impl DownloadedResource {
                 }
 
                 // Copy the main file (WASM)
-                let dest_file = dest_dir.join(
-                    file.file_name()
-                        .context("Path to copy is missing filename")?,
-                );
+                let dest_file = dest_dir.join(format!("{name}.wasm"));
 
                 // Copy the main WASM file
                 match tokio::fs::rename(&file, &dest_file).await {
@@ -156,8 +192,13 @@ pub trait Loadable: Sized {
     async fn from_oci_reference(
         reference: &str,
         oci_client: &oci_client::Client,
+        context: &LoadContext,
+    ) -> Result<DownloadedResource>;
+    async fn from_url(
+        url: &str,
+        http_client: &reqwest::Client,
+        context: &LoadContext,
     ) -> Result<DownloadedResource>;
-    async fn from_url(url: &str, http_client: &reqwest::Client) -> Result<DownloadedResource>;
 }
 
 /// Loadable implementation for WebAssembly components
@@ -190,86 +231,48 @@ impl Loadable for ComponentResource {
     async fn from_oci_reference(
         reference: &str,
         oci_client: &oci_client::Client,
+        context: &LoadContext,
     ) -> Result<DownloadedResource> {
         let reference: oci_client::Reference =
             reference.parse().context("Failed to parse OCI reference")?;
-
-        // First try oci-wasm for backwards compatibility with single-layer artifacts
-        let wasm_client = oci_wasm::WasmClient::from(oci_client.clone());
-        let result = wasm_client
-            .pull(&reference, &oci_client::secrets::RegistryAuth::Anonymous)
-            .await;
-
-        match result {
-            Ok(data) => {
-                // Successfully pulled with oci-wasm - this is a single-layer WASM artifact
-                debug!("Successfully pulled single-layer WASM artifact");
-                let (downloaded_resource, mut file) = DownloadedResource::new_temp_file(
-                    reference.repository().replace('/', "_"),
-                    Self::FILE_EXTENSION,
-                )
-                .await?;
-
-                // Use the first layer (oci-wasm validated it's WASM)
-                file.write_all(&data.layers[0].data).await?;
-                file.flush().await?;
-                file.sync_all().await?;
-                drop(file);
-                Ok(downloaded_resource)
-            }
-            Err(e) => {
-                // Check if this is a multi-layer artifact issue
-                let error_str = e.to_string();
-                if error_str.contains("Incompatible layer media type") {
-                    // Multi-layer artifact detected - use our custom handler
-                    info!("Multi-layer OCI artifact detected, using direct OCI client");
-
-                    // Use our new multi-layer support to get ALL layers
-                    let artifact =
-                        crate::oci_multi_layer::pull_multi_layer_artifact(&reference, oci_client)
-                            .await
-                            .context("Failed to extract layers from multi-layer OCI artifact")?;
-
-                    // Save the WASM data
-                    let component_name = reference.repository().replace('/', "_");
-                    let (downloaded_resource, mut file) =
-                        DownloadedResource::new_temp_file(&component_name, Self::FILE_EXTENSION)
-                            .await?;
-
-                    file.write_all(&artifact.wasm_data).await?;
-                    file.flush().await?;
-                    file.sync_all().await?;
-                    drop(file);
-
-                    // If there's a policy, save it alongside the WASM in the temp directory
-                    if let Some(policy_data) = artifact.policy_data {
-                        info!("Saving policy layer alongside component");
-
-                        // Create policy file in the same temp directory as the WASM
-                        if let DownloadedResource::Temp((ref tempdir, ref _wasm_path)) =
-                            downloaded_resource
-                        {
-                            let policy_path =
-                                tempdir.path().join(format!("{component_name}.policy.yaml"));
-                            tokio::fs::write(&policy_path, &policy_data)
-                                .await
-                                .context("Failed to save policy file")?;
-                            info!("Policy saved to: {:?}", policy_path);
-                        }
+        let pull = context.pull();
+
+        let mut last_error = None;
+        for candidate in pull.candidates(&reference) {
+            for attempt in 0..=pull.options.retries {
+                if attempt > 0 {
+                    let backoff = pull.backoff(attempt);
+                    debug!(?backoff, attempt, "Retrying OCI pull");
+                    context
+                        .cancellable(async {
+                            tokio::time::sleep(backoff).await;
+                            Ok(())
+                        })
+                        .await?;
+                }
+                match pull_component(&candidate, &reference, oci_client, &pull, context).await {
+                    Ok(downloaded_resource) => return Ok(downloaded_resource),
+                    Err(error) => {
+                        context.check_cancelled()?;
+                        warn!(
+                            registry = candidate.reference.resolve_registry(),
+                            attempt,
+                            %error,
+                            "OCI pull failed"
+                        );
+                        last_error = Some(error);
                     }
-
-                    info!("Successfully extracted WASM component and policy from multi-layer artifact");
-
-                    Ok(downloaded_resource)
-                } else {
-                    // Some other error - propagate it
-                    Err(e)
                 }
             }
         }
+        Err(last_error.expect("at least one pull attempt is made"))
     }
 
-    async fn from_url(url: &str, http_client: &reqwest::Client) -> Result<DownloadedResource> {
+    async fn from_url(
+        url: &str,
+        http_client: &reqwest::Client,
+        context: &LoadContext,
+    ) -> Result<DownloadedResource> {
         let resp = http_client.get(url).send().await?;
         let status = resp.status();
         if !status.is_success() {
@@ -289,11 +292,14 @@ impl Loadable for ComponentResource {
             .trim_end_matches(&format!(".{}", Self::FILE_EXTENSION));
         let (downloaded_resource, mut file) =
             DownloadedResource::new_temp_file(name, Self::FILE_EXTENSION).await?;
+        let total_bytes = resp.content_length();
         let stream = resp.bytes_stream();
         let mut reader = tokio_util::io::StreamReader::new(stream.map_err(std::io::Error::other));
-        tokio::io::copy(&mut reader, &mut file)
+        let mut writer = context.track_download(&mut file, total_bytes);
+        tokio::io::copy(&mut reader, &mut writer)
             .await
             .context("Failed to write downloaded component to temp file")?;
+        writer.finish();
         file.flush().await?;
         file.sync_all().await?;
         drop(file);
@@ -301,6 +307,141 @@ impl Loadable for ComponentResource {
     }
 }
 
+/// Pull a component from `source`, standing for `upstream`: the single-layer layout of oci-wasm
+/// first for backwards compatibility, then the multi-layer layout carrying a policy. When
+/// signatures are checked, the image is pulled by the digest its signature vouches for.
+async fn pull_component(
+    source: &PullSource,
+    upstream: &oci_client::Reference,
+    oci_client: &oci_client::Client,
+    pull: &OciPull,
+    context: &LoadContext,
+) -> Result<DownloadedResource> {
+    let source = &pull
+        .verify_signatures(source, upstream, oci_client)
+        .await
+        .context("Signature verification failed")?;
+    let reference = &source.reference;
+    // The layer is streamed to disk so large components report progress, never sit in memory
+    // whole and can resume after an interrupted attempt.
+    let result = pull_single_layer(source, oci_client, pull, context).await;
+
+    match result {
+        Ok(downloaded_resource) => {
+            debug!("Successfully pulled single-layer WASM artifact");
+            Ok(downloaded_resource)
+        }
+        Err(e) => {
+            // Check if this is a multi-layer artifact issue
+            let error_str = e.to_string();
+            if error_str.contains("Incompatible layer media type") {
+                // Multi-layer artifact detected - use our custom handler
+                info!("Multi-layer OCI artifact detected, using direct OCI client");
+
+                // Use our new multi-layer support to get ALL layers
+                context.report(LoadStage::Downloading, 0, None);
+                let artifact = crate::oci_multi_layer::pull_multi_layer_artifact_with_auth(
+                    reference,
+                    oci_client,
+                    &source.auth,
+                )
+                .await
+                .context("Failed to extract layers from multi-layer OCI artifact")?;
+
+                // Save the WASM data
+                let component_name = reference.repository().replace('/', "_");
+                let (downloaded_resource, mut file) = DownloadedResource::new_temp_file(
+                    &component_name,
+                    ComponentResource::FILE_EXTENSION,
+                )
+                .await?;
+
+                file.write_all(&artifact.wasm_data).await?;
+                file.flush().await?;
+                file.sync_all().await?;
+                drop(file);
+                let size = artifact.wasm_data.len() as u64;
+                context.report(LoadStage::Downloading, size, Some(size));
+
+                // If there's a policy, save it alongside the WASM in the temp directory
+                if let Some(policy_data) = artifact.policy_data {
+                    info!("Saving policy layer alongside component");
+
+                    // Create policy file in the same temp directory as the WASM
+                    if let DownloadedResource::Temp((ref tempdir, ref _wasm_path)) =
+                        downloaded_resource
+                    {
+                        let policy_path =
+                            tempdir.path().join(format!("{component_name}.policy.yaml"));
+                        tokio::fs::write(&policy_path, &policy_data)
+                            .await
+                            .context("Failed to save policy file")?;
+                        info!("Policy saved to: {:?}", policy_path);
+                    }
+                }
+
+                downloaded_resource
+                    .save_release(Release::from_annotations(artifact.annotations.as_ref()))
+                    .await?;
+                info!("Successfully extracted WASM component and policy from multi-layer artifact");
+
+                Ok(downloaded_resource)
+            } else {
+                // Some other error - propagate it
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Pull a component published with the single-layer layout of `oci-wasm`, streaming the layer
+/// to a resumable temp file. Fails with "Incompatible layer media type" for other layouts, like
+/// `oci_wasm::WasmClient::pull` does.
+async fn pull_single_layer(
+    source: &PullSource,
+    oci_client: &oci_client::Client,
+    pull: &OciPull,
+    context: &LoadContext,
+) -> Result<DownloadedResource> {
+    let reference = &source.reference;
+    // Authenticating here also authorizes the blob requests that follow.
+    let (manifest, _digest) = oci_client
+        .pull_image_manifest(reference, &source.auth)
+        .await?;
+    if let Some(layer) = manifest
+        .layers
+        .iter()
+        .find(|layer| layer.media_type != oci_wasm::WASM_LAYER_MEDIA_TYPE)
+    {
+        bail!("Incompatible layer media type: {}", layer.media_type);
+    }
+    if manifest.layers.len() != 1 {
+        bail!("Wasm components must have exactly one layer");
+    }
+    if manifest.config.media_type != oci_wasm::WASM_MANIFEST_CONFIG_MEDIA_TYPE {
+        bail!(
+            "Wasm components must have a config of type {}",
+            oci_wasm::WASM_MANIFEST_CONFIG_MEDIA_TYPE
+        );
+    }
+
+    context.report(LoadStage::Downloading, 0, None);
+    // The layer digest is verified once the download completes.
+    let downloaded = pull
+        .download_layer(
+            reference,
+            oci_client,
+            &manifest.layers[0],
+            &reference.repository().replace('/', "_"),
+            context,
+        )
+        .await?;
+    downloaded
+        .save_release(Release::from_annotations(manifest.annotations.as_ref()))
+        .await?;
+    Ok(downloaded)
+}
+
 /// Loadable implementation for policies
 pub struct PolicyResource;
 
@@ -324,11 +465,16 @@ impl Loadable for PolicyResource {
     async fn from_oci_reference(
         _reference: &str,
         _oci_client: &oci_client::Client,
+        _context: &LoadContext,
     ) -> Result<DownloadedResource> {
         bail!("OCI references are not supported for policy resources. Use 'file://' or 'https://' schemes instead.")
     }
 
-    async fn from_url(url: &str, http_client: &reqwest::Client) -> Result<DownloadedResource> {
+    async fn from_url(
+        url: &str,
+        http_client: &reqwest::Client,
+        _context: &LoadContext,
+    ) -> Result<DownloadedResource> {
         let url_obj = reqwest::Url::parse(url)?;
         let filename = url_obj
             .path_segments()
@@ -366,6 +512,7 @@ pub(crate) async fn load_resource<T: Loadable>(
     uri: &str,
     oci_client: &oci_wasm::WasmClient,
     http_client: &reqwest::Client,
+    context: &LoadContext,
 ) -> Result<DownloadedResource> {
     let uri = uri.trim();
     let error_message = format!(
@@ -376,8 +523,8 @@ pub(crate) async fn load_resource<T: Loadable>(
 
     match scheme {
         "file" => T::from_local_file(Path::new(reference)).await,
-        "oci" => T::from_oci_reference(reference, oci_client).await,
-        "https" => T::from_url(uri, http_client).await,
+        "oci" => T::from_oci_reference(reference, oci_client, context).await,
+        "https" => T::from_url(uri, http_client, context).await,
         _ => bail!("Unsupported {} scheme: {}", T::RESOURCE_TYPE, scheme),
     }
 }
@@ -0,0 +1,287 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! `wassette.lock`: the resolved digest of every component loaded from a registry or URL.
+//!
+//! Each load of an `oci://` or `https://` component records, under the component's id, the URI
+//! it was loaded from and the SHA-256 digest of the component it resolved to (for OCI
+//! components, the digest of their Wasm layer). Unloading the component drops its entry.
+//!
+//! In locked mode the lockfile is only read: such a component is refused unless its id, URI
+//! and digest match an entry, so a moved tag or a changed download can't alter what a
+//! deployment runs. Stored components with an entry are checked again before they are
+//! compiled.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::updates::file_digest;
+
+/// Name of the lockfile in the plugin directory, unless configured otherwise.
+pub const LOCKFILE_NAME: &str = "wassette.lock";
+
+/// Version of the lockfile format written by this release.
+const LOCKFILE_VERSION: u32 = 1;
+
+/// Contents of the lockfile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Lockfile {
+    version: u32,
+    /// Keyed by component id
+    #[serde(default)]
+    components: BTreeMap<String, LockedComponent>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            components: BTreeMap::new(),
+        }
+    }
+}
+
+/// Where a component was loaded from and what it resolved to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LockedComponent {
+    source: String,
+    /// `sha256:<hex>` of the component
+    digest: String,
+}
+
+/// Whether loads from `uri` are recorded in the lockfile.
+pub(crate) fn is_locked_source(uri: &str) -> bool {
+    uri.starts_with("oci://") || uri.starts_with("https://")
+}
+
+/// The lockfile of a lifecycle manager.
+pub(crate) struct ComponentLock {
+    path: PathBuf,
+    locked: bool,
+    /// Held while the file is rewritten, so concurrent loads don't lose each other's entries
+    lockfile: Mutex<Lockfile>,
+}
+
+impl ComponentLock {
+    /// Read the lockfile at `path`. In `locked` mode it must exist.
+    pub(crate) async fn open(path: PathBuf, locked: bool) -> Result<Self> {
+        let lockfile = match tokio::fs::read(&path).await {
+            Ok(bytes) => {
+                let lockfile: Lockfile = serde_json::from_slice(&bytes)
+                    .with_context(|| format!("Malformed lockfile {}", path.display()))?;
+                if lockfile.version > LOCKFILE_VERSION {
+                    bail!(
+                        "Lockfile {} was written by a newer release of wassette (version {})",
+                        path.display(),
+                        lockfile.version
+                    );
+                }
+                lockfile
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !locked => Lockfile::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read lockfile {}", path.display()))
+            }
+        };
+        if locked {
+            info!(
+                path = %path.display(),
+                components = lockfile.components.len(),
+                "Loading components in locked mode"
+            );
+        }
+        Ok(Self {
+            path,
+            locked,
+            lockfile: Mutex::new(lockfile),
+        })
+    }
+
+    /// In locked mode, fail unless `component_id` was locked to `digest` when loaded from
+    /// `uri`.
+    pub(crate) async fn check(&self, component_id: &str, uri: &str, digest: &str) -> Result<()> {
+        if !self.locked {
+            return Ok(());
+        }
+        let lockfile = self.lockfile.lock().await;
+        let Some(locked) = lockfile.components.get(component_id) else {
+            bail!(
+                "Component '{component_id}' is not in the lockfile {}",
+                self.path.display()
+            );
+        };
+        if locked.source != uri {
+            bail!(
+                "Component '{component_id}' is locked to {}, not {uri}",
+                locked.source
+            );
+        }
+        if locked.digest != digest {
+            bail!(
+                "{uri} resolved to {digest}, but component '{component_id}' is locked to {}",
+                locked.digest
+            );
+        }
+        Ok(())
+    }
+
+    /// In locked mode, fail if the stored component at `path` differs from the digest
+    /// `component_id` is locked to. Components without an entry aren't checked.
+    pub(crate) async fn check_stored(&self, component_id: &str, path: &Path) -> Result<()> {
+        if !self.locked {
+            return Ok(());
+        }
+        let Some(expected) = self
+            .lockfile
+            .lock()
+            .await
+            .components
+            .get(component_id)
+            .map(|locked| locked.digest.clone())
+        else {
+            return Ok(());
+        };
+        let digest = file_digest(path).await?;
+        if digest != expected {
+            bail!("Stored component '{component_id}' is {digest}, but it is locked to {expected}");
+        }
+        Ok(())
+    }
+
+    /// Record that `component_id` was loaded from `uri` and resolved to `digest`, or drop its
+    /// entry for a source that isn't recorded (`digest` is `None`). Nothing changes in locked
+    /// mode.
+    pub(crate) async fn record(
+        &self,
+        component_id: &str,
+        uri: &str,
+        digest: Option<&str>,
+    ) -> Result<()> {
+        if self.locked {
+            return Ok(());
+        }
+        let mut lockfile = self.lockfile.lock().await;
+        let entry = digest.map(|digest| LockedComponent {
+            source: uri.to_string(),
+            digest: digest.to_string(),
+        });
+        if lockfile.components.get(component_id) == entry.as_ref() {
+            return Ok(());
+        }
+        match entry {
+            Some(entry) => lockfile.components.insert(component_id.to_string(), entry),
+            None => lockfile.components.remove(component_id),
+        };
+        self.write(&lockfile).await
+    }
+
+    /// Drop the entry of an unloaded component. Nothing changes in locked mode.
+    pub(crate) async fn remove(&self, component_id: &str) -> Result<()> {
+        if self.locked {
+            return Ok(());
+        }
+        let mut lockfile = self.lockfile.lock().await;
+        if lockfile.components.remove(component_id).is_none() {
+            return Ok(());
+        }
+        self.write(&lockfile).await
+    }
+
+    async fn write(&self, lockfile: &Lockfile) -> Result<()> {
+        let mut contents = serde_json::to_vec_pretty(lockfile)?;
+        contents.push(b'\n');
+        let staged = self.path.with_extension("lock.tmp");
+        tokio::fs::write(&staged, contents)
+            .await
+            .with_context(|| format!("Failed to write {}", staged.display()))?;
+        tokio::fs::rename(&staged, &self.path)
+            .await
+            .with_context(|| format!("Failed to write lockfile {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "oci://ghcr.io/example/fetch:1.0";
+
+    fn digest(c: char) -> String {
+        format!("sha256:{}", c.to_string().repeat(64))
+    }
+
+    #[tokio::test]
+    async fn test_loads_are_recorded_and_enforced_when_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCKFILE_NAME);
+
+        let lock = ComponentLock::open(path.clone(), false).await.unwrap();
+        lock.check("fetch", URI, &digest('a')).await.unwrap();
+        lock.record("fetch", URI, Some(&digest('a'))).await.unwrap();
+        lock.record("time", "https://example.com/time.wasm", Some(&digest('b')))
+            .await
+            .unwrap();
+        lock.record("local", "file:///tmp/local.wasm", None)
+            .await
+            .unwrap();
+        lock.record("gone", URI, Some(&digest('c'))).await.unwrap();
+        lock.remove("gone").await.unwrap();
+
+        let locked = ComponentLock::open(path.clone(), true).await.unwrap();
+        locked.check("fetch", URI, &digest('a')).await.unwrap();
+        // The tag moved
+        assert!(locked.check("fetch", URI, &digest('b')).await.is_err());
+        // Another source
+        assert!(locked
+            .check("fetch", "oci://ghcr.io/other/fetch:1.0", &digest('a'))
+            .await
+            .is_err());
+        // Not locked at all
+        assert!(locked.check("gone", URI, &digest('c')).await.is_err());
+
+        // Locked mode leaves the file alone.
+        let before = std::fs::read_to_string(&path).unwrap();
+        locked
+            .record("fetch", URI, Some(&digest('b')))
+            .await
+            .unwrap();
+        locked.remove("fetch").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+        assert!(before.contains("\"time\""));
+        assert!(!before.contains("\"local\""));
+    }
+
+    #[tokio::test]
+    async fn test_stored_components_are_checked_when_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCKFILE_NAME);
+        let wasm = dir.path().join("fetch.wasm");
+        std::fs::write(&wasm, b"test data").unwrap();
+        let stored = file_digest(&wasm).await.unwrap();
+
+        let lock = ComponentLock::open(path.clone(), false).await.unwrap();
+        lock.record("fetch", URI, Some(&stored)).await.unwrap();
+        let locked = ComponentLock::open(path, true).await.unwrap();
+        locked.check_stored("fetch", &wasm).await.unwrap();
+        locked.check_stored("unlocked", &wasm).await.unwrap();
+
+        std::fs::write(&wasm, b"tampered").unwrap();
+        assert!(locked.check_stored("fetch", &wasm).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_locked_mode_needs_a_lockfile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCKFILE_NAME);
+        assert!(ComponentLock::open(path.clone(), true).await.is_err());
+
+        std::fs::write(&path, r#"{"version": 2, "components": {}}"#).unwrap();
+        assert!(ComponentLock::open(path, false).await.is_err());
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Signed component manifests used to distribute third-party tools.
+//!
+//! A `wassette-manifest.json` describes a component release:
+//!
+//! ```json
+//! {
+//!   "name": "fetch",
+//!   "version": "1.2.0",
+//!   "component": "oci://ghcr.io/example/fetch@sha256:…",
+//!   "id": "fetch",
+//!   "description": "Fetch web pages",
+//!   "policy": { "version": "1.0", "permissions": { "network": { "allow": [{ "host": "*" }] } } },
+//!   "dependencies": [{ "name": "acme:auth", "component": "oci://ghcr.io/acme/auth@sha256:…" }],
+//!   "publisher": { "name": "Example Inc.", "namespace": "example", "publicKey": "<base64 Ed25519 public key>" },
+//!   "signature": "<base64 Ed25519 signature>"
+//! }
+//! ```
+//!
+//! The signature covers the canonical JSON form of the manifest without its `signature` field:
+//! object keys sorted, no insignificant whitespace. The policy is embedded so the permissions a
+//! user approves are the ones that get attached. Dependencies are library components plugged into the
+//! component's imports on install, so it can share helpers such as auth with other tools.
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use policy::PolicyDocument;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::aliases::check_component_id;
+
+/// Conventional file name of a component manifest.
+pub const MANIFEST_FILE_NAME: &str = "wassette-manifest.json";
+
+/// Longest accepted publisher namespace, keeping namespaced tool names within MCP's limit.
+const MAX_NAMESPACE_LEN: usize = 32;
+
+/// Field holding the signature, excluded from the signed payload.
+const SIGNATURE_FIELD: &str = "signature";
+
+/// A component release description, signed by its publisher.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    /// Component name
+    pub name: String,
+    /// Release version
+    pub version: String,
+    /// OCI reference of the component (`oci://…`)
+    pub component: String,
+    /// Id to load the component under, instead of the one derived from `component`, so its
+    /// tools, policy and secrets stay put when the release moves to another repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Human-readable description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Policy attached to the component on install
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PolicyDocument>,
+    /// Library components plugged into the component's imports on install
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<Dependency>,
+    /// Who published the release
+    pub publisher: Publisher,
+    /// Base64 Ed25519 signature over the canonical manifest
+    #[serde(default)]
+    pub signature: String,
+}
+
+/// The publisher of a manifest and the key it is signed with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Publisher {
+    /// Display name of the publisher
+    pub name: String,
+    /// Prefix for the names of the publisher's tools, e.g. `github` for `github__list_issues`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Base64 Ed25519 public key
+    pub public_key: String,
+}
+
+/// A library component the manifest's component is composed with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dependency {
+    /// Name the library is known by and configured under in `[libraries]`, e.g. `acme:auth`
+    pub name: String,
+    /// OCI reference of the library (`oci://…`)
+    pub component: String,
+}
+
+impl Dependency {
+    /// Whether the library reference pins a digest rather than a mutable tag.
+    pub fn is_digest_pinned(&self) -> bool {
+        self.component.contains("@sha256:")
+    }
+}
+
+impl Manifest {
+    /// Parse a manifest, check its signature against the publisher key and validate it.
+    pub fn parse_and_verify(bytes: &[u8]) -> Result<Self> {
+        let value: Value = serde_json::from_slice(bytes).context("Manifest is not valid JSON")?;
+        let manifest: Manifest =
+            serde_json::from_value(value.clone()).context("Malformed component manifest")?;
+        manifest.validate()?;
+
+        let public_key = BASE64
+            .decode(&manifest.publisher.public_key)
+            .context("Publisher public key is not valid base64")?;
+        let signature = BASE64
+            .decode(&manifest.signature)
+            .context("Manifest signature is not valid base64")?;
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(&signing_payload(value)?, &signature)
+            .map_err(|_| anyhow!("Manifest signature does not match the publisher key"))?;
+
+        Ok(manifest)
+    }
+
+    /// Sign the manifest with a PKCS#8-encoded Ed25519 key, recording its public key as the
+    /// publisher key.
+    pub fn sign(&mut self, pkcs8: &[u8]) -> Result<()> {
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8)
+            .map_err(|e| anyhow!("Invalid Ed25519 signing key: {e}"))?;
+        self.publisher.public_key = BASE64.encode(key_pair.public_key().as_ref());
+        let payload = signing_payload(serde_json::to_value(&*self)?)?;
+        self.signature = BASE64.encode(key_pair.sign(&payload).as_ref());
+        Ok(())
+    }
+
+    /// Check the required fields and the embedded policy.
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            bail!("Manifest name can't be empty");
+        }
+        if self.version.trim().is_empty() {
+            bail!("Manifest version can't be empty");
+        }
+        if !self.component.starts_with("oci://") {
+            bail!(
+                "Manifest component must be an oci:// reference, got: {}",
+                self.component
+            );
+        }
+        if let Some(id) = &self.id {
+            check_component_id(id).context("Invalid manifest component id")?;
+        }
+        if self.publisher.name.trim().is_empty() {
+            bail!("Manifest publisher name can't be empty");
+        }
+        if let Some(namespace) = &self.publisher.namespace {
+            let valid = !namespace.is_empty()
+                && namespace.len() <= MAX_NAMESPACE_LEN
+                && namespace
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-');
+            if !valid {
+                bail!(
+                    "Publisher namespace must be 1-{MAX_NAMESPACE_LEN} ASCII letters, digits or '-', got: {namespace}"
+                );
+            }
+        }
+        if let Some(policy) = &self.policy {
+            policy.validate().context("Invalid manifest policy")?;
+        }
+        for (i, dependency) in self.dependencies.iter().enumerate() {
+            if dependency.name.trim().is_empty() {
+                bail!("Manifest dependency names can't be empty");
+            }
+            if self.dependencies[..i]
+                .iter()
+                .any(|other| other.name == dependency.name)
+            {
+                bail!("Manifest dependency {} is listed twice", dependency.name);
+            }
+            if !dependency.component.starts_with("oci://") {
+                bail!(
+                    "Manifest dependency {} must be an oci:// reference, got: {}",
+                    dependency.name,
+                    dependency.component
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the component reference pins a digest rather than a mutable tag.
+    pub fn is_digest_pinned(&self) -> bool {
+        self.component.contains("@sha256:")
+    }
+}
+
+/// Canonical bytes of `manifest` without its signature.
+fn signing_payload(mut manifest: Value) -> Result<Vec<u8>> {
+    manifest
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Manifest must be a JSON object"))?
+        .remove(SIGNATURE_FIELD);
+    let mut out = String::new();
+    write_canonical(&manifest, &mut out)?;
+    Ok(out.into_bytes())
+}
+
+fn write_canonical(value: &Value, out: &mut String) -> Result<()> {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::to_string(key)?);
+                out.push(':');
+                write_canonical(value, out)?;
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out)?;
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&serde_json::to_string(scalar)?),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::rand::SystemRandom;
+
+    use super::*;
+
+    fn signed_manifest() -> Manifest {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let policy = policy::PolicyParser::parse_str(
+            "version: \"1.0\"\npermissions:\n  network:\n    allow:\n      - host: api.example.com\n",
+        )
+        .unwrap();
+        let mut manifest = Manifest {
+            name: "fetch".to_string(),
+            version: "1.0.0".to_string(),
+            component: "oci://ghcr.io/example/fetch:1.0.0".to_string(),
+            id: None,
+            description: Some("Fetch web pages".to_string()),
+            policy: Some(policy),
+            dependencies: vec![Dependency {
+                name: "acme:auth".to_string(),
+                component: "oci://ghcr.io/acme/auth:1.0.0".to_string(),
+            }],
+            publisher: Publisher {
+                name: "Example".to_string(),
+                namespace: Some("example".to_string()),
+                public_key: String::new(),
+            },
+            signature: String::new(),
+        };
+        manifest.sign(pkcs8.as_ref()).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn test_signed_manifest_verifies() {
+        let manifest = signed_manifest();
+        let bytes = serde_json::to_vec_pretty(&manifest).unwrap();
+        assert_eq!(Manifest::parse_and_verify(&bytes).unwrap(), manifest);
+        assert!(!manifest.is_digest_pinned());
+        assert!(!manifest.dependencies[0].is_digest_pinned());
+    }
+
+    #[test]
+    fn test_tampered_manifest_is_rejected() {
+        let manifest = signed_manifest();
+        let mut value = serde_json::to_value(&manifest).unwrap();
+        value["policy"]["permissions"]["network"]["allow"][0]["host"] = "*".into();
+        let bytes = serde_json::to_vec(&value).unwrap();
+        let err = Manifest::parse_and_verify(&bytes).unwrap_err();
+        assert!(err.to_string().contains("signature"));
+    }
+
+    #[test]
+    fn test_manifest_requires_oci_component() {
+        let mut manifest = signed_manifest();
+        manifest.component = "https://example.com/fetch.wasm".to_string();
+        assert!(manifest.validate().is_err());
+
+        let mut manifest = signed_manifest();
+        manifest.publisher.namespace = Some("git_hub".to_string());
+        assert!(manifest.validate().is_err());
+
+        let mut manifest = signed_manifest();
+        manifest.id = Some("../fetch".to_string());
+        assert!(manifest.validate().is_err());
+
+        let mut manifest = signed_manifest();
+        manifest.dependencies[0].component = "https://example.com/auth.wasm".to_string();
+        assert!(manifest.validate().is_err());
+
+        let mut manifest = signed_manifest();
+        manifest.dependencies.push(manifest.dependencies[0].clone());
+        assert!(manifest.validate().is_err());
+    }
+}
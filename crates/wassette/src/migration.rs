@@ -0,0 +1,275 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Versioned layout of the plugin directory and migration of older layouts.
+//!
+//! The plugin directory records the version of its layout in `state.json`. A directory
+//! without one predates versioning and is treated as version 0. On startup every migration
+//! step between the recorded version and [`STATE_VERSION`] runs in order, and the version is
+//! recorded after each step so an interrupted migration resumes where it stopped. Files a step
+//! changes or removes are first copied to `migration-backup/v<from>/`. Policy files, which
+//! hold the permissions granted at runtime, are never removed. A directory written by a newer
+//! release is refused rather than touched.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{ComponentMetadata, METADATA_EXT};
+
+/// Version of the plugin directory layout written by this release.
+pub const STATE_VERSION: u32 = 1;
+
+/// File in the plugin directory recording the version of its layout.
+const STATE_FILE: &str = "state.json";
+
+/// Directory in the plugin directory holding copies of files changed by a migration.
+const BACKUP_DIR: &str = "migration-backup";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StateFile {
+    version: u32,
+}
+
+/// What a migration of the plugin directory did, or would do on a dry run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationReport {
+    /// Version the directory was at
+    pub from: u32,
+    /// Version the directory is at afterwards
+    pub to: u32,
+    /// Changes made, in order
+    pub changes: Vec<String>,
+    /// Whether the changes were only planned
+    pub dry_run: bool,
+}
+
+/// A change to a file of the plugin directory.
+#[derive(Debug)]
+enum Change {
+    /// Remove a file that is rebuilt when its component is loaded
+    Remove { path: PathBuf, reason: &'static str },
+}
+
+impl Change {
+    fn describe(&self) -> String {
+        match self {
+            Change::Remove { path, reason } => format!("remove {}: {reason}", path.display()),
+        }
+    }
+
+    async fn apply(&self, backup_dir: &Path) -> Result<()> {
+        match self {
+            Change::Remove { path, .. } => {
+                backup(path, backup_dir).await?;
+                tokio::fs::remove_file(path)
+                    .await
+                    .with_context(|| format!("Failed to remove {}", path.display()))
+            }
+        }
+    }
+}
+
+/// Bring the plugin directory at `plugin_dir` up to [`STATE_VERSION`]. With `dry_run`, only
+/// report what would change.
+pub async fn migrate_state(plugin_dir: &Path, dry_run: bool) -> Result<MigrationReport> {
+    let from = read_state_version(plugin_dir).await?;
+    if from > STATE_VERSION {
+        bail!(
+            "Plugin directory {} was written by a newer release of wassette (state version {from}, this release supports up to {STATE_VERSION})",
+            plugin_dir.display()
+        );
+    }
+
+    let mut changes = Vec::new();
+    for version in from..STATE_VERSION {
+        let step = plan_step(plugin_dir, version).await?;
+        changes.extend(step.iter().map(Change::describe));
+        changes.push(format!(
+            "record state version {} in {STATE_FILE}",
+            version + 1
+        ));
+        if dry_run {
+            continue;
+        }
+
+        let backup_dir = plugin_dir.join(BACKUP_DIR).join(format!("v{version}"));
+        for change in &step {
+            change.apply(&backup_dir).await?;
+        }
+        write_state_version(plugin_dir, version + 1).await?;
+        info!(
+            plugin_dir = %plugin_dir.display(),
+            version = version + 1,
+            "Migrated plugin directory"
+        );
+    }
+
+    Ok(MigrationReport {
+        from,
+        to: STATE_VERSION,
+        changes,
+        dry_run,
+    })
+}
+
+/// Changes that bring the directory from `version` to the next one.
+async fn plan_step(plugin_dir: &Path, version: u32) -> Result<Vec<Change>> {
+    match version {
+        // Versioning was introduced. Tool index caches that this release can't read would
+        // hide the component's tools until it is compiled again; they are rebuilt from the
+        // component on load.
+        0 => {
+            let mut changes = Vec::new();
+            for path in files_with_suffix(plugin_dir, &format!(".{METADATA_EXT}")).await? {
+                let readable = tokio::fs::read(&path)
+                    .await
+                    .ok()
+                    .and_then(|bytes| serde_json::from_slice::<ComponentMetadata>(&bytes).ok())
+                    .is_some();
+                if !readable {
+                    changes.push(Change::Remove {
+                        path,
+                        reason: "unreadable tool index, rebuilt when the component loads",
+                    });
+                }
+            }
+            Ok(changes)
+        }
+        _ => bail!("No migration from plugin directory state version {version}"),
+    }
+}
+
+async fn read_state_version(plugin_dir: &Path) -> Result<u32> {
+    let path = plugin_dir.join(STATE_FILE);
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => {
+            let state: StateFile = serde_json::from_slice(&bytes)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            Ok(state.version)
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(error) => Err(error).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+async fn write_state_version(plugin_dir: &Path, version: u32) -> Result<()> {
+    tokio::fs::create_dir_all(plugin_dir)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to create plugin directory at {}",
+                plugin_dir.display()
+            )
+        })?;
+    let path = plugin_dir.join(STATE_FILE);
+    let staged = plugin_dir.join(format!("{STATE_FILE}.tmp"));
+    tokio::fs::write(&staged, serde_json::to_vec(&StateFile { version })?)
+        .await
+        .with_context(|| format!("Failed to write {}", staged.display()))?;
+    tokio::fs::rename(&staged, &path)
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Files directly in `dir` whose name ends with `suffix`, sorted by name.
+async fn files_with_suffix(dir: &Path, suffix: &str) -> Result<Vec<PathBuf>> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(error).with_context(|| format!("Failed to read {}", dir.display()))
+        }
+    };
+    let mut files = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let matches = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(suffix));
+        if matches && entry.file_type().await?.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Copy `path` into `backup_dir`, keeping a copy made by an earlier, interrupted attempt.
+async fn backup(path: &Path, backup_dir: &Path) -> Result<()> {
+    let Some(name) = path.file_name() else {
+        return Ok(());
+    };
+    let target = backup_dir.join(name);
+    if target.exists() {
+        return Ok(());
+    }
+    tokio::fs::create_dir_all(backup_dir)
+        .await
+        .with_context(|| format!("Failed to create {}", backup_dir.display()))?;
+    tokio::fs::copy(path, &target)
+        .await
+        .with_context(|| format!("Failed to back up {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unversioned_directory_is_stamped() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+
+        let report = migrate_state(tempdir.path(), false).await?;
+        assert_eq!(report.from, 0);
+        assert_eq!(report.to, STATE_VERSION);
+        assert_eq!(read_state_version(tempdir.path()).await?, STATE_VERSION);
+
+        let again = migrate_state(tempdir.path(), false).await?;
+        assert_eq!(again.from, STATE_VERSION);
+        assert!(again.changes.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_tool_index_is_backed_up_and_removed() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let dir = tempdir.path();
+        let index = dir.join(format!("fetch_rs.{METADATA_EXT}"));
+        let policy = dir.join("fetch_rs.policy.yaml");
+        tokio::fs::write(&index, r#"{"component_id": "fetch_rs"}"#).await?;
+        tokio::fs::write(&policy, "version: \"1.0\"\npermissions: {}\n").await?;
+
+        let planned = migrate_state(dir, true).await?;
+        assert!(planned.dry_run);
+        assert_eq!(planned.changes.len(), 2);
+        assert!(planned.changes[0].contains("fetch_rs.metadata.json"));
+        assert!(index.exists());
+        assert_eq!(read_state_version(dir).await?, 0);
+
+        let report = migrate_state(dir, false).await?;
+        assert_eq!(report.changes, planned.changes);
+        assert!(!index.exists());
+        assert!(policy.exists());
+        assert!(dir
+            .join(BACKUP_DIR)
+            .join("v0")
+            .join(format!("fetch_rs.{METADATA_EXT}"))
+            .exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_newer_state_is_refused() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        write_state_version(tempdir.path(), STATE_VERSION + 1).await?;
+
+        let error = migrate_state(tempdir.path(), false).await.unwrap_err();
+        assert!(error.to_string().contains("newer release"));
+        Ok(())
+    }
+}
@@ -0,0 +1,1721 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Virtual mounts: read-only host archives and files, and in-memory scratch space.
+//!
+//! A policy `mounts` entry makes the contents of a tar or zip archive, or a single host file,
+//! visible at a path inside the component's filesystem without unpacking anything on disk.
+//! Archives are indexed once when the policy is applied, and file contents are read from the
+//! archive on demand. A `tmpfs` mount is an empty, size-capped directory held in memory for the
+//! lifetime of the component instance, so scratch files never reach the host disk.
+//!
+//! WASI descriptors always refer to real files, so each mount root is preopened as an empty
+//! read-only placeholder directory, and every descriptor opened inside a mount is another handle
+//! to that placeholder, mapped to a node of the mounted tree. The `wasi:filesystem` shadows in
+//! [`crate::fs_grants`] route operations on those descriptors here.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::task::{Context as TaskContext, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWrite};
+use wasmtime::component::{Linker, Resource};
+use wasmtime_wasi::p2::bindings::clocks::wall_clock::Datetime;
+use wasmtime_wasi::p2::bindings::filesystem::types::{
+    Descriptor, DescriptorFlags, DescriptorStat, DescriptorType, DirectoryEntry,
+    DirectoryEntryStream, ErrorCode, HostDescriptor, MetadataHashValue, NewTimestamp, OpenFlags,
+    PathFlags,
+};
+use wasmtime_wasi::p2::pipe::{AsyncReadStream, AsyncWriteStream, MemoryInputPipe};
+use wasmtime_wasi::p2::{DynInputStream, DynOutputStream, FsResult};
+use wasmtime_wasi::WasiCtxView;
+
+use crate::fs_grants::{lower, split};
+use crate::wasistate::{expand_home, WasiState};
+use crate::WassetteWasiState;
+
+const TYPES_INTERFACE: &str = "wasi:filesystem/types@0.2.6";
+
+/// Node index of a mount root.
+const ROOT: usize = 0;
+/// Size of a tar header block; entry data is padded to a multiple of it.
+const TAR_BLOCK: u64 = 512;
+/// What creating a file or directory in a tmpfs counts against its size. It isn't given back
+/// when the entry is removed, which bounds the number of entries ever created.
+const TMPFS_ENTRY_COST: u64 = 256;
+/// Largest chunk handed to a tmpfs file by a single stream write.
+const TMPFS_WRITE_BUDGET: usize = 64 * 1024;
+/// Upper bound on the size of GNU long names and pax headers.
+const TAR_MAX_EXTENSION: u64 = 1024 * 1024;
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+const ZIP_LOCAL_HEADER_LEN: u64 = 30;
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP_CENTRAL_HEADER_LEN: usize = 46;
+const ZIP_END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP_END_OF_DIRECTORY_LEN: usize = 22;
+
+#[derive(Debug)]
+enum NodeKind {
+    Directory(BTreeMap<String, usize>),
+    /// `size` bytes at `offset` in source `source`
+    File {
+        source: usize,
+        offset: u64,
+        size: u64,
+    },
+    /// A file created in a tmpfs
+    Memory {
+        data: Vec<u8>,
+        modified: Datetime,
+    },
+}
+
+#[derive(Debug)]
+struct Node {
+    parent: usize,
+    kind: NodeKind,
+}
+
+/// A host file backing mounted files.
+#[derive(Debug)]
+struct Source {
+    path: PathBuf,
+    modified: Option<Datetime>,
+}
+
+/// An entry found in an archive.
+#[derive(Debug, PartialEq, Eq)]
+struct ArchiveEntry {
+    name: String,
+    /// Offset and size of the file contents; `None` for directories
+    file: Option<(u64, u64)>,
+}
+
+/// The files visible below one mount root.
+#[derive(Debug)]
+struct MountTree {
+    nodes: Vec<Node>,
+    sources: Vec<Source>,
+    /// Size cap of a tmpfs; `None` for read-only trees
+    capacity: Option<u64>,
+    /// Bytes of the capacity in use
+    used: u64,
+}
+
+fn now() -> Datetime {
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Datetime {
+        seconds: since.as_secs(),
+        nanoseconds: since.subsec_nanos(),
+    }
+}
+
+impl MountTree {
+    fn new() -> Self {
+        Self {
+            nodes: vec![Node {
+                parent: ROOT,
+                kind: NodeKind::Directory(BTreeMap::new()),
+            }],
+            sources: Vec::new(),
+            capacity: None,
+            used: 0,
+        }
+    }
+
+    fn tmpfs(capacity: u64) -> Self {
+        Self {
+            capacity: Some(capacity),
+            ..Self::new()
+        }
+    }
+
+    /// Register the host file at `path`, returning its index and length.
+    fn add_source(&mut self, path: &Path) -> Result<(usize, u64)> {
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("Failed to read mount source {}", path.display()))?;
+        if !metadata.is_file() {
+            bail!("Mount source {} is not a file", path.display());
+        }
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| Datetime {
+                seconds: since.as_secs(),
+                nanoseconds: since.subsec_nanos(),
+            });
+        self.sources.push(Source {
+            path: path.to_path_buf(),
+            modified,
+        });
+        Ok((self.sources.len() - 1, metadata.len()))
+    }
+
+    fn mount_file(&mut self, path: &Path, name: &str) -> Result<()> {
+        let (source, size) = self.add_source(path)?;
+        self.insert(
+            name,
+            NodeKind::File {
+                source,
+                offset: 0,
+                size,
+            },
+        )
+    }
+
+    fn mount_archive(&mut self, path: &Path) -> Result<()> {
+        let (source, _) = self.add_source(path)?;
+        let entries = archive_entries(path)
+            .with_context(|| format!("Failed to index archive {}", path.display()))?;
+        for entry in entries {
+            let kind = match entry.file {
+                Some((offset, size)) => NodeKind::File {
+                    source,
+                    offset,
+                    size,
+                },
+                None => NodeKind::Directory(BTreeMap::new()),
+            };
+            self.insert(&entry.name, kind)?;
+        }
+        Ok(())
+    }
+
+    /// Add `kind` at the relative `path`, creating missing parent directories. A file replaces
+    /// an earlier file at the same path, as when the archive is unpacked.
+    fn insert(&mut self, path: &str, kind: NodeKind) -> Result<()> {
+        let names: Vec<&str> = path
+            .split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+            .collect();
+        if names.contains(&"..") {
+            bail!("Mounted path '{path}' points outside of the mount");
+        }
+        let Some((name, parents)) = names.split_last() else {
+            return match kind {
+                NodeKind::Directory(_) => Ok(()),
+                NodeKind::File { .. } => bail!("Mounted file '{path}' has no name"),
+                NodeKind::Memory { .. } => bail!("Mounted file '{path}' has no name"),
+            };
+        };
+        let mut dir = ROOT;
+        for parent in parents {
+            dir = self.child_dir(dir, parent, path)?;
+        }
+        match (kind, self.child(dir, name)) {
+            (NodeKind::Directory(_), _) => {
+                self.child_dir(dir, name, path)?;
+            }
+            (file, None) => {
+                self.push(dir, name, file);
+            }
+            (file, Some(existing)) => {
+                if matches!(self.nodes[existing].kind, NodeKind::Directory(_)) {
+                    bail!("'{path}' is mounted both as a file and as a directory");
+                }
+                self.nodes[existing].kind = file;
+            }
+        }
+        Ok(())
+    }
+
+    fn child(&self, dir: usize, name: &str) -> Option<usize> {
+        match &self.nodes[dir].kind {
+            NodeKind::Directory(children) => children.get(name).copied(),
+            _ => None,
+        }
+    }
+
+    fn child_dir(&mut self, dir: usize, name: &str, path: &str) -> Result<usize> {
+        match self.child(dir, name) {
+            Some(node) if self.is_directory(node) => Ok(node),
+            Some(_) => bail!("'{path}' is mounted both as a file and as a directory"),
+            None => Ok(self.push(dir, name, NodeKind::Directory(BTreeMap::new()))),
+        }
+    }
+
+    fn push(&mut self, dir: usize, name: &str, kind: NodeKind) -> usize {
+        let node = self.nodes.len();
+        self.nodes.push(Node { parent: dir, kind });
+        if let NodeKind::Directory(children) = &mut self.nodes[dir].kind {
+            children.insert(name.to_string(), node);
+        }
+        node
+    }
+
+    fn is_directory(&self, node: usize) -> bool {
+        matches!(self.nodes[node].kind, NodeKind::Directory(_))
+    }
+
+    /// The node `path` leads to from `base`. Like a preopen, a mount can't be left with `..`.
+    fn lookup(&self, base: usize, path: &str) -> Result<usize, ErrorCode> {
+        let mut node = base;
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => {
+                    let NodeKind::Directory(children) = &self.nodes[node].kind else {
+                        return Err(ErrorCode::NotDirectory);
+                    };
+                    node = name
+                        .to_str()
+                        .and_then(|name| children.get(name))
+                        .copied()
+                        .ok_or(ErrorCode::NoEntry)?;
+                }
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if node == ROOT {
+                        return Err(ErrorCode::NotPermitted);
+                    }
+                    node = self.nodes[node].parent;
+                }
+                Component::RootDir | Component::Prefix(_) => return Err(ErrorCode::NotPermitted),
+            }
+        }
+        Ok(node)
+    }
+
+    fn stat(&self, node: usize) -> DescriptorStat {
+        let (type_, size, modified) = match &self.nodes[node].kind {
+            NodeKind::Directory(_) => (DescriptorType::Directory, 0, None),
+            NodeKind::File { source, size, .. } => (
+                DescriptorType::RegularFile,
+                *size,
+                self.sources[*source].modified,
+            ),
+            NodeKind::Memory { data, modified } => (
+                DescriptorType::RegularFile,
+                data.len() as u64,
+                Some(*modified),
+            ),
+        };
+        DescriptorStat {
+            type_,
+            link_count: 1,
+            size,
+            data_access_timestamp: modified,
+            data_modification_timestamp: modified,
+            status_change_timestamp: modified,
+        }
+    }
+
+    fn entries(&self, node: usize) -> Result<VecDeque<DirectoryEntry>, ErrorCode> {
+        let NodeKind::Directory(children) = &self.nodes[node].kind else {
+            return Err(ErrorCode::NotDirectory);
+        };
+        Ok(children
+            .iter()
+            .map(|(name, &child)| DirectoryEntry {
+                type_: self.stat(child).type_,
+                name: name.clone(),
+            })
+            .collect())
+    }
+
+    /// Where the contents of the file at `node` live.
+    fn contents(&self, node: usize) -> Result<Contents<'_>, ErrorCode> {
+        match &self.nodes[node].kind {
+            NodeKind::File {
+                source,
+                offset,
+                size,
+            } => Ok(Contents::Host {
+                path: &self.sources[*source].path,
+                offset: *offset,
+                size: *size,
+            }),
+            NodeKind::Memory { data, .. } => Ok(Contents::Memory(data)),
+            NodeKind::Directory(_) => Err(ErrorCode::IsDirectory),
+        }
+    }
+
+    /// Fail unless the tree is a tmpfs.
+    fn check_writable(&self) -> Result<(), ErrorCode> {
+        match self.capacity {
+            Some(_) => Ok(()),
+            None => Err(ErrorCode::ReadOnly),
+        }
+    }
+
+    /// Count `bytes` more against the tmpfs size.
+    fn charge(&mut self, bytes: u64) -> Result<(), ErrorCode> {
+        let capacity = self.capacity.ok_or(ErrorCode::ReadOnly)?;
+        match self.used.checked_add(bytes) {
+            Some(used) if used <= capacity => {
+                self.used = used;
+                Ok(())
+            }
+            _ => Err(ErrorCode::InsufficientSpace),
+        }
+    }
+
+    /// Resize the tmpfs file at `node` to `len` bytes and then run `write` on its contents.
+    fn modify(
+        &mut self,
+        node: usize,
+        len: impl FnOnce(u64) -> Option<u64>,
+        write: impl FnOnce(&mut Vec<u8>, u64),
+    ) -> Result<(), ErrorCode> {
+        self.check_writable()?;
+        let current = match &self.nodes[node].kind {
+            NodeKind::Memory { data, .. } => data.len() as u64,
+            NodeKind::Directory(_) => return Err(ErrorCode::IsDirectory),
+            NodeKind::File { .. } => return Err(ErrorCode::ReadOnly),
+        };
+        let len = len(current).ok_or(ErrorCode::FileTooLarge)?;
+        let new_len = usize::try_from(len).map_err(|_| ErrorCode::FileTooLarge)?;
+        if len > current {
+            self.charge(len - current)?;
+        } else {
+            self.used -= current - len;
+        }
+        if let NodeKind::Memory { data, modified } = &mut self.nodes[node].kind {
+            data.resize(new_len, 0);
+            write(data, current);
+            *modified = now();
+        }
+        Ok(())
+    }
+
+    /// Write `buf` at `offset` of the tmpfs file at `node`, or at its end.
+    fn write(&mut self, node: usize, offset: Option<u64>, buf: &[u8]) -> Result<u64, ErrorCode> {
+        self.modify(
+            node,
+            |current| {
+                let end = offset.unwrap_or(current).checked_add(buf.len() as u64)?;
+                Some(end.max(current))
+            },
+            |data, current| {
+                let start = offset.unwrap_or(current) as usize;
+                data[start..start + buf.len()].copy_from_slice(buf);
+            },
+        )?;
+        Ok(buf.len() as u64)
+    }
+
+    /// The parent directory and final name of `path` below `base`.
+    fn split_parent<'a>(&self, base: usize, path: &'a str) -> Result<(usize, &'a str), ErrorCode> {
+        let path = Path::new(path);
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(ErrorCode::Invalid)?;
+        let parent = path.parent().and_then(Path::to_str).unwrap_or("");
+        let parent = self.lookup(base, parent)?;
+        if !self.is_directory(parent) {
+            return Err(ErrorCode::NotDirectory);
+        }
+        Ok((parent, name))
+    }
+
+    /// Create `kind` at `path` below `base` in a tmpfs.
+    fn create(&mut self, base: usize, path: &str, kind: NodeKind) -> Result<usize, ErrorCode> {
+        self.check_writable()?;
+        let (parent, name) = self.split_parent(base, path)?;
+        if self.child(parent, name).is_some() {
+            return Err(ErrorCode::Exist);
+        }
+        self.charge(TMPFS_ENTRY_COST)?;
+        Ok(self.push(parent, name, kind))
+    }
+
+    /// Unlink `name` from `parent`, giving back the memory of a file. Descriptors still open
+    /// on a removed file see it empty.
+    fn detach(&mut self, parent: usize, name: &str) {
+        let removed = match &mut self.nodes[parent].kind {
+            NodeKind::Directory(children) => children.remove(name),
+            _ => None,
+        };
+        if let Some(node) = removed {
+            if let NodeKind::Memory { data, .. } = &mut self.nodes[node].kind {
+                self.used -= data.len() as u64;
+                *data = Vec::new();
+            }
+        }
+    }
+
+    /// Remove the file, or the empty directory when `directory` is set, at `path` below `base`.
+    fn remove(&mut self, base: usize, path: &str, directory: bool) -> Result<(), ErrorCode> {
+        self.check_writable()?;
+        let (parent, name) = self.split_parent(base, path)?;
+        let node = self.child(parent, name).ok_or(ErrorCode::NoEntry)?;
+        match (&self.nodes[node].kind, directory) {
+            (NodeKind::Directory(children), true) if !children.is_empty() => {
+                return Err(ErrorCode::NotEmpty);
+            }
+            (NodeKind::Directory(_), false) => return Err(ErrorCode::IsDirectory),
+            (NodeKind::Directory(_), true) | (_, false) => {}
+            (_, true) => return Err(ErrorCode::NotDirectory),
+        }
+        self.detach(parent, name);
+        Ok(())
+    }
+
+    /// Move `from` below `from_base` to `to` below `to_base`, replacing a file or an empty
+    /// directory already there.
+    fn rename(
+        &mut self,
+        from_base: usize,
+        from: &str,
+        to_base: usize,
+        to: &str,
+    ) -> Result<(), ErrorCode> {
+        self.check_writable()?;
+        let (old_parent, old_name) = self.split_parent(from_base, from)?;
+        let node = self.child(old_parent, old_name).ok_or(ErrorCode::NoEntry)?;
+        let (new_parent, new_name) = self.split_parent(to_base, to)?;
+        // A directory can't be moved below itself.
+        let mut ancestor = new_parent;
+        loop {
+            if ancestor == node {
+                return Err(ErrorCode::Invalid);
+            }
+            if ancestor == ROOT {
+                break;
+            }
+            ancestor = self.nodes[ancestor].parent;
+        }
+        if let Some(existing) = self.child(new_parent, new_name) {
+            if existing == node {
+                return Ok(());
+            }
+            match (self.is_directory(node), &self.nodes[existing].kind) {
+                (true, NodeKind::Directory(children)) if !children.is_empty() => {
+                    return Err(ErrorCode::NotEmpty);
+                }
+                (true, NodeKind::Directory(_)) | (false, NodeKind::Memory { .. }) => {}
+                (true, _) => return Err(ErrorCode::NotDirectory),
+                (false, _) => return Err(ErrorCode::IsDirectory),
+            }
+            self.detach(new_parent, new_name);
+        }
+        if let NodeKind::Directory(children) = &mut self.nodes[old_parent].kind {
+            children.remove(old_name);
+        }
+        if let NodeKind::Directory(children) = &mut self.nodes[new_parent].kind {
+            children.insert(new_name.to_string(), node);
+        }
+        self.nodes[node].parent = new_parent;
+        Ok(())
+    }
+
+    fn set_times(&mut self, node: usize, mtim: NewTimestamp) -> Result<(), ErrorCode> {
+        self.check_writable()?;
+        if let NodeKind::Memory { modified, .. } = &mut self.nodes[node].kind {
+            match mtim {
+                NewTimestamp::NoChange => {}
+                NewTimestamp::Now => *modified = now(),
+                NewTimestamp::Timestamp(time) => *modified = time,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the contents of a mounted file live.
+enum Contents<'a> {
+    Host {
+        path: &'a Path,
+        offset: u64,
+        size: u64,
+    },
+    Memory(&'a [u8]),
+}
+
+/// Index the entries of the tar or zip archive at `path`.
+fn archive_entries(path: &Path) -> Result<Vec<ArchiveEntry>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut magic = [0u8; 2];
+    if len >= 2 {
+        file.read_exact(&mut magic)?;
+    }
+    if &magic == b"PK" {
+        zip_entries(&mut file, len)
+    } else {
+        tar_entries(&mut file, len)
+    }
+}
+
+fn read_at(file: &mut File, offset: u64, len: u64) -> Result<Vec<u8>> {
+    let mut buffer = vec![0; usize::try_from(len)?];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Index an uncompressed ustar, GNU or pax tar archive. Links and special files are skipped.
+fn tar_entries(file: &mut File, len: u64) -> Result<Vec<ArchiveEntry>> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    let mut long_name = None;
+    let mut pax_size = None;
+    while offset + TAR_BLOCK <= len {
+        let header = read_at(file, offset, TAR_BLOCK)?;
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        if !tar_checksum_matches(&header) {
+            bail!("Not a tar or zip archive");
+        }
+        let entry_type = header[156];
+        let mut size = tar_number(&header[124..136])?;
+        if !matches!(entry_type, b'L' | b'x' | b'g') {
+            size = pax_size.take().unwrap_or(size);
+        }
+        let data = offset + TAR_BLOCK;
+        if data + size > len {
+            bail!("Tar archive is truncated");
+        }
+        offset = data + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+
+        match entry_type {
+            b'L' | b'x' if size > TAR_MAX_EXTENSION => bail!("Tar extension header is too large"),
+            b'L' => {
+                long_name = Some(nul_terminated(&read_at(file, data, size)?));
+                continue;
+            }
+            b'x' => {
+                let records = read_at(file, data, size)?;
+                pax_records(&records, &mut long_name, &mut pax_size)?;
+                continue;
+            }
+            _ => {}
+        }
+        let name = long_name.take().unwrap_or_else(|| tar_name(&header));
+        match entry_type {
+            0 | b'0' | b'7' => entries.push(ArchiveEntry {
+                name,
+                file: Some((data, size)),
+            }),
+            b'5' => entries.push(ArchiveEntry { name, file: None }),
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+fn tar_checksum_matches(header: &[u8]) -> bool {
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| {
+            if (148..156).contains(&i) {
+                u64::from(b' ')
+            } else {
+                u64::from(byte)
+            }
+        })
+        .sum();
+    tar_number(&header[148..156]).is_ok_and(|expected| expected == sum)
+}
+
+/// Parse an octal header field, or a GNU base-256 one for values that don't fit.
+fn tar_number(field: &[u8]) -> Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold(u64::from(field[0] & 0x7f), |n, &byte| {
+                (n << 8) | u64::from(byte)
+            }));
+    }
+    let digits = std::str::from_utf8(field)?.trim_matches(|c| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).context("Invalid number in tar header")
+}
+
+fn tar_name(header: &[u8]) -> String {
+    let name = nul_terminated(&header[..100]);
+    let prefix = nul_terminated(&header[345..500]);
+    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+        format!("{prefix}/{name}")
+    } else {
+        name
+    }
+}
+
+fn nul_terminated(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Apply the `path` and `size` records of a pax extended header to the next entry.
+fn pax_records(data: &[u8], name: &mut Option<String>, size: &mut Option<u64>) -> Result<()> {
+    let mut rest = data;
+    while !rest.iter().all(|&byte| byte == 0) {
+        let space = rest
+            .iter()
+            .position(|&byte| byte == b' ')
+            .context("Malformed pax header")?;
+        let len: usize = std::str::from_utf8(&rest[..space])?.parse()?;
+        if len <= space || len > rest.len() {
+            bail!("Malformed pax header");
+        }
+        let record = &rest[space + 1..len];
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(value) = record.strip_prefix(b"path=") {
+            *name = Some(String::from_utf8_lossy(value).into_owned());
+        } else if let Some(value) = record.strip_prefix(b"size=") {
+            *size = Some(std::str::from_utf8(value)?.parse()?);
+        }
+        rest = &rest[len..];
+    }
+    Ok(())
+}
+
+fn le16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Index a zip archive. Only stored entries can be mounted, since compressed ones would have
+/// to be inflated.
+fn zip_entries(file: &mut File, len: u64) -> Result<Vec<ArchiveEntry>> {
+    let max_tail = (ZIP_END_OF_DIRECTORY_LEN + usize::from(u16::MAX)) as u64;
+    let tail_len = len.min(max_tail);
+    let tail = read_at(file, len - tail_len, tail_len)?;
+    let end = (0..=tail.len().saturating_sub(ZIP_END_OF_DIRECTORY_LEN))
+        .rev()
+        .find(|&i| {
+            tail.len() - i >= ZIP_END_OF_DIRECTORY_LEN && le32(&tail[i..]) == ZIP_END_OF_DIRECTORY
+        })
+        .context("Not a zip archive: end of central directory not found")?;
+    let record = &tail[end..];
+    let count = le16(&record[10..]);
+    let directory_size = le32(&record[12..]);
+    let directory_offset = le32(&record[16..]);
+    if count == u16::MAX || directory_offset == u32::MAX {
+        bail!("Zip64 archives are not supported");
+    }
+    let directory = read_at(file, directory_offset.into(), directory_size.into())?;
+
+    let mut entries = Vec::with_capacity(count.into());
+    let mut pos = 0;
+    for _ in 0..count {
+        let header = directory
+            .get(pos..pos + ZIP_CENTRAL_HEADER_LEN)
+            .filter(|header| le32(header) == ZIP_CENTRAL_HEADER)
+            .context("Corrupt zip central directory")?;
+        let flags = le16(&header[8..]);
+        let method = le16(&header[10..]);
+        let size = le32(&header[24..]);
+        let name_len = usize::from(le16(&header[28..]));
+        let extra_len = usize::from(le16(&header[30..]));
+        let comment_len = usize::from(le16(&header[32..]));
+        let local_offset = le32(&header[42..]);
+        let name_start = pos + ZIP_CENTRAL_HEADER_LEN;
+        let name = directory
+            .get(name_start..name_start + name_len)
+            .context("Corrupt zip central directory")?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        pos = name_start + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') {
+            entries.push(ArchiveEntry { name, file: None });
+            continue;
+        }
+        if flags & 1 != 0 {
+            bail!("Zip entry '{name}' is encrypted");
+        }
+        if method != 0 {
+            bail!("Zip entry '{name}' is compressed; only stored (uncompressed) entries can be mounted");
+        }
+        if size == u32::MAX || local_offset == u32::MAX {
+            bail!("Zip64 archives are not supported");
+        }
+        let local = read_at(file, local_offset.into(), ZIP_LOCAL_HEADER_LEN)?;
+        if le32(&local) != ZIP_LOCAL_HEADER {
+            bail!("Corrupt zip entry '{name}'");
+        }
+        let data = u64::from(local_offset)
+            + ZIP_LOCAL_HEADER_LEN
+            + u64::from(le16(&local[26..]))
+            + u64::from(le16(&local[28..]));
+        if data + u64::from(size) > len {
+            bail!("Zip entry '{name}' is truncated");
+        }
+        entries.push(ArchiveEntry {
+            name,
+            file: Some((data, size.into())),
+        });
+    }
+    Ok(entries)
+}
+
+/// Archive or file contents mounted read-only, or a tmpfs, at a path inside the component.
+#[derive(Debug, Clone)]
+pub struct VirtualMount {
+    guest_path: String,
+    tree: Arc<Mutex<MountTree>>,
+}
+
+impl VirtualMount {
+    fn new(guest_path: String, tree: MountTree) -> Self {
+        Self {
+            guest_path,
+            tree: Arc::new(Mutex::new(tree)),
+        }
+    }
+
+    /// The path the mount is preopened at.
+    pub fn guest_path(&self) -> &str {
+        &self.guest_path
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MountTree> {
+        self.tree.lock().unwrap()
+    }
+
+    /// The mount as a new store sees it: every instance gets its own empty tmpfs, while
+    /// read-only trees are shared.
+    fn for_store(&self) -> Self {
+        let capacity = self.lock().capacity;
+        match capacity {
+            Some(capacity) => Self::new(self.guest_path.clone(), MountTree::tmpfs(capacity)),
+            None => self.clone(),
+        }
+    }
+}
+
+/// Build the mounts a policy asks for. Relative sources are resolved against `base_dir`.
+///
+/// An archive or tmpfs is mounted at its `at` path, a file in the directory containing its
+/// `at` path. Read-only mounts sharing that directory are merged into one tree; a tmpfs can't
+/// share its directory.
+pub(crate) fn from_policy(mounts: &[policy::Mount], base_dir: &Path) -> Result<Vec<VirtualMount>> {
+    let mut trees: BTreeMap<String, MountTree> = BTreeMap::new();
+    for mount in mounts {
+        let at: Vec<&str> = mount
+            .at
+            .split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+            .collect();
+        let source = |source: &str| base_dir.join(expand_home(source));
+        match (&mount.archive, &mount.file, &mount.tmpfs) {
+            (Some(archive), None, None) => {
+                let root = format!("/{}", at.join("/"));
+                read_only_tree(&mut trees, root, &mount.at)?.mount_archive(&source(archive))?;
+            }
+            (None, Some(file), None) => {
+                let Some((name, parents)) = at.split_last() else {
+                    bail!("A file can't be mounted at /");
+                };
+                let root = format!("/{}", parents.join("/"));
+                read_only_tree(&mut trees, root, &mount.at)?.mount_file(&source(file), name)?;
+            }
+            (None, None, Some(size)) => {
+                let root = format!("/{}", at.join("/"));
+                if trees.contains_key(&root) {
+                    bail!("A tmpfs can't share {} with another mount", mount.at);
+                }
+                let capacity = size
+                    .to_bytes()
+                    .with_context(|| format!("Invalid size of the tmpfs at {}", mount.at))?;
+                trees.insert(root, MountTree::tmpfs(capacity));
+            }
+            _ => bail!(
+                "Mount at {} needs exactly one of an archive, a file or a tmpfs",
+                mount.at
+            ),
+        }
+    }
+    Ok(trees
+        .into_iter()
+        .map(|(guest_path, tree)| VirtualMount::new(guest_path, tree))
+        .collect())
+}
+
+/// The read-only tree mounted at `root`, failing if a tmpfs is mounted there.
+fn read_only_tree<'a>(
+    trees: &'a mut BTreeMap<String, MountTree>,
+    root: String,
+    at: &str,
+) -> Result<&'a mut MountTree> {
+    let tree = trees.entry(root).or_insert_with(MountTree::new);
+    if tree.capacity.is_some() {
+        bail!("A tmpfs can't share {at} with another mount");
+    }
+    Ok(tree)
+}
+
+/// An empty directory standing in for mount roots and the descriptors opened inside them.
+pub(crate) fn placeholder_dir() -> Result<&'static Path> {
+    static PLACEHOLDER: OnceLock<TempDir> = OnceLock::new();
+    if let Some(dir) = PLACEHOLDER.get() {
+        return Ok(dir.path());
+    }
+    let dir = tempfile::Builder::new()
+        .prefix("wassette-mount-")
+        .tempdir()
+        .context("Failed to create the mount placeholder directory")?;
+    Ok(PLACEHOLDER.get_or_init(|| dir).path())
+}
+
+/// A node in one of a store's mounts, and the flags the descriptor was opened with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Handle {
+    mount: usize,
+    node: usize,
+    flags: DescriptorFlags,
+}
+
+/// Per-store tracking of the descriptors and directory streams inside mounts.
+#[derive(Debug, Default)]
+pub struct VirtualFs {
+    mounts: Vec<VirtualMount>,
+    descriptors: HashMap<u32, Handle>,
+    streams: HashMap<u32, VecDeque<DirectoryEntry>>,
+}
+
+impl VirtualFs {
+    pub(crate) fn new(mounts: &[VirtualMount]) -> Self {
+        Self {
+            mounts: mounts.iter().map(VirtualMount::for_store).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn track_preopen(&mut self, rep: u32, guest_path: &str) {
+        match self
+            .mounts
+            .iter()
+            .position(|mount| mount.guest_path == guest_path)
+        {
+            Some(mount) => {
+                let mut flags = DescriptorFlags::READ;
+                if self.mounts[mount].lock().check_writable().is_ok() {
+                    flags |= DescriptorFlags::MUTATE_DIRECTORY;
+                }
+                self.descriptors.insert(
+                    rep,
+                    Handle {
+                        mount,
+                        node: ROOT,
+                        flags,
+                    },
+                );
+            }
+            None => {
+                self.descriptors.remove(&rep);
+            }
+        }
+    }
+
+    /// Forget a mounted descriptor that previously had the handle `rep`.
+    pub(crate) fn forget(&mut self, rep: u32) {
+        self.descriptors.remove(&rep);
+    }
+
+    /// Forget a mounted directory stream that previously had the handle `rep`.
+    pub(crate) fn forget_stream(&mut self, rep: u32) {
+        self.streams.remove(&rep);
+    }
+
+    /// The node descriptor `rep` refers to, or `None` outside mounts.
+    pub(crate) fn handle(&self, rep: u32) -> Option<Handle> {
+        self.descriptors.get(&rep).copied()
+    }
+
+    fn tree(&self, handle: Handle) -> MutexGuard<'_, MountTree> {
+        self.mounts[handle.mount].lock()
+    }
+
+    /// The tree of `handle`, if entries may be created and removed through it.
+    fn mutate(&self, handle: Handle) -> Result<MutexGuard<'_, MountTree>, ErrorCode> {
+        let tree = self.tree(handle);
+        tree.check_writable()?;
+        if !handle.flags.contains(DescriptorFlags::MUTATE_DIRECTORY) {
+            return Err(ErrorCode::NotPermitted);
+        }
+        Ok(tree)
+    }
+
+    /// Fail unless `handle` was opened for writing.
+    fn check_write(handle: Handle) -> Result<(), ErrorCode> {
+        if !handle.flags.contains(DescriptorFlags::WRITE) {
+            return Err(ErrorCode::NotPermitted);
+        }
+        Ok(())
+    }
+
+    /// Open `path` below the mounted directory `fd`.
+    pub(crate) async fn open_at(
+        &mut self,
+        view: &mut WasiCtxView<'_>,
+        handle: Handle,
+        fd: Resource<Descriptor>,
+        path: &str,
+        oflags: OpenFlags,
+        flags: DescriptorFlags,
+    ) -> FsResult<Resource<Descriptor>> {
+        let node = {
+            let modifies = oflags.intersects(OpenFlags::CREATE | OpenFlags::TRUNCATE)
+                || flags.intersects(DescriptorFlags::WRITE | DescriptorFlags::MUTATE_DIRECTORY);
+            let mut tree = if modifies {
+                self.mutate(handle)?
+            } else {
+                self.tree(handle)
+            };
+            let node = match tree.lookup(handle.node, path) {
+                Ok(_) if oflags.contains(OpenFlags::CREATE | OpenFlags::EXCLUSIVE) => {
+                    return Err(ErrorCode::Exist.into());
+                }
+                Ok(node) => node,
+                Err(ErrorCode::NoEntry) if oflags.contains(OpenFlags::CREATE) => {
+                    if oflags.contains(OpenFlags::DIRECTORY) {
+                        return Err(ErrorCode::Invalid.into());
+                    }
+                    let file = NodeKind::Memory {
+                        data: Vec::new(),
+                        modified: now(),
+                    };
+                    tree.create(handle.node, path, file)?
+                }
+                Err(code) => return Err(code.into()),
+            };
+            if tree.is_directory(node) {
+                if flags.contains(DescriptorFlags::WRITE) {
+                    return Err(ErrorCode::IsDirectory.into());
+                }
+            } else if oflags.contains(OpenFlags::DIRECTORY) {
+                return Err(ErrorCode::NotDirectory.into());
+            }
+            if oflags.contains(OpenFlags::TRUNCATE) {
+                tree.modify(node, |_| Some(0), |_, _| {})?;
+            }
+            node
+        };
+        let opened = HostDescriptor::open_at(
+            view,
+            fd,
+            PathFlags::empty(),
+            ".".to_string(),
+            OpenFlags::DIRECTORY,
+            DescriptorFlags::READ,
+        )
+        .await?;
+        self.descriptors.insert(
+            opened.rep(),
+            Handle {
+                mount: handle.mount,
+                node,
+                flags,
+            },
+        );
+        Ok(opened)
+    }
+
+    pub(crate) fn stat(&self, handle: Handle) -> FsResult<DescriptorStat> {
+        Ok(self.tree(handle).stat(handle.node))
+    }
+
+    pub(crate) fn stat_at(&self, handle: Handle, path: &str) -> FsResult<DescriptorStat> {
+        let tree = self.tree(handle);
+        Ok(tree.stat(tree.lookup(handle.node, path)?))
+    }
+
+    fn hash(&self, handle: Handle, node: usize) -> MetadataHashValue {
+        MetadataHashValue {
+            upper: Arc::as_ptr(&self.mounts[handle.mount].tree) as u64,
+            lower: node as u64,
+        }
+    }
+
+    pub(crate) fn metadata_hash(&self, handle: Handle) -> FsResult<MetadataHashValue> {
+        Ok(self.hash(handle, handle.node))
+    }
+
+    pub(crate) fn metadata_hash_at(
+        &self,
+        handle: Handle,
+        path: &str,
+    ) -> FsResult<MetadataHashValue> {
+        let node = self.tree(handle).lookup(handle.node, path)?;
+        Ok(self.hash(handle, node))
+    }
+
+    /// Mounts contain no symlinks.
+    pub(crate) fn readlink_at(&self, handle: Handle, path: &str) -> FsResult<String> {
+        self.tree(handle).lookup(handle.node, path)?;
+        Err(ErrorCode::Invalid.into())
+    }
+
+    pub(crate) async fn read(
+        &self,
+        handle: Handle,
+        len: u64,
+        offset: u64,
+    ) -> FsResult<(Vec<u8>, bool)> {
+        let (path, start, size) = match self.tree(handle).contents(handle.node)? {
+            Contents::Host {
+                path,
+                offset: start,
+                size,
+            } => (path.to_path_buf(), start, size),
+            Contents::Memory(data) => {
+                let Some(rest) = usize::try_from(offset).ok().and_then(|at| data.get(at..)) else {
+                    return Ok((Vec::new(), true));
+                };
+                if rest.is_empty() {
+                    return Ok((Vec::new(), true));
+                }
+                let len = usize::try_from(len).unwrap_or(usize::MAX).min(rest.len());
+                return Ok((rest[..len].to_vec(), false));
+            }
+        };
+        if offset >= size {
+            return Ok((Vec::new(), true));
+        }
+        let mut file = tokio::fs::File::open(path).await?;
+        file.seek(SeekFrom::Start(start + offset)).await?;
+        let mut buffer = Vec::new();
+        file.take(len.min(size - offset))
+            .read_to_end(&mut buffer)
+            .await?;
+        Ok((buffer, false))
+    }
+
+    pub(crate) fn read_via_stream(
+        &self,
+        view: &mut WasiCtxView<'_>,
+        handle: Handle,
+        offset: u64,
+    ) -> FsResult<Resource<DynInputStream>> {
+        let stream: DynInputStream = match self.tree(handle).contents(handle.node)? {
+            Contents::Host {
+                path,
+                offset: start,
+                size,
+            } => {
+                let offset = offset.min(size);
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(start + offset))?;
+                let reader = tokio::fs::File::from_std(file).take(size - offset);
+                Box::new(AsyncReadStream::new(reader))
+            }
+            Contents::Memory(data) => {
+                let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+                let rest = data.get(offset..).unwrap_or_default();
+                Box::new(MemoryInputPipe::new(rest.to_vec()))
+            }
+        };
+        Ok(view.table.push(stream)?)
+    }
+
+    pub(crate) fn write(&self, handle: Handle, buf: &[u8], offset: u64) -> FsResult<u64> {
+        Self::check_write(handle)?;
+        Ok(self.tree(handle).write(handle.node, Some(offset), buf)?)
+    }
+
+    /// A stream writing to the tmpfs file of `handle` from `offset`, or appending when `None`.
+    pub(crate) fn write_via_stream(
+        &self,
+        view: &mut WasiCtxView<'_>,
+        handle: Handle,
+        offset: Option<u64>,
+    ) -> FsResult<Resource<DynOutputStream>> {
+        Self::check_write(handle)?;
+        if self.tree(handle).is_directory(handle.node) {
+            return Err(ErrorCode::IsDirectory.into());
+        }
+        let writer = TmpfsWriter {
+            tree: Arc::clone(&self.mounts[handle.mount].tree),
+            node: handle.node,
+            position: offset,
+        };
+        let stream: DynOutputStream = Box::new(AsyncWriteStream::new(TMPFS_WRITE_BUDGET, writer));
+        Ok(view.table.push(stream)?)
+    }
+
+    pub(crate) fn set_size(&self, handle: Handle, size: u64) -> FsResult<()> {
+        Self::check_write(handle)?;
+        Ok(self
+            .tree(handle)
+            .modify(handle.node, |_| Some(size), |_, _| {})?)
+    }
+
+    pub(crate) fn set_times(&self, handle: Handle, mtim: NewTimestamp) -> FsResult<()> {
+        Ok(self.tree(handle).set_times(handle.node, mtim)?)
+    }
+
+    pub(crate) fn set_times_at(
+        &self,
+        handle: Handle,
+        path: &str,
+        mtim: NewTimestamp,
+    ) -> FsResult<()> {
+        let mut tree = self.mutate(handle)?;
+        let node = tree.lookup(handle.node, path)?;
+        Ok(tree.set_times(node, mtim)?)
+    }
+
+    pub(crate) fn create_directory_at(&self, handle: Handle, path: &str) -> FsResult<()> {
+        let directory = NodeKind::Directory(BTreeMap::new());
+        self.mutate(handle)?.create(handle.node, path, directory)?;
+        Ok(())
+    }
+
+    pub(crate) fn remove_directory_at(&self, handle: Handle, path: &str) -> FsResult<()> {
+        Ok(self.mutate(handle)?.remove(handle.node, path, true)?)
+    }
+
+    pub(crate) fn unlink_file_at(&self, handle: Handle, path: &str) -> FsResult<()> {
+        Ok(self.mutate(handle)?.remove(handle.node, path, false)?)
+    }
+
+    /// Rename within a mount, or `None` if neither descriptor is mounted. Renames between a
+    /// mount and anything else fail as crossing devices.
+    pub(crate) fn rename_at(
+        &self,
+        fd: u32,
+        old_path: &str,
+        new_fd: u32,
+        new_path: &str,
+    ) -> Option<FsResult<()>> {
+        let result = match (self.handle(fd), self.handle(new_fd)) {
+            (None, None) => return None,
+            (Some(from), Some(to)) if from.mount == to.mount => {
+                self.mutate(from).and_then(|mut tree| {
+                    if !to.flags.contains(DescriptorFlags::MUTATE_DIRECTORY) {
+                        return Err(ErrorCode::NotPermitted);
+                    }
+                    tree.rename(from.node, old_path, to.node, new_path)
+                })
+            }
+            _ => Err(ErrorCode::CrossDevice),
+        };
+        Some(result.map_err(Into::into))
+    }
+
+    /// List the mounted directory `fd`. The returned stream is the placeholder's own, which is
+    /// empty; its entries are served by [`VirtualFs::read_directory_entry`].
+    pub(crate) async fn read_directory(
+        &mut self,
+        view: &mut WasiCtxView<'_>,
+        handle: Handle,
+        fd: Resource<Descriptor>,
+    ) -> FsResult<Resource<DirectoryEntryStream>> {
+        let entries = self.tree(handle).entries(handle.node)?;
+        let stream = HostDescriptor::read_directory(view, fd).await?;
+        self.streams.insert(stream.rep(), entries);
+        Ok(stream)
+    }
+
+    /// The next entry of a mounted directory stream, or `None` for other streams.
+    pub(crate) fn read_directory_entry(&mut self, stream: u32) -> Option<Option<DirectoryEntry>> {
+        self.streams.get_mut(&stream).map(VecDeque::pop_front)
+    }
+
+    /// Whether `a` and `b` refer to the same node, or `None` if neither is mounted.
+    pub(crate) fn is_same_object(&self, a: u32, b: u32) -> Option<bool> {
+        let node = |handle: Option<Handle>| handle.map(|handle| (handle.mount, handle.node));
+        match (self.handle(a), self.handle(b)) {
+            (None, None) => None,
+            (a, b) => Some(node(a) == node(b)),
+        }
+    }
+}
+
+/// Writes of a tmpfs output stream.
+struct TmpfsWriter {
+    tree: Arc<Mutex<MountTree>>,
+    node: usize,
+    /// Where the next write goes, or `None` to append
+    position: Option<u64>,
+}
+
+impl AsyncWrite for TmpfsWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = self
+            .tree
+            .lock()
+            .unwrap()
+            .write(self.node, self.position, buf);
+        Poll::Ready(match written {
+            Ok(written) => {
+                if let Some(position) = &mut self.position {
+                    *position += written;
+                }
+                Ok(buf.len())
+            }
+            Err(code) => Err(io::Error::other(format!("tmpfs write failed: {code:?}"))),
+        })
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Shadow the descriptor functions that [`crate::fs_grants`] leaves alone but that must answer
+/// for mounted descriptors instead of their placeholder.
+pub(crate) fn add_shadows(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    let mut types = linker.instance(TYPES_INTERFACE)?;
+
+    types.func_wrap_async(
+        "[method]descriptor.get-type",
+        |mut store, (fd,): (Resource<Descriptor>,)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                let result = match grants.mounts.handle(fd.rep()) {
+                    Some(handle) => grants.mounts.stat(handle).map(|stat| stat.type_),
+                    None => HostDescriptor::get_type(&mut view, fd).await,
+                };
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.get-flags",
+        |mut store, (fd,): (Resource<Descriptor>,)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                let result = match grants.mounts.handle(fd.rep()) {
+                    Some(handle) => Ok(handle.flags),
+                    None => HostDescriptor::get_flags(&mut view, fd).await,
+                };
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.stat",
+        |mut store, (fd,): (Resource<Descriptor>,)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                let result = match grants.mounts.handle(fd.rep()) {
+                    Some(handle) => grants.mounts.stat(handle),
+                    None => HostDescriptor::stat(&mut view, fd).await,
+                };
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.metadata-hash",
+        |mut store, (fd,): (Resource<Descriptor>,)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                let result = match grants.mounts.handle(fd.rep()) {
+                    Some(handle) => grants.mounts.metadata_hash(handle),
+                    None => HostDescriptor::metadata_hash(&mut view, fd).await,
+                };
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.is-same-object",
+        |mut store, (a, b): (Resource<Descriptor>, Resource<Descriptor>)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                match grants.mounts.is_same_object(a.rep(), b.rep()) {
+                    Some(same) => Ok((same,)),
+                    None => Ok((HostDescriptor::is_same_object(&mut view, a, b).await?,)),
+                }
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.read",
+        |mut store, (fd, len, offset): (Resource<Descriptor>, u64, u64)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                let result = match grants.mounts.handle(fd.rep()) {
+                    Some(handle) => grants.mounts.read(handle, len, offset).await,
+                    None => HostDescriptor::read(&mut view, fd, len, offset).await,
+                };
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap(
+        "[method]descriptor.read-via-stream",
+        |mut store, (fd, offset): (Resource<Descriptor>, u64)| {
+            let (grants, mut view) = split(&mut store.data_mut().inner);
+            let result = match grants.mounts.handle(fd.rep()) {
+                Some(handle) => grants.mounts.read_via_stream(&mut view, handle, offset),
+                None => HostDescriptor::read_via_stream(&mut view, fd, offset),
+            };
+            lower(&mut view, result)
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.write",
+        |mut store, (fd, buf, offset): (Resource<Descriptor>, Vec<u8>, u64)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                let result = match grants.mounts.handle(fd.rep()) {
+                    Some(handle) => grants.mounts.write(handle, &buf, offset),
+                    None => HostDescriptor::write(&mut view, fd, buf, offset).await,
+                };
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap(
+        "[method]descriptor.write-via-stream",
+        |mut store, (fd, offset): (Resource<Descriptor>, u64)| {
+            let (grants, mut view) = split(&mut store.data_mut().inner);
+            let result = match grants.mounts.handle(fd.rep()) {
+                Some(handle) => grants
+                    .mounts
+                    .write_via_stream(&mut view, handle, Some(offset)),
+                None => HostDescriptor::write_via_stream(&mut view, fd, offset),
+            };
+            lower(&mut view, result)
+        },
+    )?;
+
+    types.func_wrap(
+        "[method]descriptor.append-via-stream",
+        |mut store, (fd,): (Resource<Descriptor>,)| {
+            let (grants, mut view) = split(&mut store.data_mut().inner);
+            let result = match grants.mounts.handle(fd.rep()) {
+                Some(handle) => grants.mounts.write_via_stream(&mut view, handle, None),
+                None => HostDescriptor::append_via_stream(&mut view, fd),
+            };
+            lower(&mut view, result)
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.set-size",
+        |mut store, (fd, size): (Resource<Descriptor>, u64)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                let result = match grants.mounts.handle(fd.rep()) {
+                    Some(handle) => grants.mounts.set_size(handle, size),
+                    None => HostDescriptor::set_size(&mut view, fd, size).await,
+                };
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.set-times",
+        |mut store, (fd, atim, mtim): (Resource<Descriptor>, NewTimestamp, NewTimestamp)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                let result = match grants.mounts.handle(fd.rep()) {
+                    Some(handle) => grants.mounts.set_times(handle, mtim),
+                    None => HostDescriptor::set_times(&mut view, fd, atim, mtim).await,
+                };
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.create-directory-at",
+        |mut store, (fd, path): (Resource<Descriptor>, String)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                let result = match grants.mounts.handle(fd.rep()) {
+                    Some(handle) => grants.mounts.create_directory_at(handle, &path),
+                    None => HostDescriptor::create_directory_at(&mut view, fd, path).await,
+                };
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    types.func_wrap_async(
+        "[method]descriptor.remove-directory-at",
+        |mut store, (fd, path): (Resource<Descriptor>, String)| {
+            Box::new(async move {
+                let (grants, mut view) = split(&mut store.data_mut().inner);
+                let result = match grants.mounts.handle(fd.rep()) {
+                    Some(handle) => grants.mounts.remove_directory_at(handle, &path),
+                    None => HostDescriptor::remove_directory_at(&mut view, fd, path).await,
+                };
+                lower(&mut view, result)
+            })
+        },
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn tar_header(name: &str, entry_type: u8, size: u64) -> Vec<u8> {
+        let mut header = vec![0u8; TAR_BLOCK as usize];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{size:011o}").as_bytes());
+        header[156] = entry_type;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[148..156].fill(b' ');
+        let sum: u64 = header.iter().map(|&byte| u64::from(byte)).sum();
+        header[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+        header
+    }
+
+    fn tar_entry(archive: &mut Vec<u8>, name: &str, entry_type: u8, data: &[u8]) {
+        archive.extend(tar_header(name, entry_type, data.len() as u64));
+        archive.extend(data);
+        archive.resize(archive.len().div_ceil(512) * 512, 0);
+    }
+
+    fn stored_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for (name, data) in files {
+            let offset = archive.len() as u32;
+            archive.extend(ZIP_LOCAL_HEADER.to_le_bytes());
+            archive.extend([0u8; 14]);
+            archive.extend((data.len() as u32).to_le_bytes());
+            archive.extend((data.len() as u32).to_le_bytes());
+            archive.extend((name.len() as u16).to_le_bytes());
+            archive.extend(0u16.to_le_bytes());
+            archive.extend(name.as_bytes());
+            archive.extend(*data);
+
+            directory.extend(ZIP_CENTRAL_HEADER.to_le_bytes());
+            directory.extend([0u8; 16]);
+            directory.extend((data.len() as u32).to_le_bytes());
+            directory.extend((data.len() as u32).to_le_bytes());
+            directory.extend((name.len() as u16).to_le_bytes());
+            directory.extend([0u8; 12]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let directory_offset = archive.len() as u32;
+        archive.extend(&directory);
+        archive.extend(ZIP_END_OF_DIRECTORY.to_le_bytes());
+        archive.extend([0u8; 4]);
+        archive.extend((files.len() as u16).to_le_bytes());
+        archive.extend((files.len() as u16).to_le_bytes());
+        archive.extend((directory.len() as u32).to_le_bytes());
+        archive.extend(directory_offset.to_le_bytes());
+        archive.extend(0u16.to_le_bytes());
+        archive
+    }
+
+    fn write(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+        let path = dir.join(name);
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        path
+    }
+
+    fn contents(tree: &MountTree, path: &str) -> Vec<u8> {
+        match tree.contents(tree.lookup(ROOT, path).unwrap()).unwrap() {
+            Contents::Host { path, offset, size } => {
+                let bytes = std::fs::read(path).unwrap();
+                bytes[offset as usize..(offset + size) as usize].to_vec()
+            }
+            Contents::Memory(data) => data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_tar_archive_is_indexed_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let long_name = format!("{}/deep.csv", "nested".repeat(20));
+        let mut archive = Vec::new();
+        tar_entry(&mut archive, "data/", b'5', b"");
+        tar_entry(&mut archive, "data/a.csv", b'0', b"a,b\n1,2\n");
+        tar_entry(&mut archive, "././@LongLink", b'L', long_name.as_bytes());
+        tar_entry(&mut archive, "ignored", b'0', b"long");
+        tar_entry(&mut archive, "data/link", b'2', b"");
+        archive.extend([0u8; 1024]);
+        let path = write(dir.path(), "data.tar", &archive);
+
+        let mut tree = MountTree::new();
+        tree.mount_archive(&path).unwrap();
+        assert_eq!(contents(&tree, "data/a.csv"), b"a,b\n1,2\n");
+        assert_eq!(contents(&tree, &long_name), b"long");
+        assert_eq!(tree.lookup(ROOT, "data/link"), Err(ErrorCode::NoEntry));
+        assert_eq!(
+            tree.lookup(ROOT, "data/a.csv/x"),
+            Err(ErrorCode::NotDirectory)
+        );
+        let data = tree.lookup(ROOT, "data").unwrap();
+        let names: Vec<String> = tree
+            .entries(data)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["a.csv"]);
+        assert_eq!(tree.lookup(data, "../.."), Err(ErrorCode::NotPermitted));
+    }
+
+    #[test]
+    fn test_zip_archive_requires_stored_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(
+            dir.path(),
+            "data.zip",
+            &stored_zip(&[("docs/", b""), ("docs/readme.txt", b"hello")]),
+        );
+        let mut tree = MountTree::new();
+        tree.mount_archive(&path).unwrap();
+        assert_eq!(contents(&tree, "docs/readme.txt"), b"hello");
+        assert_eq!(
+            tree.stat(tree.lookup(ROOT, "docs").unwrap()).type_,
+            DescriptorType::Directory
+        );
+
+        let mut compressed = stored_zip(&[("a.txt", b"x")]);
+        // Set the compression method of the central directory entry to deflate.
+        let central = compressed.len() - ZIP_END_OF_DIRECTORY_LEN - ZIP_CENTRAL_HEADER_LEN - 5;
+        compressed[central + 10] = 8;
+        let path = write(dir.path(), "compressed.zip", &compressed);
+        let err = MountTree::new().mount_archive(&path).unwrap_err();
+        assert!(err.to_string().contains("Failed to index archive"));
+
+        let path = write(dir.path(), "slip.zip", &stored_zip(&[("../evil", b"x")]));
+        assert!(MountTree::new().mount_archive(&path).is_err());
+    }
+
+    #[test]
+    fn test_mounts_sharing_a_directory_are_merged() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "model.bin", b"weights");
+        write(dir.path(), "vocab.txt", b"tokens");
+        let mount = |file: &str, at: &str| policy::Mount {
+            file: Some(file.to_string()),
+            at: at.to_string(),
+            ..Default::default()
+        };
+        let mounts = from_policy(
+            &[
+                mount("model.bin", "/models/model.bin"),
+                mount("vocab.txt", "/models/vocab.txt"),
+                mount("vocab.txt", "/vocab.txt"),
+            ],
+            dir.path(),
+        )
+        .unwrap();
+        let paths: Vec<&str> = mounts.iter().map(VirtualMount::guest_path).collect();
+        assert_eq!(paths, ["/", "/models"]);
+        assert_eq!(contents(&mounts[1].lock(), "model.bin"), b"weights");
+        assert_eq!(contents(&mounts[1].lock(), "vocab.txt"), b"tokens");
+
+        let mut fs = VirtualFs::new(&mounts);
+        fs.track_preopen(3, "/models");
+        let handle = fs.handle(3).unwrap();
+        assert_eq!(fs.stat_at(handle, "model.bin").unwrap().size, 7);
+        assert!(fs.stat_at(handle, "missing").is_err());
+        fs.track_preopen(3, "/elsewhere");
+        assert_eq!(fs.handle(3), None);
+
+        assert!(from_policy(&[mount("missing.bin", "/m/x")], dir.path()).is_err());
+    }
+
+    fn code<T: std::fmt::Debug>(result: FsResult<T>) -> ErrorCode {
+        *result.unwrap_err().downcast_ref().unwrap()
+    }
+
+    #[test]
+    fn test_tmpfs_is_writable_and_size_capped() {
+        let tmpfs = policy::Mount {
+            tmpfs: Some(policy::MemoryLimit::String("4Ki".to_string())),
+            at: "/scratch".to_string(),
+            ..Default::default()
+        };
+        let mounts = from_policy(std::slice::from_ref(&tmpfs), Path::new("/")).unwrap();
+        let mut fs = VirtualFs::new(&mounts);
+        fs.track_preopen(3, "/scratch");
+        let root = fs.handle(3).unwrap();
+        assert!(root.flags.contains(DescriptorFlags::MUTATE_DIRECTORY));
+
+        fs.create_directory_at(root, "out").unwrap();
+        let memory = NodeKind::Memory {
+            data: Vec::new(),
+            modified: now(),
+        };
+        let node = fs.tree(root).create(ROOT, "out/a.txt", memory).unwrap();
+        let file = Handle {
+            node,
+            flags: DescriptorFlags::READ | DescriptorFlags::WRITE,
+            ..root
+        };
+        assert_eq!(fs.write(file, b"hello", 0).unwrap(), 5);
+        fs.write(file, b"!", 7).unwrap();
+        assert_eq!(contents(&fs.tree(root), "out/a.txt"), b"hello\0\0!");
+        assert_eq!(
+            code(fs.write(
+                Handle {
+                    flags: DescriptorFlags::READ,
+                    ..file
+                },
+                b"x",
+                0
+            )),
+            ErrorCode::NotPermitted
+        );
+        assert_eq!(
+            code(fs.write(file, &[0; 4096], 0)),
+            ErrorCode::InsufficientSpace
+        );
+        assert_eq!(
+            code(fs.remove_directory_at(root, "out")),
+            ErrorCode::NotEmpty
+        );
+
+        fs.create_directory_at(root, "out/sub").unwrap();
+        assert_eq!(
+            code(fs.rename_at(3, "out", 3, "out/sub/x").unwrap()),
+            ErrorCode::Invalid
+        );
+        fs.rename_at(3, "out/a.txt", 3, "b.txt").unwrap().unwrap();
+        assert_eq!(fs.stat_at(root, "b.txt").unwrap().size, 8);
+        fs.unlink_file_at(root, "b.txt").unwrap();
+        assert_eq!(fs.tree(root).used, 3 * TMPFS_ENTRY_COST);
+
+        // Every store gets its own empty tmpfs.
+        let mut other = VirtualFs::new(&mounts);
+        other.track_preopen(3, "/scratch");
+        assert!(other.stat_at(other.handle(3).unwrap(), "out").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "a.bin", b"a");
+        let file = policy::Mount {
+            file: Some("a.bin".to_string()),
+            at: "/scratch/a.bin".to_string(),
+            ..Default::default()
+        };
+        assert!(from_policy(&[tmpfs, file], dir.path()).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Publisher namespaces for tool names.
+//!
+//! Components installed from a manifest whose publisher declares a `namespace` expose their
+//! tools as `<namespace><separator><tool>`, e.g. `github__list_issues`. When a tool name is
+//! still taken by another component, the [`ToolCollisionPolicy`] decides what happens.
+
+use anyhow::{bail, Result};
+use component2json::ToolMetadata;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Separator placed between a namespace and a tool name unless configured otherwise.
+pub const DEFAULT_TOOL_SEPARATOR: &str = "__";
+
+/// What to do when a component exposes a tool name another component already provides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ToolCollisionPolicy {
+    /// Register both; calling the tool fails until one of them is unloaded
+    #[default]
+    Ambiguous,
+    /// Refuse to load the component
+    Reject,
+    /// Prefix the newcomer's colliding tools with its component id
+    Qualify,
+}
+
+/// How tool names are namespaced and how collisions are resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolNaming {
+    /// Separator between a namespace and a tool name
+    pub separator: String,
+    /// Policy for tool names provided by more than one component
+    pub collisions: ToolCollisionPolicy,
+}
+
+impl Default for ToolNaming {
+    fn default() -> Self {
+        Self {
+            separator: DEFAULT_TOOL_SEPARATOR.to_string(),
+            collisions: ToolCollisionPolicy::default(),
+        }
+    }
+}
+
+impl ToolNaming {
+    /// Prefix every tool with `namespace`.
+    pub(crate) fn apply_namespace(&self, tools: &mut [ToolMetadata], namespace: &str) {
+        for tool in tools {
+            let name = format!("{namespace}{}{}", self.separator, tool.normalized_name);
+            rename(tool, name);
+        }
+    }
+
+    /// Resolve a collision of `tool` with the tools of `other_component`.
+    pub(crate) fn resolve_collision(
+        &self,
+        tool: &mut ToolMetadata,
+        component_id: &str,
+        other_component: &str,
+    ) -> Result<()> {
+        match self.collisions {
+            ToolCollisionPolicy::Ambiguous => {}
+            ToolCollisionPolicy::Reject => bail!(
+                "Tool '{}' is already provided by component '{}'",
+                tool.normalized_name,
+                other_component
+            ),
+            ToolCollisionPolicy::Qualify => {
+                let name = format!("{component_id}{}{}", self.separator, tool.normalized_name);
+                rename(tool, name);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn rename(tool: &mut ToolMetadata, name: String) {
+    if let Some(schema) = tool.schema.as_object_mut() {
+        schema.insert("name".to_string(), Value::String(name.clone()));
+    }
+    tool.normalized_name = name;
+}
+
+#[cfg(test)]
+mod tests {
+    use component2json::FunctionIdentifier;
+    use serde_json::json;
+
+    use super::*;
+
+    fn tool(name: &str) -> ToolMetadata {
+        ToolMetadata {
+            identifier: FunctionIdentifier {
+                package_name: None,
+                interface_name: None,
+                function_name: name.to_string(),
+            },
+            normalized_name: name.to_string(),
+            schema: json!({ "name": name }),
+        }
+    }
+
+    #[test]
+    fn test_namespace_prefixes_tools() {
+        let mut tools = vec![tool("list_issues")];
+        ToolNaming::default().apply_namespace(&mut tools, "github");
+        assert_eq!(tools[0].normalized_name, "github__list_issues");
+        assert_eq!(tools[0].schema["name"], "github__list_issues");
+    }
+
+    #[test]
+    fn test_collision_policies() {
+        let mut naming = ToolNaming::default();
+        let mut fetch = tool("fetch");
+        naming.resolve_collision(&mut fetch, "web", "http").unwrap();
+        assert_eq!(fetch.normalized_name, "fetch");
+
+        naming.collisions = ToolCollisionPolicy::Reject;
+        assert!(naming.resolve_collision(&mut fetch, "web", "http").is_err());
+
+        naming.collisions = ToolCollisionPolicy::Qualify;
+        naming.separator = "-".to_string();
+        naming.resolve_collision(&mut fetch, "web", "http").unwrap();
+        assert_eq!(fetch.normalized_name, "web-fetch");
+    }
+}
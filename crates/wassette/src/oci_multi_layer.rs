@@ -6,7 +6,7 @@
 //! This module provides functionality to handle OCI artifacts with multiple layers,
 //! such as WASM components bundled with security policies or signatures.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use anyhow::{bail, Context, Result};
 use oci_client::{Client, Reference};
@@ -51,10 +51,12 @@ pub struct MultiLayerArtifact {
     pub config: Option<WasmConfig>,
     /// Other layers indexed by media type
     pub additional_layers: HashMap<String, Vec<u8>>,
+    /// Annotations of the image manifest
+    pub annotations: Option<BTreeMap<String, String>>,
 }
 
 /// Media types we recognize
-const WASM_MEDIA_TYPES: &[&str] = &[
+pub(crate) const WASM_MEDIA_TYPES: &[&str] = &[
     "application/wasm",
     "application/vnd.wasm.component.v1",
     "application/vnd.bytecodealliance.wasm.component.layer.v0+wasm",
@@ -98,12 +100,24 @@ pub async fn pull_multi_layer_artifact(
     reference: &Reference,
     client: &Client,
 ) -> Result<MultiLayerArtifact> {
-    let auth = oci_client::secrets::RegistryAuth::Anonymous;
+    pull_multi_layer_artifact_with_auth(
+        reference,
+        client,
+        &oci_client::secrets::RegistryAuth::Anonymous,
+    )
+    .await
+}
 
+/// Pull a multi-layer OCI artifact from a registry requiring credentials
+pub async fn pull_multi_layer_artifact_with_auth(
+    reference: &Reference,
+    client: &Client,
+    auth: &oci_client::secrets::RegistryAuth,
+) -> Result<MultiLayerArtifact> {
     // Pull just the manifest first
     info!("Pulling OCI manifest: {}", reference);
     let (manifest, manifest_digest) = client
-        .pull_manifest(reference, &auth)
+        .pull_manifest(reference, auth)
         .await
         .context("Failed to pull OCI manifest")?;
 
@@ -232,6 +246,7 @@ pub async fn pull_multi_layer_artifact(
         policy_data,
         config: config_data,
         additional_layers,
+        annotations: image_manifest.annotations,
     })
 }
 
@@ -0,0 +1,494 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Resilient OCI pulls: retries, resumable layer downloads and mirror fallback.
+//!
+//! Single-layer components are downloaded to `downloads/partial/<digest>.part` under the plugin
+//! directory. The partial file survives failed attempts (and restarts), so the next attempt asks
+//! the registry only for the missing bytes with a ranged request. An attempt fails when no data
+//! arrives for the configured timeout; it is retried with exponential backoff, and once the
+//! retries are used up the next mirror is tried.
+//!
+//! Mirrors configured for the component's registry under `[registries.mirrors]` (internal
+//! mirrors or pull-through caches, possibly with their own credentials) are tried before the
+//! registry itself, so networks that block the upstream registry resolve references
+//! transparently.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures::StreamExt;
+use oci_client::client::BlobResponse;
+use oci_client::manifest::OciDescriptor;
+use oci_client::secrets::RegistryAuth;
+use oci_client::Reference;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info};
+
+use crate::loader::{ComponentResource, DownloadedResource, Loadable};
+use crate::progress::{LoadContext, LoadStage};
+use crate::signatures::SignatureVerifier;
+
+/// Directory under the downloads directory holding partially downloaded layers.
+pub(crate) const PARTIAL_DIR: &str = "partial";
+
+const DEFAULT_PULL_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_PULL_RETRIES: u32 = 3;
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How OCI pulls cope with unreliable networks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PullOptions {
+    /// Abandon an attempt when no data arrives for this many seconds (0 waits forever)
+    pub timeout_secs: u64,
+    /// Attempts per registry after the first one fails
+    pub retries: u32,
+    /// Registries tried in order when the component's own registry keeps failing, e.g.
+    /// `mirror.example.com:5000`. Mirrors are asked for the same repository and tag.
+    pub mirrors: Vec<String>,
+}
+
+impl Default for PullOptions {
+    fn default() -> Self {
+        Self {
+            timeout_secs: DEFAULT_PULL_TIMEOUT_SECS,
+            retries: DEFAULT_PULL_RETRIES,
+            mirrors: Vec::new(),
+        }
+    }
+}
+
+/// Mirrors standing in for upstream registries.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegistryConfig {
+    /// Mirrors per upstream registry (e.g. `ghcr.io`), tried in order before the registry itself
+    pub mirrors: HashMap<String, Vec<RegistryMirror>>,
+}
+
+impl RegistryConfig {
+    /// Check every mirror entry.
+    pub fn validate(&self) -> Result<()> {
+        for (registry, mirrors) in &self.mirrors {
+            for mirror in mirrors {
+                mirror
+                    .validate()
+                    .with_context(|| format!("Invalid mirror for registry '{registry}'"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An internal mirror or pull-through cache of an upstream registry.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RegistryMirror {
+    /// Mirror registry, optionally followed by a repository prefix, e.g.
+    /// `harbor.corp.example.com/ghcr-proxy`
+    pub host: String,
+    /// User name for basic authentication
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Password for basic authentication
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Bearer token, used instead of basic authentication
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl fmt::Debug for RegistryMirror {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegistryMirror")
+            .field("host", &self.host)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl RegistryMirror {
+    fn validate(&self) -> Result<()> {
+        let (registry, _) = self.split_host();
+        if registry.is_empty() {
+            bail!("Mirror host can't be empty");
+        }
+        if self.token.is_some() && (self.username.is_some() || self.password.is_some()) {
+            bail!(
+                "Mirror {} sets both a token and basic credentials",
+                self.host
+            );
+        }
+        if self.username.is_some() != self.password.is_some() {
+            bail!(
+                "Mirror {} needs both a username and a password for basic authentication",
+                self.host
+            );
+        }
+        Ok(())
+    }
+
+    /// The registry and optional repository prefix of the mirror.
+    fn split_host(&self) -> (&str, Option<&str>) {
+        let host = self.host.trim_end_matches('/');
+        match host.split_once('/') {
+            Some((registry, prefix)) => (registry, Some(prefix)),
+            None => (host, None),
+        }
+    }
+
+    fn auth(&self) -> RegistryAuth {
+        match (&self.token, &self.username, &self.password) {
+            (Some(token), _, _) => RegistryAuth::Bearer(token.clone()),
+            (None, Some(username), Some(password)) => {
+                RegistryAuth::Basic(username.clone(), password.clone())
+            }
+            _ => RegistryAuth::Anonymous,
+        }
+    }
+
+    /// The image of `reference` on this mirror.
+    fn mirror(&self, reference: &Reference) -> Reference {
+        match self.split_host() {
+            (registry, None) => mirror_reference(reference, registry),
+            (registry, Some(prefix)) => {
+                let repository = format!("{prefix}/{}", reference.repository());
+                match reference.digest() {
+                    Some(digest) => {
+                        Reference::with_digest(registry.to_string(), repository, digest.to_string())
+                    }
+                    None => Reference::with_tag(
+                        registry.to_string(),
+                        repository,
+                        reference.tag().unwrap_or("latest").to_string(),
+                    ),
+                }
+            }
+        }
+    }
+}
+
+fn mirror_reference(reference: &Reference, registry: &str) -> Reference {
+    let mut mirrored = reference.clone();
+    mirrored.set_mirror_registry(registry.to_string());
+    mirrored
+}
+
+/// Where an image is pulled from and the credentials to pull it with.
+#[derive(Debug, Clone)]
+pub(crate) struct PullSource {
+    pub(crate) reference: Reference,
+    pub(crate) auth: RegistryAuth,
+}
+
+impl PullSource {
+    fn anonymous(reference: Reference) -> Self {
+        Self {
+            reference,
+            auth: RegistryAuth::Anonymous,
+        }
+    }
+}
+
+/// Pull options together with where partial layers are kept.
+#[derive(Debug, Clone)]
+pub(crate) struct OciPull {
+    pub(crate) options: PullOptions,
+    registries: RegistryConfig,
+    partial_dir: PathBuf,
+    signatures: Option<Arc<SignatureVerifier>>,
+}
+
+impl Default for OciPull {
+    fn default() -> Self {
+        Self::new(
+            PullOptions::default(),
+            RegistryConfig::default(),
+            std::env::temp_dir().join("wassette").join(PARTIAL_DIR),
+        )
+    }
+}
+
+impl OciPull {
+    pub(crate) fn new(
+        options: PullOptions,
+        registries: RegistryConfig,
+        partial_dir: PathBuf,
+    ) -> Self {
+        Self {
+            options,
+            registries,
+            partial_dir,
+            signatures: None,
+        }
+    }
+
+    /// Check the cosign signatures of every pulled component with `verifier`.
+    pub(crate) fn with_signatures(mut self, verifier: SignatureVerifier) -> Self {
+        self.signatures = verifier.is_enabled().then(|| Arc::new(verifier));
+        self
+    }
+
+    /// Check the signatures of the image `source` pulls in place of `upstream`, and pin
+    /// `source` to the digest they vouch for.
+    pub(crate) async fn verify_signatures(
+        &self,
+        source: &PullSource,
+        upstream: &Reference,
+        oci_client: &oci_client::Client,
+    ) -> Result<PullSource> {
+        let Some(verifier) = &self.signatures else {
+            return Ok(source.clone());
+        };
+        let reference = match verifier.verify(source, upstream, oci_client).await? {
+            Some(digest) => source.reference.clone_with_digest(digest),
+            None => source.reference.clone(),
+        };
+        Ok(PullSource {
+            reference,
+            auth: source.auth.clone(),
+        })
+    }
+
+    /// The mirrors of `reference`'s registry, then `reference` itself, then the same image
+    /// on each fallback mirror.
+    pub(crate) fn candidates(&self, reference: &Reference) -> Vec<PullSource> {
+        let mut candidates: Vec<PullSource> = self
+            .registries
+            .mirrors
+            .get(reference.registry())
+            .into_iter()
+            .flatten()
+            .map(|mirror| PullSource {
+                reference: mirror.mirror(reference),
+                auth: mirror.auth(),
+            })
+            .collect();
+        candidates.push(PullSource::anonymous(reference.clone()));
+        candidates.extend(
+            self.options
+                .mirrors
+                .iter()
+                .map(|mirror| PullSource::anonymous(mirror_reference(reference, mirror))),
+        );
+        candidates
+    }
+
+    /// Delay before retry number `attempt` (starting at 1).
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        Duration::from_secs(1u64 << attempt.min(5).saturating_sub(1)).min(MAX_BACKOFF)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        (self.options.timeout_secs > 0).then(|| Duration::from_secs(self.options.timeout_secs))
+    }
+
+    fn partial_path(&self, digest: &str) -> PathBuf {
+        self.partial_dir
+            .join(format!("{}.part", digest.replace(':', "-")))
+    }
+
+    /// Download `layer` of `reference` into a temp file named `<name>.wasm`, resuming a
+    /// previous partial download of the same layer.
+    pub(crate) async fn download_layer(
+        &self,
+        reference: &Reference,
+        oci_client: &oci_client::Client,
+        layer: &OciDescriptor,
+        name: &str,
+        context: &LoadContext,
+    ) -> Result<DownloadedResource> {
+        tokio::fs::create_dir_all(&self.partial_dir)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to create partial download directory: {}",
+                    self.partial_dir.display()
+                )
+            })?;
+        let partial = self.partial_path(&layer.digest);
+        let total_bytes = u64::try_from(layer.size).ok();
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&partial)
+            .await?;
+        let mut offset = file.metadata().await?.len();
+        if total_bytes.is_some_and(|total| offset > total) {
+            file.set_len(0).await?;
+            offset = 0;
+        }
+
+        if total_bytes != Some(offset) {
+            let blob = if offset > 0 {
+                info!(offset, digest = %layer.digest, "Resuming layer download");
+                match oci_client
+                    .pull_blob_stream_partial(reference, layer, offset, None)
+                    .await?
+                {
+                    BlobResponse::Partial(blob) => blob,
+                    BlobResponse::Full(blob) => {
+                        debug!("Registry ignored the range request, starting over");
+                        file.set_len(0).await?;
+                        offset = 0;
+                        blob
+                    }
+                }
+            } else {
+                oci_client.pull_blob_stream(reference, layer).await?
+            };
+
+            context.report(LoadStage::Downloading, offset, total_bytes);
+            let mut stream = blob.stream;
+            let mut writer = context
+                .track_download(&mut file, total_bytes)
+                .resuming_at(offset);
+            loop {
+                let next = match self.timeout() {
+                    Some(timeout) => tokio::time::timeout(timeout, stream.next())
+                        .await
+                        .map_err(|_| anyhow!("No data received for {timeout:?}"))?,
+                    None => stream.next().await,
+                };
+                match next {
+                    Some(chunk) => writer.write_all(&chunk?).await?,
+                    None => break,
+                }
+            }
+            writer.finish();
+            file.flush().await?;
+            file.sync_all().await?;
+        }
+        drop(file);
+
+        if let Err(error) = verify_file_digest(&partial, &layer.digest).await {
+            tokio::fs::remove_file(&partial).await.ok();
+            return Err(error);
+        }
+
+        let tempdir = tempfile::tempdir_in(&self.partial_dir)?;
+        let path = tempdir
+            .path()
+            .join(format!("{name}.{}", ComponentResource::FILE_EXTENSION));
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(DownloadedResource::Temp((tempdir, path)))
+    }
+}
+
+/// Check the SHA-256 digest of the file at `path`.
+async fn verify_file_digest(path: &Path, expected: &str) -> Result<()> {
+    let path = path.to_path_buf();
+    let actual = tokio::task::spawn_blocking(move || -> Result<String> {
+        let mut file = std::fs::File::open(&path)?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        Ok(format!("sha256:{}", hex::encode(hasher.finalize())))
+    })
+    .await??;
+    if actual != expected {
+        bail!("Downloaded layer digest {actual} does not match {expected}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates_try_mirrors_after_upstream() {
+        let pull = OciPull::new(
+            PullOptions {
+                mirrors: vec!["mirror.example.com".to_string()],
+                ..Default::default()
+            },
+            RegistryConfig::default(),
+            PathBuf::from(PARTIAL_DIR),
+        );
+        let reference: Reference = "ghcr.io/example/fetch:1.0".parse().unwrap();
+        let candidates = pull.candidates(&reference);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].reference.namespace(), None);
+        let mirrored = &candidates[1].reference;
+        assert_eq!(mirrored.resolve_registry(), "mirror.example.com");
+        assert_eq!(mirrored.namespace(), Some("ghcr.io"));
+        assert_eq!(mirrored.repository(), "example/fetch");
+    }
+
+    #[test]
+    fn test_registry_mirrors_are_tried_first_with_their_credentials() {
+        let mirror = RegistryMirror {
+            host: "harbor.corp.example.com/ghcr-proxy".to_string(),
+            username: Some("robot".to_string()),
+            password: Some("secret".to_string()),
+            token: None,
+        };
+        let registries = RegistryConfig {
+            mirrors: HashMap::from([("ghcr.io".to_string(), vec![mirror])]),
+        };
+        registries.validate().unwrap();
+        let pull = OciPull::new(
+            PullOptions::default(),
+            registries,
+            PathBuf::from(PARTIAL_DIR),
+        );
+
+        let reference: Reference = "ghcr.io/example/fetch:1.0".parse().unwrap();
+        let candidates = pull.candidates(&reference);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(
+            candidates[0].reference.whole(),
+            "harbor.corp.example.com/ghcr-proxy/example/fetch:1.0"
+        );
+        assert_eq!(
+            candidates[0].auth,
+            RegistryAuth::Basic("robot".to_string(), "secret".to_string())
+        );
+        assert_eq!(candidates[1].reference, reference);
+        assert_eq!(candidates[1].auth, RegistryAuth::Anonymous);
+
+        let other: Reference = "docker.io/library/hello:1".parse().unwrap();
+        assert_eq!(pull.candidates(&other).len(), 1);
+    }
+
+    #[test]
+    fn test_mirror_credentials_are_validated_and_redacted() {
+        let mirror = RegistryMirror {
+            host: "mirror.example.com".to_string(),
+            username: None,
+            password: Some("secret".to_string()),
+            token: None,
+        };
+        assert!(mirror.validate().is_err());
+        assert!(!format!("{mirror:?}").contains("secret"));
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let pull = OciPull::default();
+        assert_eq!(pull.backoff(1), Duration::from_secs(1));
+        assert_eq!(pull.backoff(2), Duration::from_secs(2));
+        assert_eq!(pull.backoff(3), Duration::from_secs(4));
+        assert_eq!(pull.backoff(10), Duration::from_secs(16));
+    }
+
+    #[tokio::test]
+    async fn test_verify_file_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("layer");
+        std::fs::write(&path, b"test data").unwrap();
+        let digest = "sha256:916f0027a575074ce72a331777c3478d6513f786a591bd892da1a577bf2335f9";
+        assert!(verify_file_digest(&path, digest).await.is_ok());
+        assert!(verify_file_digest(&path, "sha256:00").await.is_err());
+    }
+}
@@ -0,0 +1,531 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Destinations for component stdout and stderr.
+//!
+//! By default a component's output streams are inherited from the server process. Each stream
+//! can instead be discarded, written to a size-rotated file, recorded as `wassette::audit`
+//! tracing events, or forwarded line by line to subscribers such as the MCP log channel:
+//!
+//! ```toml
+//! [output.default]
+//! stdout = "null"
+//! stderr = "log"
+//!
+//! [output.components.fetch]
+//! stdout = { file = { path = "fetch.log", max-bytes = 1048576, max-files = 3 } }
+//! stderr = "audit"
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWrite;
+use tokio::sync::broadcast;
+use wasmtime_wasi::cli::{IsTerminal, StdoutStream};
+use wasmtime_wasi::WasiCtxBuilder;
+
+/// Directory under the plugin directory where relative output files are written.
+pub(crate) const LOGS_DIR: &str = "logs";
+
+const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const DEFAULT_MAX_FILES: u32 = 5;
+
+/// Longest line forwarded as a single log or audit entry; longer lines are split.
+const MAX_LINE_BYTES: usize = 8 * 1024;
+
+/// Lines buffered for log subscribers before the slowest one starts missing output.
+const LINE_CHANNEL_CAPACITY: usize = 1024;
+
+/// Where a component output stream goes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputSink {
+    /// Write to the server's own stdout or stderr
+    #[default]
+    Inherit,
+    /// Discard the output
+    Null,
+    /// Forward each line to output subscribers, e.g. the MCP log channel
+    Log,
+    /// Record each line as a `wassette::audit` tracing event
+    Audit,
+    /// Append to a file, rotating it when it grows too large
+    File(FileSink),
+}
+
+/// A size-rotated output file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FileSink {
+    /// File to write; relative paths are resolved against the `logs` directory under the
+    /// plugin directory. Defaults to `<component>.<stream>.log` there.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Size at which the file is rotated
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    /// Number of rotated files kept next to the current one (`<path>.1` is the newest)
+    #[serde(default = "default_max_files")]
+    pub max_files: u32,
+}
+
+impl Default for FileSink {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_files: DEFAULT_MAX_FILES,
+        }
+    }
+}
+
+fn default_max_bytes() -> u64 {
+    DEFAULT_MAX_BYTES
+}
+
+fn default_max_files() -> u32 {
+    DEFAULT_MAX_FILES
+}
+
+/// Sinks for the two output streams of a component.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StdioSinks {
+    /// Sink for stdout
+    #[serde(default)]
+    pub stdout: OutputSink,
+    /// Sink for stderr
+    #[serde(default)]
+    pub stderr: OutputSink,
+}
+
+/// Output sinks for all components.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputConfig {
+    /// Sinks for components without an entry in `components`
+    #[serde(default)]
+    pub default: StdioSinks,
+    /// Sinks by component id
+    #[serde(default)]
+    pub components: HashMap<String, StdioSinks>,
+}
+
+impl OutputConfig {
+    /// Check that every file sink can rotate.
+    pub fn validate(&self) -> Result<()> {
+        let all = std::iter::once(("default", &self.default)).chain(
+            self.components
+                .iter()
+                .map(|(id, sinks)| (id.as_str(), sinks)),
+        );
+        for (name, sinks) in all {
+            for sink in [&sinks.stdout, &sinks.stderr] {
+                if let OutputSink::File(file) = sink {
+                    if file.max_bytes == 0 {
+                        bail!("Output file for '{name}' must have a max-bytes greater than 0");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn sinks_for(&self, component_id: &str) -> &StdioSinks {
+        self.components.get(component_id).unwrap_or(&self.default)
+    }
+}
+
+/// One of a component's output streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StdioStream {
+    /// Standard output
+    Stdout,
+    /// Standard error
+    Stderr,
+}
+
+impl fmt::Display for StdioStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StdioStream::Stdout => f.write_str("stdout"),
+            StdioStream::Stderr => f.write_str("stderr"),
+        }
+    }
+}
+
+/// A line written by a component to a stream with the `log` sink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLine {
+    /// Component that wrote the line
+    pub component_id: String,
+    /// Stream the line was written to
+    pub stream: StdioStream,
+    /// The line, without its terminator
+    pub line: String,
+}
+
+/// Applies the configured sinks to component WASI contexts.
+pub(crate) struct ComponentOutput {
+    config: OutputConfig,
+    logs_dir: PathBuf,
+    files: Mutex<HashMap<PathBuf, Arc<Mutex<RotatingFile>>>>,
+    lines: broadcast::Sender<OutputLine>,
+}
+
+impl ComponentOutput {
+    pub(crate) fn new(config: OutputConfig, logs_dir: PathBuf) -> Self {
+        Self {
+            config,
+            logs_dir,
+            files: Mutex::default(),
+            lines: broadcast::channel(LINE_CHANNEL_CAPACITY).0,
+        }
+    }
+
+    /// Receive the lines written to `log` sinks from now on.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<OutputLine> {
+        self.lines.subscribe()
+    }
+
+    /// Point the stdout and stderr of `builder` at the sinks configured for `component_id`.
+    /// Streams with the `inherit` sink are left as they are.
+    pub(crate) fn apply(&self, component_id: &str, builder: &mut WasiCtxBuilder) {
+        let sinks = self.config.sinks_for(component_id);
+        if let Some(sink) = self.stream_for(component_id, StdioStream::Stdout, &sinks.stdout) {
+            builder.stdout(sink);
+        }
+        if let Some(sink) = self.stream_for(component_id, StdioStream::Stderr, &sinks.stderr) {
+            builder.stderr(sink);
+        }
+    }
+
+    fn stream_for(
+        &self,
+        component_id: &str,
+        stream: StdioStream,
+        sink: &OutputSink,
+    ) -> Option<SinkStream> {
+        let target = match sink {
+            OutputSink::Inherit => return None,
+            OutputSink::Null => SinkTarget::Null,
+            OutputSink::Log => SinkTarget::Log(self.lines.clone()),
+            OutputSink::Audit => SinkTarget::Audit,
+            OutputSink::File(file) => SinkTarget::File(self.file_for(component_id, stream, file)),
+        };
+        Some(SinkStream {
+            component_id: component_id.into(),
+            stream,
+            target,
+        })
+    }
+
+    /// The shared writer for a file sink, so rotation state carries across invocations and
+    /// streams writing to the same file.
+    fn file_for(
+        &self,
+        component_id: &str,
+        stream: StdioStream,
+        sink: &FileSink,
+    ) -> Arc<Mutex<RotatingFile>> {
+        let path = match &sink.path {
+            Some(path) => self.logs_dir.join(path),
+            None => self.logs_dir.join(format!("{component_id}.{stream}.log")),
+        };
+        self.files
+            .lock()
+            .unwrap()
+            .entry(path.clone())
+            .or_insert_with(|| {
+                Arc::new(Mutex::new(RotatingFile::new(
+                    path,
+                    sink.max_bytes,
+                    sink.max_files,
+                )))
+            })
+            .clone()
+    }
+}
+
+#[derive(Clone)]
+enum SinkTarget {
+    Null,
+    Log(broadcast::Sender<OutputLine>),
+    Audit,
+    File(Arc<Mutex<RotatingFile>>),
+}
+
+/// A guest output stream backed by a configured sink.
+#[derive(Clone)]
+struct SinkStream {
+    component_id: Arc<str>,
+    stream: StdioStream,
+    target: SinkTarget,
+}
+
+impl SinkStream {
+    fn emit(&self, bytes: &[u8]) {
+        let line = String::from_utf8_lossy(bytes);
+        let line = line.trim_end_matches('\r');
+        match &self.target {
+            SinkTarget::Log(lines) => {
+                // Sending only fails when nobody is subscribed, in which case the line is dropped.
+                let _ = lines.send(OutputLine {
+                    component_id: self.component_id.to_string(),
+                    stream: self.stream,
+                    line: line.to_string(),
+                });
+            }
+            SinkTarget::Audit => tracing::info!(
+                target: "wassette::audit",
+                component_id = %self.component_id,
+                stream = %self.stream,
+                line,
+                "Component output"
+            ),
+            SinkTarget::Null | SinkTarget::File(_) => {}
+        }
+    }
+}
+
+impl IsTerminal for SinkStream {
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+impl StdoutStream for SinkStream {
+    fn async_stream(&self) -> Box<dyn AsyncWrite + Send + Sync> {
+        Box::new(SinkWriter {
+            sink: self.clone(),
+            pending: Vec::new(),
+        })
+    }
+}
+
+/// Writer handed to the guest; splits output into lines for the line-based sinks.
+struct SinkWriter {
+    sink: SinkStream,
+    pending: Vec<u8>,
+}
+
+impl SinkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        match &self.sink.target {
+            SinkTarget::Null => {}
+            SinkTarget::File(file) => file.lock().unwrap().write_all(buf)?,
+            SinkTarget::Log(_) | SinkTarget::Audit => {
+                self.pending.extend_from_slice(buf);
+                while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = self.pending.drain(..=end).collect();
+                    self.sink.emit(&line[..end]);
+                }
+                while self.pending.len() > MAX_LINE_BYTES {
+                    let chunk: Vec<u8> = self.pending.drain(..MAX_LINE_BYTES).collect();
+                    self.sink.emit(&chunk);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AsyncWrite for SinkWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.write(buf).map(|()| buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &self.sink.target {
+            SinkTarget::File(file) => Poll::Ready(file.lock().unwrap().flush()),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl Drop for SinkWriter {
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            self.sink.emit(&self.pending);
+        }
+    }
+}
+
+/// An append-only file that is renamed to `<path>.1` (shifting older files up to
+/// `<path>.<max_files>`) once it reaches `max_bytes`.
+pub(crate) struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: u32,
+    file: Option<File>,
+    written: u64,
+}
+
+impl RotatingFile {
+    pub(crate) fn new(path: PathBuf, max_bytes: u64, max_files: u32) -> Self {
+        Self {
+            path,
+            max_bytes,
+            max_files,
+            file: None,
+            written: 0,
+        }
+    }
+
+    pub(crate) fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let file = self.file.as_mut().expect("file was opened above");
+        file.write_all(buf)?;
+        self.written += buf.len() as u64;
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.written = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            remove_if_exists(&rotated_path(&self.path, self.max_files))?;
+            for n in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, n);
+                if from.exists() {
+                    std::fs::rename(&from, rotated_path(&self.path, n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        self.open()
+    }
+}
+
+fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_rotates_at_max_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        let mut file = RotatingFile::new(path.clone(), 8, 2);
+        for chunk in ["aaaaaa\n", "bbbbbb\n", "cccccc\n", "dddddd\n"] {
+            file.write_all(chunk.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "dddddd\n");
+        assert_eq!(
+            std::fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "cccccc\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(rotated_path(&path, 2)).unwrap(),
+            "bbbbbb\n"
+        );
+        assert!(!rotated_path(&path, 3).exists());
+    }
+
+    #[test]
+    fn test_log_sink_forwards_lines() {
+        let output = ComponentOutput::new(OutputConfig::default(), PathBuf::from(LOGS_DIR));
+        let mut lines = output.subscribe();
+
+        assert!(output
+            .stream_for("fetch", StdioStream::Stdout, &OutputSink::Inherit)
+            .is_none());
+        let stream = output
+            .stream_for("fetch", StdioStream::Stderr, &OutputSink::Log)
+            .unwrap();
+        let mut writer = SinkWriter {
+            sink: stream,
+            pending: Vec::new(),
+        };
+        writer.write(b"first\r\nsec").unwrap();
+        writer.write(b"ond\npartial").unwrap();
+        drop(writer);
+
+        let received: Vec<String> = std::iter::from_fn(|| lines.try_recv().ok())
+            .map(|line| {
+                assert_eq!(line.component_id, "fetch");
+                assert_eq!(line.stream, StdioStream::Stderr);
+                line.line
+            })
+            .collect();
+        assert_eq!(received, ["first", "second", "partial"]);
+    }
+
+    #[test]
+    fn test_output_config_parses_sinks() {
+        let config: OutputConfig = serde_json::from_value(serde_json::json!({
+            "default": { "stdout": "null" },
+            "components": {
+                "fetch": { "stderr": { "file": { "path": "fetch.err", "max-files": 1 } } }
+            }
+        }))
+        .unwrap();
+        assert_eq!(config.default.stdout, OutputSink::Null);
+        assert_eq!(config.default.stderr, OutputSink::Inherit);
+        assert_eq!(
+            config.sinks_for("fetch").stderr,
+            OutputSink::File(FileSink {
+                path: Some(PathBuf::from("fetch.err")),
+                max_bytes: DEFAULT_MAX_BYTES,
+                max_files: 1,
+            })
+        );
+        assert!(config.validate().is_ok());
+    }
+}
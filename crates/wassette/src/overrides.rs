@@ -0,0 +1,209 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Per-call overrides of a component's limits.
+//!
+//! An occasional heavy job may need more time or memory than a component's everyday limits
+//! allow. Instead of raising them for every call, a trusted caller can ask for a `timeout` or
+//! `memory` limit on a single call, written as in a policy's `resources.limits`. They replace
+//! the server's call timeout, the tool's `timeout_secs` and the policy's `timeout` and `memory`
+//! limits for that call only, and must stay within the ceiling the server is configured with:
+//!
+//! ```toml
+//! [call_overrides]
+//! max_timeout = "1h"
+//! max_memory = "4Gi"
+//! ```
+//!
+//! Without a ceiling for a limit, every override of it is refused. Which callers are trusted
+//! is up to the embedder; the MCP server only accepts overrides from clients whose access token
+//! has the `wassette:manage` scope. Without `[auth]` there is no token to check, so overrides
+//! are refused unless `allow_unauthenticated = true` says that anyone who can reach the server
+//! may raise limits up to the ceiling, as on a local stdio transport.
+
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use policy::{CallTimeout, MemoryLimit};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Limits a caller asked for on one call.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallOverrides {
+    /// Wall-clock time the call may run, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Linear memory the call's instance may grow to, in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_bytes: Option<u64>,
+}
+
+/// Overrides as callers write them.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RequestedOverrides {
+    timeout: Option<CallTimeout>,
+    memory: Option<MemoryLimit>,
+}
+
+impl CallOverrides {
+    /// Parse overrides written as `{"timeout": "10m", "memory": "2Gi"}`.
+    pub fn from_json(value: &Value) -> Result<Self> {
+        let requested = RequestedOverrides::deserialize(value)
+            .context("Limit overrides take a `timeout` and a `memory` limit")?;
+        Ok(Self {
+            timeout_secs: requested
+                .timeout
+                .map(|timeout| timeout.to_duration())
+                .transpose()?
+                .map(|timeout| timeout.as_secs()),
+            memory_bytes: requested
+                .memory
+                .map(|memory| memory.to_bytes())
+                .transpose()?,
+        })
+    }
+
+    /// Whether no limit is overridden.
+    pub fn is_empty(&self) -> bool {
+        self.timeout_secs.is_none() && self.memory_bytes.is_none()
+    }
+}
+
+/// The most overrides may ask for, under `[call_overrides]`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OverrideCeiling {
+    /// Longest timeout a call may ask for; timeouts can't be overridden if unset
+    pub max_timeout: Option<CallTimeout>,
+    /// Most memory a call may ask for; memory can't be overridden if unset
+    pub max_memory: Option<MemoryLimit>,
+    /// Accept overrides from callers without an access token, which lets anyone who can reach
+    /// the server raise limits up to the ceiling
+    pub allow_unauthenticated: bool,
+}
+
+impl OverrideCeiling {
+    /// Check that the ceilings are valid limits.
+    pub fn validate(&self) -> Result<()> {
+        self.limits().map(|_| ())
+    }
+
+    pub(crate) fn limits(&self) -> Result<OverrideLimits> {
+        Ok(OverrideLimits {
+            timeout: self
+                .max_timeout
+                .as_ref()
+                .map(|timeout| timeout.to_duration())
+                .transpose()
+                .context("Invalid max_timeout")?,
+            memory: self
+                .max_memory
+                .as_ref()
+                .map(|memory| memory.to_bytes())
+                .transpose()
+                .context("Invalid max_memory")?,
+            unauthenticated: self.allow_unauthenticated,
+        })
+    }
+}
+
+/// An [`OverrideCeiling`] in seconds and bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct OverrideLimits {
+    timeout: Option<Duration>,
+    memory: Option<u64>,
+    unauthenticated: bool,
+}
+
+impl OverrideLimits {
+    /// Whether a caller may override limits: one with an access token if `manages` says the
+    /// token has the manage scope, one without only if the ceiling allows it.
+    pub(crate) fn accept_from(&self, manages: Option<bool>) -> bool {
+        manages.unwrap_or(self.unauthenticated)
+    }
+
+    /// Refuse `overrides` that go past the ceiling or override a limit without one.
+    pub(crate) fn check(&self, overrides: &CallOverrides) -> Result<()> {
+        if let Some(secs) = overrides.timeout_secs {
+            match self.timeout {
+                None => bail!("The server doesn't allow overriding the timeout of calls"),
+                Some(max) if Duration::from_secs(secs) > max => bail!(
+                    "Timeout override of {secs}s is above the server's ceiling of {}s",
+                    max.as_secs()
+                ),
+                Some(_) => {}
+            }
+        }
+        if let Some(bytes) = overrides.memory_bytes {
+            match self.memory {
+                None => bail!("The server doesn't allow overriding the memory limit of calls"),
+                Some(max) if bytes > max => bail!(
+                    "Memory override of {bytes} bytes is above the server's ceiling of {max} bytes"
+                ),
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_overrides_are_checked_against_the_ceiling() {
+        let overrides =
+            CallOverrides::from_json(&json!({"timeout": "10m", "memory": "2Gi"})).unwrap();
+        assert_eq!(overrides.timeout_secs, Some(600));
+        assert_eq!(overrides.memory_bytes, Some(2 << 30));
+        assert!(CallOverrides::from_json(&json!({"fuel": 10})).is_err());
+        assert!(CallOverrides::from_json(&json!({})).unwrap().is_empty());
+
+        let ceiling = OverrideCeiling {
+            max_timeout: Some(CallTimeout::String("1h".to_string())),
+            max_memory: Some(MemoryLimit::String("4Gi".to_string())),
+            ..Default::default()
+        };
+        let limits = ceiling.limits().unwrap();
+        assert!(limits.check(&overrides).is_ok());
+        let err = limits
+            .check(&CallOverrides {
+                timeout_secs: Some(7200),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("above the server's ceiling of 3600s"));
+
+        let err = OverrideLimits::default().check(&overrides).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("doesn't allow overriding the timeout"));
+        assert!(OverrideLimits::default()
+            .check(&CallOverrides::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_only_managers_override_unless_unauthenticated_callers_are_allowed() {
+        let limits = OverrideLimits::default();
+        assert!(limits.accept_from(Some(true)));
+        assert!(!limits.accept_from(Some(false)));
+        assert!(!limits.accept_from(None));
+
+        let limits = OverrideCeiling {
+            allow_unauthenticated: true,
+            ..Default::default()
+        }
+        .limits()
+        .unwrap();
+        assert!(!limits.accept_from(Some(false)));
+        assert!(limits.accept_from(None));
+    }
+}
@@ -6,12 +6,13 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use oci_wasm::WasmClient;
 use policy::{
-    AccessType, EnvironmentPermission, NetworkHostPermission, NetworkPermission, PolicyDocument,
-    PolicyParser, StoragePermission,
+    AccessType, EnvironmentPermission, NetworkHostPermission, NetworkPermission, Permissions,
+    PolicyDocument, PolicyParser, StoragePermission,
 };
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
@@ -19,8 +20,12 @@ use tokio::sync::RwLock;
 use tracing::{info, instrument, warn};
 
 use crate::component_storage::ComponentStorage;
+use crate::email;
+use crate::env_references::{self, InjectedVariable, VariableSource};
 use crate::loader::{self, PolicyResource};
-use crate::{SecretsManager, WasiStateTemplate};
+use crate::progress::LoadContext;
+use crate::schedule::ToolHours;
+use crate::{wasistate, SecretsManager, WasiStateTemplate};
 
 /// Granular permission rule types
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,6 +70,8 @@ pub(crate) struct PolicyManager {
     environment_vars: Arc<HashMap<String, String>>,
     oci_client: Arc<WasmClient>,
     http_client: Client,
+    /// Reject policies with unknown or deprecated keys
+    strict: bool,
 }
 
 /// Information about a policy attached to a component
@@ -89,6 +96,7 @@ impl PolicyManager {
         environment_vars: Arc<HashMap<String, String>>,
         oci_client: Arc<WasmClient>,
         http_client: Client,
+        strict: bool,
     ) -> Self {
         Self {
             registry: Arc::new(RwLock::new(PolicyRegistry::default())),
@@ -97,6 +105,16 @@ impl PolicyManager {
             environment_vars,
             oci_client,
             http_client,
+            strict,
+        }
+    }
+
+    /// Parse a policy document, rejecting unknown and deprecated keys in strict mode.
+    fn parse_policy(&self, content: &str) -> Result<policy::PolicyDocument> {
+        if self.strict {
+            PolicyParser::parse_str_strict(content)
+        } else {
+            PolicyParser::parse_str(content)
         }
     }
 
@@ -104,6 +122,11 @@ impl PolicyManager {
         self.storage.policy_path(component_id)
     }
 
+    /// The server's environment variables, which `environment` permissions read
+    pub(crate) fn environment_vars(&self) -> Arc<HashMap<String, String>> {
+        Arc::clone(&self.environment_vars)
+    }
+
     pub(crate) fn metadata_path(&self, component_id: &str) -> PathBuf {
         self.storage.policy_metadata_path(component_id)
     }
@@ -146,12 +169,135 @@ impl PolicyManager {
         self.build_default_template(component_id).await
     }
 
+    /// How long results of `tool` stay cached under the component's attached policy, if the
+    /// policy caches them at all. A policy granting the session context caches nothing, since
+    /// results then depend on the session of the caller.
+    pub(crate) async fn cache_ttl(&self, component_id: &str, tool: &str) -> Option<Duration> {
+        let registry = self.registry.read().await;
+        let template = registry.component_policies.get(component_id)?;
+        if template.context {
+            return None;
+        }
+        template.cache_ttls.get(tool).copied()
+    }
+
+    /// The hours at which `tool` may be called under the component's attached policy, if
+    /// the policy restricts them.
+    pub(crate) async fn tool_hours(&self, component_id: &str, tool: &str) -> Option<ToolHours> {
+        self.registry
+            .read()
+            .await
+            .component_policies
+            .get(component_id)?
+            .tool_hours
+            .get(tool)
+            .cloned()
+    }
+
+    /// Environment variables of `component_id` to add to `template` when instantiating it: the
+    /// granted variables whose values hold references, resolved now, and the secrets of a
+    /// backend that resolves them only at instantiation. `None` when there are neither.
+    pub(crate) async fn instantiation_variables(
+        &self,
+        component_id: &str,
+        template: &WasiStateTemplate,
+    ) -> Result<Option<HashMap<String, String>>> {
+        let lazy_secrets = self.secrets.instantiation_secrets(component_id).await?;
+        let lazy = lazy_secrets.is_some();
+        if !lazy && template.env_references.is_empty() {
+            return Ok(None);
+        }
+        let secrets = match lazy_secrets {
+            Some(secrets) => secrets,
+            None => self.secrets.load_component_secrets(component_id).await?,
+        };
+        let secrets = email::visible_secrets(&secrets, template.email.is_some());
+
+        let mut variables = HashMap::new();
+        if lazy {
+            // Secrets come below the variables of the template.
+            variables.extend(
+                secrets
+                    .iter()
+                    .filter(|(key, _)| !template.config_vars.contains_key(*key))
+                    .map(|(key, value)| (key.clone(), value.clone())),
+            );
+        }
+        for (key, value) in &template.env_references {
+            let value = env_references::resolve(value, &secrets, &self.environment_vars)
+                .with_context(|| {
+                    format!("Failed to resolve environment variable {key} of {component_id}")
+                })?;
+            variables.insert(key.clone(), value);
+        }
+        Ok(Some(variables))
+    }
+
+    /// The environment variables `component_id` would be given if it were instantiated now.
+    pub(crate) async fn injected_variables(
+        &self,
+        component_id: &str,
+    ) -> Result<Vec<InjectedVariable>> {
+        let secrets = self.secrets.load_component_secrets(component_id).await?;
+        let policy_path = self.policy_path(component_id);
+        let mut injected = Vec::new();
+        if !policy_path.exists() {
+            // Without a policy, the component sees the whole environment as it is, see
+            // `build_default_template`.
+            let mut variables: HashMap<_, _> = self
+                .environment_vars
+                .iter()
+                .map(|(key, value)| (key.clone(), (value.clone(), VariableSource::Environment)))
+                .collect();
+            for (key, value) in secrets {
+                variables.insert(key, (value, VariableSource::Secret));
+            }
+            for (key, (value, source)) in variables {
+                injected.push(InjectedVariable {
+                    key,
+                    value,
+                    source,
+                    references: Vec::new(),
+                });
+            }
+            injected.sort_by(|a, b| a.key.cmp(&b.key));
+            return Ok(injected);
+        }
+
+        let content = tokio::fs::read_to_string(&policy_path).await?;
+        let policy = self.parse_policy(&content)?;
+        let variables =
+            wasistate::collect_env_vars(&policy, self.environment_vars.as_ref(), Some(&secrets));
+        let secrets = email::visible_secrets(&secrets, policy.permissions.email.is_some());
+        for (key, (value, source)) in variables {
+            let references = match source {
+                VariableSource::Secret => Vec::new(),
+                _ => env_references::references(&value)
+                    .with_context(|| format!("Invalid value of environment variable {key}"))?,
+            };
+            let value = if references.is_empty() {
+                value
+            } else {
+                env_references::resolve(&value, &secrets, &self.environment_vars)
+                    .with_context(|| format!("Failed to resolve environment variable {key}"))?
+            };
+            injected.push(InjectedVariable {
+                key,
+                value,
+                source,
+                references,
+            });
+        }
+        injected.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(injected)
+    }
+
     /// Construct a default WASI template enriched with configured environment
     /// variables and any stored secrets for the component.
     async fn build_default_template(&self, component_id: &str) -> Arc<WasiStateTemplate> {
         let mut config_vars = self.environment_vars.as_ref().clone();
 
-        if let Ok(secrets) = self.secrets.load_component_secrets(component_id).await {
+        if let Some(secrets) = self.secrets.template_secrets(component_id).await {
             for (key, value) in secrets {
                 config_vars.insert(key, value);
             }
@@ -171,14 +317,45 @@ impl PolicyManager {
             policy_uri,
             &self.oci_client,
             &self.http_client,
+            &LoadContext::default(),
         )
         .await?;
 
-        let policy = PolicyParser::parse_file(downloaded_policy.as_ref())?;
+        let content = tokio::fs::read_to_string(downloaded_policy.as_ref()).await?;
+        let policy = self
+            .parse_policy(&content)
+            .with_context(|| format!("Invalid policy {policy_uri}"))?;
 
         let policy_path = self.policy_path(component_id);
         tokio::fs::copy(downloaded_policy.as_ref(), &policy_path).await?;
 
+        self.activate_policy(component_id, &policy, policy_uri)
+            .await
+    }
+
+    /// Attach an in-memory policy document, e.g. one embedded in a component manifest.
+    pub(crate) async fn attach_policy_document(
+        &self,
+        component_id: &str,
+        policy: &PolicyDocument,
+        source_uri: &str,
+    ) -> Result<()> {
+        info!(
+            component_id,
+            source_uri, "Attaching policy document to component"
+        );
+        policy.validate()?;
+        self.save_component_policy(component_id, policy).await?;
+        self.activate_policy(component_id, policy, source_uri).await
+    }
+
+    /// Record where an attached policy came from and start enforcing it.
+    async fn activate_policy(
+        &self,
+        component_id: &str,
+        policy: &PolicyDocument,
+        policy_uri: &str,
+    ) -> Result<()> {
         let metadata = serde_json::json!({
             "source_uri": policy_uri,
             "attached_at": std::time::SystemTime::now()
@@ -189,10 +366,10 @@ impl PolicyManager {
         let metadata_path = self.metadata_path(component_id);
         tokio::fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?).await?;
 
-        let secrets = self.secrets.load_component_secrets(component_id).await.ok();
+        let secrets = self.secrets.template_secrets(component_id).await;
 
         let wasi_template = crate::create_wasi_state_template_from_policy(
-            &policy,
+            policy,
             self.storage.root(),
             self.environment_vars.as_ref(),
             secrets.as_ref(),
@@ -265,7 +442,7 @@ impl PolicyManager {
         component_id: &str,
         policy: &PolicyDocument,
     ) -> Result<()> {
-        let secrets = self.secrets.load_component_secrets(component_id).await.ok();
+        let secrets = self.secrets.template_secrets(component_id).await;
 
         let wasi_template = crate::create_wasi_state_template_from_policy(
             policy,
@@ -287,10 +464,10 @@ impl PolicyManager {
             return Ok(());
         }
 
-        let secrets = self.secrets.load_component_secrets(component_id).await.ok();
+        let secrets = self.secrets.template_secrets(component_id).await;
 
         match tokio::fs::read_to_string(&policy_path).await {
-            Ok(policy_content) => match PolicyParser::parse_str(&policy_content) {
+            Ok(policy_content) => match self.parse_policy(&policy_content) {
                 Ok(policy) => match crate::create_wasi_state_template_from_policy(
                     &policy,
                     self.storage.root(),
@@ -371,8 +548,26 @@ impl PolicyManager {
                     .get("host")
                     .and_then(|v| v.as_str())
                     .ok_or_else(|| anyhow!("Missing 'host' field for network permission"))?;
+                let ports = details
+                    .get("ports")
+                    .map(|ports| serde_json::from_value(ports.clone()))
+                    .transpose()
+                    .context("'ports' must be a list of port numbers")?;
+                let schemes = details
+                    .get("schemes")
+                    .map(|schemes| serde_json::from_value(schemes.clone()))
+                    .transpose()
+                    .context("'schemes' must be a list of URL schemes")?;
+                let address_family = details
+                    .get("address-family")
+                    .map(|family| serde_json::from_value(family.clone()))
+                    .transpose()
+                    .context("'address-family' must be v4-only, v6-only or dual")?;
                 PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission {
                     host: host.to_string(),
+                    ports,
+                    schemes,
+                    address_family,
                 }))
             }
             "storage" => {
@@ -420,8 +615,10 @@ impl PolicyManager {
                     .get("key")
                     .and_then(|v| v.as_str())
                     .ok_or_else(|| anyhow!("Missing 'key' field for environment permission"))?;
+                let value = details.get("value").and_then(|v| v.as_str());
                 PermissionRule::Environment(EnvironmentPermission {
                     key: key.to_string(),
+                    value: value.map(str::to_string),
                 })
             }
             "resource" => {
@@ -475,7 +672,7 @@ impl PolicyManager {
 
         if policy_path.exists() {
             let policy_content = tokio::fs::read_to_string(&policy_path).await?;
-            Ok(PolicyParser::parse_str(&policy_content)?)
+            self.parse_policy(&policy_content)
         } else {
             // Create minimal policy document
             Ok(policy::PolicyDocument {
@@ -530,9 +727,13 @@ impl PolicyManager {
             .allow
             .get_or_insert_with(Vec::new);
 
-        // Only add if not already present (prevent duplicates)
-        if !allow_set.contains(&network) {
-            allow_set.push(network);
+        // A rule for a destination already present replaces it (prevent duplicates)
+        match allow_set
+            .iter_mut()
+            .find(|perm| perm.destination() == network.destination())
+        {
+            Some(existing) => *existing = network,
+            None => allow_set.push(network),
         }
 
         Ok(())
@@ -598,9 +799,10 @@ impl PolicyManager {
             .allow
             .get_or_insert_with(Vec::new);
 
-        // Only add if not already present (prevent duplicates)
-        if !allow_set.contains(&env) {
-            allow_set.push(env);
+        // A key is granted once, with the value of the latest grant
+        match allow_set.iter_mut().find(|perm| perm.key == env.key) {
+            Some(existing) => *existing = env,
+            None => allow_set.push(env),
         }
 
         Ok(())
@@ -666,23 +868,26 @@ impl PolicyManager {
     /// Validate permission rule
     fn validate_permission_rule(&self, rule: &PermissionRule) -> Result<()> {
         match rule {
-            PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission { host })) => {
-                if host.is_empty() {
+            PermissionRule::Network(network) => {
+                if network.destination().is_empty() {
                     return Err(anyhow!("Network host cannot be empty"));
                 }
+                network.validate()?;
             }
             PermissionRule::Storage(storage) => {
-                // TODO: the validation should verify if the uri is actually valid or not
-                if storage.uri.is_empty() {
-                    return Err(anyhow!("Storage URI cannot be empty"));
-                }
                 // Note: access can be empty for revocation operations, but not for grant operations
                 // The validation for non-empty access is now done during parsing
+                Permissions::validate_storage_uri(&storage.uri)?;
             }
             PermissionRule::Environment(env) => {
                 if env.key.is_empty() {
                     return Err(anyhow!("Environment variable key cannot be empty"));
                 }
+                if let Some(value) = &env.value {
+                    env_references::references(value).with_context(|| {
+                        format!("Invalid value of environment variable {}", env.key)
+                    })?;
+                }
             }
             _ => {}
         }
@@ -774,7 +979,8 @@ impl PolicyManager {
     ) -> Result<()> {
         if let Some(network_perms) = &mut policy.permissions.network {
             if let Some(allow_set) = &mut network_perms.allow {
-                allow_set.retain(|perm| perm != &network);
+                // Remove the rule for the destination, regardless of its ports and schemes
+                allow_set.retain(|perm| perm.destination() != network.destination());
                 // Clean up empty structures
                 if allow_set.is_empty() {
                     network_perms.allow = None;
@@ -811,7 +1017,7 @@ impl PolicyManager {
     ) -> Result<()> {
         if let Some(env_perms) = &mut policy.permissions.environment {
             if let Some(allow_set) = &mut env_perms.allow {
-                allow_set.retain(|perm| perm != &env);
+                allow_set.retain(|perm| perm.key != env.key);
                 // Clean up empty structures
                 if allow_set.is_empty() {
                     env_perms.allow = None;
@@ -847,6 +1053,8 @@ impl PolicyManager {
 
 #[cfg(test)]
 mod tests {
+    use policy::AddressFamily;
+
     use super::*;
     use crate::tests::*;
 
@@ -972,6 +1180,132 @@ permissions: {}
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_grant_network_permission_with_ports_and_schemes() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+
+        let host_only = serde_json::json!({"host": "*.github.com"});
+        manager
+            .grant_permission(TEST_COMPONENT_ID, "network", &host_only)
+            .await?;
+        // Granting the same host again narrows the existing rule.
+        let restricted = serde_json::json!({
+            "host": "*.github.com",
+            "ports": [443],
+            "schemes": ["https"],
+            "address-family": "v4-only",
+        });
+        manager
+            .grant_permission(TEST_COMPONENT_ID, "network", &restricted)
+            .await?;
+
+        let policy_path = manager.get_component_policy_path(TEST_COMPONENT_ID);
+        let policy: PolicyDocument =
+            serde_yaml::from_str(&tokio::fs::read_to_string(&policy_path).await?)?;
+        let allow = policy.permissions.network.unwrap().allow.unwrap();
+        assert_eq!(
+            allow,
+            vec![NetworkPermission::Host(NetworkHostPermission {
+                host: "*.github.com".to_string(),
+                ports: Some(vec![443]),
+                schemes: Some(vec!["https".to_string()]),
+                address_family: Some(AddressFamily::V4Only),
+            })]
+        );
+
+        let bad_port = serde_json::json!({"host": "example.com", "ports": [0]});
+        assert!(manager
+            .grant_permission(TEST_COMPONENT_ID, "network", &bad_port)
+            .await
+            .is_err());
+        let bad_schemes = serde_json::json!({"host": "example.com", "schemes": "https"});
+        assert!(manager
+            .grant_permission(TEST_COMPONENT_ID, "network", &bad_schemes)
+            .await
+            .is_err());
+        let bad_family = serde_json::json!({"host": "example.com", "address-family": "v5"});
+        assert!(manager
+            .grant_permission(TEST_COMPONENT_ID, "network", &bad_family)
+            .await
+            .is_err());
+
+        manager
+            .revoke_permission(TEST_COMPONENT_ID, "network", &host_only)
+            .await?;
+        let policy_path = manager.get_component_policy_path(TEST_COMPONENT_ID);
+        let policy: PolicyDocument =
+            serde_yaml::from_str(&tokio::fs::read_to_string(&policy_path).await?)?;
+        assert!(policy
+            .permissions
+            .network
+            .is_none_or(|network| network.allow.is_none()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_injected_variables_resolve_references() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let manager = crate::LifecycleManager::builder(tempdir.path())
+            .with_secrets_dir(tempdir.path().join("secrets"))
+            .with_environment_vars(HashMap::from([
+                ("DB_HOST".to_string(), "db.internal".to_string()),
+                ("REGION".to_string(), "eu-${env:DB_HOST}".to_string()),
+            ]))
+            .build()
+            .await?;
+        manager
+            .set_component_secrets(
+                "svc",
+                &[
+                    ("api-token".to_string(), "s3cr3t".to_string()),
+                    ("REGION".to_string(), "us".to_string()),
+                ],
+            )
+            .await?;
+        let policy = r#"
+version: "1.0"
+permissions:
+  environment:
+    allow:
+      - key: API_TOKEN
+        value: "Bearer ${secret:api-token}"
+      - key: REGION
+"#;
+        tokio::fs::write(manager.get_component_policy_path("svc"), policy).await?;
+
+        let variables = manager.resolve_component_environment("svc").await?;
+        let summary: Vec<_> = variables
+            .iter()
+            .map(|variable| {
+                (
+                    variable.key.as_str(),
+                    variable.value.as_str(),
+                    variable.source,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("API_TOKEN", "Bearer s3cr3t", VariableSource::Policy),
+                ("REGION", "eu-db.internal", VariableSource::Environment),
+                ("api-token", "s3cr3t", VariableSource::Secret),
+            ]
+        );
+        assert_eq!(variables[0].references, vec!["secret:api-token"]);
+
+        manager
+            .delete_component_secrets("svc", &["api-token".to_string()])
+            .await?;
+        let missing = manager
+            .resolve_component_environment("svc")
+            .await
+            .unwrap_err();
+        assert!(format!("{missing:#}").contains("Secret api-token is not set"));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_grant_permission_duplicate_prevention() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -1195,6 +1529,26 @@ permissions: {}
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_grant_permission_validation_storage_uri() -> Result<()> {
+        let manager = create_test_manager().await?;
+        manager.load_test_component().await?;
+
+        for uri in ["", "kv://cache-*", "fs:///tmp/***"] {
+            let details = serde_json::json!({"uri": uri, "access": ["read"]});
+            let result = manager
+                .grant_permission(TEST_COMPONENT_ID, "storage", &details)
+                .await;
+            assert!(result.is_err(), "{uri} should be refused");
+        }
+        let details = serde_json::json!({"uri": "kv://cache", "access": ["read", "write"]});
+        manager
+            .grant_permission(TEST_COMPONENT_ID, "storage", &details)
+            .await?;
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_grant_permission_multiple_permissions() -> Result<()> {
         let manager = create_test_manager().await?;
@@ -1287,6 +1641,7 @@ permissions:
         let network_rule =
             PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission {
                 host: "example.com".to_string(),
+                ..Default::default()
             }));
         let serialized = serde_json::to_string(&network_rule)?;
         assert!(serialized.contains("example.com"));
@@ -1309,6 +1664,7 @@ permissions:
         let network_perm =
             PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission {
                 host: "example.com".to_string(),
+                ..Default::default()
             }));
         let storage_perm = PermissionRule::Storage(StoragePermission {
             uri: "fs:///tmp".to_string(),
@@ -1316,6 +1672,7 @@ permissions:
         });
         let env_perm = PermissionRule::Environment(EnvironmentPermission {
             key: "API_KEY".to_string(),
+            value: None,
         });
         let custom_perm = PermissionRule::Custom(
             "custom-type".to_string(),
@@ -1335,9 +1692,13 @@ permissions:
         // Test pattern matching works correctly
         let rule = PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission {
             host: "test.com".to_string(),
+            ..Default::default()
         }));
         match rule {
-            PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission { host })) => {
+            PermissionRule::Network(NetworkPermission::Host(NetworkHostPermission {
+                host,
+                ..
+            })) => {
                 assert_eq!(host, "test.com");
             }
             _ => panic!("Expected network permission"),
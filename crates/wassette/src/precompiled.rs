@@ -0,0 +1,173 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Disk cache of compiled components.
+//!
+//! Compiling a large Python or JavaScript component with Cranelift takes seconds, on every
+//! start of the server. The compiled artifact is therefore kept next to the component as
+//! `<component>.cwasm`, behind a header holding the SHA-256 digest of the component and a hash
+//! of the engine's compilation settings. A cached artifact is only used when both match, so a
+//! replaced component, a different Wasmtime release or an engine configured differently (for
+//! instance with other `wasm_proposals`) compiles the component again instead of running a
+//! stale artifact.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+/// Marks a cache file written by this module.
+const MAGIC: &[u8; 8] = b"wassette";
+
+/// Length of the header: the magic, the component digest and the engine hash.
+const HEADER_LEN: usize = MAGIC.len() + 32 + 8;
+
+/// Where a compiled component came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CacheUse {
+    /// Deserialized from the cache
+    Hit,
+    /// Compiled, and stored in the cache
+    Miss,
+}
+
+/// The header of the cache file for `wasm_bytes` compiled by `engine`.
+fn header(engine: &Engine, wasm_bytes: &[u8]) -> Vec<u8> {
+    let mut hasher = DefaultHasher::new();
+    engine.precompile_compatibility_hash().hash(&mut hasher);
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&Sha256::digest(wasm_bytes));
+    header.extend_from_slice(&hasher.finish().to_le_bytes());
+    header
+}
+
+/// Compile the component `wasm_bytes`, or deserialize it from the cache file at `path` if that
+/// holds it compiled by a compatible engine. A fresh compilation is stored at `path`; failing
+/// to store it is logged and otherwise ignored.
+///
+/// Blocks on file IO and compilation.
+pub(crate) fn load_or_compile(
+    engine: &Engine,
+    path: &Path,
+    wasm_bytes: &[u8],
+) -> Result<(Component, CacheUse)> {
+    let header = header(engine, wasm_bytes);
+    if let Ok(cached) = std::fs::read(path) {
+        match cached.strip_prefix(header.as_slice()) {
+            // SAFETY: the artifact was written below by `precompile_component`, into the plugin
+            // directory that is trusted like the components it holds, and the header ties it
+            // to these component bytes and to this engine's settings.
+            Some(artifact) => match unsafe { Component::deserialize(engine, artifact) } {
+                Ok(component) => return Ok((component, CacheUse::Hit)),
+                Err(error) => warn!(
+                    path = %path.display(),
+                    %error,
+                    "Ignoring unusable precompiled component"
+                ),
+            },
+            None => debug!(path = %path.display(), "Precompiled component is out of date"),
+        }
+    }
+
+    let artifact = engine.precompile_component(wasm_bytes)?;
+    // SAFETY: the artifact was just compiled by this engine.
+    let component = unsafe { Component::deserialize(engine, &artifact)? };
+    if let Err(error) = store(path, &header, &artifact) {
+        warn!(path = %path.display(), %error, "Failed to save precompiled component");
+    }
+    Ok((component, CacheUse::Miss))
+}
+
+/// Replace the cache file at `path` atomically, so a concurrent reader never sees part of it.
+fn store(path: &Path, header: &[u8], artifact: &[u8]) -> Result<()> {
+    let staged = path.with_extension(format!("{}.tmp", crate::PRECOMPILED_EXT));
+    let mut file = std::fs::File::create(&staged)
+        .with_context(|| format!("Failed to create {}", staged.display()))?;
+    file.write_all(header)
+        .and_then(|()| file.write_all(artifact))
+        .with_context(|| format!("Failed to write {}", staged.display()))?;
+    drop(file);
+    std::fs::rename(&staged, path).with_context(|| {
+        format!(
+            "Failed to write precompiled component to {}",
+            path.display()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn engine(consume_fuel: bool) -> Engine {
+        let mut config = wasmtime::Config::new();
+        config.wasm_component_model(true);
+        config.consume_fuel(consume_fuel);
+        Engine::new(&config).unwrap()
+    }
+
+    fn component(export: &str) -> Vec<u8> {
+        wat::parse_str(format!(
+            r#"(component (core module (func (export "{export}"))))"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cache_is_reused_until_the_component_changes() -> Result<()> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join("tool.cwasm");
+        let engine = engine(false);
+
+        let (_, cache) = load_or_compile(&engine, &path, &component("a"))?;
+        assert_eq!(cache, CacheUse::Miss);
+        assert!(std::fs::read(&path)?.starts_with(MAGIC));
+        let (_, cache) = load_or_compile(&engine, &path, &component("a"))?;
+        assert_eq!(cache, CacheUse::Hit);
+
+        let (_, cache) = load_or_compile(&engine, &path, &component("b"))?;
+        assert_eq!(cache, CacheUse::Miss);
+        let (_, cache) = load_or_compile(&engine, &path, &component("b"))?;
+        assert_eq!(cache, CacheUse::Hit);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_is_recompiled_for_other_engines_and_damaged_files() -> Result<()> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join("tool.cwasm");
+        let bytes = component("a");
+
+        load_or_compile(&engine(false), &path, &bytes)?;
+        let (_, cache) = load_or_compile(&engine(true), &path, &bytes)?;
+        assert_eq!(cache, CacheUse::Miss);
+
+        // An artifact from an older release, without the header
+        std::fs::write(&path, engine(true).precompile_component(&bytes)?)?;
+        let (_, cache) = load_or_compile(&engine(true), &path, &bytes)?;
+        assert_eq!(cache, CacheUse::Miss);
+
+        // A header followed by a truncated artifact
+        let mut damaged = header(&engine(true), &bytes);
+        damaged.extend_from_slice(b"\0asm");
+        std::fs::write(&path, damaged)?;
+        let (_, cache) = load_or_compile(&engine(true), &path, &bytes)?;
+        assert_eq!(cache, CacheUse::Miss);
+        assert_eq!(
+            load_or_compile(&engine(true), &path, &bytes)?.1,
+            CacheUse::Hit
+        );
+
+        assert!(load_or_compile(&engine(true), &path, b"not wasm").is_err());
+        Ok(())
+    }
+}
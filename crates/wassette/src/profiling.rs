@@ -0,0 +1,153 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Guest profiling for selected components using Wasmtime's built-in sampling profiler.
+//!
+//! Each profiled invocation writes one profile in the Firefox Profiler JSON format to
+//! `<plugin_dir>/profiles/<component-id>-<call-id>.json`. The files can be opened with
+//! <https://profiler.firefox.com> or imported into speedscope.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use wasmtime::component::Component;
+use wasmtime::{GuestProfiler, Store, UpdateDeadline};
+
+use crate::runtime_context::EPOCH_TICK;
+use crate::{limits, WasiState, WassetteWasiState};
+
+/// Directory (relative to the plugin directory) where guest profiles are written.
+pub(crate) const PROFILES_DIR: &str = "profiles";
+
+/// Epoch deadline used for stores that are not being profiled, so the engine-wide epoch
+/// ticker never interrupts them.
+const NO_DEADLINE: u64 = u64::MAX / 2;
+
+/// Configure a store that runs on an engine with epoch interruption but isn't profiled.
+pub(crate) fn disable_sampling(store: &mut Store<WassetteWasiState<WasiState>>) {
+    store.set_epoch_deadline(NO_DEADLINE);
+}
+
+/// Attach a guest profiler to `store` that samples the call stack on every epoch tick, in
+/// place of the deadline check set up by [`limits::start_deadline`].
+pub(crate) fn start(
+    store: &mut Store<WassetteWasiState<WasiState>>,
+    component_id: &str,
+    component: &Component,
+) {
+    let profiler = GuestProfiler::new_component(
+        component_id,
+        EPOCH_TICK,
+        component.clone(),
+        std::iter::empty(),
+    );
+    store.data_mut().inner.guest_profiler = Some(Box::new(profiler));
+
+    store.set_epoch_deadline(1);
+    store.epoch_deadline_callback(|mut ctx| {
+        limits::check_deadline(&ctx.data().inner)?;
+        if let Some(mut profiler) = ctx.data_mut().inner.guest_profiler.take() {
+            profiler.sample(&ctx, EPOCH_TICK);
+            ctx.data_mut().inner.guest_profiler = Some(profiler);
+        }
+        Ok(UpdateDeadline::Yield(1))
+    });
+}
+
+/// Detach the profiler from `store` and write the collected profile to `dir`.
+///
+/// Returns the path of the written profile, or `None` if the store wasn't being profiled.
+pub(crate) async fn finish(
+    store: &mut Store<WassetteWasiState<WasiState>>,
+    dir: &Path,
+    component_id: &str,
+    call_id: &str,
+) -> Result<Option<PathBuf>> {
+    let Some(profiler) = store.data_mut().inner.guest_profiler.take() else {
+        return Ok(None);
+    };
+
+    let mut profile = Vec::new();
+    profiler
+        .finish(&mut profile)
+        .context("Failed to serialize guest profile")?;
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("Failed to create profile directory {}", dir.display()))?;
+    let path = dir.join(format!("{component_id}-{call_id}.json"));
+    tokio::fs::write(&path, profile)
+        .await
+        .with_context(|| format!("Failed to write profile {}", path.display()))?;
+
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::component::Linker;
+    use wasmtime::{Config, Engine};
+
+    use super::*;
+    use crate::WasiStateTemplate;
+
+    const SPIN: &str = r#"(component
+        (core module $m
+            (func (export "spin") (result i32)
+                (local $i i32)
+                (loop $again
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br_if $again (i32.lt_u (local.get $i) (i32.const 20000000))))
+                (local.get $i)))
+        (core instance $i (instantiate $m))
+        (func (export "spin") (result u32) (canon lift (core func $i "spin")))
+    )"#;
+
+    #[tokio::test]
+    async fn test_profiled_call_writes_profile() -> Result<()> {
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        // The sampling callback yields, as the server's stores do.
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let component = Component::new(&engine, wat::parse_str(SPIN)?)?;
+        let state = WassetteWasiState::with_rules(
+            WasiStateTemplate::default().build()?,
+            Default::default(),
+        );
+        let mut store = Store::new(&engine, state);
+        start(&mut store, "spin", &component);
+
+        let instance = Linker::new(&engine)
+            .instantiate_async(&mut store, &component)
+            .await?;
+        let spin = instance.get_typed_func::<(), (u32,)>(&mut store, "spin")?;
+        let ticker = std::thread::spawn({
+            let engine = engine.weak();
+            move || {
+                for _ in 0..20 {
+                    if let Some(engine) = engine.upgrade() {
+                        engine.increment_epoch();
+                    }
+                    std::thread::sleep(EPOCH_TICK / 10);
+                }
+            }
+        });
+        assert_eq!(spin.call_async(&mut store, ()).await?, (20_000_000,));
+        ticker.join().unwrap();
+
+        let dir = tempfile::tempdir()?;
+        let path = finish(&mut store, dir.path(), "spin", "call-1")
+            .await?
+            .expect("the store was profiled");
+        assert_eq!(path, dir.path().join("spin-call-1.json"));
+        let profile: serde_json::Value = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+        assert!(profile["meta"].is_object());
+        assert!(profile["threads"].is_array());
+
+        // Stores that weren't profiled write nothing.
+        assert!(finish(&mut store, dir.path(), "spin", "call-2")
+            .await?
+            .is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Progress reporting and cancellation for component loads.
+//!
+//! Pulling and compiling a multi-hundred-megabyte component can take minutes. A [`LoadContext`]
+//! lets the caller observe download and compilation progress and abandon the load while the
+//! component is still being fetched.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use anyhow::{bail, Result};
+use tokio::io::AsyncWrite;
+use tokio_util::sync::CancellationToken;
+
+use crate::oci_pull::OciPull;
+
+/// Download progress is reported at least every this many bytes.
+const PROGRESS_STEP_BYTES: u64 = 1024 * 1024;
+
+/// Phase of a component load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStage {
+    /// Fetching the component from its source
+    Downloading,
+    /// Compiling the component to native code
+    Compiling,
+}
+
+impl fmt::Display for LoadStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadStage::Downloading => f.write_str("downloading"),
+            LoadStage::Compiling => f.write_str("compiling"),
+        }
+    }
+}
+
+/// A progress update for a component load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadProgress {
+    /// Current phase
+    pub stage: LoadStage,
+    /// Bytes downloaded so far; zero outside the download stage
+    pub bytes: u64,
+    /// Size of the download, if known
+    pub total_bytes: Option<u64>,
+}
+
+/// Callback receiving [`LoadProgress`] updates.
+pub type LoadProgressFn = Arc<dyn Fn(LoadProgress) + Send + Sync>;
+
+/// Per-load context: where progress goes and how the load is cancelled.
+#[derive(Clone, Default)]
+pub struct LoadContext {
+    progress: Option<LoadProgressFn>,
+    cancellation: CancellationToken,
+    pull: Option<Arc<OciPull>>,
+    /// Keep the policy of the component being replaced instead of one published with the new
+    /// version
+    keep_policy: bool,
+}
+
+impl LoadContext {
+    /// Report progress of the load to `progress`.
+    pub fn with_progress(
+        mut self,
+        progress: impl Fn(LoadProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Abandon the load when `token` is cancelled. Cancellation takes effect until the
+    /// component has been downloaded; once it replaces the stored artifact, the load runs to
+    /// completion.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Use the manager's OCI pull settings for this load.
+    pub(crate) fn with_pull(mut self, pull: Arc<OciPull>) -> Self {
+        self.pull = Some(pull);
+        self
+    }
+
+    /// Keep the policy of the component the load replaces, as updates do.
+    pub(crate) fn keeping_policy(mut self) -> Self {
+        self.keep_policy = true;
+        self
+    }
+
+    pub(crate) fn keeps_policy(&self) -> bool {
+        self.keep_policy
+    }
+
+    /// OCI pull settings, falling back to the defaults outside a manager.
+    pub(crate) fn pull(&self) -> Arc<OciPull> {
+        self.pull.clone().unwrap_or_default()
+    }
+
+    pub(crate) fn report(&self, stage: LoadStage, bytes: u64, total_bytes: Option<u64>) {
+        if let Some(progress) = &self.progress {
+            progress(LoadProgress {
+                stage,
+                bytes,
+                total_bytes,
+            });
+        }
+    }
+
+    /// Fail if the load was cancelled.
+    pub(crate) fn check_cancelled(&self) -> Result<()> {
+        if self.cancellation.is_cancelled() {
+            bail!("Component load was cancelled");
+        }
+        Ok(())
+    }
+
+    /// Run `fut`, failing early if the load is cancelled meanwhile.
+    pub(crate) async fn cancellable<T>(&self, fut: impl Future<Output = Result<T>>) -> Result<T> {
+        tokio::select! {
+            result = fut => result,
+            _ = self.cancellation.cancelled() => bail!("Component load was cancelled"),
+        }
+    }
+
+    /// Wrap `inner` so the bytes written to it are reported as download progress.
+    pub(crate) fn track_download<W>(
+        &self,
+        inner: W,
+        total_bytes: Option<u64>,
+    ) -> DownloadWriter<'_, W> {
+        DownloadWriter {
+            inner,
+            context: self,
+            bytes: 0,
+            reported: 0,
+            total_bytes,
+        }
+    }
+}
+
+/// Writer that counts downloaded bytes and reports them in steps.
+pub(crate) struct DownloadWriter<'a, W> {
+    inner: W,
+    context: &'a LoadContext,
+    bytes: u64,
+    reported: u64,
+    total_bytes: Option<u64>,
+}
+
+impl<W> DownloadWriter<'_, W> {
+    /// Count `bytes` already downloaded by an earlier attempt.
+    pub(crate) fn resuming_at(mut self, bytes: u64) -> Self {
+        self.bytes = bytes;
+        self.reported = bytes;
+        self
+    }
+
+    fn step(&self) -> u64 {
+        match self.total_bytes {
+            Some(total) => (total / 100).max(PROGRESS_STEP_BYTES),
+            None => PROGRESS_STEP_BYTES,
+        }
+    }
+
+    /// Report the final byte count.
+    pub(crate) fn finish(&self) {
+        self.context
+            .report(LoadStage::Downloading, self.bytes, self.total_bytes);
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for DownloadWriter<'_, W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.bytes += written as u64;
+            if self.bytes - self.reported >= self.step() {
+                self.reported = self.bytes;
+                self.context
+                    .report(LoadStage::Downloading, self.bytes, self.total_bytes);
+            }
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_download_progress_is_reported_in_steps() {
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&updates);
+        let context = LoadContext::default()
+            .with_progress(move |progress| sink.lock().unwrap().push(progress.bytes));
+
+        let total = 3 * PROGRESS_STEP_BYTES + 10;
+        let mut writer = context.track_download(Vec::new(), Some(total));
+        let chunk = vec![0u8; (PROGRESS_STEP_BYTES / 2) as usize];
+        while writer.bytes + chunk.len() as u64 <= total {
+            writer.write_all(&chunk).await.unwrap();
+        }
+        writer.write_all(&[0u8; 10]).await.unwrap();
+        writer.finish();
+
+        assert_eq!(
+            *updates.lock().unwrap(),
+            vec![
+                PROGRESS_STEP_BYTES,
+                2 * PROGRESS_STEP_BYTES,
+                3 * PROGRESS_STEP_BYTES,
+                total
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_load_stops_waiting() {
+        let token = CancellationToken::new();
+        let context = LoadContext::default().with_cancellation(token.clone());
+        assert!(context.check_cancelled().is_ok());
+
+        token.cancel();
+        let result = context
+            .cancellable(std::future::pending::<Result<()>>())
+            .await;
+        assert!(result.unwrap_err().to_string().contains("cancelled"));
+        assert!(context.check_cancelled().is_err());
+    }
+}
@@ -0,0 +1,336 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Prompt templates exported by components.
+//!
+//! A component ships recommended prompts for its tools by exporting
+//!
+//! ```wit
+//! package wassette:prompts;
+//!
+//! interface prompts {
+//!     record prompt-argument {
+//!         name: string,
+//!         description: option<string>,
+//!         required: bool,
+//!     }
+//!
+//!     record prompt {
+//!         name: string,
+//!         description: option<string>,
+//!         arguments: list<prompt-argument>,
+//!         template: string,
+//!     }
+//!
+//!     list-prompts: func() -> list<prompt>;
+//! }
+//! ```
+//!
+//! The prompts are read once when the component is loaded and kept in its metadata, so
+//! listing them doesn't run the component. Templates refer to their arguments as `{{name}}`.
+//! The interface is not exposed as a tool.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use component2json::ToolMetadata;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::warn;
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{Component, ComponentType, Instance, Lift};
+use wasmtime::{Engine, Store};
+
+use crate::{WasiState, WassetteWasiState};
+
+/// Name of the interface components export their prompts from.
+pub const PROMPTS_INTERFACE: &str = "wassette:prompts/prompts";
+
+/// Name of the function listing the prompts.
+const LIST_EXPORT: &str = "list-prompts";
+
+/// Most prompts kept per component.
+const MAX_PROMPTS: usize = 100;
+
+/// Longest template kept, in bytes.
+const MAX_TEMPLATE_BYTES: usize = 64 * 1024;
+
+/// An argument of a prompt template.
+#[derive(ComponentType, Lift, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[component(record)]
+pub struct PromptArgument {
+    /// Name the template refers to the argument by
+    pub name: String,
+    /// What the argument is for
+    pub description: Option<String>,
+    /// Whether the prompt can't be rendered without the argument
+    pub required: bool,
+}
+
+/// A prompt template a component recommends for its tools.
+#[derive(ComponentType, Lift, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[component(record)]
+pub struct Prompt {
+    /// Name of the prompt, unique within the component
+    pub name: String,
+    /// What the prompt does
+    pub description: Option<String>,
+    /// Arguments the template refers to
+    pub arguments: Vec<PromptArgument>,
+    /// Text of the prompt, with `{{argument}}` placeholders
+    pub template: String,
+}
+
+impl Prompt {
+    /// The template with its placeholders replaced by `arguments`. Placeholders of optional
+    /// arguments that weren't given are left empty, those of undeclared arguments as they are.
+    pub fn render(&self, arguments: &HashMap<String, String>) -> Result<String> {
+        let missing: Vec<&str> = self
+            .arguments
+            .iter()
+            .filter(|argument| argument.required && !arguments.contains_key(&argument.name))
+            .map(|argument| argument.name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            bail!("Missing required arguments: {}", missing.join(", "));
+        }
+
+        let mut text = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 2..start + 2 + end].trim();
+            text.push_str(&rest[..start]);
+            if self.arguments.iter().any(|argument| argument.name == name) {
+                text.push_str(arguments.get(name).map_or("", String::as_str));
+            } else {
+                text.push_str(&rest[start..start + 2 + end + 2]);
+            }
+            rest = &rest[start + 2 + end + 2..];
+        }
+        text.push_str(rest);
+        Ok(text)
+    }
+}
+
+/// A prompt of a loaded component, under the name clients get it by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentPrompt {
+    /// Component the prompt belongs to
+    pub component_id: String,
+    /// The prompt's name, qualified with the component id when another component uses it too
+    pub name: String,
+    /// The prompt
+    pub prompt: Prompt,
+}
+
+/// The name of the prompts interface `component` exports, if it does.
+pub(crate) fn prompts_export(component: &Component, engine: &Engine) -> Option<String> {
+    component
+        .component_type()
+        .exports(engine)
+        .find_map(|(name, item)| {
+            let versioned = name
+                .strip_prefix(PROMPTS_INTERFACE)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('@'));
+            (versioned && matches!(item, ComponentItem::ComponentInstance(_)))
+                .then(|| name.to_string())
+        })
+}
+
+/// Remove the functions of the prompts interface from the tools generated for a component.
+pub(crate) fn strip_prompts_export(tools: &mut Vec<ToolMetadata>, interface: Option<&str>) {
+    if let Some(interface) = interface {
+        tools.retain(|tool| tool.identifier.interface_name.as_deref() != Some(interface));
+    }
+}
+
+/// Call `list-prompts` of the prompts `interface` on an instantiated component, dropping
+/// prompts that are unnamed, named twice or too large.
+pub(crate) async fn list(
+    store: &mut Store<WassetteWasiState<WasiState>>,
+    instance: &Instance,
+    interface: &str,
+) -> Result<Vec<Prompt>> {
+    let parent = instance
+        .get_export_index(&mut *store, None, interface)
+        .with_context(|| format!("Interface not found: {interface}"))?;
+    let index = instance
+        .get_export_index(&mut *store, Some(&parent), LIST_EXPORT)
+        .with_context(|| format!("{interface} doesn't export {LIST_EXPORT}"))?;
+    let func = instance
+        .get_typed_func::<(), (Vec<Prompt>,)>(&mut *store, index)
+        .with_context(|| format!("{LIST_EXPORT} has an unexpected signature"))?;
+    let (prompts,) = func.call_async(&mut *store, ()).await?;
+    Ok(checked(prompts))
+}
+
+fn checked(prompts: Vec<Prompt>) -> Vec<Prompt> {
+    let mut names = HashSet::new();
+    let mut kept = Vec::new();
+    for prompt in prompts {
+        if prompt.name.is_empty() || !names.insert(prompt.name.clone()) {
+            warn!(name = %prompt.name, "Dropping prompt without a unique name");
+        } else if prompt.template.len() > MAX_TEMPLATE_BYTES {
+            warn!(name = %prompt.name, "Dropping prompt with an oversized template");
+        } else if kept.len() == MAX_PROMPTS {
+            warn!(name = %prompt.name, "Dropping prompts beyond {MAX_PROMPTS}");
+            break;
+        } else {
+            kept.push(prompt);
+        }
+    }
+    kept
+}
+
+/// The prompts of the loaded components.
+#[derive(Debug)]
+pub(crate) struct ComponentPrompts {
+    prompts: Mutex<BTreeMap<String, Vec<Prompt>>>,
+    /// Ids of the components whose prompts changed
+    changes: broadcast::Sender<String>,
+}
+
+impl Default for ComponentPrompts {
+    fn default() -> Self {
+        Self {
+            prompts: Mutex::default(),
+            changes: broadcast::channel(16).0,
+        }
+    }
+}
+
+impl ComponentPrompts {
+    fn prompts(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Vec<Prompt>>> {
+        self.prompts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replace the prompts of `component_id`.
+    pub(crate) fn set(&self, component_id: &str, prompts: Vec<Prompt>) {
+        let changed = {
+            let mut all = self.prompts();
+            let previous = if prompts.is_empty() {
+                all.remove(component_id)
+            } else {
+                all.insert(component_id.to_string(), prompts.clone())
+            };
+            previous.unwrap_or_default() != prompts
+        };
+        if changed {
+            let _ = self.changes.send(component_id.to_string());
+        }
+    }
+
+    /// Drop the prompts of an unloaded component.
+    pub(crate) fn forget(&self, component_id: &str) {
+        self.set(component_id, Vec::new());
+    }
+
+    /// All prompts, by component id and name. Names used by several components are qualified
+    /// as `<component id><separator><name>`.
+    pub(crate) fn list(&self, separator: &str) -> Vec<ComponentPrompt> {
+        let all = self.prompts();
+        let mut uses: HashMap<&str, usize> = HashMap::new();
+        for prompt in all.values().flatten() {
+            *uses.entry(prompt.name.as_str()).or_default() += 1;
+        }
+        let uses = &uses;
+        all.iter()
+            .flat_map(|(component_id, prompts)| {
+                prompts.iter().map(move |prompt| ComponentPrompt {
+                    component_id: component_id.clone(),
+                    name: if uses[prompt.name.as_str()] > 1 {
+                        format!("{component_id}{separator}{}", prompt.name)
+                    } else {
+                        prompt.name.clone()
+                    },
+                    prompt: prompt.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Receive the id of a component whenever its prompts change.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.changes.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt(name: &str) -> Prompt {
+        Prompt {
+            name: name.to_string(),
+            description: None,
+            arguments: vec![
+                PromptArgument {
+                    name: "city".to_string(),
+                    description: None,
+                    required: true,
+                },
+                PromptArgument {
+                    name: "units".to_string(),
+                    description: None,
+                    required: false,
+                },
+            ],
+            template: "Forecast for {{ city }}{{units}}, see {{docs}} and {{".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let prompt = prompt("forecast");
+        let arguments = HashMap::from([("city".to_string(), "Oslo".to_string())]);
+        assert_eq!(
+            prompt.render(&arguments)?,
+            "Forecast for Oslo, see {{docs}} and {{"
+        );
+
+        let error = prompt.render(&HashMap::new()).unwrap_err();
+        assert_eq!(error.to_string(), "Missing required arguments: city");
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_prompts() {
+        let prompts = ComponentPrompts::default();
+        let mut changes = prompts.subscribe();
+        prompts.set("weather", vec![prompt("forecast"), prompt("alerts")]);
+        prompts.set("news", vec![prompt("forecast")]);
+        assert_eq!(changes.try_recv().unwrap(), "weather");
+        assert_eq!(changes.try_recv().unwrap(), "news");
+
+        let names: Vec<String> = prompts.list("__").into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["news__forecast", "weather__forecast", "alerts"]);
+
+        // Loading the same prompts again changes nothing.
+        prompts.set("news", vec![prompt("forecast")]);
+        assert!(changes.try_recv().is_err());
+
+        prompts.forget("news");
+        assert_eq!(changes.try_recv().unwrap(), "news");
+        let names: Vec<String> = prompts.list("__").into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["forecast", "alerts"]);
+    }
+
+    #[test]
+    fn test_checked() {
+        let mut large = prompt("large");
+        large.template = "x".repeat(MAX_TEMPLATE_BYTES + 1);
+        let prompts = checked(vec![
+            prompt("a"),
+            prompt(""),
+            prompt("a"),
+            large,
+            prompt("b"),
+        ]);
+        let names: Vec<&str> = prompts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Optional WebAssembly proposals components may depend on, and which of them the engine
+//! enables.
+//!
+//! The engine enables a fixed set of optional proposals for every component. A component that
+//! uses one outside that set fails to compile with a validation error about an instruction or
+//! memory type, which says little about what to do. When a component fails to compile it is
+//! validated again with each proposal turned off in turn to find those it uses, and the error
+//! names the proposal and how to enable it, or that it can't be enabled.
+//!
+//! Threads and 64-bit memories are the exception: the engine always enables them, since their
+//! memories are created and grown through the store's resource limiter like any other, and
+//! each component that uses them must be granted `resources.threads` or `resources.memory64`
+//! by its policy, together with a memory limit, before it is instantiated.
+//!
+//! The component model's async ABI is off by default, since Wasmtime's support for it is still
+//! incomplete. Adding `component-model-async` to `wasm_proposals` lets components with
+//! async-lifted exports, or with `stream` and `future` types, load; their tools are called
+//! through Wasmtime's concurrent call API, so a call waiting on a host future suspends instead
+//! of holding its thread. Components that don't use the ABI are called as before.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use wasmparser::{Validator, WasmFeatures};
+
+/// An optional WebAssembly proposal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WasmProposal {
+    /// Shared memories and atomic instructions
+    Threads,
+    /// Exception handling
+    Exceptions,
+    /// Memories indexed by 64-bit addresses
+    Memory64,
+    /// Relaxed SIMD instructions, whose results may differ between platforms
+    RelaxedSimd,
+    /// The component model's async ABI: async-lifted exports, `stream` and `future` types
+    ComponentModelAsync,
+}
+
+/// Proposals the engine enables unless configured otherwise.
+pub const DEFAULT_WASM_PROPOSALS: &[WasmProposal] = &[WasmProposal::RelaxedSimd];
+
+/// Proposals the engine always enables, whose use each component's policy must grant.
+pub const PER_COMPONENT_PROPOSALS: &[WasmProposal] =
+    &[WasmProposal::Threads, WasmProposal::Memory64];
+
+impl WasmProposal {
+    /// Every proposal Wassette knows about.
+    pub const ALL: [Self; 5] = [
+        Self::Threads,
+        Self::Exceptions,
+        Self::Memory64,
+        Self::RelaxedSimd,
+        Self::ComponentModelAsync,
+    ];
+
+    /// Whether the proposal can be enabled for all components from the configuration.
+    ///
+    /// Relaxed SIMD and the async ABI only change which instructions and types a component may
+    /// use. Threads and 64-bit memories change how much memory a component can reach, so they
+    /// are granted per component, and exception handling isn't implemented by this Wasmtime
+    /// release.
+    pub fn is_configurable(self) -> bool {
+        matches!(self, Self::RelaxedSimd | Self::ComponentModelAsync)
+    }
+
+    /// The `resources` field of a policy that grants the proposal to a component, for those
+    /// in [`PER_COMPONENT_PROPOSALS`].
+    pub fn policy_field(self) -> Option<&'static str> {
+        match self {
+            Self::Threads => Some("threads"),
+            Self::Memory64 => Some("memory64"),
+            Self::Exceptions | Self::RelaxedSimd | Self::ComponentModelAsync => None,
+        }
+    }
+
+    fn features(self) -> WasmFeatures {
+        match self {
+            Self::Threads => WasmFeatures::THREADS | WasmFeatures::SHARED_EVERYTHING_THREADS,
+            Self::Exceptions => WasmFeatures::EXCEPTIONS | WasmFeatures::LEGACY_EXCEPTIONS,
+            Self::Memory64 => WasmFeatures::MEMORY64,
+            Self::RelaxedSimd => WasmFeatures::RELAXED_SIMD,
+            Self::ComponentModelAsync => {
+                WasmFeatures::CM_ASYNC
+                    | WasmFeatures::CM_ASYNC_BUILTINS
+                    | WasmFeatures::CM_ASYNC_STACKFUL
+            }
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::Threads => "shared memories and atomic instructions",
+            Self::Exceptions => "exception handling",
+            Self::Memory64 => "64-bit memories",
+            Self::RelaxedSimd => "relaxed SIMD instructions",
+            Self::ComponentModelAsync => "the component model's async ABI",
+        }
+    }
+
+    /// Why a component using this proposal can't load while it is disabled, and what to do.
+    fn remedy(self) -> String {
+        if self.is_configurable() {
+            format!("is disabled; add `{self}` to `wasm_proposals` in the configuration file")
+        } else if self == Self::Exceptions {
+            "isn't supported by this Wasmtime release; rebuild the component without exception handling".to_string()
+        } else if let Some(field) = self.policy_field() {
+            format!("must be granted by the component's policy with `resources.{field}`")
+        } else {
+            "is disabled and can't be enabled for all components; rebuild the component without it"
+                .to_string()
+        }
+    }
+}
+
+impl fmt::Display for WasmProposal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Threads => "threads",
+            Self::Exceptions => "exceptions",
+            Self::Memory64 => "memory64",
+            Self::RelaxedSimd => "relaxed-simd",
+            Self::ComponentModelAsync => "component-model-async",
+        })
+    }
+}
+
+/// Check that every proposal in `proposals` can be enabled from the configuration.
+pub(crate) fn validate(proposals: &BTreeSet<WasmProposal>) -> Result<()> {
+    if let Some(proposal) = proposals
+        .iter()
+        .find(|proposal| !proposal.is_configurable())
+    {
+        if let Some(field) = proposal.policy_field() {
+            bail!(
+                "The {proposal} proposal ({}) is granted per component, with `resources.{field}` in the component's policy",
+                proposal.description()
+            );
+        }
+        bail!(
+            "The {proposal} proposal ({}) can't be enabled for all components",
+            proposal.description()
+        );
+    }
+    Ok(())
+}
+
+/// Turn the proposals in `proposals` on and every other known proposal off in `config`.
+pub(crate) fn configure(config: &mut wasmtime::Config, proposals: &BTreeSet<WasmProposal>) {
+    config.wasm_threads(proposals.contains(&WasmProposal::Threads));
+    config.wasm_memory64(proposals.contains(&WasmProposal::Memory64));
+    config.wasm_relaxed_simd(proposals.contains(&WasmProposal::RelaxedSimd));
+    let component_model_async = proposals.contains(&WasmProposal::ComponentModelAsync);
+    config.wasm_component_model_async(component_model_async);
+    config.wasm_component_model_async_builtins(component_model_async);
+    config.wasm_component_model_async_stackful(component_model_async);
+}
+
+/// Refuse to instantiate `component_id`, which uses `proposals`, unless its policy grants
+/// each of them that is granted per component.
+pub(crate) fn check_granted(
+    component_id: &str,
+    proposals: &BTreeSet<WasmProposal>,
+    granted: &BTreeSet<WasmProposal>,
+) -> Result<()> {
+    for proposal in proposals.difference(granted) {
+        if let Some(field) = proposal.policy_field() {
+            bail!(
+                "Component {component_id} uses the {proposal} proposal ({}), which its policy doesn't grant; set `resources.{field}` and `resources.limits.memory` in the policy",
+                proposal.description()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The proposals the component or module `bytes` uses, or `None` if it doesn't validate even
+/// with all of them enabled.
+pub fn used_proposals(bytes: &[u8]) -> Option<BTreeSet<WasmProposal>> {
+    let validates = |features| {
+        Validator::new_with_features(features)
+            .validate_all(bytes)
+            .is_ok()
+    };
+    if !validates(WasmFeatures::all()) {
+        return None;
+    }
+    Some(
+        WasmProposal::ALL
+            .into_iter()
+            .filter(|proposal| !validates(WasmFeatures::all() - proposal.features()))
+            .collect(),
+    )
+}
+
+/// Explain a load failure of `bytes` caused by proposals missing from `enabled`.
+///
+/// Returns `None` when the component doesn't need any proposal that is disabled, so the
+/// failure has another cause.
+pub(crate) fn diagnose(bytes: &[u8], enabled: &BTreeSet<WasmProposal>) -> Option<String> {
+    let missing: Vec<String> = used_proposals(bytes)?
+        .difference(enabled)
+        .map(|proposal| {
+            format!(
+                "the component uses the {proposal} proposal ({}), which {}",
+                proposal.description(),
+                proposal.remedy()
+            )
+        })
+        .collect();
+    (!missing.is_empty()).then(|| missing.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(module: &str) -> Vec<u8> {
+        wat::parse_str(format!("(component (core module {module}))")).unwrap()
+    }
+
+    const RELAXED_SIMD: &str = r#"(func (param v128 v128 v128) (result v128)
+        local.get 0 local.get 1 local.get 2 i32x4.relaxed_laneselect)"#;
+
+    #[test]
+    fn test_used_proposals() {
+        assert_eq!(
+            used_proposals(&component("(memory 1)")),
+            Some(BTreeSet::new())
+        );
+        assert_eq!(
+            used_proposals(&component("(memory 1 1 shared)")),
+            Some(BTreeSet::from([WasmProposal::Threads]))
+        );
+        assert_eq!(
+            used_proposals(&component("(memory i64 1)")),
+            Some(BTreeSet::from([WasmProposal::Memory64]))
+        );
+        assert_eq!(
+            used_proposals(&component("(tag $e) (func throw $e)")),
+            Some(BTreeSet::from([WasmProposal::Exceptions]))
+        );
+        assert_eq!(
+            used_proposals(&component(&format!("(memory i64 1) {RELAXED_SIMD}"))),
+            Some(BTreeSet::from([
+                WasmProposal::Memory64,
+                WasmProposal::RelaxedSimd
+            ]))
+        );
+        assert_eq!(
+            used_proposals(&wat::parse_str("(component (type (future u32)))").unwrap()),
+            Some(BTreeSet::from([WasmProposal::ComponentModelAsync]))
+        );
+        assert_eq!(used_proposals(b"not wasm"), None);
+    }
+
+    #[test]
+    fn test_check_granted() {
+        let used = BTreeSet::from([WasmProposal::Memory64, WasmProposal::RelaxedSimd]);
+        let error = check_granted("big-data", &used, &BTreeSet::new()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Component big-data uses the memory64 proposal (64-bit memories), which its policy doesn't grant; set `resources.memory64` and `resources.limits.memory` in the policy"
+        );
+        assert!(
+            check_granted("big-data", &used, &BTreeSet::from([WasmProposal::Memory64])).is_ok()
+        );
+
+        let used = BTreeSet::from([WasmProposal::Threads]);
+        assert!(check_granted("shared", &used, &BTreeSet::new()).is_err());
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let enabled: BTreeSet<_> = DEFAULT_WASM_PROPOSALS.iter().copied().collect();
+        let bytes = component(RELAXED_SIMD);
+        assert_eq!(diagnose(&bytes, &enabled), None);
+        assert_eq!(
+            diagnose(&bytes, &BTreeSet::new()).unwrap(),
+            "the component uses the relaxed-simd proposal (relaxed SIMD instructions), which is disabled; add `relaxed-simd` to `wasm_proposals` in the configuration file"
+        );
+
+        let hint = diagnose(&component("(tag $e) (func throw $e)"), &enabled).unwrap();
+        assert!(hint.starts_with("the component uses the exceptions proposal"));
+        assert!(hint.ends_with("rebuild the component without exception handling"));
+
+        let hint = diagnose(&component("(memory i64 1)"), &BTreeSet::new()).unwrap();
+        assert!(hint.ends_with("granted by the component's policy with `resources.memory64`"));
+
+        let hint = diagnose(
+            &wat::parse_str("(component (type (stream u8)))").unwrap(),
+            &enabled,
+        )
+        .unwrap();
+        assert!(hint.ends_with(
+            "add `component-model-async` to `wasm_proposals` in the configuration file"
+        ));
+
+        assert_eq!(diagnose(&component("(memory 1)"), &BTreeSet::new()), None);
+    }
+
+    #[test]
+    fn test_only_safe_proposals_are_configurable() {
+        assert!(validate(&DEFAULT_WASM_PROPOSALS.iter().copied().collect()).is_ok());
+        let error = validate(&BTreeSet::from([WasmProposal::Threads])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The threads proposal (shared memories and atomic instructions) is granted per component, with `resources.threads` in the component's policy"
+        );
+        assert!(validate(&BTreeSet::from([WasmProposal::Exceptions])).is_err());
+        assert!(validate(&BTreeSet::from([WasmProposal::ComponentModelAsync])).is_ok());
+
+        for proposal in WasmProposal::ALL {
+            assert_eq!(
+                proposal.policy_field().is_some(),
+                PER_COMPONENT_PROPOSALS.contains(&proposal)
+            );
+        }
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Provenance pinning: which publishers may provide a component.
+//!
+//! A component id can be bound to the Ed25519 publisher keys expected to sign its
+//! [`Manifest`]. A pinned component is then only accepted when it is installed from a manifest
+//! that is signed by one of those keys and that references the component by digest. The check
+//! runs on every load and update, and again before a stored component is compiled, so a
+//! compromised registry account can't slip in a replacement.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::Manifest;
+
+/// Length of an Ed25519 public key in bytes.
+const ED25519_PUBLIC_KEY_LEN: usize = 32;
+
+/// Expected signing identities per component id.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProvenanceConfig {
+    /// Pins keyed by component id; components without an entry are not checked
+    pub components: HashMap<String, ProvenancePin>,
+}
+
+/// The publishers trusted to provide one component.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProvenancePin {
+    /// Base64 Ed25519 publisher keys, any of which may sign the component's manifest
+    pub publisher_keys: Vec<String>,
+}
+
+impl ProvenanceConfig {
+    /// Check that every pin names at least one well-formed key.
+    pub fn validate(&self) -> Result<()> {
+        for (component_id, pin) in &self.components {
+            if pin.publisher_keys.is_empty() {
+                bail!("Provenance pin for '{component_id}' lists no publisher keys");
+            }
+            for key in &pin.publisher_keys {
+                let bytes = BASE64.decode(key).with_context(|| {
+                    format!("Publisher key pinned for '{component_id}' is not valid base64")
+                })?;
+                if bytes.len() != ED25519_PUBLIC_KEY_LEN {
+                    bail!("Publisher key pinned for '{component_id}' is not an Ed25519 public key");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `component_id` has a provenance pin.
+    pub fn is_pinned(&self, component_id: &str) -> bool {
+        self.components.contains_key(component_id)
+    }
+
+    /// Fail unless `manifest` is acceptable provenance for `component_id`.
+    pub(crate) fn verify(&self, component_id: &str, manifest: Option<&Manifest>) -> Result<()> {
+        let Some(pin) = self.components.get(component_id) else {
+            return Ok(());
+        };
+        let Some(manifest) = manifest else {
+            bail!(
+                "Component '{component_id}' is pinned to specific publishers and must be installed from a signed manifest"
+            );
+        };
+        // Stored manifests are re-verified so edits on disk don't go unnoticed.
+        Manifest::parse_and_verify(&serde_json::to_vec(manifest)?)
+            .with_context(|| format!("Manifest of component '{component_id}' failed to verify"))?;
+        if !pin.publisher_keys.contains(&manifest.publisher.public_key) {
+            bail!(
+                "Component '{component_id}' is signed by publisher '{}' with a key that is not pinned for it",
+                manifest.publisher.name
+            );
+        }
+        if !manifest.is_digest_pinned() {
+            bail!(
+                "Component '{component_id}' is pinned to specific publishers, but its manifest references a tag instead of a digest"
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::rand::SystemRandom;
+    use ring::signature::Ed25519KeyPair;
+
+    use super::*;
+    use crate::manifest::Publisher;
+
+    fn signed_manifest(component: &str) -> Manifest {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let mut manifest = Manifest {
+            name: "fetch".to_string(),
+            version: "1.0.0".to_string(),
+            component: component.to_string(),
+            id: None,
+            description: None,
+            policy: None,
+            dependencies: Vec::new(),
+            publisher: Publisher {
+                name: "Example".to_string(),
+                namespace: None,
+                public_key: String::new(),
+            },
+            signature: String::new(),
+        };
+        manifest.sign(pkcs8.as_ref()).unwrap();
+        manifest
+    }
+
+    fn pinned(component_id: &str, key: &str) -> ProvenanceConfig {
+        ProvenanceConfig {
+            components: HashMap::from([(
+                component_id.to_string(),
+                ProvenancePin {
+                    publisher_keys: vec![key.to_string()],
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn test_pinned_component_requires_pinned_publisher() {
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let manifest = signed_manifest(&format!("oci://ghcr.io/example/fetch@{digest}"));
+        let config = pinned("fetch", &manifest.publisher.public_key);
+        config.validate().unwrap();
+
+        assert!(config.verify("fetch", Some(&manifest)).is_ok());
+        assert!(config.verify("fetch", None).is_err());
+        assert!(config.verify("other", None).is_ok());
+
+        let impostor = signed_manifest(&manifest.component);
+        let err = config.verify("fetch", Some(&impostor)).unwrap_err();
+        assert!(err.to_string().contains("not pinned"));
+
+        let mut tampered = manifest.clone();
+        tampered.component = "oci://ghcr.io/evil/fetch@sha256:00".to_string();
+        assert!(config.verify("fetch", Some(&tampered)).is_err());
+    }
+
+    #[test]
+    fn test_pinned_component_requires_digest_reference() {
+        let manifest = signed_manifest("oci://ghcr.io/example/fetch:1.0.0");
+        let config = pinned("fetch", &manifest.publisher.public_key);
+        let err = config.verify("fetch", Some(&manifest)).unwrap_err();
+        assert!(err.to_string().contains("digest"));
+    }
+
+    #[test]
+    fn test_invalid_pins_are_rejected() {
+        assert!(pinned("fetch", "not base64!").validate().is_err());
+        assert!(pinned("fetch", &BASE64.encode([0u8; 16]))
+            .validate()
+            .is_err());
+        assert!(ProvenanceConfig {
+            components: HashMap::from([("fetch".to_string(), ProvenancePin::default())]),
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,714 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! The component registry, run as an actor.
+//!
+//! All registry state is owned by a single task and mutated only in response to messages, so
+//! every operation observes and produces a consistent view of components and their tools.
+//! The actor also hands out per-component [`ComponentLease`]s which serialize the multi-step
+//! operations (load, unload, permission changes) that touch both disk and registry state.
+//! Leases are released when dropped, so a cancelled request can never leave a component locked.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{anyhow, Result};
+use component2json::{FunctionIdentifier, ToolMetadata};
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
+
+use crate::{ComponentInstance, LoadResult};
+
+#[derive(Debug, Clone)]
+pub(crate) struct ToolInfo {
+    pub(crate) component_id: String,
+    pub(crate) identifier: FunctionIdentifier,
+    pub(crate) schema: Value,
+}
+
+type Query = Box<dyn FnOnce(&ComponentRegistryState) + Send>;
+type Update = Box<dyn FnOnce(&mut ComponentRegistryState) + Send>;
+
+enum Message {
+    Query(Query),
+    Update(Update),
+    Acquire {
+        component_id: String,
+        reply: oneshot::Sender<ComponentLease>,
+    },
+    Release {
+        component_id: String,
+    },
+}
+
+/// Handle to the registry actor. Cloning the handle is cheap; the actor stops once every handle
+/// (and every outstanding lease) has been dropped.
+#[derive(Clone)]
+pub(crate) struct ComponentRegistry {
+    tx: mpsc::UnboundedSender<Message>,
+}
+
+/// Exclusive right to run a mutating operation on one component.
+///
+/// Dropping the lease hands it to the next waiter for the same component.
+#[must_use = "the component is unlocked as soon as the lease is dropped"]
+pub(crate) struct ComponentLease {
+    component_id: String,
+    tx: mpsc::UnboundedSender<Message>,
+}
+
+impl Drop for ComponentLease {
+    fn drop(&mut self) {
+        // If the actor is gone there is nothing left to unlock.
+        let _ = self.tx.send(Message::Release {
+            component_id: std::mem::take(&mut self.component_id),
+        });
+    }
+}
+
+impl ComponentRegistry {
+    /// Spawn the registry actor on the current Tokio runtime.
+    pub(crate) fn new() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(rx, tx.downgrade()));
+        Self { tx }
+    }
+
+    async fn query<R>(&self, f: impl FnOnce(&ComponentRegistryState) -> R + Send + 'static) -> R
+    where
+        R: Default + Send + 'static,
+    {
+        let (reply, rx) = oneshot::channel();
+        let query: Query = Box::new(move |state| {
+            let _ = reply.send(f(state));
+        });
+        if self.tx.send(Message::Query(query)).is_err() {
+            warn!("Component registry actor has stopped");
+            return R::default();
+        }
+        rx.await.unwrap_or_default()
+    }
+
+    async fn update<R>(
+        &self,
+        f: impl FnOnce(&mut ComponentRegistryState) -> R + Send + 'static,
+    ) -> Result<R>
+    where
+        R: Send + 'static,
+    {
+        let (reply, rx) = oneshot::channel();
+        let update: Update = Box::new(move |state| {
+            let _ = reply.send(f(state));
+        });
+        self.tx
+            .send(Message::Update(update))
+            .map_err(|_| anyhow!("Component registry actor has stopped"))?;
+        rx.await
+            .map_err(|_| anyhow!("Component registry actor has stopped"))
+    }
+
+    /// Wait until no other operation holds a lease on `component_id`, then take it.
+    ///
+    /// Cancelling the returned future while it waits gives up the place in the queue.
+    pub(crate) async fn lock(&self, component_id: &str) -> Result<ComponentLease> {
+        let (reply, rx) = oneshot::channel();
+        self.tx
+            .send(Message::Acquire {
+                component_id: component_id.to_string(),
+                reply,
+            })
+            .map_err(|_| anyhow!("Component registry actor has stopped"))?;
+        rx.await
+            .map_err(|_| anyhow!("Component registry actor has stopped"))
+    }
+
+    pub(crate) async fn upsert_component(
+        &self,
+        component_id: String,
+        instance: ComponentInstance,
+        tools: Vec<ToolMetadata>,
+    ) -> Result<LoadResult> {
+        self.update(move |state| state.upsert_component(component_id, instance, tools))
+            .await
+    }
+
+    pub(crate) async fn remove_component(
+        &self,
+        component_id: &str,
+    ) -> Result<Option<ComponentInstance>> {
+        let component_id = component_id.to_string();
+        self.update(move |state| state.unregister_component(&component_id))
+            .await
+    }
+
+    pub(crate) async fn register_metadata_if_absent(
+        &self,
+        component_id: &str,
+        tools: Vec<ToolMetadata>,
+    ) -> Result<bool> {
+        let component_id = component_id.to_string();
+        self.update(move |state| {
+            if state.components.contains_key(&component_id)
+                || state.component_map.contains_key(&component_id)
+            {
+                return false;
+            }
+            state.register_tools_only(&component_id, tools);
+            true
+        })
+        .await
+    }
+
+    pub(crate) async fn get_component(&self, component_id: &str) -> Option<ComponentInstance> {
+        let component_id = component_id.to_string();
+        self.query(move |state| state.components.get(&component_id).cloned())
+            .await
+    }
+
+    pub(crate) async fn contains_component(&self, component_id: &str) -> bool {
+        let component_id = component_id.to_string();
+        self.query(move |state| state.components.contains_key(&component_id))
+            .await
+    }
+
+    pub(crate) async fn list_components(&self) -> Vec<String> {
+        self.query(|state| {
+            let mut ids: Vec<String> = state.components.keys().cloned().collect();
+            ids.sort();
+            ids
+        })
+        .await
+    }
+
+    pub(crate) async fn tool_infos(&self, tool_name: &str) -> Option<Vec<ToolInfo>> {
+        let tool_name = tool_name.to_string();
+        self.query(move |state| state.tool_map.get(&tool_name).cloned())
+            .await
+    }
+
+    /// Look up a loaded component together with its tool `tool_name` in a single step, so a
+    /// concurrent reload can't pair the instance of one version with the tool of another.
+    pub(crate) async fn call_target(
+        &self,
+        component_id: &str,
+        tool_name: &str,
+    ) -> Option<(ComponentInstance, Option<ToolInfo>)> {
+        let component_id = component_id.to_string();
+        let tool_name = tool_name.to_string();
+        self.query(move |state| {
+            let instance = state.components.get(&component_id)?.clone();
+            let tool = state.tool_map.get(&tool_name).and_then(|infos| {
+                infos
+                    .iter()
+                    .find(|info| info.component_id == component_id)
+                    .cloned()
+            });
+            Some((instance, tool))
+        })
+        .await
+    }
+
+    /// Tool schemas registered for `component_id`, in export order.
+    pub(crate) async fn component_tools(&self, component_id: &str) -> Option<Vec<Value>> {
+        let component_id = component_id.to_string();
+        self.query(move |state| {
+            let tool_names = state.component_map.get(&component_id)?;
+            Some(
+                tool_names
+                    .iter()
+                    .filter_map(|tool_name| {
+                        state
+                            .tool_map
+                            .get(tool_name)?
+                            .iter()
+                            .find(|info| info.component_id == component_id)
+                    })
+                    .map(|info| info.schema.clone())
+                    .collect(),
+            )
+        })
+        .await
+    }
+
+    /// Tool schemas of every registered component, ordered by component id and then by the
+    /// order in which the component exports them.
+    pub(crate) async fn list_tools(&self) -> Vec<Value> {
+        self.query(|state| {
+            let mut component_ids: Vec<&String> = state.component_map.keys().collect();
+            component_ids.sort();
+            component_ids
+                .into_iter()
+                .flat_map(|component_id| {
+                    state.component_map[component_id]
+                        .iter()
+                        .filter_map(move |tool_name| {
+                            state
+                                .tool_map
+                                .get(tool_name)?
+                                .iter()
+                                .find(|info| &info.component_id == component_id)
+                        })
+                        .map(|info| info.schema.clone())
+                })
+                .collect()
+        })
+        .await
+    }
+}
+
+async fn run(mut rx: mpsc::UnboundedReceiver<Message>, tx: mpsc::WeakUnboundedSender<Message>) {
+    let mut state = ComponentRegistryState::default();
+    let mut leases = LeaseTable::default();
+
+    while let Some(message) = rx.recv().await {
+        match message {
+            Message::Query(query) => query(&state),
+            Message::Update(update) => update(&mut state),
+            Message::Acquire {
+                component_id,
+                reply,
+            } => {
+                if let Some(reply) = leases.acquire(&component_id, reply) {
+                    grant(&tx, component_id, reply);
+                }
+            }
+            Message::Release { component_id } => {
+                if let Some(reply) = leases.release(&component_id) {
+                    grant(&tx, component_id, reply);
+                }
+            }
+        }
+    }
+}
+
+fn grant(
+    tx: &mpsc::WeakUnboundedSender<Message>,
+    component_id: String,
+    reply: oneshot::Sender<ComponentLease>,
+) {
+    let Some(tx) = tx.upgrade() else {
+        return;
+    };
+    // If the waiter was cancelled the lease comes straight back and is dropped here, which
+    // queues a release and moves on to the next waiter.
+    let _ = reply.send(ComponentLease { component_id, tx });
+}
+
+/// Bookkeeping for per-component leases: a component is locked while it has an entry, and the
+/// entry queues the waiters in arrival order.
+#[derive(Debug)]
+pub(crate) struct LeaseTable<W> {
+    held: HashMap<String, VecDeque<W>>,
+}
+
+impl<W> Default for LeaseTable<W> {
+    fn default() -> Self {
+        Self {
+            held: HashMap::new(),
+        }
+    }
+}
+
+impl<W> LeaseTable<W> {
+    /// Register `waiter` for `component_id`. Returns the waiter back if the lease is free and
+    /// should be granted immediately.
+    pub(crate) fn acquire(&mut self, component_id: &str, waiter: W) -> Option<W> {
+        match self.held.entry(component_id.to_string()) {
+            Entry::Occupied(mut queue) => {
+                queue.get_mut().push_back(waiter);
+                None
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(VecDeque::new());
+                Some(waiter)
+            }
+        }
+    }
+
+    /// Release the lease on `component_id`. Returns the next waiter, which now holds the lease.
+    pub(crate) fn release(&mut self, component_id: &str) -> Option<W> {
+        let queue = self.held.get_mut(component_id)?;
+        let next = queue.pop_front();
+        if next.is_none() {
+            self.held.remove(component_id);
+        }
+        next
+    }
+
+    #[cfg(test)]
+    fn is_locked(&self, component_id: &str) -> bool {
+        self.held.contains_key(component_id)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ComponentRegistryState {
+    components: HashMap<String, ComponentInstance>,
+    tool_map: HashMap<String, Vec<ToolInfo>>,
+    component_map: HashMap<String, Vec<String>>,
+}
+
+impl std::fmt::Debug for ComponentRegistryState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ComponentRegistryState")
+            .field("components_len", &self.components.len())
+            .field("tool_map", &self.tool_map)
+            .field("component_map", &self.component_map)
+            .finish()
+    }
+}
+
+impl ComponentRegistryState {
+    fn upsert_component(
+        &mut self,
+        component_id: String,
+        instance: ComponentInstance,
+        tools: Vec<ToolMetadata>,
+    ) -> LoadResult {
+        let replaced = self.components.contains_key(&component_id);
+        self.unregister_tools(&component_id);
+        self.register_tools_only(&component_id, tools);
+        self.components.insert(component_id, instance);
+
+        if replaced {
+            LoadResult::Replaced
+        } else {
+            LoadResult::New
+        }
+    }
+
+    fn unregister_component(&mut self, component_id: &str) -> Option<ComponentInstance> {
+        self.unregister_tools(component_id);
+        self.components.remove(component_id)
+    }
+
+    fn unregister_tools(&mut self, component_id: &str) {
+        if let Some(tools) = self.component_map.remove(component_id) {
+            for tool_name in tools {
+                if let Some(tool_infos) = self.tool_map.get_mut(&tool_name) {
+                    tool_infos.retain(|info| info.component_id != component_id);
+                    if tool_infos.is_empty() {
+                        self.tool_map.remove(&tool_name);
+                    }
+                }
+            }
+        }
+    }
+
+    fn register_tools_only(&mut self, component_id: &str, tools: Vec<ToolMetadata>) {
+        let mut tool_names = Vec::new();
+
+        for tool_metadata in tools {
+            let ToolMetadata {
+                identifier,
+                schema,
+                normalized_name,
+            } = tool_metadata;
+
+            let tool_info = ToolInfo {
+                component_id: component_id.to_string(),
+                identifier,
+                schema,
+            };
+
+            self.tool_map
+                .entry(normalized_name.clone())
+                .or_default()
+                .push(tool_info);
+            tool_names.push(normalized_name);
+        }
+
+        self.component_map
+            .insert(component_id.to_string(), tool_names);
+    }
+
+    /// Every registered tool belongs to a component in `component_map` and vice versa.
+    #[cfg(test)]
+    fn check_invariants(&self) {
+        for (tool_name, infos) in &self.tool_map {
+            assert!(!infos.is_empty(), "empty tool entry for {tool_name}");
+            for info in infos {
+                let tools = self
+                    .component_map
+                    .get(&info.component_id)
+                    .unwrap_or_else(|| panic!("tool {tool_name} has unknown owner"));
+                assert!(tools.contains(tool_name));
+            }
+        }
+        for (component_id, tools) in &self.component_map {
+            for tool_name in tools {
+                assert!(self.tool_map[tool_name]
+                    .iter()
+                    .any(|info| &info.component_id == component_id));
+            }
+        }
+        for component_id in self.components.keys() {
+            assert!(self.component_map.contains_key(component_id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    use super::*;
+
+    fn tools(component_id: &str, names: &[&str]) -> Vec<ToolMetadata> {
+        names
+            .iter()
+            .map(|name| ToolMetadata {
+                identifier: FunctionIdentifier {
+                    package_name: None,
+                    interface_name: None,
+                    function_name: name.to_string(),
+                },
+                schema: json!({ "name": name, "owner": component_id }),
+                normalized_name: name.to_string(),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_lease_is_exclusive() -> Result<()> {
+        let registry = ComponentRegistry::new();
+        let inside = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let registry = registry.clone();
+                let inside = Arc::clone(&inside);
+                tokio::spawn(async move {
+                    let _lease = registry.lock("fetch").await?;
+                    assert_eq!(inside.fetch_add(1, Ordering::SeqCst), 0);
+                    tokio::task::yield_now().await;
+                    inside.fetch_sub(1, Ordering::SeqCst);
+                    anyhow::Ok(())
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await??;
+        }
+
+        // Leases on different components don't block each other.
+        let _a = registry.lock("a").await?;
+        let _b = tokio::time::timeout(Duration::from_secs(1), registry.lock("b")).await??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_and_holder_release_lease() -> Result<()> {
+        let registry = ComponentRegistry::new();
+
+        // A waiter that gives up must not inherit (and leak) the lease.
+        let held = registry.lock("fetch").await?;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), registry.lock("fetch"))
+                .await
+                .is_err()
+        );
+        drop(held);
+        let lease = tokio::time::timeout(Duration::from_secs(1), registry.lock("fetch")).await??;
+        drop(lease);
+
+        // A holder that is aborted mid-operation releases the lease as it unwinds.
+        let task = {
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                let _lease = registry.lock("fetch").await.unwrap();
+                std::future::pending::<()>().await;
+            })
+        };
+        tokio::task::yield_now().await;
+        task.abort();
+        let _ = task.await;
+        let _lease = tokio::time::timeout(Duration::from_secs(1), registry.lock("fetch")).await??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_updates_keep_registry_consistent() -> Result<()> {
+        let registry = ComponentRegistry::new();
+
+        let tasks: Vec<_> = (0..32)
+            .map(|i| {
+                let registry = registry.clone();
+                tokio::spawn(async move {
+                    let id = format!("c{}", i % 4);
+                    if i % 3 == 0 {
+                        registry.remove_component(&id).await?;
+                    } else {
+                        registry
+                            .register_metadata_if_absent(&id, tools(&id, &["shared", id.as_str()]))
+                            .await?;
+                    }
+                    registry.list_tools().await;
+                    anyhow::Ok(())
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await??;
+        }
+
+        registry.query(|state| state.check_invariants()).await;
+        Ok(())
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Register(u8, Vec<u8>),
+        Remove(u8),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0..4u8, prop::collection::vec(0..4u8, 0..4)).prop_map(|(c, t)| Op::Register(c, t)),
+            (0..4u8).prop_map(Op::Remove),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_registry_state_invariants(ops in prop::collection::vec(op(), 0..32)) {
+            let mut state = ComponentRegistryState::default();
+            for op in ops {
+                match op {
+                    Op::Register(c, t) => {
+                        let id = format!("c{c}");
+                        let mut names: Vec<String> = t.iter().map(|t| format!("t{t}")).collect();
+                        names.sort();
+                        names.dedup();
+                        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                        state.unregister_tools(&id);
+                        state.register_tools_only(&id, tools(&id, &names));
+                    }
+                    Op::Remove(c) => {
+                        state.unregister_component(&format!("c{c}"));
+                    }
+                }
+                state.check_invariants();
+            }
+        }
+
+        #[test]
+        fn prop_lease_table_grants_in_order(ops in prop::collection::vec((0..3u8, any::<bool>()), 0..64)) {
+            // Model: each component has at most one holder and a FIFO of waiters.
+            let mut table = LeaseTable::default();
+            let mut model: HashMap<String, VecDeque<usize>> = HashMap::new();
+            for (waiter, (c, acquire)) in ops.into_iter().enumerate() {
+                let id = format!("c{c}");
+                if acquire {
+                    let granted = table.acquire(&id, waiter);
+                    let queue = model.entry(id.clone()).or_default();
+                    queue.push_back(waiter);
+                    prop_assert_eq!(granted.is_some(), queue.len() == 1);
+                } else if let Some(queue) = model.get_mut(&id) {
+                    queue.pop_front();
+                    prop_assert_eq!(table.release(&id), queue.front().copied());
+                    if queue.is_empty() {
+                        model.remove(&id);
+                    }
+                }
+                prop_assert_eq!(table.is_locked(&id), model.contains_key(&id));
+            }
+        }
+    }
+}
+
+/// Exhaustive interleaving checks for the lease protocol, including waiters that are cancelled
+/// while the lease is being handed to them. Run with
+/// `RUSTFLAGS="--cfg wassette_loom" cargo test -p wassette --release loom`.
+#[cfg(all(test, wassette_loom))]
+mod loom_tests {
+    use loom::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+    use loom::sync::{Arc, Mutex};
+    use loom::thread;
+
+    use super::LeaseTable;
+
+    const WAITING: u8 = 0;
+    const GRANTED: u8 = 1;
+    const CANCELLED: u8 = 2;
+
+    /// Stand-in for the oneshot reply channel: granting and cancelling race on a single state.
+    type Waiter = Arc<AtomicU8>;
+
+    /// Mirrors the actor's `grant`: a cancelled waiter bounces the lease straight back.
+    fn grant(table: &Mutex<LeaseTable<Waiter>>, id: &str, mut next: Option<Waiter>) {
+        while let Some(waiter) = next {
+            if waiter
+                .compare_exchange(WAITING, GRANTED, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return;
+            }
+            next = table.lock().unwrap().release(id);
+        }
+    }
+
+    fn acquire(table: &Mutex<LeaseTable<Waiter>>, waiter: &Waiter) {
+        let granted = table.lock().unwrap().acquire("c", Arc::clone(waiter));
+        grant(table, "c", granted);
+    }
+
+    fn release(table: &Mutex<LeaseTable<Waiter>>) {
+        let next = table.lock().unwrap().release("c");
+        grant(table, "c", next);
+    }
+
+    #[test]
+    fn lease_mutual_exclusion_with_cancellation() {
+        loom::model(|| {
+            let table = Arc::new(Mutex::new(LeaseTable::default()));
+            let inside = Arc::new(AtomicUsize::new(0));
+
+            let workers: Vec<_> = (0..2)
+                .map(|_| {
+                    let table = Arc::clone(&table);
+                    let inside = Arc::clone(&inside);
+                    thread::spawn(move || {
+                        let waiter = Arc::new(AtomicU8::new(WAITING));
+                        acquire(&table, &waiter);
+                        while waiter.load(Ordering::Acquire) != GRANTED {
+                            thread::yield_now();
+                        }
+                        assert_eq!(inside.fetch_add(1, Ordering::SeqCst), 0);
+                        inside.fetch_sub(1, Ordering::SeqCst);
+                        release(&table);
+                    })
+                })
+                .collect();
+
+            // A third party queues up and then gives up, racing with the hand-off.
+            let canceller = {
+                let table = Arc::clone(&table);
+                thread::spawn(move || {
+                    let waiter = Arc::new(AtomicU8::new(WAITING));
+                    acquire(&table, &waiter);
+                    if waiter
+                        .compare_exchange(WAITING, CANCELLED, Ordering::AcqRel, Ordering::Acquire)
+                        .is_err()
+                    {
+                        // The lease arrived before we cancelled; dropping it releases it.
+                        release(&table);
+                    }
+                })
+            };
+
+            for worker in workers {
+                worker.join().unwrap();
+            }
+            canceller.join().unwrap();
+
+            assert!(table.lock().unwrap().held.is_empty(), "lease leaked");
+        });
+    }
+}
@@ -0,0 +1,492 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Resources components publish to MCP clients through the `wassette:resources/publish` host
+//! interface.
+//!
+//! Components import
+//!
+//! ```wit
+//! package wassette:resources;
+//!
+//! interface publish {
+//!     record %resource {
+//!         /// Unique within the component, e.g. `reports/weekly.md`
+//!         name: string,
+//!         description: option<string>,
+//!         mime-type: option<string>,
+//!     }
+//!
+//!     /// Publish the file at `path`, which must lie in a directory the policy grants. The
+//!     /// file is read whenever a client reads the resource.
+//!     publish-file: func(%resource: %resource, path: string) -> result<_, string>;
+//!     /// Publish `contents`, e.g. a report the tool generated.
+//!     publish-contents: func(%resource: %resource, contents: list<u8>) -> result<_, string>;
+//!     /// Stop publishing the resource `name`.
+//!     unpublish: func(name: string) -> result<_, string>;
+//! }
+//! ```
+//!
+//! so clients can list a component's files and outputs with `resources/list` and read them
+//! with `resources/read` at `wassette://components/<component-id>/<name>`, rather than a tool
+//! returning them inline. Publishing a name again replaces the resource and tells clients
+//! subscribed to its URI that it was updated; publishing a new name or unpublishing one tells
+//! clients that the list changed.
+//!
+//! No policy section is needed, since a component can only publish files it may read and
+//! contents it could return from a tool. Published resources are kept in memory until the
+//! component unpublishes them or is unloaded, so a component publishes them again after the
+//! server restarts.
+//!
+//! A component that embeds a usage guide in its `wassette:readme` custom section has it
+//! published as [`README_NAME`] whenever it is loaded, so agents can read detailed usage
+//! instructions on demand instead of finding them all in the tool descriptions.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+use tokio::sync::broadcast;
+use tracing::warn;
+use wasmtime::component::{ComponentType, Lift, Linker};
+
+use crate::fs_grants::GrantedFile;
+use crate::{WasiState, WassetteWasiState};
+
+/// Name of the host interface through which components publish resources.
+pub const RESOURCES_INTERFACE: &str = "wassette:resources/publish";
+
+/// Scheme and authority of the URIs of published resources.
+pub const RESOURCE_URI_PREFIX: &str = "wassette://components/";
+
+/// Most resources a component may publish at once.
+pub const MAX_RESOURCES: usize = 256;
+
+/// Largest contents a component may publish with `publish-contents`.
+pub const MAX_CONTENTS_BYTES: usize = 1024 * 1024;
+
+/// Largest published file served to clients.
+pub const MAX_FILE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Name of the resource a component's embedded usage guide is published as.
+pub const README_NAME: &str = "README.md";
+
+/// Longest resource name, in bytes.
+const MAX_NAME_BYTES: usize = 256;
+
+/// Resource changes buffered for subscribers before the slowest ones miss some.
+const CHANGES_CAPACITY: usize = 256;
+
+/// A resource as a component describes it.
+#[derive(ComponentType, Lift, Debug, Clone, PartialEq, Eq)]
+#[component(record)]
+pub struct ResourceInfo {
+    /// Unique within the component
+    pub name: String,
+    /// What the resource holds
+    pub description: Option<String>,
+    /// Media type of the resource, e.g. `text/markdown`
+    #[component(name = "mime-type")]
+    pub mime_type: Option<String>,
+}
+
+/// Where the contents of a published resource come from.
+#[derive(Debug, Clone)]
+pub(crate) enum ResourceSource {
+    /// A file in a directory the component was granted, read when a client reads it
+    File(GrantedFile),
+    /// Contents handed over by the component
+    Contents(Arc<[u8]>),
+}
+
+/// A resource a component published.
+#[derive(Debug, Clone)]
+pub struct PublishedResource {
+    /// Component that published the resource
+    pub component_id: String,
+    /// URI clients read the resource at
+    pub uri: String,
+    /// Name, description and media type given by the component
+    pub info: ResourceInfo,
+    pub(crate) source: ResourceSource,
+}
+
+impl PublishedResource {
+    /// The contents of the resource, read from its file if it was published as one.
+    pub(crate) async fn read(&self) -> Result<Vec<u8>> {
+        match &self.source {
+            ResourceSource::File(file) => file.read(MAX_FILE_BYTES).await,
+            ResourceSource::Contents(contents) => Ok(contents.to_vec()),
+        }
+    }
+}
+
+/// A change of the published resources, for notifying clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceChange {
+    /// A resource of this component was published under a new name, or unpublished
+    ListChanged(String),
+    /// The resource at this URI was published again
+    Updated(String),
+}
+
+/// URI of the resource `name` of `component_id`.
+pub fn resource_uri(component_id: &str, name: &str) -> String {
+    format!("{RESOURCE_URI_PREFIX}{component_id}/{name}")
+}
+
+/// Check that `name` is a relative path of plain segments, so it can be used in a URI as is.
+fn validate_name(name: &str) -> std::result::Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_BYTES {
+        return Err(format!(
+            "resource names must be 1 to {MAX_NAME_BYTES} bytes long"
+        ));
+    }
+    let plain_segments = name
+        .split('/')
+        .all(|segment| !segment.is_empty() && segment != "." && segment != "..");
+    let plain_chars = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'));
+    if !plain_segments || !plain_chars {
+        return Err(format!(
+            "resource name `{name}` may only contain letters, digits, `-`, `_`, `.` and `/`-separated segments"
+        ));
+    }
+    Ok(())
+}
+
+/// The resources all components published.
+pub(crate) struct PublishedResources {
+    /// Resources keyed by URI
+    resources: Mutex<BTreeMap<String, PublishedResource>>,
+    changes: broadcast::Sender<ResourceChange>,
+}
+
+impl std::fmt::Debug for PublishedResources {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PublishedResources").finish_non_exhaustive()
+    }
+}
+
+impl Default for PublishedResources {
+    fn default() -> Self {
+        Self {
+            resources: Mutex::default(),
+            changes: broadcast::channel(CHANGES_CAPACITY).0,
+        }
+    }
+}
+
+impl PublishedResources {
+    /// Publish `info` for `component_id`, replacing the resource with the same name.
+    fn publish(
+        &self,
+        component_id: &str,
+        info: ResourceInfo,
+        source: ResourceSource,
+    ) -> std::result::Result<(), String> {
+        validate_name(&info.name)?;
+        let uri = resource_uri(component_id, &info.name);
+        let mut resources = self.resources.lock().unwrap_or_else(|e| e.into_inner());
+        let replaced = resources.contains_key(&uri);
+        if !replaced {
+            let published = resources
+                .values()
+                .filter(|resource| resource.component_id == component_id)
+                .count();
+            if published >= MAX_RESOURCES {
+                return Err(format!(
+                    "the component already publishes {MAX_RESOURCES} resources"
+                ));
+            }
+        }
+        resources.insert(
+            uri.clone(),
+            PublishedResource {
+                component_id: component_id.to_string(),
+                uri: uri.clone(),
+                info,
+                source,
+            },
+        );
+        drop(resources);
+
+        // Nobody listening isn't an error.
+        let _ = self.changes.send(if replaced {
+            ResourceChange::Updated(uri)
+        } else {
+            ResourceChange::ListChanged(component_id.to_string())
+        });
+        Ok(())
+    }
+
+    /// Stop publishing the resource `name` of `component_id`, if it is published.
+    fn unpublish(&self, component_id: &str, name: &str) {
+        let uri = resource_uri(component_id, name);
+        let removed = self
+            .resources
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&uri);
+        if removed.is_some() {
+            let _ = self
+                .changes
+                .send(ResourceChange::ListChanged(component_id.to_string()));
+        }
+    }
+
+    /// Publish the usage guide `component_id` embeds, or stop publishing the guide of its
+    /// previous version if it has none.
+    pub(crate) fn set_readme(&self, component_id: &str, readme: Option<&str>) {
+        let Some(readme) = readme else {
+            self.unpublish(component_id, README_NAME);
+            return;
+        };
+        if readme.len() > MAX_CONTENTS_BYTES {
+            warn!(%component_id, "Not publishing a usage guide larger than {MAX_CONTENTS_BYTES} bytes");
+            self.unpublish(component_id, README_NAME);
+            return;
+        }
+        let info = ResourceInfo {
+            name: README_NAME.to_string(),
+            description: Some("Usage guide of the component's tools".to_string()),
+            mime_type: Some("text/markdown".to_string()),
+        };
+        let contents = ResourceSource::Contents(readme.as_bytes().into());
+        if let Err(error) = self.publish(component_id, info, contents) {
+            warn!(%component_id, %error, "Failed to publish the usage guide");
+        }
+    }
+
+    /// Stop publishing the resources of `component_id`, e.g. when it is unloaded.
+    pub(crate) fn forget(&self, component_id: &str) {
+        let mut resources = self.resources.lock().unwrap_or_else(|e| e.into_inner());
+        let before = resources.len();
+        resources.retain(|_, resource| resource.component_id != component_id);
+        let removed = resources.len() < before;
+        drop(resources);
+        if removed {
+            let _ = self
+                .changes
+                .send(ResourceChange::ListChanged(component_id.to_string()));
+        }
+    }
+
+    /// All published resources, sorted by URI.
+    pub(crate) fn list(&self) -> Vec<PublishedResource> {
+        self.resources
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// The resource published at `uri`.
+    pub(crate) fn get(&self, uri: &str) -> Option<PublishedResource> {
+        self.resources
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(uri)
+            .cloned()
+    }
+
+    /// Receive the changes from now on.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<ResourceChange> {
+        self.changes.subscribe()
+    }
+}
+
+/// Resource publishing of a single store.
+#[derive(Debug, Default, Clone)]
+pub struct ResourceState {
+    /// Where the component's resources are published, with its id
+    pub(crate) published: Option<(Arc<PublishedResources>, String)>,
+}
+
+impl ResourceState {
+    fn admit(&self) -> std::result::Result<(&PublishedResources, &str), String> {
+        match &self.published {
+            Some((published, component_id)) => Ok((published, component_id)),
+            None => Err("resources can't be published from here".to_string()),
+        }
+    }
+}
+
+/// Add the `wassette:resources/publish` interface to `linker`.
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    let mut resources = linker.instance(RESOURCES_INTERFACE)?;
+
+    resources.func_wrap(
+        "publish-file",
+        |store, (info, path): (ResourceInfo, String)| {
+            let state = &store.data().inner;
+            let result = state
+                .resources
+                .admit()
+                .and_then(|(published, component_id)| {
+                    let file = state.fs_grants.granted_file(&path)?;
+                    published.publish(component_id, info, ResourceSource::File(file))
+                });
+            Ok((result,))
+        },
+    )?;
+
+    resources.func_wrap(
+        "publish-contents",
+        |store, (info, contents): (ResourceInfo, Vec<u8>)| {
+            let result =
+                store
+                    .data()
+                    .inner
+                    .resources
+                    .admit()
+                    .and_then(|(published, component_id)| {
+                        if contents.len() > MAX_CONTENTS_BYTES {
+                            return Err(format!(
+                                "the contents are larger than {MAX_CONTENTS_BYTES} bytes"
+                            ));
+                        }
+                        published.publish(
+                            component_id,
+                            info,
+                            ResourceSource::Contents(contents.into()),
+                        )
+                    });
+            Ok((result,))
+        },
+    )?;
+
+    resources.func_wrap("unpublish", |store, (name,): (String,)| {
+        let result = store
+            .data()
+            .inner
+            .resources
+            .admit()
+            .map(|(published, component_id)| published.unpublish(component_id, &name));
+        Ok((result,))
+    })?;
+
+    Ok(())
+}
+
+/// The resource published at `uri` with its contents.
+pub(crate) async fn read(
+    published: &PublishedResources,
+    uri: &str,
+) -> Result<(PublishedResource, Vec<u8>)> {
+    let Some(resource) = published.get(uri) else {
+        bail!("Unknown resource: {uri}");
+    };
+    let contents = resource.read().await?;
+    Ok((resource, contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(name: &str) -> ResourceInfo {
+        ResourceInfo {
+            name: name.to_string(),
+            description: None,
+            mime_type: Some("text/plain".to_string()),
+        }
+    }
+
+    fn contents(text: &str) -> ResourceSource {
+        ResourceSource::Contents(text.as_bytes().into())
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("report.md").is_ok());
+        assert!(validate_name("reports/2025-10/weekly_summary.md").is_ok());
+        for bad in [
+            "",
+            "/etc/passwd",
+            "a//b",
+            "../secret",
+            "a/./b",
+            "with space",
+            "a?b",
+        ] {
+            assert!(validate_name(bad).is_err(), "{bad} should be refused");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_notifies_and_reads() -> Result<()> {
+        let published = PublishedResources::default();
+        let mut changes = published.subscribe();
+
+        published
+            .publish("weather", info("today.txt"), contents("sunny"))
+            .unwrap();
+        assert_eq!(
+            changes.try_recv()?,
+            ResourceChange::ListChanged("weather".to_string())
+        );
+
+        let uri = resource_uri("weather", "today.txt");
+        assert_eq!(uri, "wassette://components/weather/today.txt");
+        published
+            .publish("weather", info("today.txt"), contents("rainy"))
+            .unwrap();
+        assert_eq!(changes.try_recv()?, ResourceChange::Updated(uri.clone()));
+
+        let (resource, data) = read(&published, &uri).await?;
+        assert_eq!(resource.component_id, "weather");
+        assert_eq!(data, b"rainy");
+        assert!(read(&published, "wassette://components/weather/x")
+            .await
+            .is_err());
+
+        published.unpublish("weather", "missing.txt");
+        assert!(changes.try_recv().is_err());
+        published.publish("other", info("a"), contents("")).unwrap();
+        let _ = changes.try_recv();
+        published.forget("weather");
+        assert_eq!(
+            changes.try_recv()?,
+            ResourceChange::ListChanged("weather".to_string())
+        );
+        let uris: Vec<_> = published.list().into_iter().map(|r| r.uri).collect();
+        assert_eq!(uris, ["wassette://components/other/a"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_publish_is_capped_per_component() {
+        let published = PublishedResources::default();
+        for n in 0..MAX_RESOURCES {
+            published
+                .publish("many", info(&format!("r{n}")), contents(""))
+                .unwrap();
+        }
+        assert!(published
+            .publish("many", info("one-more"), contents(""))
+            .is_err());
+        // Replacing stays possible, and other components have their own cap.
+        assert!(published.publish("many", info("r0"), contents("")).is_ok());
+        assert!(published.publish("few", info("r0"), contents("")).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_readme() -> Result<()> {
+        let published = PublishedResources::default();
+        published.set_readme("weather", Some("# Weather"));
+        let uri = resource_uri("weather", README_NAME);
+        let (resource, data) = read(&published, &uri).await?;
+        assert_eq!(resource.info.mime_type.as_deref(), Some("text/markdown"));
+        assert_eq!(data, b"# Weather");
+
+        // A version without a guide drops the previous one.
+        published.set_readme("weather", None);
+        assert!(published.get(&uri).is_none());
+        published.set_readme("weather", Some(&"x".repeat(MAX_CONTENTS_BYTES + 1)));
+        assert!(published.get(&uri).is_none());
+        Ok(())
+    }
+}
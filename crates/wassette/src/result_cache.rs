@@ -0,0 +1,332 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Results of idempotent tools, kept for the TTL their component's policy gives them.
+//!
+//! Agents often repeat a call with the same arguments. A policy can list tools under
+//! `cache`; their results are kept for the tool's `ttl`, and identical calls are answered from
+//! here instead of instantiating the component again. Errors, including an `err` result, are
+//! not kept since they are often transient. Arguments are compared
+//! after presets and schema defaults were filled in and object keys were sorted, so calls that
+//! only differ in key order or in spelling out a default share an entry. A component's entries
+//! are dropped when it is reloaded or unloaded, or when its policy or secrets change; all of
+//! them are dropped when the client's workspace roots change.
+//!
+//! Entries belong to the subject of the access token the call was made with, so one caller
+//! is never answered with a result computed for another. Components whose results depend on
+//! the caller's session, those granted the session context or the client's workspace, aren't
+//! cached at all.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+/// Entries kept before the ones expiring soonest are evicted.
+const MAX_ENTRIES: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    component_id: String,
+    tool: String,
+    /// Subject of the caller's access token, if the call was made with one
+    subject: Option<String>,
+    arguments: String,
+}
+
+#[derive(Debug)]
+struct Entry {
+    output: String,
+    expires: Instant,
+}
+
+/// Cached results of all components.
+#[derive(Debug, Default)]
+pub(crate) struct ResultCache {
+    entries: Mutex<HashMap<CacheKey, Entry>>,
+}
+
+/// A call of a tool whose policy caches its results for `ttl`.
+pub(crate) struct CachedCall<'a> {
+    pub(crate) component_id: &'a str,
+    pub(crate) tool: &'a str,
+    /// Subject of the caller's access token, if the call was made with one
+    pub(crate) subject: Option<&'a str>,
+    /// Arguments after presets and schema defaults were filled in
+    pub(crate) arguments: &'a Value,
+    pub(crate) ttl: Duration,
+}
+
+impl ResultCache {
+    /// The output cached for `call`, unless it expired.
+    pub(crate) fn lookup(&self, call: &CachedCall<'_>) -> Option<String> {
+        self.get(call.component_id, call.tool, call.subject, call.arguments)
+    }
+
+    /// Keep the `output` of `call` for its TTL, unless its JSON `results` report an error.
+    pub(crate) fn keep(&self, call: &CachedCall<'_>, results: &Value, output: &str) {
+        // Errors the tool reports may be transient, so they are never cached.
+        if !reports_error(results) {
+            self.put(
+                call.component_id,
+                call.tool,
+                call.subject,
+                call.arguments,
+                output.to_string(),
+                call.ttl,
+            );
+        }
+    }
+
+    /// The cached output of `subject` calling `tool` with `arguments`, unless it expired.
+    pub(crate) fn get(
+        &self,
+        component_id: &str,
+        tool: &str,
+        subject: Option<&str>,
+        arguments: &Value,
+    ) -> Option<String> {
+        let key = CacheKey::new(component_id, tool, subject, arguments);
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.output.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Keep `output` of `subject` calling `tool` with `arguments` for `ttl`.
+    pub(crate) fn put(
+        &self,
+        component_id: &str,
+        tool: &str,
+        subject: Option<&str>,
+        arguments: &Value,
+        output: String,
+        ttl: Duration,
+    ) {
+        let now = Instant::now();
+        let key = CacheKey::new(component_id, tool, subject, arguments);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.expires > now);
+            if entries.len() >= MAX_ENTRIES {
+                let soonest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires)
+                    .map(|(key, _)| key.clone());
+                if let Some(soonest) = soonest {
+                    entries.remove(&soonest);
+                }
+            }
+        }
+        entries.insert(
+            key,
+            Entry {
+                output,
+                expires: now + ttl,
+            },
+        );
+    }
+
+    /// Drop every cached result of `component_id`.
+    pub(crate) fn forget(&self, component_id: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|key, _| key.component_id != component_id);
+    }
+
+    /// Drop every cached result.
+    pub(crate) fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl CacheKey {
+    fn new(component_id: &str, tool: &str, subject: Option<&str>, arguments: &Value) -> Self {
+        Self {
+            component_id: component_id.to_string(),
+            tool: tool.to_string(),
+            subject: subject.map(str::to_string),
+            arguments: canonical_json(arguments),
+        }
+    }
+}
+
+/// Whether the JSON results of a call hold a WIT `result` in its `err` case.
+pub(crate) fn reports_error(results: &Value) -> bool {
+    results["result"]
+        .as_object()
+        .is_some_and(|result| result.len() == 1 && result.contains_key("err"))
+}
+
+/// `value` serialized with the keys of every object sorted.
+pub(crate) fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut fields: Vec<_> = map.iter().collect();
+            fields.sort_by_key(|(key, _)| *key);
+            let fields: Vec<String> = fields
+                .into_iter()
+                .map(|(key, value)| {
+                    format!("{}:{}", Value::from(key.as_str()), canonical_json(value))
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_arguments_match_regardless_of_key_order() {
+        let cache = ResultCache::default();
+        cache.put(
+            "weather",
+            "get-weather",
+            None,
+            &json!({"city": "Oslo", "units": {"temp": "c", "wind": "m/s"}}),
+            "cold".to_string(),
+            TTL,
+        );
+
+        let reordered = json!({"units": {"wind": "m/s", "temp": "c"}, "city": "Oslo"});
+        assert_eq!(
+            cache
+                .get("weather", "get-weather", None, &reordered)
+                .as_deref(),
+            Some("cold")
+        );
+        assert!(cache
+            .get("weather", "get-weather", None, &json!({"city": "Bergen"}))
+            .is_none());
+        assert!(cache
+            .get("weather", "get-forecast", None, &reordered)
+            .is_none());
+        assert!(cache
+            .get("other", "get-weather", None, &reordered)
+            .is_none());
+    }
+
+    #[test]
+    fn test_entries_expire_and_are_forgotten() {
+        let cache = ResultCache::default();
+        let args = json!({"city": "Oslo"});
+        cache.put(
+            "weather",
+            "get-weather",
+            None,
+            &args,
+            "cold".to_string(),
+            Duration::ZERO,
+        );
+        assert!(cache.get("weather", "get-weather", None, &args).is_none());
+
+        cache.put(
+            "weather",
+            "get-weather",
+            None,
+            &args,
+            "cold".to_string(),
+            TTL,
+        );
+        cache.put("news", "headlines", None, &args, "quiet".to_string(), TTL);
+        cache.forget("weather");
+        assert!(cache.get("weather", "get-weather", None, &args).is_none());
+        assert!(cache.get("news", "headlines", None, &args).is_some());
+    }
+
+    #[test]
+    fn test_subjects_dont_share_entries() {
+        let cache = ResultCache::default();
+        let args = json!({"repo": "wassette"});
+        cache.put(
+            "github",
+            "list-issues",
+            Some("alice"),
+            &args,
+            "alice's issues".to_string(),
+            TTL,
+        );
+
+        assert_eq!(
+            cache
+                .get("github", "list-issues", Some("alice"), &args)
+                .as_deref(),
+            Some("alice's issues")
+        );
+        assert!(cache
+            .get("github", "list-issues", Some("bob"), &args)
+            .is_none());
+        assert!(cache.get("github", "list-issues", None, &args).is_none());
+    }
+
+    #[test]
+    fn test_reported_errors_are_not_kept() {
+        let cache = ResultCache::default();
+        let args = json!({"city": "Oslo"});
+        let call = CachedCall {
+            component_id: "weather",
+            tool: "get-weather",
+            subject: None,
+            arguments: &args,
+            ttl: TTL,
+        };
+        cache.keep(&call, &json!({"result": {"err": "timed out"}}), "timed out");
+        assert!(cache.lookup(&call).is_none());
+
+        cache.keep(&call, &json!({"result": {"ok": "cold"}}), "cold");
+        assert_eq!(cache.lookup(&call).as_deref(), Some("cold"));
+    }
+
+    #[test]
+    fn test_reports_error() {
+        assert!(reports_error(&json!({"result": {"err": "timed out"}})));
+        assert!(!reports_error(&json!({"result": {"ok": "sunny"}})));
+        assert!(!reports_error(&json!({"result": "sunny"})));
+        assert!(!reports_error(&json!({"result": {"err": 1, "retries": 3}})));
+        assert!(!reports_error(&Value::Null));
+    }
+
+    #[test]
+    fn test_full_cache_evicts_the_entry_expiring_soonest() {
+        let cache = ResultCache::default();
+        cache.put(
+            "c",
+            "t",
+            None,
+            &json!(0),
+            "short".to_string(),
+            Duration::from_secs(1),
+        );
+        for i in 1..MAX_ENTRIES {
+            cache.put("c", "t", None, &json!(i), i.to_string(), TTL);
+        }
+        cache.put("c", "t", None, &json!(MAX_ENTRIES), "new".to_string(), TTL);
+
+        assert_eq!(cache.entries.lock().unwrap().len(), MAX_ENTRIES);
+        assert!(cache.get("c", "t", None, &json!(0)).is_none());
+        assert_eq!(cache.get("c", "t", None, &json!(1)).as_deref(), Some("1"));
+        assert_eq!(
+            cache.get("c", "t", None, &json!(MAX_ENTRIES)).as_deref(),
+            Some("new")
+        );
+    }
+}
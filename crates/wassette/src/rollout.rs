@@ -0,0 +1,251 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Staged rollout of a new version of a component.
+//!
+//! Loading a component, whether new or in place of a running version, goes through the same
+//! stages:
+//!
+//! 1. **load**: the artifact is staged in the plugin directory and compiled;
+//! 2. **verify**: the new version is instantiated once under the component's policy;
+//! 3. **warm**: standby instances are built for its latency-critical tools;
+//! 4. **swap**: its tools replace those of the running version in one step;
+//! 5. **drain**: calls that started before the swap finish on the old version, which is
+//!    dropped once the last of them returns.
+//!
+//! A failure before the swap rolls back: the files of the running version are put back and it
+//! keeps serving as if nothing happened. Every stage is logged as a `wassette::audit` event,
+//! appended to `audit/rollouts.jsonl` under the plugin directory and broadcast to subscribers
+//! such as the MCP server, which forwards them to clients as log notifications.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::fs_audit::{self, AUDIT_DIR};
+
+const ROLLOUTS_FILE: &str = "rollouts.jsonl";
+
+/// Events kept for subscribers that fall behind.
+const EVENT_BUFFER: usize = 256;
+
+/// How often the old version is checked for calls still running.
+pub(crate) const DRAIN_POLL: Duration = Duration::from_millis(50);
+
+/// Calls still running on the old version this long after the swap are left to finish on
+/// their own, and the rollout is reported complete.
+pub(crate) const DRAIN_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Stage a rollout reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RolloutStage {
+    /// Staging and compiling the new version
+    Load,
+    /// Instantiating the new version once
+    Verify,
+    /// Building standby instances of the new version
+    Warm,
+    /// Replacing the running version's tools
+    Swap,
+    /// Waiting for calls on the old version to finish
+    Drain,
+    /// The new version serves every call
+    Complete,
+    /// The new version was rejected and the running version restored
+    RolledBack,
+}
+
+/// A stage of the rollout of a component version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RolloutEvent {
+    /// Identifies the rollout among those since the server started
+    pub rollout: u64,
+    /// Component being rolled out
+    pub component_id: String,
+    /// Whether a running version is being replaced
+    pub replacing: bool,
+    /// Stage reached
+    pub stage: RolloutStage,
+    /// Stage that failed, for a rollback
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_stage: Option<RolloutStage>,
+    /// Why the rollout was rolled back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Time of the event (seconds since epoch)
+    pub created_at: u64,
+}
+
+/// Where rollout events go.
+pub(crate) struct Rollouts {
+    next: AtomicU64,
+    events: broadcast::Sender<RolloutEvent>,
+    log: PathBuf,
+}
+
+impl Rollouts {
+    pub(crate) fn new(plugin_dir: &Path) -> Self {
+        Self {
+            next: AtomicU64::new(1),
+            events: broadcast::channel(EVENT_BUFFER).0,
+            log: log_path(plugin_dir),
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<RolloutEvent> {
+        self.events.subscribe()
+    }
+
+    /// Begin the rollout of a version of `component_id`, in its load stage.
+    pub(crate) fn start(self: &Arc<Self>, component_id: &str, replacing: bool) -> Rollout {
+        let rollout = Rollout {
+            id: self.next.fetch_add(1, Ordering::Relaxed),
+            component_id: component_id.to_string(),
+            replacing,
+            stage: RolloutStage::Load,
+            rollouts: Arc::clone(self),
+        };
+        rollout.record(RolloutStage::Load, None, None);
+        rollout
+    }
+}
+
+/// One rollout in progress.
+pub(crate) struct Rollout {
+    id: u64,
+    component_id: String,
+    replacing: bool,
+    stage: RolloutStage,
+    rollouts: Arc<Rollouts>,
+}
+
+impl Rollout {
+    /// Move on to `stage`.
+    pub(crate) fn enter(&mut self, stage: RolloutStage) {
+        self.stage = stage;
+        self.record(stage, None, None);
+    }
+
+    /// Report the new version serving every call.
+    pub(crate) fn complete(mut self) {
+        self.enter(RolloutStage::Complete);
+    }
+
+    /// Report that the stage in progress failed with `error` and the running version is back.
+    pub(crate) fn roll_back(self, error: &anyhow::Error) {
+        self.record(
+            RolloutStage::RolledBack,
+            Some(self.stage),
+            Some(format!("{error:#}")),
+        );
+    }
+
+    fn record(
+        &self,
+        stage: RolloutStage,
+        failed_stage: Option<RolloutStage>,
+        error: Option<String>,
+    ) {
+        match &error {
+            Some(error) => warn!(
+                target: "wassette::audit",
+                rollout = self.id,
+                component_id = %self.component_id,
+                failed_stage = ?failed_stage,
+                %error,
+                "Component rollout rolled back"
+            ),
+            None => info!(
+                target: "wassette::audit",
+                rollout = self.id,
+                component_id = %self.component_id,
+                replacing = self.replacing,
+                stage = ?stage,
+                "Component rollout"
+            ),
+        }
+        let event = RolloutEvent {
+            rollout: self.id,
+            component_id: self.component_id.clone(),
+            replacing: self.replacing,
+            stage,
+            failed_stage,
+            error,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+        if let Err(error) = fs_audit::append(&self.rollouts.log, &event) {
+            warn!(%error, "Failed to record component rollout");
+        }
+        // Nobody may be listening.
+        let _ = self.rollouts.events.send(event);
+    }
+}
+
+fn log_path(plugin_dir: &Path) -> PathBuf {
+    plugin_dir.join(AUDIT_DIR).join(ROLLOUTS_FILE)
+}
+
+/// Rollout events recorded under `plugin_dir`, oldest first. Unreadable lines are skipped.
+pub(crate) async fn list(plugin_dir: &Path) -> anyhow::Result<Vec<RolloutEvent>> {
+    fs_audit::read_records(&log_path(plugin_dir)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rollout_events_are_recorded_and_broadcast() {
+        let dir = tempfile::tempdir().unwrap();
+        let rollouts = Arc::new(Rollouts::new(dir.path()));
+        let mut events = rollouts.subscribe();
+
+        let mut rollout = rollouts.start("fetch", true);
+        rollout.enter(RolloutStage::Verify);
+        rollout.roll_back(&anyhow::anyhow!("missing export"));
+        let mut rollout = rollouts.start("fetch", true);
+        for stage in [
+            RolloutStage::Verify,
+            RolloutStage::Warm,
+            RolloutStage::Swap,
+            RolloutStage::Drain,
+        ] {
+            rollout.enter(stage);
+        }
+        rollout.complete();
+
+        let recorded = list(dir.path()).await.unwrap();
+        let stages: Vec<_> = recorded.iter().map(|event| event.stage).collect();
+        assert_eq!(
+            stages,
+            [
+                RolloutStage::Load,
+                RolloutStage::Verify,
+                RolloutStage::RolledBack,
+                RolloutStage::Load,
+                RolloutStage::Verify,
+                RolloutStage::Warm,
+                RolloutStage::Swap,
+                RolloutStage::Drain,
+                RolloutStage::Complete,
+            ]
+        );
+        assert_eq!(recorded[2].failed_stage, Some(RolloutStage::Verify));
+        assert_eq!(recorded[2].error.as_deref(), Some("missing export"));
+        assert_eq!(recorded[0].rollout, 1);
+        assert_eq!(recorded[8].rollout, 2);
+
+        for expected in recorded {
+            assert_eq!(events.recv().await.unwrap(), expected);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Per-component configuration served through the `wasi:config/store` host interface.
+//!
+//! Components import
+//!
+//! ```wit
+//! package wasi:config@0.2.0-draft;
+//!
+//! interface store {
+//!     variant error { upstream(string), io(string) }
+//!
+//!     get: func(key: string) -> result<option<string>, error>;
+//!     get-all: func() -> result<list<tuple<string, string>>, error>;
+//! }
+//! ```
+//!
+//! Besides the environment variables the policy grants and the component's secrets, the store
+//! holds the values set with `wassette config set`. They are kept as YAML in the `config`
+//! directory next to the secrets, one file per component, and read again whenever the file
+//! changes, so new instances see them without restarting the server. Unlike secrets and
+//! granted environment variables, they aren't exposed as environment variables, and they take
+//! precedence over both.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// Directory in the secrets directory holding the configuration files.
+pub const CONFIG_DIR: &str = "config";
+
+/// Configuration values of one component, by key.
+pub type ComponentConfig = BTreeMap<String, String>;
+
+/// The configuration of every component, cached until its file changes.
+#[derive(Debug)]
+pub(crate) struct RuntimeConfig {
+    dir: PathBuf,
+    cache: RwLock<HashMap<String, (SystemTime, Arc<ComponentConfig>)>>,
+}
+
+impl RuntimeConfig {
+    /// Configuration stored in the `config` directory of `secrets_dir`.
+    pub(crate) fn new(secrets_dir: &Path) -> Self {
+        Self {
+            dir: secrets_dir.join(CONFIG_DIR),
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn path(&self, component_id: &str) -> PathBuf {
+        self.dir.join(format!("{component_id}.yaml"))
+    }
+
+    /// The configuration of `component_id`, empty if none was set.
+    pub(crate) async fn load(&self, component_id: &str) -> Result<Arc<ComponentConfig>> {
+        let path = self.path(component_id);
+        let mtime = match tokio::fs::metadata(&path).await {
+            Ok(metadata) => metadata.modified()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Arc::default());
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        if let Some((cached_mtime, config)) = self.cache.read().await.get(component_id) {
+            if *cached_mtime == mtime {
+                return Ok(Arc::clone(config));
+            }
+        }
+        let config = Arc::new(self.read(&path).await?);
+        self.cache
+            .write()
+            .await
+            .insert(component_id.to_string(), (mtime, Arc::clone(&config)));
+        Ok(config)
+    }
+
+    /// Set `values` in the configuration of `component_id`, keeping its other keys.
+    pub(crate) async fn set(&self, component_id: &str, values: &[(String, String)]) -> Result<()> {
+        if let Some((key, _)) = values.iter().find(|(key, _)| key.is_empty()) {
+            bail!("Configuration keys can't be empty, got: {key:?}");
+        }
+        let path = self.path(component_id);
+        let mut config = self.read(&path).await?;
+        config.extend(values.iter().cloned());
+        self.write(component_id, &path, &config).await?;
+        info!(%component_id, keys = values.len(), "Updated component configuration");
+        Ok(())
+    }
+
+    /// Remove `keys` from the configuration of `component_id`, returning how many were set.
+    pub(crate) async fn unset(&self, component_id: &str, keys: &[String]) -> Result<usize> {
+        let path = self.path(component_id);
+        let mut config = self.read(&path).await?;
+        let removed = keys
+            .iter()
+            .filter(|key| config.remove(key.as_str()).is_some())
+            .count();
+        if removed > 0 {
+            self.write(component_id, &path, &config).await?;
+            info!(%component_id, keys = removed, "Removed component configuration");
+        }
+        Ok(removed)
+    }
+
+    async fn read(&self, path: &Path) -> Result<ComponentConfig> {
+        match tokio::fs::read_to_string(path).await {
+            Ok(content) => serde_yaml::from_str(&content)
+                .with_context(|| format!("Failed to parse configuration file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ComponentConfig::new()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    async fn write(&self, component_id: &str, path: &Path, config: &ComponentConfig) -> Result<()> {
+        // Whatever is cached, the next load reads the file again.
+        self.cache.write().await.remove(component_id);
+        if config.is_empty() {
+            return match tokio::fs::remove_file(path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+                }
+                _ => Ok(()),
+            };
+        }
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let staged = path.with_extension("yaml.tmp");
+        tokio::fs::write(&staged, serde_yaml::to_string(config)?)
+            .await
+            .with_context(|| format!("Failed to write {}", staged.display()))?;
+        tokio::fs::rename(&staged, path)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_config_is_set_and_unset_per_component() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = RuntimeConfig::new(dir.path());
+        assert!(config.load("fetch").await?.is_empty());
+
+        config
+            .set(
+                "fetch",
+                &values(&[("endpoint", "https://a"), ("retries", "3")]),
+            )
+            .await?;
+        config
+            .set("fetch", &values(&[("endpoint", "https://b")]))
+            .await?;
+        let fetch = config.load("fetch").await?;
+        assert_eq!(fetch.get("endpoint").unwrap(), "https://b");
+        assert_eq!(fetch.get("retries").unwrap(), "3");
+        assert!(config.load("time").await?.is_empty());
+        assert!(config.set("fetch", &values(&[("", "x")])).await.is_err());
+
+        let removed = config
+            .unset("fetch", &["retries".to_string(), "missing".to_string()])
+            .await?;
+        assert_eq!(removed, 1);
+        assert_eq!(config.load("fetch").await?.len(), 1);
+        config.unset("fetch", &["endpoint".to_string()]).await?;
+        assert!(!dir.path().join(CONFIG_DIR).join("fetch.yaml").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_edits_to_the_file_are_picked_up() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = RuntimeConfig::new(dir.path());
+        config.set("fetch", &values(&[("mode", "fast")])).await?;
+        assert_eq!(config.load("fetch").await?.get("mode").unwrap(), "fast");
+
+        // E.g. `wassette config set` in another process
+        let path = dir.path().join(CONFIG_DIR).join("fetch.yaml");
+        std::fs::write(&path, "mode: safe\n")?;
+        let file = std::fs::File::options().write(true).open(&path)?;
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(5))?;
+        assert_eq!(config.load("fetch").await?.get("mode").unwrap(), "safe");
+        Ok(())
+    }
+}
@@ -4,31 +4,108 @@
 //! Support utilities for sharing Wasmtime engine and linker state across lifecycle
 //! manager instances.
 
+use std::collections::BTreeSet;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
 use wasmtime::component::{Component, InstancePre, Linker};
-use wasmtime::Engine;
+use wasmtime::{Engine, InstanceAllocationStrategy, PoolingAllocationConfig};
 use wasmtime_wasi_config::WasiConfig;
 
-use crate::{WasiState, WassetteWasiState};
+use crate::{
+    browser, call_progress, elevation, email, fs_grants, keyvalue, proposals, resources, sampling,
+    session_context, vector, WasiState, WasmProposal, WassetteWasiState,
+};
+
+/// Interval at which the engine epoch advances.
+pub const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// WIT packages the linker implements for components. Keep in sync with
+/// [`RuntimeContext::initialize`].
+pub const HOST_PACKAGES: &[&str] = &[
+    "wasi:cli",
+    "wasi:clocks",
+    "wasi:filesystem",
+    "wasi:io",
+    "wasi:random",
+    "wasi:sockets",
+    "wasi:http",
+    "wasi:config",
+    "wasi:keyvalue",
+    "wassette:ai",
+    "wassette:context",
+    "wassette:browser",
+    "wassette:email",
+    "wassette:progress",
+    "wassette:resources",
+    "wassette:vector",
+];
+
+/// Engine-level settings derived from the lifecycle configuration.
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeOptions {
+    /// Attach a [`wasmtime::WasmCoreDump`] to traps.
+    pub coredump_on_trap: bool,
+    /// Optional WebAssembly proposals to enable; all others are disabled.
+    pub wasm_proposals: BTreeSet<WasmProposal>,
+    /// Allocate instances from Wasmtime's pooling allocator instead of on demand.
+    pub pooling: bool,
+}
 
 /// Encapsulates Wasmtime engine and linker setup for reuse across the lifecycle manager.
 #[derive(Clone)]
 pub struct RuntimeContext {
     engine: Arc<Engine>,
     linker: Arc<Linker<WassetteWasiState<WasiState>>>,
+    options: RuntimeOptions,
 }
 
 impl RuntimeContext {
     /// Build a runtime context with the standard configuration used by Wassette.
-    pub fn initialize() -> Result<Self> {
+    ///
+    /// When `coredump_on_trap` is set, traps carry a [`wasmtime::WasmCoreDump`] that the
+    /// lifecycle manager persists for later analysis. Fuel is always metered and epochs always
+    /// interrupt, since any component's policy may limit its fuel or its time, so every store
+    /// must be given fuel and an epoch deadline before it runs code. A background thread
+    /// advances the engine epoch every [`EPOCH_TICK`] for as long as the engine is alive.
+    ///
+    /// With `pooling`, the memories, tables and stacks of instances come from slots reserved
+    /// up front and are reset rather than unmapped when an instance is dropped, which makes
+    /// instantiation much cheaper. The pool has Wasmtime's default capacity: at most 1000
+    /// instances at once, each with one memory of up to 4 GiB per module.
+    pub fn initialize(options: &RuntimeOptions) -> Result<Self> {
+        let mut options = options.clone();
+        options
+            .wasm_proposals
+            .extend(proposals::PER_COMPONENT_PROPOSALS);
+
         let mut config = wasmtime::Config::new();
         config.wasm_component_model(true);
         config.async_support(true);
+        config.coredump_on_trap(options.coredump_on_trap);
+        config.epoch_interruption(true);
+        config.consume_fuel(true);
+        if options.pooling {
+            config.allocation_strategy(InstanceAllocationStrategy::Pooling(
+                PoolingAllocationConfig::default(),
+            ));
+        }
+        proposals::configure(&mut config, &options.wasm_proposals);
 
         let engine = Arc::new(Engine::new(&config)?);
 
+        let weak = engine.weak();
+        std::thread::Builder::new()
+            .name("wassette-epoch".to_string())
+            .spawn(move || {
+                while let Some(engine) = weak.upgrade() {
+                    engine.increment_epoch();
+                    drop(engine);
+                    std::thread::sleep(EPOCH_TICK);
+                }
+            })?;
+
         let mut linker = Linker::new(engine.as_ref());
         wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
         wasmtime_wasi_http::add_only_http_to_linker_async(&mut linker)?;
@@ -36,13 +113,30 @@ impl RuntimeContext {
             &mut linker,
             |h: &mut WassetteWasiState<WasiState>| WasiConfig::from(&h.inner.wasi_config_vars),
         )?;
+        sampling::add_to_linker(&mut linker)?;
+        keyvalue::add_to_linker(&mut linker)?;
+        vector::add_to_linker(&mut linker)?;
+        session_context::add_to_linker(&mut linker)?;
+        browser::add_to_linker(&mut linker)?;
+        email::add_to_linker(&mut linker)?;
+        resources::add_to_linker(&mut linker)?;
+        call_progress::add_to_linker(&mut linker)?;
+        fs_grants::add_to_linker(&mut linker)?;
+        elevation::add_to_linker(&mut linker)?;
 
         Ok(Self {
             engine,
             linker: Arc::new(linker),
+            options,
         })
     }
 
+    /// Optional WebAssembly proposals the engine enables, including those each component's
+    /// policy must grant.
+    pub fn wasm_proposals(&self) -> &BTreeSet<WasmProposal> {
+        &self.options.wasm_proposals
+    }
+
     /// Produce a cached `InstancePre` handle for the provided component using
     /// the shared linker configuration.
     pub fn instantiate_pre(
@@ -0,0 +1,204 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! MCP sampling exposed to components through the `wassette:ai/sample` host interface.
+//!
+//! Components import
+//!
+//! ```wit
+//! package wassette:ai;
+//!
+//! interface sample {
+//!     record request {
+//!         prompt: string,
+//!         system-prompt: option<string>,
+//!         max-tokens: u32,
+//!     }
+//!
+//!     sample: func(request: request) -> result<string, string>;
+//! }
+//! ```
+//!
+//! and the host forwards each request to the connected MCP client. Sampling is denied unless
+//! the component's policy has a `sampling` section, which also caps the tokens per request and
+//! the number of requests per tool invocation.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use policy::PolicyDocument;
+use tracing::{info_span, Instrument};
+use wasmtime::component::{ComponentType, Lift, Linker};
+
+use crate::{WasiState, WassetteWasiState};
+
+/// Name of the host interface that provides sampling.
+pub const SAMPLING_INTERFACE: &str = "wassette:ai/sample";
+
+/// Token cap applied when the policy allows sampling without setting `max-tokens`.
+pub const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+/// Call cap applied when the policy allows sampling without setting `max-calls`.
+pub const DEFAULT_MAX_CALLS: u32 = 8;
+
+/// A sampling request issued by a component.
+#[derive(ComponentType, Lift, Debug, Clone, PartialEq, Eq)]
+#[component(record)]
+pub struct SamplingRequest {
+    /// User prompt sent to the model
+    pub prompt: String,
+    /// Optional system prompt
+    #[component(name = "system-prompt")]
+    pub system_prompt: Option<String>,
+    /// Maximum number of tokens to generate, already clamped to the policy cap
+    #[component(name = "max-tokens")]
+    pub max_tokens: u32,
+}
+
+/// Forwards sampling requests to whoever can answer them, usually the connected MCP client.
+pub trait Sampler: Send + Sync {
+    /// Ask the model to complete `request`, returning the generated text.
+    fn sample(&self, request: SamplingRequest) -> BoxFuture<'_, Result<String>>;
+}
+
+/// Per-component sampling caps derived from the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplingLimits {
+    /// Upper bound on `max-tokens` for a single request
+    pub max_tokens: u32,
+    /// Maximum number of requests during a single tool invocation
+    pub max_calls: u32,
+}
+
+impl SamplingLimits {
+    /// Limits granted by `policy`, or `None` if the policy doesn't allow sampling.
+    pub fn from_policy(policy: &PolicyDocument) -> Option<Self> {
+        policy.permissions.sampling.as_ref().map(|sampling| Self {
+            max_tokens: sampling.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            max_calls: sampling.max_calls.unwrap_or(DEFAULT_MAX_CALLS),
+        })
+    }
+}
+
+/// Sampling state of a single store.
+#[derive(Default, Clone)]
+pub struct SamplingState {
+    /// Caps from the policy; `None` denies sampling
+    pub limits: Option<SamplingLimits>,
+    /// Sampler for the current invocation; `None` when no client can answer
+    pub sampler: Option<Arc<dyn Sampler>>,
+    /// Requests made so far in this store
+    pub calls: u32,
+}
+
+impl SamplingState {
+    /// Check a request against the caps and record it, clamping its token budget.
+    fn admit(
+        &mut self,
+        mut request: SamplingRequest,
+    ) -> std::result::Result<(Arc<dyn Sampler>, SamplingRequest), String> {
+        let limits = self
+            .limits
+            .ok_or_else(|| "sampling is not permitted by the component policy".to_string())?;
+        let sampler = self
+            .sampler
+            .clone()
+            .ok_or_else(|| "no client is available for sampling".to_string())?;
+        if self.calls >= limits.max_calls {
+            return Err(format!(
+                "sampling limit of {} calls per invocation reached",
+                limits.max_calls
+            ));
+        }
+        self.calls += 1;
+        request.max_tokens = request.max_tokens.clamp(1, limits.max_tokens);
+        Ok((sampler, request))
+    }
+}
+
+/// Add the `wassette:ai/sample` interface to `linker`.
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    linker.instance(SAMPLING_INTERFACE)?.func_wrap_async(
+        "sample",
+        |mut store, (request,): (SamplingRequest,)| {
+            let admitted = store.data_mut().inner.sampling.admit(request);
+            Box::new(async move {
+                let result = match admitted {
+                    Ok((sampler, request)) => sampler
+                        .sample(request)
+                        .instrument(info_span!("sample"))
+                        .await
+                        .map_err(|e| e.to_string()),
+                    Err(e) => Err(e),
+                };
+                Ok((result,))
+            })
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+    use policy::PolicyParser;
+
+    use super::*;
+
+    struct Echo;
+
+    impl Sampler for Echo {
+        fn sample(&self, request: SamplingRequest) -> BoxFuture<'_, Result<String>> {
+            async move { Ok(format!("{}:{}", request.prompt, request.max_tokens)) }.boxed()
+        }
+    }
+
+    fn request(max_tokens: u32) -> SamplingRequest {
+        SamplingRequest {
+            prompt: "hi".to_string(),
+            system_prompt: None,
+            max_tokens,
+        }
+    }
+
+    #[test]
+    fn test_limits_from_policy() {
+        let policy = PolicyParser::parse_str("version: \"1.0\"\npermissions: {}\n").unwrap();
+        assert_eq!(SamplingLimits::from_policy(&policy), None);
+
+        let policy = PolicyParser::parse_str(
+            "version: \"1.0\"\npermissions:\n  sampling:\n    max-tokens: 64\n",
+        )
+        .unwrap();
+        assert_eq!(
+            SamplingLimits::from_policy(&policy),
+            Some(SamplingLimits {
+                max_tokens: 64,
+                max_calls: DEFAULT_MAX_CALLS,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admit_enforces_caps() {
+        let mut state = SamplingState {
+            sampler: Some(Arc::new(Echo)),
+            ..Default::default()
+        };
+        assert!(state.admit(request(10)).is_err());
+
+        state.limits = Some(SamplingLimits {
+            max_tokens: 64,
+            max_calls: 2,
+        });
+        let (sampler, clamped) = state.admit(request(1000)).unwrap();
+        assert_eq!(clamped.max_tokens, 64);
+        assert_eq!(sampler.sample(clamped).await.unwrap(), "hi:64");
+        assert!(state.admit(request(10)).is_ok());
+        assert!(state.admit(request(10)).is_err());
+
+        state.calls = 0;
+        state.sampler = None;
+        assert!(state.admit(request(10)).is_err());
+    }
+}
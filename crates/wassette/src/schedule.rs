@@ -0,0 +1,164 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Hours at which tools may be called, from the `schedule` section of a component's policy.
+//!
+//! ```yaml
+//! permissions:
+//!   schedule:
+//!     - tool: drop-table
+//!       allowed-hours: "09:00-18:00 Mon-Fri"
+//!       timezone: "+01:00"
+//!       require-confirm-outside-hours: true
+//! ```
+//!
+//! The hours are checked against the clock at every call of the tool. A call outside them
+//! fails with an [`OutsideAllowedHours`] error, unless the tool has
+//! `require-confirm-outside-hours` and the user confirmed the call, when the
+//! [`PermissionPrompter`](crate::PermissionPrompter) of the call asked them, or the caller
+//! confirmed it with [`CallContext::with_confirmation`](crate::CallContext::with_confirmation).
+//! Organizations can thereby let risky tools run unattended during office hours and keep a
+//! human in the loop at night and on weekends.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, FixedOffset, Local, Timelike, Utc};
+use policy::{AllowedHours, PolicyDocument, ScheduleZone};
+
+/// A tool call was refused because it was made outside the hours its policy allows.
+///
+/// Like [`CallTimedOut`](crate::CallTimedOut), it is attached to the error of the call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutsideAllowedHours {
+    /// The component that was called
+    pub component_id: String,
+    /// The tool that was called
+    pub tool: String,
+    /// The `allowed-hours` of the tool, as written in the policy
+    pub allowed_hours: String,
+    /// Whether the call goes through once the caller confirms it
+    pub confirmable: bool,
+}
+
+impl fmt::Display for OutsideAllowedHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "outside allowed hours: tool {} of component {} may only be called {}",
+            self.tool, self.component_id, self.allowed_hours
+        )?;
+        if self.confirmable {
+            f.write_str(" unless the call is confirmed")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for OutsideAllowedHours {}
+
+/// The `schedule` entry of one tool.
+#[derive(Debug, Clone)]
+pub(crate) struct ToolHours {
+    written: String,
+    hours: AllowedHours,
+    zone: ScheduleZone,
+    confirmable: bool,
+}
+
+impl ToolHours {
+    /// The hours of each tool with a `schedule` entry in `policy`, keyed by tool name.
+    pub(crate) fn from_policy(policy: &PolicyDocument) -> Result<HashMap<String, Self>> {
+        let mut tools = HashMap::new();
+        for entry in policy.permissions.schedule.iter().flatten() {
+            let (hours, zone) = entry
+                .window()
+                .with_context(|| format!("Invalid schedule for tool {}", entry.tool))?;
+            let hours = Self {
+                written: entry.allowed_hours.trim().to_string(),
+                hours,
+                zone,
+                confirmable: entry.require_confirm_outside_hours,
+            };
+            tools.insert(entry.tool.clone(), hours);
+        }
+        Ok(tools)
+    }
+
+    /// Refuse a call of `tool` made at `now` outside the hours, unless it is `confirmed`
+    /// and the hours let confirmed calls through.
+    pub(crate) fn check(
+        &self,
+        component_id: &str,
+        tool: &str,
+        confirmed: bool,
+        now: DateTime<Utc>,
+    ) -> Result<(), OutsideAllowedHours> {
+        let local = match self.zone {
+            ScheduleZone::Local => now.with_timezone(&Local).naive_local(),
+            ScheduleZone::Offset(minutes) => {
+                let offset = FixedOffset::east_opt(minutes * 60).expect("offsets are within a day");
+                now.with_timezone(&offset).naive_local()
+            }
+        };
+        let weekday = local.weekday().num_days_from_monday() as usize;
+        let minute = (local.hour() * 60 + local.minute()) as u16;
+        if self.hours.contains(weekday, minute) || (confirmed && self.confirmable) {
+            return Ok(());
+        }
+        Err(OutsideAllowedHours {
+            component_id: component_id.to_string(),
+            tool: tool.to_string(),
+            allowed_hours: self.written.clone(),
+            confirmable: self.confirmable,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use policy::PolicyParser;
+
+    use super::*;
+
+    #[test]
+    fn test_calls_outside_hours_need_confirmation() {
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+permissions:
+  schedule:
+    - tool: drop-table
+      allowed-hours: "09:00-18:00 Mon-Fri"
+      timezone: "+01:00"
+      require-confirm-outside-hours: true
+    - tool: deploy
+      allowed-hours: "09:00-17:00"
+      timezone: utc
+"#,
+        )
+        .unwrap();
+        let tools = ToolHours::from_policy(&policy).unwrap();
+        // Monday 2025-06-02, 08:30 UTC is 09:30 at +01:00.
+        let monday = Utc.with_ymd_and_hms(2025, 6, 2, 8, 30, 0).unwrap();
+        let saturday = Utc.with_ymd_and_hms(2025, 6, 7, 10, 30, 0).unwrap();
+
+        let drop_table = &tools["drop-table"];
+        assert!(drop_table.check("db", "drop-table", false, monday).is_ok());
+        let refused = drop_table
+            .check("db", "drop-table", false, saturday)
+            .unwrap_err();
+        assert!(refused.confirmable);
+        assert_eq!(refused.allowed_hours, "09:00-18:00 Mon-Fri");
+        assert!(drop_table.check("db", "drop-table", true, saturday).is_ok());
+
+        // Without require-confirm-outside-hours, confirming doesn't help.
+        let deploy = &tools["deploy"];
+        assert!(deploy.check("ci", "deploy", false, saturday).is_ok());
+        let night = Utc.with_ymd_and_hms(2025, 6, 2, 23, 0, 0).unwrap();
+        let refused = deploy.check("ci", "deploy", true, night).unwrap_err();
+        assert!(!refused.confirmable);
+    }
+}
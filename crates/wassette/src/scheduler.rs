@@ -0,0 +1,361 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Priority scheduling of tool calls.
+//!
+//! Tools are `interactive` (the default) or `batch`. Once a tool is marked `batch`, every call
+//! takes one of a fixed number of execution slots, and calls that find them all taken wait in
+//! a queue that serves interactive calls before batch calls. A running batch call is also
+//! pre-empted at epoch boundaries: while interactive calls are waiting, it hands its slot to
+//! the first of them at the next epoch tick and queues again behind the other batch calls.
+//! Without batch tools no slots are taken and calls run as they always did.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+use wasmtime::{Store, UpdateDeadline};
+
+use crate::limits;
+use crate::warm::ToolSettings;
+use crate::wasistate::WasiState;
+use crate::WassetteWasiState;
+
+/// Scheduling class of a tool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Priority {
+    /// Chat-facing tool, served first
+    #[default]
+    Interactive,
+    /// Background tool, served after interactive calls and pre-empted by them
+    Batch,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    running: usize,
+    interactive: VecDeque<oneshot::Sender<()>>,
+    batch: VecDeque<oneshot::Sender<()>>,
+}
+
+/// Execution slots shared by all tool calls.
+#[derive(Debug)]
+pub(crate) struct Scheduler {
+    priorities: HashMap<String, Priority>,
+    slots: usize,
+    queue: Mutex<Queue>,
+}
+
+impl Scheduler {
+    /// A scheduler for `tools`, running at most `slots` calls at once if any tool is batch.
+    pub(crate) fn new(tools: &HashMap<String, ToolSettings>, slots: usize) -> Self {
+        Self {
+            priorities: tools
+                .iter()
+                .filter(|(_, settings)| settings.priority != Priority::Interactive)
+                .map(|(name, settings)| (name.clone(), settings.priority))
+                .collect(),
+            slots: slots.max(1),
+            queue: Mutex::default(),
+        }
+    }
+
+    /// Whether calls are scheduled at all, i.e. any tool is batch.
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.priorities.is_empty()
+    }
+
+    /// Execution slots shared by scheduled calls.
+    pub(crate) fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Interactive and batch calls waiting for a slot.
+    pub(crate) fn queued(&self) -> (usize, usize) {
+        let queue = self.queue.lock().unwrap();
+        (queue.interactive.len(), queue.batch.len())
+    }
+
+    /// Scheduling class of `tool`.
+    pub(crate) fn priority(&self, tool: &str) -> Priority {
+        self.priorities.get(tool).copied().unwrap_or_default()
+    }
+
+    /// Wait for a slot to run a call of `tool`. Returns `None` when calls aren't scheduled.
+    pub(crate) async fn acquire(self: &Arc<Self>, tool: &str) -> Option<CallSlot> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let priority = self.priority(tool);
+        self.wait(priority).await;
+        Some(CallSlot {
+            scheduler: Arc::clone(self),
+            priority,
+            held: Arc::new(AtomicBool::new(true)),
+        })
+    }
+
+    fn interactive_waiting(&self) -> bool {
+        !self.queue.lock().unwrap().interactive.is_empty()
+    }
+
+    /// Take a slot, queueing behind earlier calls of the same or a higher priority.
+    async fn wait(&self, priority: Priority) {
+        let receiver = {
+            let mut queue = self.queue.lock().unwrap();
+            let ahead = match priority {
+                Priority::Interactive => queue.interactive.len(),
+                Priority::Batch => queue.interactive.len() + queue.batch.len(),
+            };
+            if queue.running < self.slots && ahead == 0 {
+                queue.running += 1;
+                return;
+            }
+            let (sender, receiver) = oneshot::channel();
+            match priority {
+                Priority::Interactive => queue.interactive.push_back(sender),
+                Priority::Batch => queue.batch.push_back(sender),
+            }
+            receiver
+        };
+
+        // A slot handed over just as the waiting call was cancelled is passed on.
+        struct Pending<'a> {
+            scheduler: &'a Scheduler,
+            receiver: oneshot::Receiver<()>,
+        }
+        impl Drop for Pending<'_> {
+            fn drop(&mut self) {
+                if self.receiver.try_recv().is_ok() {
+                    self.scheduler.release();
+                }
+            }
+        }
+        let mut pending = Pending {
+            scheduler: self,
+            receiver,
+        };
+        let _ = (&mut pending.receiver).await;
+    }
+
+    /// Hand a slot to the next waiting call, interactive ones first, or free it.
+    fn release(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            let next = match queue.interactive.pop_front() {
+                Some(sender) => Some(sender),
+                None => queue.batch.pop_front(),
+            };
+            match next {
+                Some(sender) => {
+                    if sender.send(()).is_ok() {
+                        return;
+                    }
+                }
+                None => {
+                    queue.running -= 1;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// The slot a running call occupies, released when dropped.
+pub(crate) struct CallSlot {
+    scheduler: Arc<Scheduler>,
+    priority: Priority,
+    /// Cleared while a pre-empted call waits for a slot again
+    held: Arc<AtomicBool>,
+}
+
+impl CallSlot {
+    /// Let the batch call running in `store` give up its slot at epoch boundaries while
+    /// interactive calls are waiting, still checking the call's deadline. Interactive calls
+    /// are never pre-empted.
+    pub(crate) fn preempt_at_epochs(&self, store: &mut Store<WassetteWasiState<WasiState>>) {
+        if self.priority != Priority::Batch {
+            return;
+        }
+        let scheduler = Arc::clone(&self.scheduler);
+        let held = Arc::clone(&self.held);
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_callback(move |ctx| {
+            let state = &ctx.data().inner;
+            limits::check_deadline(state)?;
+            if !scheduler.interactive_waiting() {
+                return Ok(UpdateDeadline::Continue(1));
+            }
+            Ok(UpdateDeadline::YieldCustom(
+                1,
+                Box::pin(yield_slot(
+                    Arc::clone(&scheduler),
+                    Arc::clone(&held),
+                    state.deadline,
+                    state.cancellation.clone(),
+                )),
+            ))
+        });
+    }
+}
+
+impl Drop for CallSlot {
+    fn drop(&mut self) {
+        if self.held.swap(false, Ordering::SeqCst) {
+            self.scheduler.release();
+        }
+    }
+}
+
+/// Hand the slot of a pre-empted batch call to a waiting call and queue for a slot again.
+/// Stops queueing once the call's `deadline` passes or its `cancellation` is cancelled: the
+/// call then resumes without a slot and fails at the next epoch tick, where
+/// [`limits::check_deadline`] interrupts it as it would have without pre-emption.
+async fn yield_slot(
+    scheduler: Arc<Scheduler>,
+    held: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+    cancellation: Option<CancellationToken>,
+) {
+    if held.swap(false, Ordering::SeqCst) {
+        scheduler.release();
+    }
+    let waited = limits::until_deadline(deadline, cancellation.as_ref(), async {
+        scheduler.wait(Priority::Batch).await;
+        Ok(())
+    })
+    .await;
+    if waited.is_ok() {
+        held.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn scheduler(slots: usize) -> Arc<Scheduler> {
+        let tools = HashMap::from([(
+            "reindex".to_string(),
+            ToolSettings {
+                priority: Priority::Batch,
+                ..Default::default()
+            },
+        )]);
+        Arc::new(Scheduler::new(&tools, slots))
+    }
+
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    #[tokio::test]
+    async fn test_unscheduled_without_batch_tools() {
+        let scheduler = Arc::new(Scheduler::new(&HashMap::new(), 1));
+        assert!(!scheduler.is_enabled());
+        assert!(scheduler.acquire("fetch").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_interactive_calls_are_served_first() {
+        let scheduler = scheduler(1);
+        assert_eq!(scheduler.priority("reindex"), Priority::Batch);
+        assert_eq!(scheduler.priority("fetch"), Priority::Interactive);
+        let running = scheduler.acquire("fetch").await.unwrap();
+
+        let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+        for tool in ["reindex", "fetch"] {
+            let scheduler = Arc::clone(&scheduler);
+            let order_tx = order_tx.clone();
+            tokio::spawn(async move {
+                let _slot = scheduler.acquire(tool).await;
+                order_tx.send(tool).unwrap();
+                settle().await;
+            });
+            settle().await;
+        }
+
+        drop(running);
+        assert_eq!(order_rx.recv().await, Some("fetch"));
+        assert_eq!(order_rx.recv().await, Some("reindex"));
+    }
+
+    #[tokio::test]
+    async fn test_batch_call_yields_its_slot() {
+        let scheduler = scheduler(1);
+        let batch = scheduler.acquire("reindex").await.unwrap();
+
+        let waiting = tokio::spawn({
+            let scheduler = Arc::clone(&scheduler);
+            async move { scheduler.acquire("fetch").await }
+        });
+        settle().await;
+        assert!(scheduler.interactive_waiting());
+
+        let resumed = tokio::spawn(yield_slot(
+            Arc::clone(&scheduler),
+            Arc::clone(&batch.held),
+            None,
+            None,
+        ));
+        let interactive = waiting.await.unwrap();
+        assert!(!batch.held.load(Ordering::SeqCst));
+        assert!(!resumed.is_finished());
+
+        drop(interactive);
+        resumed.await.unwrap();
+        assert!(batch.held.load(Ordering::SeqCst));
+        drop(batch);
+        assert_eq!(scheduler.queue.lock().unwrap().running, 0);
+    }
+
+    #[tokio::test]
+    async fn test_yielded_call_stops_waiting_at_its_deadline() {
+        let scheduler = scheduler(1);
+        let batch = scheduler.acquire("reindex").await.unwrap();
+        let waiting = tokio::spawn({
+            let scheduler = Arc::clone(&scheduler);
+            async move { scheduler.acquire("fetch").await }
+        });
+        settle().await;
+
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let resumed = tokio::spawn(yield_slot(
+            Arc::clone(&scheduler),
+            Arc::clone(&batch.held),
+            Some(deadline),
+            None,
+        ));
+        let _interactive = waiting.await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), resumed)
+            .await
+            .expect("the yielded call waited past its deadline")
+            .unwrap();
+        assert!(!batch.held.load(Ordering::SeqCst));
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_passes_its_slot_on() {
+        let scheduler = scheduler(1);
+        let running = scheduler.acquire("fetch").await.unwrap();
+        let cancelled = tokio::spawn({
+            let scheduler = Arc::clone(&scheduler);
+            async move { scheduler.acquire("fetch").await }
+        });
+        settle().await;
+        cancelled.abort();
+        let _ = cancelled.await;
+
+        drop(running);
+        assert_eq!(scheduler.queue.lock().unwrap().running, 0);
+        assert!(scheduler.acquire("reindex").await.is_some());
+    }
+}
@@ -4,21 +4,42 @@
 //! Secret management for Wassette components
 //!
 //! This module provides functionality to manage per-component secrets that are:
-//! - Stored in OS-appropriate directories with proper permissions
+//! - Stored in OS-appropriate directories with proper permissions, or in the OS keyring
 //! - Persisted across runs without requiring server restart
 //! - Easy to edit and audit via CLI
 //! - Integrated with component environment variable system
+//!
+//! Where secrets are kept is up to a [`SecretsBackend`]. The default [`FileSecretsBackend`]
+//! keeps a YAML file per component in the secrets directory. [`KeyringSecretsBackend`] keeps
+//! them in the OS keyring instead (the macOS Keychain, the Windows Credential Manager or the
+//! Secret Service of libsecret), so no plaintext secrets are left on disk, and
+//! [`VaultSecretsBackend`](crate::VaultSecretsBackend) in HashiCorp Vault, shared by a fleet of
+//! servers. Servers choose one under `[secrets]` in their configuration.
+//!
+//! The backend also holds the master state key, from which a key sealing each component's
+//! persisted key-value state is derived.
 
 use std::collections::HashMap;
+use std::fmt;
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::SystemTime;
 
 use anyhow::{anyhow, Context, Result};
-use tokio::sync::RwLock;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use ring::aead::{LessSafeKey, UnboundKey, AES_256_GCM};
+use ring::hkdf::{Prk, Salt, HKDF_SHA256};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OnceCell, RwLock};
 use tracing::{debug, info, warn};
 
+use crate::{VaultConfig, VaultSecretsBackend};
+
 /// Cache entry for component secrets
 #[derive(Debug, Clone)]
 pub struct SecretCache {
@@ -28,80 +49,297 @@ pub struct SecretCache {
     pub last_mtime: SystemTime,
 }
 
+/// File in the secrets directory holding the master state key
+pub const STATE_KEY_FILE: &str = "state.key";
+
+/// Service under which [`KeyringSecretsBackend`] stores its entries by default
+pub const KEYRING_SERVICE: &str = "wassette";
+
+/// Keyring entry holding the master state key
+const KEYRING_STATE_KEY: &str = "state-key";
+
+/// Salt of the per-component state key derivation
+const STATE_KEY_SALT: &[u8] = b"wassette component state";
+
+/// Master key from which component state keys are derived
+struct StateKey([u8; 32]);
+
+impl fmt::Debug for StateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StateKey(<redacted>)")
+    }
+}
+
+/// Where the secrets of components and the master state key are kept.
+pub trait SecretsBackend: Send + Sync + fmt::Debug {
+    /// The secrets of `component_id`, empty if it has none.
+    fn load<'a>(&'a self, component_id: &'a str) -> BoxFuture<'a, Result<HashMap<String, String>>>;
+
+    /// Replace the secrets of `component_id`, removing them if `secrets` is empty.
+    fn store<'a>(
+        &'a self,
+        component_id: &'a str,
+        secrets: HashMap<String, String>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// The master state key, created the first time it is needed.
+    fn state_key(&self) -> BoxFuture<'_, Result<[u8; 32]>>;
+
+    /// Whether secrets are read whenever a component is instantiated, rather than once when
+    /// its policy is attached. Backends holding secrets that may change remotely do so.
+    fn resolved_at_instantiation(&self) -> bool {
+        false
+    }
+
+    /// Where the backend keeps secrets when not in files of the secrets directory, such as
+    /// `the OS keyring`. `wassette gc` can't list what such stores hold and reports them as
+    /// not scanned.
+    fn external_store(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+/// Which [`SecretsBackend`] a server keeps secrets in, chosen with `backend` under `[secrets]`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "kebab-case")]
+pub enum SecretsBackendConfig {
+    /// YAML files in the secrets directory
+    #[default]
+    File,
+    /// The OS keyring
+    Keyring,
+    /// HashiCorp Vault
+    Vault(Box<VaultConfig>),
+}
+
+impl SecretsBackendConfig {
+    /// Check the settings of the backend.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::Vault(vault) => vault.validate(),
+            Self::File | Self::Keyring => Ok(()),
+        }
+    }
+
+    /// Open the backend, with the files of [`SecretsBackendConfig::File`] in `secrets_dir` and
+    /// Vault reached with `http_client`.
+    pub fn open(
+        &self,
+        secrets_dir: &Path,
+        http_client: &reqwest::Client,
+    ) -> Result<Arc<dyn SecretsBackend>> {
+        Ok(match self {
+            Self::File => Arc::new(FileSecretsBackend::new(secrets_dir.to_path_buf())),
+            Self::Keyring => Arc::new(KeyringSecretsBackend::new(KEYRING_SERVICE)),
+            Self::Vault(vault) => Arc::new(VaultSecretsBackend::new(
+                (**vault).clone(),
+                http_client.clone(),
+            )?),
+        })
+    }
+}
+
 /// Secrets manager for components
 #[derive(Debug)]
 pub struct SecretsManager {
-    /// Directory where secrets are stored
+    /// Directory of the secrets, the state key and runtime configuration
     secrets_dir: PathBuf,
-    /// Cache of component secrets
-    cache: RwLock<HashMap<String, SecretCache>>,
+    /// Where secrets are kept
+    backend: Arc<dyn SecretsBackend>,
+    /// Master state key, loaded or created on first use
+    state_key: OnceCell<StateKey>,
 }
 
 impl SecretsManager {
-    /// Create a new secrets manager
+    /// Create a new secrets manager keeping secrets in files in `secrets_dir`
     pub fn new(secrets_dir: PathBuf) -> Self {
+        let backend = Arc::new(FileSecretsBackend::new(secrets_dir.clone()));
+        Self::with_backend(secrets_dir, backend)
+    }
+
+    /// Create a new secrets manager keeping secrets in `backend`
+    pub fn with_backend(secrets_dir: PathBuf, backend: Arc<dyn SecretsBackend>) -> Self {
         Self {
             secrets_dir,
-            cache: RwLock::new(HashMap::new()),
+            backend,
+            state_key: OnceCell::new(),
         }
     }
 
+    /// Key sealing the persisted state of `component_id`, derived from the master state key
+    pub(crate) async fn state_key(&self, component_id: &str) -> Result<LessSafeKey> {
+        let prk = self.state_prk().await?;
+        let info = [component_id.as_bytes()];
+        let okm = prk
+            .expand(&info, &AES_256_GCM)
+            .map_err(|_| anyhow!("Failed to derive the state key of {component_id}"))?;
+        Ok(LessSafeKey::new(UnboundKey::from(okm)))
+    }
+
+    /// Key naming the entries of the persisted state of `component_id`, so that the names of
+    /// its buckets and keys aren't stored, derived from the master state key
+    pub(crate) async fn state_index_key(&self, component_id: &str) -> Result<hmac::Key> {
+        let prk = self.state_prk().await?;
+        // Component ids hold no NUL, so this can't collide with the info of a sealing key.
+        let info = [b"index\0".as_slice(), component_id.as_bytes()];
+        let okm = prk
+            .expand(&info, hmac::HMAC_SHA256)
+            .map_err(|_| anyhow!("Failed to derive the state index key of {component_id}"))?;
+        Ok(hmac::Key::from(okm))
+    }
+
+    async fn state_prk(&self) -> Result<Prk> {
+        let master = self
+            .state_key
+            .get_or_try_init(|| async { self.backend.state_key().await.map(StateKey) })
+            .await?;
+        Ok(Salt::new(HKDF_SHA256, STATE_KEY_SALT).extract(&master.0))
+    }
+
     /// Get the secrets directory path
     pub fn secrets_dir(&self) -> &Path {
         &self.secrets_dir
     }
 
-    /// Get the path to a component's secrets file
-    pub fn get_component_secrets_path(&self, component_id: &str) -> PathBuf {
-        let sanitized_id = sanitize_component_id(component_id);
-        self.secrets_dir.join(format!("{sanitized_id}.yaml"))
+    /// Get the backend secrets are kept in
+    pub fn backend(&self) -> &dyn SecretsBackend {
+        &*self.backend
     }
 
     /// Ensure the secrets directory exists with proper permissions
     pub async fn ensure_secrets_dir(&self) -> Result<()> {
-        if !self.secrets_dir.exists() {
-            info!("Creating secrets directory: {}", self.secrets_dir.display());
-            tokio::fs::create_dir_all(&self.secrets_dir)
-                .await
-                .with_context(|| {
-                    format!(
-                        "Failed to create secrets directory: {}",
-                        self.secrets_dir.display()
-                    )
-                })?;
+        ensure_private_dir(&self.secrets_dir).await
+    }
+
+    /// Load secrets for a component
+    pub async fn load_component_secrets(
+        &self,
+        component_id: &str,
+    ) -> Result<HashMap<String, String>> {
+        self.backend.load(component_id).await
+    }
+
+    /// Secrets of a component to bake into the template of its policy, unless the backend
+    /// resolves them at instantiation
+    pub(crate) async fn template_secrets(
+        &self,
+        component_id: &str,
+    ) -> Option<HashMap<String, String>> {
+        if self.backend.resolved_at_instantiation() {
+            return None;
         }
+        self.backend.load(component_id).await.ok()
+    }
 
-        // Set directory permissions to 0700 (user only)
-        #[cfg(unix)]
-        {
-            let metadata = tokio::fs::metadata(&self.secrets_dir)
-                .await
-                .with_context(|| {
-                    format!(
-                        "Failed to get metadata for secrets directory: {}",
-                        self.secrets_dir.display()
-                    )
-                })?;
-            let mut perms = metadata.permissions();
-            perms.set_mode(0o700);
-            tokio::fs::set_permissions(&self.secrets_dir, perms)
-                .await
-                .with_context(|| {
-                    format!(
-                        "Failed to set permissions for secrets directory: {}",
-                        self.secrets_dir.display()
-                    )
-                })?;
+    /// Secrets of a component to add when instantiating it, if the backend resolves them then
+    pub(crate) async fn instantiation_secrets(
+        &self,
+        component_id: &str,
+    ) -> Result<Option<HashMap<String, String>>> {
+        if !self.backend.resolved_at_instantiation() {
+            return Ok(None);
         }
+        self.backend
+            .load(component_id)
+            .await
+            .map(Some)
+            .with_context(|| format!("Failed to resolve the secrets of {component_id}"))
+    }
 
+    /// List secrets for a component (keys only by default)
+    pub async fn list_component_secrets(
+        &self,
+        component_id: &str,
+        show_values: bool,
+    ) -> Result<HashMap<String, Option<String>>> {
+        let secrets = self.load_component_secrets(component_id).await?;
+
+        let result = if show_values {
+            secrets.into_iter().map(|(k, v)| (k, Some(v))).collect()
+        } else {
+            secrets.into_keys().map(|k| (k, None)).collect()
+        };
+
+        Ok(result)
+    }
+
+    /// Set secrets for a component
+    pub async fn set_component_secrets(
+        &self,
+        component_id: &str,
+        secrets: &[(String, String)],
+    ) -> Result<()> {
+        // Merge new secrets into the existing ones
+        let mut existing_secrets = self.backend.load(component_id).await?;
+        for (key, value) in secrets {
+            existing_secrets.insert(key.clone(), value.clone());
+        }
+        self.backend.store(component_id, existing_secrets).await?;
+
+        info!("Updated secrets for component: {}", component_id);
         Ok(())
     }
 
-    /// Load secrets for a component, using cache if file hasn't changed
-    pub async fn load_component_secrets(
+    /// Delete secrets for a component
+    pub async fn delete_component_secrets(
         &self,
         component_id: &str,
-    ) -> Result<HashMap<String, String>> {
+        keys: &[String],
+    ) -> Result<()> {
+        let mut secrets = self.backend.load(component_id).await?;
+        if secrets.is_empty() {
+            return Err(anyhow!("No secrets found for component: {}", component_id));
+        }
+
+        // Remove specified keys
+        for key in keys {
+            if secrets.remove(key).is_none() {
+                warn!(
+                    "Secret key '{}' not found for component: {}",
+                    key, component_id
+                );
+            }
+        }
+
+        if secrets.is_empty() {
+            info!("Removed all secrets of component: {}", component_id);
+        } else {
+            info!(
+                "Deleted {} secret(s) for component: {}",
+                keys.len(),
+                component_id
+            );
+        }
+        self.backend.store(component_id, secrets).await
+    }
+}
+
+/// Secrets kept in a YAML file per component, readable by the user only
+#[derive(Debug)]
+pub struct FileSecretsBackend {
+    /// Directory where secrets are stored
+    secrets_dir: PathBuf,
+    /// Cache of component secrets
+    cache: RwLock<HashMap<String, SecretCache>>,
+}
+
+impl FileSecretsBackend {
+    /// Create a backend keeping its files in `secrets_dir`
+    pub fn new(secrets_dir: PathBuf) -> Self {
+        Self {
+            secrets_dir,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Get the path to a component's secrets file
+    pub fn get_component_secrets_path(&self, component_id: &str) -> PathBuf {
+        let sanitized_id = sanitize_component_id(component_id);
+        self.secrets_dir.join(format!("{sanitized_id}.yaml"))
+    }
+
+    /// Load secrets for a component, using cache if file hasn't changed
+    async fn load_component_secrets(&self, component_id: &str) -> Result<HashMap<String, String>> {
         let secrets_path = self.get_component_secrets_path(component_id);
 
         // Check if file exists
@@ -158,123 +396,29 @@ impl SecretsManager {
         Ok(secrets)
     }
 
-    /// List secrets for a component (keys only by default)
-    pub async fn list_component_secrets(
-        &self,
-        component_id: &str,
-        show_values: bool,
-    ) -> Result<HashMap<String, Option<String>>> {
-        let secrets = self.load_component_secrets(component_id).await?;
-
-        let result = if show_values {
-            secrets.into_iter().map(|(k, v)| (k, Some(v))).collect()
-        } else {
-            secrets.into_keys().map(|k| (k, None)).collect()
-        };
-
-        Ok(result)
-    }
-
-    /// Set secrets for a component
-    pub async fn set_component_secrets(
+    /// Write the secrets of a component, removing its file if none remain
+    async fn store_component_secrets(
         &self,
         component_id: &str,
-        secrets: &[(String, String)],
+        secrets: HashMap<String, String>,
     ) -> Result<()> {
-        self.ensure_secrets_dir().await?;
-
         let secrets_path = self.get_component_secrets_path(component_id);
-
-        // Load existing secrets
-        let mut existing_secrets = if secrets_path.exists() {
-            let content = tokio::fs::read_to_string(&secrets_path)
-                .await
-                .with_context(|| {
-                    format!(
-                        "Failed to read existing secrets file: {}",
-                        secrets_path.display()
-                    )
-                })?;
-            serde_yaml::from_str::<HashMap<String, String>>(&content).with_context(|| {
-                format!(
-                    "Failed to parse existing secrets file: {}",
-                    secrets_path.display()
-                )
-            })?
-        } else {
-            HashMap::new()
-        };
-
-        // Merge new secrets
-        for (key, value) in secrets {
-            existing_secrets.insert(key.clone(), value.clone());
-        }
-
-        // Write atomically
-        self.write_secrets_file(&secrets_path, &existing_secrets)
-            .await?;
-
-        // Invalidate cache
-        {
-            let mut cache = self.cache.write().await;
-            cache.remove(component_id);
-        }
-
-        info!("Updated secrets for component: {}", component_id);
-        Ok(())
-    }
-
-    /// Delete secrets for a component
-    pub async fn delete_component_secrets(
-        &self,
-        component_id: &str,
-        keys: &[String],
-    ) -> Result<()> {
-        let secrets_path = self.get_component_secrets_path(component_id);
-
-        if !secrets_path.exists() {
-            return Err(anyhow!(
-                "No secrets file found for component: {}",
-                component_id
-            ));
-        }
-
-        // Load existing secrets
-        let content = tokio::fs::read_to_string(&secrets_path)
-            .await
-            .with_context(|| format!("Failed to read secrets file: {}", secrets_path.display()))?;
-        let mut secrets: HashMap<String, String> = serde_yaml::from_str(&content)
-            .with_context(|| format!("Failed to parse secrets file: {}", secrets_path.display()))?;
-
-        // Remove specified keys
-        for key in keys {
-            if secrets.remove(key).is_none() {
-                warn!(
-                    "Secret key '{}' not found for component: {}",
-                    key, component_id
-                );
-            }
-        }
-
         if secrets.is_empty() {
-            // Remove the file if no secrets remain
-            tokio::fs::remove_file(&secrets_path)
-                .await
-                .with_context(|| {
-                    format!(
-                        "Failed to remove empty secrets file: {}",
-                        secrets_path.display()
-                    )
-                })?;
-            info!("Removed empty secrets file for component: {}", component_id);
+            match tokio::fs::remove_file(&secrets_path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!(
+                            "Failed to remove empty secrets file: {}",
+                            secrets_path.display()
+                        )
+                    })
+                }
+            }
         } else {
-            // Write updated secrets
+            ensure_private_dir(&self.secrets_dir).await?;
             self.write_secrets_file(&secrets_path, &secrets).await?;
-            info!(
-                "Deleted {} secret(s) for component: {}",
-                keys.len(),
-                component_id
-            );
         }
 
         // Invalidate cache
@@ -339,11 +483,251 @@ impl SecretsManager {
 
         Ok(())
     }
+
+    /// Read the master state key, creating it the first time state is stored
+    async fn load_or_create_state_key(&self) -> Result<[u8; 32]> {
+        let path = self.secrets_dir.join(STATE_KEY_FILE);
+        match tokio::fs::read(&path).await {
+            Ok(contents) => return parse_state_key(&contents, || path.display().to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read state key: {}", path.display()))
+            }
+        }
+
+        ensure_private_dir(&self.secrets_dir).await?;
+        let key = generate_state_key()?;
+
+        // Link the finished key into place so a concurrent first run can't see a partial
+        // key, and whichever run links first wins.
+        let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        tokio::fs::write(&temp_path, key)
+            .await
+            .with_context(|| format!("Failed to write state key: {}", temp_path.display()))?;
+        #[cfg(unix)]
+        tokio::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(0o600))
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to set permissions for state key: {}",
+                    temp_path.display()
+                )
+            })?;
+        let linked = tokio::fs::hard_link(&temp_path, &path).await;
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        match linked {
+            Ok(()) => {
+                info!("Created state key: {}", path.display());
+                Ok(key)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                let contents = tokio::fs::read(&path)
+                    .await
+                    .with_context(|| format!("Failed to read state key: {}", path.display()))?;
+                parse_state_key(&contents, || path.display().to_string())
+            }
+            Err(e) => {
+                Err(e).with_context(|| format!("Failed to create state key: {}", path.display()))
+            }
+        }
+    }
+}
+
+impl SecretsBackend for FileSecretsBackend {
+    fn load<'a>(&'a self, component_id: &'a str) -> BoxFuture<'a, Result<HashMap<String, String>>> {
+        self.load_component_secrets(component_id).boxed()
+    }
+
+    fn store<'a>(
+        &'a self,
+        component_id: &'a str,
+        secrets: HashMap<String, String>,
+    ) -> BoxFuture<'a, Result<()>> {
+        self.store_component_secrets(component_id, secrets).boxed()
+    }
+
+    fn state_key(&self) -> BoxFuture<'_, Result<[u8; 32]>> {
+        self.load_or_create_state_key().boxed()
+    }
+}
+
+/// Secrets kept in the OS keyring: the macOS Keychain, the Windows Credential Manager, or the
+/// Secret Service of libsecret on Linux.
+///
+/// The secrets of a component are one JSON entry of the keyring, whose user is
+/// `component:<id>`, and the master state key is the `state-key` entry. The keyring is read
+/// at every load so that changes made by another process, such as the CLI, are seen.
+#[derive(Debug, Clone)]
+pub struct KeyringSecretsBackend {
+    service: String,
+}
+
+impl KeyringSecretsBackend {
+    /// Create a backend keeping its entries under `service`
+    pub fn new(service: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+        }
+    }
+
+    fn entry(&self, user: &str) -> Result<keyring::Entry> {
+        keyring::Entry::new(&self.service, user)
+            .with_context(|| format!("Failed to open keyring entry {}/{user}", self.service))
+    }
+
+    async fn load_component_secrets(&self, component_id: &str) -> Result<HashMap<String, String>> {
+        let entry = self.entry(&keyring_user(component_id))?;
+        let stored = blocking(move || match entry.get_password() {
+            Ok(stored) => Ok(Some(stored)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        })
+        .await
+        .with_context(|| {
+            format!("Failed to read the secrets of {component_id} from the keyring")
+        })?;
+        match stored {
+            Some(stored) => serde_json::from_str(&stored)
+                .with_context(|| format!("Failed to parse the keyring secrets of {component_id}")),
+            None => {
+                debug!("No keyring secrets found for component: {}", component_id);
+                Ok(HashMap::new())
+            }
+        }
+    }
+
+    async fn store_component_secrets(
+        &self,
+        component_id: &str,
+        secrets: HashMap<String, String>,
+    ) -> Result<()> {
+        let entry = self.entry(&keyring_user(component_id))?;
+        let stored = if secrets.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&secrets).context("Failed to serialize secrets")?)
+        };
+        blocking(move || match stored {
+            Some(stored) => Ok(entry.set_password(&stored)?),
+            None => match entry.delete_credential() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(e.into()),
+            },
+        })
+        .await
+        .with_context(|| format!("Failed to write the secrets of {component_id} to the keyring"))
+    }
+
+    async fn load_or_create_state_key(&self) -> Result<[u8; 32]> {
+        let entry = self.entry(KEYRING_STATE_KEY)?;
+        blocking(move || match entry.get_secret() {
+            Ok(contents) => parse_state_key(&contents, || "keyring".to_string()),
+            Err(keyring::Error::NoEntry) => {
+                // Keyrings can't create an entry only if it is missing, unlike the hard link
+                // of the file backend, so servers first started at the same instant could
+                // race here.
+                let key = generate_state_key()?;
+                entry.set_secret(&key)?;
+                info!("Created state key in the keyring");
+                Ok(key)
+            }
+            Err(e) => Err(e.into()),
+        })
+        .await
+        .context("Failed to read the state key from the keyring")
+    }
+}
+
+impl SecretsBackend for KeyringSecretsBackend {
+    fn load<'a>(&'a self, component_id: &'a str) -> BoxFuture<'a, Result<HashMap<String, String>>> {
+        self.load_component_secrets(component_id).boxed()
+    }
+
+    fn store<'a>(
+        &'a self,
+        component_id: &'a str,
+        secrets: HashMap<String, String>,
+    ) -> BoxFuture<'a, Result<()>> {
+        self.store_component_secrets(component_id, secrets).boxed()
+    }
+
+    fn state_key(&self) -> BoxFuture<'_, Result<[u8; 32]>> {
+        self.load_or_create_state_key().boxed()
+    }
+
+    fn external_store(&self) -> Option<&'static str> {
+        Some("the OS keyring")
+    }
+}
+
+/// User of the keyring entry holding the secrets of `component_id`
+fn keyring_user(component_id: &str) -> String {
+    format!("component:{component_id}")
+}
+
+/// Run a blocking keyring call off the async runtime
+async fn blocking<T: Send + 'static>(
+    call: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    tokio::task::spawn_blocking(call)
+        .await
+        .context("Keyring call panicked")?
+}
+
+/// Create `dir` if needed and make it accessible to the user only
+async fn ensure_private_dir(dir: &Path) -> Result<()> {
+    if !dir.exists() {
+        info!("Creating secrets directory: {}", dir.display());
+        tokio::fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("Failed to create secrets directory: {}", dir.display()))?;
+    }
+
+    // Set directory permissions to 0700 (user only)
+    #[cfg(unix)]
+    {
+        let metadata = tokio::fs::metadata(dir).await.with_context(|| {
+            format!(
+                "Failed to get metadata for secrets directory: {}",
+                dir.display()
+            )
+        })?;
+        let mut perms = metadata.permissions();
+        perms.set_mode(0o700);
+        tokio::fs::set_permissions(dir, perms)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to set permissions for secrets directory: {}",
+                    dir.display()
+                )
+            })?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn generate_state_key() -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| anyhow!("Failed to generate state key"))?;
+    Ok(key)
+}
+
+pub(crate) fn parse_state_key(
+    contents: &[u8],
+    location: impl FnOnce() -> String,
+) -> Result<[u8; 32]> {
+    contents
+        .try_into()
+        .map_err(|_| anyhow!("State key is not 32 bytes: {}", location()))
 }
 
 /// Sanitize component ID for use as filename
 /// Maps [^A-Za-z0-9._-] → _, collapses repeats, trims to 128 bytes
-fn sanitize_component_id(component_id: &str) -> String {
+pub(crate) fn sanitize_component_id(component_id: &str) -> String {
     let mut result = String::new();
     let mut last_was_underscore = false;
 
@@ -465,7 +849,7 @@ mod tests {
     async fn test_cache_invalidation() -> Result<()> {
         let temp_dir = TempDir::new()?;
         let secrets_dir = temp_dir.path().join("secrets");
-        let manager = SecretsManager::new(secrets_dir);
+        let manager = SecretsManager::new(secrets_dir.clone());
 
         // Set initial secrets
         let secrets = vec![("KEY1".to_string(), "value1".to_string())];
@@ -476,7 +860,7 @@ mod tests {
         assert_eq!(loaded1.get("KEY1"), Some(&"value1".to_string()));
 
         // Modify secrets directly
-        let secrets_path = manager.get_component_secrets_path("test");
+        let secrets_path = FileSecretsBackend::new(secrets_dir).get_component_secrets_path("test");
 
         // Sleep to ensure mtime changes
         tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
@@ -492,6 +876,74 @@ mod tests {
         Ok(())
     }
 
+    /// A backend in memory, standing in for the OS keyring.
+    #[derive(Debug, Default)]
+    struct MemoryBackend {
+        secrets: std::sync::Mutex<HashMap<String, HashMap<String, String>>>,
+    }
+
+    impl SecretsBackend for MemoryBackend {
+        fn load<'a>(
+            &'a self,
+            component_id: &'a str,
+        ) -> BoxFuture<'a, Result<HashMap<String, String>>> {
+            let secrets = self.secrets.lock().unwrap().get(component_id).cloned();
+            async move { Ok(secrets.unwrap_or_default()) }.boxed()
+        }
+
+        fn store<'a>(
+            &'a self,
+            component_id: &'a str,
+            secrets: HashMap<String, String>,
+        ) -> BoxFuture<'a, Result<()>> {
+            let mut stored = self.secrets.lock().unwrap();
+            if secrets.is_empty() {
+                stored.remove(component_id);
+            } else {
+                stored.insert(component_id.to_string(), secrets);
+            }
+            async { Ok(()) }.boxed()
+        }
+
+        fn state_key(&self) -> BoxFuture<'_, Result<[u8; 32]>> {
+            async { Ok([7; 32]) }.boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_secrets_manager_with_backend() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let secrets_dir = temp_dir.path().join("secrets");
+        let backend = Arc::new(MemoryBackend::default());
+        let manager = SecretsManager::with_backend(secrets_dir.clone(), backend.clone());
+
+        manager
+            .set_component_secrets("test", &[("KEY1".to_string(), "value1".to_string())])
+            .await?;
+        manager
+            .set_component_secrets("test", &[("KEY2".to_string(), "value2".to_string())])
+            .await?;
+        assert_eq!(manager.load_component_secrets("test").await?.len(), 2);
+        manager.state_key("test").await?;
+        // Nothing is written to the secrets directory.
+        assert!(!secrets_dir.exists());
+
+        manager
+            .delete_component_secrets("test", &["KEY1".to_string(), "KEY2".to_string()])
+            .await?;
+        assert!(backend.secrets.lock().unwrap().is_empty());
+        assert!(manager
+            .delete_component_secrets("test", &["KEY1".to_string()])
+            .await
+            .is_err());
+
+        assert_eq!(
+            serde_json::from_str::<SecretsBackendConfig>(r#"{"backend": "keyring"}"#)?,
+            SecretsBackendConfig::Keyring
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_secrets_with_environment_precedence() -> Result<()> {
         let temp_dir = TempDir::new()?;
@@ -547,4 +999,39 @@ permissions:
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_state_key_is_persisted_and_per_component() -> Result<()> {
+        use ring::aead::{Aad, Nonce};
+
+        let temp_dir = TempDir::new()?;
+        let secrets_dir = temp_dir.path().join("secrets");
+        let seal = |key: LessSafeKey| {
+            let mut data = b"state".to_vec();
+            key.seal_in_place_append_tag(
+                Nonce::assume_unique_for_key([0; 12]),
+                Aad::empty(),
+                &mut data,
+            )
+            .unwrap();
+            data
+        };
+
+        let manager = SecretsManager::new(secrets_dir.clone());
+        let first = seal(manager.state_key("notes").await?);
+        assert_ne!(first, seal(manager.state_key("other").await?));
+
+        let key_path = secrets_dir.join(STATE_KEY_FILE);
+        assert_eq!(tokio::fs::read(&key_path).await?.len(), 32);
+        #[cfg(unix)]
+        assert_eq!(
+            tokio::fs::metadata(&key_path).await?.permissions().mode() & 0o777,
+            0o600
+        );
+
+        // A new manager over the same directory derives the same key.
+        let reopened = SecretsManager::new(secrets_dir);
+        assert_eq!(first, seal(reopened.state_key("notes").await?));
+        Ok(())
+    }
 }
@@ -0,0 +1,242 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Session context exposed to components through the `wassette:context/session` host interface.
+//!
+//! Components import
+//!
+//! ```wit
+//! package wassette:context;
+//!
+//! interface session {
+//!     record context {
+//!         client-name: option<string>,
+//!         client-version: option<string>,
+//!         locale: option<string>,
+//!         profile: list<tuple<string, string>>,
+//!     }
+//!
+//!     get: func() -> result<context, string>;
+//! }
+//! ```
+//!
+//! to learn which MCP client is calling and the preferences the user configured for it, so a
+//! tool can format its output for the client without the agent restating them. The context is
+//! denied unless the component's policy has `context: read`. Everything in it is sanitized:
+//! control characters are dropped and values are capped in length, since the client name and
+//! version come from the client unchecked.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use policy::{ContextAccess, PolicyDocument};
+use serde::{Deserialize, Serialize};
+use wasmtime::component::{ComponentType, Linker, Lower};
+
+use crate::{WasiState, WassetteWasiState};
+
+/// Name of the host interface that provides the session context.
+pub const CONTEXT_INTERFACE: &str = "wassette:context/session";
+
+/// Longest value, in characters, passed to components; longer values are cut.
+pub const MAX_VALUE_CHARS: usize = 256;
+
+/// Most profile values a profile may hold.
+pub const MAX_PROFILE_VALUES: usize = 64;
+
+/// Preferences of the user shared with components granted `context: read`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionProfile {
+    /// BCP 47 language tag, e.g. `en-US`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Free-form values such as `units = "metric"`, keyed by name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub values: BTreeMap<String, String>,
+}
+
+impl SessionProfile {
+    /// Check that the locale is a language tag and the value names are plain identifiers.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(locale) = &self.locale {
+            if locale.is_empty()
+                || locale.len() > 35
+                || !locale
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                bail!("Locale `{locale}` is not a language tag such as `en-US`");
+            }
+        }
+        if self.values.len() > MAX_PROFILE_VALUES {
+            bail!(
+                "The profile has {} values, at most {MAX_PROFILE_VALUES} are allowed",
+                self.values.len()
+            );
+        }
+        for name in self.values.keys() {
+            if name.is_empty()
+                || name.len() > 64
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            {
+                bail!(
+                    "Profile value name `{name}` may only contain letters, digits, `-`, `_` and `.`"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The session context as a component sees it.
+#[derive(ComponentType, Lower, Debug, Clone, Default, PartialEq, Eq)]
+#[component(record)]
+pub struct SessionContext {
+    /// Name the MCP client reported, e.g. `vscode`
+    #[component(name = "client-name")]
+    pub client_name: Option<String>,
+    /// Version the MCP client reported
+    #[component(name = "client-version")]
+    pub client_version: Option<String>,
+    /// The user's locale
+    pub locale: Option<String>,
+    /// The user's profile values, sorted by name
+    pub profile: Vec<(String, String)>,
+}
+
+impl SessionContext {
+    /// Context of a call from the client `client` (name and version) by a user with `profile`.
+    pub fn new(client: Option<(&str, &str)>, profile: &SessionProfile) -> Self {
+        Self {
+            client_name: client.and_then(|(name, _)| sanitize(name)),
+            client_version: client.and_then(|(_, version)| sanitize(version)),
+            locale: profile.locale.as_deref().and_then(sanitize),
+            profile: profile
+                .values
+                .iter()
+                .take(MAX_PROFILE_VALUES)
+                .filter_map(|(name, value)| Some((sanitize(name)?, sanitize(value)?)))
+                .collect(),
+        }
+    }
+}
+
+/// Drop control characters and cap the length; `None` if nothing is left.
+fn sanitize(value: &str) -> Option<String> {
+    let value: String = value
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_VALUE_CHARS)
+        .collect();
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Whether `policy` grants the session context.
+pub fn allowed_by_policy(policy: &PolicyDocument) -> bool {
+    policy.permissions.context == Some(ContextAccess::Read)
+}
+
+/// Per-store session context state.
+#[derive(Debug, Clone, Default)]
+pub struct ContextState {
+    /// Whether the policy grants `context: read`
+    pub allowed: bool,
+    /// Context of the current invocation
+    pub session: SessionContext,
+}
+
+impl ContextState {
+    fn get(&self) -> std::result::Result<SessionContext, String> {
+        if !self.allowed {
+            return Err("session context is not permitted by the component policy".to_string());
+        }
+        Ok(self.session.clone())
+    }
+}
+
+/// Add the `wassette:context/session` interface to `linker`.
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    linker
+        .instance(CONTEXT_INTERFACE)?
+        .func_wrap("get", |store, (): ()| {
+            Ok((store.data().inner.context.get(),))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use policy::PolicyParser;
+
+    use super::*;
+
+    fn profile() -> SessionProfile {
+        SessionProfile {
+            locale: Some("de-DE".to_string()),
+            values: BTreeMap::from([
+                ("units".to_string(), "metric".to_string()),
+                ("date-format".to_string(), "\u{1b}[31mISO\n".to_string()),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_allowed_by_policy() {
+        let policy = PolicyParser::parse_str("version: \"1.0\"\npermissions: {}\n").unwrap();
+        assert!(!allowed_by_policy(&policy));
+
+        let policy =
+            PolicyParser::parse_str("version: \"1.0\"\npermissions:\n  context: read\n").unwrap();
+        assert!(allowed_by_policy(&policy));
+    }
+
+    #[test]
+    fn test_context_is_sanitized() {
+        let long = "x".repeat(1000);
+        let context = SessionContext::new(Some(("my\u{0}client\r\n", long.as_str())), &profile());
+        assert_eq!(context.client_name.as_deref(), Some("myclient"));
+        assert_eq!(
+            context.client_version.map(|v| v.len()),
+            Some(MAX_VALUE_CHARS)
+        );
+        assert_eq!(context.locale.as_deref(), Some("de-DE"));
+        assert_eq!(
+            context.profile,
+            [
+                ("date-format".to_string(), "[31mISO".to_string()),
+                ("units".to_string(), "metric".to_string()),
+            ]
+        );
+
+        let context = SessionContext::new(Some((" ", "")), &SessionProfile::default());
+        assert_eq!(context, SessionContext::default());
+    }
+
+    #[test]
+    fn test_profile_validation() {
+        assert!(profile().validate().is_ok());
+
+        let mut bad = profile();
+        bad.locale = Some("en US".to_string());
+        assert!(bad.validate().is_err());
+
+        let mut bad = profile();
+        bad.values.insert("font size".to_string(), "12".to_string());
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_get_requires_permission() {
+        let mut state = ContextState {
+            session: SessionContext::new(Some(("vscode", "1.100")), &profile()),
+            ..Default::default()
+        };
+        assert!(state.get().is_err());
+
+        state.allowed = true;
+        assert_eq!(state.get().unwrap().client_name.as_deref(), Some("vscode"));
+    }
+}
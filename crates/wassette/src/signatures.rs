@@ -0,0 +1,973 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Cosign signature verification of OCI components.
+//!
+//! `cosign sign` stores the signatures of an image in a `sha256-<digest>.sig` image next to it
+//! in the same repository. Each signature layer carries a "simple signing" payload naming the
+//! manifest digest it vouches for, and annotations with the signature itself:
+//!
+//! - **key-based** signatures (`cosign sign --key`) verify against a trusted public key;
+//! - **keyless** signatures come with a short-lived Fulcio certificate issued to an OIDC
+//!   identity, and a Rekor transparency log entry recording when the signature was made. The
+//!   certificate must chain to a trusted Fulcio root and have been valid at that time, the log
+//!   entry must be signed by the trusted Rekor key, and the identity (issuer and subject) must
+//!   be one trusted for the component's registry.
+//!
+//! Once a signature verifies, the component is pulled by the digest it vouches for, so a tag
+//! moved after the check, or a mirror serving other content, can't swap the artifact.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use oci_client::errors::{OciDistributionError, OciErrorCode};
+use oci_client::Reference;
+use ring::signature::{self as ring_signature, UnparsedPublicKey, VerificationAlgorithm};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use x509_parser::asn1_rs::{Any, FromDer};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::oid_registry::{
+    OID_EC_P256, OID_KEY_TYPE_EC_PUBLIC_KEY, OID_NIST_EC_P384, OID_PKCS1_RSAENCRYPTION,
+    OID_SIG_ED25519,
+};
+use x509_parser::pem::Pem;
+use x509_parser::time::ASN1Time;
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+use crate::oci_pull::PullSource;
+
+const SIMPLE_SIGNING_MEDIA_TYPE: &str = "application/vnd.dev.cosign.simplesigning.v1+json";
+const SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+const CERTIFICATE_ANNOTATION: &str = "dev.sigstore.cosign/certificate";
+const CHAIN_ANNOTATION: &str = "dev.sigstore.cosign/chain";
+const BUNDLE_ANNOTATION: &str = "dev.sigstore.cosign/bundle";
+
+/// Fulcio certificate extension holding the OIDC issuer as raw bytes (deprecated form).
+const FULCIO_ISSUER_V1_OID: &str = "1.3.6.1.4.1.57264.1.1";
+/// Fulcio certificate extension holding the OIDC issuer as a DER UTF8String.
+const FULCIO_ISSUER_V2_OID: &str = "1.3.6.1.4.1.57264.1.8";
+
+/// Certificates between a signing certificate and its root.
+const MAX_CHAIN_DEPTH: usize = 4;
+
+/// Which OCI components must be signed and by whom.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SignatureConfig {
+    /// Refuse components without a signature from a signer trusted for their registry
+    pub require_signed: bool,
+    /// Trusted signers per registry (e.g. `ghcr.io`) or repository prefix (e.g.
+    /// `ghcr.io/microsoft`); the longest match applies
+    pub registries: HashMap<String, TrustedSigners>,
+    /// PEM files of the Fulcio certificates keyless signing certificates must chain to
+    pub fulcio_roots: Vec<PathBuf>,
+    /// PEM file of the public key of the Rekor transparency log
+    pub rekor_public_key: Option<PathBuf>,
+}
+
+impl SignatureConfig {
+    /// Check every entry and load the keys and certificates it names.
+    pub fn validate(&self) -> Result<()> {
+        SignatureVerifier::new(self).map(drop)
+    }
+}
+
+/// The signers trusted for the components of a registry.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TrustedSigners {
+    /// PEM public keys of key-based signatures (`cosign sign --key`)
+    pub keys: Vec<PathBuf>,
+    /// Identities allowed to sign keylessly
+    pub identities: Vec<SignerIdentity>,
+}
+
+/// An OIDC identity keyless signatures are made with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SignerIdentity {
+    /// OIDC issuer, e.g. `https://token.actions.githubusercontent.com`
+    pub issuer: String,
+    /// Email or URI the certificate was issued to; a trailing `*` matches any suffix, e.g.
+    /// `https://github.com/microsoft/wassette/.github/workflows/*`
+    pub subject: String,
+}
+
+impl SignerIdentity {
+    fn matches(&self, issuer: &str, subject: &str) -> bool {
+        issuer == self.issuer
+            && match self.subject.strip_suffix('*') {
+                Some(prefix) => subject.starts_with(prefix),
+                None => subject == self.subject,
+            }
+    }
+}
+
+/// A public key and the algorithm its signatures are made with.
+#[derive(Debug)]
+struct VerifyingKey {
+    algorithm: &'static dyn VerificationAlgorithm,
+    bytes: Vec<u8>,
+}
+
+impl VerifyingKey {
+    fn from_spki(spki: &SubjectPublicKeyInfo) -> Result<Self> {
+        let algorithm = &spki.algorithm;
+        let algorithm: &'static dyn VerificationAlgorithm = if algorithm.algorithm
+            == OID_KEY_TYPE_EC_PUBLIC_KEY
+        {
+            let curve = algorithm
+                .parameters
+                .as_ref()
+                .and_then(|parameters| parameters.as_oid().ok());
+            match curve {
+                Some(curve) if curve == OID_EC_P256 => &ring_signature::ECDSA_P256_SHA256_ASN1,
+                Some(curve) if curve == OID_NIST_EC_P384 => &ring_signature::ECDSA_P384_SHA384_ASN1,
+                _ => bail!("Unsupported elliptic curve"),
+            }
+        } else if algorithm.algorithm == OID_SIG_ED25519 {
+            &ring_signature::ED25519
+        } else if algorithm.algorithm == OID_PKCS1_RSAENCRYPTION {
+            &ring_signature::RSA_PKCS1_2048_8192_SHA256
+        } else {
+            bail!("Unsupported key type {}", algorithm.algorithm);
+        };
+        Ok(Self {
+            algorithm,
+            bytes: spki.subject_public_key.data.to_vec(),
+        })
+    }
+
+    fn from_pem_file(path: &Path) -> Result<Self> {
+        let der = pem_blocks(&read(path)?, "PUBLIC KEY")?
+            .into_iter()
+            .next()
+            .with_context(|| format!("{} holds no PEM public key", path.display()))?;
+        let (_, spki) = SubjectPublicKeyInfo::from_der(&der)
+            .with_context(|| format!("Malformed public key in {}", path.display()))?;
+        Self::from_spki(&spki).with_context(|| format!("Unusable public key in {}", path.display()))
+    }
+
+    fn verifies(&self, message: &[u8], signature: &[u8]) -> bool {
+        UnparsedPublicKey::new(self.algorithm, &self.bytes)
+            .verify(message, signature)
+            .is_ok()
+    }
+}
+
+/// Trusted signers of a registry, loaded.
+#[derive(Debug)]
+struct Signers {
+    keys: Vec<(PathBuf, VerifyingKey)>,
+    identities: Vec<SignerIdentity>,
+}
+
+/// A signature found next to an image.
+#[derive(Debug, Clone, Default)]
+struct ImageSignature {
+    payload: Vec<u8>,
+    /// Base64 signature of the payload
+    signature: String,
+    /// PEM signing certificate of a keyless signature
+    certificate: Option<String>,
+    /// PEM certificates between the signing certificate and its root
+    chain: Option<String>,
+    /// Transparency log entry of a keyless signature, as JSON
+    bundle: Option<String>,
+}
+
+/// Rekor's promise to include a log entry, as cosign attaches it to a keyless signature.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Bundle {
+    signed_entry_timestamp: String,
+    payload: LogEntry,
+}
+
+/// Fields are in the order of their canonical JSON form, which Rekor signs.
+#[derive(Debug, Serialize, Deserialize)]
+struct LogEntry {
+    body: String,
+    #[serde(rename = "integratedTime")]
+    integrated_time: i64,
+    #[serde(rename = "logID")]
+    log_id: String,
+    #[serde(rename = "logIndex")]
+    log_index: i64,
+}
+
+/// Checks OCI components against the configured signers.
+#[derive(Debug)]
+pub(crate) struct SignatureVerifier {
+    require_signed: bool,
+    registries: Vec<(String, Signers)>,
+    fulcio_roots: Vec<Vec<u8>>,
+    rekor_key: Option<VerifyingKey>,
+}
+
+impl SignatureVerifier {
+    /// Load the keys and certificates of `config`.
+    pub(crate) fn new(config: &SignatureConfig) -> Result<Self> {
+        let mut registries = Vec::new();
+        for (scope, signers) in &config.registries {
+            let scope = scope.trim_end_matches('/');
+            ensure!(
+                !scope.is_empty(),
+                "Registry of trusted signers can't be empty"
+            );
+            ensure!(
+                !signers.keys.is_empty() || !signers.identities.is_empty(),
+                "No keys or identities are trusted for '{scope}'"
+            );
+            let keys = signers
+                .keys
+                .iter()
+                .map(|path| Ok((path.clone(), VerifyingKey::from_pem_file(path)?)))
+                .collect::<Result<_>>()
+                .with_context(|| format!("Invalid signers for '{scope}'"))?;
+            registries.push((
+                scope.to_string(),
+                Signers {
+                    keys,
+                    identities: signers.identities.clone(),
+                },
+            ));
+        }
+
+        let mut fulcio_roots = Vec::new();
+        for path in &config.fulcio_roots {
+            let certificates = pem_blocks(&read(path)?, "CERTIFICATE")?;
+            for der in &certificates {
+                parse_certificate(der).with_context(|| format!("Invalid {}", path.display()))?;
+            }
+            fulcio_roots.extend(certificates);
+        }
+        let rekor_key = config
+            .rekor_public_key
+            .as_deref()
+            .map(VerifyingKey::from_pem_file)
+            .transpose()?;
+        if registries
+            .iter()
+            .any(|(_, signers)| !signers.identities.is_empty())
+        {
+            ensure!(
+                !fulcio_roots.is_empty() && rekor_key.is_some(),
+                "Keyless signer identities need fulcio-roots and rekor-public-key"
+            );
+        }
+
+        Ok(Self {
+            require_signed: config.require_signed,
+            registries,
+            fulcio_roots,
+            rekor_key,
+        })
+    }
+
+    /// Whether any component is checked at all.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.require_signed || !self.registries.is_empty()
+    }
+
+    /// The signers trusted for `reference`, from the longest matching registry entry.
+    fn signers(&self, reference: &Reference) -> Option<&Signers> {
+        let name = format!("{}/{}", reference.registry(), reference.repository());
+        self.registries
+            .iter()
+            .filter(|(scope, _)| {
+                name.strip_prefix(scope.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(scope, _)| scope.len())
+            .map(|(_, signers)| signers)
+    }
+
+    /// Check the signatures of the image `source` pulls in place of `upstream`. Returns the
+    /// verified manifest digest to pull by, or `None` for an unsigned image that may still be
+    /// loaded.
+    pub(crate) async fn verify(
+        &self,
+        source: &PullSource,
+        upstream: &Reference,
+        oci_client: &oci_client::Client,
+    ) -> Result<Option<String>> {
+        let Some(signers) = self.signers(upstream) else {
+            if self.require_signed {
+                bail!(
+                    "No signers are trusted for {}, and components must be signed",
+                    upstream.whole()
+                );
+            }
+            return Ok(None);
+        };
+        let digest = oci_client
+            .fetch_manifest_digest(&source.reference, &source.auth)
+            .await
+            .context("Failed to resolve the image digest")?;
+        let signatures = fetch_signatures(source, &digest, oci_client).await?;
+        if signatures.is_empty() {
+            if self.require_signed {
+                bail!("{} is not signed", upstream.whole());
+            }
+            warn!(
+                target: "wassette::audit",
+                reference = %upstream.whole(),
+                %digest,
+                "Loading unsigned component"
+            );
+            return Ok(None);
+        }
+
+        let mut errors = Vec::new();
+        for signature in &signatures {
+            match self.check(signers, signature, &digest) {
+                Ok(signer) => {
+                    info!(
+                        target: "wassette::audit",
+                        reference = %upstream.whole(),
+                        %digest,
+                        %signer,
+                        "Verified component signature"
+                    );
+                    return Ok(Some(digest));
+                }
+                Err(error) => errors.push(format!("{error:#}")),
+            }
+        }
+        bail!(
+            "No signature of {} ({digest}) is from a trusted signer: {}",
+            upstream.whole(),
+            errors.join("; ")
+        )
+    }
+
+    /// Verify one signature of the image with manifest `digest`, returning who made it.
+    fn check(&self, signers: &Signers, signature: &ImageSignature, digest: &str) -> Result<String> {
+        let payload: Value =
+            serde_json::from_slice(&signature.payload).context("Malformed signature payload")?;
+        let signed_digest = payload
+            .pointer("/critical/image/docker-manifest-digest")
+            .and_then(Value::as_str)
+            .context("Signature payload names no manifest digest")?;
+        ensure!(
+            signed_digest == digest,
+            "Signature is for {signed_digest} instead"
+        );
+        let signature_bytes = BASE64
+            .decode(signature.signature.trim())
+            .context("Malformed signature")?;
+
+        if let Some((path, _)) = signers
+            .keys
+            .iter()
+            .find(|(_, key)| key.verifies(&signature.payload, &signature_bytes))
+        {
+            return Ok(format!("key {}", path.display()));
+        }
+        if signers.identities.is_empty() {
+            bail!("Signature is not made with a trusted key");
+        }
+        let Some(certificate) = &signature.certificate else {
+            bail!("Signature is not made with a trusted key or certificate");
+        };
+        self.check_keyless(signers, signature, certificate, &signature_bytes)
+    }
+
+    fn check_keyless(
+        &self,
+        signers: &Signers,
+        signature: &ImageSignature,
+        certificate_pem: &str,
+        signature_bytes: &[u8],
+    ) -> Result<String> {
+        let certificate_der = pem_blocks(certificate_pem.as_bytes(), "CERTIFICATE")?
+            .into_iter()
+            .next()
+            .context("Signature certificate is not PEM")?;
+        let certificate = parse_certificate(&certificate_der)?;
+        let key = VerifyingKey::from_spki(certificate.public_key())?;
+        ensure!(
+            key.verifies(&signature.payload, signature_bytes),
+            "Signature does not verify against its certificate"
+        );
+
+        // Fulcio certificates live for minutes: what matters is that the signature was made,
+        // as recorded by the log, while the certificate was valid.
+        let bundle = signature
+            .bundle
+            .as_deref()
+            .context("Keyless signature has no transparency log entry")?;
+        let signed_at = self.check_log_entry(
+            bundle,
+            &signature.payload,
+            signature_bytes,
+            &certificate_der,
+        )?;
+        let chain = signature
+            .chain
+            .as_deref()
+            .map(|chain| pem_blocks(chain.as_bytes(), "CERTIFICATE"))
+            .transpose()?
+            .unwrap_or_default();
+        self.check_chain(&certificate, &chain, signed_at)?;
+        let code_signing = certificate
+            .extended_key_usage()
+            .ok()
+            .flatten()
+            .is_some_and(|usage| usage.value.code_signing);
+        ensure!(code_signing, "Certificate is not issued for code signing");
+
+        let issuer = fulcio_issuer(&certificate).context("Certificate names no OIDC issuer")?;
+        let subjects = certificate_subjects(&certificate);
+        for subject in &subjects {
+            if signers
+                .identities
+                .iter()
+                .any(|identity| identity.matches(&issuer, subject))
+            {
+                return Ok(format!("{subject} ({issuer})"));
+            }
+        }
+        bail!("Signer {} ({issuer}) is not trusted", subjects.join(", "))
+    }
+
+    /// Check the transparency log entry of a keyless signature, returning when it was logged.
+    fn check_log_entry(
+        &self,
+        bundle: &str,
+        payload: &[u8],
+        signature: &[u8],
+        certificate_der: &[u8],
+    ) -> Result<i64> {
+        let rekor_key = self
+            .rekor_key
+            .as_ref()
+            .context("No Rekor public key is configured")?;
+        let bundle: Bundle = serde_json::from_str(bundle).context("Malformed log entry")?;
+        let timestamp = BASE64
+            .decode(&bundle.signed_entry_timestamp)
+            .context("Malformed signed entry timestamp")?;
+        ensure!(
+            rekor_key.verifies(&serde_json::to_vec(&bundle.payload)?, &timestamp),
+            "Log entry is not signed by the trusted Rekor key"
+        );
+
+        let body: Value = serde_json::from_slice(
+            &BASE64
+                .decode(&bundle.payload.body)
+                .context("Malformed log entry body")?,
+        )
+        .context("Malformed log entry body")?;
+        let field = |pointer: &str| {
+            body.pointer(pointer)
+                .and_then(Value::as_str)
+                .with_context(|| format!("Log entry has no {pointer}"))
+        };
+        ensure!(
+            field("/kind")? == "hashedrekord",
+            "Unsupported log entry kind"
+        );
+        ensure!(
+            field("/spec/data/hash/algorithm")? == "sha256"
+                && field("/spec/data/hash/value")? == hex::encode(Sha256::digest(payload)),
+            "Log entry is for another payload"
+        );
+        ensure!(
+            BASE64
+                .decode(field("/spec/signature/content")?)
+                .ok()
+                .as_deref()
+                == Some(signature),
+            "Log entry is for another signature"
+        );
+        let logged_certificate = BASE64.decode(field("/spec/signature/publicKey/content")?)?;
+        ensure!(
+            pem_blocks(&logged_certificate, "CERTIFICATE")?
+                .first()
+                .map(Vec::as_slice)
+                == Some(certificate_der),
+            "Log entry is for another certificate"
+        );
+        Ok(bundle.payload.integrated_time)
+    }
+
+    /// Check that `certificate`, through the intermediates in `chain`, is issued by a trusted
+    /// Fulcio certificate, every certificate on the way being valid at `at` (seconds since
+    /// epoch).
+    fn check_chain(
+        &self,
+        certificate: &X509Certificate<'_>,
+        chain: &[Vec<u8>],
+        at: i64,
+    ) -> Result<()> {
+        let at = ASN1Time::from_timestamp(at)?;
+        let roots = self
+            .fulcio_roots
+            .iter()
+            .map(|der| parse_certificate(der))
+            .collect::<Result<Vec<_>>>()?;
+        let chain = chain
+            .iter()
+            .map(|der| parse_certificate(der))
+            .collect::<Result<Vec<_>>>()?;
+        let issued_by = |certificate: &X509Certificate<'_>, issuer: &X509Certificate<'_>| {
+            certificate.issuer().as_raw() == issuer.subject().as_raw()
+                && certificate
+                    .verify_signature(Some(issuer.public_key()))
+                    .is_ok()
+        };
+
+        let mut current = certificate;
+        for _ in 0..=MAX_CHAIN_DEPTH {
+            ensure!(
+                current.validity().is_valid_at(at),
+                "Certificate {} was not valid when the signature was made",
+                current.subject()
+            );
+            if roots.iter().any(|root| issued_by(current, root)) {
+                return Ok(());
+            }
+            current = chain
+                .iter()
+                .find(|intermediate| intermediate.is_ca() && issued_by(current, intermediate))
+                .context("Certificate is not issued by a trusted Fulcio root")?;
+        }
+        bail!("Certificate chain is too long")
+    }
+}
+
+/// The signatures stored next to the image with manifest `digest`, none if it isn't signed.
+async fn fetch_signatures(
+    source: &PullSource,
+    digest: &str,
+    oci_client: &oci_client::Client,
+) -> Result<Vec<ImageSignature>> {
+    let reference = signature_reference(&source.reference, digest);
+    let manifest = match oci_client
+        .pull_image_manifest(&reference, &source.auth)
+        .await
+    {
+        Ok((manifest, _)) => manifest,
+        Err(error) if is_not_found(&error) => return Ok(Vec::new()),
+        Err(error) => return Err(error).context("Failed to fetch the image signatures"),
+    };
+
+    let mut signatures = Vec::new();
+    for layer in &manifest.layers {
+        let annotations = layer.annotations.clone().unwrap_or_default();
+        let Some(signature) = annotations.get(SIGNATURE_ANNOTATION) else {
+            continue;
+        };
+        if layer.media_type != SIMPLE_SIGNING_MEDIA_TYPE {
+            continue;
+        }
+        // The payload is checked against the layer digest as it downloads.
+        let mut payload = Vec::new();
+        oci_client
+            .pull_blob(&reference, layer, &mut payload)
+            .await
+            .context("Failed to fetch a signature payload")?;
+        signatures.push(ImageSignature {
+            payload,
+            signature: signature.clone(),
+            certificate: annotations.get(CERTIFICATE_ANNOTATION).cloned(),
+            chain: annotations.get(CHAIN_ANNOTATION).cloned(),
+            bundle: annotations.get(BUNDLE_ANNOTATION).cloned(),
+        });
+    }
+    Ok(signatures)
+}
+
+/// The `sha256-<hex>.sig` image cosign stores the signatures of `digest` in, in the same
+/// repository (and on the same mirror) as `reference`.
+fn signature_reference(reference: &Reference, digest: &str) -> Reference {
+    let mut signatures = Reference::with_tag(
+        reference.registry().to_string(),
+        reference.repository().to_string(),
+        format!("{}.sig", digest.replace(':', "-")),
+    );
+    if reference.namespace().is_some() {
+        signatures.set_mirror_registry(reference.resolve_registry().to_string());
+    }
+    signatures
+}
+
+fn is_not_found(error: &OciDistributionError) -> bool {
+    match error {
+        OciDistributionError::RegistryError { envelope, .. } => envelope.errors.iter().any(|e| {
+            matches!(
+                e.code,
+                OciErrorCode::ManifestUnknown | OciErrorCode::NameUnknown
+            )
+        }),
+        OciDistributionError::ServerError { code, .. } => *code == 404,
+        OciDistributionError::ImageManifestNotFoundError(_) => true,
+        _ => false,
+    }
+}
+
+/// The OIDC issuer Fulcio recorded in `certificate`.
+fn fulcio_issuer(certificate: &X509Certificate<'_>) -> Option<String> {
+    certificate.iter_extensions().find_map(|extension| {
+        match extension.oid.to_id_string().as_str() {
+            FULCIO_ISSUER_V2_OID => Any::from_der(extension.value)
+                .ok()
+                .and_then(|(_, value)| value.as_string().ok()),
+            FULCIO_ISSUER_V1_OID => String::from_utf8(extension.value.to_vec()).ok(),
+            _ => None,
+        }
+    })
+}
+
+/// Emails and URIs `certificate` is issued to.
+fn certificate_subjects(certificate: &X509Certificate<'_>) -> Vec<String> {
+    use x509_parser::extensions::GeneralName;
+
+    let Ok(Some(names)) = certificate.subject_alternative_name() else {
+        return Vec::new();
+    };
+    names
+        .value
+        .general_names
+        .iter()
+        .filter_map(|name| match name {
+            GeneralName::RFC822Name(subject) | GeneralName::URI(subject) => {
+                Some(subject.to_string())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn parse_certificate(der: &[u8]) -> Result<X509Certificate<'_>> {
+    let (_, certificate) =
+        x509_parser::parse_x509_certificate(der).context("Malformed certificate")?;
+    Ok(certificate)
+}
+
+/// The DER contents of the PEM blocks labelled `label` in `pem`.
+fn pem_blocks(pem: &[u8], label: &str) -> Result<Vec<Vec<u8>>> {
+    let mut blocks = Vec::new();
+    for block in Pem::iter_from_buffer(pem) {
+        let block = block.context("Malformed PEM")?;
+        if block.label == label {
+            blocks.push(block.contents);
+        }
+    }
+    Ok(blocks)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{
+        date_time_ymd, BasicConstraints, CertificateParams, CustomExtension, DnType,
+        ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair, SanType, SigningKey,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    const DIGEST: &str = "sha256:916f0027a575074ce72a331777c3478d6513f786a591bd892da1a577bf2335f9";
+    const ISSUER: &str = "https://token.actions.githubusercontent.com";
+    const SUBJECT: &str =
+        "https://github.com/example/fetch/.github/workflows/release.yml@refs/tags/v1.0.0";
+    /// 2025-01-01T12:00:00Z, while the signing certificate is valid
+    const SIGNED_AT: i64 = 1_735_732_800;
+
+    fn payload(digest: &str) -> Vec<u8> {
+        json!({
+            "critical": {
+                "identity": {"docker-reference": "ghcr.io/example/fetch"},
+                "image": {"docker-manifest-digest": digest},
+                "type": "cosign container image signature"
+            },
+            "optional": null
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn sign(key: &KeyPair, message: &[u8]) -> String {
+        BASE64.encode(key.sign(message).unwrap())
+    }
+
+    fn write(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn ca(name: &str) -> CertificateParams {
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name.push(DnType::CommonName, name);
+        params
+    }
+
+    #[test]
+    fn test_key_signature_must_match_digest_and_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = KeyPair::generate().unwrap();
+        let config = SignatureConfig {
+            registries: HashMap::from([(
+                "ghcr.io/example".to_string(),
+                TrustedSigners {
+                    keys: vec![write(dir.path(), "cosign.pub", &key.public_key_pem())],
+                    identities: vec![],
+                },
+            )]),
+            ..Default::default()
+        };
+        let verifier = SignatureVerifier::new(&config).unwrap();
+        let reference: Reference = "ghcr.io/example/fetch:1.0".parse().unwrap();
+        let signers = verifier.signers(&reference).unwrap();
+
+        let signature = ImageSignature {
+            payload: payload(DIGEST),
+            signature: sign(&key, &payload(DIGEST)),
+            ..Default::default()
+        };
+        let signer = verifier.check(signers, &signature, DIGEST).unwrap();
+        assert!(signer.ends_with("cosign.pub"));
+
+        // Signed for another image
+        let other_digest = format!("sha256:{}", "0".repeat(64));
+        assert!(verifier.check(signers, &signature, &other_digest).is_err());
+        // Tampered payload
+        let mut tampered = signature.clone();
+        tampered.payload.push(b' ');
+        assert!(verifier.check(signers, &tampered, DIGEST).is_err());
+        // Untrusted key
+        let other_key = KeyPair::generate().unwrap();
+        let untrusted = ImageSignature {
+            signature: sign(&other_key, &signature.payload),
+            ..signature
+        };
+        assert!(verifier.check(signers, &untrusted, DIGEST).is_err());
+    }
+
+    #[test]
+    fn test_signers_come_from_the_longest_matching_scope() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = KeyPair::generate().unwrap();
+        let signers = |name: &str| TrustedSigners {
+            keys: vec![write(dir.path(), name, &key.public_key_pem())],
+            identities: vec![],
+        };
+        let config = SignatureConfig {
+            registries: HashMap::from([
+                ("ghcr.io".to_string(), signers("registry.pub")),
+                ("ghcr.io/example/".to_string(), signers("example.pub")),
+            ]),
+            ..Default::default()
+        };
+        let verifier = SignatureVerifier::new(&config).unwrap();
+        let key_of = |reference: &str| {
+            let reference: Reference = reference.parse().unwrap();
+            verifier
+                .signers(&reference)
+                .map(|signers| signers.keys[0].0.file_name().unwrap().to_owned())
+        };
+        assert_eq!(key_of("ghcr.io/example/fetch:1.0").unwrap(), "example.pub");
+        assert_eq!(
+            key_of("ghcr.io/examples/fetch:1.0").unwrap(),
+            "registry.pub"
+        );
+        assert_eq!(key_of("docker.io/library/hello:1"), None);
+    }
+
+    #[test]
+    fn test_invalid_configurations_are_rejected() {
+        let empty = SignatureConfig {
+            registries: HashMap::from([("ghcr.io".to_string(), TrustedSigners::default())]),
+            ..Default::default()
+        };
+        assert!(empty.validate().is_err());
+
+        let keyless_without_roots = SignatureConfig {
+            registries: HashMap::from([(
+                "ghcr.io".to_string(),
+                TrustedSigners {
+                    keys: vec![],
+                    identities: vec![SignerIdentity {
+                        issuer: ISSUER.to_string(),
+                        subject: "*".to_string(),
+                    }],
+                },
+            )]),
+            ..Default::default()
+        };
+        assert!(keyless_without_roots.validate().is_err());
+
+        let missing_key = SignatureConfig {
+            registries: HashMap::from([(
+                "ghcr.io".to_string(),
+                TrustedSigners {
+                    keys: vec![PathBuf::from("/nonexistent/cosign.pub")],
+                    identities: vec![],
+                },
+            )]),
+            ..Default::default()
+        };
+        assert!(missing_key.validate().is_err());
+    }
+
+    /// A keyless signature by a Fulcio-style root and intermediate, logged by a Rekor-style
+    /// log.
+    struct Keyless {
+        verifier: SignatureVerifier,
+        signature: ImageSignature,
+        leaf_pem: String,
+        rekor_key: KeyPair,
+    }
+
+    impl Keyless {
+        fn new(subject: &str) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let root_key = KeyPair::generate().unwrap();
+            let root = ca("sigstore").self_signed(&root_key).unwrap();
+            let root_issuer = Issuer::new(ca("sigstore"), &root_key);
+            let intermediate_key = KeyPair::generate().unwrap();
+            let intermediate = ca("sigstore-intermediate")
+                .signed_by(&intermediate_key, &root_issuer)
+                .unwrap();
+            let intermediate_issuer = Issuer::new(ca("sigstore-intermediate"), &intermediate_key);
+
+            let leaf_key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec![]).unwrap();
+            params.subject_alt_names = vec![SanType::URI(SUBJECT.try_into().unwrap())];
+            params.extended_key_usages = vec![ExtendedKeyUsagePurpose::CodeSigning];
+            params.custom_extensions = vec![CustomExtension::from_oid_content(
+                &[1, 3, 6, 1, 4, 1, 57264, 1, 1],
+                ISSUER.as_bytes().to_vec(),
+            )];
+            params.not_before = date_time_ymd(2025, 1, 1);
+            params.not_after = date_time_ymd(2025, 1, 2);
+            let leaf = params.signed_by(&leaf_key, &intermediate_issuer).unwrap();
+
+            let rekor_key = KeyPair::generate().unwrap();
+            let config = SignatureConfig {
+                require_signed: true,
+                registries: HashMap::from([(
+                    "ghcr.io".to_string(),
+                    TrustedSigners {
+                        keys: vec![],
+                        identities: vec![SignerIdentity {
+                            issuer: ISSUER.to_string(),
+                            subject: subject.to_string(),
+                        }],
+                    },
+                )]),
+                fulcio_roots: vec![write(dir.path(), "fulcio.pem", &root.pem())],
+                rekor_public_key: Some(write(dir.path(), "rekor.pub", &rekor_key.public_key_pem())),
+            };
+            let payload = payload(DIGEST);
+            let mut keyless = Self {
+                verifier: SignatureVerifier::new(&config).unwrap(),
+                signature: ImageSignature {
+                    signature: sign(&leaf_key, &payload),
+                    payload,
+                    certificate: Some(leaf.pem()),
+                    chain: Some(format!("{}{}", intermediate.pem(), root.pem())),
+                    bundle: None,
+                },
+                leaf_pem: leaf.pem(),
+                rekor_key,
+            };
+            keyless.log(SIGNED_AT);
+            keyless
+        }
+
+        /// Record the signature in the log at `integrated_time`.
+        fn log(&mut self, integrated_time: i64) {
+            let body = json!({
+                "apiVersion": "0.0.1",
+                "kind": "hashedrekord",
+                "spec": {
+                    "data": {"hash": {
+                        "algorithm": "sha256",
+                        "value": hex::encode(Sha256::digest(&self.signature.payload)),
+                    }},
+                    "signature": {
+                        "content": self.signature.signature,
+                        "publicKey": {"content": BASE64.encode(&self.leaf_pem)},
+                    },
+                },
+            });
+            let entry = LogEntry {
+                body: BASE64.encode(body.to_string()),
+                integrated_time,
+                log_id: "c0d23d6ad406973f9559f3ba2d1ca01f84147d8ffc5b8445c224f98b9591801d"
+                    .to_string(),
+                log_index: 42,
+            };
+            let timestamp = sign(&self.rekor_key, &serde_json::to_vec(&entry).unwrap());
+            self.signature.bundle =
+                Some(json!({"SignedEntryTimestamp": timestamp, "Payload": entry}).to_string());
+        }
+
+        fn check(&self) -> Result<String> {
+            let reference: Reference = "ghcr.io/example/fetch:1.0".parse().unwrap();
+            let signers = self.verifier.signers(&reference).unwrap();
+            self.verifier.check(signers, &self.signature, DIGEST)
+        }
+    }
+
+    #[test]
+    fn test_keyless_signature_verifies_identity_chain_and_log_entry() {
+        let keyless = Keyless::new("https://github.com/example/fetch/*");
+        assert_eq!(keyless.check().unwrap(), format!("{SUBJECT} ({ISSUER})"));
+
+        // Another workflow
+        let other = Keyless::new("https://github.com/example/other/*");
+        assert!(other.check().is_err());
+
+        // Logged after the certificate expired
+        let mut late = Keyless::new("https://github.com/example/fetch/*");
+        late.log(SIGNED_AT + 7 * 24 * 3600);
+        assert!(late.check().is_err());
+
+        // Log entry signed by another log
+        let mut forged = Keyless::new("https://github.com/example/fetch/*");
+        forged.rekor_key = KeyPair::generate().unwrap();
+        forged.log(SIGNED_AT);
+        assert!(forged.check().is_err());
+
+        // Not logged at all
+        let mut unlogged = Keyless::new("https://github.com/example/fetch/*");
+        unlogged.signature.bundle = None;
+        assert!(unlogged.check().is_err());
+
+        // Certificate of an untrusted root
+        let mut foreign = Keyless::new("https://github.com/example/fetch/*");
+        foreign.signature.chain = None;
+        assert!(foreign.check().is_err());
+    }
+
+    #[test]
+    fn test_signature_reference_is_next_to_the_image() {
+        let reference: Reference = "ghcr.io/example/fetch:1.0".parse().unwrap();
+        let signatures = signature_reference(&reference, DIGEST);
+        assert_eq!(
+            signatures.whole(),
+            format!("ghcr.io/example/fetch:{}.sig", DIGEST.replace(':', "-"))
+        );
+
+        let mut mirrored = reference.clone();
+        mirrored.set_mirror_registry("mirror.example.com".to_string());
+        let signatures = signature_reference(&mirrored, DIGEST);
+        assert_eq!(signatures.resolve_registry(), "mirror.example.com");
+        assert_eq!(signatures.namespace(), Some("ghcr.io"));
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Load figures of the runtime, for autoscaling.
+//!
+//! Every tool call that runs a component (cached results don't) counts as in progress from
+//! the moment it asks for an execution slot until it returns. Its duration, including the
+//! time it waited in the queue, is kept in a window of the most recent calls from which
+//! latency percentiles are computed.
+//!
+//! Each component also keeps counters for alerting on misbehaving components: its calls and
+//! their durations, bucketed for a histogram, its loads and unloads, the network requests and
+//! filesystem escapes its policy refused, its traps, and the most linear memory a single
+//! instance of it used.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Calls whose durations the latency percentiles are computed from.
+pub(crate) const LATENCY_WINDOW: usize = 1024;
+
+/// Upper bounds of the buckets of the per-component call duration histogram, in seconds.
+pub const LATENCY_BUCKETS_SECS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// Snapshot of the load of a lifecycle manager.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeStats {
+    /// Interactive tool calls waiting for an execution slot
+    pub queued_interactive_calls: usize,
+    /// Batch tool calls waiting for an execution slot
+    pub queued_batch_calls: usize,
+    /// Tool calls running
+    pub active_calls: usize,
+    /// Calls that run at once before further calls queue (`max_concurrent_calls`); calls only
+    /// queue once a tool is `batch`
+    pub execution_slots: usize,
+    /// Running calls per execution slot; above 1 when calls aren't queued
+    pub pool_utilization: f64,
+    /// Instances of components whose policy limits their concurrent instances
+    pub limited_instances: usize,
+    /// Sum of the instance limits of those components
+    pub instance_capacity: usize,
+    /// Standby instances ready for the next call of a latency-critical tool
+    pub standby_instances: usize,
+    /// Latency-critical tools
+    pub latency_critical_tools: usize,
+    /// Components compiled and ready to run
+    pub loaded_components: usize,
+    /// Tool calls completed since the server started
+    pub calls_total: u64,
+    /// Completed calls that failed or returned a tool error
+    pub call_errors_total: u64,
+    /// Median duration of the most recent calls, in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_p50_secs: Option<f64>,
+    /// 95th percentile duration of the most recent calls, in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_p95_secs: Option<f64>,
+    /// Counters of every component loaded since the server started, by component id
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub components: BTreeMap<String, ComponentStats>,
+}
+
+/// Counters of one component since the server started.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ComponentStats {
+    /// Tool calls completed
+    pub calls_total: u64,
+    /// Completed calls that failed or returned a tool error
+    pub call_errors_total: u64,
+    /// Completed calls that took at most each of [`LATENCY_BUCKETS_SECS`], cumulative
+    pub latency_buckets: [u64; LATENCY_BUCKETS_SECS.len()],
+    /// Total duration of the completed calls, in seconds
+    pub latency_sum_secs: f64,
+    /// Times the component was loaded or replaced
+    pub loads_total: u64,
+    /// Times the component was unloaded
+    pub unloads_total: u64,
+    /// Network requests and filesystem escapes refused by the component's policy
+    pub permission_denials_total: u64,
+    /// Calls that ended in a trap, timeouts and fuel exhaustion included
+    pub traps_total: u64,
+    /// Most linear memory a single instance used, in bytes
+    pub memory_high_water_bytes: u64,
+}
+
+/// Counters of the tool calls of a lifecycle manager.
+#[derive(Debug, Default)]
+pub(crate) struct CallStats {
+    in_progress: AtomicUsize,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    latencies: Mutex<VecDeque<Duration>>,
+}
+
+impl CallStats {
+    /// Count a call as in progress until the returned guard is dropped.
+    pub(crate) fn start(self: &Arc<Self>) -> CallInProgress {
+        self.in_progress.fetch_add(1, Ordering::Relaxed);
+        CallInProgress {
+            stats: Arc::clone(self),
+            started: Instant::now(),
+        }
+    }
+
+    /// Calls in progress, queued or running.
+    pub(crate) fn in_progress(&self) -> usize {
+        self.in_progress.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn completed(&self) -> u64 {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Duration under which `quantile` (between 0 and 1) of the recent calls completed.
+    pub(crate) fn latency(&self, quantile: f64) -> Option<Duration> {
+        let mut latencies: Vec<Duration> = self.latencies.lock().unwrap().iter().copied().collect();
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort_unstable();
+        // Nearest rank
+        let rank = (quantile * latencies.len() as f64).ceil() as usize;
+        Some(latencies[rank.clamp(1, latencies.len()) - 1])
+    }
+
+    fn record(&self, elapsed: Duration, succeeded: bool) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        if !succeeded {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.len() == LATENCY_WINDOW {
+            latencies.pop_front();
+        }
+        latencies.push_back(elapsed);
+    }
+}
+
+/// A call counted as in progress. Dropping it without [`Self::finish`] (a cancelled call)
+/// leaves the completed calls and latencies untouched.
+pub(crate) struct CallInProgress {
+    stats: Arc<CallStats>,
+    started: Instant,
+}
+
+impl CallInProgress {
+    /// Record the call as completed, returning its duration.
+    pub(crate) fn finish(self, succeeded: bool) -> Duration {
+        let elapsed = self.started.elapsed();
+        self.stats.record(elapsed, succeeded);
+        elapsed
+    }
+}
+
+impl Drop for CallInProgress {
+    fn drop(&mut self) {
+        self.stats.in_progress.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counters of the components of a lifecycle manager, kept after they are unloaded.
+#[derive(Debug, Default)]
+pub(crate) struct ComponentMetrics {
+    components: Mutex<BTreeMap<String, ComponentStats>>,
+}
+
+impl ComponentMetrics {
+    fn update(&self, component_id: &str, update: impl FnOnce(&mut ComponentStats)) {
+        let mut components = self.components.lock().unwrap();
+        match components.get_mut(component_id) {
+            Some(stats) => update(stats),
+            None => update(components.entry(component_id.to_string()).or_default()),
+        }
+    }
+
+    pub(crate) fn record_call(&self, component_id: &str, elapsed: Duration, succeeded: bool) {
+        let secs = elapsed.as_secs_f64();
+        self.update(component_id, |stats| {
+            stats.calls_total += 1;
+            if !succeeded {
+                stats.call_errors_total += 1;
+            }
+            for (count, bound) in stats.latency_buckets.iter_mut().zip(LATENCY_BUCKETS_SECS) {
+                if secs <= bound {
+                    *count += 1;
+                }
+            }
+            stats.latency_sum_secs += secs;
+        });
+    }
+
+    pub(crate) fn record_load(&self, component_id: &str) {
+        self.update(component_id, |stats| stats.loads_total += 1);
+    }
+
+    pub(crate) fn record_unload(&self, component_id: &str) {
+        self.update(component_id, |stats| stats.unloads_total += 1);
+    }
+
+    pub(crate) fn record_denial(&self, component_id: &str) {
+        self.update(component_id, |stats| stats.permission_denials_total += 1);
+    }
+
+    pub(crate) fn record_trap(&self, component_id: &str) {
+        self.update(component_id, |stats| stats.traps_total += 1);
+    }
+
+    /// Raise the memory high-water mark of `component_id` to `bytes`.
+    pub(crate) fn record_memory(&self, component_id: &str, bytes: u64) {
+        self.update(component_id, |stats| {
+            stats.memory_high_water_bytes = stats.memory_high_water_bytes.max(bytes)
+        });
+    }
+
+    pub(crate) fn snapshot(&self) -> BTreeMap<String, ComponentStats> {
+        self.components.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calls_are_counted_and_timed() {
+        let stats = Arc::new(CallStats::default());
+        assert_eq!(stats.latency(0.95), None);
+
+        let call = stats.start();
+        let cancelled = stats.start();
+        assert_eq!(stats.in_progress(), 2);
+        call.finish(false);
+        drop(cancelled);
+        assert_eq!(stats.in_progress(), 0);
+        assert_eq!(stats.completed(), 1);
+        assert_eq!(stats.failed(), 1);
+
+        for millis in 1..=100 {
+            stats.record(Duration::from_millis(millis), true);
+        }
+        assert_eq!(stats.latency(0.95), Some(Duration::from_millis(95)));
+        assert_eq!(stats.latency(0.5), Some(Duration::from_millis(50)));
+
+        // Only the most recent calls count.
+        for _ in 0..LATENCY_WINDOW {
+            stats.record(Duration::from_secs(2), true);
+        }
+        assert_eq!(stats.latency(0.5), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_component_counters() {
+        let metrics = ComponentMetrics::default();
+        metrics.record_load("fetch");
+        metrics.record_call("fetch", Duration::from_millis(20), true);
+        metrics.record_call("fetch", Duration::from_secs(60), false);
+        metrics.record_trap("fetch");
+        metrics.record_denial("fetch");
+        metrics.record_memory("fetch", 2 << 20);
+        metrics.record_memory("fetch", 1 << 20);
+        metrics.record_unload("time");
+
+        let components = metrics.snapshot();
+        let fetch = &components["fetch"];
+        assert_eq!(fetch.calls_total, 2);
+        assert_eq!(fetch.call_errors_total, 1);
+        // 20ms falls in every bucket from 25ms up; a minute in none.
+        assert_eq!(fetch.latency_buckets, [0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1]);
+        assert!((fetch.latency_sum_secs - 60.02).abs() < 1e-9);
+        assert_eq!(fetch.loads_total, 1);
+        assert_eq!(fetch.traps_total, 1);
+        assert_eq!(fetch.permission_denials_total, 1);
+        assert_eq!(fetch.memory_high_water_bytes, 2 << 20);
+        assert_eq!(components["time"].unloads_total, 1);
+        assert_eq!(components["time"].calls_total, 0);
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Detection of the toolchain that built a component, used to turn load errors into advice
+//! its author can act on.
+//!
+//! Toolchains record themselves in the `producers` custom section of the component or of the
+//! core modules nested in it. When a component fails to compile or to link against the host,
+//! the error is annotated with a hint phrased in terms of that toolchain, such as the `jco
+//! componentize --disable` flag that drops an unneeded WASI import.
+
+use std::fmt;
+
+use wasmparser::{KnownCustom, Parser, Payload};
+
+use crate::runtime_context::HOST_PACKAGES;
+
+/// Toolchain that built a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Toolchain {
+    /// Python through `componentize-py`
+    ComponentizePy,
+    /// JavaScript through `jco componentize` (ComponentizeJS)
+    Jco,
+    /// Go through TinyGo's `wasip2` target
+    TinyGo,
+    /// Rust through `cargo component` or the `wasm32-wasip2` target
+    CargoComponent,
+}
+
+impl Toolchain {
+    /// Detect the toolchain from the `producers` sections of a module or component binary.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        let producers = collect_producers(bytes);
+        // The first match wins, so tools that embed others are checked before them.
+        [
+            Self::ComponentizePy,
+            Self::Jco,
+            Self::TinyGo,
+            Self::CargoComponent,
+        ]
+        .into_iter()
+        .find(|toolchain| {
+            producers
+                .iter()
+                .any(|(field, name)| toolchain.produced(field, name))
+        })
+    }
+
+    fn produced(self, field: &str, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        match (self, field) {
+            (Self::ComponentizePy, "processed-by") => name == "componentize-py",
+            (Self::ComponentizePy, "language") => name == "python",
+            (Self::Jco, "processed-by") => matches!(name.as_str(), "componentizejs" | "jco"),
+            (Self::Jco, "language") => name == "javascript",
+            (Self::TinyGo, "processed-by") => name == "tinygo",
+            (Self::TinyGo, "language") => matches!(name.as_str(), "go" | "tinygo"),
+            (Self::CargoComponent, "processed-by") => name == "cargo-component",
+            (Self::CargoComponent, "language") => name == "rust",
+            _ => false,
+        }
+    }
+
+    /// How to build a component rather than a core module with this toolchain.
+    fn component_hint(self) -> &'static str {
+        match self {
+            Self::ComponentizePy => "build it with `componentize-py componentize`",
+            Self::Jco => "build it with `jco componentize`",
+            Self::TinyGo => "build it with `tinygo build -target=wasip2`",
+            Self::CargoComponent => {
+                "build it with `cargo component build` or for the `wasm32-wasip2` target"
+            }
+        }
+    }
+
+    /// How to rebuild without an import of `interface` that the host doesn't provide.
+    fn import_hint(self, interface: &str) -> String {
+        match self {
+            Self::ComponentizePy => format!(
+                "componentize-py imports every interface of the world it builds; remove `{interface}` from the world passed to `componentize-py --wit-path ... --world ...` and rebuild"
+            ),
+            Self::Jco => match jco_feature(interface) {
+                Some(feature) => format!(
+                    "ComponentizeJS imports `{interface}` by default; if the component doesn't use it, rebuild with `jco componentize --disable {feature}`"
+                ),
+                None => format!(
+                    "remove `{interface}` from the world passed to `jco componentize --wit` and rebuild"
+                ),
+            },
+            Self::TinyGo => format!(
+                "TinyGo's wasip2 target imports the `wasi:cli/command` world unless told otherwise; pass `-wit-package` and `-wit-world` naming a world without `{interface}`"
+            ),
+            Self::CargoComponent => format!(
+                "remove `{interface}` from the component's WIT world, or the dependency that imports it, and rebuild with `cargo component build`"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Toolchain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ComponentizePy => "componentize-py",
+            Self::Jco => "jco",
+            Self::TinyGo => "TinyGo",
+            Self::CargoComponent => "cargo-component",
+        })
+    }
+}
+
+/// The `jco componentize --disable` feature that drops imports of `interface`.
+fn jco_feature(interface: &str) -> Option<&'static str> {
+    let (package, name) = interface.split_once('/')?;
+    match package {
+        "wasi:http" => Some("http"),
+        "wasi:random" => Some("random"),
+        "wasi:clocks" => Some("clocks"),
+        "wasi:cli" if name.starts_with("std") || name.starts_with("terminal") => Some("stdio"),
+        _ => None,
+    }
+}
+
+/// Advice for a component built by a known toolchain that failed to load with `error`.
+///
+/// Returns `None` when the toolchain isn't recognized or the error isn't one it explains.
+pub(crate) fn diagnose(bytes: &[u8], error: &anyhow::Error) -> Option<String> {
+    let toolchain = Toolchain::detect(bytes)?;
+    if Parser::is_core_wasm(bytes) {
+        return Some(format!(
+            "this is a core WebAssembly module built with {toolchain}, not a component; {}",
+            toolchain.component_hint()
+        ));
+    }
+
+    let import = missing_import(error)?;
+    let interface = import.split('@').next().unwrap_or(&import);
+    let package = interface.split('/').next().unwrap_or(interface);
+    if HOST_PACKAGES.contains(&package) {
+        Some(format!(
+            "the component was built with {toolchain} and imports `{import}`, a version or interface of {package} the host doesn't provide; rebuild with a {toolchain} release targeting WASI 0.2, or {}",
+            toolchain.import_hint(interface)
+        ))
+    } else {
+        Some(format!(
+            "the component was built with {toolchain} and imports `{import}`, which the host doesn't provide; {}",
+            toolchain.import_hint(interface)
+        ))
+    }
+}
+
+/// The import named by a linker error, such as
+/// ``component imports instance `wasi:cli/environment@0.2.0`, but ...``.
+fn missing_import(error: &anyhow::Error) -> Option<String> {
+    error.chain().find_map(|cause| {
+        let message = cause.to_string();
+        let rest = message.strip_prefix("component imports ")?;
+        let (_, rest) = rest.split_once('`')?;
+        let (import, _) = rest.split_once('`')?;
+        Some(import.to_string())
+    })
+}
+
+/// The `(field, name)` entries of every `producers` section in `bytes`, nested modules and
+/// components included.
+fn collect_producers(bytes: &[u8]) -> Vec<(String, String)> {
+    let mut producers = Vec::new();
+    for payload in Parser::new(0).parse_all(bytes) {
+        // What was read before a malformed or truncated section still counts.
+        let Ok(payload) = payload else {
+            break;
+        };
+        let Payload::CustomSection(section) = payload else {
+            continue;
+        };
+        let KnownCustom::Producers(fields) = section.as_known() else {
+            continue;
+        };
+        for field in fields.into_iter().flatten() {
+            for value in field.values.into_iter().flatten() {
+                producers.push((field.name.to_string(), value.name.to_string()));
+            }
+        }
+    }
+    producers
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    const COMPONENT_HEADER: &[u8] = b"\0asm\x0d\0\x01\0";
+    const CORE_MODULE_SECTION: u8 = 1;
+    const MODULE_HEADER: &[u8] = b"\0asm\x01\0\0\0";
+
+    fn leb128(mut value: usize, out: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                return;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    fn name(value: &str, out: &mut Vec<u8>) {
+        leb128(value.len(), out);
+        out.extend_from_slice(value.as_bytes());
+    }
+
+    fn section(id: u8, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![id];
+        leb128(payload.len(), &mut out);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn binary(header: &[u8], sections: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = header.to_vec();
+        for section in sections {
+            out.extend_from_slice(section);
+        }
+        out
+    }
+
+    fn producers(fields: &[(&str, &str)]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        name("producers", &mut payload);
+        leb128(fields.len(), &mut payload);
+        for (field, value) in fields {
+            name(field, &mut payload);
+            leb128(1, &mut payload);
+            name(value, &mut payload);
+            name("1.0.0", &mut payload);
+        }
+        section(0, &payload)
+    }
+
+    fn linker_error(import: &str) -> anyhow::Error {
+        anyhow!("instance export `handle` has the wrong type").context(format!(
+            "component imports instance `{import}`, but a matching implementation was not found in the linker"
+        ))
+    }
+
+    #[test]
+    fn test_detect_toolchain() {
+        let wit_component = producers(&[("processed-by", "wit-component")]);
+        let python = binary(
+            MODULE_HEADER,
+            &[producers(&[("language", "Rust"), ("language", "Python")])],
+        );
+        let component = binary(
+            COMPONENT_HEADER,
+            &[wit_component.clone(), section(CORE_MODULE_SECTION, &python)],
+        );
+        assert_eq!(
+            Toolchain::detect(&component),
+            Some(Toolchain::ComponentizePy)
+        );
+
+        let rust = binary(
+            COMPONENT_HEADER,
+            &[producers(&[("processed-by", "cargo-component")])],
+        );
+        assert_eq!(Toolchain::detect(&rust), Some(Toolchain::CargoComponent));
+
+        let unknown = binary(COMPONENT_HEADER, &[wit_component]);
+        assert_eq!(Toolchain::detect(&unknown), None);
+        assert_eq!(Toolchain::detect(b"not wasm"), None);
+        assert_eq!(Toolchain::detect(&rust[..rust.len() - 1]), None);
+    }
+
+    #[test]
+    fn test_diagnose_core_module() {
+        let module = binary(MODULE_HEADER, &[producers(&[("processed-by", "TinyGo")])]);
+        let hint = diagnose(&module, &anyhow!("failed to parse component")).unwrap();
+        assert!(hint.contains("core WebAssembly module built with TinyGo"));
+        assert!(hint.contains("-target=wasip2"));
+    }
+
+    #[test]
+    fn test_diagnose_missing_import() {
+        let js = binary(
+            COMPONENT_HEADER,
+            &[producers(&[("processed-by", "ComponentizeJS")])],
+        );
+        let hint = diagnose(&js, &linker_error("wasi:http/outgoing-handler@0.3.0")).unwrap();
+        assert!(hint.contains("built with jco"));
+        assert!(hint.contains("jco componentize --disable http"));
+
+        let rust = binary(
+            COMPONENT_HEADER,
+            &[producers(&[("processed-by", "cargo-component")])],
+        );
+        let hint = diagnose(&rust, &linker_error("acme:db/query@1.0.0")).unwrap();
+        assert!(hint.contains("`acme:db/query@1.0.0`, which the host doesn't provide"));
+        assert!(hint.contains("cargo component build"));
+
+        assert!(diagnose(&rust, &anyhow!("out of memory")).is_none());
+    }
+}
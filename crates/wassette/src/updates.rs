@@ -0,0 +1,527 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Updates of components pinned by tag, scheduled or on request.
+//!
+//! A component loaded from an OCI tag, such as `oci://ghcr.io/acme/fetch:1`, from an `https://`
+//! URL, or installed from a manifest fetched over HTTP(S) remembers where it came from. With
+//! updates configured, the manager checks these components once a day when the maintenance
+//! window opens; `wassette component update` and the `update-component` tool check one right
+//! away. A tag whose component layer no longer matches the loaded artifact is pulled again, as
+//! is a URL serving another artifact; a manifest is fetched again, its signature verified, and
+//! installed if it names another release. Provenance pins apply to updates as they do to any
+//! load.
+//!
+//! An update keeps the component's policy, with the permissions granted to it, rather than
+//! taking one published with the new release. Before an update the component's artifact,
+//! manifest and policy are copied aside. If the new version fails to load, or fails the
+//! component's health check (a tool call that must succeed), the copy is restored. Components
+//! still waiting when the window closes are checked in the next one.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveTime, TimeDelta, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::component_storage::ComponentStorage;
+use crate::manifest::Manifest;
+
+/// Settings of scheduled updates, under `[updates]` in the configuration file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UpdateConfig {
+    /// Time of day the maintenance window opens, as `HH:MM`
+    pub window: String,
+    /// Minutes the window stays open
+    pub window_minutes: u32,
+    /// Time zone of `window`: `local` for the server's own (from `TZ` or the system), `utc`,
+    /// or a fixed offset such as `+02:00`
+    pub timezone: String,
+    /// Tool calls that must succeed after an update, keyed by component id
+    pub health_checks: HashMap<String, HealthCheck>,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            window: "03:00".to_string(),
+            window_minutes: 60,
+            timezone: "local".to_string(),
+            health_checks: HashMap::new(),
+        }
+    }
+}
+
+impl UpdateConfig {
+    /// Check the window, its time zone and the health checks.
+    pub fn validate(&self) -> Result<()> {
+        self.maintenance_window()?;
+        if let Some((component, _)) = self
+            .health_checks
+            .iter()
+            .find(|(_, check)| check.tool.is_empty())
+        {
+            bail!("The health check of component {component} names no tool");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn maintenance_window(&self) -> Result<MaintenanceWindow> {
+        let start = NaiveTime::parse_from_str(self.window.trim(), "%H:%M").with_context(|| {
+            format!(
+                "Invalid maintenance window `{}`; expected a time such as 03:00",
+                self.window
+            )
+        })?;
+        if self.window_minutes == 0 {
+            bail!("The maintenance window must be open for at least one minute");
+        }
+        let zone = match self.timezone.trim().to_ascii_lowercase().as_str() {
+            "local" => Zone::Local,
+            "utc" => Zone::Fixed(FixedOffset::east_opt(0).expect("zero offset is valid")),
+            offset => Zone::Fixed(offset.parse().with_context(|| {
+                format!(
+                    "Invalid time zone `{}`; expected local, utc or an offset such as +02:00",
+                    self.timezone
+                )
+            })?),
+        };
+        Ok(MaintenanceWindow {
+            start,
+            length: TimeDelta::minutes(i64::from(self.window_minutes)),
+            zone,
+        })
+    }
+}
+
+/// A tool call that must succeed after a component is updated.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthCheck {
+    /// Name of the tool to call
+    pub tool: String,
+    /// Arguments of the call
+    pub arguments: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Zone {
+    Local,
+    Fixed(FixedOffset),
+}
+
+/// The daily window in which updates run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MaintenanceWindow {
+    start: NaiveTime,
+    length: TimeDelta,
+    zone: Zone,
+}
+
+impl MaintenanceWindow {
+    /// When the window is next open at or after `now`, and when it closes again. The opening
+    /// is `now` itself while the window is open.
+    pub(crate) fn next(&self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let today = match self.zone {
+            Zone::Local => now.with_timezone(&Local).date_naive(),
+            Zone::Fixed(offset) => now.with_timezone(&offset).date_naive(),
+        };
+        // Yesterday's window may still be open past midnight, and a window starting in a
+        // skipped hour doesn't open on that day at all.
+        (-1..=2)
+            .filter_map(|days| today.checked_add_signed(TimeDelta::days(days)))
+            .filter_map(|date| self.opening_on(date))
+            .map(|opens| (opens, opens + self.length))
+            .find(|(_, closes)| now < *closes)
+            .map(|(opens, closes)| (opens.max(now), closes))
+            .expect("a window opens within two days")
+    }
+
+    fn opening_on(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        let start = date.and_time(self.start);
+        match self.zone {
+            Zone::Local => Local
+                .from_local_datetime(&start)
+                .earliest()
+                .map(|opens| opens.with_timezone(&Utc)),
+            Zone::Fixed(offset) => offset
+                .from_local_datetime(&start)
+                .single()
+                .map(|opens| opens.with_timezone(&Utc)),
+        }
+    }
+}
+
+/// Where a component can be updated from, kept next to it in storage.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub(crate) enum UpdateSource {
+    /// An `oci://` reference by tag
+    Oci { reference: String },
+    /// A signed manifest fetched from an HTTP(S) URL
+    Manifest { location: String },
+    /// An `https://` URL serving the component
+    Url { url: String },
+}
+
+impl UpdateSource {
+    /// The source of a component loaded from `uri`, if what it names can change: an OCI
+    /// reference with a tag rather than a digest, or an `https://` URL.
+    pub(crate) fn for_uri(uri: &str) -> Option<Self> {
+        if uri.starts_with("https://") {
+            return Some(Self::Url {
+                url: uri.to_string(),
+            });
+        }
+        let reference: oci_client::Reference = uri.strip_prefix("oci://")?.parse().ok()?;
+        reference.digest().is_none().then(|| Self::Oci {
+            reference: uri.to_string(),
+        })
+    }
+
+    /// Where the component is checked for a new release.
+    pub(crate) fn location(&self) -> &str {
+        match self {
+            Self::Oci { reference } => reference,
+            Self::Manifest { location } => location,
+            Self::Url { url } => url,
+        }
+    }
+
+    /// The source of a component installed from the manifest at `location`, if it can be
+    /// fetched again.
+    pub(crate) fn for_manifest(location: &str) -> Option<Self> {
+        (location.starts_with("https://") || location.starts_with("http://")).then(|| {
+            Self::Manifest {
+                location: location.to_string(),
+            }
+        })
+    }
+}
+
+/// What an update did to a component.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum UpdateStatus {
+    /// The loaded release is the latest
+    UpToDate,
+    /// The new release is loaded
+    Updated,
+    /// The new release failed to load or its health check, and the previous one is back
+    RolledBack {
+        /// Why the new release was rejected
+        error: String,
+    },
+    /// The update couldn't be checked or applied
+    Failed {
+        /// What went wrong
+        error: String,
+    },
+}
+
+/// The outcome of a scheduled update of one component.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentUpdate {
+    /// The component checked for updates
+    pub component_id: String,
+    /// What happened to it
+    #[serde(flatten)]
+    pub status: UpdateStatus,
+}
+
+/// The outcome of an update of one component requested by a user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UpdateReport {
+    /// The component checked for updates
+    pub component_id: String,
+    /// Where it was checked for a new release
+    pub source: String,
+    /// What happened to it
+    #[serde(flatten)]
+    pub status: UpdateStatus,
+    /// Digest of the artifact loaded before, as `sha256:<hex>`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_digest: Option<String>,
+    /// Digest of the artifact loaded now
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Release version loaded before, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_version: Option<String>,
+    /// Release version loaded now, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// Digest of the component layer of an OCI image, as `sha256:<hex>`.
+pub(crate) fn component_layer_digest(
+    manifest: &oci_client::manifest::OciImageManifest,
+) -> Result<String> {
+    manifest
+        .layers
+        .iter()
+        .find(|layer| {
+            layer.media_type == oci_wasm::WASM_LAYER_MEDIA_TYPE
+                || crate::oci_multi_layer::WASM_MEDIA_TYPES.contains(&layer.media_type.as_str())
+        })
+        .map(|layer| layer.digest.clone())
+        .context("Image has no WebAssembly component layer")
+}
+
+/// Digest of the file at `path`, as `sha256:<hex>`.
+pub(crate) async fn file_digest(path: &Path) -> Result<String> {
+    let bytes = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(format!("sha256:{}", hex::encode(Sha256::digest(bytes))))
+}
+
+/// Names of the files a [`Snapshot`] is saved as.
+const SNAPSHOT_COMPONENT: &str = "component.wasm";
+const SNAPSHOT_MANIFEST: &str = "manifest.json";
+const SNAPSHOT_SOURCE: &str = "source.json";
+const SNAPSHOT_POLICY: &str = "policy.yaml";
+const SNAPSHOT_POLICY_METADATA: &str = "policy.meta.json";
+
+/// The files of a component as they were before it was replaced.
+pub(crate) struct Snapshot {
+    wasm: Vec<u8>,
+    manifest: Option<Manifest>,
+    source: Option<UpdateSource>,
+    policy: Option<Vec<u8>>,
+    policy_metadata: Option<Vec<u8>>,
+}
+
+impl Snapshot {
+    pub(crate) async fn capture(storage: &ComponentStorage, component_id: &str) -> Result<Self> {
+        let wasm = tokio::fs::read(storage.component_path(component_id))
+            .await
+            .context("Failed to copy the loaded component")?;
+        Ok(Self {
+            wasm,
+            manifest: storage.read_manifest(component_id).await?,
+            source: storage.read_update_source(component_id).await?,
+            policy: read_if_exists(&storage.policy_path(component_id)).await?,
+            policy_metadata: read_if_exists(&storage.policy_metadata_path(component_id)).await?,
+        })
+    }
+
+    /// Digest of the artifact, as `sha256:<hex>`.
+    pub(crate) fn digest(&self) -> String {
+        format!("sha256:{}", hex::encode(Sha256::digest(&self.wasm)))
+    }
+
+    /// Write the files to `dir`, creating it.
+    pub(crate) async fn save(&self, dir: &Path) -> Result<()> {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let manifest = self
+            .manifest
+            .as_ref()
+            .map(serde_json::to_vec_pretty)
+            .transpose()?;
+        let source = self
+            .source
+            .as_ref()
+            .map(serde_json::to_vec_pretty)
+            .transpose()?;
+        for (name, contents) in [
+            (SNAPSHOT_COMPONENT, Some(&self.wasm)),
+            (SNAPSHOT_MANIFEST, manifest.as_ref()),
+            (SNAPSHOT_SOURCE, source.as_ref()),
+            (SNAPSHOT_POLICY, self.policy.as_ref()),
+            (SNAPSHOT_POLICY_METADATA, self.policy_metadata.as_ref()),
+        ] {
+            if let Some(contents) = contents {
+                let path = dir.join(name);
+                tokio::fs::write(&path, contents)
+                    .await
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the files [`Snapshot::save`] wrote to `dir`.
+    pub(crate) async fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(SNAPSHOT_COMPONENT);
+        let wasm = tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let manifest = read_if_exists(&dir.join(SNAPSHOT_MANIFEST))
+            .await?
+            .map(|bytes| serde_json::from_slice(&bytes))
+            .transpose()
+            .context("Invalid manifest of the kept version")?;
+        let source = read_if_exists(&dir.join(SNAPSHOT_SOURCE))
+            .await?
+            .map(|bytes| serde_json::from_slice(&bytes))
+            .transpose()
+            .context("Invalid update source of the kept version")?;
+        Ok(Self {
+            wasm,
+            manifest,
+            source,
+            policy: read_if_exists(&dir.join(SNAPSHOT_POLICY)).await?,
+            policy_metadata: read_if_exists(&dir.join(SNAPSHOT_POLICY_METADATA)).await?,
+        })
+    }
+
+    /// Put the files back; the component still has to be compiled again.
+    pub(crate) async fn restore(
+        self,
+        storage: &ComponentStorage,
+        component_id: &str,
+    ) -> Result<()> {
+        storage.remove_component_artifacts(component_id).await?;
+        tokio::fs::write(storage.component_path(component_id), &self.wasm)
+            .await
+            .context("Failed to restore the component")?;
+        storage
+            .write_manifest(component_id, self.manifest.as_ref())
+            .await?;
+        storage
+            .write_update_source(component_id, self.source.as_ref())
+            .await?;
+        self.restore_policy(storage, component_id).await
+    }
+
+    /// Put the policy back, along with the permissions granted since it was attached.
+    pub(crate) async fn restore_policy(
+        &self,
+        storage: &ComponentStorage,
+        component_id: &str,
+    ) -> Result<()> {
+        for (path, contents, description) in [
+            (
+                storage.policy_path(component_id),
+                &self.policy,
+                "policy file",
+            ),
+            (
+                storage.policy_metadata_path(component_id),
+                &self.policy_metadata,
+                "policy metadata file",
+            ),
+        ] {
+            match contents {
+                Some(contents) => tokio::fs::write(&path, contents)
+                    .await
+                    .with_context(|| format!("Failed to restore {}", path.display()))?,
+                None => {
+                    storage
+                        .remove_if_exists(&path, description, component_id)
+                        .await?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>> {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn daily_window(start: &str, minutes: u32, timezone: &str) -> MaintenanceWindow {
+        UpdateConfig {
+            window: start.to_string(),
+            window_minutes: minutes,
+            timezone: timezone.to_string(),
+            ..Default::default()
+        }
+        .maintenance_window()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_next_maintenance_window() {
+        // 03:00 to 04:00 at UTC+2 is 01:00 to 02:00 UTC.
+        let window = daily_window("03:00", 60, "+02:00");
+        let opens = utc("2026-10-16T01:00:00Z");
+        let closes = utc("2026-10-16T02:00:00Z");
+        assert_eq!(window.next(utc("2026-10-16T00:30:00Z")), (opens, closes));
+        let now = utc("2026-10-16T01:30:00Z");
+        assert_eq!(window.next(now), (now, closes));
+        assert_eq!(
+            window.next(closes),
+            (utc("2026-10-17T01:00:00Z"), utc("2026-10-17T02:00:00Z"))
+        );
+
+        // A window open across midnight
+        let window = daily_window("23:30", 120, "utc");
+        let now = utc("2026-10-17T00:15:00Z");
+        assert_eq!(window.next(now), (now, utc("2026-10-17T01:30:00Z")));
+    }
+
+    #[test]
+    fn test_update_config_is_validated() {
+        assert!(UpdateConfig::default().validate().is_ok());
+        for config in [
+            UpdateConfig {
+                window: "3am".to_string(),
+                ..Default::default()
+            },
+            UpdateConfig {
+                window_minutes: 0,
+                ..Default::default()
+            },
+            UpdateConfig {
+                timezone: "Mars/Olympus".to_string(),
+                ..Default::default()
+            },
+            UpdateConfig {
+                health_checks: HashMap::from([("fetch".to_string(), HealthCheck::default())]),
+                ..Default::default()
+            },
+        ] {
+            assert!(config.validate().is_err(), "{config:?} is valid");
+        }
+    }
+
+    #[test]
+    fn test_update_sources() {
+        assert_eq!(
+            UpdateSource::for_uri("oci://ghcr.io/acme/fetch:1"),
+            Some(UpdateSource::Oci {
+                reference: "oci://ghcr.io/acme/fetch:1".to_string()
+            })
+        );
+        let digest = "sha256:".to_string() + &"a".repeat(64);
+        assert_eq!(
+            UpdateSource::for_uri(&format!("oci://ghcr.io/acme/fetch@{digest}")),
+            None
+        );
+        assert_eq!(UpdateSource::for_uri("file:///tmp/fetch.wasm"), None);
+        assert_eq!(
+            UpdateSource::for_uri("https://acme.example/fetch.wasm"),
+            Some(UpdateSource::Url {
+                url: "https://acme.example/fetch.wasm".to_string()
+            })
+        );
+        assert_eq!(
+            UpdateSource::for_manifest("https://acme.example/fetch.json"),
+            Some(UpdateSource::Manifest {
+                location: "https://acme.example/fetch.json".to_string()
+            })
+        );
+        assert_eq!(UpdateSource::for_manifest("/tmp/fetch.json"), None);
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Argument validation and JSON conversion off the async runtime.
+//!
+//! Every tool call parses its arguments, checks them against the tool's parameter types while
+//! converting them to component values, and converts the results back to JSON. That is CPU
+//! work proportional to the size of the payloads, and by default it runs inline on the
+//! runtime thread handling the call, which is cheapest for the small arguments of most tools.
+//!
+//! Servers handling hundreds of calls per second can set `[runtime] validation_threads` to run
+//! it on a dedicated rayon pool instead, so large payloads don't hold up the runtime threads
+//! that drive every other call's I/O. Each conversion then has a budget, counted from when it
+//! is queued: a job still waiting when its budget runs out is dropped without running, and a
+//! call whose conversion doesn't finish in time fails, so a backlog sheds calls instead of
+//! growing without bound.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use tokio::sync::oneshot;
+use tracing::warn;
+
+/// Time a conversion on the pool may take, queueing included, unless configured otherwise.
+pub const DEFAULT_VALIDATION_BUDGET: Duration = Duration::from_millis(100);
+
+/// Where tool call arguments and results are validated and converted.
+pub struct ValidationPool {
+    /// Dedicated worker threads, or `None` to convert inline
+    pool: Option<rayon::ThreadPool>,
+    budget: Duration,
+}
+
+impl ValidationPool {
+    /// Convert on the calling thread, without a budget.
+    pub fn inline() -> Self {
+        Self {
+            pool: None,
+            budget: DEFAULT_VALIDATION_BUDGET,
+        }
+    }
+
+    /// Convert on `threads` dedicated threads, failing conversions that take longer than
+    /// `budget` including the time spent queued.
+    pub fn new(threads: usize, budget: Duration) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|index| format!("wassette-validation-{index}"))
+            .panic_handler(|_| warn!("Argument conversion panicked"))
+            .build()
+            .context("Failed to start the validation threads")?;
+        Ok(Self {
+            pool: Some(pool),
+            budget,
+        })
+    }
+
+    /// Run the conversion `job`, on the pool if there is one.
+    pub async fn run<T, F>(&self, job: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let Some(pool) = &self.pool else {
+            return job();
+        };
+        let budget = self.budget;
+        let queued = Instant::now();
+        let (tx, rx) = oneshot::channel();
+        pool.spawn(move || {
+            // Nobody waits for the result of a job that sat out its budget in the queue.
+            if tx.is_closed() || queued.elapsed() >= budget {
+                return;
+            }
+            let _ = tx.send(job());
+        });
+        match tokio::time::timeout(budget, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) if queued.elapsed() < budget => bail!("Argument conversion failed"),
+            Ok(Err(_)) | Err(_) => {
+                bail!("Argument conversion did not finish within its budget of {budget:?}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_inline_and_pooled_conversion() {
+        let inline = ValidationPool::inline();
+        assert_eq!(inline.run(|| Ok(2 + 2)).await.unwrap(), 4);
+
+        let pooled = ValidationPool::new(2, DEFAULT_VALIDATION_BUDGET).unwrap();
+        let thread = pooled
+            .run(|| Ok(std::thread::current().name().map(str::to_string)))
+            .await
+            .unwrap();
+        assert!(thread.unwrap().starts_with("wassette-validation-"));
+        let err = pooled.run(|| -> Result<()> { bail!("bad") }).await;
+        assert_eq!(err.unwrap_err().to_string(), "bad");
+    }
+
+    #[tokio::test]
+    async fn test_budget_drops_queued_conversions() {
+        let pool = ValidationPool::new(1, Duration::from_millis(50)).unwrap();
+        let (slow, queued) = tokio::join!(
+            pool.run(|| {
+                std::thread::sleep(Duration::from_millis(200));
+                Ok(())
+            }),
+            pool.run(|| -> Result<()> { panic!("ran after its budget ran out") }),
+        );
+        assert!(slow.unwrap_err().to_string().contains("budget"));
+        assert!(queued.unwrap_err().to_string().contains("budget"));
+    }
+}
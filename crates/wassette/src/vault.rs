@@ -0,0 +1,600 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Secrets kept in HashiCorp Vault, shared by a fleet of servers.
+//!
+//! [`VaultSecretsBackend`] keeps the secrets of each component in a secret of a KV version 2
+//! engine, at `<path>/components/<id>` under `mount`, and the master state key at
+//! `<path>/state-key`. Every server pointed at the same Vault sees the same secrets, so no
+//! secret files need to be synced to each host.
+//!
+//! ```toml
+//! [secrets]
+//! backend = "vault"
+//! address = "https://vault.example.com:8200"
+//! mount = "secret"
+//! path = "wassette"
+//! auth = { method = "approle", role-id = "wassette", secret-id-file = "/etc/wassette/secret-id" }
+//! ```
+//!
+//! Unlike files and the keyring, secrets are read when a component is instantiated rather
+//! than when its policy is attached, so a secret rotated in Vault reaches the next instance
+//! without reattaching the policy. They are cached for `cache-ttl-secs`, and when Vault can't
+//! be read an expired copy is used rather than failing the call.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use reqwest::{Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::secrets::{generate_state_key, parse_state_key, sanitize_component_id};
+use crate::{SecretsBackend, DEFAULT_HTTP_TIMEOUT_SECS};
+
+/// Service account token mounted into Kubernetes pods
+const KUBERNETES_JWT_FILE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+/// Secret under the path holding the master state key
+const STATE_KEY_SECRET: &str = "state-key";
+
+/// Share of a login's lease after which the backend logs in again
+const RELOGIN_AFTER: f64 = 0.8;
+
+/// Settings of the Vault backend, under `[secrets]` with `backend = "vault"`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct VaultConfig {
+    /// Address of the Vault server, such as `https://vault.example.com:8200`
+    pub address: String,
+    /// Mount of the KV version 2 secrets engine
+    #[serde(default = "default_mount")]
+    pub mount: String,
+    /// Path under the mount holding the secrets of the server
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// Vault Enterprise namespace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// PEM file of a CA certificate to trust for the Vault server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<PathBuf>,
+    /// How long secrets read from Vault are used before being read again, in seconds
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+    /// How the server logs in to Vault
+    #[serde(default)]
+    pub auth: VaultAuth,
+}
+
+fn default_mount() -> String {
+    "secret".to_string()
+}
+
+fn default_path() -> String {
+    "wassette".to_string()
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    300
+}
+
+impl VaultConfig {
+    /// Check that the address is an HTTP URL and the paths aren't empty.
+    pub fn validate(&self) -> Result<()> {
+        let address = Url::parse(&self.address)
+            .with_context(|| format!("Invalid Vault address: {}", self.address))?;
+        if !matches!(address.scheme(), "http" | "https") {
+            bail!(
+                "The Vault address must be an http or https URL: {}",
+                self.address
+            );
+        }
+        if self.mount.trim_matches('/').is_empty() {
+            bail!("The Vault mount can't be empty");
+        }
+        if self.path.trim_matches('/').is_empty() {
+            bail!("The Vault path can't be empty");
+        }
+        match &self.auth {
+            VaultAuth::Approle { role_id, .. } if role_id.is_empty() => {
+                bail!("AppRole login needs a role-id")
+            }
+            VaultAuth::Kubernetes { role, .. } if role.is_empty() => {
+                bail!("Kubernetes login needs a role")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How a server logs in to Vault, chosen with `method`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "method",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case",
+    deny_unknown_fields
+)]
+pub enum VaultAuth {
+    /// A token read from `token-file`, or else from the `VAULT_TOKEN` environment variable
+    Token {
+        /// File holding the token
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token_file: Option<PathBuf>,
+    },
+    /// The AppRole auth method
+    Approle {
+        /// Role ID of the server
+        role_id: String,
+        /// File holding the secret ID, or else the `VAULT_SECRET_ID` environment variable
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secret_id_file: Option<PathBuf>,
+        /// Mount of the auth method
+        #[serde(default = "default_approle_mount")]
+        mount: String,
+    },
+    /// The Kubernetes auth method, with the service account token of the pod
+    Kubernetes {
+        /// Vault role bound to the service account
+        role: String,
+        /// File holding the service account token
+        #[serde(default = "default_jwt_file")]
+        jwt_file: PathBuf,
+        /// Mount of the auth method
+        #[serde(default = "default_kubernetes_mount")]
+        mount: String,
+    },
+}
+
+impl Default for VaultAuth {
+    fn default() -> Self {
+        Self::Token { token_file: None }
+    }
+}
+
+fn default_approle_mount() -> String {
+    "approle".to_string()
+}
+
+fn default_kubernetes_mount() -> String {
+    "kubernetes".to_string()
+}
+
+fn default_jwt_file() -> PathBuf {
+    PathBuf::from(KUBERNETES_JWT_FILE)
+}
+
+/// A Vault token, and when to log in again for a new one.
+struct Token {
+    value: String,
+    renew_at: Option<Instant>,
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Token")
+            .field("value", &"<redacted>")
+            .field("renew_at", &self.renew_at)
+            .finish()
+    }
+}
+
+/// Secrets of a component as last read from Vault.
+#[derive(Debug, Clone)]
+struct CachedSecrets {
+    secrets: HashMap<String, String>,
+    read_at: Instant,
+}
+
+/// Secrets kept in the KV version 2 engine of a HashiCorp Vault.
+#[derive(Debug)]
+pub struct VaultSecretsBackend {
+    config: VaultConfig,
+    http_client: reqwest::Client,
+    token: tokio::sync::Mutex<Option<Token>>,
+    cache: Mutex<HashMap<String, CachedSecrets>>,
+}
+
+impl VaultSecretsBackend {
+    /// Create a backend for the Vault of `config`, reached with `http_client` unless the
+    /// configuration names a CA certificate of its own.
+    pub fn new(config: VaultConfig, http_client: reqwest::Client) -> Result<Self> {
+        let http_client = match &config.ca_cert {
+            Some(path) => {
+                let pem = std::fs::read(path).with_context(|| {
+                    format!("Failed to read Vault CA certificate: {}", path.display())
+                })?;
+                let certificate = reqwest::Certificate::from_pem(&pem)
+                    .with_context(|| format!("Invalid Vault CA certificate: {}", path.display()))?;
+                reqwest::Client::builder()
+                    .timeout(Duration::from_secs(DEFAULT_HTTP_TIMEOUT_SECS))
+                    .add_root_certificate(certificate)
+                    .build()
+                    .context("Failed to create the Vault HTTP client")?
+            }
+            None => http_client,
+        };
+        Ok(Self {
+            config,
+            http_client,
+            token: tokio::sync::Mutex::new(None),
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Path of a secret of the server, under `kind` (`data` or `metadata`) of the engine
+    fn kv_path(&self, kind: &str, secret: &str) -> String {
+        format!(
+            "{}/{kind}/{}/{secret}",
+            self.config.mount.trim_matches('/'),
+            self.config.path.trim_matches('/')
+        )
+    }
+
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/v1/{path}", self.config.address.trim_end_matches('/'));
+        let request = self.http_client.request(method, url);
+        match &self.config.namespace {
+            Some(namespace) => request.header("X-Vault-Namespace", namespace),
+            None => request,
+        }
+    }
+
+    /// The token of the server, logging in if there is none yet or its lease is running out
+    async fn token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token
+            .as_ref()
+            .filter(|token| token.renew_at.is_none_or(|at| Instant::now() < at))
+        {
+            return Ok(token.value.clone());
+        }
+        let fresh = self.login().await?;
+        let value = fresh.value.clone();
+        *token = Some(fresh);
+        Ok(value)
+    }
+
+    async fn login(&self) -> Result<Token> {
+        let (mount, body) = match &self.config.auth {
+            VaultAuth::Token { token_file } => {
+                let value = match token_file {
+                    Some(path) => read_credential(path).await?,
+                    None => std::env::var("VAULT_TOKEN")
+                        .context("Set VAULT_TOKEN or `token-file` to log in to Vault")?,
+                };
+                return Ok(Token {
+                    value,
+                    renew_at: None,
+                });
+            }
+            VaultAuth::Approle {
+                role_id,
+                secret_id_file,
+                mount,
+            } => {
+                let secret_id = match secret_id_file {
+                    Some(path) => Some(read_credential(path).await?),
+                    None => std::env::var("VAULT_SECRET_ID").ok(),
+                };
+                let mut body = json!({ "role_id": role_id });
+                if let Some(secret_id) = secret_id {
+                    body["secret_id"] = secret_id.into();
+                }
+                (mount, body)
+            }
+            VaultAuth::Kubernetes {
+                role,
+                jwt_file,
+                mount,
+            } => (
+                mount,
+                json!({ "role": role, "jwt": read_credential(jwt_file).await? }),
+            ),
+        };
+
+        let path = format!("auth/{}/login", mount.trim_matches('/'));
+        let response = self
+            .request(Method::POST, &path)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body)?)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach Vault at {}", self.config.address))?;
+        let status = response.status();
+        let bytes = response.bytes().await?;
+        if !status.is_success() {
+            bail!("Vault login failed with {status}: {}", vault_errors(&bytes));
+        }
+        let response: Value =
+            serde_json::from_slice(&bytes).context("Failed to parse the Vault login response")?;
+        let value = response["auth"]["client_token"]
+            .as_str()
+            .context("The Vault login response has no client token")?
+            .to_string();
+        let lease = response["auth"]["lease_duration"].as_u64().unwrap_or(0);
+        info!(mount = %mount, lease_secs = lease, "Logged in to Vault");
+        Ok(Token {
+            value,
+            renew_at: (lease > 0)
+                .then(|| Instant::now() + Duration::from_secs(lease).mul_f64(RELOGIN_AFTER)),
+        })
+    }
+
+    /// Send a request to `path` of the Vault API with the server's token, returning the JSON
+    /// answer, or `None` if nothing is at `path`.
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Option<Value>> {
+        let mut relogged = false;
+        loop {
+            let mut request = self
+                .request(method.clone(), path)
+                .header("X-Vault-Token", self.token().await?);
+            if let Some(body) = body {
+                request = request
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(serde_json::to_vec(body)?);
+            }
+            let response = request
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach Vault at {}", self.config.address))?;
+            let status = response.status();
+            let logs_in = !matches!(self.config.auth, VaultAuth::Token { .. });
+            if status == StatusCode::FORBIDDEN && logs_in && !relogged {
+                // The token may have been revoked before its lease ran out.
+                debug!("Vault refused the token, logging in again");
+                *self.token.lock().await = None;
+                relogged = true;
+                continue;
+            }
+            if status == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let bytes = response.bytes().await?;
+            if !status.is_success() {
+                bail!(
+                    "Vault answered {status} for {path}: {}",
+                    vault_errors(&bytes)
+                );
+            }
+            if bytes.is_empty() {
+                return Ok(Some(Value::Null));
+            }
+            let answer = serde_json::from_slice(&bytes)
+                .with_context(|| format!("Failed to parse the Vault answer for {path}"))?;
+            return Ok(Some(answer));
+        }
+    }
+
+    /// Load the secrets of a component, from the cache while they are fresh
+    async fn load_component_secrets(&self, component_id: &str) -> Result<HashMap<String, String>> {
+        let ttl = Duration::from_secs(self.config.cache_ttl_secs);
+        let cached = self.cache.lock().unwrap().get(component_id).cloned();
+        if let Some(cached) = &cached {
+            if cached.read_at.elapsed() < ttl {
+                debug!("Using cached Vault secrets for component: {}", component_id);
+                return Ok(cached.secrets.clone());
+            }
+        }
+
+        match self.read_component_secrets(component_id).await {
+            Ok(secrets) => {
+                self.cache_secrets(component_id, secrets.clone());
+                Ok(secrets)
+            }
+            Err(e) => match cached {
+                Some(cached) => {
+                    warn!(
+                        component_id,
+                        "Using expired secrets, Vault could not be read: {e:#}"
+                    );
+                    Ok(cached.secrets)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    async fn read_component_secrets(&self, component_id: &str) -> Result<HashMap<String, String>> {
+        let path = self.kv_path("data", &component_secret(component_id));
+        let answer = self
+            .send(Method::GET, &path, None)
+            .await
+            .with_context(|| format!("Failed to read the secrets of {component_id} from Vault"))?;
+        // A secret whose latest version was deleted has no data.
+        let Some(data) = answer
+            .as_ref()
+            .and_then(|answer| answer["data"]["data"].as_object())
+        else {
+            debug!("No Vault secrets found for component: {}", component_id);
+            return Ok(HashMap::new());
+        };
+        // Values written through the Vault UI or CLI may be numbers or booleans.
+        Ok(data
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                (key.clone(), value)
+            })
+            .collect())
+    }
+
+    async fn store_component_secrets(
+        &self,
+        component_id: &str,
+        secrets: HashMap<String, String>,
+    ) -> Result<()> {
+        let secret = component_secret(component_id);
+        let written = if secrets.is_empty() {
+            self.send(Method::DELETE, &self.kv_path("metadata", &secret), None)
+                .await
+        } else {
+            let body = json!({ "data": secrets });
+            self.send(Method::POST, &self.kv_path("data", &secret), Some(&body))
+                .await
+        };
+        written
+            .with_context(|| format!("Failed to write the secrets of {component_id} to Vault"))?;
+        self.cache_secrets(component_id, secrets);
+        Ok(())
+    }
+
+    fn cache_secrets(&self, component_id: &str, secrets: HashMap<String, String>) {
+        self.cache.lock().unwrap().insert(
+            component_id.to_string(),
+            CachedSecrets {
+                secrets,
+                read_at: Instant::now(),
+            },
+        );
+    }
+
+    async fn read_state_key(&self, path: &str) -> Result<Option<[u8; 32]>> {
+        let answer = self.send(Method::GET, path, None).await?;
+        let Some(key) = answer
+            .as_ref()
+            .and_then(|answer| answer["data"]["data"]["key"].as_str())
+        else {
+            return Ok(None);
+        };
+        let key =
+            hex::decode(key).with_context(|| format!("State key is not hex: Vault {path}"))?;
+        parse_state_key(&key, || format!("Vault {path}")).map(Some)
+    }
+
+    async fn load_or_create_state_key(&self) -> Result<[u8; 32]> {
+        let path = self.kv_path("data", STATE_KEY_SECRET);
+        if let Some(key) = self.read_state_key(&path).await? {
+            return Ok(key);
+        }
+
+        // Check-and-set 0 writes the key only if there is none yet, so servers started at
+        // the same instant agree on one key.
+        let key = generate_state_key()?;
+        let body = json!({ "options": { "cas": 0 }, "data": { "key": hex::encode(key) } });
+        match self.send(Method::POST, &path, Some(&body)).await {
+            Ok(_) => {
+                info!("Created state key in Vault");
+                Ok(key)
+            }
+            Err(e) => match self.read_state_key(&path).await? {
+                Some(key) => Ok(key),
+                None => Err(e.context("Failed to create the state key in Vault")),
+            },
+        }
+    }
+}
+
+impl SecretsBackend for VaultSecretsBackend {
+    fn load<'a>(&'a self, component_id: &'a str) -> BoxFuture<'a, Result<HashMap<String, String>>> {
+        self.load_component_secrets(component_id).boxed()
+    }
+
+    fn store<'a>(
+        &'a self,
+        component_id: &'a str,
+        secrets: HashMap<String, String>,
+    ) -> BoxFuture<'a, Result<()>> {
+        self.store_component_secrets(component_id, secrets).boxed()
+    }
+
+    fn state_key(&self) -> BoxFuture<'_, Result<[u8; 32]>> {
+        self.load_or_create_state_key().boxed()
+    }
+
+    fn resolved_at_instantiation(&self) -> bool {
+        true
+    }
+
+    fn external_store(&self) -> Option<&'static str> {
+        Some("HashiCorp Vault")
+    }
+}
+
+/// Secret under the path holding the secrets of `component_id`
+fn component_secret(component_id: &str) -> String {
+    format!("components/{}", sanitize_component_id(component_id))
+}
+
+/// Read a token or secret ID from `path`, without surrounding whitespace
+async fn read_credential(path: &Path) -> Result<String> {
+    let credential = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read Vault credential: {}", path.display()))?;
+    Ok(credential.trim().to_string())
+}
+
+/// The `errors` of a Vault error answer, or the answer itself
+fn vault_errors(body: &[u8]) -> String {
+    serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|body| {
+            let errors = body["errors"].as_array()?;
+            Some(
+                errors
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            )
+        })
+        .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_expired_secrets_are_used_while_vault_is_unreachable() -> Result<()> {
+        let config: VaultConfig = serde_json::from_value(json!({
+            "address": "http://127.0.0.1:1",
+            "cache-ttl-secs": 0,
+            "auth": { "method": "token", "token-file": "token" },
+        }))?;
+        assert_eq!(config.mount, "secret");
+        assert_eq!(config.path, "wassette");
+        config.validate()?;
+
+        let temp_dir = TempDir::new()?;
+        let token_file = temp_dir.path().join("token");
+        tokio::fs::write(&token_file, "s.token\n").await?;
+        let config = VaultConfig {
+            auth: VaultAuth::Token {
+                token_file: Some(token_file),
+            },
+            ..config
+        };
+        let backend = VaultSecretsBackend::new(config, reqwest::Client::new())?;
+        assert_eq!(
+            backend.kv_path("data", &component_secret("fetch")),
+            "secret/data/wassette/components/fetch"
+        );
+
+        // Nothing was read yet, so the error surfaces.
+        assert!(backend.load("fetch").await.is_err());
+
+        let secrets = HashMap::from([("API_KEY".to_string(), "secret".to_string())]);
+        backend.cache_secrets("fetch", secrets.clone());
+        assert_eq!(backend.load("fetch").await?, secrets);
+        Ok(())
+    }
+}
@@ -0,0 +1,650 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Per-component embedding collections exposed through the `wassette:vector/store` host
+//! interface.
+//!
+//! Components import
+//!
+//! ```wit
+//! package wassette:vector;
+//!
+//! interface store {
+//!     record entry {
+//!         id: string,
+//!         vector: list<f32>,
+//!         /// Opaque to the host, typically JSON describing the embedded text
+//!         metadata: string,
+//!     }
+//!
+//!     record match {
+//!         id: string,
+//!         /// Cosine similarity to the query, from -1 to 1
+//!         score: f32,
+//!         metadata: string,
+//!     }
+//!
+//!     /// Insert `entries` into `namespace`, replacing entries with the same ids.
+//!     upsert: func(namespace: string, entries: list<entry>) -> result<_, string>;
+//!     /// The `limit` entries of `namespace` most similar to `vector`, best first.
+//!     query: func(namespace: string, vector: list<f32>, limit: u32) -> result<list<match>, string>;
+//!     /// Remove the entries `ids` from `namespace`, returning how many existed.
+//!     delete: func(namespace: string, ids: list<string>) -> result<u32, string>;
+//! }
+//! ```
+//!
+//! so a retrieval tool can index documents and look up the passages closest to a question
+//! without embedding a database engine of its own. Each component has its own namespaces,
+//! stored in one file next to the component so they survive restarts and reloads. The first
+//! upsert into a namespace fixes the number of dimensions of its vectors.
+//!
+//! Access is denied unless the component's policy has a `vector` section, which also caps the
+//! number of entries, the total size of ids, vectors and metadata, and the dimensions of a
+//! vector. Queries compare the query with every entry of the namespace, which is fast enough
+//! for the collections these quotas allow.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use policy::PolicyDocument;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use wasmtime::component::{ComponentType, Lift, Linker, Lower};
+
+use crate::component_storage::ComponentStorage;
+use crate::{WasiState, WassetteWasiState};
+
+/// Name of the host interface that provides vector collections.
+pub const VECTOR_INTERFACE: &str = "wassette:vector/store";
+
+/// Size cap applied when the policy allows vectors without setting `max-bytes`.
+pub const DEFAULT_MAX_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Entry cap applied when the policy allows vectors without setting `max-entries`.
+pub const DEFAULT_MAX_ENTRIES: u32 = 10_000;
+
+/// Dimension cap applied when the policy allows vectors without setting `max-dimensions`.
+pub const DEFAULT_MAX_DIMENSIONS: u32 = 4096;
+
+/// Most matches a single query returns.
+pub const MAX_QUERY_RESULTS: u32 = 100;
+
+/// Per-component quotas derived from the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VectorQuota {
+    /// Upper bound on the total size of ids, vectors and metadata across all namespaces
+    pub max_bytes: u64,
+    /// Maximum number of entries across all namespaces
+    pub max_entries: u32,
+    /// Maximum number of dimensions of a vector
+    pub max_dimensions: u32,
+}
+
+impl VectorQuota {
+    /// Quota granted by `policy`, or `None` if the policy doesn't allow vectors.
+    pub fn from_policy(policy: &PolicyDocument) -> Result<Option<Self>> {
+        let Some(vector) = &policy.permissions.vector else {
+            return Ok(None);
+        };
+        let max_bytes = match &vector.max_bytes {
+            Some(limit) => limit.to_bytes()?,
+            None => DEFAULT_MAX_BYTES,
+        };
+        Ok(Some(Self {
+            max_bytes,
+            max_entries: vector.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES),
+            max_dimensions: vector.max_dimensions.unwrap_or(DEFAULT_MAX_DIMENSIONS),
+        }))
+    }
+}
+
+/// `wassette:vector/store.entry`
+#[derive(ComponentType, Lift, Debug, Clone, PartialEq)]
+#[component(record)]
+pub struct VectorEntry {
+    /// Identifier, unique within the namespace
+    pub id: String,
+    /// The embedding
+    pub vector: Vec<f32>,
+    /// Data returned with matches
+    pub metadata: String,
+}
+
+/// `wassette:vector/store.match`
+#[derive(ComponentType, Lower, Debug, Clone, PartialEq)]
+#[component(record)]
+pub struct VectorMatch {
+    /// Identifier of the matching entry
+    pub id: String,
+    /// Cosine similarity to the query
+    pub score: f32,
+    /// Metadata of the matching entry
+    pub metadata: String,
+}
+
+/// A stored vector, written as base64 of its little-endian `f32`s.
+#[derive(Debug, Clone, PartialEq)]
+struct StoredVector(Vec<f32>);
+
+impl Serialize for StoredVector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes: Vec<u8> = self.0.iter().flat_map(|x| x.to_le_bytes()).collect();
+        serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(bytes))
+    }
+}
+
+impl<'de> Deserialize<'de> for StoredVector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(serde::de::Error::custom)?;
+        if bytes.len() % 4 != 0 {
+            return Err(serde::de::Error::custom(
+                "vector length is not a multiple of 4",
+            ));
+        }
+        Ok(StoredVector(
+            bytes
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+                .collect(),
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoredEntry {
+    vector: StoredVector,
+    metadata: String,
+}
+
+impl StoredEntry {
+    fn bytes(&self, id: &str) -> u64 {
+        (id.len() + self.vector.0.len() * 4 + self.metadata.len()) as u64
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Namespace {
+    /// Dimensions of every vector in the namespace
+    dimensions: u32,
+    /// Entries by id
+    entries: BTreeMap<String, StoredEntry>,
+}
+
+/// The vector collections of a component, as stored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct VectorIndex {
+    /// Namespaces by name
+    namespaces: BTreeMap<String, Namespace>,
+}
+
+impl VectorIndex {
+    /// Number of entries across all namespaces.
+    fn entries(&self) -> usize {
+        self.namespaces.values().map(|ns| ns.entries.len()).sum()
+    }
+
+    /// Total size of ids, vectors and metadata across all namespaces.
+    fn bytes(&self) -> u64 {
+        self.namespaces
+            .values()
+            .flat_map(|ns| ns.entries.iter())
+            .map(|(id, entry)| entry.bytes(id))
+            .sum()
+    }
+
+    /// Check the index against `quota`.
+    fn check(&self, quota: &VectorQuota) -> Result<()> {
+        if self.entries() > quota.max_entries as usize {
+            bail!(
+                "vector quota of {} entries exceeded ({} entries)",
+                quota.max_entries,
+                self.entries()
+            );
+        }
+        if self.bytes() > quota.max_bytes {
+            bail!(
+                "vector quota of {} bytes exceeded ({} bytes)",
+                quota.max_bytes,
+                self.bytes()
+            );
+        }
+        Ok(())
+    }
+
+    /// Insert `entries` into `namespace`, all of them or, if one is invalid or they don't fit
+    /// in `quota`, none.
+    fn upsert(
+        &mut self,
+        namespace: &str,
+        entries: Vec<VectorEntry>,
+        quota: &VectorQuota,
+    ) -> Result<()> {
+        let Some(first) = entries.first() else {
+            return Ok(());
+        };
+        let dimensions = match self.namespaces.get(namespace) {
+            Some(ns) => ns.dimensions,
+            None => first.vector.len() as u32,
+        };
+        if dimensions == 0 {
+            bail!("vectors must have at least one dimension");
+        }
+        if dimensions > quota.max_dimensions {
+            bail!(
+                "vectors of {dimensions} dimensions exceed the limit of {}",
+                quota.max_dimensions
+            );
+        }
+        for entry in &entries {
+            if entry.vector.len() != dimensions as usize {
+                bail!(
+                    "vector `{}` has {} dimensions, namespace `{namespace}` holds vectors of {dimensions}",
+                    entry.id,
+                    entry.vector.len()
+                );
+            }
+            if !entry.vector.iter().all(|x| x.is_finite()) {
+                bail!("vector `{}` has a value that isn't finite", entry.id);
+            }
+        }
+
+        let ns = self
+            .namespaces
+            .entry(namespace.to_string())
+            .or_insert_with(|| Namespace {
+                dimensions,
+                entries: BTreeMap::new(),
+            });
+        let mut replaced = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let stored = StoredEntry {
+                vector: StoredVector(entry.vector),
+                metadata: entry.metadata,
+            };
+            let previous = ns.entries.insert(entry.id.clone(), stored);
+            replaced.push((entry.id, previous));
+        }
+        if let Err(error) = self.check(quota) {
+            let ns = self.namespaces.get_mut(namespace).unwrap();
+            for (id, previous) in replaced.into_iter().rev() {
+                match previous {
+                    Some(previous) => ns.entries.insert(id, previous),
+                    None => ns.entries.remove(&id),
+                };
+            }
+            if ns.entries.is_empty() {
+                self.namespaces.remove(namespace);
+            }
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// The `limit` entries of `namespace` closest to `vector`, best first.
+    fn query(&self, namespace: &str, vector: &[f32], limit: u32) -> Result<Vec<VectorMatch>> {
+        let Some(ns) = self.namespaces.get(namespace) else {
+            return Ok(Vec::new());
+        };
+        if vector.len() != ns.dimensions as usize {
+            bail!(
+                "query has {} dimensions, namespace `{namespace}` holds vectors of {}",
+                vector.len(),
+                ns.dimensions
+            );
+        }
+        let mut matches: Vec<VectorMatch> = ns
+            .entries
+            .iter()
+            .map(|(id, entry)| VectorMatch {
+                id: id.clone(),
+                score: cosine_similarity(vector, &entry.vector.0),
+                metadata: entry.metadata.clone(),
+            })
+            .collect();
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(limit.min(MAX_QUERY_RESULTS) as usize);
+        Ok(matches)
+    }
+
+    /// Remove the entries `ids` from `namespace`, returning how many existed.
+    fn delete(&mut self, namespace: &str, ids: &[String]) -> u32 {
+        let Some(ns) = self.namespaces.get_mut(namespace) else {
+            return 0;
+        };
+        let removed = ids
+            .iter()
+            .filter(|id| ns.entries.remove(id.as_str()).is_some())
+            .count();
+        if ns.entries.is_empty() {
+            self.namespaces.remove(namespace);
+        }
+        removed as u32
+    }
+}
+
+/// Cosine similarity of two vectors of the same length, 0 if either is all zeros.
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let (mut dot, mut norm_a, mut norm_b) = (0f32, 0f32, 0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+/// The vector files of all components in the plugin directory, kept in memory once read.
+pub(crate) struct VectorStore {
+    storage: ComponentStorage,
+    /// Loaded indexes by component; the lock also serializes changes per component
+    indexes: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<VectorIndex>>>>>,
+}
+
+impl VectorStore {
+    pub(crate) fn new(storage: ComponentStorage) -> Self {
+        Self {
+            storage,
+            indexes: Mutex::default(),
+        }
+    }
+
+    fn slot(&self, component_id: &str) -> Arc<tokio::sync::Mutex<Option<VectorIndex>>> {
+        let mut indexes = self.indexes.lock().unwrap();
+        Arc::clone(indexes.entry(component_id.to_string()).or_default())
+    }
+
+    async fn load(&self, component_id: &str) -> Result<VectorIndex> {
+        let Some(contents) = self.storage.read_vectors(component_id).await? else {
+            return Ok(VectorIndex::default());
+        };
+        serde_json::from_slice(&contents)
+            .with_context(|| format!("Failed to parse the vectors of {component_id}"))
+    }
+
+    /// Run `read` on the index of `component_id`.
+    async fn read<R>(&self, component_id: &str, read: impl FnOnce(&VectorIndex) -> R) -> Result<R> {
+        let slot = self.slot(component_id);
+        let mut index = slot.lock().await;
+        if index.is_none() {
+            *index = Some(self.load(component_id).await?);
+        }
+        Ok(read(index.as_ref().unwrap()))
+    }
+
+    /// Apply `change` to the index of `component_id` and store the result. A change that fails
+    /// must leave the index as it was.
+    async fn update<R>(
+        &self,
+        component_id: &str,
+        change: impl FnOnce(&mut VectorIndex) -> Result<R>,
+    ) -> Result<R> {
+        let slot = self.slot(component_id);
+        let mut guard = slot.lock().await;
+        let index = match &mut *guard {
+            Some(index) => index,
+            empty => empty.insert(self.load(component_id).await?),
+        };
+        let result = change(index)?;
+
+        let contents = if index.namespaces.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_vec(&*index).context("Failed to serialize vectors")?)
+        };
+        if let Err(error) = self
+            .storage
+            .write_vectors(component_id, contents.as_deref())
+            .await
+        {
+            // Read the file again next time rather than serve what wasn't stored.
+            *guard = None;
+            return Err(error);
+        }
+        Ok(result)
+    }
+}
+
+/// Vector collections of a single store.
+#[derive(Default, Clone)]
+pub struct VectorState {
+    /// Quota from the policy; `None` denies access
+    pub quota: Option<VectorQuota>,
+    /// Where the component's collections are kept
+    pub(crate) store: Option<(Arc<VectorStore>, String)>,
+}
+
+impl VectorState {
+    fn admit(&self) -> std::result::Result<(Arc<VectorStore>, String, VectorQuota), String> {
+        match (&self.store, self.quota) {
+            (Some((store, component_id)), Some(quota)) => {
+                Ok((Arc::clone(store), component_id.clone(), quota))
+            }
+            _ => Err("vector store access denied by policy".to_string()),
+        }
+    }
+}
+
+/// Add the `wassette:vector/store` interface to `linker`.
+pub(crate) fn add_to_linker(linker: &mut Linker<WassetteWasiState<WasiState>>) -> Result<()> {
+    let mut vector = linker.instance(VECTOR_INTERFACE)?;
+
+    vector.func_wrap_async(
+        "upsert",
+        |store, (namespace, entries): (String, Vec<VectorEntry>)| {
+            let admitted = store.data().inner.vector.admit();
+            Box::new(async move {
+                let result = match admitted {
+                    Ok((store, component_id, quota)) => store
+                        .update(&component_id, |index| {
+                            index.upsert(&namespace, entries, &quota)
+                        })
+                        .await
+                        .map_err(|e| format!("{e:#}")),
+                    Err(e) => Err(e),
+                };
+                Ok((result,))
+            })
+        },
+    )?;
+
+    vector.func_wrap_async(
+        "query",
+        |store, (namespace, query, limit): (String, Vec<f32>, u32)| {
+            let admitted = store.data().inner.vector.admit();
+            Box::new(async move {
+                let result = match admitted {
+                    Ok((store, component_id, _)) => store
+                        .read(&component_id, |index| {
+                            index.query(&namespace, &query, limit)
+                        })
+                        .await
+                        .and_then(|matches| matches)
+                        .map_err(|e| format!("{e:#}")),
+                    Err(e) => Err(e),
+                };
+                Ok((result,))
+            })
+        },
+    )?;
+
+    vector.func_wrap_async(
+        "delete",
+        |store, (namespace, ids): (String, Vec<String>)| {
+            let admitted = store.data().inner.vector.admit();
+            Box::new(async move {
+                let result = match admitted {
+                    Ok((store, component_id, _)) => store
+                        .update(&component_id, |index| Ok(index.delete(&namespace, &ids)))
+                        .await
+                        .map_err(|e| format!("{e:#}")),
+                    Err(e) => Err(e),
+                };
+                Ok((result,))
+            })
+        },
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use policy::PolicyParser;
+
+    use super::*;
+
+    fn entry(id: &str, vector: &[f32]) -> VectorEntry {
+        VectorEntry {
+            id: id.to_string(),
+            vector: vector.to_vec(),
+            metadata: format!("{{\"id\":\"{id}\"}}"),
+        }
+    }
+
+    fn quota(max_bytes: u64, max_entries: u32) -> VectorQuota {
+        VectorQuota {
+            max_bytes,
+            max_entries,
+            max_dimensions: 8,
+        }
+    }
+
+    #[test]
+    fn test_quota_from_policy() {
+        let policy = PolicyParser::parse_str("version: \"1.0\"\npermissions: {}\n").unwrap();
+        assert_eq!(VectorQuota::from_policy(&policy).unwrap(), None);
+
+        let policy = PolicyParser::parse_str(
+            "version: \"1.0\"\npermissions:\n  vector:\n    max-entries: 50\n",
+        )
+        .unwrap();
+        assert_eq!(
+            VectorQuota::from_policy(&policy).unwrap(),
+            Some(VectorQuota {
+                max_bytes: DEFAULT_MAX_BYTES,
+                max_entries: 50,
+                max_dimensions: DEFAULT_MAX_DIMENSIONS,
+            })
+        );
+    }
+
+    #[test]
+    fn test_query_ranks_by_cosine_similarity() -> Result<()> {
+        let mut index = VectorIndex::default();
+        index.upsert(
+            "docs",
+            vec![
+                entry("north", &[0.0, 1.0]),
+                entry("east", &[1.0, 0.0]),
+                entry("north-east", &[1.0, 1.0]),
+                entry("zero", &[0.0, 0.0]),
+            ],
+            &quota(1024, 10),
+        )?;
+
+        let matches = index.query("docs", &[0.0, 2.0], 2)?;
+        let ids: Vec<&str> = matches.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["north", "north-east"]);
+        assert!((matches[0].score - 1.0).abs() < 1e-6);
+        assert_eq!(matches[0].metadata, "{\"id\":\"north\"}");
+
+        assert!(index.query("docs", &[1.0, 0.0, 0.0], 2).is_err());
+        assert!(index.query("missing", &[1.0], 2)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert_is_all_or_nothing() -> Result<()> {
+        let mut index = VectorIndex::default();
+        // Each entry takes its id plus 8 bytes of vector plus 10 bytes of metadata.
+        let quota = quota(40, 2);
+        index.upsert("docs", vec![entry("a", &[1.0, 0.0])], &quota)?;
+        let before = index.clone();
+
+        // Dimensions are fixed by the first upsert.
+        assert!(index
+            .upsert("docs", vec![entry("b", &[1.0, 0.0, 0.0])], &quota)
+            .is_err());
+        assert!(index
+            .upsert("docs", vec![entry("b", &[f32::NAN, 0.0])], &quota)
+            .is_err());
+        assert!(index
+            .upsert("wide", vec![entry("b", &[0.0; 9])], &quota)
+            .is_err());
+        let error = index
+            .upsert(
+                "docs",
+                vec![
+                    entry("a", &[0.0, 1.0]),
+                    entry("b", &[1.0, 1.0]),
+                    entry("c", &[1.0, 1.0]),
+                ],
+                &quota,
+            )
+            .unwrap_err();
+        assert!(error.to_string().contains("quota"), "{error}");
+        assert_eq!(index, before);
+
+        // Replacing an entry doesn't count it twice.
+        index.upsert(
+            "docs",
+            vec![entry("a", &[0.0, 1.0]), entry("b", &[1.0, 1.0])],
+            &quota,
+        )?;
+        assert_eq!(index.entries(), 2);
+        assert_eq!(index.bytes(), 38);
+
+        assert_eq!(index.delete("docs", &["a".to_string(), "z".to_string()]), 1);
+        assert_eq!(index.delete("docs", &["b".to_string()]), 1);
+        assert!(index.namespaces.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_vectors_persist_per_component() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let storage = ComponentStorage::new(tempdir.path().join("plugins"), 1).await?;
+        let quota = quota(1024, 10);
+
+        let vectors = VectorStore::new(storage.clone());
+        vectors
+            .update("rag", |index| {
+                index.upsert("docs", vec![entry("a", &[0.5, -0.25])], &quota)
+            })
+            .await?;
+        assert!(vectors
+            .update("rag", |index| {
+                index.upsert("docs", vec![entry("b", &[1.0])], &quota)
+            })
+            .await
+            .is_err());
+
+        // A fresh store reads back what was written, for that component only.
+        let reloaded = VectorStore::new(storage.clone());
+        let matches = reloaded
+            .read("rag", |index| index.query("docs", &[0.5, -0.25], 10))
+            .await??;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].id, "a");
+        assert!(reloaded
+            .read("other", |index| index.query("docs", &[0.5, -0.25], 10))
+            .await??
+            .is_empty());
+
+        reloaded
+            .update("rag", |index| Ok(index.delete("docs", &["a".to_string()])))
+            .await?;
+        assert!(!storage.vectors_path("rag").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,449 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Vendoring of the WIT packages a component project depends on.
+//!
+//! `wassette component vendor` reads the WIT files of a project's `wit` directory, finds the
+//! packages they use besides their own, and writes each one to
+//! `wit/deps/<namespace>-<name>[-<version>]/package.wit`, the layout wit-bindgen, jco,
+//! componentize-py and wkg read. Packages those depend on are vendored too.
+//!
+//! `wasi` packages are downloaded from the registry the WASI project publishes them to,
+//! `ghcr.io/webassembly/wasi/<name>:<version>`, together with the packages they depend on.
+//! `wassette` packages are the host interfaces of this release and are written without a
+//! download. Packages of other namespaces can't be downloaded, but once placed under
+//! `wit/deps` by hand they are pinned like the others.
+//!
+//! Every vendored package is recorded in `wit/deps.lock` with its source and the SHA-256
+//! digest of its `package.wit`. A package whose file still matches its digest isn't
+//! downloaded again, so a project with its `deps` directory checked in builds offline, and a
+//! download that doesn't match the digest it is pinned to fails instead of changing the
+//! project's interfaces. `--update` downloads everything again and pins what it gets;
+//! `--check` only verifies that every package is vendored and matches its pin, for CI.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use oci_client::Reference;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+use wit_component::{DecodedWasm, WitPrinter};
+use wit_parser::{PackageName, UnresolvedPackageGroup};
+
+use crate::oci_multi_layer::pull_wasm_only;
+
+/// Registry and repository prefix `wasi` packages are published under.
+pub const WASI_REGISTRY: &str = "ghcr.io/webassembly/wasi";
+
+/// Name of the lock file in a project's `wit` directory.
+pub const DEPS_LOCK_NAME: &str = "deps.lock";
+
+/// Version of the lock file format written by this release.
+const DEPS_LOCK_VERSION: u32 = 1;
+
+/// Source recorded for the host interfaces of this release.
+const BUILTIN_SOURCE: &str = "builtin";
+
+/// Source recorded for packages placed under `wit/deps` by hand.
+const LOCAL_SOURCE: &str = "local";
+
+/// WIT of the `wassette` host interfaces, by package name.
+const HOST_WIT: &[(&str, &str)] = &[
+    ("wassette:ai", include_str!("../wit/ai.wit")),
+    ("wassette:browser", include_str!("../wit/browser.wit")),
+    ("wassette:context", include_str!("../wit/context.wit")),
+    ("wassette:email", include_str!("../wit/email.wit")),
+    ("wassette:progress", include_str!("../wit/progress.wit")),
+    ("wassette:prompts", include_str!("../wit/prompts.wit")),
+    ("wassette:resources", include_str!("../wit/resources.wit")),
+    ("wassette:vector", include_str!("../wit/vector.wit")),
+];
+
+/// What a vendoring run may do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VendorMode {
+    /// Vendor missing packages and those that don't match their pin
+    #[default]
+    Sync,
+    /// Download every package again and pin what it gets
+    Update,
+    /// Only check that every package is vendored and matches its pin
+    Check,
+}
+
+/// A package in a project's `wit/deps` directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VendoredPackage {
+    /// Package name, e.g. `wasi:cli@0.2.0`
+    pub name: String,
+    /// Where it came from: an `oci://` reference, `builtin` or `local`
+    pub source: String,
+    /// `sha256:<hex>` of its `package.wit`
+    pub digest: String,
+    /// Whether this run wrote the package
+    pub written: bool,
+}
+
+/// Contents of `wit/deps.lock`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct DepsLock {
+    version: u32,
+    /// Keyed by package name
+    #[serde(default)]
+    packages: BTreeMap<String, LockedPackage>,
+}
+
+impl Default for DepsLock {
+    fn default() -> Self {
+        Self {
+            version: DEPS_LOCK_VERSION,
+            packages: BTreeMap::new(),
+        }
+    }
+}
+
+/// Where a package came from and what it resolved to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LockedPackage {
+    source: String,
+    /// `sha256:<hex>` of its `package.wit`
+    digest: String,
+}
+
+/// A package to write: its name, source and WIT.
+type FetchedPackage = (PackageName, String, String);
+
+/// Vendor the WIT packages the project in `project` depends on into its `wit/deps`
+/// directory, returning every package it needs.
+pub async fn vendor(project: &Path, mode: VendorMode) -> Result<Vec<VendoredPackage>> {
+    let wit_dir = project.join("wit");
+    let deps_dir = wit_dir.join("deps");
+    let lock_path = wit_dir.join(DEPS_LOCK_NAME);
+    let group = UnresolvedPackageGroup::parse_dir(&wit_dir)
+        .with_context(|| format!("Failed to read the WIT files in {}", wit_dir.display()))?;
+    let local: BTreeSet<String> = std::iter::once(&group.main)
+        .chain(&group.nested)
+        .map(|package| package.name.to_string())
+        .collect();
+    let mut queue: Vec<PackageName> = std::iter::once(&group.main)
+        .chain(&group.nested)
+        .flat_map(|package| package.foreign_deps.keys().cloned())
+        .filter(|name| !local.contains(&name.to_string()))
+        .collect();
+
+    let mut lock = read_lock(&lock_path)?;
+    let mut vendored = BTreeMap::new();
+    while let Some(name) = queue.pop() {
+        let key = name.to_string();
+        if vendored.contains_key(&key) {
+            continue;
+        }
+        let path = package_path(&deps_dir, &name);
+        let pinned = lock
+            .packages
+            .get(&key)
+            .filter(|_| mode != VendorMode::Update);
+        if let Some(pinned) = pinned {
+            if let Ok(wit) = std::fs::read_to_string(&path) {
+                if digest(&wit) == pinned.digest {
+                    queue.extend(dependencies(&path, &wit)?);
+                    vendored.insert(
+                        key.clone(),
+                        VendoredPackage {
+                            name: key,
+                            source: pinned.source.clone(),
+                            digest: pinned.digest.clone(),
+                            written: false,
+                        },
+                    );
+                    continue;
+                }
+            }
+        }
+        if mode == VendorMode::Check {
+            bail!(
+                "{key} is missing from {} or doesn't match {}; run `wassette component vendor`",
+                deps_dir.display(),
+                lock_path.display()
+            );
+        }
+
+        let fetched = match name.namespace.as_str() {
+            "wassette" => vec![builtin_package(&name)?],
+            "wasi" => fetch_wasi_packages(&name).await?,
+            _ => match std::fs::read_to_string(&path) {
+                Ok(wit) => vec![(name.clone(), LOCAL_SOURCE.to_string(), wit)],
+                Err(_) => bail!(
+                    "{key} can't be downloaded, only wasi and wassette packages can; place it in {} yourself",
+                    path.display()
+                ),
+            },
+        };
+        for (name, source, wit) in fetched {
+            let key = name.to_string();
+            if vendored.contains_key(&key) || local.contains(&key) {
+                continue;
+            }
+            let digest = digest(&wit);
+            if let Some(pinned) = lock
+                .packages
+                .get(&key)
+                .filter(|_| mode != VendorMode::Update)
+            {
+                if pinned.digest != digest {
+                    bail!(
+                        "{key} from {source} is {digest}, but {} pins it to {}; run with --update to pin the new version",
+                        lock_path.display(),
+                        pinned.digest
+                    );
+                }
+            }
+            let path = package_path(&deps_dir, &name);
+            queue.extend(dependencies(&path, &wit)?);
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+            }
+            std::fs::write(&path, &wit)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            info!(package = %key, %source, %digest, "Vendored WIT package");
+            lock.packages.insert(
+                key.clone(),
+                LockedPackage {
+                    source: source.clone(),
+                    digest: digest.clone(),
+                },
+            );
+            vendored.insert(
+                key.clone(),
+                VendoredPackage {
+                    name: key,
+                    source,
+                    digest,
+                    written: true,
+                },
+            );
+        }
+        if !vendored.contains_key(&name.to_string()) {
+            bail!("The package downloaded for {name} doesn't define it");
+        }
+    }
+
+    if mode != VendorMode::Check {
+        // Packages the project no longer uses drop out of the lock.
+        lock.packages.retain(|key, _| vendored.contains_key(key));
+        let mut json = serde_json::to_string_pretty(&lock)?;
+        json.push('\n');
+        std::fs::write(&lock_path, json)
+            .with_context(|| format!("Failed to write {}", lock_path.display()))?;
+    }
+    Ok(vendored.into_values().collect())
+}
+
+/// Read the lock file at `path`, or an empty one if there is none.
+fn read_lock(path: &Path) -> Result<DepsLock> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(DepsLock::default()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let lock: DepsLock = serde_json::from_slice(&bytes)
+        .with_context(|| format!("Malformed lock file {}", path.display()))?;
+    if lock.version > DEPS_LOCK_VERSION {
+        bail!(
+            "{} was written by a newer release of wassette (version {})",
+            path.display(),
+            lock.version
+        );
+    }
+    Ok(lock)
+}
+
+/// Where `name` is vendored under `deps_dir`.
+fn package_path(deps_dir: &Path, name: &PackageName) -> PathBuf {
+    let mut dir = format!("{}-{}", name.namespace, name.name);
+    if let Some(version) = &name.version {
+        dir.push_str(&format!("-{version}"));
+    }
+    deps_dir.join(dir).join("package.wit")
+}
+
+fn digest(wit: &str) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(wit.as_bytes())))
+}
+
+/// Packages the WIT of the package vendored at `path` uses.
+fn dependencies(path: &Path, wit: &str) -> Result<Vec<PackageName>> {
+    let group = UnresolvedPackageGroup::parse(path, wit)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(group.main.foreign_deps.keys().cloned().collect())
+}
+
+/// The host interface package `name` of this release.
+fn builtin_package(name: &PackageName) -> Result<FetchedPackage> {
+    let key = format!("{}:{}", name.namespace, name.name);
+    let Some((_, wit)) = HOST_WIT.iter().find(|(package, _)| *package == key) else {
+        bail!("{key} isn't a host interface of this release of wassette");
+    };
+    if name.version.is_some() {
+        bail!("{name}: the wassette host interfaces aren't versioned, use {key}");
+    }
+    Ok((name.clone(), BUILTIN_SOURCE.to_string(), wit.to_string()))
+}
+
+/// Download the `wasi` package `name` and the packages it uses.
+async fn fetch_wasi_packages(name: &PackageName) -> Result<Vec<FetchedPackage>> {
+    let Some(version) = &name.version else {
+        bail!("{name} needs a version to be downloaded, e.g. {name}@0.2.0");
+    };
+    let reference: Reference = format!("{WASI_REGISTRY}/{}:{version}", name.name)
+        .parse()
+        .with_context(|| format!("Invalid reference for {name}"))?;
+    info!(package = %name, %reference, "Downloading WIT package");
+    let client = oci_client::Client::default();
+    let bytes = pull_wasm_only(&reference, &client)
+        .await
+        .with_context(|| format!("Failed to download {name} from {reference}"))?;
+    let source = format!("oci://{reference}");
+    wit_packages(&bytes, &source).with_context(|| format!("Failed to read {name}"))
+}
+
+/// The packages in the binary WIT package `bytes`, each printed as WIT.
+fn wit_packages(bytes: &[u8], source: &str) -> Result<Vec<FetchedPackage>> {
+    let DecodedWasm::WitPackage(resolve, _) = wit_component::decode(bytes)? else {
+        bail!("Not a WIT package");
+    };
+    resolve
+        .packages
+        .iter()
+        .map(|(id, package)| {
+            let mut printer = WitPrinter::default();
+            printer.print(&resolve, id, &[])?;
+            Ok((
+                package.name.clone(),
+                source.to_string(),
+                printer.output.to_string(),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use wit_parser::Resolve;
+
+    use super::*;
+
+    const WORLD: &str = r#"
+package local:notifier;
+
+world notifier {
+    import wassette:email/send;
+    import wassette:progress/report;
+    export run: func() -> result<_, string>;
+}
+"#;
+
+    fn project(world: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("wit")).unwrap();
+        std::fs::write(dir.path().join("wit/world.wit"), world).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_host_wit_parses() {
+        for (package, wit) in HOST_WIT {
+            let group = UnresolvedPackageGroup::parse(format!("{package}.wit"), wit).unwrap();
+            assert_eq!(group.main.name.to_string(), *package);
+            assert!(group.main.foreign_deps.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_vendor_pins_and_checks_packages() {
+        let project = project(WORLD);
+        let vendored = vendor(project.path(), VendorMode::Sync).await.unwrap();
+        let names: Vec<_> = vendored.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["wassette:email", "wassette:progress"]);
+        assert!(vendored.iter().all(|p| p.written && p.source == "builtin"));
+        let email = project.path().join("wit/deps/wassette-email/package.wit");
+        assert!(std::fs::read_to_string(&email)
+            .unwrap()
+            .starts_with("package wassette:email;"));
+
+        // The vendored project resolves as a whole.
+        Resolve::default()
+            .push_dir(project.path().join("wit"))
+            .unwrap();
+
+        let vendored = vendor(project.path(), VendorMode::Check).await.unwrap();
+        assert!(vendored.iter().all(|p| !p.written));
+        let again = vendor(project.path(), VendorMode::Sync).await.unwrap();
+        assert!(again.iter().all(|p| !p.written));
+
+        std::fs::write(&email, "package wassette:email;\n").unwrap();
+        let err = vendor(project.path(), VendorMode::Check).await.unwrap_err();
+        assert!(err.to_string().contains("wassette:email"));
+        let repaired = vendor(project.path(), VendorMode::Sync).await.unwrap();
+        assert!(repaired[0].written && !repaired[1].written);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_packages_must_be_placed_by_hand() {
+        let project =
+            project("package local:app;\n\nworld app {\n    import acme:auth/token@1.0.0;\n}\n");
+        let err = vendor(project.path(), VendorMode::Sync).await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("acme:auth@1.0.0 can't be downloaded"));
+
+        let deps = project.path().join("wit/deps/acme-auth-1.0.0");
+        std::fs::create_dir_all(&deps).unwrap();
+        std::fs::write(
+            deps.join("package.wit"),
+            "package acme:auth@1.0.0;\n\ninterface token {\n    get: func() -> string;\n}\n",
+        )
+        .unwrap();
+        let vendored = vendor(project.path(), VendorMode::Sync).await.unwrap();
+        assert_eq!(vendored[0].source, "local");
+        let lock = std::fs::read_to_string(project.path().join("wit/deps.lock")).unwrap();
+        assert!(lock.contains(&vendored[0].digest));
+    }
+
+    #[test]
+    fn test_binary_packages_are_printed_with_their_dependencies() {
+        let mut resolve = Resolve::default();
+        resolve
+            .push_str(
+                "io.wit",
+                "package wasi:io@0.2.0;\n\ninterface streams {\n    resource input-stream;\n}\n",
+            )
+            .unwrap();
+        let cli = resolve
+            .push_str(
+                "cli.wit",
+                "package wasi:cli@0.2.0;\n\ninterface stdin {\n    use wasi:io/streams@0.2.0.{input-stream};\n    get-stdin: func() -> input-stream;\n}\n",
+            )
+            .unwrap();
+        let bytes = wit_component::encode(&resolve, cli).unwrap();
+
+        let packages = wit_packages(&bytes, "oci://example").unwrap();
+        let names: BTreeSet<_> = packages
+            .iter()
+            .map(|(name, _, _)| name.to_string())
+            .collect();
+        assert_eq!(
+            names,
+            BTreeSet::from(["wasi:cli@0.2.0".to_string(), "wasi:io@0.2.0".to_string()])
+        );
+        let (_, _, cli) = packages
+            .iter()
+            .find(|(name, _, _)| name.name == "cli")
+            .unwrap();
+        assert!(cli.contains("use wasi:io/streams@0.2.0.{input-stream};"));
+        assert!(wit_packages(&wat::parse_str("(module)").unwrap(), "x").is_err());
+    }
+}
@@ -0,0 +1,284 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Earlier versions of components, kept to roll back to.
+//!
+//! When a load replaces a component with another artifact, the version replaced is kept in
+//! `versions/<component id>/<digest>/` under the plugin directory: its artifact, its policy
+//! with the permissions granted to it, and the manifest and update source it came with. The
+//! last few versions of every component are kept, five unless configured otherwise, and the
+//! oldest are removed as new ones come in.
+//!
+//! `wassette component rollback` puts a kept version back without downloading anything: the
+//! latest one, or the one with a given digest. The version it replaces is kept in turn, so a
+//! rollback can be undone the same way.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::updates::Snapshot;
+
+/// Versions of each component kept unless configured otherwise.
+pub const DEFAULT_KEPT_VERSIONS: usize = 5;
+
+/// Directory of the kept versions under the plugin directory.
+pub(crate) const VERSIONS_DIR: &str = "versions";
+
+/// File describing a kept version, next to its files.
+const VERSION_FILE: &str = "version.json";
+
+/// A version of a component kept to roll back to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeptVersion {
+    /// Digest of the artifact, as `sha256:<hex>`
+    pub digest: String,
+    /// Release version, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Time the version was replaced (milliseconds since epoch)
+    pub kept_at: u64,
+}
+
+/// What a rollback put back
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RollbackReport {
+    /// Component rolled back
+    pub component_id: String,
+    /// Digest of the artifact replaced, as `sha256:<hex>`
+    pub previous_digest: String,
+    /// Digest of the artifact put back, as `sha256:<hex>`
+    pub digest: String,
+    /// Release version replaced, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_version: Option<String>,
+    /// Release version put back, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+fn component_dir(plugin_dir: &Path, component_id: &str) -> PathBuf {
+    plugin_dir.join(VERSIONS_DIR).join(component_id)
+}
+
+fn version_dir(plugin_dir: &Path, component_id: &str, digest: &str) -> PathBuf {
+    component_dir(plugin_dir, component_id).join(digest.strip_prefix("sha256:").unwrap_or(digest))
+}
+
+pub(crate) async fn remove_dir_if_exists(dir: &Path) -> Result<()> {
+    match tokio::fs::remove_dir_all(dir).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {}", dir.display())),
+    }
+}
+
+/// Keep `snapshot`, released as `version`, among the versions of `component_id`, removing
+/// the oldest beyond the last `limit`. Nothing is kept with a `limit` of zero.
+pub(crate) async fn keep(
+    plugin_dir: &Path,
+    component_id: &str,
+    snapshot: &Snapshot,
+    version: Option<String>,
+    limit: usize,
+) -> Result<()> {
+    if limit == 0 {
+        return Ok(());
+    }
+    let kept = KeptVersion {
+        digest: snapshot.digest(),
+        version,
+        kept_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default(),
+    };
+    // Written aside first, so a crash never leaves a version half kept.
+    let dir = version_dir(plugin_dir, component_id, &kept.digest);
+    let staged = dir.with_extension("tmp");
+    remove_dir_if_exists(&staged).await?;
+    snapshot.save(&staged).await?;
+    tokio::fs::write(staged.join(VERSION_FILE), serde_json::to_vec_pretty(&kept)?)
+        .await
+        .context("Failed to describe the kept version")?;
+    remove_dir_if_exists(&dir).await?;
+    tokio::fs::rename(&staged, &dir)
+        .await
+        .with_context(|| format!("Failed to keep version in {}", dir.display()))?;
+
+    for old in list(plugin_dir, component_id)
+        .await?
+        .into_iter()
+        .skip(limit)
+    {
+        remove_dir_if_exists(&version_dir(plugin_dir, component_id, &old.digest)).await?;
+    }
+    Ok(())
+}
+
+/// The versions of `component_id` kept under `plugin_dir`, newest first.
+pub(crate) async fn list(plugin_dir: &Path, component_id: &str) -> Result<Vec<KeptVersion>> {
+    let dir = component_dir(plugin_dir, component_id);
+    let mut entries = match tokio::fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    let mut versions = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        // Versions still being written have an extension.
+        if !entry.file_type().await?.is_dir() || path.extension().is_some() {
+            continue;
+        }
+        let described = tokio::fs::read(path.join(VERSION_FILE))
+            .await
+            .map(|content| serde_json::from_slice::<KeptVersion>(&content));
+        match described {
+            Ok(Ok(version)) => versions.push(version),
+            Ok(Err(error)) => {
+                warn!(%error, path = %path.display(), "Ignoring invalid kept version")
+            }
+            Err(error) => warn!(%error, path = %path.display(), "Failed to read kept version"),
+        }
+    }
+    versions.sort_by_key(|version| std::cmp::Reverse(version.kept_at));
+    Ok(versions)
+}
+
+/// The kept version of `component_id` to roll back to from the `installed` digest: the one
+/// whose digest is or starts with `digest` if given, or else the newest other than `installed`.
+pub(crate) async fn find(
+    plugin_dir: &Path,
+    component_id: &str,
+    digest: Option<&str>,
+    installed: &str,
+) -> Result<KeptVersion> {
+    let kept = list(plugin_dir, component_id).await?;
+    let Some(digest) = digest else {
+        return kept
+            .into_iter()
+            .find(|version| version.digest != installed)
+            .with_context(|| format!("Component {component_id} has no earlier version kept"));
+    };
+    let prefix = digest.strip_prefix("sha256:").unwrap_or(digest);
+    let mut matching: Vec<KeptVersion> = kept
+        .iter()
+        .filter(|version| {
+            !prefix.is_empty()
+                && version
+                    .digest
+                    .strip_prefix("sha256:")
+                    .is_some_and(|hex| hex.starts_with(prefix))
+        })
+        .cloned()
+        .collect();
+    match matching.len() {
+        0 if kept.is_empty() => bail!("Component {component_id} has no earlier version kept"),
+        0 => bail!(
+            "No version of component {component_id} with digest {digest} is kept; kept are: {}",
+            kept.iter()
+                .map(|version| version.digest.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        1 if matching[0].digest == installed => {
+            bail!(
+                "Version {} of component {component_id} is already installed",
+                matching[0].digest
+            )
+        }
+        1 => Ok(matching.remove(0)),
+        _ => bail!("Digest {digest} matches several kept versions of component {component_id}"),
+    }
+}
+
+/// The files of the kept `version` of `component_id`.
+pub(crate) async fn load(
+    plugin_dir: &Path,
+    component_id: &str,
+    version: &KeptVersion,
+) -> Result<Snapshot> {
+    Snapshot::load(&version_dir(plugin_dir, component_id, &version.digest))
+        .await
+        .with_context(|| format!("Failed to read kept version {}", version.digest))
+}
+
+/// Remove the kept versions of `component_id`.
+pub(crate) async fn forget(plugin_dir: &Path, component_id: &str) -> Result<()> {
+    remove_dir_if_exists(&component_dir(plugin_dir, component_id)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::component_storage::ComponentStorage;
+
+    #[tokio::test]
+    async fn test_versions_are_kept_and_found() -> Result<()> {
+        let plugin_dir = tempfile::tempdir()?;
+        let storage = ComponentStorage::new(plugin_dir.path(), 1).await?;
+        let mut digests = Vec::new();
+        for (wasm, policy) in [
+            ("v1", "policy: 1"),
+            ("v2", "policy: 2"),
+            ("v3", "policy: 3"),
+        ] {
+            tokio::fs::write(storage.component_path("fetch"), wasm).await?;
+            tokio::fs::write(storage.policy_path("fetch"), policy).await?;
+            let snapshot = Snapshot::capture(&storage, "fetch").await?;
+            digests.push(snapshot.digest());
+            keep(plugin_dir.path(), "fetch", &snapshot, Some(wasm.into()), 2).await?;
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+
+        let kept = list(plugin_dir.path(), "fetch").await?;
+        let kept: Vec<&str> = kept.iter().map(|version| version.digest.as_str()).collect();
+        assert_eq!(kept, [digests[2].as_str(), digests[1].as_str()]);
+
+        // The newest kept version other than the installed one
+        let installed = &digests[2];
+        let previous = find(plugin_dir.path(), "fetch", None, installed).await?;
+        assert_eq!(previous.digest, digests[1]);
+        assert_eq!(previous.version.as_deref(), Some("v2"));
+        let prefix = &digests[1]["sha256:".len()..][..12];
+        assert_eq!(
+            find(plugin_dir.path(), "fetch", Some(prefix), installed)
+                .await?
+                .digest,
+            digests[1]
+        );
+        let error = find(plugin_dir.path(), "fetch", Some(&digests[0]), installed)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("kept are"));
+        let error = find(plugin_dir.path(), "fetch", Some(installed), installed)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("already installed"));
+
+        load(plugin_dir.path(), "fetch", &previous)
+            .await?
+            .restore(&storage, "fetch")
+            .await?;
+        assert_eq!(
+            tokio::fs::read_to_string(storage.component_path("fetch")).await?,
+            "v2"
+        );
+        assert_eq!(
+            tokio::fs::read_to_string(storage.policy_path("fetch")).await?,
+            "policy: 2"
+        );
+
+        forget(plugin_dir.path(), "fetch").await?;
+        assert!(find(plugin_dir.path(), "fetch", None, installed)
+            .await
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Warm standby instances for latency-critical tools.
+//!
+//! Every tool call normally gets a fresh store and instance, which puts instantiation on the
+//! critical path. Tools marked `latency_critical` instead keep instances prepared ahead of
+//! time: a call takes a standby, and a replacement is instantiated in the background right
+//! away. Standbys are never reused, so calls stay as isolated as cold ones; the cost is the
+//! memory of the idle instances, one per tool unless [`RuntimeSettings::pool_size`] keeps more
+//! for tools called faster than one can be instantiated. A standby built before its component
+//! was reloaded, or before its policy, secrets or workspace roots changed, is thrown away
+//! instead of used.
+//!
+//! Standbys are instantiated in the background on the component's fuel allowance and under
+//! the server's call timeout, so a start function that never returns can't hold a runtime
+//! thread. A call taking a standby starts its own clock and fuel allowance.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use wasmtime::component::{Instance, InstancePre};
+use wasmtime::Store;
+
+use crate::scheduler::Priority;
+use crate::wasistate::WasiState;
+use crate::WassetteWasiState;
+
+/// Per-tool runtime settings, keyed by tool name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolSettings {
+    /// Keep an instantiated instance of the tool's component ready for the next call
+    pub latency_critical: bool,
+    /// Scheduling class of the tool's calls
+    pub priority: Priority,
+    /// Seconds a call of the tool may run, in place of the server-wide call timeout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Calls of the tool can run again with the same arguments without harm, so a call a
+    /// crash interrupted is run again when the server restarts
+    pub idempotent: bool,
+}
+
+/// Settings of the engine shared by every component.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeSettings {
+    /// Standby instances kept per latency-critical tool, with instances allocated from
+    /// Wasmtime's pooling allocator; one standby and on-demand allocation by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_size: Option<usize>,
+    /// Threads of a dedicated pool that validates and converts tool call arguments and
+    /// results, instead of the runtime threads handling the calls
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validation_threads: Option<usize>,
+    /// Milliseconds a conversion on the validation threads may take, queueing included;
+    /// 100 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validation_budget_ms: Option<u64>,
+}
+
+/// An instance prepared for the next call of a tool.
+pub(crate) struct Standby {
+    /// The pre-instantiated component the instance was created from
+    pub(crate) instance_pre: Arc<InstancePre<WassetteWasiState<WasiState>>>,
+    pub(crate) store: Store<WassetteWasiState<WasiState>>,
+    pub(crate) instance: Instance,
+    /// Concurrent instance limit of the component's policy when the standby was built
+    pub(crate) instance_limit: Option<usize>,
+}
+
+#[derive(Default)]
+struct Standbys {
+    /// Standbys per tool, with the id of the component providing them
+    ready: HashMap<String, (String, VecDeque<Standby>)>,
+    /// Bumped whenever the standbys of a component become stale
+    generations: HashMap<String, u64>,
+}
+
+/// The standby instances of all latency-critical tools.
+pub(crate) struct WarmPool {
+    latency_critical: HashSet<String>,
+    /// Standbys kept per tool
+    size: usize,
+    standbys: Mutex<Standbys>,
+}
+
+impl WarmPool {
+    pub(crate) fn new(tools: &HashMap<String, ToolSettings>, size: usize) -> Self {
+        Self {
+            latency_critical: tools
+                .iter()
+                .filter(|(_, settings)| settings.latency_critical)
+                .map(|(name, _)| name.clone())
+                .collect(),
+            size: size.max(1),
+            standbys: Mutex::default(),
+        }
+    }
+
+    /// Number of standbys kept per latency-critical tool.
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether `tool` keeps a standby instance.
+    pub(crate) fn is_latency_critical(&self, tool: &str) -> bool {
+        self.latency_critical.contains(tool)
+    }
+
+    /// Number of latency-critical tools.
+    pub(crate) fn latency_critical_tools(&self) -> usize {
+        self.latency_critical.len()
+    }
+
+    /// Number of standbys ready to be taken.
+    pub(crate) fn ready(&self) -> usize {
+        let standbys = self.standbys.lock().unwrap();
+        standbys.ready.values().map(|(_, queue)| queue.len()).sum()
+    }
+
+    /// Current generation of `component_id`'s standbys; pass it to [`Self::put`].
+    pub(crate) fn generation(&self, component_id: &str) -> u64 {
+        let mut standbys = self.standbys.lock().unwrap();
+        // Registered so that `forget_all` also invalidates standbys still being built.
+        *standbys
+            .generations
+            .entry(component_id.to_string())
+            .or_default()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_ready(&self, tool: &str) -> bool {
+        self.standbys.lock().unwrap().ready.contains_key(tool)
+    }
+
+    /// Take a standby of `tool`, if one was built from `instance_pre`.
+    pub(crate) fn take(
+        &self,
+        tool: &str,
+        instance_pre: &Arc<InstancePre<WassetteWasiState<WasiState>>>,
+    ) -> Option<Standby> {
+        let mut standbys = self.standbys.lock().unwrap();
+        let (_, queue) = standbys.ready.get_mut(tool)?;
+        let standby = queue.pop_front()?;
+        if queue.is_empty() {
+            standbys.ready.remove(tool);
+        }
+        // A standby of a reloaded component is dropped here.
+        Arc::ptr_eq(&standby.instance_pre, instance_pre).then_some(standby)
+    }
+
+    /// Keep `standby` for a next call of `tool`, unless `component_id` changed since
+    /// `generation` was read or the tool already has all its standbys.
+    pub(crate) fn put(&self, tool: &str, component_id: &str, generation: u64, standby: Standby) {
+        let mut standbys = self.standbys.lock().unwrap();
+        let current = standbys
+            .generations
+            .get(component_id)
+            .copied()
+            .unwrap_or_default();
+        if current != generation {
+            return;
+        }
+        let (owner, queue) = standbys
+            .ready
+            .entry(tool.to_string())
+            .or_insert_with(|| (component_id.to_string(), VecDeque::new()));
+        if owner != component_id {
+            // The tool moved to another component; its standbys are of no use.
+            *owner = component_id.to_string();
+            queue.clear();
+        }
+        if queue.len() < self.size {
+            queue.push_back(standby);
+        }
+    }
+
+    /// Drop the standbys of `component_id`, including ones still being built. Returns the
+    /// tools that had one.
+    pub(crate) fn forget(&self, component_id: &str) -> Vec<String> {
+        let mut standbys = self.standbys.lock().unwrap();
+        *standbys
+            .generations
+            .entry(component_id.to_string())
+            .or_default() += 1;
+        let tools: Vec<String> = standbys
+            .ready
+            .iter()
+            .filter(|(_, (owner, _))| owner == component_id)
+            .map(|(tool, _)| tool.clone())
+            .collect();
+        for tool in &tools {
+            standbys.ready.remove(tool);
+        }
+        tools
+    }
+
+    /// Drop every standby, including ones still being built. Returns the component id and
+    /// tool of each one dropped.
+    pub(crate) fn forget_all(&self) -> Vec<(String, String)> {
+        let mut standbys = self.standbys.lock().unwrap();
+        for generation in standbys.generations.values_mut() {
+            *generation += 1;
+        }
+        standbys
+            .ready
+            .drain()
+            .map(|(tool, (owner, _))| (owner, tool))
+            .collect()
+    }
+}
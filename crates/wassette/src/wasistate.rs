@@ -1,25 +1,94 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT license.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
-
-use policy::{AccessType, PolicyDocument};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use policy::{
+    AccessType, NetworkPermission, PermissionList, PolicyDocument, RequestHeader,
+    ResourceLimitValues, StorageKind,
+};
+use tokio::sync::OwnedSemaphorePermit;
+use tokio_util::sync::CancellationToken;
 use wasmtime::component::ResourceTable;
 use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiCtxView};
 use wasmtime_wasi_config::WasiConfigVariables;
 use wasmtime_wasi_http::{WasiHttpCtx, WasiHttpView};
 
+use crate::browser::{BrowserLimits, BrowserState};
+use crate::call_progress::ProgressState;
+use crate::email::{self, EmailLimits, EmailState};
+use crate::env_references::{self, VariableSource};
+use crate::fs_grants::{self, FsGrants, PathFilter};
+use crate::keyvalue::{KeyValueQuota, KeyValueState};
+use crate::limits::{InvocationLimits, LimitedResource, OpenFiles};
+use crate::mounts::{self, VirtualMount};
+use crate::resources::ResourceState;
+use crate::sampling::{SamplingLimits, SamplingState};
+use crate::schedule::ToolHours;
+use crate::session_context::{self, ContextState};
+use crate::vector::{VectorQuota, VectorState};
+use crate::WasmProposal;
+
 /// Custom resource limiter that stores the limits
 #[derive(Clone)]
 pub struct CustomResourceLimiter {
     limits: wasmtime::StoreLimits,
+    /// The policy's memory limit in bytes, to tell its refusals from the module's own maximum
+    memory_limit: Option<usize>,
+    /// Whether `memory_limit` replaces the memory limit of `limits`
+    memory_overridden: bool,
+    /// The policy's table element limit
+    table_elements_limit: Option<usize>,
+    /// The last policy limit growth was refused at
+    exceeded: Option<(LimitedResource, u64)>,
+    /// Linear memory of the store's instances, in bytes; memories never shrink
+    memory_usage: usize,
 }
 
 impl CustomResourceLimiter {
     /// Create a new CustomResourceLimiter with the given limits
     pub fn new(limits: wasmtime::StoreLimits) -> Self {
-        Self { limits }
+        Self {
+            limits,
+            memory_limit: None,
+            memory_overridden: false,
+            table_elements_limit: None,
+            exceeded: None,
+            memory_usage: 0,
+        }
+    }
+
+    /// Name the policy limits `limits` was built from, so refusals to grow past them are
+    /// reported as such.
+    pub fn with_policy_limits(
+        mut self,
+        memory_limit: Option<u64>,
+        table_elements_limit: Option<u32>,
+    ) -> Self {
+        self.memory_limit = memory_limit.and_then(|limit| limit.try_into().ok());
+        self.table_elements_limit = table_elements_limit.map(|limit| limit as usize);
+        self
+    }
+
+    /// Limit memory growth to `limit` bytes instead of the policy's limit, for one call whose
+    /// limits were overridden.
+    pub(crate) fn override_memory_limit(&mut self, limit: u64) {
+        self.memory_limit = Some(limit.try_into().unwrap_or(usize::MAX));
+        self.memory_overridden = true;
+    }
+
+    /// The last policy limit growth was refused at, with the limit.
+    pub(crate) fn exceeded(&self) -> Option<(LimitedResource, u64)> {
+        self.exceeded
+    }
+
+    /// Linear memory the store's instances grew to, in bytes.
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.memory_usage
     }
 }
 
@@ -28,9 +97,22 @@ impl wasmtime::ResourceLimiter for CustomResourceLimiter {
         &mut self,
         current: usize,
         desired: usize,
-        _maximum: Option<usize>,
+        maximum: Option<usize>,
     ) -> anyhow::Result<bool> {
-        self.limits.memory_growing(current, desired, _maximum)
+        let allowed = match self.memory_limit.filter(|_| self.memory_overridden) {
+            Some(limit) => desired <= limit && maximum.is_none_or(|maximum| desired <= maximum),
+            None => self.limits.memory_growing(current, desired, maximum)?,
+        };
+        if allowed {
+            self.memory_usage += desired.saturating_sub(current);
+        }
+        if let Some(limit) = self
+            .memory_limit
+            .filter(|limit| !allowed && desired > *limit)
+        {
+            self.exceeded = Some((LimitedResource::Memory, limit as u64));
+        }
+        Ok(allowed)
     }
 
     fn table_growing(
@@ -39,7 +121,26 @@ impl wasmtime::ResourceLimiter for CustomResourceLimiter {
         desired: usize,
         _maximum: Option<usize>,
     ) -> anyhow::Result<bool> {
-        self.limits.table_growing(current, desired, _maximum)
+        let allowed = self.limits.table_growing(current, desired, _maximum)?;
+        if let Some(limit) = self
+            .table_elements_limit
+            .filter(|limit| !allowed && desired > *limit)
+        {
+            self.exceeded = Some((LimitedResource::TableElements, limit as u64));
+        }
+        Ok(allowed)
+    }
+
+    fn instances(&self) -> usize {
+        self.limits.instances()
+    }
+
+    fn tables(&self) -> usize {
+        self.limits.tables()
+    }
+
+    fn memories(&self) -> usize {
+        self.limits.memories()
     }
 }
 
@@ -48,7 +149,48 @@ pub struct WasiState {
     pub table: wasmtime_wasi::ResourceTable,
     pub http: wasmtime_wasi_http::WasiHttpCtx,
     pub wasi_config_vars: WasiConfigVariables,
+    /// The variables of `wasi_config_vars`, which can't be read back, for lookups
+    pub config_values: HashMap<String, String>,
     pub resource_limiter: Option<CustomResourceLimiter>,
+    /// Fuel each tool invocation starts with; `None` leaves invocations unmetered
+    pub fuel_limit: Option<u64>,
+    /// Wall-clock time each tool invocation may run under the policy
+    pub timeout_limit: Option<Duration>,
+    /// When the current invocation times out
+    pub deadline: Option<Instant>,
+    /// Cancelled when the caller of the current invocation abandons it
+    pub cancellation: Option<CancellationToken>,
+    /// Guest profiler sampling this store, when profiling is enabled for the component
+    pub guest_profiler: Option<Box<wasmtime::GuestProfiler>>,
+    /// MCP sampling caps and the sampler for the current invocation
+    pub sampling: SamplingState,
+    /// Where the current invocation's progress reports go
+    pub progress: ProgressState,
+    /// Key-value quota and the component's state
+    pub keyvalue: KeyValueState,
+    /// Vector quota and the component's collections
+    pub vector: VectorState,
+    /// Whether the session context is granted and the context of the current invocation
+    pub context: ContextState,
+    /// Sites the browser may load and the host's browser
+    pub browser: BrowserState,
+    /// Recipients the component may email and the manager's outbox
+    pub email: EmailState,
+    /// Where the component publishes resources
+    pub resources: ResourceState,
+    /// Descriptors opened below glob-restricted preopens
+    pub fs_grants: FsGrants,
+    /// Slot held against the component's concurrent instance limit while the store lives
+    pub instance_slot: Option<OwnedSemaphorePermit>,
+}
+
+impl WasiState {
+    /// The last policy limit the store's limiter refused growth past, with the limit.
+    pub(crate) fn limit_exceeded(&self) -> Option<(LimitedResource, u64)> {
+        self.resource_limiter
+            .as_ref()
+            .and_then(CustomResourceLimiter::exceeded)
+    }
 }
 
 impl wasmtime_wasi::WasiView for WasiState {
@@ -70,9 +212,38 @@ impl WasiHttpView for WasiState {
     }
 }
 
+/// Embedder hook that customizes the [`WasiCtxBuilder`] of a component (identified by the
+/// first argument) before it is instantiated.
+///
+/// Hooks run after stdio and arguments are set up, so they can replace the stdout and stderr
+/// sinks, and before the policy is applied: network switches are reset to what the policy
+/// allows afterwards, and policy environment variables win over ones set by a hook.
+pub type WasiCtxHook = Arc<dyn Fn(&str, &mut WasiCtxBuilder) -> anyhow::Result<()> + Send + Sync>;
+
 impl WasiStateTemplate {
+    /// The WebAssembly proposals granted per component that the policy grants.
+    pub fn granted_proposals(&self) -> BTreeSet<WasmProposal> {
+        let mut granted = BTreeSet::new();
+        if self.threads.is_some() {
+            granted.insert(WasmProposal::Threads);
+        }
+        if self.memory64 {
+            granted.insert(WasmProposal::Memory64);
+        }
+        granted
+    }
+
     /// Creates a new `WasiState` from the template.
     pub fn build(&self) -> anyhow::Result<WasiState> {
+        self.build_with(|_| Ok(()))
+    }
+
+    /// Creates a new `WasiState` from the template, letting `customize` adjust the WASI context
+    /// before the policy is applied to it.
+    pub fn build_with(
+        &self,
+        customize: impl FnOnce(&mut WasiCtxBuilder) -> anyhow::Result<()>,
+    ) -> anyhow::Result<WasiState> {
         let mut ctx_builder = WasiCtxBuilder::new();
         if self.allow_stdout {
             ctx_builder.inherit_stdout();
@@ -84,10 +255,16 @@ impl WasiStateTemplate {
         if self.allow_args {
             ctx_builder.inherit_args();
         }
+        customize(&mut ctx_builder)?;
         // Note(mossaka): removed ctx_builder.inherit_network() to implement deny-by-default network policy
         // For HTTP requests to work, we need to allow TCP and DNS lookups when there are network permissions
         // But HTTP-level filtering happens in WassetteWasiState::send_request
-        if self.network_perms.allow_tcp || !self.allowed_hosts.is_empty() {
+        let allows_requests = self
+            .network_rules
+            .allow
+            .as_ref()
+            .is_some_and(|allow| !allow.is_empty());
+        if self.network_perms.allow_tcp || allows_requests {
             ctx_builder.allow_tcp(true);
             ctx_builder.allow_ip_name_lookup(true);
         } else {
@@ -103,6 +280,14 @@ impl WasiStateTemplate {
                 preopened_dir.file_perms,
             )?;
         }
+        for mount in &self.mounts {
+            ctx_builder.preopened_dir(
+                mounts::placeholder_dir()?,
+                mount.guest_path(),
+                wasmtime_wasi::DirPerms::READ,
+                wasmtime_wasi::FilePerms::READ,
+            )?;
+        }
 
         // Inject forwarded config variables as real WASI environment variables so that
         // component code using std::env::var can observe them.
@@ -110,15 +295,51 @@ impl WasiStateTemplate {
             ctx_builder.env(k, v);
         }
 
+        let mut fs_grants = FsGrants::new(&self.preopened_dirs, &self.mounts);
+        fs_grants.open_files = OpenFiles::new(self.open_files_limit);
         Ok(WasiState {
             ctx: ctx_builder.build(),
             table: wasmtime_wasi::ResourceTable::default(),
             http: WasiHttpCtx::new(),
             wasi_config_vars: WasiConfigVariables::from_iter(self.config_vars.clone()),
-            resource_limiter: self
-                .store_limits
-                .as_ref()
-                .map(|limits| CustomResourceLimiter::new(limits.clone())),
+            config_values: self.config_vars.clone(),
+            resource_limiter: self.store_limits.as_ref().map(|limits| {
+                CustomResourceLimiter::new(limits.clone())
+                    .with_policy_limits(self.memory_limit, self.table_elements_limit)
+            }),
+            fuel_limit: self.fuel_limit,
+            timeout_limit: self.timeout_limit,
+            deadline: None,
+            cancellation: None,
+            guest_profiler: None,
+            sampling: SamplingState {
+                limits: self.sampling,
+                ..Default::default()
+            },
+            progress: ProgressState::default(),
+            keyvalue: KeyValueState {
+                quota: self.keyvalue.clone(),
+                ..Default::default()
+            },
+            vector: VectorState {
+                quota: self.vector,
+                ..Default::default()
+            },
+            context: ContextState {
+                allowed: self.context,
+                ..Default::default()
+            },
+            browser: BrowserState {
+                limits: self.browser.clone(),
+                ..Default::default()
+            },
+            email: EmailState {
+                limits: self.email.clone(),
+                ..Default::default()
+            },
+            resources: ResourceState::default(),
+            fs_grants,
+            instance_slot: None,
         })
     }
 }
@@ -130,6 +351,8 @@ pub struct PreopenedDir {
     pub guest_path: String,
     pub dir_perms: wasmtime_wasi::DirPerms,
     pub file_perms: wasmtime_wasi::FilePerms,
+    /// Glob patterns restricting which paths below the directory are reachable
+    pub path_filter: Option<PathFilter>,
 }
 
 /// A struct that presents the network permissions passed to wasmtime_wasi::WasiContextBuilder
@@ -156,12 +379,52 @@ pub struct WasiStateTemplate {
     pub config_vars: HashMap<String, String>,
     /// Preopened directories for filesystem access
     pub preopened_dirs: Vec<PreopenedDir>,
+    /// Read-only mounts of host archives and files
+    pub mounts: Vec<VirtualMount>,
     /// Allowed network hosts for HTTP requests
     pub allowed_hosts: HashSet<String>,
+    /// Network rules outgoing HTTP requests are checked against
+    pub network_rules: PermissionList<NetworkPermission>,
+    /// Headers added to allowed outgoing HTTP requests
+    pub request_headers: Vec<RequestHeader>,
     /// Memory limit in bytes for the component
     pub memory_limit: Option<u64>,
-    /// Store limits for wasmtime (built from memory_limit)
+    /// Store limits for wasmtime (built from memory_limit and the table limits)
     pub store_limits: Option<wasmtime::StoreLimits>,
+    /// Maximum number of elements in any one table
+    pub table_elements_limit: Option<u32>,
+    /// Fuel a single tool invocation may consume
+    pub fuel_limit: Option<u64>,
+    /// Wall-clock time a single tool invocation may run
+    pub timeout_limit: Option<Duration>,
+    /// Maximum number of descriptors a store may hold open
+    pub open_files_limit: Option<usize>,
+    /// Maximum number of concurrently running instances of the component
+    pub instance_limit: Option<usize>,
+    /// Caps on the component's tool calls, enforced by the server dispatching them
+    pub invocation_limits: InvocationLimits,
+    /// MCP sampling caps; `None` denies sampling
+    pub sampling: Option<SamplingLimits>,
+    /// Key-value quota; `None` denies key-value state
+    pub keyvalue: Option<KeyValueQuota>,
+    /// Vector quota; `None` denies vector collections
+    pub vector: Option<VectorQuota>,
+    /// Whether the component may read the session context
+    pub context: bool,
+    /// Sites the component may load in the headless browser; `None` denies the browser
+    pub browser: Option<BrowserLimits>,
+    /// Recipients the component may email and its hourly quota; `None` denies email
+    pub email: Option<EmailLimits>,
+    /// Threads the component may run; `None` denies shared memory
+    pub threads: Option<u32>,
+    /// Whether the component may use 64-bit memories
+    pub memory64: bool,
+    /// How long results of each cached tool stay valid, keyed by tool name
+    pub cache_ttls: HashMap<String, Duration>,
+    /// Hours at which each scheduled tool may be called, keyed by tool name
+    pub(crate) tool_hours: HashMap<String, ToolHours>,
+    /// Granted environment variables whose values hold references, resolved at instantiation
+    pub(crate) env_references: HashMap<String, String>,
 }
 
 impl Default for WasiStateTemplate {
@@ -173,9 +436,29 @@ impl Default for WasiStateTemplate {
             network_perms: NetworkPermissions::default(),
             config_vars: HashMap::new(),
             preopened_dirs: Vec::new(),
+            mounts: Vec::new(),
             allowed_hosts: HashSet::new(),
+            network_rules: PermissionList::default(),
+            request_headers: Vec::new(),
             memory_limit: None,
             store_limits: None,
+            table_elements_limit: None,
+            fuel_limit: None,
+            timeout_limit: None,
+            open_files_limit: None,
+            instance_limit: None,
+            invocation_limits: InvocationLimits::default(),
+            sampling: None,
+            keyvalue: None,
+            vector: None,
+            context: false,
+            browser: None,
+            email: None,
+            threads: None,
+            memory64: false,
+            cache_ttls: HashMap::new(),
+            tool_hours: HashMap::new(),
+            env_references: HashMap::new(),
         }
     }
 }
@@ -188,42 +471,82 @@ pub fn create_wasi_state_template_from_policy(
     secrets: Option<&HashMap<String, String>>,
 ) -> anyhow::Result<WasiStateTemplate> {
     let env_vars = extract_env_vars(policy, environment_vars, secrets)?;
+    let env_references = extract_env_references(policy, environment_vars)?;
     let network_perms = extract_network_perms(policy);
     let preopened_dirs = extract_storage_permissions(policy, plugin_dir)?;
+    let mounts = extract_mounts(policy, plugin_dir, &preopened_dirs)?;
     let allowed_hosts = extract_allowed_hosts(policy);
     let memory_limit = extract_memory_limit(policy)?;
-    let store_limits = memory_limit
-        .map(|limit| -> anyhow::Result<wasmtime::StoreLimits> {
-            let limit_usize = limit.try_into().map_err(|_| {
-                anyhow::anyhow!("Memory limit {} too large for target architecture", limit)
-            })?;
-            Ok(wasmtime::StoreLimitsBuilder::new()
-                .memory_size(limit_usize)
-                .build())
-        })
-        .transpose()?;
+    let limit_values = extract_limit_values(policy);
+    let store_limits = build_store_limits(memory_limit, limit_values)?;
 
     Ok(WasiStateTemplate {
         network_perms,
         config_vars: env_vars,
         preopened_dirs,
+        mounts,
         allowed_hosts,
+        network_rules: policy.permissions.network.clone().unwrap_or_default(),
+        request_headers: policy.permissions.headers.clone().unwrap_or_default(),
         memory_limit,
         store_limits,
+        table_elements_limit: limit_values.and_then(|limits| limits.table_elements),
+        fuel_limit: limit_values.and_then(|limits| limits.fuel),
+        timeout_limit: limit_values
+            .and_then(|limits| limits.timeout.as_ref())
+            .map(|timeout| timeout.to_duration())
+            .transpose()?,
+        open_files_limit: limit_values
+            .and_then(|limits| limits.open_files)
+            .map(|n| n as usize),
+        instance_limit: limit_values
+            .and_then(|limits| limits.instances)
+            .map(|n| n as usize),
+        invocation_limits: InvocationLimits {
+            max_concurrent: limit_values.and_then(|limits| limits.max_concurrent_invocations),
+            per_minute: limit_values.and_then(|limits| limits.rate_limit),
+        },
+        sampling: SamplingLimits::from_policy(policy),
+        keyvalue: KeyValueQuota::from_policy(policy)?,
+        vector: VectorQuota::from_policy(policy)?,
+        context: session_context::allowed_by_policy(policy),
+        browser: BrowserLimits::from_policy(policy),
+        email: EmailLimits::from_policy(policy),
+        threads: policy
+            .permissions
+            .resources
+            .as_ref()
+            .and_then(|resources| resources.threads),
+        memory64: policy
+            .permissions
+            .resources
+            .as_ref()
+            .is_some_and(|resources| resources.memory64),
+        cache_ttls: extract_cache_ttls(policy)?,
+        tool_hours: ToolHours::from_policy(policy)?,
+        env_references,
         ..Default::default()
     })
 }
 
-pub(crate) fn extract_env_vars(
+/// The environment variables `policy` gives a component, with their values as written and
+/// where each comes from. See [`env_references`](crate::env_references) for the precedence.
+pub(crate) fn collect_env_vars(
     policy: &PolicyDocument,
     environment_vars: &HashMap<String, String>,
     secrets: Option<&HashMap<String, String>>,
-) -> anyhow::Result<HashMap<String, String>> {
+) -> HashMap<String, (String, VariableSource)> {
     let mut env_vars = HashMap::new();
 
-    // Add secrets first (lowest precedence)
+    // Add secrets first (lowest precedence). The SMTP settings of a component allowed to send
+    // email are for the host's use only.
     if let Some(secrets_map) = secrets {
-        env_vars.extend(secrets_map.clone());
+        let email = policy.permissions.email.is_some();
+        env_vars.extend(
+            email::visible_secrets(secrets_map, email)
+                .into_iter()
+                .map(|(key, value)| (key, (value, VariableSource::Secret))),
+        );
     }
 
     // Add inherited environment vars (middle precedence)
@@ -234,14 +557,58 @@ pub(crate) fn extract_env_vars(
     if let Some(env_perms) = &policy.permissions.environment {
         if let Some(env_allow_vec) = &env_perms.allow {
             for env_allow in env_allow_vec {
-                if let Some(value) = environment_vars.get(&env_allow.key) {
-                    env_vars.insert(env_allow.key.clone(), value.clone());
+                let value = match &env_allow.value {
+                    Some(value) => Some((value.clone(), VariableSource::Policy)),
+                    None => environment_vars
+                        .get(&env_allow.key)
+                        .map(|value| (value.clone(), VariableSource::Environment)),
+                };
+                if let Some(value) = value {
+                    env_vars.insert(env_allow.key.clone(), value);
                 }
             }
         }
     }
 
-    Ok(env_vars)
+    env_vars
+}
+
+/// Whether the value of a variable from `source` is resolved at instantiation. Malformed
+/// references count, so that they are reported.
+fn has_references(value: &str, source: VariableSource) -> bool {
+    source != VariableSource::Secret
+        && !matches!(env_references::references(value), Ok(references) if references.is_empty())
+}
+
+/// The environment variables `policy` gives a component whose values are known when the policy
+/// is attached.
+pub(crate) fn extract_env_vars(
+    policy: &PolicyDocument,
+    environment_vars: &HashMap<String, String>,
+    secrets: Option<&HashMap<String, String>>,
+) -> anyhow::Result<HashMap<String, String>> {
+    Ok(collect_env_vars(policy, environment_vars, secrets)
+        .into_iter()
+        .filter(|(_, (value, source))| !has_references(value, *source))
+        .map(|(key, (value, _))| (key, value))
+        .collect())
+}
+
+/// The environment variables `policy` grants whose values refer to secrets or server
+/// environment variables, as written.
+pub(crate) fn extract_env_references(
+    policy: &PolicyDocument,
+    environment_vars: &HashMap<String, String>,
+) -> anyhow::Result<HashMap<String, String>> {
+    let mut references = HashMap::new();
+    for (key, (value, source)) in collect_env_vars(policy, environment_vars, None) {
+        if has_references(&value, source) {
+            env_references::references(&value)
+                .with_context(|| format!("Invalid value of environment variable {key}"))?;
+            references.insert(key, value);
+        }
+    }
+    Ok(references)
 }
 
 pub(crate) fn extract_network_perms(policy: &PolicyDocument) -> NetworkPermissions {
@@ -282,21 +649,37 @@ pub(crate) fn extract_storage_permissions(
     policy: &PolicyDocument,
     plugin_dir: &Path,
 ) -> anyhow::Result<Vec<PreopenedDir>> {
-    let mut preopened_dirs = Vec::new();
+    let mut preopened_dirs: Vec<PreopenedDir> = Vec::new();
     if let Some(storage) = &policy.permissions.storage {
         if let Some(allow) = &storage.allow {
             for storage_permission in allow {
-                if storage_permission.uri.starts_with("fs://") {
-                    let uri = storage_permission.uri.strip_prefix("fs://").unwrap();
-                    let path = Path::new(uri);
+                if let Some(StorageKind::Filesystem(uri)) = storage_permission.kind() {
+                    let uri = expand_home(uri);
+                    // Globs preopen their literal prefix and filter the paths below it.
+                    let (base, path_filter) = fs_grants::split_glob(&uri);
+                    let path = Path::new(&base);
                     let (file_perms, dir_perms) = calculate_permissions(&storage_permission.access);
                     let guest_path = path.to_string_lossy().to_string();
                     let host_path = plugin_dir.join(path);
+                    if let Some(existing) = preopened_dirs.iter_mut().find(|dir| {
+                        dir.host_path == host_path
+                            && dir.dir_perms == dir_perms
+                            && dir.file_perms == file_perms
+                    }) {
+                        // Several globs below the same directory share its preopen; a grant
+                        // of the whole directory lifts the filter.
+                        match (&mut existing.path_filter, path_filter) {
+                            (Some(filter), Some(other)) => filter.extend(other),
+                            (filter, _) => *filter = None,
+                        }
+                        continue;
+                    }
                     preopened_dirs.push(PreopenedDir {
                         host_path,
                         guest_path,
                         dir_perms,
                         file_perms,
+                        path_filter,
                     });
                 }
             }
@@ -305,6 +688,43 @@ pub(crate) fn extract_storage_permissions(
     Ok(preopened_dirs)
 }
 
+/// Index the archives and files the policy mounts. A mount can't share its directory with a
+/// storage grant, since both would be preopened at the same path.
+pub(crate) fn extract_mounts(
+    policy: &PolicyDocument,
+    plugin_dir: &Path,
+    preopened_dirs: &[PreopenedDir],
+) -> anyhow::Result<Vec<VirtualMount>> {
+    let Some(mounts) = &policy.permissions.mounts else {
+        return Ok(Vec::new());
+    };
+    let mounts = mounts::from_policy(mounts, plugin_dir)?;
+    for mount in &mounts {
+        if preopened_dirs
+            .iter()
+            .any(|dir| Path::new(&dir.guest_path) == Path::new(mount.guest_path()))
+        {
+            anyhow::bail!(
+                "Mount at {} conflicts with a storage permission for the same directory",
+                mount.guest_path()
+            );
+        }
+    }
+    Ok(mounts)
+}
+
+/// Expand a leading `~` to the user's home directory.
+pub(crate) fn expand_home(path: &str) -> String {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ => return path.to_string(),
+    };
+    match etcetera::home_dir() {
+        Ok(home) => format!("{}{rest}", home.display()),
+        Err(_) => path.to_string(),
+    }
+}
+
 pub(crate) fn calculate_permissions(
     access_types: &[AccessType],
 ) -> (wasmtime_wasi::FilePerms, wasmtime_wasi::DirPerms) {
@@ -331,6 +751,49 @@ pub(crate) fn calculate_permissions(
     (file_perms, dir_perms)
 }
 
+/// The k8s-style `resources.limits` section of the policy document, if any
+fn extract_limit_values(policy: &PolicyDocument) -> Option<&ResourceLimitValues> {
+    policy.permissions.resources.as_ref()?.limits.as_ref()
+}
+
+/// Build the wasmtime store limits for the memory and table limits, if any is set
+fn build_store_limits(
+    memory_limit: Option<u64>,
+    limits: Option<&ResourceLimitValues>,
+) -> anyhow::Result<Option<wasmtime::StoreLimits>> {
+    let tables = limits.and_then(|limits| limits.tables);
+    let table_elements = limits.and_then(|limits| limits.table_elements);
+    if memory_limit.is_none() && tables.is_none() && table_elements.is_none() {
+        return Ok(None);
+    }
+
+    let mut builder = wasmtime::StoreLimitsBuilder::new();
+    if let Some(limit) = memory_limit {
+        let limit_usize = limit.try_into().map_err(|_| {
+            anyhow::anyhow!("Memory limit {} too large for target architecture", limit)
+        })?;
+        builder = builder.memory_size(limit_usize);
+    }
+    if let Some(tables) = tables {
+        builder = builder.tables(tables as usize);
+    }
+    if let Some(elements) = table_elements {
+        builder = builder.table_elements(elements as usize);
+    }
+    Ok(Some(builder.build()))
+}
+
+/// Extract the result cache TTL of each tool listed in the policy's `cache` section
+pub(crate) fn extract_cache_ttls(
+    policy: &PolicyDocument,
+) -> anyhow::Result<HashMap<String, Duration>> {
+    let mut ttls = HashMap::new();
+    for entry in policy.permissions.cache.iter().flatten() {
+        ttls.insert(entry.tool.clone(), entry.ttl.to_duration()?);
+    }
+    Ok(ttls)
+}
+
 /// Extract memory limit from the policy document
 pub(crate) fn extract_memory_limit(policy: &PolicyDocument) -> anyhow::Result<Option<u64>> {
     if let Some(resources) = &policy.permissions.resources {
@@ -532,6 +995,91 @@ permissions:
         assert!(extracted_vars.is_empty());
     }
 
+    #[test]
+    fn test_extract_environment_variables_hides_smtp_secrets() {
+        let secrets = HashMap::from([
+            ("SMTP_PASSWORD".to_string(), "hunter2".to_string()),
+            ("API_TOKEN".to_string(), "abc".to_string()),
+        ]);
+
+        let policy = create_zero_permission_policy();
+        let extracted_vars = extract_env_vars(&policy, &HashMap::new(), Some(&secrets)).unwrap();
+        assert_eq!(extracted_vars, secrets);
+
+        let policy = PolicyParser::parse_str(
+            "version: \"1.0\"\npermissions:\n  email:\n    allow:\n    - domain: example.com\n",
+        )
+        .unwrap();
+        let extracted_vars = extract_env_vars(&policy, &HashMap::new(), Some(&secrets)).unwrap();
+        assert_eq!(
+            extracted_vars,
+            HashMap::from([("API_TOKEN".to_string(), "abc".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_env_vars_with_references_are_left_for_instantiation() {
+        let policy = PolicyParser::parse_str(
+            r#"
+version: "1.0"
+permissions:
+  environment:
+    allow:
+      - key: API_TOKEN
+        value: "Bearer ${secret:api-token}"
+      - key: DATABASE_URL
+      - key: LEVEL
+        value: debug
+"#,
+        )
+        .unwrap();
+        let environment = HashMap::from([
+            (
+                "DATABASE_URL".to_string(),
+                "postgres://${env:DB_HOST}".to_string(),
+            ),
+            ("LEVEL".to_string(), "info".to_string()),
+        ]);
+        let secrets = HashMap::from([("API_TOKEN".to_string(), "stored".to_string())]);
+
+        let template = create_wasi_state_template_from_policy(
+            &policy,
+            Path::new("/tmp"),
+            &environment,
+            Some(&secrets),
+        )
+        .unwrap();
+        assert_eq!(
+            template.config_vars,
+            HashMap::from([("LEVEL".to_string(), "debug".to_string())])
+        );
+        assert_eq!(
+            template.env_references,
+            HashMap::from([
+                (
+                    "API_TOKEN".to_string(),
+                    "Bearer ${secret:api-token}".to_string()
+                ),
+                (
+                    "DATABASE_URL".to_string(),
+                    "postgres://${env:DB_HOST}".to_string()
+                ),
+            ])
+        );
+
+        let malformed = PolicyParser::parse_str(
+            "version: \"1.0\"\npermissions:\n  environment:\n    allow:\n    - key: A\n      value: \"${secret:\"\n",
+        )
+        .unwrap();
+        assert!(create_wasi_state_template_from_policy(
+            &malformed,
+            Path::new("/tmp"),
+            &environment,
+            None
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_extract_network_permissions_with_allow() {
         let policy = create_test_policy();
@@ -652,6 +1200,39 @@ permissions:
         assert!(preopened_dirs.is_empty());
     }
 
+    #[test]
+    fn test_extract_storage_permissions_globs_share_a_filtered_preopen() {
+        let temp_dir = TempDir::new().unwrap();
+        let plugin_dir = temp_dir.path();
+
+        let yaml_content = r#"
+version: "1.0"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://notes/**/*.md"
+        access: ["read"]
+      - uri: "fs://notes/*.txt"
+        access: ["read"]
+      - uri: "fs://data/**"
+        access: ["read"]
+"#;
+        let policy = PolicyParser::parse_str(yaml_content).unwrap();
+        let preopened_dirs = extract_storage_permissions(&policy, plugin_dir).unwrap();
+
+        assert_eq!(preopened_dirs.len(), 2);
+        let notes = &preopened_dirs[0];
+        assert_eq!(notes.guest_path, "notes");
+        let filter = notes.path_filter.as_ref().unwrap();
+        assert!(filter.matches(Path::new("a/b.md")));
+        assert!(filter.matches(Path::new("c.txt")));
+        assert!(!filter.matches(Path::new("a/c.txt")));
+
+        let data = &preopened_dirs[1];
+        assert_eq!(data.host_path, plugin_dir.join("data"));
+        assert!(data.path_filter.is_none());
+    }
+
     #[test]
     fn test_extract_storage_permissions_duplicated_access_has_no_effect() {
         let temp_dir = TempDir::new().unwrap();
@@ -688,6 +1269,45 @@ permissions:
         assert_eq!(template.preopened_dirs.len(), 3);
     }
 
+    #[test]
+    fn test_create_wasi_state_template_with_mounts() {
+        let temp_dir = TempDir::new().unwrap();
+        let plugin_dir = temp_dir.path();
+        std::fs::write(plugin_dir.join("model.bin"), b"weights").unwrap();
+        let policy_with_mount = |at: &str| {
+            let yaml_content = format!(
+                r#"
+version: "1.0"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://{}"
+        access: ["read"]
+  mounts:
+    - file: model.bin
+      at: {at}
+"#,
+                plugin_dir.display()
+            );
+            PolicyParser::parse_str(&yaml_content).unwrap()
+        };
+
+        let policy = policy_with_mount("/models/model.bin");
+        let template =
+            create_wasi_state_template_from_policy(&policy, plugin_dir, &HashMap::new(), None)
+                .unwrap();
+        assert_eq!(template.mounts.len(), 1);
+        assert_eq!(template.mounts[0].guest_path(), "/models");
+        template.build().unwrap();
+
+        // The mount would be preopened at the same path as the storage grant.
+        let policy = policy_with_mount(&format!("{}/model.bin", plugin_dir.display()));
+        assert!(
+            create_wasi_state_template_from_policy(&policy, plugin_dir, &HashMap::new(), None)
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_create_wasi_state_template_from_policy_no_permissions() {
         let temp_dir = TempDir::new().unwrap();
@@ -762,6 +1382,168 @@ permissions:
 
         assert_eq!(template.memory_limit, Some(512 * 1024 * 1024));
         assert!(template.store_limits.is_some());
+        assert_eq!(template.threads, None);
+    }
+
+    #[test]
+    fn test_create_wasi_state_template_with_threads() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let yaml_content = r#"
+version: "1.0"
+description: "Policy granting threads"
+permissions:
+  resources:
+    threads: 4
+    limits:
+      memory: "64Mi"
+"#;
+        let policy = PolicyParser::parse_str(yaml_content)?;
+        let template = create_wasi_state_template_from_policy(
+            &policy,
+            temp_dir.path(),
+            &HashMap::new(),
+            None,
+        )?;
+        assert_eq!(template.threads, Some(4));
+        assert_eq!(template.memory_limit, Some(64 * 1024 * 1024));
+        assert_eq!(
+            template.granted_proposals(),
+            BTreeSet::from([WasmProposal::Threads])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_wasi_state_template_with_memory64() -> anyhow::Result<()> {
+        use wasmtime::ResourceLimiter as _;
+
+        let temp_dir = TempDir::new().unwrap();
+        let yaml_content = r#"
+version: "1.0"
+description: "Policy granting 64-bit memories"
+permissions:
+  resources:
+    memory64: true
+    limits:
+      memory: "6Gi"
+"#;
+        let policy = PolicyParser::parse_str(yaml_content)?;
+        let template = create_wasi_state_template_from_policy(
+            &policy,
+            temp_dir.path(),
+            &HashMap::new(),
+            None,
+        )?;
+        assert_eq!(
+            template.granted_proposals(),
+            BTreeSet::from([WasmProposal::Memory64])
+        );
+
+        // The memory limit still applies beyond 4GiB.
+        let mut limiter = template.build()?.resource_limiter.unwrap();
+        assert!(limiter.memory_growing(0, 6 << 30, None)?);
+        assert_eq!(limiter.exceeded(), None);
+        assert!(!limiter.memory_growing(0, (6 << 30) + 1, None)?);
+        assert_eq!(limiter.exceeded(), Some((LimitedResource::Memory, 6 << 30)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_wasi_state_template_with_count_limits() -> anyhow::Result<()> {
+        use wasmtime::ResourceLimiter as _;
+
+        let temp_dir = TempDir::new().unwrap();
+        let yaml_content = r#"
+version: "1.0"
+description: "Policy with count limits"
+permissions:
+  resources:
+    limits:
+      open-files: 16
+      instances: 2
+      rate-limit: 30
+      tables: 4
+      table-elements: 1000
+      fuel: 5000
+      timeout: "90s"
+"#;
+        let policy = PolicyParser::parse_str(yaml_content)?;
+        let template = create_wasi_state_template_from_policy(
+            &policy,
+            temp_dir.path(),
+            &HashMap::new(),
+            None,
+        )?;
+        assert_eq!(template.memory_limit, None);
+        assert_eq!(template.open_files_limit, Some(16));
+        assert_eq!(template.instance_limit, Some(2));
+        assert_eq!(
+            template.invocation_limits,
+            InvocationLimits {
+                max_concurrent: None,
+                per_minute: Some(30),
+            }
+        );
+        assert_eq!(template.fuel_limit, Some(5000));
+        assert_eq!(template.timeout_limit, Some(Duration::from_secs(90)));
+
+        let state = template.build()?;
+        assert_eq!(state.fuel_limit, Some(5000));
+        assert_eq!(state.timeout_limit, Some(Duration::from_secs(90)));
+        let mut limiter = state.resource_limiter.unwrap();
+        assert_eq!(limiter.tables(), 4);
+        assert!(limiter.table_growing(0, 1000, None)?);
+        assert!(!limiter.table_growing(0, 1001, None)?);
+        assert_eq!(
+            limiter.exceeded(),
+            Some((LimitedResource::TableElements, 1000))
+        );
+        // Memory stays unlimited when only table limits are set, and refusals under the
+        // module's own maximum aren't the policy's.
+        assert!(limiter.memory_growing(0, 1 << 30, None)?);
+        assert!(!limiter.memory_growing(0, 2 << 20, Some(1 << 20))?);
+        assert_eq!(
+            limiter.exceeded(),
+            Some((LimitedResource::TableElements, 1000))
+        );
+        // Only allowed growth counts towards the memory in use.
+        assert_eq!(limiter.memory_usage(), 1 << 30);
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_wasi_state_template_with_cache_ttls() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let yaml_content = r#"
+version: "1.0"
+description: "Policy with cached tools"
+permissions:
+  cache:
+    - tool: get-weather
+      ttl: 5m
+    - tool: lookup
+      ttl: 30
+"#;
+        let policy = PolicyParser::parse_str(yaml_content)?;
+        let template = create_wasi_state_template_from_policy(
+            &policy,
+            temp_dir.path(),
+            &HashMap::new(),
+            None,
+        )?;
+        assert_eq!(template.cache_ttls.len(), 2);
+        assert_eq!(template.cache_ttls["get-weather"], Duration::from_secs(300));
+        assert_eq!(template.cache_ttls["lookup"], Duration::from_secs(30));
+
+        let policy = PolicyParser::parse_str("version: \"1.0\"\npermissions: {}\n")?;
+        let template = create_wasi_state_template_from_policy(
+            &policy,
+            temp_dir.path(),
+            &HashMap::new(),
+            None,
+        )?;
+        assert!(template.cache_ttls.is_empty());
+        Ok(())
     }
 
     #[test]
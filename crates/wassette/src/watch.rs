@@ -0,0 +1,216 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Hot reload of components loaded from files.
+//!
+//! In watch mode the manager remembers the file each component was loaded from through a
+//! `file://` URI, and watches the directory holding it. When the file changes, typically
+//! because the component was rebuilt, the component is loaded again from the same URI.
+//! Directories are watched rather than the files themselves, since builds often replace a
+//! file by renaming a new one over it, which ends a watch on the old file. Changes are
+//! collected until the directory has been quiet for [`WATCH_DEBOUNCE`], and a file whose
+//! content is the one last loaded is skipped.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// How long a source directory must be quiet before its changed files are reloaded.
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// The file a component was loaded from.
+struct Source {
+    component_id: String,
+    digest: [u8; 32],
+}
+
+#[derive(Default)]
+struct Sources {
+    /// Source files by canonical path
+    files: HashMap<PathBuf, Source>,
+    /// Watched directories and the number of source files in each
+    dirs: HashMap<PathBuf, usize>,
+}
+
+/// Watches the files components were loaded from.
+pub(crate) struct SourceWatch {
+    watcher: Mutex<(RecommendedWatcher, Sources)>,
+    events: tokio::sync::Mutex<mpsc::UnboundedReceiver<PathBuf>>,
+}
+
+impl SourceWatch {
+    pub(crate) fn new() -> Result<Self> {
+        let (sender, events) = mpsc::unbounded_channel();
+        let watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                    for path in event.paths {
+                        let _ = sender.send(path);
+                    }
+                }
+                Ok(_) => {}
+                Err(error) => warn!(%error, "Error watching component sources"),
+            })
+            .context("Failed to start watching component sources")?;
+        Ok(Self {
+            watcher: Mutex::new((watcher, Sources::default())),
+            events: tokio::sync::Mutex::new(events),
+        })
+    }
+
+    /// Watch `path`, the file `component_id` was just loaded from, in place of the file it was
+    /// loaded from before.
+    pub(crate) async fn track(&self, component_id: &str, path: &Path) -> Result<()> {
+        let path = tokio::fs::canonicalize(path)
+            .await
+            .with_context(|| format!("Failed to resolve {}", path.display()))?;
+        let digest = Sha256::digest(tokio::fs::read(&path).await?).into();
+        let dir = path
+            .parent()
+            .context("Component source has no parent directory")?
+            .to_path_buf();
+
+        self.untrack(component_id);
+        let mut guard = self.watcher.lock().unwrap();
+        let (watcher, sources) = &mut *guard;
+        if !sources.dirs.contains_key(&dir) {
+            watcher
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .with_context(|| format!("Failed to watch {}", dir.display()))?;
+        }
+        *sources.dirs.entry(dir).or_default() += 1;
+        sources.files.insert(
+            path,
+            Source {
+                component_id: component_id.to_string(),
+                digest,
+            },
+        );
+        Ok(())
+    }
+
+    /// Stop watching the file `component_id` was loaded from.
+    pub(crate) fn untrack(&self, component_id: &str) {
+        let mut guard = self.watcher.lock().unwrap();
+        let (watcher, sources) = &mut *guard;
+        let Some(path) = sources
+            .files
+            .iter()
+            .find(|(_, source)| source.component_id == component_id)
+            .map(|(path, _)| path.clone())
+        else {
+            return;
+        };
+        sources.files.remove(&path);
+        let Some(dir) = path.parent() else {
+            return;
+        };
+        if let Some(count) = sources.dirs.get_mut(dir) {
+            *count -= 1;
+            if *count == 0 {
+                sources.dirs.remove(dir);
+                let _ = watcher.unwatch(dir);
+            }
+        }
+    }
+
+    /// The components whose source files changed, with their paths, once the next batch of
+    /// changes has settled. `None` when no more changes can arrive.
+    pub(crate) async fn next_changes(&self) -> Option<Vec<(String, PathBuf)>> {
+        let mut events = self.events.lock().await;
+        loop {
+            let mut paths = vec![events.recv().await?];
+            while let Ok(Some(path)) = tokio::time::timeout(WATCH_DEBOUNCE, events.recv()).await {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+            let mut changed = Vec::new();
+            for path in paths {
+                if let Some(component_id) = self.changed(&path).await {
+                    changed.push((component_id, path));
+                }
+            }
+            if !changed.is_empty() {
+                return Some(changed);
+            }
+        }
+    }
+
+    /// The component loaded from `path`, if the file's content differs from the content last
+    /// seen, which is then remembered so a failing reload isn't retried until the file changes
+    /// again. Files that can't be read, e.g. because they were removed, count as unchanged.
+    async fn changed(&self, path: &Path) -> Option<String> {
+        let digest: [u8; 32] = Sha256::digest(tokio::fs::read(path).await.ok()?).into();
+        let mut guard = self.watcher.lock().unwrap();
+        let source = guard.1.files.get_mut(path)?;
+        if source.digest == digest {
+            return None;
+        }
+        source.digest = digest;
+        Some(source.component_id.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_changes_are_reported_once_settled() -> Result<()> {
+        let dir = TempDir::new()?;
+        let dir_path = dir.path().canonicalize()?;
+        let fetch = dir_path.join("fetch.wasm");
+        let time = dir_path.join("time.wasm");
+        std::fs::write(&fetch, b"v1")?;
+        std::fs::write(&time, b"v1")?;
+
+        let watch = SourceWatch::new()?;
+        watch.track("fetch", &fetch).await?;
+        watch.track("time", &time).await?;
+
+        // Rewriting the same content, and unrelated files, change nothing.
+        std::fs::write(&fetch, b"v1")?;
+        std::fs::write(dir_path.join("notes.txt"), b"hi")?;
+        std::fs::write(&time, b"v2")?;
+        let changes = watch.next_changes().await.unwrap();
+        assert_eq!(changes, [("time".to_string(), time.clone())]);
+
+        // A rebuild that renames a new file into place
+        let staged = dir_path.join("fetch.wasm.tmp");
+        std::fs::write(&staged, b"v2")?;
+        std::fs::rename(&staged, &fetch)?;
+        let changes = watch.next_changes().await.unwrap();
+        assert_eq!(changes, [("fetch".to_string(), fetch.clone())]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_untracked_sources_are_ignored() -> Result<()> {
+        let dir = TempDir::new()?;
+        let dir_path = dir.path().canonicalize()?;
+        let fetch = dir_path.join("fetch.wasm");
+        std::fs::write(&fetch, b"v1")?;
+
+        let watch = SourceWatch::new()?;
+        watch.track("fetch", &fetch).await?;
+        watch.untrack("fetch");
+        assert!(watch.watcher.lock().unwrap().1.dirs.is_empty());
+        assert_eq!(watch.changed(&fetch).await, None);
+
+        watch.track("fetch", &fetch).await?;
+        std::fs::write(&fetch, b"v2")?;
+        assert_eq!(watch.changed(&fetch).await, Some("fetch".to_string()));
+        assert_eq!(watch.changed(&fetch).await, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Read-only access to the MCP client's workspace roots for designated components.
+//!
+//! Clients that support the `roots` capability tell the server which directories make up the
+//! open project. Components opted in as "workspace" components get those directories preopened
+//! read-only, at the same path as on the host, on top of whatever their policy grants. The
+//! grants live only in memory and follow the client's roots as they change.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use tracing::debug;
+
+use crate::wasistate::PreopenedDir;
+
+/// Workspace components and the client roots they can read.
+#[derive(Debug, Default)]
+pub(crate) struct WorkspaceAccess {
+    components: HashSet<String>,
+    roots: RwLock<Vec<PathBuf>>,
+}
+
+impl WorkspaceAccess {
+    pub(crate) fn new(components: HashSet<String>) -> Self {
+        Self {
+            components,
+            roots: RwLock::default(),
+        }
+    }
+
+    /// Whether any component is opted in to workspace access.
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.components.is_empty()
+    }
+
+    /// Whether `component_id` is opted in to workspace access.
+    pub(crate) fn includes(&self, component_id: &str) -> bool {
+        self.components.contains(component_id)
+    }
+
+    /// Replace the workspace roots with the directories named by `uris`, returning the ones
+    /// that were accepted. Roots that aren't `file://` URIs of existing directories are skipped.
+    pub(crate) fn set_roots(&self, uris: &[String]) -> Vec<PathBuf> {
+        let roots: Vec<PathBuf> = uris
+            .iter()
+            .filter_map(|uri| match root_path(uri) {
+                Some(path) => Some(path),
+                None => {
+                    debug!(%uri, "Ignoring workspace root that is not a local directory");
+                    None
+                }
+            })
+            .collect();
+        *self.roots.write().unwrap() = roots.clone();
+        roots
+    }
+
+    /// Current workspace roots.
+    pub(crate) fn roots(&self) -> Vec<PathBuf> {
+        self.roots.read().unwrap().clone()
+    }
+
+    /// Read-only preopens for `component_id`, skipping paths in `existing`.
+    pub(crate) fn preopens_for(
+        &self,
+        component_id: &str,
+        existing: &[PreopenedDir],
+    ) -> Vec<PreopenedDir> {
+        if !self.components.contains(component_id) {
+            return Vec::new();
+        }
+        self.roots
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|root| !existing.iter().any(|dir| dir.host_path == **root))
+            .map(|root| PreopenedDir {
+                host_path: root.clone(),
+                guest_path: root.to_string_lossy().to_string(),
+                dir_perms: wasmtime_wasi::DirPerms::READ,
+                file_perms: wasmtime_wasi::FilePerms::READ,
+                path_filter: None,
+            })
+            .collect()
+    }
+}
+
+fn root_path(uri: &str) -> Option<PathBuf> {
+    let url = url::Url::parse(uri).ok()?;
+    if url.scheme() != "file" {
+        return None;
+    }
+    let path = url.to_file_path().ok()?;
+    path.is_dir().then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roots_are_granted_to_workspace_components_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let uri = url::Url::from_directory_path(dir.path())
+            .unwrap()
+            .to_string();
+
+        let access = WorkspaceAccess::new(HashSet::from(["editor".to_string()]));
+        let accepted = access.set_roots(&[
+            uri,
+            "https://example.com/repo".to_string(),
+            "file:///does/not/exist".to_string(),
+        ]);
+        assert_eq!(accepted, vec![dir.path().to_path_buf()]);
+
+        let preopens = access.preopens_for("editor", &[]);
+        assert_eq!(preopens.len(), 1);
+        assert_eq!(preopens[0].host_path, dir.path());
+        assert_eq!(preopens[0].dir_perms, wasmtime_wasi::DirPerms::READ);
+        assert!(access.preopens_for("other", &[]).is_empty());
+        assert!(access.preopens_for("editor", &preopens).is_empty());
+
+        access.set_roots(&[]);
+        assert!(access.preopens_for("editor", &[]).is_empty());
+    }
+}
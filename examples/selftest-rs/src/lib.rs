@@ -0,0 +1,59 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Adversarial component of `wassette security selftest`. Each export tries one way out of
+//! the sandbox; the self-test calls them under a narrow policy and expects every attempt to
+//! be blocked.
+
+use std::fs::File;
+
+use wasi::http::outgoing_handler;
+use wasi::http::types::{Fields, OutgoingRequest, Scheme};
+
+wit_bindgen::generate!({
+    world: "selftest",
+    path: "wit",
+});
+
+struct Component;
+
+impl Guest for Component {
+    fn read_file(path: String) -> Result<String, String> {
+        std::fs::read_to_string(&path).map_err(|e| e.to_string())
+    }
+
+    fn environment() -> Vec<String> {
+        std::env::vars().map(|(key, _)| key).collect()
+    }
+
+    fn fetch(authority: String) -> Result<u16, String> {
+        let request = OutgoingRequest::new(Fields::new());
+        request
+            .set_scheme(Some(&Scheme::Http))
+            .map_err(|()| "Invalid scheme".to_string())?;
+        request
+            .set_authority(Some(&authority))
+            .map_err(|()| format!("Invalid authority: {authority}"))?;
+        let response = outgoing_handler::handle(request, None).map_err(|e| format!("{e:?}"))?;
+        response.subscribe().block();
+        match response.get() {
+            Some(Ok(Ok(response))) => Ok(response.status()),
+            Some(Ok(Err(e))) => Err(format!("{e:?}")),
+            _ => Err("No response".to_string()),
+        }
+    }
+
+    fn open_files(path: String, count: u32) -> u32 {
+        // Keep every file open, so each open needs a descriptor of its own.
+        let mut files = Vec::new();
+        while files.len() < count as usize {
+            match File::open(&path) {
+                Ok(file) => files.push(file),
+                Err(_) => break,
+            }
+        }
+        files.len() as u32
+    }
+}
+
+export!(Component);
@@ -0,0 +1,196 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Idle shutdown and on-demand activation
+//!
+//! Every editor that launches its own server keeps a process, its compiled components and
+//! their instance pools in memory, mostly doing nothing. With `--idle-timeout`, the server
+//! exits once no MCP request has been in progress for that long, after the component
+//! operations it started have finished. A stdio client starts it again when it next needs
+//! it. The HTTP transports can instead be started by systemd socket activation: the server
+//! then accepts connections on the socket it was handed, and systemd starts it again on the
+//! next connection.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::time::Instant;
+
+/// Requests in progress and the time the last one ended.
+#[derive(Debug)]
+struct Activity {
+    in_progress: usize,
+    last: Instant,
+}
+
+/// Tracks MCP requests to tell when the server has been idle for its timeout.
+#[derive(Debug)]
+pub struct IdleTimer {
+    timeout: Duration,
+    activity: Mutex<Activity>,
+}
+
+impl IdleTimer {
+    /// A timer that expires after `timeout` without requests, counting from now.
+    pub fn new(timeout: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            timeout,
+            activity: Mutex::new(Activity {
+                in_progress: 0,
+                last: Instant::now(),
+            }),
+        })
+    }
+
+    /// Count a request as in progress until the returned guard is dropped.
+    pub fn begin(self: &Arc<Self>) -> Request {
+        let mut activity = self.activity.lock().unwrap();
+        activity.in_progress += 1;
+        activity.last = Instant::now();
+        Request {
+            timer: Arc::clone(self),
+        }
+    }
+
+    /// Resolves once no request has been in progress for the timeout.
+    pub async fn expired(&self) {
+        loop {
+            let remaining = {
+                let activity = self.activity.lock().unwrap();
+                if activity.in_progress > 0 {
+                    self.timeout
+                } else {
+                    self.timeout.saturating_sub(activity.last.elapsed())
+                }
+            };
+            if remaining.is_zero() {
+                tracing::info!(timeout = ?self.timeout, "No requests within the idle timeout");
+                return;
+            }
+            tokio::time::sleep(remaining).await;
+        }
+    }
+}
+
+/// A request counted as in progress.
+pub struct Request {
+    timer: Arc<IdleTimer>,
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        let mut activity = self.timer.activity.lock().unwrap();
+        activity.in_progress -= 1;
+        activity.last = Instant::now();
+    }
+}
+
+/// Resolves on Ctrl-C or, with `idle`, once the server has been idle for its timeout.
+pub async fn shutdown_signal(idle: Option<Arc<IdleTimer>>) {
+    let idle_expired = async {
+        match idle {
+            Some(idle) => idle.expired().await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = idle_expired => {}
+    }
+}
+
+/// The listening socket systemd passed to this process through socket activation, if any.
+#[cfg(unix)]
+pub fn activated_listener() -> Result<Option<tokio::net::TcpListener>> {
+    use std::os::unix::io::FromRawFd;
+
+    use anyhow::Context as _;
+
+    /// First file descriptor systemd passes
+    const LISTEN_FDS_START: i32 = 3;
+
+    let for_this_process = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        == Some(std::process::id());
+    let fds = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse::<u32>().ok())
+        .unwrap_or(0);
+    // Like sd_listen_fds, so that processes this one starts don't take the sockets for theirs.
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(name);
+    }
+    if !for_this_process || fds == 0 {
+        return Ok(None);
+    }
+    if fds > 1 {
+        tracing::warn!(fds, "Only the first socket passed by systemd is used");
+    }
+    // systemd passes the sockets inheritable; keep them from leaking into child processes.
+    // SAFETY: fcntl on a descriptor number touches no memory of this process; it fails with
+    // EBADF if systemd did not actually pass that descriptor.
+    let cloexec = unsafe {
+        let flags = libc::fcntl(LISTEN_FDS_START, libc::F_GETFD);
+        flags >= 0 && libc::fcntl(LISTEN_FDS_START, libc::F_SETFD, flags | libc::FD_CLOEXEC) >= 0
+    };
+    if !cloexec {
+        return Err(std::io::Error::last_os_error())
+            .context("Failed to take the socket passed by systemd");
+    }
+    // SAFETY: with LISTEN_PID naming this process, systemd passed it the listening sockets
+    // starting at LISTEN_FDS_START, and nothing else in the process owns that descriptor.
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    listener.set_nonblocking(true)?;
+    Ok(Some(tokio::net::TcpListener::from_std(listener)?))
+}
+
+/// Socket activation needs a Unix host.
+#[cfg(not(unix))]
+pub fn activated_listener() -> Result<Option<tokio::net::TcpListener>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_expires_after_the_last_request() {
+        let timer = IdleTimer::new(Duration::from_secs(600));
+        let started = Instant::now();
+
+        tokio::time::sleep(Duration::from_secs(300)).await;
+        let request = timer.begin();
+        // A long request keeps the server up.
+        tokio::time::sleep(Duration::from_secs(900)).await;
+        drop(request);
+        let ended = Instant::now();
+
+        timer.expired().await;
+        assert_eq!(ended.elapsed(), Duration::from_secs(600));
+        assert_eq!(started.elapsed(), Duration::from_secs(1800));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_expires_without_requests() {
+        let timer = IdleTimer::new(Duration::from_secs(60));
+        let started = Instant::now();
+        timer.expired().await;
+        assert_eq!(started.elapsed(), Duration::from_secs(60));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_activation_environment_is_consumed() {
+        // Meant for another process, so no descriptor is taken.
+        std::env::set_var("LISTEN_PID", (std::process::id() + 1).to_string());
+        std::env::set_var("LISTEN_FDS", "1");
+        std::env::set_var("LISTEN_FDNAMES", "http");
+        assert!(activated_listener().unwrap().is_none());
+        for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            assert!(std::env::var_os(name).is_none(), "{name} is still set");
+        }
+    }
+}
@@ -0,0 +1,709 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! OAuth 2.1 authorization of the HTTP transports
+//!
+//! Following the MCP authorization specification, Wassette acts as a resource server: it
+//! publishes its protected resource metadata (RFC 9728) naming the authorization servers that
+//! issue tokens for it, and every other request must carry one of their access tokens as
+//! `Authorization: Bearer <token>`. Requests without a valid token get `401 Unauthorized` with a
+//! `WWW-Authenticate` header pointing at the metadata, from which clients discover where to
+//! obtain a token.
+//!
+//! Tokens are JWTs signed with a key from the configured JWKS. They must be issued by one of the
+//! authorization servers, name this server's resource URL in `aud` and not be expired. Their
+//! `scope` decides what the client may do: `wassette:manage` allows the built-in tools that load
+//! components and change their permissions and the server's own resources,
+//! `wassette:component:<id>` allows the tools, prompts and resources of one component and
+//! `wassette:component:*` those of every component.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use axum::extract::{Request, State};
+use axum::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use ring::signature::{
+    RsaPublicKeyComponents, UnparsedPublicKey, ECDSA_P256_SHA256_FIXED, ED25519,
+    RSA_PKCS1_2048_8192_SHA256,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+
+/// Path of the protected resource metadata
+pub const METADATA_PATH: &str = "/.well-known/oauth-protected-resource";
+
+/// Scope allowing the built-in management tools
+pub const MANAGE_SCOPE: &str = "wassette:manage";
+
+/// Prefix of the scopes allowing the tools of a component
+pub const COMPONENT_SCOPE_PREFIX: &str = "wassette:component:";
+
+/// Keys aren't fetched again for an unknown key id sooner than this after the last attempt.
+const MIN_JWKS_REFETCH: Duration = Duration::from_secs(30);
+
+/// Settings of OAuth authorization, under `[auth]` in the configuration file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// URL clients reach this server at, such as `https://wassette.example.com/mcp`; tokens
+    /// must name it in their audience
+    pub resource: String,
+    /// Issuers of the tokens accepted, as published in the metadata
+    pub authorization_servers: Vec<String>,
+    /// URL of the JSON Web Key Set holding the keys tokens are signed with
+    pub jwks_url: String,
+    /// Seconds the keys are used before they are fetched again
+    pub jwks_refresh_secs: u64,
+    /// Seconds of clock skew tolerated when checking a token's expiry
+    pub leeway_secs: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            resource: String::new(),
+            authorization_servers: Vec::new(),
+            jwks_url: String::new(),
+            jwks_refresh_secs: 300,
+            leeway_secs: 60,
+        }
+    }
+}
+
+impl AuthConfig {
+    /// Check that the URLs are set and can be used.
+    pub fn validate(&self) -> Result<()> {
+        check_url("resource", &self.resource)?;
+        check_url("jwks_url", &self.jwks_url)?;
+        if self.authorization_servers.is_empty() {
+            bail!("At least one authorization server must be configured");
+        }
+        for server in &self.authorization_servers {
+            check_url("authorization_servers", server)?;
+        }
+        Ok(())
+    }
+
+    /// URL of the metadata for the resource, as RFC 9728 places it: the well-known path
+    /// inserted between the resource's origin and its path.
+    fn metadata_url(&self) -> String {
+        let url = reqwest::Url::parse(&self.resource).expect("validated resource URL");
+        format!(
+            "{}{METADATA_PATH}{}",
+            url.origin().ascii_serialization(),
+            resource_path(&url)
+        )
+    }
+
+    fn metadata(&self) -> Value {
+        json!({
+            "resource": self.resource,
+            "authorization_servers": self.authorization_servers,
+            "bearer_methods_supported": ["header"],
+            "scopes_supported": [MANAGE_SCOPE, format!("{COMPONENT_SCOPE_PREFIX}*")],
+        })
+    }
+}
+
+/// Refuse URLs that aren't HTTPS, except for local testing.
+fn check_url(field: &str, value: &str) -> Result<()> {
+    let url =
+        reqwest::Url::parse(value).with_context(|| format!("Invalid {field} URL `{value}`"))?;
+    let local = url
+        .host_str()
+        .is_some_and(|host| matches!(host, "localhost" | "127.0.0.1" | "[::1]"));
+    if url.scheme() != "https" && !(url.scheme() == "http" && local) {
+        bail!("The {field} URL `{value}` must use https");
+    }
+    if url.fragment().is_some() {
+        bail!("The {field} URL `{value}` must not have a fragment");
+    }
+    Ok(())
+}
+
+/// Path of a resource URL, empty for the root.
+fn resource_path(url: &reqwest::Url) -> &str {
+    url.path().trim_end_matches('/')
+}
+
+/// What the token of a request allows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Grant {
+    /// Who the token was issued to, for logs
+    pub subject: Option<String>,
+    manage: bool,
+    all_components: bool,
+    components: HashSet<String>,
+}
+
+impl Grant {
+    /// What a token issued to `subject` with `scope` allows.
+    pub(crate) fn from_scope(subject: Option<String>, scope: &str) -> Self {
+        let mut grant = Self {
+            subject,
+            ..Default::default()
+        };
+        for scope in scope.split_whitespace() {
+            if scope == MANAGE_SCOPE {
+                grant.manage = true;
+            } else if let Some(component) = scope.strip_prefix(COMPONENT_SCOPE_PREFIX) {
+                if component == "*" {
+                    grant.all_components = true;
+                } else {
+                    grant.components.insert(component.to_string());
+                }
+            }
+        }
+        grant
+    }
+
+    /// Whether the token allows the built-in management tools.
+    pub fn may_manage(&self) -> bool {
+        self.manage
+    }
+
+    /// Whether the token allows the tools of `component_id`.
+    pub fn may_call(&self, component_id: &str) -> bool {
+        self.all_components || self.components.contains(component_id)
+    }
+}
+
+/// The grant of the HTTP request a message came in, if the server checks tokens.
+pub fn request_grant(extensions: &rmcp::model::Extensions) -> Option<&Grant> {
+    extensions
+        .get::<axum::http::request::Parts>()?
+        .extensions
+        .get::<Grant>()
+}
+
+/// A key of a JSON Web Key Set; only the members needed to verify signatures.
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    alg: Option<String>,
+    #[serde(rename = "use")]
+    key_use: Option<String>,
+    crv: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    iss: Option<String>,
+    sub: Option<String>,
+    aud: Option<Audience>,
+    exp: Option<u64>,
+    nbf: Option<u64>,
+    #[serde(default)]
+    scope: String,
+}
+
+/// Checks the bearer tokens of requests against the configured authorization servers.
+pub struct Authorizer {
+    config: AuthConfig,
+    http_client: reqwest::Client,
+    keys: RwLock<KeyCache>,
+}
+
+impl Authorizer {
+    /// Check bearer tokens as `config` says, fetching keys with `http_client`.
+    pub fn new(config: AuthConfig, http_client: reqwest::Client) -> Result<Self> {
+        config
+            .validate()
+            .context("Invalid authorization configuration")?;
+        Ok(Self {
+            config,
+            http_client,
+            keys: RwLock::default(),
+        })
+    }
+
+    /// What `token` allows, if it is valid.
+    async fn authorize(&self, token: &str) -> Result<Grant> {
+        let (header, _, _) = split_token(token)?;
+        let header: JwtHeader = decode_part(header).context("Malformed token header")?;
+        let keys = self.keys(header.kid.as_deref()).await?;
+        validate_token(token, &keys, &self.config, unix_now())
+    }
+
+    /// The current keys, fetched again once they are stale or when none has the id `kid`.
+    async fn keys(&self, kid: Option<&str>) -> Result<Vec<Jwk>> {
+        let refresh = Duration::from_secs(self.config.jwks_refresh_secs);
+        if let Some(keys) = self.keys.read().await.current(kid, refresh) {
+            return Ok(keys.to_vec());
+        }
+
+        let mut cache = self.keys.write().await;
+        // Another request may have fetched the keys while this one waited for the lock.
+        if let Some(keys) = cache.current(kid, refresh) {
+            return Ok(keys.to_vec());
+        }
+        cache.attempted = Some(Instant::now());
+        let response = self
+            .http_client
+            .get(&self.config.jwks_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to fetch keys from {}", self.config.jwks_url))?;
+        let body = response
+            .bytes()
+            .await
+            .with_context(|| format!("Failed to fetch keys from {}", self.config.jwks_url))?;
+        let set: JwkSet = serde_json::from_slice(&body)
+            .with_context(|| format!("Invalid key set at {}", self.config.jwks_url))?;
+        tracing::debug!(keys = set.keys.len(), "Fetched token signing keys");
+        cache.keys = Some((Instant::now(), set.keys.clone()));
+        Ok(set.keys)
+    }
+}
+
+/// The fetched keys of an [`Authorizer`].
+#[derive(Debug, Default)]
+struct KeyCache {
+    /// When the keys were fetched, and the keys.
+    keys: Option<(Instant, Vec<Jwk>)>,
+    /// When a fetch was last attempted, whether or not it succeeded.
+    attempted: Option<Instant>,
+}
+
+impl KeyCache {
+    /// The keys, unless they are older than `refresh` or lack `kid` while a fetch is due.
+    fn current(&self, kid: Option<&str>, refresh: Duration) -> Option<&[Jwk]> {
+        let (fetched, keys) = self.keys.as_ref()?;
+        let known = kid.is_none_or(|kid| keys.iter().any(|key| key.kid.as_deref() == Some(kid)));
+        let throttled = self
+            .attempted
+            .is_some_and(|attempted| attempted.elapsed() < MIN_JWKS_REFETCH);
+        (fetched.elapsed() < refresh && (known || throttled)).then_some(keys.as_slice())
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+fn split_token(token: &str) -> Result<(&str, &str, &str)> {
+    let mut parts = token.split('.');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(header), Some(claims), Some(signature), None) => Ok((header, claims, signature)),
+        _ => bail!("Token is not a signed JWT"),
+    }
+}
+
+fn decode_part<T: serde::de::DeserializeOwned>(part: &str) -> Result<T> {
+    let bytes = URL_SAFE_NO_PAD.decode(part)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Check the signature and claims of `token` against `keys` and `config` at time `now`.
+fn validate_token(token: &str, keys: &[Jwk], config: &AuthConfig, now: u64) -> Result<Grant> {
+    let (header_part, claims_part, signature_part) = split_token(token)?;
+    let header: JwtHeader = decode_part(header_part).context("Malformed token header")?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature_part)
+        .context("Malformed token signature")?;
+    let message = &token.as_bytes()[..header_part.len() + 1 + claims_part.len()];
+
+    let verified = keys
+        .iter()
+        .filter(|key| header.kid.is_none() || key.kid == header.kid)
+        .filter(|key| key.alg.as_ref().is_none_or(|alg| *alg == header.alg))
+        .filter(|key| {
+            key.key_use
+                .as_deref()
+                .is_none_or(|key_use| key_use == "sig")
+        })
+        .any(|key| verify_signature(&header.alg, key, message, &signature).unwrap_or(false));
+    if !verified {
+        bail!("Token signature doesn't match any key of the authorization server");
+    }
+
+    let claims: Claims = decode_part(claims_part).context("Malformed token claims")?;
+    let same = |a: &str, b: &str| a.trim_end_matches('/') == b.trim_end_matches('/');
+    let issuer = claims.iss.as_deref().unwrap_or_default();
+    if !config
+        .authorization_servers
+        .iter()
+        .any(|server| same(server, issuer))
+    {
+        bail!("Token was issued by `{issuer}`, not by a configured authorization server");
+    }
+    let audience = match &claims.aud {
+        Some(Audience::One(audience)) => std::slice::from_ref(audience),
+        Some(Audience::Many(audiences)) => audiences.as_slice(),
+        None => &[],
+    };
+    if !audience.iter().any(|aud| same(aud, &config.resource)) {
+        bail!("Token was not issued for {}", config.resource);
+    }
+    let Some(exp) = claims.exp else {
+        bail!("Token has no expiry");
+    };
+    if now > exp.saturating_add(config.leeway_secs) {
+        bail!("Token expired");
+    }
+    if claims
+        .nbf
+        .is_some_and(|nbf| now.saturating_add(config.leeway_secs) < nbf)
+    {
+        bail!("Token is not valid yet");
+    }
+    // Calls are told apart by subject, in the audit log, the result cache and the journal,
+    // so a token without one would pass for no token at all.
+    let Some(subject) = claims.sub.filter(|sub| !sub.is_empty()) else {
+        bail!("Token has no subject");
+    };
+    Ok(Grant::from_scope(Some(subject), &claims.scope))
+}
+
+/// Whether `signature` over `message` verifies with `key` for `alg`. Algorithms and keys
+/// other than RS256, ES256 and EdDSA (Ed25519) aren't supported.
+fn verify_signature(alg: &str, key: &Jwk, message: &[u8], signature: &[u8]) -> Result<bool> {
+    let decode = |member: &Option<String>| -> Result<Vec<u8>> {
+        Ok(URL_SAFE_NO_PAD.decode(member.as_deref().context("Incomplete key")?)?)
+    };
+    let verified = match (alg, key.kty.as_str(), key.crv.as_deref()) {
+        ("RS256", "RSA", _) => RsaPublicKeyComponents {
+            n: decode(&key.n)?,
+            e: decode(&key.e)?,
+        }
+        .verify(&RSA_PKCS1_2048_8192_SHA256, message, signature)
+        .is_ok(),
+        ("ES256", "EC", Some("P-256")) => {
+            let point = [vec![0x04], decode(&key.x)?, decode(&key.y)?].concat();
+            UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point)
+                .verify(message, signature)
+                .is_ok()
+        }
+        ("EdDSA", "OKP", Some("Ed25519")) => UnparsedPublicKey::new(&ED25519, decode(&key.x)?)
+            .verify(message, signature)
+            .is_ok(),
+        _ => false,
+    };
+    Ok(verified)
+}
+
+/// Require a valid bearer token on every request to `router`, and serve the protected
+/// resource metadata.
+pub fn apply(router: Router, authorizer: Arc<Authorizer>) -> Router {
+    let metadata = authorizer.config.metadata();
+    let resource = reqwest::Url::parse(&authorizer.config.resource).expect("validated resource");
+    let mut metadata_router = Router::new().route(
+        METADATA_PATH,
+        get({
+            let metadata = metadata.clone();
+            move || async move { Json(metadata) }
+        }),
+    );
+    let path = resource_path(&resource);
+    if !path.is_empty() {
+        metadata_router = metadata_router.route(
+            &format!("{METADATA_PATH}{path}"),
+            get(move || async move { Json(metadata) }),
+        );
+    }
+    router
+        .layer(axum::middleware::from_fn_with_state(
+            authorizer,
+            check_token,
+        ))
+        .merge(metadata_router)
+}
+
+async fn check_token(
+    State(authorizer): State<Arc<Authorizer>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .strip_prefix("Bearer ")
+                .or_else(|| value.strip_prefix("bearer "))
+        })
+        .map(str::trim);
+    let Some(token) = token else {
+        return unauthorized(&authorizer.config, false);
+    };
+    match authorizer.authorize(token).await {
+        Ok(grant) => {
+            tracing::debug!(subject = ?grant.subject, "Authorized request");
+            request.extensions_mut().insert(grant);
+            next.run(request).await
+        }
+        Err(error) => {
+            tracing::info!(
+                error = format!("{error:#}"),
+                "Refused request with invalid token"
+            );
+            unauthorized(&authorizer.config, true)
+        }
+    }
+}
+
+/// `401 Unauthorized` telling the client where to find the metadata, and that its token was
+/// refused if it sent one. Why is only logged, so as not to guide anyone forging tokens.
+fn unauthorized(config: &AuthConfig, invalid_token: bool) -> Response {
+    let mut challenge = format!("Bearer resource_metadata=\"{}\"", config.metadata_url());
+    if invalid_token {
+        challenge.push_str(", error=\"invalid_token\", error_description=\"invalid token\"");
+    }
+    let mut response =
+        (StatusCode::UNAUTHORIZED, "A valid bearer token is required").into_response();
+    if let Ok(challenge) = HeaderValue::from_str(&challenge) {
+        response.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+    use super::*;
+
+    const NOW: u64 = 1_800_000_000;
+
+    fn config() -> AuthConfig {
+        AuthConfig {
+            resource: "https://wassette.example.com/mcp".to_string(),
+            authorization_servers: vec!["https://login.example.com".to_string()],
+            jwks_url: "https://login.example.com/jwks.json".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn sign(alg: &str, kid: &str, claims: Value, sign: impl Fn(&[u8]) -> Vec<u8>) -> String {
+        let header = URL_SAFE_NO_PAD.encode(json!({"alg": alg, "kid": kid}).to_string());
+        let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let message = format!("{header}.{claims}");
+        let signature = URL_SAFE_NO_PAD.encode(sign(message.as_bytes()));
+        format!("{message}.{signature}")
+    }
+
+    fn ed25519_key(kid: &str) -> (Ed25519KeyPair, Jwk) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let jwk = serde_json::from_value(json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "kid": kid,
+            "x": URL_SAFE_NO_PAD.encode(pair.public_key().as_ref()),
+        }))
+        .unwrap();
+        (pair, jwk)
+    }
+
+    fn claims(scope: &str) -> Value {
+        json!({
+            "iss": "https://login.example.com/",
+            "sub": "agent-7",
+            "aud": ["https://wassette.example.com/mcp"],
+            "exp": NOW + 300,
+            "scope": scope,
+        })
+    }
+
+    #[test]
+    fn test_valid_tokens_grant_their_scope() {
+        let (pair, jwk) = ed25519_key("ed");
+        let token = sign("EdDSA", "ed", claims("wassette:component:fetch"), |m| {
+            pair.sign(m).as_ref().to_vec()
+        });
+        let grant = validate_token(&token, &[jwk], &config(), NOW).unwrap();
+        assert_eq!(grant.subject.as_deref(), Some("agent-7"));
+        assert!(grant.may_call("fetch"));
+        assert!(!grant.may_call("filesystem"));
+        assert!(!grant.may_manage());
+
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap();
+        let point = pair.public_key().as_ref();
+        let jwk: Jwk = serde_json::from_value(json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "kid": "ec",
+            "alg": "ES256",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+        }))
+        .unwrap();
+        let token = sign(
+            "ES256",
+            "ec",
+            claims("wassette:manage wassette:component:*"),
+            |m| pair.sign(&rng, m).unwrap().as_ref().to_vec(),
+        );
+        let grant = validate_token(&token, &[jwk], &config(), NOW).unwrap();
+        assert!(grant.may_manage());
+        assert!(grant.may_call("filesystem"));
+    }
+
+    #[test]
+    fn test_invalid_tokens_are_refused() {
+        let (pair, jwk) = ed25519_key("ed");
+        let keys = [jwk];
+        let token = |claims: Value| sign("EdDSA", "ed", claims, |m| pair.sign(m).as_ref().to_vec());
+        let with = |key: &str, value: Value| {
+            let mut claims = claims("wassette:manage");
+            claims[key] = value;
+            claims
+        };
+
+        let refused = [
+            (
+                "another audience",
+                token(with("aud", json!("https://other.example.com"))),
+            ),
+            (
+                "another issuer",
+                token(with("iss", json!("https://evil.example.com"))),
+            ),
+            ("expired", token(with("exp", json!(NOW - 3600)))),
+            ("no expiry", token(with("exp", Value::Null))),
+            ("no subject", token(with("sub", Value::Null))),
+            ("empty subject", token(with("sub", json!("")))),
+            ("not yet valid", token(with("nbf", json!(NOW + 3600)))),
+            (
+                "unknown key",
+                sign("EdDSA", "other", claims("wassette:manage"), |m| {
+                    pair.sign(m).as_ref().to_vec()
+                }),
+            ),
+            (
+                "unsigned",
+                sign("none", "ed", claims("wassette:manage"), |_| Vec::new()),
+            ),
+            ("not a JWT", "opaque-token".to_string()),
+        ];
+        for (case, token) in refused {
+            assert!(
+                validate_token(&token, &keys, &config(), NOW).is_err(),
+                "{case} token accepted"
+            );
+        }
+
+        // A tampered payload no longer matches the signature.
+        let valid = token(claims("wassette:component:fetch"));
+        let (header, _, signature) = split_token(&valid).unwrap();
+        let forged = URL_SAFE_NO_PAD.encode(claims("wassette:manage").to_string());
+        let forged = format!("{header}.{forged}.{signature}");
+        assert!(validate_token(&forged, &keys, &config(), NOW).is_err());
+
+        // Expiry tolerates the configured clock skew.
+        let recent = token(with("exp", json!(NOW - 30)));
+        assert!(validate_token(&recent, &keys, &config(), NOW).is_ok());
+    }
+
+    #[test]
+    fn test_key_cache_refetches_for_unknown_kids() {
+        let refresh = Duration::from_secs(300);
+        let (_, jwk) = ed25519_key("ed");
+        let ago = |secs| Instant::now() - Duration::from_secs(secs);
+        let cache = |fetched, attempted| KeyCache {
+            keys: Some((ago(fetched), vec![jwk.clone()])),
+            attempted: Some(ago(attempted)),
+        };
+
+        assert!(KeyCache::default().current(None, refresh).is_none());
+        assert!(cache(60, 60).current(Some("ed"), refresh).is_some());
+        assert!(cache(600, 600).current(Some("ed"), refresh).is_none());
+        // An unknown key id fetches again, but not more often than the minimum interval,
+        // counted from the last attempt even when that failed.
+        assert!(cache(60, 60).current(Some("other"), refresh).is_none());
+        assert!(cache(60, 5).current(Some("other"), refresh).is_some());
+    }
+
+    #[test]
+    fn test_refusal_doesnt_say_why() {
+        let challenge = |response: Response| {
+            response.headers()[WWW_AUTHENTICATE]
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            challenge(unauthorized(&config(), false)),
+            "Bearer resource_metadata=\"https://wassette.example.com/.well-known/oauth-protected-resource/mcp\""
+        );
+        assert!(challenge(unauthorized(&config(), true))
+            .ends_with(", error=\"invalid_token\", error_description=\"invalid token\""));
+    }
+
+    #[test]
+    fn test_metadata_location_and_validation() {
+        let config = config();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.metadata_url(),
+            "https://wassette.example.com/.well-known/oauth-protected-resource/mcp"
+        );
+        assert_eq!(
+            config.metadata()["authorization_servers"],
+            json!(["https://login.example.com"])
+        );
+
+        assert!(AuthConfig::default().validate().is_err());
+        for config in [
+            AuthConfig {
+                jwks_url: "http://login.example.com/jwks.json".to_string(),
+                ..config.clone()
+            },
+            AuthConfig {
+                authorization_servers: vec![],
+                ..config.clone()
+            },
+        ] {
+            assert!(config.validate().is_err(), "{config:?} is valid");
+        }
+        let local = AuthConfig {
+            resource: "http://localhost:9001/mcp".to_string(),
+            authorization_servers: vec!["http://127.0.0.1:8080".to_string()],
+            jwks_url: "http://127.0.0.1:8080/jwks".to_string(),
+            ..Default::default()
+        };
+        assert!(local.validate().is_ok());
+    }
+}
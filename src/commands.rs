@@ -7,8 +7,11 @@ use std::path::PathBuf;
 
 use clap::{Args, Parser, Subcommand};
 use serde::{Deserialize, Serialize};
+use wassette::{AuditDecision, AuditEvent};
 
 use crate::format::OutputFormat;
+use crate::harden::ProfileKind;
+use crate::mcp_shim;
 
 #[derive(Parser, Debug)]
 #[command(name = "wassette-mcp-server", about, long_about = None)]
@@ -49,6 +52,84 @@ pub enum Commands {
         #[command(subcommand)]
         command: SecretCommands,
     },
+    /// Manage per-tool argument presets.
+    Preset {
+        #[command(subcommand)]
+        command: PresetCommands,
+    },
+    /// Install a component from a signed wassette-manifest.json.
+    Install {
+        /// URL, file:// URI or path of the manifest
+        manifest: String,
+        /// Install without asking to confirm the requested permissions
+        #[arg(long)]
+        yes: bool,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// Debugging utilities for component authors.
+    Debug {
+        #[command(subcommand)]
+        command: DebugCommands,
+    },
+    /// Generate operating system confinement profiles matching the configuration.
+    Harden {
+        /// Kind of profile to generate
+        #[arg(long, value_enum)]
+        generate: ProfileKind,
+        /// Directory to write the profile files to. Defaults to printing them
+        #[arg(long, short = 'O')]
+        output: Option<PathBuf>,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Also allow the socket of the shared server, for servers run with `--shared`
+        #[arg(long)]
+        shared: bool,
+    },
+    /// Manage the configuration file, stored state and component configuration.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Inspect, back up or reset the key-value state a component has stored.
+    State {
+        /// Component ID whose state to manage
+        component_id: String,
+        #[command(subcommand)]
+        command: StateCommands,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long, global = true)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// Remove the policies, state, secrets and other files of components that are no longer
+    /// installed.
+    Gc {
+        /// List what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+        /// Remove without asking for confirmation
+        #[arg(long)]
+        yes: bool,
+        /// Leave secrets and configuration alone, e.g. when several plugin directories share
+        /// the secrets directory
+        #[arg(long)]
+        keep_secrets: bool,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// Review the log of tool calls and permission decisions.
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+    /// Check the sandbox of this build and configuration.
+    Security {
+        #[command(subcommand)]
+        command: SecurityCommands,
+    },
 }
 
 #[derive(Parser, Debug, Clone, Serialize, Deserialize)]
@@ -61,6 +142,18 @@ pub struct Serve {
     #[command(flatten)]
     pub transport: TransportFlags,
 
+    /// With `--stdio`, relay to the shared server of this user and plugin directory, starting it
+    /// in the background if it isn't running, so all clients share one set of loaded components.
+    /// Unix only.
+    #[arg(long)]
+    #[serde(skip)]
+    pub shared: bool,
+
+    /// Run as the shared server that `--shared` clients relay to.
+    #[arg(long, hide = true)]
+    #[serde(skip)]
+    pub shared_server: bool,
+
     /// Set environment variables (KEY=VALUE format). Can be specified multiple times.
     #[arg(long = "env", value_parser = crate::parse_env_var)]
     #[serde(skip)]
@@ -70,6 +163,112 @@ pub struct Serve {
     #[arg(long = "env-file")]
     #[serde(skip)]
     pub env_file: Option<PathBuf>,
+
+    /// Capture a Wasm core dump when a component traps. Export it with `wassette debug coredump`.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub coredump_on_trap: bool,
+
+    /// Reject component policies with unknown or deprecated keys, reporting their line and
+    /// column, instead of ignoring them.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub strict_policy: bool,
+
+    /// Reload components loaded from `file://` paths when their files change, and tell clients
+    /// that the tool list changed.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub watch: bool,
+
+    /// Refuse to load `oci://` and `https://` components whose digest differs from the one
+    /// recorded in `wassette.lock`, and leave the lockfile unchanged.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+
+    /// Stop a tool call that runs longer than this many seconds and report a timeout. Tools
+    /// can override it under `[tools.<name>]`, and a component's policy can set a shorter
+    /// `resources.limits.timeout`.
+    #[arg(long = "call-timeout", value_name = "SECS")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub call_timeout_secs: Option<u64>,
+
+    /// Exit once no MCP request has arrived for this long, e.g. `10m`, after the component
+    /// operations in progress finish. For servers launched by a client or systemd socket
+    /// activation, which start them again when needed.
+    #[arg(long = "idle-timeout", value_name = "DURATION", value_parser = crate::parse_duration_secs)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_secs: Option<u64>,
+
+    /// Write the JSON of tool results, tool schemas and resources with sorted keys and no
+    /// insignificant whitespace, and list tools by name, so that recorded sessions and golden
+    /// tests are stable across runs and releases.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub canonical_json: bool,
+
+    /// Sample invocations of the given component with the guest profiler, writing one profile
+    /// per call. Can be specified multiple times.
+    #[arg(long = "profile-guest", value_name = "COMPONENT")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub profile_guest: Vec<String>,
+
+    /// Give the component read access to the workspace roots reported by the MCP client.
+    /// Can be specified multiple times.
+    #[arg(long = "workspace-component", value_name = "COMPONENT")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub workspace_components: Vec<String>,
+
+    #[command(flatten)]
+    pub sse: SseFlags,
+
+    #[command(flatten)]
+    pub http: StreamableHttpFlags,
+
+    /// Address the HTTP and SSE transports listen on, e.g. `0.0.0.0:9001` to accept remote
+    /// clients [default: 127.0.0.1:9001]
+    #[arg(long = "bind", value_name = "ADDR")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind_address: Option<String>,
+
+    /// Let browser pages from this origin, e.g. `https://playground.example.com`, use the HTTP
+    /// and SSE transports; `*` allows any. Can be specified multiple times.
+    #[arg(long = "allow-origin", value_name = "ORIGIN")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_origins: Vec<String>,
+}
+
+/// Settings of the SSE transport; unset ones come from the configuration file.
+#[derive(Args, Debug, Clone, Serialize, Deserialize, Default)]
+pub struct SseFlags {
+    /// Seconds without events after which a heartbeat event is sent on SSE streams; 0 turns
+    /// heartbeats off [default: 15]
+    #[arg(long = "sse-heartbeat", value_name = "SECS")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heartbeat_secs: Option<u64>,
+
+    /// Seconds an SSE session is kept after its stream drops, for the client to resume it with
+    /// `Last-Event-ID` [default: 60]
+    #[arg(long = "sse-resume-window", value_name = "SECS")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resume_window_secs: Option<u64>,
+}
+
+/// Settings of the streamable HTTP transport; unset ones come from the configuration file.
+#[derive(Args, Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StreamableHttpFlags {
+    /// Seconds without requests after which a streamable HTTP session is closed; 0 keeps
+    /// sessions until the client ends them [default: 1800]
+    #[arg(long = "http-session-idle", value_name = "SECS")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_idle_secs: Option<u64>,
+
+    /// Serve every streamable HTTP request on its own, without sessions, so that any server
+    /// behind a load balancer can take it
+    #[arg(long = "http-stateless")]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stateless: bool,
 }
 
 #[derive(Args, Debug, Clone, Serialize, Deserialize, Default)]
@@ -98,6 +297,17 @@ pub enum Transport {
     StreamableHttp,
 }
 
+impl Transport {
+    /// Name of the transport as reported by `get-server-info`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transport::Sse => "sse",
+            Transport::Stdio => "stdio",
+            Transport::StreamableHttp => "streamable-http",
+        }
+    }
+}
+
 impl From<&TransportFlags> for Transport {
     fn from(f: &TransportFlags) -> Self {
         match (f.sse, f.stdio, f.streamable_http) {
@@ -127,6 +337,18 @@ pub enum ComponentCommands {
         #[arg(long)]
         plugin_dir: Option<PathBuf>,
     },
+    /// Update a component to the latest release where it was loaded from, keeping its
+    /// permissions.
+    Update {
+        /// Component ID to update
+        id: String,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
     /// List all loaded components.
     List {
         /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
@@ -136,6 +358,160 @@ pub enum ComponentCommands {
         #[arg(short = 'o', long = "output-format", default_value = "json")]
         output_format: OutputFormat,
     },
+    /// Search the catalog indexes for components to load.
+    Search {
+        /// Words the name, description or URI of a component must all contain. Lists every
+        /// component when empty
+        query: Vec<String>,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
+    /// Describe a component without loading it: its world, its tools with their JSON schemas,
+    /// the permissions it asks for and its size and compile time.
+    Inspect {
+        /// Path to the component (file://, oci:// or https://)
+        path: String,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
+    /// Give a component another id, so loading it under that id replaces the component.
+    Alias {
+        /// Component ID the alias stands for
+        id: String,
+        /// New id, e.g. the one derived from the component's new OCI reference
+        alias: String,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// Remove a component alias.
+    Unalias {
+        /// Alias to remove
+        alias: String,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// List component aliases and the ids they stand for.
+    Aliases {
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// Show the versions a component was installed, updated or rolled back to, newest first.
+    History {
+        /// Component ID. Defaults to every component
+        id: Option<String>,
+        /// Only the last changes, at most this many
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
+    /// Put back an earlier version of a component, kept when an update replaced it, with the
+    /// policy it had.
+    Rollback {
+        /// Component ID to roll back
+        id: String,
+        /// Digest of the version to put back, or its first characters. Defaults to the version
+        /// the installed one replaced
+        #[arg(long)]
+        to: Option<String>,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
+    /// Compare the tool schemas of a component with an OpenAPI document or a list of tool
+    /// schemas, failing on mismatches.
+    CheckSchema {
+        /// Path to the component's .wasm file
+        wasm: PathBuf,
+        /// OpenAPI 3 document or `tools` list, in YAML or JSON
+        #[arg(long)]
+        against: PathBuf,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// Generate a component whose tools call operations of an HTTP API, from its OpenAPI 3
+    /// document.
+    FromOpenapi {
+        /// OpenAPI 3 document, in YAML or JSON
+        spec: PathBuf,
+        /// Directory to write the component project to. Defaults to the component's name
+        #[arg(long, short = 'O')]
+        output: Option<PathBuf>,
+        /// Name of the component. Defaults to the title of the API
+        #[arg(long)]
+        name: Option<String>,
+        /// URL the API is served at. Defaults to the first server of the document
+        #[arg(long)]
+        base_url: Option<String>,
+        /// `operationId` of an operation to wrap; repeat for several. Defaults to all of them
+        #[arg(long = "operation")]
+        operations: Vec<String>,
+        /// Build the component with cargo (needs the wasm32-wasip2 target)
+        #[arg(long)]
+        build: bool,
+        /// Overwrite the files of an existing project
+        #[arg(long)]
+        force: bool,
+    },
+    /// Generate a component forwarding the tools of a stdio MCP server, served over streamable
+    /// HTTP, so they run under wassette's policies.
+    FromMcp {
+        /// Server to list the tools of: `npm:<package>`, `pypi:<package>` or a program
+        server: String,
+        /// Arguments of the server, after `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+        /// Directory to write the component project to. Defaults to the component's name
+        #[arg(long, short = 'O')]
+        output: Option<PathBuf>,
+        /// Name of the component. Defaults to the name the server gives itself
+        #[arg(long)]
+        name: Option<String>,
+        /// Streamable HTTP endpoint the component calls the server at
+        #[arg(long, default_value = mcp_shim::DEFAULT_ENDPOINT)]
+        endpoint: String,
+        /// Name of a tool to wrap; repeat for several. Defaults to all of them
+        #[arg(long = "tool")]
+        tools: Vec<String>,
+        /// Build the component with cargo (needs the wasm32-wasip2 target)
+        #[arg(long)]
+        build: bool,
+        /// Overwrite the files of an existing project
+        #[arg(long)]
+        force: bool,
+    },
+    /// Download the WIT packages a component project imports into its `wit/deps` directory,
+    /// pinned by digest in `wit/deps.lock`, so it builds offline and reproducibly.
+    Vendor {
+        /// Directory of the component project, holding the `wit` directory
+        #[arg(default_value = ".")]
+        project: PathBuf,
+        /// Only check that every package is vendored and matches its pin, without downloading
+        #[arg(long, conflicts_with = "update")]
+        check: bool,
+        /// Download every package again and pin the new digests
+        #[arg(long)]
+        update: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -151,6 +527,18 @@ pub enum PolicyCommands {
         #[arg(short = 'o', long = "output-format", default_value = "json")]
         output_format: OutputFormat,
     },
+    /// Print the JSON Schema of policy files, for editors and CI.
+    Schema {
+        /// Write the schema to this file instead of stdout
+        #[arg(long, short = 'o')]
+        output: Option<PathBuf>,
+    },
+    /// Check policy files against the policy schema.
+    Validate {
+        /// Policy files to check
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -181,7 +569,7 @@ pub enum GrantPermissionCommands {
     Storage {
         /// Component ID to grant permission to
         component_id: String,
-        /// URI of the storage resource (e.g., fs:///path/to/directory)
+        /// URI of the storage resource (e.g., fs:///path/to/directory or kv://cache)
         uri: String,
         /// Access level (read, write, or read,write)
         #[arg(long, value_delimiter = ',')]
@@ -207,6 +595,10 @@ pub enum GrantPermissionCommands {
         component_id: String,
         /// Environment variable key
         key: String,
+        /// Value to give the variable instead of the server's. May refer to the component's
+        /// secrets as ${secret:name} and to server environment variables as ${env:NAME}.
+        #[arg(long)]
+        value: Option<String>,
         /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
         #[arg(long)]
         plugin_dir: Option<PathBuf>,
@@ -229,7 +621,7 @@ pub enum RevokePermissionCommands {
     Storage {
         /// Component ID to revoke permission from
         component_id: String,
-        /// URI of the storage resource (e.g., fs:///path/to/directory)
+        /// URI of the storage resource (e.g., fs:///path/to/directory or kv://cache)
         uri: String,
         /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
         #[arg(long)]
@@ -298,4 +690,224 @@ pub enum SecretCommands {
         #[arg(long)]
         plugin_dir: Option<PathBuf>,
     },
+    /// Show the environment variables a component would be given, with ${secret:name} and
+    /// ${env:NAME} references resolved. Nothing is injected; only instantiation does that.
+    Resolve {
+        /// Component ID to resolve the environment of
+        component_id: String,
+        /// Show values (prompts for confirmation)
+        #[arg(long)]
+        show_values: bool,
+        /// Skip confirmation prompt when showing values
+        #[arg(long)]
+        yes: bool,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PresetCommands {
+    /// List argument presets for a component's tools.
+    List {
+        /// Component ID to list presets for
+        component_id: String,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
+    /// Set argument presets for a tool. Values are parsed as JSON, falling back to a string.
+    Set {
+        /// Tool to set presets for, as <component-id>.<tool-name>
+        #[arg(value_parser = crate::parse_tool_target)]
+        tool: (String, String),
+        /// Presets in KEY=VALUE format. Can be specified multiple times.
+        #[arg(value_parser = crate::parse_env_var, required = true)]
+        values: Vec<(String, String)>,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// Remove argument presets from a tool. Removes all presets when no keys are given.
+    Unset {
+        /// Tool to remove presets from, as <component-id>.<tool-name>
+        #[arg(value_parser = crate::parse_tool_target)]
+        tool: (String, String),
+        /// Preset keys to remove
+        keys: Vec<String>,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DebugCommands {
+    /// List core dumps captured from trapped component calls.
+    Coredumps {
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
+    /// Export the core dump captured for a call, for analysis with tools such as wasmgdb.
+    Coredump {
+        /// Call ID reported in the trap error
+        call_id: String,
+        /// File to write the core dump to. Defaults to <call-id>.coredump
+        #[arg(long, short = 'O')]
+        output: Option<PathBuf>,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// List attempts by components to reach host paths outside their granted directories.
+    FsEscapes {
+        /// Only list attempts by this component
+        #[arg(long)]
+        component: Option<String>,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SecurityCommands {
+    /// Make an adversarial component attempt known sandbox escapes and report which were
+    /// blocked. Exits with an error if any attempt got through.
+    Selftest {
+        /// Adversarial component to use instead of the one bundled with wassette
+        #[arg(long)]
+        component: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuditCommands {
+    /// Print the latest audit records as JSON lines.
+    Tail {
+        /// Number of records to print
+        #[arg(short = 'n', long, default_value_t = 20)]
+        lines: usize,
+        /// Keep printing records as they are written
+        #[arg(long, short = 'f')]
+        follow: bool,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// Search the audit log, including rotated files.
+    Query {
+        /// Only records about this component
+        #[arg(long)]
+        component: Option<String>,
+        /// Only records of calls of this tool
+        #[arg(long)]
+        tool: Option<String>,
+        /// Only records of this kind: tool-call, permission-denied, permission-grant,
+        /// permission-revoke or permission-reset
+        #[arg(long, value_parser = crate::parse_audit_event)]
+        event: Option<AuditEvent>,
+        /// Only records with this decision: allowed or denied
+        #[arg(long, value_parser = crate::parse_audit_decision)]
+        decision: Option<AuditDecision>,
+        /// Only calls made with an access token issued to this subject
+        #[arg(long)]
+        subject: Option<String>,
+        /// Only records written within this long, e.g. 30m, 12h or 7d
+        #[arg(long, value_name = "DURATION", value_parser = crate::parse_duration_secs)]
+        since: Option<u64>,
+        /// Only the last records, at most this many
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Upgrade the configuration file and plugin directory to the layout of this release.
+    Migrate {
+        /// Report the changes without making them
+        #[arg(long)]
+        dry_run: bool,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// Set configuration values a component reads through wasi:config.
+    Set {
+        /// Component ID to configure
+        component_id: String,
+        /// Values in KEY=VALUE format. Can be specified multiple times.
+        #[arg(value_parser = crate::parse_env_var, required = true)]
+        values: Vec<(String, String)>,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// Show the configuration values of a component.
+    Get {
+        /// Component ID to show the configuration of
+        component_id: String,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+        /// Output format
+        #[arg(short = 'o', long = "output-format", default_value = "json")]
+        output_format: OutputFormat,
+    },
+    /// Remove configuration values from a component.
+    Unset {
+        /// Component ID to remove values from
+        component_id: String,
+        /// Keys to remove
+        #[arg(required = true)]
+        keys: Vec<String>,
+        /// Directory where plugins are stored. Defaults to $XDG_DATA_HOME/wassette/components
+        #[arg(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StateCommands {
+    /// Print the component's state as JSON, or write it to a file.
+    Export {
+        /// File to write the state to. Defaults to printing it
+        #[arg(long, short = 'O')]
+        output: Option<PathBuf>,
+    },
+    /// Replace the component's state with a previous export.
+    Import {
+        /// File holding the exported state
+        file: PathBuf,
+    },
+    /// Remove all of the component's state.
+    Clear {
+        /// Skip the confirmation prompt
+        #[arg(long)]
+        yes: bool,
+    },
 }
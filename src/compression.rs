@@ -0,0 +1,342 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Compression of the responses of the HTTP transports
+//!
+//! Responses are compressed with Brotli or gzip, whichever the client prefers in its
+//! `Accept-Encoding` header, preferring Brotli on a tie; clients that send neither get them as
+//! they are. Tool results travel in SSE streams, so bodies are compressed chunk by chunk, and
+//! the encoder is flushed after every chunk: each event reaches the client as soon as it is
+//! sent instead of waiting for the encoder's buffer to fill, while a large tool result in one
+//! event still compresses well. Responses of a known size below the configured minimum, images
+//! and responses that are already encoded are left alone.
+
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use axum::body::{Body, BodyDataStream, Bytes, HttpBody};
+use axum::extract::{Request, State};
+use axum::http::header::{
+    ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, VARY,
+};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use tokio_stream::Stream;
+
+/// Brotli quality, low enough to keep up with a stream of events
+const BROTLI_QUALITY: u32 = 5;
+
+/// Base-2 logarithm of the Brotli window size
+const BROTLI_WINDOW: u32 = 22;
+
+/// Size of the Brotli encoder's input buffer
+const BROTLI_BUFFER: usize = 4096;
+
+/// Settings of response compression
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    /// Compress responses for clients that accept it
+    pub enabled: bool,
+    /// Responses of a known size smaller than this many bytes are sent uncompressed
+    pub min_bytes: u64,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_bytes: 1024,
+        }
+    }
+}
+
+/// A content coding the server compresses with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn encoder(self, output: Output) -> Encoder {
+        match self {
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                output,
+                BROTLI_BUFFER,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            ))),
+            Encoding::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                output,
+                flate2::Compression::default(),
+            )),
+        }
+    }
+}
+
+/// The coding to compress with for a client sending `accept_encoding`, if it accepts one.
+fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let (mut brotli, mut gzip, mut any) = (None, None, None);
+    for item in accept_encoding.split(',') {
+        let item = item.to_ascii_lowercase();
+        let mut params = item.split(';');
+        let coding = params.next().unwrap_or_default().trim();
+        let quality = params
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok());
+        let Some(quality) = quality else {
+            continue;
+        };
+        match coding {
+            "br" => brotli = Some(quality),
+            "gzip" | "x-gzip" => gzip = Some(quality),
+            "*" => any = Some(quality),
+            _ => {}
+        }
+    }
+    let brotli = brotli.or(any).unwrap_or(0.0);
+    let gzip = gzip.or(any).unwrap_or(0.0);
+    if brotli > 0.0 && brotli >= gzip {
+        Some(Encoding::Brotli)
+    } else if gzip > 0.0 {
+        Some(Encoding::Gzip)
+    } else {
+        None
+    }
+}
+
+/// Compress the responses of `router` for clients that accept it.
+pub fn apply(router: Router, config: CompressionConfig) -> Router {
+    if !config.enabled {
+        return router;
+    }
+    router.layer(axum::middleware::from_fn_with_state(
+        Arc::new(config),
+        compress,
+    ))
+}
+
+async fn compress(
+    State(config): State<Arc<CompressionConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let accept_encoding = request
+        .headers()
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut response = next.run(request).await;
+    if !compressible(&response, config.min_bytes) {
+        return response;
+    }
+    response
+        .headers_mut()
+        .append(VARY, HeaderValue::from_static("accept-encoding"));
+    let Some(encoding) = negotiate(&accept_encoding) else {
+        return response;
+    };
+
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+    let output = Output::default();
+    let body = Compressed {
+        inner: body.into_data_stream(),
+        encoder: Some(encoding.encoder(output.clone())),
+        output,
+    };
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// Whether `response` is worth compressing and can be.
+fn compressible(response: &Response, min_bytes: u64) -> bool {
+    let headers = response.headers();
+    let image = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("image/"));
+    let small = response
+        .body()
+        .size_hint()
+        .exact()
+        .is_some_and(|size| size < min_bytes);
+    !(image
+        || small
+        || headers.contains_key(CONTENT_ENCODING)
+        || headers.contains_key(CONTENT_RANGE)
+        || response.status() == StatusCode::NO_CONTENT
+        || response.status() == StatusCode::NOT_MODIFIED
+        || response.status().is_informational())
+}
+
+/// Compressed bytes the encoder wrote and the stream hasn't sent yet
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Output {
+    fn take(&self) -> Bytes {
+        let mut buffer = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        Bytes::from(std::mem::take(&mut *buffer))
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Encoder {
+    Brotli(Box<brotli::CompressorWriter<Output>>),
+    Gzip(flate2::write::GzEncoder<Output>),
+}
+
+impl Encoder {
+    /// Compress `data` and flush it to the output, so it can be decoded without what follows.
+    fn write_chunk(&mut self, data: &[u8]) -> io::Result<()> {
+        let writer: &mut dyn Write = match self {
+            Encoder::Brotli(encoder) => encoder.as_mut(),
+            Encoder::Gzip(encoder) => encoder,
+        };
+        writer.write_all(data)?;
+        writer.flush()
+    }
+
+    /// Write the end of the compressed stream to the output.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Encoder::Brotli(encoder) => {
+                encoder.into_inner();
+                Ok(())
+            }
+            Encoder::Gzip(encoder) => encoder.finish().map(drop),
+        }
+    }
+}
+
+/// A response body, compressed as it is produced
+struct Compressed {
+    inner: BodyDataStream,
+    /// Gone once the body ended or failed
+    encoder: Option<Encoder>,
+    output: Output,
+}
+
+impl Stream for Compressed {
+    type Item = Result<Bytes, axum::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            let Some(encoder) = this.encoder.as_mut() else {
+                return Poll::Ready(None);
+            };
+            let written = match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(Ok(data)) if data.is_empty() => continue,
+                Some(Ok(data)) => encoder.write_chunk(&data),
+                Some(Err(e)) => {
+                    this.encoder = None;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                None => this.encoder.take().map_or(Ok(()), Encoder::finish),
+            };
+            if let Err(e) = written {
+                this.encoder = None;
+                return Poll::Ready(Some(Err(axum::Error::new(e))));
+            }
+            let output = this.output.take();
+            if !output.is_empty() {
+                return Poll::Ready(Some(Ok(output)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tokio_stream::StreamExt;
+
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0.5, gzip;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("*;q=0.5, br;q=0"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    /// The compressed chunks of `events`, each decodable on its own with what came before.
+    async fn compress_events(encoding: Encoding, events: &[&str]) -> Vec<Bytes> {
+        let events: Vec<Result<Bytes, io::Error>> = events
+            .iter()
+            .map(|event| Ok(Bytes::from(event.to_string())))
+            .collect();
+        let output = Output::default();
+        let body = Compressed {
+            inner: Body::from_stream(tokio_stream::iter(events)).into_data_stream(),
+            encoder: Some(encoding.encoder(output.clone())),
+            output,
+        };
+        body.map(Result::unwrap).collect().await
+    }
+
+    #[tokio::test]
+    async fn test_events_are_flushed_as_they_are_sent() {
+        let result = format!("data: {}\n\n", "{\"row\": 42}".repeat(1000));
+        let events = ["data: first\n\n", result.as_str(), "data: last\n\n"];
+
+        let chunks = compress_events(Encoding::Gzip, &events).await;
+        // The first event can be decoded before the rest of the stream arrives.
+        let mut first = String::new();
+        let _ = flate2::read::GzDecoder::new(&chunks[0][..]).read_to_string(&mut first);
+        assert_eq!(first, events[0]);
+        let compressed = chunks.concat();
+        assert!(compressed.len() < result.len() / 10);
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&compressed[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, events.concat());
+
+        let compressed = compress_events(Encoding::Brotli, &events).await.concat();
+        let mut decoded = String::new();
+        brotli::Decompressor::new(&compressed[..], BROTLI_BUFFER)
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, events.concat());
+    }
+}
@@ -4,10 +4,20 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use anyhow::Context;
+use crate::auth::AuthConfig;
+use crate::compression::CompressionConfig;
+use crate::sse::SseConfig;
+use crate::streamable_http::StreamableHttpConfig;
+use anyhow::{bail, Context};
 use etcetera::BaseStrategy;
 use figment::providers::{Env, Format, Serialized, Toml};
 use serde::{Deserialize, Serialize};
+use wassette::{
+    AnomalyConfig, BrowserConfig, CatalogConfig, FsHardening, HookConfig, NetworkConfig,
+    OutputConfig, OverrideCeiling, PermissionPrompts, ProvenanceConfig, PullOptions,
+    RegistryConfig, RuntimeSettings, SecretsBackendConfig, SessionProfile, SignatureConfig,
+    ToolCollisionPolicy, ToolSettings, UpdateConfig, WasmProposal, DEFAULT_TOOL_SEPARATOR,
+};
 
 /// Get the default component directory path based on the OS
 pub fn get_component_dir() -> Result<PathBuf, anyhow::Error> {
@@ -21,6 +31,28 @@ pub fn get_secrets_dir() -> Result<PathBuf, anyhow::Error> {
     Ok(dir_strategy.config_dir().join("wassette").join("secrets"))
 }
 
+/// Version of the configuration file schema understood by this release.
+///
+/// Older files are read as they are and upgraded on disk by `wassette config migrate`; files
+/// written for a newer release are refused.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Address the HTTP and SSE transports listen on unless configured otherwise
+pub const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1:9001";
+
+/// Get the path of the configuration file: `WASETTE_CONFIG_FILE` if set, otherwise
+/// `$XDG_CONFIG_HOME/wassette/config.toml`
+pub fn get_config_file() -> Result<PathBuf, anyhow::Error> {
+    match std::env::var_os("WASETTE_CONFIG_FILE") {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Ok(etcetera::choose_base_strategy()
+            .context("Unable to get home directory")?
+            .config_dir()
+            .join("wassette")
+            .join("config.toml")),
+    }
+}
+
 fn default_plugin_dir() -> PathBuf {
     get_component_dir().unwrap_or_else(|_| {
         eprintln!("WARN: Unable to determine default component directory, using `components` directory in the current working directory");
@@ -35,6 +67,14 @@ fn default_secrets_dir() -> PathBuf {
     })
 }
 
+fn default_tool_separator() -> String {
+    DEFAULT_TOOL_SEPARATOR.to_string()
+}
+
+fn default_bind_address() -> String {
+    DEFAULT_BIND_ADDRESS.to_string()
+}
+
 /// Configuration for the Wasette MCP server
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
@@ -46,9 +86,183 @@ pub struct Config {
     #[serde(default = "default_secrets_dir")]
     pub secrets_dir: PathBuf,
 
+    /// Where component secrets are kept: `file` in the secrets directory, the OS `keyring`,
+    /// or HashiCorp `vault`, chosen with `backend` under `[secrets]`
+    #[serde(default)]
+    pub secrets: SecretsBackendConfig,
+
     /// Environment variables to be made available to components
     #[serde(default)]
     pub environment_vars: HashMap<String, String>,
+
+    /// Capture a Wasm core dump when a component traps
+    #[serde(default)]
+    pub coredump_on_trap: bool,
+
+    /// Reject component policies with unknown or deprecated keys
+    #[serde(default)]
+    pub strict_policy: bool,
+
+    /// Reload components loaded from `file://` paths when their files change
+    #[serde(default)]
+    pub watch: bool,
+
+    /// Refuse registry and URL components whose digest differs from the lockfile
+    #[serde(default)]
+    pub locked: bool,
+
+    /// Lockfile recording the digests of loaded components, `wassette.lock` in the plugin
+    /// directory by default
+    #[serde(default)]
+    pub lockfile: Option<PathBuf>,
+
+    /// Whether paths escaping granted directories are refused (`enforce`) or only recorded
+    /// (`audit`)
+    #[serde(default)]
+    pub fs_hardening: FsHardening,
+
+    /// Components whose invocations are sampled by the guest profiler
+    #[serde(default)]
+    pub profile_guest: Vec<String>,
+
+    /// Components granted read access to the MCP client's workspace roots
+    #[serde(default)]
+    pub workspace_components: Vec<String>,
+
+    /// Separator between a publisher namespace and a tool name
+    #[serde(default = "default_tool_separator")]
+    pub tool_separator: String,
+
+    /// How tool name collisions between components are resolved
+    #[serde(default)]
+    pub tool_collisions: ToolCollisionPolicy,
+
+    /// Where component stdout and stderr go, by default and per component
+    #[serde(default)]
+    pub output: OutputConfig,
+
+    /// Timeout, retries and mirrors for OCI pulls
+    #[serde(default)]
+    pub pull: PullOptions,
+
+    /// Internal mirrors and pull-through caches of upstream registries
+    #[serde(default)]
+    pub registries: RegistryConfig,
+
+    /// Cosign signers trusted for the components of each registry
+    #[serde(default)]
+    pub signatures: SignatureConfig,
+
+    /// Publisher keys that must sign the manifests of specific components
+    #[serde(default)]
+    pub provenance: ProvenanceConfig,
+
+    /// Locations of the library components manifests depend on, overriding the manifests'
+    #[serde(default)]
+    pub libraries: HashMap<String, String>,
+
+    /// Runtime settings of individual tools, keyed by tool name
+    #[serde(default)]
+    pub tools: HashMap<String, ToolSettings>,
+
+    /// Instance allocation and standby instances of the engine
+    #[serde(default)]
+    pub runtime: RuntimeSettings,
+
+    /// Tool calls that run at once when some tool has batch priority; the number of CPUs
+    /// by default
+    #[serde(default)]
+    pub max_concurrent_calls: Option<usize>,
+
+    /// Seconds a tool call may run before it is stopped with a timeout error; unlimited by
+    /// default
+    #[serde(default)]
+    pub call_timeout_secs: Option<u64>,
+
+    /// Most a privileged client may raise the timeout and memory limit of a single call to;
+    /// limits can't be overridden unless configured
+    #[serde(default)]
+    pub call_overrides: OverrideCeiling,
+
+    /// Seconds without MCP requests after which the server exits; it runs until stopped by
+    /// default
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+
+    /// Write the JSON of tool results, tool schemas and resources canonically and list tools
+    /// by name
+    #[serde(default)]
+    pub canonical_json: bool,
+
+    /// Commands and webhooks run on component lifecycle events
+    #[serde(default)]
+    pub hooks: Vec<HookConfig>,
+
+    /// Heartbeats and resumption of the SSE transport
+    #[serde(default)]
+    pub sse: SseConfig,
+
+    /// Sessions of the streamable HTTP transport
+    #[serde(default)]
+    pub http: StreamableHttpConfig,
+
+    /// Compression of the responses of the HTTP and SSE transports
+    #[serde(default)]
+    pub compression: CompressionConfig,
+
+    /// Address the HTTP and SSE transports listen on
+    #[serde(default = "default_bind_address")]
+    pub bind_address: String,
+
+    /// Browser origins allowed to use the HTTP and SSE transports besides the server's own
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+
+    /// OAuth authorization required of HTTP and SSE clients; none unless configured
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+
+    /// Locale and profile values shared with components granted `context: read`
+    #[serde(default)]
+    pub context: SessionProfile,
+
+    /// Optional WebAssembly proposals the engine enables; `relaxed-simd` by default
+    #[serde(default)]
+    pub wasm_proposals: Option<Vec<WasmProposal>>,
+
+    /// Headless browser offered to components whose policy has a `browser` section; none
+    /// unless configured
+    #[serde(default)]
+    pub browser: Option<BrowserConfig>,
+
+    /// Egress proxy and extra trusted CAs of the outgoing HTTP requests of components, globally
+    /// and per component
+    #[serde(default)]
+    pub network: NetworkConfig,
+
+    /// Daily updates of components loaded from OCI tags or manifest URLs; off unless
+    /// configured
+    #[serde(default)]
+    pub updates: Option<UpdateConfig>,
+
+    /// Earlier versions of each component kept to roll back to; five by default, none with 0
+    #[serde(default)]
+    pub kept_versions: Option<usize>,
+
+    /// Kinds of refusals the user is asked to grant instead, `network` and `environment`;
+    /// off unless configured
+    #[serde(default)]
+    pub permission_prompts: PermissionPrompts,
+
+    /// Warnings about tool calls that deviate sharply from the usual calls of their component;
+    /// off unless configured
+    #[serde(default)]
+    pub anomaly_detection: Option<AnomalyConfig>,
+
+    /// Catalog indexes searched by `wassette component search` and the `search-components`
+    /// tool, besides the catalog bundled with wassette
+    #[serde(default)]
+    pub catalog: CatalogConfig,
 }
 
 impl Config {
@@ -63,15 +277,7 @@ impl Config {
     /// 2. Environment variables prefixed with `WASETTE_`
     /// 3. Configuration file specified by `WASETTE_CONFIG_FILE` or default location
     pub fn new<T: Serialize>(cli_config: &T) -> Result<Self, anyhow::Error> {
-        let config_file_path = match std::env::var_os("WASETTE_CONFIG_FILE") {
-            Some(path) => PathBuf::from(path),
-            None => etcetera::choose_base_strategy()
-                .context("Unable to get home directory")?
-                .config_dir()
-                .join("wassette")
-                .join("config.toml"),
-        };
-        Self::new_from_path(cli_config, config_file_path)
+        Self::new_from_path(cli_config, get_config_file()?)
     }
 
     /// Same as [`Config::new`], but allows specifying a custom path for the configuration file.
@@ -79,6 +285,14 @@ impl Config {
         cli_config: &T,
         config_file_path: impl AsRef<Path>,
     ) -> Result<Self, anyhow::Error> {
+        let version = config_file_version(config_file_path.as_ref())?;
+        if version > CONFIG_VERSION {
+            bail!(
+                "Configuration file {} was written for a newer release of wassette (version {version}, this release supports up to {CONFIG_VERSION})",
+                config_file_path.as_ref().display()
+            );
+        }
+
         figment::Figment::new()
             .admerge(Toml::file(config_file_path))
             .admerge(Env::prefixed("WASETTE_"))
@@ -119,12 +333,89 @@ impl Config {
     }
 }
 
+/// What a migration of the configuration file did, or would do on a dry run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfigMigration {
+    /// Path of the configuration file
+    pub path: PathBuf,
+    /// Version the file was at
+    pub from: u32,
+    /// Version the file is at afterwards
+    pub to: u32,
+    /// Changes made, in order
+    pub changes: Vec<String>,
+}
+
+/// Bring the configuration file at `path` up to [`CONFIG_VERSION`], keeping its comments and
+/// layout. With `dry_run`, only report what would change. A missing file is left missing.
+pub fn migrate_config_file(
+    path: impl AsRef<Path>,
+    dry_run: bool,
+) -> Result<ConfigMigration, anyhow::Error> {
+    let path = path.as_ref();
+    let mut migration = ConfigMigration {
+        path: path.to_path_buf(),
+        from: CONFIG_VERSION,
+        to: CONFIG_VERSION,
+        changes: vec![],
+    };
+    if !path.exists() {
+        return Ok(migration);
+    }
+
+    migration.from = config_file_version(path)?;
+    if migration.from > CONFIG_VERSION {
+        bail!(
+            "Configuration file {} was written for a newer release of wassette (version {}, this release supports up to {CONFIG_VERSION})",
+            path.display(),
+            migration.from
+        );
+    }
+
+    let mut content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    for version in migration.from..CONFIG_VERSION {
+        match version {
+            // Versioning was introduced; the keys are unchanged.
+            0 => {
+                content = format!("version = 1\n{content}");
+                migration.changes.push("add `version = 1`".to_string());
+            }
+            _ => bail!("No migration from configuration file version {version}"),
+        }
+    }
+
+    if !dry_run && !migration.changes.is_empty() {
+        let staged = path.with_extension("toml.tmp");
+        std::fs::write(&staged, content)
+            .with_context(|| format!("Failed to write {}", staged.display()))?;
+        std::fs::rename(&staged, path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(migration)
+}
+
+/// The `version` of the configuration file at `path`; 0 if it has none or doesn't exist.
+fn config_file_version(path: &Path) -> Result<u32, anyhow::Error> {
+    #[derive(Deserialize)]
+    struct Versioned {
+        #[serde(default)]
+        version: u32,
+    }
+
+    let versioned: Versioned = figment::Figment::from(Toml::file(path))
+        .extract()
+        .with_context(|| format!("Unable to read the version of {}", path.display()))?;
+    Ok(versioned.version)
+}
+
 #[cfg(test)]
 mod tests {
     use std::ffi::OsString;
     use std::fs;
 
     use tempfile::TempDir;
+    use wassette::{HookEvent, OutputSink, Priority, VaultAuth};
 
     use super::*;
 
@@ -132,8 +423,23 @@ mod tests {
         crate::Serve {
             plugin_dir: Some(PathBuf::from("/test/plugin/dir")),
             transport: Default::default(),
+            shared: false,
+            shared_server: false,
             env_vars: vec![],
             env_file: None,
+            coredump_on_trap: false,
+            strict_policy: false,
+            profile_guest: vec![],
+            workspace_components: vec![],
+            sse: Default::default(),
+            http: Default::default(),
+            allowed_origins: vec![],
+            watch: false,
+            locked: false,
+            call_timeout_secs: None,
+            idle_timeout_secs: None,
+            canonical_json: false,
+            bind_address: None,
         }
     }
 
@@ -141,8 +447,23 @@ mod tests {
         crate::Serve {
             plugin_dir: None,
             transport: Default::default(),
+            shared: false,
+            shared_server: false,
             env_vars: vec![],
             env_file: None,
+            coredump_on_trap: false,
+            strict_policy: false,
+            profile_guest: vec![],
+            workspace_components: vec![],
+            sse: Default::default(),
+            http: Default::default(),
+            allowed_origins: vec![],
+            watch: false,
+            locked: false,
+            call_timeout_secs: None,
+            idle_timeout_secs: None,
+            canonical_json: false,
+            bind_address: None,
         }
     }
 
@@ -218,6 +539,811 @@ plugin_dir = "/config/plugin/dir"
         assert_eq!(config.plugin_dir, PathBuf::from("/config/plugin/dir"));
     }
 
+    #[test]
+    fn test_config_file_tool_naming() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_file,
+            "tool_separator = \"-\"\ntool_collisions = \"qualify\"\n",
+        )
+        .unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.tool_separator, "-");
+        assert_eq!(config.tool_collisions, ToolCollisionPolicy::Qualify);
+
+        let config = Config::new_from_path(&empty_test_cli_config(), temp_dir.path().join("none"))
+            .expect("Failed to create config");
+        assert_eq!(config.tool_separator, DEFAULT_TOOL_SEPARATOR);
+        assert_eq!(config.tool_collisions, ToolCollisionPolicy::Ambiguous);
+    }
+
+    #[test]
+    fn test_config_file_output_sinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let toml_content = r#"
+[output.default]
+stderr = "log"
+
+[output.components.fetch]
+stdout = { file = { path = "fetch.log", max-bytes = 4096 } }
+stderr = "null"
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.output.default.stdout, OutputSink::Inherit);
+        assert_eq!(config.output.default.stderr, OutputSink::Log);
+        let fetch = &config.output.components["fetch"];
+        assert_eq!(fetch.stderr, OutputSink::Null);
+        match &fetch.stdout {
+            OutputSink::File(file) => {
+                assert_eq!(file.path.as_deref(), Some(Path::new("fetch.log")));
+                assert_eq!(file.max_bytes, 4096);
+            }
+            other => panic!("Expected a file sink, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_config_file_pull_options() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let toml_content = r#"
+[pull]
+timeout-secs = 120
+mirrors = ["mirror.example.com:5000"]
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.pull.timeout_secs, 120);
+        assert_eq!(config.pull.retries, PullOptions::default().retries);
+        assert_eq!(config.pull.mirrors, vec!["mirror.example.com:5000"]);
+    }
+
+    #[test]
+    fn test_config_file_registry_mirrors() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let toml_content = r#"
+[[registries.mirrors."ghcr.io"]]
+host = "harbor.corp.example.com/ghcr-proxy"
+username = "robot"
+password = "secret"
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        let mirrors = &config.registries.mirrors["ghcr.io"];
+        assert_eq!(mirrors.len(), 1);
+        assert_eq!(mirrors[0].host, "harbor.corp.example.com/ghcr-proxy");
+        assert_eq!(mirrors[0].username.as_deref(), Some("robot"));
+        assert!(config.registries.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_file_hooks() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let toml_content = r#"
+[[hooks]]
+events = ["load", "unload"]
+command = ["/usr/local/bin/cmdb-sync", "--source", "wassette"]
+
+[[hooks]]
+events = ["error", "permission-grant"]
+url = "https://hooks.example.com/wassette"
+timeout-secs = 5
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.hooks.len(), 2);
+        assert_eq!(config.hooks[0].events, [HookEvent::Load, HookEvent::Unload]);
+        assert_eq!(config.hooks[0].command[0], "/usr/local/bin/cmdb-sync");
+        assert_eq!(
+            config.hooks[1].url.as_deref(),
+            Some("https://hooks.example.com/wassette")
+        );
+        assert_eq!(config.hooks[1].timeout_secs, Some(5));
+        assert!(config.hooks.iter().all(|hook| hook.validate().is_ok()));
+    }
+
+    #[test]
+    fn test_config_file_provenance_pins() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let toml_content = r#"
+[provenance.components.fetch]
+publisher-keys = ["GZ0+E1dA8mCzTvhuDJb4E3ZXXYtbmOPc5XI/zB9cYJA="]
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.provenance.is_pinned("fetch"));
+        assert!(!config.provenance.is_pinned("time"));
+        assert!(config.provenance.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_file_libraries() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let toml_content = r#"
+[libraries]
+"acme:auth" = "oci://registry.internal/acme/auth@sha256:4c1f"
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(
+            config.libraries.get("acme:auth").map(String::as_str),
+            Some("oci://registry.internal/acme/auth@sha256:4c1f")
+        );
+    }
+
+    #[test]
+    fn test_config_file_signatures() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let toml_content = r#"
+[signatures]
+require-signed = true
+fulcio-roots = ["/etc/wassette/fulcio.pem"]
+rekor-public-key = "/etc/wassette/rekor.pub"
+
+[signatures.registries."ghcr.io/microsoft"]
+keys = ["/etc/wassette/cosign.pub"]
+
+[[signatures.registries."ghcr.io/microsoft".identities]]
+issuer = "https://token.actions.githubusercontent.com"
+subject = "https://github.com/microsoft/wassette/.github/workflows/*"
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.signatures.require_signed);
+        let signers = &config.signatures.registries["ghcr.io/microsoft"];
+        assert_eq!(signers.keys, [PathBuf::from("/etc/wassette/cosign.pub")]);
+        assert_eq!(
+            signers.identities[0].issuer,
+            "https://token.actions.githubusercontent.com"
+        );
+        // The key files don't exist.
+        assert!(config.signatures.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_file_latency_critical_tools() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let toml_content = r#"
+[tools.get-weather]
+latency_critical = true
+
+[tools.fetch]
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.tools["get-weather"].latency_critical);
+        assert!(!config.tools["fetch"].latency_critical);
+
+        fs::write(
+            &config_file,
+            "[tools.fetch]
+latency-critical = true
+",
+        )
+        .unwrap();
+        assert!(Config::new_from_path(&empty_test_cli_config(), &config_file).is_err());
+    }
+
+    #[test]
+    fn test_config_file_tool_priorities() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let toml_content = r#"
+max_concurrent_calls = 2
+
+[tools.reindex]
+priority = "batch"
+
+[tools.get-weather]
+priority = "interactive"
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.max_concurrent_calls, Some(2));
+        assert_eq!(config.tools["reindex"].priority, Priority::Batch);
+        assert_eq!(config.tools["get-weather"].priority, Priority::Interactive);
+
+        fs::write(&config_file, "[tools.reindex]\npriority = \"background\"\n").unwrap();
+        assert!(Config::new_from_path(&empty_test_cli_config(), &config_file).is_err());
+    }
+
+    #[test]
+    fn test_call_timeout_from_cli_or_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let toml_content = r#"
+call_timeout_secs = 60
+
+[tools.reindex]
+timeout_secs = 600
+"#;
+        fs::write(&config_file, toml_content).unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.call_timeout_secs, Some(60));
+        assert_eq!(config.tools["reindex"].timeout_secs, Some(600));
+
+        let cli_config = crate::Serve {
+            call_timeout_secs: Some(5),
+            ..empty_test_cli_config()
+        };
+        let config =
+            Config::new_from_path(&cli_config, &config_file).expect("Failed to create config");
+        assert_eq!(config.call_timeout_secs, Some(5));
+    }
+
+    #[test]
+    fn test_idle_timeout_from_cli_or_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.idle_timeout_secs, None);
+
+        fs::write(&config_file, "idle_timeout_secs = 1800\n").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.idle_timeout_secs, Some(1800));
+
+        let cli_config = crate::Serve {
+            idle_timeout_secs: Some(600),
+            ..empty_test_cli_config()
+        };
+        let config =
+            Config::new_from_path(&cli_config, &config_file).expect("Failed to create config");
+        assert_eq!(config.idle_timeout_secs, Some(600));
+    }
+
+    #[test]
+    fn test_strict_policy_from_cli_or_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(!config.strict_policy);
+
+        let cli_config = crate::Serve {
+            strict_policy: true,
+            ..empty_test_cli_config()
+        };
+        let config =
+            Config::new_from_path(&cli_config, &config_file).expect("Failed to create config");
+        assert!(config.strict_policy);
+
+        fs::write(&config_file, "strict_policy = true\n").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.strict_policy);
+    }
+
+    #[test]
+    fn test_watch_from_cli_or_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(!config.watch);
+
+        let cli_config = crate::Serve {
+            watch: true,
+            ..empty_test_cli_config()
+        };
+        let config =
+            Config::new_from_path(&cli_config, &config_file).expect("Failed to create config");
+        assert!(config.watch);
+
+        fs::write(&config_file, "watch = true\n").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.watch);
+    }
+
+    #[test]
+    fn test_canonical_json_from_cli_or_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(!config.canonical_json);
+
+        let cli_config = crate::Serve {
+            canonical_json: true,
+            ..empty_test_cli_config()
+        };
+        let config =
+            Config::new_from_path(&cli_config, &config_file).expect("Failed to create config");
+        assert!(config.canonical_json);
+
+        fs::write(
+            &config_file,
+            "canonical_json = true
+",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.canonical_json);
+    }
+
+    #[test]
+    fn test_locked_from_cli_or_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(!config.locked);
+        assert_eq!(config.lockfile, None);
+
+        let cli_config = crate::Serve {
+            locked: true,
+            ..empty_test_cli_config()
+        };
+        let config =
+            Config::new_from_path(&cli_config, &config_file).expect("Failed to create config");
+        assert!(config.locked);
+
+        fs::write(
+            &config_file,
+            "locked = true\nlockfile = \"/etc/wassette/wassette.lock\"\n",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.locked);
+        assert_eq!(
+            config.lockfile,
+            Some(PathBuf::from("/etc/wassette/wassette.lock"))
+        );
+    }
+
+    #[test]
+    fn test_sse_settings_from_cli_or_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "[sse]\nresume_window_secs = 300\n").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.sse.heartbeat_secs, 15);
+        assert_eq!(config.sse.resume_window_secs, 300);
+
+        let cli_config = crate::Serve {
+            sse: crate::commands::SseFlags {
+                heartbeat_secs: Some(0),
+                resume_window_secs: None,
+            },
+            ..empty_test_cli_config()
+        };
+        let config =
+            Config::new_from_path(&cli_config, &config_file).expect("Failed to create config");
+        assert_eq!(config.sse.heartbeat_secs, 0);
+        assert_eq!(config.sse.resume_window_secs, 300);
+    }
+
+    #[test]
+    fn test_streamable_http_settings_from_cli_or_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_file,
+            "bind_address = \"0.0.0.0:8080\"\n\n[http]\nreplay_events = 256\n",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.bind_address, "0.0.0.0:8080");
+        assert_eq!(config.http.replay_events, 256);
+        assert_eq!(config.http.session_idle_secs, 1800);
+        assert!(!config.http.stateless);
+
+        let cli_config = crate::Serve {
+            http: crate::commands::StreamableHttpFlags {
+                session_idle_secs: Some(60),
+                stateless: true,
+            },
+            bind_address: Some("[::]:9001".to_string()),
+            ..empty_test_cli_config()
+        };
+        let config =
+            Config::new_from_path(&cli_config, &config_file).expect("Failed to create config");
+        assert_eq!(config.bind_address, "[::]:9001");
+        assert_eq!(config.http.session_idle_secs, 60);
+        assert_eq!(config.http.replay_events, 256);
+        assert!(config.http.stateless);
+
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.bind_address, DEFAULT_BIND_ADDRESS);
+    }
+
+    #[test]
+    fn test_config_file_compression() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.compression.enabled);
+        assert_eq!(config.compression.min_bytes, 1024);
+
+        fs::write(
+            &config_file,
+            "[compression]
+enabled = false
+",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(!config.compression.enabled);
+        assert_eq!(config.compression.min_bytes, 1024);
+    }
+
+    #[test]
+    fn test_config_file_auth() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.auth, None);
+
+        fs::write(
+            &config_file,
+            "[auth]\nresource = \"https://wassette.example.com/mcp\"\nauthorization_servers = [\"https://login.example.com\"]\njwks_url = \"https://login.example.com/jwks.json\"\n",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        let auth = config.auth.unwrap();
+        assert_eq!(auth.resource, "https://wassette.example.com/mcp");
+        assert_eq!(auth.authorization_servers, ["https://login.example.com"]);
+        assert_eq!(auth.jwks_refresh_secs, 300);
+        assert!(auth.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_file_context() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_file,
+            "[context]\nlocale = \"en-GB\"\n\n[context.values]\nunits = \"metric\"\n",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.context.locale.as_deref(), Some("en-GB"));
+        assert_eq!(config.context.values["units"], "metric");
+        assert!(config.context.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_file_wasm_proposals() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.wasm_proposals, None);
+
+        fs::write(&config_file, "wasm_proposals = []\n").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.wasm_proposals, Some(vec![]));
+
+        fs::write(&config_file, "wasm_proposals = [\"relaxed-simd\"]\n").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.wasm_proposals, Some(vec![WasmProposal::RelaxedSimd]));
+    }
+
+    #[test]
+    fn test_config_file_browser() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.browser, None);
+
+        fs::write(
+            &config_file,
+            "[browser]\nexecutable = \"/usr/bin/chromium\"\nargs = [\"--no-sandbox\"]\ntimeout-secs = 10\n",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        let browser = config.browser.unwrap();
+        assert_eq!(
+            browser.executable.as_deref(),
+            Some(Path::new("/usr/bin/chromium"))
+        );
+        assert_eq!(browser.args, ["--no-sandbox"]);
+        assert_eq!(browser.timeout_secs, Some(10));
+        assert!(browser.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_file_updates() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.updates, None);
+
+        fs::write(
+            &config_file,
+            "[updates]\nwindow = \"02:30\"\ntimezone = \"utc\"\n\n[updates.health-checks.fetch]\ntool = \"fetch\"\narguments = { url = \"https://example.com\" }\n",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        let updates = config.updates.unwrap();
+        assert_eq!(updates.window, "02:30");
+        assert_eq!(updates.window_minutes, 60);
+        assert_eq!(updates.timezone, "utc");
+        let check = &updates.health_checks["fetch"];
+        assert_eq!(check.tool, "fetch");
+        assert_eq!(check.arguments["url"], "https://example.com");
+        assert!(updates.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_file_anomaly_detection() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.anomaly_detection, None);
+
+        fs::write(
+            &config_file,
+            "[anomaly_detection]
+threshold = 3.0
+",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        let anomaly_detection = config.anomaly_detection.unwrap();
+        assert_eq!(anomaly_detection.threshold, 3.0);
+        assert_eq!(anomaly_detection.warmup_calls, 20);
+        assert!(anomaly_detection.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_file_catalog() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.catalog.indexes.is_empty());
+        assert!(config.catalog.builtin);
+
+        fs::write(
+            &config_file,
+            r#"[catalog]
+indexes = ["oci://ghcr.io/example/catalog:latest"]
+builtin = false
+"#,
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(
+            config.catalog.indexes,
+            vec!["oci://ghcr.io/example/catalog:latest"]
+        );
+        assert!(!config.catalog.builtin);
+        assert!(config.catalog.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_file_runtime() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.runtime.pool_size, None);
+
+        fs::write(&config_file, "[runtime]\npool_size = 4\n").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.runtime.pool_size, Some(4));
+
+        fs::write(
+            &config_file,
+            "[runtime]\nvalidation_threads = 4\nvalidation_budget_ms = 250\n",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.runtime.validation_threads, Some(4));
+        assert_eq!(config.runtime.validation_budget_ms, Some(250));
+    }
+
+    #[test]
+    fn test_config_file_network() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_file,
+            r#"
+[network]
+proxy = "http://proxy.corp.example:3128"
+no_proxy = ["*.corp.example"]
+
+[network.components.fetch]
+proxy = "direct"
+"#,
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(
+            config.network.default.proxy.as_deref(),
+            Some("http://proxy.corp.example:3128")
+        );
+        assert_eq!(
+            config.network.default.no_proxy,
+            Some(vec!["*.corp.example".to_string()])
+        );
+        assert_eq!(config.network.default.ca_bundle, None);
+        assert_eq!(
+            config.network.components["fetch"].proxy.as_deref(),
+            Some("direct")
+        );
+    }
+
+    #[test]
+    fn test_config_file_secrets_backend() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.secrets, SecretsBackendConfig::File);
+
+        fs::write(&config_file, "[secrets]\nbackend = \"keyring\"\n").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.secrets, SecretsBackendConfig::Keyring);
+
+        fs::write(
+            &config_file,
+            r#"
+[secrets]
+backend = "vault"
+address = "https://vault.example.com:8200"
+namespace = "platform"
+cache-ttl-secs = 60
+auth = { method = "kubernetes", role = "wassette" }
+"#,
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        let SecretsBackendConfig::Vault(vault) = config.secrets else {
+            panic!("Expected the Vault backend");
+        };
+        assert_eq!(vault.mount, "secret");
+        assert_eq!(vault.namespace.as_deref(), Some("platform"));
+        assert_eq!(vault.cache_ttl_secs, 60);
+        assert!(matches!(vault.auth, VaultAuth::Kubernetes { ref role, .. } if role == "wassette"));
+    }
+
+    #[test]
+    fn test_config_file_call_overrides() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_file,
+            r#"
+[call_overrides]
+max_timeout = "1h"
+"#,
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.call_overrides.max_timeout.is_some());
+        assert_eq!(config.call_overrides.max_memory, None);
+        assert!(!config.call_overrides.allow_unauthenticated);
+
+        fs::write(
+            &config_file,
+            "[call_overrides]\nmax_memory = \"4Gi\"\nallow_unauthenticated = true\n",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.call_overrides.allow_unauthenticated);
+    }
+
+    #[test]
+    fn test_config_file_fs_hardening() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.fs_hardening, FsHardening::Enforce);
+
+        fs::write(&config_file, "fs_hardening = \"audit\"\n").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert_eq!(config.fs_hardening, FsHardening::Audit);
+    }
+
+    #[test]
+    fn test_config_file_permission_prompts() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(!config.permission_prompts.any());
+
+        fs::write(&config_file, "[permission_prompts]\nnetwork = true\n").unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.permission_prompts.network);
+        assert!(!config.permission_prompts.environment);
+        assert!(!config.permission_prompts.persist);
+
+        fs::write(
+            &config_file,
+            "[permission_prompts]\nstorage = true\npersist = true\n",
+        )
+        .unwrap();
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file)
+            .expect("Failed to create config");
+        assert!(config.permission_prompts.storage);
+        assert!(config.permission_prompts.persist);
+
+        fs::write(&config_file, "[permission_prompts]\nsecrets = true\n").unwrap();
+        assert!(Config::new_from_path(&empty_test_cli_config(), &config_file).is_err());
+    }
+
     #[test]
     fn test_cli_config_provides_defaults() {
         let temp_dir = TempDir::new().unwrap();
@@ -302,4 +1428,48 @@ policy_file = "custom_policy.yaml"
 
         assert_eq!(config.plugin_dir, PathBuf::from("/custom/plugin/dir"));
     }
+
+    #[test]
+    fn test_config_from_newer_release_is_refused() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, format!("version = {}\n", CONFIG_VERSION + 1)).unwrap();
+
+        let error = Config::new_from_path(&empty_test_cli_config(), &config_file).unwrap_err();
+        assert!(error.to_string().contains("newer release"));
+        assert!(migrate_config_file(&config_file, true).is_err());
+    }
+
+    #[test]
+    fn test_migrate_config_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let original = "# Components\nplugin_dir = \"/config/plugin/dir\"\n";
+        fs::write(&config_file, original).unwrap();
+
+        let planned = migrate_config_file(&config_file, true).unwrap();
+        assert_eq!(planned.from, 0);
+        assert_eq!(planned.changes.len(), 1);
+        assert_eq!(fs::read_to_string(&config_file).unwrap(), original);
+
+        let migrated = migrate_config_file(&config_file, false).unwrap();
+        assert_eq!(migrated, planned);
+        assert_eq!(
+            fs::read_to_string(&config_file).unwrap(),
+            format!("version = 1\n{original}")
+        );
+        let config = Config::new_from_path(&empty_test_cli_config(), &config_file).unwrap();
+        assert_eq!(config.plugin_dir, PathBuf::from("/config/plugin/dir"));
+
+        let again = migrate_config_file(&config_file, false).unwrap();
+        assert_eq!(again.from, CONFIG_VERSION);
+        assert!(again.changes.is_empty());
+
+        let missing = temp_dir.path().join("missing.toml");
+        assert!(migrate_config_file(&missing, false)
+            .unwrap()
+            .changes
+            .is_empty());
+        assert!(!missing.exists());
+    }
 }
@@ -0,0 +1,215 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Origin checks and CORS headers for the HTTP transports
+//!
+//! Browsers send an `Origin` header with cross-origin requests and with POSTs; requests without
+//! one come from other clients and pass. A browser request passes if it comes from a page served
+//! by this server on a loopback address, or from an origin on the allow-list. Anything else is
+//! refused, which also keeps web pages from reaching the local server through DNS rebinding.
+//! Allowed cross-origin requests get the CORS headers browsers need to read the responses, and
+//! preflight requests are answered here.
+
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::http::header::{
+    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, HOST, ORIGIN, VARY,
+};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+
+/// Request headers MCP clients send
+const ALLOWED_HEADERS: &str =
+    "accept, authorization, content-type, last-event-id, mcp-protocol-version, mcp-session-id";
+
+/// Cross-origin browser clients allowed to connect
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllowedOrigins {
+    any: bool,
+    origins: Vec<String>,
+}
+
+/// What a request's origin may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    SameOrigin,
+    CrossOrigin,
+    Denied,
+}
+
+impl AllowedOrigins {
+    /// Allow the given origins, such as `https://playground.example.com`; `*` allows any.
+    pub fn new(origins: impl IntoIterator<Item = String>) -> Self {
+        let mut allowed = Self::default();
+        for origin in origins {
+            let origin = origin.trim().trim_end_matches('/').to_ascii_lowercase();
+            if origin == "*" {
+                allowed.any = true;
+            } else {
+                allowed.origins.push(origin);
+            }
+        }
+        allowed
+    }
+
+    fn check(&self, origin: &str, host: Option<&str>) -> Access {
+        let origin = origin.to_ascii_lowercase();
+        if host.is_some_and(|host| is_loopback(host) && same_origin(&origin, host)) {
+            Access::SameOrigin
+        } else if self.any || self.origins.contains(&origin) {
+            Access::CrossOrigin
+        } else {
+            Access::Denied
+        }
+    }
+}
+
+/// Whether `origin` is the page origin of a server reached at `host`.
+fn same_origin(origin: &str, host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    ["http://", "https://"]
+        .iter()
+        .any(|scheme| origin.strip_prefix(scheme) == Some(host.as_str()))
+}
+
+/// Whether the `Host` header names the local machine.
+fn is_loopback(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Check the origin of every request to `router`.
+pub fn apply(router: Router, origins: AllowedOrigins) -> Router {
+    router.layer(axum::middleware::from_fn_with_state(
+        Arc::new(origins),
+        check_origin,
+    ))
+}
+
+async fn check_origin(
+    State(origins): State<Arc<AllowedOrigins>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(origin) = request
+        .headers()
+        .get(ORIGIN)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+    else {
+        return next.run(request).await;
+    };
+    let host = request
+        .headers()
+        .get(HOST)
+        .and_then(|value| value.to_str().ok());
+
+    match origins.check(&origin, host) {
+        Access::SameOrigin => next.run(request).await,
+        Access::Denied => {
+            tracing::warn!(origin, "Refused request from a browser origin not allowed");
+            (
+                StatusCode::FORBIDDEN,
+                format!("Origin {origin} is not allowed; allow it with --allow-origin"),
+            )
+                .into_response()
+        }
+        Access::CrossOrigin if request.method() == Method::OPTIONS => {
+            let mut response = StatusCode::NO_CONTENT.into_response();
+            add_cors_headers(response.headers_mut(), &origin);
+            let headers = response.headers_mut();
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_METHODS,
+                HeaderValue::from_static("GET, POST, DELETE, OPTIONS"),
+            );
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_HEADERS,
+                HeaderValue::from_static(ALLOWED_HEADERS),
+            );
+            headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static("600"));
+            response
+        }
+        Access::CrossOrigin => {
+            let mut response = next.run(request).await;
+            add_cors_headers(response.headers_mut(), &origin);
+            response
+        }
+    }
+}
+
+fn add_cors_headers(headers: &mut HeaderMap, origin: &str) {
+    if let Ok(origin) = HeaderValue::from_str(origin) {
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    }
+    headers.append(VARY, HeaderValue::from_static("origin"));
+    headers.insert(
+        ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static("mcp-session-id"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_origin_only_by_default() {
+        let origins = AllowedOrigins::default();
+        let host = Some("127.0.0.1:9001");
+        assert_eq!(
+            origins.check("http://127.0.0.1:9001", host),
+            Access::SameOrigin
+        );
+        assert_eq!(
+            origins.check("http://localhost:9001", Some("localhost:9001")),
+            Access::SameOrigin
+        );
+        assert_eq!(
+            origins.check("http://[::1]:9001", Some("[::1]:9001")),
+            Access::SameOrigin
+        );
+        assert_eq!(origins.check("http://localhost:3000", host), Access::Denied);
+        assert_eq!(origins.check("https://evil.example", host), Access::Denied);
+        assert_eq!(origins.check("null", host), Access::Denied);
+
+        // A name rebound to 127.0.0.1 matches its own Host header, but isn't loopback.
+        assert_eq!(
+            origins.check("http://evil.example:9001", Some("evil.example:9001")),
+            Access::Denied
+        );
+    }
+
+    #[test]
+    fn test_allow_list() {
+        let origins = AllowedOrigins::new([
+            "https://Playground.example.com/".to_string(),
+            "http://localhost:3000".to_string(),
+        ]);
+        let host = Some("127.0.0.1:9001");
+        assert_eq!(
+            origins.check("https://playground.example.com", host),
+            Access::CrossOrigin
+        );
+        assert_eq!(
+            origins.check("http://localhost:3000", host),
+            Access::CrossOrigin
+        );
+        assert_eq!(origins.check("http://localhost:3001", host), Access::Denied);
+
+        let origins = AllowedOrigins::new(["*".to_string()]);
+        assert_eq!(
+            origins.check("https://any.example", host),
+            Access::CrossOrigin
+        );
+    }
+}
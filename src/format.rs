@@ -9,9 +9,10 @@ use rmcp::model::CallToolResult;
 use serde_json::{Map, Value};
 
 /// Output format options for CLI commands
-#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
 pub enum OutputFormat {
     /// JSON format
+    #[default]
     Json,
     /// YAML format
     Yaml,
@@ -19,12 +20,6 @@ pub enum OutputFormat {
     Table,
 }
 
-impl Default for OutputFormat {
-    fn default() -> Self {
-        Self::Json
-    }
-}
-
 /// Format a JSON value as YAML string
 pub fn format_as_yaml(value: &Value) -> Result<String> {
     serde_yaml::to_string(value).map_err(|e| anyhow::anyhow!("Failed to format as YAML: {}", e))
@@ -54,6 +49,87 @@ pub fn format_as_table(value: &Value) -> Result<String> {
             }
             return Ok(table);
         }
+
+        // Check if this is a security self-test report
+        if let Some(probes) = obj.get("probes").and_then(|v| v.as_array()) {
+            let mut table = String::new();
+            table.push_str("Probe                 | Status  | Detail\n");
+            table.push_str("----------------------|---------|--------\n");
+
+            for probe in probes {
+                let field = |name: &str| probe.get(name).and_then(|v| v.as_str()).unwrap_or("");
+                table.push_str(&format!(
+                    "{:<21} | {:<7} | {}\n",
+                    field("probe"),
+                    field("status"),
+                    field("detail")
+                ));
+            }
+            return Ok(table);
+        }
+
+        // Check if this is a catalog search
+        if let Some(results) = obj.get("results").and_then(|v| v.as_array()) {
+            let mut table = String::new();
+            table.push_str("Name                  | Version    | URI | Permissions\n");
+            table.push_str("----------------------|------------|-----|-------------\n");
+
+            for entry in results {
+                let field = |name: &str| entry.get(name).and_then(|v| v.as_str()).unwrap_or("");
+                let permissions = entry
+                    .get("permissions")
+                    .and_then(|v| v.as_array())
+                    .map(|permissions| {
+                        permissions
+                            .iter()
+                            .filter_map(|p| p.as_str())
+                            .collect::<Vec<_>>()
+                            .join("; ")
+                    })
+                    .unwrap_or_default();
+                table.push_str(&format!(
+                    "{:<21} | {:<10} | {} | {}\n",
+                    field("name"),
+                    field("version"),
+                    field("uri"),
+                    permissions
+                ));
+            }
+            return Ok(table);
+        }
+
+        // Check if this is the resolved environment of a component
+        if let Some(variables) = obj.get("variables").and_then(|v| v.as_array()) {
+            let mut table = String::new();
+            table.push_str("Key                   | Source      | Value\n");
+            table.push_str("----------------------|-------------|--------\n");
+
+            for variable in variables {
+                let field = |name: &str| variable.get(name).and_then(|v| v.as_str()).unwrap_or("");
+                let value = match variable.get("value") {
+                    Some(_) => field("value").to_string(),
+                    None => variable
+                        .get("references")
+                        .and_then(|v| v.as_array())
+                        .map(|references| {
+                            references
+                                .iter()
+                                .filter_map(|r| r.as_str())
+                                .map(|r| format!("${{{r}}}"))
+                                .collect::<Vec<_>>()
+                                .join(" ")
+                        })
+                        .unwrap_or_default(),
+                };
+                table.push_str(&format!(
+                    "{:<21} | {:<11} | {}\n",
+                    field("key"),
+                    field("source"),
+                    value
+                ));
+            }
+            return Ok(table);
+        }
     }
 
     // Default generic table format
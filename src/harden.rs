@@ -0,0 +1,521 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Generation of AppArmor and SELinux confinement profiles matching the configuration
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use wassette::GrantedAccess;
+
+/// Confinement systems profiles can be generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProfileKind {
+    /// An AppArmor profile
+    Apparmor,
+    /// An SELinux policy module (type enforcement and file contexts)
+    Selinux,
+}
+
+/// What the wassette process needs to reach
+#[derive(Debug, Clone)]
+pub struct HardenInputs {
+    /// The wassette executable the profile applies to
+    pub executable: PathBuf,
+    /// The configuration file
+    pub config_file: PathBuf,
+    /// Directory where plugins are stored
+    pub plugin_dir: PathBuf,
+    /// Directory where secrets are stored
+    pub secrets_dir: PathBuf,
+    /// Programs run by lifecycle hooks
+    pub hook_programs: Vec<PathBuf>,
+    /// What the stored policies grant to components
+    pub access: GrantedAccess,
+    /// Chromium or Chrome executables the headless browser runs, when one is configured
+    pub browser_executables: Vec<PathBuf>,
+    /// Directory of the socket of the shared server, for servers run with `--shared`
+    pub shared_socket_dir: Option<PathBuf>,
+    /// CA bundles trusted for the requests of components and for the Vault server
+    pub ca_bundles: BTreeSet<PathBuf>,
+    /// Services the wassette process connects to itself, such as Vault or an OTLP collector
+    pub services: Vec<Service>,
+}
+
+/// A service the wassette process connects to on its own behalf
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    /// What the service is, for the comments of the profile
+    pub name: &'static str,
+    /// Host name or address of the service
+    pub host: String,
+    /// TCP port of the service
+    pub port: u16,
+}
+
+impl Service {
+    /// The service `name` at `url`, if the URL has a host and a port, explicit or implied
+    /// by its scheme.
+    pub fn from_url(name: &'static str, url: &str) -> Option<Self> {
+        let url = reqwest::Url::parse(url).ok()?;
+        Some(Self {
+            name,
+            host: url.host_str()?.to_string(),
+            port: url.port_or_known_default()?,
+        })
+    }
+}
+
+/// Ports SELinux labels `http_port_t`, which the profile already lets the process reach.
+const HTTP_PORTS: &[u16] = &[80, 81, 443, 488, 8008, 8009, 8443, 9000];
+
+/// Generate the files of a profile, as pairs of file name and contents.
+pub fn generate(kind: ProfileKind, inputs: &HardenInputs) -> Vec<(String, String)> {
+    match kind {
+        ProfileKind::Apparmor => vec![("wassette".to_string(), apparmor_profile(inputs))],
+        ProfileKind::Selinux => vec![
+            ("wassette.te".to_string(), selinux_type_enforcement(inputs)),
+            ("wassette.fc".to_string(), selinux_file_contexts(inputs)),
+        ],
+    }
+}
+
+/// The path `program` runs from: itself if it names a path, otherwise the first match on
+/// `PATH`, as the hook would be started.
+pub fn resolve_program(program: &str) -> PathBuf {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.to_path_buf();
+    }
+    std::env::var_os("PATH")
+        .iter()
+        .flat_map(std::env::split_paths)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+fn header(inputs: &HardenInputs) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# Generated by `wassette harden` for plugin directory {}.",
+        inputs.plugin_dir.display()
+    );
+    let _ = writeln!(
+        out,
+        "# Regenerate it after changing the configuration or granting permissions."
+    );
+    if !inputs.access.hosts.is_empty() {
+        let hosts: Vec<&str> = inputs.access.hosts.iter().map(String::as_str).collect();
+        let _ = writeln!(
+            out,
+            "# Components may connect to {}; policies restrict the host names.",
+            hosts.join(", ")
+        );
+    }
+    for service in &inputs.services {
+        let _ = writeln!(
+            out,
+            "# The server connects to {} at {}:{}.",
+            service.name, service.host, service.port
+        );
+    }
+    if inputs.access.email {
+        let _ = writeln!(
+            out,
+            "# Components may send email over the SMTP servers named in their secrets."
+        );
+    }
+    out
+}
+
+fn apparmor_profile(inputs: &HardenInputs) -> String {
+    let mut out = header(inputs);
+    let executable = apparmor_path(&inputs.executable);
+    out.push_str("abi <abi/3.0>,\n\ninclude <tunables/global>\n\n");
+    let _ = writeln!(out, "profile wassette {executable} {{");
+    out.push_str("  include <abstractions/base>\n");
+    out.push_str("  include <abstractions/nameservice>\n");
+    out.push_str("  include <abstractions/ssl_certs>\n\n");
+    out.push_str("  # Registry pulls, HTTP transports and component network access\n");
+    out.push_str("  network inet stream,\n  network inet6 stream,\n");
+    out.push_str("  network inet dgram,\n  network inet6 dgram,\n\n");
+    let _ = writeln!(out, "  {executable} mr,");
+    let _ = writeln!(out, "  {} r,", apparmor_path(&inputs.config_file));
+    let _ = writeln!(out, "  owner @{{PROC}}/@{{pid}}/** r,\n");
+
+    if !inputs.hook_programs.is_empty() {
+        out.push_str("  # Lifecycle hooks run under this profile\n");
+        for program in &inputs.hook_programs {
+            let _ = writeln!(out, "  {} rix,", apparmor_path(program));
+        }
+        out.push('\n');
+    }
+
+    if !inputs.browser_executables.is_empty() {
+        out.push_str(
+            "  # Headless browser, under its own profile if one is loaded and this one if not,\n",
+        );
+        out.push_str("  # with its temporary profile directory and shared memory\n");
+        for executable in &inputs.browser_executables {
+            let _ = writeln!(out, "  {} rpix,", apparmor_path(executable));
+        }
+        out.push_str("  owner /tmp/** rwlk,\n  owner /dev/shm/** rwlk,\n\n");
+    }
+
+    if let Some(dir) = &inputs.shared_socket_dir {
+        let dir = apparmor_path(dir);
+        let dir = dir.trim_end_matches('/');
+        out.push_str("  # Socket of the shared server\n");
+        out.push_str("  network unix stream,\n");
+        let _ = writeln!(out, "  owner {dir}/ rw,");
+        let _ = writeln!(out, "  owner {dir}/** rwk,\n");
+    }
+
+    if !inputs.ca_bundles.is_empty() {
+        out.push_str("  # Trusted CA bundles\n");
+        for bundle in &inputs.ca_bundles {
+            let _ = writeln!(out, "  {} r,", apparmor_path(bundle));
+        }
+        out.push('\n');
+    }
+
+    out.push_str("  # Components, policies and runtime state\n");
+    apparmor_tree(&mut out, &inputs.plugin_dir, "rwlk");
+    out.push_str("  # Component secrets\n");
+    apparmor_tree(&mut out, &inputs.secrets_dir, "rw");
+
+    if !inputs.access.directories.is_empty() || !inputs.access.mounted_files.is_empty() {
+        out.push_str("  # Granted to components by their policies\n");
+        for (dir, writable) in &inputs.access.directories {
+            apparmor_tree(&mut out, dir, if *writable { "rwlk" } else { "r" });
+        }
+        for file in &inputs.access.mounted_files {
+            let _ = writeln!(out, "  {} r,", apparmor_path(file));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Rules for a directory and everything below it.
+fn apparmor_tree(out: &mut String, dir: &Path, perms: &str) {
+    let dir = apparmor_path(dir);
+    let dir = dir.trim_end_matches('/');
+    let _ = writeln!(out, "  {dir}/ r,");
+    let _ = writeln!(out, "  {dir}/** {perms},\n");
+}
+
+/// A path as an AppArmor rule expects it, quoted if it contains spaces.
+fn apparmor_path(path: &Path) -> String {
+    let path = path.display().to_string();
+    if path.contains(char::is_whitespace) {
+        format!("\"{path}\"")
+    } else {
+        path
+    }
+}
+
+fn selinux_type_enforcement(inputs: &HardenInputs) -> String {
+    let mut out = header(inputs);
+    out.push_str("policy_module(wassette, 1.0.0)\n\n");
+    out.push_str("type wassette_t;\ntype wassette_exec_t;\n");
+    out.push_str("init_daemon_domain(wassette_t, wassette_exec_t)\n\n");
+
+    out.push_str("# Components, policies and runtime state\n");
+    out.push_str("type wassette_data_t;\nfiles_type(wassette_data_t)\n");
+    out.push_str("manage_dirs_pattern(wassette_t, wassette_data_t, wassette_data_t)\n");
+    out.push_str("manage_files_pattern(wassette_t, wassette_data_t, wassette_data_t)\n\n");
+
+    out.push_str("# Component secrets\n");
+    out.push_str("type wassette_secret_t;\nfiles_type(wassette_secret_t)\n");
+    out.push_str("manage_dirs_pattern(wassette_t, wassette_secret_t, wassette_secret_t)\n");
+    out.push_str("manage_files_pattern(wassette_t, wassette_secret_t, wassette_secret_t)\n\n");
+
+    out.push_str("# Configuration\n");
+    out.push_str("type wassette_conf_t;\nfiles_config_file(wassette_conf_t)\n");
+    out.push_str("read_files_pattern(wassette_t, wassette_conf_t, wassette_conf_t)\n\n");
+
+    let (writable, read_only): (Vec<_>, Vec<_>) = inputs
+        .access
+        .directories
+        .iter()
+        .partition(|(_, writable)| **writable);
+    if !read_only.is_empty() || !inputs.access.mounted_files.is_empty() {
+        out.push_str("# Granted read-only to components by their policies\n");
+        out.push_str("type wassette_content_t;\nfiles_type(wassette_content_t)\n");
+        out.push_str("list_dirs_pattern(wassette_t, wassette_content_t, wassette_content_t)\n");
+        out.push_str("read_files_pattern(wassette_t, wassette_content_t, wassette_content_t)\n\n");
+    }
+    if !writable.is_empty() {
+        out.push_str("# Granted read-write to components by their policies\n");
+        out.push_str("type wassette_rw_content_t;\nfiles_type(wassette_rw_content_t)\n");
+        out.push_str(
+            "manage_dirs_pattern(wassette_t, wassette_rw_content_t, wassette_rw_content_t)\n",
+        );
+        out.push_str(
+            "manage_files_pattern(wassette_t, wassette_rw_content_t, wassette_rw_content_t)\n\n",
+        );
+    }
+
+    out.push_str("# Registry pulls, HTTP transports and component network access\n");
+    out.push_str("sysnet_dns_name_resolve(wassette_t)\n");
+    out.push_str("miscfiles_read_generic_certs(wassette_t)\n");
+    out.push_str("corenet_tcp_connect_http_port(wassette_t)\n");
+    out.push_str("corenet_tcp_bind_generic_node(wassette_t)\n");
+    out.push_str("corenet_tcp_bind_all_unreserved_ports(wassette_t)\n");
+    if inputs.access.email {
+        out.push_str("\n# Email sent by components\n");
+        out.push_str("corenet_tcp_connect_smtp_port(wassette_t)\n");
+    }
+    let services: Vec<&Service> = inputs
+        .services
+        .iter()
+        .filter(|service| !HTTP_PORTS.contains(&service.port))
+        .collect();
+    if !services.is_empty() {
+        let names: Vec<String> = services
+            .iter()
+            .map(|service| format!("{} ({})", service.name, service.port))
+            .collect();
+        let _ = writeln!(out, "\n# {}", names.join(", "));
+        if services.iter().any(|service| service.port < 1024) {
+            out.push_str("corenet_tcp_connect_all_ports(wassette_t)\n");
+        } else {
+            out.push_str("corenet_tcp_connect_all_unreserved_ports(wassette_t)\n");
+        }
+    }
+
+    if inputs.shared_socket_dir.is_some() {
+        out.push_str("\n# Socket of the shared server\n");
+        out.push_str("type wassette_runtime_t;\nfiles_type(wassette_runtime_t)\n");
+        out.push_str("manage_dirs_pattern(wassette_t, wassette_runtime_t, wassette_runtime_t)\n");
+        out.push_str("manage_files_pattern(wassette_t, wassette_runtime_t, wassette_runtime_t)\n");
+        out.push_str(
+            "manage_sock_files_pattern(wassette_t, wassette_runtime_t, wassette_runtime_t)\n",
+        );
+        out.push_str(
+            "allow wassette_t self:unix_stream_socket { create_stream_socket_perms connectto };\n",
+        );
+    }
+
+    if !inputs.hook_programs.is_empty() {
+        out.push_str("\n# Lifecycle hooks run in this domain\n");
+        out.push_str("corecmd_exec_bin(wassette_t)\n");
+        out.push_str("corecmd_exec_shell(wassette_t)\n");
+    }
+    if !inputs.browser_executables.is_empty() {
+        out.push_str(
+            "\n# Headless browser, with its temporary profile directory and shared memory\n",
+        );
+        if inputs.hook_programs.is_empty() {
+            out.push_str("corecmd_exec_bin(wassette_t)\n");
+        }
+        out.push_str("files_manage_generic_tmp_dirs(wassette_t)\n");
+        out.push_str("files_manage_generic_tmp_files(wassette_t)\n");
+        out.push_str("fs_manage_tmpfs_files(wassette_t)\n");
+    }
+    out
+}
+
+fn selinux_file_contexts(inputs: &HardenInputs) -> String {
+    let mut out = header(inputs);
+    let context = |file_type: &str| format!("gen_context(system_u:object_r:{file_type},s0)");
+    let _ = writeln!(
+        out,
+        "{}\t--\t{}",
+        selinux_regex(&inputs.executable),
+        context("wassette_exec_t")
+    );
+    let _ = writeln!(
+        out,
+        "{}\t--\t{}",
+        selinux_regex(&inputs.config_file),
+        context("wassette_conf_t")
+    );
+    let _ = writeln!(
+        out,
+        "{}(/.*)?\t\t{}",
+        selinux_regex(&inputs.plugin_dir),
+        context("wassette_data_t")
+    );
+    let _ = writeln!(
+        out,
+        "{}(/.*)?\t\t{}",
+        selinux_regex(&inputs.secrets_dir),
+        context("wassette_secret_t")
+    );
+    for (dir, writable) in &inputs.access.directories {
+        let file_type = if *writable {
+            "wassette_rw_content_t"
+        } else {
+            "wassette_content_t"
+        };
+        let _ = writeln!(
+            out,
+            "{}(/.*)?\t\t{}",
+            selinux_regex(dir),
+            context(file_type)
+        );
+    }
+    for file in &inputs.access.mounted_files {
+        let _ = writeln!(
+            out,
+            "{}\t--\t{}",
+            selinux_regex(file),
+            context("wassette_content_t")
+        );
+    }
+    if let Some(dir) = &inputs.shared_socket_dir {
+        let _ = writeln!(
+            out,
+            "{}(/.*)?\t\t{}",
+            selinux_regex(dir),
+            context("wassette_runtime_t")
+        );
+    }
+    for bundle in &inputs.ca_bundles {
+        let _ = writeln!(out, "{}\t--\t{}", selinux_regex(bundle), context("cert_t"));
+    }
+    out
+}
+
+/// A path as an SELinux file context regular expression.
+fn selinux_regex(path: &Path) -> String {
+    let path = path.display().to_string();
+    let path = path.trim_end_matches('/');
+    let mut regex = String::with_capacity(path.len());
+    for c in path.chars() {
+        if ".+*?^$()[]{}|\\".contains(c) {
+            regex.push('\\');
+        }
+        regex.push(c);
+    }
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn inputs() -> HardenInputs {
+        HardenInputs {
+            executable: PathBuf::from("/usr/local/bin/wassette"),
+            config_file: PathBuf::from("/etc/wassette/config.toml"),
+            plugin_dir: PathBuf::from("/var/lib/wassette/components"),
+            secrets_dir: PathBuf::from("/etc/wassette/secrets"),
+            hook_programs: vec![PathBuf::from("/usr/local/bin/cmdb-sync")],
+            access: GrantedAccess {
+                directories: BTreeMap::from([
+                    (PathBuf::from("/srv/data"), true),
+                    (PathBuf::from("/srv/notes"), false),
+                ]),
+                mounted_files: BTreeSet::from([PathBuf::from("/srv/models.tar")]),
+                hosts: BTreeSet::from(["api.example.com".to_string()]),
+                email: false,
+            },
+            browser_executables: vec![],
+            shared_socket_dir: None,
+            ca_bundles: BTreeSet::new(),
+            services: vec![],
+        }
+    }
+
+    /// [`inputs`] with the browser, the shared server, extra CAs, email, Vault and trace
+    /// export configured.
+    fn inputs_with_features() -> HardenInputs {
+        let mut inputs = inputs();
+        inputs.access.email = true;
+        inputs.browser_executables = vec![
+            PathBuf::from("/usr/bin/chromium"),
+            PathBuf::from("/usr/bin/google-chrome"),
+        ];
+        inputs.shared_socket_dir = Some(PathBuf::from("/run/user/1000/wassette"));
+        inputs.ca_bundles = BTreeSet::from([PathBuf::from("/etc/ssl/corp-inspection.pem")]);
+        inputs.services = [
+            ("Vault", "https://vault.example.com:8200"),
+            (
+                "the OTLP collector",
+                "http://otel.example.com:4318/v1/traces",
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, url)| Service::from_url(name, url))
+        .collect();
+        inputs
+    }
+
+    #[test]
+    fn test_apparmor_profile() {
+        let files = generate(ProfileKind::Apparmor, &inputs());
+        assert_eq!(files.len(), 1);
+        let profile = &files[0].1;
+        assert!(profile.contains("profile wassette /usr/local/bin/wassette {"));
+        assert!(profile.contains("  /var/lib/wassette/components/** rwlk,"));
+        assert!(profile.contains("  /etc/wassette/secrets/** rw,"));
+        assert!(profile.contains("  /srv/data/** rwlk,"));
+        assert!(profile.contains("  /srv/notes/** r,"));
+        assert!(profile.contains("  /srv/models.tar r,"));
+        assert!(profile.contains("  /usr/local/bin/cmdb-sync rix,"));
+        assert!(profile.contains("api.example.com"));
+        assert!(profile.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_selinux_module() {
+        let files = generate(ProfileKind::Selinux, &inputs());
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["wassette.te", "wassette.fc"]);
+
+        let te = &files[0].1;
+        assert!(te.contains("policy_module(wassette, 1.0.0)"));
+        assert!(te.contains("type wassette_rw_content_t;"));
+        assert!(te.contains("type wassette_content_t;"));
+
+        let fc = &files[1].1;
+        assert!(fc.contains(
+            "/etc/wassette/config\\.toml\t--\tgen_context(system_u:object_r:wassette_conf_t,s0)"
+        ));
+        assert!(fc.contains(
+            "/srv/data(/.*)?\t\tgen_context(system_u:object_r:wassette_rw_content_t,s0)"
+        ));
+        assert!(
+            fc.contains("/srv/notes(/.*)?\t\tgen_context(system_u:object_r:wassette_content_t,s0)")
+        );
+    }
+
+    #[test]
+    fn test_profiles_cover_configured_features() {
+        let apparmor = &generate(ProfileKind::Apparmor, &inputs())[0].1;
+        assert!(!apparmor.contains("chromium"));
+        assert!(!apparmor.contains("network unix"));
+
+        let inputs = inputs_with_features();
+        let apparmor = &generate(ProfileKind::Apparmor, &inputs)[0].1;
+        assert!(apparmor.contains("  /usr/bin/chromium rpix,"));
+        assert!(apparmor.contains("  /usr/bin/google-chrome rpix,"));
+        assert!(apparmor.contains("  network unix stream,"));
+        assert!(apparmor.contains("  owner /run/user/1000/wassette/** rwk,"));
+        assert!(apparmor.contains("  /etc/ssl/corp-inspection.pem r,"));
+        assert!(apparmor.contains("# The server connects to Vault at vault.example.com:8200."));
+
+        let files = generate(ProfileKind::Selinux, &inputs);
+        let (te, fc) = (&files[0].1, &files[1].1);
+        assert!(te.contains("corenet_tcp_connect_smtp_port(wassette_t)"));
+        assert!(te.contains("# Vault (8200), the OTLP collector (4318)"));
+        assert!(te.contains("corenet_tcp_connect_all_unreserved_ports(wassette_t)"));
+        assert!(te.contains("type wassette_runtime_t;"));
+        assert!(te.contains("files_manage_generic_tmp_files(wassette_t)"));
+        assert!(fc.contains(
+            "/run/user/1000/wassette(/.*)?\t\tgen_context(system_u:object_r:wassette_runtime_t,s0)"
+        ));
+        assert!(fc.contains(
+            "/etc/ssl/corp-inspection\\.pem\t--\tgen_context(system_u:object_r:cert_t,s0)"
+        ));
+    }
+}
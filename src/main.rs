@@ -10,37 +10,67 @@ use std::future::Future;
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
 use clap::Parser;
 use mcp_server::components::{
     handle_list_components, handle_load_component_cli, handle_unload_component_cli,
+    handle_update_component_cli,
 };
+use mcp_server::resources::UPDATES_RESOURCE_URI;
+use mcp_server::server_info::SERVER_RESOURCE_URI;
 use mcp_server::tools::*;
 use mcp_server::{
-    handle_prompts_list, handle_resources_list, handle_tools_call, handle_tools_list,
-    LifecycleManager,
+    caller_context, canonicalize_resource, canonicalize_tool_result, canonicalize_tools,
+    forward_anomalies, forward_component_output, forward_prompt_changes, forward_resource_changes,
+    forward_rollout_events, handle_completion, handle_prompts_get, handle_prompts_list,
+    handle_resources_list, handle_resources_read, handle_resources_subscribe,
+    handle_resources_unsubscribe, handle_tools_call, handle_tools_list, sync_workspace_roots,
+    CallThrottle, CallThrottled, CallerSubject, ComponentFilter, LifecycleManager,
+    ResourceSubscriptions, ServerDetails,
 };
 use rmcp::model::{
-    CallToolRequestParam, CallToolResult, ErrorData, ListPromptsResult, ListResourcesResult,
-    ListToolsResult, PaginatedRequestParam, ServerCapabilities, ServerInfo, ToolsCapability,
+    CallToolRequestParam, CallToolResult, CompleteRequestParam, CompleteResult, ErrorData,
+    GetPromptRequestParam, GetPromptResult, ListPromptsResult, ListResourcesResult,
+    ListToolsResult, PaginatedRequestParam, PromptsCapability, ReadResourceRequestParam,
+    ReadResourceResult, Reference, ResourcesCapability, ServerCapabilities, ServerInfo,
+    SubscribeRequestParam, ToolsCapability, UnsubscribeRequestParam,
 };
-use rmcp::service::{serve_server, RequestContext, RoleServer};
-use rmcp::transport::streamable_http_server::session::local::LocalSessionManager;
-use rmcp::transport::streamable_http_server::StreamableHttpService;
-use rmcp::transport::{stdio as stdio_transport, SseServer};
+use rmcp::service::{serve_server, NotificationContext, RequestContext, RoleServer};
+use rmcp::transport::stdio as stdio_transport;
 use rmcp::ServerHandler;
 use serde_json::{json, Map, Value};
 use tracing_subscriber::layer::SubscriberExt as _;
 use tracing_subscriber::util::SubscriberInitExt as _;
+use wassette::vendor::VendorMode;
+use wassette::{
+    AuditDecision, AuditEvent, AuditQuery, AuditRecord, ComponentLoadOutcome, ComponentUpdate,
+    Contract, SecretsBackendConfig, ToolNaming, DEFAULT_TOOL_SEPARATOR,
+};
 
+mod activation;
+mod auth;
 mod commands;
+mod compression;
 mod config;
+mod cors;
 mod format;
+mod harden;
+mod mcp_shim;
+mod metrics;
+mod openapi;
+mod selftest;
+mod shared;
+mod sse;
+mod streamable_http;
+mod telemetry;
 
+use activation::IdleTimer;
 use commands::{
-    Cli, Commands, ComponentCommands, GrantPermissionCommands, PermissionCommands, PolicyCommands,
-    RevokePermissionCommands, SecretCommands, Serve, Transport,
+    AuditCommands, Cli, Commands, ComponentCommands, ConfigCommands, DebugCommands,
+    GrantPermissionCommands, PermissionCommands, PolicyCommands, PresetCommands,
+    RevokePermissionCommands, SecretCommands, SecurityCommands, Serve, StateCommands, Transport,
 };
 use format::{print_result, OutputFormat};
 
@@ -49,8 +79,10 @@ use format::{print_result, OutputFormat};
 enum ToolName {
     LoadComponent,
     UnloadComponent,
+    UpdateComponent,
     ListComponents,
     GetPolicy,
+    InspectComponent,
     GrantStoragePermission,
     GrantNetworkPermission,
     GrantEnvironmentVariablePermission,
@@ -68,8 +100,10 @@ impl TryFrom<&str> for ToolName {
         match value {
             "load-component" => Ok(Self::LoadComponent),
             "unload-component" => Ok(Self::UnloadComponent),
+            "update-component" => Ok(Self::UpdateComponent),
             "list-components" => Ok(Self::ListComponents),
             "get-policy" => Ok(Self::GetPolicy),
+            "inspect-component" => Ok(Self::InspectComponent),
             "grant-storage-permission" => Ok(Self::GrantStoragePermission),
             "grant-network-permission" => Ok(Self::GrantNetworkPermission),
             "grant-environment-variable-permission" => Ok(Self::GrantEnvironmentVariablePermission),
@@ -98,8 +132,10 @@ impl AsRef<str> for ToolName {
         match self {
             Self::LoadComponent => "load-component",
             Self::UnloadComponent => "unload-component",
+            Self::UpdateComponent => "update-component",
             Self::ListComponents => "list-components",
             Self::GetPolicy => "get-policy",
+            Self::InspectComponent => "inspect-component",
             Self::GrantStoragePermission => "grant-storage-permission",
             Self::GrantNetworkPermission => "grant-network-permission",
             Self::GrantEnvironmentVariablePermission => "grant-environment-variable-permission",
@@ -133,6 +169,54 @@ fn parse_env_var(s: &str) -> Result<(String, String), String> {
     }
 }
 
+/// Parse a duration such as `90`, `90s`, `10m`, `2h` or `7d` into seconds.
+fn parse_duration_secs(s: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid duration '{s}', expected e.g. 90s, 10m, 2h or 7d");
+    let (number, scale) = match s.find(|c: char| !c.is_ascii_digit()) {
+        None => (s, 1),
+        Some(i) => match s.split_at(i) {
+            (number, "s") => (number, 1),
+            (number, "m") => (number, 60),
+            (number, "h") => (number, 60 * 60),
+            (number, "d") => (number, 24 * 60 * 60),
+            _ => return Err(invalid()),
+        },
+    };
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    number
+        .checked_mul(scale)
+        .ok_or_else(|| format!("Duration '{s}' is too long"))
+}
+
+/// Parse the kind of an audit record, as written in the log.
+fn parse_audit_event(s: &str) -> Result<AuditEvent, String> {
+    serde_json::from_value(Value::String(s.to_string()))
+        .map_err(|_| format!("Unknown audit event '{s}'"))
+}
+
+/// Parse the decision of an audit record, as written in the log.
+fn parse_audit_decision(s: &str) -> Result<AuditDecision, String> {
+    serde_json::from_value(Value::String(s.to_string()))
+        .map_err(|_| format!("Unknown decision '{s}', expected allowed or denied"))
+}
+
+/// Parse a `<component-id>.<tool-name>` reference. Tool names never contain dots, so the last
+/// dot separates the two parts.
+fn parse_tool_target(s: &str) -> Result<(String, String), String> {
+    match s.rsplit_once('.') {
+        Some((component_id, tool)) if !component_id.is_empty() && !tool.is_empty() => {
+            Ok((component_id.to_string(), tool.to_string()))
+        }
+        _ => Err("Tool must be in <component-id>.<tool-name> format".to_string()),
+    }
+}
+
+/// Interpret a preset value as JSON when possible so numbers, booleans and objects keep their
+/// type, falling back to a plain string.
+fn parse_preset_value(raw: &str) -> Value {
+    serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
 /// Load environment variables from a file (supports .env format)
 fn load_env_file(path: &PathBuf) -> Result<HashMap<String, String>, anyhow::Error> {
     use std::fs;
@@ -187,13 +271,22 @@ mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
 
-const BIND_ADDRESS: &str = "127.0.0.1:9001";
-
 /// A security-oriented runtime that runs WebAssembly Components via MCP.
 #[derive(Clone)]
 pub struct McpServer {
     lifecycle_manager: LifecycleManager,
+    server_details: Arc<ServerDetails>,
+    /// The client of this session
     peer: Arc<Mutex<Option<rmcp::Peer<rmcp::RoleServer>>>>,
+    /// The clients of all sessions, for notifications meant for every client
+    peers: Arc<Mutex<Vec<rmcp::Peer<rmcp::RoleServer>>>>,
+    idle: Option<Arc<IdleTimer>>,
+    /// Resources the client of this session subscribed to
+    resource_subscriptions: ResourceSubscriptions,
+    /// Caps on the tool calls of each component, across sessions
+    call_throttle: CallThrottle,
+    /// Whether JSON output is written canonically
+    canonical_json: bool,
 }
 
 /// Handle CLI tool commands by creating appropriate tool call requests
@@ -213,8 +306,10 @@ async fn handle_tool_cli_command(
     let result = match tool {
         ToolName::LoadComponent => handle_load_component_cli(&req, lifecycle_manager).await?,
         ToolName::UnloadComponent => handle_unload_component_cli(&req, lifecycle_manager).await?,
+        ToolName::UpdateComponent => handle_update_component_cli(&req, lifecycle_manager).await?,
         ToolName::ListComponents => handle_list_components(lifecycle_manager).await?,
         ToolName::GetPolicy => handle_get_policy(&req, lifecycle_manager).await?,
+        ToolName::InspectComponent => handle_inspect_component(&req, lifecycle_manager).await?,
         ToolName::GrantStoragePermission => {
             handle_grant_storage_permission(&req, lifecycle_manager).await?
         }
@@ -250,6 +345,54 @@ async fn handle_tool_cli_command(
     Ok(())
 }
 
+/// Write the files of a generated component project to `output`, or a directory named after
+/// the component, and build it if asked to.
+async fn write_component_project(
+    component: &openapi::GeneratedComponent,
+    output: Option<&PathBuf>,
+    force: bool,
+    build: bool,
+) -> Result<()> {
+    let dir = output
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from(&component.name));
+    if !force && dir.join("Cargo.toml").exists() {
+        bail!(
+            "{} already holds a project, pass --force to overwrite it",
+            dir.display()
+        );
+    }
+    for (name, content) in &component.files {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        eprintln!("Wrote {}", path.display());
+    }
+    eprintln!("Tools: {}", component.functions.join(", "));
+
+    if build {
+        let status = tokio::process::Command::new("cargo")
+            .args(["build", "--release", "--target", "wasm32-wasip2"])
+            .current_dir(&dir)
+            .status()
+            .await
+            .context("Failed to run cargo")?;
+        if !status.success() {
+            bail!("Building {} failed: {status}", dir.display());
+        }
+        let built = dir.join("target").join(component.artifact());
+        let wasm = dir.join(format!("{}.wasm", component.name));
+        std::fs::copy(&built, &wasm)
+            .with_context(|| format!("Failed to copy {}", built.display()))?;
+        eprintln!("Built {}", wasm.display());
+    }
+    Ok(())
+}
+
 /// Create LifecycleManager from plugin directory
 ///
 /// For CLI responsiveness, we create an unloaded lifecycle manager which
@@ -263,33 +406,221 @@ async fn create_lifecycle_manager(plugin_dir: Option<PathBuf>) -> Result<Lifecyc
                 eprintln!("WARN: Unable to determine default secrets directory, using `secrets` directory in the current working directory");
                 PathBuf::from("./secrets")
             }),
+            secrets: Default::default(),
             environment_vars: std::collections::HashMap::new(),
+            coredump_on_trap: false,
+            strict_policy: false,
+            fs_hardening: Default::default(),
+            profile_guest: vec![],
+            workspace_components: vec![],
+            tool_separator: DEFAULT_TOOL_SEPARATOR.to_string(),
+            tool_collisions: Default::default(),
+            output: Default::default(),
+            pull: Default::default(),
+            registries: Default::default(),
+            signatures: Default::default(),
+            provenance: Default::default(),
+            libraries: Default::default(),
+            tools: Default::default(),
+            runtime: Default::default(),
+            max_concurrent_calls: None,
+            hooks: Default::default(),
+            sse: Default::default(),
+            http: Default::default(),
+            compression: Default::default(),
+            allowed_origins: vec![],
+            auth: None,
+            context: Default::default(),
+            wasm_proposals: None,
+            browser: None,
+            network: Default::default(),
+            updates: None,
+            kept_versions: None,
+            permission_prompts: Default::default(),
+            anomaly_detection: None,
+            catalog: Default::default(),
+            watch: false,
+            locked: false,
+            lockfile: None,
+            call_timeout_secs: None,
+            call_overrides: Default::default(),
+            idle_timeout_secs: None,
+            canonical_json: false,
+            bind_address: config::DEFAULT_BIND_ADDRESS.to_string(),
         }
     } else {
         config::Config::from_serve(&crate::Serve {
             plugin_dir: None,
             transport: Default::default(),
+            shared: false,
+            shared_server: false,
             env_vars: vec![],
             env_file: None,
+            coredump_on_trap: false,
+            strict_policy: false,
+            profile_guest: vec![],
+            workspace_components: vec![],
+            sse: Default::default(),
+            http: Default::default(),
+            allowed_origins: vec![],
+            watch: false,
+            locked: false,
+            call_timeout_secs: None,
+            idle_timeout_secs: None,
+            canonical_json: false,
+            bind_address: None,
         })
         .context("Failed to load configuration")?
     };
 
     // Use unloaded manager for fast CLI startup, but preserve custom secrets dir
+    build_lifecycle_manager(config).await
+}
+
+/// Serve `router` and the load metrics of `lifecycle_manager` on `bind_address`, or on the
+/// socket passed by systemd socket activation, until Ctrl-C or, with `idle`, until the server
+/// has been idle for its timeout. Browser origins that aren't allowed are refused and, with
+/// `authorizer`, so are requests to `router` without a valid access token. Responses are
+/// compressed for clients that accept it, as `compression` configures.
+async fn serve_http(
+    router: axum::Router,
+    lifecycle_manager: LifecycleManager,
+    bind_address: &str,
+    allowed_origins: cors::AllowedOrigins,
+    authorizer: Option<Arc<auth::Authorizer>>,
+    compression: compression::CompressionConfig,
+    idle: Option<Arc<IdleTimer>>,
+) -> Result<()> {
+    let router = match authorizer {
+        Some(authorizer) => auth::apply(router, authorizer),
+        None => router,
+    };
+    let router = router.merge(metrics::router(lifecycle_manager));
+    let router = compression::apply(router, compression);
+    let router = cors::apply(router, allowed_origins);
+    let tcp_listener = match activation::activated_listener()
+        .context("Failed to use the socket passed by systemd")?
+    {
+        Some(tcp_listener) => {
+            tracing::info!("Listening on the socket passed by systemd");
+            tcp_listener
+        }
+        None => tokio::net::TcpListener::bind(bind_address)
+            .await
+            .with_context(|| format!("Failed to listen on {bind_address}"))?,
+    };
+    axum::serve(tcp_listener, router)
+        .with_graceful_shutdown(activation::shutdown_signal(idle))
+        .await?;
+    Ok(())
+}
+
+/// Build an unloaded lifecycle manager from the resolved configuration. Components are loaded
+/// lazily or by the background loader, never eagerly during construction.
+async fn build_lifecycle_manager(config: config::Config) -> Result<LifecycleManager> {
     let config::Config {
         plugin_dir,
         secrets_dir,
+        secrets,
         environment_vars,
+        coredump_on_trap,
+        strict_policy,
+        fs_hardening,
+        profile_guest,
+        workspace_components,
+        tool_separator,
+        tool_collisions,
+        output,
+        pull,
+        registries,
+        signatures,
+        provenance,
+        libraries,
+        tools,
+        runtime,
+        max_concurrent_calls,
+        hooks,
+        sse: _,
+        http: _,
+        compression: _,
+        bind_address: _,
+        allowed_origins: _,
+        auth: _,
+        context,
+        wasm_proposals,
+        browser,
+        network,
+        updates,
+        kept_versions,
+        permission_prompts,
+        anomaly_detection,
+        catalog,
+        watch,
+        locked,
+        lockfile,
+        call_timeout_secs,
+        call_overrides,
+        idle_timeout_secs: _,
+        canonical_json: _,
     } = config;
 
-    LifecycleManager::builder(plugin_dir)
+    let mut builder = LifecycleManager::builder(plugin_dir)
         .with_environment_vars(environment_vars)
         .with_secrets_dir(secrets_dir)
+        .with_secrets_backend(secrets)
         .with_oci_client(oci_client::Client::default())
         .with_http_client(reqwest::Client::default())
-        .with_eager_loading(false)
-        .build()
-        .await
+        .with_coredump_on_trap(coredump_on_trap)
+        .with_strict_policy(strict_policy)
+        .with_fs_hardening(fs_hardening)
+        .with_guest_profiling(profile_guest)
+        .with_workspace_components(workspace_components)
+        .with_tool_naming(ToolNaming {
+            separator: tool_separator,
+            collisions: tool_collisions,
+        })
+        .with_output(output)
+        .with_pull_options(pull)
+        .with_registries(registries)
+        .with_signatures(signatures)
+        .with_provenance(provenance)
+        .with_libraries(libraries)
+        .with_tool_settings(tools)
+        .with_runtime_settings(runtime)
+        .with_hooks(hooks)
+        .with_session_profile(context)
+        .with_network(network)
+        .with_override_ceiling(call_overrides)
+        .with_watch(watch)
+        .with_locked(locked)
+        .with_catalog(catalog)
+        .with_eager_loading(false);
+    if let Some(max_concurrent_calls) = max_concurrent_calls {
+        builder = builder.with_max_concurrent_calls(max_concurrent_calls);
+    }
+    if let Some(secs) = call_timeout_secs {
+        builder = builder.with_call_timeout(Duration::from_secs(secs));
+    }
+    if let Some(wasm_proposals) = wasm_proposals {
+        builder = builder.with_wasm_proposals(wasm_proposals);
+    }
+    if let Some(browser) = browser {
+        builder = builder.with_browser(browser);
+    }
+    if let Some(lockfile) = lockfile {
+        builder = builder.with_lockfile(lockfile);
+    }
+    if let Some(updates) = updates {
+        builder = builder.with_updates(updates);
+    }
+    if let Some(kept_versions) = kept_versions {
+        builder = builder.with_kept_versions(kept_versions);
+    }
+    builder = builder.with_permission_prompts(permission_prompts);
+    if let Some(anomaly_detection) = anomaly_detection {
+        builder = builder.with_anomaly_detection(anomaly_detection);
+    }
+    builder.build().await
 }
 
 impl McpServer {
@@ -297,24 +628,269 @@ impl McpServer {
     ///
     /// # Arguments
     /// * `lifecycle_manager` - The lifecycle manager for handling component operations
-    pub fn new(lifecycle_manager: LifecycleManager) -> Self {
+    /// * `server_details` - What `get-server-info` reports about this server
+    pub fn new(lifecycle_manager: LifecycleManager, server_details: ServerDetails) -> Self {
         Self {
             lifecycle_manager,
+            server_details: Arc::new(server_details),
+            peer: Arc::new(Mutex::new(None)),
+            peers: Arc::default(),
+            idle: None,
+            resource_subscriptions: ResourceSubscriptions::default(),
+            call_throttle: CallThrottle::default(),
+            canonical_json: false,
+        }
+    }
+
+    /// A handler for another client session, sharing everything but the session's client
+    /// and subscriptions.
+    pub fn for_session(&self) -> Self {
+        Self {
             peer: Arc::new(Mutex::new(None)),
+            resource_subscriptions: ResourceSubscriptions::default(),
+            ..self.clone()
         }
     }
 
+    /// Count requests as activity on `idle`, which expires once there are none for its
+    /// timeout.
+    pub fn with_idle_timer(mut self, idle: Arc<IdleTimer>) -> Self {
+        self.idle = Some(idle);
+        self
+    }
+
+    /// Write the JSON of tool results, tool schemas and resources with sorted keys and no
+    /// insignificant whitespace, and list tools by name, so recorded sessions diff cleanly.
+    pub fn with_canonical_json(mut self, canonical_json: bool) -> Self {
+        self.canonical_json = canonical_json;
+        self
+    }
+
+    /// Count a request as in progress until the returned guard is dropped.
+    fn begin_request(&self) -> Option<activation::Request> {
+        self.idle.as_ref().map(|idle| idle.begin())
+    }
+
     /// Store the peer for background notifications (called on first request)
     fn store_peer_if_empty(&self, peer: rmcp::Peer<rmcp::RoleServer>) {
         let mut peer_guard = self.peer.lock().unwrap();
         if peer_guard.is_none() {
-            *peer_guard = Some(peer);
+            *peer_guard = Some(peer.clone());
+            self.peers.lock().unwrap().push(peer);
+        }
+    }
+
+    /// Tell the client of every session that is still connected that the tool list changed.
+    pub fn notify_tool_list_changed(&self) {
+        let mut peers = self.peers.lock().unwrap();
+        peers.retain(|peer| !peer.is_transport_closed());
+        for peer in peers.iter() {
+            let peer = peer.clone();
+            tokio::spawn(async move {
+                if let Err(e) = peer.notify_tool_list_changed().await {
+                    tracing::warn!("Failed to notify tool list changed: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Whether the access token behind `grant` allows calling `tool`.
+    async fn may_use_tool(&self, grant: &auth::Grant, tool: &str) -> bool {
+        if is_builtin_tool(tool) {
+            return grant.may_manage();
         }
+        self.lifecycle_manager
+            .get_component_id_for_tool(tool)
+            .await
+            .is_ok_and(|component_id| grant.may_call(&component_id))
     }
 
-    /// Get a clone of the stored peer if available
-    pub fn get_peer(&self) -> Option<rmcp::Peer<rmcp::RoleServer>> {
-        self.peer.lock().unwrap().clone()
+    /// Whether a client holding `grant` may override a call's limits. Without a token only an
+    /// explicit `allow_unauthenticated` under `[call_overrides]` lets it.
+    fn may_override(&self, grant: Option<&auth::Grant>) -> bool {
+        self.lifecycle_manager
+            .accepts_overrides_from(grant.map(auth::Grant::may_manage))
+    }
+
+    /// Component of the prompt clients know as `name`, if there is one.
+    fn prompt_component(&self, name: &str) -> Option<String> {
+        self.lifecycle_manager
+            .list_prompts()
+            .into_iter()
+            .find(|prompt| prompt.name == name)
+            .map(|prompt| prompt.component_id)
+    }
+
+    /// Whether the access token behind `grant` allows the prompt clients know as `name`.
+    /// Unknown prompts are left for the request to fail on.
+    fn may_use_prompt(&self, grant: &auth::Grant, name: &str) -> bool {
+        match self.prompt_component(name) {
+            Some(component_id) => grant.may_call(&component_id),
+            None => true,
+        }
+    }
+
+    /// Whether the access token behind `grant` allows reading the resource at `uri`. The
+    /// server's own resources need the manage scope, like its built-in tools; unknown
+    /// resources are left for the request to fail on.
+    fn may_read_resource(&self, grant: &auth::Grant, uri: &str) -> bool {
+        if uri == SERVER_RESOURCE_URI || uri == UPDATES_RESOURCE_URI {
+            return grant.may_manage();
+        }
+        match self
+            .lifecycle_manager
+            .published_resources()
+            .into_iter()
+            .find(|resource| resource.uri == uri)
+        {
+            Some(resource) => grant.may_call(&resource.component_id),
+            None => true,
+        }
+    }
+
+    /// Whether the access token behind `grant` allows completing the arguments of the prompt
+    /// or tool `reference` names.
+    async fn may_complete(&self, grant: &auth::Grant, reference: &Reference) -> bool {
+        match reference {
+            Reference::Prompt(prompt) => match self.prompt_component(&prompt.name) {
+                Some(component_id) => grant.may_call(&component_id),
+                None => self.may_use_tool(grant, &prompt.name).await,
+            },
+            Reference::Resource(resource) => self.may_read_resource(grant, &resource.uri),
+        }
+    }
+
+    /// Complete the argument in `params` for a client holding `grant`.
+    async fn complete_for(
+        &self,
+        grant: Option<&auth::Grant>,
+        params: CompleteRequestParam,
+    ) -> Result<CompleteResult, ErrorData> {
+        if let Some(grant) = grant {
+            if !self.may_complete(grant, &params.r#ref).await {
+                tracing::warn!(subject = ?grant.subject, reference = ?params.r#ref, "Refused completion outside the token's scope");
+                return Err(ErrorData::invalid_request(
+                    "The access token doesn't allow completing these arguments",
+                    None,
+                ));
+            }
+        }
+        handle_completion(params, &self.lifecycle_manager)
+            .await
+            .map_err(|err| ErrorData::invalid_params(err.to_string(), None))
+    }
+
+    /// The prompts a client holding `grant` may get.
+    fn prompts_for(&self, grant: Option<&auth::Grant>) -> ListPromptsResult {
+        let mut prompts = handle_prompts_list(&self.lifecycle_manager);
+        if let Some(grant) = grant {
+            prompts
+                .prompts
+                .retain(|prompt| self.may_use_prompt(grant, &prompt.name));
+        }
+        prompts
+    }
+
+    /// Render the prompt in `params` for a client holding `grant`.
+    fn prompt_for(
+        &self,
+        grant: Option<&auth::Grant>,
+        params: &GetPromptRequestParam,
+    ) -> Result<GetPromptResult, ErrorData> {
+        if let Some(grant) = grant {
+            if !self.may_use_prompt(grant, &params.name) {
+                tracing::warn!(subject = ?grant.subject, prompt = %params.name, "Refused prompt outside the token's scope");
+                return Err(ErrorData::invalid_request(
+                    format!("The access token doesn't allow the prompt {}", params.name),
+                    None,
+                ));
+            }
+        }
+        handle_prompts_get(params, &self.lifecycle_manager)
+            .map_err(|err| ErrorData::invalid_params(err.to_string(), None))
+    }
+
+    /// The resources a client holding `grant` may read.
+    fn resources_for(&self, grant: Option<&auth::Grant>) -> ListResourcesResult {
+        let mut resources = handle_resources_list(&self.lifecycle_manager);
+        if let Some(grant) = grant {
+            resources
+                .resources
+                .retain(|resource| self.may_read_resource(grant, &resource.uri));
+        }
+        resources
+    }
+
+    /// Refuse access to the resource at `uri` unless `grant` allows reading it.
+    fn check_resource_scope(
+        &self,
+        grant: Option<&auth::Grant>,
+        uri: &str,
+    ) -> Result<(), ErrorData> {
+        match grant {
+            Some(grant) if !self.may_read_resource(grant, uri) => {
+                tracing::warn!(subject = ?grant.subject, uri, "Refused resource outside the token's scope");
+                Err(ErrorData::invalid_request(
+                    format!("The access token doesn't allow reading {uri}"),
+                    None,
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Read the resource in `params` for a client holding `grant`.
+    async fn read_resource_for(
+        &self,
+        grant: Option<&auth::Grant>,
+        params: &ReadResourceRequestParam,
+    ) -> Result<ReadResourceResult, ErrorData> {
+        self.check_resource_scope(grant, &params.uri)?;
+        handle_resources_read(params, &self.lifecycle_manager, &self.server_details)
+            .await
+            .map_err(|err| ErrorData::resource_not_found(err.to_string(), None))
+    }
+
+    /// Subscribe a client holding `grant` to the resource in `params`.
+    fn subscribe_for(
+        &self,
+        grant: Option<&auth::Grant>,
+        params: &SubscribeRequestParam,
+    ) -> Result<(), ErrorData> {
+        self.check_resource_scope(grant, &params.uri)?;
+        handle_resources_subscribe(
+            params,
+            &self.lifecycle_manager,
+            &self.resource_subscriptions,
+        )
+        .map_err(|err| ErrorData::resource_not_found(err.to_string(), None))
+    }
+
+    /// Record in the audit log that the call in `params` was refused because of `reason`.
+    async fn audit_refused_call(
+        &self,
+        params: &CallToolRequestParam,
+        ctx: &RequestContext<RoleServer>,
+        reason: &str,
+    ) {
+        let component_id = if is_builtin_tool(&params.name) {
+            None
+        } else {
+            self.lifecycle_manager
+                .get_component_id_for_tool(&params.name)
+                .await
+                .ok()
+        };
+        let arguments = serde_json::to_string(&params.arguments.clone().unwrap_or_default())
+            .unwrap_or_default();
+        let record = AuditRecord::tool_call(
+            component_id.as_deref(),
+            &params.name,
+            &arguments,
+            &caller_context(ctx),
+        )
+        .refused(reason);
+        self.lifecycle_manager.audit(&record);
     }
 }
 
@@ -326,6 +902,15 @@ impl ServerHandler for McpServer {
                 tools: Some(ToolsCapability {
                     list_changed: Some(true),
                 }),
+                completions: Some(Default::default()),
+                logging: Some(Default::default()),
+                prompts: Some(PromptsCapability {
+                    list_changed: Some(true),
+                }),
+                resources: Some(ResourcesCapability {
+                    subscribe: Some(true),
+                    list_changed: Some(true),
+                }),
                 ..Default::default()
             },
             instructions: Some(
@@ -349,19 +934,81 @@ Key points:
         params: CallToolRequestParam,
         ctx: RequestContext<RoleServer>,
     ) -> Pin<Box<dyn Future<Output = Result<CallToolResult, ErrorData>> + Send + 'a>> {
-        let peer_clone = ctx.peer.clone();
-
         // Store peer on first request
-        self.store_peer_if_empty(peer_clone.clone());
+        self.store_peer_if_empty(ctx.peer.clone());
 
         Box::pin(async move {
-            let result = handle_tools_call(params, &self.lifecycle_manager, peer_clone).await;
-            match result {
+            let _request = self.begin_request();
+            let mut ctx = ctx;
+            if let Some(grant) = auth::request_grant(&ctx.extensions) {
+                let allowed = self.may_use_tool(grant, &params.name).await;
+                let subject = grant.subject.clone();
+                if let Some(subject) = subject.clone() {
+                    ctx.extensions.insert(CallerSubject(subject));
+                }
+                if !allowed {
+                    tracing::warn!(subject = ?subject, tool = %params.name, "Refused tool call outside the token's scope");
+                    self.audit_refused_call(&params, &ctx, "outside the access token's scope")
+                        .await;
+                    return Err(ErrorData::invalid_request(
+                        format!("The access token doesn't allow calling {}", params.name),
+                        None,
+                    ));
+                }
+            }
+            if requested_overrides(&ctx.meta).is_some()
+                && !self.may_override(auth::request_grant(&ctx.extensions))
+            {
+                tracing::warn!(tool = %params.name, "Refused limit overrides from a caller without the manage scope");
+                self.audit_refused_call(
+                    &params,
+                    &ctx,
+                    "limit overrides need the wassette:manage scope",
+                )
+                .await;
+                return Err(ErrorData::invalid_request(
+                    "Limit overrides need an access token with the wassette:manage scope",
+                    None,
+                ));
+            }
+            let result = handle_tools_call(
+                params,
+                &self.lifecycle_manager,
+                &self.server_details,
+                &self.call_throttle,
+                ctx,
+            )
+            .await;
+            let mut result: CallToolResult = match result {
                 Ok(value) => serde_json::from_value(value).map_err(|e| {
                     ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
-                }),
-                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
+                })?,
+                Err(err) => {
+                    return Err(match err.downcast::<CallThrottled>() {
+                        Ok(throttled) => throttled.into(),
+                        Err(err) => ErrorData::parse_error(err.to_string(), None),
+                    })
+                }
+            };
+            if self.canonical_json {
+                canonicalize_tool_result(&mut result);
             }
+            Ok(result)
+        })
+    }
+
+    fn complete<'a>(
+        &'a self,
+        params: CompleteRequestParam,
+        ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<CompleteResult, ErrorData>> + Send + 'a>> {
+        // Store peer on first request
+        self.store_peer_if_empty(ctx.peer.clone());
+
+        Box::pin(async move {
+            let _request = self.begin_request();
+            self.complete_for(auth::request_grant(&ctx.extensions), params)
+                .await
         })
     }
 
@@ -374,13 +1021,28 @@ Key points:
         self.store_peer_if_empty(ctx.peer.clone());
 
         Box::pin(async move {
+            let _request = self.begin_request();
             let result = handle_tools_list(&self.lifecycle_manager).await;
-            match result {
+            let mut tools: ListToolsResult = match result {
                 Ok(value) => serde_json::from_value(value).map_err(|e| {
                     ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
-                }),
-                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
+                })?,
+                Err(err) => return Err(ErrorData::parse_error(err.to_string(), None)),
+            };
+            // Only list the tools the client's access token allows it to call.
+            if let Some(grant) = auth::request_grant(&ctx.extensions) {
+                let mut allowed = Vec::with_capacity(tools.tools.len());
+                for tool in tools.tools {
+                    if self.may_use_tool(grant, &tool.name).await {
+                        allowed.push(tool);
+                    }
+                }
+                tools.tools = allowed;
             }
+            if self.canonical_json {
+                canonicalize_tools(&mut tools.tools);
+            }
+            Ok(tools)
         })
     }
 
@@ -393,13 +1055,22 @@ Key points:
         self.store_peer_if_empty(ctx.peer.clone());
 
         Box::pin(async move {
-            let result = handle_prompts_list(serde_json::Value::Null).await;
-            match result {
-                Ok(value) => serde_json::from_value(value).map_err(|e| {
-                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
-                }),
-                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
-            }
+            let _request = self.begin_request();
+            Ok(self.prompts_for(auth::request_grant(&ctx.extensions)))
+        })
+    }
+
+    fn get_prompt<'a>(
+        &'a self,
+        params: GetPromptRequestParam,
+        ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<GetPromptResult, ErrorData>> + Send + 'a>> {
+        // Store peer on first request
+        self.store_peer_if_empty(ctx.peer.clone());
+
+        Box::pin(async move {
+            let _request = self.begin_request();
+            self.prompt_for(auth::request_grant(&ctx.extensions), &params)
         })
     }
 
@@ -412,17 +1083,123 @@ Key points:
         self.store_peer_if_empty(ctx.peer.clone());
 
         Box::pin(async move {
-            let result = handle_resources_list(serde_json::Value::Null).await;
-            match result {
-                Ok(value) => serde_json::from_value(value).map_err(|e| {
-                    ErrorData::parse_error(format!("Failed to parse result: {e}"), None)
-                }),
-                Err(err) => Err(ErrorData::parse_error(err.to_string(), None)),
+            let _request = self.begin_request();
+            Ok(self.resources_for(auth::request_grant(&ctx.extensions)))
+        })
+    }
+
+    fn read_resource<'a>(
+        &'a self,
+        params: ReadResourceRequestParam,
+        ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<ReadResourceResult, ErrorData>> + Send + 'a>> {
+        // Store peer on first request
+        self.store_peer_if_empty(ctx.peer.clone());
+
+        Box::pin(async move {
+            let _request = self.begin_request();
+            let mut result = self
+                .read_resource_for(auth::request_grant(&ctx.extensions), &params)
+                .await?;
+            if self.canonical_json {
+                canonicalize_resource(&mut result);
+            }
+            Ok(result)
+        })
+    }
+
+    fn subscribe<'a>(
+        &'a self,
+        params: SubscribeRequestParam,
+        ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), ErrorData>> + Send + 'a>> {
+        self.store_peer_if_empty(ctx.peer.clone());
+
+        Box::pin(async move {
+            let _request = self.begin_request();
+            self.subscribe_for(auth::request_grant(&ctx.extensions), &params)
+        })
+    }
+
+    fn unsubscribe<'a>(
+        &'a self,
+        params: UnsubscribeRequestParam,
+        ctx: RequestContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), ErrorData>> + Send + 'a>> {
+        self.store_peer_if_empty(ctx.peer.clone());
+
+        Box::pin(async move {
+            let _request = self.begin_request();
+            handle_resources_unsubscribe(&params, &self.resource_subscriptions);
+            Ok(())
+        })
+    }
+
+    fn on_initialized<'a>(
+        &'a self,
+        ctx: NotificationContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        self.store_peer_if_empty(ctx.peer.clone());
+        let grant = auth::request_grant(&ctx.extensions);
+        let callable = component_filter(grant, false);
+        tokio::spawn(forward_component_output(
+            ctx.peer.clone(),
+            self.lifecycle_manager.clone(),
+            callable.clone(),
+        ));
+        tokio::spawn(forward_rollout_events(
+            ctx.peer.clone(),
+            self.lifecycle_manager.clone(),
+            component_filter(grant, true),
+        ));
+        tokio::spawn(forward_anomalies(
+            ctx.peer.clone(),
+            self.lifecycle_manager.clone(),
+            callable.clone(),
+        ));
+        tokio::spawn(forward_resource_changes(
+            ctx.peer.clone(),
+            self.lifecycle_manager.clone(),
+            self.resource_subscriptions.clone(),
+            callable.clone(),
+        ));
+        tokio::spawn(forward_prompt_changes(
+            ctx.peer.clone(),
+            self.lifecycle_manager.clone(),
+            callable,
+        ));
+
+        Box::pin(async move {
+            if let Err(error) = sync_workspace_roots(&ctx.peer, &self.lifecycle_manager).await {
+                tracing::warn!(%error, "Failed to fetch workspace roots");
+            }
+        })
+    }
+
+    fn on_roots_list_changed<'a>(
+        &'a self,
+        ctx: NotificationContext<RoleServer>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        Box::pin(async move {
+            if let Err(error) = sync_workspace_roots(&ctx.peer, &self.lifecycle_manager).await {
+                tracing::warn!(%error, "Failed to fetch workspace roots");
             }
         })
     }
 }
 
+/// Which components a session holding `grant` receives the events of: all of them without a
+/// token, otherwise those the token may call and, if `managed`, every one for a token with the
+/// manage scope.
+fn component_filter(grant: Option<&auth::Grant>, managed: bool) -> ComponentFilter {
+    match grant.cloned() {
+        None => Arc::new(|_| true),
+        Some(grant) => Arc::new(move |component_id| {
+            (managed && grant.may_manage()) || grant.may_call(component_id)
+        }),
+    }
+}
+
 /// Formats build information similar to agentgateway's version output
 fn format_build_info() -> String {
     // Parse Rust version more robustly by looking for version pattern
@@ -464,6 +1241,15 @@ fn format_build_info() -> String {
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    #[cfg(not(unix))]
+    if matches!(&cli.command, Some(Commands::Serve(cfg)) if cfg.shared) {
+        <Cli as clap::CommandFactory>::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--shared relays to a Unix socket, which this platform doesn't have",
+            )
+            .exit();
+    }
 
     // Handle version flag
     if cli.version {
@@ -482,7 +1268,10 @@ async fn main() -> Result<()> {
                     .into()
                 });
 
-                let registry = tracing_subscriber::registry().with(env_filter);
+                let (otel_layer, _telemetry) = telemetry::init()?.unzip();
+                let registry = tracing_subscriber::registry()
+                    .with(env_filter)
+                    .with(otel_layer);
 
                 // Initialize logging based on transport type
                 let transport: Transport = (&cfg.transport).into();
@@ -502,40 +1291,63 @@ async fn main() -> Result<()> {
                 let config =
                     config::Config::from_serve(cfg).context("Failed to load configuration")?;
 
+                // A `--shared` client only relays its stdio; the shared server it starts takes
+                // this branch again with `--shared-server`.
+                let shared_listener = if cfg.shared && matches!(transport, Transport::Stdio) {
+                    match shared::role(&config, cfg.shared_server).await? {
+                        shared::Role::Client(socket) => return shared::proxy_stdio(&socket).await,
+                        shared::Role::Server(listener, socket) => Some((listener, socket)),
+                        shared::Role::Redundant => {
+                            tracing::info!("Another shared server is already running");
+                            return Ok(());
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                let sse_config = config.sse.clone();
+                let http_config = config.http.clone();
+                let compression = config.compression.clone();
+                let bind_address = config.bind_address.clone();
+                let watch = config.watch;
+                let canonical_json = config.canonical_json;
+                let idle = config
+                    .idle_timeout_secs
+                    .filter(|&secs| secs > 0)
+                    .map(|secs| IdleTimer::new(Duration::from_secs(secs)));
+                let scheduled_updates = config.updates.is_some();
+                let allowed_origins = cors::AllowedOrigins::new(config.allowed_origins.clone());
+                let authorizer = config
+                    .auth
+                    .clone()
+                    .map(|auth| auth::Authorizer::new(auth, reqwest::Client::default()))
+                    .transpose()?
+                    .map(Arc::new);
+
                 // Build the lifecycle manager without eagerly loading components so the
                 // background loader is the single source of tool registration.
-                let config::Config {
-                    plugin_dir,
-                    secrets_dir,
-                    environment_vars,
-                } = config;
-
-                let lifecycle_manager = LifecycleManager::builder(plugin_dir)
-                    .with_environment_vars(environment_vars)
-                    .with_secrets_dir(secrets_dir)
-                    .with_oci_client(oci_client::Client::default())
-                    .with_http_client(reqwest::Client::default())
-                    .with_eager_loading(false)
-                    .build()
-                    .await?;
+                let lifecycle_manager = build_lifecycle_manager(config).await?;
 
-                let server = McpServer::new(lifecycle_manager.clone());
+                let mut server = McpServer::new(
+                    lifecycle_manager.clone(),
+                    ServerDetails {
+                        version: built_info::PKG_VERSION.to_string(),
+                        wasmtime_version: env!("WASMTIME_VERSION").to_string(),
+                        transport: transport.name().to_string(),
+                    },
+                )
+                .with_canonical_json(canonical_json);
+                if let Some(idle) = &idle {
+                    server = server.with_idle_timer(Arc::clone(idle));
+                }
 
                 // Start background component loading
                 let server_clone = server.clone();
                 let lifecycle_manager_clone = lifecycle_manager.clone();
                 tokio::spawn(async move {
-                    let notify_fn = move || {
-                        // Notify clients when a new component is loaded (if peer is available)
-                        if let Some(peer) = server_clone.get_peer() {
-                            let peer_clone = peer.clone();
-                            tokio::spawn(async move {
-                                if let Err(e) = peer_clone.notify_tool_list_changed().await {
-                                    tracing::warn!("Failed to notify tool list changed: {}", e);
-                                }
-                            });
-                        }
-                    };
+                    // Notify clients when a new component is loaded
+                    let notify_fn = move || server_clone.notify_tool_list_changed();
 
                     if let Err(e) = lifecycle_manager_clone
                         .load_existing_components_async(None, Some(notify_fn))
@@ -543,51 +1355,99 @@ async fn main() -> Result<()> {
                     {
                         tracing::error!("Background component loading failed: {}", e);
                     }
+                    let interrupted = lifecycle_manager_clone.recover_interrupted_calls().await;
+                    if !interrupted.is_empty() {
+                        let replayed = interrupted
+                            .iter()
+                            .filter(|call| call.replayed.is_some())
+                            .count();
+                        tracing::warn!(
+                            "Recovered {} tool calls interrupted by a crash, replayed {}",
+                            interrupted.len(),
+                            replayed
+                        );
+                    }
                 });
 
+                if watch {
+                    let server_clone = server.clone();
+                    let lifecycle_manager_clone = lifecycle_manager.clone();
+                    tokio::spawn(async move {
+                        let notify_fn =
+                            move |_: &ComponentLoadOutcome| server_clone.notify_tool_list_changed();
+                        if let Err(e) = lifecycle_manager_clone.watch_components(notify_fn).await {
+                            tracing::error!("Watching component sources failed: {}", e);
+                        }
+                    });
+                }
+
+                if scheduled_updates {
+                    let server_clone = server.clone();
+                    let lifecycle_manager_clone = lifecycle_manager.clone();
+                    tokio::spawn(async move {
+                        let notify_fn =
+                            move |_: &ComponentUpdate| server_clone.notify_tool_list_changed();
+                        if let Err(e) = lifecycle_manager_clone
+                            .run_scheduled_updates(notify_fn)
+                            .await
+                        {
+                            tracing::error!("Scheduled component updates failed: {}", e);
+                        }
+                    });
+                }
+
                 match transport {
                     Transport::Stdio => {
-                        tracing::info!("Starting MCP server with stdio transport. Components will load in the background.");
-                        let transport = stdio_transport();
-                        let running_service = serve_server(server, transport).await?;
+                        if let Some((listener, socket)) = shared_listener {
+                            shared::serve(listener, &socket, server, idle).await?;
+                        } else {
+                            tracing::info!("Starting MCP server with stdio transport. Components will load in the background.");
+                            let transport = stdio_transport();
+                            let running_service = serve_server(server, transport).await?;
 
-                        tokio::signal::ctrl_c().await?;
-                        let _ = running_service.cancel().await;
+                            activation::shutdown_signal(idle).await;
+                            let _ = running_service.cancel().await;
+                        }
                     }
                     Transport::StreamableHttp => {
                         tracing::info!(
                         "Starting MCP server on {} with streamable HTTP transport. Components will load in the background.",
-                        BIND_ADDRESS
+                        bind_address
                     );
-                        let service = StreamableHttpService::new(
-                            move || Ok(server.clone()),
-                            LocalSessionManager::default().into(),
-                            Default::default(),
-                        );
-
-                        let router = axum::Router::new().nest_service("/mcp", service);
-                        let tcp_listener = tokio::net::TcpListener::bind(BIND_ADDRESS).await?;
-                        let _ = axum::serve(tcp_listener, router)
-                            .with_graceful_shutdown(async {
-                                tokio::signal::ctrl_c().await.unwrap()
-                            })
-                            .await;
+                        let router =
+                            streamable_http::router(http_config, move || server.for_session());
+                        serve_http(
+                            router,
+                            lifecycle_manager.clone(),
+                            &bind_address,
+                            allowed_origins,
+                            authorizer,
+                            compression,
+                            idle,
+                        )
+                        .await?;
                     }
                     Transport::Sse => {
                         tracing::info!(
                         "Starting MCP server on {} with SSE HTTP transport. Components will load in the background.",
-                        BIND_ADDRESS
+                        bind_address
                     );
-                        let ct = SseServer::serve(BIND_ADDRESS.parse().unwrap())
-                            .await?
-                            .with_service(move || server.clone());
-
-                        tokio::signal::ctrl_c().await?;
-                        ct.cancel();
+                        let router = sse::router(sse_config, move || server.for_session());
+                        serve_http(
+                            router,
+                            lifecycle_manager.clone(),
+                            &bind_address,
+                            allowed_origins,
+                            authorizer,
+                            compression,
+                            idle,
+                        )
+                        .await?;
                     }
                 }
 
                 tracing::info!("MCP server shutting down");
+                lifecycle_manager.shutdown().await;
             }
             Commands::Component { command } => match command {
                 ComponentCommands::Load { path, plugin_dir } => {
@@ -616,6 +1476,23 @@ async fn main() -> Result<()> {
                     )
                     .await?;
                 }
+                ComponentCommands::Update {
+                    id,
+                    plugin_dir,
+                    output_format,
+                } => {
+                    let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
+                    let mut args = Map::new();
+                    args.insert("component_id".to_string(), json!(id));
+                    handle_tool_cli_command(
+                        &lifecycle_manager,
+                        "update-component",
+                        args,
+                        *output_format,
+                    )
+                    .await?;
+                }
                 ComponentCommands::List {
                     plugin_dir,
                     output_format,
@@ -631,30 +1508,352 @@ async fn main() -> Result<()> {
                     )
                     .await?;
                 }
-            },
-            Commands::Policy { command } => match command {
-                PolicyCommands::Get {
-                    component_id,
+                ComponentCommands::Search {
+                    query,
                     plugin_dir,
                     output_format,
                 } => {
                     let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
                     let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
-                    let mut args = Map::new();
-                    args.insert("component_id".to_string(), json!(component_id));
-                    handle_tool_cli_command(&lifecycle_manager, "get-policy", args, *output_format)
-                        .await?;
-                }
-            },
-            Commands::Permission { command } => match command {
-                PermissionCommands::Grant { permission } => match permission {
-                    GrantPermissionCommands::Storage {
-                        component_id,
-                        uri,
-                        access,
-                        plugin_dir,
-                    } => {
-                        let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
+                    let query = query.join(" ");
+                    let search = lifecycle_manager.search_catalog(&query).await;
+                    let result = json!({
+                        "query": query,
+                        "results": search.components,
+                        "total": search.components.len(),
+                        "errors": search.errors,
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        *output_format,
+                    )?;
+                }
+                ComponentCommands::Alias {
+                    id,
+                    alias,
+                    plugin_dir,
+                } => {
+                    let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
+                    lifecycle_manager.alias_component(id, alias).await?;
+                    let result = json!({
+                        "status": "success",
+                        "component_id": id,
+                        "alias": alias,
+                        "message": format!("'{alias}' now stands for component '{id}'")
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                }
+                ComponentCommands::Unalias { alias, plugin_dir } => {
+                    let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
+                    let Some(id) = lifecycle_manager.remove_component_alias(alias).await? else {
+                        bail!("'{alias}' is not a component alias");
+                    };
+                    let result = json!({
+                        "status": "success",
+                        "component_id": id,
+                        "alias": alias,
+                        "message": format!("Removed alias '{alias}'")
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                }
+                ComponentCommands::Aliases { plugin_dir } => {
+                    let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
+                    let aliases = lifecycle_manager.list_component_aliases().await;
+                    let result = json!({ "aliases": aliases });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                }
+                ComponentCommands::Inspect {
+                    path,
+                    plugin_dir,
+                    output_format,
+                } => {
+                    let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
+                    let mut args = Map::new();
+                    args.insert("path".to_string(), json!(path));
+                    handle_tool_cli_command(
+                        &lifecycle_manager,
+                        "inspect-component",
+                        args,
+                        *output_format,
+                    )
+                    .await?;
+                }
+                ComponentCommands::History {
+                    id,
+                    limit,
+                    plugin_dir,
+                    output_format,
+                } => {
+                    let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
+                    let changes = lifecycle_manager
+                        .component_history(id.as_deref(), *limit)
+                        .await?;
+                    let result = json!({
+                        "changes": changes,
+                        "total": changes.len(),
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        *output_format,
+                    )?;
+                }
+                ComponentCommands::Rollback {
+                    id,
+                    to,
+                    plugin_dir,
+                    output_format,
+                } => {
+                    let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
+                    let report = lifecycle_manager
+                        .rollback_component(id, to.as_deref())
+                        .await?;
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&report)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        *output_format,
+                    )?;
+                }
+                ComponentCommands::CheckSchema {
+                    wasm,
+                    against,
+                    plugin_dir,
+                } => {
+                    let document = std::fs::read_to_string(against)
+                        .with_context(|| format!("Failed to read {}", against.display()))?;
+                    let contract = Contract::parse(&document)
+                        .with_context(|| format!("Invalid contract {}", against.display()))?;
+                    let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
+                    let report = lifecycle_manager
+                        .check_component_contract(wasm, &contract)
+                        .await?;
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&report)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                    if !report.is_compatible() {
+                        bail!(
+                            "{} doesn't match {}: {} mismatches",
+                            wasm.display(),
+                            against.display(),
+                            report.mismatches.len()
+                        );
+                    }
+                }
+                ComponentCommands::FromOpenapi {
+                    spec,
+                    output,
+                    name,
+                    base_url,
+                    operations,
+                    build,
+                    force,
+                } => {
+                    let document = std::fs::read_to_string(spec)
+                        .with_context(|| format!("Failed to read {}", spec.display()))?;
+                    let options = openapi::GenerateOptions {
+                        name: name.clone(),
+                        base_url: base_url.clone(),
+                        operations: operations.clone(),
+                    };
+                    let component = openapi::generate(&document, &options).with_context(|| {
+                        format!("Failed to generate a component from {}", spec.display())
+                    })?;
+                    write_component_project(&component, output.as_ref(), *force, *build).await?;
+                }
+                ComponentCommands::FromMcp {
+                    server,
+                    args,
+                    output,
+                    name,
+                    endpoint,
+                    tools,
+                    build,
+                    force,
+                } => {
+                    let command = mcp_shim::server_command(server, args);
+                    eprintln!("Listing the tools of `{}`", command.join(" "));
+                    let listed = mcp_shim::list_tools(&command)
+                        .await
+                        .with_context(|| format!("Failed to list the tools of {server}"))?;
+                    let options = mcp_shim::GenerateOptions {
+                        name: name.clone(),
+                        endpoint: Some(endpoint.clone()),
+                        tools: tools.clone(),
+                    };
+                    let component = mcp_shim::generate(&listed, &options)
+                        .with_context(|| format!("Failed to generate a component from {server}"))?;
+                    write_component_project(&component, output.as_ref(), *force, *build).await?;
+                    eprintln!(
+                        "Serve `{}` over streamable HTTP at {endpoint} for the component to call it",
+                        command.join(" ")
+                    );
+                }
+                ComponentCommands::Vendor {
+                    project,
+                    check,
+                    update,
+                } => {
+                    let mode = if *check {
+                        VendorMode::Check
+                    } else if *update {
+                        VendorMode::Update
+                    } else {
+                        VendorMode::Sync
+                    };
+                    let packages = wassette::vendor::vendor(project, mode)
+                        .await
+                        .with_context(|| format!("Failed to vendor {}", project.display()))?;
+                    for package in &packages {
+                        let state = if package.written {
+                            "vendored"
+                        } else {
+                            "up to date"
+                        };
+                        println!(
+                            "{:<32} {:<12} {} ({})",
+                            package.name, state, package.digest, package.source
+                        );
+                    }
+                    if packages.is_empty() {
+                        eprintln!("The project imports no WIT packages besides its own");
+                    }
+                }
+            },
+            Commands::Policy { command } => match command {
+                PolicyCommands::Get {
+                    component_id,
+                    plugin_dir,
+                    output_format,
+                } => {
+                    let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
+                    let mut args = Map::new();
+                    args.insert("component_id".to_string(), json!(component_id));
+                    handle_tool_cli_command(&lifecycle_manager, "get-policy", args, *output_format)
+                        .await?;
+                }
+                PolicyCommands::Schema { output } => match output {
+                    Some(output) => std::fs::write(output, policy::POLICY_SCHEMA)
+                        .with_context(|| format!("Failed to write {}", output.display()))?,
+                    None => print!("{}", policy::POLICY_SCHEMA),
+                },
+                PolicyCommands::Validate { files } => {
+                    let mut invalid = 0;
+                    let mut results = Vec::new();
+                    for file in files {
+                        let content = std::fs::read_to_string(file)
+                            .with_context(|| format!("Failed to read {}", file.display()))?;
+                        let violations = policy::validate_against_schema(&content)
+                            .with_context(|| format!("Invalid policy {}", file.display()))?;
+                        if !violations.is_empty() {
+                            invalid += 1;
+                        }
+                        results.push(json!({
+                            "file": file.display().to_string(),
+                            "valid": violations.is_empty(),
+                            "violations": violations
+                                .iter()
+                                .map(|violation| json!({
+                                    "pointer": violation.pointer,
+                                    "message": violation.message,
+                                    "line": violation.location.map(|(line, _)| line),
+                                    "column": violation.location.map(|(_, column)| column),
+                                }))
+                                .collect::<Vec<_>>(),
+                        }));
+                    }
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&json!({ "files": results }))?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                    if invalid > 0 {
+                        bail!(
+                            "{invalid} of {} policy files don't match the schema",
+                            files.len()
+                        );
+                    }
+                }
+            },
+            Commands::Permission { command } => match command {
+                PermissionCommands::Grant { permission } => match permission {
+                    GrantPermissionCommands::Storage {
+                        component_id,
+                        uri,
+                        access,
+                        plugin_dir,
+                    } => {
+                        let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
                         let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
                         let mut args = Map::new();
                         args.insert("component_id".to_string(), json!(component_id));
@@ -699,18 +1898,18 @@ async fn main() -> Result<()> {
                     GrantPermissionCommands::EnvironmentVariable {
                         component_id,
                         key,
+                        value,
                         plugin_dir,
                     } => {
                         let plugin_dir = plugin_dir.clone().or_else(|| cli.plugin_dir.clone());
                         let lifecycle_manager = create_lifecycle_manager(plugin_dir).await?;
                         let mut args = Map::new();
                         args.insert("component_id".to_string(), json!(component_id));
-                        args.insert(
-                            "details".to_string(),
-                            json!({
-                                "key": key
-                            }),
-                        );
+                        let mut details = json!({ "key": key });
+                        if let Some(value) = value {
+                            details["value"] = json!(value);
+                        }
+                        args.insert("details".to_string(), details);
                         handle_tool_cli_command(
                             &lifecycle_manager,
                             "grant-environment-variable-permission",
@@ -946,31 +2145,867 @@ async fn main() -> Result<()> {
                         OutputFormat::Json,
                     )?;
                 }
-            },
-        },
-        None => {
-            eprintln!("No command provided. Use --help for usage information.");
-            std::process::exit(1);
-        }
-    }
-
-    Ok(())
-}
+                SecretCommands::Resolve {
+                    component_id,
+                    show_values,
+                    yes,
+                    plugin_dir,
+                    output_format,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
 
-#[cfg(test)]
-mod version_tests {
-    use super::*;
+                    // Prompt for confirmation if showing values
+                    if *show_values && !*yes {
+                        print!("Show resolved values? [y/N]: ");
+                        std::io::Write::flush(&mut std::io::stdout())?;
+                        let mut input = String::new();
+                        std::io::stdin().read_line(&mut input)?;
+                        if !input.trim().eq_ignore_ascii_case("y") {
+                            println!("Cancelled.");
+                            return Ok(());
+                        }
+                    }
 
-    /// Formats build information similar to agentgateway's version output
-    fn format_build_info() -> String {
-        // Parse Rust version more robustly by looking for version pattern
-        // Expected format: "rustc 1.88.0 (extra info)"
-        let rust_version = built_info::RUSTC_VERSION
-            .split_whitespace()
-            .find(|part| part.chars().next().is_some_and(|c| c.is_ascii_digit()))
-            .unwrap_or("unknown");
+                    let variables = lifecycle_manager
+                        .resolve_component_environment(component_id)
+                        .await?
+                        .into_iter()
+                        .map(|variable| {
+                            let mut entry = json!({
+                                "key": variable.key,
+                                "source": variable.source,
+                                "references": variable.references,
+                            });
+                            if *show_values {
+                                entry["value"] = json!(variable.value);
+                            }
+                            entry
+                        })
+                        .collect::<Vec<_>>();
 
-        let build_profile = built_info::PROFILE;
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&json!({
+                                    "component_id": component_id,
+                                    "variables": variables
+                                }))?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        *output_format,
+                    )?;
+                }
+            },
+            Commands::Preset { command } => match command {
+                PresetCommands::List {
+                    component_id,
+                    plugin_dir,
+                    output_format,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                    let presets = lifecycle_manager.list_tool_presets(component_id).await?;
+
+                    let result = json!({
+                        "component_id": component_id,
+                        "presets": presets,
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        *output_format,
+                    )?;
+                }
+                PresetCommands::Set {
+                    tool: (component_id, tool_name),
+                    values,
+                    plugin_dir,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                    let preset: Map<String, Value> = values
+                        .iter()
+                        .map(|(key, value)| (key.clone(), parse_preset_value(value)))
+                        .collect();
+                    lifecycle_manager
+                        .set_tool_preset(component_id, tool_name, preset)
+                        .await?;
+
+                    let result = json!({
+                        "status": "success",
+                        "component_id": component_id,
+                        "tool": tool_name,
+                        "message": format!("Set {} preset(s) for tool", values.len())
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                }
+                PresetCommands::Unset {
+                    tool: (component_id, tool_name),
+                    keys,
+                    plugin_dir,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                    lifecycle_manager
+                        .remove_tool_preset(component_id, tool_name, keys)
+                        .await?;
+
+                    let message = if keys.is_empty() {
+                        "Removed all presets from tool".to_string()
+                    } else {
+                        format!("Removed {} preset(s) from tool", keys.len())
+                    };
+                    let result = json!({
+                        "status": "success",
+                        "component_id": component_id,
+                        "tool": tool_name,
+                        "message": message
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                }
+            },
+            Commands::Install {
+                manifest: location,
+                yes,
+                plugin_dir,
+            } => {
+                let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                let manifest = lifecycle_manager.fetch_manifest(location).await?;
+
+                println!("{} {}", manifest.name, manifest.version);
+                if let Some(description) = &manifest.description {
+                    println!("  {description}");
+                }
+                println!(
+                    "Publisher: {} (signature verified)",
+                    manifest.publisher.name
+                );
+                println!("Component: {}", manifest.component);
+                if !manifest.is_digest_pinned() {
+                    println!("WARNING: the component reference is a tag, not a digest; its contents are not covered by the signature.");
+                }
+                if !manifest.dependencies.is_empty() {
+                    println!("Libraries:");
+                    for dependency in &manifest.dependencies {
+                        let source = lifecycle_manager.library_source(dependency);
+                        println!("  {}: {source}", dependency.name);
+                        if source == dependency.component && !dependency.is_digest_pinned() {
+                            println!("  WARNING: {} is referenced by a tag, not a digest; its contents are not covered by the signature.", dependency.name);
+                        }
+                    }
+                }
+                match &manifest.policy {
+                    Some(policy) => {
+                        println!("Requested permissions:");
+                        let permissions = serde_yaml::to_string(&policy.permissions)?;
+                        for line in permissions.lines() {
+                            println!("  {line}");
+                        }
+                    }
+                    None => println!("Requested permissions: none"),
+                }
+
+                if !*yes {
+                    print!("Install {}? [y/N]: ", manifest.name);
+                    std::io::Write::flush(&mut std::io::stdout())?;
+                    let mut input = String::new();
+                    std::io::stdin().read_line(&mut input)?;
+                    if !input.trim().eq_ignore_ascii_case("y") {
+                        println!("Cancelled.");
+                        return Ok(());
+                    }
+                }
+
+                let outcome = lifecycle_manager
+                    .install_manifest(&manifest, location)
+                    .await?;
+
+                let result = json!({
+                    "status": "success",
+                    "id": outcome.component_id,
+                    "name": manifest.name,
+                    "version": manifest.version,
+                    "publisher": manifest.publisher.name,
+                    "tools": outcome.tool_names,
+                });
+
+                print_result(
+                    &rmcp::model::CallToolResult {
+                        content: Some(vec![rmcp::model::Content::text(
+                            serde_json::to_string_pretty(&result)?,
+                        )]),
+                        structured_content: None,
+                        is_error: None,
+                    },
+                    OutputFormat::Json,
+                )?;
+            }
+            Commands::Debug { command } => match command {
+                DebugCommands::Coredumps {
+                    plugin_dir,
+                    output_format,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                    let coredumps = lifecycle_manager.list_coredumps().await?;
+
+                    let result = json!({
+                        "coredumps": coredumps,
+                        "total": coredumps.len(),
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        *output_format,
+                    )?;
+                }
+                DebugCommands::Coredump {
+                    call_id,
+                    output,
+                    plugin_dir,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                    let (record, dump) = lifecycle_manager.get_coredump(call_id).await?;
+
+                    let output = output
+                        .clone()
+                        .unwrap_or_else(|| PathBuf::from(format!("{call_id}.coredump")));
+                    tokio::fs::write(&output, &dump).await.with_context(|| {
+                        format!("Failed to write core dump to {}", output.display())
+                    })?;
+
+                    let result = json!({
+                        "status": "success",
+                        "call_id": record.call_id,
+                        "component_id": record.component_id,
+                        "function_name": record.function_name,
+                        "error": record.error,
+                        "path": output.display().to_string(),
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                }
+                DebugCommands::FsEscapes {
+                    component,
+                    plugin_dir,
+                    output_format,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                    let mut attempts = lifecycle_manager.list_escape_attempts().await?;
+                    if let Some(component) = component {
+                        attempts.retain(|attempt| &attempt.component_id == component);
+                    }
+
+                    let result = json!({
+                        "attempts": attempts,
+                        "total": attempts.len(),
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        *output_format,
+                    )?;
+                }
+            },
+            Commands::Harden {
+                generate,
+                output,
+                plugin_dir,
+                shared,
+            } => {
+                let config = config::Config::from_serve(&crate::Serve {
+                    plugin_dir: plugin_dir.clone(),
+                    transport: Default::default(),
+                    shared: false,
+                    shared_server: false,
+                    env_vars: vec![],
+                    env_file: None,
+                    coredump_on_trap: false,
+                    strict_policy: false,
+                    profile_guest: vec![],
+                    workspace_components: vec![],
+                    sse: Default::default(),
+                    http: Default::default(),
+                    allowed_origins: vec![],
+                    watch: false,
+                    locked: false,
+                    call_timeout_secs: None,
+                    idle_timeout_secs: None,
+                    canonical_json: false,
+                    bind_address: None,
+                })
+                .context("Failed to load configuration")?;
+                let absolute = |path: &std::path::Path| {
+                    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+                };
+                let plugin_dir = absolute(&config.plugin_dir);
+                let secrets_dir = absolute(&config.secrets_dir);
+                let hook_programs = config
+                    .hooks
+                    .iter()
+                    .filter_map(|hook| hook.command.first())
+                    .map(|program| absolute(&harden::resolve_program(program)))
+                    .collect();
+                let browser_executables = config
+                    .browser
+                    .iter()
+                    .flat_map(|browser| browser.executables())
+                    .map(|executable| absolute(&executable))
+                    .collect();
+                let shared_socket_dir = if *shared {
+                    let socket = shared::socket_path(&config.plugin_dir)?;
+                    socket.parent().map(absolute)
+                } else {
+                    None
+                };
+                let mut ca_bundles: std::collections::BTreeSet<_> =
+                    std::iter::once(&config.network.default)
+                        .chain(config.network.components.values())
+                        .filter_map(|settings| settings.ca_bundle.as_deref())
+                        .map(absolute)
+                        .collect();
+                let mut services = Vec::new();
+                if let SecretsBackendConfig::Vault(vault) = &config.secrets {
+                    ca_bundles.extend(vault.ca_cert.as_deref().map(absolute));
+                    services.extend(harden::Service::from_url("Vault", &vault.address));
+                }
+                if let Ok(endpoint) = std::env::var(telemetry::OTEL_ENDPOINT_ENV) {
+                    services.extend(harden::Service::from_url("the OTLP collector", &endpoint));
+                }
+                let lifecycle_manager = build_lifecycle_manager(config).await?;
+                let inputs = harden::HardenInputs {
+                    executable: std::env::current_exe()
+                        .context("Unable to locate the wassette executable")?,
+                    config_file: absolute(&config::get_config_file()?),
+                    plugin_dir,
+                    secrets_dir,
+                    hook_programs,
+                    access: lifecycle_manager.granted_access().await?,
+                    browser_executables,
+                    shared_socket_dir,
+                    ca_bundles,
+                    services,
+                };
+
+                let files = harden::generate(*generate, &inputs);
+                match output {
+                    Some(dir) => {
+                        std::fs::create_dir_all(dir)
+                            .with_context(|| format!("Failed to create {}", dir.display()))?;
+                        for (name, content) in &files {
+                            let path = dir.join(name);
+                            std::fs::write(&path, content)
+                                .with_context(|| format!("Failed to write {}", path.display()))?;
+                            eprintln!("Wrote {}", path.display());
+                        }
+                    }
+                    None => {
+                        for (index, (name, content)) in files.iter().enumerate() {
+                            if files.len() > 1 {
+                                if index > 0 {
+                                    println!();
+                                }
+                                println!("# ==> {name} <==");
+                            }
+                            print!("{content}");
+                        }
+                    }
+                }
+            }
+            Commands::Config { command } => match command {
+                ConfigCommands::Migrate {
+                    dry_run,
+                    plugin_dir,
+                } => {
+                    let config_file = config::get_config_file()?;
+                    let config_migration = config::migrate_config_file(&config_file, *dry_run)?;
+                    let plugin_dir = match plugin_dir {
+                        Some(dir) => dir.clone(),
+                        None => {
+                            config::Config::new_from_path(
+                                &crate::Serve {
+                                    plugin_dir: None,
+                                    transport: Default::default(),
+                                    shared: false,
+                                    shared_server: false,
+                                    env_vars: vec![],
+                                    env_file: None,
+                                    coredump_on_trap: false,
+                                    strict_policy: false,
+                                    profile_guest: vec![],
+                                    workspace_components: vec![],
+                                    sse: Default::default(),
+                                    http: Default::default(),
+                                    allowed_origins: vec![],
+                                    watch: false,
+                                    locked: false,
+                                    call_timeout_secs: None,
+                                    idle_timeout_secs: None,
+                                    canonical_json: false,
+                                    bind_address: None,
+                                },
+                                &config_file,
+                            )
+                            .context("Failed to load configuration")?
+                            .plugin_dir
+                        }
+                    };
+                    let state_migration = wassette::migrate_state(&plugin_dir, *dry_run).await?;
+
+                    let result = json!({
+                        "dry_run": dry_run,
+                        "config": config_migration,
+                        "state": {
+                            "plugin_dir": plugin_dir.display().to_string(),
+                            "from": state_migration.from,
+                            "to": state_migration.to,
+                            "changes": state_migration.changes,
+                        },
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                }
+                ConfigCommands::Set {
+                    component_id,
+                    values,
+                    plugin_dir,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                    lifecycle_manager
+                        .set_component_config(component_id, values)
+                        .await?;
+
+                    let result = json!({
+                        "status": "success",
+                        "component_id": component_id,
+                        "message": format!("Set {} configuration value(s) for component", values.len())
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                }
+                ConfigCommands::Get {
+                    component_id,
+                    plugin_dir,
+                    output_format,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                    let config = lifecycle_manager
+                        .list_component_config(component_id)
+                        .await?;
+
+                    let result = json!({
+                        "component_id": component_id,
+                        "config": config,
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        *output_format,
+                    )?;
+                }
+                ConfigCommands::Unset {
+                    component_id,
+                    keys,
+                    plugin_dir,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                    let removed = lifecycle_manager
+                        .unset_component_config(component_id, keys)
+                        .await?;
+
+                    let result = json!({
+                        "status": "success",
+                        "component_id": component_id,
+                        "message": format!("Removed {removed} configuration value(s) from component")
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                }
+            },
+            Commands::State {
+                component_id,
+                command,
+                plugin_dir,
+            } => {
+                let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                let result = match command {
+                    StateCommands::Export { output } => {
+                        let state = lifecycle_manager.export_state(component_id).await?;
+                        let json = serde_json::to_string_pretty(&state)?;
+                        let Some(output) = output else {
+                            println!("{json}");
+                            return Ok(());
+                        };
+                        std::fs::write(output, json)
+                            .with_context(|| format!("Failed to write {}", output.display()))?;
+                        json!({
+                            "status": "success",
+                            "component_id": component_id,
+                            "keys": state.keys(),
+                            "bytes": state.bytes(),
+                            "output": output.display().to_string(),
+                        })
+                    }
+                    StateCommands::Import { file } => {
+                        let content = std::fs::read(file)
+                            .with_context(|| format!("Failed to read {}", file.display()))?;
+                        let state: wassette::ComponentState = serde_json::from_slice(&content)
+                            .with_context(|| format!("Failed to parse {}", file.display()))?;
+                        let keys = state.keys();
+                        lifecycle_manager.import_state(component_id, state).await?;
+                        json!({
+                            "status": "success",
+                            "component_id": component_id,
+                            "keys": keys,
+                        })
+                    }
+                    StateCommands::Clear { yes } => {
+                        if !*yes {
+                            print!("Remove all state of component '{component_id}'? [y/N]: ");
+                            std::io::Write::flush(&mut std::io::stdout())?;
+                            let mut input = String::new();
+                            std::io::stdin().read_line(&mut input)?;
+                            if !input.trim().eq_ignore_ascii_case("y") {
+                                println!("Cancelled.");
+                                return Ok(());
+                            }
+                        }
+                        let keys = lifecycle_manager.clear_state(component_id).await?;
+                        json!({
+                            "status": "success",
+                            "component_id": component_id,
+                            "keys_removed": keys,
+                        })
+                    }
+                };
+
+                print_result(
+                    &rmcp::model::CallToolResult {
+                        content: Some(vec![rmcp::model::Content::text(
+                            serde_json::to_string_pretty(&result)?,
+                        )]),
+                        structured_content: None,
+                        is_error: None,
+                    },
+                    OutputFormat::Json,
+                )?;
+            }
+            Commands::Gc {
+                dry_run,
+                yes,
+                keep_secrets,
+                plugin_dir,
+            } => {
+                let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                let orphans = lifecycle_manager.find_orphans(!*keep_secrets).await?;
+
+                if *dry_run || orphans.is_empty() {
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&orphans)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        OutputFormat::Json,
+                    )?;
+                    return Ok(());
+                }
+
+                if !*yes {
+                    println!("Left behind by components that are no longer installed:");
+                    for file in &orphans.files {
+                        println!(
+                            "  {} ({:?}): {}",
+                            file.component_id,
+                            file.kind,
+                            file.path.display()
+                        );
+                    }
+                    for (alias, component_id) in &orphans.aliases {
+                        println!("  {component_id} (Alias): {alias}");
+                    }
+                    for store in &orphans.not_scanned {
+                        println!("Not scanned: {store}");
+                    }
+                    print!("Remove them? [y/N]: ");
+                    std::io::Write::flush(&mut std::io::stdout())?;
+                    let mut input = String::new();
+                    std::io::stdin().read_line(&mut input)?;
+                    if !input.trim().eq_ignore_ascii_case("y") {
+                        println!("Cancelled.");
+                        return Ok(());
+                    }
+                }
+
+                let removed = lifecycle_manager.remove_orphans(&orphans).await?;
+                let result = json!({
+                    "status": "success",
+                    "removed": removed,
+                    "not_scanned": orphans.not_scanned,
+                    "message": format!("Removed {removed} orphaned file(s) and alias(es)")
+                });
+
+                print_result(
+                    &rmcp::model::CallToolResult {
+                        content: Some(vec![rmcp::model::Content::text(
+                            serde_json::to_string_pretty(&result)?,
+                        )]),
+                        structured_content: None,
+                        is_error: None,
+                    },
+                    OutputFormat::Json,
+                )?;
+            }
+            Commands::Audit { command } => match command {
+                AuditCommands::Tail {
+                    lines,
+                    follow,
+                    plugin_dir,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                    let path = lifecycle_manager.audit_log_path();
+                    // Note where the log ends before reading it so records written meanwhile
+                    // are printed when following rather than lost.
+                    let mut offset = tokio::fs::metadata(&path).await.map_or(0, |m| m.len());
+                    let records = lifecycle_manager
+                        .audit_log(&AuditQuery {
+                            limit: Some(*lines),
+                            ..Default::default()
+                        })
+                        .await?;
+                    for record in &records {
+                        println!("{}", serde_json::to_string(record)?);
+                    }
+
+                    if *follow {
+                        loop {
+                            tokio::time::sleep(Duration::from_millis(500)).await;
+                            let Ok(content) = tokio::fs::read(&path).await else {
+                                continue;
+                            };
+                            let len = content.len() as u64;
+                            if len < offset {
+                                // The log was rotated, so the current file only holds new records.
+                                offset = 0;
+                            }
+                            let new = &content[offset as usize..];
+                            // Leave a partly written line for the next round.
+                            let Some(end) = new.iter().rposition(|&b| b == b'\n') else {
+                                continue;
+                            };
+                            print!("{}", String::from_utf8_lossy(&new[..=end]));
+                            std::io::Write::flush(&mut std::io::stdout())?;
+                            offset += end as u64 + 1;
+                        }
+                    }
+                }
+                AuditCommands::Query {
+                    component,
+                    tool,
+                    event,
+                    decision,
+                    subject,
+                    since,
+                    limit,
+                    plugin_dir,
+                    output_format,
+                } => {
+                    let lifecycle_manager = create_lifecycle_manager(plugin_dir.clone()).await?;
+                    let since = since.map(|since| {
+                        std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs()
+                            .saturating_sub(since)
+                    });
+                    let records = lifecycle_manager
+                        .audit_log(&AuditQuery {
+                            component_id: component.clone(),
+                            tool: tool.clone(),
+                            event: *event,
+                            decision: *decision,
+                            subject: subject.clone(),
+                            since,
+                            limit: *limit,
+                        })
+                        .await?;
+
+                    let result = json!({
+                        "records": records,
+                        "total": records.len(),
+                    });
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&result)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        *output_format,
+                    )?;
+                }
+            },
+            Commands::Security { command } => match command {
+                SecurityCommands::Selftest {
+                    component,
+                    output_format,
+                } => {
+                    let component = match component {
+                        Some(path) => std::fs::read(path).with_context(|| {
+                            format!("Failed to read component: {}", path.display())
+                        })?,
+                        None => selftest::BUNDLED_COMPONENT
+                            .context(
+                                "This build of wassette has no bundled self-test component; \
+                                 build it with `just build-selftest` or pass --component",
+                            )?
+                            .to_vec(),
+                    };
+                    let config = config::Config::from_serve(&crate::Serve {
+                        plugin_dir: None,
+                        transport: Default::default(),
+                        shared: false,
+                        shared_server: false,
+                        env_vars: vec![],
+                        env_file: None,
+                        coredump_on_trap: false,
+                        strict_policy: false,
+                        profile_guest: vec![],
+                        workspace_components: vec![],
+                        sse: Default::default(),
+                        http: Default::default(),
+                        allowed_origins: vec![],
+                        watch: false,
+                        locked: false,
+                        call_timeout_secs: None,
+                        idle_timeout_secs: None,
+                        canonical_json: false,
+                        bind_address: None,
+                    })
+                    .context("Failed to load configuration")?;
+                    let report = selftest::run(config, &component).await?;
+
+                    print_result(
+                        &rmcp::model::CallToolResult {
+                            content: Some(vec![rmcp::model::Content::text(
+                                serde_json::to_string_pretty(&report)?,
+                            )]),
+                            structured_content: None,
+                            is_error: None,
+                        },
+                        *output_format,
+                    )?;
+                    if !report.passed {
+                        std::process::exit(1);
+                    }
+                }
+            },
+        },
+        None => {
+            eprintln!("No command provided. Use --help for usage information.");
+            std::process::exit(1);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod version_tests {
+    use super::*;
+
+    /// Formats build information similar to agentgateway's version output
+    fn format_build_info() -> String {
+        // Parse Rust version more robustly by looking for version pattern
+        // Expected format: "rustc 1.88.0 (extra info)"
+        let rust_version = built_info::RUSTC_VERSION
+            .split_whitespace()
+            .find(|part| part.chars().next().is_some_and(|c| c.is_ascii_digit()))
+            .unwrap_or("unknown");
+
+        let build_profile = built_info::PROFILE;
 
         let build_status = if built_info::GIT_DIRTY.unwrap_or(false) {
             "Modified"
@@ -1037,6 +3072,10 @@ mod cli_tests {
             ToolName::try_from("unload-component").unwrap(),
             ToolName::UnloadComponent
         );
+        assert_eq!(
+            ToolName::try_from("update-component").unwrap(),
+            ToolName::UpdateComponent
+        );
         assert_eq!(
             ToolName::try_from("list-components").unwrap(),
             ToolName::ListComponents
@@ -1045,6 +3084,10 @@ mod cli_tests {
             ToolName::try_from("get-policy").unwrap(),
             ToolName::GetPolicy
         );
+        assert_eq!(
+            ToolName::try_from("inspect-component").unwrap(),
+            ToolName::InspectComponent
+        );
         assert_eq!(
             ToolName::try_from("grant-storage-permission").unwrap(),
             ToolName::GrantStoragePermission
@@ -1086,8 +3129,10 @@ mod cli_tests {
     fn test_tool_name_as_str() {
         assert_eq!(ToolName::LoadComponent.as_str(), "load-component");
         assert_eq!(ToolName::UnloadComponent.as_str(), "unload-component");
+        assert_eq!(ToolName::UpdateComponent.as_str(), "update-component");
         assert_eq!(ToolName::ListComponents.as_str(), "list-components");
         assert_eq!(ToolName::GetPolicy.as_str(), "get-policy");
+        assert_eq!(ToolName::InspectComponent.as_str(), "inspect-component");
         assert_eq!(
             ToolName::GrantStoragePermission.as_str(),
             "grant-storage-permission"
@@ -1113,129 +3158,1018 @@ mod cli_tests {
             "revoke-network-permission"
         );
         assert_eq!(
-            ToolName::RevokeEnvironmentVariablePermission.as_str(),
-            "revoke-environment-variable-permission"
+            ToolName::RevokeEnvironmentVariablePermission.as_str(),
+            "revoke-environment-variable-permission"
+        );
+        assert_eq!(ToolName::ResetPermission.as_str(), "reset-permission");
+    }
+
+    #[test]
+    fn test_tool_name_roundtrip() {
+        let test_cases = [
+            ToolName::LoadComponent,
+            ToolName::UnloadComponent,
+            ToolName::ListComponents,
+            ToolName::GetPolicy,
+            ToolName::GrantStoragePermission,
+            ToolName::GrantNetworkPermission,
+            ToolName::GrantEnvironmentVariablePermission,
+            ToolName::GrantMemoryPermission,
+            ToolName::RevokeStoragePermission,
+            ToolName::RevokeNetworkPermission,
+            ToolName::RevokeEnvironmentVariablePermission,
+            ToolName::ResetPermission,
+        ];
+
+        for tool in test_cases {
+            let str_repr = tool.as_str();
+            let parsed = ToolName::try_from(str_repr).unwrap();
+            assert_eq!(tool, parsed);
+        }
+    }
+
+    #[test]
+    fn test_cli_command_parsing() {
+        // Test component commands
+        let args = vec!["wassette", "component", "list"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        matches!(cli.command, Some(Commands::Component { .. }));
+
+        // Test policy commands
+        let args = vec!["wassette", "policy", "get", "test-component"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        matches!(cli.command, Some(Commands::Policy { .. }));
+
+        let args = vec!["wassette", "policy", "validate", "a.yaml", "b.yaml"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        if let Some(Commands::Policy {
+            command: PolicyCommands::Validate { files },
+        }) = cli.command
+        {
+            assert_eq!(files, [PathBuf::from("a.yaml"), PathBuf::from("b.yaml")]);
+        } else {
+            panic!("Expected policy validate command");
+        }
+        assert!(Cli::try_parse_from(["wassette", "policy", "validate"]).is_err());
+
+        // Test permission commands
+        let args = vec![
+            "wassette",
+            "permission",
+            "grant",
+            "storage",
+            "test-component",
+            "fs:///tmp",
+            "--access",
+            "read",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        matches!(cli.command, Some(Commands::Permission { .. }));
+
+        // Test serve command still works
+        let args = vec!["wassette", "serve", "--sse"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        matches!(cli.command, Some(Commands::Serve(_)));
+    }
+
+    #[test]
+    fn test_idle_timeout_parsing() {
+        for (flag, secs) in [
+            ("90", 90),
+            ("90s", 90),
+            ("10m", 600),
+            ("2h", 7200),
+            ("1d", 86400),
+        ] {
+            let cli = Cli::try_parse_from(["wassette", "serve", "--idle-timeout", flag]).unwrap();
+            let Some(Commands::Serve(serve)) = cli.command else {
+                panic!("Expected serve command");
+            };
+            assert_eq!(serve.idle_timeout_secs, Some(secs));
+        }
+        for flag in ["10w", "m", "-5m", "99999999999999999999h"] {
+            assert!(Cli::try_parse_from(["wassette", "serve", "--idle-timeout", flag]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_permission_grant_storage_parsing() {
+        let args = vec![
+            "wassette",
+            "permission",
+            "grant",
+            "storage",
+            "test-component",
+            "fs:///tmp/test",
+            "--access",
+            "read,write",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Permission {
+            command:
+                PermissionCommands::Grant {
+                    permission:
+                        GrantPermissionCommands::Storage {
+                            component_id,
+                            uri,
+                            access,
+                            ..
+                        },
+                },
+        }) = cli.command
+        {
+            assert_eq!(component_id, "test-component");
+            assert_eq!(uri, "fs:///tmp/test");
+            assert_eq!(access, vec!["read", "write"]);
+        } else {
+            panic!("Expected storage grant command");
+        }
+    }
+
+    #[test]
+    fn test_permission_revoke_network_parsing() {
+        let args = vec![
+            "wassette",
+            "permission",
+            "revoke",
+            "network",
+            "test-component",
+            "example.com",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Permission {
+            command:
+                PermissionCommands::Revoke {
+                    permission:
+                        RevokePermissionCommands::Network {
+                            component_id, host, ..
+                        },
+                },
+        }) = cli.command
+        {
+            assert_eq!(component_id, "test-component");
+            assert_eq!(host, "example.com");
+        } else {
+            panic!("Expected network revoke command");
+        }
+    }
+
+    #[test]
+    fn test_component_search_parsing() {
+        let args = vec![
+            "wassette",
+            "component",
+            "search",
+            "github",
+            "issues",
+            "-o",
+            "table",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Component {
+            command:
+                ComponentCommands::Search {
+                    query,
+                    output_format,
+                    ..
+                },
+        }) = cli.command
+        {
+            assert_eq!(query, vec!["github", "issues"]);
+            assert_eq!(output_format, OutputFormat::Table);
+        } else {
+            panic!("Expected component search command");
+        }
+    }
+
+    #[test]
+    fn test_component_inspect_parsing() {
+        let args = vec![
+            "wassette",
+            "component",
+            "inspect",
+            "oci://ghcr.io/microsoft/fetch-rs:latest",
+            "-o",
+            "yaml",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Component {
+            command:
+                ComponentCommands::Inspect {
+                    path,
+                    output_format,
+                    ..
+                },
+        }) = cli.command
+        {
+            assert_eq!(path, "oci://ghcr.io/microsoft/fetch-rs:latest");
+            assert_eq!(output_format, OutputFormat::Yaml);
+        } else {
+            panic!("Expected component inspect command");
+        }
+    }
+
+    #[test]
+    fn test_component_update_parsing() {
+        let args = vec!["wassette", "component", "update", "fetch_rs", "-o", "yaml"];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Component {
+            command: ComponentCommands::Update {
+                id, output_format, ..
+            },
+        }) = cli.command
+        {
+            assert_eq!(id, "fetch_rs");
+            assert_eq!(output_format, OutputFormat::Yaml);
+        } else {
+            panic!("Expected component update command");
+        }
+    }
+
+    #[test]
+    fn test_component_alias_parsing() {
+        let args = vec!["wassette", "component", "alias", "fetch_rs", "fetch"];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Component {
+            command: ComponentCommands::Alias { id, alias, .. },
+        }) = cli.command
+        {
+            assert_eq!(id, "fetch_rs");
+            assert_eq!(alias, "fetch");
+        } else {
+            panic!("Expected component alias command");
+        }
+
+        assert!(Cli::try_parse_from(["wassette", "component", "alias", "fetch_rs"]).is_err());
+    }
+
+    #[test]
+    fn test_component_history_parsing() {
+        let args = vec!["wassette", "component", "history", "fetch", "--limit", "5"];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Component {
+            command: ComponentCommands::History { id, limit, .. },
+        }) = cli.command
+        {
+            assert_eq!(id.as_deref(), Some("fetch"));
+            assert_eq!(limit, Some(5));
+        } else {
+            panic!("Expected component history command");
+        }
+    }
+
+    #[test]
+    fn test_component_rollback_parsing() {
+        let args = vec![
+            "wassette",
+            "component",
+            "rollback",
+            "fetch",
+            "--to",
+            "sha256:3f2a",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Component {
+            command: ComponentCommands::Rollback { id, to, .. },
+        }) = cli.command
+        {
+            assert_eq!(id, "fetch");
+            assert_eq!(to.as_deref(), Some("sha256:3f2a"));
+        } else {
+            panic!("Expected component rollback command");
+        }
+    }
+
+    #[test]
+    fn test_component_check_schema_parsing() {
+        let args = vec![
+            "wassette",
+            "component",
+            "check-schema",
+            "weather.wasm",
+            "--against",
+            "api.yaml",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Component {
+            command: ComponentCommands::CheckSchema { wasm, against, .. },
+        }) = cli.command
+        {
+            assert_eq!(wasm, PathBuf::from("weather.wasm"));
+            assert_eq!(against, PathBuf::from("api.yaml"));
+        } else {
+            panic!("Expected component check-schema command");
+        }
+
+        assert!(
+            Cli::try_parse_from(["wassette", "component", "check-schema", "weather.wasm"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_component_from_openapi_parsing() {
+        let args = vec![
+            "wassette",
+            "component",
+            "from-openapi",
+            "api.yaml",
+            "--operation",
+            "getForecast",
+            "--operation",
+            "listAlerts",
+            "-O",
+            "weather",
+            "--build",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Component {
+            command:
+                ComponentCommands::FromOpenapi {
+                    spec,
+                    output,
+                    operations,
+                    build,
+                    force,
+                    ..
+                },
+        }) = cli.command
+        {
+            assert_eq!(spec, PathBuf::from("api.yaml"));
+            assert_eq!(output, Some(PathBuf::from("weather")));
+            assert_eq!(operations, vec!["getForecast", "listAlerts"]);
+            assert!(build);
+            assert!(!force);
+        } else {
+            panic!("Expected component from-openapi command");
+        }
+    }
+
+    #[test]
+    fn test_component_from_mcp_parsing() {
+        let args = vec![
+            "wassette",
+            "component",
+            "from-mcp",
+            "npm:@modelcontextprotocol/server-filesystem",
+            "--tool",
+            "read_file",
+            "--",
+            "/srv/docs",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Component {
+            command:
+                ComponentCommands::FromMcp {
+                    server,
+                    args,
+                    endpoint,
+                    tools,
+                    ..
+                },
+        }) = cli.command
+        {
+            assert_eq!(server, "npm:@modelcontextprotocol/server-filesystem");
+            assert_eq!(args, vec!["/srv/docs"]);
+            assert_eq!(endpoint, mcp_shim::DEFAULT_ENDPOINT);
+            assert_eq!(tools, vec!["read_file"]);
+        } else {
+            panic!("Expected component from-mcp command");
+        }
+    }
+
+    #[test]
+    fn test_component_vendor_parsing() {
+        let cli = Cli::try_parse_from(["wassette", "component", "vendor", "--check"]).unwrap();
+        if let Some(Commands::Component {
+            command:
+                ComponentCommands::Vendor {
+                    project,
+                    check,
+                    update,
+                },
+        }) = cli.command
+        {
+            assert_eq!(project, PathBuf::from("."));
+            assert!(check);
+            assert!(!update);
+        } else {
+            panic!("Expected component vendor command");
+        }
+
+        let args = ["wassette", "component", "vendor", "--check", "--update"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_preset_set_parsing() {
+        let args = vec![
+            "wassette",
+            "preset",
+            "set",
+            "fetch.fetch",
+            "user_agent=wassette/1.0",
+            "retries=3",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Preset {
+            command: PresetCommands::Set { tool, values, .. },
+        }) = cli.command
+        {
+            assert_eq!(tool, ("fetch".to_string(), "fetch".to_string()));
+            assert_eq!(values.len(), 2);
+            assert_eq!(parse_preset_value(&values[0].1), json!("wassette/1.0"));
+            assert_eq!(parse_preset_value(&values[1].1), json!(3));
+        } else {
+            panic!("Expected preset set command");
+        }
+
+        assert!(parse_tool_target("no-tool").is_err());
+        assert_eq!(
+            parse_tool_target("my.component.tool").unwrap(),
+            ("my.component".to_string(), "tool".to_string())
         );
-        assert_eq!(ToolName::ResetPermission.as_str(), "reset-permission");
     }
 
     #[test]
-    fn test_tool_name_roundtrip() {
-        let test_cases = [
-            ToolName::LoadComponent,
-            ToolName::UnloadComponent,
-            ToolName::ListComponents,
-            ToolName::GetPolicy,
-            ToolName::GrantStoragePermission,
-            ToolName::GrantNetworkPermission,
-            ToolName::GrantEnvironmentVariablePermission,
-            ToolName::GrantMemoryPermission,
-            ToolName::RevokeStoragePermission,
-            ToolName::RevokeNetworkPermission,
-            ToolName::RevokeEnvironmentVariablePermission,
-            ToolName::ResetPermission,
+    fn test_debug_coredump_parsing() {
+        let args = vec![
+            "wassette",
+            "debug",
+            "coredump",
+            "18c3f-0001",
+            "--output",
+            "/tmp/fetch.coredump",
         ];
+        let cli = Cli::try_parse_from(args).unwrap();
 
-        for tool in test_cases {
-            let str_repr = tool.as_str();
-            let parsed = ToolName::try_from(str_repr).unwrap();
-            assert_eq!(tool, parsed);
+        if let Some(Commands::Debug {
+            command: DebugCommands::Coredump {
+                call_id, output, ..
+            },
+        }) = cli.command
+        {
+            assert_eq!(call_id, "18c3f-0001");
+            assert_eq!(output, Some(PathBuf::from("/tmp/fetch.coredump")));
+        } else {
+            panic!("Expected debug coredump command");
         }
     }
 
     #[test]
-    fn test_cli_command_parsing() {
-        // Test component commands
-        let args = vec!["wassette", "component", "list"];
+    fn test_debug_fs_escapes_parsing() {
+        let args = vec!["wassette", "debug", "fs-escapes", "--component", "fetch"];
         let cli = Cli::try_parse_from(args).unwrap();
-        matches!(cli.command, Some(Commands::Component { .. }));
 
-        // Test policy commands
-        let args = vec!["wassette", "policy", "get", "test-component"];
+        if let Some(Commands::Debug {
+            command: DebugCommands::FsEscapes { component, .. },
+        }) = cli.command
+        {
+            assert_eq!(component.as_deref(), Some("fetch"));
+        } else {
+            panic!("Expected debug fs-escapes command");
+        }
+    }
+
+    #[test]
+    fn test_harden_parsing() {
+        let args = vec![
+            "wassette",
+            "harden",
+            "--generate",
+            "selinux",
+            "-O",
+            "/tmp/profile",
+        ];
         let cli = Cli::try_parse_from(args).unwrap();
-        matches!(cli.command, Some(Commands::Policy { .. }));
 
-        // Test permission commands
+        if let Some(Commands::Harden {
+            generate, output, ..
+        }) = cli.command
+        {
+            assert_eq!(generate, harden::ProfileKind::Selinux);
+            assert_eq!(output, Some(PathBuf::from("/tmp/profile")));
+        } else {
+            panic!("Expected harden command");
+        }
+
+        assert!(Cli::try_parse_from(["wassette", "harden", "--generate", "seccomp"]).is_err());
+    }
+
+    #[test]
+    fn test_security_selftest_parsing() {
         let args = vec![
             "wassette",
-            "permission",
-            "grant",
-            "storage",
-            "test-component",
-            "fs:///tmp",
-            "--access",
-            "read",
+            "security",
+            "selftest",
+            "--component",
+            "selftest.wasm",
+            "-o",
+            "table",
         ];
         let cli = Cli::try_parse_from(args).unwrap();
-        matches!(cli.command, Some(Commands::Permission { .. }));
 
-        // Test serve command still works
-        let args = vec!["wassette", "serve", "--sse"];
+        if let Some(Commands::Security {
+            command:
+                SecurityCommands::Selftest {
+                    component,
+                    output_format,
+                },
+        }) = cli.command
+        {
+            assert_eq!(component, Some(PathBuf::from("selftest.wasm")));
+            assert_eq!(output_format, OutputFormat::Table);
+        } else {
+            panic!("Expected security selftest command");
+        }
+    }
+
+    #[test]
+    fn test_secret_resolve_parsing() {
+        let args = vec!["wassette", "secret", "resolve", "my-component"];
         let cli = Cli::try_parse_from(args).unwrap();
-        matches!(cli.command, Some(Commands::Serve(_)));
+
+        if let Some(Commands::Secret {
+            command:
+                SecretCommands::Resolve {
+                    component_id,
+                    show_values,
+                    ..
+                },
+        }) = cli.command
+        {
+            assert_eq!(component_id, "my-component");
+            assert!(!show_values);
+        } else {
+            panic!("Expected secret resolve command");
+        }
     }
 
     #[test]
-    fn test_permission_grant_storage_parsing() {
+    fn test_config_migrate_parsing() {
+        let args = vec!["wassette", "config", "migrate", "--dry-run"];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Config {
+            command:
+                ConfigCommands::Migrate {
+                    dry_run,
+                    plugin_dir,
+                },
+        }) = cli.command
+        {
+            assert!(dry_run);
+            assert_eq!(plugin_dir, None);
+        } else {
+            panic!("Expected config migrate command");
+        }
+    }
+
+    #[test]
+    fn test_config_set_parsing() {
         let args = vec![
             "wassette",
-            "permission",
-            "grant",
-            "storage",
-            "test-component",
-            "fs:///tmp/test",
-            "--access",
-            "read,write",
+            "config",
+            "set",
+            "fetch",
+            "endpoint=https://api.example.com",
+            "retries=3",
         ];
         let cli = Cli::try_parse_from(args).unwrap();
 
-        if let Some(Commands::Permission {
+        if let Some(Commands::Config {
             command:
-                PermissionCommands::Grant {
-                    permission:
-                        GrantPermissionCommands::Storage {
-                            component_id,
-                            uri,
-                            access,
-                            ..
-                        },
+                ConfigCommands::Set {
+                    component_id,
+                    values,
+                    ..
                 },
         }) = cli.command
         {
-            assert_eq!(component_id, "test-component");
-            assert_eq!(uri, "fs:///tmp/test");
-            assert_eq!(access, vec!["read", "write"]);
+            assert_eq!(component_id, "fetch");
+            assert_eq!(
+                values,
+                vec![
+                    (
+                        "endpoint".to_string(),
+                        "https://api.example.com".to_string()
+                    ),
+                    ("retries".to_string(), "3".to_string()),
+                ]
+            );
         } else {
-            panic!("Expected storage grant command");
+            panic!("Expected config set command");
         }
+
+        assert!(Cli::try_parse_from(["wassette", "config", "set", "fetch"]).is_err());
     }
 
     #[test]
-    fn test_permission_revoke_network_parsing() {
+    fn test_state_parsing() {
+        let args = vec!["wassette", "state", "notes", "export", "-O", "notes.json"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        if let Some(Commands::State {
+            component_id,
+            command: StateCommands::Export { output },
+            plugin_dir,
+        }) = cli.command
+        {
+            assert_eq!(component_id, "notes");
+            assert_eq!(output, Some(PathBuf::from("notes.json")));
+            assert_eq!(plugin_dir, None);
+        } else {
+            panic!("Expected state export command");
+        }
+
         let args = vec![
             "wassette",
-            "permission",
-            "revoke",
-            "network",
-            "test-component",
-            "example.com",
+            "state",
+            "notes",
+            "clear",
+            "--yes",
+            "--plugin-dir",
+            "/tmp/plugins",
         ];
         let cli = Cli::try_parse_from(args).unwrap();
+        if let Some(Commands::State {
+            command: StateCommands::Clear { yes },
+            plugin_dir,
+            ..
+        }) = cli.command
+        {
+            assert!(yes);
+            assert_eq!(plugin_dir, Some(PathBuf::from("/tmp/plugins")));
+        } else {
+            panic!("Expected state clear command");
+        }
+
+        assert!(Cli::try_parse_from(vec!["wassette", "state", "notes"]).is_err());
+    }
+
+    #[test]
+    fn test_gc_parsing() {
+        let args = vec!["wassette", "gc", "--dry-run", "--keep-secrets"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        if let Some(Commands::Gc {
+            dry_run,
+            yes,
+            keep_secrets,
+            plugin_dir,
+        }) = cli.command
+        {
+            assert!(dry_run);
+            assert!(!yes);
+            assert!(keep_secrets);
+            assert_eq!(plugin_dir, None);
+        } else {
+            panic!("Expected gc command");
+        }
+    }
 
-        if let Some(Commands::Permission {
+    #[test]
+    fn test_audit_parsing() {
+        let args = vec!["wassette", "audit", "tail", "-n", "5", "--follow"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        if let Some(Commands::Audit {
+            command: AuditCommands::Tail { lines, follow, .. },
+        }) = cli.command
+        {
+            assert_eq!(lines, 5);
+            assert!(follow);
+        } else {
+            panic!("Expected audit tail command");
+        }
+
+        let args = vec![
+            "wassette",
+            "audit",
+            "query",
+            "--component",
+            "fetch",
+            "--decision",
+            "denied",
+            "--event",
+            "permission-denied",
+            "--since",
+            "7d",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        if let Some(Commands::Audit {
             command:
-                PermissionCommands::Revoke {
-                    permission:
-                        RevokePermissionCommands::Network {
-                            component_id, host, ..
-                        },
+                AuditCommands::Query {
+                    component,
+                    event,
+                    decision,
+                    since,
+                    limit,
+                    ..
                 },
         }) = cli.command
         {
-            assert_eq!(component_id, "test-component");
-            assert_eq!(host, "example.com");
+            assert_eq!(component.as_deref(), Some("fetch"));
+            assert_eq!(event, Some(AuditEvent::PermissionDenied));
+            assert_eq!(decision, Some(AuditDecision::Denied));
+            assert_eq!(since, Some(7 * 24 * 60 * 60));
+            assert_eq!(limit, None);
         } else {
-            panic!("Expected network revoke command");
+            panic!("Expected audit query command");
         }
+
+        assert!(
+            Cli::try_parse_from(vec!["wassette", "audit", "query", "--decision", "maybe"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_install_parsing() {
+        let args = vec![
+            "wassette",
+            "install",
+            "https://example.com/wassette-manifest.json",
+            "--yes",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        if let Some(Commands::Install { manifest, yes, .. }) = cli.command {
+            assert_eq!(manifest, "https://example.com/wassette-manifest.json");
+            assert!(yes);
+        } else {
+            panic!("Expected install command");
+        }
+    }
+}
+
+#[cfg(test)]
+mod scope_tests {
+    use rmcp::model::{ArgumentInfo, PromptReference, ResourceReference};
+
+    use super::*;
+
+    /// A component with the tool `ping` and the prompt `greet`, loaded as `weather`.
+    const WEATHER: &str = r#"(component
+        (core module $m
+            (memory (export "memory") 1)
+            (data (i32.const 64) "\80\00\00\00\01\00\00\00")
+            (data (i32.const 128)
+                "\00\01\00\00\05\00\00\00"
+                "\00\00\00\00\00\00\00\00\00\00\00\00"
+                "\00\00\00\00\00\00\00\00"
+                "\10\01\00\00\09\00\00\00")
+            (data (i32.const 256) "greet")
+            (data (i32.const 272) "Say hello")
+            (func (export "ping") (result i32) (i32.const 1))
+            (func (export "list-prompts") (result i32) (i32.const 64)))
+        (core instance $i (instantiate $m))
+        (func (export "ping") (result u32) (canon lift (core func $i "ping")))
+        (type $argument (record
+            (field "name" string)
+            (field "description" (option string))
+            (field "required" bool)))
+        (type $prompt (record
+            (field "name" string)
+            (field "description" (option string))
+            (field "arguments" (list $argument))
+            (field "template" string)))
+        (func $list-prompts (result (list $prompt))
+            (canon lift (core func $i "list-prompts") (memory $i "memory")))
+        (instance $prompts
+            (export "prompt-argument" (type $argument))
+            (export "prompt" (type $prompt))
+            (export "list-prompts" (func $list-prompts)))
+        (export "wassette:prompts/prompts" (instance $prompts))
+    )"#;
+
+    /// A server with the `weather` component loaded, with a usage guide.
+    async fn server(dir: &std::path::Path) -> Result<McpServer> {
+        let manager = LifecycleManager::builder(dir.join("components"))
+            .with_eager_loading(false)
+            .build()
+            .await?;
+        let mut wasm = wat::parse_str(WEATHER)?;
+        let name = b"wassette:readme";
+        let readme = b"# Weather";
+        wasm.push(0);
+        wasm.push((1 + name.len() + readme.len()) as u8);
+        wasm.push(name.len() as u8);
+        wasm.extend_from_slice(name);
+        wasm.extend_from_slice(readme);
+        let path = dir.join("weather.wasm");
+        std::fs::write(&path, wasm)?;
+        manager
+            .load_component(&format!("file://{}", path.display()))
+            .await?;
+        Ok(McpServer::new(manager, ServerDetails::default()))
+    }
+
+    fn grant(scope: &str) -> auth::Grant {
+        auth::Grant::from_scope(None, scope)
+    }
+
+    fn usage_uri(server: &McpServer) -> String {
+        server
+            .lifecycle_manager
+            .usage_uri("weather")
+            .expect("the component embeds a guide")
+    }
+
+    #[tokio::test]
+    async fn test_complete_is_scoped() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let server = server(dir.path()).await?;
+        let other = grant("wassette:component:other");
+        let complete = |r#ref| CompleteRequestParam {
+            r#ref,
+            argument: ArgumentInfo {
+                name: "host".to_string(),
+                value: String::new(),
+            },
+        };
+
+        for name in ["ping", "greet"] {
+            let reference = Reference::Prompt(PromptReference {
+                name: name.to_string(),
+            });
+            let error = server
+                .complete_for(Some(&other), complete(reference))
+                .await
+                .expect_err("the reference belongs to another component");
+            assert!(error.message.contains("doesn't allow"), "{error:?}");
+        }
+        let reference = Reference::Prompt(PromptReference {
+            name: "ping".to_string(),
+        });
+        assert!(server
+            .complete_for(
+                Some(&grant("wassette:component:weather")),
+                complete(reference)
+            )
+            .await
+            .is_ok());
+        let reference = Reference::Resource(ResourceReference {
+            uri: usage_uri(&server),
+        });
+        assert!(server
+            .complete_for(Some(&other), complete(reference))
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_prompts_is_scoped() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let server = server(dir.path()).await?;
+
+        assert!(server
+            .prompts_for(Some(&grant("wassette:component:other")))
+            .prompts
+            .is_empty());
+        let names: Vec<_> = server
+            .prompts_for(Some(&grant("wassette:component:weather")))
+            .prompts
+            .into_iter()
+            .map(|prompt| prompt.name)
+            .collect();
+        assert_eq!(names, ["greet"]);
+        assert_eq!(server.prompts_for(None).prompts.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_prompt_is_scoped() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let server = server(dir.path()).await?;
+        let params = GetPromptRequestParam {
+            name: "greet".to_string(),
+            arguments: None,
+        };
+
+        let error = server
+            .prompt_for(Some(&grant("wassette:component:other")), &params)
+            .expect_err("the prompt belongs to another component");
+        assert!(error.message.contains("doesn't allow"), "{error:?}");
+        let prompt = server
+            .prompt_for(Some(&grant("wassette:component:*")), &params)
+            .map_err(|err| anyhow::anyhow!("{err:?}"))?;
+        assert_eq!(prompt.messages.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_resources_is_scoped() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let server = server(dir.path()).await?;
+        let uris = |grant: &auth::Grant| -> Vec<String> {
+            server
+                .resources_for(Some(grant))
+                .resources
+                .into_iter()
+                .map(|resource| resource.raw.uri)
+                .collect()
+        };
+
+        assert!(uris(&grant("wassette:component:other")).is_empty());
+        assert_eq!(
+            uris(&grant("wassette:component:weather")),
+            [usage_uri(&server)]
+        );
+        assert_eq!(
+            uris(&grant("wassette:manage")),
+            [SERVER_RESOURCE_URI, UPDATES_RESOURCE_URI]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_resource_is_scoped() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let server = server(dir.path()).await?;
+        let other = grant("wassette:component:other");
+
+        for uri in [usage_uri(&server), SERVER_RESOURCE_URI.to_string()] {
+            let params = ReadResourceRequestParam { uri };
+            let error = server
+                .read_resource_for(Some(&other), &params)
+                .await
+                .expect_err("the resource isn't in the token's scope");
+            assert!(error.message.contains("doesn't allow"), "{error:?}");
+        }
+        let params = ReadResourceRequestParam {
+            uri: usage_uri(&server),
+        };
+        assert!(server
+            .read_resource_for(Some(&grant("wassette:component:weather")), &params)
+            .await
+            .is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_overrides_need_the_manage_scope() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let server = server(dir.path()).await?;
+
+        assert!(!server.may_override(None));
+        assert!(!server.may_override(Some(&grant("wassette:component:*"))));
+        assert!(server.may_override(Some(&grant("wassette:manage"))));
+
+        let manager = LifecycleManager::builder(dir.path().join("open"))
+            .with_eager_loading(false)
+            .with_override_ceiling(wassette::OverrideCeiling {
+                allow_unauthenticated: true,
+                ..Default::default()
+            })
+            .build()
+            .await?;
+        let server = McpServer::new(manager, ServerDetails::default());
+        assert!(server.may_override(None));
+        assert!(!server.may_override(Some(&grant("wassette:component:*"))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_is_scoped() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let server = server(dir.path()).await?;
+        let params = SubscribeRequestParam {
+            uri: usage_uri(&server),
+        };
+
+        assert!(server
+            .subscribe_for(Some(&grant("wassette:component:other")), &params)
+            .is_err());
+        assert!(!server.resource_subscriptions.contains(&params.uri));
+        server
+            .subscribe_for(Some(&grant("wassette:component:weather")), &params)
+            .map_err(|err| anyhow::anyhow!("{err:?}"))?;
+        assert!(server.resource_subscriptions.contains(&params.uri));
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_events_are_scoped() {
+        let everything = component_filter(None, false);
+        assert!(everything("weather"));
+
+        let weather = grant("wassette:component:weather");
+        let callable = component_filter(Some(&weather), false);
+        assert!(callable("weather"));
+        assert!(!callable("other"));
+
+        let manager = grant("wassette:manage");
+        assert!(!component_filter(Some(&manager), false)("weather"));
+        assert!(component_filter(Some(&manager), true)("weather"));
+        assert!(!component_filter(Some(&weather), true)("other"));
     }
 }
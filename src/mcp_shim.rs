@@ -0,0 +1,779 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Generation of components wrapping the tools of an existing MCP server
+//!
+//! `wassette component from-mcp` starts a stdio MCP server, from an npm or PyPI package or a
+//! command, lists its tools and turns them into a Rust component project: one exported
+//! function per tool, forwarding calls to the server over MCP's streamable HTTP transport,
+//! and a policy granting access to the server's endpoint only. The server itself can't run
+//! in the sandbox, so it keeps running behind a stdio-to-HTTP bridge; loading the component
+//! instead of connecting agents to the server directly puts its tools behind wassette's
+//! policies, approvals and audit log.
+//!
+//! Properties of a tool's input schema that are strings, numbers, booleans or lists of those
+//! become function parameters; other properties are passed as JSON text. Functions return
+//! the text of the tool's result.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{ChildStdin, ChildStdout};
+
+use crate::openapi::{
+    cargo_toml, doc_comment, kebab_case, rust_ident, wit_ident, GeneratedComponent, ParamType,
+};
+
+/// Endpoint the generated component calls unless told otherwise
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:8000/mcp";
+
+/// MCP protocol version used to talk to servers
+const PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// How long a server may take to start and list its tools, including a package download.
+const LIST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A tool of an MCP server, as listed by `tools/list`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tool {
+    /// Name of the tool
+    pub name: String,
+    /// What the tool does
+    #[serde(default)]
+    pub description: Option<String>,
+    /// JSON schema of the tool's arguments
+    #[serde(default)]
+    pub input_schema: Value,
+}
+
+/// The tools of an MCP server and the name it gives itself.
+#[derive(Debug, Clone)]
+pub struct ServerTools {
+    /// Name from the server's `serverInfo`
+    pub name: String,
+    /// Tools the server lists
+    pub tools: Vec<Tool>,
+}
+
+/// What to generate from the tools of an MCP server.
+#[derive(Debug, Clone, Default)]
+pub struct GenerateOptions {
+    /// Name of the component. Defaults to the name of the server
+    pub name: Option<String>,
+    /// Streamable HTTP endpoint the server is served at. Defaults to [`DEFAULT_ENDPOINT`]
+    pub endpoint: Option<String>,
+    /// Names of the tools to wrap. Defaults to all of them
+    pub tools: Vec<String>,
+}
+
+/// A parameter of a generated function.
+#[derive(Debug, Clone)]
+struct Arg {
+    /// Name of the property in the tool's input schema
+    name: String,
+    /// Name of the function parameter, in kebab case
+    label: String,
+    /// Type of the parameter, or `None` for JSON text
+    ty: Option<ParamType>,
+    required: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Function {
+    /// Name of the exported function, in kebab case
+    function: String,
+    tool: String,
+    description: Option<String>,
+    args: Vec<Arg>,
+}
+
+/// The command starting the stdio MCP server `server`: `npm:<package>` runs the package with
+/// `npx`, `pypi:<package>` with `uvx`, and anything else is the program to run, with `args`.
+pub fn server_command(server: &str, args: &[String]) -> Vec<String> {
+    let mut command: Vec<String> = if let Some(package) = server.strip_prefix("npm:") {
+        vec!["npx".to_string(), "-y".to_string(), package.to_string()]
+    } else if let Some(package) = server.strip_prefix("pypi:") {
+        vec!["uvx".to_string(), package.to_string()]
+    } else {
+        vec![server.to_string()]
+    };
+    command.extend(args.iter().cloned());
+    command
+}
+
+/// JSON-RPC session with a server over its standard input and output.
+struct StdioSession {
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    next_id: u64,
+}
+
+impl StdioSession {
+    async fn send(&mut self, message: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.stdin.write_all(&line).await?;
+        self.stdin.flush().await?;
+        Ok(())
+    }
+
+    async fn notify(&mut self, method: &str) -> Result<()> {
+        self.send(&json!({ "jsonrpc": "2.0", "method": method }))
+            .await
+    }
+
+    /// Send a request and wait for its result, skipping whatever else the server writes.
+    async fn request(&mut self, method: &str, params: Value) -> Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .await?;
+        loop {
+            let line = self
+                .stdout
+                .next_line()
+                .await?
+                .context("The server exited before answering")?;
+            let Ok(message) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if message.get("id") != Some(&json!(id)) || message.get("method").is_some() {
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                let error = error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("unknown error");
+                bail!("{method} failed: {error}");
+            }
+            return Ok(message.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+}
+
+/// Start the stdio MCP server `command` and list its tools.
+pub async fn list_tools(command: &[String]) -> Result<ServerTools> {
+    let (program, args) = command.split_first().context("No server command")?;
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start {program}"))?;
+    let mut session = StdioSession {
+        stdin: child.stdin.take().context("No stdin")?,
+        stdout: BufReader::new(child.stdout.take().context("No stdout")?).lines(),
+        next_id: 0,
+    };
+
+    let listing = async {
+        let initialized = session
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": { "name": "wassette", "version": env!("CARGO_PKG_VERSION") },
+                }),
+            )
+            .await?;
+        session.notify("notifications/initialized").await?;
+        let name = initialized
+            .pointer("/serverInfo/name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let page = session.request("tools/list", params).await?;
+            let listed: Vec<Tool> =
+                serde_json::from_value(page.get("tools").cloned().unwrap_or_else(|| json!([])))
+                    .context("Invalid tools/list result")?;
+            tools.extend(listed);
+            cursor = page
+                .get("nextCursor")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            if cursor.is_none() {
+                break;
+            }
+        }
+        anyhow::Ok(ServerTools { name, tools })
+    };
+    let tools = tokio::time::timeout(LIST_TIMEOUT, listing)
+        .await
+        .with_context(|| format!("{program} didn't list its tools in time"))??;
+    let _ = child.kill().await;
+    Ok(tools)
+}
+
+/// Generate a component project forwarding the tools of `server` to its HTTP endpoint.
+pub fn generate(server: &ServerTools, options: &GenerateOptions) -> Result<GeneratedComponent> {
+    let name = kebab_case(options.name.as_deref().unwrap_or(&server.name));
+    if name.is_empty() {
+        bail!(
+            "Can't derive a component name from {:?}, pass --name",
+            server.name
+        );
+    }
+    let endpoint = options.endpoint.as_deref().unwrap_or(DEFAULT_ENDPOINT);
+    let host = match Url::parse(endpoint) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            url.host_str()
+                .map(str::to_string)
+                .context("The endpoint has no host")?
+        }
+        _ => bail!("{endpoint:?} isn't an absolute HTTP URL"),
+    };
+    let functions = functions(&server.tools, &options.tools)?;
+    if functions.is_empty() {
+        bail!("The server has no tools to wrap");
+    }
+
+    let files = vec![
+        (
+            "Cargo.toml".to_string(),
+            cargo_toml(&name, &["serde_json = \"1\""]),
+        ),
+        ("wit/world.wit".to_string(), world_wit(&name, &functions)),
+        (
+            "src/lib.rs".to_string(),
+            lib_rs(&name, &server.name, endpoint, &functions),
+        ),
+        ("policy.yaml".to_string(), policy_yaml(&server.name, &host)),
+        (".gitignore".to_string(), "/target\n".to_string()),
+    ];
+    Ok(GeneratedComponent {
+        name,
+        functions: functions.into_iter().map(|f| f.function).collect(),
+        files,
+    })
+}
+
+/// The functions wrapping `tools`, only those in `selected` unless it is empty.
+fn functions(tools: &[Tool], selected: &[String]) -> Result<Vec<Function>> {
+    let mut names = BTreeMap::new();
+    let mut functions = Vec::new();
+    for tool in tools {
+        if !selected.is_empty() && !selected.contains(&tool.name) {
+            continue;
+        }
+        let function = kebab_case(&tool.name);
+        if function.is_empty() {
+            bail!("Can't derive a function name from tool {:?}", tool.name);
+        }
+        if let Some(other) = names.insert(function.clone(), tool.name.clone()) {
+            bail!(
+                "Tools {other:?} and {:?} are both named {function:?}, select one with --tool",
+                tool.name
+            );
+        }
+        let args = args(&tool.input_schema)
+            .with_context(|| format!("Unsupported input schema of tool {:?}", tool.name))?;
+        let description = tool
+            .description
+            .as_deref()
+            .map(str::trim)
+            .filter(|description| !description.is_empty())
+            .map(str::to_string);
+        functions.push(Function {
+            function,
+            tool: tool.name.clone(),
+            description,
+            args,
+        });
+    }
+
+    let missing: Vec<_> = selected
+        .iter()
+        .filter(|name| !names.values().any(|tool| tool == *name))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        bail!("Tools not found: {}", missing.join(", "));
+    }
+    Ok(functions)
+}
+
+/// The parameters of a function taking the properties of the input `schema`.
+fn args(schema: &Value) -> Result<Vec<Arg>> {
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let mut args: Vec<Arg> = Vec::new();
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (name, property) in properties.into_iter().flatten() {
+        let label = kebab_case(name);
+        if label.is_empty() {
+            bail!("Can't derive a parameter name from {name:?}");
+        }
+        if let Some(other) = args.iter().find(|arg| arg.label == label) {
+            bail!(
+                "Properties {:?} and {name:?} are both named {label:?}",
+                other.name
+            );
+        }
+        args.push(Arg {
+            name: name.clone(),
+            label,
+            ty: arg_type(property),
+            required: required.contains(&name.as_str()),
+        });
+    }
+    // Required parameters first, as the order of properties means nothing to the tool.
+    args.sort_by_key(|arg| !arg.required);
+    Ok(args)
+}
+
+/// The type of a parameter for a property with `schema`, or `None` to pass it as JSON text.
+fn arg_type(schema: &Value) -> Option<ParamType> {
+    let ty = match schema.get("type") {
+        Some(Value::String(ty)) => ty.as_str(),
+        // `type: [integer, "null"]`
+        Some(Value::Array(types)) => {
+            let mut types = types
+                .iter()
+                .filter_map(Value::as_str)
+                .filter(|ty| *ty != "null");
+            match (types.next(), types.next()) {
+                (Some(ty), None) => ty,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    match ty {
+        "string" => Some(ParamType::String),
+        "integer" => Some(ParamType::Integer),
+        "number" => Some(ParamType::Number),
+        "boolean" => Some(ParamType::Boolean),
+        "array" => match schema.get("items").and_then(arg_type) {
+            Some(ParamType::List(_)) | None => None,
+            Some(item) => Some(ParamType::List(Box::new(item))),
+        },
+        _ => None,
+    }
+}
+
+impl Arg {
+    fn wit(&self) -> String {
+        let ty = self
+            .ty
+            .as_ref()
+            .map_or("string".to_string(), ParamType::wit);
+        if self.required {
+            ty
+        } else {
+            format!("option<{ty}>")
+        }
+    }
+
+    fn rust(&self) -> String {
+        let ty = self
+            .ty
+            .as_ref()
+            .map_or("String".to_string(), ParamType::rust);
+        if self.required {
+            ty
+        } else {
+            format!("Option<{ty}>")
+        }
+    }
+}
+
+fn world_wit(name: &str, functions: &[Function]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "package component:{name};");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "world {} {{", wit_ident(name));
+    for (index, function) in functions.iter().enumerate() {
+        if index > 0 {
+            let _ = writeln!(out);
+        }
+        let description = function
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("The `{}` tool", function.tool));
+        doc_comment(&mut out, "    ", &description);
+        let params: Vec<String> = function
+            .args
+            .iter()
+            .map(|arg| format!("{}: {}", wit_ident(&arg.label), arg.wit()))
+            .collect();
+        let _ = writeln!(
+            out,
+            "    export {}: func({}) -> result<string, string>;",
+            wit_ident(&function.function),
+            params.join(", ")
+        );
+    }
+    let _ = writeln!(out, "}}");
+    out
+}
+
+/// Code shared by the functions of the generated component, calling tools of the server.
+const CALL_TOOL: &str = r#"/// `text`, the value of the `name` argument as JSON.
+fn parse(name: &str, text: &str) -> Result<Value, String> {
+    serde_json::from_str(text).map_err(|e| format!("`{name}` isn't valid JSON: {e}"))
+}
+
+/// Call the tool `name` of the server, returning the text of its result.
+fn call_tool(name: &str, arguments: Map<String, Value>) -> Result<String, String> {
+    spin_executor::run(async move {
+        let initialize = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": {},
+                "clientInfo": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+            },
+        });
+        let (session, _) = post(None, initialize).await?;
+        let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        post(session.as_deref(), initialized).await?;
+        let call = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/call",
+            "params": { "name": name, "arguments": arguments },
+        });
+        let (_, response) = post(session.as_deref(), call).await?;
+        let response = response.ok_or("The server didn't answer the call")?;
+        if let Some(error) = response.get("error") {
+            let message = error.get("message").and_then(Value::as_str);
+            return Err(message.unwrap_or("The call failed").to_string());
+        }
+        let result = &response["result"];
+        let text: Vec<&str> = result["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|content| content.get("text").and_then(Value::as_str))
+            .collect();
+        let text = if text.is_empty() {
+            result
+                .get("structuredContent")
+                .unwrap_or(result)
+                .to_string()
+        } else {
+            text.join("\n")
+        };
+        if result["isError"].as_bool() == Some(true) {
+            Err(text)
+        } else {
+            Ok(text)
+        }
+    })
+}
+
+/// Send `message` to the server, returning the session it runs in and the response to the
+/// message, if it has an `id`.
+async fn post(
+    session: Option<&str>,
+    message: Value,
+) -> Result<(Option<String>, Option<Value>), String> {
+    let mut request = RequestBuilder::new(Method::Post, ENDPOINT);
+    request.header("accept", "application/json, text/event-stream");
+    request.header("content-type", "application/json");
+    if let Some(session) = session {
+        request.header("mcp-session-id", session);
+    }
+    request.body(message.to_string());
+    let response: Response = send(request.build()).await.map_err(|e| e.to_string())?;
+    let status = *response.status();
+    let body = String::from_utf8_lossy(response.body()).into_owned();
+    if !(200..300).contains(&status) {
+        return Err(format!(
+            "The server answered with status code {status}: {body}"
+        ));
+    }
+    let session = response
+        .header("mcp-session-id")
+        .and_then(|value| value.as_str())
+        .or(session)
+        .map(str::to_string);
+
+    // The response is either JSON or a stream of server-sent events.
+    let id = message.get("id");
+    let response = match serde_json::from_str::<Value>(&body) {
+        Ok(response) => Some(response),
+        Err(_) => body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+            .find(|event| event.get("id") == id),
+    };
+    Ok((session, response.filter(|_| id.is_some())))
+}
+"#;
+
+fn lib_rs(name: &str, server: &str, endpoint: &str, functions: &[Function]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "//! Tools of the {server} MCP server, generated by `wassette component from-mcp`."
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(out, "use serde_json::{{json, Map, Value}};");
+    let _ = writeln!(
+        out,
+        "use spin_sdk::http::{{send, Method, RequestBuilder, Response}};"
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "wit_bindgen::generate!({{ world: {name:?}, path: \"wit\" }});"
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "/// Where the server is served, over MCP's streamable HTTP transport"
+    );
+    let _ = writeln!(out, "const ENDPOINT: &str = {endpoint:?};");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "const PROTOCOL_VERSION: &str = {PROTOCOL_VERSION:?};");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "struct Component;");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "export!(Component);");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "impl Guest for Component {{");
+    for (index, function) in functions.iter().enumerate() {
+        if index > 0 {
+            let _ = writeln!(out);
+        }
+        let params: Vec<String> = function
+            .args
+            .iter()
+            .map(|arg| format!("{}: {}", rust_ident(&arg.label), arg.rust()))
+            .collect();
+        let _ = writeln!(
+            out,
+            "    fn {}({}) -> Result<String, String> {{",
+            rust_ident(&function.function),
+            params.join(", ")
+        );
+        if function.args.is_empty() {
+            let _ = writeln!(out, "        let arguments = Map::new();");
+        } else {
+            let _ = writeln!(out, "        let mut arguments = Map::new();");
+        }
+        for arg in &function.args {
+            let ident = rust_ident(&arg.label);
+            let name = &arg.name;
+            let value = |value: &str| match arg.ty {
+                Some(_) => format!("json!({value})"),
+                None => format!("parse({name:?}, &{value})?"),
+            };
+            if arg.required {
+                let _ = writeln!(
+                    out,
+                    "        arguments.insert({name:?}.to_string(), {});",
+                    value(&ident)
+                );
+            } else {
+                let _ = writeln!(out, "        if let Some(value) = {ident} {{");
+                let _ = writeln!(
+                    out,
+                    "            arguments.insert({name:?}.to_string(), {});",
+                    value("value")
+                );
+                let _ = writeln!(out, "        }}");
+            }
+        }
+        let _ = writeln!(out, "        call_tool({:?}, arguments)", function.tool);
+        let _ = writeln!(out, "    }}");
+    }
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out);
+    out.push_str(CALL_TOOL);
+    out
+}
+
+fn policy_yaml(server: &str, host: &str) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "version: \"1.0\"");
+    let _ = writeln!(
+        out,
+        "description: {:?}",
+        format!("Permission policy for the {server} MCP server")
+    );
+    let _ = writeln!(out, "permissions:");
+    let _ = writeln!(out, "  network:");
+    let _ = writeln!(out, "    allow:");
+    let _ = writeln!(out, "      - host: {host:?}");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> ServerTools {
+        let tools = json!([
+            {
+                "name": "read_file",
+                "description": "Read a file\nfrom the allowed directories",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "head": { "type": ["integer", "null"] },
+                        "path": { "type": "string" },
+                        "options": { "type": "object" },
+                        "exclude": { "type": "array", "items": { "type": "string" } }
+                    },
+                    "required": ["path", "options"]
+                }
+            },
+            { "name": "list-allowed", "inputSchema": { "type": "object" } }
+        ]);
+        ServerTools {
+            name: "secure-filesystem-server".to_string(),
+            tools: serde_json::from_value(tools).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_server_command() {
+        let args = vec!["/tmp".to_string()];
+        assert_eq!(
+            server_command("npm:@modelcontextprotocol/server-filesystem", &args),
+            vec![
+                "npx",
+                "-y",
+                "@modelcontextprotocol/server-filesystem",
+                "/tmp"
+            ]
+        );
+        assert_eq!(
+            server_command("pypi:mcp-server-fetch", &[]),
+            vec!["uvx", "mcp-server-fetch"]
+        );
+        assert_eq!(server_command("./server", &args), vec!["./server", "/tmp"]);
+    }
+
+    #[test]
+    fn test_generate_component() -> Result<()> {
+        let component = generate(&server(), &GenerateOptions::default())?;
+        assert_eq!(component.name, "secure-filesystem-server");
+        assert_eq!(component.functions, vec!["read-file", "list-allowed"]);
+        let file = |name: &str| {
+            component
+                .files
+                .iter()
+                .find(|(path, _)| path == name)
+                .map(|(_, content)| content.as_str())
+                .unwrap()
+        };
+
+        assert!(file("Cargo.toml").contains("wit-bindgen = \"0.26.0\"\nserde_json = \"1\"\n"));
+
+        let wit = file("wit/world.wit");
+        assert!(wit.contains("    /// Read a file\n    /// from the allowed directories\n"));
+        assert!(wit.contains(
+            "export read-file: func(options: string, path: string, exclude: option<list<string>>, head: option<s64>) -> result<string, string>;"
+        ));
+        assert!(wit.contains("    /// The `list-allowed` tool\n"));
+        assert!(wit.contains("export list-allowed: func() -> result<string, string>;"));
+
+        let lib = file("src/lib.rs");
+        assert!(lib.contains("const ENDPOINT: &str = \"http://localhost:8000/mcp\";"));
+        assert!(lib.contains(
+            "fn read_file(options: String, path: String, exclude: Option<Vec<String>>, head: Option<i64>) -> Result<String, String> {"
+        ));
+        assert!(lib
+            .contains("arguments.insert(\"options\".to_string(), parse(\"options\", &options)?);"));
+        assert!(lib.contains("arguments.insert(\"path\".to_string(), json!(path));"));
+        assert!(lib.contains("        if let Some(value) = head {\n"));
+        assert!(lib.contains("call_tool(\"read_file\", arguments)"));
+        assert!(lib.contains(
+            "        let arguments = Map::new();\n        call_tool(\"list-allowed\", arguments)"
+        ));
+
+        let policy: Value = serde_yaml::from_str(file("policy.yaml"))?;
+        assert_eq!(
+            policy.pointer("/permissions/network/allow/0/host"),
+            Some(&Value::from("localhost"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_tools() -> Result<()> {
+        let options = GenerateOptions {
+            name: Some("files".to_string()),
+            endpoint: Some("https://mcp.example.com/files".to_string()),
+            tools: vec!["list-allowed".to_string()],
+        };
+        let component = generate(&server(), &options)?;
+        assert_eq!(component.name, "files");
+        assert_eq!(component.functions, vec!["list-allowed"]);
+        assert!(component.files[3].1.contains("\"mcp.example.com\""));
+
+        let options = GenerateOptions {
+            tools: vec!["delete_file".to_string()],
+            ..Default::default()
+        };
+        let error = generate(&server(), &options).unwrap_err();
+        assert_eq!(error.to_string(), "Tools not found: delete_file");
+
+        let options = GenerateOptions {
+            endpoint: Some("localhost:8000".to_string()),
+            ..Default::default()
+        };
+        assert!(generate(&server(), &options).is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_list_tools() -> Result<()> {
+        // Answers initialize, skips the initialized notification and answers tools/list.
+        let script = r#"
+read -r line
+echo 'starting up'
+echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2025-03-26","capabilities":{"tools":{}},"serverInfo":{"name":"echo","version":"1.0.0"}}}'
+read -r line
+read -r line
+echo '{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info","data":"hi"}}'
+echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"echo","inputSchema":{"type":"object","properties":{"text":{"type":"string"}}}}]}}'
+read -r line
+"#;
+        let command = server_command("sh", &["-c".to_string(), script.to_string()]);
+        let server = list_tools(&command).await?;
+        assert_eq!(server.name, "echo");
+        assert_eq!(server.tools.len(), 1);
+        assert_eq!(server.tools[0].name, "echo");
+        assert_eq!(
+            server.tools[0].input_schema["properties"]["text"]["type"],
+            "string"
+        );
+
+        let command = server_command("sh", &["-c".to_string(), "exit 0".to_string()]);
+        assert!(list_tools(&command).await.is_err());
+        Ok(())
+    }
+}
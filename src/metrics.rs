@@ -0,0 +1,310 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Load and component metrics
+//!
+//! The HTTP transports report the load of the server at `/metrics`, in the Prometheus text
+//! format, and at `/stats` as JSON, so a Kubernetes HorizontalPodAutoscaler (through the
+//! Prometheus adapter) or a KEDA scaler can add replicas when calls queue up or slow down.
+//! Both also carry counters per component (calls, a call duration histogram, loads, unloads,
+//! permission denials, traps and memory high-water marks) for alerting on misbehaving
+//! components. Neither path requires an access token: they carry component ids, counts and
+//! durations, never call contents.
+
+use std::fmt::Write as _;
+
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use wassette::{ComponentStats, LifecycleManager, RuntimeStats, LATENCY_BUCKETS_SECS};
+
+/// Path of the metrics in the Prometheus text format
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Path of the metrics as JSON
+pub const STATS_PATH: &str = "/stats";
+
+/// Routes serving the load of `lifecycle_manager`.
+pub fn router(lifecycle_manager: LifecycleManager) -> Router {
+    Router::new()
+        .route(METRICS_PATH, get(metrics))
+        .route(STATS_PATH, get(stats))
+        .with_state(lifecycle_manager)
+}
+
+async fn metrics(State(lifecycle_manager): State<LifecycleManager>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        render(&lifecycle_manager.stats().await),
+    )
+}
+
+async fn stats(State(lifecycle_manager): State<LifecycleManager>) -> Json<RuntimeStats> {
+    Json(lifecycle_manager.stats().await)
+}
+
+/// Format `stats` in the Prometheus text exposition format.
+fn render(stats: &RuntimeStats) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, f64)]| {
+        let _ = writeln!(out, "# HELP wassette_{name} {help}");
+        let _ = writeln!(out, "# TYPE wassette_{name} {kind}");
+        for (labels, value) in samples {
+            let _ = writeln!(out, "wassette_{name}{labels} {value}");
+        }
+    };
+    metric(
+        "queued_calls",
+        "gauge",
+        "Tool calls waiting for an execution slot.",
+        &[
+            (
+                "{priority=\"interactive\"}",
+                stats.queued_interactive_calls as f64,
+            ),
+            ("{priority=\"batch\"}", stats.queued_batch_calls as f64),
+        ],
+    );
+    metric(
+        "active_calls",
+        "gauge",
+        "Tool calls running.",
+        &[("", stats.active_calls as f64)],
+    );
+    metric(
+        "execution_slots",
+        "gauge",
+        "Tool calls that run at once before further calls queue.",
+        &[("", stats.execution_slots as f64)],
+    );
+    metric(
+        "pool_utilization",
+        "gauge",
+        "Running tool calls per execution slot.",
+        &[("", stats.pool_utilization)],
+    );
+    metric(
+        "limited_instances",
+        "gauge",
+        "Instances of components with a concurrent instance limit.",
+        &[("", stats.limited_instances as f64)],
+    );
+    metric(
+        "instance_capacity",
+        "gauge",
+        "Sum of the concurrent instance limits of components.",
+        &[("", stats.instance_capacity as f64)],
+    );
+    metric(
+        "standby_instances",
+        "gauge",
+        "Standby instances ready for latency-critical tools.",
+        &[("", stats.standby_instances as f64)],
+    );
+    metric(
+        "latency_critical_tools",
+        "gauge",
+        "Tools keeping a standby instance.",
+        &[("", stats.latency_critical_tools as f64)],
+    );
+    metric(
+        "loaded_components",
+        "gauge",
+        "Components compiled and ready to run.",
+        &[("", stats.loaded_components as f64)],
+    );
+    metric(
+        "calls_total",
+        "counter",
+        "Tool calls completed.",
+        &[("", stats.calls_total as f64)],
+    );
+    metric(
+        "call_errors_total",
+        "counter",
+        "Tool calls that failed or returned a tool error.",
+        &[("", stats.call_errors_total as f64)],
+    );
+    let latencies: Vec<(&str, f64)> = [
+        ("{quantile=\"0.5\"}", stats.latency_p50_secs),
+        ("{quantile=\"0.95\"}", stats.latency_p95_secs),
+    ]
+    .into_iter()
+    .filter_map(|(labels, value)| Some((labels, value?)))
+    .collect();
+    metric(
+        "call_latency_seconds",
+        "gauge",
+        "Duration of the most recent tool calls, queueing included.",
+        &latencies,
+    );
+
+    let mut per_component =
+        |name: &str, kind: &str, help: &str, value: fn(&ComponentStats) -> f64| {
+            let samples: Vec<(String, f64)> = stats
+                .components
+                .iter()
+                .map(|(id, component)| {
+                    (
+                        format!("{{component=\"{}\"}}", escape(id)),
+                        value(component),
+                    )
+                })
+                .collect();
+            let samples: Vec<(&str, f64)> = samples
+                .iter()
+                .map(|(labels, value)| (labels.as_str(), *value))
+                .collect();
+            metric(name, kind, help, &samples);
+        };
+    per_component(
+        "component_calls_total",
+        "counter",
+        "Tool calls of the component completed.",
+        |component| component.calls_total as f64,
+    );
+    per_component(
+        "component_call_errors_total",
+        "counter",
+        "Tool calls of the component that failed or returned a tool error.",
+        |component| component.call_errors_total as f64,
+    );
+    per_component(
+        "component_loads_total",
+        "counter",
+        "Times the component was loaded or replaced.",
+        |component| component.loads_total as f64,
+    );
+    per_component(
+        "component_unloads_total",
+        "counter",
+        "Times the component was unloaded.",
+        |component| component.unloads_total as f64,
+    );
+    per_component(
+        "component_permission_denials_total",
+        "counter",
+        "Network requests and filesystem escapes the component's policy refused.",
+        |component| component.permission_denials_total as f64,
+    );
+    per_component(
+        "component_traps_total",
+        "counter",
+        "Tool calls of the component that trapped, timeouts and fuel exhaustion included.",
+        |component| component.traps_total as f64,
+    );
+    per_component(
+        "component_memory_high_water_bytes",
+        "gauge",
+        "Most linear memory a single instance of the component used.",
+        |component| component.memory_high_water_bytes as f64,
+    );
+
+    let name = "wassette_component_call_duration_seconds";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Duration of the tool calls of the component, queueing included."
+    );
+    let _ = writeln!(out, "# TYPE {name} histogram");
+    for (id, component) in &stats.components {
+        let id = escape(id);
+        for (bound, count) in LATENCY_BUCKETS_SECS.iter().zip(component.latency_buckets) {
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{component=\"{id}\",le=\"{bound}\"}} {count}"
+            );
+        }
+        let count = component.calls_total;
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{component=\"{id}\",le=\"+Inf\"}} {count}"
+        );
+        let _ = writeln!(
+            out,
+            "{name}_sum{{component=\"{id}\"}} {}",
+            component.latency_sum_secs
+        );
+        let _ = writeln!(out, "{name}_count{{component=\"{id}\"}} {count}");
+    }
+    out
+}
+
+/// Escape `value` for use as a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_text() {
+        let stats = RuntimeStats {
+            queued_interactive_calls: 3,
+            active_calls: 4,
+            execution_slots: 4,
+            pool_utilization: 1.0,
+            calls_total: 10,
+            latency_p95_secs: Some(0.25),
+            ..Default::default()
+        };
+        let text = render(&stats);
+        assert!(text.contains("# TYPE wassette_queued_calls gauge\n"));
+        assert!(text.contains("wassette_queued_calls{priority=\"interactive\"} 3\n"));
+        assert!(text.contains("wassette_queued_calls{priority=\"batch\"} 0\n"));
+        assert!(text.contains("wassette_pool_utilization 1\n"));
+        assert!(text.contains("# TYPE wassette_calls_total counter\nwassette_calls_total 10\n"));
+        assert!(text.contains("wassette_call_latency_seconds{quantile=\"0.95\"} 0.25\n"));
+        // No calls, no median.
+        assert!(!text.contains("quantile=\"0.5\""));
+        assert!(text.contains("# TYPE wassette_component_call_duration_seconds histogram\n"));
+    }
+
+    #[test]
+    fn test_render_component_metrics() {
+        let mut buckets = [0; LATENCY_BUCKETS_SECS.len()];
+        buckets[5..].fill(2);
+        buckets[4] = 1;
+        let stats = RuntimeStats {
+            components: [(
+                "fetch".to_string(),
+                ComponentStats {
+                    calls_total: 3,
+                    call_errors_total: 1,
+                    latency_buckets: buckets,
+                    latency_sum_secs: 45.5,
+                    loads_total: 2,
+                    traps_total: 1,
+                    memory_high_water_bytes: 1 << 20,
+                    ..Default::default()
+                },
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let text = render(&stats);
+        assert!(text.contains("wassette_component_calls_total{component=\"fetch\"} 3\n"));
+        assert!(text.contains("wassette_component_loads_total{component=\"fetch\"} 2\n"));
+        assert!(text.contains("wassette_component_unloads_total{component=\"fetch\"} 0\n"));
+        assert!(text.contains("wassette_component_traps_total{component=\"fetch\"} 1\n"));
+        assert!(text
+            .contains("wassette_component_memory_high_water_bytes{component=\"fetch\"} 1048576\n"));
+        for line in [
+            "wassette_component_call_duration_seconds_bucket{component=\"fetch\",le=\"0.05\"} 0",
+            "wassette_component_call_duration_seconds_bucket{component=\"fetch\",le=\"0.1\"} 1",
+            "wassette_component_call_duration_seconds_bucket{component=\"fetch\",le=\"30\"} 2",
+            "wassette_component_call_duration_seconds_bucket{component=\"fetch\",le=\"+Inf\"} 3",
+            "wassette_component_call_duration_seconds_sum{component=\"fetch\"} 45.5",
+            "wassette_component_call_duration_seconds_count{component=\"fetch\"} 3",
+        ] {
+            assert!(text.contains(&format!("{line}\n")), "{line}");
+        }
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
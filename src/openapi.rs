@@ -0,0 +1,1176 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Generation of components wrapping the operations of an HTTP API
+//!
+//! `wassette component from-openapi` turns operations of an OpenAPI 3 document into a Rust
+//! component project: one exported function per operation, calling the API through
+//! `wasi:http`, and a policy granting access to the API's host only. Path and query
+//! parameters become function parameters and a request body a `body` string, sent as is;
+//! functions return the response body. Header and cookie parameters are left out. An API key
+//! or bearer token the API requires is read from the `API_KEY` environment variable, which
+//! the policy grants.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+
+use anyhow::{bail, Context, Result};
+use reqwest::Url;
+use serde_json::Value;
+
+/// Environment variable the generated component reads the API key from
+pub const API_KEY_ENV: &str = "API_KEY";
+
+/// HTTP methods of operations, in the order of the OpenAPI path item.
+const METHODS: &[(&str, &str)] = &[
+    ("get", "Get"),
+    ("put", "Put"),
+    ("post", "Post"),
+    ("delete", "Delete"),
+    ("options", "Options"),
+    ("head", "Head"),
+    ("patch", "Patch"),
+    ("trace", "Trace"),
+];
+
+/// Identifiers that must be escaped with `%` in WIT.
+const WIT_KEYWORDS: &[&str] = &[
+    "as",
+    "async",
+    "bool",
+    "borrow",
+    "char",
+    "constructor",
+    "enum",
+    "export",
+    "f32",
+    "f64",
+    "flags",
+    "from",
+    "func",
+    "future",
+    "import",
+    "include",
+    "interface",
+    "list",
+    "own",
+    "option",
+    "package",
+    "record",
+    "resource",
+    "result",
+    "s16",
+    "s32",
+    "s64",
+    "s8",
+    "static",
+    "stream",
+    "string",
+    "tuple",
+    "type",
+    "u16",
+    "u32",
+    "u64",
+    "u8",
+    "use",
+    "variant",
+    "with",
+    "world",
+];
+
+/// Identifiers the Rust bindings suffix with `_`.
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// What to generate from an OpenAPI document.
+#[derive(Debug, Clone, Default)]
+pub struct GenerateOptions {
+    /// Name of the component. Defaults to the title of the API
+    pub name: Option<String>,
+    /// URL the API is served at. Defaults to the first server of the document
+    pub base_url: Option<String>,
+    /// `operationId`s of the operations to wrap. Defaults to all of them
+    pub operations: Vec<String>,
+}
+
+/// A component project generated from an OpenAPI document.
+#[derive(Debug, Clone)]
+pub struct GeneratedComponent {
+    /// Name of the component and its crate
+    pub name: String,
+    /// Names of the exported functions, one per wrapped operation
+    pub functions: Vec<String>,
+    /// Files of the project, as pairs of path relative to its directory and contents
+    pub files: Vec<(String, String)>,
+}
+
+impl GeneratedComponent {
+    /// File name of the component the project builds, relative to its `target` directory.
+    pub fn artifact(&self) -> String {
+        format!("wasm32-wasip2/release/{}.wasm", self.name.replace('-', "_"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Location {
+    Path,
+    Query,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ParamType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+    List(Box<ParamType>),
+}
+
+impl ParamType {
+    pub(crate) fn wit(&self) -> String {
+        match self {
+            Self::String => "string".to_string(),
+            Self::Integer => "s64".to_string(),
+            Self::Number => "f64".to_string(),
+            Self::Boolean => "bool".to_string(),
+            Self::List(item) => format!("list<{}>", item.wit()),
+        }
+    }
+
+    pub(crate) fn rust(&self) -> String {
+        match self {
+            Self::String => "String".to_string(),
+            Self::Integer => "i64".to_string(),
+            Self::Number => "f64".to_string(),
+            Self::Boolean => "bool".to_string(),
+            Self::List(item) => format!("Vec<{}>", item.rust()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Param {
+    /// Name in the API
+    name: String,
+    /// Name of the function parameter, in kebab case
+    label: String,
+    location: Location,
+    ty: ParamType,
+    required: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Auth {
+    Header,
+    Query,
+    Bearer,
+}
+
+#[derive(Debug, Clone)]
+struct Operation {
+    /// Name of the exported function, in kebab case
+    function: String,
+    method: &'static str,
+    path: String,
+    summary: Option<String>,
+    params: Vec<Param>,
+    /// Content type and label of the request body, and whether it's required
+    body: Option<(String, String, bool)>,
+    authenticated: bool,
+}
+
+/// Where the API expects its key, and under which name.
+#[derive(Debug, Clone)]
+struct ApiKey {
+    auth: Auth,
+    name: String,
+}
+
+/// Generate a component project wrapping operations of the OpenAPI 3 `spec`, in YAML or JSON.
+pub fn generate(spec: &str, options: &GenerateOptions) -> Result<GeneratedComponent> {
+    let document: Value = serde_yaml::from_str(spec).context("Invalid OpenAPI document")?;
+    match document.get("openapi").and_then(Value::as_str) {
+        Some(version) if version.starts_with("3.") => {}
+        Some(version) => bail!("Unsupported OpenAPI version {version}, expected 3.x"),
+        None => bail!("Not an OpenAPI 3 document: `openapi` is missing"),
+    }
+
+    let title = document
+        .pointer("/info/title")
+        .and_then(Value::as_str)
+        .unwrap_or("api");
+    let name = kebab_case(options.name.as_deref().unwrap_or(title));
+    if name.is_empty() {
+        bail!("Can't derive a component name from {title:?}, pass --name");
+    }
+    let base_url = base_url(&document, options.base_url.as_deref())?;
+    let host = Url::parse(&base_url)?
+        .host_str()
+        .map(str::to_string)
+        .context("The base URL has no host")?;
+    let api_key = api_key(&document);
+    let operations = operations(&document, &options.operations, api_key.as_ref())?;
+    if operations.is_empty() {
+        bail!("The document has no operations to wrap");
+    }
+    let authenticated = operations.iter().any(|operation| operation.authenticated);
+    let api_key = api_key.filter(|_| authenticated);
+
+    let files = vec![
+        ("Cargo.toml".to_string(), cargo_toml(&name, &[])),
+        ("wit/world.wit".to_string(), world_wit(&name, &operations)),
+        (
+            "src/lib.rs".to_string(),
+            lib_rs(&name, title, &base_url, api_key.as_ref(), &operations),
+        ),
+        (
+            "policy.yaml".to_string(),
+            policy_yaml(title, &host, api_key.is_some()),
+        ),
+        (".gitignore".to_string(), "/target\n".to_string()),
+    ];
+    Ok(GeneratedComponent {
+        name,
+        functions: operations.into_iter().map(|op| op.function).collect(),
+        files,
+    })
+}
+
+/// `name` as a WIT label: lowercase words separated by `-`, each starting with a letter.
+pub(crate) fn kebab_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        // `getHTTPStatus` is `get-http-status`
+        let starts_word = c.is_ascii_uppercase()
+            && i > 0
+            && (!chars[i - 1].is_ascii_uppercase()
+                || chars.get(i + 1).is_some_and(char::is_ascii_lowercase));
+        if starts_word && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c.to_ascii_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    let mut label = String::new();
+    for word in words {
+        // Words can't start with a digit, so those are joined to the previous one.
+        if word.starts_with(|c: char| c.is_ascii_digit()) {
+            if label.is_empty() {
+                label.push('n');
+            }
+        } else if !label.is_empty() {
+            label.push('-');
+        }
+        label.push_str(&word);
+    }
+    label
+}
+
+/// `label` as a WIT identifier.
+pub(crate) fn wit_ident(label: &str) -> String {
+    if WIT_KEYWORDS.contains(&label) {
+        format!("%{label}")
+    } else {
+        label.to_string()
+    }
+}
+
+/// The Rust identifier the bindings give `label`.
+pub(crate) fn rust_ident(label: &str) -> String {
+    let ident = label.replace('-', "_");
+    if RUST_KEYWORDS.contains(&ident.as_str()) {
+        format!("{ident}_")
+    } else {
+        ident
+    }
+}
+
+/// `value` with the local `$ref`s it is made of followed.
+fn resolve<'a>(document: &'a Value, mut value: &'a Value) -> Result<&'a Value> {
+    for _ in 0..32 {
+        let Some(reference) = value.get("$ref").and_then(Value::as_str) else {
+            return Ok(value);
+        };
+        let pointer = reference
+            .strip_prefix('#')
+            .with_context(|| format!("Only local references are supported, got {reference}"))?;
+        value = document
+            .pointer(pointer)
+            .with_context(|| format!("Unresolved reference {reference}"))?;
+    }
+    bail!("Too many nested references")
+}
+
+/// The base URL of the API, without a trailing `/`.
+fn base_url(document: &Value, base_url: Option<&str>) -> Result<String> {
+    let url = match base_url {
+        Some(url) => url.to_string(),
+        None => {
+            let server = document
+                .pointer("/servers/0")
+                .context("The document lists no servers, pass --base-url")?;
+            let mut url = server
+                .get("url")
+                .and_then(Value::as_str)
+                .context("The first server has no URL")?
+                .to_string();
+            if let Some(variables) = server.get("variables").and_then(Value::as_object) {
+                for (variable, spec) in variables {
+                    if let Some(default) = spec.get("default").and_then(Value::as_str) {
+                        url = url.replace(&format!("{{{variable}}}"), default);
+                    }
+                }
+            }
+            url
+        }
+    };
+    match Url::parse(&url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {
+            Ok(url.trim_end_matches('/').to_string())
+        }
+        _ => bail!("{url:?} isn't an absolute HTTP URL, pass --base-url"),
+    }
+}
+
+/// The first API key or bearer token scheme of the document the component can send.
+fn api_key(document: &Value) -> Option<ApiKey> {
+    let schemes = document
+        .pointer("/components/securitySchemes")?
+        .as_object()?;
+    schemes.values().find_map(|scheme| {
+        let scheme = resolve(document, scheme).ok()?;
+        match scheme.get("type").and_then(Value::as_str)? {
+            "apiKey" => {
+                let auth = match scheme.get("in").and_then(Value::as_str)? {
+                    "header" => Auth::Header,
+                    "query" => Auth::Query,
+                    _ => return None,
+                };
+                let name = scheme.get("name").and_then(Value::as_str)?.to_string();
+                Some(ApiKey { auth, name })
+            }
+            "http"
+                if scheme
+                    .get("scheme")
+                    .and_then(Value::as_str)
+                    .is_some_and(|s| s.eq_ignore_ascii_case("bearer")) =>
+            {
+                Some(ApiKey {
+                    auth: Auth::Bearer,
+                    name: "authorization".to_string(),
+                })
+            }
+            _ => None,
+        }
+    })
+}
+
+/// The scheme names an operation requires, from its `security` or the document's.
+fn security_schemes<'a>(document: &'a Value, operation: &'a Value) -> BTreeSet<&'a str> {
+    operation
+        .get("security")
+        .or_else(|| document.get("security"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object)
+        .flat_map(|requirement| requirement.keys().map(String::as_str))
+        .collect()
+}
+
+/// The operations of the document, only those in `selected` unless it is empty.
+fn operations(
+    document: &Value,
+    selected: &[String],
+    api_key: Option<&ApiKey>,
+) -> Result<Vec<Operation>> {
+    let api_key_scheme = api_key.and_then(|key| {
+        let schemes = document
+            .pointer("/components/securitySchemes")?
+            .as_object()?;
+        schemes.iter().find_map(|(name, scheme)| {
+            let scheme = resolve(document, scheme).ok()?;
+            let matches = match key.auth {
+                Auth::Bearer => scheme.get("type").and_then(Value::as_str) == Some("http"),
+                _ => scheme.get("name").and_then(Value::as_str) == Some(key.name.as_str()),
+            };
+            matches.then_some(name.as_str())
+        })
+    });
+
+    let mut found = BTreeSet::new();
+    let mut functions = BTreeMap::new();
+    let mut operations = Vec::new();
+    let paths = document
+        .get("paths")
+        .and_then(Value::as_object)
+        .context("The document has no paths")?;
+    for (path, item) in paths {
+        let item = resolve(document, item)?;
+        for (method, variant) in METHODS {
+            let Some(operation) = item.get(*method) else {
+                continue;
+            };
+            let id = operation
+                .get("operationId")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("{method} {path}"));
+            if !selected.is_empty() && !selected.contains(&id) {
+                continue;
+            }
+            found.insert(id.clone());
+
+            let function = kebab_case(&id);
+            if function.is_empty() {
+                bail!("Can't derive a function name from operation {id:?}");
+            }
+            if let Some(other) = functions.insert(function.clone(), id.clone()) {
+                bail!(
+                    "Operations {other:?} and {id:?} are both named {function:?}, select one with --operation"
+                );
+            }
+            let params = params(document, path, item, operation)
+                .with_context(|| format!("Unsupported parameters in operation {id:?}"))?;
+            let body = match operation.get("requestBody") {
+                Some(body) => {
+                    let body = resolve(document, body)?;
+                    let content_type = body
+                        .get("content")
+                        .and_then(Value::as_object)
+                        .and_then(|content| {
+                            content
+                                .keys()
+                                .find(|content_type| content_type.contains("json"))
+                                .or_else(|| content.keys().next())
+                        })
+                        .cloned()
+                        .unwrap_or_else(|| "application/json".to_string());
+                    let required = body.get("required").and_then(Value::as_bool) == Some(true);
+                    let mut label = "body".to_string();
+                    if params.iter().any(|param| param.label == label) {
+                        label = "request-body".to_string();
+                    }
+                    Some((content_type, label, required))
+                }
+                None => None,
+            };
+            let authenticated = api_key_scheme
+                .is_some_and(|scheme| security_schemes(document, operation).contains(scheme));
+            let summary = operation
+                .get("summary")
+                .or_else(|| operation.get("description"))
+                .and_then(Value::as_str)
+                .map(|summary| summary.trim().to_string())
+                .filter(|summary| !summary.is_empty());
+            operations.push(Operation {
+                function,
+                method: variant,
+                path: path.clone(),
+                summary,
+                params,
+                body,
+                authenticated,
+            });
+        }
+    }
+
+    let missing: Vec<_> = selected
+        .iter()
+        .filter(|id| !found.contains(*id))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        bail!("Operations not found: {}", missing.join(", "));
+    }
+    Ok(operations)
+}
+
+/// The path and query parameters of `operation`, including those of its path `item`.
+fn params(document: &Value, path: &str, item: &Value, operation: &Value) -> Result<Vec<Param>> {
+    let mut declared: Vec<&Value> = Vec::new();
+    for list in [item.get("parameters"), operation.get("parameters")] {
+        for param in list.and_then(Value::as_array).into_iter().flatten() {
+            let param = resolve(document, param)?;
+            // Operation parameters override those of the path item.
+            let key = |p: &Value| (p.get("name").cloned(), p.get("in").cloned());
+            declared.retain(|existing| key(existing) != key(param));
+            declared.push(param);
+        }
+    }
+
+    let mut params: Vec<Param> = Vec::new();
+    for param in declared {
+        let name = param
+            .get("name")
+            .and_then(Value::as_str)
+            .context("Parameter without a name")?
+            .to_string();
+        let location = match param.get("in").and_then(Value::as_str) {
+            Some("path") => Location::Path,
+            Some("query") => Location::Query,
+            _ => continue,
+        };
+        let ty = match param.get("schema") {
+            Some(schema) => param_type(document, schema)?,
+            None => ParamType::String,
+        };
+        let required = location == Location::Path
+            || param.get("required").and_then(Value::as_bool) == Some(true);
+        add_param(&mut params, name, location, ty, required)?;
+    }
+    // Placeholders of the path that weren't declared.
+    for segment in path.split('{').skip(1) {
+        let name = segment.split('}').next().unwrap_or_default();
+        let declared = params
+            .iter()
+            .any(|param| param.location == Location::Path && param.name == name);
+        if !declared {
+            add_param(
+                &mut params,
+                name.to_string(),
+                Location::Path,
+                ParamType::String,
+                true,
+            )?;
+        }
+    }
+    Ok(params)
+}
+
+/// Add a parameter, labelled after its location if its name is taken.
+fn add_param(
+    params: &mut Vec<Param>,
+    name: String,
+    location: Location,
+    ty: ParamType,
+    required: bool,
+) -> Result<()> {
+    let mut label = kebab_case(&name);
+    if label.is_empty() || params.iter().any(|param| param.label == label) {
+        let prefix = match location {
+            Location::Path => "path",
+            Location::Query => "query",
+        };
+        label = format!("{prefix}-{label}")
+            .trim_end_matches('-')
+            .to_string();
+    }
+    if params.iter().any(|param| param.label == label) {
+        bail!("Parameters named {name:?} can't be told apart");
+    }
+    params.push(Param {
+        name,
+        label,
+        location,
+        ty,
+        required,
+    });
+    Ok(())
+}
+
+fn param_type(document: &Value, schema: &Value) -> Result<ParamType> {
+    let schema = resolve(document, schema)?;
+    let ty = match schema.get("type") {
+        Some(Value::String(ty)) => ty.as_str(),
+        // OpenAPI 3.1 `type: [integer, "null"]`
+        Some(Value::Array(types)) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|ty| *ty != "null")
+            .unwrap_or("string"),
+        _ => "string",
+    };
+    Ok(match ty {
+        "integer" => ParamType::Integer,
+        "number" => ParamType::Number,
+        "boolean" => ParamType::Boolean,
+        "array" => {
+            let item = match schema.get("items") {
+                Some(items) => param_type(document, items)?,
+                None => ParamType::String,
+            };
+            match item {
+                ParamType::List(_) => ParamType::List(Box::new(ParamType::String)),
+                item => ParamType::List(Box::new(item)),
+            }
+        }
+        _ => ParamType::String,
+    })
+}
+
+/// `Cargo.toml` of a component project, with `dependencies` lines besides the Spin SDK.
+pub(crate) fn cargo_toml(name: &str, dependencies: &[&str]) -> String {
+    let dependencies: String = dependencies
+        .iter()
+        .map(|line| format!("\n{line}"))
+        .collect();
+    format!(
+        r#"[package]
+name = "{name}"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+spin-executor = "3.0.1"
+spin-sdk = "3.0.1"
+wit-bindgen = "0.26.0"{dependencies}
+
+[lib]
+crate-type = ["cdylib"]
+
+[profile.release]
+codegen-units = 1
+opt-level = "s"
+debug = false
+strip = true
+lto = true
+
+[workspace]
+"#
+    )
+}
+
+pub(crate) fn doc_comment(out: &mut String, indent: &str, text: &str) {
+    for line in text.lines() {
+        let _ = writeln!(out, "{indent}/// {}", line.trim_end());
+    }
+}
+
+fn world_wit(name: &str, operations: &[Operation]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "package component:{name};");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "world {} {{", wit_ident(name));
+    for (index, operation) in operations.iter().enumerate() {
+        if index > 0 {
+            let _ = writeln!(out);
+        }
+        let summary = operation
+            .summary
+            .clone()
+            .unwrap_or_else(|| format!("{} {}", operation.method.to_uppercase(), operation.path));
+        doc_comment(&mut out, "    ", &summary);
+        let mut params: Vec<String> = operation
+            .params
+            .iter()
+            .map(|param| {
+                let ty = param.ty.wit();
+                let ty = if param.required {
+                    ty
+                } else {
+                    format!("option<{ty}>")
+                };
+                format!("{}: {ty}", wit_ident(&param.label))
+            })
+            .collect();
+        if let Some((_, label, required)) = &operation.body {
+            let ty = if *required {
+                "string"
+            } else {
+                "option<string>"
+            };
+            params.push(format!("{}: {ty}", wit_ident(label)));
+        }
+        let _ = writeln!(
+            out,
+            "    export {}: func({}) -> result<string, string>;",
+            wit_ident(&operation.function),
+            params.join(", ")
+        );
+    }
+    let _ = writeln!(out, "}}");
+    out
+}
+
+/// Rust code formatting the path of `operation`, with its parameters percent-encoded.
+fn path_expr(operation: &Operation) -> String {
+    let mut template = String::new();
+    let mut args = Vec::new();
+    let mut rest = operation.path.as_str();
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        template.push_str(&rest[..start].replace('{', "{{").replace('}', "}}"));
+        template.push_str("{}");
+        let name = &rest[start + 1..start + end];
+        if let Some(param) = operation
+            .params
+            .iter()
+            .find(|param| param.location == Location::Path && param.name == name)
+        {
+            let ident = rust_ident(&param.label);
+            args.push(match param.ty {
+                ParamType::String => format!("encode(&{ident})"),
+                _ => format!("encode(&{ident}.to_string())"),
+            });
+        }
+        rest = &rest[start + end + 1..];
+    }
+    template.push_str(&rest.replace('{', "{{").replace('}', "}}"));
+    if args.is_empty() {
+        format!("{template:?}.to_string()")
+    } else {
+        format!("format!({template:?}, {})", args.join(", "))
+    }
+}
+
+fn lib_rs(
+    name: &str,
+    title: &str,
+    base_url: &str,
+    api_key: Option<&ApiKey>,
+    operations: &[Operation],
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "//! Tools calling the {title} API, generated by `wassette component from-openapi`."
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "use spin_sdk::http::{{send, Method, RequestBuilder, Response}};"
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "wit_bindgen::generate!({{ world: {name:?}, path: \"wit\" }});"
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(out, "/// Where the API is served");
+    let _ = writeln!(out, "const BASE_URL: &str = {base_url:?};");
+    if api_key.is_some() {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "/// Environment variable holding the API key");
+        let _ = writeln!(out, "const API_KEY_ENV: &str = {API_KEY_ENV:?};");
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(out, "struct Component;");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "export!(Component);");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "impl Guest for Component {{");
+    for (index, operation) in operations.iter().enumerate() {
+        if index > 0 {
+            let _ = writeln!(out);
+        }
+        let mut params: Vec<String> = operation
+            .params
+            .iter()
+            .map(|param| {
+                let ty = param.ty.rust();
+                let ty = if param.required {
+                    ty
+                } else {
+                    format!("Option<{ty}>")
+                };
+                format!("{}: {ty}", rust_ident(&param.label))
+            })
+            .collect();
+        if let Some((_, label, required)) = &operation.body {
+            let ty = if *required {
+                "String"
+            } else {
+                "Option<String>"
+            };
+            params.push(format!("{}: {ty}", rust_ident(label)));
+        }
+        let _ = writeln!(
+            out,
+            "    fn {}({}) -> Result<String, String> {{",
+            rust_ident(&operation.function),
+            params.join(", ")
+        );
+        let _ = writeln!(out, "        let path = {};", path_expr(operation));
+        let query: Vec<&Param> = operation
+            .params
+            .iter()
+            .filter(|param| param.location == Location::Query)
+            .collect();
+        if query.is_empty() {
+            let _ = writeln!(out, "        let query = Vec::new();");
+        } else {
+            let _ = writeln!(out, "        let mut query = Vec::new();");
+        }
+        for param in query {
+            let ident = rust_ident(&param.label);
+            let name = &param.name;
+            match (&param.ty, param.required) {
+                (ParamType::List(_), true) => {
+                    let _ = writeln!(out, "        for value in &{ident} {{");
+                    let _ = writeln!(
+                        out,
+                        "            query.push(({name:?}, value.to_string()));"
+                    );
+                    let _ = writeln!(out, "        }}");
+                }
+                (ParamType::List(_), false) => {
+                    let _ = writeln!(out, "        for value in {ident}.iter().flatten() {{");
+                    let _ = writeln!(
+                        out,
+                        "            query.push(({name:?}, value.to_string()));"
+                    );
+                    let _ = writeln!(out, "        }}");
+                }
+                (_, true) => {
+                    let _ = writeln!(out, "        query.push(({name:?}, {ident}.to_string()));");
+                }
+                (_, false) => {
+                    let _ = writeln!(out, "        if let Some(value) = &{ident} {{");
+                    let _ = writeln!(
+                        out,
+                        "            query.push(({name:?}, value.to_string()));"
+                    );
+                    let _ = writeln!(out, "        }}");
+                }
+            }
+        }
+        let body = match &operation.body {
+            Some((content_type, label, true)) => {
+                format!("Some(({content_type:?}, {}))", rust_ident(label))
+            }
+            Some((content_type, label, false)) => {
+                format!("{}.map(|body| ({content_type:?}, body))", rust_ident(label))
+            }
+            None => "None".to_string(),
+        };
+        let authenticated = if api_key.is_some() {
+            format!(", {}", operation.authenticated)
+        } else {
+            String::new()
+        };
+        let _ = writeln!(
+            out,
+            "        call(Method::{}, path, query, {body}{authenticated})",
+            operation.method
+        );
+        let _ = writeln!(out, "    }}");
+    }
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out);
+
+    let _ = writeln!(
+        out,
+        "/// Send a request to the API, returning the response body if it succeeded."
+    );
+    let _ = writeln!(out, "fn call(");
+    let _ = writeln!(out, "    method: Method,");
+    let _ = writeln!(out, "    path: String,");
+    let mutable = if api_key.is_some_and(|key| key.auth == Auth::Query) {
+        "mut "
+    } else {
+        ""
+    };
+    let _ = writeln!(out, "    {mutable}query: Vec<(&str, String)>,");
+    let _ = writeln!(out, "    body: Option<(&str, String)>,");
+    if api_key.is_some() {
+        let _ = writeln!(out, "    authenticated: bool,");
+    }
+    let _ = writeln!(out, ") -> Result<String, String> {{");
+    let _ = writeln!(out, "    spin_executor::run(async move {{");
+    let key_header = match api_key {
+        Some(ApiKey {
+            auth: Auth::Query,
+            name,
+        }) => {
+            let _ = writeln!(out, "        if authenticated {{");
+            let _ = writeln!(
+                out,
+                "            if let Ok(key) = std::env::var(API_KEY_ENV) {{"
+            );
+            let _ = writeln!(out, "                query.push(({name:?}, key));");
+            let _ = writeln!(out, "            }}");
+            let _ = writeln!(out, "        }}");
+            None
+        }
+        Some(ApiKey {
+            auth: Auth::Header,
+            name,
+        }) => Some((name.clone(), "key".to_string())),
+        Some(ApiKey {
+            auth: Auth::Bearer,
+            name,
+        }) => Some((name.clone(), "format!(\"Bearer {key}\")".to_string())),
+        None => None,
+    };
+    let _ = writeln!(
+        out,
+        "        let mut url = format!(\"{{BASE_URL}}{{path}}\");"
+    );
+    let _ = writeln!(
+        out,
+        "        for (index, (name, value)) in query.iter().enumerate() {{"
+    );
+    let _ = writeln!(
+        out,
+        "            url.push(if index == 0 {{ '?' }} else {{ '&' }});"
+    );
+    let _ = writeln!(out, "            url.push_str(&encode(name));");
+    let _ = writeln!(out, "            url.push('=');");
+    let _ = writeln!(out, "            url.push_str(&encode(value));");
+    let _ = writeln!(out, "        }}");
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "        let mut request = RequestBuilder::new(method, url);"
+    );
+    let _ = writeln!(
+        out,
+        "        request.header(\"accept\", \"application/json\");"
+    );
+    if let Some((header, value)) = key_header {
+        let _ = writeln!(out, "        if authenticated {{");
+        let _ = writeln!(
+            out,
+            "            if let Ok(key) = std::env::var(API_KEY_ENV) {{"
+        );
+        let _ = writeln!(out, "                request.header({header:?}, {value});");
+        let _ = writeln!(out, "            }}");
+        let _ = writeln!(out, "        }}");
+    }
+    let _ = writeln!(out, "        if let Some((content_type, body)) = body {{");
+    let _ = writeln!(
+        out,
+        "            request.header(\"content-type\", content_type);"
+    );
+    let _ = writeln!(out, "            request.body(body);");
+    let _ = writeln!(out, "        }}");
+    let _ = writeln!(
+        out,
+        "        let response: Response = send(request.build()).await.map_err(|e| e.to_string())?;"
+    );
+    let _ = writeln!(out, "        let status = *response.status();");
+    let _ = writeln!(
+        out,
+        "        let body = String::from_utf8_lossy(response.body()).into_owned();"
+    );
+    let _ = writeln!(out, "        if (200..300).contains(&status) {{");
+    let _ = writeln!(out, "            Ok(body)");
+    let _ = writeln!(out, "        }} else {{");
+    let _ = writeln!(
+        out,
+        "            Err(format!(\"Request failed with status code {{status}}: {{body}}\"))"
+    );
+    let _ = writeln!(out, "        }}");
+    let _ = writeln!(out, "    }})");
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "/// `value` percent-encoded for a URL path segment or query string."
+    );
+    let _ = writeln!(out, "fn encode(value: &str) -> String {{");
+    let _ = writeln!(out, "    let mut encoded = String::new();");
+    let _ = writeln!(out, "    for byte in value.bytes() {{");
+    let _ = writeln!(out, "        match byte {{");
+    let _ = writeln!(
+        out,
+        "            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {{"
+    );
+    let _ = writeln!(out, "                encoded.push(byte as char)");
+    let _ = writeln!(out, "            }}");
+    let _ = writeln!(
+        out,
+        "            _ => encoded.push_str(&format!(\"%{{byte:02X}}\")),"
+    );
+    let _ = writeln!(out, "        }}");
+    let _ = writeln!(out, "    }}");
+    let _ = writeln!(out, "    encoded");
+    let _ = writeln!(out, "}}");
+    out
+}
+
+fn policy_yaml(title: &str, host: &str, api_key: bool) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "version: \"1.0\"");
+    let _ = writeln!(
+        out,
+        "description: {:?}",
+        format!("Permission policy for the {title} API")
+    );
+    let _ = writeln!(out, "permissions:");
+    let _ = writeln!(out, "  network:");
+    let _ = writeln!(out, "    allow:");
+    let _ = writeln!(out, "      - host: {host:?}");
+    if api_key {
+        let _ = writeln!(out, "  environment:");
+        let _ = writeln!(out, "    allow:");
+        let _ = writeln!(out, "      - key: {API_KEY_ENV:?}");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r#"
+openapi: 3.0.3
+info:
+  title: Weather Service
+servers:
+  - url: https://{region}.weather.example.com/v1/
+    variables:
+      region:
+        default: eu
+components:
+  securitySchemes:
+    key:
+      type: apiKey
+      in: header
+      name: X-Api-Key
+  parameters:
+    City:
+      name: city
+      in: path
+      required: true
+      schema:
+        type: string
+security:
+  - key: []
+paths:
+  /forecast/{city}:
+    parameters:
+      - $ref: '#/components/parameters/City'
+    get:
+      operationId: getForecast
+      summary: Forecast for a city
+      parameters:
+        - name: days
+          in: query
+          schema:
+            type: integer
+        - name: fields
+          in: query
+          required: true
+          schema:
+            type: array
+            items:
+              type: string
+        - name: X-Trace
+          in: header
+          schema:
+            type: string
+  /alerts:
+    post:
+      operationId: subscribe_alerts
+      security: []
+      requestBody:
+        required: true
+        content:
+          application/json:
+            schema:
+              type: object
+"#;
+
+    #[test]
+    fn test_kebab_case() {
+        assert_eq!(kebab_case("getForecast"), "get-forecast");
+        assert_eq!(kebab_case("getHTTPStatus"), "get-http-status");
+        assert_eq!(kebab_case("subscribe_alerts"), "subscribe-alerts");
+        assert_eq!(kebab_case("get /v2/items/{id}"), "get-v2-items-id");
+        assert_eq!(kebab_case("list 2fa devices"), "list2fa-devices");
+        assert_eq!(kebab_case("3d models"), "n3d-models");
+        assert_eq!(wit_ident("type"), "%type");
+        assert_eq!(rust_ident("type"), "type_");
+    }
+
+    #[test]
+    fn test_generate_component() -> Result<()> {
+        let component = generate(SPEC, &GenerateOptions::default())?;
+        assert_eq!(component.name, "weather-service");
+        assert_eq!(
+            component.functions,
+            vec!["subscribe-alerts", "get-forecast"]
+        );
+        assert_eq!(
+            component.artifact(),
+            "wasm32-wasip2/release/weather_service.wasm"
+        );
+        let file = |name: &str| {
+            component
+                .files
+                .iter()
+                .find(|(path, _)| path == name)
+                .map(|(_, content)| content.as_str())
+                .unwrap()
+        };
+
+        let wit = file("wit/world.wit");
+        assert!(wit.contains("world weather-service {"));
+        assert!(wit.contains("    /// Forecast for a city\n"));
+        assert!(wit.contains(
+            "export get-forecast: func(city: string, days: option<s64>, fields: list<string>) -> result<string, string>;"
+        ));
+        assert!(wit.contains("export subscribe-alerts: func(body: string)"));
+
+        let lib = file("src/lib.rs");
+        assert!(lib.contains("const BASE_URL: &str = \"https://eu.weather.example.com/v1\";"));
+        assert!(lib.contains(
+            "fn get_forecast(city: String, days: Option<i64>, fields: Vec<String>) -> Result<String, String> {"
+        ));
+        assert!(lib.contains("let path = format!(\"/forecast/{}\", encode(&city));"));
+        assert!(lib.contains("call(Method::Get, path, query, None, true)"));
+        assert!(lib.contains(
+            "call(Method::Post, path, query, Some((\"application/json\", body)), false)"
+        ));
+        assert!(lib.contains("request.header(\"X-Api-Key\", key);"));
+        assert!(!lib.contains("X-Trace"));
+
+        let policy: Value = serde_yaml::from_str(file("policy.yaml"))?;
+        assert_eq!(
+            policy.pointer("/permissions/network/allow/0/host"),
+            Some(&Value::from("eu.weather.example.com"))
+        );
+        assert_eq!(
+            policy.pointer("/permissions/environment/allow/0/key"),
+            Some(&Value::from(API_KEY_ENV))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_operations() -> Result<()> {
+        let options = GenerateOptions {
+            name: Some("alerts".to_string()),
+            base_url: Some("http://localhost:8080".to_string()),
+            operations: vec!["subscribe_alerts".to_string()],
+        };
+        let component = generate(SPEC, &options)?;
+        assert_eq!(component.functions, vec!["subscribe-alerts"]);
+        let lib = &component.files[2].1;
+        // The only selected operation doesn't need the key.
+        assert!(!lib.contains("API_KEY_ENV"));
+        assert!(lib.contains("call(Method::Post, path, query, Some((\"application/json\", body)))"));
+        assert!(!component.files[3].1.contains("environment"));
+
+        let options = GenerateOptions {
+            operations: vec!["deleteEverything".to_string()],
+            ..Default::default()
+        };
+        let error = generate(SPEC, &options).unwrap_err();
+        assert_eq!(error.to_string(), "Operations not found: deleteEverything");
+
+        let error = generate("swagger: '2.0'", &GenerateOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("Not an OpenAPI 3 document"));
+        Ok(())
+    }
+}
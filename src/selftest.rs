@@ -0,0 +1,365 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! `wassette security selftest`: escape and abuse attempts against the sandbox
+//!
+//! The self-test loads an adversarial component (`examples/selftest-rs`) into a throwaway
+//! plugin directory, under a policy granting it one directory, one environment variable, one
+//! host and a few open files, and makes it try to reach past each grant: reading files
+//! outside the directory, listing the server's environment, sending requests to a host the
+//! policy doesn't allow, and opening files past the limit. Every attempt the sandbox blocks
+//! passes. The runtime settings of the configuration apply, so operators can check an upgrade
+//! or a configuration change before rolling it out.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use wassette::{LifecycleManager, SecretsBackendConfig};
+
+use crate::config::Config;
+
+/// The only environment variable the policy of the self-test grants
+const GRANTED_VAR: &str = "SELFTEST_GRANTED";
+
+/// An environment variable of the server the component must not see
+const CANARY_VAR: &str = "SELFTEST_CANARY";
+
+/// Contents of the file outside the granted directory
+const CANARY: &str = "wassette-selftest-canary";
+
+/// The `open-files` limit of the policy of the self-test
+const OPEN_FILES_LIMIT: u32 = 16;
+
+/// Files the component tries to hold open at once
+const OPEN_FILES_ATTEMPTS: u32 = 1024;
+
+/// How long to wait for a request that got through to reach the local listener
+const CONNECT_GRACE: Duration = Duration::from_millis(200);
+
+/// The adversarial component built into this binary, if it was built before the binary.
+#[cfg(bundled_selftest)]
+pub const BUNDLED_COMPONENT: Option<&[u8]> =
+    Some(include_bytes!(env!("WASSETTE_SELFTEST_COMPONENT")));
+/// The adversarial component built into this binary, if it was built before the binary.
+#[cfg(not(bundled_selftest))]
+pub const BUNDLED_COMPONENT: Option<&[u8]> = None;
+
+/// How an attempt to escape the sandbox ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProbeStatus {
+    /// The sandbox stopped the attempt
+    Blocked,
+    /// The attempt reached past the policy
+    Escaped,
+    /// The attempt couldn't be made, so nothing is known
+    Error,
+}
+
+/// One attempt to escape the sandbox and how it ended
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeOutcome {
+    /// Short name of the attempt
+    pub probe: &'static str,
+    /// What the component tried
+    pub attempt: &'static str,
+    /// How it ended
+    pub status: ProbeStatus,
+    /// What happened, in the words of the sandbox or the component
+    pub detail: String,
+}
+
+/// Outcome of every attempt of the self-test
+#[derive(Debug, Clone, Serialize)]
+pub struct SelftestReport {
+    /// Whether every attempt was blocked
+    pub passed: bool,
+    /// The attempts, in the order they were made
+    pub probes: Vec<ProbeOutcome>,
+}
+
+/// Run the self-test with the settings of `config`, making the attempts with `component`.
+///
+/// The component runs from a temporary plugin and secrets directory; the components, secrets,
+/// hooks and updates of the configuration are left alone.
+pub async fn run(mut config: Config, component: &[u8]) -> Result<SelftestReport> {
+    let temp_dir = tempfile::tempdir().context("Failed to create the self-test directory")?;
+    // Canonical, so the granted path matches what the sandbox resolves, e.g. on macOS where
+    // the temporary directory is behind a symlink.
+    let root = temp_dir
+        .path()
+        .canonicalize()
+        .context("Failed to resolve the self-test directory")?;
+    let sandbox = root.join("sandbox");
+    std::fs::create_dir(&sandbox)?;
+    let granted_file = sandbox.join("granted.txt");
+    std::fs::write(&granted_file, "granted")?;
+    let outside = root.join("outside.txt");
+    std::fs::write(&outside, CANARY)?;
+    let component_path = root.join("selftest.wasm");
+    std::fs::write(&component_path, component)?;
+    let policy_path = root.join("policy.yaml");
+    std::fs::write(&policy_path, policy(&sandbox))?;
+
+    config.plugin_dir = root.join("plugins");
+    config.secrets_dir = root.join("secrets");
+    config.secrets = SecretsBackendConfig::File;
+    config.environment_vars = HashMap::from([
+        (GRANTED_VAR.to_string(), "granted".to_string()),
+        (CANARY_VAR.to_string(), CANARY.to_string()),
+    ]);
+    config.hooks.clear();
+    config.updates = None;
+    config.anomaly_detection = None;
+    config.workspace_components.clear();
+    config.libraries.clear();
+    config.provenance = Default::default();
+    config.watch = false;
+    config.locked = false;
+    config.lockfile = None;
+
+    let manager = crate::build_lifecycle_manager(config).await?;
+    let outcome = manager
+        .load_component(&format!("file://{}", component_path.display()))
+        .await
+        .context("Failed to load the self-test component")?;
+    let component_id = outcome.component_id;
+    manager
+        .attach_policy(&component_id, &format!("file://{}", policy_path.display()))
+        .await
+        .context("Failed to attach the self-test policy")?;
+    let probes = Probes {
+        manager: &manager,
+        component_id: &component_id,
+    };
+
+    let mut outcomes = vec![
+        probes
+            .read(
+                "path-traversal",
+                "read a file outside the granted directory through `..`",
+                &sandbox.join("..").join("outside.txt"),
+            )
+            .await,
+        probes
+            .read(
+                "absolute-path",
+                "read a file outside the granted directory by its absolute path",
+                &outside,
+            )
+            .await,
+    ];
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&outside, sandbox.join("escape"))?;
+        outcomes.push(
+            probes
+                .read(
+                    "symlink-escape",
+                    "read a file outside the granted directory through a symlink in it",
+                    &sandbox.join("escape"),
+                )
+                .await,
+        );
+    }
+    outcomes.push(probes.environment().await);
+    outcomes.push(probes.fetch().await?);
+    outcomes.push(probes.open_files(&granted_file).await);
+
+    Ok(SelftestReport {
+        passed: outcomes
+            .iter()
+            .all(|outcome| outcome.status == ProbeStatus::Blocked),
+        probes: outcomes,
+    })
+}
+
+/// The policy of the self-test, granting `sandbox`
+fn policy(sandbox: &Path) -> String {
+    format!(
+        r#"version: "1.0"
+description: "wassette security selftest"
+permissions:
+  storage:
+    allow:
+      - uri: "fs://{sandbox}"
+        access: ["read", "write"]
+  network:
+    allow:
+      - host: "example.com"
+  environment:
+    allow:
+      - key: "{GRANTED_VAR}"
+  resources:
+    limits:
+      open-files: {OPEN_FILES_LIMIT}
+"#,
+        sandbox = sandbox.display()
+    )
+}
+
+/// The exports of the self-test component, called as tools
+struct Probes<'a> {
+    manager: &'a LifecycleManager,
+    component_id: &'a str,
+}
+
+impl Probes<'_> {
+    /// Call `tool`, returning the `ok` value of its result or the `err` value the component
+    /// returned. Errors of the call itself, such as traps, are the outer error.
+    async fn call(&self, tool: &str, arguments: Value) -> Result<Result<Value, String>> {
+        let output = self
+            .manager
+            .execute_component_call(self.component_id, tool, &arguments.to_string())
+            .await?;
+        let output: Value = serde_json::from_str(&output)?;
+        let result = &output["result"];
+        Ok(match result.get("err") {
+            Some(err) => Err(err.as_str().map_or_else(|| err.to_string(), str::to_string)),
+            None => Ok(result.get("ok").unwrap_or(result).clone()),
+        })
+    }
+
+    async fn read(&self, probe: &'static str, attempt: &'static str, path: &Path) -> ProbeOutcome {
+        let (status, detail) = match self
+            .call("read-file", json!({ "path": path.to_string_lossy() }))
+            .await
+        {
+            Ok(Ok(contents)) if contents.as_str() == Some(CANARY) => {
+                (ProbeStatus::Escaped, format!("read {}", path.display()))
+            }
+            Ok(Ok(_)) => (
+                ProbeStatus::Blocked,
+                "read another file than the one outside".to_string(),
+            ),
+            Ok(Err(e)) => (ProbeStatus::Blocked, e),
+            Err(e) => (ProbeStatus::Error, format!("{e:#}")),
+        };
+        ProbeOutcome {
+            probe,
+            attempt,
+            status,
+            detail,
+        }
+    }
+
+    async fn environment(&self) -> ProbeOutcome {
+        let (status, detail) = match self.call("environment", json!({})).await {
+            Ok(Ok(names)) => {
+                let names: Vec<String> = serde_json::from_value(names).unwrap_or_default();
+                let leaked = leaked_variables(&names);
+                if leaked.is_empty() {
+                    (
+                        ProbeStatus::Blocked,
+                        format!("saw only {}", names.join(", ")),
+                    )
+                } else {
+                    (ProbeStatus::Escaped, format!("saw {}", leaked.join(", ")))
+                }
+            }
+            Ok(Err(e)) => (ProbeStatus::Error, e),
+            Err(e) => (ProbeStatus::Error, format!("{e:#}")),
+        };
+        ProbeOutcome {
+            probe: "environment-probing",
+            attempt: "list environment variables the policy doesn't grant",
+            status,
+            detail,
+        }
+    }
+
+    async fn fetch(&self) -> Result<ProbeOutcome> {
+        // A local listener tells a request that got through from one that failed anyway.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .context("Failed to start the self-test listener")?;
+        let authority = listener.local_addr()?.to_string();
+        let result = self.call("fetch", json!({ "authority": authority })).await;
+        let connected = tokio::time::timeout(CONNECT_GRACE, listener.accept())
+            .await
+            .is_ok();
+
+        let (status, detail) = match result {
+            _ if connected => (ProbeStatus::Escaped, format!("connected to {authority}")),
+            Ok(Ok(status)) => (
+                ProbeStatus::Escaped,
+                format!("{authority} answered {status}"),
+            ),
+            Ok(Err(e)) => (ProbeStatus::Blocked, e),
+            Err(e) => (ProbeStatus::Error, format!("{e:#}")),
+        };
+        Ok(ProbeOutcome {
+            probe: "disallowed-host",
+            attempt: "send a request to a host the policy doesn't allow",
+            status,
+            detail,
+        })
+    }
+
+    async fn open_files(&self, path: &Path) -> ProbeOutcome {
+        let arguments = json!({ "path": path.to_string_lossy(), "count": OPEN_FILES_ATTEMPTS });
+        let (status, detail) = match self.call("open-files", arguments).await {
+            Ok(Ok(opened)) => {
+                let opened = opened.as_u64().unwrap_or_default();
+                let status = if opened <= u64::from(OPEN_FILES_LIMIT) {
+                    ProbeStatus::Blocked
+                } else {
+                    ProbeStatus::Escaped
+                };
+                (
+                    status,
+                    format!(
+                        "opened {opened} of {OPEN_FILES_ATTEMPTS} files, limit {OPEN_FILES_LIMIT}"
+                    ),
+                )
+            }
+            Ok(Err(e)) => (ProbeStatus::Error, e),
+            // Running out of descriptors may trap the component, which stops it too.
+            Err(e) => (ProbeStatus::Blocked, format!("{e:#}")),
+        };
+        ProbeOutcome {
+            probe: "fd-exhaustion",
+            attempt: "hold more files open than the policy's open-files limit",
+            status,
+            detail,
+        }
+    }
+}
+
+/// The variables among `names` the component must not see: the canary of the self-test and
+/// those of the server's own environment.
+fn leaked_variables(names: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| {
+            name.as_str() != GRANTED_VAR
+                && (name.as_str() == CANARY_VAR || std::env::var_os(name).is_some())
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leaked_variables() {
+        let server_var = std::env::vars().next().map(|(name, _)| name);
+        let mut names = vec![
+            GRANTED_VAR.to_string(),
+            "SELFTEST_COMPONENT_ONLY".to_string(),
+        ];
+        assert!(leaked_variables(&names).is_empty());
+
+        names.push(CANARY_VAR.to_string());
+        names.extend(server_var.clone());
+        let mut expected = vec![CANARY_VAR.to_string()];
+        expected.extend(server_var);
+        assert_eq!(leaked_variables(&names), expected);
+    }
+}
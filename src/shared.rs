@@ -0,0 +1,388 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! One shared server for all the desktop clients of a user
+//!
+//! Every editor that launches `wassette serve --stdio` would otherwise run its own server,
+//! compiling and holding the same components. With `--shared`, the launched process only
+//! relays its stdio to a per-user server listening on a Unix socket, which it starts in the
+//! background if none is running. That server keeps one set of loaded components and
+//! compiled modules, and serves each connected client as its own MCP session. There is one
+//! shared server per plugin directory, so clients configured with different directories don't
+//! see each other's components.
+//!
+//! Servers started at the same time by several clients race for the socket. The one holding
+//! a lock on the `.lock` file next to it owns the socket: it alone replaces a socket left
+//! behind by a server that crashed, keeps the lock while it serves, and removes the socket
+//! when it exits. The others find the lock taken and leave.
+//!
+//! The socket lives in a directory only the user can open, which the server creates or checks
+//! before it binds, and the server serves only connections whose peer runs as the same user.
+//! Other platforms have no such socket, so `--shared` is refused when the arguments are
+//! parsed.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use crate::activation::IdleTimer;
+use crate::config::Config;
+use crate::McpServer;
+
+/// How long a client waits for the shared server it started to accept connections
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Socket of the shared server for `plugin_dir`, in the user's runtime directory.
+pub fn socket_path(plugin_dir: &Path) -> Result<PathBuf> {
+    use etcetera::BaseStrategy;
+
+    let dir_strategy = etcetera::choose_base_strategy().context("Unable to get home directory")?;
+    let dir = dir_strategy
+        .runtime_dir()
+        .unwrap_or_else(|| dir_strategy.data_dir())
+        .join("wassette");
+    let plugin_dir = std::fs::canonicalize(plugin_dir).unwrap_or_else(|_| plugin_dir.into());
+    let digest = ring::digest::digest(
+        &ring::digest::SHA256,
+        plugin_dir.as_os_str().as_encoded_bytes(),
+    );
+    let id: String = digest.as_ref()[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok(dir.join(format!("shared-{id}.sock")))
+}
+
+/// What a process launched with `--shared` does.
+pub enum Role {
+    /// Relay its stdio to the shared server at the socket
+    Client(PathBuf),
+    /// Serve the clients' sessions on the listener, bound at the socket
+    Server(SharedListener, PathBuf),
+    /// Nothing, since another shared server already owns the socket
+    Redundant,
+}
+
+/// Role of a process launched with `--shared` for `config`, `server` telling whether it was
+/// started as the shared server by a client.
+pub async fn role(config: &Config, server: bool) -> Result<Role> {
+    // Workspace roots are the server's, not a session's, so clients sharing a server would see
+    // each other's projects.
+    if !config.workspace_components.is_empty() {
+        bail!("--shared can't be used with workspace_components, since the clients would share their workspace roots");
+    }
+    let socket = socket_path(&config.plugin_dir)?;
+    if !server {
+        return Ok(Role::Client(socket));
+    }
+    Ok(match listen(&socket).await? {
+        Some(listener) => Role::Server(listener, socket),
+        None => Role::Redundant,
+    })
+}
+
+#[cfg(unix)]
+pub use unix::{listen, proxy_stdio, serve, SharedListener};
+
+#[cfg(not(unix))]
+pub use fallback::{listen, proxy_stdio, serve, SharedListener};
+
+#[cfg(unix)]
+mod unix {
+    use std::fs::{File, Permissions, TryLockError};
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    use rmcp::service::serve_server;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{UnixListener, UnixStream};
+
+    use super::*;
+
+    /// Relay stdin and stdout to the shared server at `socket`, starting it with the arguments
+    /// of this process if it isn't running.
+    pub async fn proxy_stdio(socket: &Path) -> Result<()> {
+        let stream = match UnixStream::connect(socket).await {
+            Ok(stream) => stream,
+            Err(_) => start(socket).await?,
+        };
+        tracing::info!(socket = %socket.display(), "Connected to the shared server");
+
+        let (mut from_server, mut to_server) = stream.into_split();
+        let requests = async {
+            tokio::io::copy(&mut tokio::io::stdin(), &mut to_server).await?;
+            to_server.shutdown().await
+        };
+        let responses = async {
+            tokio::io::copy(&mut from_server, &mut tokio::io::stdout()).await?;
+            Ok::<_, std::io::Error>(())
+        };
+        // The session ends when the client closes stdin or the shared server exits.
+        tokio::select! {
+            result = requests => result.context("Failed to relay requests to the shared server"),
+            result = responses => result.context("Failed to relay responses from the shared server"),
+        }
+    }
+
+    /// Start the shared server in the background and connect to it once it listens.
+    async fn start(socket: &Path) -> Result<UnixStream> {
+        use std::os::unix::process::CommandExt;
+
+        private_dir(socket)?;
+        let log_path = socket.with_extension("log");
+        let log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .with_context(|| format!("Failed to open {}", log_path.display()))?;
+
+        tracing::info!(log = %log_path.display(), "Starting the shared server");
+        std::process::Command::new(std::env::current_exe()?)
+            .args(std::env::args_os().skip(1))
+            .arg("--shared-server")
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(log)
+            // Outlive the client that started it, and its terminal's signals.
+            .process_group(0)
+            .spawn()
+            .context("Failed to start the shared server")?;
+
+        let started = tokio::time::Instant::now();
+        loop {
+            match UnixStream::connect(socket).await {
+                Ok(stream) => return Ok(stream),
+                Err(_) if started.elapsed() < STARTUP_TIMEOUT => {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("The shared server didn't start; see {}", log_path.display())
+                    })
+                }
+            }
+        }
+    }
+
+    /// Create the directory of `socket` with mode `0700`, or check that an existing one
+    /// belongs to this user and close it to others, so no other user can reach the socket.
+    fn private_dir(socket: &Path) -> Result<()> {
+        let dir = socket
+            .parent()
+            .context("Socket path has no parent directory")?;
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let metadata = std::fs::symlink_metadata(dir)
+            .with_context(|| format!("Failed to read {}", dir.display()))?;
+        if !metadata.is_dir() || metadata.uid() != user_id() {
+            bail!(
+                "{} isn't a directory of this user, so it can't hold the shared server's socket",
+                dir.display()
+            );
+        }
+        if metadata.mode() & 0o077 != 0 {
+            std::fs::set_permissions(dir, Permissions::from_mode(0o700))
+                .with_context(|| format!("Failed to restrict {} to this user", dir.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Effective user id of this process
+    fn user_id() -> u32 {
+        // SAFETY: geteuid has no preconditions and always succeeds.
+        unsafe { libc::geteuid() }
+    }
+
+    /// The socket of the shared server, owned for as long as the lock next to it is held.
+    pub struct SharedListener {
+        listener: UnixListener,
+        /// Device and inode of the socket this server bound
+        socket: (u64, u64),
+        _lock: File,
+    }
+
+    impl SharedListener {
+        /// Remove the socket, unless it was replaced by one this server didn't bind.
+        pub(super) fn remove(&self, socket: &Path) {
+            let ours = std::fs::symlink_metadata(socket)
+                .is_ok_and(|metadata| (metadata.dev(), metadata.ino()) == self.socket);
+            if ours {
+                let _ = std::fs::remove_file(socket);
+            }
+        }
+    }
+
+    /// Listen on `socket` as the shared server, or `None` if another one already does.
+    pub async fn listen(socket: &Path) -> Result<Option<SharedListener>> {
+        private_dir(socket)?;
+        let lock_path = socket.with_extension("lock");
+        let lock = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock {}", lock_path.display()))
+            }
+        }
+        // With the lock held, a socket at the path was left behind by a shared server that
+        // didn't exit cleanly.
+        match std::fs::remove_file(socket) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| format!("Failed to remove {}", socket.display()))
+            }
+            _ => {}
+        }
+        let listener = UnixListener::bind(socket)
+            .with_context(|| format!("Failed to listen on {}", socket.display()))?;
+        let metadata = std::fs::symlink_metadata(socket)
+            .with_context(|| format!("Failed to read {}", socket.display()))?;
+        Ok(Some(SharedListener {
+            listener,
+            socket: (metadata.dev(), metadata.ino()),
+            _lock: lock,
+        }))
+    }
+
+    /// Serve every client that connects to `listener` with its own session of `server`, until
+    /// Ctrl-C or, with `idle`, until no client has made a request for the idle timeout.
+    pub async fn serve(
+        listener: SharedListener,
+        socket: &Path,
+        server: McpServer,
+        idle: Option<Arc<IdleTimer>>,
+    ) -> Result<()> {
+        tracing::info!(socket = %socket.display(), "Serving desktop clients as the shared server");
+        let shutdown = crate::activation::shutdown_signal(idle);
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                accepted = listener.listener.accept() => {
+                    let (stream, _) = accepted.context("Failed to accept a client")?;
+                    match stream.peer_cred() {
+                        Ok(peer) if peer.uid() == user_id() => {}
+                        Ok(peer) => {
+                            tracing::warn!(uid = peer.uid(), "Refused a client of another user");
+                            continue;
+                        }
+                        Err(error) => {
+                            tracing::warn!(%error, "Refused a client whose user is unknown");
+                            continue;
+                        }
+                    }
+                    let server = server.for_session();
+                    tokio::spawn(async move {
+                        match serve_server(server, stream).await {
+                            Ok(session) => {
+                                let _ = session.waiting().await;
+                            }
+                            Err(error) => {
+                                tracing::warn!(%error, "Failed to start a client session")
+                            }
+                        }
+                    });
+                }
+            }
+        }
+        // New clients start a fresh shared server instead of connecting to this one. The lock
+        // is only released once the socket is gone.
+        listener.remove(socket);
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod fallback {
+    use super::*;
+
+    /// Stands in for the Unix socket listener.
+    pub struct SharedListener;
+
+    pub async fn proxy_stdio(_socket: &Path) -> Result<()> {
+        bail!("--shared needs a Unix host")
+    }
+
+    pub async fn listen(_socket: &Path) -> Result<Option<SharedListener>> {
+        bail!("--shared needs a Unix host")
+    }
+
+    pub async fn serve(
+        _listener: SharedListener,
+        _socket: &Path,
+        _server: McpServer,
+        _idle: Option<Arc<IdleTimer>>,
+    ) -> Result<()> {
+        bail!("--shared needs a Unix host")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_socket_per_plugin_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = socket_path(&dir.path().join("a")).unwrap();
+        assert_eq!(first, socket_path(&dir.path().join("a")).unwrap());
+        assert_ne!(first, socket_path(&dir.path().join("b")).unwrap());
+        assert!(first.extension().is_some_and(|ext| ext == "sock"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_socket_directory_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket_dir = dir.path().join("wassette");
+        std::fs::create_dir(&socket_dir).unwrap();
+        std::fs::set_permissions(&socket_dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let _listener = listen(&socket_dir.join("shared.sock"))
+            .await
+            .unwrap()
+            .unwrap();
+        let mode = std::fs::metadata(&socket_dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // A socket directory that isn't a directory of this user is refused.
+        let elsewhere = dir.path().join("elsewhere");
+        std::fs::create_dir(&elsewhere).unwrap();
+        std::os::unix::fs::symlink(&elsewhere, dir.path().join("link")).unwrap();
+        assert!(listen(&dir.path().join("link/shared.sock")).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_only_one_shared_server_listens() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("shared.sock");
+
+        let listener = listen(&socket).await.unwrap();
+        assert!(listener.is_some());
+        assert!(listen(&socket).await.unwrap().is_none());
+        assert!(socket.exists());
+
+        // The socket of a server that is gone is taken over.
+        drop(listener);
+        assert!(socket.exists());
+        let listener = listen(&socket).await.unwrap().unwrap();
+
+        // A socket this server didn't bind is left alone.
+        std::fs::remove_file(&socket).unwrap();
+        let other = tokio::net::UnixListener::bind(&socket).unwrap();
+        listener.remove(&socket);
+        assert!(socket.exists());
+        drop(other);
+    }
+}
@@ -0,0 +1,516 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! SSE transport with heartbeats and resumable streams
+//!
+//! Every message event carries an id of the form `<session>:<n>`. A client whose stream drops
+//! opens `/sse` again with the last id it saw in `Last-Event-ID` and is sent the events it
+//! missed; responses the server produces in the meantime are kept for it. A session whose
+//! client doesn't come back within the resume window is closed. Heartbeat events keep proxies
+//! that cut idle connections from closing the stream. When the server checks tokens, a session
+//! belongs to the grant it was opened with: resuming it or posting to it with another token is
+//! refused.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::{Query, State};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use rmcp::model::ClientJsonRpcMessage;
+use rmcp::service::{serve_server, RoleServer, RxJsonRpcMessage, TxJsonRpcMessage};
+use rmcp::transport::common::server_side_http::session_id;
+use rmcp::transport::Transport;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::StreamExt;
+
+use crate::auth::Grant;
+
+/// Path clients open the event stream on
+const SSE_PATH: &str = "/sse";
+
+/// Path clients post their messages to
+const POST_PATH: &str = "/message";
+
+/// Settings of the SSE transport
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SseConfig {
+    /// Seconds without events after which a heartbeat event is sent; 0 turns heartbeats off
+    pub heartbeat_secs: u64,
+    /// Seconds a session is kept after its stream drops, for the client to resume it
+    pub resume_window_secs: u64,
+    /// Events kept per session to replay to a resuming client
+    pub replay_events: usize,
+}
+
+impl Default for SseConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_secs: 15,
+            resume_window_secs: 60,
+            replay_events: 1024,
+        }
+    }
+}
+
+/// Routes serving MCP over SSE, with a service from `service` per session.
+pub fn router<S, F>(config: SseConfig, service: F) -> Router
+where
+    S: rmcp::Service<RoleServer>,
+    F: Fn() -> S + Send + Sync + 'static,
+{
+    let start: Arc<dyn Fn(SessionTransport) + Send + Sync> = Arc::new(move |transport| {
+        let service = service();
+        tokio::spawn(async move {
+            match serve_server(service, transport).await {
+                Ok(running) => {
+                    let _ = running.waiting().await;
+                }
+                Err(e) => tracing::warn!("Failed to start SSE session: {}", e),
+            }
+        });
+    });
+    let app = App {
+        sessions: Default::default(),
+        config,
+        start,
+    };
+    Router::new()
+        .route(SSE_PATH, get(open_stream))
+        .route(POST_PATH, post(post_message))
+        .with_state(app)
+}
+
+type Sessions = Arc<Mutex<HashMap<String, Arc<Mutex<Session>>>>>;
+
+#[derive(Clone)]
+struct App {
+    sessions: Sessions,
+    config: SseConfig,
+    /// Runs the MCP service of a new session
+    start: Arc<dyn Fn(SessionTransport) + Send + Sync>,
+}
+
+/// An event waiting to be written to a client's stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Queued {
+    /// Where to post messages; carries the session's first event id when it is new
+    Endpoint { new: bool },
+    /// A JSON-RPC message, with its sequence number in the session
+    Message(u64, Arc<str>),
+}
+
+struct Session {
+    id: String,
+    /// Grant of the token the session was opened with, if the server checks tokens
+    owner: Option<Grant>,
+    /// Messages from the client to the service; `None` once the session is closed
+    to_service: Option<mpsc::Sender<RxJsonRpcMessage<RoleServer>>>,
+    /// Messages sent to the client, oldest first, kept for replay
+    sent: VecDeque<(u64, Arc<str>)>,
+    /// Most messages kept in `sent`
+    capacity: usize,
+    /// Sequence number of the next message; 0 is the endpoint event of a new session
+    next_seq: u64,
+    /// The client's stream, while it is connected
+    stream: Option<mpsc::UnboundedSender<Queued>>,
+    /// Counts streams attached, so a stream that was replaced doesn't detach its successor
+    generation: u64,
+}
+
+impl Session {
+    fn new(
+        id: String,
+        owner: Option<Grant>,
+        to_service: mpsc::Sender<RxJsonRpcMessage<RoleServer>>,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            id,
+            owner,
+            to_service: Some(to_service),
+            sent: VecDeque::new(),
+            capacity,
+            next_seq: 1,
+            stream: None,
+            generation: 0,
+        }
+    }
+
+    /// Whether a request made with `grant` may use the session.
+    fn allows(&self, grant: Option<&Grant>) -> bool {
+        self.owner.as_ref() == grant
+    }
+
+    /// Send a message to the client, or keep it until the client resumes.
+    fn send(&mut self, data: Arc<str>) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if let Some(stream) = &self.stream {
+            if stream.send(Queued::Message(seq, data.clone())).is_err() {
+                self.stream = None;
+            }
+        }
+        self.sent.push_back((seq, data));
+        while self.sent.len() > self.capacity {
+            self.sent.pop_front();
+        }
+    }
+
+    /// Attach a new stream, replacing any other, and queue the endpoint event followed by
+    /// the messages after sequence number `after` when resuming.
+    fn attach(&mut self, after: Option<u64>) -> mpsc::UnboundedReceiver<Queued> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let _ = tx.send(Queued::Endpoint {
+            new: after.is_none(),
+        });
+        if let Some(after) = after {
+            if self
+                .sent
+                .front()
+                .is_some_and(|(oldest, _)| *oldest > after + 1)
+            {
+                tracing::warn!(
+                    session = %self.id,
+                    "SSE client resumed after more than {} events; older events are lost",
+                    self.capacity
+                );
+            }
+            for (seq, data) in self.sent.iter().filter(|(seq, _)| *seq > after) {
+                let _ = tx.send(Queued::Message(*seq, data.clone()));
+            }
+        }
+        self.stream = Some(tx);
+        self.generation += 1;
+        rx
+    }
+
+    fn event(&self, queued: Queued) -> Event {
+        match queued {
+            Queued::Endpoint { new } => {
+                let event = Event::default()
+                    .event("endpoint")
+                    .data(format!("{POST_PATH}?sessionId={}", self.id));
+                // A resumed stream keeps the client's last event id.
+                if new {
+                    event.id(format!("{}:0", self.id))
+                } else {
+                    event
+                }
+            }
+            Queued::Message(seq, data) => Event::default()
+                .event("message")
+                .id(format!("{}:{seq}", self.id))
+                .data(data.as_ref()),
+        }
+    }
+}
+
+/// Split a `Last-Event-ID` into the session id and sequence number.
+fn parse_event_id(id: &str) -> Option<(&str, u64)> {
+    let (session, seq) = id.rsplit_once(':')?;
+    Some((session, seq.parse().ok()?))
+}
+
+fn close(sessions: &Sessions, session: &Mutex<Session>) {
+    let mut session = session.lock().unwrap();
+    session.to_service = None;
+    session.stream = None;
+    sessions.lock().unwrap().remove(&session.id);
+}
+
+async fn open_stream(
+    State(app): State<App>,
+    grant: Option<Extension<Grant>>,
+    headers: HeaderMap,
+) -> Response {
+    let grant = grant.map(|Extension(grant)| grant);
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok());
+    let resumed = last_event_id
+        .and_then(parse_event_id)
+        .and_then(|(id, seq)| {
+            let session = app.sessions.lock().unwrap().get(id).cloned()?;
+            let rx = {
+                let mut locked = session.lock().unwrap();
+                if !locked.allows(grant.as_ref()) {
+                    return Some(Err(id));
+                }
+                locked.attach(Some(seq))
+            };
+            tracing::info!(session = %id, "SSE stream resumed");
+            Some(Ok((session, rx)))
+        });
+    let (session, rx) = match resumed {
+        Some(Ok(resumed)) => resumed,
+        Some(Err(id)) => {
+            tracing::warn!(
+                session = %id,
+                "Refused to resume an SSE session opened with another token"
+            );
+            return StatusCode::FORBIDDEN.into_response();
+        }
+        None => {
+            if let Some(id) = last_event_id {
+                tracing::info!(
+                    "SSE client asked to resume unknown or expired event {}; starting a new session",
+                    id
+                );
+            }
+            open_session(&app, grant)
+        }
+    };
+
+    let detach = Detach {
+        sessions: app.sessions.clone(),
+        generation: session.lock().unwrap().generation,
+        session: session.clone(),
+        window: Duration::from_secs(app.config.resume_window_secs),
+    };
+    let stream = UnboundedReceiverStream::new(rx).map(move |queued| {
+        let event = detach.session.lock().unwrap().event(queued);
+        Ok::<_, Infallible>(event)
+    });
+
+    let sse = Sse::new(stream);
+    match app.config.heartbeat_secs {
+        0 => sse.into_response(),
+        secs => sse
+            .keep_alive(
+                KeepAlive::new()
+                    .interval(Duration::from_secs(secs))
+                    .event(Event::default().event("heartbeat").data("")),
+            )
+            .into_response(),
+    }
+}
+
+fn open_session(
+    app: &App,
+    owner: Option<Grant>,
+) -> (Arc<Mutex<Session>>, mpsc::UnboundedReceiver<Queued>) {
+    let id = session_id().to_string();
+    let (to_service, from_client) = mpsc::channel(64);
+    let session = Arc::new(Mutex::new(Session::new(
+        id.clone(),
+        owner,
+        to_service,
+        app.config.replay_events,
+    )));
+    let rx = session.lock().unwrap().attach(None);
+    app.sessions
+        .lock()
+        .unwrap()
+        .insert(id.clone(), session.clone());
+    tracing::info!(session = %id, "SSE session opened");
+
+    (app.start)(SessionTransport {
+        session: session.clone(),
+        sessions: app.sessions.clone(),
+        from_client,
+    });
+    (session, rx)
+}
+
+/// Closes the session when its stream is dropped and the client doesn't resume in time.
+struct Detach {
+    sessions: Sessions,
+    session: Arc<Mutex<Session>>,
+    generation: u64,
+    window: Duration,
+}
+
+impl Drop for Detach {
+    fn drop(&mut self) {
+        {
+            let mut session = self.session.lock().unwrap();
+            if session.generation != self.generation {
+                return;
+            }
+            session.stream = None;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let sessions = self.sessions.clone();
+        let session = self.session.clone();
+        let generation = self.generation;
+        let window = self.window;
+        runtime.spawn(async move {
+            tokio::time::sleep(window).await;
+            let expired = {
+                let session = session.lock().unwrap();
+                session.generation == generation && session.stream.is_none()
+            };
+            if expired {
+                tracing::info!(
+                    session = %session.lock().unwrap().id,
+                    "SSE session closed; the client didn't resume it"
+                );
+                close(&sessions, &session);
+            }
+        });
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PostQuery {
+    session_id: String,
+}
+
+async fn post_message(
+    State(app): State<App>,
+    Query(PostQuery { session_id }): Query<PostQuery>,
+    parts: Parts,
+    Json(mut message): Json<ClientJsonRpcMessage>,
+) -> StatusCode {
+    let Some(session) = app.sessions.lock().unwrap().get(&session_id).cloned() else {
+        return StatusCode::NOT_FOUND;
+    };
+    let to_service = {
+        let session = session.lock().unwrap();
+        if !session.allows(parts.extensions.get::<Grant>()) {
+            tracing::warn!(
+                session = %session_id,
+                "Refused a message posted with another token than the SSE session's"
+            );
+            return StatusCode::FORBIDDEN;
+        }
+        session.to_service.clone()
+    };
+    let Some(to_service) = to_service else {
+        return StatusCode::GONE;
+    };
+    message.insert_extension(parts);
+    if to_service.send(message).await.is_err() {
+        return StatusCode::GONE;
+    }
+    StatusCode::ACCEPTED
+}
+
+/// The service's end of a session.
+struct SessionTransport {
+    session: Arc<Mutex<Session>>,
+    sessions: Sessions,
+    from_client: mpsc::Receiver<RxJsonRpcMessage<RoleServer>>,
+}
+
+impl Transport<RoleServer> for SessionTransport {
+    type Error = io::Error;
+
+    fn send(
+        &mut self,
+        item: TxJsonRpcMessage<RoleServer>,
+    ) -> impl Future<Output = io::Result<()>> + Send + 'static {
+        let result = serde_json::to_string(&item)
+            .map(|data| self.session.lock().unwrap().send(data.into()))
+            .map_err(io::Error::other);
+        std::future::ready(result)
+    }
+
+    fn receive(&mut self) -> impl Future<Output = Option<RxJsonRpcMessage<RoleServer>>> + Send {
+        self.from_client.recv()
+    }
+
+    fn close(&mut self) -> impl Future<Output = io::Result<()>> + Send {
+        close(&self.sessions, &self.session);
+        std::future::ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(capacity: usize) -> Session {
+        Session::new("s".to_string(), None, mpsc::channel(1).0, capacity)
+    }
+
+    fn drain(rx: &mut mpsc::UnboundedReceiver<Queued>) -> Vec<Queued> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn test_resume_replays_missed_messages() {
+        let mut session = session(16);
+        let mut rx = session.attach(None);
+        session.send("one".into());
+        assert_eq!(
+            drain(&mut rx),
+            [
+                Queued::Endpoint { new: true },
+                Queued::Message(1, "one".into())
+            ]
+        );
+
+        // The stream drops; responses keep coming.
+        drop(rx);
+        session.send("two".into());
+        session.send("three".into());
+        assert!(session.stream.is_none());
+
+        let mut rx = session.attach(Some(1));
+        session.send("four".into());
+        assert_eq!(
+            drain(&mut rx),
+            [
+                Queued::Endpoint { new: false },
+                Queued::Message(2, "two".into()),
+                Queued::Message(3, "three".into()),
+                Queued::Message(4, "four".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay_is_bounded() {
+        let mut session = session(2);
+        for data in ["one", "two", "three"] {
+            session.send(data.into());
+        }
+        let mut rx = session.attach(Some(0));
+        assert_eq!(
+            drain(&mut rx),
+            [
+                Queued::Endpoint { new: false },
+                Queued::Message(2, "two".into()),
+                Queued::Message(3, "three".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_session_is_kept_to_its_owner() {
+        let owner = Grant::from_scope(Some("alice".to_string()), "wassette:manage");
+        let session = Session::new("s".to_string(), Some(owner.clone()), mpsc::channel(1).0, 1);
+        assert!(session.allows(Some(&owner)));
+        assert!(!session.allows(None));
+        let other = Grant::from_scope(Some("mallory".to_string()), "wassette:manage");
+        assert!(!session.allows(Some(&other)));
+        let narrower = Grant::from_scope(Some("alice".to_string()), "");
+        assert!(!session.allows(Some(&narrower)));
+
+        // Without token checks, nobody owns a session.
+        assert!(self::session(1).allows(None));
+    }
+
+    #[test]
+    fn test_parse_event_id() {
+        assert_eq!(parse_event_id("abc-123:7"), Some(("abc-123", 7)));
+        assert_eq!(parse_event_id("abc"), None);
+        assert_eq!(parse_event_id("abc:x"), None);
+    }
+}
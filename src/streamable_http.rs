@@ -0,0 +1,160 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Streamable HTTP transport with sessions
+//!
+//! Clients post JSON-RPC messages to `/mcp` and get the responses back as an SSE stream. The
+//! `initialize` response carries an `Mcp-Session-Id` header that the client sends with every
+//! later request; `GET /mcp` opens a stream for server-initiated messages and `DELETE /mcp`
+//! ends the session. Every event has an id, and a client whose stream drops can open it again
+//! with the last id it saw in `Last-Event-ID` to be sent the events it missed.
+//!
+//! Sessions live in the memory of one server, so a load balancer in front of several servers
+//! must route requests by their `Mcp-Session-Id`. A request naming a session this server
+//! doesn't know, because it was routed elsewhere, the session expired or the server restarted,
+//! gets `404 Not Found`, which tells the client to initialize a new session. In stateless mode
+//! there are no sessions: every POST is served on its own and any server can take it, but the
+//! server can't send requests or notifications of its own, such as sampling or list changes.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use rmcp::service::RoleServer;
+use rmcp::transport::common::http_header::HEADER_SESSION_ID;
+use rmcp::transport::streamable_http_server::session::local::{LocalSessionManager, SessionConfig};
+use rmcp::transport::streamable_http_server::session::SessionManager;
+use rmcp::transport::streamable_http_server::{StreamableHttpServerConfig, StreamableHttpService};
+use serde::{Deserialize, Serialize};
+
+/// Path clients post their messages to and open streams on
+pub const MCP_PATH: &str = "/mcp";
+
+/// Settings of the streamable HTTP transport
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamableHttpConfig {
+    /// Seconds without events after which a ping is sent on open streams; 0 turns pings off
+    pub heartbeat_secs: u64,
+    /// Seconds without requests after which a session is closed; 0 keeps sessions until the
+    /// client ends them
+    pub session_idle_secs: u64,
+    /// Events kept per stream to replay to a client resuming it
+    pub replay_events: usize,
+    /// Serve every request on its own, without sessions
+    pub stateless: bool,
+}
+
+impl Default for StreamableHttpConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_secs: 15,
+            session_idle_secs: 1800,
+            replay_events: 64,
+            stateless: false,
+        }
+    }
+}
+
+impl StreamableHttpConfig {
+    fn session_config(&self) -> SessionConfig {
+        SessionConfig {
+            channel_capacity: self.replay_events.max(1),
+            keep_alive: (self.session_idle_secs > 0)
+                .then(|| Duration::from_secs(self.session_idle_secs)),
+        }
+    }
+
+    fn server_config(&self) -> StreamableHttpServerConfig {
+        StreamableHttpServerConfig {
+            sse_keep_alive: (self.heartbeat_secs > 0)
+                .then(|| Duration::from_secs(self.heartbeat_secs)),
+            stateful_mode: !self.stateless,
+        }
+    }
+}
+
+/// Routes serving MCP over streamable HTTP, with a service from `service` per session.
+pub fn router<S, F>(config: StreamableHttpConfig, service: F) -> Router
+where
+    S: rmcp::Service<RoleServer> + Send + 'static,
+    F: Fn() -> S + Send + Sync + 'static,
+{
+    let sessions = Arc::new(LocalSessionManager {
+        sessions: Default::default(),
+        session_config: config.session_config(),
+    });
+    let service = StreamableHttpService::new(
+        move || Ok(service()),
+        sessions.clone(),
+        config.server_config(),
+    );
+    let router = Router::new().nest_service(MCP_PATH, service);
+    if config.stateless {
+        return router;
+    }
+    router.layer(axum::middleware::from_fn_with_state(
+        sessions,
+        check_session,
+    ))
+}
+
+/// Answer requests naming a session that isn't open on this server with `404 Not Found`, as
+/// the MCP specification asks, so the client starts a new one.
+async fn check_session(
+    State(sessions): State<Arc<LocalSessionManager>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(session_id) = request
+        .headers()
+        .get(HEADER_SESSION_ID)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return next.run(request).await;
+    };
+    if sessions
+        .has_session(&session_id.into())
+        .await
+        .unwrap_or(false)
+    {
+        return next.run(request).await;
+    }
+    tracing::debug!(session_id, "Request for a session that isn't open");
+    (
+        StatusCode::NOT_FOUND,
+        "Session not found; send an initialize request to start a new one",
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_map_to_rmcp() {
+        let config = StreamableHttpConfig::default();
+        let session = config.session_config();
+        assert_eq!(session.channel_capacity, 64);
+        assert_eq!(session.keep_alive, Some(Duration::from_secs(1800)));
+        let server = config.server_config();
+        assert_eq!(server.sse_keep_alive, Some(Duration::from_secs(15)));
+        assert!(server.stateful_mode);
+
+        let config = StreamableHttpConfig {
+            heartbeat_secs: 0,
+            session_idle_secs: 0,
+            replay_events: 0,
+            stateless: true,
+        };
+        assert_eq!(config.session_config().channel_capacity, 1);
+        assert_eq!(config.session_config().keep_alive, None);
+        assert_eq!(config.server_config().sse_keep_alive, None);
+        assert!(!config.server_config().stateful_mode);
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+
+//! Exporting traces over OTLP
+//!
+//! When `WASSETTE_OTEL_ENDPOINT` is set, the spans of `wassette serve` are exported to that
+//! OTLP/HTTP collector, so the time of a tool call can be followed from the MCP request
+//! through queueing, instantiating the component and running it, down to the HTTP requests
+//! and sampling calls it makes. A client passing a W3C `traceparent` in the request's `_meta`
+//! gets the call's spans in its own trace. `RUST_LOG` selects the spans exported as it does
+//! the ones logged.
+
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Environment variable holding the OTLP/HTTP endpoint traces are exported to
+pub const OTEL_ENDPOINT_ENV: &str = "WASSETTE_OTEL_ENDPOINT";
+
+/// Flushes the spans not exported yet when dropped.
+pub struct Telemetry {
+    provider: SdkTracerProvider,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            eprintln!("Failed to export the remaining traces: {e}");
+        }
+    }
+}
+
+/// The URL traces are posted to for `endpoint`, the collector's base URL or the full path.
+fn traces_endpoint(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{endpoint}/v1/traces")
+    }
+}
+
+/// The layer exporting spans to the endpoint in `WASSETTE_OTEL_ENDPOINT`, if it is set,
+/// along with the guard flushing them on shutdown.
+pub fn init<S>() -> Result<Option<(OpenTelemetryLayer<S, SdkTracer>, Telemetry)>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let Some(endpoint) = std::env::var(OTEL_ENDPOINT_ENV)
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+    else {
+        return Ok(None);
+    };
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(traces_endpoint(&endpoint))
+        .build()
+        .with_context(|| format!("Failed to set up trace export to {endpoint}"))?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("wassette").build())
+        .build();
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("wassette"));
+    Ok(Some((layer, Telemetry { provider })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traces_endpoint() {
+        assert_eq!(
+            traces_endpoint("http://localhost:4318"),
+            "http://localhost:4318/v1/traces"
+        );
+        assert_eq!(
+            traces_endpoint("http://localhost:4318/"),
+            "http://localhost:4318/v1/traces"
+        );
+        assert_eq!(
+            traces_endpoint("https://otel.example.com/v1/traces"),
+            "https://otel.example.com/v1/traces"
+        );
+    }
+}
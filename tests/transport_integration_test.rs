@@ -837,26 +837,8 @@ async fn test_tool_list_notification() -> Result<()> {
 #[test(tokio::test)]
 async fn test_http_transport() -> Result<()> {
     // Use a random available port to avoid conflicts
-    let port = find_open_port().await?;
-
-    // We need to modify the source to support configurable bind address
-    // For now, let's test with the default port but check if it's available
-    let default_port = 9001u16;
-    let test_port = if TcpListener::bind(format!("127.0.0.1:{default_port}"))
-        .await
-        .is_ok()
-    {
-        default_port
-    } else {
-        port
-    };
-
-    // If we're not using the default port, skip this test for now
-    // since the server code uses a hardcoded bind address
-    if test_port != default_port {
-        println!("Skipping HTTP transport test: default port 9001 is not available");
-        return Ok(());
-    }
+    let test_port = find_open_port().await?;
+    let bind_arg = format!("--bind=127.0.0.1:{test_port}");
 
     // Create a temporary directory for this test to avoid loading existing components
     let temp_dir = tempfile::tempdir()?;
@@ -869,7 +851,7 @@ async fn test_http_transport() -> Result<()> {
 
     // Start the server with HTTP transport
     let mut child = tokio::process::Command::new(&binary_path)
-        .args(["serve", "--sse", &plugin_dir_arg])
+        .args(["serve", "--sse", &plugin_dir_arg, &bind_arg])
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -899,6 +881,201 @@ async fn test_http_transport() -> Result<()> {
     Ok(())
 }
 
+#[test(tokio::test)]
+async fn test_streamable_http_sessions() -> Result<()> {
+    let port = find_open_port().await?;
+    let temp_dir = tempfile::tempdir()?;
+    let plugin_dir_arg = format!("--plugin-dir={}", temp_dir.path().display());
+    let bind_arg = format!("--bind=127.0.0.1:{port}");
+    let binary_path = std::env::current_dir()
+        .context("Failed to get current directory")?
+        .join("target/debug/wassette");
+
+    let mut child = tokio::process::Command::new(&binary_path)
+        .args(["serve", "--streamable-http", &plugin_dir_arg, &bind_arg])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to start wassette with streamable HTTP transport")?;
+
+    let url = format!("http://127.0.0.1:{port}/mcp");
+    let client = reqwest::Client::new();
+    let post = |session: Option<&str>, body: serde_json::Value| {
+        let mut request = client
+            .post(&url)
+            .header("Accept", "application/json, text/event-stream")
+            .json(&body);
+        if let Some(session) = session {
+            request = request.header("Mcp-Session-Id", session);
+        }
+        request.send()
+    };
+    let initialize = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": "2025-03-26",
+            "capabilities": {},
+            "clientInfo": {"name": "test-client", "version": "1.0.0"}
+        }
+    });
+
+    // Wait for the server to listen
+    let mut response = None;
+    for _ in 0..50 {
+        if let Ok(r) = post(None, initialize.clone()).await {
+            response = Some(r);
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    let response = response.context("Server did not start listening")?;
+    assert_eq!(response.status(), 200);
+    let session = response
+        .headers()
+        .get("mcp-session-id")
+        .context("initialize response has no Mcp-Session-Id")?
+        .to_str()?
+        .to_string();
+    let body = tokio::time::timeout(Duration::from_secs(10), response.text()).await??;
+    assert!(body.contains("serverInfo"), "unexpected response: {body}");
+
+    let initialized = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/initialized"
+    });
+    let response = post(Some(&session), initialized.clone()).await?;
+    assert_eq!(response.status(), 202);
+
+    // A session this server doesn't know, e.g. one opened on another replica
+    let response = post(Some("not-a-session"), initialized.clone()).await?;
+    assert_eq!(response.status(), 404);
+
+    let response = client
+        .delete(&url)
+        .header("Mcp-Session-Id", &session)
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    let response = post(Some(&session), initialized).await?;
+    assert_eq!(response.status(), 404);
+
+    child.kill().await.ok();
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_streamable_http_requires_token() -> Result<()> {
+    let port = find_open_port().await?;
+    let temp_dir = tempfile::tempdir()?;
+    let config_file = temp_dir.path().join("config.toml");
+    // Nothing listens on the authorization server; the keys are only fetched for well-formed
+    // tokens.
+    tokio::fs::write(
+        &config_file,
+        format!(
+            "[auth]\nresource = \"http://127.0.0.1:{port}/mcp\"\nauthorization_servers = [\"http://127.0.0.1:1\"]\njwks_url = \"http://127.0.0.1:1/jwks.json\"\n"
+        ),
+    )
+    .await?;
+    let plugin_dir_arg = format!("--plugin-dir={}", temp_dir.path().join("plugins").display());
+    let bind_arg = format!("--bind=127.0.0.1:{port}");
+    let binary_path = std::env::current_dir()
+        .context("Failed to get current directory")?
+        .join("target/debug/wassette");
+
+    let mut child = tokio::process::Command::new(&binary_path)
+        .args(["serve", "--streamable-http", &plugin_dir_arg, &bind_arg])
+        .env("WASETTE_CONFIG_FILE", &config_file)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to start wassette with authorization")?;
+
+    let client = reqwest::Client::new();
+    let initialize = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": "2025-06-18",
+            "capabilities": {},
+            "clientInfo": {"name": "test-client", "version": "1.0.0"}
+        }
+    });
+    let post = |token: Option<&str>| {
+        let mut request = client
+            .post(format!("http://127.0.0.1:{port}/mcp"))
+            .header("Accept", "application/json, text/event-stream")
+            .json(&initialize);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send()
+    };
+
+    let mut response = None;
+    for _ in 0..50 {
+        if let Ok(r) = post(None).await {
+            response = Some(r);
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    let response = response.context("Server did not start listening")?;
+    assert_eq!(response.status(), 401);
+    let metadata_url = format!("http://127.0.0.1:{port}/.well-known/oauth-protected-resource/mcp");
+    let challenge = response
+        .headers()
+        .get("www-authenticate")
+        .context("401 response has no WWW-Authenticate header")?
+        .to_str()?
+        .to_string();
+    assert!(
+        challenge.contains(&format!("resource_metadata=\"{metadata_url}\"")),
+        "unexpected challenge: {challenge}"
+    );
+
+    let response = post(Some("not-a-jwt")).await?;
+    assert_eq!(response.status(), 401);
+    let challenge = response.headers()["www-authenticate"].to_str()?.to_string();
+    assert!(
+        challenge.contains("error=\"invalid_token\""),
+        "unexpected challenge: {challenge}"
+    );
+
+    let metadata: serde_json::Value = client.get(&metadata_url).send().await?.json().await?;
+    assert_eq!(metadata["resource"], format!("http://127.0.0.1:{port}/mcp"));
+    assert_eq!(
+        metadata["authorization_servers"],
+        serde_json::json!(["http://127.0.0.1:1"])
+    );
+
+    // Load metrics don't need a token.
+    let stats: serde_json::Value = client
+        .get(format!("http://127.0.0.1:{port}/stats"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(stats["active_calls"], 0);
+    let metrics = client
+        .get(format!("http://127.0.0.1:{port}/metrics"))
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert!(metrics.contains("wassette_queued_calls{priority=\"interactive\"} 0"));
+
+    child.kill().await.ok();
+    Ok(())
+}
+
 #[test(tokio::test)]
 async fn test_default_stdio_transport() -> Result<()> {
     // Create a temporary directory for this test to avoid loading existing components